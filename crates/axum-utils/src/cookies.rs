@@ -12,7 +12,8 @@ use axum::{
     extract::{FromRef, FromRequestParts},
     response::{IntoResponseParts, ResponseParts},
 };
-use axum_extra::extract::cookie::{Cookie, Key, PrivateCookieJar, SameSite};
+pub use axum_extra::extract::cookie::SameSite;
+use axum_extra::extract::cookie::{Cookie, Key, PrivateCookieJar};
 use http::request::Parts;
 use serde::{Serialize, de::DeserializeOwned};
 use thiserror::Error;
@@ -47,6 +48,20 @@ impl CookieManager {
         Self::new(base_url, key)
     }
 
+    /// Set the `SameSite` attribute of the cookies. Defaults to `Lax`.
+    #[must_use]
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.options.same_site = same_site;
+        self
+    }
+
+    /// Set a prefix added to the name of all the cookies, like `__Host-`
+    #[must_use]
+    pub fn with_name_prefix(mut self, name_prefix: String) -> Self {
+        self.options.name_prefix = name_prefix;
+        self
+    }
+
     #[must_use]
     pub fn cookie_jar(&self) -> CookieJar {
         let inner = PrivateCookieJar::new(self.key.clone());
@@ -80,11 +95,17 @@ where
 #[derive(Debug, Clone)]
 struct CookieOption {
     base_url: Url,
+    same_site: SameSite,
+    name_prefix: String,
 }
 
 impl CookieOption {
     const fn new(base_url: Url) -> Self {
-        Self { base_url }
+        Self {
+            base_url,
+            same_site: SameSite::Lax,
+            name_prefix: String::new(),
+        }
     }
 
     fn secure(&self) -> bool {
@@ -95,11 +116,15 @@ impl CookieOption {
         self.base_url.path()
     }
 
+    fn name(&self, key: &str) -> String {
+        format!("{}{key}", self.name_prefix)
+    }
+
     fn apply<'a>(&self, mut cookie: Cookie<'a>) -> Cookie<'a> {
         cookie.set_http_only(true);
         cookie.set_secure(self.secure());
         cookie.set_path(self.path().to_owned());
-        cookie.set_same_site(SameSite::Lax);
+        cookie.set_same_site(self.same_site);
         cookie
     }
 }
@@ -123,7 +148,7 @@ impl CookieJar {
         let serialized =
             serde_json::to_string(payload).expect("failed to serialize cookie payload");
 
-        let cookie = Cookie::new(key.to_owned(), serialized);
+        let cookie = Cookie::new(self.options.name(key), serialized);
         let mut cookie = self.options.apply(cookie);
 
         if permanent {
@@ -139,7 +164,7 @@ impl CookieJar {
     /// Remove a cookie from the jar
     #[must_use]
     pub fn remove(mut self, key: &str) -> Self {
-        self.inner = self.inner.remove(key.to_owned());
+        self.inner = self.inner.remove(self.options.name(key));
         self
     }
 
@@ -151,7 +176,7 @@ impl CookieJar {
     ///
     /// Returns an error if the cookie cannot be deserialized
     pub fn load<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, CookieDecodeError> {
-        let Some(cookie) = self.inner.get(key) else {
+        let Some(cookie) = self.inner.get(&self.options.name(key)) else {
             return Ok(None);
         };
 
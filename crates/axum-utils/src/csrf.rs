@@ -12,7 +12,10 @@ use serde::{Deserialize, Serialize};
 use serde_with::{TimestampSeconds, serde_as};
 use thiserror::Error;
 
-use crate::cookies::{CookieDecodeError, CookieJar};
+use crate::{
+    cookies::{CookieDecodeError, CookieJar},
+    session::{SESSION_COOKIE, SessionInfo},
+};
 
 /// Failed to validate CSRF token
 #[derive(Debug, Error)]
@@ -36,6 +39,10 @@ pub enum CsrfError {
     /// Failed to decode the token
     #[error("could not decode CSRF token")]
     Decode(#[from] base64ct::Error),
+
+    /// The token was issued before the current session was authenticated
+    #[error("CSRF token was issued before the session was authenticated")]
+    Stale,
 }
 
 /// A CSRF token
@@ -44,25 +51,36 @@ pub enum CsrfError {
 pub struct CsrfToken {
     #[serde_as(as = "TimestampSeconds<i64>")]
     expiration: DateTime<Utc>,
+
+    /// When the token value was generated. Tokens saved before this was
+    /// recorded get the UNIX epoch, making them older than any session.
+    #[serde_as(as = "TimestampSeconds<i64>")]
+    #[serde(default)]
+    issued_at: DateTime<Utc>,
+
     token: [u8; 32],
 }
 
 impl CsrfToken {
     /// Create a new token from a defined value valid for a specified duration
-    fn new(token: [u8; 32], now: DateTime<Utc>, ttl: Duration) -> Self {
+    fn new(token: [u8; 32], issued_at: DateTime<Utc>, now: DateTime<Utc>, ttl: Duration) -> Self {
         let expiration = now + ttl;
-        Self { expiration, token }
+        Self {
+            expiration,
+            issued_at,
+            token,
+        }
     }
 
     /// Generate a new random token valid for a specified duration
     fn generate(now: DateTime<Utc>, mut rng: impl Rng, ttl: Duration) -> Self {
         let token = Standard.sample(&mut rng);
-        Self::new(token, now, ttl)
+        Self::new(token, now, now, ttl)
     }
 
     /// Generate a new token with the same value but an up to date expiration
     fn refresh(self, now: DateTime<Utc>, ttl: Duration) -> Self {
-        Self::new(self.token, now, ttl)
+        Self::new(self.token, self.issued_at, now, ttl)
     }
 
     /// Get the value to include in HTML forms
@@ -92,6 +110,18 @@ impl CsrfToken {
             Err(CsrfError::Expired)
         }
     }
+
+    /// Check that the token was not issued before the session was
+    /// authenticated, so that tokens don't survive privilege changes
+    fn verify_issued_after(
+        self,
+        authenticated_at: Option<DateTime<Utc>>,
+    ) -> Result<Self, CsrfError> {
+        match authenticated_at {
+            Some(authenticated_at) if self.issued_at < authenticated_at => Err(CsrfError::Stale),
+            _ => Ok(self),
+        }
+    }
 }
 
 // A CSRF-protected form
@@ -121,6 +151,15 @@ pub trait CsrfExt {
     fn verify_form<C, T>(&self, clock: &C, form: ProtectedForm<T>) -> Result<T, CsrfError>
     where
         C: Clock;
+
+    /// Replace the CSRF token with a new one, invalidating the forms rendered
+    /// before. This should be called when the privileges of the browser
+    /// change, for example when a user logs in.
+    #[must_use]
+    fn rotate_csrf_token<C, R>(self, clock: &C, rng: R) -> Self
+    where
+        R: RngCore,
+        C: Clock;
 }
 
 impl CsrfExt for CookieJar {
@@ -130,11 +169,14 @@ impl CsrfExt for CookieJar {
         C: Clock,
     {
         let now = clock.now();
+        let authenticated_at = self.authenticated_at();
         let maybe_token = match self.load::<CsrfToken>("csrf") {
             Ok(Some(token)) => {
-                let token = token.verify_expiration(now);
+                let token = token
+                    .verify_expiration(now)
+                    .and_then(|token| token.verify_issued_after(authenticated_at));
 
-                // If the token is expired, just ignore it
+                // If the token is expired or stale, just ignore it
                 token.ok()
             }
             Ok(None) => None,
@@ -158,8 +200,98 @@ impl CsrfExt for CookieJar {
         C: Clock,
     {
         let token: CsrfToken = self.load("csrf")?.ok_or(CsrfError::Missing)?;
-        let token = token.verify_expiration(clock.now())?;
+        let token = token
+            .verify_expiration(clock.now())?
+            .verify_issued_after(self.authenticated_at())?;
         token.verify_form_value(&form.csrf)?;
         Ok(form.inner)
     }
+
+    fn rotate_csrf_token<C, R>(self, clock: &C, rng: R) -> Self
+    where
+        R: RngCore,
+        C: Clock,
+    {
+        let token = CsrfToken::generate(clock.now(), rng, Duration::try_hours(1).unwrap());
+        self.save("csrf", &token, false)
+    }
+}
+
+impl CookieJar {
+    /// When the current browser session was last authenticated, if known
+    fn authenticated_at(&self) -> Option<DateTime<Utc>> {
+        match self.load::<SessionInfo>(SESSION_COOKIE) {
+            Ok(Some(info)) => info.authenticated_at(),
+            Ok(None) | Err(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_data_model::{BrowserSession, clock::MockClock};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{SessionInfoExt, cookies::CookieManager};
+
+    fn protected_form(token: &CsrfToken) -> ProtectedForm<()> {
+        ProtectedForm {
+            csrf: token.form_value(),
+            inner: (),
+        }
+    }
+
+    #[test]
+    fn test_rotate_on_authentication() {
+        let clock = MockClock::default();
+        let mut rng = StdRng::seed_from_u64(42);
+        let manager =
+            CookieManager::derive_from("https://example.com/".parse().unwrap(), &[0x42; 32]);
+        let session = BrowserSession::samples(clock.now(), &mut rng).remove(0);
+
+        // Render a form before logging in
+        let (token, jar) = manager.cookie_jar().csrf_token(&clock, &mut rng);
+        jar.verify_form(&clock, protected_form(&token)).unwrap();
+
+        // Logging in rotates the token, so the form rendered before is rejected
+        clock.advance(Duration::try_minutes(1).unwrap());
+        let jar = jar.set_authenticated_session(&session, &clock, &mut rng);
+        assert!(matches!(
+            jar.verify_form(&clock, protected_form(&token)),
+            Err(CsrfError::Mismatch)
+        ));
+
+        // Forms rendered after logging in are accepted
+        let (new_token, jar) = jar.csrf_token(&clock, &mut rng);
+        assert_ne!(new_token.form_value(), token.form_value());
+        jar.verify_form(&clock, protected_form(&new_token)).unwrap();
+    }
+
+    #[test]
+    fn test_reject_stale_token() {
+        let clock = MockClock::default();
+        let mut rng = StdRng::seed_from_u64(42);
+        let manager =
+            CookieManager::derive_from("https://example.com/".parse().unwrap(), &[0x42; 32]);
+        let session = BrowserSession::samples(clock.now(), &mut rng).remove(0);
+
+        let (token, jar) = manager.cookie_jar().csrf_token(&clock, &mut rng);
+
+        // Put back the token minted before the authentication in the jar, like
+        // if the cookie survived the rotation
+        clock.advance(Duration::try_minutes(1).unwrap());
+        let jar = jar
+            .set_authenticated_session(&session, &clock, &mut rng)
+            .save("csrf", &token, false);
+        assert!(matches!(
+            jar.verify_form(&clock, protected_form(&token)),
+            Err(CsrfError::Stale)
+        ));
+
+        // Rendering a new form replaces the stale token
+        let (new_token, jar) = jar.csrf_token(&clock, &mut rng);
+        assert_ne!(new_token.form_value(), token.form_value());
+        jar.verify_form(&clock, protected_form(&new_token)).unwrap();
+    }
 }
@@ -0,0 +1,211 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Negotiation of the format of error responses
+//!
+//! Errors built with [`GenericError`](crate::GenericError) and
+//! [`InternalError`](crate::InternalError) carry an [`ErrorContext`]. Depending
+//! on the `Accept` header of the request and on the class of the route which
+//! produced them, they are either rendered as an HTML error page or as a JSON
+//! document.
+
+use http::{HeaderMap, StatusCode, header::ACCEPT};
+use mas_templates::{ErrorCode, ErrorContext};
+use serde::Serialize;
+
+/// The class of a route, which decides the format of its error responses when
+/// the client doesn't express a preference
+///
+/// Routers attach it to their responses as an extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteClass {
+    /// Routes rendering pages for browsers, defaulting to HTML errors
+    Browser,
+
+    /// Routes used by API clients, defaulting to JSON errors
+    Api,
+}
+
+impl RouteClass {
+    /// The format of error responses when the client has no preference
+    #[must_use]
+    pub const fn default_error_format(self) -> ErrorFormat {
+        match self {
+            Self::Browser => ErrorFormat::Html,
+            Self::Api => ErrorFormat::Json,
+        }
+    }
+}
+
+/// The format of an error response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// An HTML error page
+    Html,
+
+    /// A JSON document, see [`ErrorDocument`]
+    Json,
+}
+
+impl ErrorFormat {
+    /// Find the format preferred by the client from the `Accept` header of the
+    /// request, if any
+    ///
+    /// This compares the quality of the HTML and JSON media types, and returns
+    /// [`None`] if they are equally acceptable, for example with `*/*`.
+    #[must_use]
+    pub fn preferred(headers: &HeaderMap) -> Option<Self> {
+        let mut html: Option<u16> = None;
+        let mut json: Option<u16> = None;
+
+        for value in headers.get_all(ACCEPT) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+
+            for range in value.split(',') {
+                let mut params = range.split(';');
+                let media_type = params.next().unwrap_or_default().trim();
+                let quality = params
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .and_then(|quality| quality.trim().parse::<f64>().ok())
+                    .map_or(1000, |quality| {
+                        // Quality is between 0 and 1 with 3 decimal places, which we map
+                        // from 0 to 1000
+                        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+                        {
+                            f64::round(quality.clamp(0_f64, 1_f64) * 1000_f64) as u16
+                        }
+                    });
+
+                let slot = if media_type.eq_ignore_ascii_case("text/html")
+                    || media_type.eq_ignore_ascii_case("application/xhtml+xml")
+                {
+                    &mut html
+                } else if media_type.eq_ignore_ascii_case("application/json")
+                    || media_type.eq_ignore_ascii_case("application/problem+json")
+                {
+                    &mut json
+                } else {
+                    continue;
+                };
+
+                *slot = Some(slot.map_or(quality, |previous| previous.max(quality)));
+            }
+        }
+
+        match (html.unwrap_or(0), json.unwrap_or(0)) {
+            (html, json) if html > json => Some(Self::Html),
+            (html, json) if json > html => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// Negotiate the format of an error response, from the `Accept` header of
+    /// the request and the class of the route
+    #[must_use]
+    pub fn negotiate(headers: &HeaderMap, class: RouteClass) -> Self {
+        Self::preferred(headers).unwrap_or(class.default_error_format())
+    }
+}
+
+/// A JSON error document, loosely following the shape of RFC 9457 problem
+/// details
+#[derive(Serialize)]
+pub struct ErrorDocument<'a> {
+    /// The HTTP status code of the response
+    status: u16,
+
+    /// A short, human-readable summary of the status
+    title: &'a str,
+
+    /// The stable, machine-readable code of the error, as shown on the HTML
+    /// error page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<ErrorCode>,
+
+    /// A human-readable explanation of the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<&'a str>,
+
+    /// The ID of the request, to correlate it with the server logs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<&'a str>,
+}
+
+impl<'a> ErrorDocument<'a> {
+    /// Build the JSON document for an error response with the given status
+    #[must_use]
+    pub fn new(status: StatusCode, context: &'a ErrorContext) -> Self {
+        Self {
+            status: status.as_u16(),
+            title: status.canonical_reason().unwrap_or("Error"),
+            code: context.code(),
+            detail: context.description(),
+            request_id: context.request_id(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    fn headers(accept: &str) -> HeaderMap {
+        HeaderMap::from_iter([(ACCEPT, HeaderValue::from_str(accept).unwrap())])
+    }
+
+    #[test]
+    fn test_preferred() {
+        assert_eq!(ErrorFormat::preferred(&HeaderMap::new()), None);
+        assert_eq!(ErrorFormat::preferred(&headers("*/*")), None);
+        assert_eq!(
+            ErrorFormat::preferred(&headers("application/json")),
+            Some(ErrorFormat::Json)
+        );
+        assert_eq!(
+            ErrorFormat::preferred(&headers("application/json, text/plain, */*")),
+            Some(ErrorFormat::Json)
+        );
+        // What browsers send when navigating
+        assert_eq!(
+            ErrorFormat::preferred(&headers(
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+            )),
+            Some(ErrorFormat::Html)
+        );
+        assert_eq!(
+            ErrorFormat::preferred(&headers("text/html;q=0.5, application/json;q=0.9")),
+            Some(ErrorFormat::Json)
+        );
+        assert_eq!(
+            ErrorFormat::preferred(&headers("text/html, application/json")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let empty = HeaderMap::new();
+        assert_eq!(
+            ErrorFormat::negotiate(&empty, RouteClass::Browser),
+            ErrorFormat::Html
+        );
+        assert_eq!(
+            ErrorFormat::negotiate(&empty, RouteClass::Api),
+            ErrorFormat::Json
+        );
+        assert_eq!(
+            ErrorFormat::negotiate(&headers("application/json"), RouteClass::Browser),
+            ErrorFormat::Json
+        );
+        assert_eq!(
+            ErrorFormat::negotiate(&headers("text/html"), RouteClass::Api),
+            ErrorFormat::Html
+        );
+    }
+}
@@ -4,9 +4,13 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use axum::response::{IntoResponse, Response};
+use axum::{
+    http::{StatusCode, header::RETRY_AFTER},
+    response::{IntoResponse, Response},
+};
+use mas_storage::RepositoryError;
 
-use crate::InternalError;
+use crate::{GenericError, InternalError};
 
 /// A simple wrapper around an error that implements [`IntoResponse`].
 #[derive(Debug, thiserror::Error)]
@@ -21,3 +25,30 @@ where
         InternalError::from(self.0).into_response()
     }
 }
+
+/// The rejection of the extractors which create a
+/// [`mas_storage::BoxRepository`].
+///
+/// If the storage backend is temporarily unavailable, this responds with a
+/// `503 Service Unavailable` and a `Retry-After` header, so that clients back
+/// off instead of considering it a server bug.
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub struct RepositoryRejection(#[from] pub RepositoryError);
+
+impl IntoResponse for RepositoryRejection {
+    fn into_response(self) -> Response {
+        let Some(retry_after) = self.0.retry_after() else {
+            return InternalError::from(self.0).into_response();
+        };
+
+        // Retry-After is in whole seconds, round up so that we never tell
+        // clients to retry right away
+        let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        (
+            [(RETRY_AFTER, seconds.max(1).to_string())],
+            GenericError::new(StatusCode::SERVICE_UNAVAILABLE, self.0),
+        )
+            .into_response()
+    }
+}
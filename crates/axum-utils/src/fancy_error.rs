@@ -11,11 +11,12 @@ use axum::{
 };
 use axum_extra::typed_header::TypedHeader;
 use headers::ContentType;
-use mas_templates::ErrorContext;
+use mas_context::LogContext;
+use mas_templates::{ErrorCode, ErrorContext};
 
 use crate::sentry::SentryEventID;
 
-fn build_context(mut err: &dyn std::error::Error) -> ErrorContext {
+fn build_context(mut err: &dyn std::error::Error, code: ErrorCode) -> ErrorContext {
     let description = err.to_string();
     let mut details = Vec::new();
     while let Some(source) = err.source() {
@@ -24,6 +25,7 @@ fn build_context(mut err: &dyn std::error::Error) -> ErrorContext {
     }
 
     ErrorContext::new()
+        .with_code(code)
         .with_description(description)
         .with_details(details.join("\n"))
 }
@@ -31,12 +33,13 @@ fn build_context(mut err: &dyn std::error::Error) -> ErrorContext {
 pub struct GenericError {
     error: Box<dyn std::error::Error + 'static>,
     code: StatusCode,
+    error_code: ErrorCode,
 }
 
 impl IntoResponse for GenericError {
     fn into_response(self) -> Response {
         tracing::warn!(message = &*self.error);
-        let context = build_context(&*self.error);
+        let context = build_context(&*self.error, self.error_code);
         let context_text = format!("{context}");
 
         (
@@ -54,8 +57,27 @@ impl GenericError {
         Self {
             error: Box::new(err),
             code,
+            error_code: ErrorCode::from_status(code),
         }
     }
+
+    /// Create a new error from an [`anyhow::Error`]
+    #[must_use]
+    pub fn from_anyhow(code: StatusCode, err: anyhow::Error) -> Self {
+        Self {
+            error: err.into_boxed_dyn_error(),
+            code,
+            error_code: ErrorCode::from_status(code),
+        }
+    }
+
+    /// Set the machine-readable error code shown on the error page, instead
+    /// of the generic one derived from the status code
+    #[must_use]
+    pub fn with_error_code(mut self, error_code: ErrorCode) -> Self {
+        self.error_code = error_code;
+        self
+    }
 }
 
 pub struct InternalError {
@@ -66,7 +88,10 @@ impl IntoResponse for InternalError {
     fn into_response(self) -> Response {
         tracing::error!(message = &*self.error);
         let event_id = SentryEventID::for_last_event();
-        let context = build_context(&*self.error);
+        let mut context = build_context(&*self.error, ErrorCode::InternalError);
+        if let Some(request_id) = LogContext::maybe_with(ToString::to_string) {
+            context = context.with_request_id(request_id);
+        }
         let context_text = format!("{context}");
 
         (
@@ -10,6 +10,7 @@
 pub mod client_authorization;
 pub mod cookies;
 pub mod csrf;
+pub mod error_format;
 pub mod error_wrapper;
 pub mod fancy_error;
 pub mod jwt;
@@ -21,7 +22,7 @@ pub mod user_authorization;
 pub use axum;
 
 pub use self::{
-    error_wrapper::ErrorWrapper,
+    error_wrapper::{ErrorWrapper, RepositoryRejection},
     fancy_error::{GenericError, InternalError},
     session::{SessionInfo, SessionInfoExt},
 };
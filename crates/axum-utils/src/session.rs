@@ -4,17 +4,29 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use mas_data_model::BrowserSession;
+use chrono::{DateTime, Utc};
+use mas_data_model::{BrowserSession, Clock};
 use mas_storage::RepositoryAccess;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use serde_with::{TimestampSeconds, serde_as};
 use ulid::Ulid;
 
-use crate::cookies::CookieJar;
+use crate::{cookies::CookieJar, csrf::CsrfExt};
+
+/// The name of the cookie holding the [`SessionInfo`]
+pub(crate) const SESSION_COOKIE: &str = "session";
 
 /// An encrypted cookie to save the session ID
+#[serde_as]
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct SessionInfo {
     current: Option<Ulid>,
+
+    /// When the current session was last authenticated in this browser
+    #[serde_as(as = "Option<TimestampSeconds<i64>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    authenticated_at: Option<DateTime<Utc>>,
 }
 
 impl SessionInfo {
@@ -23,6 +35,7 @@ impl SessionInfo {
     pub fn from_session(session: &BrowserSession) -> Self {
         Self {
             current: Some(session.id),
+            authenticated_at: None,
         }
     }
 
@@ -30,9 +43,17 @@ impl SessionInfo {
     #[must_use]
     pub fn mark_session_ended(mut self) -> Self {
         self.current = None;
+        self.authenticated_at = None;
         self
     }
 
+    /// When the current session was last authenticated in this browser, if
+    /// known
+    #[must_use]
+    pub fn authenticated_at(&self) -> Option<DateTime<Utc>> {
+        self.authenticated_at
+    }
+
     /// Load the active [`BrowserSession`] from database
     ///
     /// # Errors
@@ -78,11 +99,20 @@ pub trait SessionInfoExt {
         let session_info = SessionInfo::from_session(session);
         self.update_session_info(&session_info)
     }
+
+    /// Set the given session as the current one, right after it was
+    /// authenticated. This rotates the CSRF token, so that forms rendered
+    /// before the authentication are rejected.
+    #[must_use]
+    fn set_authenticated_session<C, R>(self, session: &BrowserSession, clock: &C, rng: R) -> Self
+    where
+        C: Clock,
+        R: RngCore;
 }
 
 impl SessionInfoExt for CookieJar {
     fn session_info(self) -> (SessionInfo, Self) {
-        let info = match self.load("session") {
+        let info = match self.load(SESSION_COOKIE) {
             Ok(Some(s)) => s,
             Ok(None) => SessionInfo::default(),
             Err(e) => {
@@ -96,6 +126,17 @@ impl SessionInfoExt for CookieJar {
     }
 
     fn update_session_info(self, info: &SessionInfo) -> Self {
-        self.save("session", info, true)
+        self.save(SESSION_COOKIE, info, true)
+    }
+
+    fn set_authenticated_session<C, R>(self, session: &BrowserSession, clock: &C, rng: R) -> Self
+    where
+        C: Clock,
+        R: RngCore,
+    {
+        let mut session_info = SessionInfo::from_session(session);
+        session_info.authenticated_at = Some(clock.now());
+        self.update_session_info(&session_info)
+            .rotate_csrf_token(clock, rng)
     }
 }
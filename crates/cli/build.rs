@@ -24,6 +24,7 @@ fn main() -> anyhow::Result<()> {
 
     let gitcl = GitclBuilder::default()
         .describe(true, false, Some("v*.*.*"))
+        .sha(false)
         .build()?;
     let rustc = RustcBuilder::default().semver(true).build()?;
 
@@ -7,12 +7,16 @@
 use std::{convert::Infallible, net::IpAddr, sync::Arc};
 
 use axum::extract::{FromRef, FromRequestParts};
+use chrono::{DateTime, Utc};
 use ipnetwork::IpNetwork;
 use mas_context::LogContext;
-use mas_data_model::{AppVersion, BoxClock, BoxRng, SiteConfig, SystemClock, TchapConfig}; /*  */
+use mas_data_model::{
+    AppBuildInfo, AppVersion, BoxClock, BoxRng, SiteConfig, SystemClock, TchapConfig, TchapFeatures,
+}; /*  */
 use mas_handlers::{
-    ActivityTracker, BoundActivityTracker, CookieManager, ErrorWrapper, GraphQLSchema, Limiter,
-    MetadataCache, RequesterFingerprint, passwords::PasswordManager,
+    ActivityTracker, BoundActivityTracker, ClaimsAugmentors, CookieManager, ErrorWrapper,
+    GraphQLSchema, Limiter, MetadataCache, RepositoryRejection, RequesterFingerprint,
+    passwords::PasswordManager,
 };
 use mas_i18n::Translator;
 use mas_keystore::{Encrypter, Keystore};
@@ -27,7 +31,7 @@ use rand::SeedableRng;
 use sqlx::PgPool;
 use tracing::Instrument;
 
-use crate::{VERSION, telemetry::METER};
+use crate::{GIT_SHA, RUSTC_VERSION, VERSION, telemetry::METER};
 
 #[derive(Clone)]
 pub struct AppState {
@@ -47,8 +51,11 @@ pub struct AppState {
     pub activity_tracker: ActivityTracker,
     pub trusted_proxies: Vec<IpNetwork>,
     pub limiter: Limiter,
+    pub claims_augmentors: ClaimsAugmentors,
+    pub started_at: DateTime<Utc>,
     //:tchap:
     pub tchap_config: TchapConfig,
+    pub tchap_features: TchapFeatures,
     //:tchap: end
 }
 
@@ -175,6 +182,12 @@ impl FromRef<AppState> for reqwest::Client {
     }
 }
 
+impl FromRef<AppState> for ClaimsAugmentors {
+    fn from_ref(input: &AppState) -> Self {
+        input.claims_augmentors.clone()
+    }
+}
+
 impl FromRef<AppState> for PasswordManager {
     fn from_ref(input: &AppState) -> Self {
         input.password_manager.clone()
@@ -223,12 +236,28 @@ impl FromRef<AppState> for AppVersion {
     }
 }
 
+impl FromRef<AppState> for AppBuildInfo {
+    fn from_ref(input: &AppState) -> Self {
+        AppBuildInfo {
+            git_sha: GIT_SHA,
+            rustc_version: RUSTC_VERSION,
+            started_at: input.started_at,
+        }
+    }
+}
+
 //:tchap:
 impl FromRef<AppState> for TchapConfig {
     fn from_ref(input: &AppState) -> Self {
         input.tchap_config.clone()
     }
 }
+
+impl FromRef<AppState> for TchapFeatures {
+    fn from_ref(input: &AppState) -> Self {
+        input.tchap_features
+    }
+}
 //:tchap:end
 
 impl FromRequestParts<AppState> for BoxClock {
@@ -373,7 +402,7 @@ impl FromRequestParts<AppState> for RequesterFingerprint {
 }
 
 impl FromRequestParts<AppState> for BoxRepository {
-    type Rejection = ErrorWrapper<mas_storage::RepositoryError>;
+    type Rejection = RepositoryRejection;
 
     async fn from_request_parts(
         _parts: &mut axum::http::request::Parts,
@@ -10,14 +10,15 @@ use anyhow::Context;
 use camino::Utf8PathBuf;
 use clap::Parser;
 use figment::Figment;
-use mas_config::{ConfigurationSection, RootConfig, SyncConfig};
+use mas_config::{ConfigurationSection, RootConfig, SyncConfig, find_unknown_keys};
 use mas_data_model::{Clock as _, SystemClock};
+use mas_handlers::{EffectiveSiteConfig, EffectiveTemplatesPaths, EffectiveUpstreamProvider};
 use mas_storage_pg::MIGRATOR;
 use rand::SeedableRng;
 use tokio::io::AsyncWriteExt;
-use tracing::{Instrument, info, info_span};
+use tracing::{Instrument, error, info, info_span};
 
-use crate::util::database_connection_from_config;
+use crate::util::{database_connection_from_config, site_config_from_config};
 
 #[derive(Parser, Debug)]
 pub(super) struct Options {
@@ -34,6 +35,12 @@ enum Subcommand {
         /// If not specified, the config will be written to stdout
         #[clap(short, long)]
         output: Option<Utf8PathBuf>,
+
+        /// Dump a sanitized view of the configuration effectively used at
+        /// runtime instead, as returned by the admin API `site-config`
+        /// endpoint
+        #[clap(long)]
+        effective: bool,
     },
 
     /// Check a config file
@@ -69,11 +76,15 @@ impl Options {
     pub async fn run(self, figment: &Figment) -> anyhow::Result<ExitCode> {
         use Subcommand as SC;
         match self.subcommand {
-            SC::Dump { output } => {
+            SC::Dump { output, effective } => {
                 let _span = info_span!("cli.config.dump").entered();
 
                 let config = RootConfig::extract(figment).map_err(anyhow::Error::from_boxed)?;
-                let config = serde_yaml::to_string(&config)?;
+                let config = if effective {
+                    serde_yaml::to_string(&effective_site_config(&config)?)?
+                } else {
+                    serde_yaml::to_string(&config)?
+                };
 
                 if let Some(output) = output {
                     info!("Writing configuration to {output:?}");
@@ -89,6 +100,19 @@ impl Options {
                 let _span = info_span!("cli.config.check").entered();
 
                 let _config = RootConfig::extract(figment).map_err(anyhow::Error::from_boxed)?;
+
+                let unknown_keys = find_unknown_keys(figment)?;
+                if !unknown_keys.is_empty() {
+                    for key in &unknown_keys {
+                        error!(key = %key.path, source = %key.source, "Unknown configuration key");
+                    }
+
+                    anyhow::bail!(
+                        "Found {} unknown configuration key(s), check for typos",
+                        unknown_keys.len()
+                    );
+                }
+
                 info!("Configuration file looks good");
             }
 
@@ -152,3 +176,47 @@ impl Options {
         Ok(ExitCode::SUCCESS)
     }
 }
+
+/// Build the sanitized view of the effective configuration, the same way the
+/// admin API does at runtime
+fn effective_site_config(config: &RootConfig) -> anyhow::Result<EffectiveSiteConfig> {
+    let site_config = site_config_from_config(
+        &config.branding,
+        &config.matrix,
+        &config.experimental,
+        &config.passwords,
+        &config.account,
+        &config.emails,
+        &config.login,
+        &config.captcha,
+        &config.rate_limiting,
+        &config.audit_webhook,
+        &config.openid,
+        &config.sessions,
+        &config.email,
+    )?;
+
+    let upstream_providers = config
+        .upstream_oauth2
+        .providers
+        .iter()
+        .filter(|provider| provider.enabled)
+        .map(|provider| EffectiveUpstreamProvider {
+            id: provider.id,
+            issuer: provider.issuer.clone(),
+        })
+        .collect();
+
+    let templates = EffectiveTemplatesPaths {
+        path: config.templates.path.to_string(),
+        translations_path: config.templates.translations_path.to_string(),
+        assets_manifest: config.templates.assets_manifest.to_string(),
+    };
+
+    Ok(EffectiveSiteConfig::new(
+        &site_config,
+        &config.rate_limiting,
+        upstream_providers,
+        templates,
+    ))
+}
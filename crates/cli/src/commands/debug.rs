@@ -4,18 +4,31 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::process::ExitCode;
+use std::{
+    process::ExitCode,
+    time::{Duration, Instant},
+};
 
+use anyhow::{Context, bail};
+use camino::Utf8PathBuf;
 use clap::Parser;
 use figment::Figment;
 use mas_config::{
-    ConfigurationSection, ConfigurationSectionExt, DatabaseConfig, MatrixConfig, PolicyConfig,
+    ConfigurationSection, ConfigurationSectionExt, DatabaseConfig, MatrixConfig, PasswordsConfig,
+    PolicyConfig,
 };
+use mas_handlers::passwords::{Hasher, PasswordManager};
 use mas_storage_pg::PgRepositoryFactory;
-use tracing::{info, info_span};
+use mas_templates::Templates;
+use rand::SeedableRng;
+use tokio::io::AsyncWriteExt as _;
+use tracing::{info, info_span, warn};
+use zeroize::Zeroizing;
 
+use super::templates::load_templates;
 use crate::util::{
-    database_pool_from_config, load_policy_factory_dynamic_data, policy_factory_from_config,
+    database_pool_from_config, load_policy_factory_dynamic_data, password_hasher_from_config,
+    policy_factory_from_config,
 };
 
 #[derive(Parser, Debug)]
@@ -32,6 +45,41 @@ enum Subcommand {
         #[arg(long)]
         with_dynamic_data: bool,
     },
+
+    /// Measure how long hashing and verifying a password takes with the
+    /// current password hashing scheme, and suggest argon2id parameters
+    /// fitting a time budget
+    BenchmarkPasswords {
+        /// The time budget for hashing a password, in milliseconds
+        #[arg(long, default_value_t = 250)]
+        budget_ms: u64,
+
+        /// How many passwords to hash and verify for each measurement
+        #[arg(long, default_value_t = 5)]
+        rounds: u32,
+    },
+
+    /// Render a template with a context read from a JSON file, to preview
+    /// changes to the templates without going through the pages rendering them
+    RenderTemplate {
+        /// The name of the template to render, like `pages/login.html`
+        name: String,
+
+        /// Path to a JSON file with the context to render the template with
+        #[arg(long)]
+        context: Utf8PathBuf,
+
+        /// The locale to render the template in. Defaults to the `lang` of the
+        /// context, or English
+        #[arg(long)]
+        locale: Option<String>,
+
+        /// The path to write the render to
+        ///
+        /// If not specified, the render will be written to stdout
+        #[arg(short, long)]
+        output: Option<Utf8PathBuf>,
+    },
 }
 
 impl Options {
@@ -58,8 +106,146 @@ impl Options {
 
                 let _instance = policy_factory.instantiate().await?;
             }
+
+            SC::BenchmarkPasswords { budget_ms, rounds } => {
+                let _span = info_span!("cli.debug.benchmark_passwords").entered();
+                let config = PasswordsConfig::extract_or_default(figment)
+                    .map_err(anyhow::Error::from_boxed)?;
+
+                // The schemes are sorted with the current one first
+                let (version, algorithm, cost, argon2id_parameters, secret, unicode_normalization) =
+                    config
+                        .load()
+                        .await?
+                        .into_iter()
+                        .next()
+                        .context("No password hashing scheme configured")?;
+
+                let hasher = password_hasher_from_config(
+                    algorithm,
+                    cost,
+                    argon2id_parameters,
+                    secret,
+                    unicode_normalization,
+                )?;
+                let current_params = hasher.argon2id_params();
+
+                let (hash_time, verify_time) = benchmark_hasher(hasher, rounds).await?;
+                info!(
+                    version,
+                    ?algorithm,
+                    hash_ms = hash_time.as_millis(),
+                    verify_ms = verify_time.as_millis(),
+                    "Benchmarked the current password hashing scheme"
+                );
+
+                let Some((memory, iterations, parallelism)) = current_params else {
+                    warn!("Only the argon2id parameters can be tuned, not suggesting any");
+                    return Ok(ExitCode::SUCCESS);
+                };
+
+                let budget = Duration::from_millis(budget_ms);
+                let memory = scale_memory(memory, parallelism, hash_time, budget);
+                let hasher = Hasher::argon2id(None, false).with_argon2id_params(
+                    Some(memory),
+                    Some(iterations),
+                    Some(parallelism),
+                )?;
+
+                let (hash_time, verify_time) = benchmark_hasher(hasher, rounds).await?;
+                info!(
+                    memory,
+                    iterations,
+                    parallelism,
+                    hash_ms = hash_time.as_millis(),
+                    verify_ms = verify_time.as_millis(),
+                    "Suggested argon2id parameters for a {budget_ms}ms budget. Add them as a new hashing scheme with a version higher than {version}, so that existing passwords get rehashed on login"
+                );
+            }
+
+            SC::RenderTemplate {
+                name,
+                context,
+                locale,
+                output,
+            } => {
+                let _span = info_span!("cli.debug.render_template").entered();
+                let names = Templates::template_names();
+                if !names.contains(&name.as_str()) {
+                    bail!(
+                        "Unknown template {name:?}, the available templates are:\n  {}",
+                        names.join("\n  ")
+                    );
+                }
+
+                let raw = tokio::fs::read_to_string(&context)
+                    .await
+                    .with_context(|| format!("could not read {context}"))?;
+                let mut ctx: serde_json::Value = serde_json::from_str(&raw)
+                    .with_context(|| format!("could not parse {context} as JSON"))?;
+                let fields = ctx
+                    .as_object_mut()
+                    .context("The context must be a JSON object")?;
+                match locale {
+                    Some(locale) => {
+                        fields.insert("lang".to_owned(), locale.into());
+                    }
+                    None => {
+                        fields.entry("lang").or_insert_with(|| "en".into());
+                    }
+                }
+
+                // Render like the server does, without the strict mode
+                let templates = load_templates(figment, false).await?;
+                let rendered = templates.render_dynamic(&name, &ctx)?;
+
+                if let Some(output) = output {
+                    info!("Writing the render to {output:?}");
+                    let mut file = tokio::fs::File::create(output).await?;
+                    file.write_all(rendered.as_bytes()).await?;
+                } else {
+                    tokio::io::stdout().write_all(rendered.as_bytes()).await?;
+                }
+            }
         }
 
         Ok(ExitCode::SUCCESS)
     }
 }
+
+/// Hash and verify a password `rounds` times with the given hasher, and return
+/// the average time taken by each operation
+async fn benchmark_hasher(hasher: Hasher, rounds: u32) -> anyhow::Result<(Duration, Duration)> {
+    let manager = PasswordManager::new(0, [(1, hasher)])?;
+    // XXX: we should disallow SeedableRng::from_entropy
+    let mut rng = rand_chacha::ChaChaRng::from_entropy();
+    let password = Zeroizing::new("correct horse battery staple".to_owned());
+    let rounds = rounds.max(1);
+
+    let mut hash_time = Duration::ZERO;
+    let mut verify_time = Duration::ZERO;
+    for _ in 0..rounds {
+        let start = Instant::now();
+        let (version, hashed_password) = manager.hash(&mut rng, password.clone()).await?;
+        hash_time += start.elapsed();
+
+        let start = Instant::now();
+        let result = manager
+            .verify(version, password.clone(), hashed_password)
+            .await?;
+        verify_time += start.elapsed();
+
+        anyhow::ensure!(result.is_success(), "Password verification failed");
+    }
+
+    Ok((hash_time / rounds, verify_time / rounds))
+}
+
+/// The time taken by argon2id grows linearly with its memory size, so scale it
+/// to fit in the budget, without going below the minimum of the algorithm
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn scale_memory(memory: u32, parallelism: u32, measured: Duration, budget: Duration) -> u32 {
+    let ratio = budget.as_secs_f64() / measured.as_secs_f64().max(f64::EPSILON);
+    let scaled = (f64::from(memory) * ratio).min(f64::from(u32::MAX)) as u32;
+    scaled.max(8 * parallelism)
+}
@@ -12,20 +12,58 @@
 use std::process::ExitCode;
 
 use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
 use clap::Parser;
 use figment::Figment;
 use hyper::StatusCode;
-use mas_config::{ConfigurationSection, RootConfig};
+use mas_config::{
+    ConfigurationSection, DatabaseConfig, EmailTransportKind, HomeserverKind, RootConfig,
+    TchapAppConfig,
+};
+use mas_data_model::{Clock as _, EmailProbeResult, SystemClock};
 use mas_http::RequestBuilderExt;
+use mas_storage::RepositoryAccess;
+use mas_storage_pg::PgRepository;
+use sqlx::Connection as _;
 use tracing::{error, info, info_span, warn};
 use url::{Host, Url};
 
+use crate::util::database_connection_from_config;
+
 /// Base URL for the human-readable documentation
 const DOCS_BASE: &str = "https://element-hq.github.io/matrix-authentication-service";
 
+/// How old the last email delivery check can be before we suspect that the
+/// checks don't run anymore. They are scheduled once an hour.
+const EMAIL_PROBE_MAX_AGE: Duration = Duration::hours(2);
+
 #[derive(Parser, Debug)]
 pub(super) struct Options {}
 
+/// The outcome of a single diagnostic check, with the message to show to the
+/// operator
+#[derive(Debug, PartialEq, Eq)]
+enum CheckOutcome {
+    /// The check passed
+    Success(String),
+
+    /// The check found something which might be a problem
+    Warning(String),
+
+    /// The check found a misconfiguration
+    Error(String),
+}
+
+impl CheckOutcome {
+    fn log(&self) {
+        match self {
+            Self::Success(message) => info!("✅ {message}"),
+            Self::Warning(message) => warn!("⚠️ {message}"),
+            Self::Error(message) => error!("❌ {message}"),
+        }
+    }
+}
+
 impl Options {
     pub async fn run(self, figment: &Figment) -> anyhow::Result<ExitCode> {
         let _span = info_span!("cli.doctor").entered();
@@ -45,6 +83,7 @@ impl Options {
 See {DOCS_BASE}/setup/homeserver.html",
         )?;
         let secret = config.matrix.secret().await?;
+        let kind = config.matrix.kind;
         let hs_api = config.matrix.endpoint;
 
         if !issuer.starts_with("https://") {
@@ -280,56 +319,24 @@ Error details: {e}
                 ),
             }
 
-            // Try to reach an authenticated MAS API endpoint
-            let mas_api = hs_api.join("/_synapse/mas/is_localpart_available")?;
-            let result = http_client
-                .get(mas_api.as_str())
-                .bearer_auth(&secret)
-                .send_traced()
-                .await;
-            match result {
-                Ok(response) => {
-                    let status = response.status();
-                    // We intentionally omit the required 'localpart' parameter
-                    // in this request. If authentication is successful, Synapse
-                    // returns a 400 Bad Request because of the missing
-                    // parameter. If authentication fails, Synapse will return a
-                    // 403 Forbidden. If the MAS integration isn't enabled,
-                    // Synapse will return a 404 Not found.
-                    if status == StatusCode::BAD_REQUEST {
-                        info!(
-                            r#"✅ The Synapse MAS API is reachable with authentication at "{mas_api}"."#
-                        );
-                    } else {
-                        error!(
-                            r#"❌ A Synapse MAS API endpoint at "{mas_api}" replied with {status}.
-Make sure the homeserver is running, and that the MAS config has the correct `matrix.secret`.
-It should match the `secret` set in the Synapse config.
+            // Check that the homeserver advertises this MAS as its authorization server
+            check_auth_metadata(&http_client, &hs_api, issuer)
+                .await?
+                .log();
 
-  matrix_authentication_service:
-    enabled: true
-    endpoint: {issuer:?}
-    # This must exactly match the secret in the MAS config:
-    secret: {secret:?}
-
-And in the MAS config:
-
-  matrix:
-    homeserver: "{matrix_domain}"
-    endpoint: "{hs_api}"
-    secret: {secret:?}
-"#
-                        );
-                    }
+            // Check that the credentials MAS uses to talk to the homeserver are accepted
+            let outcome = match kind {
+                HomeserverKind::SynapseLegacy => {
+                    check_legacy_admin_token(&http_client, &hs_api, &secret).await?
                 }
-                Err(e) => error!(
-                    r#"❌ Can't reach the Synapse MAS API at "{mas_api}".
-Make sure the homeserver is running, and that the MAS config has the correct `matrix.secret`.
-
-Error details: {e}
-"#
-                ),
-            }
+                HomeserverKind::Synapse
+                | HomeserverKind::SynapseReadOnly
+                | HomeserverKind::SynapseModern => {
+                    check_mas_api_secret(&http_client, &hs_api, issuer, &matrix_domain, &secret)
+                        .await?
+                }
+            };
+            outcome.log();
         }
 
         let external_cs_api_endpoint = discovered_cs_api.as_ref().unwrap_or(&hs_api);
@@ -400,6 +407,578 @@ Error details: {e}"#
             ),
         }
 
+        // Check that emails could be delivered the last time the worker checked
+        if config.email.probe.enabled
+            && !matches!(config.email.transport(), EmailTransportKind::Blackhole)
+        {
+            let clock = SystemClock::default();
+            let outcome = match latest_email_probe(&config.database).await {
+                Ok(latest) => check_email_probe(latest.as_ref(), clock.now()),
+                Err(e) => CheckOutcome::Warning(format!(
+                    r"Can't load the outcome of the email delivery checks from the database.
+
+Error details: {e:#}
+"
+                )),
+            };
+            outcome.log();
+        }
+
+        //:tchap:
+        // Check that the identity server accepts the configured token
+        let tchap_config = TchapAppConfig::extract(figment).map_err(anyhow::Error::from_boxed)?;
+        if tchap_config.features.identity_server_lookups {
+            let outcome = match tchap_config.identity_server_token().await {
+                Ok(token) => {
+                    check_identity_server_token(
+                        &http_client,
+                        &tchap_config.identity_server_url,
+                        token.as_deref(),
+                    )
+                    .await?
+                }
+                Err(e) => CheckOutcome::Error(format!(
+                    r"Can't read the identity server token from `tchap.identity_server_token_file`.
+
+Error details: {e:#}
+"
+                )),
+            };
+            outcome.log();
+        }
+        //:tchap:end
+
         Ok(ExitCode::SUCCESS)
     }
 }
+
+/// Check that the homeserver advertises the same issuer as the one MAS is
+/// configured with, through the MSC2965 authorization server metadata
+/// endpoint
+async fn check_auth_metadata(
+    http_client: &reqwest::Client,
+    hs_api: &Url,
+    issuer: &str,
+) -> anyhow::Result<CheckOutcome> {
+    let auth_metadata = hs_api.join("/_matrix/client/unstable/org.matrix.msc2965/auth_metadata")?;
+    let result = http_client.get(auth_metadata.as_str()).send_traced().await;
+
+    let response = match result {
+        Ok(response) => response,
+        Err(e) => {
+            return Ok(CheckOutcome::Error(format!(
+                r#"Can't reach the authorization server metadata endpoint at "{auth_metadata}".
+Make sure the homeserver is running.
+
+Error details: {e}
+"#
+            )));
+        }
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        return Ok(CheckOutcome::Error(format!(
+            r#"The homeserver at "{auth_metadata}" replied with {status}.
+This usually means that the MAS integration is not enabled in Synapse.
+Make sure the Synapse config contains:
+
+  matrix_authentication_service:
+    enabled: true
+    endpoint: {issuer:?}
+    # ...
+
+See {DOCS_BASE}/setup/homeserver.html
+"#
+        )));
+    }
+
+    let body = response
+        .json::<serde_json::Value>()
+        .await
+        .unwrap_or_default();
+    let Some(advertised_issuer) = body.get("issuer").and_then(|issuer| issuer.as_str()) else {
+        return Ok(CheckOutcome::Error(format!(
+            r#"The authorization server metadata at "{auth_metadata}" does not have a valid "issuer" field.
+Make sure the homeserver is able to reach MAS at the `endpoint` set in the Synapse config.
+"#
+        )));
+    };
+
+    if advertised_issuer == issuer {
+        Ok(CheckOutcome::Success(format!(
+            r#"The homeserver advertises "{issuer}" as its authorization server at "{auth_metadata}"."#
+        )))
+    } else {
+        Ok(CheckOutcome::Error(format!(
+            r#"The homeserver advertises "{advertised_issuer}" as its authorization server, but MAS is configured with the issuer "{issuer}".
+This means clients will try to log in against the wrong server, and tokens will be rejected.
+Make sure that the MAS config contains:
+
+  http:
+    public_base: {issuer:?}
+
+And that the Synapse config points to this MAS instance:
+
+  matrix_authentication_service:
+    enabled: true
+    endpoint: {issuer:?}
+    # ...
+
+See {DOCS_BASE}/setup/homeserver.html
+"#
+        )))
+    }
+}
+
+/// Load the outcome of the last email delivery check, recorded by the worker
+async fn latest_email_probe(config: &DatabaseConfig) -> anyhow::Result<Option<EmailProbeResult>> {
+    let mut conn = database_connection_from_config(config).await?;
+    let txn = conn.begin().await?;
+    let mut repo = PgRepository::from_conn(txn);
+    let latest = repo.email_probe().latest().await?;
+    repo.into_inner().rollback().await?;
+    Ok(latest)
+}
+
+/// Check that the last email delivery check succeeded, and that it is recent
+/// enough
+fn check_email_probe(latest: Option<&EmailProbeResult>, now: DateTime<Utc>) -> CheckOutcome {
+    let Some(latest) = latest else {
+        return CheckOutcome::Warning(
+            r"No email delivery check was recorded yet.
+The checks run once an hour on the worker, make sure it is running."
+                .to_owned(),
+        );
+    };
+
+    let checked_at = latest.created_at;
+    if let Some(error) = &latest.error {
+        return CheckOutcome::Error(format!(
+            r"The last email delivery check, at {checked_at}, failed.
+This means that emails, like the verification codes, may not be delivered.
+Make sure the settings and credentials in the `email` section of the config are valid.
+
+See {DOCS_BASE}/reference/configuration.html#email
+
+Error details: {error}
+"
+        ));
+    }
+
+    if now - checked_at > EMAIL_PROBE_MAX_AGE {
+        return CheckOutcome::Warning(format!(
+            r"The last email delivery check, at {checked_at}, succeeded, but no check ran since.
+The checks run once an hour on the worker, make sure it is running."
+        ));
+    }
+
+    CheckOutcome::Success(format!(
+        "The last email delivery check, at {checked_at}, succeeded in {latency_ms}ms.",
+        latency_ms = latest.latency_ms,
+    ))
+}
+
+/// Check that the shared secret is accepted by the Synapse MAS API
+async fn check_mas_api_secret(
+    http_client: &reqwest::Client,
+    hs_api: &Url,
+    issuer: &str,
+    matrix_domain: &Host,
+    secret: &str,
+) -> anyhow::Result<CheckOutcome> {
+    let mas_api = hs_api.join("/_synapse/mas/is_localpart_available")?;
+    let result = http_client
+        .get(mas_api.as_str())
+        .bearer_auth(secret)
+        .send_traced()
+        .await;
+
+    let response = match result {
+        Ok(response) => response,
+        Err(e) => {
+            return Ok(CheckOutcome::Error(format!(
+                r#"Can't reach the Synapse MAS API at "{mas_api}".
+Make sure the homeserver is running, and that the MAS config has the correct `matrix.secret`.
+
+Error details: {e}
+"#
+            )));
+        }
+    };
+
+    // We intentionally omit the required 'localpart' parameter in this request.
+    // If authentication is successful, Synapse returns a 400 Bad Request because
+    // of the missing parameter. If authentication fails, Synapse will return a
+    // 403 Forbidden. If the MAS integration isn't enabled, Synapse will return a
+    // 404 Not found.
+    let status = response.status();
+    if status == StatusCode::BAD_REQUEST {
+        Ok(CheckOutcome::Success(format!(
+            r#"The Synapse MAS API is reachable with authentication at "{mas_api}"."#
+        )))
+    } else {
+        Ok(CheckOutcome::Error(format!(
+            r#"A Synapse MAS API endpoint at "{mas_api}" replied with {status}.
+Make sure the homeserver is running, and that the MAS config has the correct `matrix.secret`.
+It should match the `secret` set in the Synapse config.
+
+  matrix_authentication_service:
+    enabled: true
+    endpoint: {issuer:?}
+    # This must exactly match the secret in the MAS config:
+    secret: {secret:?}
+
+And in the MAS config:
+
+  matrix:
+    homeserver: "{matrix_domain}"
+    endpoint: "{hs_api}"
+    secret: {secret:?}
+"#
+        )))
+    }
+}
+
+/// Check that the shared secret is accepted as an admin token by the legacy
+/// Synapse admin API
+async fn check_legacy_admin_token(
+    http_client: &reqwest::Client,
+    hs_api: &Url,
+    secret: &str,
+) -> anyhow::Result<CheckOutcome> {
+    // This endpoint requires admin privileges, but has no side effect
+    let admin_api =
+        hs_api.join("/_synapse/admin/v1/username_available?username=mas-doctor-check")?;
+    let result = http_client
+        .get(admin_api.as_str())
+        .bearer_auth(secret)
+        .send_traced()
+        .await;
+
+    let response = match result {
+        Ok(response) => response,
+        Err(e) => {
+            return Ok(CheckOutcome::Error(format!(
+                r#"Can't reach the Synapse admin API at "{admin_api}".
+Make sure the homeserver is running, and that the admin API is not blocked by your reverse proxy for MAS.
+
+Error details: {e}
+"#
+            )));
+        }
+    };
+
+    // The endpoint replies with a 200 OK if the username is available, and a
+    // 400 Bad Request if it is already taken. Both mean the token was accepted.
+    let status = response.status();
+    match status {
+        StatusCode::OK | StatusCode::BAD_REQUEST => Ok(CheckOutcome::Success(format!(
+            r#"The Synapse admin API is reachable with admin privileges at "{admin_api}"."#
+        ))),
+
+        StatusCode::UNAUTHORIZED => Ok(CheckOutcome::Error(format!(
+            r#"The Synapse admin API at "{admin_api}" replied with {status}.
+This means Synapse did not recognise the `matrix.secret` as a valid token.
+It should match the `admin_token` set in the Synapse config:
+
+  experimental_features:
+    msc3861:
+      enabled: true
+      # This must exactly match the secret in the MAS config:
+      admin_token: {secret:?}
+
+See {DOCS_BASE}/setup/homeserver.html
+"#
+        ))),
+
+        StatusCode::FORBIDDEN => Ok(CheckOutcome::Error(format!(
+            r#"The Synapse admin API at "{admin_api}" replied with {status}.
+This means the `matrix.secret` is a valid token, but it doesn't have admin privileges.
+Make sure the MAS config uses the `admin_token` from the Synapse config, not a regular user access token:
+
+  experimental_features:
+    msc3861:
+      enabled: true
+      admin_token: {secret:?}
+
+See {DOCS_BASE}/setup/homeserver.html
+"#
+        ))),
+
+        _ => Ok(CheckOutcome::Warning(format!(
+            r#"The Synapse admin API at "{admin_api}" replied with {status}.
+Check that the homeserver is running, and that the admin API is reachable from MAS."#
+        ))),
+    }
+}
+
+//:tchap:
+/// Check that the identity server accepts the token MAS is configured with
+async fn check_identity_server_token(
+    http_client: &reqwest::Client,
+    identity_server_url: &Url,
+    token: Option<&str>,
+) -> anyhow::Result<CheckOutcome> {
+    let internal_info = identity_server_url.join("_matrix/identity/api/v1/internal-info")?;
+    let mut request = http_client.get(internal_info.as_str()).query(&[
+        ("medium", "email"),
+        ("address", "mas-doctor-check@example.invalid"),
+    ]);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+
+    let response = match request.send_traced().await {
+        Ok(response) => response,
+        Err(e) => {
+            return Ok(CheckOutcome::Error(format!(
+                r#"Can't reach the identity server at "{internal_info}".
+Make sure the identity server is running, and that `tchap.identity_server_url` is correct.
+
+Error details: {e}
+"#
+            )));
+        }
+    };
+
+    let status = response.status();
+    match status {
+        status if status.is_success() => Ok(CheckOutcome::Success(format!(
+            r#"The identity server is reachable at "{internal_info}"."#
+        ))),
+
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN if token.is_some() => {
+            Ok(CheckOutcome::Error(format!(
+                r#"The identity server at "{internal_info}" replied with {status}.
+This means the identity server did not accept the configured token: it may have expired or been rotated.
+Update `tchap.identity_server_token` or the content of `tchap.identity_server_token_file`."#
+            )))
+        }
+
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Ok(CheckOutcome::Error(format!(
+            r#"The identity server at "{internal_info}" replied with {status}.
+This means the identity server requires a token, but none is configured.
+Set either `tchap.identity_server_token` or `tchap.identity_server_token_file`."#
+        ))),
+
+        _ => Ok(CheckOutcome::Warning(format!(
+            r#"The identity server at "{internal_info}" replied with {status}.
+Check that the identity server is running, and that it is reachable from MAS."#
+        ))),
+    }
+}
+//:tchap:end
+
+#[cfg(test)]
+mod tests {
+    use mas_data_model::{EmailProbeKind, Ulid};
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method, path},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_auth_metadata_wrong_issuer() {
+        let mock_server = MockServer::start().await;
+        let hs_api = Url::parse(&mock_server.uri()).unwrap();
+        let http_client = mas_http::reqwest_client();
+
+        Mock::given(method("GET"))
+            .and(path(
+                "/_matrix/client/unstable/org.matrix.msc2965/auth_metadata",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "issuer": "https://other-auth.example.com/",
+            })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = check_auth_metadata(&http_client, &hs_api, "https://auth.example.com/")
+            .await
+            .unwrap();
+        assert!(
+            matches!(&outcome, CheckOutcome::Error(message) if message.contains("https://other-auth.example.com/")),
+            "{outcome:?}"
+        );
+
+        let outcome = check_auth_metadata(&http_client, &hs_api, "https://other-auth.example.com/")
+            .await
+            .unwrap();
+        assert!(matches!(outcome, CheckOutcome::Success(_)), "{outcome:?}");
+    }
+
+    #[tokio::test]
+    async fn test_legacy_admin_token_unauthorized() {
+        let mock_server = MockServer::start().await;
+        let hs_api = Url::parse(&mock_server.uri()).unwrap();
+        let http_client = mas_http::reqwest_client();
+
+        Mock::given(method("GET"))
+            .and(path("/_synapse/admin/v1/username_available"))
+            .and(header("authorization", "Bearer good-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "available": true,
+            })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_synapse/admin/v1/username_available"))
+            .and(header("authorization", "Bearer user-token"))
+            .respond_with(ResponseTemplate::new(403).set_body_json(serde_json::json!({
+                "errcode": "M_FORBIDDEN",
+                "error": "You are not a server admin",
+            })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_synapse/admin/v1/username_available"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+                "errcode": "M_UNKNOWN_TOKEN",
+                "error": "Invalid access token passed.",
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let outcome = check_legacy_admin_token(&http_client, &hs_api, "good-token")
+            .await
+            .unwrap();
+        assert!(matches!(outcome, CheckOutcome::Success(_)), "{outcome:?}");
+
+        let outcome = check_legacy_admin_token(&http_client, &hs_api, "user-token")
+            .await
+            .unwrap();
+        assert!(
+            matches!(&outcome, CheckOutcome::Error(message) if message.contains("admin privileges")),
+            "{outcome:?}"
+        );
+
+        let outcome = check_legacy_admin_token(&http_client, &hs_api, "wrong-token")
+            .await
+            .unwrap();
+        assert!(
+            matches!(&outcome, CheckOutcome::Error(message) if message.contains("did not recognise")),
+            "{outcome:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mas_api_secret_unauthorized() {
+        let mock_server = MockServer::start().await;
+        let hs_api = Url::parse(&mock_server.uri()).unwrap();
+        let http_client = mas_http::reqwest_client();
+
+        Mock::given(method("GET"))
+            .and(path("/_synapse/mas/is_localpart_available"))
+            .and(header("authorization", "Bearer good-secret"))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_synapse/mas/is_localpart_available"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&mock_server)
+            .await;
+
+        let issuer = "https://auth.example.com/";
+        let matrix_domain = Host::Domain("example.com".to_owned());
+
+        let outcome =
+            check_mas_api_secret(&http_client, &hs_api, issuer, &matrix_domain, "good-secret")
+                .await
+                .unwrap();
+        assert!(matches!(outcome, CheckOutcome::Success(_)), "{outcome:?}");
+
+        let outcome =
+            check_mas_api_secret(&http_client, &hs_api, issuer, &matrix_domain, "bad-secret")
+                .await
+                .unwrap();
+        assert!(
+            matches!(&outcome, CheckOutcome::Error(message)
+                if message.contains(r#"endpoint: "https://auth.example.com/""#)
+                    && message.contains(r#"homeserver: "example.com""#)),
+            "{outcome:?}"
+        );
+    }
+
+    #[test]
+    fn test_email_probe() {
+        let now = DateTime::UNIX_EPOCH + Duration::days(365);
+        let mut result = EmailProbeResult {
+            id: Ulid::nil(),
+            created_at: now - Duration::minutes(30),
+            kind: EmailProbeKind::Message,
+            latency_ms: 120,
+            error: None,
+        };
+
+        let outcome = check_email_probe(None, now);
+        assert!(matches!(outcome, CheckOutcome::Warning(_)), "{outcome:?}");
+
+        let outcome = check_email_probe(Some(&result), now);
+        assert!(matches!(outcome, CheckOutcome::Success(_)), "{outcome:?}");
+
+        // A successful check which is too old means the worker may be down
+        result.created_at = now - Duration::hours(3);
+        let outcome = check_email_probe(Some(&result), now);
+        assert!(matches!(outcome, CheckOutcome::Warning(_)), "{outcome:?}");
+
+        result.error = Some("Connection refused".to_owned());
+        let outcome = check_email_probe(Some(&result), now);
+        assert!(
+            matches!(&outcome, CheckOutcome::Error(message) if message.contains("Connection refused")),
+            "{outcome:?}"
+        );
+    }
+
+    //:tchap:
+    #[tokio::test]
+    async fn test_identity_server_token() {
+        let mock_server = MockServer::start().await;
+        let identity_server_url = Url::parse(&(mock_server.uri() + "/")).unwrap();
+        let http_client = mas_http::reqwest_client();
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/identity/api/v1/internal-info"))
+            .and(header("authorization", "Bearer good-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/identity/api/v1/internal-info"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&mock_server)
+            .await;
+
+        let outcome =
+            check_identity_server_token(&http_client, &identity_server_url, Some("good-token"))
+                .await
+                .unwrap();
+        assert!(matches!(outcome, CheckOutcome::Success(_)), "{outcome:?}");
+
+        let outcome =
+            check_identity_server_token(&http_client, &identity_server_url, Some("old-token"))
+                .await
+                .unwrap();
+        assert!(
+            matches!(&outcome, CheckOutcome::Error(message) if message.contains("did not accept")),
+            "{outcome:?}"
+        );
+
+        let outcome = check_identity_server_token(&http_client, &identity_server_url, None)
+            .await
+            .unwrap();
+        assert!(
+            matches!(&outcome, CheckOutcome::Error(message) if message.contains("none is configured")),
+            "{outcome:?}"
+        );
+    }
+    //:tchap:end
+}
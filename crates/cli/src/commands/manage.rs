@@ -7,16 +7,26 @@
 use std::{collections::BTreeMap, process::ExitCode};
 
 use anyhow::Context;
+use camino::Utf8PathBuf;
 use chrono::Duration;
 use clap::{ArgAction, CommandFactory, Parser};
 use console::{Alignment, Style, Term, pad_str, style};
 use dialoguer::{Confirm, FuzzySelect, Input, Password, theme::ColorfulTheme};
 use figment::Figment;
 use mas_config::{
-    ConfigurationSection, ConfigurationSectionExt, DatabaseConfig, MatrixConfig, PasswordsConfig,
+    AccountConfig, AuditWebhookConfig, BrandingConfig, CaptchaConfig, ConfigurationSection,
+    ConfigurationSectionExt, DatabaseConfig, EmailConfig, EmailsConfig, ExperimentalConfig,
+    LoginConfig, MatrixConfig, OpenIdConfig, PasswordsConfig, PolicyConfig, RateLimitingConfig,
+    SessionsConfig,
+};
+use mas_data_model::{
+    AuthenticatedBy, Clock, Device, SiteConfig, SystemClock, TokenType, Ulid,
+    UpstreamOAuthProvider, User,
 };
-use mas_data_model::{Clock, Device, SystemClock, TokenType, Ulid, UpstreamOAuthProvider, User};
 use mas_email::Address;
+use mas_handlers::user_actions::{
+    AddEmailError, RevokedSessions, add_email, lock_user, revoke_all_sessions, unlock_user,
+};
 use mas_matrix::HomeserverConnection;
 use mas_storage::{
     Pagination, RepositoryAccess,
@@ -24,7 +34,7 @@ use mas_storage::{
     oauth2::OAuth2SessionFilter,
     queue::{
         DeactivateUserJob, ProvisionUserJob, QueueJobRepositoryExt as _, ReactivateUserJob,
-        SyncDevicesJob,
+        ReconcileProvisioningJob, SyncDevicesJob,
     },
     user::{
         BrowserSessionFilter, UserEmailRepository, UserFilter, UserPasswordRepository,
@@ -40,11 +50,14 @@ use sqlx::{Acquire, types::Uuid};
 use tracing::{error, info, info_span, warn};
 use zeroize::Zeroizing;
 
+use self::import_users::{ImportStatus, Importer};
 use crate::util::{
-    database_connection_from_config, homeserver_connection_from_config,
-    password_manager_from_config,
+    database_connection_from_config, database_pool_from_config, homeserver_connection_from_config,
+    password_manager_from_config, policy_factory_from_config, site_config_from_config,
 };
 
+mod import_users;
+
 const USER_ATTRIBUTES_HEADING: &str = "User attributes";
 
 #[derive(Debug, Clone)]
@@ -75,8 +88,19 @@ enum Subcommand {
     /// Add an email address to the specified user
     AddEmail { username: String, email: String },
 
-    /// (DEPRECATED) Mark email address as verified
-    VerifyEmail { username: String, email: String },
+    /// Add an email address to a user, as a verified address
+    ///
+    /// This does the same checks as the admin API: the address must be valid
+    /// and not used by any other user.
+    VerifyEmail {
+        /// User to which the email address belongs
+        #[arg(long = "user")]
+        username: String,
+
+        /// Email address to verify
+        #[arg(long)]
+        email: String,
+    },
 
     /// Set a user password
     SetPassword {
@@ -137,6 +161,16 @@ enum Subcommand {
     /// Trigger a provisioning job for all users
     ProvisionAllUsers,
 
+    /// Compare the users with their state on the homeserver
+    ///
+    /// This schedules a job which reports the discrepancies it finds. The
+    /// report can be retrieved through the admin API once the job completed.
+    ReconcileProvisioning {
+        /// Provision the active users which are missing on the homeserver
+        #[arg(long)]
+        fix: bool,
+    },
+
     /// Kill all sessions for a user
     KillSessions {
         /// User for which to kill sessions
@@ -155,6 +189,10 @@ enum Subcommand {
         /// Whether to deactivate the user
         #[arg(long)]
         deactivate: bool,
+
+        /// Whether to also end all the sessions of the user
+        #[arg(long)]
+        end_sessions: bool,
     },
 
     /// Unlock a user
@@ -167,6 +205,13 @@ enum Subcommand {
         reactivate: bool,
     },
 
+    /// End all the sessions of a user, and revoke their OAuth 2.0 tokens and
+    /// the personal sessions acting as them
+    RevokeAllSessions {
+        /// User for which to revoke the sessions
+        username: String,
+    },
+
     /// Register a user
     ///
     /// This will interactively prompt for the user's attributes unless the
@@ -216,6 +261,35 @@ enum Subcommand {
         #[clap(long)]
         ignore_password_complexity: bool,
     },
+
+    /// Import users from a CSV file
+    ///
+    /// The file must have a header row with a `username` column, and
+    /// optionally `email`, `displayname` and `password` columns. Each row is
+    /// checked against the registration policy. A password is generated for
+    /// rows without one, and written to the results.
+    ImportUsers {
+        /// Path to the CSV file to import
+        #[arg(long)]
+        csv: Utf8PathBuf,
+
+        /// Path where to write the results CSV. Defaults to the standard output
+        #[arg(long)]
+        output: Option<Utf8PathBuf>,
+
+        /// Number of users to create in each database transaction
+        #[arg(long, default_value_t = 100)]
+        batch_size: usize,
+
+        /// Validate the file without creating any user
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Don't enforce that the passwords provided are above the minimum
+        /// configured complexity.
+        #[clap(long)]
+        ignore_password_complexity: bool,
+    },
 }
 
 impl Options {
@@ -320,8 +394,37 @@ impl Options {
                 )
                 .entered();
 
-                tracing::warn!(
-                    "The 'verify-email' command is deprecated and will be removed in a future version. Use 'add-email' instead."
+                let database_config = DatabaseConfig::extract_or_default(figment)
+                    .map_err(anyhow::Error::from_boxed)?;
+                let pool = database_pool_from_config(&database_config).await?;
+                let mut repo = PgRepository::from_pool(&pool).await?.boxed();
+
+                let Some(user) = repo.user().find_by_username(&username).await? else {
+                    error!("User not found");
+                    return Ok(ExitCode::from(1));
+                };
+
+                if let Some(email) = repo.user_email().find(&user, &email).await? {
+                    info!(%user.id, %email.id, %email.email, "Email already verified");
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                let email = match add_email(&mut repo, &mut rng, &clock, &user, email).await {
+                    Ok(email) => email,
+                    Err(AddEmailError::Repository(e)) => return Err(e.into()),
+                    Err(e) => {
+                        error!("{e}");
+                        return Ok(ExitCode::from(1));
+                    }
+                };
+
+                repo.save().await?;
+                info!(
+                    %user.id,
+                    %user.username,
+                    %email.id,
+                    %email.email,
+                    "Email verified"
                 );
 
                 Ok(ExitCode::SUCCESS)
@@ -427,7 +530,7 @@ impl Options {
                     .context("User not found")?;
 
                 let device = if let Some(device_id) = device_id {
-                    device_id.into()
+                    Device::try_from(device_id).context("Invalid device ID")?
                 } else {
                     Device::generate(&mut rng)
                 };
@@ -454,6 +557,10 @@ impl Options {
                     .compat_session()
                     .add(&mut rng, &clock, &user, device, None, admin, None)
                     .await?;
+                let compat_session = repo
+                    .compat_session()
+                    .set_authenticated_by(compat_session, AuthenticatedBy::AdminIssued)
+                    .await?;
 
                 let token = TokenType::CompatAccessToken.generate(&mut rng);
 
@@ -543,6 +650,32 @@ impl Options {
                 Ok(ExitCode::SUCCESS)
             }
 
+            SC::ReconcileProvisioning { fix } => {
+                let _span = info_span!("cli.manage.reconcile_provisioning").entered();
+                let database_config = DatabaseConfig::extract_or_default(figment)
+                    .map_err(anyhow::Error::from_boxed)?;
+                let mut conn = database_connection_from_config(&database_config).await?;
+                let txn = conn.begin().await?;
+                let mut repo = PgRepository::from_conn(txn);
+
+                let report = repo
+                    .provisioning_report()
+                    .add(&mut rng, &clock, fix)
+                    .await?;
+
+                info!(
+                    provisioning_report.id = %report.id,
+                    "Scheduling provisioning reconciliation, the report will be available through the admin API"
+                );
+                repo.queue_job()
+                    .schedule_job(&mut rng, &clock, ReconcileProvisioningJob::new(&report))
+                    .await?;
+
+                repo.into_inner().commit().await?;
+
+                Ok(ExitCode::SUCCESS)
+            }
+
             SC::KillSessions { username, dry_run } => {
                 let _span =
                     info_span!("cli.manage.kill_sessions", user.username = username).entered();
@@ -617,26 +750,32 @@ impl Options {
             SC::LockUser {
                 username,
                 deactivate,
+                end_sessions,
             } => {
                 let _span = info_span!("cli.manage.lock_user", user.username = username).entered();
                 let config = DatabaseConfig::extract_or_default(figment)
                     .map_err(anyhow::Error::from_boxed)?;
-                let mut conn = database_connection_from_config(&config).await?;
-                let txn = conn.begin().await?;
-                let mut repo = PgRepository::from_conn(txn);
+                let site_config = site_config_from_figment(figment)?;
+                let pool = database_pool_from_config(&config).await?;
+                let mut repo = PgRepository::from_pool(&pool).await?.boxed();
 
-                let user = repo
-                    .user()
-                    .find_by_username(&username)
-                    .await?
-                    .context("User not found")?;
+                let Some(user) = repo.user().find_by_username(&username).await? else {
+                    error!("User not found");
+                    return Ok(ExitCode::from(1));
+                };
 
                 info!(%user.id, "Locking user");
 
                 // Even though the deactivation job will lock the user, we lock it here in case
                 // the worker is not running, as we don't have a good way to run a job
                 // synchronously yet.
-                let user = repo.user().lock(&clock, user).await?;
+                let user = lock_user(&mut repo, &mut rng, &clock, &site_config, user).await?;
+
+                if end_sessions {
+                    let revoked =
+                        revoke_all_sessions(&mut repo, &mut rng, &clock, &user, None).await?;
+                    print_revoked_sessions(&revoked);
+                }
 
                 if deactivate {
                     warn!(%user.id, "Scheduling user deactivation");
@@ -645,7 +784,8 @@ impl Options {
                         .await?;
                 }
 
-                repo.into_inner().commit().await?;
+                repo.save().await?;
+                info!(%user.id, %user.username, "User locked");
 
                 Ok(ExitCode::SUCCESS)
             }
@@ -658,15 +798,13 @@ impl Options {
                     info_span!("cli.manage.unlock_user", user.username = username).entered();
                 let config = DatabaseConfig::extract_or_default(figment)
                     .map_err(anyhow::Error::from_boxed)?;
-                let mut conn = database_connection_from_config(&config).await?;
-                let txn = conn.begin().await?;
-                let mut repo = PgRepository::from_conn(txn);
+                let pool = database_pool_from_config(&config).await?;
+                let mut repo = PgRepository::from_pool(&pool).await?.boxed();
 
-                let user = repo
-                    .user()
-                    .find_by_username(&username)
-                    .await?
-                    .context("User not found")?;
+                let Some(user) = repo.user().find_by_username(&username).await? else {
+                    error!("User not found");
+                    return Ok(ExitCode::from(1));
+                };
 
                 if reactivate {
                     warn!(%user.id, "Scheduling user reactivation");
@@ -674,10 +812,32 @@ impl Options {
                         .schedule_job(&mut rng, &clock, ReactivateUserJob::new(&user))
                         .await?;
                 } else {
-                    repo.user().unlock(user).await?;
+                    let user = unlock_user(&mut repo, user).await?;
+                    info!(%user.id, %user.username, "User unlocked");
                 }
 
-                repo.into_inner().commit().await?;
+                repo.save().await?;
+
+                Ok(ExitCode::SUCCESS)
+            }
+
+            SC::RevokeAllSessions { username } => {
+                let _span = info_span!("cli.manage.revoke_all_sessions", user.username = username)
+                    .entered();
+                let config = DatabaseConfig::extract_or_default(figment)
+                    .map_err(anyhow::Error::from_boxed)?;
+                let pool = database_pool_from_config(&config).await?;
+                let mut repo = PgRepository::from_pool(&pool).await?.boxed();
+
+                let Some(user) = repo.user().find_by_username(&username).await? else {
+                    error!("User not found");
+                    return Ok(ExitCode::from(1));
+                };
+
+                let revoked = revoke_all_sessions(&mut repo, &mut rng, &clock, &user, None).await?;
+                print_revoked_sessions(&revoked);
+
+                repo.save().await?;
 
                 Ok(ExitCode::SUCCESS)
             }
@@ -928,6 +1088,84 @@ impl Options {
 
                 Ok(ExitCode::SUCCESS)
             }
+
+            SC::ImportUsers {
+                csv,
+                output,
+                batch_size,
+                dry_run,
+                ignore_password_complexity,
+            } => {
+                let _span = info_span!("cli.manage.import_users", csv = %csv, dry_run).entered();
+
+                let http_client = mas_http::reqwest_client();
+                let password_config = PasswordsConfig::extract_or_default(figment)
+                    .map_err(anyhow::Error::from_boxed)?;
+                let database_config = DatabaseConfig::extract_or_default(figment)
+                    .map_err(anyhow::Error::from_boxed)?;
+                let policy_config =
+                    PolicyConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+                let matrix_config =
+                    MatrixConfig::extract(figment).map_err(anyhow::Error::from_boxed)?;
+
+                let file =
+                    std::fs::File::open(&csv).with_context(|| format!("Failed to open {csv}"))?;
+                let rows = import_users::read_rows(file)?;
+                info!("Read {} rows from {csv}", rows.len());
+
+                let password_manager = password_manager_from_config(&password_config).await?;
+                let policy_factory =
+                    policy_factory_from_config(&policy_config, &matrix_config).await?;
+                let policy = policy_factory.instantiate().await?;
+                let homeserver =
+                    homeserver_connection_from_config(&matrix_config, http_client).await?;
+                let mut conn = database_connection_from_config(&database_config).await?;
+
+                let mut importer = Importer {
+                    homeserver: &homeserver,
+                    policy,
+                    password_manager,
+                    ignore_password_complexity,
+                    batch_size,
+                    dry_run,
+                };
+                let results = importer.run(&mut conn, &mut rng, &clock, rows).await?;
+
+                if let Some(output) = output {
+                    let file = std::fs::File::create(&output)
+                        .with_context(|| format!("Failed to create {output}"))?;
+                    import_users::write_results(file, &results)?;
+                } else {
+                    import_users::write_results(std::io::stdout().lock(), &results)?;
+                }
+
+                let count = |status: ImportStatus| {
+                    results
+                        .iter()
+                        .filter(|result| result.status() == status)
+                        .count()
+                };
+                let invalid = count(ImportStatus::Invalid);
+                let failed = count(ImportStatus::Failed);
+                if dry_run {
+                    info!(
+                        valid = count(ImportStatus::Valid),
+                        invalid, "Dry run finished"
+                    );
+                } else {
+                    info!(
+                        created = count(ImportStatus::Created),
+                        invalid, failed, "Import finished"
+                    );
+                }
+
+                if invalid + failed > 0 {
+                    warn!("Some rows could not be imported, check the results for details");
+                    return Ok(ExitCode::from(1));
+                }
+
+                Ok(ExitCode::SUCCESS)
+            }
         }
     }
 }
@@ -949,6 +1187,38 @@ impl std::fmt::Display for HumanReadable<&UpstreamOAuthProvider> {
     }
 }
 
+/// Build the site configuration from the sections relevant to it, for the
+/// operations which need it
+fn site_config_from_figment(figment: &Figment) -> anyhow::Result<SiteConfig> {
+    site_config_from_config(
+        &BrandingConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &MatrixConfig::extract(figment).map_err(anyhow::Error::from_boxed)?,
+        &ExperimentalConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &PasswordsConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &AccountConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &EmailsConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &LoginConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &CaptchaConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &RateLimitingConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &AuditWebhookConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &OpenIdConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &SessionsConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+        &EmailConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?,
+    )
+}
+
+fn print_revoked_sessions(revoked: &RevokedSessions) {
+    info!(
+        "Ended {} compatibility, {} OAuth 2.0 and {} browser sessions, and revoked {} personal sessions, {} access and {} refresh tokens",
+        revoked.compat_sessions,
+        revoked.oauth2_sessions,
+        revoked.browser_sessions,
+        revoked.personal_sessions,
+        revoked.oauth2_access_tokens,
+        revoked.oauth2_refresh_tokens,
+    );
+}
+
 async fn check_and_normalize_username<'a>(
     localpart_or_mxid: &'a str,
     repo: &mut dyn RepositoryAccess<Error = DatabaseError>,
@@ -1119,23 +1389,20 @@ impl UserCreationRequest<'_> {
                 .await?;
         }
 
-        for email in emails {
-            repo.user_email()
-                .add(rng, clock, &user, email.to_string())
-                .await?;
-        }
-
-        for (provider, subject) in upstream_provider_mappings {
-            // Note that we don't pass a human_account_name here, as we don't ask for it
-            let link = repo
-                .upstream_oauth_link()
-                .add(rng, clock, provider, subject, None)
-                .await?;
-
-            repo.upstream_oauth_link()
-                .associate_to_user(&link, &user)
-                .await?;
-        }
+        let emails: Vec<_> = emails
+            .into_iter()
+            .map(|email| (&user, email.to_string()))
+            .collect();
+        repo.user_email().add_many(rng, clock, &emails).await?;
+
+        // Note that the links don't have a human_account_name, as we don't ask for it
+        let links: Vec<_> = upstream_provider_mappings
+            .into_iter()
+            .map(|(provider, subject)| (provider, &user, subject))
+            .collect();
+        repo.upstream_oauth_link()
+            .add_many(rng, clock, &links)
+            .await?;
 
         if let Some(admin) = admin {
             user = repo.user().set_can_request_admin(user, admin).await?;
@@ -1242,3 +1509,63 @@ impl std::fmt::Display for UserCreationCommand<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use figment::providers::Serialized;
+    use mas_storage::user::BrowserSessionRepository;
+    use sqlx::PgPool;
+
+    use super::*;
+
+    /// Build a configuration which points to the test database
+    fn figment(pool: &PgPool) -> Figment {
+        let mut uri = url::Url::parse(&std::env::var("DATABASE_URL").unwrap()).unwrap();
+        uri.set_path(pool.connect_options().get_database().unwrap());
+
+        Figment::new().merge(Serialized::defaults(serde_json::json!({
+            "database": { "uri": uri.as_str() },
+            "matrix": { "secret": "test" },
+        })))
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_lock_user_end_sessions(pool: PgPool) {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let clock = SystemClock::default();
+
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+        let user = repo
+            .user()
+            .add(&mut rng, &clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let session = repo
+            .browser_session()
+            .add(&mut rng, &clock, &user, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let options =
+            Options::try_parse_from(["manage", "lock-user", "alice", "--end-sessions"]).unwrap();
+        let code = Box::pin(options.run(&figment(&pool))).await.unwrap();
+        assert_eq!(code, ExitCode::SUCCESS);
+
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert!(user.locked_at.is_some());
+        let session = repo
+            .browser_session()
+            .lookup(session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(session.finished_at.is_some());
+
+        // Unknown users are reported with a non-zero exit code
+        let options = Options::try_parse_from(["manage", "lock-user", "bob"]).unwrap();
+        let code = Box::pin(options.run(&figment(&pool))).await.unwrap();
+        assert_eq!(code, ExitCode::from(1));
+    }
+}
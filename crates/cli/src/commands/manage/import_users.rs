@@ -0,0 +1,549 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Bulk import of users from a CSV file
+
+use std::collections::HashSet;
+
+use anyhow::Context;
+use mas_data_model::Clock;
+use mas_email::Address;
+use mas_handlers::passwords::PasswordManager;
+use mas_matrix::HomeserverConnection;
+use mas_policy::{Policy, RegisterInput, RegistrationMethod, Requester};
+use mas_storage::{RepositoryAccess, user::UserEmailRepository as _};
+use mas_storage_pg::{DatabaseError, PgRepository};
+use rand::{
+    CryptoRng, RngCore,
+    distributions::{Alphanumeric, DistString as _},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Acquire, PgConnection, Postgres, Transaction};
+use tracing::{error, info};
+use zeroize::Zeroizing;
+
+use super::{UserCreationRequest, check_and_normalize_username};
+
+/// Length of the passwords generated for rows without one
+const GENERATED_PASSWORD_LENGTH: usize = 24;
+
+/// A row of the CSV file to import
+#[derive(Debug, Deserialize)]
+pub(super) struct ImportRow {
+    /// The line of the row in the CSV file
+    #[serde(skip)]
+    line: u64,
+
+    username: String,
+
+    #[serde(default)]
+    email: Option<String>,
+
+    #[serde(default, rename = "displayname")]
+    display_name: Option<String>,
+
+    #[serde(default)]
+    password: Option<String>,
+}
+
+/// Parse the rows to import out of a CSV file
+///
+/// The file must have a header row. Only the `username` column is required,
+/// the `email`, `displayname` and `password` columns are optional and can be
+/// left empty.
+pub(super) fn read_rows<R: std::io::Read>(reader: R) -> anyhow::Result<Vec<ImportRow>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader
+        .headers()
+        .context("Failed to read the CSV header")?
+        .clone();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.context("Failed to read the CSV file")?;
+        let line = record.position().map_or(0, csv::Position::line);
+        let mut row: ImportRow = record
+            .deserialize(Some(&headers))
+            .with_context(|| format!("Invalid row on line {line}"))?;
+        row.line = line;
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// The outcome of the import of a single row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum ImportStatus {
+    /// The user was created
+    Created,
+
+    /// The row is valid, but nothing was written because of the dry run
+    Valid,
+
+    /// The row did not pass validation
+    Invalid,
+
+    /// The row was valid, but writing it to the database failed
+    Failed,
+}
+
+/// A row of the results CSV file
+#[derive(Debug, Serialize)]
+pub(super) struct ImportResult {
+    line: u64,
+    username: String,
+    status: ImportStatus,
+    error: Option<String>,
+
+    /// The generated password, if the row did not have one
+    generated_password: Option<String>,
+}
+
+impl ImportResult {
+    pub(super) const fn status(&self) -> ImportStatus {
+        self.status
+    }
+}
+
+/// Write the results of an import as CSV
+pub(super) fn write_results<W: std::io::Write>(
+    writer: W,
+    results: &[ImportResult],
+) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for result in results {
+        writer.serialize(result)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A row which passed validation
+struct ValidatedRow {
+    line: u64,
+    localpart: String,
+    email: Option<Address>,
+    display_name: Option<String>,
+    password: Option<Zeroizing<String>>,
+    generated_password: bool,
+}
+
+/// Imports users, validating each of them against the registration policy
+pub(super) struct Importer<'a> {
+    pub homeserver: &'a dyn HomeserverConnection,
+    pub policy: Policy,
+    pub password_manager: PasswordManager,
+    pub ignore_password_complexity: bool,
+    pub batch_size: usize,
+    pub dry_run: bool,
+}
+
+impl Importer<'_> {
+    /// Validate and import the given rows
+    ///
+    /// Each batch of rows is written in its own transaction. If writing a batch
+    /// fails, all the valid rows of that batch are reported as failed, but
+    /// previous batches are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database could not be reached or the policy
+    /// could not be evaluated
+    pub(super) async fn run<R: RngCore + CryptoRng + Send>(
+        &mut self,
+        conn: &mut PgConnection,
+        rng: &mut R,
+        clock: &dyn Clock,
+        rows: Vec<ImportRow>,
+    ) -> anyhow::Result<Vec<ImportResult>> {
+        let mut results = Vec::with_capacity(rows.len());
+        let mut seen_localparts = HashSet::new();
+
+        for batch in rows.chunks(self.batch_size.max(1)) {
+            let txn = conn.begin().await?;
+            let mut repo = PgRepository::from_conn(txn);
+
+            let mut validated = Vec::with_capacity(batch.len());
+            for row in batch {
+                match self
+                    .validate(&mut repo, rng, &mut seen_localparts, row)
+                    .await?
+                {
+                    Ok(row) => validated.push(row),
+                    Err(message) => results.push(ImportResult {
+                        line: row.line,
+                        username: row.username.clone(),
+                        status: ImportStatus::Invalid,
+                        error: Some(message),
+                        generated_password: None,
+                    }),
+                }
+            }
+
+            let (status, error) = if self.dry_run {
+                // Dropping the repository rolls back the transaction
+                (ImportStatus::Valid, None)
+            } else {
+                match self.register(repo, rng, clock, &validated).await {
+                    Ok(()) => (ImportStatus::Created, None),
+                    Err(e) => {
+                        error!(error = ?e, "Failed to import a batch of users");
+                        (ImportStatus::Failed, Some(e.to_string()))
+                    }
+                }
+            };
+
+            for row in validated {
+                let generated_password =
+                    if status == ImportStatus::Created && row.generated_password {
+                        row.password.map(|password| String::clone(&password))
+                    } else {
+                        None
+                    };
+
+                results.push(ImportResult {
+                    line: row.line,
+                    username: row.localpart,
+                    status,
+                    error: error.clone(),
+                    generated_password,
+                });
+            }
+        }
+
+        results.sort_by_key(|result| result.line);
+        Ok(results)
+    }
+
+    /// Validate a single row
+    ///
+    /// The outer result is for unexpected errors, the inner one for validation
+    /// errors.
+    async fn validate<R: RngCore + CryptoRng + Send>(
+        &mut self,
+        repo: &mut dyn RepositoryAccess<Error = DatabaseError>,
+        rng: &mut R,
+        seen_localparts: &mut HashSet<String>,
+        row: &ImportRow,
+    ) -> anyhow::Result<Result<ValidatedRow, String>> {
+        let localpart =
+            match check_and_normalize_username(&row.username, repo, self.homeserver).await {
+                Ok(localpart) => localpart.to_owned(),
+                Err(e) => return Ok(Err(e.to_string())),
+            };
+
+        if seen_localparts.contains(&localpart) {
+            return Ok(Err("Username appears more than once in the file".to_owned()));
+        }
+
+        let email = match &row.email {
+            Some(email) => match email.parse::<Address>() {
+                Ok(email) => Some(email),
+                Err(_) => return Ok(Err("Invalid email address".to_owned())),
+            },
+            None => None,
+        };
+
+        let (password, generated_password) = match &row.password {
+            Some(_) if !self.password_manager.is_enabled() => {
+                return Ok(Err("Password authentication is disabled".to_owned()));
+            }
+            Some(password)
+                if !self.ignore_password_complexity
+                    && !self.password_manager.is_password_complex_enough(password)? =>
+            {
+                return Ok(Err("Password is too weak".to_owned()));
+            }
+            Some(password) => (Some(Zeroizing::new(password.clone())), false),
+            // Only generate passwords when they are going to be written
+            None if self.password_manager.is_enabled() && !self.dry_run => {
+                let password = Alphanumeric.sample_string(rng, GENERATED_PASSWORD_LENGTH);
+                (Some(Zeroizing::new(password)), true)
+            }
+            None => (None, false),
+        };
+
+        let res = self
+            .policy
+            .evaluate_register(RegisterInput {
+                registration_method: RegistrationMethod::Password,
+                username: &localpart,
+                email: email.as_ref().map(AsRef::as_ref),
+                organization: None,
+                can_request_admin: None,
+                requester: Requester::default(),
+            })
+            .await?;
+
+        if !res.valid() {
+            return Ok(Err(res.to_string()));
+        }
+
+        seen_localparts.insert(localpart.clone());
+
+        Ok(Ok(ValidatedRow {
+            line: row.line,
+            localpart,
+            email,
+            display_name: row.display_name.clone(),
+            password,
+            generated_password,
+        }))
+    }
+
+    /// Register a batch of validated rows in a single transaction
+    async fn register<R: RngCore + CryptoRng + Send>(
+        &self,
+        mut repo: PgRepository<Transaction<'_, Postgres>>,
+        rng: &mut R,
+        clock: &dyn Clock,
+        rows: &[ValidatedRow],
+    ) -> anyhow::Result<()> {
+        let mut users = Vec::with_capacity(rows.len());
+        for row in rows {
+            let hashed_password = match &row.password {
+                Some(password) => Some(
+                    self.password_manager
+                        .hash(&mut *rng, password.clone())
+                        .await?,
+                ),
+                None => None,
+            };
+
+            // The emails are added afterwards for the whole batch at once. This
+            // is fine, as the provisioning job only runs once the transaction is
+            // committed.
+            let req = UserCreationRequest {
+                username: row.localpart.clone(),
+                hashed_password,
+                emails: Vec::new(),
+                upstream_provider_mappings: Vec::new(),
+                display_name: row.display_name.clone(),
+                admin: None,
+            };
+
+            let user = req.do_register(&mut repo, rng, clock).await?;
+            info!(%user.id, %user.username, "User imported");
+            users.push(user);
+        }
+
+        let (lines, emails): (Vec<_>, Vec<_>) = rows
+            .iter()
+            .zip(&users)
+            .filter_map(|(row, user)| Some((row.line, (user, row.email.as_ref()?.to_string()))))
+            .unzip();
+
+        if let Err(e) = repo.user_email().add_many(rng, clock, &emails).await {
+            let context = match e.batch_index() {
+                Some(index) => format!("Failed to add the email of line {}", lines[index]),
+                None => "Failed to add the emails".to_owned(),
+            };
+            return Err(anyhow::Error::new(e).context(context));
+        }
+
+        repo.into_inner().commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_data_model::clock::MockClock;
+    use mas_handlers::passwords::Hasher;
+    use mas_matrix::MockHomeserverConnection;
+    use mas_policy::{Entrypoints, PolicyFactory};
+    use mas_storage::user::{UserEmailRepository, UserPasswordRepository, UserRepository};
+    use rand::SeedableRng;
+    use sqlx::PgPool;
+
+    use super::*;
+
+    async fn policy() -> Policy {
+        let workspace_root = camino::Utf8Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("..");
+
+        let file = tokio::fs::File::open(workspace_root.join("policies").join("policy.wasm"))
+            .await
+            .unwrap();
+
+        let entrypoints = Entrypoints {
+            register: "register/violation".to_owned(),
+            client_registration: "client_registration/violation".to_owned(),
+            authorization_grant: "authorization_grant/violation".to_owned(),
+            email: "email/violation".to_owned(),
+            account_recovery: "account_recovery/violation".to_owned(),
+        };
+
+        let data = mas_policy::Data::new("example.com".to_owned());
+        let factory = PolicyFactory::load(file, data, entrypoints).await.unwrap();
+        factory.instantiate().await.unwrap()
+    }
+
+    fn password_manager() -> PasswordManager {
+        PasswordManager::new(0, [(1, Hasher::argon2id(None, false))]).unwrap()
+    }
+
+    fn fixture() -> Vec<ImportRow> {
+        read_rows(include_str!("../../../tests/import-users.csv").as_bytes()).unwrap()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_import_users(pool: PgPool) {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let homeserver = MockHomeserverConnection::new("example.com");
+        let mut conn = pool.acquire().await.unwrap();
+
+        let mut importer = Importer {
+            homeserver: &homeserver,
+            policy: policy().await,
+            password_manager: password_manager(),
+            ignore_password_complexity: false,
+            batch_size: 2,
+            dry_run: false,
+        };
+
+        let results = importer
+            .run(&mut conn, &mut rng, &clock, fixture())
+            .await
+            .unwrap();
+
+        let statuses: Vec<_> = results.iter().map(ImportResult::status).collect();
+        assert_eq!(
+            statuses,
+            [
+                ImportStatus::Created,
+                ImportStatus::Created,
+                ImportStatus::Invalid,
+                ImportStatus::Created,
+            ]
+        );
+
+        // The bad row is reported with the policy violation
+        assert_eq!(results[2].line, 4);
+        assert!(
+            results[2]
+                .error
+                .as_deref()
+                .unwrap()
+                .contains("invalid characters")
+        );
+
+        // Only the row without a password gets a generated one
+        assert!(results[0].generated_password.is_none());
+        let generated_password = results[1].generated_password.clone().unwrap();
+        assert_eq!(generated_password.len(), GENERATED_PASSWORD_LENGTH);
+
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+        let alice = repo
+            .user()
+            .find_by_username("alice")
+            .await
+            .unwrap()
+            .unwrap();
+        let emails = repo.user_email().all(&alice).await.unwrap();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].email, "alice@example.com");
+        assert!(repo.user_password().active(&alice).await.unwrap().is_some());
+
+        let bob = repo.user().find_by_username("bob").await.unwrap().unwrap();
+        assert!(repo.user_password().active(&bob).await.unwrap().is_some());
+
+        let dave = repo.user().find_by_username("dave").await.unwrap().unwrap();
+        assert!(repo.user_email().all(&dave).await.unwrap().is_empty());
+
+        // A provisioning job was scheduled for each created user
+        let jobs: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM queue_jobs WHERE queue_name = 'provision-user'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(jobs, 3);
+
+        // The results CSV has a line per row
+        let mut output = Vec::new();
+        write_results(&mut output, &results).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines();
+        assert_eq!(
+            lines.next(),
+            Some("line,username,status,error,generated_password")
+        );
+        assert_eq!(lines.next(), Some("2,alice,created,,"));
+        assert_eq!(
+            lines.next(),
+            Some(format!("3,bob,created,,{generated_password}").as_str())
+        );
+        assert!(lines.next().unwrap().starts_with("4,Carol Smith,invalid,"));
+        assert_eq!(lines.next(), Some("5,dave,created,,"));
+        assert_eq!(lines.next(), None);
+
+        // Importing the same file again reports the existing users as invalid
+        let results = importer
+            .run(&mut conn, &mut rng, &clock, fixture())
+            .await
+            .unwrap();
+        assert!(
+            results
+                .iter()
+                .all(|result| result.status() == ImportStatus::Invalid)
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_import_users_dry_run(pool: PgPool) {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let homeserver = MockHomeserverConnection::new("example.com");
+        let mut conn = pool.acquire().await.unwrap();
+
+        let mut importer = Importer {
+            homeserver: &homeserver,
+            policy: policy().await,
+            password_manager: password_manager(),
+            ignore_password_complexity: false,
+            batch_size: 100,
+            dry_run: true,
+        };
+
+        let results = importer
+            .run(&mut conn, &mut rng, &clock, fixture())
+            .await
+            .unwrap();
+
+        let statuses: Vec<_> = results.iter().map(ImportResult::status).collect();
+        assert_eq!(
+            statuses,
+            [
+                ImportStatus::Valid,
+                ImportStatus::Valid,
+                ImportStatus::Invalid,
+                ImportStatus::Valid,
+            ]
+        );
+        assert!(
+            results
+                .iter()
+                .all(|result| result.generated_password.is_none())
+        );
+
+        // Nothing was written
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+        assert!(!repo.user().exists("alice").await.unwrap());
+        assert!(!repo.user().exists("bob").await.unwrap());
+        assert!(!repo.user().exists("dave").await.unwrap());
+
+        let jobs: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM queue_jobs")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(jobs, 0);
+    }
+}
@@ -19,17 +19,21 @@ use mas_config::{
     TchapAppConfig,
     // :tchap: end
     UpstreamOAuth2Config,
+    find_unknown_keys,
 };
 use mas_context::LogContext;
 use mas_data_model::{
+    //:tchap:end
+    Clock,
     //:tchap:
+    EmailCheckCacheTtls,
     EmailLookupFallbackRule,
-    //:tchap:end
+    IdentityServerToken,
     SystemClock,
     //:tchap:
     TchapConfig, // :tchap: end
 };
-use mas_handlers::{ActivityTracker, CookieManager, Limiter, MetadataCache};
+use mas_handlers::{ActivityTracker, ClaimsAugmentors, Limiter, MetadataCache};
 use mas_listener::server::Server;
 use mas_router::UrlBuilder;
 use mas_storage_pg::{MIGRATOR, PgRepositoryFactory};
@@ -40,13 +44,16 @@ use crate::{
     app_state::AppState,
     lifecycle::LifecycleManager,
     util::{
-        database_pool_from_config, homeserver_connection_from_config,
-        load_policy_factory_dynamic_data_continuously, mailer_from_config,
-        password_manager_from_config, policy_factory_from_config, site_config_from_config,
-        templates_from_config, test_mailer_in_background,
+        cookie_manager_from_config, database_pool_from_config, homeserver_connection_from_config,
+        install_user_agent_parser, load_policy_factory_dynamic_data_continuously,
+        mailer_from_config, password_manager_from_config, policy_factory_from_config,
+        site_config_from_config, tchap_features_from_tchap_app_config, templates_from_config,
+        test_mailer_in_background,
     },
 };
 
+mod preflight;
+
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Parser, Debug, Default)]
 pub(super) struct Options {
@@ -66,6 +73,14 @@ pub(super) struct Options {
     /// Do not sync the configuration with the database
     #[arg(long)]
     no_sync: bool,
+
+    /// Refuse to start if the configuration has unknown keys, instead of
+    /// logging warnings
+    #[arg(long)]
+    strict_config: bool,
+
+    #[command(flatten)]
+    preflight: preflight::Options,
 }
 
 impl Options {
@@ -78,7 +93,26 @@ impl Options {
             TchapAppConfig::extract(figment).map_err(anyhow::Error::from_boxed)?;
         //:tchap: end
 
+        let unknown_keys = find_unknown_keys(figment)?;
+        if !unknown_keys.is_empty() {
+            for key in &unknown_keys {
+                warn!(
+                    key = %key.path,
+                    source = %key.source,
+                    "Unknown configuration key, it will be ignored. Check for typos!"
+                );
+            }
+
+            if self.strict_config {
+                anyhow::bail!(
+                    "Found {} unknown configuration key(s), refusing to start because of `--strict-config`",
+                    unknown_keys.len()
+                );
+            }
+        }
+
         info!(version = crate::VERSION, "Starting up");
+        let started_at = SystemClock::default().now();
 
         if self.migrate {
             warn!(
@@ -143,7 +177,8 @@ impl Options {
             .await
             .context("could not import keys from config")?;
 
-        let cookie_manager = CookieManager::derive_from(
+        let cookie_manager = cookie_manager_from_config(
+            &config.http.cookies,
             config.http.public_base.clone(),
             &config.secrets.encryption().await?,
         );
@@ -165,7 +200,16 @@ impl Options {
             config.http.public_base.clone(),
             config.http.issuer.clone(),
             None,
-        );
+        )
+        .with_issuer_aliases(config.http.issuer_aliases.clone());
+
+        if !config.http.issuer_aliases.is_empty() {
+            warn!(
+                issuer = %url_builder.oidc_issuer(),
+                aliases = %config.http.issuer_aliases.iter().join(", "),
+                "Accepting tokens from legacy issuers. Remove `http.issuer_aliases` once clients have migrated to the new issuer."
+            );
+        }
 
         // Load the site configuration
         let site_config = site_config_from_config(
@@ -174,11 +218,21 @@ impl Options {
             &config.experimental,
             &config.passwords,
             &config.account,
+            &config.emails,
+            &config.login,
             &config.captcha,
+            &config.rate_limiting,
+            &config.audit_webhook,
+            &config.openid,
+            &config.sessions,
+            &config.email,
         )?;
 
+        install_user_agent_parser(&config.experimental)?;
+
         //:tchap:
         let tchap_config = tchap_config_from_tchap_app_config(&tchap_app_config);
+        let tchap_features = tchap_features_from_tchap_app_config(&tchap_app_config);
         //:tchap: end
 
         // Load and compile the templates
@@ -197,6 +251,12 @@ impl Options {
         let homeserver_connection =
             homeserver_connection_from_config(&config.matrix, http_client.clone()).await?;
 
+        // Check that the homeserver, the policy and the templates work before starting
+        // anything which depends on them
+        self.preflight
+            .run(homeserver_connection.as_ref(), &policy_factory, &templates)
+            .await?;
+
         if !self.no_worker {
             let mailer = mailer_from_config(&config.email, &templates)?;
             test_mailer_in_background(&mailer, Duration::from_secs(30));
@@ -209,6 +269,7 @@ impl Options {
                 homeserver_connection.clone(),
                 url_builder.clone(),
                 &site_config,
+                &tchap_features,
                 shutdown.soft_shutdown_token(),
                 shutdown.task_tracker(),
             )
@@ -234,6 +295,7 @@ impl Options {
         shutdown.register_reloadable(&activity_tracker);
 
         let trusted_proxies = config.http.trusted_proxies.clone();
+        let compression = config.http.compression.clone();
 
         // Build a rate limiter.
         // This should not raise an error here as the config should already have been
@@ -254,6 +316,10 @@ impl Options {
             password_manager.clone(),
             url_builder.clone(),
             limiter.clone(),
+            templates.clone(),
+            //:tchap:
+            tchap_config.clone(),
+            //:tchap: end
         );
 
         let state = {
@@ -274,8 +340,11 @@ impl Options {
                 activity_tracker,
                 trusted_proxies,
                 limiter,
+                claims_augmentors: ClaimsAugmentors::from_tchap_features(&tchap_features),
+                started_at,
                 //:tchap:
                 tchap_config,
+                tchap_features,
                 //:tchap:end
             };
             s.init_metrics();
@@ -283,13 +352,23 @@ impl Options {
             s
         };
 
+        // Read the names of the file descriptors before `ListenFd` takes over the
+        // environment
+        let fd_names = crate::server::listen_fd_names();
         let mut fd_manager = listenfd::ListenFd::from_env();
 
+        // Keep track of the binds, to cleanup the UNIX sockets on shutdown
+        let binds: Vec<_> = listeners_config
+            .iter()
+            .flat_map(|config| config.binds.iter().cloned())
+            .collect();
+
         let servers: Vec<Server<_>> = listeners_config
             .into_iter()
             .map(|config| {
                 // Let's first grab all the listeners
-                let listeners = crate::server::build_listeners(&mut fd_manager, &config.binds)?;
+                let listeners =
+                    crate::server::build_listeners(&mut fd_manager, &fd_names, &config.binds)?;
 
                 // Load the TLS config
                 let tls_config = if let Some(tls_config) = config.tls.as_ref() {
@@ -305,6 +384,7 @@ impl Options {
                     &config.resources,
                     config.prefix.as_deref(),
                     config.name.as_deref(),
+                    &compression,
                 );
 
 
@@ -362,6 +442,8 @@ impl Options {
 
         let exit_code = shutdown.run().await;
 
+        crate::server::remove_unix_sockets(&binds);
+
         Ok(exit_code)
     }
 }
@@ -370,6 +452,14 @@ impl Options {
 fn tchap_config_from_tchap_app_config(tchap_app_config: &TchapAppConfig) -> TchapConfig {
     TchapConfig {
         identity_server_url: tchap_app_config.identity_server_url.clone(),
+        identity_server_token: match (
+            &tchap_app_config.identity_server_token,
+            &tchap_app_config.identity_server_token_file,
+        ) {
+            (Some(token), _) => Some(IdentityServerToken::Inline(token.clone())),
+            (None, Some(path)) => Some(IdentityServerToken::File(path.clone())),
+            (None, None) => None,
+        },
         email_lookup_fallback_rules: tchap_app_config
             .email_lookup_fallback_rules
             .iter()
@@ -378,6 +468,18 @@ fn tchap_config_from_tchap_app_config(tchap_app_config: &TchapAppConfig) -> Tcha
                 search: rule.search.clone(),
             })
             .collect(),
+        additional_server_names: tchap_app_config.additional_server_names.clone(),
+        domain_labels: tchap_app_config
+            .domain_labels
+            .iter()
+            .map(|(domain, label)| (domain.to_lowercase(), label.clone()))
+            .collect(),
+        email_check_cache: EmailCheckCacheTtls {
+            allowed: tchap_app_config.email_check_cache.allowed,
+            wrong_server: tchap_app_config.email_check_cache.wrong_server,
+            invitation_missing: tchap_app_config.email_check_cache.invitation_missing,
+            error: tchap_app_config.email_check_cache.error,
+        },
     }
 }
 //:tchap: end
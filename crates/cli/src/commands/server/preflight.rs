@@ -0,0 +1,267 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Checks run on startup, before binding the listeners, to catch obvious
+//! misconfigurations early
+
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use mas_matrix::HomeserverConnection;
+use mas_policy::PolicyFactory;
+use mas_templates::{ErrorContext, Templates};
+use tracing::{info, warn};
+
+/// The localpart used to check that the homeserver answers our requests.
+/// Whether it is available or not doesn't matter, only that the request
+/// succeeds.
+const PREFLIGHT_LOCALPART: &str = "mas-preflight-check";
+
+/// What to do when one of the startup checks fails
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) enum OnFailure {
+    /// Refuse to start
+    #[default]
+    Fail,
+
+    /// Log a warning and start anyway. Useful in high-availability setups,
+    /// where the homeserver may be temporarily unreachable.
+    Warn,
+}
+
+#[derive(Args, Debug, Default)]
+pub(super) struct Options {
+    /// Do not check that the homeserver connection, the policy and the
+    /// templates work before starting
+    #[arg(long)]
+    skip_preflight: bool,
+
+    /// What to do when one of the startup checks fails
+    #[arg(long, value_enum, default_value_t)]
+    preflight_on_failure: OnFailure,
+}
+
+impl Options {
+    /// Run the startup checks
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing every failed check if one of them failed, and
+    /// the server is configured to refuse to start in that case
+    pub async fn run(
+        &self,
+        homeserver: &dyn HomeserverConnection,
+        policy_factory: &PolicyFactory,
+        templates: &Templates,
+    ) -> anyhow::Result<()> {
+        if self.skip_preflight {
+            info!("Skipping startup checks");
+            return Ok(());
+        }
+
+        info!("Running startup checks");
+
+        let failures: Vec<anyhow::Error> = [
+            check_homeserver(homeserver).await,
+            check_policy(policy_factory).await,
+            check_templates(templates),
+        ]
+        .into_iter()
+        .filter_map(Result::err)
+        .collect();
+
+        if failures.is_empty() {
+            return Ok(());
+        }
+
+        match self.preflight_on_failure {
+            OnFailure::Fail => {
+                let details = failures
+                    .iter()
+                    .map(|error| format!("  - {error:#}"))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                anyhow::bail!(
+                    "{} startup check(s) failed, refusing to start:\n{details}\nFix the configuration, or use `--preflight-on-failure=warn` to start anyway.",
+                    failures.len(),
+                );
+            }
+
+            OnFailure::Warn => {
+                for error in &failures {
+                    warn!(error = ?error, "Startup check failed, starting anyway");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Check that the homeserver is reachable and accepts our shared secret, by
+/// making a cheap read request
+async fn check_homeserver(homeserver: &dyn HomeserverConnection) -> anyhow::Result<()> {
+    homeserver
+        .is_localpart_available(PREFLIGHT_LOCALPART)
+        .await
+        .with_context(|| {
+            format!(
+                "Could not query the homeserver for {:?}. Check that it is running, and that `matrix.endpoint` and `matrix.secret` are correct",
+                homeserver.homeserver(),
+            )
+        })?;
+
+    Ok(())
+}
+
+/// Check that the policy can be instantiated
+async fn check_policy(policy_factory: &PolicyFactory) -> anyhow::Result<()> {
+    policy_factory.instantiate().await.context(
+        "Could not instantiate the policy. Check the `policy.wasm_module` and `policy.data` settings",
+    )?;
+
+    Ok(())
+}
+
+/// Check that the templates render, using the error page as a smoke test
+fn check_templates(templates: &Templates) -> anyhow::Result<()> {
+    let locale: mas_i18n::DataLocale = mas_i18n::locale!("en").into();
+    let ctx = ErrorContext::new().with_language(&locale);
+    templates.render_error(&ctx).context(
+        "Could not render the error page. Check the `templates.path`, `templates.assets_manifest` and `templates.translations_path` settings",
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8Path;
+    use mas_matrix::MockHomeserverConnection;
+    use mas_matrix_synapse::SynapseConnection;
+    use mas_router::UrlBuilder;
+    use mas_templates::{SiteBranding, SiteFeatures};
+    use url::Url;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    use super::*;
+
+    async fn load_policy_factory() -> PolicyFactory {
+        let workspace_root = Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let file = tokio::fs::File::open(workspace_root.join("policies/policy.wasm"))
+            .await
+            .unwrap();
+        let entrypoints = mas_policy::Entrypoints {
+            register: "register/violation".to_owned(),
+            client_registration: "client_registration/violation".to_owned(),
+            authorization_grant: "authorization_grant/violation".to_owned(),
+            email: "email/violation".to_owned(),
+            account_recovery: "account_recovery/violation".to_owned(),
+        };
+        let data = mas_policy::Data::new("example.com".to_owned());
+        PolicyFactory::load(file, data, entrypoints).await.unwrap()
+    }
+
+    async fn load_templates() -> Templates {
+        let workspace_root = Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let url_builder = UrlBuilder::new("https://example.com/".parse().unwrap(), None, None);
+        let features = SiteFeatures {
+            password_login: true,
+            password_registration: true,
+            password_registration_email_required: true,
+            account_recovery: true,
+            login_with_email_allowed: true,
+        };
+        Templates::load(
+            workspace_root.join("templates"),
+            url_builder,
+            workspace_root.join("frontend/dist/manifest.json"),
+            workspace_root.join("translations"),
+            SiteBranding::new("example.com"),
+            features,
+            true,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Start a fake homeserver which rejects our shared secret
+    async fn rejecting_homeserver(expected_calls: u64) -> (MockServer, SynapseConnection) {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/_synapse/mas/is_localpart_available"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+                "errcode": "M_UNKNOWN_TOKEN",
+                "error": "Invalid access token",
+            })))
+            .expect(expected_calls)
+            .mount(&mock_server)
+            .await;
+
+        let connection = SynapseConnection::new(
+            "example.com".to_owned(),
+            Url::parse(&mock_server.uri()).unwrap(),
+            "wrong-secret".to_owned(),
+            mas_http::reqwest_client(),
+        );
+
+        (mock_server, connection)
+    }
+
+    #[tokio::test]
+    async fn test_preflight_pass() {
+        let homeserver = MockHomeserverConnection::new("example.com");
+        let policy_factory = load_policy_factory().await;
+        let templates = load_templates().await;
+
+        Options::default()
+            .run(&homeserver, &policy_factory, &templates)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_preflight_fail() {
+        let (_mock_server, homeserver) = rejecting_homeserver(2).await;
+        let policy_factory = load_policy_factory().await;
+        let templates = load_templates().await;
+
+        let error = Options::default()
+            .run(&homeserver, &policy_factory, &templates)
+            .await
+            .unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("1 startup check(s) failed"), "{message}");
+        assert!(message.contains("`matrix.secret`"), "{message}");
+
+        // In degraded mode, the failure is only logged
+        let options = Options {
+            preflight_on_failure: OnFailure::Warn,
+            ..Options::default()
+        };
+        options
+            .run(&homeserver, &policy_factory, &templates)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_preflight_skip() {
+        // The homeserver must not be called at all
+        let (_mock_server, homeserver) = rejecting_homeserver(0).await;
+        let policy_factory = load_policy_factory().await;
+        let templates = load_templates().await;
+
+        let options = Options {
+            skip_preflight: true,
+            ..Options::default()
+        };
+        options
+            .run(&homeserver, &policy_factory, &templates)
+            .await
+            .unwrap();
+    }
+}
@@ -13,7 +13,7 @@ use mas_config::{
     ConfigurationSection, ConfigurationSectionExt, DatabaseConfig, MatrixConfig, SyncConfig,
     UpstreamOAuth2Config,
 };
-use mas_data_model::SystemClock;
+use mas_data_model::{Clock as _, SystemClock, Ulid};
 use mas_storage_pg::MIGRATOR;
 use rand::thread_rng;
 use sqlx::{Connection, Either, PgConnection, postgres::PgConnectOptions, types::Uuid};
@@ -241,14 +241,26 @@ impl Options {
                     }))
                     .instrument(tracing::info_span!("syn2mas.mas_writer_connections"))
                     .await?;
-                let writer =
-                    MasWriter::new(mas_connection, writer_mas_connections, dry_run).await?;
 
                 let clock = SystemClock::default();
                 // TODO is this rng ok?
                 #[allow(clippy::disallowed_methods)]
                 let mut rng = thread_rng();
 
+                let run_id = Uuid::from(Ulid::from_datetime_with_source(
+                    clock.now().into(),
+                    &mut rng,
+                ));
+                info!(%run_id, "Recording syn2mas run");
+                let writer = MasWriter::new(
+                    mas_connection,
+                    writer_mas_connections,
+                    dry_run,
+                    run_id,
+                    &clock,
+                )
+                .await?;
+
                 let progress = Progress::default();
 
                 let occasional_progress_logger_task =
@@ -11,10 +11,13 @@ use camino::Utf8PathBuf;
 use clap::Parser;
 use figment::Figment;
 use mas_config::{
-    AccountConfig, BrandingConfig, CaptchaConfig, ConfigurationSection, ConfigurationSectionExt,
-    ExperimentalConfig, MatrixConfig, PasswordsConfig, TemplatesConfig,
+    AccountConfig, AuditWebhookConfig, BrandingConfig, CaptchaConfig, ConfigurationSection,
+    ConfigurationSectionExt, EmailConfig, EmailsConfig, ExperimentalConfig, LoginConfig,
+    MatrixConfig, OpenIdConfig, PasswordsConfig, RateLimitingConfig, SessionsConfig,
+    TemplatesConfig,
 };
 use mas_data_model::{Clock, SystemClock};
+use mas_templates::Templates;
 use rand::SeedableRng;
 use tracing::info_span;
 
@@ -44,41 +47,11 @@ impl Options {
             SC::Check { out_dir } => {
                 let _span = info_span!("cli.templates.check").entered();
 
-                let template_config = TemplatesConfig::extract_or_default(figment)
-                    .map_err(anyhow::Error::from_boxed)?;
-                let branding_config = BrandingConfig::extract_or_default(figment)
-                    .map_err(anyhow::Error::from_boxed)?;
-                let matrix_config =
-                    MatrixConfig::extract(figment).map_err(anyhow::Error::from_boxed)?;
-                let experimental_config = ExperimentalConfig::extract_or_default(figment)
-                    .map_err(anyhow::Error::from_boxed)?;
-                let password_config = PasswordsConfig::extract_or_default(figment)
-                    .map_err(anyhow::Error::from_boxed)?;
-                let account_config = AccountConfig::extract_or_default(figment)
-                    .map_err(anyhow::Error::from_boxed)?;
-                let captcha_config = CaptchaConfig::extract_or_default(figment)
-                    .map_err(anyhow::Error::from_boxed)?;
-
                 let clock = SystemClock::default();
                 // XXX: we should disallow SeedableRng::from_entropy
                 let mut rng = rand_chacha::ChaChaRng::from_entropy();
-                let url_builder =
-                    mas_router::UrlBuilder::new("https://example.com/".parse()?, None, None);
-                let site_config = site_config_from_config(
-                    &branding_config,
-                    &matrix_config,
-                    &experimental_config,
-                    &password_config,
-                    &account_config,
-                    &captcha_config,
-                )?;
-                let templates = templates_from_config(
-                    &template_config,
-                    &site_config,
-                    &url_builder, // Use strict mode in template checks
-                    true,
-                )
-                .await?;
+                // Use strict mode in template checks
+                let templates = load_templates(figment, true).await?;
                 let all_renders = templates.check_render(clock.now(), &mut rng)?;
 
                 if let Some(out_dir) = out_dir {
@@ -126,3 +99,53 @@ impl Options {
         }
     }
 }
+
+/// Load the templates as configured, with a placeholder base URL
+#[expect(clippy::similar_names, reason = "the email and emails sections are distinct")]
+pub(super) async fn load_templates(figment: &Figment, strict: bool) -> anyhow::Result<Templates> {
+    let template_config =
+        TemplatesConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let branding_config =
+        BrandingConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let matrix_config = MatrixConfig::extract(figment).map_err(anyhow::Error::from_boxed)?;
+    let experimental_config =
+        ExperimentalConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let password_config =
+        PasswordsConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let account_config =
+        AccountConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let emails_config =
+        EmailsConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let login_config =
+        LoginConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let captcha_config =
+        CaptchaConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let rate_limiting_config =
+        RateLimitingConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let audit_webhook_config =
+        AuditWebhookConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let openid_config =
+        OpenIdConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let sessions_config =
+        SessionsConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+    let email_config =
+        EmailConfig::extract_or_default(figment).map_err(anyhow::Error::from_boxed)?;
+
+    let url_builder = mas_router::UrlBuilder::new("https://example.com/".parse()?, None, None);
+    let site_config = site_config_from_config(
+        &branding_config,
+        &matrix_config,
+        &experimental_config,
+        &password_config,
+        &account_config,
+        &emails_config,
+        &login_config,
+        &captcha_config,
+        &rate_limiting_config,
+        &audit_webhook_config,
+        &openid_config,
+        &sessions_config,
+        &email_config,
+    )?;
+    templates_from_config(&template_config, &site_config, &url_builder, strict).await
+}
@@ -8,7 +8,7 @@ use std::{process::ExitCode, time::Duration};
 
 use clap::Parser;
 use figment::Figment;
-use mas_config::{AppConfig, ConfigurationSection};
+use mas_config::{AppConfig, ConfigurationSection, TchapAppConfig};
 use mas_data_model::SystemClock;
 use mas_router::UrlBuilder;
 use mas_storage_pg::PgRepositoryFactory;
@@ -17,8 +17,9 @@ use tracing::{info, info_span};
 use crate::{
     lifecycle::LifecycleManager,
     util::{
-        database_pool_from_config, homeserver_connection_from_config, mailer_from_config,
-        site_config_from_config, templates_from_config, test_mailer_in_background,
+        database_pool_from_config, homeserver_connection_from_config, install_user_agent_parser,
+        mailer_from_config, site_config_from_config, tchap_features_from_tchap_app_config,
+        templates_from_config, test_mailer_in_background,
     },
 };
 
@@ -30,6 +31,11 @@ impl Options {
         let shutdown = LifecycleManager::new()?;
         let span = info_span!("cli.worker.init").entered();
         let config = AppConfig::extract(figment).map_err(anyhow::Error::from_boxed)?;
+        //:tchap:
+        let tchap_app_config =
+            TchapAppConfig::extract(figment).map_err(anyhow::Error::from_boxed)?;
+        let tchap_features = tchap_features_from_tchap_app_config(&tchap_app_config);
+        //:tchap:end
 
         // Connect to the database
         info!("Connecting to the database");
@@ -48,9 +54,18 @@ impl Options {
             &config.experimental,
             &config.passwords,
             &config.account,
+            &config.emails,
+            &config.login,
             &config.captcha,
+            &config.rate_limiting,
+            &config.audit_webhook,
+            &config.openid,
+            &config.sessions,
+            &config.email,
         )?;
 
+        install_user_agent_parser(&config.experimental)?;
+
         // Load and compile the templates
         let templates = templates_from_config(
             &config.templates,
@@ -77,6 +92,7 @@ impl Options {
             conn,
             url_builder,
             &site_config,
+            &tchap_features,
             shutdown.soft_shutdown_token(),
             shutdown.task_tracker(),
         )
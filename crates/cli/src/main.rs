@@ -30,6 +30,13 @@ mod util;
 /// The application version, as reported by `git describe` at build time
 static VERSION: &str = env!("VERGEN_GIT_DESCRIBE");
 
+/// The git commit the application was built from, if it was known at build
+/// time
+static GIT_SHA: Option<&str> = option_env!("VERGEN_GIT_SHA");
+
+/// The version of the Rust compiler used to build the application
+static RUSTC_VERSION: Option<&str> = option_env!("VERGEN_RUSTC_SEMVER");
+
 #[derive(Debug)]
 struct SentryTransportFactory {
     client: reqwest::Client,
@@ -5,8 +5,9 @@
 // Please see LICENSE files in the repository root for full details.
 
 use std::{
+    collections::BTreeSet,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, ToSocketAddrs},
-    os::unix::net::UnixListener,
+    os::unix::{fs::PermissionsExt as _, net::UnixListener},
     time::Duration,
 };
 
@@ -18,10 +19,10 @@ use axum::{
 use headers::{CacheControl, HeaderMapExt as _, UserAgent};
 use hyper::{Method, Request, Response, StatusCode, Version, header::USER_AGENT};
 use listenfd::ListenFd;
-use mas_config::{HttpBindConfig, HttpResource, HttpTlsConfig, UnixOrTcp};
+use mas_config::{HttpBindConfig, HttpCompressionConfig, HttpResource, HttpTlsConfig, UnixOrTcp};
 use mas_context::LogContext;
 use mas_listener::{ConnectionInfo, unix_or_tcp::UnixOrTcpListener};
-use mas_router::Route;
+use mas_router::{Route, UrlBuilder};
 use mas_templates::Templates;
 use mas_tower::{
     DurationRecorderLayer, InFlightCounterLayer, KV, TraceLayer, make_span_fn,
@@ -220,9 +221,11 @@ pub fn build_router(
     resources: &[HttpResource],
     prefix: Option<&str>,
     name: Option<&str>,
+    compression: &HttpCompressionConfig,
 ) -> Router<()> {
     let templates = Templates::from_ref(&state);
     let mut router = Router::new();
+    let mut assets = None;
 
     for resource in resources {
         router = match resource {
@@ -236,7 +239,7 @@ pub fn build_router(
                 router.merge(mas_handlers::discovery_router::<AppState>())
             }
             mas_config::HttpResource::Human => {
-                router.merge(mas_handlers::human_router::<AppState>(templates.clone()))
+                router.merge(mas_handlers::human_router::<AppState>())
             }
             mas_config::HttpResource::GraphQL {
                 playground,
@@ -271,10 +274,10 @@ pub fn build_router(
                     },
                 );
 
-                router.nest_service(
-                    mas_router::StaticAsset::route(),
-                    add_cache_headers.layer(static_service),
-                )
+                // The assets are mounted after the compression layer is added,
+                // as they are already served precompressed
+                assets = Some(add_cache_headers.layer(static_service));
+                router
             }
             mas_config::HttpResource::OAuth => router.merge(mas_handlers::api_router::<AppState>()),
             mas_config::HttpResource::Compat => {
@@ -294,6 +297,14 @@ pub fn build_router(
         }
     }
 
+    if compression.enabled {
+        router = router.layer(mas_handlers::compression_layer(compression.min_size));
+    }
+
+    if let Some(assets) = assets {
+        router = router.nest_service(mas_router::StaticAsset::route(), assets);
+    }
+
     // We normalize the prefix:
     //  - if it's None, it becomes '/'
     //  - if it's Some(..), any trailing '/' is first trimmed, then a '/' is added
@@ -305,9 +316,37 @@ pub fn build_router(
         router = Router::new().nest(&prefix, router);
     }
 
+    // Also serve the JWKS under the base path of the issuer aliases, as clients
+    // still configured with a legacy issuer may fetch the keys from there
+    if resources
+        .iter()
+        .any(|resource| matches!(resource, HttpResource::OAuth))
+    {
+        let url_builder = UrlBuilder::from_ref(&state);
+        let alias_prefixes: BTreeSet<String> = url_builder
+            .oidc_issuer_aliases()
+            .iter()
+            .map(|alias| format!("{}/", alias.path().trim_end_matches('/')))
+            .filter(|alias_prefix| *alias_prefix != prefix)
+            .collect();
+
+        for alias_prefix in alias_prefixes {
+            router = if alias_prefix == "/" {
+                router.merge(mas_handlers::jwks_router::<AppState>())
+            } else {
+                router.nest(&alias_prefix, mas_handlers::jwks_router::<AppState>())
+            };
+        }
+    }
+
     router = router.fallback(mas_handlers::fallback);
 
     router
+        .layer(axum::middleware::from_fn(
+            move |request: axum::extract::Request, next: axum::middleware::Next| {
+                mas_handlers::negotiate_error_format(templates.clone(), request, next)
+            },
+        ))
         .layer(axum::middleware::from_fn(log_response_middleware))
         .layer(
             InFlightCounterLayer::new("http.server.active_requests").on_request((
@@ -359,8 +398,17 @@ pub fn build_tls_server_config(config: &HttpTlsConfig) -> Result<ServerConfig, a
     Ok(config)
 }
 
+/// Get the names of the file descriptors passed by systemd, as set in the
+/// `LISTEN_FDNAMES` environment variable
+pub fn listen_fd_names() -> Vec<String> {
+    std::env::var("LISTEN_FDNAMES")
+        .map(|names| names.split(':').map(ToOwned::to_owned).collect())
+        .unwrap_or_default()
+}
+
 pub fn build_listeners(
     fd_manager: &mut ListenFd,
+    fd_names: &[String],
     configs: &[HttpBindConfig],
 ) -> Result<Vec<UnixOrTcpListener>, anyhow::Error> {
     let mut listeners = Vec::with_capacity(configs.len());
@@ -394,31 +442,53 @@ pub fn build_listeners(
                 listener.try_into()?
             }
 
-            HttpBindConfig::Unix { socket } => {
+            HttpBindConfig::Unix {
+                socket,
+                mode,
+                owner,
+                group,
+            } => {
                 let listener = UnixListener::bind(socket).context("could not bind socket")?;
-                listener.try_into()?
-            }
 
-            HttpBindConfig::FileDescriptor {
-                fd,
-                kind: UnixOrTcp::Tcp,
-            } => {
-                let listener = fd_manager
-                    .take_tcp_listener(*fd)?
-                    .context("no listener found on file descriptor")?;
-                listener.set_nonblocking(true)?;
+                if let Some(mode) = mode {
+                    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(*mode))
+                        .context("could not set the permissions of the socket")?;
+                }
+
+                if owner.is_some() || group.is_some() {
+                    std::os::unix::fs::chown(socket, *owner, *group)
+                        .context("could not change the owner of the socket")?;
+                }
+
                 listener.try_into()?
             }
 
-            HttpBindConfig::FileDescriptor {
-                fd,
-                kind: UnixOrTcp::Unix,
-            } => {
-                let listener = fd_manager
-                    .take_unix_listener(*fd)?
-                    .context("no unix socket found on file descriptor")?;
-                listener.set_nonblocking(true)?;
-                listener.try_into()?
+            HttpBindConfig::FileDescriptor { fd, name, kind } => {
+                let fd = match name {
+                    Some(name) => fd_names
+                        .iter()
+                        .position(|fd_name| fd_name == name)
+                        .with_context(|| format!("no file descriptor named {name:?} was passed"))?,
+                    None => *fd,
+                };
+
+                match kind {
+                    UnixOrTcp::Tcp => {
+                        let listener = fd_manager
+                            .take_tcp_listener(fd)?
+                            .context("no listener found on file descriptor")?;
+                        listener.set_nonblocking(true)?;
+                        listener.try_into()?
+                    }
+
+                    UnixOrTcp::Unix => {
+                        let listener = fd_manager
+                            .take_unix_listener(fd)?
+                            .context("no unix socket found on file descriptor")?;
+                        listener.set_nonblocking(true)?;
+                        listener.try_into()?
+                    }
+                }
             }
         };
 
@@ -427,3 +497,76 @@ pub fn build_listeners(
 
     Ok(listeners)
 }
+
+/// Remove the UNIX domain sockets created for the listeners, once the server
+/// has stopped
+pub fn remove_unix_sockets(configs: &[HttpBindConfig]) {
+    for bind in configs {
+        if let HttpBindConfig::Unix { socket, .. } = bind
+            && let Err(e) = std::fs::remove_file(socket)
+        {
+            tracing::warn!(
+                error = &e as &dyn std::error::Error,
+                "Could not remove socket {socket}"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    use axum::routing::get;
+    use camino::Utf8PathBuf;
+    use listenfd::ListenFd;
+    use mas_config::HttpBindConfig;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio_util::sync::CancellationToken;
+
+    use super::{build_listeners, remove_unix_sockets};
+
+    #[tokio::test]
+    async fn test_unix_socket_listener() {
+        let socket = Utf8PathBuf::try_from(std::env::temp_dir())
+            .unwrap()
+            .join(format!("mas-test-{}.sock", std::process::id()));
+
+        let binds = vec![HttpBindConfig::Unix {
+            socket: socket.clone(),
+            mode: Some(0o660),
+            owner: None,
+            group: None,
+        }];
+
+        let mut listeners = build_listeners(&mut ListenFd::empty(), &[], &binds).unwrap();
+        let listener = listeners.pop().unwrap();
+
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o660);
+
+        let router = axum::Router::new().route("/health", get(|| async { "ok" }));
+        let server = mas_listener::server::Server::new(listener, router);
+        let soft_shutdown_token = CancellationToken::new();
+        let hard_shutdown_token = CancellationToken::new();
+        let task =
+            tokio::spawn(server.run(soft_shutdown_token.clone(), hard_shutdown_token.clone()));
+
+        let mut stream = tokio::net::UnixStream::connect(&socket).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("ok"), "{response}");
+
+        soft_shutdown_token.cancel();
+        hard_shutdown_token.cancel();
+        task.await.unwrap();
+
+        remove_unix_sockets(&binds);
+        assert!(!socket.exists());
+    }
+}
@@ -38,6 +38,22 @@ fn map_import_action(
     }
 }
 
+fn map_admin_import_action(
+    config: mas_config::UpstreamOAuth2AdminImportAction,
+) -> mas_data_model::UpstreamOAuthProviderAdminImportAction {
+    match config {
+        mas_config::UpstreamOAuth2AdminImportAction::Ignore => {
+            mas_data_model::UpstreamOAuthProviderAdminImportAction::Ignore
+        }
+        mas_config::UpstreamOAuth2AdminImportAction::Always => {
+            mas_data_model::UpstreamOAuthProviderAdminImportAction::Always
+        }
+        mas_config::UpstreamOAuth2AdminImportAction::Never => {
+            mas_data_model::UpstreamOAuthProviderAdminImportAction::Never
+        }
+    }
+}
+
 fn map_import_on_conflict(
     config: mas_config::UpstreamOAuth2OnConflict,
 ) -> mas_data_model::UpstreamOAuthProviderOnConflict {
@@ -51,6 +67,22 @@ fn map_import_on_conflict(
     }
 }
 
+fn map_import_on_unavailable(
+    config: mas_config::UpstreamOAuth2OnUnavailable,
+) -> mas_data_model::UpstreamOAuthProviderOnUnavailable {
+    match config {
+        mas_config::UpstreamOAuth2OnUnavailable::Fail => {
+            mas_data_model::UpstreamOAuthProviderOnUnavailable::Fail
+        }
+        mas_config::UpstreamOAuth2OnUnavailable::Suggest => {
+            mas_data_model::UpstreamOAuthProviderOnUnavailable::Suggest
+        }
+        mas_config::UpstreamOAuth2OnUnavailable::AutoAccept => {
+            mas_data_model::UpstreamOAuthProviderOnUnavailable::AutoAccept
+        }
+    }
+}
+
 fn map_claims_imports(
     config: &mas_config::UpstreamOAuth2ClaimsImports,
 ) -> mas_data_model::UpstreamOAuthProviderClaimsImports {
@@ -62,6 +94,7 @@ fn map_claims_imports(
             action: map_import_action(config.localpart.action),
             template: config.localpart.template.clone(),
             on_conflict: map_import_on_conflict(config.localpart.on_conflict),
+            on_unavailable: map_import_on_unavailable(config.localpart.on_unavailable),
         },
         displayname: mas_data_model::UpstreamOAuthProviderImportPreference {
             action: map_import_action(config.displayname.action),
@@ -74,6 +107,14 @@ fn map_claims_imports(
         account_name: mas_data_model::UpstreamOAuthProviderSubjectPreference {
             template: config.account_name.template.clone(),
         },
+        organization: mas_data_model::UpstreamOAuthProviderImportPreference {
+            action: map_import_action(config.organization.action),
+            template: config.organization.template.clone(),
+        },
+        admin: mas_data_model::UpstreamOAuthProviderAdminPreference {
+            action: map_admin_import_action(config.admin.action),
+            template: config.admin.template.clone(),
+        },
     }
 }
 
@@ -186,8 +227,11 @@ pub async fn config_sync(
                 continue;
             }
 
-            // Use the position in the config of the provider as position in the UI
-            let ui_order = index.try_into().unwrap_or(i32::MAX);
+            // Use the position in the config of the provider as position in the UI,
+            // unless it was explicitly set
+            let ui_order = provider
+                .ui_order
+                .unwrap_or_else(|| index.try_into().unwrap_or(i32::MAX));
 
             let _span = info_span!("provider", %provider.id).entered();
             if existing_enabled_ids.contains(&provider.id) {
@@ -218,6 +262,21 @@ pub async fn config_sync(
                     }
                     let encoded = serde_json::to_vec(&siwa)?;
                     Some(encrypter.encrypt_to_string(&encoded)?)
+                } else if let Some(private_key_jwt) = &provider.private_key_jwt
+                    && !private_key_jwt.keys.is_empty()
+                {
+                    // Dedicated keys are stored as PEM documents alongside their key ID, so
+                    // that the key files don't need to be available at runtime
+                    let keys: Vec<_> = private_key_jwt
+                        .load_keys()
+                        .await?
+                        .into_iter()
+                        .map(|(kid, private_key)| {
+                            serde_json::json!({ "kid": kid, "private_key": private_key })
+                        })
+                        .collect();
+                    let encoded = serde_json::to_vec(&serde_json::json!({ "keys": keys }))?;
+                    Some(encrypter.encrypt_to_string(&encoded)?)
                 } else {
                     None
                 };
@@ -334,6 +393,8 @@ pub async fn config_sync(
                             .collect(),
                         forward_login_hint: provider.forward_login_hint,
                         ui_order,
+                        ui_hidden: provider.visibility
+                            == mas_config::UpstreamOAuth2ProviderVisibility::Hidden,
                         on_backchannel_logout,
                     },
                 )
@@ -391,6 +452,11 @@ pub async fn config_sync(
             let client_auth_method = client.client_auth_method();
             let jwks = client.jwks.as_ref();
             let jwks_uri = client.jwks_uri.as_ref();
+            let allowed_scopes = client
+                .allowed_scopes
+                .as_ref()
+                .map(|scopes| scopes.join(" ").parse())
+                .transpose()?;
 
             // TODO: should be moved somewhere else
             let encrypted_client_secret = client_secret
@@ -406,6 +472,8 @@ pub async fn config_sync(
                     jwks.cloned(),
                     jwks_uri.cloned(),
                     client.redirect_uris,
+                    client.always_prompt_consent,
+                    allowed_scopes,
                 )
                 .await?;
         }
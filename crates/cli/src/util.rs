@@ -8,14 +8,23 @@ use std::{sync::Arc, time::Duration};
 
 use anyhow::Context;
 use mas_config::{
-    AccountConfig, BrandingConfig, CaptchaConfig, DatabaseConfig, EmailConfig, EmailSmtpMode,
-    EmailTransportKind, ExperimentalConfig, HomeserverKind, MatrixConfig, PasswordsConfig,
-    PolicyConfig, TemplatesConfig,
+    AccountConfig, Argon2idParameters, AuditWebhookConfig, BrandingConfig, CaptchaConfig,
+    DatabaseConfig, EmailConfig, EmailSmtpMode, EmailTransportKind, EmailsConfig,
+    ExperimentalConfig, HomeserverKind, HttpCookiesConfig, LoginConfig, MatrixConfig, OpenIdConfig,
+    PasswordsConfig, PolicyConfig, RateLimitingConfig, SessionsConfig, TchapAppConfig,
+    TemplatesConfig, UserAgentDeviceType,
 };
 use mas_context::LogContext;
-use mas_data_model::{SessionExpirationConfig, SiteConfig};
+use mas_data_model::{
+    BrowserSessionLifetimeConfig, DeviceType, DisallowedScopeHandling, EmailProbeConfig,
+    SessionExpirationConfig, SessionLimitConfig, SessionLimitStrategy, SiteConfig, TchapFeatures,
+    UserAgentParser, UserAgentRule, UserinfoClaimsConfig,
+};
 use mas_email::{MailTransport, Mailer};
-use mas_handlers::passwords::PasswordManager;
+use mas_handlers::{
+    CookieManager, CookieSameSite,
+    passwords::{Hasher, PasswordManager},
+};
 use mas_matrix::{HomeserverConnection, ReadOnlyHomeserverConnection};
 use mas_matrix_synapse::{LegacySynapseConnection, SynapseConnection};
 use mas_policy::PolicyFactory;
@@ -28,6 +37,7 @@ use sqlx::{
 };
 use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tracing::{Instrument, log::LevelFilter};
+use url::Url;
 
 pub async fn password_manager_from_config(
     config: &PasswordsConfig,
@@ -36,28 +46,54 @@ pub async fn password_manager_from_config(
         return Ok(PasswordManager::disabled());
     }
 
-    let schemes = config.load().await?.into_iter().map(
-        |(version, algorithm, cost, secret, unicode_normalization)| {
-            use mas_handlers::passwords::Hasher;
-            let hasher = match algorithm {
-                mas_config::PasswordAlgorithm::Pbkdf2 => {
-                    Hasher::pbkdf2(secret, unicode_normalization)
-                }
-                mas_config::PasswordAlgorithm::Bcrypt => {
-                    Hasher::bcrypt(cost, secret, unicode_normalization)
-                }
-                mas_config::PasswordAlgorithm::Argon2id => {
-                    Hasher::argon2id(secret, unicode_normalization)
-                }
-            };
-
-            (version, hasher)
-        },
-    );
+    let schemes = config
+        .load()
+        .await?
+        .into_iter()
+        .map(
+            |(version, algorithm, cost, argon2id_parameters, secret, unicode_normalization)| {
+                let hasher = password_hasher_from_config(
+                    algorithm,
+                    cost,
+                    argon2id_parameters,
+                    secret,
+                    unicode_normalization,
+                )
+                .with_context(|| format!("Invalid password hashing scheme version {version}"))?;
+
+                Ok((version, hasher))
+            },
+        )
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
 
     PasswordManager::new(config.minimum_complexity(), schemes)
 }
 
+/// Build a password hasher from the parameters of a hashing scheme, as loaded
+/// by [`PasswordsConfig::load`]
+pub fn password_hasher_from_config(
+    algorithm: mas_config::PasswordAlgorithm,
+    cost: Option<u32>,
+    argon2id_parameters: Argon2idParameters,
+    secret: Option<Vec<u8>>,
+    unicode_normalization: bool,
+) -> Result<Hasher, anyhow::Error> {
+    let hasher = match algorithm {
+        mas_config::PasswordAlgorithm::Pbkdf2 => Hasher::pbkdf2(secret, unicode_normalization),
+        mas_config::PasswordAlgorithm::Bcrypt => {
+            Hasher::bcrypt(cost, secret, unicode_normalization)
+        }
+        mas_config::PasswordAlgorithm::Argon2id => Hasher::argon2id(secret, unicode_normalization)
+            .with_argon2id_params(
+                argon2id_parameters.memory,
+                argon2id_parameters.iterations,
+                argon2id_parameters.parallelism,
+            )?,
+    };
+
+    Ok(hasher)
+}
+
 pub fn mailer_from_config(
     config: &EmailConfig,
     templates: &Templates,
@@ -145,6 +181,7 @@ pub async fn policy_factory_from_config(
         client_registration: config.client_registration_entrypoint.clone(),
         authorization_grant: config.authorization_grant_entrypoint.clone(),
         email: config.email_entrypoint.clone(),
+        account_recovery: config.account_recovery_entrypoint.clone(),
     };
 
     let data =
@@ -183,15 +220,76 @@ pub fn captcha_config_from_config(
     }))
 }
 
+pub fn audit_webhook_config_from_config(
+    audit_webhook_config: &AuditWebhookConfig,
+) -> Result<Option<mas_data_model::AuditWebhookConfig>, anyhow::Error> {
+    let Some(url) = audit_webhook_config.url.clone() else {
+        return Ok(None);
+    };
+
+    let secret = audit_webhook_config
+        .secret()
+        .context("failed to read the audit webhook secret")?;
+
+    let events = audit_webhook_config
+        .events
+        .iter()
+        .map(|kind| match kind {
+            mas_config::AuditEventKind::LoginSuccess => {
+                mas_data_model::AuditEventKind::LoginSuccess
+            }
+            mas_config::AuditEventKind::LoginFailure => {
+                mas_data_model::AuditEventKind::LoginFailure
+            }
+            mas_config::AuditEventKind::SessionEnd => mas_data_model::AuditEventKind::SessionEnd,
+            mas_config::AuditEventKind::PasswordChange => {
+                mas_data_model::AuditEventKind::PasswordChange
+            }
+            mas_config::AuditEventKind::AccountLock => mas_data_model::AuditEventKind::AccountLock,
+            mas_config::AuditEventKind::AdminPermissionChange => {
+                mas_data_model::AuditEventKind::AdminPermissionChange
+            }
+            mas_config::AuditEventKind::UpstreamLinkTransfer => {
+                mas_data_model::AuditEventKind::UpstreamLinkTransfer
+            }
+            mas_config::AuditEventKind::AccountMerge => {
+                mas_data_model::AuditEventKind::AccountMerge
+            }
+        })
+        .collect();
+
+    Ok(Some(mas_data_model::AuditWebhookConfig {
+        url,
+        secret,
+        events,
+    }))
+}
+
+#[expect(clippy::too_many_arguments, reason = "this is fine")]
+#[expect(clippy::similar_names, reason = "the email and emails sections are distinct")]
 pub fn site_config_from_config(
     branding_config: &BrandingConfig,
     matrix_config: &MatrixConfig,
     experimental_config: &ExperimentalConfig,
     password_config: &PasswordsConfig,
     account_config: &AccountConfig,
+    emails_config: &EmailsConfig,
+    login_config: &LoginConfig,
     captcha_config: &CaptchaConfig,
+    rate_limiting_config: &RateLimitingConfig,
+    audit_webhook_config: &AuditWebhookConfig,
+    openid_config: &OpenIdConfig,
+    sessions_config: &SessionsConfig,
+    email_config: &EmailConfig,
 ) -> Result<SiteConfig, anyhow::Error> {
     let captcha = captcha_config_from_config(captcha_config)?;
+    let audit_webhook = audit_webhook_config_from_config(audit_webhook_config)?;
+    // There is nothing to check when emails are not sent anywhere
+    let email_probe = (email_config.probe.enabled
+        && !matches!(email_config.transport(), EmailTransportKind::Blackhole))
+    .then(|| EmailProbeConfig {
+        recipient: email_config.probe.recipient.clone(),
+    });
     let session_expiration = experimental_config
         .inactive_session_expiration
         .as_ref()
@@ -204,30 +302,119 @@ pub fn site_config_from_config(
     Ok(SiteConfig {
         access_token_ttl: experimental_config.access_token_ttl,
         compat_token_ttl: experimental_config.compat_token_ttl,
+        pushed_authorization_request_ttl: experimental_config.pushed_authorization_request_ttl,
         server_name: matrix_config.homeserver.clone(),
         policy_uri: branding_config.policy_uri.clone(),
         tos_uri: branding_config.tos_uri.clone(),
         imprint: branding_config.imprint.clone(),
+        //:tchap:
+        template_globals: branding_config.template_globals.clone(),
+        //:tchap:end
         password_login_enabled: password_config.enabled(),
         password_registration_enabled: password_config.enabled()
             && account_config.password_registration_enabled,
         password_registration_email_required: account_config.password_registration_email_required,
         registration_token_required: account_config.registration_token_required,
         email_change_allowed: account_config.email_change_allowed,
+        email_change_double_confirmation: emails_config.double_confirmation,
+        new_login_notification: account_config.new_login_notification,
         displayname_change_allowed: account_config.displayname_change_allowed,
         password_change_allowed: password_config.enabled()
             && account_config.password_change_allowed,
         account_recovery_allowed: password_config.enabled()
             && account_config.password_recovery_enabled,
+        account_recovery_email_interval: rate_limiting_config.account_recovery.email_interval,
         account_deactivation_allowed: account_config.account_deactivation_allowed,
+        deactivation_grace_period: account_config.deactivation_grace_period,
+        deleted_user_retention: account_config.deleted_user_retention,
+        protected_usernames: account_config.protected_usernames.clone(),
+        upstream_authorization_retention: sessions_config.upstream_authorization_retention,
         captcha,
         minimum_password_complexity: password_config.minimum_complexity(),
         session_expiration,
         login_with_email_allowed: account_config.login_with_email_allowed,
+        auto_redirect_single_provider: login_config.auto_redirect_single_provider,
+        prefer_password_login: login_config.prefer_password_login,
         plan_management_iframe_uri: experimental_config.plan_management_iframe_uri.clone(),
+        admin_api_csv_export_limit: experimental_config.admin_api_csv_export_limit,
+        audit_webhook,
+        email_probe,
+        sync_emails_to_homeserver: matrix_config.sync_emails,
+        userinfo_claims: UserinfoClaimsConfig {
+            name: openid_config.userinfo_claims.name,
+            locale: openid_config.userinfo_claims.locale,
+            updated_at: openid_config.userinfo_claims.updated_at,
+        },
+        device_code_sensitive_scopes: experimental_config.device_code_sensitive_scopes.clone(),
+        session_limit: SessionLimitConfig {
+            max_per_user: sessions_config.max_per_user,
+            strategy: match sessions_config.strategy {
+                mas_config::SessionLimitStrategy::Reject => SessionLimitStrategy::Reject,
+                mas_config::SessionLimitStrategy::EvictOldest => SessionLimitStrategy::EvictOldest,
+            },
+        },
+        browser_session_lifetime: BrowserSessionLifetimeConfig {
+            idle_timeout: sessions_config.browser_idle_timeout,
+            max_lifetime: sessions_config.browser_max_lifetime,
+            reauthentication_max_age: sessions_config.reauthentication_max_age,
+        },
+        disallowed_scope_handling: match experimental_config.disallowed_scope_handling {
+            mas_config::DisallowedScopeHandling::Reject => DisallowedScopeHandling::Reject,
+            mas_config::DisallowedScopeHandling::Trim => DisallowedScopeHandling::Trim,
+        },
+        matrix_session_in_token_response: experimental_config.matrix_session_in_token_response,
+        admin_api_examples: experimental_config.admin_api_examples,
     })
 }
 
+/// Build the cookie manager, applying the cookie settings from the config
+pub fn cookie_manager_from_config(
+    cookies_config: &HttpCookiesConfig,
+    public_base: Url,
+    encryption_key: &[u8],
+) -> CookieManager {
+    let same_site = match cookies_config.same_site {
+        mas_config::CookieSameSite::Lax => CookieSameSite::Lax,
+        mas_config::CookieSameSite::Strict => CookieSameSite::Strict,
+    };
+
+    let mut cookie_manager =
+        CookieManager::derive_from(public_base, encryption_key).with_same_site(same_site);
+
+    if let Some(name_prefix) = &cookies_config.name_prefix {
+        cookie_manager = cookie_manager.with_name_prefix(name_prefix.clone());
+    }
+
+    cookie_manager
+}
+
+/// Install the user agent parser, with the custom rules from the config
+pub fn install_user_agent_parser(
+    experimental_config: &ExperimentalConfig,
+) -> Result<(), anyhow::Error> {
+    let rules = experimental_config
+        .user_agent_rules
+        .iter()
+        .map(|rule| {
+            let device_type = match rule.device_type {
+                UserAgentDeviceType::Pc => DeviceType::Pc,
+                UserAgentDeviceType::Mobile => DeviceType::Mobile,
+                UserAgentDeviceType::Tablet => DeviceType::Tablet,
+                UserAgentDeviceType::Unknown => DeviceType::Unknown,
+            };
+
+            UserAgentRule::new(&rule.pattern, device_type)
+                .with_context(|| format!("Invalid user agent rule pattern {:?}", rule.pattern))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if UserAgentParser::new(rules).install().is_err() {
+        tracing::warn!("The user agent parser was already installed");
+    }
+
+    Ok(())
+}
+
 pub async fn templates_from_config(
     config: &TemplatesConfig,
     site_config: &SiteConfig,
@@ -499,6 +686,20 @@ pub async fn homeserver_connection_from_config(
     })
 }
 
+//:tchap:
+pub fn tchap_features_from_tchap_app_config(tchap_app_config: &TchapAppConfig) -> TchapFeatures {
+    let features = &tchap_app_config.features;
+    TchapFeatures {
+        registration_email_gatekeeping: features.registration_email_gatekeeping,
+        upstream_email_gatekeeping: features.upstream_email_gatekeeping,
+        upstream_email_matching: features.upstream_email_matching,
+        displayname_suffixing: features.displayname_suffixing,
+        identity_server_lookups: features.identity_server_lookups,
+        organization_claim: features.organization_claim,
+    }
+}
+//:tchap:end
+
 #[cfg(test)]
 mod tests {
     use rand::SeedableRng;
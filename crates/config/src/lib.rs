@@ -16,9 +16,11 @@ compile_error!("Only one of the `docker` and `dist` features can be enabled at o
 
 pub(crate) mod schema;
 mod sections;
+mod unknown_keys;
 pub(crate) mod util;
 
 pub use self::{
     sections::*,
+    unknown_keys::{UnknownKey, find_unknown_keys},
     util::{ConfigurationSection, ConfigurationSectionExt},
 };
@@ -4,8 +4,10 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use chrono::Duration;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
 
 use crate::ConfigurationSection;
 
@@ -27,8 +29,25 @@ const fn is_default_false(value: &bool) -> bool {
     *value == default_false()
 }
 
+fn default_deleted_user_retention() -> Duration {
+    Duration::days(30)
+}
+
+fn is_default_deleted_user_retention(value: &Duration) -> bool {
+    *value == default_deleted_user_retention()
+}
+
+fn default_deactivation_grace_period() -> Duration {
+    Duration::days(7)
+}
+
+fn is_default_deactivation_grace_period(value: &Duration) -> bool {
+    *value == default_deactivation_grace_period()
+}
+
 /// Configuration section to configure features related to account management
 #[allow(clippy::struct_excessive_bools)]
+#[serde_as]
 #[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 pub struct AccountConfig {
     /// Whether users are allowed to change their email addresses. Defaults to
@@ -36,6 +55,14 @@ pub struct AccountConfig {
     #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
     pub email_change_allowed: bool,
 
+    /// Whether to email users when a new session is started on their account
+    /// through the Matrix client-server login API. Defaults to `false`.
+    ///
+    /// The email holds a link to sign the new session out and start
+    /// recovering the account. The link expires after 7 days.
+    #[serde(default = "default_false", skip_serializing_if = "is_default_false")]
+    pub new_login_notification: bool,
+
     /// Whether users are allowed to change their display names. Defaults to
     /// `true`.
     ///
@@ -74,6 +101,19 @@ pub struct AccountConfig {
     #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
     pub account_deactivation_allowed: bool,
 
+    /// How long to wait before deactivating the account of a user who asked
+    /// for it, in seconds. Defaults to 7 days.
+    ///
+    /// The account is locked right away, and the user can cancel the
+    /// deactivation by logging in again during this period.
+    #[schemars(with = "u64")]
+    #[serde(
+        default = "default_deactivation_grace_period",
+        skip_serializing_if = "is_default_deactivation_grace_period"
+    )]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub deactivation_grace_period: Duration,
+
     /// Whether users can log in with their email address. Defaults to `false`.
     ///
     /// This has no effect if password login is disabled.
@@ -88,20 +128,42 @@ pub struct AccountConfig {
     /// is disabled.
     #[serde(default = "default_false", skip_serializing_if = "is_default_false")]
     pub registration_token_required: bool,
+
+    /// How long deleted users are kept before being permanently removed, in
+    /// seconds. Defaults to 30 days.
+    ///
+    /// During this period, the deletion can be undone through the admin API,
+    /// and the username of the deleted user can't be reused.
+    #[schemars(with = "u64")]
+    #[serde(
+        default = "default_deleted_user_retention",
+        skip_serializing_if = "is_default_deleted_user_retention"
+    )]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub deleted_user_retention: Duration,
+
+    /// Usernames of the accounts which can't be permanently deleted through
+    /// the admin API, like service accounts. Defaults to an empty list.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protected_usernames: Vec<String>,
 }
 
 impl Default for AccountConfig {
     fn default() -> Self {
         Self {
             email_change_allowed: default_true(),
+            new_login_notification: default_false(),
             displayname_change_allowed: default_true(),
             password_registration_enabled: default_false(),
             password_registration_email_required: default_true(),
             password_change_allowed: default_true(),
             password_recovery_enabled: default_false(),
             account_deactivation_allowed: default_true(),
+            deactivation_grace_period: default_deactivation_grace_period(),
             login_with_email_allowed: default_false(),
             registration_token_required: default_false(),
+            deleted_user_retention: default_deleted_user_retention(),
+            protected_usernames: Vec::new(),
         }
     }
 }
@@ -111,12 +173,16 @@ impl AccountConfig {
     pub(crate) fn is_default(&self) -> bool {
         is_default_false(&self.password_registration_enabled)
             && is_default_true(&self.email_change_allowed)
+            && is_default_false(&self.new_login_notification)
             && is_default_true(&self.displayname_change_allowed)
             && is_default_true(&self.password_change_allowed)
             && is_default_false(&self.password_recovery_enabled)
             && is_default_true(&self.account_deactivation_allowed)
+            && is_default_deactivation_grace_period(&self.deactivation_grace_period)
             && is_default_false(&self.login_with_email_allowed)
             && is_default_false(&self.registration_token_required)
+            && is_default_deleted_user_retention(&self.deleted_user_retention)
+            && self.protected_usernames.is_empty()
     }
 }
 
@@ -0,0 +1,144 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use camino::Utf8PathBuf;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::ConfigurationSection;
+
+/// Kind of event which can be sent to the audit webhook
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+    /// A user successfully logged in
+    LoginSuccess,
+
+    /// A login attempt failed for a known user
+    LoginFailure,
+
+    /// A session was ended
+    SessionEnd,
+
+    /// The password of a user was changed
+    PasswordChange,
+
+    /// A user was locked
+    AccountLock,
+
+    /// The right of a user to request admin access was changed by the claims
+    /// of an upstream provider
+    AdminPermissionChange,
+
+    /// An upstream link was moved from one user to another by an
+    /// administrator
+    UpstreamLinkTransfer,
+
+    /// A user was merged into another one by an administrator
+    AccountMerge,
+}
+
+fn default_events() -> Vec<AuditEventKind> {
+    vec![
+        AuditEventKind::LoginSuccess,
+        AuditEventKind::LoginFailure,
+        AuditEventKind::SessionEnd,
+        AuditEventKind::PasswordChange,
+        AuditEventKind::AccountLock,
+        AuditEventKind::AdminPermissionChange,
+        AuditEventKind::UpstreamLinkTransfer,
+        AuditEventKind::AccountMerge,
+    ]
+}
+
+fn is_default_events(value: &[AuditEventKind]) -> bool {
+    value == default_events()
+}
+
+/// Configuration section to send audit events, like logins and password
+/// changes, to a webhook
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct AuditWebhookConfig {
+    /// URL to which the audit events are posted as JSON. No events are sent if
+    /// this is not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+
+    /// Secret sent as a bearer token in the `Authorization` header
+    ///
+    /// At most one of `secret` or `secret_file` can be set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+
+    /// File containing the secret sent as a bearer token in the
+    /// `Authorization` header
+    ///
+    /// At most one of `secret` or `secret_file` can be set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub secret_file: Option<Utf8PathBuf>,
+
+    /// Which kinds of events are sent. Defaults to all of them
+    #[serde(default = "default_events", skip_serializing_if = "is_default_events")]
+    pub events: Vec<AuditEventKind>,
+}
+
+impl Default for AuditWebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            secret: None,
+            secret_file: None,
+            events: default_events(),
+        }
+    }
+}
+
+impl AuditWebhookConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        self.url.is_none()
+            && self.secret.is_none()
+            && self.secret_file.is_none()
+            && is_default_events(&self.events)
+    }
+
+    /// Returns the secret to send along the events, if any.
+    ///
+    /// If `secret_file` was given, the secret is read from that file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when the secret could not be read from file.
+    pub fn secret(&self) -> anyhow::Result<Option<String>> {
+        Ok(match (&self.secret, &self.secret_file) {
+            (Some(secret), _) => Some(secret.clone()),
+            (None, Some(path)) => Some(std::fs::read_to_string(path)?.trim().to_owned()),
+            (None, None) => None,
+        })
+    }
+}
+
+impl ConfigurationSection for AuditWebhookConfig {
+    const PATH: Option<&'static str> = Some("audit_webhook");
+
+    fn validate(
+        &self,
+        figment: &figment::Figment,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        if self.secret.is_some() && self.secret_file.is_some() {
+            let mut error = figment::Error::from(
+                "Only one of `secret` or `secret_file` can be set at a time".to_owned(),
+            );
+            error.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+            error.profile = Some(figment::Profile::Default);
+            error.path = vec![Self::PATH.unwrap().to_owned()];
+            return Err(error.into());
+        }
+
+        Ok(())
+    }
+}
@@ -4,6 +4,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::collections::BTreeMap;
+
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use url::Url;
@@ -37,6 +39,14 @@ pub struct BrandingConfig {
     /// Logo displayed in some web pages.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub logo_uri: Option<Url>,
+
+    //:tchap:
+    /// Instance-specific values available to all templates, including emails,
+    /// as `{{ globals.<key> }}`, like `support_email: support@example.com`.
+    /// Values can be plain strings or any JSON value.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub template_globals: BTreeMap<String, serde_json::Value>,
+    //:tchap:end
 }
 
 impl BrandingConfig {
@@ -47,6 +57,7 @@ impl BrandingConfig {
             && self.tos_uri.is_none()
             && self.imprint.is_none()
             && self.logo_uri.is_none()
+            && self.template_globals.is_empty()
     }
 }
 
@@ -166,10 +166,40 @@ pub struct ClientConfig {
     /// List of allowed redirect URIs
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub redirect_uris: Vec<Url>,
+
+    /// Whether users should be asked for consent on every authorization
+    /// request from this client, even if they previously chose to remember
+    /// their decision. Defaults to `false`.
+    #[serde(default, skip_serializing_if = "is_default_false")]
+    pub always_prompt_consent: bool,
+
+    /// Scopes this client is allowed to request. A value ending with `*`
+    /// allows all the scopes starting with the same prefix. Scopes outside of
+    /// this list are either trimmed from the request or rejected, depending on
+    /// `experimental.disallowed_scope_handling`. If not set, the client can
+    /// request any scope allowed by the policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_scopes: Option<Vec<String>>,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_default_false(value: &bool) -> bool {
+    !*value
 }
 
 impl ClientConfig {
     fn validate(&self) -> Result<(), Box<figment::error::Error>> {
+        if let Some(allowed_scopes) = &self.allowed_scopes
+            && allowed_scopes
+                .iter()
+                .any(|scope| scope.is_empty() || scope.contains(char::is_whitespace))
+        {
+            let error = figment::error::Error::custom(
+                "allowed_scopes must be a list of individual scope tokens",
+            );
+            return Err(Box::new(error.with_path("allowed_scopes")));
+        }
+
         let auth_method = self.client_auth_method;
         match self.client_auth_method {
             ClientAuthMethodConfig::PrivateKeyJwt => {
@@ -355,6 +385,9 @@ mod tests {
                           client_auth_method: none
                           redirect_uris:
                             - https://exemple.fr/callback
+                          allowed_scopes:
+                            - openid
+                            - urn:matrix:org.matrix.msc2967.client:device:*
 
                         - client_id: 01GFWR32NCQ12B8Z0J8CPXRRB6
                           client_auth_method: client_secret_basic
@@ -410,6 +443,15 @@ mod tests {
                 );
                 assert_eq!(config.0[1].redirect_uris, Vec::new());
 
+                assert_eq!(
+                    config.0[0].allowed_scopes,
+                    Some(vec![
+                        "openid".to_owned(),
+                        "urn:matrix:org.matrix.msc2967.client:device:*".to_owned(),
+                    ])
+                );
+                assert_eq!(config.0[1].allowed_scopes, None);
+
                 assert!(config.0[0].client_secret.is_none());
                 assert!(matches!(config.0[1].client_secret, Some(ClientSecret::File(ref p)) if p == "secret"));
                 assert!(matches!(config.0[2].client_secret, Some(ClientSecret::Value(ref v)) if v == "c1!3n753c237"));
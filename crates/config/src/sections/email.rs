@@ -61,6 +61,49 @@ fn default_sendmail_command() -> Option<String> {
     Some("sendmail".to_owned())
 }
 
+fn default_probe_enabled() -> bool {
+    true
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_default_probe_enabled(value: &bool) -> bool {
+    *value == default_probe_enabled()
+}
+
+/// Configuration of the periodic check of the email delivery
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct EmailProbeConfig {
+    /// Whether to periodically check that emails can be sent. Defaults to
+    /// `true`. The check never runs with the `blackhole` transport.
+    #[serde(
+        default = "default_probe_enabled",
+        skip_serializing_if = "is_default_probe_enabled"
+    )]
+    pub enabled: bool,
+
+    /// Mailbox to which a probe email is sent on each check. If not set, only
+    /// the connection to the mail server is checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(email)]
+    pub recipient: Option<String>,
+}
+
+impl Default for EmailProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_probe_enabled(),
+            recipient: None,
+        }
+    }
+}
+
+impl EmailProbeConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        is_default_probe_enabled(&self.enabled) && self.recipient.is_none()
+    }
+}
+
 /// Configuration related to sending emails
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct EmailConfig {
@@ -110,6 +153,10 @@ pub struct EmailConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[schemars(default = "default_sendmail_command")]
     command: Option<String>,
+
+    /// Periodic check of the email delivery
+    #[serde(default, skip_serializing_if = "EmailProbeConfig::is_default")]
+    pub probe: EmailProbeConfig,
 }
 
 impl EmailConfig {
@@ -168,6 +215,7 @@ impl Default for EmailConfig {
             username: None,
             password: None,
             command: None,
+            probe: EmailProbeConfig::default(),
         }
     }
 }
@@ -199,6 +247,12 @@ impl ConfigurationSection for EmailConfig {
             )
         };
 
+        if let Some(recipient) = &self.probe.recipient
+            && let Err(e) = Mailbox::from_str(recipient)
+        {
+            return Err(error_on_field(figment::error::Error::custom(e), "probe").into());
+        }
+
         match self.transport {
             EmailTransportKind::Blackhole => {}
 
@@ -241,6 +295,7 @@ impl ConfigurationSection for EmailConfig {
                             "port",
                             "username",
                             "password",
+                            "probe",
                         ],
                     )
                     .into());
@@ -248,7 +303,7 @@ impl ConfigurationSection for EmailConfig {
             }
 
             EmailTransportKind::Sendmail => {
-                let expected_fields = &["from", "reply_to", "transport", "command"];
+                let expected_fields = &["from", "reply_to", "transport", "command", "probe"];
 
                 if let Err(e) = Mailbox::from_str(&self.from) {
                     return Err(error_on_field(figment::error::Error::custom(e), "from").into());
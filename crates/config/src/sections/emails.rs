@@ -0,0 +1,71 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::ConfigurationSection;
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_default_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Configuration section to configure how users manage their email addresses
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+pub struct EmailsConfig {
+    /// Whether a change of email address must also be confirmed from the
+    /// address the user already had. Defaults to `false`.
+    ///
+    /// When enabled, a new address is only added to the account once the
+    /// change was confirmed with a link sent to the oldest address of the
+    /// user. The link expires after 24 hours, and can also be used to cancel
+    /// the change.
+    #[serde(default, skip_serializing_if = "is_default_false")]
+    pub double_confirmation: bool,
+}
+
+impl EmailsConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        is_default_false(&self.double_confirmation)
+    }
+}
+
+impl ConfigurationSection for EmailsConfig {
+    const PATH: Option<&'static str> = Some("emails");
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        Figment, Jail,
+        providers::{Format, Yaml},
+    };
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    emails:
+                      double_confirmation: true
+                ",
+            )?;
+
+            let config = Figment::new()
+                .merge(Yaml::file("config.yaml"))
+                .extract_inner::<EmailsConfig>("emails")?;
+
+            assert!(config.double_confirmation);
+            assert!(!config.is_default());
+
+            Ok(())
+        });
+    }
+}
@@ -23,6 +23,36 @@ fn is_default_token_ttl(value: &Duration) -> bool {
     *value == default_token_ttl()
 }
 
+fn default_pushed_authorization_request_ttl() -> Duration {
+    Duration::microseconds(60 * 1000 * 1000)
+}
+
+fn is_default_pushed_authorization_request_ttl(value: &Duration) -> bool {
+    *value == default_pushed_authorization_request_ttl()
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_default_false(value: &bool) -> bool {
+    !*value
+}
+
+fn default_admin_api_csv_export_limit() -> usize {
+    100_000
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_default_admin_api_csv_export_limit(value: &usize) -> bool {
+    *value == default_admin_api_csv_export_limit()
+}
+
+fn default_device_code_sensitive_scopes() -> Vec<String> {
+    vec!["urn:mas:admin".to_owned(), "urn:synapse:admin:*".to_owned()]
+}
+
+fn is_default_device_code_sensitive_scopes(value: &[String]) -> bool {
+    value == default_device_code_sensitive_scopes()
+}
+
 /// Configuration options for the inactive session expiration feature
 #[serde_as]
 #[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
@@ -45,6 +75,58 @@ pub struct InactiveSessionExpirationConfig {
     pub expire_user_sessions: bool,
 }
 
+/// The type of device recognised by a custom user agent rule
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserAgentDeviceType {
+    /// A personal computer, laptop or desktop
+    Pc,
+
+    /// A mobile phone
+    Mobile,
+
+    /// A tablet
+    Tablet,
+
+    /// Unknown device type
+    #[default]
+    Unknown,
+}
+
+/// A custom rule to recognise user agents, evaluated before the built-in
+/// parser
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct UserAgentRuleConfig {
+    /// Regular expression matching the user agent string.
+    ///
+    /// The `name`, `version`, `os`, `os_version` and `model` named groups are
+    /// used to fill the corresponding fields of the parsed user agent.
+    pub pattern: String,
+
+    /// The type of device recognised by this rule
+    #[serde(default)]
+    pub device_type: UserAgentDeviceType,
+}
+
+/// What to do when a client requests scopes it is not allowed to request
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisallowedScopeHandling {
+    /// Reject the whole request with an `invalid_scope` error
+    #[default]
+    Reject,
+
+    /// Remove the disallowed scopes from the request and carry on
+    Trim,
+}
+
+impl DisallowedScopeHandling {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 /// Configuration sections for experimental options
 ///
 /// Do not change these options unless you know what you are doing.
@@ -70,6 +152,16 @@ pub struct ExperimentalConfig {
     #[serde_as(as = "serde_with::DurationSeconds<i64>")]
     pub compat_token_ttl: Duration,
 
+    /// Time-to-live of pushed authorization requests in seconds, after which
+    /// their `request_uri` can't be used anymore. Defaults to 1 minute.
+    #[schemars(with = "u64", range(min = 10, max = 600))]
+    #[serde(
+        default = "default_pushed_authorization_request_ttl",
+        skip_serializing_if = "is_default_pushed_authorization_request_ttl"
+    )]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub pushed_authorization_request_ttl: Duration,
+
     /// Experimetal feature to automatically expire inactive sessions
     ///
     /// Disabled by default
@@ -81,6 +173,46 @@ pub struct ExperimentalConfig {
     /// validation.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub plan_management_iframe_uri: Option<String>,
+
+    /// Maximum number of rows returned by a CSV export of the admin API list
+    /// endpoints. Defaults to 100000.
+    #[schemars(range(min = 1))]
+    #[serde(
+        default = "default_admin_api_csv_export_limit",
+        skip_serializing_if = "is_default_admin_api_csv_export_limit"
+    )]
+    pub admin_api_csv_export_limit: usize,
+
+    /// Custom rules to recognise user agents, evaluated in order before the
+    /// built-in parser
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub user_agent_rules: Vec<UserAgentRuleConfig>,
+
+    /// Scopes which need an extra confirmation when they are requested through
+    /// a device code grant: the user has to re-enter the code shown on the
+    /// device before approving the grant. Defaults to the admin scopes.
+    #[serde(
+        default = "default_device_code_sensitive_scopes",
+        skip_serializing_if = "is_default_device_code_sensitive_scopes"
+    )]
+    pub device_code_sensitive_scopes: Vec<String>,
+
+    /// What to do when a client requests scopes outside of its
+    /// `allowed_scopes`. Defaults to `reject`.
+    #[serde(default, skip_serializing_if = "DisallowedScopeHandling::is_default")]
+    pub disallowed_scope_handling: DisallowedScopeHandling,
+
+    /// Whether to include a `matrix_session` object, with the Matrix device
+    /// ID, the MXID and the session ID, in the token and introspection
+    /// responses of sessions carrying a device scope. Defaults to `false`.
+    #[serde(default, skip_serializing_if = "is_default_false")]
+    pub matrix_session_in_token_response: bool,
+
+    /// Whether to serve the examples of all the admin API responses at
+    /// `/api/admin/v1/__examples`, for client SDK smoke tests. Always enabled
+    /// in debug builds. Defaults to `false`.
+    #[serde(default, skip_serializing_if = "is_default_false")]
+    pub admin_api_examples: bool,
 }
 
 impl Default for ExperimentalConfig {
@@ -88,8 +220,15 @@ impl Default for ExperimentalConfig {
         Self {
             access_token_ttl: default_token_ttl(),
             compat_token_ttl: default_token_ttl(),
+            pushed_authorization_request_ttl: default_pushed_authorization_request_ttl(),
             inactive_session_expiration: None,
             plan_management_iframe_uri: None,
+            admin_api_csv_export_limit: default_admin_api_csv_export_limit(),
+            user_agent_rules: Vec::new(),
+            device_code_sensitive_scopes: default_device_code_sensitive_scopes(),
+            disallowed_scope_handling: DisallowedScopeHandling::default(),
+            matrix_session_in_token_response: false,
+            admin_api_examples: false,
         }
     }
 }
@@ -98,8 +237,15 @@ impl ExperimentalConfig {
     pub(crate) fn is_default(&self) -> bool {
         is_default_token_ttl(&self.access_token_ttl)
             && is_default_token_ttl(&self.compat_token_ttl)
+            && is_default_pushed_authorization_request_ttl(&self.pushed_authorization_request_ttl)
             && self.inactive_session_expiration.is_none()
             && self.plan_management_iframe_uri.is_none()
+            && is_default_admin_api_csv_export_limit(&self.admin_api_csv_export_limit)
+            && self.user_agent_rules.is_empty()
+            && is_default_device_code_sensitive_scopes(&self.device_code_sensitive_scopes)
+            && self.disallowed_scope_handling.is_default()
+            && is_default_false(&self.matrix_session_in_token_response)
+            && is_default_false(&self.admin_api_examples)
     }
 }
 
@@ -124,6 +124,20 @@ pub enum BindConfig {
         /// Path to the socket
         #[schemars(with = "String")]
         socket: Utf8PathBuf,
+
+        /// Permissions to set on the socket once created, for example `0o660`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<u32>,
+
+        /// ID of the user owning the socket. Defaults to the user running the
+        /// server
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner: Option<u32>,
+
+        /// ID of the group owning the socket. Defaults to the primary group
+        /// of the user running the server
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<u32>,
     },
 
     /// Accept connections on file descriptors passed by the parent process.
@@ -138,6 +152,12 @@ pub enum BindConfig {
         #[serde(default)]
         fd: usize,
 
+        /// Name of the file descriptor, as set with `FileDescriptorName=` in
+        /// the systemd socket unit. When set, the file descriptor is looked up
+        /// by name in `LISTEN_FDNAMES` and `fd` is ignored.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+
         /// Whether the socket is a TCP socket or a UNIX domain socket. Defaults
         /// to TCP.
         #[serde(default = "UnixOrTcp::tcp")]
@@ -268,7 +288,7 @@ impl TlsConfig {
 #[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 #[serde(tag = "name", rename_all = "lowercase")]
 pub enum Resource {
-    /// Healthcheck endpoint (/health)
+    /// Healthcheck endpoints (/health and /health/ready)
     Health,
 
     /// Prometheus metrics endpoint (/metrics)
@@ -344,6 +364,104 @@ pub struct ListenerConfig {
     pub tls: Option<TlsConfig>,
 }
 
+/// The `SameSite` attribute set on the cookies
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieSameSite {
+    /// Cookies are sent on top-level navigations coming from other sites, but
+    /// not on cross-site subrequests
+    #[default]
+    Lax,
+
+    /// Cookies are never sent on requests coming from other sites. Users
+    /// coming from another site, like an OAuth 2.0 client, will appear as
+    /// logged out on the first page they land on.
+    Strict,
+}
+
+impl CookieSameSite {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Settings of the cookies set by the service
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+pub struct CookiesConfig {
+    /// The `SameSite` attribute of the cookies. Defaults to `lax`.
+    #[serde(default, skip_serializing_if = "CookieSameSite::is_default")]
+    pub same_site: CookieSameSite,
+
+    /// A prefix added to the name of all the cookies, for example `__Host-` to
+    /// have browsers only accept them over a secure connection and for the
+    /// exact host. Changing it logs out all users.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_prefix: Option<String>,
+}
+
+impl CookiesConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        self.same_site.is_default() && self.name_prefix.is_none()
+    }
+}
+
+fn default_compression_enabled() -> bool {
+    true
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_default_compression_enabled(value: &bool) -> bool {
+    *value == default_compression_enabled()
+}
+
+fn default_compression_min_size() -> u16 {
+    1024
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_default_compression_min_size(value: &u16) -> bool {
+    *value == default_compression_min_size()
+}
+
+/// Settings of the compression of the HTTP responses
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct CompressionConfig {
+    /// Whether to compress the responses with brotli or gzip when the client
+    /// accepts it. Defaults to `true`.
+    #[serde(
+        default = "default_compression_enabled",
+        skip_serializing_if = "is_default_compression_enabled"
+    )]
+    pub enabled: bool,
+
+    /// Responses smaller than this size, in bytes, are not compressed.
+    /// Defaults to `1024`.
+    #[serde(
+        default = "default_compression_min_size",
+        skip_serializing_if = "is_default_compression_min_size"
+    )]
+    pub min_size: u16,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_compression_enabled(),
+            min_size: default_compression_min_size(),
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        is_default_compression_enabled(&self.enabled)
+            && is_default_compression_min_size(&self.min_size)
+    }
+}
+
 /// Configuration related to the web server
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct HttpConfig {
@@ -362,6 +480,21 @@ pub struct HttpConfig {
     /// OIDC issuer URL. Defaults to `public_base` if not set.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub issuer: Option<Url>,
+
+    /// Legacy issuer URLs which are still accepted in tokens issued by the
+    /// service, for example while migrating to a new domain.
+    ///
+    /// Discovery documents only advertise `issuer`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub issuer_aliases: Vec<Url>,
+
+    /// Settings of the cookies set by the service
+    #[serde(default, skip_serializing_if = "CookiesConfig::is_default")]
+    pub cookies: CookiesConfig,
+
+    /// Settings of the compression of the HTTP responses
+    #[serde(default, skip_serializing_if = "CompressionConfig::is_default")]
+    pub compression: CompressionConfig,
 }
 
 impl Default for HttpConfig {
@@ -404,7 +537,10 @@ impl Default for HttpConfig {
             ],
             trusted_proxies: default_trusted_proxies(),
             issuer: Some(default_public_base()),
+            issuer_aliases: Vec::new(),
             public_base: default_public_base(),
+            cookies: CookiesConfig::default(),
+            compression: CompressionConfig::default(),
         }
     }
 }
@@ -416,6 +552,50 @@ impl ConfigurationSection for HttpConfig {
         &self,
         figment: &figment::Figment,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        if let Some(prefix) = &self.cookies.name_prefix {
+            let annotate = |mut error: figment::Error| {
+                error.metadata = figment
+                    .find_metadata(&format!("{root}.cookies", root = Self::PATH.unwrap()))
+                    .cloned();
+                error.profile = Some(figment::Profile::Default);
+                error.path = vec![
+                    Self::PATH.unwrap().to_owned(),
+                    "cookies".to_owned(),
+                    "name_prefix".to_owned(),
+                ];
+                error
+            };
+
+            if !prefix
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            {
+                return Err(annotate(figment::Error::from(
+                    "cookie name prefix can only contain letters, digits, '-', '_' and '.'"
+                        .to_owned(),
+                ))
+                .into());
+            }
+
+            // Browsers only accept cookies with those prefixes if they are
+            // set over HTTPS, and for `__Host-`, on the root path
+            let is_host = prefix.starts_with("__Host-");
+            if (is_host || prefix.starts_with("__Secure-")) && self.public_base.scheme() != "https"
+            {
+                return Err(annotate(figment::Error::from(format!(
+                    "the {prefix:?} cookie name prefix requires the public base to use https"
+                )))
+                .into());
+            }
+
+            if is_host && self.public_base.path() != "/" {
+                return Err(annotate(figment::Error::from(format!(
+                    "the {prefix:?} cookie name prefix requires the public base to be served on the root path"
+                )))
+                .into());
+            }
+        }
+
         for (index, listener) in self.listeners.iter().enumerate() {
             let annotate = |mut error: figment::Error| {
                 error.metadata = figment
@@ -443,6 +623,19 @@ impl ConfigurationSection for HttpConfig {
                 .into());
             }
 
+            for bind in &listener.binds {
+                if let BindConfig::Unix {
+                    mode: Some(mode), ..
+                } = bind
+                    && *mode > 0o7777
+                {
+                    return Err(annotate(figment::Error::from(format!(
+                        "invalid socket mode {mode:#o}"
+                    )))
+                    .into());
+                }
+            }
+
             if let Some(tls_config) = &listener.tls {
                 if tls_config.certificate.is_some() && tls_config.certificate_file.is_some() {
                     return Err(annotate(figment::Error::from(
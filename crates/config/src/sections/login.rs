@@ -0,0 +1,92 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::ConfigurationSection;
+
+const fn default_true() -> bool {
+    true
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_default_true(value: &bool) -> bool {
+    *value == default_true()
+}
+
+/// Configuration section to configure the behaviour of the login page
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct LoginConfig {
+    /// Whether to send users straight to the upstream provider when it is the
+    /// only one shown on the login page. Defaults to `true`.
+    ///
+    /// Users can still get to the login page by adding `?no_redirect=1` to
+    /// its URL.
+    #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
+    pub auto_redirect_single_provider: bool,
+
+    /// Whether password login is preferred over the upstream providers when
+    /// both are available. Defaults to `true`.
+    ///
+    /// When set, users are never automatically redirected to an upstream
+    /// provider as long as password login is enabled.
+    #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
+    pub prefer_password_login: bool,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            auto_redirect_single_provider: default_true(),
+            prefer_password_login: default_true(),
+        }
+    }
+}
+
+impl LoginConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        is_default_true(&self.auto_redirect_single_provider)
+            && is_default_true(&self.prefer_password_login)
+    }
+}
+
+impl ConfigurationSection for LoginConfig {
+    const PATH: Option<&'static str> = Some("login");
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        Figment, Jail,
+        providers::{Format, Yaml},
+    };
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    login:
+                      prefer_password_login: false
+                ",
+            )?;
+
+            let config = Figment::new()
+                .merge(Yaml::file("config.yaml"))
+                .extract_inner::<LoginConfig>("login")?;
+
+            assert!(config.auto_redirect_single_provider);
+            assert!(!config.prefer_password_login);
+            assert!(!config.is_default());
+
+            Ok(())
+        });
+    }
+}
@@ -25,6 +25,15 @@ fn default_endpoint() -> Url {
     Url::parse("http://localhost:8008/").unwrap()
 }
 
+const fn default_true() -> bool {
+    true
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_default_true(value: &bool) -> bool {
+    *value == default_true()
+}
+
 /// The kind of homeserver it is.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
 #[serde(rename_all = "snake_case")]
@@ -115,6 +124,14 @@ pub struct MatrixConfig {
     /// The base URL of the homeserver's client API
     #[serde(default = "default_endpoint")]
     pub endpoint: Url,
+
+    /// Whether to push the email addresses of users to the homeserver as
+    /// threepids when provisioning them. Defaults to `true`.
+    ///
+    /// Disable this if the threepids on the homeserver are managed by
+    /// something else.
+    #[serde(default = "default_true", skip_serializing_if = "is_default_true")]
+    pub sync_emails: bool,
 }
 
 impl ConfigurationSection for MatrixConfig {
@@ -149,6 +166,7 @@ impl MatrixConfig {
             homeserver: default_homeserver(),
             secret: Secret::Value(Alphanumeric.sample_string(&mut rng, 32)),
             endpoint: default_endpoint(),
+            sync_emails: default_true(),
         }
     }
 
@@ -158,6 +176,7 @@ impl MatrixConfig {
             homeserver: default_homeserver(),
             secret: Secret::Value("test".to_owned()),
             endpoint: default_endpoint(),
+            sync_emails: default_true(),
         }
     }
 }
@@ -192,6 +211,7 @@ mod tests {
 
                 Handle::current().block_on(async move {
                     assert_eq!(&config.homeserver, "matrix.org");
+                    assert!(config.sync_emails);
                     assert!(matches!(config.secret, Secret::File(ref p) if p == "secret"));
                     assert_eq!(config.secret().await.unwrap(), "m472!x53c237");
                 });
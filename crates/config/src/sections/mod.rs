@@ -9,18 +9,23 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 mod account;
+mod audit_webhook;
 mod branding;
 mod captcha;
 mod clients;
 mod database;
 mod email;
+mod emails;
 mod experimental;
 mod http;
+mod login;
 mod matrix;
+mod openid;
 mod passwords;
 mod policy;
 mod rate_limiting;
 mod secrets;
+mod sessions;
 //:tchap:
 mod tchap;
 //:tchap:end
@@ -30,25 +35,36 @@ mod upstream_oauth2;
 
 pub use self::{
     account::AccountConfig,
+    audit_webhook::{AuditEventKind, AuditWebhookConfig},
     branding::BrandingConfig,
     captcha::{CaptchaConfig, CaptchaServiceKind},
     clients::{ClientAuthMethodConfig, ClientConfig, ClientsConfig},
     database::{DatabaseConfig, PgSslMode},
-    email::{EmailConfig, EmailSmtpMode, EmailTransportKind},
-    experimental::ExperimentalConfig,
+    email::{EmailConfig, EmailProbeConfig, EmailSmtpMode, EmailTransportKind},
+    emails::EmailsConfig,
+    experimental::{
+        DisallowedScopeHandling, ExperimentalConfig, UserAgentDeviceType, UserAgentRuleConfig,
+    },
     http::{
-        BindConfig as HttpBindConfig, HttpConfig, ListenerConfig as HttpListenerConfig,
+        BindConfig as HttpBindConfig, CompressionConfig as HttpCompressionConfig, CookieSameSite,
+        CookiesConfig as HttpCookiesConfig, HttpConfig, ListenerConfig as HttpListenerConfig,
         Resource as HttpResource, TlsConfig as HttpTlsConfig, UnixOrTcp,
     },
+    login::LoginConfig,
     matrix::{HomeserverKind, MatrixConfig},
+    openid::{OpenIdConfig, UserinfoClaimsConfig},
     passwords::{
-        Algorithm as PasswordAlgorithm, HashingScheme as PasswordHashingScheme, PasswordsConfig,
+        Algorithm as PasswordAlgorithm, Argon2idParameters, HashingScheme as PasswordHashingScheme,
+        PasswordsConfig,
     },
     policy::PolicyConfig,
-    rate_limiting::RateLimitingConfig,
-    secrets::SecretsConfig,
+    rate_limiting::{
+        AdminApiClientRateLimitingConfig, RateLimiterConfiguration, RateLimitingConfig,
+    },
+    secrets::{KeyConfig, SecretsConfig},
+    sessions::{SessionLimitStrategy, SessionsConfig},
     //:tchap:
-    tchap::TchapAppConfig,
+    tchap::{TchapAppConfig, TchapFeaturesConfig},
     //:tchap:end
     telemetry::{
         MetricsConfig, MetricsExporterKind, Propagator, TelemetryConfig, TracingConfig,
@@ -56,12 +72,15 @@ pub use self::{
     },
     templates::TemplatesConfig,
     upstream_oauth2::{
+        AdminImportAction as UpstreamOAuth2AdminImportAction,
         ClaimsImports as UpstreamOAuth2ClaimsImports, DiscoveryMode as UpstreamOAuth2DiscoveryMode,
         EmailImportPreference as UpstreamOAuth2EmailImportPreference,
         ImportAction as UpstreamOAuth2ImportAction,
         OnBackchannelLogout as UpstreamOAuth2OnBackchannelLogout,
-        OnConflict as UpstreamOAuth2OnConflict, PkceMethod as UpstreamOAuth2PkceMethod,
-        Provider as UpstreamOAuth2Provider, ResponseMode as UpstreamOAuth2ResponseMode,
+        OnConflict as UpstreamOAuth2OnConflict, OnUnavailable as UpstreamOAuth2OnUnavailable,
+        PkceMethod as UpstreamOAuth2PkceMethod, PrivateKeyJwt as UpstreamOAuth2PrivateKeyJwt,
+        Provider as UpstreamOAuth2Provider, ProviderVisibility as UpstreamOAuth2ProviderVisibility,
+        ResponseMode as UpstreamOAuth2ResponseMode,
         TokenAuthMethod as UpstreamOAuth2TokenAuthMethod, UpstreamOAuth2Config,
     },
 };
@@ -130,6 +149,27 @@ pub struct RootConfig {
     #[serde(default, skip_serializing_if = "AccountConfig::is_default")]
     pub account: AccountConfig,
 
+    /// Configuration section to configure how users manage their email
+    /// addresses
+    #[serde(default, skip_serializing_if = "EmailsConfig::is_default")]
+    pub emails: EmailsConfig,
+
+    /// Configuration section to configure the behaviour of the login page
+    #[serde(default, skip_serializing_if = "LoginConfig::is_default")]
+    pub login: LoginConfig,
+
+    /// Configuration section to send audit events to a webhook
+    #[serde(default, skip_serializing_if = "AuditWebhookConfig::is_default")]
+    pub audit_webhook: AuditWebhookConfig,
+
+    /// Configuration section for the OpenID Connect provider
+    #[serde(default, skip_serializing_if = "OpenIdConfig::is_default")]
+    pub openid: OpenIdConfig,
+
+    /// Configuration section for the limits on user sessions
+    #[serde(default, skip_serializing_if = "SessionsConfig::is_default")]
+    pub sessions: SessionsConfig,
+
     /// Experimental configuration options
     #[serde(default, skip_serializing_if = "ExperimentalConfig::is_default")]
     pub experimental: ExperimentalConfig,
@@ -155,6 +195,11 @@ impl ConfigurationSection for RootConfig {
         self.branding.validate(figment)?;
         self.captcha.validate(figment)?;
         self.account.validate(figment)?;
+        self.emails.validate(figment)?;
+        self.login.validate(figment)?;
+        self.audit_webhook.validate(figment)?;
+        self.openid.validate(figment)?;
+        self.sessions.validate(figment)?;
         self.experimental.validate(figment)?;
 
         Ok(())
@@ -187,6 +232,11 @@ impl RootConfig {
             branding: BrandingConfig::default(),
             captcha: CaptchaConfig::default(),
             account: AccountConfig::default(),
+            emails: EmailsConfig::default(),
+            login: LoginConfig::default(),
+            audit_webhook: AuditWebhookConfig::default(),
+            openid: OpenIdConfig::default(),
+            sessions: SessionsConfig::default(),
             experimental: ExperimentalConfig::default(),
         })
     }
@@ -210,6 +260,11 @@ impl RootConfig {
             branding: BrandingConfig::default(),
             captcha: CaptchaConfig::default(),
             account: AccountConfig::default(),
+            emails: EmailsConfig::default(),
+            login: LoginConfig::default(),
+            audit_webhook: AuditWebhookConfig::default(),
+            openid: OpenIdConfig::default(),
+            sessions: SessionsConfig::default(),
             experimental: ExperimentalConfig::default(),
         }
     }
@@ -253,6 +308,21 @@ pub struct AppConfig {
     #[serde(default)]
     pub account: AccountConfig,
 
+    #[serde(default)]
+    pub emails: EmailsConfig,
+
+    #[serde(default)]
+    pub login: LoginConfig,
+
+    #[serde(default)]
+    pub audit_webhook: AuditWebhookConfig,
+
+    #[serde(default)]
+    pub openid: OpenIdConfig,
+
+    #[serde(default)]
+    pub sessions: SessionsConfig,
+
     #[serde(default)]
     pub experimental: ExperimentalConfig,
 }
@@ -274,6 +344,11 @@ impl ConfigurationSection for AppConfig {
         self.branding.validate(figment)?;
         self.captcha.validate(figment)?;
         self.account.validate(figment)?;
+        self.emails.validate(figment)?;
+        self.login.validate(figment)?;
+        self.audit_webhook.validate(figment)?;
+        self.openid.validate(figment)?;
+        self.sessions.validate(figment)?;
         self.experimental.validate(figment)?;
 
         Ok(())
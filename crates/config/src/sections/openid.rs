@@ -0,0 +1,102 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::ConfigurationSection;
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+const fn is_default_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Which optional claims are released about users, on the userinfo endpoint
+/// and in ID tokens
+///
+/// The `email` and `email_verified` claims are always released when the
+/// `email` scope was granted.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+pub struct UserinfoClaimsConfig {
+    /// Whether to release the display name of the user as the `name` claim.
+    ///
+    /// The display name is fetched from the homeserver. Defaults to `false`.
+    #[serde(default, skip_serializing_if = "is_default_false")]
+    pub name: bool,
+
+    /// Whether to release the `locale` claim, which is the locale detected
+    /// when the user authorized the client. Since sessions don't keep track
+    /// of it, it is only released in ID tokens. Defaults to `false`.
+    #[serde(default, skip_serializing_if = "is_default_false")]
+    pub locale: bool,
+
+    /// Whether to release the `updated_at` claim, which is the last time the
+    /// user or their primary email address changed. Defaults to `false`.
+    #[serde(default, skip_serializing_if = "is_default_false")]
+    pub updated_at: bool,
+}
+
+impl UserinfoClaimsConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        !self.name && !self.locale && !self.updated_at
+    }
+}
+
+/// Configuration section for the OpenID Connect provider
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+pub struct OpenIdConfig {
+    /// Which optional claims are released about users
+    #[serde(default, skip_serializing_if = "UserinfoClaimsConfig::is_default")]
+    pub userinfo_claims: UserinfoClaimsConfig,
+}
+
+impl OpenIdConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        self.userinfo_claims.is_default()
+    }
+}
+
+impl ConfigurationSection for OpenIdConfig {
+    const PATH: Option<&'static str> = Some("openid");
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        Figment, Jail,
+        providers::{Format, Yaml},
+    };
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    openid:
+                      userinfo_claims:
+                        name: true
+                        updated_at: true
+                ",
+            )?;
+
+            let config = Figment::new()
+                .merge(Yaml::file("config.yaml"))
+                .extract_inner::<OpenIdConfig>("openid")?;
+
+            assert!(config.userinfo_claims.name);
+            assert!(!config.userinfo_claims.locale);
+            assert!(config.userinfo_claims.updated_at);
+            assert!(!config.is_default());
+
+            Ok(())
+        });
+    }
+}
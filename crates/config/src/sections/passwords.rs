@@ -18,6 +18,9 @@ fn default_schemes() -> Vec<HashingScheme> {
         version: 1,
         algorithm: Algorithm::default(),
         cost: None,
+        memory: None,
+        iterations: None,
+        parallelism: None,
         secret: None,
         secret_file: None,
         unicode_normalization: false,
@@ -102,6 +105,14 @@ impl ConfigurationSection for PasswordsConfig {
                 ))
                 .into());
             }
+
+            if scheme.algorithm != Algorithm::Argon2id && scheme.argon2id_parameters().is_set() {
+                return Err(annotate(figment::Error::from(format!(
+                    "The `memory`, `iterations` and `parallelism` parameters of scheme version {} only apply to the `argon2id` algorithm",
+                    scheme.version,
+                )))
+                .into());
+            }
         }
 
         Ok(())
@@ -128,9 +139,20 @@ impl PasswordsConfig {
     ///
     /// Returns an error if the config is invalid, or if the secret file could
     /// not be read.
+    #[allow(clippy::type_complexity)]
     pub async fn load(
         &self,
-    ) -> Result<Vec<(u16, Algorithm, Option<u32>, Option<Vec<u8>>, bool)>, anyhow::Error> {
+    ) -> Result<
+        Vec<(
+            u16,
+            Algorithm,
+            Option<u32>,
+            Argon2idParameters,
+            Option<Vec<u8>>,
+            bool,
+        )>,
+        anyhow::Error,
+    > {
         let mut schemes: Vec<&HashingScheme> = self.schemes.iter().collect();
         schemes.sort_unstable_by_key(|a| Reverse(a.version));
         schemes.dedup_by_key(|a| a.version);
@@ -161,6 +183,7 @@ impl PasswordsConfig {
                 scheme.version,
                 scheme.algorithm,
                 scheme.cost,
+                scheme.argon2id_parameters(),
                 secret,
                 scheme.unicode_normalization,
             ));
@@ -198,6 +221,25 @@ pub struct HashingScheme {
     #[schemars(default = "default_bcrypt_cost")]
     pub cost: Option<u32>,
 
+    /// Memory size in KiB for the argon2id algorithm. Defaults to 19456 (19
+    /// MiB).
+    ///
+    /// Changing the argon2id parameters should be done by adding a new scheme
+    /// with a higher version, so that existing passwords get rehashed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 8))]
+    pub memory: Option<u32>,
+
+    /// Number of iterations for the argon2id algorithm. Defaults to 2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1))]
+    pub iterations: Option<u32>,
+
+    /// Degree of parallelism for the argon2id algorithm. Defaults to 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1))]
+    pub parallelism: Option<u32>,
+
     /// An optional secret to use when hashing passwords. This makes it harder
     /// to brute-force the passwords in case of a database leak.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -209,6 +251,40 @@ pub struct HashingScheme {
     pub secret_file: Option<Utf8PathBuf>,
 }
 
+impl HashingScheme {
+    /// The argon2id parameters set on this scheme
+    #[must_use]
+    pub fn argon2id_parameters(&self) -> Argon2idParameters {
+        Argon2idParameters {
+            memory: self.memory,
+            iterations: self.iterations,
+            parallelism: self.parallelism,
+        }
+    }
+}
+
+/// Cost parameters for the argon2id algorithm. Unset parameters use the
+/// defaults of the algorithm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Argon2idParameters {
+    /// Memory size in KiB
+    pub memory: Option<u32>,
+
+    /// Number of iterations
+    pub iterations: Option<u32>,
+
+    /// Degree of parallelism
+    pub parallelism: Option<u32>,
+}
+
+impl Argon2idParameters {
+    /// Whether any of the parameters is set
+    #[must_use]
+    pub const fn is_set(&self) -> bool {
+        self.memory.is_some() || self.iterations.is_some() || self.parallelism.is_some()
+    }
+}
+
 #[allow(clippy::unnecessary_wraps)]
 fn default_bcrypt_cost() -> Option<u32> {
     Some(12)
@@ -228,3 +304,72 @@ pub enum Algorithm {
     /// PBKDF2
     Pbkdf2,
 }
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        Figment, Jail,
+        providers::{Format, Yaml},
+    };
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    passwords:
+                      schemes:
+                        - version: 1
+                          algorithm: bcrypt
+                        - version: 2
+                          algorithm: argon2id
+                          memory: 65536
+                          iterations: 3
+                          parallelism: 4
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<PasswordsConfig>("passwords")?;
+            config.validate(&figment).unwrap();
+
+            assert_eq!(config.schemes.len(), 2);
+            assert!(!config.schemes[0].argon2id_parameters().is_set());
+            assert_eq!(
+                config.schemes[1].argon2id_parameters(),
+                Argon2idParameters {
+                    memory: Some(65536),
+                    iterations: Some(3),
+                    parallelism: Some(4),
+                }
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn reject_argon2id_parameters_on_other_algorithms() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    passwords:
+                      schemes:
+                        - version: 1
+                          algorithm: bcrypt
+                          memory: 65536
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<PasswordsConfig>("passwords")?;
+            assert!(config.validate(&figment).is_err());
+
+            Ok(())
+        });
+    }
+}
@@ -70,6 +70,14 @@ fn is_default_email_entrypoint(value: &String) -> bool {
     *value == default_email_entrypoint()
 }
 
+fn default_account_recovery_entrypoint() -> String {
+    "account_recovery/violation".to_owned()
+}
+
+fn is_default_account_recovery_entrypoint(value: &String) -> bool {
+    *value == default_account_recovery_entrypoint()
+}
+
 fn default_data() -> serde_json::Value {
     serde_json::json!({})
 }
@@ -125,6 +133,13 @@ pub struct PolicyConfig {
     )]
     pub email_entrypoint: String,
 
+    /// Entrypoint to use when recovering an account
+    #[serde(
+        default = "default_account_recovery_entrypoint",
+        skip_serializing_if = "is_default_account_recovery_entrypoint"
+    )]
+    pub account_recovery_entrypoint: String,
+
     /// Arbitrary data to pass to the policy
     #[serde(default = "default_data", skip_serializing_if = "is_default_data")]
     pub data: serde_json::Value,
@@ -139,6 +154,7 @@ impl Default for PolicyConfig {
             authorization_grant_entrypoint: default_authorization_grant_entrypoint(),
             password_entrypoint: default_password_entrypoint(),
             email_entrypoint: default_email_entrypoint(),
+            account_recovery_entrypoint: default_account_recovery_entrypoint(),
             data: default_data(),
         }
     }
@@ -153,6 +169,7 @@ impl PolicyConfig {
             && is_default_authorization_grant_entrypoint(&self.authorization_grant_entrypoint)
             && is_default_password_entrypoint(&self.password_entrypoint)
             && is_default_email_entrypoint(&self.email_entrypoint)
+            && is_default_account_recovery_entrypoint(&self.account_recovery_entrypoint)
             && is_default_data(&self.data)
     }
 }
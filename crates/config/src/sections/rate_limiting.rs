@@ -9,6 +9,8 @@ use std::{num::NonZeroU32, time::Duration};
 use governor::Quota;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, de::Error as _};
+use serde_with::serde_as;
+use ulid::Ulid;
 
 use crate::ConfigurationSection;
 
@@ -31,6 +33,15 @@ pub struct RateLimitingConfig {
     /// Email authentication-specific rate limits
     #[serde(default)]
     pub email_authentication: EmailauthenticationRateLimitingConfig,
+
+    /// Controls how many attempts at re-entering the user code are permitted
+    /// per device code grant, when the grant requests sensitive scopes.
+    #[serde(default = "default_device_code_confirmation")]
+    pub device_code_confirmation: RateLimiterConfiguration,
+
+    /// Admin API-specific rate limits
+    #[serde(default)]
+    pub admin_api: AdminApiRateLimitingConfig,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
@@ -56,6 +67,7 @@ pub struct LoginRateLimitingConfig {
     pub per_account: RateLimiterConfiguration,
 }
 
+#[serde_as]
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct AccountRecoveryRateLimitingConfig {
     /// Controls how many account recovery attempts are permitted
@@ -73,6 +85,15 @@ pub struct AccountRecoveryRateLimitingConfig {
     /// Note: this limit also applies to re-sends.
     #[serde(default = "default_account_recovery_per_address")]
     pub per_address: RateLimiterConfiguration,
+
+    /// Minimum time, in seconds, between two recovery e-mails sent to the same
+    /// address. Recovery requests made within this interval don't send another
+    /// e-mail, even if they were made through different recovery sessions.
+    /// Defaults to 5 minutes.
+    #[schemars(with = "u64")]
+    #[serde(default = "default_account_recovery_email_interval")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub email_interval: chrono::Duration,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
@@ -104,6 +125,44 @@ pub struct EmailauthenticationRateLimitingConfig {
     pub attempt_per_session: RateLimiterConfiguration,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct AdminApiRateLimitingConfig {
+    /// Controls how many read requests (`GET`, `HEAD` and `OPTIONS`) to the
+    /// admin API are permitted per OAuth 2.0 client.
+    #[serde(default = "default_admin_api_read")]
+    pub read: RateLimiterConfiguration,
+
+    /// Controls how many write requests (any other method) to the admin API
+    /// are permitted per OAuth 2.0 client.
+    #[serde(default = "default_admin_api_write")]
+    pub write: RateLimiterConfiguration,
+
+    /// Overrides of the limits for specific OAuth 2.0 clients
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clients: Vec<AdminApiClientRateLimitingConfig>,
+}
+
+/// Overrides of the admin API rate limits for a specific OAuth 2.0 client
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct AdminApiClientRateLimitingConfig {
+    /// The ID of the OAuth 2.0 client these limits apply to
+    #[schemars(
+        with = "String",
+        regex(pattern = r"^[0123456789ABCDEFGHJKMNPQRSTVWXYZ]{26}$"),
+        description = "A ULID as per https://github.com/ulid/spec"
+    )]
+    pub client_id: Ulid,
+
+    /// Limit on read requests for this client. Defaults to the global one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read: Option<RateLimiterConfiguration>,
+
+    /// Limit on write requests for this client. Defaults to the global one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write: Option<RateLimiterConfiguration>,
+}
+
+/// Configuration of a single rate limiter
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct RateLimiterConfiguration {
     /// A one-off burst of actions that the user can perform
@@ -167,6 +226,10 @@ impl ConfigurationSection for RateLimitingConfig {
             return Err(error_on_field(error, "registration").into());
         }
 
+        if let Some(error) = error_on_limiter(&self.device_code_confirmation) {
+            return Err(error_on_field(error, "device_code_confirmation").into());
+        }
+
         if let Some(error) = error_on_limiter(&self.login.per_ip) {
             return Err(error_on_nested_field(error, "login", "per_ip").into());
         }
@@ -174,6 +237,34 @@ impl ConfigurationSection for RateLimitingConfig {
             return Err(error_on_nested_field(error, "login", "per_account").into());
         }
 
+        if let Some(error) = error_on_limiter(&self.admin_api.read) {
+            return Err(error_on_nested_field(error, "admin_api", "read").into());
+        }
+        if let Some(error) = error_on_limiter(&self.admin_api.write) {
+            return Err(error_on_nested_field(error, "admin_api", "write").into());
+        }
+
+        let mut seen_clients = std::collections::BTreeSet::new();
+        for client in &self.admin_api.clients {
+            if !seen_clients.insert(client.client_id) {
+                return Err(error_on_nested_field(
+                    figment::error::Error::custom(format!(
+                        "duplicate rate limits for client {}",
+                        client.client_id
+                    )),
+                    "admin_api",
+                    "clients",
+                )
+                .into());
+            }
+
+            for limiter in [&client.read, &client.write].into_iter().flatten() {
+                if let Some(error) = error_on_limiter(limiter) {
+                    return Err(error_on_nested_field(error, "admin_api", "clients").into());
+                }
+            }
+        }
+
         Ok(())
     }
 }
@@ -185,6 +276,9 @@ impl RateLimitingConfig {
 }
 
 impl RateLimiterConfiguration {
+    /// Convert the configuration into a [`Quota`], returning `None` if the
+    /// rate is zero, i.e. if the limiter should never replenish
+    #[must_use]
     pub fn to_quota(self) -> Option<Quota> {
         let reciprocal = self.per_second.recip();
         if !reciprocal.is_finite() {
@@ -229,6 +323,10 @@ fn default_account_recovery_per_address() -> RateLimiterConfiguration {
     }
 }
 
+fn default_account_recovery_email_interval() -> chrono::Duration {
+    chrono::Duration::minutes(5)
+}
+
 fn default_email_authentication_per_ip() -> RateLimiterConfiguration {
     RateLimiterConfiguration {
         burst: NonZeroU32::new(5).unwrap(),
@@ -257,6 +355,27 @@ fn default_email_authentication_attempt_per_session() -> RateLimiterConfiguratio
     }
 }
 
+fn default_device_code_confirmation() -> RateLimiterConfiguration {
+    RateLimiterConfiguration {
+        burst: NonZeroU32::new(5).unwrap(),
+        per_second: 1.0 / 3600.0,
+    }
+}
+
+fn default_admin_api_read() -> RateLimiterConfiguration {
+    RateLimiterConfiguration {
+        burst: NonZeroU32::new(300).unwrap(),
+        per_second: 10.0,
+    }
+}
+
+fn default_admin_api_write() -> RateLimiterConfiguration {
+    RateLimiterConfiguration {
+        burst: NonZeroU32::new(60).unwrap(),
+        per_second: 2.0,
+    }
+}
+
 impl Default for RateLimitingConfig {
     fn default() -> Self {
         RateLimitingConfig {
@@ -264,6 +383,8 @@ impl Default for RateLimitingConfig {
             registration: default_registration(),
             account_recovery: AccountRecoveryRateLimitingConfig::default(),
             email_authentication: EmailauthenticationRateLimitingConfig::default(),
+            device_code_confirmation: default_device_code_confirmation(),
+            admin_api: AdminApiRateLimitingConfig::default(),
         }
     }
 }
@@ -282,6 +403,7 @@ impl Default for AccountRecoveryRateLimitingConfig {
         AccountRecoveryRateLimitingConfig {
             per_ip: default_account_recovery_per_ip(),
             per_address: default_account_recovery_per_address(),
+            email_interval: default_account_recovery_email_interval(),
         }
     }
 }
@@ -296,3 +418,13 @@ impl Default for EmailauthenticationRateLimitingConfig {
         }
     }
 }
+
+impl Default for AdminApiRateLimitingConfig {
+    fn default() -> Self {
+        AdminApiRateLimitingConfig {
+            read: default_admin_api_read(),
+            write: default_admin_api_write(),
+            clients: Vec::new(),
+        }
+    }
+}
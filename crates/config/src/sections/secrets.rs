@@ -170,7 +170,9 @@ impl KeyConfig {
     /// Returns the JSON Web Key derived from this key config.
     ///
     /// Password and/or key are read from file if they’re given as path.
-    async fn json_web_key(&self) -> anyhow::Result<JsonWebKey<mas_keystore::PrivateKey>> {
+    pub(crate) async fn json_web_key(
+        &self,
+    ) -> anyhow::Result<JsonWebKey<mas_keystore::PrivateKey>> {
         let (key, password) = try_join(self.key(), self.password()).await?;
 
         let private_key = match password {
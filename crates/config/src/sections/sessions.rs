@@ -0,0 +1,232 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::num::NonZeroU32;
+
+use chrono::Duration;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use crate::ConfigurationSection;
+
+/// What to do when a user who reached the maximum number of active sessions
+/// starts a new one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionLimitStrategy {
+    /// Refuse to start the new session
+    #[default]
+    Reject,
+
+    /// End the least recently active sessions to make room for the new one
+    EvictOldest,
+}
+
+impl SessionLimitStrategy {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn default_upstream_authorization_retention() -> Duration {
+    Duration::days(7)
+}
+
+fn is_default_upstream_authorization_retention(value: &Duration) -> bool {
+    *value == default_upstream_authorization_retention()
+}
+
+/// Configuration section for the limits on user sessions
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct SessionsConfig {
+    /// Maximum number of active sessions with a device a user can have at
+    /// once. This covers sessions started through the compatibility login API
+    /// and OAuth 2.0 sessions with a device scope.
+    ///
+    /// This can be overridden for each user through the admin API. If not
+    /// set, there is no limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_per_user: Option<NonZeroU32>,
+
+    /// What to do when a user reached the limit and starts a new session.
+    /// Defaults to `reject`.
+    #[serde(default, skip_serializing_if = "SessionLimitStrategy::is_default")]
+    pub strategy: SessionLimitStrategy,
+
+    /// Time in seconds after which a browser session without any activity has
+    /// to be re-authenticated. If not set, idle browser sessions stay
+    /// authenticated.
+    #[schemars(with = "Option<u64>", range(min = 60))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub browser_idle_timeout: Option<Duration>,
+
+    /// Time in seconds after which a browser session has to be
+    /// re-authenticated, counted from its last authentication. If not set,
+    /// browser sessions stay authenticated until they are ended.
+    #[schemars(with = "Option<u64>", range(min = 60))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub browser_max_lifetime: Option<Duration>,
+
+    /// Time in seconds within which the user must have authenticated to
+    /// perform sensitive actions, like changing their password or adding an
+    /// email address. If not set, those actions don't require a recent
+    /// authentication.
+    #[schemars(with = "Option<u64>", range(min = 60))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde_as(as = "Option<serde_with::DurationSeconds<i64>>")]
+    pub reauthentication_max_age: Option<Duration>,
+
+    /// Time in seconds after which the upstream OAuth 2.0 authorization
+    /// sessions which were never completed are deleted. Defaults to 7 days.
+    ///
+    /// Those are left behind by users who started logging in with an upstream
+    /// provider but never came back from it.
+    #[schemars(with = "u64", range(min = 3600))]
+    #[serde(
+        default = "default_upstream_authorization_retention",
+        skip_serializing_if = "is_default_upstream_authorization_retention"
+    )]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub upstream_authorization_retention: Duration,
+}
+
+impl Default for SessionsConfig {
+    fn default() -> Self {
+        Self {
+            max_per_user: None,
+            strategy: SessionLimitStrategy::default(),
+            browser_idle_timeout: None,
+            browser_max_lifetime: None,
+            reauthentication_max_age: None,
+            upstream_authorization_retention: default_upstream_authorization_retention(),
+        }
+    }
+}
+
+impl SessionsConfig {
+    /// Returns true if the configuration is the default one
+    pub(crate) fn is_default(&self) -> bool {
+        self.max_per_user.is_none()
+            && self.strategy.is_default()
+            && self.browser_idle_timeout.is_none()
+            && self.browser_max_lifetime.is_none()
+            && self.reauthentication_max_age.is_none()
+            && is_default_upstream_authorization_retention(&self.upstream_authorization_retention)
+    }
+}
+
+impl ConfigurationSection for SessionsConfig {
+    const PATH: Option<&'static str> = Some("sessions");
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        Figment, Jail,
+        providers::{Format, Yaml},
+    };
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    sessions:
+                      max_per_user: 2
+                      strategy: evict_oldest
+                ",
+            )?;
+
+            let config = Figment::new()
+                .merge(Yaml::file("config.yaml"))
+                .extract_inner::<SessionsConfig>("sessions")?;
+
+            assert_eq!(config.max_per_user, NonZeroU32::new(2));
+            assert_eq!(config.strategy, SessionLimitStrategy::EvictOldest);
+            assert_eq!(config.upstream_authorization_retention, Duration::days(7));
+            assert!(!config.is_default());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn load_browser_timeouts() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    sessions:
+                      browser_idle_timeout: 3600
+                      browser_max_lifetime: 86400
+                      reauthentication_max_age: 600
+                ",
+            )?;
+
+            let config = Figment::new()
+                .merge(Yaml::file("config.yaml"))
+                .extract_inner::<SessionsConfig>("sessions")?;
+
+            assert_eq!(config.browser_idle_timeout, Some(Duration::hours(1)));
+            assert_eq!(config.browser_max_lifetime, Some(Duration::days(1)));
+            assert_eq!(config.reauthentication_max_age, Some(Duration::minutes(10)));
+            assert!(config.max_per_user.is_none());
+            assert!(!config.is_default());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn load_upstream_authorization_retention() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    sessions:
+                      upstream_authorization_retention: 86400
+                ",
+            )?;
+
+            let config = Figment::new()
+                .merge(Yaml::file("config.yaml"))
+                .extract_inner::<SessionsConfig>("sessions")?;
+
+            assert_eq!(config.upstream_authorization_retention, Duration::days(1));
+            assert!(!config.is_default());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn reject_zero_limit() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    sessions:
+                      max_per_user: 0
+                ",
+            )?;
+
+            let res = Figment::new()
+                .merge(Yaml::file("config.yaml"))
+                .extract_inner::<SessionsConfig>("sessions");
+
+            assert!(res.is_err());
+
+            Ok(())
+        });
+    }
+}
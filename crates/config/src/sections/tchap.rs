@@ -23,6 +23,9 @@
 // USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 
+use std::collections::BTreeMap;
+
+use camino::Utf8PathBuf;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
@@ -34,6 +37,10 @@ fn default_identity_server_url() -> Url {
     Url::parse("http://localhost:8090/").unwrap()
 }
 
+fn default_true() -> bool {
+    true
+}
+
 /// Tchap specific configuration
 #[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -42,9 +49,173 @@ pub struct TchapAppConfig {
     #[serde(default = "default_identity_server_url")]
     pub identity_server_url: Url,
 
+    /// Token sent as a bearer token in the `Authorization` header of the
+    /// requests to the identity server
+    ///
+    /// At most one of `identity_server_token` or `identity_server_token_file`
+    /// can be set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_server_token: Option<String>,
+
+    /// File containing the token sent to the identity server. The file is read
+    /// again when the identity server rejects the token, so that it can be
+    /// rotated without restarting the service
+    ///
+    /// At most one of `identity_server_token` or `identity_server_token_file`
+    /// can be set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub identity_server_token_file: Option<Utf8PathBuf>,
+
     /// Fallback Rules to use when linking an upstream account
     #[serde(default)]
     pub email_lookup_fallback_rules: Vec<EmailLookupFallbackRule>,
+
+    /// Other server names which the identity server may return for this
+    /// homeserver, like a legacy name. The `matrix.homeserver` name is
+    /// always accepted. Server names are compared case-insensitively.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub additional_server_names: Vec<String>,
+
+    /// Labels to show next to the users, keyed by email domain, like
+    /// `numerique.gouv.fr: DINUM`. A domain also applies to its subdomains,
+    /// the most specific one winning. Domains are compared
+    /// case-insensitively.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub domain_labels: BTreeMap<String, String>,
+
+    /// Toggles for the Tchap behaviours. Everything is enabled by default.
+    #[serde(default)]
+    pub features: TchapFeaturesConfig,
+
+    /// How long the answers of the identity server to email checks are
+    /// cached, per outcome
+    #[serde(default)]
+    pub email_check_cache: EmailCheckCacheTtls,
+}
+
+impl TchapAppConfig {
+    /// Returns the token to send to the identity server, if any.
+    ///
+    /// If `identity_server_token_file` was given, the token is read from that
+    /// file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when the token could not be read from file.
+    pub async fn identity_server_token(&self) -> anyhow::Result<Option<String>> {
+        Ok(
+            match (
+                &self.identity_server_token,
+                &self.identity_server_token_file,
+            ) {
+                (Some(token), _) => Some(token.clone()),
+                (None, Some(path)) => {
+                    Some(tokio::fs::read_to_string(path).await?.trim().to_owned())
+                }
+                (None, None) => None,
+            },
+        )
+    }
+}
+
+fn default_positive_ttl() -> chrono::Duration {
+    chrono::Duration::hours(1)
+}
+
+/// How long each outcome of an email check against the identity server is
+/// cached, in seconds. Zero disables caching for that outcome
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct EmailCheckCacheTtls {
+    /// How long to remember that an email is allowed on this server. Defaults
+    /// to 1 hour.
+    #[schemars(with = "u64")]
+    #[serde(default = "default_positive_ttl")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub allowed: chrono::Duration,
+
+    /// How long to remember that an email is mapped to another server.
+    /// Defaults to 1 hour.
+    #[schemars(with = "u64")]
+    #[serde(default = "default_positive_ttl")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub wrong_server: chrono::Duration,
+
+    /// How long to remember that an email requires an invitation which is
+    /// missing. This changes as soon as an invitation is sent, so this isn't
+    /// cached by default.
+    #[schemars(with = "u64")]
+    #[serde(default = "chrono::Duration::zero")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub invitation_missing: chrono::Duration,
+
+    /// How long to remember that the identity server could not be queried.
+    /// Not cached by default.
+    #[schemars(with = "u64")]
+    #[serde(default = "chrono::Duration::zero")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub error: chrono::Duration,
+}
+
+impl Default for EmailCheckCacheTtls {
+    fn default() -> Self {
+        Self {
+            allowed: default_positive_ttl(),
+            wrong_server: default_positive_ttl(),
+            invitation_missing: chrono::Duration::zero(),
+            error: chrono::Duration::zero(),
+        }
+    }
+}
+
+/// Toggles for the Tchap behaviours, so that they can be switched off on a
+/// non-Tchap deployment
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TchapFeaturesConfig {
+    /// Check with the identity server that the email is allowed on this
+    /// server when registering with a password
+    #[serde(default = "default_true")]
+    pub registration_email_gatekeeping: bool,
+
+    /// Check with the identity server that the email is allowed on this
+    /// server when creating an account through an upstream provider
+    #[serde(default = "default_true")]
+    pub upstream_email_gatekeeping: bool,
+
+    /// Match upstream accounts with existing users by their email address,
+    /// instead of by their username
+    #[serde(default = "default_true")]
+    pub upstream_email_matching: bool,
+
+    /// Derive the display name of new users from their email, suffixed with
+    /// their organisation
+    #[serde(default = "default_true")]
+    pub displayname_suffixing: bool,
+
+    /// Whether the identity server can be queried at all. When disabled, the
+    /// email gatekeeping checks are skipped
+    #[serde(default = "default_true")]
+    pub identity_server_lookups: bool,
+
+    /// Add the organization of the user as an `organization` claim in the ID
+    /// tokens and userinfo responses
+    #[serde(default = "default_true")]
+    pub organization_claim: bool,
+}
+
+impl Default for TchapFeaturesConfig {
+    fn default() -> Self {
+        Self {
+            registration_email_gatekeeping: default_true(),
+            upstream_email_gatekeeping: default_true(),
+            upstream_email_matching: default_true(),
+            displayname_suffixing: default_true(),
+            identity_server_lookups: default_true(),
+            organization_claim: default_true(),
+        }
+    }
 }
 
 /// When linking the localpart, the email can be used to find the correct
@@ -67,8 +238,31 @@ impl ConfigurationSection for TchapAppConfig {
     // NOTE: implement this function to perform validation on config
     fn validate(
         &self,
-        _figment: &figment::Figment,
+        figment: &figment::Figment,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        if self.identity_server_token.is_some() && self.identity_server_token_file.is_some() {
+            let mut error = figment::Error::from(
+                "Only one of `identity_server_token` or `identity_server_token_file` can be set at a time"
+                    .to_owned(),
+            );
+            error.metadata = figment.find_metadata(Self::PATH.unwrap()).cloned();
+            error.profile = Some(figment::Profile::Default);
+            error.path = vec![Self::PATH.unwrap().to_owned()];
+            return Err(error.into());
+        }
+
+        let cache = &self.email_check_cache;
+        for ttl in [
+            cache.allowed,
+            cache.wrong_server,
+            cache.invitation_missing,
+            cache.error,
+        ] {
+            if ttl < chrono::Duration::zero() {
+                return Err("tchap.email_check_cache: durations must not be negative".into());
+            }
+        }
+
         Ok(())
     }
 }
@@ -90,9 +284,18 @@ mod tests {
                 r"
                     tchap:
                       identity_server_url: http://localhost:8091
+                      identity_server_token_file: /run/secrets/identity_server_token
                       email_lookup_fallback_rules:
                         - match_with : '@upstream.domain.tld'
                           search: '@matrix.domain.tld'
+                      additional_server_names:
+                        - legacy.domain.tld
+                      domain_labels:
+                        Domain.TLD: Domain
+                      features:
+                        displayname_suffixing: false
+                      email_check_cache:
+                        invitation_missing: 30
                 ",
             )?;
 
@@ -105,6 +308,12 @@ mod tests {
                 "http://localhost:8091/"
             );
 
+            assert_eq!(config.identity_server_token, None);
+            assert_eq!(
+                config.identity_server_token_file,
+                Some(Utf8PathBuf::from("/run/secrets/identity_server_token"))
+            );
+
             assert_eq!(
                 config.email_lookup_fallback_rules,
                 vec![EmailLookupFallbackRule {
@@ -113,6 +322,50 @@ mod tests {
                 }]
             );
 
+            assert_eq!(
+                config.additional_server_names,
+                vec!["legacy.domain.tld".to_owned()]
+            );
+
+            assert_eq!(
+                config.domain_labels,
+                BTreeMap::from([("Domain.TLD".to_owned(), "Domain".to_owned())])
+            );
+
+            assert!(!config.features.displayname_suffixing);
+            assert!(config.features.registration_email_gatekeeping);
+            assert!(config.features.upstream_email_gatekeeping);
+            assert!(config.features.upstream_email_matching);
+            assert!(config.features.identity_server_lookups);
+            assert!(config.features.organization_claim);
+
+            assert_eq!(
+                config.email_check_cache.invitation_missing,
+                chrono::Duration::seconds(30)
+            );
+            assert_eq!(config.email_check_cache.allowed, chrono::Duration::hours(1));
+            assert_eq!(config.email_check_cache.error, chrono::Duration::zero());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn reject_both_identity_server_tokens() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    tchap:
+                      identity_server_token: secret
+                      identity_server_token_file: /run/secrets/identity_server_token
+                ",
+            )?;
+
+            let figment = Figment::new().merge(Yaml::file("config.yaml"));
+            let config = figment.extract_inner::<TchapAppConfig>("tchap")?;
+            assert!(config.validate(&figment).is_err());
+
             Ok(())
         });
     }
@@ -8,12 +8,14 @@ use std::collections::BTreeMap;
 
 use camino::Utf8PathBuf;
 use mas_iana::jose::JsonWebSignatureAlg;
+use mas_jose::constraints::Constrainable;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, de::Error};
 use serde_with::skip_serializing_none;
 use ulid::Ulid;
 use url::Url;
 
+use super::secrets::KeyConfig;
 use crate::ConfigurationSection;
 
 /// Upstream OAuth 2.0 providers configuration
@@ -118,6 +120,18 @@ impl ConfigurationSection for UpstreamOAuth2Config {
                 }
             }
 
+            if provider.private_key_jwt.is_some()
+                && !matches!(
+                    provider.token_endpoint_auth_method,
+                    TokenAuthMethod::PrivateKeyJwt
+                )
+            {
+                return Err(annotate(figment::Error::custom(
+                    "Unexpected field `private_key_jwt` for the selected authentication method",
+                ))
+                .into());
+            }
+
             if matches!(
                 provider.claims_imports.localpart.on_conflict,
                 OnConflict::Add
@@ -222,6 +236,30 @@ impl OnConflict {
     }
 }
 
+/// How to handle a localpart claim which is not available on registration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OnUnavailable {
+    /// Fails the sso login
+    #[default]
+    Fail,
+
+    /// Let the user choose another username, suggesting available ones
+    /// derived from the claim
+    Suggest,
+
+    /// Use the first available suggestion without asking the user. This is
+    /// useful for kiosk-style deployments where users can't pick a username
+    AutoAccept,
+}
+
+impl OnUnavailable {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    const fn is_default(&self) -> bool {
+        matches!(self, OnUnavailable::Fail)
+    }
+}
+
 /// What should be done for the subject attribute
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
 pub struct SubjectImportPreference {
@@ -254,11 +292,22 @@ pub struct LocalpartImportPreference {
     /// How to handle conflicts on the claim, default value is `Fail`
     #[serde(default, skip_serializing_if = "OnConflict::is_default")]
     pub on_conflict: OnConflict,
+
+    /// How to handle a claim which is not available as a username on
+    /// registration, default value is `fail`
+    ///
+    /// When no localpart is imported, suggestions are derived from the email
+    /// address instead
+    #[serde(default, skip_serializing_if = "OnUnavailable::is_default")]
+    pub on_unavailable: OnUnavailable,
 }
 
 impl LocalpartImportPreference {
     const fn is_default(&self) -> bool {
-        self.action.is_default() && self.template.is_none()
+        self.action.is_default()
+            && self.template.is_none()
+            && self.on_conflict.is_default()
+            && self.on_unavailable.is_default()
     }
 }
 
@@ -302,6 +351,75 @@ impl EmailImportPreference {
     }
 }
 
+/// What should be done for the organization attribute
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
+pub struct OrganizationImportPreference {
+    /// How to handle the attribute.
+    ///
+    /// Unlike other attributes, the organization is also updated on later
+    /// logins: with `force` and `require` it is replaced by the value from the
+    /// claims, and with `suggest` it is only set if the user has none yet.
+    #[serde(default, skip_serializing_if = "ImportAction::is_default")]
+    pub action: ImportAction,
+
+    /// The Jinja2 template to use for the organization attribute
+    ///
+    /// If not provided, the default template is `{{ user.organization }}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+impl OrganizationImportPreference {
+    const fn is_default(&self) -> bool {
+        self.action.is_default() && self.template.is_none()
+    }
+}
+
+/// How to handle the claim granting the right to request admin access
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminImportAction {
+    /// Ignore the claim
+    #[default]
+    Ignore,
+
+    /// Import the claim on registration, and update it on every subsequent
+    /// login, granting or revoking the right when the claim changes
+    Always,
+
+    /// Import the claim on registration, but never update it on subsequent
+    /// logins
+    Never,
+}
+
+impl AdminImportAction {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    const fn is_default(&self) -> bool {
+        matches!(self, AdminImportAction::Ignore)
+    }
+}
+
+/// What should be done with the right to request admin access
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
+pub struct AdminImportPreference {
+    /// How to handle the claim
+    #[serde(default, skip_serializing_if = "AdminImportAction::is_default")]
+    pub action: AdminImportAction,
+
+    /// The Jinja2 template to use to determine whether the user can request
+    /// admin access. It must render to `true` or `false`.
+    ///
+    /// If not provided, the default template is `{{ user.can_request_admin }}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+impl AdminImportPreference {
+    const fn is_default(&self) -> bool {
+        self.action.is_default() && self.template.is_none()
+    }
+}
+
 /// What should be done for the account name attribute
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
 pub struct AccountNameImportPreference {
@@ -348,6 +466,17 @@ pub struct ClaimsImports {
         skip_serializing_if = "AccountNameImportPreference::is_default"
     )]
     pub account_name: AccountNameImportPreference,
+
+    /// Import the organization the user belongs to
+    #[serde(
+        default,
+        skip_serializing_if = "OrganizationImportPreference::is_default"
+    )]
+    pub organization: OrganizationImportPreference,
+
+    /// Grant the user the right to request admin access
+    #[serde(default, skip_serializing_if = "AdminImportPreference::is_default")]
+    pub admin: AdminImportPreference,
 }
 
 impl ClaimsImports {
@@ -356,6 +485,8 @@ impl ClaimsImports {
             && self.localpart.is_default()
             && self.displayname.is_default()
             && self.email.is_default()
+            && self.organization.is_default()
+            && self.admin.is_default()
     }
 }
 
@@ -443,6 +574,39 @@ pub struct SignInWithApple {
     pub key_id: String,
 }
 
+/// Additional parameters for the `private_key_jwt` method
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Default)]
+pub struct PrivateKeyJwt {
+    /// Dedicated keys used to sign the client assertion.
+    ///
+    /// If empty, the keys from the `secrets.keys` section are used instead.
+    ///
+    /// To rotate keys, append the new key at the end of the list: the last key
+    /// compatible with the `token_endpoint_auth_signing_alg` is always used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<KeyConfig>,
+}
+
+impl PrivateKeyJwt {
+    /// Load the dedicated keys as PEM-encoded documents, alongside their key
+    /// ID, in the order they were configured
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when a key could not be read or imported
+    pub async fn load_keys(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let mut keys = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            let key = key.json_web_key().await?;
+            let kid = key.kid().unwrap_or_default().to_owned();
+            let pem = key.params().to_pem(pem_rfc7468::LineEnding::LF)?;
+            keys.push((kid, pem.to_string()));
+        }
+
+        Ok(keys)
+    }
+}
+
 fn default_scope() -> String {
     "openid".to_owned()
 }
@@ -474,6 +638,26 @@ impl OnBackchannelLogout {
     }
 }
 
+/// Whether a provider is shown on the login and registration pages
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderVisibility {
+    /// The provider is listed on the login and registration pages
+    #[default]
+    Visible,
+
+    /// The provider is not listed, but can still be used through a direct
+    /// link, which is useful for internal-only providers
+    Hidden,
+}
+
+impl ProviderVisibility {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    const fn is_default(&self) -> bool {
+        matches!(self, ProviderVisibility::Visible)
+    }
+}
+
 /// Configuration for one upstream OAuth 2 provider.
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -551,6 +735,10 @@ pub struct Provider {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sign_in_with_apple: Option<SignInWithApple>,
 
+    /// Additional parameters for the `private_key_jwt` method
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key_jwt: Option<PrivateKeyJwt>,
+
     /// The JWS algorithm to use when authenticating the client with the
     /// provider
     ///
@@ -655,4 +843,18 @@ pub struct Provider {
     /// Defaults to `do_nothing`.
     #[serde(default, skip_serializing_if = "OnBackchannelLogout::is_default")]
     pub on_backchannel_logout: OnBackchannelLogout,
+
+    /// The position of the provider on the login and registration pages.
+    /// Providers are shown in ascending order.
+    ///
+    /// Defaults to the position of the provider in this list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ui_order: Option<i32>,
+
+    /// Whether this provider is listed on the login and registration pages.
+    ///
+    /// Hidden providers can still be used through a direct link, which is
+    /// useful for internal-only providers. Defaults to `visible`.
+    #[serde(default, skip_serializing_if = "ProviderVisibility::is_default")]
+    pub visibility: ProviderVisibility,
 }
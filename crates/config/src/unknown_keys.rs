@@ -0,0 +1,408 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Detection of configuration keys which are not known to MAS
+
+use figment::{
+    Figment, Provider,
+    value::{Dict, Value},
+};
+use schemars::{
+    r#gen::SchemaSettings,
+    schema::{InstanceType, RootSchema, Schema, SchemaObject, SingleOrVec},
+};
+
+use crate::RootConfig;
+
+/// A schema accepting anything
+static ANYTHING: Schema = Schema::Bool(true);
+
+/// A key present in the configuration which is not known to MAS, most likely
+/// because of a typo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    /// The full path of the key, for example
+    /// `upstream_oauth2.providers[0].client_secret_fil`
+    pub path: String,
+
+    /// Where the key was defined, usually the path to a configuration file
+    pub source: String,
+}
+
+impl std::fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (in {})", self.path, self.source)
+    }
+}
+
+/// Find all the keys in the configuration which are not known to MAS.
+///
+/// The keys are checked against the JSON schema of the configuration. Keys
+/// which don't come from a file, like the ones derived from the `MAS_`
+/// environment variables, are ignored: the environment commonly has unrelated
+/// variables with that prefix, and keys with underscores can't be expressed
+/// there anyway.
+///
+/// # Errors
+///
+/// Returns an error if the configuration could not be loaded
+pub fn find_unknown_keys(figment: &Figment) -> Result<Vec<UnknownKey>, Box<figment::Error>> {
+    let schema = config_schema();
+    let root = Schema::Object(schema.schema.clone());
+    let mut checker = Checker {
+        figment,
+        schema: &schema,
+        unknown: Vec::new(),
+    };
+
+    for dict in figment.data()?.values() {
+        checker.check_dict("", dict, &[&root]);
+    }
+
+    checker.unknown.sort_by(|a, b| a.path.cmp(&b.path));
+    checker.unknown.dedup();
+    Ok(checker.unknown)
+}
+
+/// Generate the schema of all the configuration sections
+fn config_schema() -> RootSchema {
+    let mut generator = SchemaSettings::draft07().into_generator();
+
+    //:tchap:
+    let tchap = generator.subschema_for::<crate::TchapAppConfig>();
+    //:tchap:end
+
+    let mut schema = generator.into_root_schema_for::<RootConfig>();
+
+    //:tchap:
+    schema
+        .schema
+        .object()
+        .properties
+        .insert("tchap".to_owned(), tchap);
+    //:tchap:end
+
+    schema
+}
+
+/// The object-related parts of a set of schemas which may describe a value
+#[derive(Default)]
+struct ObjectShape<'a> {
+    /// Known properties, with the schema of their value
+    properties: Vec<(&'a str, &'a Schema)>,
+
+    /// Schemas of the values of arbitrary keys, for maps
+    additional: Vec<&'a Schema>,
+
+    /// Whether any key is accepted, without knowing the schema of the value
+    open: bool,
+}
+
+struct Checker<'a> {
+    figment: &'a Figment,
+    schema: &'a RootSchema,
+    unknown: Vec<UnknownKey>,
+}
+
+impl<'a> Checker<'a> {
+    /// Resolve references and combinations of schemas into a flat list of
+    /// schema objects
+    fn flatten(&self, schema: &'a Schema, out: &mut Vec<Option<&'a SchemaObject>>) {
+        let object = match schema {
+            Schema::Bool(true) => {
+                // Anything goes
+                out.push(None);
+                return;
+            }
+            Schema::Bool(false) => return,
+            Schema::Object(object) => object,
+        };
+
+        if let Some(reference) = &object.reference {
+            if let Some(schema) = reference
+                .strip_prefix("#/definitions/")
+                .and_then(|name| self.schema.definitions.get(name))
+            {
+                self.flatten(schema, out);
+            } else {
+                // We don't know what this points to, so accept anything
+                out.push(None);
+            }
+        }
+
+        if let Some(subschemas) = &object.subschemas {
+            let all = subschemas.all_of.iter().flatten();
+            let any = subschemas.any_of.iter().flatten();
+            let one = subschemas.one_of.iter().flatten();
+            for schema in all.chain(any).chain(one) {
+                self.flatten(schema, out);
+            }
+        }
+
+        if object.reference.is_none() {
+            out.push(Some(object));
+        }
+    }
+
+    fn object_shape(&self, schemas: &[&'a Schema]) -> ObjectShape<'a> {
+        let mut flat = Vec::new();
+        for schema in schemas {
+            self.flatten(schema, &mut flat);
+        }
+
+        let mut shape = ObjectShape::default();
+        for object in flat {
+            let Some(object) = object else {
+                shape.open = true;
+                continue;
+            };
+
+            if let Some(validation) = &object.object {
+                shape.properties.extend(
+                    validation
+                        .properties
+                        .iter()
+                        .map(|(key, schema)| (key.as_str(), schema)),
+                );
+
+                if let Some(additional) = &validation.additional_properties
+                    && !matches!(**additional, Schema::Bool(false))
+                {
+                    shape.additional.push(additional);
+                }
+
+                if !validation.pattern_properties.is_empty() {
+                    shape.open = true;
+                }
+            } else if object.subschemas.is_none() && accepts_objects(object) {
+                // A schema without any constraint on the object keys
+                shape.open = true;
+            }
+        }
+
+        shape
+    }
+
+    fn array_items(&self, schemas: &[&'a Schema], index: usize) -> Vec<&'a Schema> {
+        let mut flat = Vec::new();
+        for schema in schemas {
+            self.flatten(schema, &mut flat);
+        }
+
+        let mut items: Vec<&'a Schema> = Vec::new();
+        for object in flat {
+            let Some(object) = object else {
+                items.push(&ANYTHING);
+                continue;
+            };
+
+            match object.array.as_ref().and_then(|array| array.items.as_ref()) {
+                Some(SingleOrVec::Single(schema)) => items.push(schema),
+                Some(SingleOrVec::Vec(schemas)) => {
+                    items.push(schemas.get(index).unwrap_or(&ANYTHING));
+                }
+                None => {}
+            }
+        }
+
+        items
+    }
+
+    fn check_value(&mut self, path: &str, value: &Value, schemas: &[&'a Schema]) {
+        match value {
+            Value::Dict(_, dict) => self.check_dict(path, dict, schemas),
+            Value::Array(_, values) => {
+                for (index, value) in values.iter().enumerate() {
+                    let items = self.array_items(schemas, index);
+                    if !items.is_empty() {
+                        self.check_value(&format!("{path}[{index}]"), value, &items);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_dict(&mut self, path: &str, dict: &Dict, schemas: &[&'a Schema]) {
+        let shape = self.object_shape(schemas);
+
+        for (key, value) in dict {
+            let key_path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{path}.{key}")
+            };
+
+            let matching: Vec<&Schema> = shape
+                .properties
+                .iter()
+                .filter(|(name, _)| name == key)
+                .map(|(_, schema)| *schema)
+                .collect();
+
+            if !matching.is_empty() {
+                self.check_value(&key_path, value, &matching);
+            } else if !shape.additional.is_empty() {
+                self.check_value(&key_path, value, &shape.additional);
+            } else if !shape.open {
+                self.report(key_path, value);
+            }
+        }
+    }
+
+    fn report(&mut self, path: String, value: &Value) {
+        let Some(source) = self
+            .figment
+            .get_metadata(value.tag())
+            .and_then(|metadata| metadata.source.as_ref())
+        else {
+            // Values without a source come from the environment
+            return;
+        };
+
+        self.unknown.push(UnknownKey {
+            path,
+            source: source.to_string(),
+        });
+    }
+}
+
+/// Whether a schema object accepts objects as values
+fn accepts_objects(object: &SchemaObject) -> bool {
+    match &object.instance_type {
+        None => object.enum_values.is_none() && object.const_value.is_none(),
+        Some(SingleOrVec::Single(instance_type)) => **instance_type == InstanceType::Object,
+        Some(SingleOrVec::Vec(instance_types)) => instance_types.contains(&InstanceType::Object),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        Figment, Jail,
+        providers::{Env, Format, Yaml},
+    };
+
+    use super::*;
+
+    fn figment() -> Figment {
+        Figment::new()
+            .merge(Env::prefixed("MAS_").split("_"))
+            .admerge(Yaml::file("config.yaml"))
+    }
+
+    fn paths(figment: &Figment) -> Vec<String> {
+        find_unknown_keys(figment)
+            .unwrap()
+            .into_iter()
+            .map(|key| key.path)
+            .collect()
+    }
+
+    #[test]
+    fn known_keys() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    http:
+                      listeners:
+                        - name: web
+                          resources:
+                            - name: discovery
+                            - name: assets
+                          binds:
+                            - address: '[::]:8080'
+                    account:
+                      password_registration_enabled: true
+                    matrix:
+                      homeserver: example.com
+                      secret: hunter2
+                      endpoint: http://localhost:8008/
+                    upstream_oauth2:
+                      providers:
+                        - id: 01H8PKNWKKRPCBW4YGH1RWV279
+                          issuer: https://accounts.google.com/
+                          client_id: client
+                          claims_imports:
+                            localpart:
+                              action: require
+                              template: '{{ user.preferred_username }}'
+                          additional_authorization_parameters:
+                            prompt: consent
+                    tchap:
+                      identity_server_url: http://localhost:8091
+                ",
+            )?;
+
+            // Environment variables are not reported
+            jail.set_env("MAS_CONFIG", "config.yaml");
+            jail.set_env("MAS_SOME_UNRELATED_VARIABLE", "1");
+
+            assert_eq!(paths(&figment()), Vec::<String>::new());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn unknown_top_level_key() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    acount:
+                      password_registration_enabled: true
+                    matrix:
+                      homeserver: example.com
+                      secret: hunter2
+                      endpoint: http://localhost:8008/
+                ",
+            )?;
+
+            let unknown = find_unknown_keys(&figment()).unwrap();
+            assert_eq!(unknown.len(), 1);
+            assert_eq!(unknown[0].path, "acount");
+            assert!(unknown[0].source.contains("config.yaml"));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn unknown_provider_key() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r"
+                    upstream_oauth2:
+                      provider:
+                        - id: 01H8PKNWKKRPCBW4YGH1RWV279
+                          client_id: client
+                      providers:
+                        - id: 01H8PKNWKKRPCBW4YGH1RWV279
+                          issuer: https://accounts.google.com/
+                          client_id: client
+                          client_secret_fil: /run/secrets/client_secret
+                          claims_imports:
+                            localpart:
+                              action: require
+                              templat: '{{ user.preferred_username }}'
+                ",
+            )?;
+
+            assert_eq!(
+                paths(&figment()),
+                vec![
+                    "upstream_oauth2.provider",
+                    "upstream_oauth2.providers[0].claims_imports.localpart.templat",
+                    "upstream_oauth2.providers[0].client_secret_fil",
+                ]
+            );
+
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,353 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Events sent to the audit webhook
+//!
+//! Those are serialized as JSON and posted as-is to the webhook, so they must
+//! never contain secrets like passwords or tokens.
+
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{Clock, LoginFailureOrigin, LoginFailureReason, UpstreamOAuthLink, User};
+
+/// The kind of an audit event, used to filter which events are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+    /// A user successfully logged in
+    LoginSuccess,
+
+    /// A login attempt failed for a known user
+    LoginFailure,
+
+    /// A session was ended
+    SessionEnd,
+
+    /// The password of a user was changed
+    PasswordChange,
+
+    /// A user was locked
+    AccountLock,
+
+    /// The right of a user to request admin access was changed by the claims
+    /// of an upstream provider
+    AdminPermissionChange,
+
+    /// An upstream link was moved from one user to another by an
+    /// administrator
+    UpstreamLinkTransfer,
+
+    /// A user was merged into another one by an administrator
+    AccountMerge,
+}
+
+/// The kind of session which was ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditSessionType {
+    /// A browser session
+    Browser,
+
+    /// A session created through the Matrix compatibility layer
+    Compat,
+
+    /// An OAuth 2.0 session
+    #[serde(rename = "oauth2")]
+    OAuth2,
+}
+
+/// Who initiated a password change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordChangeInitiator {
+    /// The user changed their own password
+    User,
+
+    /// The user reset their password through account recovery
+    Recovery,
+
+    /// An administrator set the password
+    Admin,
+}
+
+/// What happened in an audit event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditEventPayload {
+    /// A user successfully logged in
+    LoginSuccess {
+        /// The ID of the user
+        user_id: Ulid,
+
+        /// The username of the user
+        username: String,
+
+        /// Where the login happened
+        origin: LoginFailureOrigin,
+
+        /// The ID of the session created by the login
+        session_id: Ulid,
+    },
+
+    /// A login attempt failed for a known user
+    LoginFailure {
+        /// The ID of the user
+        user_id: Ulid,
+
+        /// The username of the user
+        username: String,
+
+        /// Where the login attempt happened
+        origin: LoginFailureOrigin,
+
+        /// Why the login attempt failed
+        reason: LoginFailureReason,
+    },
+
+    /// A session was ended
+    SessionEnd {
+        /// The ID of the user owning the session
+        user_id: Ulid,
+
+        /// The ID of the session
+        session_id: Ulid,
+
+        /// The kind of session
+        session_type: AuditSessionType,
+    },
+
+    /// The password of a user was changed
+    PasswordChange {
+        /// The ID of the user
+        user_id: Ulid,
+
+        /// The username of the user
+        username: String,
+
+        /// Who initiated the change
+        initiator: PasswordChangeInitiator,
+    },
+
+    /// A user was locked
+    AccountLock {
+        /// The ID of the user
+        user_id: Ulid,
+
+        /// The username of the user
+        username: String,
+    },
+
+    /// The right of a user to request admin access was changed by the claims
+    /// of an upstream provider
+    AdminPermissionChange {
+        /// The ID of the user
+        user_id: Ulid,
+
+        /// The username of the user
+        username: String,
+
+        /// Whether the user can now request admin access
+        can_request_admin: bool,
+
+        /// The ID of the upstream provider whose claims changed the right
+        provider_id: Ulid,
+    },
+
+    /// An upstream link was moved from one user to another by an
+    /// administrator
+    UpstreamLinkTransfer {
+        /// The ID of the upstream link
+        link_id: Ulid,
+
+        /// The ID of the upstream provider of the link
+        provider_id: Ulid,
+
+        /// The ID of the user which previously owned the link, if any
+        previous_user_id: Option<Ulid>,
+
+        /// The ID of the user which now owns the link
+        user_id: Ulid,
+
+        /// The username of the user which now owns the link
+        username: String,
+    },
+
+    /// A user was merged into another one by an administrator
+    AccountMerge {
+        /// The ID of the user which was merged, and deactivated
+        source_user_id: Ulid,
+
+        /// The username of the user which was merged, and deactivated
+        source_username: String,
+
+        /// The ID of the user which received the sessions, emails and
+        /// upstream links
+        user_id: Ulid,
+
+        /// The username of the user which received the sessions, emails and
+        /// upstream links
+        username: String,
+    },
+}
+
+impl AuditEventPayload {
+    /// A successful login of the given user
+    #[must_use]
+    pub fn login_success(user: &User, origin: LoginFailureOrigin, session_id: Ulid) -> Self {
+        Self::LoginSuccess {
+            user_id: user.id,
+            username: user.username.clone(),
+            origin,
+            session_id,
+        }
+    }
+
+    /// A failed login attempt of the given user
+    #[must_use]
+    pub fn login_failure(
+        user: &User,
+        origin: LoginFailureOrigin,
+        reason: LoginFailureReason,
+    ) -> Self {
+        Self::LoginFailure {
+            user_id: user.id,
+            username: user.username.clone(),
+            origin,
+            reason,
+        }
+    }
+
+    /// The end of a session of the given user
+    #[must_use]
+    pub fn session_end(user_id: Ulid, session_id: Ulid, session_type: AuditSessionType) -> Self {
+        Self::SessionEnd {
+            user_id,
+            session_id,
+            session_type,
+        }
+    }
+
+    /// A password change of the given user
+    #[must_use]
+    pub fn password_change(user: &User, initiator: PasswordChangeInitiator) -> Self {
+        Self::PasswordChange {
+            user_id: user.id,
+            username: user.username.clone(),
+            initiator,
+        }
+    }
+
+    /// The given user was locked
+    #[must_use]
+    pub fn account_lock(user: &User) -> Self {
+        Self::AccountLock {
+            user_id: user.id,
+            username: user.username.clone(),
+        }
+    }
+
+    /// A change of the right of the given user to request admin access,
+    /// following the claims of the given upstream provider
+    #[must_use]
+    pub fn admin_permission_change(user: &User, provider_id: Ulid) -> Self {
+        Self::AdminPermissionChange {
+            user_id: user.id,
+            username: user.username.clone(),
+            can_request_admin: user.can_request_admin,
+            provider_id,
+        }
+    }
+
+    /// The transfer of the given upstream link, which was previously owned by
+    /// `previous_user_id`, to the given user
+    #[must_use]
+    pub fn upstream_link_transfer(
+        link: &UpstreamOAuthLink,
+        previous_user_id: Option<Ulid>,
+        user: &User,
+    ) -> Self {
+        Self::UpstreamLinkTransfer {
+            link_id: link.id,
+            provider_id: link.provider_id,
+            previous_user_id,
+            user_id: user.id,
+            username: user.username.clone(),
+        }
+    }
+
+    /// The merge of the `source` user into the `target` user
+    #[must_use]
+    pub fn account_merge(source: &User, target: &User) -> Self {
+        Self::AccountMerge {
+            source_user_id: source.id,
+            source_username: source.username.clone(),
+            user_id: target.id,
+            username: target.username.clone(),
+        }
+    }
+
+    /// The kind of this event
+    #[must_use]
+    pub fn kind(&self) -> AuditEventKind {
+        match self {
+            Self::LoginSuccess { .. } => AuditEventKind::LoginSuccess,
+            Self::LoginFailure { .. } => AuditEventKind::LoginFailure,
+            Self::SessionEnd { .. } => AuditEventKind::SessionEnd,
+            Self::PasswordChange { .. } => AuditEventKind::PasswordChange,
+            Self::AccountLock { .. } => AuditEventKind::AccountLock,
+            Self::AdminPermissionChange { .. } => AuditEventKind::AdminPermissionChange,
+            Self::UpstreamLinkTransfer { .. } => AuditEventKind::UpstreamLinkTransfer,
+            Self::AccountMerge { .. } => AuditEventKind::AccountMerge,
+        }
+    }
+}
+
+/// An event sent to the audit webhook
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// Unique ID of the event, which receivers can use to deduplicate
+    /// deliveries
+    pub id: Ulid,
+
+    /// When the event happened
+    pub occurred_at: DateTime<Utc>,
+
+    /// The IP address of the client which triggered the event, if known
+    pub ip_address: Option<IpAddr>,
+
+    /// What happened
+    #[serde(flatten)]
+    pub payload: AuditEventPayload,
+}
+
+impl AuditEvent {
+    /// Create a new audit event happening now
+    #[must_use]
+    pub fn new(
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        ip_address: Option<IpAddr>,
+        payload: AuditEventPayload,
+    ) -> Self {
+        let occurred_at = clock.now();
+        Self {
+            id: Ulid::from_datetime_with_source(occurred_at.into(), rng),
+            occurred_at,
+            ip_address,
+            payload,
+        }
+    }
+
+    /// The kind of this event
+    #[must_use]
+    pub fn kind(&self) -> AuditEventKind {
+        self.payload.kind()
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use serde::Serialize;
+use thiserror::Error;
+use ulid::Ulid;
+
+/// How the user authenticated when an OAuth 2.0 or compatibility session was
+/// established
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Default)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum AuthenticatedBy {
+    /// The session was created before this was recorded, or through a path
+    /// which doesn't record it
+    #[default]
+    Unknown,
+
+    /// The user entered their password
+    Password,
+
+    /// The user authenticated through an upstream OAuth 2.0 provider
+    Upstream {
+        /// The ID of the upstream OAuth 2.0 provider
+        provider_id: Ulid,
+    },
+
+    /// The session was obtained by exchanging a login token
+    Token,
+
+    /// The session was issued by an administrator
+    AdminIssued,
+}
+
+impl AuthenticatedBy {
+    /// Returns the string representation of the kind of authentication, as
+    /// stored in the database
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::Password => "password",
+            Self::Upstream { .. } => "upstream",
+            Self::Token => "token",
+            Self::AdminIssued => "admin_issued",
+        }
+    }
+
+    /// Returns the ID of the upstream OAuth 2.0 provider the user
+    /// authenticated with, if any
+    #[must_use]
+    pub const fn upstream_provider_id(self) -> Option<Ulid> {
+        match self {
+            Self::Upstream { provider_id } => Some(provider_id),
+            Self::Unknown | Self::Password | Self::Token | Self::AdminIssued => None,
+        }
+    }
+
+    /// Rebuild the authentication method from its representation in the
+    /// database
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the kind is unknown, or if the upstream provider ID
+    /// is missing or set when it shouldn't be.
+    pub fn from_parts(
+        kind: &str,
+        upstream_provider_id: Option<Ulid>,
+    ) -> Result<Self, InvalidAuthenticatedByError> {
+        match (kind, upstream_provider_id) {
+            ("unknown", None) => Ok(Self::Unknown),
+            ("password", None) => Ok(Self::Password),
+            ("upstream", Some(provider_id)) => Ok(Self::Upstream { provider_id }),
+            ("token", None) => Ok(Self::Token),
+            ("admin_issued", None) => Ok(Self::AdminIssued),
+            _ => Err(InvalidAuthenticatedByError(kind.to_owned())),
+        }
+    }
+}
+
+impl std::fmt::Display for AuthenticatedBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid session authentication method {0:?}")]
+pub struct InvalidAuthenticatedByError(String);
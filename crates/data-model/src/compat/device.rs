@@ -13,11 +13,13 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 static GENERATED_DEVICE_ID_LENGTH: usize = 10;
+static MAX_DEVICE_ID_LENGTH: usize = 255;
+static VALID_DEVICE_ID_SYMBOLS: &str = "._~!$&'()*+,;=:/-";
 static UNSTABLE_DEVICE_SCOPE_PREFIX: &str = "urn:matrix:org.matrix.msc2967.client:device:";
 static STABLE_DEVICE_SCOPE_PREFIX: &str = "urn:matrix:client:device:";
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(transparent)]
+#[serde(try_from = "String", into = "String")]
 pub struct Device {
     id: String,
 }
@@ -28,6 +30,23 @@ pub enum ToScopeTokenError {
     InvalidCharacters,
 }
 
+/// The error returned when a string isn't a valid device ID
+///
+/// Device IDs must be between 1 and 255 characters long, and only contain
+/// characters which can be safely used in the device scope:
+/// `[A-Za-z0-9._~!$&'()*+,;=:/-]`
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum InvalidDeviceIdError {
+    #[error("Device ID is empty")]
+    Empty,
+
+    #[error("Device ID is longer than {MAX_DEVICE_ID_LENGTH} characters")]
+    TooLong,
+
+    #[error("Device ID contains an invalid character {0:?}")]
+    InvalidCharacter(char),
+}
+
 impl Device {
     /// Get the corresponding stable and unstable [`ScopeToken`] for that device
     ///
@@ -48,13 +67,29 @@ impl Device {
 
     /// Get the corresponding [`Device`] from a [`ScopeToken`]
     ///
-    /// Returns `None` if the [`ScopeToken`] is not a device scope
+    /// Returns `None` if the [`ScopeToken`] is not a device scope, or if the
+    /// device ID in it is invalid
     #[must_use]
     pub fn from_scope_token(token: &ScopeToken) -> Option<Self> {
+        Self::parse_scope_token(token).ok().flatten()
+    }
+
+    /// Get the corresponding [`Device`] from a [`ScopeToken`], checking that
+    /// the device ID is valid
+    ///
+    /// Returns `Ok(None)` if the [`ScopeToken`] is not a device scope
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the [`ScopeToken`] is a device scope with an invalid
+    /// device ID
+    pub fn parse_scope_token(token: &ScopeToken) -> Result<Option<Self>, InvalidDeviceIdError> {
         let stable = token.as_str().strip_prefix(STABLE_DEVICE_SCOPE_PREFIX);
         let unstable = token.as_str().strip_prefix(UNSTABLE_DEVICE_SCOPE_PREFIX);
-        let id = stable.or(unstable)?;
-        Some(Device::from(id.to_owned()))
+        let Some(id) = stable.or(unstable) else {
+            return Ok(None);
+        };
+        Device::try_from(id.to_owned()).map(Some)
     }
 
     /// Generate a random device ID
@@ -63,6 +98,16 @@ impl Device {
         Self { id }
     }
 
+    /// Create a [`Device`] from an ID which was already validated, like the
+    /// ones stored in the database
+    ///
+    /// This must not be used on IDs coming from outside of MAS, use
+    /// [`Device::try_from`] instead.
+    #[must_use]
+    pub fn from_trusted(id: String) -> Self {
+        Self { id }
+    }
+
     /// Get the inner device ID as [`&str`]
     #[must_use]
     pub fn as_str(&self) -> &str {
@@ -70,9 +115,26 @@ impl Device {
     }
 }
 
-impl From<String> for Device {
-    fn from(id: String) -> Self {
-        Self { id }
+impl TryFrom<String> for Device {
+    type Error = InvalidDeviceIdError;
+
+    fn try_from(id: String) -> Result<Self, Self::Error> {
+        if id.is_empty() {
+            return Err(InvalidDeviceIdError::Empty);
+        }
+
+        if id.len() > MAX_DEVICE_ID_LENGTH {
+            return Err(InvalidDeviceIdError::TooLong);
+        }
+
+        if let Some(c) = id
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && !VALID_DEVICE_ID_SYMBOLS.contains(*c))
+        {
+            return Err(InvalidDeviceIdError::InvalidCharacter(c));
+        }
+
+        Ok(Self { id })
     }
 }
 
@@ -92,11 +154,51 @@ impl std::fmt::Display for Device {
 mod test {
     use oauth2_types::scope::OPENID;
 
+    use super::InvalidDeviceIdError;
     use crate::Device;
 
+    #[test]
+    fn test_device_id_validation() {
+        for valid in [
+            "AABBCCDDEE",
+            "a",
+            "device-1.2_3~",
+            "ab:c/d=e",
+            "A".repeat(255).as_str(),
+        ] {
+            let device = Device::try_from(valid.to_owned()).unwrap();
+            assert_eq!(device.as_str(), valid);
+        }
+
+        assert_eq!(
+            Device::try_from(String::new()),
+            Err(InvalidDeviceIdError::Empty)
+        );
+        assert_eq!(
+            Device::try_from("A".repeat(256)),
+            Err(InvalidDeviceIdError::TooLong)
+        );
+        assert_eq!(
+            Device::try_from("my device".to_owned()),
+            Err(InvalidDeviceIdError::InvalidCharacter(' '))
+        );
+        assert_eq!(
+            Device::try_from("DEVICE@1".to_owned()),
+            Err(InvalidDeviceIdError::InvalidCharacter('@'))
+        );
+        assert_eq!(
+            Device::try_from("appareil-é".to_owned()),
+            Err(InvalidDeviceIdError::InvalidCharacter('é'))
+        );
+
+        // Deserializing also validates the ID
+        assert!(serde_json::from_str::<Device>(r#""ABCDEF""#).is_ok());
+        assert!(serde_json::from_str::<Device>(r#""ABC DEF""#).is_err());
+    }
+
     #[test]
     fn test_device_id_to_from_scope_token() {
-        let device = Device::from("AABBCCDDEE".to_owned());
+        let device = Device::try_from("AABBCCDDEE".to_owned()).unwrap();
         let [stable_scope_token, unstable_scope_token] = device.to_scope_token().unwrap();
         assert_eq!(
             stable_scope_token.as_str(),
@@ -115,5 +217,14 @@ mod test {
             Some(&device)
         );
         assert_eq!(Device::from_scope_token(&OPENID), None);
+
+        // Scopes with an invalid device ID are not device scopes
+        let token = "urn:matrix:client:device:AA#BB".parse().unwrap();
+        assert_eq!(Device::from_scope_token(&token), None);
+        assert_eq!(
+            Device::parse_scope_token(&token),
+            Err(InvalidDeviceIdError::InvalidCharacter('#'))
+        );
+        assert_eq!(Device::parse_scope_token(&OPENID), Ok(None));
     }
 }
@@ -0,0 +1,76 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+use ulid::Ulid;
+
+/// How the email delivery was checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailProbeKind {
+    /// A probe email was sent to the configured recipient
+    Message,
+
+    /// Only the connection to the mail server was checked
+    Connection,
+}
+
+impl EmailProbeKind {
+    /// Returns the string representation of the kind, as stored in the
+    /// database
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Message => "message",
+            Self::Connection => "connection",
+        }
+    }
+}
+
+impl std::fmt::Display for EmailProbeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid email probe kind {0:?}")]
+pub struct InvalidEmailProbeKindError(String);
+
+impl std::str::FromStr for EmailProbeKind {
+    type Err = InvalidEmailProbeKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "message" => Ok(Self::Message),
+            "connection" => Ok(Self::Connection),
+            _ => Err(InvalidEmailProbeKindError(s.to_owned())),
+        }
+    }
+}
+
+/// The outcome of a periodic check of the email delivery
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EmailProbeResult {
+    pub id: Ulid,
+    pub created_at: DateTime<Utc>,
+    pub kind: EmailProbeKind,
+
+    /// How long the check took, in milliseconds
+    pub latency_ms: u64,
+
+    /// Why the check failed, if it did
+    pub error: Option<String>,
+}
+
+impl EmailProbeResult {
+    /// Returns `true` if the check succeeded
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
@@ -8,12 +8,17 @@
 
 use thiserror::Error;
 
+pub(crate) mod audit;
+pub(crate) mod authenticated_by;
 pub mod clock;
 pub(crate) mod compat;
+pub(crate) mod email_probe;
 pub mod oauth2;
 pub mod personal;
 pub(crate) mod policy_data;
+pub(crate) mod provisioning_report;
 mod site_config;
+pub(crate) mod syn2mas_run;
 //:tchap:
 pub(crate) mod tchap_config;
 //:tchap:end
@@ -32,17 +37,32 @@ pub struct InvalidTransitionError;
 pub use ulid::Ulid;
 
 pub use self::{
+    audit::{
+        AuditEvent, AuditEventKind, AuditEventPayload, AuditSessionType, PasswordChangeInitiator,
+    },
+    authenticated_by::{AuthenticatedBy, InvalidAuthenticatedByError},
     clock::{Clock, SystemClock},
     compat::{
         CompatAccessToken, CompatRefreshToken, CompatRefreshTokenState, CompatSession,
-        CompatSessionState, CompatSsoLogin, CompatSsoLoginState, Device, ToScopeTokenError,
+        CompatSessionState, CompatSsoLogin, CompatSsoLoginState, Device, InvalidDeviceIdError,
+        ToScopeTokenError,
     },
+    email_probe::{EmailProbeKind, EmailProbeResult, InvalidEmailProbeKindError},
     oauth2::{
-        AuthorizationCode, AuthorizationGrant, AuthorizationGrantStage, Client, DeviceCodeGrant,
-        DeviceCodeGrantState, InvalidRedirectUriError, JwksOrJwksUri, Pkce, Session, SessionState,
+        AuthorizationCode, AuthorizationGrant, AuthorizationGrantStage, Client, Consent,
+        DeviceCodeGrant, DeviceCodeGrantState, InvalidRedirectUriError, JwksOrJwksUri, Pkce,
+        PushedAuthorizationRequest, Session, SessionState, without_device_scopes,
     },
     policy_data::PolicyData,
-    site_config::{CaptchaConfig, CaptchaService, SessionExpirationConfig, SiteConfig},
+    provisioning_report::{
+        ProvisioningDiscrepancy, ProvisioningDiscrepancyKind, ProvisioningReport,
+    },
+    site_config::{
+        AuditWebhookConfig, BrowserSessionLifetimeConfig, CaptchaConfig, CaptchaService,
+        DisallowedScopeHandling, EmailProbeConfig, SessionExpirationConfig, SessionLimitConfig,
+        SessionLimitStrategy, SiteConfig, UserinfoClaimsConfig,
+    },
+    syn2mas_run::Syn2masRun,
     //:tchap:
     tchap_config::*,
     //:tchap:end
@@ -51,19 +71,23 @@ pub use self::{
     },
     upstream_oauth2::{
         UpstreamOAuthAuthorizationSession, UpstreamOAuthAuthorizationSessionState,
-        UpstreamOAuthLink, UpstreamOAuthProvider, UpstreamOAuthProviderClaimsImports,
+        UpstreamOAuthLink, UpstreamOAuthProvider, UpstreamOAuthProviderAdminImportAction,
+        UpstreamOAuthProviderAdminPreference, UpstreamOAuthProviderClaimsImports,
         UpstreamOAuthProviderDiscoveryMode, UpstreamOAuthProviderImportAction,
         UpstreamOAuthProviderImportPreference, UpstreamOAuthProviderLocalpartPreference,
         UpstreamOAuthProviderOnBackchannelLogout, UpstreamOAuthProviderOnConflict,
-        UpstreamOAuthProviderPkceMode, UpstreamOAuthProviderResponseMode,
-        UpstreamOAuthProviderSubjectPreference, UpstreamOAuthProviderTokenAuthMethod,
+        UpstreamOAuthProviderOnUnavailable, UpstreamOAuthProviderPkceMode,
+        UpstreamOAuthProviderResponseMode, UpstreamOAuthProviderSubjectPreference,
+        UpstreamOAuthProviderTokenAuthMethod,
     },
-    user_agent::{DeviceType, UserAgent},
+    user_agent::{DeviceType, UserAgent, UserAgentParser, UserAgentRule},
     users::{
-        Authentication, AuthenticationMethod, BrowserSession, Password, User, UserEmail,
-        UserEmailAuthentication, UserEmailAuthenticationCode, UserRecoverySession,
-        UserRecoveryTicket, UserRegistration, UserRegistrationPassword, UserRegistrationToken,
+        Authentication, AuthenticationMethod, BrowserSession, InvalidLoginFailureOriginError,
+        InvalidLoginFailureReasonError, LoginFailureOrigin, LoginFailureReason, Password, User,
+        UserEmail, UserEmailAuthentication, UserEmailAuthenticationCode, UserEmailChange,
+        UserLoginFailure, UserNote, UserRecoverySession, UserRecoveryTicket, UserRegistration,
+        UserRegistrationPassword, UserRegistrationToken,
     },
     utils::{BoxClock, BoxRng},
-    version::AppVersion,
+    version::{AppBuildInfo, AppVersion},
 };
@@ -6,7 +6,7 @@
 
 use std::str::FromStr as _;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use mas_iana::oauth::PkceCodeChallengeMethod;
 use oauth2_types::{
     pkce::{CodeChallengeError, CodeChallengeMethodExt},
@@ -164,6 +164,10 @@ pub struct AuthorizationGrant {
     pub created_at: DateTime<Utc>,
     pub login_hint: Option<String>,
     pub locale: Option<String>,
+    pub id_token_claims: Vec<String>,
+    pub max_age: Option<u32>,
+    pub acr_values: Vec<String>,
+    pub acr: Option<String>,
 }
 
 impl std::ops::Deref for AuthorizationGrant {
@@ -200,6 +204,19 @@ impl AuthorizationGrant {
         }
     }
 
+    /// Check whether an authentication which happened at `authenticated_at` is
+    /// recent enough for the `max_age` requested by the client.
+    ///
+    /// The age is measured from the start of the authorization request, so that
+    /// authenticating again during the request always satisfies it, even with
+    /// `max_age=0`.
+    #[must_use]
+    pub fn is_authentication_fresh(&self, authenticated_at: DateTime<Utc>) -> bool {
+        self.max_age.is_none_or(|max_age| {
+            authenticated_at >= self.created_at - Duration::seconds(i64::from(max_age))
+        })
+    }
+
     /// Mark the authorization grant as exchanged.
     ///
     /// # Errors
@@ -223,8 +240,10 @@ impl AuthorizationGrant {
         mut self,
         fulfilled_at: DateTime<Utc>,
         session: &Session,
+        acr: Option<String>,
     ) -> Result<Self, InvalidTransitionError> {
         self.stage = self.stage.fulfill(fulfilled_at, session)?;
+        self.acr = acr;
         Ok(self)
     }
 
@@ -263,6 +282,10 @@ impl AuthorizationGrant {
             created_at: now,
             login_hint: Some(String::from("mxid:@example-user:example.com")),
             locale: Some(String::from("fr")),
+            id_token_claims: Vec::new(),
+            max_age: None,
+            acr_values: Vec::new(),
+            acr: None,
         }
     }
 }
@@ -363,4 +386,27 @@ mod tests {
 
         assert!(matches!(hint, LoginHint::None));
     }
+
+    #[test]
+    fn authentication_freshness() {
+        let now = MockClock::default().now();
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+
+        let grant = AuthorizationGrant::sample(now, &mut rng);
+        assert!(grant.is_authentication_fresh(now - Duration::try_days(365).unwrap()));
+
+        let grant = AuthorizationGrant {
+            max_age: Some(0),
+            ..grant
+        };
+        assert!(!grant.is_authentication_fresh(now - Duration::try_seconds(1).unwrap()));
+        assert!(grant.is_authentication_fresh(now));
+
+        let grant = AuthorizationGrant {
+            max_age: Some(60),
+            ..grant
+        };
+        assert!(!grant.is_authentication_fresh(now - Duration::try_seconds(61).unwrap()));
+        assert!(grant.is_authentication_fresh(now - Duration::try_seconds(60).unwrap()));
+    }
 }
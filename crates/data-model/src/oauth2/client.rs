@@ -11,6 +11,7 @@ use oauth2_types::{
     oidc::ApplicationType,
     registration::{ClientMetadata, Localized},
     requests::GrantType,
+    scope::{Scope, ScopeToken},
 };
 use rand::RngCore;
 use serde::Serialize;
@@ -75,6 +76,10 @@ pub struct Client {
     /// JWS alg algorithm REQUIRED for signing `UserInfo` Responses.
     pub userinfo_signed_response_alg: Option<JsonWebSignatureAlg>,
 
+    /// JWS alg algorithm REQUIRED for signing authorization responses, when
+    /// using a JWT response mode.
+    pub authorization_signed_response_alg: Option<JsonWebSignatureAlg>,
+
     /// Requested authentication method for the token endpoint
     pub token_endpoint_auth_method: Option<OAuthClientAuthenticationMethod>,
 
@@ -86,6 +91,15 @@ pub struct Client {
     /// URI using the https scheme that a third party can use to initiate a
     /// login by the RP
     pub initiate_login_uri: Option<Url>,
+
+    /// Whether users should always be asked for consent, even if they
+    /// previously chose to remember their decision for this client
+    pub always_prompt_consent: bool,
+
+    /// Scopes this client is allowed to request. A value ending with `*`
+    /// allows all the scope tokens starting with the same prefix. `None` means
+    /// the client is not restricted.
+    pub allowed_scopes: Option<Scope>,
 }
 
 #[derive(Debug, Error)]
@@ -123,6 +137,22 @@ impl Client {
         }
     }
 
+    /// Whether this client is allowed to request the given scope token
+    #[must_use]
+    pub fn is_scope_allowed(&self, token: &ScopeToken) -> bool {
+        let Some(allowed_scopes) = &self.allowed_scopes else {
+            return true;
+        };
+
+        allowed_scopes.iter().any(|allowed| {
+            if let Some(prefix) = allowed.as_str().strip_suffix('*') {
+                token.as_str().starts_with(prefix)
+            } else {
+                allowed == token
+            }
+        })
+    }
+
     /// Create a client metadata object for this client
     #[must_use]
     pub fn into_metadata(self) -> ClientMetadata {
@@ -135,6 +165,7 @@ impl Client {
             redirect_uris: Some(self.redirect_uris.clone()),
             response_types: None,
             grant_types: Some(self.grant_types.clone()),
+            scope: self.allowed_scopes,
             application_type: self.application_type.clone(),
             client_name: self.client_name.map(|n| Localized::new(n, [])),
             logo_uri: self.logo_uri.map(|n| Localized::new(n, [])),
@@ -145,6 +176,7 @@ impl Client {
             jwks,
             id_token_signed_response_alg: self.id_token_signed_response_alg,
             userinfo_signed_response_alg: self.userinfo_signed_response_alg,
+            authorization_signed_response_alg: self.authorization_signed_response_alg,
             token_endpoint_auth_method: self.token_endpoint_auth_method,
             token_endpoint_auth_signing_alg: self.token_endpoint_auth_signing_alg,
             initiate_login_uri: self.initiate_login_uri,
@@ -200,7 +232,10 @@ impl Client {
                 token_endpoint_auth_signing_alg: None,
                 id_token_signed_response_alg: None,
                 userinfo_signed_response_alg: None,
+                authorization_signed_response_alg: None,
                 jwks: None,
+                always_prompt_consent: false,
+                allowed_scopes: None,
             },
             // Another client without any URIs set
             Self {
@@ -221,7 +256,10 @@ impl Client {
                 token_endpoint_auth_signing_alg: None,
                 id_token_signed_response_alg: None,
                 userinfo_signed_response_alg: None,
+                authorization_signed_response_alg: None,
                 jwks: None,
+                always_prompt_consent: false,
+                allowed_scopes: None,
             },
         ]
     }
@@ -248,6 +286,8 @@ fn uri_matches_one_of(uri: &Url, registered_uris: &[Url]) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use chrono::TimeZone;
+    use rand::SeedableRng;
     use url::Url;
 
     use super::*;
@@ -283,4 +323,31 @@ mod tests {
             registered_uris
         ));
     }
+
+    #[test]
+    fn test_is_scope_allowed() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let mut client = Client::samples(now, &mut rng).remove(0);
+
+        let openid: ScopeToken = "openid".parse().unwrap();
+        let admin: ScopeToken = "urn:mas:admin".parse().unwrap();
+        let device: ScopeToken = "urn:matrix:org.matrix.msc2967.client:device:ABCDEF"
+            .parse()
+            .unwrap();
+
+        // Clients are not restricted by default
+        assert!(client.is_scope_allowed(&openid));
+        assert!(client.is_scope_allowed(&admin));
+        assert!(client.is_scope_allowed(&device));
+
+        client.allowed_scopes = Some(
+            "openid urn:matrix:org.matrix.msc2967.client:device:*"
+                .parse()
+                .unwrap(),
+        );
+        assert!(client.is_scope_allowed(&openid));
+        assert!(!client.is_scope_allowed(&admin));
+        assert!(client.is_scope_allowed(&device));
+    }
 }
@@ -0,0 +1,44 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use chrono::{DateTime, Utc};
+use oauth2_types::scope::Scope;
+use serde::Serialize;
+use ulid::Ulid;
+
+use crate::compat::Device;
+
+/// The scopes a user remembered consenting to for a client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Consent {
+    /// The ID of the client the consent was given to
+    pub client_id: Ulid,
+
+    /// The ID of the user who gave the consent
+    pub user_id: Ulid,
+
+    /// All the scopes the user consented to for this client
+    pub scope: Scope,
+
+    /// When the user first consented to this client
+    pub created_at: DateTime<Utc>,
+
+    /// When the user last consented to this client
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Remove the device scopes from a scope
+///
+/// Device scopes are different for every session, so they are never
+/// remembered in consents, and ignored when comparing a request with a
+/// remembered consent.
+#[must_use]
+pub fn without_device_scopes(scope: &Scope) -> Scope {
+    scope
+        .iter()
+        .filter(|token| Device::from_scope_token(token).is_none())
+        .cloned()
+        .collect()
+}
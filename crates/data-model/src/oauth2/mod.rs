@@ -6,7 +6,9 @@
 
 mod authorization_grant;
 mod client;
+mod consent;
 mod device_code_grant;
+mod pushed_authorization_request;
 mod session;
 
 pub use self::{
@@ -14,6 +16,8 @@ pub use self::{
         AuthorizationCode, AuthorizationGrant, AuthorizationGrantStage, LoginHint, Pkce,
     },
     client::{Client, InvalidRedirectUriError, JwksOrJwksUri},
+    consent::{Consent, without_device_scopes},
     device_code_grant::{DeviceCodeGrant, DeviceCodeGrantState},
+    pushed_authorization_request::PushedAuthorizationRequest,
     session::{Session, SessionState},
 };
@@ -0,0 +1,99 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use ulid::Ulid;
+
+/// The prefix of the `request_uri` handed out to clients, as suggested by
+/// [RFC 9126](https://www.rfc-editor.org/rfc/rfc9126.html#section-2.2)
+const REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+/// An authorization request pushed by a client to the pushed authorization
+/// request endpoint, to be referenced later on the authorization endpoint
+/// through a `request_uri`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PushedAuthorizationRequest {
+    pub id: Ulid,
+
+    /// The ID of the client which pushed this request
+    pub client_id: Ulid,
+
+    /// The authorization request parameters, form-encoded
+    pub parameters: String,
+
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+
+    /// When the request was used on the authorization endpoint. A request can
+    /// only be used once.
+    pub consumed_at: Option<DateTime<Utc>>,
+}
+
+impl PushedAuthorizationRequest {
+    /// The `request_uri` which references this request
+    #[must_use]
+    pub fn request_uri(&self) -> String {
+        format!("{REQUEST_URI_PREFIX}{}", self.id)
+    }
+
+    /// Parse the ID of a pushed authorization request out of a `request_uri`.
+    ///
+    /// Returns `None` if the `request_uri` wasn't issued by us
+    #[must_use]
+    pub fn id_from_request_uri(request_uri: &str) -> Option<Ulid> {
+        request_uri
+            .strip_prefix(REQUEST_URI_PREFIX)
+            .and_then(|id| id.parse().ok())
+    }
+
+    /// Whether this request expired at the given time
+    #[must_use]
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires_at
+    }
+
+    /// Whether this request was already used on the authorization endpoint
+    #[must_use]
+    pub fn is_consumed(&self) -> bool {
+        self.consumed_at.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_uri_roundtrip() {
+        let now = DateTime::UNIX_EPOCH;
+        let request = PushedAuthorizationRequest {
+            id: Ulid::from_parts(0, 42),
+            client_id: Ulid::nil(),
+            parameters: String::new(),
+            created_at: now,
+            expires_at: now,
+            consumed_at: None,
+        };
+
+        let request_uri = request.request_uri();
+        assert!(request_uri.starts_with("urn:ietf:params:oauth:request_uri:"));
+        assert_eq!(
+            PushedAuthorizationRequest::id_from_request_uri(&request_uri),
+            Some(request.id)
+        );
+
+        assert_eq!(
+            PushedAuthorizationRequest::id_from_request_uri("https://example.com/request"),
+            None
+        );
+        assert_eq!(
+            PushedAuthorizationRequest::id_from_request_uri(
+                "urn:ietf:params:oauth:request_uri:not-an-id"
+            ),
+            None
+        );
+    }
+}
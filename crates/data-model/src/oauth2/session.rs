@@ -11,7 +11,7 @@ use oauth2_types::scope::Scope;
 use serde::Serialize;
 use ulid::Ulid;
 
-use crate::InvalidTransitionError;
+use crate::{AuthenticatedBy, InvalidTransitionError};
 
 #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
 pub enum SessionState {
@@ -84,6 +84,7 @@ pub struct Session {
     pub last_active_at: Option<DateTime<Utc>>,
     pub last_active_ip: Option<IpAddr>,
     pub human_name: Option<String>,
+    pub authenticated_by: AuthenticatedBy,
 }
 
 impl std::ops::Deref for Session {
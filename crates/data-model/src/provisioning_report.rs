@@ -0,0 +1,63 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+/// A kind of discrepancy between a user in MAS and its counterpart on the
+/// homeserver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvisioningDiscrepancyKind {
+    /// The user doesn't exist on the homeserver
+    MissingOnHomeserver,
+
+    /// The user is deactivated in MAS but not on the homeserver, or the other
+    /// way around
+    DeactivatedMismatch,
+
+    /// The user has no display name on the homeserver, or one which differs
+    /// from the display name derived from their email address
+    DisplaynameDrift,
+}
+
+/// A discrepancy found for a user by a provisioning reconciliation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvisioningDiscrepancy {
+    pub user_id: Ulid,
+    pub username: String,
+    pub kind: ProvisioningDiscrepancyKind,
+
+    /// Whether the reconciliation scheduled a fix for the discrepancy
+    pub fixed: bool,
+}
+
+/// The report of a provisioning reconciliation, comparing the users in MAS with
+/// their state on the homeserver
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProvisioningReport {
+    pub id: Ulid,
+    pub created_at: DateTime<Utc>,
+
+    /// Whether the reconciliation should fix the discrepancies it can fix
+    pub fix: bool,
+
+    /// When the reconciliation finished. `None` while it is still running.
+    pub completed_at: Option<DateTime<Utc>>,
+
+    /// How many users were checked
+    pub users_checked: u64,
+
+    pub discrepancies: Vec<ProvisioningDiscrepancy>,
+}
+
+impl ProvisioningReport {
+    /// Returns `true` if the reconciliation finished
+    #[must_use]
+    pub fn is_completed(&self) -> bool {
+        self.completed_at.is_some()
+    }
+}
@@ -4,9 +4,13 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::{collections::BTreeMap, num::NonZeroU32};
+
 use chrono::Duration;
 use url::Url;
 
+use crate::AuditEventKind;
+
 /// Which Captcha service is being used
 #[derive(Debug, Clone, Copy)]
 pub enum CaptchaService {
@@ -28,6 +32,35 @@ pub struct CaptchaConfig {
     pub secret_key: String,
 }
 
+/// Configuration of the webhook receiving audit events
+#[derive(Debug, Clone)]
+pub struct AuditWebhookConfig {
+    /// The URL to which events are posted
+    pub url: Url,
+
+    /// The secret sent as a bearer token along with the events
+    pub secret: Option<String>,
+
+    /// Which kinds of events are sent
+    pub events: Vec<AuditEventKind>,
+}
+
+impl AuditWebhookConfig {
+    /// Returns `true` if events of the given kind should be sent
+    #[must_use]
+    pub fn wants(&self, kind: AuditEventKind) -> bool {
+        self.events.contains(&kind)
+    }
+}
+
+/// Configuration of the periodic check of the email delivery
+#[derive(Debug, Clone, Default)]
+pub struct EmailProbeConfig {
+    /// The mailbox which receives the probe emails. If not set, only the
+    /// connection to the mail server is checked.
+    pub recipient: Option<String>,
+}
+
 /// Automatic session expiration configuration
 #[derive(Debug, Clone)]
 pub struct SessionExpirationConfig {
@@ -36,6 +69,72 @@ pub struct SessionExpirationConfig {
     pub compat_session_inactivity_ttl: Option<Duration>,
 }
 
+/// Which optional claims are released about users, on the userinfo endpoint
+/// and in ID tokens
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserinfoClaimsConfig {
+    /// Whether to release the display name of the user as the `name` claim
+    pub name: bool,
+
+    /// Whether to release the `locale` claim
+    pub locale: bool,
+
+    /// Whether to release the `updated_at` claim
+    pub updated_at: bool,
+}
+
+/// What to do when a user who reached the maximum number of active sessions
+/// starts a new one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionLimitStrategy {
+    /// Refuse to start the new session
+    #[default]
+    Reject,
+
+    /// End the least recently active sessions to make room for the new one
+    EvictOldest,
+}
+
+/// Limit on the number of active sessions with a device a user can have
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SessionLimitConfig {
+    /// The default maximum number of sessions per user, which can be
+    /// overridden on each user. `None` means there is no limit.
+    pub max_per_user: Option<NonZeroU32>,
+
+    /// What to do when the limit is reached
+    pub strategy: SessionLimitStrategy,
+}
+
+/// How long browser sessions stay authenticated before the user has to
+/// authenticate again
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BrowserSessionLifetimeConfig {
+    /// Time without activity after which the session has to be
+    /// re-authenticated
+    pub idle_timeout: Option<Duration>,
+
+    /// Time after the last authentication of the session after which it has
+    /// to be re-authenticated
+    pub max_lifetime: Option<Duration>,
+
+    /// Time within which the session must have been authenticated to perform
+    /// sensitive actions
+    pub reauthentication_max_age: Option<Duration>,
+}
+
+/// What to do when a client requests scopes it is not allowed to request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisallowedScopeHandling {
+    /// Reject the request
+    #[default]
+    Reject,
+
+    /// Remove the disallowed scopes from the request
+    Trim,
+}
+
 /// Random site configuration we want accessible in various places.
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Clone)]
@@ -46,6 +145,9 @@ pub struct SiteConfig {
     /// Time-to-live of compatibility access tokens.
     pub compat_token_ttl: Duration,
 
+    /// Time-to-live of pushed authorization requests.
+    pub pushed_authorization_request_ttl: Duration,
+
     /// The server name, e.g. "matrix.org".
     pub server_name: String,
 
@@ -58,6 +160,10 @@ pub struct SiteConfig {
     /// Imprint to show in the footer.
     pub imprint: Option<String>,
 
+    //:tchap:
+    /// Values exposed to all templates as `globals`.
+    pub template_globals: BTreeMap<String, serde_json::Value>,
+    //:tchap:end
     /// Whether password login is enabled.
     pub password_login_enabled: bool,
 
@@ -73,6 +179,14 @@ pub struct SiteConfig {
     /// Whether users can change their email.
     pub email_change_allowed: bool,
 
+    /// Whether email changes must also be confirmed from the previous address
+    /// of the user.
+    pub email_change_double_confirmation: bool,
+
+    /// Whether users are emailed when a new session is started on their
+    /// account through the compatibility login API.
+    pub new_login_notification: bool,
+
     /// Whether users can change their display name.
     pub displayname_change_allowed: bool,
 
@@ -82,9 +196,28 @@ pub struct SiteConfig {
     /// Whether users can recover their account via email.
     pub account_recovery_allowed: bool,
 
+    /// Minimum interval between two account recovery emails sent to the same
+    /// address
+    pub account_recovery_email_interval: Duration,
+
     /// Whether users can delete their own account.
     pub account_deactivation_allowed: bool,
 
+    /// How long to wait before deactivating the account of a user who asked
+    /// for it.
+    pub deactivation_grace_period: Duration,
+
+    /// How long deleted users are kept before being permanently removed.
+    pub deleted_user_retention: Duration,
+
+    /// Usernames of the accounts which can't be permanently deleted through
+    /// the admin API.
+    pub protected_usernames: Vec<String>,
+
+    /// How long upstream OAuth 2.0 authorization sessions which were never
+    /// completed are kept before being deleted.
+    pub upstream_authorization_retention: Duration,
+
     /// Captcha configuration
     pub captcha: Option<CaptchaConfig>,
 
@@ -97,6 +230,50 @@ pub struct SiteConfig {
     /// Whether users can log in with their email address.
     pub login_with_email_allowed: bool,
 
+    /// Whether to redirect users to the upstream provider when it is the only
+    /// one shown on the login page.
+    pub auto_redirect_single_provider: bool,
+
+    /// Whether password login is preferred over the upstream providers, in
+    /// which case users are not automatically redirected to an upstream
+    /// provider while password login is enabled.
+    pub prefer_password_login: bool,
+
     /// The iframe URL to show in the plan tab of the UI
     pub plan_management_iframe_uri: Option<String>,
+
+    /// The maximum number of rows in a CSV export of the admin API
+    pub admin_api_csv_export_limit: usize,
+
+    /// The webhook receiving audit events, if any
+    pub audit_webhook: Option<AuditWebhookConfig>,
+
+    /// The periodic check of the email delivery, if enabled
+    pub email_probe: Option<EmailProbeConfig>,
+
+    /// Whether to push the email addresses of users to the homeserver
+    pub sync_emails_to_homeserver: bool,
+
+    /// Which optional claims are released about users
+    pub userinfo_claims: UserinfoClaimsConfig,
+
+    /// Scopes for which the user has to re-enter the user code when approving
+    /// a device code grant
+    pub device_code_sensitive_scopes: Vec<String>,
+
+    /// Limit on the number of active sessions per user
+    pub session_limit: SessionLimitConfig,
+
+    /// How long browser sessions stay authenticated
+    pub browser_session_lifetime: BrowserSessionLifetimeConfig,
+
+    /// What to do when a client requests scopes outside of its allowed scopes
+    pub disallowed_scope_handling: DisallowedScopeHandling,
+
+    /// Whether to include the Matrix session details in the token and
+    /// introspection responses
+    pub matrix_session_in_token_response: bool,
+
+    /// Whether to serve the examples of the admin API responses
+    pub admin_api_examples: bool,
 }
@@ -0,0 +1,38 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use ulid::Ulid;
+
+/// A run of the syn2mas migration tool against this database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Syn2masRun {
+    pub id: Ulid,
+    pub started_at: DateTime<Utc>,
+
+    /// Whether the migration ran in dry-run mode, in which case the migrated
+    /// data was discarded
+    pub dry_run: bool,
+
+    /// When the migration finished. `None` if it is still running, or if it
+    /// was interrupted.
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// How many users were migrated. Only set once the migration finished.
+    pub user_count: Option<u64>,
+
+    /// How many compatibility sessions were migrated. Only set once the
+    /// migration finished.
+    pub compat_session_count: Option<u64>,
+}
+
+impl Syn2masRun {
+    /// Returns `true` if the migration finished
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+}
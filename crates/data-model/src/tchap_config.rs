@@ -23,6 +23,10 @@
 // USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 
+use std::collections::BTreeMap;
+
+use camino::Utf8PathBuf;
+use chrono::Duration;
 use url::Url;
 
 /// Random tchap configuration we want accessible in various places.
@@ -32,8 +36,163 @@ pub struct TchapConfig {
     /// Identity Server Url
     pub identity_server_url: Url,
 
+    /// Token sent as a bearer token to the identity server, if it requires
+    /// authentication
+    pub identity_server_token: Option<IdentityServerToken>,
+
     /// Fallback Rules to use when linking an upstream account
     pub email_lookup_fallback_rules: Vec<EmailLookupFallbackRule>,
+
+    /// Other names under which the identity server may know this homeserver,
+    /// like a legacy name
+    pub additional_server_names: Vec<String>,
+
+    /// Labels to show next to the users, keyed by lowercase email domain.
+    /// A domain also applies to its subdomains
+    pub domain_labels: BTreeMap<String, String>,
+
+    /// How long the answers of the identity server are cached, per outcome
+    pub email_check_cache: EmailCheckCacheTtls,
+}
+
+/// Where the token used to authenticate against the identity server comes
+/// from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdentityServerToken {
+    /// The token is set inline in the configuration
+    Inline(String),
+
+    /// The token is read from a file. The file is read again when the identity
+    /// server rejects the token, so that it can be rotated without a restart
+    File(Utf8PathBuf),
+}
+
+/// How long each outcome of an email check against the identity server is
+/// cached. A zero duration disables caching for that outcome
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmailCheckCacheTtls {
+    /// The email is allowed on this server
+    pub allowed: Duration,
+
+    /// The email is mapped to another server. This rarely changes
+    pub wrong_server: Duration,
+
+    /// The email requires an invitation which is missing. This changes as soon
+    /// as an invitation is sent
+    pub invitation_missing: Duration,
+
+    /// The identity server could not be queried
+    pub error: Duration,
+}
+
+impl Default for EmailCheckCacheTtls {
+    fn default() -> Self {
+        Self {
+            allowed: Duration::hours(1),
+            wrong_server: Duration::hours(1),
+            invitation_missing: Duration::zero(),
+            error: Duration::zero(),
+        }
+    }
+}
+
+impl TchapConfig {
+    /// Returns the server names which are accepted for this homeserver, with
+    /// the primary server name first
+    pub fn acceptable_server_names<'a>(
+        &'a self,
+        server_name: &'a str,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        std::iter::once(server_name).chain(self.additional_server_names.iter().map(String::as_str))
+    }
+
+    /// Returns the label configured for the domain of the given email, if
+    /// any. The most specific domain wins, so `sub.example.com` is looked up
+    /// before `example.com`
+    #[must_use]
+    pub fn email_domain_label(&self, email: &str) -> Option<&str> {
+        let (_, domain) = email.rsplit_once('@')?;
+        let mut domain = domain.to_lowercase();
+
+        loop {
+            if let Some(label) = self.domain_labels.get(&domain) {
+                return Some(label);
+            }
+
+            let (_, parent) = domain.split_once('.')?;
+            domain = parent.to_owned();
+        }
+    }
+}
+
+/// Tchap behaviours which can be toggled per deployment, so that the same
+/// binary can run a non-Tchap instance. Everything is enabled by default.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TchapFeatures {
+    /// Check with the identity server that the email is allowed on this
+    /// server when registering with a password
+    pub registration_email_gatekeeping: bool,
+
+    /// Check with the identity server that the email is allowed on this
+    /// server when creating an account through an upstream provider
+    pub upstream_email_gatekeeping: bool,
+
+    /// Match upstream accounts with existing users by their email address,
+    /// instead of by their username
+    pub upstream_email_matching: bool,
+
+    /// Derive the display name of new users from their email, suffixed with
+    /// their organisation
+    pub displayname_suffixing: bool,
+
+    /// Whether the identity server can be queried at all. When disabled, the
+    /// email gatekeeping checks are skipped
+    pub identity_server_lookups: bool,
+
+    /// Add the organization of the user as an `organization` claim in the ID
+    /// tokens and userinfo responses
+    pub organization_claim: bool,
+}
+
+impl Default for TchapFeatures {
+    fn default() -> Self {
+        Self {
+            registration_email_gatekeeping: true,
+            upstream_email_gatekeeping: true,
+            upstream_email_matching: true,
+            displayname_suffixing: true,
+            identity_server_lookups: true,
+            organization_claim: true,
+        }
+    }
+}
+
+impl TchapFeatures {
+    /// Whether the email should be checked against the identity server when
+    /// registering with a password
+    #[must_use]
+    pub const fn checks_registration_email(&self) -> bool {
+        self.identity_server_lookups && self.registration_email_gatekeeping
+    }
+
+    /// Whether the email should be checked against the identity server when
+    /// creating an account through an upstream provider
+    #[must_use]
+    pub const fn checks_upstream_email(&self) -> bool {
+        self.identity_server_lookups && self.upstream_email_gatekeeping
+    }
+
+    /// Whether any of the Tchap behaviours is enabled
+    #[must_use]
+    pub const fn any_enabled(&self) -> bool {
+        self.registration_email_gatekeeping
+            || self.upstream_email_gatekeeping
+            || self.upstream_email_matching
+            || self.displayname_suffixing
+            || self.identity_server_lookups
+            || self.organization_claim
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -41,3 +200,34 @@ pub struct EmailLookupFallbackRule {
     pub match_with: String,
     pub search: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_email_domain_label() {
+        let config = TchapConfig {
+            identity_server_url: Url::parse("http://localhost:8090/").unwrap(),
+            identity_server_token: None,
+            email_lookup_fallback_rules: Vec::new(),
+            additional_server_names: Vec::new(),
+            domain_labels: BTreeMap::from([
+                ("gouv.fr".to_owned(), "Administration".to_owned()),
+                ("numerique.gouv.fr".to_owned(), "DINUM".to_owned()),
+            ]),
+            email_check_cache: EmailCheckCacheTtls::default(),
+        };
+
+        assert_eq!(
+            config.email_domain_label("alice@numerique.gouv.fr"),
+            Some("DINUM")
+        );
+        assert_eq!(
+            config.email_domain_label("bob@Interieur.Gouv.FR"),
+            Some("Administration")
+        );
+        assert_eq!(config.email_domain_label("carol@example.com"), None);
+        assert_eq!(config.email_domain_label("not-an-email"), None);
+    }
+}
@@ -285,6 +285,7 @@ pub struct UpstreamOAuthProvider {
     pub additional_authorization_parameters: Vec<(String, String)>,
     pub forward_login_hint: bool,
     pub on_backchannel_logout: OnBackchannelLogout,
+    pub ui_hidden: bool,
 }
 
 impl PartialOrd for UpstreamOAuthProvider {
@@ -323,6 +324,12 @@ pub struct ClaimsImports {
 
     #[serde(default)]
     pub account_name: SubjectPreference,
+
+    #[serde(default)]
+    pub organization: ImportPreference,
+
+    #[serde(default)]
+    pub admin: AdminPreference,
 }
 
 // XXX: this should have another name
@@ -342,6 +349,9 @@ pub struct LocalpartPreference {
 
     #[serde(default)]
     pub on_conflict: OnConflict,
+
+    #[serde(default)]
+    pub on_unavailable: OnUnavailable,
 }
 
 impl std::ops::Deref for LocalpartPreference {
@@ -369,6 +379,49 @@ impl std::ops::Deref for ImportPreference {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct AdminPreference {
+    #[serde(default)]
+    pub action: AdminImportAction,
+
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+impl std::ops::Deref for AdminPreference {
+    type Target = AdminImportAction;
+
+    fn deref(&self) -> &Self::Target {
+        &self.action
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminImportAction {
+    /// Ignore the claim
+    #[default]
+    Ignore,
+
+    /// Import the claim on registration, and update it on every login
+    Always,
+
+    /// Import the claim on registration only
+    Never,
+}
+
+impl AdminImportAction {
+    #[must_use]
+    pub fn ignore(&self) -> bool {
+        matches!(self, Self::Ignore)
+    }
+
+    #[must_use]
+    pub fn update_on_login(&self) -> bool {
+        matches!(self, Self::Always)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum ImportAction {
@@ -423,3 +476,20 @@ pub enum OnConflict {
     /// existing link or not
     Add,
 }
+
+/// What to do when the localpart mapped from the claims is not available on
+/// registration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OnUnavailable {
+    /// Fails the upstream OAuth 2.0 login
+    #[default]
+    Fail,
+
+    /// Let the user choose another username, with suggestions derived from
+    /// the mapped one
+    Suggest,
+
+    /// Use the first available suggestion, without asking the user
+    AutoAccept,
+}
@@ -39,6 +39,10 @@ pub enum UpstreamOAuthAuthorizationSessionState {
         id_token: Option<String>,
         id_token_claims: Option<serde_json::Value>,
     },
+    Failed {
+        failed_at: DateTime<Utc>,
+        error_code: String,
+    },
 }
 
 impl UpstreamOAuthAuthorizationSessionState {
@@ -68,9 +72,36 @@ impl UpstreamOAuthAuthorizationSessionState {
                 extra_callback_parameters,
                 userinfo,
             }),
-            Self::Completed { .. } | Self::Consumed { .. } | Self::Unlinked { .. } => {
-                Err(InvalidTransitionError)
-            }
+            Self::Completed { .. }
+            | Self::Consumed { .. }
+            | Self::Unlinked { .. }
+            | Self::Failed { .. } => Err(InvalidTransitionError),
+        }
+    }
+
+    /// Mark the upstream OAuth 2.0 authorization session as failed, because
+    /// the provider answered with an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the upstream OAuth 2.0 authorization session state
+    /// is not [`Pending`].
+    ///
+    /// [`Pending`]: UpstreamOAuthAuthorizationSessionState::Pending
+    pub fn fail(
+        self,
+        failed_at: DateTime<Utc>,
+        error_code: String,
+    ) -> Result<Self, InvalidTransitionError> {
+        match self {
+            Self::Pending => Ok(Self::Failed {
+                failed_at,
+                error_code,
+            }),
+            Self::Completed { .. }
+            | Self::Consumed { .. }
+            | Self::Unlinked { .. }
+            | Self::Failed { .. } => Err(InvalidTransitionError),
         }
     }
 
@@ -100,7 +131,7 @@ impl UpstreamOAuthAuthorizationSessionState {
                 extra_callback_parameters,
                 userinfo,
             }),
-            Self::Pending | Self::Consumed { .. } | Self::Unlinked { .. } => {
+            Self::Pending | Self::Consumed { .. } | Self::Unlinked { .. } | Self::Failed { .. } => {
                 Err(InvalidTransitionError)
             }
         }
@@ -115,7 +146,7 @@ impl UpstreamOAuthAuthorizationSessionState {
     #[must_use]
     pub fn link_id(&self) -> Option<Ulid> {
         match self {
-            Self::Pending | Self::Unlinked { .. } => None,
+            Self::Pending | Self::Unlinked { .. } | Self::Failed { .. } => None,
             Self::Completed { link_id, .. } | Self::Consumed { link_id, .. } => Some(*link_id),
         }
     }
@@ -130,7 +161,7 @@ impl UpstreamOAuthAuthorizationSessionState {
     #[must_use]
     pub fn completed_at(&self) -> Option<DateTime<Utc>> {
         match self {
-            Self::Pending => None,
+            Self::Pending | Self::Failed { .. } => None,
             Self::Completed { completed_at, .. }
             | Self::Consumed { completed_at, .. }
             | Self::Unlinked { completed_at, .. } => Some(*completed_at),
@@ -146,7 +177,7 @@ impl UpstreamOAuthAuthorizationSessionState {
     #[must_use]
     pub fn id_token(&self) -> Option<&str> {
         match self {
-            Self::Pending => None,
+            Self::Pending | Self::Failed { .. } => None,
             Self::Completed { id_token, .. }
             | Self::Consumed { id_token, .. }
             | Self::Unlinked { id_token, .. } => id_token.as_deref(),
@@ -163,7 +194,7 @@ impl UpstreamOAuthAuthorizationSessionState {
     #[must_use]
     pub fn id_token_claims(&self) -> Option<&serde_json::Value> {
         match self {
-            Self::Pending => None,
+            Self::Pending | Self::Failed { .. } => None,
             Self::Completed {
                 id_token_claims, ..
             }
@@ -185,7 +216,7 @@ impl UpstreamOAuthAuthorizationSessionState {
     #[must_use]
     pub fn extra_callback_parameters(&self) -> Option<&serde_json::Value> {
         match self {
-            Self::Pending | Self::Unlinked { .. } => None,
+            Self::Pending | Self::Unlinked { .. } | Self::Failed { .. } => None,
             Self::Completed {
                 extra_callback_parameters,
                 ..
@@ -200,7 +231,7 @@ impl UpstreamOAuthAuthorizationSessionState {
     #[must_use]
     pub fn userinfo(&self) -> Option<&serde_json::Value> {
         match self {
-            Self::Pending | Self::Unlinked { .. } => None,
+            Self::Pending | Self::Unlinked { .. } | Self::Failed { .. } => None,
             Self::Completed { userinfo, .. } | Self::Consumed { userinfo, .. } => userinfo.as_ref(),
         }
     }
@@ -215,7 +246,7 @@ impl UpstreamOAuthAuthorizationSessionState {
     #[must_use]
     pub fn consumed_at(&self) -> Option<DateTime<Utc>> {
         match self {
-            Self::Pending | Self::Completed { .. } => None,
+            Self::Pending | Self::Completed { .. } | Self::Failed { .. } => None,
             Self::Consumed { consumed_at, .. } => Some(*consumed_at),
             Self::Unlinked { consumed_at, .. } => *consumed_at,
         }
@@ -231,11 +262,43 @@ impl UpstreamOAuthAuthorizationSessionState {
     #[must_use]
     pub fn unlinked_at(&self) -> Option<DateTime<Utc>> {
         match self {
-            Self::Pending | Self::Completed { .. } | Self::Consumed { .. } => None,
+            Self::Pending
+            | Self::Completed { .. }
+            | Self::Consumed { .. }
+            | Self::Failed { .. } => None,
             Self::Unlinked { unlinked_at, .. } => Some(*unlinked_at),
         }
     }
 
+    /// Get the time at which the upstream OAuth 2.0 authorization session
+    /// failed.
+    ///
+    /// Returns `None` if the upstream OAuth 2.0 authorization session state is
+    /// not [`Failed`].
+    ///
+    /// [`Failed`]: UpstreamOAuthAuthorizationSessionState::Failed
+    #[must_use]
+    pub fn failed_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Failed { failed_at, .. } => Some(*failed_at),
+            _ => None,
+        }
+    }
+
+    /// Get the error code the upstream provider answered with.
+    ///
+    /// Returns `None` if the upstream OAuth 2.0 authorization session state is
+    /// not [`Failed`].
+    ///
+    /// [`Failed`]: UpstreamOAuthAuthorizationSessionState::Failed
+    #[must_use]
+    pub fn error_code(&self) -> Option<&str> {
+        match self {
+            Self::Failed { error_code, .. } => Some(error_code),
+            _ => None,
+        }
+    }
+
     /// Returns `true` if the upstream OAuth 2.0 authorization session state is
     /// [`Pending`].
     ///
@@ -271,6 +334,15 @@ impl UpstreamOAuthAuthorizationSessionState {
     pub fn is_unlinked(&self) -> bool {
         matches!(self, Self::Unlinked { .. })
     }
+
+    /// Returns `true` if the upstream OAuth 2.0 authorization session state is
+    /// [`Failed`].
+    ///
+    /// [`Failed`]: UpstreamOAuthAuthorizationSessionState::Failed
+    #[must_use]
+    pub fn is_failed(&self) -> bool {
+        matches!(self, Self::Failed { .. })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
@@ -335,4 +407,22 @@ impl UpstreamOAuthAuthorizationSession {
         self.state = self.state.consume(consumed_at)?;
         Ok(self)
     }
+
+    /// Mark the upstream OAuth 2.0 authorization session as failed. Returns
+    /// the updated session.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the upstream OAuth 2.0 authorization session state
+    /// is not [`Pending`].
+    ///
+    /// [`Pending`]: UpstreamOAuthAuthorizationSessionState::Pending
+    pub fn fail(
+        mut self,
+        failed_at: DateTime<Utc>,
+        error_code: String,
+    ) -> Result<Self, InvalidTransitionError> {
+        self.state = self.state.fail(failed_at, error_code)?;
+        Ok(self)
+    }
 }
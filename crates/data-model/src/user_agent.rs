@@ -4,17 +4,34 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::sync::LazyLock;
+use std::sync::{LazyLock, OnceLock};
 
 use serde::Serialize;
 use woothee::{parser::Parser, woothee::VALUE_UNKNOWN};
 
 static CUSTOM_USER_AGENT_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| {
-    regex::Regex::new(r"^(?P<name>[^/]+)/(?P<version>[^ ]+) \((?P<segments>.+)\)$").unwrap()
+    // Some clients, like Element X, append the SDK they use after the
+    // parenthesis, which we ignore
+    regex::Regex::new(r"^(?P<name>[^/]+)/(?P<version>[^ ]+) \((?P<segments>[^)]+)\)(?: .*)?$")
+        .unwrap()
 });
 
 static ELECTRON_USER_AGENT_REGEX: LazyLock<regex::Regex> =
-    LazyLock::new(|| regex::Regex::new(r"(?m)\w+/[\w.]+").unwrap());
+    LazyLock::new(|| regex::Regex::new(r"(?m)[\w-]+/[\w.]+").unwrap());
+
+/// Matches simple `product/version` user agents, as sent by most Matrix SDKs
+/// and bots
+static PRODUCT_USER_AGENT_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^(?P<name>[\w.-]+)/(?P<version>[\w.+-]+)(?: .*)?$").unwrap()
+});
+
+/// Operating systems which may appear in the segments of custom user agents
+const KNOWN_OPERATING_SYSTEMS: [&str; 7] = [
+    "Android", "iOS", "iPadOS", "macOS", "Windows", "Linux", "watchOS",
+];
+
+/// The parser used by [`UserAgent::parse`], if one was installed
+static INSTALLED_PARSER: OnceLock<UserAgentParser> = OnceLock::new();
 
 #[derive(Debug, Serialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -58,6 +75,13 @@ impl UserAgent {
 
         match segments[..] {
             ["Linux", "U", os, model, ..] | [model, os, ..] => {
+                // Some clients put the OS first, e.g. `FluffyChat/1.20.0 (Android 14; Pixel 8)`
+                let (model, os) = if is_known_os(model) && !is_known_os(os) {
+                    (os, model)
+                } else {
+                    (model, os)
+                };
+
                 // Most android model have a `/[build version]` suffix we don't care about
                 let model = model.split_once('/').map_or(model, |(model, _)| model);
                 // Some android version also have `Build/[build version]` suffix we don't care
@@ -86,8 +110,19 @@ impl UserAgent {
             .find(|pair| !omit_keys.contains(&pair.0));
     }
 
+    /// Parse a user agent string
+    ///
+    /// This uses the parser installed with [`UserAgentParser::install`] if
+    /// any, or the built-in heuristics otherwise.
     #[must_use]
     pub fn parse(user_agent: String) -> Self {
+        match INSTALLED_PARSER.get() {
+            Some(parser) => parser.parse(user_agent),
+            None => Self::parse_builtin(user_agent),
+        }
+    }
+
+    fn parse_builtin(user_agent: String) -> Self {
         if !user_agent.contains("Mozilla/")
             && let Some((name, version, model, os, os_version)) =
                 UserAgent::parse_custom(&user_agent)
@@ -117,6 +152,19 @@ impl UserAgent {
 
         let mut model = None;
         let Some(mut result) = Parser::new().parse(&user_agent) else {
+            // Fallback to simple `product/version` user agents
+            if let Some(captures) = PRODUCT_USER_AGENT_REGEX.captures(&user_agent) {
+                return Self {
+                    name: Some(captures["name"].to_owned()),
+                    version: Some(captures["version"].to_owned()),
+                    os: None,
+                    os_version: None,
+                    model: None,
+                    device_type: DeviceType::Unknown,
+                    raw: user_agent,
+                };
+            }
+
             return Self {
                 raw: user_agent,
                 name: None,
@@ -223,3 +271,415 @@ impl UserAgent {
         }
     }
 }
+
+/// Whether the given user agent segment looks like an operating system, e.g.
+/// `Android 14`
+fn is_known_os(segment: &str) -> bool {
+    let name = segment.split_once(' ').map_or(segment, |(name, _)| name);
+    KNOWN_OPERATING_SYSTEMS.contains(&name)
+}
+
+/// A custom rule to recognise user agents, evaluated before the built-in
+/// heuristics
+///
+/// The regular expression can use the `name`, `version`, `os`, `os_version`
+/// and `model` named groups to fill the corresponding fields.
+#[derive(Debug, Clone)]
+pub struct UserAgentRule {
+    regex: regex::Regex,
+    device_type: DeviceType,
+}
+
+impl UserAgentRule {
+    /// Create a new rule out of a regular expression, recognising user agents
+    /// of the given device type
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pattern is not a valid regular expression
+    pub fn new(pattern: &str, device_type: DeviceType) -> Result<Self, regex::Error> {
+        let regex = regex::Regex::new(pattern)?;
+        Ok(Self { regex, device_type })
+    }
+
+    fn apply(&self, user_agent: &str) -> Option<UserAgent> {
+        let captures = self.regex.captures(user_agent)?;
+        let field = |name| captures.name(name).map(|m| m.as_str().to_owned());
+
+        Some(UserAgent {
+            name: field("name"),
+            version: field("version"),
+            os: field("os"),
+            os_version: field("os_version"),
+            model: field("model"),
+            device_type: self.device_type.clone(),
+            raw: user_agent.to_owned(),
+        })
+    }
+}
+
+/// A user agent parser, with custom rules evaluated before the built-in
+/// heuristics
+#[derive(Debug, Clone, Default)]
+pub struct UserAgentParser {
+    rules: Vec<UserAgentRule>,
+}
+
+impl UserAgentParser {
+    /// Create a new parser with the given custom rules, evaluated in order
+    #[must_use]
+    pub fn new(rules: Vec<UserAgentRule>) -> Self {
+        Self { rules }
+    }
+
+    /// Parse a user agent string, using the first custom rule which matches,
+    /// or the built-in heuristics if none does
+    #[must_use]
+    pub fn parse(&self, user_agent: String) -> UserAgent {
+        self.rules
+            .iter()
+            .find_map(|rule| rule.apply(&user_agent))
+            .unwrap_or_else(|| UserAgent::parse_builtin(user_agent))
+    }
+
+    /// Install this parser as the one used by [`UserAgent::parse`]
+    ///
+    /// # Errors
+    ///
+    /// Returns the parser back if one was already installed
+    pub fn install(self) -> Result<(), Self> {
+        INSTALLED_PARSER.set(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A corpus of real-world user agents, with the expected name, version,
+    /// OS, OS version, model and device type
+    #[allow(clippy::type_complexity)]
+    const CORPUS: &[(
+        &str,
+        Option<&str>,
+        Option<&str>,
+        Option<&str>,
+        Option<&str>,
+        Option<&str>,
+        DeviceType,
+    )] = &[
+        (
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36",
+            Some("Chrome"),
+            Some("124.0.0.0"),
+            Some("Windows"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36 Edg/124.0.0.0",
+            Some("Edge"),
+            Some("124.0.0.0"),
+            Some("Windows"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:125.0) Gecko/20100101 Firefox/125.0",
+            Some("Firefox"),
+            Some("125.0"),
+            Some("Windows"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4.1 Safari/605.1.15",
+            Some("Safari"),
+            Some("17.4.1"),
+            Some("macOS"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:125.0) Gecko/20100101 Firefox/125.0",
+            Some("Firefox"),
+            Some("125.0"),
+            Some("macOS"),
+            Some("10.15"),
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36",
+            Some("Chrome"),
+            Some("124.0.0.0"),
+            Some("macOS"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36",
+            Some("Chrome"),
+            Some("124.0.0.0"),
+            Some("Linux"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:125.0) Gecko/20100101 Firefox/125.0",
+            Some("Firefox"),
+            Some("125.0"),
+            Some("Linux"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (X11; CrOS x86_64 14541.0.0) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36",
+            Some("Chrome"),
+            Some("124.0.0.0"),
+            Some("Chrome OS"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_4_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4.1 Mobile/15E148 Safari/604.1",
+            Some("Safari"),
+            Some("17.4.1"),
+            Some("iOS"),
+            Some("17.4.1"),
+            Some("iPhone"),
+            DeviceType::Mobile,
+        ),
+        (
+            "Mozilla/5.0 (iPad; CPU OS 17_4_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.4.1 Mobile/15E148 Safari/604.1",
+            Some("Safari"),
+            Some("17.4.1"),
+            Some("iPadOS"),
+            Some("17.4.1"),
+            Some("iPad"),
+            DeviceType::Tablet,
+        ),
+        (
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) CriOS/124.0.6367.88 Mobile/15E148 Safari/604.1",
+            Some("Chrome"),
+            Some("124.0.6367.88"),
+            Some("iOS"),
+            Some("17.4"),
+            Some("iPhone"),
+            DeviceType::Mobile,
+        ),
+        (
+            "Mozilla/5.0 (Linux; Android 10; K) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Mobile Safari/537.36",
+            Some("Chrome"),
+            Some("124.0.0.0"),
+            Some("Android"),
+            None,
+            None,
+            DeviceType::Mobile,
+        ),
+        (
+            "Mozilla/5.0 (Android 14; Mobile; rv:125.0) Gecko/125.0 Firefox/125.0",
+            Some("Firefox"),
+            Some("125.0"),
+            Some("Android"),
+            Some("14"),
+            None,
+            DeviceType::Mobile,
+        ),
+        (
+            "Mozilla/5.0 (Linux; Android 14; SM-S918B) AppleWebKit/537.36 (KHTML, like Gecko) SamsungBrowser/24.0 Chrome/117.0.0.0 Mobile Safari/537.36",
+            Some("SamsungBrowser"),
+            Some("24.0"),
+            Some("Android"),
+            Some("14"),
+            None,
+            DeviceType::Mobile,
+        ),
+        (
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36 OPR/109.0.0.0",
+            Some("Opera"),
+            Some("109.0.0.0"),
+            Some("Windows"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Element/1.11.66 Chrome/124.0.6367.60 Electron/30.0.1 Safari/537.36",
+            Some("Element"),
+            Some("1.11.66"),
+            Some("macOS"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Element/1.11.66 Chrome/124.0.6367.60 Electron/30.0.1 Safari/537.36",
+            Some("Element"),
+            Some("1.11.66"),
+            Some("Windows"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Element-Nightly/2024050101 Chrome/124.0.6367.60 Electron/30.0.1 Safari/537.36",
+            Some("Element-Nightly"),
+            Some("2024050101"),
+            Some("Linux"),
+            None,
+            None,
+            DeviceType::Pc,
+        ),
+        (
+            "Element X/1.6.5 (iPhone15,2; iOS 17.4.1; Scale/3.00)",
+            Some("Element X"),
+            Some("1.6.5"),
+            Some("iOS"),
+            Some("17.4.1"),
+            Some("iPhone15,2"),
+            DeviceType::Mobile,
+        ),
+        (
+            "Element X/1.6.5 (iPad13,8; iPadOS 17.4; Scale/2.00)",
+            Some("Element X"),
+            Some("1.6.5"),
+            Some("iPadOS"),
+            Some("17.4"),
+            Some("iPad13,8"),
+            DeviceType::Tablet,
+        ),
+        (
+            "Element X/0.4.10 (Google Pixel 8; Android 14; AP1A.240405.002; Sdk 1a2b3c4)",
+            Some("Element X"),
+            Some("0.4.10"),
+            Some("Android"),
+            Some("14"),
+            Some("Google Pixel 8"),
+            DeviceType::Mobile,
+        ),
+        (
+            "Element/1.6.12 (Samsung SM-G991B; Android 13; TP1A.220624.014 release-keys; Flavour GooglePlay; MatrixAndroidSdk2 1.6.12)",
+            Some("Element"),
+            Some("1.6.12"),
+            Some("Android"),
+            Some("13"),
+            Some("Samsung SM-G991B"),
+            DeviceType::Mobile,
+        ),
+        (
+            "Element/1.11.35 (iPhone; iOS 17.4.1; Scale/3.00)",
+            Some("Element"),
+            Some("1.11.35"),
+            Some("iOS"),
+            Some("17.4.1"),
+            Some("iPhone"),
+            DeviceType::Mobile,
+        ),
+        (
+            "Element X/25.04.1 (Google Pixel 7; Android 15; AP4A.250405.002; Sdk 25.4.1) matrix-rust-sdk/0.11.0",
+            Some("Element X"),
+            Some("25.04.1"),
+            Some("Android"),
+            Some("15"),
+            Some("Google Pixel 7"),
+            DeviceType::Mobile,
+        ),
+        (
+            "matrix-nio/0.24.0",
+            Some("matrix-nio"),
+            Some("0.24.0"),
+            None,
+            None,
+            None,
+            DeviceType::Unknown,
+        ),
+        (
+            "mautrix-go/0.18.1",
+            Some("mautrix-go"),
+            Some("0.18.1"),
+            None,
+            None,
+            None,
+            DeviceType::Unknown,
+        ),
+        (
+            "matrix-rust-sdk/0.7.1",
+            Some("matrix-rust-sdk"),
+            Some("0.7.1"),
+            None,
+            None,
+            None,
+            DeviceType::Unknown,
+        ),
+        (
+            "Synapse/1.105.0",
+            Some("Synapse"),
+            Some("1.105.0"),
+            None,
+            None,
+            None,
+            DeviceType::Unknown,
+        ),
+        (
+            "FluffyChat/1.20.0 (Android 14; Pixel 8)",
+            Some("FluffyChat"),
+            Some("1.20.0"),
+            Some("Android"),
+            Some("14"),
+            Some("Pixel 8"),
+            DeviceType::Mobile,
+        ),
+    ];
+
+    #[test]
+    fn test_parse_corpus() {
+        for (raw, name, version, os, os_version, model, device_type) in CORPUS {
+            let user_agent = UserAgent::parse_builtin((*raw).to_owned());
+            assert_eq!(user_agent.name.as_deref(), *name, "name of {raw}");
+            assert_eq!(user_agent.version.as_deref(), *version, "version of {raw}");
+            assert_eq!(user_agent.os.as_deref(), *os, "os of {raw}");
+            assert_eq!(
+                user_agent.os_version.as_deref(),
+                *os_version,
+                "os_version of {raw}"
+            );
+            assert_eq!(user_agent.model.as_deref(), *model, "model of {raw}");
+            assert_eq!(&user_agent.device_type, device_type, "device type of {raw}");
+        }
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        let parser = UserAgentParser::new(vec![
+            UserAgentRule::new(
+                r"^Tchap/(?P<version>[\d.]+) \((?P<os>\w+) (?P<os_version>[\d.]+)\)$",
+                DeviceType::Mobile,
+            )
+            .unwrap(),
+        ]);
+
+        let user_agent = parser.parse("Tchap/2.15.0 (Android 14)".to_owned());
+        assert_eq!(user_agent.name, None);
+        assert_eq!(user_agent.version.as_deref(), Some("2.15.0"));
+        assert_eq!(user_agent.os.as_deref(), Some("Android"));
+        assert_eq!(user_agent.os_version.as_deref(), Some("14"));
+        assert_eq!(user_agent.device_type, DeviceType::Mobile);
+
+        // User agents not matching any rule fallback to the built-in parser
+        let user_agent = parser.parse("matrix-nio/0.24.0".to_owned());
+        assert_eq!(user_agent.name.as_deref(), Some("matrix-nio"));
+        assert_eq!(user_agent.version.as_deref(), Some("0.24.0"));
+
+        // Invalid patterns are rejected
+        assert!(UserAgentRule::new("(", DeviceType::Unknown).is_err());
+    }
+}
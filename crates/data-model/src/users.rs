@@ -8,7 +8,8 @@ use std::net::IpAddr;
 
 use chrono::{DateTime, Utc};
 use rand::Rng;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use ulid::Ulid;
 use url::Url;
 
@@ -20,8 +21,14 @@ pub struct User {
     pub created_at: DateTime<Utc>,
     pub locked_at: Option<DateTime<Utc>>,
     pub deactivated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
     pub can_request_admin: bool,
     pub is_guest: bool,
+    pub organization: Option<String>,
+    pub max_sessions: Option<u32>,
+    pub locale: Option<String>,
+    pub deactivation_scheduled_at: Option<DateTime<Utc>>,
+    pub external_id: Option<String>,
 }
 
 impl User {
@@ -44,6 +51,14 @@ impl User {
     pub fn is_valid_actor(&self) -> bool {
         self.deactivated_at.is_none()
     }
+
+    /// Returns `true` if the user was deleted and is waiting to be purged.
+    ///
+    /// A deleted user is always deactivated as well.
+    #[must_use]
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
 }
 
 impl User {
@@ -57,8 +72,14 @@ impl User {
             created_at: now,
             locked_at: None,
             deactivated_at: None,
+            deleted_at: None,
             can_request_admin: false,
             is_guest: false,
+            organization: None,
+            max_sessions: None,
+            locale: None,
+            deactivation_scheduled_at: None,
+            external_id: None,
         }]
     }
 }
@@ -86,6 +107,30 @@ pub enum AuthenticationMethod {
     Unknown,
 }
 
+impl AuthenticationMethod {
+    /// The Authentication Context Class Reference of a password authentication
+    pub const PASSWORD_ACR: &'static str = "urn:mas:acr:password";
+
+    /// The Authentication Context Class Reference of an authentication through
+    /// an upstream OAuth 2.0 provider
+    pub const UPSTREAM_OAUTH2_ACR: &'static str = "urn:mas:acr:upstream_oauth2";
+
+    /// All the Authentication Context Class References which can be satisfied
+    pub const SUPPORTED_ACR_VALUES: [&'static str; 2] =
+        [Self::PASSWORD_ACR, Self::UPSTREAM_OAUTH2_ACR];
+
+    /// Get the Authentication Context Class Reference satisfied by this
+    /// authentication method, if any
+    #[must_use]
+    pub fn acr(&self) -> Option<&'static str> {
+        match self {
+            Self::Password { .. } => Some(Self::PASSWORD_ACR),
+            Self::UpstreamOAuth2 { .. } => Some(Self::UPSTREAM_OAUTH2_ACR),
+            Self::Unknown => None,
+        }
+    }
+}
+
 /// A session to recover a user if they have lost their credentials
 ///
 /// For each session intiated, there may be multiple [`UserRecoveryTicket`]s
@@ -145,6 +190,62 @@ pub struct UserEmailAuthenticationCode {
     pub expires_at: DateTime<Utc>,
 }
 
+/// A change of the email address of a user, waiting to be confirmed from the
+/// address the user already had
+///
+/// The new address was verified with a [`UserEmailAuthentication`], but is
+/// only added to the account once the change is confirmed with the ticket sent
+/// to the previous address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserEmailChange {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub user_email_authentication_id: Ulid,
+    pub email: String,
+    pub previous_email: String,
+    pub ticket: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub cancelled_at: Option<DateTime<Utc>>,
+}
+
+impl UserEmailChange {
+    /// Whether the change can still be confirmed or cancelled
+    #[must_use]
+    pub fn is_pending(&self, now: DateTime<Utc>) -> bool {
+        self.confirmed_at.is_none() && self.cancelled_at.is_none() && now < self.expires_at
+    }
+
+    #[must_use]
+    pub fn samples(now: chrono::DateTime<Utc>, rng: &mut impl Rng) -> Vec<Self> {
+        let pending = Self {
+            id: Ulid::from_datetime_with_source(now.into(), rng),
+            user_id: Ulid::from_datetime_with_source(now.into(), rng),
+            user_email_authentication_id: Ulid::from_datetime_with_source(now.into(), rng),
+            email: "alice@example.org".to_owned(),
+            previous_email: "alice@example.com".to_owned(),
+            ticket: "ticket".to_owned(),
+            created_at: now,
+            expires_at: now + chrono::Duration::hours(24),
+            confirmed_at: None,
+            cancelled_at: None,
+        };
+
+        let confirmed = Self {
+            confirmed_at: Some(now),
+            ..pending.clone()
+        };
+
+        let cancelled = Self {
+            cancelled_at: Some(now),
+            ..pending.clone()
+        };
+
+        vec![pending, confirmed, cancelled]
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct BrowserSession {
     pub id: Ulid,
@@ -183,6 +284,137 @@ impl BrowserSession {
     }
 }
 
+/// Where a failed login attempt came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginFailureOrigin {
+    /// A login through the Matrix compatibility layer
+    Compat,
+
+    /// A login through the interactive login form
+    Interactive,
+}
+
+impl LoginFailureOrigin {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Compat => "compat",
+            Self::Interactive => "interactive",
+        }
+    }
+}
+
+impl std::fmt::Display for LoginFailureOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for LoginFailureOrigin {
+    type Err = InvalidLoginFailureOriginError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compat" => Ok(Self::Compat),
+            "interactive" => Ok(Self::Interactive),
+            s => Err(InvalidLoginFailureOriginError(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid login failure origin: {0}")]
+pub struct InvalidLoginFailureOriginError(String);
+
+/// The category of reason why a login attempt failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginFailureReason {
+    /// The password did not match
+    PasswordMismatch,
+
+    /// The user has no password set
+    NoPassword,
+
+    /// The attempt was rejected by the rate limiter
+    RateLimited,
+
+    /// The user is locked
+    UserLocked,
+
+    /// The user is deactivated
+    UserDeactivated,
+}
+
+impl LoginFailureReason {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::PasswordMismatch => "password_mismatch",
+            Self::NoPassword => "no_password",
+            Self::RateLimited => "rate_limited",
+            Self::UserLocked => "user_locked",
+            Self::UserDeactivated => "user_deactivated",
+        }
+    }
+}
+
+impl std::fmt::Display for LoginFailureReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for LoginFailureReason {
+    type Err = InvalidLoginFailureReasonError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "password_mismatch" => Ok(Self::PasswordMismatch),
+            "no_password" => Ok(Self::NoPassword),
+            "rate_limited" => Ok(Self::RateLimited),
+            "user_locked" => Ok(Self::UserLocked),
+            "user_deactivated" => Ok(Self::UserDeactivated),
+            s => Err(InvalidLoginFailureReasonError(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Invalid login failure reason: {0}")]
+pub struct InvalidLoginFailureReasonError(String);
+
+/// A failed login attempt for a known user
+///
+/// Those are only recorded when the user could be resolved, so that unknown
+/// usernames don't end up being stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserLoginFailure {
+    pub id: Ulid,
+    pub user_id: Ulid,
+    pub origin: LoginFailureOrigin,
+    pub reason: LoginFailureReason,
+    pub ip_address: Option<IpAddr>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A free-text note left on a user account by an administrator
+///
+/// Notes are only visible to administrators, never to the user themselves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserNote {
+    pub id: Ulid,
+    pub user_id: Ulid,
+
+    /// The administrator who wrote the note, if the note was added by a user
+    /// and not by a client acting on its own behalf
+    pub author_id: Option<Ulid>,
+
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct UserEmail {
     pub id: Ulid,
@@ -3,6 +3,21 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use chrono::{DateTime, Utc};
+
 /// A structure which holds information about the running version of the app
 #[derive(Debug, Clone, Copy)]
 pub struct AppVersion(pub &'static str);
+
+/// Information about how the running app was built, and when it started
+#[derive(Debug, Clone, Copy)]
+pub struct AppBuildInfo {
+    /// The git commit the app was built from, if known
+    pub git_sha: Option<&'static str>,
+
+    /// The version of the Rust compiler the app was built with, if known
+    pub rustc_version: Option<&'static str>,
+
+    /// When the app process started
+    pub started_at: DateTime<Utc>,
+}
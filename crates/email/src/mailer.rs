@@ -8,9 +8,13 @@
 
 use lettre::{
     AsyncTransport, Message,
-    message::{Mailbox, MessageBuilder, MultiPart},
+    message::{Mailbox, MessageBuilder, MultiPart, header::ContentType},
+};
+use mas_templates::{
+    AccountRecoveredContext, DeactivationScheduledContext, EmailAddedContext,
+    EmailChangeConfirmationContext, EmailRecoveryContext, EmailVerificationContext,
+    NewLoginContext, Templates, WithLanguage,
 };
-use mas_templates::{EmailRecoveryContext, EmailVerificationContext, Templates, WithLanguage};
 use thiserror::Error;
 
 use crate::MailTransport;
@@ -99,6 +103,28 @@ impl Mailer {
         Ok(message)
     }
 
+    fn prepare_email_change_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailChangeConfirmationContext>,
+    ) -> Result<Message, Error> {
+        let plain = self.templates.render_email_change_txt(context)?;
+
+        let html = self.templates.render_email_change_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self.templates.render_email_change_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
     /// Send the verification email to a user
     ///
     /// # Errors
@@ -147,6 +173,262 @@ impl Mailer {
         Ok(())
     }
 
+    /// Send the email asking a user to confirm a change of their email
+    /// address, to their previous address
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering or failed sending
+    #[tracing::instrument(
+        name = "email.email_change.send",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+            user_email_change.id = %context.change().id,
+        ),
+    )]
+    pub async fn send_email_change_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailChangeConfirmationContext>,
+    ) -> Result<(), Error> {
+        let message = self.prepare_email_change_email(to, context)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    fn prepare_account_recovered_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<AccountRecoveredContext>,
+    ) -> Result<Message, Error> {
+        let plain = self.templates.render_email_account_recovered_txt(context)?;
+
+        let html = self
+            .templates
+            .render_email_account_recovered_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self
+            .templates
+            .render_email_account_recovered_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Send the notification that an account was recovered to a user
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering or failed sending
+    #[tracing::instrument(
+        name = "email.account_recovered.send",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+        ),
+    )]
+    pub async fn send_account_recovered_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<AccountRecoveredContext>,
+    ) -> Result<(), Error> {
+        let message = self.prepare_account_recovered_email(to, context)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    fn prepare_email_added_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailAddedContext>,
+    ) -> Result<Message, Error> {
+        let plain = self.templates.render_email_added_txt(context)?;
+
+        let html = self.templates.render_email_added_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self.templates.render_email_added_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Send the notification that an email address was added to their account
+    /// to a user
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering or failed sending
+    #[tracing::instrument(
+        name = "email.email_added.send",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+        ),
+    )]
+    pub async fn send_email_added_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<EmailAddedContext>,
+    ) -> Result<(), Error> {
+        let message = self.prepare_email_added_email(to, context)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    fn prepare_new_login_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<NewLoginContext>,
+    ) -> Result<Message, Error> {
+        let plain = self.templates.render_email_new_login_txt(context)?;
+
+        let html = self.templates.render_email_new_login_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self.templates.render_email_new_login_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Send the notification that a new session was started on their account
+    /// to a user
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering or failed sending
+    #[tracing::instrument(
+        name = "email.new_login.send",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+        ),
+    )]
+    pub async fn send_new_login_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<NewLoginContext>,
+    ) -> Result<(), Error> {
+        let message = self.prepare_new_login_email(to, context)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    fn prepare_deactivation_scheduled_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<DeactivationScheduledContext>,
+    ) -> Result<Message, Error> {
+        let plain = self
+            .templates
+            .render_email_deactivation_scheduled_txt(context)?;
+
+        let html = self
+            .templates
+            .render_email_deactivation_scheduled_html(context)?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let subject = self
+            .templates
+            .render_email_deactivation_scheduled_subject(context)?;
+
+        let message = self
+            .base_message()
+            .subject(subject.trim())
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Send the confirmation that the deactivation of their account was
+    /// scheduled to a user
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering or failed sending
+    #[tracing::instrument(
+        name = "email.deactivation_scheduled.send",
+        skip_all,
+        fields(
+            email.to = %to,
+            email.language = %context.language(),
+            user.id = %context.user().id,
+        ),
+    )]
+    pub async fn send_deactivation_scheduled_email(
+        &self,
+        to: Mailbox,
+        context: &WithLanguage<DeactivationScheduledContext>,
+    ) -> Result<(), Error> {
+        let message = self.prepare_deactivation_scheduled_email(to, context)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    fn prepare_probe_email(&self, to: Mailbox) -> Result<Message, Error> {
+        let message = self
+            .base_message()
+            .subject("Email delivery check")
+            .to(to)
+            .header(ContentType::TEXT_PLAIN)
+            .body(
+                "This message was sent by the authentication service to check that it can \
+                 send emails. It can be safely ignored."
+                    .to_owned(),
+            )?;
+
+        Ok(message)
+    }
+
+    /// Send a probe email, to check that emails can be delivered
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed sending
+    #[tracing::instrument(
+        name = "email.probe.send",
+        skip_all,
+        fields(
+            email.to = %to,
+        ),
+    )]
+    pub async fn send_probe_email(&self, to: Mailbox) -> Result<(), Error> {
+        let message = self.prepare_probe_email(to)?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
     /// Test the connetion to the mail server
     ///
     /// # Errors
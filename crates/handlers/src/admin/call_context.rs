@@ -9,12 +9,12 @@ use std::convert::Infallible;
 use aide::OperationIo;
 use axum::{
     Json,
-    extract::FromRequestParts,
+    extract::{FromRef, FromRequestParts},
     response::{IntoResponse, Response},
 };
 use axum_extra::TypedHeader;
 use headers::{Authorization, authorization::Bearer};
-use hyper::StatusCode;
+use hyper::{StatusCode, header::RETRY_AFTER};
 use mas_axum_utils::record_error;
 use mas_data_model::{
     BoxClock, Session, TokenFormatError, TokenType, User,
@@ -25,7 +25,10 @@ use oauth2_types::scope::Scope;
 use ulid::Ulid;
 
 use super::response::ErrorResponse;
-use crate::BoundActivityTracker;
+use crate::{
+    BoundActivityTracker, Limiter,
+    rate_limit::{AdminApiCaller, AdminApiLimitedError, AdminApiOperation},
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Rejection {
@@ -76,6 +79,10 @@ pub enum Rejection {
     /// The session does not have the `urn:mas:admin` scope
     #[error("Missing urn:mas:admin scope")]
     MissingScope,
+
+    /// The client made too many requests
+    #[error(transparent)]
+    RateLimited(#[from] AdminApiLimitedError),
 }
 
 impl IntoResponse for Rejection {
@@ -105,6 +112,20 @@ impl IntoResponse for Rejection {
             | Rejection::Repository(_)
             | Rejection::LoadSession(_)
             | Rejection::LoadUser(_) => StatusCode::INTERNAL_SERVER_ERROR,
+
+            Rejection::RateLimited(e) => {
+                // Retry-After is in whole seconds, round up so that clients
+                // don't retry too early
+                let retry_after = e.retry_after();
+                let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(RETRY_AFTER, seconds.max(1).to_string())],
+                    sentry_event_id,
+                    Json(response),
+                )
+                    .into_response();
+            }
         };
 
         (status, sentry_event_id, Json(response)).into_response()
@@ -131,6 +152,7 @@ where
     BoundActivityTracker: FromRequestParts<S, Rejection = Infallible>,
     BoxRepository: FromRequestParts<S>,
     BoxClock: FromRequestParts<S, Rejection = Infallible>,
+    Limiter: FromRef<S>,
     <BoxRepository as FromRequestParts<S>>::Rejection:
         Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
 {
@@ -165,6 +187,9 @@ where
         let token = token.token();
         let token_type = TokenType::check(token)?;
 
+        let limiter = Limiter::from_ref(state);
+        let operation = AdminApiOperation::from_method(&parts.method);
+
         let session = match token_type {
             TokenType::AccessToken => {
                 // Look for the access token in the database
@@ -181,6 +206,9 @@ where
                     .await?
                     .ok_or_else(|| Rejection::LoadSession(token.session_id))?;
 
+                // Check the client didn't exceed its budget before doing any more work
+                limiter.check_admin_api(AdminApiCaller::Client(session.client_id), operation)?;
+
                 if !session.is_valid() {
                     return Err(Rejection::SessionRevoked);
                 }
@@ -211,6 +239,16 @@ where
                     .await?
                     .ok_or_else(|| Rejection::LoadSession(token.session_id))?;
 
+                // Personal sessions owned by a client share its budget, the
+                // ones owned by users have their own
+                let caller = match session.owner {
+                    PersonalSessionOwner::OAuth2Client(client_id) => {
+                        AdminApiCaller::Client(client_id)
+                    }
+                    PersonalSessionOwner::User(_) => AdminApiCaller::PersonalSession(session.id),
+                };
+                limiter.check_admin_api(caller, operation)?;
+
                 if !session.is_valid() {
                     return Err(Rejection::SessionRevoked);
                 }
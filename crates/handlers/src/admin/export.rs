@@ -0,0 +1,300 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! CSV exports of the list endpoints
+//!
+//! The rows are streamed directly out of the repository, page by page, so that
+//! large exports don't have to be buffered in memory. As the headers are sent
+//! before the rows, whether the export is truncated is known upfront from the
+//! number of matching items.
+
+use aide::{
+    OperationOutput,
+    openapi::{MediaType, ReferenceOr, SchemaObject, StatusCode},
+    transform::TransformOperation,
+};
+use axum::{
+    body::{Body, Bytes},
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, TryStreamExt, future::BoxFuture, stream};
+use hyper::header::{CONTENT_DISPOSITION, CONTENT_TYPE, HeaderName, HeaderValue};
+use mas_storage::{BoxRepository, Page, Pagination, RepositoryError};
+use ulid::Ulid;
+
+use super::{model::Resource, response::PaginatedResponse};
+
+/// How many items are fetched from the database at once during an export
+const EXPORT_PAGE_SIZE: usize = 100;
+
+/// Header giving the number of items matching the filters, which can be more
+/// than the number of exported rows
+static TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+/// Header telling whether the export was truncated to the export limit
+static EXPORT_TRUNCATED: HeaderName = HeaderName::from_static("x-export-truncated");
+
+/// A resource which can be exported as CSV
+pub trait CsvResource: Resource {
+    /// The name of the columns, in the order in which they appear in the
+    /// export
+    const CSV_COLUMNS: &'static [&'static str];
+
+    /// The value of each column for this resource, in the same order as
+    /// [`Self::CSV_COLUMNS`]
+    fn csv_record(&self) -> Vec<String>;
+}
+
+/// Function used to fetch one page of resources out of the repository
+type FetchPage<T> = Box<
+    dyn for<'a> FnMut(
+            &'a mut BoxRepository,
+            Pagination,
+        ) -> BoxFuture<'a, Result<Page<T>, RepositoryError>>
+        + Send,
+>;
+
+/// State of a CSV export while it is being streamed
+struct ExportState<T> {
+    repo: BoxRepository,
+    fetch: FetchPage<T>,
+    after: Option<Ulid>,
+    remaining: usize,
+    done: bool,
+}
+
+/// A streamed CSV export
+pub struct CsvExport {
+    filename: &'static str,
+    total: usize,
+    truncated: bool,
+    body: Body,
+}
+
+impl CsvExport {
+    /// Start a CSV export, fetching the pages with the given function until
+    /// either all items were exported or the `limit` was reached
+    ///
+    /// `total` is the number of items matching the filters, used to tell
+    /// whether the export is truncated.
+    pub fn new<T, F>(
+        repo: BoxRepository,
+        filename: &'static str,
+        limit: usize,
+        total: usize,
+        fetch: F,
+    ) -> Self
+    where
+        T: CsvResource + Send + 'static,
+        F: for<'a> FnMut(
+                &'a mut BoxRepository,
+                Pagination,
+            ) -> BoxFuture<'a, Result<Page<T>, RepositoryError>>
+            + Send
+            + 'static,
+    {
+        let header = csv_line(T::CSV_COLUMNS);
+
+        let state = ExportState {
+            repo,
+            fetch: Box::new(fetch),
+            after: None,
+            remaining: limit,
+            done: false,
+        };
+
+        let rows = stream::try_unfold(state, |mut state| async move {
+            if state.done || state.remaining == 0 {
+                state.repo.cancel().await?;
+                return Ok(None);
+            }
+
+            let pagination = Pagination::first(state.remaining.min(EXPORT_PAGE_SIZE));
+            let pagination = match state.after {
+                Some(after) => pagination.after(after),
+                None => pagination,
+            };
+
+            let page = (state.fetch)(&mut state.repo, pagination).await?;
+
+            let mut chunk = Vec::new();
+            for edge in &page.edges {
+                let record: Vec<String> = edge
+                    .node
+                    .csv_record()
+                    .into_iter()
+                    .map(escape_formula)
+                    .collect();
+                chunk.extend(csv_line(&record));
+            }
+
+            state.remaining = state.remaining.saturating_sub(page.edges.len());
+            state.after = page.edges.last().map(|edge| edge.cursor);
+            state.done = !page.has_next_page || page.edges.is_empty();
+
+            Ok::<_, RepositoryError>(Some((Bytes::from(chunk), state)))
+        })
+        .inspect_err(|e| tracing::error!(error = e as &dyn std::error::Error, "CSV export failed"));
+
+        let body =
+            stream::once(async move { Ok::<_, RepositoryError>(Bytes::from(header)) }).chain(rows);
+
+        Self {
+            filename,
+            total,
+            truncated: total > limit,
+            body: Body::from_stream(body),
+        }
+    }
+}
+
+impl IntoResponse for CsvExport {
+    fn into_response(self) -> Response {
+        let disposition = format!("attachment; filename=\"{}\"", self.filename);
+        let disposition = HeaderValue::from_str(&disposition)
+            .unwrap_or_else(|_| HeaderValue::from_static("attachment"));
+
+        (
+            [
+                (
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("text/csv; charset=utf-8"),
+                ),
+                (CONTENT_DISPOSITION, disposition),
+                (TOTAL_COUNT.clone(), HeaderValue::from(self.total)),
+                (
+                    EXPORT_TRUNCATED.clone(),
+                    HeaderValue::from_static(if self.truncated { "true" } else { "false" }),
+                ),
+            ],
+            self.body,
+        )
+            .into_response()
+    }
+}
+
+/// Neutralize the values which spreadsheets would evaluate as formulas, by
+/// prefixing them with a single quote
+///
+/// Exported values like usernames and email addresses are chosen by users, and
+/// the exports are meant to be opened in spreadsheets.
+fn escape_formula(value: String) -> String {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{value}")
+    } else {
+        value
+    }
+}
+
+/// Encode a single CSV line, quoting and escaping the values as needed
+fn csv_line<S: AsRef<[u8]>>(values: &[S]) -> Vec<u8> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    // Writing to a Vec can't fail, and we always write the same number of
+    // fields
+    writer
+        .write_record(values)
+        .expect("failed to write CSV record");
+    writer.into_inner().expect("failed to flush CSV writer")
+}
+
+/// The response of a list endpoint, either as paginated JSON or as a CSV export
+pub enum ListResponse<T> {
+    Json(PaginatedResponse<T>),
+    Csv(CsvExport),
+}
+
+impl<T: serde::Serialize> IntoResponse for ListResponse<T> {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Json(response) => response.into_response(),
+            Self::Csv(export) => export.into_response(),
+        }
+    }
+}
+
+impl<T> OperationOutput for ListResponse<T> {
+    type Inner = PaginatedResponse<T>;
+}
+
+/// Document the CSV export of a list endpoint
+///
+/// This must be called after the JSON response has been documented, as it adds
+/// the `text/csv` content type to the existing `200` response.
+pub fn document_csv_export<T: CsvResource>(
+    mut operation: TransformOperation,
+) -> TransformOperation {
+    let columns = T::CSV_COLUMNS
+        .iter()
+        .map(|column| format!("`{column}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let example = String::from_utf8_lossy(&csv_line(T::CSV_COLUMNS)).into_owned();
+
+    if let Some(ReferenceOr::Item(response)) = operation
+        .inner_mut()
+        .responses
+        .as_mut()
+        .and_then(|responses| responses.responses.get_mut(&StatusCode::Code(200)))
+    {
+        response.content.insert(
+            "text/csv".to_owned(),
+            MediaType {
+                schema: Some(SchemaObject {
+                    json_schema: schemars::schema::Schema::Object(schemars::schema::SchemaObject {
+                        instance_type: Some(schemars::schema::InstanceType::String.into()),
+                        ..Default::default()
+                    }),
+                    external_docs: None,
+                    example: None,
+                }),
+                example: Some(serde_json::Value::String(example)),
+                ..Default::default()
+            },
+        );
+    }
+
+    let description = format!(
+        "The items can also be exported as CSV, either by setting the `format=csv` query \
+         parameter or by sending an `Accept: text/csv` header. The export includes all the \
+         items matching the filters, up to the configured export limit, ignoring the \
+         pagination parameters. The `X-Total-Count` response header gives the number of \
+         matching items, and the `X-Export-Truncated` header is `true` if the export stopped \
+         at the limit. Values starting with `=`, `+`, `-`, `@`, a tab or a carriage return \
+         are prefixed with a single quote, so that spreadsheets don't evaluate them as \
+         formulas.\n\nThe CSV export has the following columns, in this order: {columns}."
+    );
+
+    // Append to the existing description, if any
+    let inner = operation.inner_mut();
+    inner.description = Some(match inner.description.take() {
+        Some(existing) => format!("{existing}\n\n{description}"),
+        None => description,
+    });
+
+    operation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{csv_line, escape_formula};
+
+    #[test]
+    fn test_escape_formula() {
+        assert_eq!(escape_formula("alice".to_owned()), "alice");
+        assert_eq!(escape_formula("a=b".to_owned()), "a=b");
+        assert_eq!(escape_formula(String::new()), "");
+
+        for value in ["=1+1", "+1", "-1", "@SUM(A1)", "\tx", "\rx"] {
+            assert_eq!(escape_formula(value.to_owned()), format!("'{value}"));
+        }
+
+        // The escaped value is still quoted as needed by the CSV encoding
+        assert_eq!(
+            csv_line(&[escape_formula("=HYPERLINK(\"x\")".to_owned())]),
+            b"\"'=HYPERLINK(\"\"x\"\")\"\n"
+        );
+    }
+}
@@ -8,19 +8,21 @@ use std::sync::Arc;
 
 use aide::{
     axum::ApiRouter,
-    openapi::{OAuth2Flow, OAuth2Flows, OpenApi, SecurityScheme, Server, Tag},
+    openapi::{
+        OAuth2Flow, OAuth2Flows, OpenApi, ReferenceOr, SecurityScheme, Server, StatusCode, Tag,
+    },
     transform::TransformOpenApi,
 };
 use axum::{
-    Json, Router,
+    Extension, Json, Router,
     extract::{FromRef, FromRequestParts, State},
     http::HeaderName,
-    response::Html,
+    response::{Html, IntoResponse},
 };
 use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
 use indexmap::IndexMap;
-use mas_axum_utils::InternalError;
-use mas_data_model::{AppVersion, BoxRng, SiteConfig};
+use mas_axum_utils::{InternalError, error_format::RouteClass};
+use mas_data_model::{AppBuildInfo, AppVersion, BoxRng, SiteConfig, TchapFeatures};
 use mas_http::CorsLayerExt;
 use mas_matrix::HomeserverConnection;
 use mas_policy::PolicyFactory;
@@ -32,6 +34,7 @@ use mas_templates::{ApiDocContext, Templates};
 use tower_http::cors::{Any, CorsLayer};
 
 mod call_context;
+mod export;
 mod model;
 mod params;
 mod response;
@@ -39,7 +42,11 @@ mod schema;
 mod v1;
 
 use self::call_context::CallContext;
-use crate::passwords::PasswordManager;
+pub use self::v1::site_config::{
+    SiteConfig as EffectiveSiteConfig, TemplatesPaths as EffectiveTemplatesPaths,
+    UpstreamProvider as EffectiveUpstreamProvider,
+};
+use crate::{Limiter, passwords::PasswordManager, upstream_oauth2::cache::MetadataCache};
 
 fn finish(t: TransformOpenApi) -> TransformOpenApi {
     t.title("Matrix Authentication Service admin API")
@@ -53,16 +60,49 @@ fn finish(t: TransformOpenApi) -> TransformOpenApi {
             description: Some("Manage compatibility sessions from legacy clients".to_owned()),
             ..Tag::default()
         })
+        .tag(Tag {
+            name: "compat-sso-login".to_owned(),
+            description: Some(
+                "Inspect SSO logins of legacy clients, used to hand off to them a login token"
+                    .to_owned(),
+            ),
+            ..Tag::default()
+        })
         .tag(Tag {
             name: "policy-data".to_owned(),
             description: Some("Manage the dynamic policy data".to_owned()),
             ..Tag::default()
         })
+        .tag(Tag {
+            name: "provisioning-report".to_owned(),
+            description: Some("Compare the users with their state on the homeserver".to_owned()),
+            ..Tag::default()
+        })
+        .tag(Tag {
+            name: "report".to_owned(),
+            description: Some("Reports to help detect abusive registrations".to_owned()),
+            ..Tag::default()
+        })
+        .tag(Tag {
+            name: "syn2mas-run".to_owned(),
+            description: Some("Inspect the migrations of users from Synapse".to_owned()),
+            ..Tag::default()
+        })
+        .tag(Tag {
+            name: "queue-job".to_owned(),
+            description: Some("Inspect and retry jobs in the job queue".to_owned()),
+            ..Tag::default()
+        })
         .tag(Tag {
             name: "oauth2-session".to_owned(),
             description: Some("Manage OAuth2 sessions".to_owned()),
             ..Tag::default()
         })
+        .tag(Tag {
+            name: "device-code-grant".to_owned(),
+            description: Some("Inspect OAuth 2.0 device code grants".to_owned()),
+            ..Tag::default()
+        })
         .tag(Tag {
             name: "user".to_owned(),
             description: Some("Manage users".to_owned()),
@@ -73,6 +113,11 @@ fn finish(t: TransformOpenApi) -> TransformOpenApi {
             description: Some("Manage emails associated with users".to_owned()),
             ..Tag::default()
         })
+        .tag(Tag {
+            name: "user-note".to_owned(),
+            description: Some("Manage notes left on users by administrators".to_owned()),
+            ..Tag::default()
+        })
         .tag(Tag {
             name: "user-session".to_owned(),
             description: Some("Manage browser sessions of users".to_owned()),
@@ -165,6 +210,11 @@ where
     Arc<PolicyFactory>: FromRef<S>,
     SiteConfig: FromRef<S>,
     AppVersion: FromRef<S>,
+    AppBuildInfo: FromRef<S>,
+    TchapFeatures: FromRef<S>,
+    MetadataCache: FromRef<S>,
+    reqwest::Client: FromRef<S>,
+    Limiter: FromRef<S>,
 {
     // We *always* want to explicitly set the possible responses, beacuse the
     // infered ones are not necessarily correct
@@ -201,12 +251,32 @@ where
                 }
             }),
         )
+        // Serve the examples of all the responses, so that client SDKs can check
+        // that they deserialize them. This isn't part of the spec on purpose
+        .route(
+            "/api/admin/v1/__examples",
+            axum::routing::get({
+                let examples = response_examples(&api);
+                move |State(site_config): State<SiteConfig>| {
+                    let response = if cfg!(debug_assertions) || site_config.admin_api_examples {
+                        Json(examples.clone()).into_response()
+                    } else {
+                        hyper::StatusCode::NOT_FOUND.into_response()
+                    };
+
+                    std::future::ready(response)
+                }
+            }),
+        )
         // Serve the Swagger API reference
         .route(ApiDoc::route(), axum::routing::get(swagger))
         .route(
             ApiDocCallback::route(),
             axum::routing::get(swagger_callback),
         )
+        .layer(axum::middleware::map_response(
+            async |response: axum::response::Response| (Extension(RouteClass::Api), response),
+        ))
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
@@ -223,6 +293,55 @@ where
     (api, router)
 }
 
+/// Collect the examples of the successful JSON responses of every operation,
+/// keyed by operation ID and status code
+fn response_examples(api: &OpenApi) -> IndexMap<String, IndexMap<String, serde_json::Value>> {
+    let mut examples = IndexMap::new();
+
+    let Some(paths) = &api.paths else {
+        return examples;
+    };
+
+    for (path, item) in &paths.paths {
+        let ReferenceOr::Item(item) = item else {
+            continue;
+        };
+
+        for (method, operation) in item.iter() {
+            let Some(responses) = &operation.responses else {
+                continue;
+            };
+
+            let operation_examples: IndexMap<String, serde_json::Value> = responses
+                .responses
+                .iter()
+                .filter_map(|(status, response)| {
+                    let StatusCode::Code(code @ 200..300) = status else {
+                        return None;
+                    };
+                    let ReferenceOr::Item(response) = response else {
+                        return None;
+                    };
+                    let example = response.content.get("application/json")?.example.clone()?;
+                    Some((code.to_string(), example))
+                })
+                .collect();
+
+            if operation_examples.is_empty() {
+                continue;
+            }
+
+            let id = operation
+                .operation_id
+                .clone()
+                .unwrap_or_else(|| format!("{method} {path}"));
+            examples.insert(id, operation_examples);
+        }
+    }
+
+    examples
+}
+
 async fn swagger(
     State(url_builder): State<UrlBuilder>,
     State(templates): State<Templates>,
@@ -240,3 +359,73 @@ async fn swagger_callback(
     let res = templates.render_swagger_callback(&ctx)?;
     Ok(Html(res))
 }
+
+#[cfg(test)]
+mod tests {
+    use aide::openapi::{ReferenceOr, StatusCode};
+    use hyper::Request;
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    /// Every successful response with a body must have an example, as client
+    /// generators rely on them
+    #[test]
+    fn every_success_response_has_an_example() {
+        let (api, _) = super::router::<TestState>();
+
+        let mut missing = Vec::new();
+        for (path, item) in &api.paths.unwrap().paths {
+            let ReferenceOr::Item(item) = item else {
+                continue;
+            };
+
+            for (method, operation) in item.iter() {
+                for (status, response) in &operation.responses.as_ref().unwrap().responses {
+                    let StatusCode::Code(code @ 200..300) = status else {
+                        continue;
+                    };
+                    if *code == 204 {
+                        continue;
+                    }
+
+                    let ReferenceOr::Item(response) = response else {
+                        continue;
+                    };
+                    let has_example = response
+                        .content
+                        .get("application/json")
+                        .is_some_and(|media| media.example.is_some());
+                    if !has_example {
+                        missing.push(format!("{method} {path} {code}"));
+                    }
+                }
+            }
+        }
+
+        assert!(
+            missing.is_empty(),
+            "Responses without an example: {missing:?}"
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_response_examples(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Tests are built with debug assertions, so the examples are served
+        let request = Request::get("/api/admin/v1/__examples").empty();
+        let response = state.request(request).await;
+        response.assert_status(hyper::StatusCode::OK);
+        let body: serde_json::Value = response.json();
+
+        assert_eq!(body["getUser"]["200"]["data"]["type"], "user");
+        assert_eq!(
+            body["createPersonalSession"]["201"]["data"]["attributes"]["access_token"],
+            "mpt_FM44zJN5qePGMLvvMXC4Ds1A3lCWc6_bJ9Wj1"
+        );
+        // Responses without a body are left out
+        assert!(body["deleteUserEmail"].is_null());
+    }
+}
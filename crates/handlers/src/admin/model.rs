@@ -6,7 +6,7 @@
 
 use std::net::IpAddr;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SecondsFormat, Utc};
 use mas_data_model::{
     Device,
     personal::{
@@ -20,6 +20,9 @@ use thiserror::Error;
 use ulid::Ulid;
 use url::Url;
 
+use super::export::CsvResource;
+use crate::upstream_oauth2::cache::DiscoveryStatus;
+
 /// A resource, with a type and an ID
 pub trait Resource {
     /// The type of the resource
@@ -39,6 +42,16 @@ pub trait Resource {
     }
 }
 
+/// Format a timestamp for a CSV export, the same way it is serialized in JSON
+fn csv_datetime(value: DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// Format an optional value for a CSV export, using an empty string for `None`
+fn csv_optional<T: std::fmt::Display>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
 /// A user
 #[derive(Serialize, JsonSchema)]
 pub struct User {
@@ -57,11 +70,39 @@ pub struct User {
     /// When the user was deactivated. If null, the user is not deactivated.
     deactivated_at: Option<DateTime<Utc>>,
 
+    /// When the user was deleted. If null, the user is not deleted. Deleted
+    /// users are permanently removed after a retention period.
+    deleted_at: Option<DateTime<Utc>>,
+
     /// Whether the user can request admin privileges.
     admin: bool,
 
     /// Whether the user was a guest before migrating to MAS,
     legacy_guest: bool,
+
+    /// The organization the user belongs to, usually imported from an
+    /// upstream provider. If null, the user doesn't belong to any
+    /// organization.
+    organization: Option<String>,
+
+    /// The maximum number of active sessions with a device the user can have,
+    /// overriding the limit from the configuration. If null, the limit from
+    /// the configuration applies.
+    max_sessions: Option<u32>,
+
+    /// The ID used by an external provisioning system to identify the user.
+    /// If null, no external ID was set.
+    external_id: Option<String>,
+
+    /// The most recent failed login attempts of the user, most recent first.
+    /// Only present when fetching a single user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recent_login_failures: Option<Vec<UserLoginFailure>>,
+
+    /// The most recent note left on the user by an administrator. Only
+    /// present when fetching a single user, and if the user has any note.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latest_note: Option<LatestUserNote>,
 }
 
 impl User {
@@ -74,8 +115,14 @@ impl User {
                 created_at: DateTime::default(),
                 locked_at: None,
                 deactivated_at: None,
+                deleted_at: None,
                 admin: false,
                 legacy_guest: false,
+                organization: Some("Ministère de l'Intérieur".to_owned()),
+                max_sessions: Some(2),
+                external_id: Some("hr-1234".to_owned()),
+                recent_login_failures: None,
+                latest_note: None,
             },
             Self {
                 id: Ulid::from_bytes([0x02; 16]),
@@ -83,8 +130,14 @@ impl User {
                 created_at: DateTime::default(),
                 locked_at: None,
                 deactivated_at: None,
+                deleted_at: None,
                 admin: true,
                 legacy_guest: false,
+                organization: None,
+                max_sessions: None,
+                external_id: None,
+                recent_login_failures: None,
+                latest_note: None,
             },
             Self {
                 id: Ulid::from_bytes([0x03; 16]),
@@ -92,8 +145,14 @@ impl User {
                 created_at: DateTime::default(),
                 locked_at: Some(DateTime::default()),
                 deactivated_at: None,
+                deleted_at: None,
                 admin: false,
                 legacy_guest: true,
+                organization: None,
+                max_sessions: None,
+                external_id: None,
+                recent_login_failures: None,
+                latest_note: None,
             },
         ]
     }
@@ -107,8 +166,141 @@ impl From<mas_data_model::User> for User {
             created_at: user.created_at,
             locked_at: user.locked_at,
             deactivated_at: user.deactivated_at,
+            deleted_at: user.deleted_at,
             admin: user.can_request_admin,
             legacy_guest: user.is_guest,
+            organization: user.organization,
+            max_sessions: user.max_sessions,
+            external_id: user.external_id,
+            recent_login_failures: None,
+            latest_note: None,
+        }
+    }
+}
+
+impl User {
+    /// Attach the most recent failed login attempts to the user
+    #[must_use]
+    pub fn with_recent_login_failures(
+        mut self,
+        failures: impl IntoIterator<Item = mas_data_model::UserLoginFailure>,
+    ) -> Self {
+        self.recent_login_failures = Some(failures.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Attach the most recent note left on the user
+    #[must_use]
+    pub fn with_latest_note(mut self, note: Option<mas_data_model::UserNote>) -> Self {
+        self.latest_note = note.map(Into::into);
+        self
+    }
+}
+
+/// The most recent note left on a user, as included in the user resource
+#[derive(Serialize, JsonSchema)]
+pub struct LatestUserNote {
+    /// The ID of the note
+    #[schemars(with = "super::schema::Ulid")]
+    id: Ulid,
+
+    /// When the note was written
+    created_at: DateTime<Utc>,
+
+    /// The ID of the user who wrote the note, if any
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    author_id: Option<Ulid>,
+
+    /// The content of the note
+    content: String,
+}
+
+impl From<mas_data_model::UserNote> for LatestUserNote {
+    fn from(value: mas_data_model::UserNote) -> Self {
+        Self {
+            id: value.id,
+            created_at: value.created_at,
+            author_id: value.author_id,
+            content: value.content,
+        }
+    }
+}
+
+/// Where a failed login attempt came from
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginFailureOrigin {
+    /// A login through the Matrix compatibility layer
+    Compat,
+
+    /// A login through the interactive login form
+    Interactive,
+}
+
+impl From<mas_data_model::LoginFailureOrigin> for LoginFailureOrigin {
+    fn from(value: mas_data_model::LoginFailureOrigin) -> Self {
+        match value {
+            mas_data_model::LoginFailureOrigin::Compat => Self::Compat,
+            mas_data_model::LoginFailureOrigin::Interactive => Self::Interactive,
+        }
+    }
+}
+
+/// The category of reason why a login attempt failed
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginFailureReason {
+    /// The password did not match
+    PasswordMismatch,
+
+    /// The user has no password set
+    NoPassword,
+
+    /// The attempt was rejected by the rate limiter
+    RateLimited,
+
+    /// The user is locked
+    UserLocked,
+
+    /// The user is deactivated
+    UserDeactivated,
+}
+
+impl From<mas_data_model::LoginFailureReason> for LoginFailureReason {
+    fn from(value: mas_data_model::LoginFailureReason) -> Self {
+        match value {
+            mas_data_model::LoginFailureReason::PasswordMismatch => Self::PasswordMismatch,
+            mas_data_model::LoginFailureReason::NoPassword => Self::NoPassword,
+            mas_data_model::LoginFailureReason::RateLimited => Self::RateLimited,
+            mas_data_model::LoginFailureReason::UserLocked => Self::UserLocked,
+            mas_data_model::LoginFailureReason::UserDeactivated => Self::UserDeactivated,
+        }
+    }
+}
+
+/// A failed login attempt
+#[derive(Serialize, JsonSchema)]
+pub struct UserLoginFailure {
+    /// When the attempt happened
+    created_at: DateTime<Utc>,
+
+    /// Where the attempt came from
+    origin: LoginFailureOrigin,
+
+    /// Why the attempt failed
+    reason: LoginFailureReason,
+
+    /// The IP address of the requester, if known
+    ip_address: Option<IpAddr>,
+}
+
+impl From<mas_data_model::UserLoginFailure> for UserLoginFailure {
+    fn from(value: mas_data_model::UserLoginFailure) -> Self {
+        Self {
+            created_at: value.created_at,
+            origin: value.origin.into(),
+            reason: value.reason.into(),
+            ip_address: value.ip_address,
         }
     }
 }
@@ -122,6 +314,36 @@ impl Resource for User {
     }
 }
 
+impl CsvResource for User {
+    const CSV_COLUMNS: &'static [&'static str] = &[
+        "id",
+        "username",
+        "created_at",
+        "locked_at",
+        "deactivated_at",
+        "admin",
+        "legacy_guest",
+        "organization",
+        "max_sessions",
+        "external_id",
+    ];
+
+    fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.username.clone(),
+            csv_datetime(self.created_at),
+            csv_optional(self.locked_at.map(csv_datetime)),
+            csv_optional(self.deactivated_at.map(csv_datetime)),
+            self.admin.to_string(),
+            self.legacy_guest.to_string(),
+            csv_optional(self.organization.clone()),
+            csv_optional(self.max_sessions),
+            csv_optional(self.external_id.clone()),
+        ]
+    }
+}
+
 /// An email address for a user
 #[derive(Serialize, JsonSchema)]
 pub struct UserEmail {
@@ -148,6 +370,19 @@ impl Resource for UserEmail {
     }
 }
 
+impl CsvResource for UserEmail {
+    const CSV_COLUMNS: &'static [&'static str] = &["id", "created_at", "user_id", "email"];
+
+    fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            csv_datetime(self.created_at),
+            self.user_id.to_string(),
+            self.email.clone(),
+        ]
+    }
+}
+
 impl From<mas_data_model::UserEmail> for UserEmail {
     fn from(value: mas_data_model::UserEmail) -> Self {
         Self {
@@ -170,6 +405,183 @@ impl UserEmail {
     }
 }
 
+/// A note left on a user by an administrator
+#[derive(Serialize, JsonSchema)]
+pub struct UserNote {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// When the note was written
+    created_at: DateTime<Utc>,
+
+    /// The ID of the user on whom the note was left
+    #[schemars(with = "super::schema::Ulid")]
+    user_id: Ulid,
+
+    /// The ID of the user who wrote the note. This is null if the note was
+    /// written by a client acting on its own behalf, or if its author was
+    /// removed since.
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    author_id: Option<Ulid>,
+
+    /// The content of the note
+    content: String,
+}
+
+impl Resource for UserNote {
+    const KIND: &'static str = "user-note";
+    const PATH: &'static str = "/api/admin/v1/user-notes";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+impl From<mas_data_model::UserNote> for UserNote {
+    fn from(value: mas_data_model::UserNote) -> Self {
+        Self {
+            id: value.id,
+            created_at: value.created_at,
+            user_id: value.user_id,
+            author_id: value.author_id,
+            content: value.content,
+        }
+    }
+}
+
+impl UserNote {
+    /// Samples of user notes with different properties for examples in the
+    /// schema
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                created_at: DateTime::default(),
+                user_id: Ulid::from_bytes([0x02; 16]),
+                author_id: Some(Ulid::from_bytes([0x03; 16])),
+                content: "Called the support desk about a lost device".to_owned(),
+            },
+            Self {
+                id: Ulid::from_bytes([0x04; 16]),
+                created_at: DateTime::default(),
+                user_id: Ulid::from_bytes([0x02; 16]),
+                author_id: None,
+                content: "Account reviewed after the yearly audit".to_owned(),
+            },
+        ]
+    }
+}
+
+/// The type of device a user agent runs on
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceType {
+    /// A desktop or laptop computer
+    Pc,
+
+    /// A phone
+    Mobile,
+
+    /// A tablet
+    Tablet,
+
+    /// The device type could not be determined
+    Unknown,
+}
+
+impl From<mas_data_model::DeviceType> for DeviceType {
+    fn from(value: mas_data_model::DeviceType) -> Self {
+        match value {
+            mas_data_model::DeviceType::Pc => Self::Pc,
+            mas_data_model::DeviceType::Mobile => Self::Mobile,
+            mas_data_model::DeviceType::Tablet => Self::Tablet,
+            mas_data_model::DeviceType::Unknown => Self::Unknown,
+        }
+    }
+}
+
+/// Structured information parsed from a user agent string
+#[derive(Serialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct UserAgentDetails {
+    /// The name of the browser or application
+    name: Option<String>,
+
+    /// The version of the browser or application
+    version: Option<String>,
+
+    /// The operating system
+    os: Option<String>,
+
+    /// The version of the operating system
+    os_version: Option<String>,
+
+    /// The model of the device
+    model: Option<String>,
+
+    /// The type of device
+    device_type: DeviceType,
+}
+
+impl UserAgentDetails {
+    /// Parse a raw user agent string, if any
+    fn parse(user_agent: Option<&str>) -> Option<Self> {
+        let user_agent = mas_data_model::UserAgent::parse(user_agent?.to_owned());
+        Some(Self {
+            name: user_agent.name,
+            version: user_agent.version,
+            os: user_agent.os,
+            os_version: user_agent.os_version,
+            model: user_agent.model,
+            device_type: user_agent.device_type.into(),
+        })
+    }
+}
+
+/// How the user authenticated when a session was established
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionAuthenticationMethod {
+    /// The session was created before this was recorded, or through a path
+    /// which doesn't record it
+    Unknown,
+
+    /// The user entered their password
+    Password,
+
+    /// The user authenticated through an upstream OAuth 2.0 provider
+    Upstream,
+
+    /// The session was obtained by exchanging a login token
+    Token,
+
+    /// The session was issued by an administrator
+    AdminIssued,
+}
+
+impl From<mas_data_model::AuthenticatedBy> for SessionAuthenticationMethod {
+    fn from(value: mas_data_model::AuthenticatedBy) -> Self {
+        match value {
+            mas_data_model::AuthenticatedBy::Unknown => Self::Unknown,
+            mas_data_model::AuthenticatedBy::Password => Self::Password,
+            mas_data_model::AuthenticatedBy::Upstream { .. } => Self::Upstream,
+            mas_data_model::AuthenticatedBy::Token => Self::Token,
+            mas_data_model::AuthenticatedBy::AdminIssued => Self::AdminIssued,
+        }
+    }
+}
+
+impl std::fmt::Display for SessionAuthenticationMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown => f.write_str("unknown"),
+            Self::Password => f.write_str("password"),
+            Self::Upstream => f.write_str("upstream"),
+            Self::Token => f.write_str("token"),
+            Self::AdminIssued => f.write_str("admin_issued"),
+        }
+    }
+}
+
 /// A compatibility session for legacy clients
 #[derive(Serialize, JsonSchema)]
 pub struct CompatSession {
@@ -197,6 +609,10 @@ pub struct CompatSession {
     /// The user agent string that started this session, if any
     pub user_agent: Option<String>,
 
+    /// Structured information parsed from the user agent string, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent_details: Option<UserAgentDetails>,
+
     /// The time this session was last active
     pub last_active_at: Option<DateTime<Utc>>,
 
@@ -208,6 +624,14 @@ pub struct CompatSession {
 
     /// The user-provided name, if any
     pub human_name: Option<String>,
+
+    /// How the user authenticated when this session was established
+    pub authenticated_by: SessionAuthenticationMethod,
+
+    /// The ID of the upstream OAuth 2.0 provider the user authenticated with,
+    /// if they authenticated through one
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    pub authenticated_by_upstream_provider_id: Option<Ulid>,
 }
 
 impl
@@ -230,11 +654,14 @@ impl
             user_session_id: session.user_session_id,
             redirect_uri: sso_login.map(|sso| sso.redirect_uri),
             created_at: session.created_at,
+            user_agent_details: UserAgentDetails::parse(session.user_agent.as_deref()),
             user_agent: session.user_agent,
             last_active_at: session.last_active_at,
             last_active_ip: session.last_active_ip,
             finished_at,
             human_name: session.human_name,
+            authenticated_by: session.authenticated_by.into(),
+            authenticated_by_upstream_provider_id: session.authenticated_by.upstream_provider_id(),
         }
     }
 }
@@ -248,34 +675,76 @@ impl Resource for CompatSession {
     }
 }
 
+impl CsvResource for CompatSession {
+    const CSV_COLUMNS: &'static [&'static str] = &[
+        "id",
+        "user_id",
+        "device_id",
+        "user_session_id",
+        "redirect_uri",
+        "created_at",
+        "user_agent",
+        "last_active_at",
+        "last_active_ip",
+        "finished_at",
+        "human_name",
+        "authenticated_by",
+        "authenticated_by_upstream_provider_id",
+    ];
+
+    fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.user_id.to_string(),
+            csv_optional(self.device_id.as_ref()),
+            csv_optional(self.user_session_id),
+            csv_optional(self.redirect_uri.as_ref()),
+            csv_datetime(self.created_at),
+            csv_optional(self.user_agent.as_deref()),
+            csv_optional(self.last_active_at.map(csv_datetime)),
+            csv_optional(self.last_active_ip),
+            csv_optional(self.finished_at.map(csv_datetime)),
+            csv_optional(self.human_name.as_deref()),
+            self.authenticated_by.to_string(),
+            csv_optional(self.authenticated_by_upstream_provider_id),
+        ]
+    }
+}
+
 impl CompatSession {
     pub fn samples() -> [Self; 3] {
         [
             Self {
                 id: Ulid::from_bytes([0x01; 16]),
                 user_id: Ulid::from_bytes([0x01; 16]),
-                device_id: Some("AABBCCDDEE".to_owned().into()),
+                device_id: Some(Device::try_from("AABBCCDDEE".to_owned()).unwrap()),
                 user_session_id: Some(Ulid::from_bytes([0x11; 16])),
                 redirect_uri: Some("https://example.com/redirect".parse().unwrap()),
                 created_at: DateTime::default(),
                 user_agent: Some("Mozilla/5.0".to_owned()),
+                user_agent_details: UserAgentDetails::parse(Some("Mozilla/5.0")),
                 last_active_at: Some(DateTime::default()),
                 last_active_ip: Some([1, 2, 3, 4].into()),
                 finished_at: None,
                 human_name: Some("Laptop".to_owned()),
+                authenticated_by: SessionAuthenticationMethod::Password,
+                authenticated_by_upstream_provider_id: None,
             },
             Self {
                 id: Ulid::from_bytes([0x02; 16]),
                 user_id: Ulid::from_bytes([0x01; 16]),
-                device_id: Some("FFGGHHIIJJ".to_owned().into()),
+                device_id: Some(Device::try_from("FFGGHHIIJJ".to_owned()).unwrap()),
                 user_session_id: Some(Ulid::from_bytes([0x12; 16])),
                 redirect_uri: None,
                 created_at: DateTime::default(),
                 user_agent: Some("Mozilla/5.0".to_owned()),
+                user_agent_details: UserAgentDetails::parse(Some("Mozilla/5.0")),
                 last_active_at: Some(DateTime::default()),
                 last_active_ip: Some([1, 2, 3, 4].into()),
                 finished_at: Some(DateTime::default()),
                 human_name: None,
+                authenticated_by: SessionAuthenticationMethod::Upstream,
+                authenticated_by_upstream_provider_id: Some(Ulid::from_bytes([0x21; 16])),
             },
             Self {
                 id: Ulid::from_bytes([0x03; 16]),
@@ -285,15 +754,155 @@ impl CompatSession {
                 redirect_uri: None,
                 created_at: DateTime::default(),
                 user_agent: None,
+                user_agent_details: None,
                 last_active_at: None,
                 last_active_ip: None,
                 finished_at: None,
                 human_name: None,
+                authenticated_by: SessionAuthenticationMethod::AdminIssued,
+                authenticated_by_upstream_provider_id: None,
+            },
+        ]
+    }
+}
+
+/// The state of a compatibility SSO login
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompatSsoLoginState {
+    /// The login was started, and is waiting for the user to authenticate
+    Pending,
+
+    /// The user authenticated, and the login token is waiting to be
+    /// exchanged by the client
+    Fulfilled,
+
+    /// The client exchanged the login token for a compatibility session
+    Exchanged,
+}
+
+/// A compatibility SSO login, used by legacy clients to get a login token
+/// through the `m.login.sso` flow
+#[derive(Serialize, JsonSchema)]
+pub struct CompatSsoLogin {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// The state of the login
+    state: CompatSsoLoginState,
+
+    /// The URL the user is redirected to with the login token
+    redirect_uri: Url,
+
+    /// When the object was created
+    created_at: DateTime<Utc>,
+
+    /// When the user authenticated
+    fulfilled_at: Option<DateTime<Utc>>,
+
+    /// When the client exchanged the login token
+    exchanged_at: Option<DateTime<Utc>>,
+
+    /// The ID of the browser session which fulfilled the login, if it is not
+    /// exchanged yet
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    user_session_id: Option<Ulid>,
+
+    /// The ID of the compatibility session created when the login token was
+    /// exchanged
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    compat_session_id: Option<Ulid>,
+}
+
+impl From<mas_data_model::CompatSsoLogin> for CompatSsoLogin {
+    fn from(login: mas_data_model::CompatSsoLogin) -> Self {
+        let mut fulfilled_at = None;
+        let mut exchanged_at = None;
+        let mut user_session_id = None;
+        let mut compat_session_id = None;
+
+        let state = match login.state {
+            mas_data_model::CompatSsoLoginState::Pending => CompatSsoLoginState::Pending,
+            mas_data_model::CompatSsoLoginState::Fulfilled {
+                fulfilled_at: at,
+                browser_session_id,
+            } => {
+                fulfilled_at = Some(at);
+                user_session_id = Some(browser_session_id);
+                CompatSsoLoginState::Fulfilled
+            }
+            mas_data_model::CompatSsoLoginState::Exchanged {
+                fulfilled_at: fulfilled,
+                exchanged_at: exchanged,
+                compat_session_id: session_id,
+            } => {
+                fulfilled_at = Some(fulfilled);
+                exchanged_at = Some(exchanged);
+                compat_session_id = Some(session_id);
+                CompatSsoLoginState::Exchanged
+            }
+        };
+
+        Self {
+            id: login.id,
+            state,
+            redirect_uri: login.redirect_uri,
+            created_at: login.created_at,
+            fulfilled_at,
+            exchanged_at,
+            user_session_id,
+            compat_session_id,
+        }
+    }
+}
+
+impl CompatSsoLogin {
+    /// Samples of compatibility SSO logins
+    pub fn samples() -> [Self; 3] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                state: CompatSsoLoginState::Pending,
+                redirect_uri: "https://app.element.io/".parse().unwrap(),
+                created_at: DateTime::default(),
+                fulfilled_at: None,
+                exchanged_at: None,
+                user_session_id: None,
+                compat_session_id: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                state: CompatSsoLoginState::Fulfilled,
+                redirect_uri: "https://app.element.io/".parse().unwrap(),
+                created_at: DateTime::default(),
+                fulfilled_at: Some(DateTime::default() + chrono::Duration::minutes(1)),
+                exchanged_at: None,
+                user_session_id: Some(Ulid::from_bytes([0x11; 16])),
+                compat_session_id: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x03; 16]),
+                state: CompatSsoLoginState::Exchanged,
+                redirect_uri: "https://app.element.io/".parse().unwrap(),
+                created_at: DateTime::default(),
+                fulfilled_at: Some(DateTime::default() + chrono::Duration::minutes(1)),
+                exchanged_at: Some(DateTime::default() + chrono::Duration::minutes(2)),
+                user_session_id: None,
+                compat_session_id: Some(Ulid::from_bytes([0x21; 16])),
             },
         ]
     }
 }
 
+impl Resource for CompatSsoLogin {
+    const KIND: &'static str = "compat-sso-login";
+    const PATH: &'static str = "/api/admin/v1/compat-sso-logins";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
 /// A OAuth 2.0 session
 #[derive(Serialize, JsonSchema)]
 pub struct OAuth2Session {
@@ -324,6 +933,10 @@ pub struct OAuth2Session {
     /// The user agent string of the client which started this session
     user_agent: Option<String>,
 
+    /// Structured information parsed from the user agent string, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_agent_details: Option<UserAgentDetails>,
+
     /// The last time the session was active
     last_active_at: Option<DateTime<Utc>>,
 
@@ -332,6 +945,14 @@ pub struct OAuth2Session {
 
     /// The user-provided name, if any
     human_name: Option<String>,
+
+    /// How the user authenticated when this session was established
+    authenticated_by: SessionAuthenticationMethod,
+
+    /// The ID of the upstream OAuth 2.0 provider the user authenticated with,
+    /// if they authenticated through one
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    authenticated_by_upstream_provider_id: Option<Ulid>,
 }
 
 impl From<mas_data_model::Session> for OAuth2Session {
@@ -344,10 +965,13 @@ impl From<mas_data_model::Session> for OAuth2Session {
             user_session_id: session.user_session_id,
             client_id: session.client_id,
             scope: session.scope.to_string(),
+            user_agent_details: UserAgentDetails::parse(session.user_agent.as_deref()),
             user_agent: session.user_agent,
             last_active_at: session.last_active_at,
             last_active_ip: session.last_active_ip,
             human_name: session.human_name,
+            authenticated_by: session.authenticated_by.into(),
+            authenticated_by_upstream_provider_id: session.authenticated_by.upstream_provider_id(),
         }
     }
 }
@@ -365,9 +989,12 @@ impl OAuth2Session {
                 client_id: Ulid::from_bytes([0x04; 16]),
                 scope: "openid".to_owned(),
                 user_agent: Some("Mozilla/5.0".to_owned()),
+                user_agent_details: UserAgentDetails::parse(Some("Mozilla/5.0")),
                 last_active_at: Some(DateTime::default()),
                 last_active_ip: Some("127.0.0.1".parse().unwrap()),
                 human_name: Some("Laptop".to_owned()),
+                authenticated_by: SessionAuthenticationMethod::Upstream,
+                authenticated_by_upstream_provider_id: Some(Ulid::from_bytes([0x21; 16])),
             },
             Self {
                 id: Ulid::from_bytes([0x02; 16]),
@@ -378,9 +1005,12 @@ impl OAuth2Session {
                 client_id: Ulid::from_bytes([0x05; 16]),
                 scope: "urn:mas:admin".to_owned(),
                 user_agent: None,
+                user_agent_details: None,
                 last_active_at: None,
                 last_active_ip: None,
                 human_name: None,
+                authenticated_by: SessionAuthenticationMethod::Unknown,
+                authenticated_by_upstream_provider_id: None,
             },
             Self {
                 id: Ulid::from_bytes([0x03; 16]),
@@ -391,9 +1021,12 @@ impl OAuth2Session {
                 client_id: Ulid::from_bytes([0x06; 16]),
                 scope: "urn:matrix:client:api:*".to_owned(),
                 user_agent: Some("Mozilla/5.0".to_owned()),
+                user_agent_details: UserAgentDetails::parse(Some("Mozilla/5.0")),
                 last_active_at: Some(DateTime::default()),
                 last_active_ip: Some("127.0.0.1".parse().unwrap()),
                 human_name: None,
+                authenticated_by: SessionAuthenticationMethod::Password,
+                authenticated_by_upstream_provider_id: None,
             },
         ]
     }
@@ -408,6 +1041,173 @@ impl Resource for OAuth2Session {
     }
 }
 
+/// The state of an OAuth 2.0 device code grant
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceCodeGrantState {
+    /// The grant is waiting for a user to approve or reject it
+    Pending,
+
+    /// The grant was approved by a user, and is waiting for the device to
+    /// exchange it
+    Fulfilled,
+
+    /// The grant was rejected by a user
+    Rejected,
+
+    /// The grant was exchanged by the device for an OAuth 2.0 session
+    Exchanged,
+}
+
+/// An OAuth 2.0 device code grant
+#[derive(Serialize, JsonSchema)]
+pub struct DeviceCodeGrant {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// The state of the grant
+    state: DeviceCodeGrantState,
+
+    /// Whether the grant has expired
+    expired: bool,
+
+    /// The ID of the client which started the grant
+    #[schemars(with = "super::schema::Ulid")]
+    client_id: Ulid,
+
+    /// The scope requested by the client
+    scope: String,
+
+    /// When the object was created
+    created_at: DateTime<Utc>,
+
+    /// When the grant expires
+    expires_at: DateTime<Utc>,
+
+    /// When the grant was approved by the user
+    fulfilled_at: Option<DateTime<Utc>>,
+
+    /// When the grant was rejected by the user
+    rejected_at: Option<DateTime<Utc>>,
+
+    /// When the grant was exchanged by the device
+    exchanged_at: Option<DateTime<Utc>>,
+
+    /// The ID of the browser session which approved or rejected the grant
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    user_session_id: Option<Ulid>,
+
+    /// The ID of the OAuth 2.0 session created when the grant was exchanged
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    oauth2_session_id: Option<Ulid>,
+
+    /// The user agent string of the device which started the grant
+    user_agent: Option<String>,
+}
+
+impl DeviceCodeGrant {
+    pub fn new(grant: mas_data_model::DeviceCodeGrant, now: DateTime<Utc>) -> Self {
+        let mut fulfilled_at = None;
+        let mut rejected_at = None;
+        let mut exchanged_at = None;
+        let mut user_session_id = None;
+        let mut oauth2_session_id = None;
+
+        let state = match grant.state {
+            mas_data_model::DeviceCodeGrantState::Pending => DeviceCodeGrantState::Pending,
+            mas_data_model::DeviceCodeGrantState::Fulfilled {
+                browser_session_id,
+                fulfilled_at: at,
+            } => {
+                fulfilled_at = Some(at);
+                user_session_id = Some(browser_session_id);
+                DeviceCodeGrantState::Fulfilled
+            }
+            mas_data_model::DeviceCodeGrantState::Rejected {
+                browser_session_id,
+                rejected_at: at,
+            } => {
+                rejected_at = Some(at);
+                user_session_id = Some(browser_session_id);
+                DeviceCodeGrantState::Rejected
+            }
+            mas_data_model::DeviceCodeGrantState::Exchanged {
+                browser_session_id,
+                fulfilled_at: fulfilled,
+                exchanged_at: exchanged,
+                session_id,
+            } => {
+                fulfilled_at = Some(fulfilled);
+                exchanged_at = Some(exchanged);
+                user_session_id = Some(browser_session_id);
+                oauth2_session_id = Some(session_id);
+                DeviceCodeGrantState::Exchanged
+            }
+        };
+
+        Self {
+            id: grant.id,
+            state,
+            expired: grant.expires_at < now,
+            client_id: grant.client_id,
+            scope: grant.scope.to_string(),
+            created_at: grant.created_at,
+            expires_at: grant.expires_at,
+            fulfilled_at,
+            rejected_at,
+            exchanged_at,
+            user_session_id,
+            oauth2_session_id,
+            user_agent: grant.user_agent,
+        }
+    }
+
+    /// Samples of OAuth 2.0 device code grants
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                state: DeviceCodeGrantState::Fulfilled,
+                expired: false,
+                client_id: Ulid::from_bytes([0x02; 16]),
+                scope: "openid urn:matrix:client:api:*".to_owned(),
+                created_at: DateTime::default(),
+                expires_at: DateTime::default() + chrono::Duration::minutes(20),
+                fulfilled_at: Some(DateTime::default() + chrono::Duration::minutes(1)),
+                rejected_at: None,
+                exchanged_at: None,
+                user_session_id: Some(Ulid::from_bytes([0x03; 16])),
+                oauth2_session_id: None,
+                user_agent: Some("Mozilla/5.0".to_owned()),
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                state: DeviceCodeGrantState::Exchanged,
+                expired: true,
+                client_id: Ulid::from_bytes([0x02; 16]),
+                scope: "openid urn:matrix:client:api:*".to_owned(),
+                created_at: DateTime::default(),
+                expires_at: DateTime::default() + chrono::Duration::minutes(20),
+                fulfilled_at: Some(DateTime::default() + chrono::Duration::minutes(1)),
+                rejected_at: None,
+                exchanged_at: Some(DateTime::default() + chrono::Duration::minutes(2)),
+                user_session_id: Some(Ulid::from_bytes([0x03; 16])),
+                oauth2_session_id: Some(Ulid::from_bytes([0x04; 16])),
+                user_agent: None,
+            },
+        ]
+    }
+}
+
+impl Resource for DeviceCodeGrant {
+    const KIND: &'static str = "device-code-grant";
+    const PATH: &'static str = "/api/admin/v1/device-code-grants";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
 /// The browser (cookie) session for a user
 #[derive(Serialize, JsonSchema)]
 pub struct UserSession {
@@ -427,6 +1227,10 @@ pub struct UserSession {
     /// The user agent string of the client which started this session
     user_agent: Option<String>,
 
+    /// Structured information parsed from the user agent string, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_agent_details: Option<UserAgentDetails>,
+
     /// The last time the session was active
     last_active_at: Option<DateTime<Utc>>,
 
@@ -441,6 +1245,7 @@ impl From<mas_data_model::BrowserSession> for UserSession {
             created_at: value.created_at,
             finished_at: value.finished_at,
             user_id: value.user.id,
+            user_agent_details: UserAgentDetails::parse(value.user_agent.as_deref()),
             user_agent: value.user_agent,
             last_active_at: value.last_active_at,
             last_active_ip: value.last_active_ip,
@@ -458,6 +1263,7 @@ impl UserSession {
                 finished_at: None,
                 user_id: Ulid::from_bytes([0x02; 16]),
                 user_agent: Some("Mozilla/5.0".to_owned()),
+                user_agent_details: UserAgentDetails::parse(Some("Mozilla/5.0")),
                 last_active_at: Some(DateTime::default()),
                 last_active_ip: Some("127.0.0.1".parse().unwrap()),
             },
@@ -467,6 +1273,7 @@ impl UserSession {
                 finished_at: None,
                 user_id: Ulid::from_bytes([0x03; 16]),
                 user_agent: None,
+                user_agent_details: None,
                 last_active_at: None,
                 last_active_ip: None,
             },
@@ -476,6 +1283,7 @@ impl UserSession {
                 finished_at: Some(DateTime::default()),
                 user_id: Ulid::from_bytes([0x04; 16]),
                 user_agent: Some("Mozilla/5.0".to_owned()),
+                user_agent_details: UserAgentDetails::parse(Some("Mozilla/5.0")),
                 last_active_at: Some(DateTime::default()),
                 last_active_ip: Some("127.0.0.1".parse().unwrap()),
             },
@@ -617,6 +1425,375 @@ impl PolicyData {
     }
 }
 
+/// A kind of discrepancy between a user in MAS and on the homeserver
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProvisioningDiscrepancyKind {
+    /// The user doesn't exist on the homeserver
+    MissingOnHomeserver,
+
+    /// The user is deactivated in MAS but not on the homeserver, or the other
+    /// way around
+    DeactivatedMismatch,
+
+    /// The user has no display name on the homeserver, or one which differs
+    /// from the display name derived from their email address
+    DisplaynameDrift,
+}
+
+impl From<mas_data_model::ProvisioningDiscrepancyKind> for ProvisioningDiscrepancyKind {
+    fn from(value: mas_data_model::ProvisioningDiscrepancyKind) -> Self {
+        match value {
+            mas_data_model::ProvisioningDiscrepancyKind::MissingOnHomeserver => {
+                Self::MissingOnHomeserver
+            }
+            mas_data_model::ProvisioningDiscrepancyKind::DeactivatedMismatch => {
+                Self::DeactivatedMismatch
+            }
+            mas_data_model::ProvisioningDiscrepancyKind::DisplaynameDrift => Self::DisplaynameDrift,
+        }
+    }
+}
+
+/// A discrepancy found for a user by a provisioning reconciliation
+#[derive(Serialize, JsonSchema)]
+pub struct ProvisioningDiscrepancy {
+    /// The ID of the user
+    #[schemars(with = "super::schema::Ulid")]
+    user_id: Ulid,
+
+    /// The username of the user
+    username: String,
+
+    /// The kind of discrepancy
+    kind: ProvisioningDiscrepancyKind,
+
+    /// Whether a fix was scheduled for the discrepancy
+    fixed: bool,
+}
+
+impl From<mas_data_model::ProvisioningDiscrepancy> for ProvisioningDiscrepancy {
+    fn from(value: mas_data_model::ProvisioningDiscrepancy) -> Self {
+        Self {
+            user_id: value.user_id,
+            username: value.username,
+            kind: value.kind.into(),
+            fixed: value.fixed,
+        }
+    }
+}
+
+/// The report of a reconciliation between the users in MAS and on the
+/// homeserver
+#[derive(Serialize, JsonSchema)]
+pub struct ProvisioningReport {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// When the reconciliation was requested
+    created_at: DateTime<Utc>,
+
+    /// Whether the reconciliation provisions the active users missing on the
+    /// homeserver
+    fix: bool,
+
+    /// When the reconciliation finished. If null, it is still running.
+    completed_at: Option<DateTime<Utc>>,
+
+    /// How many users were checked
+    users_checked: u64,
+
+    /// The discrepancies which were found
+    discrepancies: Vec<ProvisioningDiscrepancy>,
+}
+
+impl From<mas_data_model::ProvisioningReport> for ProvisioningReport {
+    fn from(value: mas_data_model::ProvisioningReport) -> Self {
+        Self {
+            id: value.id,
+            created_at: value.created_at,
+            fix: value.fix,
+            completed_at: value.completed_at,
+            users_checked: value.users_checked,
+            discrepancies: value.discrepancies.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Resource for ProvisioningReport {
+    const KIND: &'static str = "provisioning-report";
+    const PATH: &'static str = "/api/admin/v1/provisioning-reports";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+impl ProvisioningReport {
+    /// Samples of provisioning reports
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                created_at: DateTime::default(),
+                fix: true,
+                completed_at: Some(DateTime::default()),
+                users_checked: 42,
+                discrepancies: vec![
+                    ProvisioningDiscrepancy {
+                        user_id: Ulid::from_bytes([0x02; 16]),
+                        username: "alice".to_owned(),
+                        kind: ProvisioningDiscrepancyKind::MissingOnHomeserver,
+                        fixed: true,
+                    },
+                    ProvisioningDiscrepancy {
+                        user_id: Ulid::from_bytes([0x03; 16]),
+                        username: "bob".to_owned(),
+                        kind: ProvisioningDiscrepancyKind::DisplaynameDrift,
+                        fixed: false,
+                    },
+                ],
+            },
+            Self {
+                id: Ulid::from_bytes([0x04; 16]),
+                created_at: DateTime::default(),
+                fix: false,
+                completed_at: None,
+                users_checked: 0,
+                discrepancies: Vec::new(),
+            },
+        ]
+    }
+}
+
+/// A run of the syn2mas tool, which migrated users from Synapse
+#[derive(Serialize, JsonSchema)]
+pub struct Syn2masRun {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// When the migration started
+    started_at: DateTime<Utc>,
+
+    /// Whether the migration ran in dry-run mode, in which case the migrated
+    /// data was discarded
+    dry_run: bool,
+
+    /// When the migration finished. If null, it is still running, or it was
+    /// interrupted.
+    finished_at: Option<DateTime<Utc>>,
+
+    /// How many users were migrated. Null until the migration finished.
+    user_count: Option<u64>,
+
+    /// How many compatibility sessions were migrated. Null until the migration
+    /// finished.
+    compat_session_count: Option<u64>,
+}
+
+impl From<mas_data_model::Syn2masRun> for Syn2masRun {
+    fn from(value: mas_data_model::Syn2masRun) -> Self {
+        Self {
+            id: value.id,
+            started_at: value.started_at,
+            dry_run: value.dry_run,
+            finished_at: value.finished_at,
+            user_count: value.user_count,
+            compat_session_count: value.compat_session_count,
+        }
+    }
+}
+
+impl Resource for Syn2masRun {
+    const KIND: &'static str = "syn2mas-run";
+    const PATH: &'static str = "/api/admin/v1/migrations";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+impl Syn2masRun {
+    /// Samples of syn2mas runs
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                started_at: DateTime::default(),
+                dry_run: true,
+                finished_at: Some(DateTime::default()),
+                user_count: Some(42),
+                compat_session_count: Some(108),
+            },
+            Self {
+                id: Ulid::from_bytes([0x02; 16]),
+                started_at: DateTime::default(),
+                dry_run: false,
+                finished_at: Some(DateTime::default()),
+                user_count: Some(42),
+                compat_session_count: Some(108),
+            },
+        ]
+    }
+}
+
+/// The status of a job in the job queue
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueJobStatus {
+    /// The job is waiting to be picked up by a worker
+    Available,
+
+    /// The job is being processed by a worker
+    Running,
+
+    /// The job completed successfully
+    Completed,
+
+    /// The worker processing the job was lost
+    Lost,
+
+    /// The job failed
+    Failed,
+
+    /// The job is scheduled to run at a later date
+    Scheduled,
+}
+
+impl From<mas_storage::queue::JobStatus> for QueueJobStatus {
+    fn from(value: mas_storage::queue::JobStatus) -> Self {
+        match value {
+            mas_storage::queue::JobStatus::Available => Self::Available,
+            mas_storage::queue::JobStatus::Running => Self::Running,
+            mas_storage::queue::JobStatus::Completed => Self::Completed,
+            mas_storage::queue::JobStatus::Lost => Self::Lost,
+            mas_storage::queue::JobStatus::Failed => Self::Failed,
+            mas_storage::queue::JobStatus::Scheduled => Self::Scheduled,
+        }
+    }
+}
+
+/// A job in the job queue
+#[derive(Serialize, JsonSchema)]
+pub struct QueueJob {
+    #[serde(skip)]
+    id: Ulid,
+
+    /// The queue on which the job was placed, which defines what the job does
+    queue_name: String,
+
+    /// The status of the job
+    status: QueueJobStatus,
+
+    /// Which attempt it is, starting at 0
+    attempt: usize,
+
+    /// The ID of the user this job is about. If null, the job isn't about a
+    /// user, or was scheduled before jobs were associated with users.
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    user_id: Option<Ulid>,
+
+    /// The payload of the job
+    payload: serde_json::Value,
+
+    /// When the job was created
+    created_at: DateTime<Utc>,
+
+    /// When the job is scheduled to run, if it was scheduled for later
+    scheduled_at: Option<DateTime<Utc>>,
+
+    /// When a worker started processing the job
+    started_at: Option<DateTime<Utc>>,
+
+    /// When the job completed
+    completed_at: Option<DateTime<Utc>>,
+
+    /// When the job failed
+    failed_at: Option<DateTime<Utc>>,
+
+    /// Why the job failed
+    failed_reason: Option<String>,
+
+    /// The ID of the job which retries this one. If null, the job was not
+    /// retried.
+    #[schemars(with = "Option<super::schema::Ulid>")]
+    next_attempt_id: Option<Ulid>,
+}
+
+impl From<mas_storage::queue::QueueJob> for QueueJob {
+    fn from(value: mas_storage::queue::QueueJob) -> Self {
+        Self {
+            id: value.id,
+            queue_name: value.queue_name,
+            status: value.status.into(),
+            attempt: value.attempt,
+            user_id: value.user_id,
+            payload: value.payload,
+            created_at: value.created_at,
+            scheduled_at: value.scheduled_at,
+            started_at: value.started_at,
+            completed_at: value.completed_at,
+            failed_at: value.failed_at,
+            failed_reason: value.failed_reason,
+            next_attempt_id: value.next_attempt_id,
+        }
+    }
+}
+
+impl Resource for QueueJob {
+    const KIND: &'static str = "queue-job";
+    const PATH: &'static str = "/api/admin/v1/jobs";
+
+    fn id(&self) -> Ulid {
+        self.id
+    }
+}
+
+impl QueueJob {
+    /// Samples of queue jobs
+    pub fn samples() -> [Self; 2] {
+        [
+            Self {
+                id: Ulid::from_bytes([0x01; 16]),
+                queue_name: "send-email-authentication-code".to_owned(),
+                status: QueueJobStatus::Failed,
+                attempt: 0,
+                user_id: Some(Ulid::from_bytes([0x02; 16])),
+                payload: serde_json::json!({
+                    "user_email_authentication_id": "030C1G60R30C1G60R30C1G60R3",
+                    "language": "en",
+                    "user_id": "02081040G2081040G2081040G2",
+                }),
+                created_at: DateTime::default(),
+                scheduled_at: None,
+                started_at: Some(DateTime::default()),
+                completed_at: None,
+                failed_at: Some(DateTime::default()),
+                failed_reason: Some("Failed to send email".to_owned()),
+                next_attempt_id: None,
+            },
+            Self {
+                id: Ulid::from_bytes([0x04; 16]),
+                queue_name: "provision-user".to_owned(),
+                status: QueueJobStatus::Completed,
+                attempt: 0,
+                user_id: Some(Ulid::from_bytes([0x02; 16])),
+                payload: serde_json::json!({
+                    "user_id": "02081040G2081040G2081040G2",
+                    "set_display_name": null,
+                }),
+                created_at: DateTime::default(),
+                scheduled_at: None,
+                started_at: Some(DateTime::default()),
+                completed_at: Some(DateTime::default()),
+                failed_at: None,
+                failed_reason: None,
+                next_attempt_id: None,
+            },
+        ]
+    }
+}
+
 /// A registration token
 #[derive(Serialize, JsonSchema)]
 pub struct UserRegistrationToken {
@@ -718,11 +1895,18 @@ pub struct UpstreamOAuthProvider {
     /// A brand identifier, e.g. "apple" or "google"
     brand_name: Option<String>,
 
+    /// The scope requested to the provider during authorization
+    scope: String,
+
     /// When the provider was created
     created_at: DateTime<Utc>,
 
     /// When the provider was disabled. If null, the provider is enabled.
     disabled_at: Option<DateTime<Utc>>,
+
+    /// The outcome of the last metadata discovery of the provider. If null,
+    /// discovery is disabled for this provider or it wasn't attempted yet.
+    discovery_status: Option<UpstreamOAuthProviderDiscoveryStatus>,
 }
 
 impl From<mas_data_model::UpstreamOAuthProvider> for UpstreamOAuthProvider {
@@ -732,8 +1916,43 @@ impl From<mas_data_model::UpstreamOAuthProvider> for UpstreamOAuthProvider {
             issuer: provider.issuer,
             human_name: provider.human_name,
             brand_name: provider.brand_name,
+            scope: provider.scope.to_string(),
             created_at: provider.created_at,
             disabled_at: provider.disabled_at,
+            discovery_status: None,
+        }
+    }
+}
+
+impl UpstreamOAuthProvider {
+    /// Set the discovery status of the provider
+    #[must_use]
+    pub fn with_discovery_status(mut self, status: Option<&DiscoveryStatus>) -> Self {
+        self.discovery_status = status.map(UpstreamOAuthProviderDiscoveryStatus::from);
+        self
+    }
+}
+
+/// The outcome of the last metadata discovery of an upstream OAuth 2.0
+/// provider
+#[derive(Serialize, JsonSchema)]
+pub struct UpstreamOAuthProviderDiscoveryStatus {
+    /// When the metadata was last fetched
+    fetched_at: DateTime<Utc>,
+
+    /// Whether the last fetch succeeded
+    ok: bool,
+
+    /// The error message of the last fetch, if it failed
+    error: Option<String>,
+}
+
+impl From<&DiscoveryStatus> for UpstreamOAuthProviderDiscoveryStatus {
+    fn from(status: &DiscoveryStatus) -> Self {
+        Self {
+            fetched_at: status.fetched_at,
+            ok: status.error.is_none(),
+            error: status.error.clone(),
         }
     }
 }
@@ -756,24 +1975,38 @@ impl UpstreamOAuthProvider {
                 issuer: Some("https://accounts.google.com".to_owned()),
                 human_name: Some("Google".to_owned()),
                 brand_name: Some("google".to_owned()),
+                scope: "openid profile email".to_owned(),
                 created_at: DateTime::default(),
                 disabled_at: None,
+                discovery_status: Some(UpstreamOAuthProviderDiscoveryStatus {
+                    fetched_at: DateTime::default(),
+                    ok: true,
+                    error: None,
+                }),
             },
             Self {
                 id: Ulid::from_bytes([0x02; 16]),
                 issuer: Some("https://appleid.apple.com".to_owned()),
                 human_name: Some("Apple ID".to_owned()),
                 brand_name: Some("apple".to_owned()),
+                scope: "openid name email".to_owned(),
                 created_at: DateTime::default(),
                 disabled_at: Some(DateTime::default()),
+                discovery_status: Some(UpstreamOAuthProviderDiscoveryStatus {
+                    fetched_at: DateTime::default(),
+                    ok: false,
+                    error: Some("Failed to fetch provider metadata".to_owned()),
+                }),
             },
             Self {
                 id: Ulid::from_bytes([0x03; 16]),
                 issuer: None,
                 human_name: Some("Custom OAuth Provider".to_owned()),
                 brand_name: None,
+                scope: "openid".to_owned(),
                 created_at: DateTime::default(),
                 disabled_at: None,
+                discovery_status: None,
             },
         ]
     }
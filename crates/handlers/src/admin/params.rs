@@ -7,7 +7,12 @@
 // Generated code from schemars violates this rule
 #![allow(clippy::str_to_string)]
 
-use std::{borrow::Cow, num::NonZeroUsize};
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+    num::NonZeroUsize,
+    sync::{Arc, LazyLock, Mutex},
+};
 
 use aide::OperationIo;
 use axum::{
@@ -17,13 +22,16 @@ use axum::{
 };
 use axum_extra::extract::{Query, QueryRejection};
 use axum_macros::FromRequestParts;
-use hyper::StatusCode;
+use hyper::{StatusCode, header::ACCEPT};
 use mas_storage::pagination::PaginationDirection;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use ulid::Ulid;
 
-use super::response::ErrorResponse;
+use super::{
+    model::{CompatSession, OAuth2Session, Resource, User, UserSession},
+    response::ErrorResponse,
+};
 
 #[derive(Debug, thiserror::Error)]
 #[error("Invalid ULID in path")]
@@ -59,6 +67,28 @@ impl std::ops::Deref for UlidPathParam {
     }
 }
 
+#[derive(JsonSchema, Debug, Clone, Copy, Deserialize)]
+struct TargetUlidInPath {
+    /// # The ID of the target resource
+    #[schemars(with = "super::schema::Ulid")]
+    target_id: Ulid,
+}
+
+/// The ID of a second resource in the path, for operations involving two
+/// resources, like `/users/{id}/merge-into/{target_id}`
+#[derive(FromRequestParts, OperationIo, Debug, Clone, Copy)]
+#[from_request(rejection(UlidPathParamRejection))]
+#[aide(input_with = "Path<TargetUlidInPath>")]
+pub struct TargetUlidPathParam(#[from_request(via(Path))] TargetUlidInPath);
+
+impl std::ops::Deref for TargetUlidPathParam {
+    type Target = Ulid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0.target_id
+    }
+}
+
 /// The default page size if not specified
 const DEFAULT_PAGE_SIZE: usize = 10;
 
@@ -171,3 +201,246 @@ impl<S: Send + Sync> FromRequestParts<S> for Pagination {
         ))
     }
 }
+
+/// The format of a list response
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// A paginated JSON:API response (default)
+    #[default]
+    Json,
+
+    /// A CSV export of all the items matching the filters, ignoring the
+    /// pagination parameters
+    Csv,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct ExportFormatParams {
+    /// The format of the response. Defaults to `json`, unless the `Accept`
+    /// header asks for `text/csv`.
+    format: Option<ExportFormat>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid format parameter")]
+pub struct ExportFormatRejection(#[from] QueryRejection);
+
+impl IntoResponse for ExportFormatRejection {
+    fn into_response(self) -> axum::response::Response {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::from_error(&self)),
+        )
+            .into_response()
+    }
+}
+
+/// An extractor for the response format, either from the `format` query
+/// parameter or from the `Accept` header
+#[derive(OperationIo, Debug, Clone, Copy)]
+#[aide(input_with = "Query<ExportFormatParams>")]
+pub struct Format(pub ExportFormat);
+
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = ExportFormatRejection;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let params = Query::<ExportFormatParams>::from_request_parts(parts, state).await?;
+
+        // The query parameter takes precedence over the Accept header
+        if let Some(format) = params.format {
+            return Ok(Self(format));
+        }
+
+        let accepts_csv = parts
+            .headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|media_type| media_type.split(';').next())
+            .any(|media_type| media_type.trim().eq_ignore_ascii_case("text/csv"));
+
+        if accepts_csv {
+            Ok(Self(ExportFormat::Csv))
+        } else {
+            Ok(Self(ExportFormat::Json))
+        }
+    }
+}
+
+/// A sparse fieldset, restricting which attributes of the resources of a given
+/// type are included in the response
+#[derive(Debug, Clone, Default)]
+pub struct Fieldset {
+    kind: &'static str,
+
+    /// The requested attributes, or `None` to include all of them
+    fields: Option<Arc<BTreeSet<String>>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FieldsetRejection {
+    #[error("Invalid fieldset parameter")]
+    Invalid(#[from] QueryRejection),
+
+    #[error("Unknown field {field:?} for resources of type {kind:?}")]
+    UnknownField { kind: &'static str, field: String },
+}
+
+impl IntoResponse for FieldsetRejection {
+    fn into_response(self) -> axum::response::Response {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::from_error(&self)),
+        )
+            .into_response()
+    }
+}
+
+/// The attributes of each resource kind, keyed by the kind
+type KnownFieldsCache = HashMap<&'static str, Arc<BTreeSet<String>>>;
+
+/// The attributes of each resource kind, computed from their schema the first
+/// time a fieldset is requested for them
+static KNOWN_FIELDS: LazyLock<Mutex<KnownFieldsCache>> = LazyLock::new(Mutex::default);
+
+/// Get the attributes of the resource `T`
+fn known_fields<T: Resource + JsonSchema>() -> Arc<BTreeSet<String>> {
+    KNOWN_FIELDS
+        .lock()
+        .unwrap()
+        .entry(T::KIND)
+        .or_insert_with(|| {
+            let schema = schemars::schema_for!(T);
+            let fields = schema
+                .schema
+                .object
+                .map(|object| object.properties.into_keys().collect())
+                .unwrap_or_default();
+            Arc::new(fields)
+        })
+        .clone()
+}
+
+impl Fieldset {
+    /// Parse a comma-separated list of attributes for the resource `T`,
+    /// checking them against the attributes of its schema
+    fn parse<T: Resource + JsonSchema>(value: Option<&str>) -> Result<Self, FieldsetRejection> {
+        let Some(value) = value else {
+            return Ok(Self {
+                kind: T::KIND,
+                fields: None,
+            });
+        };
+
+        let known = known_fields::<T>();
+
+        let fields = value
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                if known.contains(field) {
+                    Ok(field.to_owned())
+                } else {
+                    Err(FieldsetRejection::UnknownField {
+                        kind: T::KIND,
+                        field: field.to_owned(),
+                    })
+                }
+            })
+            .collect::<Result<BTreeSet<_>, _>>()?;
+
+        Ok(Self {
+            kind: T::KIND,
+            fields: Some(Arc::new(fields)),
+        })
+    }
+
+    /// The requested attributes, or `None` if all of them should be included
+    pub(crate) fn fields(&self) -> Option<&BTreeSet<String>> {
+        self.fields.as_deref()
+    }
+
+    /// Add the fieldset to the base URL of a list, so that the pagination
+    /// links keep the same fieldset
+    pub(crate) fn add_to_base<'a>(&self, base: &'a str) -> Cow<'a, str> {
+        let Some(fields) = &self.fields else {
+            return Cow::Borrowed(base);
+        };
+
+        let separator = if base.contains('?') { '&' } else { '?' };
+        let fields = fields
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{base}{separator}fields[{kind}]={fields}", kind = self.kind).into()
+    }
+}
+
+/// Define an extractor for the sparse fieldset of a type of resource, read from
+/// the `fields[<type>]` query parameter
+macro_rules! fieldset_extractor {
+    ($name:ident, $params:ident, $resource:ty, $param:literal) => {
+        #[derive(Deserialize, JsonSchema)]
+        struct $params {
+            /// Comma-separated list of attributes to include in the response.
+            /// Defaults to all the attributes.
+            #[serde(rename = $param)]
+            fields: Option<String>,
+        }
+
+        #[doc = concat!("An extractor for the `", $param, "` sparse fieldset parameter")]
+        #[derive(Debug, Clone)]
+        pub struct $name(pub Fieldset);
+
+        impl<S: Send + Sync> FromRequestParts<S> for $name {
+            type Rejection = FieldsetRejection;
+
+            async fn from_request_parts(
+                parts: &mut axum::http::request::Parts,
+                state: &S,
+            ) -> Result<Self, Self::Rejection> {
+                let params = Query::<$params>::from_request_parts(parts, state).await?;
+                Ok(Self(Fieldset::parse::<$resource>(
+                    params.fields.as_deref(),
+                )?))
+            }
+        }
+
+        impl aide::OperationInput for $name {
+            fn operation_input(
+                ctx: &mut aide::generate::GenContext,
+                operation: &mut aide::openapi::Operation,
+            ) {
+                <Query<$params> as aide::OperationInput>::operation_input(ctx, operation);
+            }
+        }
+    };
+}
+
+fieldset_extractor!(UserFields, UserFieldsParams, User, "fields[user]");
+fieldset_extractor!(
+    CompatSessionFields,
+    CompatSessionFieldsParams,
+    CompatSession,
+    "fields[compat-session]"
+);
+fieldset_extractor!(
+    OAuth2SessionFields,
+    OAuth2SessionFieldsParams,
+    OAuth2Session,
+    "fields[oauth2-session]"
+);
+fieldset_extractor!(
+    UserSessionFields,
+    UserSessionFieldsParams,
+    UserSession,
+    "fields[user-session]"
+);
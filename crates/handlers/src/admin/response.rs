@@ -6,12 +6,18 @@
 
 #![allow(clippy::module_name_repetitions)]
 
+use aide::{OperationOutput, generate::GenContext, openapi::Operation};
+use axum::{
+    Json,
+    response::{IntoResponse, Response},
+};
+use hyper::header::{HeaderValue, LINK};
 use mas_storage::{Pagination, pagination::Edge};
 use schemars::JsonSchema;
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 use ulid::Ulid;
 
-use super::model::Resource;
+use super::{model::Resource, params::Fieldset};
 
 /// Related links
 #[derive(Serialize, JsonSchema)]
@@ -148,6 +154,18 @@ impl<T: Resource> PaginatedResponse<T> {
         }
     }
 
+    /// Restrict the attributes of the resources in the page to the given
+    /// sparse fieldset
+    #[must_use]
+    pub fn with_fieldset(mut self, fieldset: &Fieldset) -> Self {
+        if let Some(data) = &mut self.data {
+            for resource in data {
+                resource.attributes.fieldset = fieldset.clone();
+            }
+        }
+        self
+    }
+
     pub fn for_count_only(count: usize, base: &str) -> Self {
         let links = PaginationLinks {
             self_: base.to_owned(),
@@ -165,6 +183,60 @@ impl<T: Resource> PaginatedResponse<T> {
     }
 }
 
+impl<T> PaginatedResponse<T> {
+    /// Build the value of the `Link` header (RFC 8288) out of the pagination
+    /// links, so that clients can paginate without parsing the body
+    fn link_header(&self) -> Option<HeaderValue> {
+        let links = [
+            ("next", &self.links.next),
+            ("prev", &self.links.prev),
+            ("first", &self.links.first),
+            ("last", &self.links.last),
+        ];
+
+        let value = links
+            .into_iter()
+            .filter_map(|(rel, link)| link.as_ref().map(|link| format!("<{link}>; rel=\"{rel}\"")))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if value.is_empty() {
+            return None;
+        }
+
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+impl<T: Serialize> IntoResponse for PaginatedResponse<T> {
+    fn into_response(self) -> Response {
+        let link = self.link_header();
+        let mut response = Json(self).into_response();
+        if let Some(link) = link {
+            response.headers_mut().insert(LINK, link);
+        }
+        response
+    }
+}
+
+impl<T: JsonSchema> OperationOutput for PaginatedResponse<T> {
+    type Inner = Self;
+
+    fn operation_response(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Option<aide::openapi::Response> {
+        Json::<Self>::operation_response(ctx, operation)
+    }
+
+    fn inferred_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, aide::openapi::Response)> {
+        Json::<Self>::inferred_responses(ctx, operation)
+    }
+}
+
 /// A single resource, with its type, ID, attributes and related links
 #[derive(Serialize, JsonSchema)]
 struct SingleResource<T> {
@@ -177,7 +249,8 @@ struct SingleResource<T> {
     id: Ulid,
 
     /// The attributes of the resource
-    attributes: T,
+    #[schemars(with = "T")]
+    attributes: Attributes<T>,
 
     /// Related links
     links: SelfLinks,
@@ -188,6 +261,27 @@ struct SingleResource<T> {
     meta: SingleResourceMeta,
 }
 
+/// The attributes of a resource, optionally restricted to a sparse fieldset
+struct Attributes<T> {
+    value: T,
+    fieldset: Fieldset,
+}
+
+impl<T: Serialize> Serialize for Attributes<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Some(fields) = self.fieldset.fields() else {
+            return self.value.serialize(serializer);
+        };
+
+        let value = serde_json::to_value(&self.value).map_err(serde::ser::Error::custom)?;
+        let serde_json::Value::Object(mut attributes) = value else {
+            return value.serialize(serializer);
+        };
+        attributes.retain(|key, _| fields.contains(key));
+        attributes.serialize(serializer)
+    }
+}
+
 /// Metadata associated with a resource
 #[derive(Serialize, JsonSchema)]
 struct SingleResourceMeta {
@@ -215,7 +309,10 @@ impl<T: Resource> SingleResource<T> {
         Self {
             type_: T::KIND,
             id: resource.id(),
-            attributes: resource,
+            attributes: Attributes {
+                value: resource,
+                fieldset: Fieldset::default(),
+            },
             links: SelfLinks { self_ },
             meta: SingleResourceMeta { page: None },
         }
@@ -258,6 +355,13 @@ impl<T: Resource> SingleResponse<T> {
         let self_ = resource.path();
         Self::new(resource, self_)
     }
+
+    /// Restrict the attributes of the resource to the given sparse fieldset
+    #[must_use]
+    pub fn with_fieldset(mut self, fieldset: &Fieldset) -> Self {
+        self.data.attributes.fieldset = fieldset.clone();
+        self
+    }
 }
 
 /// A single error
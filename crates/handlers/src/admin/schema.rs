@@ -68,6 +68,7 @@ impl JsonSchema for Device {
             })),
 
             string: Some(Box::new(StringValidation {
+                max_length: Some(255),
                 pattern: Some(r"^[A-Za-z0-9._~!$&'()*+,;=:&/-]+$".into()),
                 ..StringValidation::default()
             })),
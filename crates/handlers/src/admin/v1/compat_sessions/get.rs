@@ -13,7 +13,7 @@ use crate::{
     admin::{
         call_context::CallContext,
         model::CompatSession,
-        params::UlidPathParam,
+        params::{CompatSessionFields, UlidPathParam},
         response::{ErrorResponse, SingleResponse},
     },
     impl_from_error_for_route,
@@ -66,6 +66,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
     id: UlidPathParam,
+    CompatSessionFields(fields): CompatSessionFields,
 ) -> Result<Json<SingleResponse<CompatSession>>, RouteError> {
     let session = repo
         .compat_session()
@@ -75,9 +76,9 @@ pub async fn handler(
 
     let sso_login = repo.compat_sso_login().find_for_session(&session).await?;
 
-    Ok(Json(SingleResponse::new_canonical(CompatSession::from((
-        session, sso_login,
-    )))))
+    let response = SingleResponse::new_canonical(CompatSession::from((session, sso_login)));
+
+    Ok(Json(response.with_fieldset(&fields)))
 }
 
 #[cfg(test)]
@@ -134,7 +135,9 @@ mod tests {
               "last_active_at": null,
               "last_active_ip": null,
               "finished_at": null,
-              "human_name": null
+              "human_name": null,
+              "authenticated_by": "unknown",
+              "authenticated_by_upstream_provider_id": null
             },
             "links": {
               "self": "/api/admin/v1/compat-sessions/01FSHN9AG0QHEHKX2JNQ2A2D07"
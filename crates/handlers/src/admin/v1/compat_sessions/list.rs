@@ -4,11 +4,12 @@
 // Please see LICENSE files in the repository root for full details.
 
 use aide::{OperationIo, transform::TransformOperation};
-use axum::{Json, response::IntoResponse};
+use axum::{Json, extract::State, response::IntoResponse};
 use axum_extra::extract::{Query, QueryRejection};
 use axum_macros::FromRequestParts;
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
+use mas_data_model::{BrowserSession, SiteConfig, User};
 use mas_storage::{Page, compat::CompatSessionFilter};
 use schemars::JsonSchema;
 use serde::Deserialize;
@@ -17,8 +18,9 @@ use ulid::Ulid;
 use crate::{
     admin::{
         call_context::CallContext,
+        export::{CsvExport, ListResponse, document_csv_export},
         model::{CompatSession, Resource},
-        params::{IncludeCount, Pagination},
+        params::{CompatSessionFields, ExportFormat, Format, IncludeCount, Pagination},
         response::{ErrorResponse, PaginatedResponse},
     },
     impl_from_error_for_route,
@@ -130,7 +132,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 Note that by default, all sessions, including finished ones are returned, with the oldest first.
 Use the `filter[status]` parameter to filter the sessions by their status and `page[last]` parameter to retrieve the last N sessions.")
         .tag("compat-session")
-        .response_with::<200, Json<PaginatedResponse<CompatSession>>, _>(|t| {
+        .response_with::<200, PaginatedResponse<CompatSession>, _>(|t| {
             let sessions = CompatSession::samples();
             let pagination = mas_storage::Pagination::first(sessions.len());
             let page = Page {
@@ -157,17 +159,46 @@ Use the `filter[status]` parameter to filter the sessions by their status and `p
             let response = ErrorResponse::from_error(&RouteError::UserNotFound(Ulid::nil()));
             t.description("User was not found").example(response)
         })
+        .with(document_csv_export::<CompatSession>)
+}
+
+/// Build the repository filter out of the parameters
+fn build_filter<'a>(
+    user: Option<&'a User>,
+    user_session: Option<&'a BrowserSession>,
+    status: Option<CompatSessionStatus>,
+) -> CompatSessionFilter<'a> {
+    let filter = CompatSessionFilter::default();
+
+    let filter = match user {
+        Some(user) => filter.for_user(user),
+        None => filter,
+    };
+
+    let filter = match user_session {
+        Some(user_session) => filter.for_browser_session(user_session),
+        None => filter,
+    };
+
+    match status {
+        Some(CompatSessionStatus::Active) => filter.active_only(),
+        Some(CompatSessionStatus::Finished) => filter.finished_only(),
+        None => filter,
+    }
 }
 
 #[tracing::instrument(name = "handler.admin.v1.compat_sessions.list", skip_all)]
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
+    State(site_config): State<SiteConfig>,
     Pagination(pagination, include_count): Pagination,
+    Format(format): Format,
+    CompatSessionFields(fields): CompatSessionFields,
     params: FilterParams,
-) -> Result<Json<PaginatedResponse<CompatSession>>, RouteError> {
+) -> Result<ListResponse<CompatSession>, RouteError> {
     let base = format!("{path}{params}", path = CompatSession::PATH);
     let base = include_count.add_to_base(&base);
-    let filter = CompatSessionFilter::default();
+    let base = fields.add_to_base(&base);
 
     // Load the user from the filter
     let user = if let Some(user_id) = params.user {
@@ -182,11 +213,6 @@ pub async fn handler(
         None
     };
 
-    let filter = match &user {
-        Some(user) => filter.for_user(user),
-        None => filter,
-    };
-
     let user_session = if let Some(user_session_id) = params.user_session {
         let user_session = repo
             .browser_session()
@@ -199,16 +225,31 @@ pub async fn handler(
         None
     };
 
-    let filter = match &user_session {
-        Some(user_session) => filter.for_browser_session(user_session),
-        None => filter,
-    };
+    if format == ExportFormat::Csv {
+        let status = params.status;
+        let total = repo
+            .compat_session()
+            .count(build_filter(user.as_ref(), user_session.as_ref(), status))
+            .await?;
+        let export = CsvExport::new(
+            repo,
+            "compat-sessions.csv",
+            site_config.admin_api_csv_export_limit,
+            total,
+            move |repo, pagination| {
+                let user = user.clone();
+                let user_session = user_session.clone();
+                Box::pin(async move {
+                    let filter = build_filter(user.as_ref(), user_session.as_ref(), status);
+                    let page = repo.compat_session().list(filter, pagination).await?;
+                    Ok(page.map(CompatSession::from))
+                })
+            },
+        );
+        return Ok(ListResponse::Csv(export));
+    }
 
-    let filter = match params.status {
-        Some(CompatSessionStatus::Active) => filter.active_only(),
-        Some(CompatSessionStatus::Finished) => filter.finished_only(),
-        None => filter,
-    };
+    let filter = build_filter(user.as_ref(), user_session.as_ref(), params.status);
 
     let response = match include_count {
         IncludeCount::True => {
@@ -234,7 +275,7 @@ pub async fn handler(
         }
     };
 
-    Ok(Json(response))
+    Ok(ListResponse::Json(response.with_fieldset(&fields)))
 }
 
 #[cfg(test)]
@@ -315,7 +356,9 @@ mod tests {
                 "last_active_at": null,
                 "last_active_ip": null,
                 "finished_at": null,
-                "human_name": null
+                "human_name": null,
+                "authenticated_by": "unknown",
+                "authenticated_by_upstream_provider_id": null
               },
               "links": {
                 "self": "/api/admin/v1/compat-sessions/01FSHNB530AAPR7PEV8KNBZD5Y"
@@ -339,7 +382,9 @@ mod tests {
                 "last_active_at": null,
                 "last_active_ip": null,
                 "finished_at": "2022-01-16T14:43:00Z",
-                "human_name": null
+                "human_name": null,
+                "authenticated_by": "unknown",
+                "authenticated_by_upstream_provider_id": null
               },
               "links": {
                 "self": "/api/admin/v1/compat-sessions/01FSHNCZP0PPF7X0EVMJNECPZW"
@@ -388,7 +433,9 @@ mod tests {
                 "last_active_at": null,
                 "last_active_ip": null,
                 "finished_at": null,
-                "human_name": null
+                "human_name": null,
+                "authenticated_by": "unknown",
+                "authenticated_by_upstream_provider_id": null
               },
               "links": {
                 "self": "/api/admin/v1/compat-sessions/01FSHNB530AAPR7PEV8KNBZD5Y"
@@ -434,7 +481,9 @@ mod tests {
                 "last_active_at": null,
                 "last_active_ip": null,
                 "finished_at": null,
-                "human_name": null
+                "human_name": null,
+                "authenticated_by": "unknown",
+                "authenticated_by_upstream_provider_id": null
               },
               "links": {
                 "self": "/api/admin/v1/compat-sessions/01FSHNB530AAPR7PEV8KNBZD5Y"
@@ -480,7 +529,9 @@ mod tests {
                 "last_active_at": null,
                 "last_active_ip": null,
                 "finished_at": "2022-01-16T14:43:00Z",
-                "human_name": null
+                "human_name": null,
+                "authenticated_by": "unknown",
+                "authenticated_by_upstream_provider_id": null
               },
               "links": {
                 "self": "/api/admin/v1/compat-sessions/01FSHNCZP0PPF7X0EVMJNECPZW"
@@ -523,7 +574,9 @@ mod tests {
                 "last_active_at": null,
                 "last_active_ip": null,
                 "finished_at": null,
-                "human_name": null
+                "human_name": null,
+                "authenticated_by": "unknown",
+                "authenticated_by_upstream_provider_id": null
               },
               "links": {
                 "self": "/api/admin/v1/compat-sessions/01FSHNB530AAPR7PEV8KNBZD5Y"
@@ -547,7 +600,9 @@ mod tests {
                 "last_active_at": null,
                 "last_active_ip": null,
                 "finished_at": "2022-01-16T14:43:00Z",
-                "human_name": null
+                "human_name": null,
+                "authenticated_by": "unknown",
+                "authenticated_by_upstream_provider_id": null
               },
               "links": {
                 "self": "/api/admin/v1/compat-sessions/01FSHNCZP0PPF7X0EVMJNECPZW"
@@ -611,7 +666,9 @@ mod tests {
                 "last_active_at": null,
                 "last_active_ip": null,
                 "finished_at": null,
-                "human_name": null
+                "human_name": null,
+                "authenticated_by": "unknown",
+                "authenticated_by_upstream_provider_id": null
               },
               "links": {
                 "self": "/api/admin/v1/compat-sessions/01FSHNB530AAPR7PEV8KNBZD5Y"
@@ -650,4 +707,57 @@ mod tests {
         }
         "#);
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_compat_session_export_csv(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        // Provision a user with a session which has a name needing to be escaped
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let session = repo
+            .compat_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &alice,
+                device.clone(),
+                None,
+                false,
+                Some("Alice's \"work\" laptop, v2".to_owned()),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get(format!(
+            "/api/admin/v1/compat-sessions?format=csv&filter[user]={}",
+            alice.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert_eq!(
+            response.body(),
+            &format!(
+                "id,user_id,device_id,user_session_id,redirect_uri,created_at,user_agent,\
+                 last_active_at,last_active_ip,finished_at,human_name,authenticated_by,\
+                 authenticated_by_upstream_provider_id\n\
+                 {},{},{},,,2022-01-16T14:40:00Z,,,,,\"Alice's \"\"work\"\" laptop, v2\",\
+                 unknown,\n",
+                session.id,
+                alice.id,
+                device.as_str(),
+            )
+        );
+    }
 }
@@ -0,0 +1,389 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use axum_extra::extract::{Query, QueryRejection};
+use axum_macros::FromRequestParts;
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_storage::{Page, compat::CompatSsoLoginFilter};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::{CompatSsoLogin, Resource},
+        params::{IncludeCount, Pagination},
+        response::{ErrorResponse, PaginatedResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum CompatSsoLoginStatus {
+    Pending,
+    Fulfilled,
+    Exchanged,
+}
+
+impl std::fmt::Display for CompatSsoLoginStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pending => write!(f, "pending"),
+            Self::Fulfilled => write!(f, "fulfilled"),
+            Self::Exchanged => write!(f, "exchanged"),
+        }
+    }
+}
+
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[serde(rename = "CompatSsoLoginFilter")]
+#[aide(input_with = "Query<FilterParams>")]
+#[from_request(via(Query), rejection(RouteError))]
+pub struct FilterParams {
+    /// Retrieve the logins fulfilled by the given user
+    #[serde(rename = "filter[user]")]
+    #[schemars(with = "Option<crate::admin::schema::Ulid>")]
+    user: Option<Ulid>,
+
+    /// Retrieve the logins with the given status
+    ///
+    /// Defaults to retrieve all logins.
+    ///
+    /// * `pending`: Only retrieve logins waiting for the user to authenticate
+    ///
+    /// * `fulfilled`: Only retrieve logins whose login token was not yet
+    ///   exchanged by the client
+    ///
+    /// * `exchanged`: Only retrieve logins exchanged by the client
+    #[serde(rename = "filter[status]")]
+    status: Option<CompatSsoLoginStatus>,
+}
+
+impl std::fmt::Display for FilterParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sep = '?';
+
+        if let Some(user) = self.user {
+            write!(f, "{sep}filter[user]={user}")?;
+            sep = '&';
+        }
+
+        if let Some(status) = self.status {
+            write!(f, "{sep}filter[status]={status}")?;
+            sep = '&';
+        }
+
+        let _ = sep;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    UserNotFound(Ulid),
+
+    #[error("Invalid filter parameters")]
+    InvalidFilter(#[from] QueryRejection),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UserNotFound(_) => StatusCode::NOT_FOUND,
+            Self::InvalidFilter(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("listCompatSsoLogins")
+        .summary("List compatibility SSO logins")
+        .description(
+            "Retrieve a list of the SSO logins started by legacy clients through the \
+`m.login.sso` flow, with the oldest first.
+Pending logins are not associated with any user yet, so they are never returned when filtering \
+by user.",
+        )
+        .tag("compat-sso-login")
+        .response_with::<200, PaginatedResponse<CompatSsoLogin>, _>(|t| {
+            let logins = CompatSsoLogin::samples();
+            let pagination = mas_storage::Pagination::first(logins.len());
+            let page = Page {
+                edges: logins
+                    .into_iter()
+                    .map(|node| mas_storage::pagination::Edge {
+                        cursor: node.id(),
+                        node,
+                    })
+                    .collect(),
+                has_next_page: true,
+                has_previous_page: false,
+            };
+
+            t.description("Paginated response of compatibility SSO logins")
+                .example(PaginatedResponse::for_page(
+                    page,
+                    pagination,
+                    Some(42),
+                    CompatSsoLogin::PATH,
+                ))
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::UserNotFound(Ulid::nil()));
+            t.description("User was not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.compat_sso_logins.list", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    Pagination(pagination, include_count): Pagination,
+    params: FilterParams,
+) -> Result<PaginatedResponse<CompatSsoLogin>, RouteError> {
+    let base = format!("{path}{params}", path = CompatSsoLogin::PATH);
+    let base = include_count.add_to_base(&base);
+
+    // Load the user from the filter
+    let user = if let Some(user_id) = params.user {
+        let user = repo
+            .user()
+            .lookup(user_id)
+            .await?
+            .ok_or(RouteError::UserNotFound(user_id))?;
+
+        Some(user)
+    } else {
+        None
+    };
+
+    let filter = CompatSsoLoginFilter::new();
+
+    let filter = match &user {
+        Some(user) => filter.for_user(user),
+        None => filter,
+    };
+
+    let filter = match params.status {
+        Some(CompatSsoLoginStatus::Pending) => filter.pending_only(),
+        Some(CompatSsoLoginStatus::Fulfilled) => filter.fulfilled_only(),
+        Some(CompatSsoLoginStatus::Exchanged) => filter.exchanged_only(),
+        None => filter,
+    };
+
+    let response = match include_count {
+        IncludeCount::True => {
+            let page = repo
+                .compat_sso_login()
+                .list(filter, pagination)
+                .await?
+                .map(CompatSsoLogin::from);
+            let count = repo.compat_sso_login().count(filter).await?;
+            PaginatedResponse::for_page(page, pagination, Some(count), &base)
+        }
+        IncludeCount::False => {
+            let page = repo
+                .compat_sso_login()
+                .list(filter, pagination)
+                .await?
+                .map(CompatSsoLogin::from);
+            PaginatedResponse::for_page(page, pagination, None, &base)
+        }
+        IncludeCount::Only => {
+            let count = repo.compat_sso_login().count(filter).await?;
+            PaginatedResponse::for_count_only(count, &base)
+        }
+    };
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::Device;
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &alice, None)
+            .await
+            .unwrap();
+        let login = repo
+            .compat_sso_login()
+            .add(
+                &mut rng,
+                &state.clock,
+                "login-token".to_owned(),
+                "https://example.com/callback".parse().unwrap(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // While pending, the login isn't associated with any user
+        let request = Request::get("/api/admin/v1/compat-sso-logins?filter[status]=pending")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["type"], "compat-sso-login");
+        assert_eq!(body["data"][0]["id"], login.id.to_string());
+        assert_eq!(body["data"][0]["attributes"]["state"], "pending");
+
+        let request = Request::get(format!(
+            "/api/admin/v1/compat-sso-logins?filter[user]={}",
+            alice.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 0);
+
+        // Fulfill the login with alice's browser session
+        let mut repo = state.repository().await.unwrap();
+        let login = repo
+            .compat_sso_login()
+            .fulfill(&state.clock, login, &browser_session)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get(format!(
+            "/api/admin/v1/compat-sso-logins?filter[user]={}&filter[status]=fulfilled",
+            alice.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        let attributes = &body["data"][0]["attributes"];
+        assert_eq!(attributes["state"], "fulfilled");
+        assert!(attributes["fulfilled_at"].is_string());
+        assert_eq!(attributes["exchanged_at"], serde_json::Value::Null);
+        assert_eq!(
+            attributes["user_session_id"],
+            browser_session.id.to_string()
+        );
+
+        let request = Request::get(format!(
+            "/api/admin/v1/compat-sso-logins?filter[user]={}",
+            bob.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 0);
+
+        // Exchange the login for a compat session
+        let mut repo = state.repository().await.unwrap();
+        let device = Device::generate(&mut rng);
+        let compat_session = repo
+            .compat_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &alice,
+                device,
+                Some(&browser_session),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.compat_sso_login()
+            .exchange(&state.clock, login, &compat_session)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get("/api/admin/v1/compat-sso-logins?filter[status]=fulfilled")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 0);
+
+        let request = Request::get(format!(
+            "/api/admin/v1/compat-sso-logins?filter[user]={}&filter[status]=exchanged",
+            alice.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        let attributes = &body["data"][0]["attributes"];
+        assert_eq!(attributes["state"], "exchanged");
+        assert!(attributes["exchanged_at"].is_string());
+        assert_eq!(
+            attributes["compat_session_id"],
+            compat_session.id.to_string()
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_user_not_found(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::get(format!(
+            "/api/admin/v1/compat-sso-logins?filter[user]={}",
+            Ulid::nil()
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,402 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use axum_extra::extract::{Query, QueryRejection};
+use axum_macros::FromRequestParts;
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_storage::{
+    Page,
+    oauth2::{OAuth2DeviceCodeGrantFilter, OAuth2DeviceCodeGrantState},
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::{DeviceCodeGrant, Resource, User},
+        params::{IncludeCount, Pagination, UlidPathParam},
+        response::{ErrorResponse, PaginatedResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum DeviceCodeGrantStatus {
+    Fulfilled,
+    Rejected,
+    Exchanged,
+}
+
+impl std::fmt::Display for DeviceCodeGrantStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fulfilled => write!(f, "fulfilled"),
+            Self::Rejected => write!(f, "rejected"),
+            Self::Exchanged => write!(f, "exchanged"),
+        }
+    }
+}
+
+impl From<DeviceCodeGrantStatus> for OAuth2DeviceCodeGrantState {
+    fn from(status: DeviceCodeGrantStatus) -> Self {
+        match status {
+            DeviceCodeGrantStatus::Fulfilled => Self::Fulfilled,
+            DeviceCodeGrantStatus::Rejected => Self::Rejected,
+            DeviceCodeGrantStatus::Exchanged => Self::Exchanged,
+        }
+    }
+}
+
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[serde(rename = "DeviceCodeGrantFilter")]
+#[aide(input_with = "Query<FilterParams>")]
+#[from_request(via(Query), rejection(RouteError))]
+pub struct FilterParams {
+    /// Retrieve the grants with the given status
+    ///
+    /// Defaults to retrieve all grants.
+    ///
+    /// * `fulfilled`: Only retrieve grants approved by the user, but not yet
+    ///   exchanged by the device
+    ///
+    /// * `rejected`: Only retrieve grants rejected by the user
+    ///
+    /// * `exchanged`: Only retrieve grants exchanged by the device
+    #[serde(rename = "filter[status]")]
+    status: Option<DeviceCodeGrantStatus>,
+
+    /// Retrieve grants that are (or are not) expired
+    #[serde(rename = "filter[expired]")]
+    expired: Option<bool>,
+}
+
+impl std::fmt::Display for FilterParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut sep = '?';
+
+        if let Some(status) = self.status {
+            write!(f, "{sep}filter[status]={status}")?;
+            sep = '&';
+        }
+
+        if let Some(expired) = self.expired {
+            write!(f, "{sep}filter[expired]={expired}")?;
+            sep = '&';
+        }
+
+        let _ = sep;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    UserNotFound(Ulid),
+
+    #[error("Invalid filter parameters")]
+    InvalidFilter(#[from] QueryRejection),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UserNotFound(_) => StatusCode::NOT_FOUND,
+            Self::InvalidFilter(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("listUserDeviceCodeGrants")
+        .summary("List the OAuth 2.0 device code grants of a user")
+        .description(
+            "Lists the device code grants approved or rejected by a user. Grants still waiting \
+for a user to enter their code are not associated with any user yet, so they are not listed.",
+        )
+        .tag("device-code-grant")
+        .response_with::<200, PaginatedResponse<DeviceCodeGrant>, _>(|t| {
+            let grants = DeviceCodeGrant::samples();
+            let pagination = mas_storage::Pagination::first(grants.len());
+            let page = Page {
+                edges: grants
+                    .into_iter()
+                    .map(|node| mas_storage::pagination::Edge {
+                        cursor: node.id(),
+                        node,
+                    })
+                    .collect(),
+                has_next_page: true,
+                has_previous_page: false,
+            };
+
+            t.description("Paginated response of device code grants")
+                .example(PaginatedResponse::for_page(
+                    page,
+                    pagination,
+                    Some(42),
+                    "/api/admin/v1/users/01040G2081040G2081040G2081/device-code-grants",
+                ))
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::UserNotFound(Ulid::nil()));
+            t.description("User was not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.device_code_grants.list", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext,
+    id: UlidPathParam,
+    Pagination(pagination, include_count): Pagination,
+    params: FilterParams,
+) -> Result<PaginatedResponse<DeviceCodeGrant>, RouteError> {
+    let id = *id;
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::UserNotFound(id))?;
+
+    let base = format!("{path}/{id}/device-code-grants{params}", path = User::PATH);
+    let base = include_count.add_to_base(&base);
+    let now = clock.now();
+    let mut filter = OAuth2DeviceCodeGrantFilter::new(now).for_user(&user);
+
+    if let Some(status) = params.status {
+        filter = filter.with_state(status.into());
+    }
+
+    if let Some(expired) = params.expired {
+        filter = filter.with_expired(expired);
+    }
+
+    let response = match include_count {
+        IncludeCount::True => {
+            let page = repo
+                .oauth2_device_code_grant()
+                .list(filter, pagination)
+                .await?
+                .map(|grant| DeviceCodeGrant::new(grant, now));
+            let count = repo.oauth2_device_code_grant().count(filter).await?;
+            PaginatedResponse::for_page(page, pagination, Some(count), &base)
+        }
+        IncludeCount::False => {
+            let page = repo
+                .oauth2_device_code_grant()
+                .list(filter, pagination)
+                .await?
+                .map(|grant| DeviceCodeGrant::new(grant, now));
+            PaginatedResponse::for_page(page, pagination, None, &base)
+        }
+        IncludeCount::Only => {
+            let count = repo.oauth2_device_code_grant().count(filter).await?;
+            PaginatedResponse::for_count_only(count, &base)
+        }
+    };
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_router::SimpleRoute;
+    use oauth2_types::{
+        registration::ClientRegistrationResponse, requests::DeviceAuthorizationResponse,
+    };
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        // Provision a client allowed to use the device code grant
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "token_endpoint_auth_method": "none",
+                "grant_types": ["urn:ietf:params:oauth:grant-type:device_code"],
+                "response_types": [],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let response: ClientRegistrationResponse = response.json();
+        let client_id = response.client_id;
+
+        // Start a device code grant
+        let request = Request::post(mas_router::OAuth2DeviceAuthorizationEndpoint::PATH).form(
+            serde_json::json!({
+                "client_id": client_id,
+                "scope": "openid",
+            }),
+        );
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let device_grant: DeviceAuthorizationResponse = response.json();
+
+        // Provision a user, a browser session, and fulfill the grant with it
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+        let grant = repo
+            .oauth2_device_code_grant()
+            .find_by_user_code(&device_grant.user_code)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // While pending, the grant is not associated with the user
+        repo.save().await.unwrap();
+        let request = Request::get(format!(
+            "/api/admin/v1/users/{}/device-code-grants",
+            user.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 0);
+
+        let mut repo = state.repository().await.unwrap();
+        repo.oauth2_device_code_grant()
+            .fulfill(&state.clock, grant, &browser_session)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Once fulfilled, it shows up as waiting for the device
+        let request = Request::get(format!(
+            "/api/admin/v1/users/{}/device-code-grants?filter[status]=fulfilled",
+            user.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        let attributes = &body["data"][0]["attributes"];
+        assert_eq!(body["data"][0]["type"], "device-code-grant");
+        assert_eq!(attributes["state"], "fulfilled");
+        assert_eq!(attributes["expired"], false);
+        assert_eq!(attributes["scope"], "openid");
+        assert_eq!(
+            attributes["user_session_id"],
+            browser_session.id.to_string()
+        );
+        assert_eq!(attributes["exchanged_at"], serde_json::Value::Null);
+
+        // The device exchanges the grant
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "urn:ietf:params:oauth:grant-type:device_code",
+                "device_code": device_grant.device_code,
+                "client_id": client_id,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let request = Request::get(format!(
+            "/api/admin/v1/users/{}/device-code-grants",
+            user.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        let attributes = &body["data"][0]["attributes"];
+        assert_eq!(attributes["state"], "exchanged");
+        assert!(attributes["oauth2_session_id"].is_string());
+
+        // It can be looked up by its ID
+        let grant_id = body["data"][0]["id"].as_str().unwrap();
+        let request = Request::get(format!("/api/admin/v1/device-code-grants/{grant_id}"))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"]["state"], "exchanged");
+
+        // Filter on expiration
+        let request = Request::get(format!(
+            "/api/admin/v1/users/{}/device-code-grants?filter[expired]=true",
+            user.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 0);
+
+        state.clock.advance(Duration::try_hours(1).unwrap());
+
+        let request = Request::get(format!(
+            "/api/admin/v1/users/{}/device-code-grants?filter[expired]=true",
+            user.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["attributes"]["expired"], true);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_user_not_found(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::get(format!(
+            "/api/admin/v1/users/{}/device-code-grants",
+            Ulid::nil()
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
@@ -11,21 +11,30 @@ use aide::axum::{
     routing::{get_with, post_with},
 };
 use axum::extract::{FromRef, FromRequestParts};
-use mas_data_model::{AppVersion, BoxRng, SiteConfig};
+use mas_data_model::{AppBuildInfo, AppVersion, BoxRng, SiteConfig, TchapFeatures};
 use mas_matrix::HomeserverConnection;
 use mas_policy::PolicyFactory;
+use mas_templates::Templates;
 
 use super::call_context::CallContext;
-use crate::passwords::PasswordManager;
+use crate::{Limiter, passwords::PasswordManager, upstream_oauth2::cache::MetadataCache};
 
 mod compat_sessions;
+mod compat_sso_logins;
+mod device_code_grants;
 mod oauth2_sessions;
 mod personal_sessions;
 mod policy_data;
-mod site_config;
+mod provisioning_reports;
+mod queue_jobs;
+mod reports;
+mod server_info;
+pub(super) mod site_config;
+mod syn2mas_runs;
 mod upstream_oauth_links;
 mod upstream_oauth_providers;
 mod user_emails;
+mod user_notes;
 mod user_registration_tokens;
 mod user_sessions;
 mod users;
@@ -38,7 +47,13 @@ where
     PasswordManager: FromRef<S>,
     SiteConfig: FromRef<S>,
     AppVersion: FromRef<S>,
+    AppBuildInfo: FromRef<S>,
+    TchapFeatures: FromRef<S>,
     Arc<PolicyFactory>: FromRef<S>,
+    MetadataCache: FromRef<S>,
+    reqwest::Client: FromRef<S>,
+    Limiter: FromRef<S>,
+    Templates: FromRef<S>,
     BoxRng: FromRequestParts<S>,
     CallContext: FromRequestParts<S>,
 {
@@ -51,6 +66,10 @@ where
             "/version",
             get_with(self::version::handler, self::version::doc),
         )
+        .api_route(
+            "/server-info",
+            get_with(self::server_info::handler, self::server_info::doc),
+        )
         .api_route(
             "/compat-sessions",
             get_with(self::compat_sessions::list, self::compat_sessions::list_doc),
@@ -66,6 +85,20 @@ where
                 self::compat_sessions::finish_doc,
             ),
         )
+        .api_route(
+            "/compat-sso-logins",
+            get_with(
+                self::compat_sso_logins::list,
+                self::compat_sso_logins::list_doc,
+            ),
+        )
+        .api_route(
+            "/compat-sso-logins/{id}",
+            get_with(
+                self::compat_sso_logins::get,
+                self::compat_sso_logins::get_doc,
+            ),
+        )
         .api_route(
             "/oauth2-sessions",
             get_with(self::oauth2_sessions::list, self::oauth2_sessions::list_doc),
@@ -81,6 +114,13 @@ where
                 self::oauth2_sessions::finish_doc,
             ),
         )
+        .api_route(
+            "/device-code-grants/{id}",
+            get_with(
+                self::device_code_grants::get,
+                self::device_code_grants::get_doc,
+            ),
+        )
         .api_route(
             "/personal-sessions",
             get_with(
@@ -128,6 +168,39 @@ where
             "/policy-data/{id}",
             get_with(self::policy_data::get, self::policy_data::get_doc),
         )
+        .api_route(
+            "/provisioning-reports",
+            post_with(
+                self::provisioning_reports::add,
+                self::provisioning_reports::add_doc,
+            ),
+        )
+        .api_route(
+            "/provisioning-reports/{id}",
+            get_with(
+                self::provisioning_reports::get,
+                self::provisioning_reports::get_doc,
+            ),
+        )
+        .api_route(
+            "/reports/registrations",
+            get_with(
+                self::reports::registrations,
+                self::reports::registrations_doc,
+            ),
+        )
+        .api_route(
+            "/migrations",
+            get_with(self::syn2mas_runs::list, self::syn2mas_runs::list_doc),
+        )
+        .api_route(
+            "/jobs/{id}",
+            get_with(self::queue_jobs::get, self::queue_jobs::get_doc),
+        )
+        .api_route(
+            "/jobs/{id}/retry",
+            post_with(self::queue_jobs::retry, self::queue_jobs::retry_doc),
+        )
         .api_route(
             "/users",
             get_with(self::users::list, self::users::list_doc)
@@ -135,7 +208,8 @@ where
         )
         .api_route(
             "/users/{id}",
-            get_with(self::users::get, self::users::get_doc),
+            get_with(self::users::get, self::users::get_doc)
+                .delete_with(self::users::purge, self::users::purge_doc),
         )
         .api_route(
             "/users/{id}/set-password",
@@ -145,10 +219,40 @@ where
             "/users/by-username/{username}",
             get_with(self::users::by_username, self::users::by_username_doc),
         )
+        .api_route(
+            "/users/by-mxid/{mxid}",
+            get_with(self::users::by_mxid, self::users::by_mxid_doc),
+        )
+        .api_route(
+            "/users/by-email/{email}",
+            get_with(self::users::by_email, self::users::by_email_doc),
+        )
+        .api_route(
+            "/users/by-external-id/{external_id}",
+            get_with(self::users::by_external_id, self::users::by_external_id_doc),
+        )
         .api_route(
             "/users/{id}/set-admin",
             post_with(self::users::set_admin, self::users::set_admin_doc),
         )
+        .api_route(
+            "/users/{id}/set-email",
+            post_with(self::users::set_email, self::users::set_email_doc),
+        )
+        .api_route(
+            "/users/{id}/set-external-id",
+            post_with(
+                self::users::set_external_id,
+                self::users::set_external_id_doc,
+            ),
+        )
+        .api_route(
+            "/users/{id}/set-max-sessions",
+            post_with(
+                self::users::set_max_sessions,
+                self::users::set_max_sessions_doc,
+            ),
+        )
         .api_route(
             "/users/{id}/deactivate",
             post_with(self::users::deactivate, self::users::deactivate_doc),
@@ -165,6 +269,49 @@ where
             "/users/{id}/unlock",
             post_with(self::users::unlock, self::users::unlock_doc),
         )
+        .api_route(
+            "/users/{id}/delete",
+            post_with(self::users::delete, self::users::delete_doc),
+        )
+        .api_route(
+            "/users/{id}/undelete",
+            post_with(self::users::undelete, self::users::undelete_doc),
+        )
+        .api_route(
+            "/users/{id}/revoke-all-sessions",
+            post_with(
+                self::users::revoke_all_sessions,
+                self::users::revoke_all_sessions_doc,
+            ),
+        )
+        .api_route(
+            "/users/{id}/purge-sessions",
+            post_with(self::users::purge_sessions, self::users::purge_sessions_doc),
+        )
+        .api_route(
+            "/users/{id}/merge-into/{target_id}",
+            post_with(self::users::merge, self::users::merge_doc),
+        )
+        .api_route(
+            "/users/{id}/permissions",
+            get_with(self::users::permissions, self::users::permissions_doc),
+        )
+        .api_route(
+            "/users/{id}/notes",
+            get_with(self::user_notes::list, self::user_notes::list_doc)
+                .post_with(self::user_notes::add, self::user_notes::add_doc),
+        )
+        .api_route(
+            "/users/{id}/device-code-grants",
+            get_with(
+                self::device_code_grants::list,
+                self::device_code_grants::list_doc,
+            ),
+        )
+        .api_route(
+            "/users/{id}/jobs",
+            get_with(self::queue_jobs::list, self::queue_jobs::list_doc),
+        )
         //:tchap:
         .api_route(
             "/users/{id}/kill-sessions",
@@ -181,6 +328,11 @@ where
             get_with(self::user_emails::get, self::user_emails::get_doc)
                 .delete_with(self::user_emails::delete, self::user_emails::delete_doc),
         )
+        .api_route(
+            "/user-notes/{id}",
+            get_with(self::user_notes::get, self::user_notes::get_doc)
+                .delete_with(self::user_notes::delete, self::user_notes::delete_doc),
+        )
         .api_route(
             "/user-sessions",
             get_with(self::user_sessions::list, self::user_sessions::list_doc),
@@ -265,4 +417,11 @@ where
                 self::upstream_oauth_providers::get_doc,
             ),
         )
+        .api_route(
+            "/upstream-oauth-providers/{id}/refresh-metadata",
+            post_with(
+                self::upstream_oauth_providers::refresh_metadata,
+                self::upstream_oauth_providers::refresh_metadata_doc,
+            ),
+        )
 }
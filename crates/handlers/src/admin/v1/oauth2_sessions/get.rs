@@ -14,7 +14,7 @@ use crate::{
     admin::{
         call_context::CallContext,
         model::OAuth2Session,
-        params::UlidPathParam,
+        params::{OAuth2SessionFields, UlidPathParam},
         response::{ErrorResponse, SingleResponse},
     },
     impl_from_error_for_route,
@@ -66,6 +66,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
     id: UlidPathParam,
+    OAuth2SessionFields(fields): OAuth2SessionFields,
 ) -> Result<Json<SingleResponse<OAuth2Session>>, RouteError> {
     let session = repo
         .oauth2_session()
@@ -73,9 +74,9 @@ pub async fn handler(
         .await?
         .ok_or(RouteError::NotFound(*id))?;
 
-    Ok(Json(SingleResponse::new_canonical(OAuth2Session::from(
-        session,
-    ))))
+    let response = SingleResponse::new_canonical(OAuth2Session::from(session));
+
+    Ok(Json(response.with_fieldset(&fields)))
 }
 
 #[cfg(test)]
@@ -125,7 +126,9 @@ mod tests {
               "user_agent": null,
               "last_active_at": null,
               "last_active_ip": null,
-              "human_name": null
+              "human_name": null,
+              "authenticated_by": "unknown",
+              "authenticated_by_upstream_provider_id": null
             },
             "links": {
               "self": "/api/admin/v1/oauth2-sessions/01FSHN9AG0MKGTBNZ16RDR3PVY"
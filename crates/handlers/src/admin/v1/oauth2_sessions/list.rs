@@ -22,7 +22,7 @@ use crate::{
     admin::{
         call_context::CallContext,
         model::{OAuth2Session, Resource},
-        params::{IncludeCount, Pagination},
+        params::{IncludeCount, OAuth2SessionFields, Pagination},
         response::{ErrorResponse, PaginatedResponse},
     },
     impl_from_error_for_route,
@@ -185,7 +185,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 Note that by default, all sessions, including finished ones are returned, with the oldest first.
 Use the `filter[status]` parameter to filter the sessions by their status and `page[last]` parameter to retrieve the last N sessions.")
         .tag("oauth2-session")
-        .response_with::<200, Json<PaginatedResponse<OAuth2Session>>, _>(|t| {
+        .response_with::<200, PaginatedResponse<OAuth2Session>, _>(|t| {
             let sessions = OAuth2Session::samples();
             let pagination = mas_storage::Pagination::first(sessions.len());
             let page = Page {
@@ -222,10 +222,12 @@ Use the `filter[status]` parameter to filter the sessions by their status and `p
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
     Pagination(pagination, include_count): Pagination,
+    OAuth2SessionFields(fields): OAuth2SessionFields,
     params: FilterParams,
-) -> Result<Json<PaginatedResponse<OAuth2Session>>, RouteError> {
+) -> Result<PaginatedResponse<OAuth2Session>, RouteError> {
     let base = format!("{path}{params}", path = OAuth2Session::PATH);
     let base = include_count.add_to_base(&base);
+    let base = fields.add_to_base(&base);
     let filter = OAuth2SessionFilter::default();
 
     // Load the user from the filter
@@ -328,7 +330,7 @@ pub async fn handler(
         }
     };
 
-    Ok(Json(response))
+    Ok(response.with_fieldset(&fields))
 }
 
 #[cfg(test)]
@@ -370,7 +372,9 @@ mod tests {
                 "user_agent": null,
                 "last_active_at": null,
                 "last_active_ip": null,
-                "human_name": null
+                "human_name": null,
+                "authenticated_by": "unknown",
+                "authenticated_by_upstream_provider_id": null
               },
               "links": {
                 "self": "/api/admin/v1/oauth2-sessions/01FSHN9AG0MKGTBNZ16RDR3PVY"
@@ -413,7 +417,9 @@ mod tests {
                 "user_agent": null,
                 "last_active_at": null,
                 "last_active_ip": null,
-                "human_name": null
+                "human_name": null,
+                "authenticated_by": "unknown",
+                "authenticated_by_upstream_provider_id": null
               },
               "links": {
                 "self": "/api/admin/v1/oauth2-sessions/01FSHN9AG0MKGTBNZ16RDR3PVY"
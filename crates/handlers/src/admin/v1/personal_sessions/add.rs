@@ -86,7 +86,11 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .summary("Create a new personal session with personal access token")
         .tag("personal-session")
         .response_with::<201, Json<SingleResponse<PersonalSession>>, _>(|t| {
+            let [sample, ..] = PersonalSession::samples();
+            let sample = sample.with_token("mpt_FM44zJN5qePGMLvvMXC4Ds1A3lCWc6_bJ9Wj1".to_owned());
+            let response = SingleResponse::new_canonical(sample);
             t.description("Personal session and personal access token were created")
+                .example(response)
         })
         .response_with::<400, RouteError, _>(|t| {
             let response = ErrorResponse::from_error(&RouteError::InvalidScope);
@@ -124,6 +128,14 @@ pub async fn handler(
 
     let scope: Scope = params.scope.parse().map_err(|_| RouteError::InvalidScope)?;
 
+    // Reject device scopes with a device ID the homeserver wouldn't accept
+    if scope
+        .iter()
+        .any(|token| Device::parse_scope_token(token).is_err())
+    {
+        return Err(RouteError::InvalidScope);
+    }
+
     // Create the personal session
     let session = repo
         .personal_session()
@@ -308,4 +320,34 @@ mod tests {
         let response = state.request(request).await;
         response.assert_status(StatusCode::BAD_REQUEST);
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_create_personal_session_invalid_device_id(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let mut rng = state.rng();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        let request_body = serde_json::json!({
+            "actor_user_id": user.id,
+            "human_name": "Test Session",
+            "scope": "urn:matrix:client:api:* urn:matrix:client:device:AA#BB",
+        });
+
+        let request = Request::post("/api/admin/v1/personal-sessions")
+            .bearer(&token)
+            .json(&request_body);
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
 }
@@ -178,7 +178,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 Note that by default, all sessions, including revoked ones are returned, with the oldest first.
 Use the `filter[status]` parameter to filter the sessions by their status and `page[last]` parameter to retrieve the last N sessions.")
         .tag("personal-session")
-        .response_with::<200, Json<PaginatedResponse<PersonalSession>>, _>(|t| {
+        .response_with::<200, PaginatedResponse<PersonalSession>, _>(|t| {
             let sessions = PersonalSession::samples();
             let pagination = mas_storage::Pagination::first(sessions.len());
             let page = mas_storage::Page {
@@ -216,7 +216,7 @@ pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
     Pagination(pagination, include_count): Pagination,
     params: FilterParams,
-) -> Result<Json<PaginatedResponse<PersonalSession>>, RouteError> {
+) -> Result<PaginatedResponse<PersonalSession>, RouteError> {
     let base = format!("{path}{params}", path = PersonalSession::PATH);
     let base = include_count.add_to_base(&base);
 
@@ -332,7 +332,7 @@ pub async fn handler(
         }
     };
 
-    Ok(Json(response))
+    Ok(response)
 }
 
 #[cfg(test)]
@@ -75,9 +75,13 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .summary("Regenerate a personal session by replacing its personal access token")
         .tag("personal-session")
         .response_with::<201, Json<SingleResponse<PersonalSession>>, _>(|t| {
+            let [sample, ..] = PersonalSession::samples();
+            let sample = sample.with_token("mpt_FM44zJN5qePGMLvvMXC4Ds1A3lCWc6_bJ9Wj1".to_owned());
+            let response = SingleResponse::new_canonical(sample);
             t.description(
                 "Personal session was regenerated and a personal access token was created",
             )
+            .example(response)
         })
         .response_with::<404, RouteError, _>(|t| {
             let response = ErrorResponse::from_error(&RouteError::UserNotFound);
@@ -0,0 +1,315 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::BoxRng;
+use mas_storage::queue::{QueueJobRepositoryExt as _, ReconcileProvisioningJob};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::ProvisioningReport,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/provisioning-reports` endpoint
+#[derive(Default, Deserialize, JsonSchema)]
+#[serde(rename = "AddProvisioningReportRequest")]
+pub struct Request {
+    /// Whether to provision the active users which are missing on the
+    /// homeserver. Defaults to `false`.
+    #[serde(default)]
+    fix: bool,
+}
+
+pub fn doc(mut operation: TransformOperation) -> TransformOperation {
+    operation
+        .inner_mut()
+        .request_body
+        .as_mut()
+        .unwrap()
+        .as_item_mut()
+        .unwrap()
+        .required = false;
+
+    operation
+        .id("addProvisioningReport")
+        .summary("Start a provisioning reconciliation")
+        .description(
+            "Schedule a job which compares every user with their state on the homeserver.
+The returned report is filled once the job completes.",
+        )
+        .tag("provisioning-report")
+        .response_with::<201, Json<SingleResponse<ProvisioningReport>>, _>(|t| {
+            let [_completed, pending] = ProvisioningReport::samples();
+            let response = SingleResponse::new_canonical(pending);
+            t.description("The reconciliation was scheduled")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.provisioning_reports.add", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    body: Option<Json<Request>>,
+) -> Result<(StatusCode, Json<SingleResponse<ProvisioningReport>>), RouteError> {
+    let Json(params) = body.unwrap_or_default();
+
+    let report = repo
+        .provisioning_report()
+        .add(&mut rng, &clock, params.fix)
+        .await?;
+
+    info!(provisioning_report.id = %report.id, "Scheduling provisioning reconciliation");
+    repo.queue_job()
+        .schedule_job(&mut rng, &clock, ReconcileProvisioningJob::new(&report))
+        .await?;
+
+    repo.save().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(SingleResponse::new_canonical(report.into())),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_matrix::{HomeserverConnection, ProvisionRequest};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    /// Create the users checked by the tests: `alice` is provisioned with a
+    /// display name, `bob` has no display name on the homeserver, and
+    /// `charlie` is missing on the homeserver
+    async fn seed_users(state: &TestState) {
+        let mut repo = state.repository().await.unwrap();
+        for username in ["alice", "bob", "charlie"] {
+            let user = repo
+                .user()
+                .add(&mut state.rng(), &state.clock, username.to_owned())
+                .await
+                .unwrap();
+
+            if username == "charlie" {
+                continue;
+            }
+
+            let mut request = ProvisionRequest::new(&user.username, &user.sub);
+            if username == "alice" {
+                request = request.set_displayname("Alice".to_owned());
+            }
+            state
+                .homeserver_connection
+                .provision_user(&request)
+                .await
+                .unwrap();
+        }
+        repo.save().await.unwrap();
+    }
+
+    async fn run_reconciliation(
+        state: &mut TestState,
+        body: Option<serde_json::Value>,
+    ) -> serde_json::Value {
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/provisioning-reports").bearer(&token);
+        let request = match body {
+            None => request.empty(),
+            Some(body) => request.json(body),
+        };
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let body: serde_json::Value = response.json();
+        let id = body["data"]["id"].as_str().unwrap().to_owned();
+
+        // The report is empty until the job runs
+        assert_eq!(
+            body["data"]["attributes"]["completed_at"],
+            serde_json::Value::Null
+        );
+        assert_eq!(
+            body["data"]["attributes"]["discrepancies"],
+            serde_json::json!([])
+        );
+
+        state.run_jobs_in_queue().await;
+
+        let request = Request::get(format!("/api/admin/v1/provisioning-reports/{id}"))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        body["data"]["attributes"].clone()
+    }
+
+    /// The discrepancies of a report, as `(username, kind, fixed)` tuples
+    /// sorted by username
+    fn discrepancies(report: &serde_json::Value) -> Vec<(&str, &str, bool)> {
+        let mut discrepancies: Vec<_> = report["discrepancies"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| {
+                (
+                    d["username"].as_str().unwrap(),
+                    d["kind"].as_str().unwrap(),
+                    d["fixed"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        discrepancies.sort_unstable();
+        discrepancies
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_report_discrepancies(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        seed_users(&state).await;
+
+        let report = run_reconciliation(&mut state, None).await;
+        assert_eq!(report["fix"], false);
+        assert_ne!(report["completed_at"], serde_json::Value::Null);
+        assert_eq!(report["users_checked"], 3);
+
+        assert_eq!(
+            discrepancies(&report),
+            [
+                ("bob", "displayname_drift", false),
+                ("charlie", "missing_on_homeserver", false),
+            ]
+        );
+
+        // Nothing was fixed
+        assert!(
+            state
+                .homeserver_connection
+                .query_user("charlie")
+                .await
+                .is_err()
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_fix_missing_users(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        seed_users(&state).await;
+
+        let report = run_reconciliation(
+            &mut state,
+            Some(serde_json::json!({
+                "fix": true,
+            })),
+        )
+        .await;
+        assert_eq!(report["fix"], true);
+
+        assert_eq!(
+            discrepancies(&report),
+            [
+                ("bob", "displayname_drift", false),
+                ("charlie", "missing_on_homeserver", true),
+            ]
+        );
+
+        // The reconciliation scheduled a provisioning job for the missing user
+        state.run_jobs_in_queue().await;
+        state
+            .homeserver_connection
+            .query_user("charlie")
+            .await
+            .unwrap();
+
+        // Another reconciliation doesn't find the user missing anymore. Charlie
+        // now has no display name on the homeserver though.
+        let report = run_reconciliation(&mut state, None).await;
+        assert_eq!(
+            discrepancies(&report),
+            [
+                ("bob", "displayname_drift", false),
+                ("charlie", "displayname_drift", false),
+            ]
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_displayname_drift(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+
+        // `dave` has the display name derived from their email address, while
+        // `erin` has another one
+        let mut repo = state.repository().await.unwrap();
+        for (username, displayname) in [("dave", "Dave [Example]"), ("erin", "Someone [Else]")] {
+            let user = repo
+                .user()
+                .add(&mut state.rng(), &state.clock, username.to_owned())
+                .await
+                .unwrap();
+            repo.user_email()
+                .add(
+                    &mut state.rng(),
+                    &state.clock,
+                    &user,
+                    format!("{username}@example.com"),
+                )
+                .await
+                .unwrap();
+
+            let request = ProvisionRequest::new(&user.username, &user.sub)
+                .set_displayname(displayname.to_owned());
+            state
+                .homeserver_connection
+                .provision_user(&request)
+                .await
+                .unwrap();
+        }
+        repo.save().await.unwrap();
+
+        let report = run_reconciliation(&mut state, None).await;
+        assert_eq!(report["users_checked"], 2);
+        assert_eq!(
+            discrepancies(&report),
+            [("erin", "displayname_drift", false)]
+        );
+    }
+}
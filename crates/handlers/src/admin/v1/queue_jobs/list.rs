@@ -0,0 +1,213 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_storage::{Page, queue::QueueJobFilter};
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::{QueueJob, Resource, User},
+        params::{IncludeCount, Pagination, UlidPathParam},
+        response::{ErrorResponse, PaginatedResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    UserNotFound(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UserNotFound(_) => StatusCode::NOT_FOUND,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("listUserQueueJobs")
+        .summary("List the jobs about a user in the job queue")
+        .description(
+            "Lists the jobs scheduled about a user, like sending verification emails, \
+provisioning the user or syncing their devices on the homeserver, oldest first. Jobs scheduled \
+before jobs were associated with users are not listed.",
+        )
+        .tag("queue-job")
+        .response_with::<200, PaginatedResponse<QueueJob>, _>(|t| {
+            let jobs = QueueJob::samples();
+            let pagination = mas_storage::Pagination::first(jobs.len());
+            let page = Page {
+                edges: jobs
+                    .into_iter()
+                    .map(|node| mas_storage::pagination::Edge {
+                        cursor: node.id(),
+                        node,
+                    })
+                    .collect(),
+                has_next_page: true,
+                has_previous_page: false,
+            };
+
+            t.description("Paginated response of jobs")
+                .example(PaginatedResponse::for_page(
+                    page,
+                    pagination,
+                    Some(42),
+                    "/api/admin/v1/users/02081040G2081040G2081040G2/jobs",
+                ))
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::UserNotFound(Ulid::nil()));
+            t.description("User was not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.queue_jobs.list", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    id: UlidPathParam,
+    Pagination(pagination, include_count): Pagination,
+) -> Result<PaginatedResponse<QueueJob>, RouteError> {
+    let id = *id;
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::UserNotFound(id))?;
+
+    let base = format!("{path}/{id}/jobs", path = User::PATH);
+    let base = include_count.add_to_base(&base);
+    let filter = QueueJobFilter::new().for_user(&user);
+
+    let response = match include_count {
+        IncludeCount::True => {
+            let page = repo
+                .queue_job()
+                .list(filter, pagination)
+                .await?
+                .map(QueueJob::from);
+            let count = repo.queue_job().count(filter).await?;
+            PaginatedResponse::for_page(page, pagination, Some(count), &base)
+        }
+        IncludeCount::False => {
+            let page = repo
+                .queue_job()
+                .list(filter, pagination)
+                .await?
+                .map(QueueJob::from);
+            PaginatedResponse::for_page(page, pagination, None, &base)
+        }
+        IncludeCount::Only => {
+            let count = repo.queue_job().count(filter).await?;
+            PaginatedResponse::for_count_only(count, &base)
+        }
+    };
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_storage::{
+        RepositoryAccess,
+        queue::{ProvisionUserJob, QueueJobRepositoryExt as _, SyncDevicesJob},
+        user::UserRepository,
+    };
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.queue_job()
+            .schedule_job(&mut rng, &state.clock, ProvisionUserJob::new(&alice))
+            .await
+            .unwrap();
+        state.clock.advance(Duration::try_seconds(1).unwrap());
+        repo.queue_job()
+            .schedule_job(&mut rng, &state.clock, SyncDevicesJob::new(&alice))
+            .await
+            .unwrap();
+        repo.queue_job()
+            .schedule_job(&mut rng, &state.clock, ProvisionUserJob::new(&bob))
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get(format!("/api/admin/v1/users/{}/jobs", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 2);
+        assert_eq!(body["data"][0]["type"], "queue-job");
+        let attributes = &body["data"][0]["attributes"];
+        assert_eq!(attributes["queue_name"], "provision-user");
+        assert_eq!(attributes["status"], "available");
+        assert_eq!(attributes["attempt"], 0);
+        assert_eq!(attributes["user_id"], alice.id.to_string());
+        assert_eq!(body["data"][1]["attributes"]["queue_name"], "sync-devices");
+
+        let request = Request::get(format!("/api/admin/v1/users/{}/jobs", bob.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_user_not_found(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::get(format!("/api/admin/v1/users/{}/jobs", Ulid::nil()))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use chrono::Duration;
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::BoxRng;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::{QueueJob, Resource},
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Job with ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("Job with ID {0} did not fail, or was already retried")]
+    NotRetryable(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::NotRetryable(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("retryQueueJob")
+        .summary("Retry a failed job")
+        .description(
+            "Schedules a new attempt of a failed job, to be run as soon as possible. The response \
+is the new attempt.",
+        )
+        .tag("queue-job")
+        .response_with::<200, Json<SingleResponse<QueueJob>>, _>(|t| {
+            let [failed, ..] = QueueJob::samples();
+            let id = failed.id();
+            let response = SingleResponse::new(failed, format!("/api/admin/v1/jobs/{id}/retry"));
+            t.description("Job was retried").example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotRetryable(Ulid::nil()));
+            t.description("Job did not fail, or was already retried")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("Job was not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.queue_jobs.retry", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    id: UlidPathParam,
+) -> Result<Json<SingleResponse<QueueJob>>, RouteError> {
+    let id = *id;
+    let job = repo
+        .queue_job()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    if !job.can_be_retried() {
+        return Err(RouteError::NotRetryable(id));
+    }
+
+    repo.queue_job()
+        .retry(&mut rng, &clock, id, Duration::zero())
+        .await?;
+
+    // Retrying links the failed job to its new attempt
+    let new_job = repo
+        .queue_job()
+        .lookup(id)
+        .await?
+        .and_then(|job| job.next_attempt_id)
+        .ok_or(RouteError::NotFound(id))?;
+    let new_job = repo
+        .queue_job()
+        .lookup(new_job)
+        .await?
+        .ok_or(RouteError::NotFound(new_job))?;
+
+    tracing::info!(
+        queue_job.id = %id,
+        queue_job.next_attempt_id = %new_job.id,
+        "Retrying failed job"
+    );
+
+    repo.save().await?;
+
+    Ok(Json(SingleResponse::new(
+        QueueJob::from(new_job),
+        format!("/api/admin/v1/jobs/{id}/retry"),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_storage::{
+        RepositoryAccess,
+        queue::{
+            QueueJobRepository as _, QueueJobRepositoryExt as _, QueueWorkerRepository as _,
+            SendEmailAuthenticationCodeJob,
+        },
+        user::{BrowserSessionRepository as _, UserEmailRepository as _, UserRepository as _},
+    };
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_retry_failed_email_job(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        // Seed a failed job sending a verification email to alice
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        let authentication = repo
+            .user_email()
+            .add_authentication_for_session(
+                &mut rng,
+                &state.clock,
+                "alice@example.com".to_owned(),
+                &browser_session,
+            )
+            .await
+            .unwrap();
+        repo.queue_job()
+            .schedule_job(
+                &mut rng,
+                &state.clock,
+                SendEmailAuthenticationCodeJob::new(&authentication, "en".to_owned())
+                    .for_user(&user),
+            )
+            .await
+            .unwrap();
+        let worker = repo
+            .queue_worker()
+            .register(&mut rng, &state.clock)
+            .await
+            .unwrap();
+        let mut jobs = repo
+            .queue_job()
+            .reserve(
+                &state.clock,
+                &worker,
+                &["send-email-authentication-code"],
+                1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(jobs.len(), 1);
+        let job = jobs.remove(0);
+        repo.queue_job()
+            .mark_as_failed(&state.clock, job.id, "Failed to send email")
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // The failed job shows up in the jobs of the user
+        let request = Request::get(format!("/api/admin/v1/users/{}/jobs", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["id"], job.id.to_string());
+        let attributes = &body["data"][0]["attributes"];
+        assert_eq!(attributes["queue_name"], "send-email-authentication-code");
+        assert_eq!(attributes["status"], "failed");
+        assert_eq!(attributes["failed_reason"], "Failed to send email");
+
+        // Retry it
+        let request = Request::post(format!("/api/admin/v1/jobs/{}/retry", job.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        let new_job_id = body["data"]["id"].as_str().unwrap().to_owned();
+        assert_ne!(new_job_id, job.id.to_string());
+        let attributes = &body["data"]["attributes"];
+        assert_eq!(attributes["status"], "scheduled");
+        assert_eq!(attributes["attempt"], 1);
+        assert_eq!(attributes["user_id"], user.id.to_string());
+
+        // The failed job points to the new attempt
+        let request = Request::get(format!("/api/admin/v1/jobs/{}", job.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"]["next_attempt_id"], new_job_id);
+
+        // It can't be retried twice
+        let request = Request::post(format!("/api/admin/v1/jobs/{}/retry", job.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        // Nor can the new attempt, which didn't fail
+        let request = Request::post(format!("/api/admin/v1/jobs/{new_job_id}/retry"))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        // Both attempts are listed for the user
+        let request = Request::get(format!("/api/admin/v1/users/{}/jobs", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 2);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_retry_not_found(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/jobs/01040G2081040G2081040G2081/retry")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
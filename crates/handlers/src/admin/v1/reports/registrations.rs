@@ -0,0 +1,460 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use axum_extra::extract::{Query, QueryRejection};
+use axum_macros::FromRequestParts;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    admin::{call_context::CallContext, response::ErrorResponse},
+    impl_from_error_for_route,
+};
+
+/// The default number of entries in each section of the report
+const DEFAULT_LIMIT: usize = 10;
+
+/// The maximum number of entries in each section of the report
+const MAX_LIMIT: usize = 100;
+
+/// How far back the report goes if no start is given
+const DEFAULT_WINDOW: Duration = Duration::days(7);
+
+/// The maximum time between two registrations from the same IP address for
+/// them to be grouped in the same burst
+const BURST_INTERVAL: Duration = Duration::minutes(10);
+
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[serde(rename = "RegistrationReportParams")]
+#[aide(input_with = "Query<Params>")]
+#[from_request(via(Query), rejection(RouteError))]
+pub struct Params {
+    /// Only consider accounts registered at or after this time. Defaults to
+    /// seven days before `to`
+    from: Option<DateTime<Utc>>,
+
+    /// Only consider accounts registered before this time. Defaults to now
+    to: Option<DateTime<Utc>>,
+
+    /// The maximum number of entries in each section of the report, between 1
+    /// and 100. Defaults to 10
+    #[schemars(range(min = 1, max = 100))]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Invalid parameters")]
+    InvalidParams(#[from] QueryRejection),
+
+    #[error("The start of the time window must be before its end")]
+    InvalidTimeWindow,
+
+    #[error("The limit must be between 1 and {MAX_LIMIT}")]
+    InvalidLimit,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidParams(_) | Self::InvalidTimeWindow | Self::InvalidLimit => {
+                StatusCode::BAD_REQUEST
+            }
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// Number of accounts registered with an email address on a domain
+#[derive(Serialize, JsonSchema)]
+pub struct EmailDomainCount {
+    /// The domain of the email addresses
+    domain: String,
+
+    /// How many accounts were registered with an email address on this domain
+    count: usize,
+}
+
+/// Number of accounts registered from an IP address
+#[derive(Serialize, JsonSchema)]
+pub struct IpAddressCount {
+    /// The IP address the accounts were registered from
+    ip_address: IpAddr,
+
+    /// How many accounts were registered from this IP address
+    count: usize,
+}
+
+/// An account which is part of a registration burst
+#[derive(Serialize, JsonSchema)]
+pub struct RegistrationBurstUser {
+    /// The ID of the user
+    #[schemars(with = "crate::admin::schema::Ulid")]
+    id: Ulid,
+
+    /// The username of the user
+    username: String,
+}
+
+/// Accounts registered from the same IP address in quick succession
+#[derive(Serialize, JsonSchema)]
+pub struct RegistrationBurst {
+    /// The IP address the accounts were registered from
+    ip_address: IpAddr,
+
+    /// When the first account of the burst was registered
+    first_registered_at: DateTime<Utc>,
+
+    /// When the last account of the burst was registered
+    last_registered_at: DateTime<Utc>,
+
+    /// The accounts registered during the burst, in registration order
+    users: Vec<RegistrationBurstUser>,
+}
+
+impl From<mas_storage::user::RegistrationBurst> for RegistrationBurst {
+    fn from(burst: mas_storage::user::RegistrationBurst) -> Self {
+        Self {
+            ip_address: burst.ip_address,
+            first_registered_at: burst.first_registered_at,
+            last_registered_at: burst.last_registered_at,
+            users: burst
+                .users
+                .into_iter()
+                .map(|(id, username)| RegistrationBurstUser { id, username })
+                .collect(),
+        }
+    }
+}
+
+/// Signals about the accounts registered in a time window
+#[derive(Serialize, JsonSchema)]
+pub struct RegistrationReport {
+    /// The start of the time window covered by the report
+    from: DateTime<Utc>,
+
+    /// The end of the time window covered by the report
+    to: DateTime<Utc>,
+
+    /// The email domains with the most accounts registered in the time window
+    email_domains: Vec<EmailDomainCount>,
+
+    /// The IP addresses from which the most accounts were registered in the
+    /// time window
+    ip_addresses: Vec<IpAddressCount>,
+
+    /// The biggest groups of accounts registered from the same IP address
+    /// less than ten minutes apart from each other
+    bursts: Vec<RegistrationBurst>,
+}
+
+impl RegistrationReport {
+    fn sample() -> Self {
+        let from = Utc.with_ymd_and_hms(2022, 1, 9, 14, 40, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2022, 1, 16, 14, 40, 0).unwrap();
+        let ip_address = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 42));
+
+        Self {
+            from,
+            to,
+            email_domains: vec![
+                EmailDomainCount {
+                    domain: "example.com".to_owned(),
+                    count: 42,
+                },
+                EmailDomainCount {
+                    domain: "example.org".to_owned(),
+                    count: 3,
+                },
+            ],
+            ip_addresses: vec![IpAddressCount {
+                ip_address,
+                count: 12,
+            }],
+            bursts: vec![RegistrationBurst {
+                ip_address,
+                first_registered_at: Utc.with_ymd_and_hms(2022, 1, 12, 8, 0, 0).unwrap(),
+                last_registered_at: Utc.with_ymd_and_hms(2022, 1, 12, 8, 3, 0).unwrap(),
+                users: vec![
+                    RegistrationBurstUser {
+                        id: Ulid::from_bytes([0x01; 16]),
+                        username: "alice".to_owned(),
+                    },
+                    RegistrationBurstUser {
+                        id: Ulid::from_bytes([0x02; 16]),
+                        username: "bob".to_owned(),
+                    },
+                ],
+            }],
+        }
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("registrationReport")
+        .summary("Report on the accounts registered in a time window")
+        .description(
+            "Get simple signals to help detect abuse: the email domains and IP addresses with the most registrations, and the accounts registered from the same IP address in quick succession.
+IP addresses are only known for accounts registered with a password.",
+        )
+        .tag("report")
+        .response_with::<200, Json<RegistrationReport>, _>(|t| {
+            t.description("The report on the registrations in the time window")
+                .example(RegistrationReport::sample())
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::InvalidTimeWindow);
+            t.description("The parameters are invalid").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.reports.registrations", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext,
+    params: Params,
+) -> Result<Json<RegistrationReport>, RouteError> {
+    let to = params.to.unwrap_or_else(|| clock.now());
+    let from = params.from.unwrap_or(to - DEFAULT_WINDOW);
+    if from >= to {
+        return Err(RouteError::InvalidTimeWindow);
+    }
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(RouteError::InvalidLimit);
+    }
+
+    let email_domains = repo
+        .user_email()
+        .count_users_by_domain(from, to, limit)
+        .await?
+        .into_iter()
+        .map(|(domain, count)| EmailDomainCount { domain, count })
+        .collect();
+
+    let ip_addresses = repo
+        .user_registration()
+        .count_by_ip_address(from, to, limit)
+        .await?
+        .into_iter()
+        .map(|(ip_address, count)| IpAddressCount { ip_address, count })
+        .collect();
+
+    let bursts = repo
+        .user_registration()
+        .list_bursts(from, to, BURST_INTERVAL, limit)
+        .await?
+        .into_iter()
+        .map(RegistrationBurst::from)
+        .collect();
+
+    Ok(Json(RegistrationReport {
+        from,
+        to,
+        email_domains,
+        ip_addresses,
+        bursts,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_data_model::Clock;
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    /// Register an account with a password, as the registration flow does
+    async fn register(state: &TestState, username: &str, email: &str, ip_address: IpAddr) {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let registration = repo
+            .user_registration()
+            .add(
+                &mut rng,
+                &state.clock,
+                username.to_owned(),
+                Some(ip_address),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.user_registration()
+            .complete(&state.clock, registration)
+            .await
+            .unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, username.to_owned())
+            .await
+            .unwrap();
+        repo.user_email()
+            .add(&mut rng, &state.clock, &user, email.to_owned())
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_registration_report(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let spammer = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
+        let legit = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+
+        let start = state.clock.now();
+
+        // A first account from the spammer's IP, long before the burst
+        register(&state, "early", "early@spam.example", spammer).await;
+        state.clock.advance(Duration::hours(1));
+
+        // A burst of accounts from the same IP, a minute apart
+        for i in 0..5 {
+            register(
+                &state,
+                &format!("spam{i}"),
+                &format!("spam{i}@spam.example"),
+                spammer,
+            )
+            .await;
+            state.clock.advance(Duration::minutes(1));
+        }
+
+        // A few legitimate accounts, far apart
+        for i in 0..2 {
+            state.clock.advance(Duration::hours(1));
+            register(
+                &state,
+                &format!("user{i}"),
+                &format!("user{i}@EXAMPLE.com"),
+                legit,
+            )
+            .await;
+        }
+        state.clock.advance(Duration::minutes(1));
+
+        let request = Request::get(format!(
+            "/api/admin/v1/reports/registrations?from={}",
+            start.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+
+        assert_eq!(
+            body["email_domains"],
+            serde_json::json!([
+                { "domain": "spam.example", "count": 6 },
+                { "domain": "example.com", "count": 2 },
+            ])
+        );
+        assert_eq!(
+            body["ip_addresses"],
+            serde_json::json!([
+                { "ip_address": "203.0.113.1", "count": 6 },
+                { "ip_address": "198.51.100.1", "count": 2 },
+            ])
+        );
+
+        // Only the accounts registered in quick succession are grouped together
+        let bursts = body["bursts"].as_array().unwrap();
+        assert_eq!(bursts.len(), 1);
+        assert_eq!(bursts[0]["ip_address"], "203.0.113.1");
+        let usernames: Vec<&str> = bursts[0]["users"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|user| user["username"].as_str().unwrap())
+            .collect();
+        assert_eq!(usernames, ["spam0", "spam1", "spam2", "spam3", "spam4"]);
+
+        // The limit applies to each section
+        let request = Request::get(format!(
+            "/api/admin/v1/reports/registrations?from={}&limit=1",
+            start.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["email_domains"].as_array().unwrap().len(), 1);
+        assert_eq!(body["ip_addresses"].as_array().unwrap().len(), 1);
+
+        // Registrations outside of the time window are ignored
+        let request = Request::get(
+            "/api/admin/v1/reports/registrations?from=2000-01-01T00:00:00Z&to=2000-01-02T00:00:00Z",
+        )
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["email_domains"], serde_json::json!([]));
+        assert_eq!(body["ip_addresses"], serde_json::json!([]));
+        assert_eq!(body["bursts"], serde_json::json!([]));
+
+        // The time window must not be empty
+        let request = Request::get(
+            "/api/admin/v1/reports/registrations?from=2000-01-02T00:00:00Z&to=2000-01-01T00:00:00Z",
+        )
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        // The limit is bounded
+        let request = Request::get("/api/admin/v1/reports/registrations?limit=1000")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_registration_report_requires_admin(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:graphql:*").await;
+
+        let request = Request::get("/api/admin/v1/reports/registrations")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::collections::BTreeMap;
+
+use aide::transform::TransformOperation;
+use axum::{Json, extract::State};
+use chrono::{DateTime, Utc};
+use mas_data_model::{
+    AppBuildInfo, AppVersion, DisallowedScopeHandling, SiteConfig, TchapFeatures,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::admin::call_context::CallContext;
+
+/// Information about the running MAS instance and how it was built
+#[derive(Serialize, JsonSchema)]
+pub struct ServerInfo {
+    /// The version of the app, as reported by `git describe` at build time
+    version: &'static str,
+
+    /// The git commit the app was built from, if known
+    git_sha: Option<&'static str>,
+
+    /// The version of the Rust compiler the app was built with, if known
+    rustc_version: Option<&'static str>,
+
+    /// When the server process started
+    started_at: DateTime<Utc>,
+
+    /// How long the server process has been running, in seconds
+    uptime_seconds: i64,
+
+    /// The optional features of the server, and whether they are enabled
+    features: BTreeMap<&'static str, bool>,
+}
+
+impl ServerInfo {
+    fn sample() -> Self {
+        Self {
+            version: "v1.0.0",
+            git_sha: Some("3f7a9d2c1b8e4f6a0d5c9b2e7a1f3d8c6b4e2a90"),
+            rustc_version: Some("1.89.0"),
+            started_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            uptime_seconds: 86_400,
+            features: features(TchapFeatures::default(), None),
+        }
+    }
+}
+
+/// Collect the feature flags of the server: the Tchap features, and the
+/// experimental toggles from the configuration
+fn features(
+    tchap_features: TchapFeatures,
+    site_config: Option<&SiteConfig>,
+) -> BTreeMap<&'static str, bool> {
+    let mut features = BTreeMap::from([
+        ("tchap", tchap_features.any_enabled()),
+        (
+            "tchap.registration_email_gatekeeping",
+            tchap_features.registration_email_gatekeeping,
+        ),
+        (
+            "tchap.upstream_email_gatekeeping",
+            tchap_features.upstream_email_gatekeeping,
+        ),
+        (
+            "tchap.upstream_email_matching",
+            tchap_features.upstream_email_matching,
+        ),
+        (
+            "tchap.displayname_suffixing",
+            tchap_features.displayname_suffixing,
+        ),
+        (
+            "tchap.identity_server_lookups",
+            tchap_features.identity_server_lookups,
+        ),
+        (
+            "tchap.organization_claim",
+            tchap_features.organization_claim,
+        ),
+    ]);
+
+    let (session_expiration, plan_management, trim_scopes, matrix_session, examples) = site_config
+        .map_or((false, false, false, false, false), |c| {
+            (
+                c.session_expiration.is_some(),
+                c.plan_management_iframe_uri.is_some(),
+                c.disallowed_scope_handling == DisallowedScopeHandling::Trim,
+                c.matrix_session_in_token_response,
+                c.admin_api_examples,
+            )
+        });
+
+    features.extend([
+        (
+            "experimental.inactive_session_expiration",
+            session_expiration,
+        ),
+        ("experimental.plan_management_iframe", plan_management),
+        ("experimental.trim_disallowed_scopes", trim_scopes),
+        (
+            "experimental.matrix_session_in_token_response",
+            matrix_session,
+        ),
+        ("experimental.admin_api_examples", examples),
+    ]);
+
+    features
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("serverInfo")
+        .tag("server")
+        .summary("Get information about the running server")
+        .description("Returns the version of the server, how it was built, since when it is running, and which optional features are enabled.")
+        .response_with::<200, Json<ServerInfo>, _>(|t| t.example(ServerInfo::sample()))
+}
+
+#[tracing::instrument(name = "handler.admin.v1.server_info", skip_all)]
+pub async fn handler(
+    CallContext { clock, .. }: CallContext,
+    State(AppVersion(version)): State<AppVersion>,
+    State(build_info): State<AppBuildInfo>,
+    State(tchap_features): State<TchapFeatures>,
+    State(site_config): State<SiteConfig>,
+) -> Json<ServerInfo> {
+    let uptime = clock.now() - build_info.started_at;
+
+    Json(ServerInfo {
+        version,
+        git_sha: build_info.git_sha,
+        rustc_version: build_info.rustc_version,
+        started_at: build_info.started_at,
+        uptime_seconds: uptime.num_seconds(),
+        features: features(tchap_features, Some(&site_config)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_data_model::AppVersion;
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_server_info(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        state.clock.advance(Duration::try_minutes(5).unwrap());
+
+        let request = Request::get("/api/admin/v1/server-info")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+
+        let AppVersion(version) = axum::extract::FromRef::from_ref(&state);
+        assert_eq!(body["version"], version);
+        assert_eq!(body["git_sha"], "0000000000000000000000000000000000000000");
+        assert_eq!(body["rustc_version"], "1.0.0");
+        assert_eq!(
+            body["started_at"],
+            serde_json::to_value(state.started_at).unwrap()
+        );
+        assert_eq!(body["uptime_seconds"], 300);
+
+        let features = body["features"].as_object().unwrap();
+        assert_eq!(features["tchap"], true);
+        assert_eq!(features["tchap.registration_email_gatekeeping"], true);
+        assert_eq!(
+            features["experimental.matrix_session_in_token_response"],
+            false
+        );
+        assert!(features.values().all(serde_json::Value::is_boolean));
+    }
+}
@@ -3,13 +3,50 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use aide::transform::TransformOperation;
-use axum::{Json, extract::State};
+use std::collections::BTreeMap;
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, extract::State, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_config::{RateLimiterConfiguration, RateLimitingConfig};
+use mas_storage::{RepositoryAccess, upstream_oauth2::UpstreamOAuthProviderRepository};
+use mas_templates::Templates;
 use schemars::JsonSchema;
 use serde::Serialize;
+use ulid::Ulid;
+
+use crate::{
+    Limiter,
+    admin::{call_context::CallContext, response::ErrorResponse},
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
 
-use crate::admin::call_context::CallContext;
+impl_from_error_for_route!(mas_storage::RepositoryError);
 
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// A sanitized view of the configuration effectively used by this MAS
+/// instance.
+///
+/// This never includes secrets: only the values listed here are exposed.
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Serialize, JsonSchema)]
 pub struct SiteConfig {
@@ -50,6 +87,228 @@ pub struct SiteConfig {
     /// This is a score from zxcvbn.
     #[schemars(range(min = 0, max = 4))]
     pub minimum_password_complexity: u8,
+
+    /// Time-to-live of OAuth 2.0 access tokens, in seconds.
+    pub access_token_ttl: u64,
+
+    /// Time-to-live of compatibility access tokens, in seconds.
+    pub compat_token_ttl: u64,
+
+    /// The URL to the privacy policy, if any.
+    pub policy_uri: Option<String>,
+
+    /// The URL to the terms of service, if any.
+    pub tos_uri: Option<String>,
+
+    /// Whether changing an email address requires confirming the old one too.
+    pub email_change_double_confirmation: bool,
+
+    /// Whether users are emailed when a new session is started on their
+    /// account through the compatibility login API.
+    pub new_login_notification: bool,
+
+    /// Whether users can log in with their email address.
+    pub login_with_email_allowed: bool,
+
+    /// Whether users are automatically redirected to the only upstream
+    /// provider.
+    pub auto_redirect_single_provider: bool,
+
+    /// Whether the password form is shown before the upstream providers.
+    pub prefer_password_login: bool,
+
+    /// Minimum time between two account recovery emails, in seconds.
+    pub account_recovery_email_interval: u64,
+
+    /// How long deactivated users are kept before being deleted, in seconds.
+    pub deleted_user_retention: u64,
+
+    /// Maximum number of rows in a CSV export of the admin API.
+    pub admin_api_csv_export_limit: usize,
+
+    /// Whether the audit webhook is enabled.
+    pub audit_webhook_enabled: bool,
+
+    /// Whether the email delivery probe is enabled.
+    pub email_probe_enabled: bool,
+
+    /// Whether email changes are synced to the homeserver.
+    pub sync_emails_to_homeserver: bool,
+
+    /// The rate limits, keyed by the path of their configuration option.
+    pub rate_limits: BTreeMap<String, RateLimit>,
+
+    /// The enabled upstream OAuth 2.0 providers.
+    pub upstream_providers: Vec<UpstreamProvider>,
+
+    /// Where the templates and assets are loaded from.
+    pub templates: TemplatesPaths,
+}
+
+/// A rate limit
+#[derive(Serialize, JsonSchema)]
+pub struct RateLimit {
+    /// How many actions can be performed in one go without waiting.
+    pub burst: u32,
+
+    /// How quickly the allowance replenishes, in number of actions per second.
+    pub per_second: f64,
+}
+
+impl From<&RateLimiterConfiguration> for RateLimit {
+    fn from(config: &RateLimiterConfiguration) -> Self {
+        Self {
+            burst: config.burst.get(),
+            per_second: config.per_second,
+        }
+    }
+}
+
+/// An enabled upstream OAuth 2.0 provider
+#[derive(Serialize, JsonSchema)]
+pub struct UpstreamProvider {
+    /// The ID of the provider
+    #[schemars(with = "crate::admin::schema::Ulid")]
+    pub id: Ulid,
+
+    /// The OIDC issuer of the provider, if any
+    pub issuer: Option<String>,
+}
+
+/// The paths from which the templates and assets are loaded
+#[derive(Serialize, JsonSchema)]
+pub struct TemplatesPaths {
+    /// The path to the templates directory
+    pub path: String,
+
+    /// The path to the translations directory
+    pub translations_path: String,
+
+    /// The path to the assets manifest
+    pub assets_manifest: String,
+}
+
+impl SiteConfig {
+    /// Build a sanitized view of the effective configuration
+    #[must_use]
+    pub fn new(
+        site_config: &mas_data_model::SiteConfig,
+        rate_limiting: &RateLimitingConfig,
+        upstream_providers: Vec<UpstreamProvider>,
+        templates: TemplatesPaths,
+    ) -> Self {
+        Self {
+            server_name: site_config.server_name.clone(),
+            password_login_enabled: site_config.password_login_enabled,
+            password_registration_enabled: site_config.password_registration_enabled,
+            password_registration_email_required: site_config.password_registration_email_required,
+            registration_token_required: site_config.registration_token_required,
+            email_change_allowed: site_config.email_change_allowed,
+            displayname_change_allowed: site_config.displayname_change_allowed,
+            password_change_allowed: site_config.password_change_allowed,
+            account_recovery_allowed: site_config.account_recovery_allowed,
+            account_deactivation_allowed: site_config.account_deactivation_allowed,
+            captcha_enabled: site_config.captcha.is_some(),
+            minimum_password_complexity: site_config.minimum_password_complexity,
+            access_token_ttl: site_config.access_token_ttl.num_seconds().unsigned_abs(),
+            compat_token_ttl: site_config.compat_token_ttl.num_seconds().unsigned_abs(),
+            policy_uri: site_config.policy_uri.as_ref().map(ToString::to_string),
+            tos_uri: site_config.tos_uri.as_ref().map(ToString::to_string),
+            email_change_double_confirmation: site_config.email_change_double_confirmation,
+            new_login_notification: site_config.new_login_notification,
+            login_with_email_allowed: site_config.login_with_email_allowed,
+            auto_redirect_single_provider: site_config.auto_redirect_single_provider,
+            prefer_password_login: site_config.prefer_password_login,
+            account_recovery_email_interval: site_config
+                .account_recovery_email_interval
+                .num_seconds()
+                .unsigned_abs(),
+            deleted_user_retention: site_config
+                .deleted_user_retention
+                .num_seconds()
+                .unsigned_abs(),
+            admin_api_csv_export_limit: site_config.admin_api_csv_export_limit,
+            audit_webhook_enabled: site_config.audit_webhook.is_some(),
+            email_probe_enabled: site_config.email_probe.is_some(),
+            sync_emails_to_homeserver: site_config.sync_emails_to_homeserver,
+            rate_limits: rate_limits(rate_limiting),
+            upstream_providers,
+            templates,
+        }
+    }
+}
+
+/// Flatten the rate limiting configuration into a map keyed by the path of
+/// each option
+fn rate_limits(config: &RateLimitingConfig) -> BTreeMap<String, RateLimit> {
+    let mut limits = BTreeMap::from([
+        (
+            "account_recovery.per_ip".to_owned(),
+            RateLimit::from(&config.account_recovery.per_ip),
+        ),
+        (
+            "account_recovery.per_address".to_owned(),
+            RateLimit::from(&config.account_recovery.per_address),
+        ),
+        (
+            "login.per_ip".to_owned(),
+            RateLimit::from(&config.login.per_ip),
+        ),
+        (
+            "login.per_account".to_owned(),
+            RateLimit::from(&config.login.per_account),
+        ),
+        (
+            "registration".to_owned(),
+            RateLimit::from(&config.registration),
+        ),
+        (
+            "email_authentication.per_ip".to_owned(),
+            RateLimit::from(&config.email_authentication.per_ip),
+        ),
+        (
+            "email_authentication.per_address".to_owned(),
+            RateLimit::from(&config.email_authentication.per_address),
+        ),
+        (
+            "email_authentication.emails_per_session".to_owned(),
+            RateLimit::from(&config.email_authentication.emails_per_session),
+        ),
+        (
+            "email_authentication.attempt_per_session".to_owned(),
+            RateLimit::from(&config.email_authentication.attempt_per_session),
+        ),
+        (
+            "device_code_confirmation".to_owned(),
+            RateLimit::from(&config.device_code_confirmation),
+        ),
+        (
+            "admin_api.read".to_owned(),
+            RateLimit::from(&config.admin_api.read),
+        ),
+        (
+            "admin_api.write".to_owned(),
+            RateLimit::from(&config.admin_api.write),
+        ),
+    ]);
+
+    for client in &config.admin_api.clients {
+        if let Some(read) = &client.read {
+            limits.insert(
+                format!("admin_api.clients.{}.read", client.client_id),
+                read.into(),
+            );
+        }
+
+        if let Some(write) = &client.write {
+            limits.insert(
+                format!("admin_api.clients.{}.write", client.client_id),
+                write.into(),
+            );
+        }
+    }
+
+    limits
 }
 
 pub fn doc(operation: TransformOperation) -> TransformOperation {
@@ -57,6 +316,10 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .id("siteConfig")
         .tag("server")
         .summary("Get informations about the configuration of this MAS instance")
+        .description(
+            "This returns a sanitized view of the configuration effectively used by this instance, \
+            which never includes any secret.",
+        )
         .response_with::<200, Json<SiteConfig>, _>(|t| {
             t.example(SiteConfig {
                 server_name: "example.com".to_owned(),
@@ -71,27 +334,279 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
                 account_deactivation_allowed: true,
                 captcha_enabled: true,
                 minimum_password_complexity: 3,
+                access_token_ttl: 300,
+                compat_token_ttl: 300,
+                policy_uri: Some("https://example.com/privacy".to_owned()),
+                tos_uri: None,
+                email_change_double_confirmation: false,
+                new_login_notification: false,
+                login_with_email_allowed: false,
+                auto_redirect_single_provider: false,
+                prefer_password_login: false,
+                account_recovery_email_interval: 60,
+                deleted_user_retention: 2_592_000,
+                admin_api_csv_export_limit: 10_000,
+                audit_webhook_enabled: false,
+                email_probe_enabled: true,
+                sync_emails_to_homeserver: false,
+                rate_limits: BTreeMap::from([(
+                    "login.per_ip".to_owned(),
+                    RateLimit {
+                        burst: 3,
+                        per_second: 3.0 / 60.0,
+                    },
+                )]),
+                upstream_providers: vec![UpstreamProvider {
+                    id: Ulid::from_bytes([0x01; 16]),
+                    issuer: Some("https://accounts.google.com".to_owned()),
+                }],
+                templates: TemplatesPaths {
+                    path: "/usr/local/share/mas-cli/templates/".to_owned(),
+                    translations_path: "/usr/local/share/mas-cli/translations/".to_owned(),
+                    assets_manifest: "/usr/local/share/mas-cli/manifest.json".to_owned(),
+                },
             })
         })
 }
 
 #[tracing::instrument(name = "handler.admin.v1.site_config", skip_all)]
 pub async fn handler(
-    _: CallContext,
+    CallContext { mut repo, .. }: CallContext,
     State(site_config): State<mas_data_model::SiteConfig>,
-) -> Json<SiteConfig> {
-    Json(SiteConfig {
-        server_name: site_config.server_name,
-        password_login_enabled: site_config.password_login_enabled,
-        password_registration_enabled: site_config.password_registration_enabled,
-        password_registration_email_required: site_config.password_registration_email_required,
-        registration_token_required: site_config.registration_token_required,
-        email_change_allowed: site_config.email_change_allowed,
-        displayname_change_allowed: site_config.displayname_change_allowed,
-        password_change_allowed: site_config.password_change_allowed,
-        account_recovery_allowed: site_config.account_recovery_allowed,
-        account_deactivation_allowed: site_config.account_deactivation_allowed,
-        captcha_enabled: site_config.captcha.is_some(),
-        minimum_password_complexity: site_config.minimum_password_complexity,
-    })
+    State(limiter): State<Limiter>,
+    State(templates): State<Templates>,
+) -> Result<Json<SiteConfig>, RouteError> {
+    let upstream_providers = repo
+        .upstream_oauth_provider()
+        .all_enabled()
+        .await?
+        .into_iter()
+        .map(|provider| UpstreamProvider {
+            id: provider.id,
+            issuer: provider.issuer,
+        })
+        .collect();
+
+    let templates = TemplatesPaths {
+        path: templates.path().to_string(),
+        translations_path: templates.translations_path().to_string(),
+        assets_manifest: templates.vite_manifest_path().to_string(),
+    };
+
+    Ok(Json(SiteConfig::new(
+        &site_config,
+        limiter.config(),
+        upstream_providers,
+        templates,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use insta::assert_json_snapshot;
+    use mas_data_model::{
+        EmailProbeConfig, UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderDiscoveryMode,
+        UpstreamOAuthProviderOnBackchannelLogout, UpstreamOAuthProviderPkceMode,
+        UpstreamOAuthProviderTokenAuthMethod,
+    };
+    use mas_iana::jose::JsonWebSignatureAlg;
+    use mas_storage::{
+        RepositoryAccess,
+        upstream_oauth2::{UpstreamOAuthProviderParams, UpstreamOAuthProviderRepository},
+    };
+    use oauth2_types::scope::{OPENID, Scope};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup, test_site_config};
+
+    fn provider_params(issuer: &str) -> UpstreamOAuthProviderParams {
+        UpstreamOAuthProviderParams {
+            issuer: Some(issuer.to_owned()),
+            human_name: None,
+            brand_name: None,
+            discovery_mode: UpstreamOAuthProviderDiscoveryMode::Oidc,
+            pkce_mode: UpstreamOAuthProviderPkceMode::Auto,
+            jwks_uri_override: None,
+            authorization_endpoint_override: None,
+            token_endpoint_override: None,
+            userinfo_endpoint_override: None,
+            fetch_userinfo: false,
+            userinfo_signed_response_alg: None,
+            client_id: "client-id".to_owned(),
+            encrypted_client_secret: Some("encrypted-secret".to_owned()),
+            token_endpoint_signing_alg: None,
+            token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::ClientSecretPost,
+            id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+            response_mode: None,
+            scope: Scope::from_iter([OPENID]),
+            claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+            additional_authorization_parameters: vec![],
+            forward_login_hint: false,
+            on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+            ui_order: 0,
+            ui_hidden: false,
+        }
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_effective_site_config(pool: PgPool) {
+        setup();
+        let site_config = mas_data_model::SiteConfig {
+            compat_token_ttl: Duration::hours(1),
+            registration_token_required: true,
+            prefer_password_login: false,
+            email_probe: Some(EmailProbeConfig {
+                recipient: Some("probe@example.com".to_owned()),
+            }),
+            ..test_site_config()
+        };
+        let mut state = TestState::from_pool_with_site_config(pool, site_config)
+            .await
+            .unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        // Only the enabled providers are listed
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                provider_params("https://accounts.google.com"),
+            )
+            .await
+            .unwrap();
+        let disabled = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                provider_params("https://disabled.example.com"),
+            )
+            .await
+            .unwrap();
+        repo.upstream_oauth_provider()
+            .disable(&state.clock, disabled)
+            .await
+            .unwrap();
+        Box::new(repo).save().await.unwrap();
+
+        let request = Request::get("/api/admin/v1/site-config")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let mut body: serde_json::Value = response.json();
+
+        // The provider ID and the template paths depend on the environment
+        assert_eq!(
+            body["upstream_providers"][0]["id"],
+            provider.id.to_string().as_str()
+        );
+        body["upstream_providers"][0]["id"] = "[id]".into();
+        let templates = body.as_object_mut().unwrap().remove("templates").unwrap();
+        assert!(templates["path"].as_str().unwrap().ends_with("templates"));
+        assert!(
+            templates["translations_path"]
+                .as_str()
+                .unwrap()
+                .ends_with("translations")
+        );
+        assert!(
+            templates["assets_manifest"]
+                .as_str()
+                .unwrap()
+                .ends_with("manifest.json")
+        );
+
+        assert_json_snapshot!(body, @r#"
+        {
+          "server_name": "example.com",
+          "password_login_enabled": true,
+          "password_registration_enabled": true,
+          "password_registration_email_required": true,
+          "registration_token_required": true,
+          "email_change_allowed": true,
+          "displayname_change_allowed": true,
+          "password_change_allowed": true,
+          "account_recovery_allowed": true,
+          "account_deactivation_allowed": true,
+          "captcha_enabled": false,
+          "minimum_password_complexity": 1,
+          "access_token_ttl": 300,
+          "compat_token_ttl": 3600,
+          "policy_uri": "https://example.com/policy",
+          "tos_uri": "https://example.com/tos",
+          "email_change_double_confirmation": false,
+          "new_login_notification": false,
+          "login_with_email_allowed": true,
+          "auto_redirect_single_provider": true,
+          "prefer_password_login": false,
+          "account_recovery_email_interval": 300,
+          "deleted_user_retention": 2592000,
+          "admin_api_csv_export_limit": 100000,
+          "audit_webhook_enabled": false,
+          "email_probe_enabled": true,
+          "sync_emails_to_homeserver": true,
+          "rate_limits": {
+            "account_recovery.per_address": {
+              "burst": 3,
+              "per_second": 0.0002777777777777778
+            },
+            "account_recovery.per_ip": {
+              "burst": 3,
+              "per_second": 0.0008333333333333334
+            },
+            "admin_api.read": {
+              "burst": 300,
+              "per_second": 10.0
+            },
+            "admin_api.write": {
+              "burst": 60,
+              "per_second": 2.0
+            },
+            "device_code_confirmation": {
+              "burst": 5,
+              "per_second": 0.0002777777777777778
+            },
+            "email_authentication.attempt_per_session": {
+              "burst": 10,
+              "per_second": 0.016666666666666666
+            },
+            "email_authentication.emails_per_session": {
+              "burst": 2,
+              "per_second": 0.0033333333333333335
+            },
+            "email_authentication.per_address": {
+              "burst": 3,
+              "per_second": 0.0002777777777777778
+            },
+            "email_authentication.per_ip": {
+              "burst": 5,
+              "per_second": 0.016666666666666666
+            },
+            "login.per_account": {
+              "burst": 1800,
+              "per_second": 0.5
+            },
+            "login.per_ip": {
+              "burst": 3,
+              "per_second": 0.05
+            },
+            "registration": {
+              "burst": 3,
+              "per_second": 0.0008333333333333334
+            }
+          },
+          "upstream_providers": [
+            {
+              "id": "[id]",
+              "issuer": "https://accounts.google.com"
+            }
+          ]
+        }
+        "#);
+    }
 }
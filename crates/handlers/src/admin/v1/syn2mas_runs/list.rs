@@ -0,0 +1,175 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_storage::Page;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::{Resource, Syn2masRun},
+        params::{IncludeCount, Pagination},
+        response::{ErrorResponse, PaginatedResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("listSyn2masRuns")
+        .summary("List the syn2mas migrations")
+        .description(
+            "Lists the runs of the syn2mas tool against this database, oldest first. Users \
+migrated by a run can be listed with the `filter[migrated]` parameter of the user list.",
+        )
+        .tag("syn2mas-run")
+        .response_with::<200, PaginatedResponse<Syn2masRun>, _>(|t| {
+            let runs = Syn2masRun::samples();
+            let pagination = mas_storage::Pagination::first(runs.len());
+            let page = Page {
+                edges: runs
+                    .into_iter()
+                    .map(|node| mas_storage::pagination::Edge {
+                        cursor: node.id(),
+                        node,
+                    })
+                    .collect(),
+                has_next_page: false,
+                has_previous_page: false,
+            };
+
+            t.description("Paginated response of syn2mas runs").example(
+                PaginatedResponse::for_page(page, pagination, Some(2), Syn2masRun::PATH),
+            )
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.syn2mas_runs.list", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    Pagination(pagination, include_count): Pagination,
+) -> Result<PaginatedResponse<Syn2masRun>, RouteError> {
+    let base = include_count.add_to_base(Syn2masRun::PATH);
+
+    let response = match include_count {
+        IncludeCount::True => {
+            let page = repo
+                .syn2mas_run()
+                .list(pagination)
+                .await?
+                .map(Syn2masRun::from);
+            let count = repo.syn2mas_run().count().await?;
+            PaginatedResponse::for_page(page, pagination, Some(count), &base)
+        }
+        IncludeCount::False => {
+            let page = repo
+                .syn2mas_run()
+                .list(pagination)
+                .await?
+                .map(Syn2masRun::from);
+            PaginatedResponse::for_page(page, pagination, None, &base)
+        }
+        IncludeCount::Only => {
+            let count = repo.syn2mas_run().count().await?;
+            PaginatedResponse::for_count_only(count, &base)
+        }
+    };
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_data_model::Clock as _;
+    use sqlx::{PgPool, types::Uuid};
+    use ulid::Ulid;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let request = Request::get("/api/admin/v1/migrations")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 0);
+
+        // Those are only ever written by syn2mas, so insert them directly: an
+        // interrupted run, followed by a finished one
+        let now = state.clock.now();
+        let interrupted = Ulid::from_datetime_with_source(now.into(), &mut rng);
+        state.clock.advance(Duration::try_minutes(5).unwrap());
+        let finished = Ulid::from_datetime_with_source(state.clock.now().into(), &mut rng);
+        sqlx::query(
+            r"
+                INSERT INTO syn2mas_runs
+                    (syn2mas_run_id, started_at, dry_run, finished_at, user_count, compat_session_count)
+                VALUES
+                    ($1, $2, false, NULL, NULL, NULL),
+                    ($3, $4, false, $4, 3, 5)
+            ",
+        )
+        .bind(Uuid::from(interrupted))
+        .bind(now)
+        .bind(Uuid::from(finished))
+        .bind(state.clock.now())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let request = Request::get("/api/admin/v1/migrations")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 2);
+
+        assert_eq!(body["data"][0]["type"], "syn2mas-run");
+        assert_eq!(body["data"][0]["id"], interrupted.to_string());
+        let attributes = &body["data"][0]["attributes"];
+        assert_eq!(attributes["dry_run"], false);
+        assert_eq!(attributes["finished_at"], serde_json::Value::Null);
+        assert_eq!(attributes["user_count"], serde_json::Value::Null);
+
+        assert_eq!(body["data"][1]["id"], finished.to_string());
+        let attributes = &body["data"][1]["attributes"];
+        assert_eq!(attributes["finished_at"], "2022-01-16T14:45:00Z");
+        assert_eq!(attributes["user_count"], 3);
+        assert_eq!(attributes["compat_session_count"], 5);
+    }
+}
@@ -105,7 +105,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .summary("List upstream OAuth 2.0 links")
         .description("Retrieve a list of upstream OAuth 2.0 links.")
         .tag("upstream-oauth-link")
-        .response_with::<200, Json<PaginatedResponse<UpstreamOAuthLink>>, _>(|t| {
+        .response_with::<200, PaginatedResponse<UpstreamOAuthLink>, _>(|t| {
             let links = UpstreamOAuthLink::samples();
             let pagination = mas_storage::Pagination::first(links.len());
             let page = Page {
@@ -140,7 +140,7 @@ pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
     Pagination(pagination, include_count): Pagination,
     params: FilterParams,
-) -> Result<Json<PaginatedResponse<UpstreamOAuthLink>>, RouteError> {
+) -> Result<PaginatedResponse<UpstreamOAuthLink>, RouteError> {
     let base = format!("{path}{params}", path = UpstreamOAuthLink::PATH);
     let base = include_count.add_to_base(&base);
     let filter = UpstreamOAuthLinkFilter::default();
@@ -211,7 +211,7 @@ pub async fn handler(
         }
     };
 
-    Ok(Json(response))
+    Ok(response)
 }
 
 #[cfg(test)]
@@ -4,7 +4,7 @@
 // Please see LICENSE files in the repository root for full details.
 
 use aide::{OperationIo, transform::TransformOperation};
-use axum::{Json, response::IntoResponse};
+use axum::{Json, extract::State, response::IntoResponse};
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
 use mas_storage::{RepositoryAccess, upstream_oauth2::UpstreamOAuthProviderRepository};
@@ -17,6 +17,7 @@ use crate::{
         response::{ErrorResponse, SingleResponse},
     },
     impl_from_error_for_route,
+    upstream_oauth2::cache::MetadataCache,
 };
 
 #[derive(Debug, thiserror::Error, OperationIo)]
@@ -60,6 +61,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 #[tracing::instrument(name = "handler.admin.v1.upstream_oauth_providers.get", skip_all)]
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
+    State(metadata_cache): State<MetadataCache>,
     id: UlidPathParam,
 ) -> Result<Json<SingleResponse<UpstreamOAuthProvider>>, RouteError> {
     let provider = repo
@@ -68,8 +70,11 @@ pub async fn handler(
         .await?
         .ok_or(RouteError::NotFound)?;
 
+    let statuses = metadata_cache.discovery_statuses().await;
+    let status = statuses.for_provider(&provider);
+
     Ok(Json(SingleResponse::new_canonical(
-        UpstreamOAuthProvider::from(provider),
+        UpstreamOAuthProvider::from(provider).with_discovery_status(status),
     )))
 }
 
@@ -119,6 +124,7 @@ mod tests {
             forward_login_hint: false,
             on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
             ui_order: 0,
+            ui_hidden: false,
         };
 
         let provider = repo
@@ -163,8 +169,10 @@ mod tests {
               "issuer": "https://accounts.google.com",
               "human_name": "Google",
               "brand_name": "google",
+              "scope": "openid",
               "created_at": "2022-01-16T14:40:00Z",
-              "disabled_at": null
+              "disabled_at": null,
+              "discovery_status": null
             },
             "links": {
               "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG0MZAA6S4AF7CTV32E"
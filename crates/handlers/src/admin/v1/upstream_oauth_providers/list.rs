@@ -4,7 +4,7 @@
 // Please see LICENSE files in the repository root for full details.
 
 use aide::{OperationIo, transform::TransformOperation};
-use axum::{Json, response::IntoResponse};
+use axum::{Json, extract::State, response::IntoResponse};
 use axum_extra::extract::{Query, QueryRejection};
 use axum_macros::FromRequestParts;
 use hyper::StatusCode;
@@ -21,6 +21,7 @@ use crate::{
         response::{ErrorResponse, PaginatedResponse},
     },
     impl_from_error_for_route,
+    upstream_oauth2::cache::MetadataCache,
 };
 
 #[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
@@ -77,7 +78,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .id("listUpstreamOAuthProviders")
         .summary("List upstream OAuth 2.0 providers")
         .tag("upstream-oauth-provider")
-        .response_with::<200, Json<PaginatedResponse<UpstreamOAuthProvider>>, _>(|t| {
+        .response_with::<200, PaginatedResponse<UpstreamOAuthProvider>, _>(|t| {
             let providers = UpstreamOAuthProvider::samples();
             let pagination = mas_storage::Pagination::first(providers.len());
             let page = Page {
@@ -105,9 +106,10 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 #[tracing::instrument(name = "handler.admin.v1.upstream_oauth_providers.list", skip_all)]
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
+    State(metadata_cache): State<MetadataCache>,
     Pagination(pagination, include_count): Pagination,
     params: FilterParams,
-) -> Result<Json<PaginatedResponse<UpstreamOAuthProvider>>, RouteError> {
+) -> Result<PaginatedResponse<UpstreamOAuthProvider>, RouteError> {
     let base = format!("{path}{params}", path = UpstreamOAuthProvider::PATH);
     let base = include_count.add_to_base(&base);
     let filter = UpstreamOAuthProviderFilter::new();
@@ -118,13 +120,21 @@ pub async fn handler(
         None => filter,
     };
 
+    // This only looks at the outcome of the last discovery, and never triggers
+    // a fetch, so that listing providers is never blocked by a slow provider
+    let statuses = metadata_cache.discovery_statuses().await;
+    let into_resource = |provider: mas_data_model::UpstreamOAuthProvider| {
+        let status = statuses.for_provider(&provider);
+        UpstreamOAuthProvider::from(provider).with_discovery_status(status)
+    };
+
     let response = match include_count {
         IncludeCount::True => {
             let page = repo
                 .upstream_oauth_provider()
                 .list(filter, pagination)
                 .await?
-                .map(UpstreamOAuthProvider::from);
+                .map(into_resource);
             let count = repo.upstream_oauth_provider().count(filter).await?;
             PaginatedResponse::for_page(page, pagination, Some(count), &base)
         }
@@ -133,7 +143,7 @@ pub async fn handler(
                 .upstream_oauth_provider()
                 .list(filter, pagination)
                 .await?
-                .map(UpstreamOAuthProvider::from);
+                .map(into_resource);
             PaginatedResponse::for_page(page, pagination, None, &base)
         }
         IncludeCount::Only => {
@@ -142,7 +152,7 @@ pub async fn handler(
         }
     };
 
-    Ok(Json(response))
+    Ok(response)
 }
 
 #[cfg(test)]
@@ -191,6 +201,7 @@ mod tests {
             forward_login_hint: false,
             on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
             ui_order: 0,
+            ui_hidden: false,
         };
 
         repo.upstream_oauth_provider()
@@ -223,6 +234,7 @@ mod tests {
             forward_login_hint: false,
             on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
             ui_order: 1,
+            ui_hidden: false,
         };
 
         let disabled_provider = repo
@@ -262,6 +274,7 @@ mod tests {
             forward_login_hint: false,
             on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
             ui_order: 2,
+            ui_hidden: false,
         };
 
         repo.upstream_oauth_provider()
@@ -303,8 +316,10 @@ mod tests {
                 "issuer": "https://appleid.apple.com",
                 "human_name": "Apple ID",
                 "brand_name": "apple",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": "2022-01-16T14:40:00Z"
+                "disabled_at": "2022-01-16T14:40:00Z",
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG07HNEZXNQM2KNBNF6"
@@ -322,8 +337,10 @@ mod tests {
                 "issuer": "https://login.microsoftonline.com/common/v2.0",
                 "human_name": "Microsoft",
                 "brand_name": "microsoft",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": null
+                "disabled_at": null,
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG09AVTNSQFMSR34AJC"
@@ -341,8 +358,10 @@ mod tests {
                 "issuer": "https://accounts.google.com",
                 "human_name": "Google",
                 "brand_name": "google",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": null
+                "disabled_at": null,
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG0MZAA6S4AF7CTV32E"
@@ -391,8 +410,10 @@ mod tests {
                 "issuer": "https://login.microsoftonline.com/common/v2.0",
                 "human_name": "Microsoft",
                 "brand_name": "microsoft",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": null
+                "disabled_at": null,
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG09AVTNSQFMSR34AJC"
@@ -410,8 +431,10 @@ mod tests {
                 "issuer": "https://accounts.google.com",
                 "human_name": "Google",
                 "brand_name": "google",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": null
+                "disabled_at": null,
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG0MZAA6S4AF7CTV32E"
@@ -460,8 +483,10 @@ mod tests {
                 "issuer": "https://appleid.apple.com",
                 "human_name": "Apple ID",
                 "brand_name": "apple",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": "2022-01-16T14:40:00Z"
+                "disabled_at": "2022-01-16T14:40:00Z",
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG07HNEZXNQM2KNBNF6"
@@ -511,8 +536,10 @@ mod tests {
                 "issuer": "https://appleid.apple.com",
                 "human_name": "Apple ID",
                 "brand_name": "apple",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": "2022-01-16T14:40:00Z"
+                "disabled_at": "2022-01-16T14:40:00Z",
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG07HNEZXNQM2KNBNF6"
@@ -530,8 +557,10 @@ mod tests {
                 "issuer": "https://login.microsoftonline.com/common/v2.0",
                 "human_name": "Microsoft",
                 "brand_name": "microsoft",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": null
+                "disabled_at": null,
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG09AVTNSQFMSR34AJC"
@@ -577,8 +606,10 @@ mod tests {
                 "issuer": "https://accounts.google.com",
                 "human_name": "Google",
                 "brand_name": "google",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": null
+                "disabled_at": null,
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG0MZAA6S4AF7CTV32E"
@@ -639,8 +670,10 @@ mod tests {
                 "issuer": "https://appleid.apple.com",
                 "human_name": "Apple ID",
                 "brand_name": "apple",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": "2022-01-16T14:40:00Z"
+                "disabled_at": "2022-01-16T14:40:00Z",
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG07HNEZXNQM2KNBNF6"
@@ -658,8 +691,10 @@ mod tests {
                 "issuer": "https://login.microsoftonline.com/common/v2.0",
                 "human_name": "Microsoft",
                 "brand_name": "microsoft",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": null
+                "disabled_at": null,
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG09AVTNSQFMSR34AJC"
@@ -677,8 +712,10 @@ mod tests {
                 "issuer": "https://accounts.google.com",
                 "human_name": "Google",
                 "brand_name": "google",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": null
+                "disabled_at": null,
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG0MZAA6S4AF7CTV32E"
@@ -736,8 +773,10 @@ mod tests {
                 "issuer": "https://login.microsoftonline.com/common/v2.0",
                 "human_name": "Microsoft",
                 "brand_name": "microsoft",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": null
+                "disabled_at": null,
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG09AVTNSQFMSR34AJC"
@@ -755,8 +794,10 @@ mod tests {
                 "issuer": "https://accounts.google.com",
                 "human_name": "Google",
                 "brand_name": "google",
+                "scope": "openid",
                 "created_at": "2022-01-16T14:40:00Z",
-                "disabled_at": null
+                "disabled_at": null,
+                "discovery_status": null
               },
               "links": {
                 "self": "/api/admin/v1/upstream-oauth-providers/01FSHN9AG0MZAA6S4AF7CTV32E"
@@ -5,8 +5,10 @@
 
 mod get;
 mod list;
+mod refresh_metadata;
 
 pub use self::{
     get::{doc as get_doc, handler as get},
     list::{doc as list_doc, handler as list},
+    refresh_metadata::{doc as refresh_metadata_doc, handler as refresh_metadata},
 };
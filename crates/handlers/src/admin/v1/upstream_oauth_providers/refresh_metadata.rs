@@ -0,0 +1,350 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, extract::State, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_oidc_client::error::DiscoveryError;
+use mas_storage::{RepositoryAccess, upstream_oauth2::UpstreamOAuthProviderRepository};
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::{Resource, UpstreamOAuthProvider},
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+    upstream_oauth2::cache::MetadataCache,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Provider ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("Metadata discovery is disabled for provider ID {0}")]
+    DiscoveryDisabled(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::DiscoveryDisabled(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("refreshUpstreamOAuthProviderMetadata")
+        .summary("Refresh the metadata of an upstream OAuth 2.0 provider")
+        .description("Calling this endpoint will re-fetch the discovery document of the provider, bypassing the cache.
+The outcome of the fetch is reported in the `discovery_status` of the returned provider: a failure to fetch the metadata does not make this request fail.")
+        .tag("upstream-oauth-provider")
+        .response_with::<200, Json<SingleResponse<UpstreamOAuthProvider>>, _>(|t| {
+            let [sample, ..] = UpstreamOAuthProvider::samples();
+            let id = sample.id();
+            let response = SingleResponse::new(
+                sample,
+                format!("/api/admin/v1/upstream-oauth-providers/{id}/refresh-metadata"),
+            );
+            t.description("The metadata of the provider was re-fetched")
+                .example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::DiscoveryDisabled(Ulid::nil()));
+            t.description("Metadata discovery is disabled for this provider")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("Provider ID not found").example(response)
+        })
+}
+
+#[tracing::instrument(
+    name = "handler.admin.v1.upstream_oauth_providers.refresh_metadata",
+    skip_all
+)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    State(metadata_cache): State<MetadataCache>,
+    State(http_client): State<reqwest::Client>,
+    id: UlidPathParam,
+) -> Result<Json<SingleResponse<UpstreamOAuthProvider>>, RouteError> {
+    let id = *id;
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    match metadata_cache.refresh(&http_client, &provider).await {
+        Ok(_) => {}
+        Err(DiscoveryError::Disabled | DiscoveryError::MissingIssuer) => {
+            return Err(RouteError::DiscoveryDisabled(id));
+        }
+        Err(e) => {
+            // The failure is recorded in the discovery status, which is
+            // returned below
+            tracing::warn!(
+                error = &e as &dyn std::error::Error,
+                "Failed to refresh provider metadata"
+            );
+        }
+    }
+
+    let statuses = metadata_cache.discovery_statuses().await;
+    let status = statuses.for_provider(&provider);
+
+    Ok(Json(SingleResponse::new(
+        UpstreamOAuthProvider::from(provider).with_discovery_status(status),
+        format!("/api/admin/v1/upstream-oauth-providers/{id}/refresh-metadata"),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{
+        UpstreamOAuthProvider, UpstreamOAuthProviderClaimsImports,
+        UpstreamOAuthProviderDiscoveryMode, UpstreamOAuthProviderOnBackchannelLogout,
+        UpstreamOAuthProviderPkceMode, UpstreamOAuthProviderTokenAuthMethod,
+    };
+    use mas_iana::jose::JsonWebSignatureAlg;
+    use mas_storage::{
+        RepositoryAccess,
+        upstream_oauth2::{UpstreamOAuthProviderParams, UpstreamOAuthProviderRepository},
+    };
+    use oauth2_types::scope::{OPENID, Scope};
+    use sqlx::PgPool;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    async fn create_provider(
+        state: &mut TestState,
+        issuer: String,
+        discovery_mode: UpstreamOAuthProviderDiscoveryMode,
+    ) -> UpstreamOAuthProvider {
+        let mut repo = state.repository().await.unwrap();
+
+        let params = UpstreamOAuthProviderParams {
+            issuer: Some(issuer),
+            human_name: Some("Example".to_owned()),
+            brand_name: None,
+            discovery_mode,
+            pkce_mode: UpstreamOAuthProviderPkceMode::Auto,
+            jwks_uri_override: None,
+            authorization_endpoint_override: None,
+            token_endpoint_override: None,
+            userinfo_endpoint_override: None,
+            fetch_userinfo: false,
+            userinfo_signed_response_alg: None,
+            client_id: "client-id".to_owned(),
+            encrypted_client_secret: None,
+            token_endpoint_signing_alg: None,
+            token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+            id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+            response_mode: None,
+            scope: Scope::from_iter([OPENID]),
+            claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+            additional_authorization_parameters: vec![],
+            forward_login_hint: false,
+            on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+            ui_order: 0,
+            ui_hidden: false,
+        };
+
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(&mut state.rng(), &state.clock, params)
+            .await
+            .unwrap();
+
+        Box::new(repo).save().await.unwrap();
+
+        provider
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_refresh_healthy_provider(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let admin_token = state.token_with_scope("urn:mas:admin").await;
+
+        let mock_server = MockServer::start().await;
+        let _mock_guard = Mock::given(method("GET"))
+            .and(path("/.well-known/openid-configuration"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "issuer": mock_server.uri(),
+                "authorization_endpoint": "https://example.com/authorize",
+                "token_endpoint": "https://example.com/token",
+                "jwks_uri": "https://example.com/jwks",
+                "userinfo_endpoint": "https://example.com/userinfo",
+                "scopes_supported": ["openid"],
+                "response_types_supported": ["code"],
+                "response_modes_supported": ["query", "fragment"],
+                "grant_types_supported": ["authorization_code"],
+                "subject_types_supported": ["public"],
+                "id_token_signing_alg_values_supported": ["RS256"],
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let provider = create_provider(
+            &mut state,
+            mock_server.uri(),
+            UpstreamOAuthProviderDiscoveryMode::Insecure,
+        )
+        .await;
+
+        // Before any discovery, the status is unknown
+        let request = Request::get(format!(
+            "/api/admin/v1/upstream-oauth-providers/{}",
+            provider.id
+        ))
+        .bearer(&admin_token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"]["attributes"]["discovery_status"],
+            serde_json::Value::Null
+        );
+
+        let request = Request::post(format!(
+            "/api/admin/v1/upstream-oauth-providers/{}/refresh-metadata",
+            provider.id
+        ))
+        .bearer(&admin_token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        let status = &body["data"]["attributes"]["discovery_status"];
+        assert_eq!(status["ok"], true);
+        assert_eq!(status["error"], serde_json::Value::Null);
+        assert!(status["fetched_at"].is_string());
+
+        // The status is then visible when listing providers, without fetching the
+        // metadata again
+        let request = Request::get("/api/admin/v1/upstream-oauth-providers")
+            .bearer(&admin_token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"][0]["attributes"]["discovery_status"]["ok"],
+            true
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_refresh_failing_provider(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let admin_token = state.token_with_scope("urn:mas:admin").await;
+
+        let mock_server = MockServer::start().await;
+        let _mock_guard = Mock::given(method("GET"))
+            .and(path("/.well-known/openid-configuration"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let provider = create_provider(
+            &mut state,
+            mock_server.uri(),
+            UpstreamOAuthProviderDiscoveryMode::Insecure,
+        )
+        .await;
+
+        let request = Request::post(format!(
+            "/api/admin/v1/upstream-oauth-providers/{}/refresh-metadata",
+            provider.id
+        ))
+        .bearer(&admin_token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        let status = &body["data"]["attributes"]["discovery_status"];
+        assert_eq!(status["ok"], false);
+        assert!(status["error"].is_string());
+        assert!(status["fetched_at"].is_string());
+
+        // The failure is also reported when listing providers
+        let request = Request::get("/api/admin/v1/upstream-oauth-providers")
+            .bearer(&admin_token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"][0]["attributes"]["discovery_status"]["ok"],
+            false
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_refresh_discovery_disabled(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let admin_token = state.token_with_scope("urn:mas:admin").await;
+
+        let provider = create_provider(
+            &mut state,
+            "https://example.com/".to_owned(),
+            UpstreamOAuthProviderDiscoveryMode::Disabled,
+        )
+        .await;
+
+        let request = Request::post(format!(
+            "/api/admin/v1/upstream-oauth-providers/{}/refresh-metadata",
+            provider.id
+        ))
+        .bearer(&admin_token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_refresh_not_found(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let admin_token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post(
+            "/api/admin/v1/upstream-oauth-providers/01040G2081040G2081040G2081/refresh-metadata",
+        )
+        .bearer(&admin_token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
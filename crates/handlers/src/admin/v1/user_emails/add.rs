@@ -3,17 +3,12 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::str::FromStr as _;
-
 use aide::{NoApi, OperationIo, transform::TransformOperation};
 use axum::{Json, response::IntoResponse};
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
 use mas_data_model::BoxRng;
-use mas_storage::{
-    queue::{ProvisionUserJob, QueueJobRepositoryExt as _},
-    user::UserEmailFilter,
-};
+use mas_storage::queue::{QueueJobRepositoryExt as _, SendEmailAddedEmailJob};
 use schemars::JsonSchema;
 use serde::Deserialize;
 use ulid::Ulid;
@@ -25,6 +20,7 @@ use crate::{
         response::{ErrorResponse, SingleResponse},
     },
     impl_from_error_for_route,
+    user_actions::{AddEmailError, add_email},
 };
 
 #[derive(Debug, thiserror::Error, OperationIo)]
@@ -50,6 +46,16 @@ pub enum RouteError {
 
 impl_from_error_for_route!(mas_storage::RepositoryError);
 
+impl From<AddEmailError> for RouteError {
+    fn from(err: AddEmailError) -> Self {
+        match err {
+            AddEmailError::EmailNotValid { email, source } => Self::EmailNotValid { email, source },
+            AddEmailError::EmailAlreadyInUse(email) => Self::EmailAlreadyInUse(email),
+            AddEmailError::Repository(err) => Self::Internal(Box::new(err)),
+        }
+    }
+}
+
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
         let error = ErrorResponse::from_error(&self);
@@ -75,6 +81,11 @@ pub struct Request {
     /// The email address of the user to add.
     #[schemars(email)]
     email: String,
+
+    /// Whether to notify the user by email that this address was added to
+    /// their account. Defaults to `false`.
+    #[serde(default)]
+    notify_user: bool,
 }
 
 pub fn doc(operation: TransformOperation) -> TransformOperation {
@@ -82,7 +93,9 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .id("addUserEmail")
         .summary("Add a user email")
         .description(r"Add an email address to a user.
-Note that this endpoint ignores any policy which would normally prevent the email from being added.")
+Note that this endpoint ignores any policy which would normally prevent the email from being added.
+
+The user can optionally be notified by email that the address was added, with the `notify_user` flag.")
         .tag("user-email")
         .response_with::<201, Json<SingleResponse<UserEmail>>, _>(|t| {
             let [sample, ..] = UserEmail::samples();
@@ -123,35 +136,18 @@ pub async fn handler(
         .await?
         .ok_or(RouteError::UserNotFound(params.user_id))?;
 
-    // Validate the email
-    if let Err(source) = lettre::Address::from_str(&params.email) {
-        return Err(RouteError::EmailNotValid {
-            email: params.email,
-            source,
-        });
-    }
+    let user_email = add_email(&mut repo, &mut rng, &clock, &user, params.email).await?;
 
-    // Check if the email already exists
-    let count = repo
-        .user_email()
-        .count(UserEmailFilter::new().for_email(&params.email))
-        .await?;
-
-    if count > 0 {
-        return Err(RouteError::EmailAlreadyInUse(params.email));
+    if params.notify_user {
+        repo.queue_job()
+            .schedule_job(
+                &mut rng,
+                &clock,
+                SendEmailAddedEmailJob::new(&user_email, "en".to_owned()),
+            )
+            .await?;
     }
 
-    // Add the email to the user
-    let user_email = repo
-        .user_email()
-        .add(&mut rng, &clock, &user, params.email)
-        .await?;
-
-    // Schedule a job to update the user
-    repo.queue_job()
-        .schedule_job(&mut rng, &clock, ProvisionUserJob::new_for_id(user.id))
-        .await?;
-
     repo.save().await?;
 
     Ok((
@@ -164,10 +160,12 @@ pub async fn handler(
 mod tests {
     use hyper::{Request, StatusCode};
     use insta::assert_json_snapshot;
+    use mas_data_model::SiteConfig;
+    use mas_matrix::HomeserverConnection as _;
     use sqlx::PgPool;
     use ulid::Ulid;
 
-    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup, test_site_config};
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_create(pool: PgPool) {
         setup();
@@ -214,6 +212,113 @@ mod tests {
         "###);
     }
 
+    async fn count_jobs(pool: &PgPool, queue_name: &str) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM queue_jobs WHERE queue_name = $1")
+            .bind(queue_name)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_create_notify_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // By default, only the homeserver gets updated
+        let request = Request::post("/api/admin/v1/user-emails")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "email": "alice@example.com",
+                "user_id": alice.id,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        assert_eq!(count_jobs(&pool, "provision-user").await, 1);
+        assert_eq!(count_jobs(&pool, "send-email-added-email").await, 0);
+
+        // The user can be notified as well
+        let request = Request::post("/api/admin/v1/user-emails")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "email": "alice@example.org",
+                "user_id": alice.id,
+                "notify_user": true,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        assert_eq!(count_jobs(&pool, "provision-user").await, 2);
+        assert_eq!(count_jobs(&pool, "send-email-added-email").await, 1);
+
+        // Explicitly not notifying the user
+        let request = Request::post("/api/admin/v1/user-emails")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "email": "alice@example.net",
+                "user_id": alice.id,
+                "notify_user": false,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        assert_eq!(count_jobs(&pool, "provision-user").await, 3);
+        assert_eq!(count_jobs(&pool, "send-email-added-email").await, 1);
+
+        // The notification job goes through
+        state.run_jobs_in_queue().await;
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_create_without_email_sync(pool: PgPool) {
+        setup();
+        let site_config = SiteConfig {
+            sync_emails_to_homeserver: false,
+            ..test_site_config()
+        };
+        let mut state = TestState::from_pool_with_site_config(pool, site_config)
+            .await
+            .unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post("/api/admin/v1/user-emails")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "email": "alice@example.com",
+                "user_id": alice.id,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+
+        // The user got provisioned, but without touching its emails
+        state.run_jobs_in_queue().await;
+        assert!(
+            state
+                .homeserver_connection
+                .query_user("alice")
+                .await
+                .is_ok()
+        );
+        assert_eq!(state.homeserver_connection.emails("alice").await, None);
+    }
+
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_user_not_found(pool: PgPool) {
         setup();
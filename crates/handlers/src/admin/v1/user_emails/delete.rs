@@ -125,6 +125,60 @@ mod tests {
         response.assert_status(StatusCode::NOT_FOUND);
     }
 
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_delete_syncs_homeserver(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Add two emails through the API, which provisions the user on the
+        // homeserver with both of them
+        let mut ids = Vec::new();
+        for email in ["alice@example.com", "alice@example.org"] {
+            let request = Request::post("/api/admin/v1/user-emails")
+                .bearer(&token)
+                .json(serde_json::json!({
+                    "email": email,
+                    "user_id": alice.id,
+                }));
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::CREATED);
+            let body: serde_json::Value = response.json();
+            ids.push(body["data"]["id"].as_str().unwrap().to_owned());
+        }
+
+        state.run_jobs_in_queue().await;
+        assert_eq!(
+            state.homeserver_connection.emails("alice").await,
+            Some(vec![
+                "alice@example.com".to_owned(),
+                "alice@example.org".to_owned()
+            ])
+        );
+
+        // Deleting one of them removes it from the homeserver
+        let request = Request::delete(format!("/api/admin/v1/user-emails/{}", ids[0]))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+
+        state.run_jobs_in_queue().await;
+        assert_eq!(
+            state.homeserver_connection.emails("alice").await,
+            Some(vec!["alice@example.org".to_owned()])
+        );
+    }
+
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_not_found(pool: PgPool) {
         setup();
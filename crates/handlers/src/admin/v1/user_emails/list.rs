@@ -4,11 +4,12 @@
 // Please see LICENSE files in the repository root for full details.
 
 use aide::{OperationIo, transform::TransformOperation};
-use axum::{Json, response::IntoResponse};
+use axum::{Json, extract::State, response::IntoResponse};
 use axum_extra::extract::{Query, QueryRejection};
 use axum_macros::FromRequestParts;
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
+use mas_data_model::{SiteConfig, User};
 use mas_storage::{Page, user::UserEmailFilter};
 use schemars::JsonSchema;
 use serde::Deserialize;
@@ -17,8 +18,9 @@ use ulid::Ulid;
 use crate::{
     admin::{
         call_context::CallContext,
+        export::{CsvExport, ListResponse, document_csv_export},
         model::{Resource, UserEmail},
-        params::{IncludeCount, Pagination},
+        params::{ExportFormat, Format, IncludeCount, Pagination},
         response::{ErrorResponse, PaginatedResponse},
     },
     impl_from_error_for_route,
@@ -92,7 +94,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .summary("List user emails")
         .description("Retrieve a list of user emails.")
         .tag("user-email")
-        .response_with::<200, Json<PaginatedResponse<UserEmail>>, _>(|t| {
+        .response_with::<200, PaginatedResponse<UserEmail>, _>(|t| {
             let emails = UserEmail::samples();
             let pagination = mas_storage::Pagination::first(emails.len());
             let page = Page {
@@ -119,17 +121,34 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
             let response = ErrorResponse::from_error(&RouteError::UserNotFound(Ulid::nil()));
             t.description("User was not found").example(response)
         })
+        .with(document_csv_export::<UserEmail>)
+}
+
+/// Build the repository filter out of the parameters
+fn build_filter<'a>(user: Option<&'a User>, email: Option<&'a str>) -> UserEmailFilter<'a> {
+    let filter = UserEmailFilter::default();
+
+    let filter = match user {
+        Some(user) => filter.for_user(user),
+        None => filter,
+    };
+
+    match email {
+        Some(email) => filter.for_email(email),
+        None => filter,
+    }
 }
 
 #[tracing::instrument(name = "handler.admin.v1.user_emails.list", skip_all)]
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
+    State(site_config): State<SiteConfig>,
     Pagination(pagination, include_count): Pagination,
+    Format(format): Format,
     params: FilterParams,
-) -> Result<Json<PaginatedResponse<UserEmail>>, RouteError> {
+) -> Result<ListResponse<UserEmail>, RouteError> {
     let base = format!("{path}{params}", path = UserEmail::PATH);
     let base = include_count.add_to_base(&base);
-    let filter = UserEmailFilter::default();
 
     // Load the user from the filter
     let user = if let Some(user_id) = params.user {
@@ -144,15 +163,31 @@ pub async fn handler(
         None
     };
 
-    let filter = match &user {
-        Some(user) => filter.for_user(user),
-        None => filter,
-    };
+    if format == ExportFormat::Csv {
+        let email = params.email;
+        let total = repo
+            .user_email()
+            .count(build_filter(user.as_ref(), email.as_deref()))
+            .await?;
+        let export = CsvExport::new(
+            repo,
+            "user-emails.csv",
+            site_config.admin_api_csv_export_limit,
+            total,
+            move |repo, pagination| {
+                let user = user.clone();
+                let email = email.clone();
+                Box::pin(async move {
+                    let filter = build_filter(user.as_ref(), email.as_deref());
+                    let page = repo.user_email().list(filter, pagination).await?;
+                    Ok(page.map(UserEmail::from))
+                })
+            },
+        );
+        return Ok(ListResponse::Csv(export));
+    }
 
-    let filter = match &params.email {
-        Some(email) => filter.for_email(email),
-        None => filter,
-    };
+    let filter = build_filter(user.as_ref(), params.email.as_deref());
 
     let response = match include_count {
         IncludeCount::True => {
@@ -178,7 +213,7 @@ pub async fn handler(
         }
     };
 
-    Ok(Json(response))
+    Ok(ListResponse::Json(response))
 }
 
 #[cfg(test)]
@@ -0,0 +1,215 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::BoxRng;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::UserNote,
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("The content of the note must not be empty")]
+    EmptyContent,
+
+    #[error("User ID {0} not found")]
+    UserNotFound(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::EmptyContent => StatusCode::BAD_REQUEST,
+            Self::UserNotFound(_) => StatusCode::NOT_FOUND,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/users/:id/notes` endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "AddUserNoteRequest")]
+pub struct Request {
+    /// The content of the note
+    content: String,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("addUserNote")
+        .summary("Leave a note on a user")
+        .description(
+            "The note is attributed to the user who owns the access token used to call this \
+endpoint, if any. Notes are only visible to administrators.",
+        )
+        .tag("user-note")
+        .response_with::<201, Json<SingleResponse<UserNote>>, _>(|t| {
+            let [sample, ..] = UserNote::samples();
+            let response = SingleResponse::new_canonical(sample);
+            t.description("User note was created").example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::EmptyContent);
+            t.description("The content of the note is empty")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::UserNotFound(Ulid::nil()));
+            t.description("User was not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.user_notes.add", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo,
+        clock,
+        user: author,
+        ..
+    }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    id: UlidPathParam,
+    Json(params): Json<Request>,
+) -> Result<(StatusCode, Json<SingleResponse<UserNote>>), RouteError> {
+    let id = *id;
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::UserNotFound(id))?;
+
+    let content = params.content.trim();
+    if content.is_empty() {
+        return Err(RouteError::EmptyContent);
+    }
+
+    let note = repo
+        .user_note()
+        .add(&mut rng, &clock, &user, author.as_ref(), content.to_owned())
+        .await?;
+
+    repo.save().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(SingleResponse::new_canonical(note.into())),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/notes", alice.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "content": "  Called the support desk about a lost device\n",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["type"], "user-note");
+        let attributes = &body["data"]["attributes"];
+        assert_eq!(attributes["user_id"], alice.id.to_string());
+        assert_eq!(
+            attributes["content"],
+            "Called the support desk about a lost device"
+        );
+        // The token was obtained through the client credentials grant, so there
+        // is no author
+        assert_eq!(attributes["author_id"], serde_json::Value::Null);
+
+        // The note was saved
+        let mut repo = state.repository().await.unwrap();
+        let note = repo.user_note().latest(&alice).await.unwrap().unwrap();
+        assert_eq!(body["data"]["id"], note.id.to_string());
+        assert_eq!(note.content, "Called the support desk about a lost device");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add_empty(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/notes", alice.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "content": "   ",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "The content of the note must not be empty"
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add_user_not_found(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/notes", Ulid::nil()))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "content": "Hello",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
@@ -105,7 +105,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .id("listUserRegistrationTokens")
         .summary("List user registration tokens")
         .tag("user-registration-token")
-        .response_with::<200, Json<PaginatedResponse<UserRegistrationToken>>, _>(|t| {
+        .response_with::<200, PaginatedResponse<UserRegistrationToken>, _>(|t| {
             let tokens = UserRegistrationToken::samples();
             let pagination = mas_storage::Pagination::first(tokens.len());
             let page = Page {
@@ -137,7 +137,7 @@ pub async fn handler(
     }: CallContext,
     Pagination(pagination, include_count): Pagination,
     params: FilterParams,
-) -> Result<Json<PaginatedResponse<UserRegistrationToken>>, RouteError> {
+) -> Result<PaginatedResponse<UserRegistrationToken>, RouteError> {
     let base = format!("{path}{params}", path = UserRegistrationToken::PATH);
     let base = include_count.add_to_base(&base);
     let now = clock.now();
@@ -183,7 +183,7 @@ pub async fn handler(
         }
     };
 
-    Ok(Json(response))
+    Ok(response)
 }
 
 #[cfg(test)]
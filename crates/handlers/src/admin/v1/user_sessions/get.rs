@@ -13,7 +13,7 @@ use crate::{
     admin::{
         call_context::CallContext,
         model::UserSession,
-        params::UlidPathParam,
+        params::{UlidPathParam, UserSessionFields},
         response::{ErrorResponse, SingleResponse},
     },
     impl_from_error_for_route,
@@ -64,6 +64,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
     id: UlidPathParam,
+    UserSessionFields(fields): UserSessionFields,
 ) -> Result<Json<SingleResponse<UserSession>>, RouteError> {
     let session = repo
         .browser_session()
@@ -71,9 +72,9 @@ pub async fn handler(
         .await?
         .ok_or(RouteError::NotFound(*id))?;
 
-    Ok(Json(SingleResponse::new_canonical(UserSession::from(
-        session,
-    ))))
+    let response = SingleResponse::new_canonical(UserSession::from(session));
+
+    Ok(Json(response.with_fieldset(&fields)))
 }
 
 #[cfg(test)]
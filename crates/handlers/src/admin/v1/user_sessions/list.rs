@@ -18,7 +18,7 @@ use crate::{
     admin::{
         call_context::CallContext,
         model::{Resource, UserSession},
-        params::{IncludeCount, Pagination},
+        params::{IncludeCount, Pagination, UserSessionFields},
         response::{ErrorResponse, PaginatedResponse},
     },
     impl_from_error_for_route,
@@ -116,7 +116,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 Note that by default, all sessions, including finished ones are returned, with the oldest first.
 Use the `filter[status]` parameter to filter the sessions by their status and `page[last]` parameter to retrieve the last N sessions.")
         .tag("user-session")
-        .response_with::<200, Json<PaginatedResponse<UserSession>>, _>(|t| {
+        .response_with::<200, PaginatedResponse<UserSession>, _>(|t| {
             let sessions = UserSession::samples();
             let pagination = mas_storage::Pagination::first(sessions.len());
             let page = Page {
@@ -149,10 +149,12 @@ Use the `filter[status]` parameter to filter the sessions by their status and `p
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
     Pagination(pagination, include_count): Pagination,
+    UserSessionFields(fields): UserSessionFields,
     params: FilterParams,
-) -> Result<Json<PaginatedResponse<UserSession>>, RouteError> {
+) -> Result<PaginatedResponse<UserSession>, RouteError> {
     let base = format!("{path}{params}", path = UserSession::PATH);
     let base = include_count.add_to_base(&base);
+    let base = fields.add_to_base(&base);
     let filter = BrowserSessionFilter::default();
 
     // Load the user from the filter
@@ -203,7 +205,7 @@ pub async fn handler(
         }
     };
 
-    Ok(Json(response))
+    Ok(response.with_fieldset(&fields))
 }
 
 #[cfg(test)]
@@ -209,8 +209,13 @@ mod tests {
               "created_at": "2022-01-16T14:40:00Z",
               "locked_at": null,
               "deactivated_at": "2022-01-16T14:40:00Z",
+              "deleted_at": null,
               "admin": false,
-              "legacy_guest": false
+              "legacy_guest": false,
+              "organization": null,
+              "max_sessions": null,
+              "external_id": null,
+              "recent_login_failures": []
             },
             "links": {
               "self": "/api/admin/v1/users/01FSHN9AG0MZAA6S4AF7CTV32E"
@@ -290,8 +295,13 @@ mod tests {
               "created_at": "2022-01-16T14:40:00Z",
               "locked_at": "2022-01-16T14:40:00Z",
               "deactivated_at": "2022-01-16T14:41:00Z",
+              "deleted_at": null,
               "admin": false,
-              "legacy_guest": false
+              "legacy_guest": false,
+              "organization": null,
+              "max_sessions": null,
+              "external_id": null,
+              "recent_login_failures": []
             },
             "links": {
               "self": "/api/admin/v1/users/01FSHN9AG0MZAA6S4AF7CTV32E"
@@ -0,0 +1,338 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::BoxRng;
+use tracing::info;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::{Resource, User},
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+    user_actions::delete_user,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("User ID {0} is already deleted")]
+    AlreadyDeleted(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::AlreadyDeleted(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("deleteUser")
+        .summary("Delete a user")
+        .description(
+            "Calling this endpoint will mark the user as deleted and deactivate it, finishing all its sessions and revoking its tokens right away.
+The user and all its data are permanently removed after the configured retention period, and the user is erased from the homeserver at that point.
+Until then, the deletion can be undone with the undelete endpoint, and the username can't be reused.",
+        )
+        .tag("user")
+        .response_with::<200, Json<SingleResponse<User>>, _>(|t| {
+            let [alice, ..] = User::samples();
+            let id = alice.id();
+            let response = SingleResponse::new(alice, format!("/api/admin/v1/users/{id}/delete"));
+            t.description("User was deleted").example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::AlreadyDeleted(Ulid::nil()));
+            t.description("User is already deleted").example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User ID not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.delete", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    id: UlidPathParam,
+) -> Result<Json<SingleResponse<User>>, RouteError> {
+    let id = *id;
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    if user.is_deleted() {
+        return Err(RouteError::AlreadyDeleted(id));
+    }
+
+    let user = delete_user(&mut repo, &mut rng, &clock, user).await?;
+
+    repo.save().await?;
+
+    info!(%user.id, "Deleted user");
+
+    Ok(Json(SingleResponse::new(
+        User::from(user),
+        format!("/api/admin/v1/users/{id}/delete"),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{Clock, Device};
+    use mas_matrix::{HomeserverConnection, ProvisionRequest};
+    use mas_storage::{
+        RepositoryAccess,
+        compat::CompatSessionRepository,
+        queue::{PurgeDeletedUsersJob, QueueJobRepositoryExt as _},
+        user::{BrowserSessionRepository, UserEmailRepository, UserRepository},
+    };
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_delete_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let compat_session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &user, device, None, false, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/delete", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+
+        // The user is both deleted and deactivated
+        assert_eq!(
+            body["data"]["attributes"]["deleted_at"],
+            serde_json::json!(state.clock.now())
+        );
+        assert_eq!(
+            body["data"]["attributes"]["deactivated_at"],
+            serde_json::json!(state.clock.now())
+        );
+
+        // The sessions were finished right away
+        let mut repo = state.repository().await.unwrap();
+        let browser_session = repo
+            .browser_session()
+            .lookup(browser_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(browser_session.finished_at.is_some());
+        let compat_session = repo
+            .compat_session()
+            .lookup(compat_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(compat_session.is_finished());
+        repo.save().await.unwrap();
+
+        // The username can't be reused while the user is kept around
+        let request = Request::post("/api/admin/v1/users")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "username": "alice",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CONFLICT);
+
+        // Deleting the user twice fails
+        let request = Request::post(format!("/api/admin/v1/users/{}/delete", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_purge_deleted_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &alice,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &alice, None)
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Provision alice on the homeserver, as she will be erased from it
+        state
+            .homeserver_connection
+            .provision_user(&ProvisionRequest::new(&alice.username, &alice.sub))
+            .await
+            .unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/delete", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/delete", bob.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        // Run the purge job within the retention period: nothing is removed
+        state.clock.advance(Duration::days(29));
+        let mut repo = state.repository().await.unwrap();
+        repo.queue_job()
+            .schedule_job(&mut rng, &state.clock, PurgeDeletedUsersJob)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        state.run_jobs_in_queue().await;
+
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.user().lookup(alice.id).await.unwrap().is_some());
+        repo.save().await.unwrap();
+
+        // Restore bob within the retention period
+        let request = Request::post(format!("/api/admin/v1/users/{}/undelete", bob.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        // Run the purge job after the retention period
+        state.clock.advance(Duration::days(2));
+        let mut repo = state.repository().await.unwrap();
+        repo.queue_job()
+            .schedule_job(&mut rng, &state.clock, PurgeDeletedUsersJob)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        state.run_jobs_in_queue().await;
+
+        // Everything about alice is gone
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.user().lookup(alice.id).await.unwrap().is_none());
+        assert!(!repo.user().exists("alice").await.unwrap());
+        assert!(
+            repo.user_email()
+                .find(&alice, "alice@example.com")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.browser_session()
+                .lookup(browser_session.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Bob was restored, so he is still there
+        let bob = repo.user().lookup(bob.id).await.unwrap().unwrap();
+        assert!(!bob.is_deleted());
+        repo.save().await.unwrap();
+
+        // Alice was erased from the homeserver
+        let matrix_user = state
+            .homeserver_connection
+            .query_user(&alice.username)
+            .await
+            .unwrap();
+        assert!(matrix_user.deactivated);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_delete_unknown_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::post("/api/admin/v1/users/01040G2081040G2081040G2081/delete")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "User ID 01040G2081040G2081040G2081 not found"
+        );
+    }
+}
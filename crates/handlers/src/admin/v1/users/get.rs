@@ -14,12 +14,15 @@ use crate::{
     admin::{
         call_context::CallContext,
         model::User,
-        params::UlidPathParam,
+        params::{UlidPathParam, UserFields},
         response::{ErrorResponse, SingleResponse},
     },
     impl_from_error_for_route,
 };
 
+/// How many recent failed login attempts to include in the response
+const RECENT_LOGIN_FAILURES: usize = 10;
+
 #[derive(Debug, thiserror::Error, OperationIo)]
 #[aide(output_with = "Json<ErrorResponse>")]
 pub enum RouteError {
@@ -48,6 +51,10 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
     operation
         .id("getUser")
         .summary("Get a user")
+        .description(
+            "This includes the most recent failed login attempts of the user, and the most \
+recent note left on the user by an administrator.",
+        )
         .tag("user")
         .response_with::<200, Json<SingleResponse<User>>, _>(|t| {
             let [sample, ..] = User::samples();
@@ -64,6 +71,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
     id: UlidPathParam,
+    UserFields(fields): UserFields,
 ) -> Result<Json<SingleResponse<User>>, RouteError> {
     let user = repo
         .user()
@@ -71,5 +79,190 @@ pub async fn handler(
         .await?
         .ok_or(RouteError::NotFound(*id))?;
 
-    Ok(Json(SingleResponse::new_canonical(User::from(user))))
+    let failures = repo
+        .user_login_failure()
+        .list_recent(&user, RECENT_LOGIN_FAILURES)
+        .await?;
+
+    let latest_note = repo.user_note().latest(&user).await?;
+
+    let response = SingleResponse::new_canonical(
+        User::from(user)
+            .with_recent_login_failures(failures)
+            .with_latest_note(latest_note),
+    );
+
+    Ok(Json(response.with_fieldset(&fields)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{LoginFailureOrigin, LoginFailureReason};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_get_with_login_failures(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user_login_failure()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                LoginFailureOrigin::Compat,
+                LoginFailureReason::PasswordMismatch,
+                Some("192.0.2.1".parse().unwrap()),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get(format!("/api/admin/v1/users/{}", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body["data"]["attributes"]["recent_login_failures"], @r#"
+        [
+          {
+            "created_at": "2022-01-16T14:40:00Z",
+            "origin": "compat",
+            "reason": "password_mismatch",
+            "ip_address": "192.0.2.1"
+          }
+        ]
+        "#);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_get_with_latest_note(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Without any note, the field is not present
+        let request = Request::get(format!("/api/admin/v1/users/{}", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert!(body["data"]["attributes"].get("latest_note").is_none());
+
+        let mut repo = state.repository().await.unwrap();
+        repo.user_note()
+            .add(&mut rng, &state.clock, &user, None, "First note".to_owned())
+            .await
+            .unwrap();
+        state.clock.advance(Duration::try_minutes(1).unwrap());
+        let note = repo
+            .user_note()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                None,
+                "Second note".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get(format!("/api/admin/v1/users/{}", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        let latest_note = &body["data"]["attributes"]["latest_note"];
+        assert_eq!(latest_note["id"], note.id.to_string());
+        assert_eq!(latest_note["content"], "Second note");
+        assert_eq!(latest_note["author_id"], serde_json::Value::Null);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_get_sparse_fieldset(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get(format!(
+            "/api/admin/v1/users/{}?fields[user]=username,locked_at",
+            user.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"]["attributes"],
+            serde_json::json!({
+                "username": "alice",
+                "locked_at": null,
+            })
+        );
+        assert_eq!(body["data"]["type"], "user");
+        assert_eq!(body["data"]["id"], user.id.to_string());
+        assert_eq!(
+            body["data"]["links"]["self"],
+            format!("/api/admin/v1/users/{}", user.id)
+        );
+        assert_eq!(
+            body["links"]["self"],
+            format!("/api/admin/v1/users/{}", user.id)
+        );
+
+        // An empty fieldset only leaves the type, ID and links
+        let request = Request::get(format!("/api/admin/v1/users/{}?fields[user]=", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"], serde_json::json!({}));
+        assert_eq!(body["data"]["id"], user.id.to_string());
+
+        // Unknown attributes are rejected
+        let request = Request::get(format!(
+            "/api/admin/v1/users/{}?fields[user]=password",
+            user.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
 }
@@ -9,13 +9,8 @@ use axum::{Json, response::IntoResponse};
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
 use mas_data_model::BoxRng;
-use mas_storage::{
-    compat::CompatSessionFilter,
-    oauth2::OAuth2SessionFilter,
-    queue::{QueueJobRepositoryExt as _, SyncDevicesJob},
-    user::BrowserSessionFilter,
-};
-use tracing::{error, info};
+use schemars::JsonSchema;
+use serde::Deserialize;
 use ulid::Ulid;
 
 use crate::{
@@ -26,6 +21,7 @@ use crate::{
         response::{ErrorResponse, SingleResponse},
     },
     impl_from_error_for_route,
+    user_actions::revoke_all_sessions,
 };
 
 #[derive(Debug, thiserror::Error, OperationIo)]
@@ -52,12 +48,36 @@ impl IntoResponse for RouteError {
     }
 }
 
-pub fn doc(operation: TransformOperation) -> TransformOperation {
+/// # JSON payload for the `POST /api/admin/v1/users/:id/kill-sessions`
+/// endpoint
+#[derive(Default, Deserialize, JsonSchema)]
+#[serde(rename = "KillSessionsRequest")]
+pub struct Request {
+    /// The ID of a compatibility, OAuth 2.0, browser or personal session to
+    /// keep active, for example the one used by an administrator fixing their
+    /// own account.
+    #[serde(default)]
+    #[schemars(with = "Option<crate::admin::schema::Ulid>")]
+    except_session_id: Option<Ulid>,
+}
+
+pub fn doc(mut operation: TransformOperation) -> TransformOperation {
+    operation
+        .inner_mut()
+        .request_body
+        .as_mut()
+        .unwrap()
+        .as_item_mut()
+        .unwrap()
+        .required = false;
+
     operation
         .id("KillSessions")
-        .summary("Kill all sessions (compatibility, oauth2, user sessions)")
+        .summary("Kill all sessions (compatibility, oauth2, personal, user sessions)")
         .description(
-            "Calling this endpoint will end all the compatibility, oauth2 and user sessions, preventing any further use. A job will be scheduled to sync the user's devices with the homeserver.",
+            "Calling this endpoint will end all the compatibility, oauth2 and user sessions, revoke the access and refresh tokens of the oauth2 sessions, and revoke the personal sessions acting as the user, preventing any further use.
+A session can be kept active by passing its ID as `except_session_id`.
+A job will be scheduled to sync the user's devices with the homeserver.",
         )
         .tag("user")
         .response_with::<200, Json<SingleResponse<User>>, _>(|t| {
@@ -81,7 +101,9 @@ pub async fn handler(
     }: CallContext,
     NoApi(mut rng): NoApi<BoxRng>,
     id: UlidPathParam,
+    body: Option<Json<Request>>,
 ) -> Result<Json<SingleResponse<User>>, RouteError> {
+    let Json(params) = body.unwrap_or_default();
     let id = *id;
     let user = repo
         .user()
@@ -89,25 +111,10 @@ pub async fn handler(
         .await?
         .ok_or(RouteError::NotFound(id))?;
 
-    let filter = CompatSessionFilter::new().for_user(&user).active_only();
-    let compat_session_affected = repo.compat_session().finish_bulk(&clock, filter).await?;
-
-    let filter = OAuth2SessionFilter::new().for_user(&user).active_only();
-    let oauth2_session_affected = repo.oauth2_session().finish_bulk(&clock, filter).await?;
-
-    let filter = BrowserSessionFilter::new().for_user(&user).active_only();
-    let browser_session_affected = repo.browser_session().finish_bulk(&clock, filter).await?;
-    // Schedule a job to sync the devices of the user with the homeserver
-    repo.queue_job()
-        .schedule_job(&mut rng, &clock, SyncDevicesJob::new(&user))
-        .await?;
+    revoke_all_sessions(&mut repo, &mut rng, &clock, &user, params.except_session_id).await?;
 
     repo.save().await?;
 
-    info!("Ended {compat_session_affected} active compatibility sessions");
-    info!("Ended {oauth2_session_affected} active OAuth 2.0 sessions");
-    info!("Ended {browser_session_affected} active browser sessions");
-
     Ok(Json(SingleResponse::new(
         User::from(user),
         format!("/api/admin/v1/users/{id}/kill-sessions"),
@@ -118,10 +125,13 @@ pub async fn handler(
 mod tests {
     use chrono::Duration;
     use hyper::{Request, StatusCode};
-    use mas_data_model::{Clock as _, Device};
+    use mas_data_model::{
+        Clock as _, Device, RefreshTokenState, TokenType, personal::session::PersonalSessionOwner,
+    };
+    use oauth2_types::scope::{OPENID, Scope};
     use sqlx::PgPool;
 
-    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, create_test_client, setup};
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_kill_sessions(pool: PgPool) {
@@ -215,6 +225,171 @@ mod tests {
         assert_ne!(expected.finished_at().unwrap(), state.clock.now());
     }
 
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_kill_sessions_revokes_tokens(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let client = create_test_client(&state).await;
+        let mut rng = state.rng();
+
+        // Provision a user with a browser session, an OAuth 2.0 session with an
+        // access and a refresh token, and a personal session acting as them
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        let oauth2_session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut rng,
+                &state.clock,
+                &client,
+                &browser_session,
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        let access_token = TokenType::AccessToken.generate(&mut rng);
+        let access_token = repo
+            .oauth2_access_token()
+            .add(&mut rng, &state.clock, &oauth2_session, access_token, None)
+            .await
+            .unwrap();
+        let refresh_token = TokenType::RefreshToken.generate(&mut rng);
+        let refresh_token = repo
+            .oauth2_refresh_token()
+            .add(
+                &mut rng,
+                &state.clock,
+                &oauth2_session,
+                &access_token,
+                refresh_token,
+            )
+            .await
+            .unwrap();
+        let personal_session = repo
+            .personal_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                PersonalSessionOwner::User(user.id),
+                &user,
+                "Automation".to_owned(),
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/kill-sessions", &user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        // Everything should be finished or revoked
+        let mut repo = state.repository().await.unwrap();
+        let browser_session = repo
+            .browser_session()
+            .lookup(browser_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(browser_session.finished_at.is_some());
+        let oauth2_session = repo
+            .oauth2_session()
+            .lookup(oauth2_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(oauth2_session.is_finished());
+        let access_token = repo
+            .oauth2_access_token()
+            .lookup(access_token.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(access_token.state.is_revoked());
+        let refresh_token = repo
+            .oauth2_refresh_token()
+            .lookup(refresh_token.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            refresh_token.state,
+            RefreshTokenState::Revoked { .. }
+        ));
+        let personal_session = repo
+            .personal_session()
+            .lookup(personal_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(personal_session.is_revoked());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_kill_sessions_except_one(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        // Provision a user with two compat sessions
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let kept_session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &user, device, None, false, None)
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let killed_session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &user, device, None, false, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/kill-sessions", &user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "except_session_id": kept_session.id,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let mut repo = state.repository().await.unwrap();
+        let kept_session = repo
+            .compat_session()
+            .lookup(kept_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(kept_session.is_valid());
+        let killed_session = repo
+            .compat_session()
+            .lookup(killed_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(killed_session.is_finished());
+    }
+
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_kill_sessions_on_unknown_users(pool: PgPool) {
         setup();
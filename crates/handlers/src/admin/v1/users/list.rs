@@ -5,20 +5,23 @@
 // Please see LICENSE files in the repository root for full details.
 
 use aide::{OperationIo, transform::TransformOperation};
-use axum::{Json, response::IntoResponse};
+use axum::{Json, extract::State, response::IntoResponse};
 use axum_extra::extract::{Query, QueryRejection};
 use axum_macros::FromRequestParts;
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
+use mas_data_model::SiteConfig;
 use mas_storage::{Page, user::UserFilter};
 use schemars::JsonSchema;
 use serde::Deserialize;
+use ulid::Ulid;
 
 use crate::{
     admin::{
         call_context::CallContext,
+        export::{CsvExport, ListResponse, document_csv_export},
         model::{Resource, User},
-        params::{IncludeCount, Pagination},
+        params::{ExportFormat, Format, IncludeCount, Pagination, UserFields},
         response::{ErrorResponse, PaginatedResponse},
     },
     impl_from_error_for_route,
@@ -42,7 +45,7 @@ impl std::fmt::Display for UserStatus {
     }
 }
 
-#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo, Clone)]
 #[serde(rename = "UserFilter")]
 #[aide(input_with = "Query<FilterParams>")]
 #[from_request(via(Query), rejection(RouteError))]
@@ -62,6 +65,27 @@ pub struct FilterParams {
     #[serde(rename = "filter[search]")]
     search: Option<String>,
 
+    /// Retrieve users which belong to the given organization
+    #[serde(rename = "filter[organization]")]
+    organization: Option<String>,
+
+    /// Retrieve users which have a link to the given upstream provider
+    #[serde(rename = "filter[upstream_provider]")]
+    #[schemars(with = "Option<crate::admin::schema::Ulid>")]
+    upstream_provider: Option<Ulid>,
+
+    /// Retrieve users which have (or don't have) at least one upstream link
+    #[serde(rename = "filter[has_upstream_link]")]
+    has_upstream_link: Option<bool>,
+
+    /// Retrieve users which have (or don't have) an external ID set
+    #[serde(rename = "filter[has_external_id]")]
+    has_external_id: Option<bool>,
+
+    /// Retrieve users which were (or weren't) migrated from Synapse by syn2mas
+    #[serde(rename = "filter[migrated]")]
+    migrated: Option<bool>,
+
     /// Retrieve the items with the given status
     ///
     /// Defaults to retrieve all users, including locked ones.
@@ -91,6 +115,26 @@ impl std::fmt::Display for FilterParams {
             write!(f, "{sep}filter[search]={search}")?;
             sep = '&';
         }
+        if let Some(organization) = &self.organization {
+            write!(f, "{sep}filter[organization]={organization}")?;
+            sep = '&';
+        }
+        if let Some(upstream_provider) = self.upstream_provider {
+            write!(f, "{sep}filter[upstream_provider]={upstream_provider}")?;
+            sep = '&';
+        }
+        if let Some(has_upstream_link) = self.has_upstream_link {
+            write!(f, "{sep}filter[has_upstream_link]={has_upstream_link}")?;
+            sep = '&';
+        }
+        if let Some(has_external_id) = self.has_external_id {
+            write!(f, "{sep}filter[has_external_id]={has_external_id}")?;
+            sep = '&';
+        }
+        if let Some(migrated) = self.migrated {
+            write!(f, "{sep}filter[migrated]={migrated}")?;
+            sep = '&';
+        }
         if let Some(status) = self.status {
             write!(f, "{sep}filter[status]={status}")?;
             sep = '&';
@@ -101,6 +145,65 @@ impl std::fmt::Display for FilterParams {
     }
 }
 
+impl FilterParams {
+    /// Build the repository filter out of the parameters
+    fn filter(&self) -> UserFilter<'_> {
+        let filter = UserFilter::default();
+
+        let filter = match self.admin {
+            Some(true) => filter.can_request_admin_only(),
+            Some(false) => filter.cannot_request_admin_only(),
+            None => filter,
+        };
+
+        let filter = match self.legacy_guest {
+            Some(true) => filter.guest_only(),
+            Some(false) => filter.non_guest_only(),
+            None => filter,
+        };
+
+        let filter = match self.search.as_deref() {
+            Some(search) => filter.matching_search(search),
+            None => filter,
+        };
+
+        let filter = match self.organization.as_deref() {
+            Some(organization) => filter.with_organization(organization),
+            None => filter,
+        };
+
+        let filter = match self.upstream_provider {
+            Some(upstream_provider) => filter.linked_to_upstream_provider(upstream_provider),
+            None => filter,
+        };
+
+        let filter = match self.has_upstream_link {
+            Some(true) => filter.with_upstream_link_only(),
+            Some(false) => filter.without_upstream_link_only(),
+            None => filter,
+        };
+
+        let filter = match self.has_external_id {
+            Some(true) => filter.with_external_id_only(),
+            Some(false) => filter.without_external_id_only(),
+            None => filter,
+        };
+
+        let filter = match self.migrated {
+            Some(true) => filter.migrated_only(),
+            Some(false) => filter.not_migrated_only(),
+            None => filter,
+        };
+
+        match self.status {
+            Some(UserStatus::Active) => filter.active_only(),
+            Some(UserStatus::Locked) => filter.locked_only(),
+            Some(UserStatus::Deactivated) => filter.deactivated_only(),
+            None => filter,
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error, OperationIo)]
 #[aide(output_with = "Json<ErrorResponse>")]
 pub enum RouteError {
@@ -130,7 +233,7 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
         .id("listUsers")
         .summary("List users")
         .tag("user")
-        .response_with::<200, Json<PaginatedResponse<User>>, _>(|t| {
+        .response_with::<200, PaginatedResponse<User>, _>(|t| {
             let users = User::samples();
             let pagination = mas_storage::Pagination::first(users.len());
             let page = Page {
@@ -153,41 +256,40 @@ pub fn doc(operation: TransformOperation) -> TransformOperation {
                     User::PATH,
                 ))
         })
+        .with(document_csv_export::<User>)
 }
 
 #[tracing::instrument(name = "handler.admin.v1.users.list", skip_all)]
 pub async fn handler(
     CallContext { mut repo, .. }: CallContext,
+    State(site_config): State<SiteConfig>,
     Pagination(pagination, include_count): Pagination,
+    Format(format): Format,
+    UserFields(fields): UserFields,
     params: FilterParams,
-) -> Result<Json<PaginatedResponse<User>>, RouteError> {
+) -> Result<ListResponse<User>, RouteError> {
+    if format == ExportFormat::Csv {
+        let total = repo.user().count(params.filter()).await?;
+        let export = CsvExport::new(
+            repo,
+            "users.csv",
+            site_config.admin_api_csv_export_limit,
+            total,
+            move |repo, pagination| {
+                let params = params.clone();
+                Box::pin(async move {
+                    let page = repo.user().list(params.filter(), pagination).await?;
+                    Ok(page.map(User::from))
+                })
+            },
+        );
+        return Ok(ListResponse::Csv(export));
+    }
+
     let base = format!("{path}{params}", path = User::PATH);
     let base = include_count.add_to_base(&base);
-    let filter = UserFilter::default();
-
-    let filter = match params.admin {
-        Some(true) => filter.can_request_admin_only(),
-        Some(false) => filter.cannot_request_admin_only(),
-        None => filter,
-    };
-
-    let filter = match params.legacy_guest {
-        Some(true) => filter.guest_only(),
-        Some(false) => filter.non_guest_only(),
-        None => filter,
-    };
-
-    let filter = match params.search.as_deref() {
-        Some(search) => filter.matching_search(search),
-        None => filter,
-    };
-
-    let filter = match params.status {
-        Some(UserStatus::Active) => filter.active_only(),
-        Some(UserStatus::Locked) => filter.locked_only(),
-        Some(UserStatus::Deactivated) => filter.deactivated_only(),
-        None => filter,
-    };
+    let base = fields.add_to_base(&base);
+    let filter = params.filter();
 
     let response = match include_count {
         IncludeCount::True => {
@@ -205,15 +307,24 @@ pub async fn handler(
         }
     };
 
-    Ok(Json(response))
+    Ok(ListResponse::Json(response.with_fieldset(&fields)))
 }
 
 #[cfg(test)]
 mod tests {
-    use hyper::{Request, StatusCode};
-    use sqlx::PgPool;
+    use chrono::Duration;
+    use hyper::{
+        Request, StatusCode,
+        header::{ACCEPT, CONTENT_DISPOSITION, CONTENT_TYPE, HeaderName, LINK},
+    };
+    use mas_data_model::{Clock as _, SiteConfig};
+    use sqlx::{PgPool, types::Uuid};
+    use ulid::Ulid;
 
-    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+    use crate::{
+        admin::v1::upstream_oauth_links::test_utils,
+        test_utils::{RequestBuilderExt, ResponseExt, TestState, setup, test_site_config},
+    };
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_list_users(pool: PgPool) {
@@ -253,8 +364,12 @@ mod tests {
                 "created_at": "2022-01-16T14:40:00Z",
                 "locked_at": null,
                 "deactivated_at": null,
+                "deleted_at": null,
                 "admin": false,
-                "legacy_guest": false
+                "legacy_guest": false,
+              "organization": null,
+              "max_sessions": null,
+              "external_id": null
               },
               "links": {
                 "self": "/api/admin/v1/users/01FSHN9AG0AJ6AC5HQ9X6H4RP4"
@@ -273,8 +388,12 @@ mod tests {
                 "created_at": "2022-01-16T14:40:00Z",
                 "locked_at": null,
                 "deactivated_at": null,
+                "deleted_at": null,
                 "admin": false,
-                "legacy_guest": false
+                "legacy_guest": false,
+              "organization": null,
+              "max_sessions": null,
+              "external_id": null
               },
               "links": {
                 "self": "/api/admin/v1/users/01FSHN9AG0MZAA6S4AF7CTV32E"
@@ -312,8 +431,12 @@ mod tests {
                 "created_at": "2022-01-16T14:40:00Z",
                 "locked_at": null,
                 "deactivated_at": null,
+                "deleted_at": null,
                 "admin": false,
-                "legacy_guest": false
+                "legacy_guest": false,
+              "organization": null,
+              "max_sessions": null,
+              "external_id": null
               },
               "links": {
                 "self": "/api/admin/v1/users/01FSHN9AG0AJ6AC5HQ9X6H4RP4"
@@ -332,8 +455,12 @@ mod tests {
                 "created_at": "2022-01-16T14:40:00Z",
                 "locked_at": null,
                 "deactivated_at": null,
+                "deleted_at": null,
                 "admin": false,
-                "legacy_guest": false
+                "legacy_guest": false,
+              "organization": null,
+              "max_sessions": null,
+              "external_id": null
               },
               "links": {
                 "self": "/api/admin/v1/users/01FSHN9AG0MZAA6S4AF7CTV32E"
@@ -389,8 +516,12 @@ mod tests {
                 "created_at": "2022-01-16T14:40:00Z",
                 "locked_at": null,
                 "deactivated_at": null,
+                "deleted_at": null,
                 "admin": false,
-                "legacy_guest": false
+                "legacy_guest": false,
+              "organization": null,
+              "max_sessions": null,
+              "external_id": null
               },
               "links": {
                 "self": "/api/admin/v1/users/01FSHN9AG0MZAA6S4AF7CTV32E"
@@ -428,4 +559,529 @@ mod tests {
         }
         "#);
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list_users_by_organization(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        // Provision three users, two of them in an organization
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user()
+            .set_organization(alice, Some("interieur".to_owned()))
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.user()
+            .set_organization(bob, Some("culture".to_owned()))
+            .await
+            .unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "charlie".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get("/api/admin/v1/users?filter[organization]=interieur")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body, @r#"
+        {
+          "meta": {
+            "count": 1
+          },
+          "data": [
+            {
+              "type": "user",
+              "id": "01FSHN9AG0MZAA6S4AF7CTV32E",
+              "attributes": {
+                "username": "alice",
+                "created_at": "2022-01-16T14:40:00Z",
+                "locked_at": null,
+                "deactivated_at": null,
+                "deleted_at": null,
+                "admin": false,
+                "legacy_guest": false,
+                "organization": "interieur",
+                "max_sessions": null,
+                "external_id": null
+              },
+              "links": {
+                "self": "/api/admin/v1/users/01FSHN9AG0MZAA6S4AF7CTV32E"
+              },
+              "meta": {
+                "page": {
+                  "cursor": "01FSHN9AG0MZAA6S4AF7CTV32E"
+                }
+              }
+            }
+          ],
+          "links": {
+            "self": "/api/admin/v1/users?filter[organization]=interieur&page[first]=10",
+            "first": "/api/admin/v1/users?filter[organization]=interieur&page[first]=10",
+            "last": "/api/admin/v1/users?filter[organization]=interieur&page[last]=10"
+          }
+        }
+        "#);
+
+        // The organization must match exactly
+        let request = Request::get("/api/admin/v1/users?count=only&filter[organization]=inter")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 0);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list_users_by_upstream_link(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        // Provision two providers and three users: alice is linked to both
+        // providers, bob only to the second one, and charlie to none
+        let mut repo = state.repository().await.unwrap();
+        let proconnect = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                test_utils::oidc_provider_params("proconnect"),
+            )
+            .await
+            .unwrap();
+        let other = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                test_utils::oidc_provider_params("other"),
+            )
+            .await
+            .unwrap();
+
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "charlie".to_owned())
+            .await
+            .unwrap();
+
+        for (provider, user, subject) in [
+            (&proconnect, &alice, "alice-proconnect"),
+            (&other, &alice, "alice-other"),
+            (&other, &bob, "bob-other"),
+        ] {
+            let link = repo
+                .upstream_oauth_link()
+                .add(&mut rng, &state.clock, provider, subject.to_owned(), None)
+                .await
+                .unwrap();
+            repo.upstream_oauth_link()
+                .associate_to_user(&link, user)
+                .await
+                .unwrap();
+        }
+
+        // A link which isn't associated to any user yet doesn't count
+        repo.upstream_oauth_link()
+            .add(
+                &mut rng,
+                &state.clock,
+                &proconnect,
+                "unassociated".to_owned(),
+                None,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let usernames = async |query: &str| {
+            let request = Request::get(format!("/api/admin/v1/users?{query}"))
+                .bearer(&token)
+                .empty();
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::OK);
+            let body: serde_json::Value = response.json();
+            let mut usernames: Vec<String> = body["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|user| user["attributes"]["username"].as_str().unwrap().to_owned())
+                .collect();
+            usernames.sort();
+
+            // The count must match the list
+            assert_eq!(body["meta"]["count"], usernames.len());
+            usernames
+        };
+
+        assert_eq!(
+            usernames(&format!("filter[upstream_provider]={}", proconnect.id)).await,
+            vec!["alice"]
+        );
+        assert_eq!(
+            usernames(&format!("filter[upstream_provider]={}", other.id)).await,
+            vec!["alice", "bob"]
+        );
+        assert_eq!(
+            usernames("filter[has_upstream_link]=true").await,
+            vec!["alice", "bob"]
+        );
+        assert_eq!(
+            usernames("filter[has_upstream_link]=false").await,
+            vec!["charlie"]
+        );
+
+        // Both filters can be combined
+        assert_eq!(
+            usernames(&format!(
+                "filter[upstream_provider]={}&filter[has_upstream_link]=false",
+                proconnect.id
+            ))
+            .await,
+            Vec::<String>::new()
+        );
+
+        // An unknown provider gives an empty list
+        assert_eq!(
+            usernames(&format!("filter[upstream_provider]={}", Ulid::nil())).await,
+            Vec::<String>::new()
+        );
+
+        // The filters are kept in the pagination links
+        let request = Request::get(format!(
+            "/api/admin/v1/users?filter[upstream_provider]={}&filter[has_upstream_link]=true",
+            proconnect.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["links"]["self"],
+            format!(
+                "/api/admin/v1/users?filter[upstream_provider]={}&filter[has_upstream_link]=true&page[first]=10",
+                proconnect.id
+            )
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list_migrated_users(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Migrated users are only ever written by syn2mas, so stamp alice
+        // directly
+        let run_id = Ulid::from_datetime_with_source(state.clock.now().into(), &mut rng);
+        sqlx::query(
+            "INSERT INTO syn2mas_runs (syn2mas_run_id, started_at, dry_run) VALUES ($1, $2, false)",
+        )
+        .bind(Uuid::from(run_id))
+        .bind(state.clock.now())
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("UPDATE users SET syn2mas_run_id = $1 WHERE user_id = $2")
+            .bind(Uuid::from(run_id))
+            .bind(Uuid::from(alice.id))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let request = Request::get("/api/admin/v1/users?filter[migrated]=true")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["attributes"]["username"], "alice");
+        assert_eq!(
+            body["links"]["self"],
+            "/api/admin/v1/users?filter[migrated]=true&page[first]=10"
+        );
+
+        let request = Request::get("/api/admin/v1/users?filter[migrated]=false")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["attributes"]["username"], "bob");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_export_users_csv(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                admin_api_csv_export_limit: 2,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        // Provision three users, one minute apart so that they are ordered
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        state.clock.advance(Duration::minutes(1));
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        state.clock.advance(Duration::minutes(1));
+        let charlie = repo
+            .user()
+            .add(&mut rng, &state.clock, "charlie".to_owned())
+            .await
+            .unwrap();
+        let charlie = repo.user().lock(&state.clock, charlie).await.unwrap();
+        repo.save().await.unwrap();
+
+        // The export is capped to two rows
+        let request = Request::get("/api/admin/v1/users?format=csv")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header_value(CONTENT_TYPE, "text/csv; charset=utf-8");
+        response.assert_header_value(CONTENT_DISPOSITION, "attachment; filename=\"users.csv\"");
+        response.assert_header_value(HeaderName::from_static("x-total-count"), "3");
+        response.assert_header_value(HeaderName::from_static("x-export-truncated"), "true");
+        assert_eq!(
+            response.body(),
+            &format!(
+                "id,username,created_at,locked_at,deactivated_at,admin,legacy_guest,organization,max_sessions,external_id\n\
+                 {},alice,2022-01-16T14:40:00Z,,,false,false,,,\n\
+                 {},bob,2022-01-16T14:41:00Z,,,false,false,,,\n",
+                alice.id, bob.id,
+            )
+        );
+
+        // The filters are applied, and the Accept header is honoured. The
+        // pagination parameters are ignored.
+        let request = Request::get("/api/admin/v1/users?filter[status]=locked&page[first]=1")
+            .bearer(&token)
+            .header(ACCEPT, "text/csv")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header_value(CONTENT_TYPE, "text/csv; charset=utf-8");
+        response.assert_header_value(HeaderName::from_static("x-total-count"), "1");
+        response.assert_header_value(HeaderName::from_static("x-export-truncated"), "false");
+        assert_eq!(
+            response.body(),
+            &format!(
+                "id,username,created_at,locked_at,deactivated_at,admin,legacy_guest,organization,max_sessions,external_id\n\
+                 {},charlie,2022-01-16T14:42:00Z,2022-01-16T14:42:00Z,,false,false,,,\n",
+                charlie.id,
+            )
+        );
+
+        // An empty export still has the header row
+        let request = Request::get("/api/admin/v1/users?format=csv&filter[search]=nobody")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert_eq!(
+            response.body(),
+            "id,username,created_at,locked_at,deactivated_at,admin,legacy_guest,organization,max_sessions,external_id\n"
+        );
+
+        // An invalid format is rejected
+        let request = Request::get("/api/admin/v1/users?format=xml")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list_users_link_header(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        // Provision three users, one minute apart so that they are ordered,
+        // with one of them locked
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        state.clock.advance(Duration::minutes(1));
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        state.clock.advance(Duration::minutes(1));
+        let charlie = repo
+            .user()
+            .add(&mut rng, &state.clock, "charlie".to_owned())
+            .await
+            .unwrap();
+        repo.user().lock(&state.clock, charlie).await.unwrap();
+        repo.save().await.unwrap();
+
+        // The links include the filters, and match the ones in the body
+        let request = Request::get("/api/admin/v1/users?filter[status]=active&page[first]=1")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header_value(
+            LINK,
+            &format!(
+                "</api/admin/v1/users?filter[status]=active&page[after]={alice}&page[first]=1>; rel=\"next\", \
+                 </api/admin/v1/users?filter[status]=active&page[first]=1>; rel=\"first\", \
+                 </api/admin/v1/users?filter[status]=active&page[last]=1>; rel=\"last\"",
+                alice = alice.id,
+            ),
+        );
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["links"]["next"],
+            format!(
+                "/api/admin/v1/users?filter[status]=active&page[after]={}&page[first]=1",
+                alice.id
+            )
+        );
+
+        // Paginating backwards gives a link to the previous page
+        let request = Request::get("/api/admin/v1/users?filter[status]=active&page[last]=1")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header_value(
+            LINK,
+            &format!(
+                "</api/admin/v1/users?filter[status]=active&page[before]={bob}&page[last]=1>; rel=\"prev\", \
+                 </api/admin/v1/users?filter[status]=active&page[first]=1>; rel=\"first\", \
+                 </api/admin/v1/users?filter[status]=active&page[last]=1>; rel=\"last\"",
+                bob = bob.id,
+            ),
+        );
+
+        // There are no pagination links when only asking for the count
+        let request = Request::get("/api/admin/v1/users?filter[status]=active&count=only")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.headers().get(LINK).is_none());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list_users_sparse_fieldset(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Only the requested attributes are included, but the type, ID and links
+        // are always there
+        let request = Request::get("/api/admin/v1/users?fields[user]=username,admin")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        let data = body["data"].as_array().unwrap();
+        assert_eq!(data.len(), 2);
+        for resource in data {
+            assert_eq!(resource["type"], "user");
+            assert!(resource["id"].is_string());
+            assert!(resource["links"]["self"].is_string());
+            let mut attributes: Vec<_> =
+                resource["attributes"].as_object().unwrap().keys().collect();
+            attributes.sort();
+            assert_eq!(attributes, ["admin", "username"]);
+        }
+
+        // The pagination links keep the fieldset
+        assert_eq!(
+            body["links"]["self"],
+            "/api/admin/v1/users?fields[user]=admin,username&page[first]=10"
+        );
+
+        // Unknown attributes are rejected
+        let request = Request::get("/api/admin/v1/users?fields[user]=username,status")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "Unknown field \"status\" for resources of type \"user\""
+        );
+    }
 }
@@ -4,10 +4,11 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use aide::{OperationIo, transform::TransformOperation};
-use axum::{Json, response::IntoResponse};
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, extract::State, response::IntoResponse};
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
+use mas_data_model::{BoxRng, SiteConfig};
 use ulid::Ulid;
 
 use crate::{
@@ -18,6 +19,7 @@ use crate::{
         response::{ErrorResponse, SingleResponse},
     },
     impl_from_error_for_route,
+    user_actions::lock_user,
 };
 
 #[derive(Debug, thiserror::Error, OperationIo)]
@@ -69,6 +71,8 @@ pub async fn handler(
     CallContext {
         mut repo, clock, ..
     }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    State(site_config): State<SiteConfig>,
     id: UlidPathParam,
 ) -> Result<Json<SingleResponse<User>>, RouteError> {
     let id = *id;
@@ -78,7 +82,7 @@ pub async fn handler(
         .await?
         .ok_or(RouteError::NotFound(id))?;
 
-    let user = repo.user().lock(&clock, user).await?;
+    let user = lock_user(&mut repo, &mut rng, &clock, &site_config, user).await?;
 
     repo.save().await?;
 
@@ -90,13 +94,19 @@ pub async fn handler(
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroU32;
+
     use chrono::Duration;
-    use hyper::{Request, StatusCode};
+    use hyper::{Request, StatusCode, header::RETRY_AFTER};
+    use mas_config::{RateLimiterConfiguration, RateLimitingConfig};
     use mas_data_model::Clock;
     use mas_storage::{RepositoryAccess, user::UserRepository};
     use sqlx::PgPool;
 
-    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+    use crate::{
+        Limiter,
+        test_utils::{RequestBuilderExt, ResponseExt, TestState, setup},
+    };
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_lock_user(pool: PgPool) {
@@ -179,4 +189,66 @@ mod tests {
             "User ID 01040G2081040G2081040G2081 not found"
         );
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_lock_user_rate_limited(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+
+        // Only allow two writes per client, replenished once an hour
+        let mut config = RateLimitingConfig::default();
+        config.admin_api.write = RateLimiterConfiguration {
+            burst: NonZeroU32::new(2).unwrap(),
+            per_second: 1.0 / 3600.0,
+        };
+        state.limiter = Limiter::new(&config).unwrap();
+
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        for _ in 0..2 {
+            let request = Request::post(format!("/api/admin/v1/users/{}/lock", user.id))
+                .bearer(&token)
+                .empty();
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::OK);
+        }
+
+        // The third write is rejected
+        let request = Request::post(format!("/api/admin/v1/users/{}/lock", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response
+            .headers()
+            .get(RETRY_AFTER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(retry_after > 0 && retry_after <= 3600);
+        let body: serde_json::Value = response.json();
+        assert!(
+            body["errors"][0]["title"]
+                .as_str()
+                .unwrap()
+                .starts_with("Too many write requests to the admin API for client")
+        );
+
+        // Reads have their own budget and are not affected
+        let request = Request::get(format!("/api/admin/v1/users/{}", user.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+    }
 }
@@ -0,0 +1,1264 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::collections::{HashMap, HashSet};
+
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, extract::State, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::{AuditEventPayload, BoxRng, Device, SiteConfig, UpstreamOAuthLink, User};
+use mas_storage::{
+    BoxRepository, Pagination, RepositoryError,
+    compat::CompatSessionFilter,
+    oauth2::OAuth2SessionFilter,
+    personal::PersonalSessionFilter,
+    queue::{DeactivateUserJob, QueueJobRepositoryExt as _, SyncDevicesJob},
+    upstream_oauth2::UpstreamOAuthLinkFilter,
+    user::BrowserSessionFilter,
+};
+use rand::RngCore;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        params::{TargetUlidPathParam, UlidPathParam},
+        response::ErrorResponse,
+    },
+    audit::schedule_audit_event,
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("Target user ID {0} not found")]
+    TargetNotFound(Ulid),
+
+    #[error("A user cannot be merged into itself")]
+    SameUser,
+
+    #[error("Target user ID {0} is deactivated")]
+    TargetDeactivated(Ulid),
+
+    #[error("Merging users cannot be undone, set `confirm` to `true` to proceed")]
+    ConfirmationRequired,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) | Self::TargetNotFound(_) => StatusCode::NOT_FOUND,
+            Self::SameUser | Self::TargetDeactivated(_) | Self::ConfirmationRequired => {
+                StatusCode::BAD_REQUEST
+            }
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/users/{id}/merge-into/{target_id}` endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "MergeUserRequest")]
+pub struct Request {
+    /// Must be set to `true` to proceed, as merging users cannot be undone
+    #[serde(default)]
+    confirm: bool,
+}
+
+/// # JSON response for the `POST /api/admin/v1/users/{id}/merge-into/{target_id}` endpoint
+#[derive(Serialize, JsonSchema)]
+#[serde(rename = "MergeUserResponse")]
+pub struct Response {
+    /// The number of browser sessions which were moved to the target user
+    browser_sessions: usize,
+
+    /// The number of compatibility sessions which were moved to the target
+    /// user
+    compat_sessions: usize,
+
+    /// The number of OAuth 2.0 sessions which were moved to the target user
+    oauth2_sessions: usize,
+
+    /// The number of email addresses which were moved to the target user
+    emails: usize,
+
+    /// The number of email addresses which were left on the merged user,
+    /// because the target user already had them
+    skipped_emails: usize,
+
+    /// The number of upstream links which were moved to the target user
+    upstream_links: usize,
+
+    /// The number of upstream links which were left on the merged user,
+    /// because the target user already had a link to the same provider
+    skipped_upstream_links: usize,
+
+    /// The number of devices which were given a new ID, because the target
+    /// user already had a device with the same ID
+    reidentified_devices: usize,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("mergeUser")
+        .summary("Merge a user into another one")
+        .description(
+            "Move the browser, compatibility and OAuth 2.0 sessions, the email addresses and the upstream links of the user onto the target user, then deactivate the user.
+Email addresses the target user already has, and upstream links to providers the target user is already linked to, are left on the deactivated user, and devices whose ID is already used by an active session of the target user are given a new ID.
+A job is scheduled to sync the devices of the target user with the homeserver.
+This cannot be undone, so the request body must have `confirm` set to `true`.",
+        )
+        .tag("user")
+        .response_with::<200, Json<Response>, _>(|t| {
+            t.description("The user was merged").example(Response {
+                browser_sessions: 2,
+                compat_sessions: 1,
+                oauth2_sessions: 3,
+                emails: 1,
+                skipped_emails: 1,
+                upstream_links: 1,
+                skipped_upstream_links: 0,
+                reidentified_devices: 1,
+            })
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::ConfirmationRequired);
+            t.description("The merge was not confirmed, or the target user is not valid")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User ID or target user ID not found")
+                .example(response)
+        })
+}
+
+/// Collect all the upstream links of the user
+///
+/// They are collected before being moved, as moving them changes the result of
+/// the listing
+async fn upstream_links_of(
+    repo: &mut BoxRepository,
+    user: &User,
+) -> Result<Vec<UpstreamOAuthLink>, RepositoryError> {
+    let mut links = Vec::new();
+    let mut cursor = Pagination::first(100);
+    loop {
+        let page = repo
+            .upstream_oauth_link()
+            .list(UpstreamOAuthLinkFilter::new().for_user(user), cursor)
+            .await?;
+
+        for edge in page.edges {
+            links.push(edge.node);
+            cursor = cursor.after(edge.cursor);
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    Ok(links)
+}
+
+/// Collect the IDs of the devices the user has active sessions for
+async fn active_devices(
+    repo: &mut BoxRepository,
+    user: &User,
+) -> Result<HashSet<String>, RepositoryError> {
+    let mut devices = HashSet::new();
+
+    let mut cursor = Pagination::first(5000);
+    loop {
+        let page = repo
+            .compat_session()
+            .list(
+                CompatSessionFilter::new().for_user(user).active_only(),
+                cursor,
+            )
+            .await?;
+
+        for edge in page.edges {
+            let (compat_session, _) = edge.node;
+            if let Some(device) = compat_session.device {
+                devices.insert(device.as_str().to_owned());
+            }
+            cursor = cursor.after(edge.cursor);
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    let mut cursor = Pagination::first(5000);
+    loop {
+        let page = repo
+            .oauth2_session()
+            .list(
+                OAuth2SessionFilter::new().for_user(user).active_only(),
+                cursor,
+            )
+            .await?;
+
+        for edge in page.edges {
+            for scope in &*edge.node.scope {
+                if let Some(device) = Device::from_scope_token(scope) {
+                    devices.insert(device.as_str().to_owned());
+                }
+            }
+            cursor = cursor.after(edge.cursor);
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    let mut cursor = Pagination::first(5000);
+    loop {
+        let page = repo
+            .personal_session()
+            .list(
+                PersonalSessionFilter::new()
+                    .for_actor_user(user)
+                    .active_only(),
+                cursor,
+            )
+            .await?;
+
+        for edge in page.edges {
+            let (session, _) = &edge.node;
+            for scope in &*session.scope {
+                if let Some(device) = Device::from_scope_token(scope) {
+                    devices.insert(device.as_str().to_owned());
+                }
+            }
+            cursor = cursor.after(edge.cursor);
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    Ok(devices)
+}
+
+/// Keeps track of the new IDs given to the devices of the merged user which
+/// collide with the devices of the target user
+struct DeviceReplacements {
+    /// The device IDs which can't be used by the merged sessions
+    taken: HashSet<String>,
+
+    /// The new device for each colliding device ID
+    replacements: HashMap<String, Device>,
+}
+
+impl DeviceReplacements {
+    fn new(taken: HashSet<String>) -> Self {
+        Self {
+            taken,
+            replacements: HashMap::new(),
+        }
+    }
+
+    /// Get the new device to use instead of the given one, or `None` if it
+    /// doesn't collide with a device of the target user.
+    ///
+    /// The same new device is returned for all the sessions sharing a device
+    /// ID, so that they still end up on the same device.
+    fn replace(&mut self, rng: &mut (dyn RngCore + Send), device: &Device) -> Option<Device> {
+        if !self.taken.contains(device.as_str()) {
+            return None;
+        }
+
+        let replacement = self
+            .replacements
+            .entry(device.as_str().to_owned())
+            .or_insert_with(|| {
+                loop {
+                    let device = Device::generate(rng);
+                    if self.taken.insert(device.as_str().to_owned()) {
+                        break device;
+                    }
+                }
+            });
+
+        Some(replacement.clone())
+    }
+}
+
+/// Give a new ID to the devices of the active sessions of the `source` user
+/// which are already used by the `target` user
+///
+/// Returns the number of devices which were given a new ID
+async fn reidentify_colliding_devices(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    source: &User,
+    target: &User,
+) -> Result<usize, RepositoryError> {
+    let mut devices = DeviceReplacements::new(active_devices(repo, target).await?);
+
+    let mut cursor = Pagination::first(5000);
+    loop {
+        let page = repo
+            .compat_session()
+            .list(
+                CompatSessionFilter::new().for_user(source).active_only(),
+                cursor,
+            )
+            .await?;
+
+        for edge in page.edges {
+            cursor = cursor.after(edge.cursor);
+            let (compat_session, _) = edge.node;
+            let Some(device) = &compat_session.device else {
+                continue;
+            };
+
+            if let Some(new_device) = devices.replace(rng, device) {
+                info!(
+                    %compat_session.id,
+                    previous_device.id = device.as_str(),
+                    device.id = new_device.as_str(),
+                    "Giving a new ID to a colliding device"
+                );
+                repo.compat_session()
+                    .set_device(compat_session, new_device)
+                    .await?;
+            }
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    let mut cursor = Pagination::first(5000);
+    loop {
+        let page = repo
+            .oauth2_session()
+            .list(
+                OAuth2SessionFilter::new().for_user(source).active_only(),
+                cursor,
+            )
+            .await?;
+
+        for edge in page.edges {
+            cursor = cursor.after(edge.cursor);
+            let session = edge.node;
+
+            let mut scope = session.scope.clone();
+            for device in session.scope.iter().filter_map(Device::from_scope_token) {
+                let Some(new_device) = devices.replace(rng, &device) else {
+                    continue;
+                };
+
+                info!(
+                    %session.id,
+                    previous_device.id = device.as_str(),
+                    device.id = new_device.as_str(),
+                    "Giving a new ID to a colliding device"
+                );
+
+                // Swap both the stable and unstable device scope tokens
+                scope.retain(|token| Device::from_scope_token(token).as_ref() != Some(&device));
+                scope.extend(
+                    new_device
+                        .to_scope_token()
+                        .expect("generated device IDs are valid scope tokens"),
+                );
+            }
+
+            if scope != session.scope {
+                repo.oauth2_session().set_scope(session, scope).await?;
+            }
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    Ok(devices.replacements.len())
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.merge", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    State(site_config): State<SiteConfig>,
+    id: UlidPathParam,
+    target_id: TargetUlidPathParam,
+    Json(params): Json<Request>,
+) -> Result<Json<Response>, RouteError> {
+    if !params.confirm {
+        return Err(RouteError::ConfirmationRequired);
+    }
+
+    let (id, target_id) = (*id, *target_id);
+    if id == target_id {
+        return Err(RouteError::SameUser);
+    }
+
+    let source = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    let target = repo
+        .user()
+        .lookup(target_id)
+        .await?
+        .ok_or(RouteError::TargetNotFound(target_id))?;
+
+    if target.deactivated_at.is_some() {
+        return Err(RouteError::TargetDeactivated(target_id));
+    }
+
+    // Devices have to be re-identified before moving the sessions, as we need
+    // to tell apart the devices of both users
+    let reidentified_devices =
+        reidentify_colliding_devices(&mut repo, &mut rng, &source, &target).await?;
+
+    let browser_sessions = repo
+        .browser_session()
+        .transfer_bulk(BrowserSessionFilter::new().for_user(&source), &target)
+        .await?;
+
+    let compat_sessions = repo
+        .compat_session()
+        .transfer_bulk(CompatSessionFilter::new().for_user(&source), &target)
+        .await?;
+
+    let oauth2_sessions = repo
+        .oauth2_session()
+        .transfer_bulk(OAuth2SessionFilter::new().for_user(&source), &target)
+        .await?;
+
+    let target_emails: HashSet<String> = repo
+        .user_email()
+        .all(&target)
+        .await?
+        .into_iter()
+        .map(|user_email| user_email.email.to_lowercase())
+        .collect();
+
+    let source_emails = repo.user_email().all(&source).await?;
+    let mut emails = 0;
+    let mut skipped_emails = 0;
+    for user_email in source_emails {
+        if target_emails.contains(&user_email.email.to_lowercase()) {
+            skipped_emails += 1;
+            continue;
+        }
+
+        repo.user_email()
+            .transfer_to_user(user_email, &target)
+            .await?;
+        emails += 1;
+    }
+
+    // A user can only have one link per provider, so the links to providers
+    // the target user is already linked to stay on the merged user
+    let target_providers: HashSet<Ulid> = upstream_links_of(&mut repo, &target)
+        .await?
+        .into_iter()
+        .map(|link| link.provider_id)
+        .collect();
+
+    let mut upstream_links = 0;
+    let mut skipped_upstream_links = 0;
+    for link in upstream_links_of(&mut repo, &source).await? {
+        if target_providers.contains(&link.provider_id) {
+            skipped_upstream_links += 1;
+            continue;
+        }
+
+        repo.upstream_oauth_link()
+            .transfer_to_user(link, &target)
+            .await?;
+        upstream_links += 1;
+    }
+
+    if source.deactivated_at.is_none() {
+        let source = repo.user().deactivate(&clock, source.clone()).await?;
+
+        info!(%source.id, "Scheduling deactivation of merged user");
+        repo.queue_job()
+            .schedule_job(&mut rng, &clock, DeactivateUserJob::new(&source, false))
+            .await?;
+    }
+
+    info!(%target.id, "Scheduling device sync for target user");
+    repo.queue_job()
+        .schedule_job(&mut rng, &clock, SyncDevicesJob::new(&target))
+        .await?;
+
+    schedule_audit_event(
+        &mut repo,
+        &mut rng,
+        &clock,
+        &site_config,
+        None,
+        AuditEventPayload::account_merge(&source, &target),
+    )
+    .await?;
+
+    info!(
+        %source.id,
+        %target.id,
+        browser_sessions,
+        compat_sessions,
+        oauth2_sessions,
+        emails,
+        skipped_emails,
+        upstream_links,
+        skipped_upstream_links,
+        reidentified_devices,
+        "Merged user into target user"
+    );
+
+    repo.save().await?;
+
+    Ok(Json(Response {
+        browser_sessions,
+        compat_sessions,
+        oauth2_sessions,
+        emails,
+        skipped_emails,
+        upstream_links,
+        skipped_upstream_links,
+        reidentified_devices,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{
+        AuditEventKind, AuditWebhookConfig, Clock, Device, SiteConfig, UpstreamOAuthProvider, User,
+    };
+    use mas_storage::{
+        Pagination, RepositoryAccess,
+        compat::{CompatSessionFilter, CompatSessionRepository},
+        oauth2::{OAuth2ClientRepository, OAuth2SessionFilter, OAuth2SessionRepository},
+        upstream_oauth2::{UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository},
+        user::{
+            BrowserSessionFilter, BrowserSessionRepository, UserEmailRepository, UserRepository,
+        },
+    };
+    use oauth2_types::scope::{OPENID, Scope};
+    use sqlx::{PgPool, types::Json};
+
+    use crate::{
+        admin::v1::upstream_oauth_links::test_utils::oidc_provider_params,
+        test_utils::{RequestBuilderExt, ResponseExt, TestState, setup, test_site_config},
+    };
+
+    async fn add_users(state: &TestState) -> (User, User) {
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        (alice, bob)
+    }
+
+    async fn merge(
+        state: &TestState,
+        token: &str,
+        source: &User,
+        target: &User,
+    ) -> serde_json::Value {
+        let request = Request::post(format!(
+            "/api/admin/v1/users/{}/merge-into/{}",
+            source.id, target.id
+        ))
+        .bearer(token)
+        .json(serde_json::json!({ "confirm": true }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        response.json()
+    }
+
+    async fn add_provider(state: &TestState, name: &str) -> UpstreamOAuthProvider {
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(&mut state.rng(), &state.clock, oidc_provider_params(name))
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        provider
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_merge_requires_confirmation(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let (alice, bob) = add_users(&state).await;
+
+        let uri = format!("/api/admin/v1/users/{}/merge-into/{}", alice.id, bob.id);
+
+        let request = Request::post(&uri)
+            .bearer(&token)
+            .json(serde_json::json!({}));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "Merging users cannot be undone, set `confirm` to `true` to proceed"
+        );
+
+        let request = Request::post(&uri)
+            .bearer(&token)
+            .json(serde_json::json!({ "confirm": false }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        // Nothing happened
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+        assert!(alice.deactivated_at.is_none());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_merge_invalid_users(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let (alice, bob) = add_users(&state).await;
+        let confirm = serde_json::json!({ "confirm": true });
+
+        // Into itself
+        let request = Request::post(format!(
+            "/api/admin/v1/users/{}/merge-into/{}",
+            alice.id, alice.id
+        ))
+        .bearer(&token)
+        .json(&confirm);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "A user cannot be merged into itself"
+        );
+
+        // Unknown source user
+        let request = Request::post(format!(
+            "/api/admin/v1/users/01FSHNB530AAPR7PEV8KNBZD5Y/merge-into/{}",
+            bob.id
+        ))
+        .bearer(&token)
+        .json(&confirm);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "User ID 01FSHNB530AAPR7PEV8KNBZD5Y not found"
+        );
+
+        // Unknown target user
+        let request = Request::post(format!(
+            "/api/admin/v1/users/{}/merge-into/01FSHNB530AAPR7PEV8KNBZD5Y",
+            alice.id
+        ))
+        .bearer(&token)
+        .json(&confirm);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "Target user ID 01FSHNB530AAPR7PEV8KNBZD5Y not found"
+        );
+
+        // Deactivated target user
+        let mut repo = state.repository().await.unwrap();
+        let bob = repo.user().deactivate(&state.clock, bob).await.unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!(
+            "/api/admin/v1/users/{}/merge-into/{}",
+            alice.id, bob.id
+        ))
+        .bearer(&token)
+        .json(&confirm);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            format!("Target user ID {} is deactivated", bob.id)
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_merge_sessions(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let (alice, bob) = add_users(&state).await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &state.clock,
+                vec![],
+                None,
+                None,
+                None,
+                vec![],
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        // An active and a finished browser session for alice
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &alice, None)
+            .await
+            .unwrap();
+        let finished_browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &alice, None)
+            .await
+            .unwrap();
+        repo.browser_session()
+            .finish(&state.clock, finished_browser_session)
+            .await
+            .unwrap();
+
+        // A compat session and an OAuth 2.0 session for alice
+        let device = Device::generate(&mut rng);
+        let compat_session = repo
+            .compat_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &alice,
+                device.clone(),
+                Some(&browser_session),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        let oauth2_session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut rng,
+                &state.clock,
+                &client,
+                &browser_session,
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+
+        // A browser session for bob, which should stay there
+        repo.browser_session()
+            .add(&mut rng, &state.clock, &bob, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let body = merge(&state, &token, &alice, &bob).await;
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "browser_sessions": 2,
+                "compat_sessions": 1,
+                "oauth2_sessions": 1,
+                "emails": 0,
+                "skipped_emails": 0,
+                "upstream_links": 0,
+                "skipped_upstream_links": 0,
+                "reidentified_devices": 0,
+            })
+        );
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+        assert_eq!(alice.deactivated_at, Some(state.clock.now()));
+        let bob = repo.user().lookup(bob.id).await.unwrap().unwrap();
+        assert!(bob.deactivated_at.is_none());
+
+        let browser_session = repo
+            .browser_session()
+            .lookup(browser_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(browser_session.user.id, bob.id);
+        assert!(browser_session.finished_at.is_none());
+        assert_eq!(
+            repo.browser_session()
+                .count(BrowserSessionFilter::new().for_user(&bob))
+                .await
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            repo.browser_session()
+                .count(BrowserSessionFilter::new().for_user(&alice))
+                .await
+                .unwrap(),
+            0
+        );
+
+        // The sessions kept their device and are still active
+        let compat_session = repo
+            .compat_session()
+            .lookup(compat_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(compat_session.user_id, bob.id);
+        assert_eq!(compat_session.device, Some(device));
+        assert!(compat_session.is_valid());
+
+        let oauth2_session = repo
+            .oauth2_session()
+            .lookup(oauth2_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(oauth2_session.user_id, Some(bob.id));
+        assert!(oauth2_session.is_valid());
+
+        // The merged user is deactivated without erasure, and the devices of
+        // the target user are synced
+        let job: Json<serde_json::Value> = sqlx::query_scalar(
+            "SELECT payload FROM queue_jobs WHERE queue_name = 'deactivate-user'",
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Deactivation job to be scheduled");
+        assert_eq!(job["user_id"], serde_json::json!(alice.id));
+        assert_eq!(job["hs_erase"], serde_json::json!(false));
+
+        let job: Json<serde_json::Value> =
+            sqlx::query_scalar("SELECT payload FROM queue_jobs WHERE queue_name = 'sync-devices'")
+                .fetch_one(&pool)
+                .await
+                .expect("Device sync job to be scheduled");
+        assert_eq!(job["user_id"], serde_json::json!(bob.id));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_merge_emails(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let (alice, bob) = add_users(&state).await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice_email = repo
+            .user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &alice,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        // Bob already has this one, with a different case
+        let shared_email = repo
+            .user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &alice,
+                "Shared@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &bob,
+                "shared@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let body = merge(&state, &token, &alice, &bob).await;
+        assert_eq!(body["emails"], 1);
+        assert_eq!(body["skipped_emails"], 1);
+
+        let mut repo = state.repository().await.unwrap();
+        let alice_email = repo
+            .user_email()
+            .lookup(alice_email.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(alice_email.user_id, bob.id);
+
+        // The duplicate was left on the merged user
+        let shared_email = repo
+            .user_email()
+            .lookup(shared_email.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(shared_email.user_id, alice.id);
+
+        let mut bob_emails: Vec<String> = repo
+            .user_email()
+            .all(&bob)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|user_email| user_email.email)
+            .collect();
+        bob_emails.sort();
+        assert_eq!(bob_emails, ["alice@example.com", "shared@example.com"]);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_merge_upstream_links(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let (alice, bob) = add_users(&state).await;
+        let proconnect = add_provider(&state, "proconnect").await;
+        let other = add_provider(&state, "other").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let mut links = Vec::new();
+        for (provider, subject, user) in [
+            (&proconnect, "alice-proconnect", &alice),
+            (&other, "alice-other", &alice),
+            (&other, "bob-other", &bob),
+        ] {
+            let link = repo
+                .upstream_oauth_link()
+                .add(&mut rng, &state.clock, provider, subject.to_owned(), None)
+                .await
+                .unwrap();
+            repo.upstream_oauth_link()
+                .associate_to_user(&link, user)
+                .await
+                .unwrap();
+            links.push(link);
+        }
+        repo.save().await.unwrap();
+
+        // Bob is already linked to the other provider, so only the link to
+        // proconnect is moved
+        let body = merge(&state, &token, &alice, &bob).await;
+        assert_eq!(body["upstream_links"], 1);
+        assert_eq!(body["skipped_upstream_links"], 1);
+
+        let mut repo = state.repository().await.unwrap();
+        let mut owners = Vec::new();
+        for link in links {
+            let link = repo
+                .upstream_oauth_link()
+                .lookup(link.id)
+                .await
+                .unwrap()
+                .unwrap();
+            owners.push(link.user_id);
+        }
+        assert_eq!(owners, [Some(bob.id), Some(alice.id), Some(bob.id)]);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_merge_colliding_devices(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let (alice, bob) = add_users(&state).await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &state.clock,
+                vec![],
+                None,
+                None,
+                None,
+                vec![],
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let shared = Device::try_from("SHAREDDEVICE".to_owned()).unwrap();
+        let unique = Device::try_from("ALICEDEVICE".to_owned()).unwrap();
+        let finished = Device::try_from("OLDDEVICE".to_owned()).unwrap();
+
+        // Bob has an active session on the shared device, and a finished one
+        // on the old device
+        repo.compat_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &bob,
+                shared.clone(),
+                None,
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        let bob_old_session = repo
+            .compat_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &bob,
+                finished.clone(),
+                None,
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.compat_session()
+            .finish(&state.clock, bob_old_session)
+            .await
+            .unwrap();
+
+        // Alice uses the shared device through both a compat and an OAuth 2.0
+        // session, and also has her own devices
+        let alice_shared_compat = repo
+            .compat_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &alice,
+                shared.clone(),
+                None,
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        let alice_unique_compat = repo
+            .compat_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &alice,
+                unique.clone(),
+                None,
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        let alice_old_compat = repo
+            .compat_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &alice,
+                finished.clone(),
+                None,
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &alice, None)
+            .await
+            .unwrap();
+        let mut scope = Scope::from_iter([OPENID]);
+        scope.extend(shared.to_scope_token().unwrap());
+        let alice_shared_oauth2 = repo
+            .oauth2_session()
+            .add_from_browser_session(&mut rng, &state.clock, &client, &browser_session, scope)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let body = merge(&state, &token, &alice, &bob).await;
+        assert_eq!(body["compat_sessions"], 3);
+        assert_eq!(body["oauth2_sessions"], 1);
+        assert_eq!(body["reidentified_devices"], 1);
+
+        let mut repo = state.repository().await.unwrap();
+
+        // The colliding device got a new ID, shared by both sessions
+        let alice_shared_compat = repo
+            .compat_session()
+            .lookup(alice_shared_compat.id)
+            .await
+            .unwrap()
+            .unwrap();
+        let new_device = alice_shared_compat.device.unwrap();
+        assert_ne!(new_device, shared);
+
+        let alice_shared_oauth2 = repo
+            .oauth2_session()
+            .lookup(alice_shared_oauth2.id)
+            .await
+            .unwrap()
+            .unwrap();
+        let devices: Vec<Device> = alice_shared_oauth2
+            .scope
+            .iter()
+            .filter_map(Device::from_scope_token)
+            .collect();
+        assert_eq!(devices, [new_device.clone(), new_device]);
+        assert!(alice_shared_oauth2.scope.contains("openid"));
+
+        // The other devices were left untouched, including the one which only
+        // collides with a finished session
+        let alice_unique_compat = repo
+            .compat_session()
+            .lookup(alice_unique_compat.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(alice_unique_compat.device, Some(unique));
+
+        let alice_old_compat = repo
+            .compat_session()
+            .lookup(alice_old_compat.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(alice_old_compat.device, Some(finished));
+
+        // Bob now has all the sessions, each active device being used once
+        let page = repo
+            .compat_session()
+            .list(
+                CompatSessionFilter::new().for_user(&bob).active_only(),
+                Pagination::first(10),
+            )
+            .await
+            .unwrap();
+        let mut devices: Vec<String> = page
+            .edges
+            .into_iter()
+            .filter_map(|edge| edge.node.0.device)
+            .map(String::from)
+            .collect();
+        devices.sort();
+        devices.dedup();
+        assert_eq!(devices.len(), 4);
+        assert_eq!(
+            repo.oauth2_session()
+                .count(OAuth2SessionFilter::new().for_user(&bob))
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_merge_audit_event(pool: PgPool) {
+        setup();
+        let site_config = SiteConfig {
+            audit_webhook: Some(AuditWebhookConfig {
+                url: "https://audit.example.com/".parse().unwrap(),
+                secret: None,
+                events: vec![AuditEventKind::AccountMerge],
+            }),
+            ..test_site_config()
+        };
+        let mut state = TestState::from_pool_with_site_config(pool.clone(), site_config)
+            .await
+            .unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let (alice, bob) = add_users(&state).await;
+
+        merge(&state, &token, &alice, &bob).await;
+
+        let job: Json<serde_json::Value> = sqlx::query_scalar(
+            "SELECT payload FROM queue_jobs WHERE queue_name = 'send-audit-event'",
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Audit event to be scheduled");
+        let event = &job["event"];
+        assert_eq!(event["type"], "account_merge");
+        assert_eq!(event["source_user_id"], serde_json::json!(alice.id));
+        assert_eq!(event["source_username"], "alice");
+        assert_eq!(event["user_id"], serde_json::json!(bob.id));
+        assert_eq!(event["username"], "bob");
+    }
+}
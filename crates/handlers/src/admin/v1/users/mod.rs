@@ -5,31 +5,57 @@
 // Please see LICENSE files in the repository root for full details.
 
 mod add;
+mod by_email;
+mod by_external_id;
+mod by_mxid;
 mod by_username;
 mod deactivate;
+mod delete;
 mod get;
 //:tchap:
 mod kill_sessions;
 //:tchap:end
 mod list;
 mod lock;
+mod merge;
+mod permissions;
+mod purge;
+mod purge_sessions;
 mod reactivate;
+mod revoke_all_sessions;
 mod set_admin;
+mod set_email;
+mod set_external_id;
+mod set_max_sessions;
 mod set_password;
+mod undelete;
 mod unlock;
 
 pub use self::{
     add::{doc as add_doc, handler as add},
+    by_email::{doc as by_email_doc, handler as by_email},
+    by_external_id::{doc as by_external_id_doc, handler as by_external_id},
+    by_mxid::{doc as by_mxid_doc, handler as by_mxid},
     by_username::{doc as by_username_doc, handler as by_username},
     deactivate::{doc as deactivate_doc, handler as deactivate},
+    delete::{doc as delete_doc, handler as delete},
     get::{doc as get_doc, handler as get},
     //:tchap:
     kill_sessions::{doc as kill_sessions_doc, handler as kill_sessions},
     //:tchap:end
     list::{doc as list_doc, handler as list},
     lock::{doc as lock_doc, handler as lock},
+    merge::{doc as merge_doc, handler as merge},
+    permissions::{doc as permissions_doc, handler as permissions},
+    purge::{doc as purge_doc, handler as purge},
+    purge_sessions::{doc as purge_sessions_doc, handler as purge_sessions},
     reactivate::{doc as reactivate_doc, handler as reactivate},
+    revoke_all_sessions::{doc as revoke_all_sessions_doc, handler as revoke_all_sessions},
     set_admin::{doc as set_admin_doc, handler as set_admin},
+    set_email::{doc as set_email_doc, handler as set_email},
+    set_external_id::{doc as set_external_id_doc, handler as set_external_id},
+    set_max_sessions::{doc as set_max_sessions_doc, handler as set_max_sessions},
     set_password::{doc as set_password_doc, handler as set_password},
+    undelete::{doc as undelete_doc, handler as undelete},
     unlock::{doc as unlock_doc, handler as unlock},
 };
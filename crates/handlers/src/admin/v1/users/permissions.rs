@@ -0,0 +1,410 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::sync::Arc;
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, extract::State, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::{User, personal::session::PersonalSessionOwner};
+use mas_policy::PolicyFactory;
+use mas_storage::{
+    BoxRepository, Pagination, RepositoryError, oauth2::OAuth2SessionFilter,
+    personal::PersonalSessionFilter,
+};
+use oauth2_types::scope::{Scope, ScopeToken};
+use schemars::JsonSchema;
+use serde::Serialize;
+use ulid::Ulid;
+
+use crate::{
+    admin::{call_context::CallContext, params::UlidPathParam, response::ErrorResponse},
+    impl_from_error_for_route,
+};
+
+/// The scope giving access to the admin API
+const ADMIN_SCOPE: ScopeToken = ScopeToken::from_static("urn:mas:admin");
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    NotFound(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// The kind of session holding the admin scope
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AdminSessionKind {
+    /// An OAuth 2.0 session
+    #[serde(rename = "oauth2-session")]
+    OAuth2Session,
+
+    /// A personal session, acting as the user
+    PersonalSession,
+}
+
+/// An active session of the user which holds the `urn:mas:admin` scope
+#[derive(Serialize, JsonSchema)]
+#[serde(rename = "UserPermissionsAdminSession")]
+pub struct AdminSession {
+    /// The kind of session
+    kind: AdminSessionKind,
+
+    /// The ID of the session
+    #[schemars(with = "crate::admin::schema::Ulid")]
+    id: Ulid,
+
+    /// The ID of the OAuth 2.0 client the session was created for. For
+    /// personal sessions, this is the client owning the session, if it isn't
+    /// owned by a user.
+    #[schemars(with = "Option<crate::admin::schema::Ulid>")]
+    client_id: Option<Ulid>,
+
+    /// When the session was created
+    created_at: DateTime<Utc>,
+
+    /// Whether the lock or deactivation status of the user currently lets
+    /// this session call the admin API
+    usable: bool,
+}
+
+/// # JSON response for the `GET /api/admin/v1/users/{id}/permissions` endpoint
+#[derive(Serialize, JsonSchema)]
+#[serde(rename = "UserPermissions")]
+pub struct Response {
+    /// Whether the user has the `can_request_admin` flag set
+    can_request_admin: bool,
+
+    /// Whether the username is listed in the `admin_users` policy data, which
+    /// lets the user request the admin scope regardless of the
+    /// `can_request_admin` flag
+    admin_by_policy: bool,
+
+    /// When the user was locked, if they are. Locked users can't use OAuth
+    /// 2.0 sessions to call the admin API, but can still use personal
+    /// sessions.
+    locked_at: Option<DateTime<Utc>>,
+
+    /// When the user was deactivated, if they are. Deactivated users can't
+    /// call the admin API at all.
+    deactivated_at: Option<DateTime<Utc>>,
+
+    /// The active sessions of the user which hold the `urn:mas:admin` scope
+    admin_sessions: Vec<AdminSession>,
+
+    /// Whether at least one of the sessions of the user can currently call the
+    /// admin API
+    has_admin_access: bool,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("getUserPermissions")
+        .summary("Get a summary of the admin permissions of a user")
+        .description(
+            "Explains whether the user can access the admin API: whether they can request the admin scope, which of their active sessions hold it, and whether their lock or deactivation status blocks those sessions.",
+        )
+        .tag("user")
+        .response_with::<200, Json<Response>, _>(|t| {
+            t.description("The permissions of the user").example(Response {
+                can_request_admin: true,
+                admin_by_policy: false,
+                locked_at: None,
+                deactivated_at: None,
+                admin_sessions: vec![AdminSession {
+                    kind: AdminSessionKind::OAuth2Session,
+                    id: Ulid::from_bytes([0x01; 16]),
+                    client_id: Some(Ulid::from_bytes([0x02; 16])),
+                    created_at: DateTime::default(),
+                    usable: true,
+                }],
+                has_admin_access: true,
+            })
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User was not found").example(response)
+        })
+}
+
+/// Collect the active sessions of the user holding the admin scope
+async fn admin_sessions(
+    repo: &mut BoxRepository,
+    user: &User,
+) -> Result<Vec<AdminSession>, RepositoryError> {
+    let scope = Scope::from_iter([ADMIN_SCOPE]);
+    let mut sessions = Vec::new();
+
+    let mut cursor = Pagination::first(1000);
+    loop {
+        let page = repo
+            .oauth2_session()
+            .list(
+                OAuth2SessionFilter::new()
+                    .for_user(user)
+                    .active_only()
+                    .with_scope(&scope),
+                cursor,
+            )
+            .await?;
+
+        for edge in page.edges {
+            let session = edge.node;
+            sessions.push(AdminSession {
+                kind: AdminSessionKind::OAuth2Session,
+                id: session.id,
+                client_id: Some(session.client_id),
+                created_at: session.created_at,
+                // This is the check done by the admin API for OAuth 2.0 sessions
+                usable: user.is_valid(),
+            });
+            cursor = cursor.after(edge.cursor);
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    let mut cursor = Pagination::first(1000);
+    loop {
+        let page = repo
+            .personal_session()
+            .list(
+                PersonalSessionFilter::new()
+                    .for_actor_user(user)
+                    .active_only()
+                    .with_scope(&scope),
+                cursor,
+            )
+            .await?;
+
+        for edge in page.edges {
+            let (session, _) = edge.node;
+            let client_id = match session.owner {
+                PersonalSessionOwner::User(_) => None,
+                PersonalSessionOwner::OAuth2Client(client_id) => Some(client_id),
+            };
+            sessions.push(AdminSession {
+                kind: AdminSessionKind::PersonalSession,
+                id: session.id,
+                client_id,
+                created_at: session.created_at,
+                // This is the check done by the admin API for personal sessions
+                usable: user.is_valid_actor(),
+            });
+            cursor = cursor.after(edge.cursor);
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    Ok(sessions)
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.permissions", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    State(policy_factory): State<Arc<PolicyFactory>>,
+    id: UlidPathParam,
+) -> Result<Json<Response>, RouteError> {
+    let user = repo
+        .user()
+        .lookup(*id)
+        .await?
+        .ok_or(RouteError::NotFound(*id))?;
+
+    let admin_sessions = admin_sessions(&mut repo, &user).await?;
+    let has_admin_access = admin_sessions.iter().any(|session| session.usable);
+
+    Ok(Json(Response {
+        can_request_admin: user.can_request_admin,
+        admin_by_policy: policy_factory.is_admin_user(&user.username),
+        locked_at: user.locked_at,
+        deactivated_at: user.deactivated_at,
+        admin_sessions,
+        has_admin_access,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::Clock;
+    use mas_storage::{
+        RepositoryAccess,
+        oauth2::{OAuth2ClientRepository, OAuth2SessionRepository},
+        user::{BrowserSessionRepository, UserRepository},
+    };
+    use oauth2_types::scope::{OPENID, Scope};
+    use sqlx::PgPool;
+
+    use super::ADMIN_SCOPE;
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, policy_factory, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_permissions(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let alice = repo
+            .user()
+            .set_can_request_admin(alice, true)
+            .await
+            .unwrap();
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &state.clock,
+                vec![],
+                None,
+                None,
+                None,
+                vec![],
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        // One session with the admin scope, one without
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &alice, None)
+            .await
+            .unwrap();
+        let admin_session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut rng,
+                &state.clock,
+                &client,
+                &browser_session,
+                Scope::from_iter([OPENID, ADMIN_SCOPE]),
+            )
+            .await
+            .unwrap();
+        repo.oauth2_session()
+            .add_from_browser_session(
+                &mut rng,
+                &state.clock,
+                &client,
+                &browser_session,
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get(format!("/api/admin/v1/users/{}/permissions", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "can_request_admin": true,
+                "admin_by_policy": false,
+                "locked_at": null,
+                "deactivated_at": null,
+                "admin_sessions": [{
+                    "kind": "oauth2-session",
+                    "id": admin_session.id,
+                    "client_id": client.id,
+                    "created_at": admin_session.created_at,
+                    "usable": true,
+                }],
+                "has_admin_access": true,
+            })
+        );
+
+        // Once locked, the session can't be used anymore
+        let mut repo = state.repository().await.unwrap();
+        repo.user().lock(&state.clock, alice.clone()).await.unwrap();
+        repo.save().await.unwrap();
+
+        // Listing the user in the policy data is reported as well
+        state.policy_factory = policy_factory(
+            "example.com",
+            serde_json::json!({
+                "admin_users": ["alice"],
+            }),
+        )
+        .await
+        .unwrap();
+
+        let request = Request::get(format!("/api/admin/v1/users/{}/permissions", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["admin_by_policy"], true);
+        assert_eq!(body["locked_at"], serde_json::json!(state.clock.now()));
+        assert_eq!(body["admin_sessions"][0]["usable"], false);
+        assert_eq!(body["has_admin_access"], false);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_not_found(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request = Request::get(format!(
+            "/api/admin/v1/users/{}/permissions",
+            ulid::Ulid::nil()
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
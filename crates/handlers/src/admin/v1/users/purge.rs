@@ -0,0 +1,420 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::sync::Arc;
+
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, extract::State, response::IntoResponse};
+use axum_extra::extract::{Query, QueryRejection};
+use axum_macros::FromRequestParts;
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::{BoxRng, SiteConfig};
+use mas_matrix::HomeserverConnection;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+use ulid::Ulid;
+
+use crate::{
+    admin::{call_context::CallContext, params::UlidPathParam, response::ErrorResponse},
+    impl_from_error_for_route,
+    user_actions::delete_user,
+};
+
+#[derive(FromRequestParts, Deserialize, JsonSchema, OperationIo)]
+#[serde(rename = "PurgeUserParams")]
+#[aide(input_with = "Query<Params>")]
+#[from_request(via(Query), rejection(RouteError))]
+pub struct Params {
+    /// The username of the user, to confirm the deletion. It must match
+    /// exactly.
+    confirm: String,
+}
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error(transparent)]
+    Homeserver(anyhow::Error),
+
+    #[error("Invalid parameters")]
+    InvalidParams(#[from] QueryRejection),
+
+    #[error("User ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("User ID {0} is protected and can't be deleted")]
+    Protected(Ulid),
+
+    #[error("User ID {0} must be deactivated or locked before being deleted")]
+    StillActive(Ulid),
+
+    #[error("The confirmation doesn't match the username of user ID {0}")]
+    ConfirmationMismatch(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_) | Self::Homeserver(_));
+        let status = match self {
+            Self::Internal(_) | Self::Homeserver(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidParams(_) | Self::ConfirmationMismatch(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Protected(_) => StatusCode::FORBIDDEN,
+            Self::StillActive(_) => StatusCode::CONFLICT,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("purgeUser")
+        .summary("Permanently delete a user")
+        .description(
+            "Calling this endpoint on a user which isn't deleted yet will mark it as deleted, like the delete endpoint does, if deleted users are kept for a retention period (`account.deleted_user_retention`). Calling it again on the deleted user erases it from the homeserver, and permanently removes it and all its data right away, without waiting for the end of the retention period.
+If deleted users are not retained, the user is permanently removed on the first call.
+The user must already be deactivated or locked, and its username must be given in the `confirm` parameter.
+Users listed in the `account.protected_usernames` configuration option can't be deleted.",
+        )
+        .tag("user")
+        .response_with::<202, (), _>(|t| t.description("User was marked as deleted"))
+        .response_with::<204, (), _>(|t| t.description("User was permanently deleted"))
+        .response_with::<400, RouteError, _>(|t| {
+            let response =
+                ErrorResponse::from_error(&RouteError::ConfirmationMismatch(Ulid::nil()));
+            t.description("The confirmation doesn't match the username")
+                .example(response)
+        })
+        .response_with::<403, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::Protected(Ulid::nil()));
+            t.description("User is protected").example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User ID not found").example(response)
+        })
+        .response_with::<409, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::StillActive(Ulid::nil()));
+            t.description("User is neither deactivated nor locked")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.purge", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    State(site_config): State<SiteConfig>,
+    State(homeserver): State<Arc<dyn HomeserverConnection>>,
+    id: UlidPathParam,
+    params: Params,
+) -> Result<StatusCode, RouteError> {
+    let id = *id;
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    if site_config.protected_usernames.contains(&user.username) {
+        return Err(RouteError::Protected(id));
+    }
+
+    if user.deactivated_at.is_none() && user.locked_at.is_none() {
+        return Err(RouteError::StillActive(id));
+    }
+
+    if params.confirm != user.username {
+        return Err(RouteError::ConfirmationMismatch(id));
+    }
+
+    // When deleted users are retained, go through the regular deletion first,
+    // so that it can still be undone until the user is purged
+    if !site_config.deleted_user_retention.is_zero() && !user.is_deleted() {
+        let user = delete_user(&mut repo, &mut rng, &clock, user).await?;
+
+        repo.save().await?;
+
+        info!(%user.id, "Deleted user, it will be purged after the retention period");
+
+        return Ok(StatusCode::ACCEPTED);
+    }
+
+    // Erase the user on the homeserver first, so that nothing is removed
+    // locally if that fails
+    homeserver
+        .delete_user(&user.username, true)
+        .await
+        .map_err(RouteError::Homeserver)?;
+
+    let username = user.username.clone();
+    repo.user().purge(user).await?;
+
+    repo.save().await?;
+
+    info!(user.id = %id, "Permanently deleted user {username}");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{Device, SiteConfig};
+    use mas_matrix::{HomeserverConnection, ProvisionRequest};
+    use mas_storage::{
+        RepositoryAccess,
+        compat::CompatSessionRepository,
+        user::{BrowserSessionRepository, UserEmailRepository, UserRepository},
+    };
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup, test_site_config};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_purge_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &alice,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &alice, None)
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let compat_session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &alice, device, None, false, None)
+            .await
+            .unwrap();
+        let alice = repo.user().deactivate(&state.clock, alice).await.unwrap();
+        repo.save().await.unwrap();
+
+        state
+            .homeserver_connection
+            .provision_user(&ProvisionRequest::new(&alice.username, &alice.sub))
+            .await
+            .unwrap();
+
+        // The first call only marks alice as deleted, and ends her sessions
+        let request = Request::delete(format!("/api/admin/v1/users/{}?confirm=alice", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::ACCEPTED);
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(alice.id).await.unwrap().unwrap();
+        assert!(user.is_deleted());
+        let browser_session = repo
+            .browser_session()
+            .lookup(browser_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(browser_session.finished_at.is_some());
+        repo.save().await.unwrap();
+
+        // The second call purges alice right away
+        let request = Request::delete(format!("/api/admin/v1/users/{}?confirm=alice", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+
+        // Everything about alice is gone
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.user().lookup(alice.id).await.unwrap().is_none());
+        assert!(!repo.user().exists("alice").await.unwrap());
+        assert!(
+            repo.user_email()
+                .find(&alice, "alice@example.com")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.browser_session()
+                .lookup(browser_session.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.compat_session()
+                .lookup(compat_session.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        repo.save().await.unwrap();
+
+        // Alice was erased from the homeserver
+        let matrix_user = state
+            .homeserver_connection
+            .query_user(&alice.username)
+            .await
+            .unwrap();
+        assert!(matrix_user.deactivated);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_purge_user_interlocks(pool: PgPool) {
+        setup();
+        let site_config = SiteConfig {
+            protected_usernames: vec!["service".to_owned()],
+            ..test_site_config()
+        };
+        let mut state = TestState::from_pool_with_site_config(pool, site_config)
+            .await
+            .unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let service = repo
+            .user()
+            .add(&mut rng, &state.clock, "service".to_owned())
+            .await
+            .unwrap();
+        let service = repo.user().lock(&state.clock, service).await.unwrap();
+        repo.save().await.unwrap();
+
+        // Active users can't be deleted
+        let request = Request::delete(format!("/api/admin/v1/users/{}?confirm=alice", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CONFLICT);
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo.user().lock(&state.clock, alice).await.unwrap();
+        repo.save().await.unwrap();
+
+        // The confirmation is required, and must match the username exactly
+        let request = Request::delete(format!("/api/admin/v1/users/{}", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let request = Request::delete(format!("/api/admin/v1/users/{}?confirm=Alice", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            format!(
+                "The confirmation doesn't match the username of user ID {}",
+                alice.id
+            )
+        );
+
+        // Protected users can't be deleted, even when locked
+        let request = Request::delete(format!(
+            "/api/admin/v1/users/{}?confirm=service",
+            service.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        // Nothing was deleted
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.user().lookup(alice.id).await.unwrap().is_some());
+        assert!(repo.user().lookup(service.id).await.unwrap().is_some());
+        repo.save().await.unwrap();
+
+        // Once all the interlocks are satisfied, the user is deleted, then
+        // purged
+        let request = Request::delete(format!("/api/admin/v1/users/{}?confirm=alice", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::ACCEPTED);
+
+        let request = Request::delete(format!("/api/admin/v1/users/{}?confirm=alice", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+
+        let request = Request::delete(format!("/api/admin/v1/users/{}?confirm=alice", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_purge_user_without_retention(pool: PgPool) {
+        setup();
+        let site_config = SiteConfig {
+            deleted_user_retention: Duration::zero(),
+            ..test_site_config()
+        };
+        let mut state = TestState::from_pool_with_site_config(pool, site_config)
+            .await
+            .unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let alice = repo.user().deactivate(&state.clock, alice).await.unwrap();
+        repo.save().await.unwrap();
+
+        // Deleted users are not retained, so alice is purged right away
+        let request = Request::delete(format!("/api/admin/v1/users/{}?confirm=alice", alice.id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.user().lookup(alice.id).await.unwrap().is_none());
+        repo.save().await.unwrap();
+    }
+}
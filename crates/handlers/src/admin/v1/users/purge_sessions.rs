@@ -0,0 +1,412 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use ulid::Ulid;
+
+use crate::{
+    admin::{call_context::CallContext, params::UlidPathParam, response::ErrorResponse},
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    NotFound(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/users/:id/purge-sessions`
+/// endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "PurgeUserSessionsRequest")]
+pub struct Request {
+    /// Only sessions which finished strictly before this date are deleted.
+    /// Sessions which are still active are never deleted.
+    finished_before: DateTime<Utc>,
+}
+
+/// # JSON response for the `POST /api/admin/v1/users/:id/purge-sessions`
+/// endpoint
+#[derive(Serialize, JsonSchema)]
+#[serde(rename = "PurgeUserSessionsResponse")]
+pub struct Response {
+    /// The number of compatibility sessions which were deleted, along with
+    /// their access and refresh tokens
+    compat_sessions: usize,
+
+    /// The number of OAuth 2.0 sessions which were deleted, along with their
+    /// access and refresh tokens
+    oauth2_sessions: usize,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("purgeUserSessions")
+        .summary("Permanently delete the old finished sessions of a user")
+        .description(
+            "Permanently delete the compatibility and OAuth 2.0 sessions of the user which finished before the given date, along with their access and refresh tokens.
+Sessions which are still active are never deleted, regardless of when they were created.
+This cannot be undone.",
+        )
+        .tag("user")
+        .response_with::<200, Json<Response>, _>(|t| {
+            t.description("The sessions were deleted").example(Response {
+                compat_sessions: 3,
+                oauth2_sessions: 1,
+            })
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User ID not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.purge_sessions", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    id: UlidPathParam,
+    Json(params): Json<Request>,
+) -> Result<Json<Response>, RouteError> {
+    let id = *id;
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    let compat_sessions = repo
+        .compat_session()
+        .delete_finished_before(&user, params.finished_before)
+        .await?;
+
+    let oauth2_sessions = repo
+        .oauth2_session()
+        .delete_finished_before(&user, params.finished_before)
+        .await?;
+
+    info!(
+        %user.id,
+        finished_before = %params.finished_before,
+        compat_sessions,
+        oauth2_sessions,
+        "Purged finished sessions of user"
+    );
+
+    repo.save().await?;
+
+    Ok(Json(Response {
+        compat_sessions,
+        oauth2_sessions,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{Clock, Device, TokenType};
+    use mas_storage::{
+        RepositoryAccess,
+        compat::{CompatAccessTokenRepository, CompatSessionRepository},
+        oauth2::{
+            OAuth2AccessTokenRepository, OAuth2ClientRepository, OAuth2RefreshTokenRepository,
+            OAuth2SessionRepository,
+        },
+        user::{BrowserSessionRepository, UserRepository},
+    };
+    use oauth2_types::scope::{OPENID, Scope};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_purge_sessions(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &state.clock,
+                vec![],
+                None,
+                None,
+                None,
+                vec![],
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &alice, None)
+            .await
+            .unwrap();
+
+        // An old finished compat session with an access token
+        let device = Device::generate(&mut rng);
+        let old_compat_session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &alice, device, None, false, None)
+            .await
+            .unwrap();
+        let compat_access_token = TokenType::CompatAccessToken.generate(&mut rng);
+        let old_compat_access_token = repo
+            .compat_access_token()
+            .add(
+                &mut rng,
+                &state.clock,
+                &old_compat_session,
+                compat_access_token,
+                None,
+            )
+            .await
+            .unwrap();
+        let old_compat_session = repo
+            .compat_session()
+            .finish(&state.clock, old_compat_session)
+            .await
+            .unwrap();
+
+        // An old finished OAuth 2.0 session with an access and a refresh token
+        let old_oauth2_session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut rng,
+                &state.clock,
+                &client,
+                &browser_session,
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        let access_token = TokenType::AccessToken.generate(&mut rng);
+        let old_access_token = repo
+            .oauth2_access_token()
+            .add(
+                &mut rng,
+                &state.clock,
+                &old_oauth2_session,
+                access_token,
+                None,
+            )
+            .await
+            .unwrap();
+        let refresh_token = TokenType::RefreshToken.generate(&mut rng);
+        let old_refresh_token = repo
+            .oauth2_refresh_token()
+            .add(
+                &mut rng,
+                &state.clock,
+                &old_oauth2_session,
+                &old_access_token,
+                refresh_token,
+            )
+            .await
+            .unwrap();
+        let old_oauth2_session = repo
+            .oauth2_session()
+            .finish(&state.clock, old_oauth2_session)
+            .await
+            .unwrap();
+
+        // Bob's old finished session must not be touched
+        let device = Device::generate(&mut rng);
+        let bob_compat_session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &bob, device, None, false, None)
+            .await
+            .unwrap();
+        let bob_compat_session = repo
+            .compat_session()
+            .finish(&state.clock, bob_compat_session)
+            .await
+            .unwrap();
+
+        // An old session which is still active must not be touched
+        let device = Device::generate(&mut rng);
+        let active_compat_session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &alice, device, None, false, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        state.clock.advance(Duration::try_days(30).unwrap());
+        let cutoff = state.clock.now();
+        state.clock.advance(Duration::try_days(1).unwrap());
+
+        // A session which finished after the cutoff must not be touched
+        let mut repo = state.repository().await.unwrap();
+        let recent_oauth2_session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut rng,
+                &state.clock,
+                &client,
+                &browser_session,
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        let recent_oauth2_session = repo
+            .oauth2_session()
+            .finish(&state.clock, recent_oauth2_session)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/purge-sessions", alice.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "finished_before": cutoff,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "compat_sessions": 1,
+                "oauth2_sessions": 1,
+            })
+        );
+
+        let mut repo = state.repository().await.unwrap();
+
+        // The old finished sessions and their tokens are gone
+        assert!(
+            repo.compat_session()
+                .lookup(old_compat_session.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.compat_access_token()
+                .lookup(old_compat_access_token.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.oauth2_session()
+                .lookup(old_oauth2_session.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.oauth2_access_token()
+                .lookup(old_access_token.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.oauth2_refresh_token()
+                .lookup(old_refresh_token.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Everything else is still there
+        assert!(
+            repo.compat_session()
+                .lookup(bob_compat_session.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            repo.compat_session()
+                .lookup(active_compat_session.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            repo.oauth2_session()
+                .lookup(recent_oauth2_session.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_purge_sessions_unknown_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request =
+            Request::post("/api/admin/v1/users/01040G2081040G2081040G2081/purge-sessions")
+                .bearer(&token)
+                .json(serde_json::json!({
+                    "finished_before": "2023-01-01T00:00:00Z",
+                }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "User ID 01040G2081040G2081040G2081 not found"
+        );
+    }
+}
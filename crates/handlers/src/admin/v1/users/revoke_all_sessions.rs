@@ -0,0 +1,372 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::BoxRng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    admin::{call_context::CallContext, params::UlidPathParam, response::ErrorResponse},
+    impl_from_error_for_route,
+    user_actions::{RevokedSessions, revoke_all_sessions},
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    NotFound(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/users/:id/revoke-all-sessions`
+/// endpoint
+#[derive(Default, Deserialize, JsonSchema)]
+#[serde(rename = "RevokeAllUserSessionsRequest")]
+pub struct Request {
+    /// The ID of a compatibility, OAuth 2.0, browser or personal session to
+    /// keep active, for example the one used by an administrator fixing their
+    /// own account.
+    #[serde(default)]
+    #[schemars(with = "Option<crate::admin::schema::Ulid>")]
+    except_session_id: Option<Ulid>,
+}
+
+/// # JSON response for the `POST /api/admin/v1/users/:id/revoke-all-sessions`
+/// endpoint
+#[derive(Serialize, JsonSchema)]
+#[serde(rename = "RevokeAllUserSessionsResponse")]
+pub struct Response {
+    /// The number of compatibility sessions which were finished
+    compat_sessions: usize,
+
+    /// The number of OAuth 2.0 sessions which were finished
+    oauth2_sessions: usize,
+
+    /// The number of browser sessions which were finished
+    browser_sessions: usize,
+
+    /// The number of personal sessions acting as the user which were revoked
+    personal_sessions: usize,
+
+    /// The number of OAuth 2.0 access tokens which were revoked
+    oauth2_access_tokens: usize,
+
+    /// The number of OAuth 2.0 refresh tokens which were revoked
+    oauth2_refresh_tokens: usize,
+}
+
+impl From<RevokedSessions> for Response {
+    fn from(revoked: RevokedSessions) -> Self {
+        Self {
+            compat_sessions: revoked.compat_sessions,
+            oauth2_sessions: revoked.oauth2_sessions,
+            browser_sessions: revoked.browser_sessions,
+            personal_sessions: revoked.personal_sessions,
+            oauth2_access_tokens: revoked.oauth2_access_tokens,
+            oauth2_refresh_tokens: revoked.oauth2_refresh_tokens,
+        }
+    }
+}
+
+pub fn doc(mut operation: TransformOperation) -> TransformOperation {
+    operation
+        .inner_mut()
+        .request_body
+        .as_mut()
+        .unwrap()
+        .as_item_mut()
+        .unwrap()
+        .required = false;
+
+    operation
+        .id("revokeAllUserSessions")
+        .summary("Revoke all the sessions of a user")
+        .description(
+            "Finish all the active compatibility, OAuth 2.0 and browser sessions of the user, revoke the OAuth 2.0 access and refresh tokens of the active OAuth 2.0 sessions, and revoke the personal sessions acting as the user.
+This does the same as the `kill-sessions` endpoint, but reports how many sessions and tokens were ended in each category.
+A job will be scheduled to sync the user's devices with the homeserver.",
+        )
+        .tag("user")
+        .response_with::<200, Json<Response>, _>(|t| {
+            t.description("The sessions were revoked").example(Response {
+                compat_sessions: 2,
+                oauth2_sessions: 1,
+                browser_sessions: 3,
+                personal_sessions: 1,
+                oauth2_access_tokens: 1,
+                oauth2_refresh_tokens: 1,
+            })
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User ID not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.revoke_all_sessions", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    id: UlidPathParam,
+    body: Option<Json<Request>>,
+) -> Result<Json<Response>, RouteError> {
+    let Json(params) = body.unwrap_or_default();
+    let id = *id;
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    let revoked =
+        revoke_all_sessions(&mut repo, &mut rng, &clock, &user, params.except_session_id).await?;
+
+    repo.save().await?;
+
+    Ok(Json(Response::from(revoked)))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{Device, TokenType, personal::session::PersonalSessionOwner};
+    use oauth2_types::scope::{OPENID, Scope};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, create_test_client, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_revoke_all_sessions(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let client = create_test_client(&state).await;
+        let mut rng = state.rng();
+
+        // Provision a user with two browser sessions, one compat session, one
+        // OAuth 2.0 session with an access and a refresh token, and one personal
+        // session acting as them
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        repo.browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        repo.compat_session()
+            .add(&mut rng, &state.clock, &user, device, None, false, None)
+            .await
+            .unwrap();
+        let oauth2_session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut rng,
+                &state.clock,
+                &client,
+                &browser_session,
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        let access_token = TokenType::AccessToken.generate(&mut rng);
+        let access_token = repo
+            .oauth2_access_token()
+            .add(&mut rng, &state.clock, &oauth2_session, access_token, None)
+            .await
+            .unwrap();
+        let refresh_token = TokenType::RefreshToken.generate(&mut rng);
+        repo.oauth2_refresh_token()
+            .add(
+                &mut rng,
+                &state.clock,
+                &oauth2_session,
+                &access_token,
+                refresh_token,
+            )
+            .await
+            .unwrap();
+        repo.personal_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                PersonalSessionOwner::User(user.id),
+                &user,
+                "Automation".to_owned(),
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!(
+            "/api/admin/v1/users/{}/revoke-all-sessions",
+            user.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "compat_sessions": 1,
+                "oauth2_sessions": 1,
+                "browser_sessions": 2,
+                "personal_sessions": 1,
+                "oauth2_access_tokens": 1,
+                "oauth2_refresh_tokens": 1,
+            })
+        );
+
+        // Calling it again doesn't revoke anything
+        let request = Request::post(format!(
+            "/api/admin/v1/users/{}/revoke-all-sessions",
+            user.id
+        ))
+        .bearer(&token)
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "compat_sessions": 0,
+                "oauth2_sessions": 0,
+                "browser_sessions": 0,
+                "personal_sessions": 0,
+                "oauth2_access_tokens": 0,
+                "oauth2_refresh_tokens": 0,
+            })
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_revoke_all_sessions_except_one(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        // Provision a user with a compat session and two personal sessions
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let compat_session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &user, device, None, false, None)
+            .await
+            .unwrap();
+        let kept_session = repo
+            .personal_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                PersonalSessionOwner::User(user.id),
+                &user,
+                "Kept".to_owned(),
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        let revoked_session = repo
+            .personal_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                PersonalSessionOwner::User(user.id),
+                &user,
+                "Revoked".to_owned(),
+                Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!(
+            "/api/admin/v1/users/{}/revoke-all-sessions",
+            user.id
+        ))
+        .bearer(&token)
+        .json(serde_json::json!({
+            "except_session_id": kept_session.id,
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "compat_sessions": 1,
+                "oauth2_sessions": 0,
+                "browser_sessions": 0,
+                "personal_sessions": 1,
+                "oauth2_access_tokens": 0,
+                "oauth2_refresh_tokens": 0,
+            })
+        );
+
+        let mut repo = state.repository().await.unwrap();
+        let compat_session = repo
+            .compat_session()
+            .lookup(compat_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(compat_session.is_finished());
+        let kept_session = repo
+            .personal_session()
+            .lookup(kept_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(kept_session.is_valid());
+        let revoked_session = repo
+            .personal_session()
+            .lookup(revoked_session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(revoked_session.is_revoked());
+    }
+}
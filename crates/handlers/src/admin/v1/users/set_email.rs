@@ -0,0 +1,317 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::str::FromStr as _;
+
+use aide::{NoApi, OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::BoxRng;
+use mas_storage::{
+    queue::{ProvisionUserJob, QueueJobRepositoryExt as _},
+    user::UserEmailFilter,
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::UserEmail,
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User email {0:?} already in use")]
+    EmailAlreadyInUse(String),
+
+    #[error("Email {email:?} is not valid")]
+    EmailNotValid {
+        email: String,
+
+        #[source]
+        source: lettre::address::AddressError,
+    },
+
+    #[error("Unverified email addresses are not supported")]
+    UnverifiedEmail,
+
+    #[error("User ID {0} not found")]
+    UserNotFound(Ulid),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::EmailAlreadyInUse(_) => StatusCode::CONFLICT,
+            Self::EmailNotValid { .. } | Self::UnverifiedEmail => StatusCode::BAD_REQUEST,
+            Self::UserNotFound(_) => StatusCode::NOT_FOUND,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// # JSON payload for the `POST /api/admin/v1/users/:id/set-email` endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "UserSetEmailRequest")]
+pub struct Request {
+    /// The email address to set on the user.
+    #[schemars(email)]
+    email: String,
+
+    /// Whether the email address should be considered as verified.
+    ///
+    /// Email addresses are always verified once they are attached to a user,
+    /// so this must be `true`.
+    #[serde(default = "default_true")]
+    verified: bool,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("userSetEmail")
+        .summary("Set an email address on a user")
+        .description(r"Attach a verified email address to a user, if it isn't already.
+The other email addresses of the user are kept, and the oldest one is still the one released in the `email` claim.
+The change is then synchronised with the homeserver.
+Note that this endpoint ignores any policy which would normally prevent the email from being added.")
+        .tag("user")
+        .response_with::<200, Json<SingleResponse<UserEmail>>, _>(|t| {
+            let [sample, ..] = UserEmail::samples();
+            let response = SingleResponse::new_canonical(sample);
+            t.description("Email address was set on the user").example(response)
+        })
+        .response_with::<409, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::EmailAlreadyInUse(
+                "alice@example.com".to_owned(),
+            ));
+            t.description("Email already in use by another user").example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::EmailNotValid {
+                email: "not a valid email".to_owned(),
+                source: lettre::address::AddressError::MissingParts,
+            });
+            t.description("Email is not valid").example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::UnverifiedEmail);
+            t.description("Email is not marked as verified").example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::UserNotFound(Ulid::nil()));
+            t.description("User was not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.set_email", skip_all)]
+pub async fn handler(
+    CallContext {
+        mut repo, clock, ..
+    }: CallContext,
+    NoApi(mut rng): NoApi<BoxRng>,
+    id: UlidPathParam,
+    Json(params): Json<Request>,
+) -> Result<Json<SingleResponse<UserEmail>>, RouteError> {
+    let id = *id;
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::UserNotFound(id))?;
+
+    if !params.verified {
+        return Err(RouteError::UnverifiedEmail);
+    }
+
+    // Validate the email
+    if let Err(source) = lettre::Address::from_str(&params.email) {
+        return Err(RouteError::EmailNotValid {
+            email: params.email,
+            source,
+        });
+    }
+
+    let existing_email = repo
+        .user_email()
+        .all(&user)
+        .await?
+        .into_iter()
+        .find(|user_email| user_email.email.eq_ignore_ascii_case(&params.email));
+
+    let user_email = if let Some(user_email) = existing_email {
+        user_email
+    } else {
+        // The email isn't attached to this user, make sure it isn't used by
+        // someone else
+        let count = repo
+            .user_email()
+            .count(UserEmailFilter::new().for_email(&params.email))
+            .await?;
+
+        if count > 0 {
+            return Err(RouteError::EmailAlreadyInUse(params.email));
+        }
+
+        repo.user_email()
+            .add(&mut rng, &clock, &user, params.email)
+            .await?
+    };
+
+    // Schedule a job to update the user on the homeserver
+    repo.queue_job()
+        .schedule_job(&mut rng, &clock, ProvisionUserJob::new(&user))
+        .await?;
+
+    repo.save().await?;
+
+    Ok(Json(SingleResponse::new_canonical(user_email.into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use oauth2_types::scope::{EMAIL, OPENID, Scope};
+    use sqlx::PgPool;
+
+    use crate::{
+        oauth2::user_claims,
+        test_utils::{RequestBuilderExt, ResponseExt, TestState, setup},
+    };
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_set_email(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-email", alice.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "email": "alice@example.org",
+                "verified": true,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"]["email"], "alice@example.org");
+        assert_eq!(body["data"]["attributes"]["user_id"], alice.id.to_string());
+
+        // Setting a second address later keeps both
+        state.clock.advance(Duration::minutes(1));
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-email", alice.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "email": "alice@example.com",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        // Setting an address the user already has doesn't add it again
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-email", alice.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "email": "Alice@example.org",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"]["email"], "alice@example.org");
+
+        let mut repo = state.repository().await.unwrap();
+        let emails = repo.user_email().all(&alice).await.unwrap();
+        let emails: Vec<_> = emails.into_iter().map(|e| e.email).collect();
+        assert_eq!(emails, ["alice@example.com", "alice@example.org"]);
+
+        // The oldest address is still the one released to clients
+        let scope = Scope::from_iter([OPENID, EMAIL]);
+        let claims = user_claims::load(
+            &mut repo,
+            &state.homeserver_connection,
+            &state.site_config,
+            &alice,
+            &scope,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(claims["email"], "alice@example.org");
+        repo.save().await.unwrap();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_email_used_by_another_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut rng, &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.user_email()
+            .add(&mut rng, &state.clock, &bob, "bob@example.com".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-email", alice.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "email": "BOB@example.com",
+                "verified": true,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CONFLICT);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "User email \"BOB@example.com\" already in use"
+        );
+
+        // Nothing changed
+        let mut repo = state.repository().await.unwrap();
+        assert!(repo.user_email().all(&alice).await.unwrap().is_empty());
+        assert_eq!(repo.user_email().all(&bob).await.unwrap().len(), 1);
+        repo.save().await.unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::{Resource, User},
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("External ID {0:?} is already used by another user")]
+    Conflict(String),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/users/:id/set-external-id`
+/// endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "UserSetExternalIdRequest")]
+pub struct Request {
+    /// The ID used by an external provisioning system to identify the user.
+    /// It must be unique across users. If null, the external ID is removed.
+    external_id: Option<String>,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("userSetExternalId")
+        .summary("Set the external ID of a user")
+        .description("The external ID lets an external provisioning system correlate its records with users, without relying on their username. The user can then be looked up with the `GET /api/admin/v1/users/by-external-id/{external_id}` endpoint.")
+        .tag("user")
+        .response_with::<200, Json<SingleResponse<User>>, _>(|t| {
+            // In the samples, the first user has an external ID set
+            let [alice, ..] = User::samples();
+            let id = alice.id();
+            let response =
+                SingleResponse::new(alice, format!("/api/admin/v1/users/{id}/set-external-id"));
+            t.description("User had their external ID set")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User ID not found").example(response)
+        })
+        .response_with::<409, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::Conflict("hr-1234".to_owned()));
+            t.description("Another user already has this external ID")
+                .example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.set_external_id", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    id: UlidPathParam,
+    Json(params): Json<Request>,
+) -> Result<Json<SingleResponse<User>>, RouteError> {
+    let id = *id;
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    if let Some(external_id) = &params.external_id
+        && let Some(other) = repo.user().find_by_external_id(external_id).await?
+        && other.id != user.id
+    {
+        return Err(RouteError::Conflict(external_id.clone()));
+    }
+
+    let user = repo
+        .user()
+        .set_external_id(user, params.external_id)
+        .await?;
+
+    repo.save().await?;
+
+    Ok(Json(SingleResponse::new(
+        User::from(user),
+        format!("/api/admin/v1/users/{id}/set-external-id"),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_storage::{RepositoryAccess, user::UserRepository};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_set_external_id(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-external-id", user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "external_id": "hr-1234",
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"]["external_id"], "hr-1234");
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert_eq!(user.external_id.as_deref(), Some("hr-1234"));
+        repo.save().await.unwrap();
+
+        // Setting the same value again is fine
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-external-id", user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "external_id": "hr-1234",
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        // The user shows up when filtering on users with an external ID
+        let request = Request::get("/api/admin/v1/users?filter[has_external_id]=true")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["meta"]["count"], 1);
+        assert_eq!(body["data"][0]["id"], user.id.to_string());
+
+        // Remove the external ID
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-external-id", user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "external_id": null,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"]["attributes"]["external_id"],
+            serde_json::Value::Null
+        );
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert_eq!(user.external_id, None);
+        repo.save().await.unwrap();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_set_external_id_conflict(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let alice = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user()
+            .set_external_id(alice, Some("hr-1234".to_owned()))
+            .await
+            .unwrap();
+        let bob = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "bob".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-external-id", bob.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "external_id": "hr-1234",
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CONFLICT);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "External ID \"hr-1234\" is already used by another user"
+        );
+
+        // Bob was left untouched
+        let mut repo = state.repository().await.unwrap();
+        let bob = repo.user().lookup(bob.id).await.unwrap().unwrap();
+        assert_eq!(bob.external_id, None);
+        repo.save().await.unwrap();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_set_external_id_unknown_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request =
+            Request::post("/api/admin/v1/users/01040G2081040G2081040G2081/set-external-id")
+                .bearer(&token)
+                .json(serde_json::json!({
+                    "external_id": "hr-1234",
+                }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
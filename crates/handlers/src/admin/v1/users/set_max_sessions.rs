@@ -0,0 +1,227 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::num::NonZeroU32;
+
+use aide::{OperationIo, transform::TransformOperation};
+use axum::{Json, response::IntoResponse};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use ulid::Ulid;
+
+use crate::{
+    admin::{
+        call_context::CallContext,
+        model::{Resource, User},
+        params::UlidPathParam,
+        response::{ErrorResponse, SingleResponse},
+    },
+    impl_from_error_for_route,
+};
+
+#[derive(Debug, thiserror::Error, OperationIo)]
+#[aide(output_with = "Json<ErrorResponse>")]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("User ID {0} not found")]
+    NotFound(Ulid),
+
+    #[error("Maximum number of sessions {0} is too large, it must be at most {max}", max = i32::MAX)]
+    MaxSessionsTooLarge(u32),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let error = ErrorResponse::from_error(&self);
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let status = match self {
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::MaxSessionsTooLarge(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, sentry_event_id, Json(error)).into_response()
+    }
+}
+
+/// # JSON payload for the `POST /api/admin/v1/users/:id/set-max-sessions`
+/// endpoint
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename = "UserSetMaxSessionsRequest")]
+pub struct Request {
+    /// The maximum number of active sessions with a device the user can have.
+    /// If null, the limit from the configuration applies.
+    max_sessions: Option<NonZeroU32>,
+}
+
+pub fn doc(operation: TransformOperation) -> TransformOperation {
+    operation
+        .id("userSetMaxSessions")
+        .summary("Set the maximum number of active sessions of a user")
+        .description("This overrides the `sessions.max_per_user` configuration option for this user. Existing sessions are not ended, the limit is enforced when the user starts a new session.")
+        .tag("user")
+        .response_with::<200, Json<SingleResponse<User>>, _>(|t| {
+            // In the samples, the first user has a session limit set
+            let [alice, ..] = User::samples();
+            let id = alice.id();
+            let response =
+                SingleResponse::new(alice, format!("/api/admin/v1/users/{id}/set-max-sessions"));
+            t.description("User had their session limit set")
+                .example(response)
+        })
+        .response_with::<400, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::MaxSessionsTooLarge(u32::MAX));
+            t.description("Maximum number of sessions is too large")
+                .example(response)
+        })
+        .response_with::<404, RouteError, _>(|t| {
+            let response = ErrorResponse::from_error(&RouteError::NotFound(Ulid::nil()));
+            t.description("User ID not found").example(response)
+        })
+}
+
+#[tracing::instrument(name = "handler.admin.v1.users.set_max_sessions", skip_all)]
+pub async fn handler(
+    CallContext { mut repo, .. }: CallContext,
+    id: UlidPathParam,
+    Json(params): Json<Request>,
+) -> Result<Json<SingleResponse<User>>, RouteError> {
+    let id = *id;
+
+    // The limit is stored as a signed 32-bit integer
+    let max_sessions = params.max_sessions.map(NonZeroU32::get);
+    if let Some(max_sessions) = max_sessions
+        && i32::try_from(max_sessions).is_err()
+    {
+        return Err(RouteError::MaxSessionsTooLarge(max_sessions));
+    }
+
+    let user = repo
+        .user()
+        .lookup(id)
+        .await?
+        .ok_or(RouteError::NotFound(id))?;
+
+    let user = repo.user().set_max_sessions(user, max_sessions).await?;
+
+    repo.save().await?;
+
+    Ok(Json(SingleResponse::new(
+        User::from(user),
+        format!("/api/admin/v1/users/{id}/set-max-sessions"),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_storage::{RepositoryAccess, user::UserRepository};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_set_max_sessions(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-max-sessions", user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "max_sessions": 2,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["data"]["attributes"]["max_sessions"], 2);
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert_eq!(user.max_sessions, Some(2));
+        repo.save().await.unwrap();
+
+        // Zero is not a valid limit
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-max-sessions", user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "max_sessions": 0,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+        // The limit must fit in the database column
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-max-sessions", user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "max_sessions": u32::MAX,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["errors"][0]["title"],
+            "Maximum number of sessions 4294967295 is too large, it must be at most 2147483647"
+        );
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert_eq!(user.max_sessions, Some(2));
+        repo.save().await.unwrap();
+
+        // Remove the override
+        let request = Request::post(format!("/api/admin/v1/users/{}/set-max-sessions", user.id))
+            .bearer(&token)
+            .json(serde_json::json!({
+                "max_sessions": null,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(
+            body["data"]["attributes"]["max_sessions"],
+            serde_json::Value::Null
+        );
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert_eq!(user.max_sessions, None);
+        repo.save().await.unwrap();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_set_max_sessions_unknown_user(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+
+        let request =
+            Request::post("/api/admin/v1/users/01040G2081040G2081040G2081/set-max-sessions")
+                .bearer(&token)
+                .json(serde_json::json!({
+                    "max_sessions": 2,
+                }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
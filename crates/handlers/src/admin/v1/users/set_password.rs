@@ -8,7 +8,7 @@ use aide::{NoApi, OperationIo, transform::TransformOperation};
 use axum::{Json, extract::State, response::IntoResponse};
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
-use mas_data_model::BoxRng;
+use mas_data_model::{AuditEventPayload, BoxRng, PasswordChangeInitiator, SiteConfig};
 use schemars::JsonSchema;
 use serde::Deserialize;
 use ulid::Ulid;
@@ -16,6 +16,7 @@ use zeroize::Zeroizing;
 
 use crate::{
     admin::{call_context::CallContext, params::UlidPathParam, response::ErrorResponse},
+    audit::schedule_audit_event,
     impl_from_error_for_route,
     passwords::PasswordManager,
 };
@@ -99,6 +100,7 @@ pub async fn handler(
     }: CallContext,
     NoApi(mut rng): NoApi<BoxRng>,
     State(password_manager): State<PasswordManager>,
+    State(site_config): State<SiteConfig>,
     id: UlidPathParam,
     Json(params): Json<Request>,
 ) -> Result<StatusCode, RouteError> {
@@ -132,6 +134,16 @@ pub async fn handler(
         .add(&mut rng, &clock, &user, version, hashed_password, None)
         .await?;
 
+    schedule_audit_event(
+        &mut repo,
+        &mut rng,
+        &clock,
+        &site_config,
+        None,
+        AuditEventPayload::password_change(&user, PasswordChangeInitiator::Admin),
+    )
+    .await?;
+
     repo.save().await?;
 
     Ok(StatusCode::NO_CONTENT)
@@ -18,6 +18,7 @@ use crate::{
         response::{ErrorResponse, SingleResponse},
     },
     impl_from_error_for_route,
+    user_actions::unlock_user,
 };
 
 #[derive(Debug, thiserror::Error, OperationIo)]
@@ -76,7 +77,7 @@ pub async fn handler(
         .await?
         .ok_or(RouteError::NotFound(id))?;
 
-    let user = repo.user().unlock(user).await?;
+    let user = unlock_user(&mut repo, user).await?;
 
     repo.save().await?;
 
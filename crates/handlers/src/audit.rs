@@ -0,0 +1,257 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Scheduling of the events sent to the audit webhook
+
+use std::net::IpAddr;
+
+use mas_data_model::{AuditEvent, AuditEventPayload, Clock, SiteConfig};
+use mas_storage::{
+    BoxRepository, RepositoryError,
+    queue::{QueueJobRepositoryExt as _, SendAuditEventJob},
+};
+use rand::RngCore;
+
+/// Schedule an event to be sent to the audit webhook, in the same transaction
+/// as the operation it reports.
+///
+/// This does nothing if no webhook is configured, or if it is not interested
+/// in this kind of event.
+pub(crate) async fn schedule_audit_event(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    site_config: &SiteConfig,
+    ip_address: Option<IpAddr>,
+    payload: AuditEventPayload,
+) -> Result<(), RepositoryError> {
+    let Some(config) = &site_config.audit_webhook else {
+        return Ok(());
+    };
+
+    if !config.wants(payload.kind()) {
+        return Ok(());
+    }
+
+    let event = AuditEvent::new(rng, clock, ip_address, payload);
+    repo.queue_job()
+        .schedule_job(rng, clock, SendAuditEventJob::new(event))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{AuditEventKind, AuditWebhookConfig, SiteConfig};
+    use mas_matrix::{HomeserverConnection, ProvisionRequest};
+    use mas_storage::{
+        RepositoryAccess,
+        user::{UserPasswordRepository, UserRepository},
+    };
+    use sqlx::PgPool;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method, path},
+    };
+    use zeroize::Zeroizing;
+
+    use crate::test_utils::{RequestBuilderExt, TestState, setup, test_site_config};
+
+    async fn state_with_webhook(pool: PgPool, mock_server: &MockServer) -> TestState {
+        let site_config = SiteConfig {
+            audit_webhook: Some(AuditWebhookConfig {
+                url: format!("{}/audit", mock_server.uri()).parse().unwrap(),
+                secret: Some("hunter2".to_owned()),
+                events: vec![AuditEventKind::LoginSuccess, AuditEventKind::LoginFailure],
+            }),
+            ..test_site_config()
+        };
+
+        TestState::from_pool_with_site_config(pool, site_config)
+            .await
+            .unwrap()
+    }
+
+    async fn add_user_with_password(state: &TestState) {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new("password".to_owned()))
+            .await
+            .unwrap();
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        state
+            .homeserver_connection
+            .provision_user(&ProvisionRequest::new(&user.username, &user.sub))
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+    }
+
+    async fn login(state: &TestState, password: &str) -> StatusCode {
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": "alice",
+            },
+            "password": password,
+        }));
+        state.request(request).await.status()
+    }
+
+    async fn received_events(mock_server: &MockServer) -> Vec<serde_json::Value> {
+        mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| serde_json::from_slice(&request.body).unwrap())
+            .collect()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_audit_webhook_delivery(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/audit"))
+            .and(header("authorization", "Bearer hunter2"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let state = state_with_webhook(pool, &mock_server).await;
+        add_user_with_password(&state).await;
+
+        assert_eq!(login(&state, "wrong").await, StatusCode::FORBIDDEN);
+        assert_eq!(login(&state, "password").await, StatusCode::OK);
+
+        // Nothing is sent until the jobs run
+        assert!(received_events(&mock_server).await.is_empty());
+
+        state.run_jobs_in_queue().await;
+
+        let mut events = received_events(&mock_server).await;
+        events.sort_by_key(|event| event["type"].as_str().unwrap().to_owned());
+        assert_eq!(events.len(), 2);
+
+        assert_eq!(events[0]["type"], "login_failure");
+        assert_eq!(events[0]["username"], "alice");
+        assert_eq!(events[0]["origin"], "compat");
+        assert_eq!(events[0]["reason"], "password_mismatch");
+
+        assert_eq!(events[1]["type"], "login_success");
+        assert_eq!(events[1]["username"], "alice");
+        assert_eq!(events[1]["origin"], "compat");
+
+        // The attempted password never ends up in the payloads
+        for event in &events {
+            assert!(!event.to_string().contains("wrong"));
+            assert!(!event.to_string().contains("password\""));
+        }
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_audit_webhook_retry(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/audit"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/audit"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let state = state_with_webhook(pool, &mock_server).await;
+        add_user_with_password(&state).await;
+
+        assert_eq!(login(&state, "password").await, StatusCode::OK);
+
+        // The first delivery fails, and is retried later
+        state.run_jobs_in_queue().await;
+        assert_eq!(received_events(&mock_server).await.len(), 1);
+
+        state.clock.advance(Duration::minutes(1));
+        state.run_jobs_in_queue().await;
+
+        let events = received_events(&mock_server).await;
+        assert_eq!(events.len(), 2);
+        // Both deliveries are for the same event
+        assert_eq!(events[0], events[1]);
+        assert_eq!(events[1]["type"], "login_success");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_audit_webhook_dead_letter(pool: PgPool) {
+        setup();
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/audit"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(5)
+            .mount(&mock_server)
+            .await;
+
+        let state = state_with_webhook(pool.clone(), &mock_server).await;
+        add_user_with_password(&state).await;
+
+        assert_eq!(login(&state, "password").await, StatusCode::OK);
+
+        // The event is retried a few times before giving up on it
+        for _ in 0..4 {
+            state.run_jobs_in_queue().await;
+            state.clock.advance(Duration::hours(1));
+        }
+        state.run_jobs_in_queue().await;
+        assert_eq!(received_events(&mock_server).await.len(), 5);
+
+        // The circuit breaker is now open, so new events are not even tried
+        assert_eq!(login(&state, "password").await, StatusCode::OK);
+        state.run_jobs_in_queue().await;
+        assert_eq!(received_events(&mock_server).await.len(), 5);
+
+        // Nothing is retried anymore, and both events are kept as failed jobs
+        state.clock.advance(Duration::hours(1));
+        state.run_jobs_in_queue().await;
+        assert_eq!(received_events(&mock_server).await.len(), 5);
+
+        let dead_letters: i64 = sqlx::query_scalar(
+            r"
+                SELECT COUNT(*)
+                FROM queue_jobs
+                WHERE queue_name = 'send-audit-event'
+                  AND status = 'failed'
+                  AND next_attempt_id IS NULL
+            ",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(dead_letters, 2);
+    }
+}
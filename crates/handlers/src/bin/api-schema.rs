@@ -61,6 +61,11 @@ impl_from_ref!(mas_handlers::passwords::PasswordManager);
 impl_from_ref!(Arc<mas_policy::PolicyFactory>);
 impl_from_ref!(mas_data_model::SiteConfig);
 impl_from_ref!(mas_data_model::AppVersion);
+impl_from_ref!(mas_data_model::AppBuildInfo);
+impl_from_ref!(mas_data_model::TchapFeatures);
+impl_from_ref!(mas_handlers::MetadataCache);
+impl_from_ref!(mas_handlers::Limiter);
+impl_from_ref!(reqwest::Client);
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (mut api, _) = mas_handlers::admin_api_router::<DummyState>();
@@ -0,0 +1,376 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Third-party identifiers management for legacy clients
+//!
+//! Email addresses are managed by MAS, so we only expose them read-only, and
+//! allow removing them. Adding new ones has to go through the account
+//! management UI.
+
+use axum::{
+    Json,
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use axum_extra::typed_header::TypedHeader;
+use headers::{Authorization, authorization::Bearer};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::{BoxClock, BoxRng, SiteConfig, UserEmail};
+use mas_storage::{
+    BoxRepository, RepositoryAccess,
+    queue::{ProvisionUserJob, QueueJobRepositoryExt as _},
+    user::UserEmailRepository,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ulid::Ulid;
+
+use super::{CompatAuthError, MatrixError, MatrixJsonBody, authenticate};
+use crate::{BoundActivityTracker, impl_from_error_for_route};
+
+#[derive(Error, Debug)]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error(transparent)]
+    Authentication(#[from] CompatAuthError),
+
+    #[error("Unsupported third-party identifier medium")]
+    UnsupportedMedium,
+
+    #[error("Email address not found")]
+    EmailNotFound,
+
+    #[error("Email changes are not allowed")]
+    EmailChangesDisabled,
+
+    #[error("Can't remove the last email address of user {0}")]
+    LastEmail(Ulid),
+
+    #[error("Adding third-party identifiers is not supported")]
+    AddNotSupported,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+
+        let response = match self {
+            Self::Authentication(e) => return e.into_response(),
+            Self::Internal(_) => MatrixError {
+                errcode: "M_UNKNOWN",
+                error: "Internal error",
+                key: "error.compat.internal",
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::UnsupportedMedium => MatrixError {
+                errcode: "M_INVALID_PARAM",
+                error: "Only email addresses are supported",
+                key: "error.compat.only_email_supported",
+                status: StatusCode::BAD_REQUEST,
+            },
+            Self::EmailNotFound => MatrixError {
+                errcode: "M_NOT_FOUND",
+                error: "Email address not found",
+                key: "error.compat.email_not_found",
+                status: StatusCode::NOT_FOUND,
+            },
+            Self::EmailChangesDisabled => MatrixError {
+                errcode: "M_FORBIDDEN",
+                error: "Email changes are not allowed on this server",
+                key: "error.compat.email_change_not_allowed",
+                status: StatusCode::FORBIDDEN,
+            },
+            Self::LastEmail(_) => MatrixError {
+                errcode: "M_FORBIDDEN",
+                error: "The last email address can't be removed, as it is needed to recover the account",
+                key: "error.compat.last_email",
+                status: StatusCode::FORBIDDEN,
+            },
+            Self::AddNotSupported => MatrixError {
+                errcode: "M_THREEPID_DENIED",
+                error: "Email addresses must be added through the account management page",
+                key: "error.compat.email_add_unsupported",
+                status: StatusCode::FORBIDDEN,
+            },
+        };
+
+        (sentry_event_id, response).into_response()
+    }
+}
+
+#[derive(Serialize)]
+struct ThirdPartyIdentifier {
+    medium: &'static str,
+    address: String,
+    validated_at: i64,
+    added_at: i64,
+}
+
+impl From<UserEmail> for ThirdPartyIdentifier {
+    fn from(email: UserEmail) -> Self {
+        // We only ever store verified email addresses, so they were validated
+        // when they were added
+        let timestamp = email.created_at.timestamp_millis();
+        Self {
+            medium: "email",
+            address: email.email,
+            validated_at: timestamp,
+            added_at: timestamp,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ListResponse {
+    threepids: Vec<ThirdPartyIdentifier>,
+}
+
+#[tracing::instrument(name = "handlers.compat.account_3pid.get", skip_all)]
+pub(crate) async fn get(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    activity_tracker: BoundActivityTracker,
+    maybe_authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<impl IntoResponse, RouteError> {
+    let (_session, user) =
+        authenticate(&clock, &mut repo, &activity_tracker, maybe_authorization).await?;
+
+    let emails = repo.user_email().all(&user).await?;
+
+    Ok(Json(ListResponse {
+        threepids: emails.into_iter().map(ThirdPartyIdentifier::from).collect(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct DeleteRequest {
+    medium: String,
+    address: String,
+}
+
+#[tracing::instrument(name = "handlers.compat.account_3pid.delete", skip_all)]
+pub(crate) async fn delete(
+    clock: BoxClock,
+    mut rng: BoxRng,
+    mut repo: BoxRepository,
+    State(site_config): State<SiteConfig>,
+    activity_tracker: BoundActivityTracker,
+    maybe_authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    MatrixJsonBody(input): MatrixJsonBody<DeleteRequest>,
+) -> Result<impl IntoResponse, RouteError> {
+    let (_session, user) =
+        authenticate(&clock, &mut repo, &activity_tracker, maybe_authorization).await?;
+
+    if input.medium != "email" {
+        return Err(RouteError::UnsupportedMedium);
+    }
+
+    if !site_config.email_change_allowed {
+        return Err(RouteError::EmailChangesDisabled);
+    }
+
+    let emails = repo.user_email().all(&user).await?;
+
+    let Some(email) = emails
+        .iter()
+        .find(|email| email.email.eq_ignore_ascii_case(&input.address))
+        .cloned()
+    else {
+        return Err(RouteError::EmailNotFound);
+    };
+
+    // Account recovery sends a link to one of the user's email addresses, so
+    // removing the last one would lock the user out of recovery
+    if site_config.account_recovery_allowed && emails.len() <= 1 {
+        return Err(RouteError::LastEmail(user.id));
+    }
+
+    repo.user_email().remove(email).await?;
+
+    // Schedule a job to update the user
+    repo.queue_job()
+        .schedule_job(&mut rng, &clock, ProvisionUserJob::new(&user))
+        .await?;
+
+    repo.save().await?;
+
+    // We never bind email addresses on identity servers
+    Ok(Json(serde_json::json!({
+        "id_server_unbind_result": "no-support",
+    })))
+}
+
+#[tracing::instrument(name = "handlers.compat.account_3pid.add", skip_all)]
+pub(crate) async fn add(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    activity_tracker: BoundActivityTracker,
+    maybe_authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<Response, RouteError> {
+    // Still authenticate the request, so that invalid tokens get the usual
+    // error
+    authenticate(&clock, &mut repo, &activity_tracker, maybe_authorization).await?;
+
+    Err(RouteError::AddNotSupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Request;
+    use mas_data_model::{Device, TokenType, User};
+    use mas_storage::{
+        compat::{CompatAccessTokenRepository, CompatSessionRepository},
+        user::UserRepository,
+    };
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    /// Provision a user with the given email addresses and a compatibility
+    /// session, returning the user and its access token
+    async fn user_with_emails(state: &TestState, emails: &[&str]) -> (User, String) {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        for email in emails {
+            repo.user_email()
+                .add(&mut rng, &state.clock, &user, (*email).to_owned())
+                .await
+                .unwrap();
+        }
+
+        let device = Device::generate(&mut rng);
+        let session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &user, device, None, false, None)
+            .await
+            .unwrap();
+
+        let token = TokenType::CompatAccessToken.generate(&mut rng);
+        repo.compat_access_token()
+            .add(&mut rng, &state.clock, &session, token.clone(), None)
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        (user, token)
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_list(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (_user, token) =
+            user_with_emails(&state, &["alice@example.com", "alice@example.org"]).await;
+
+        let request = Request::get("/_matrix/client/v3/account/3pid")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body, @r###"
+        {
+          "threepids": [
+            {
+              "medium": "email",
+              "address": "alice@example.com",
+              "validated_at": 1642344000000,
+              "added_at": 1642344000000
+            },
+            {
+              "medium": "email",
+              "address": "alice@example.org",
+              "validated_at": 1642344000000,
+              "added_at": 1642344000000
+            }
+          ]
+        }
+        "###);
+
+        // Adding email addresses isn't supported
+        let request = Request::post("/_matrix/client/v3/account/3pid/add")
+            .bearer(&token)
+            .json(serde_json::json!({}));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_THREEPID_DENIED");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_delete(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (user, token) =
+            user_with_emails(&state, &["alice@example.com", "alice@example.org"]).await;
+
+        let request = Request::post("/_matrix/client/v3/account/3pid/delete")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "medium": "email",
+                "address": "alice@example.org",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body, @r###"
+        {
+          "id_server_unbind_result": "no-support"
+        }
+        "###);
+
+        let mut repo = state.repository().await.unwrap();
+        let emails = repo.user_email().all(&user).await.unwrap();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].email, "alice@example.com");
+
+        // Removing an unknown address fails
+        let request = Request::post("/_matrix/client/v3/account/3pid/delete")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "medium": "email",
+                "address": "alice@example.org",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_delete_last_email(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (user, token) = user_with_emails(&state, &["alice@example.com"]).await;
+
+        let request = Request::post("/_matrix/client/v3/account/3pid/delete")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "medium": "email",
+                "address": "alice@example.com",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_FORBIDDEN");
+
+        // The email address is still there
+        let mut repo = state.repository().await.unwrap();
+        let emails = repo.user_email().all(&user).await.unwrap();
+        assert_eq!(emails.len(), 1);
+    }
+}
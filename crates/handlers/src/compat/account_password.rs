@@ -0,0 +1,541 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::sync::LazyLock;
+
+use axum::{Extension, Json, extract::State, response::IntoResponse};
+use axum_extra::typed_header::TypedHeader;
+use headers::{Authorization, authorization::Bearer};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::{AuditEventPayload, BoxClock, BoxRng, PasswordChangeInitiator, SiteConfig};
+use mas_storage::{
+    BoxRepository, Pagination, RepositoryAccess,
+    compat::{CompatSessionFilter, CompatSessionRepository},
+    oauth2::{OAuth2SessionFilter, OAuth2SessionRepository},
+    queue::{QueueJobRepositoryExt as _, SyncDevicesJob},
+    user::UserPasswordRepository,
+};
+use opentelemetry::{Key, KeyValue, metrics::Counter};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::info;
+use zeroize::Zeroizing;
+
+use super::{CompatAuthError, MatrixError, MatrixJsonBody, authenticate};
+use crate::{
+    BoundActivityTracker, Limiter, METER, RequesterFingerprint, audit::schedule_audit_event,
+    impl_from_error_for_route, passwords::PasswordManager, rate_limit::PasswordCheckLimitedError,
+};
+
+static PASSWORD_CHANGE_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    METER
+        .u64_counter("mas.compat.password_change_request")
+        .with_description("How many compatibility password change requests have happened")
+        .with_unit("{request}")
+        .build()
+});
+const RESULT: Key = Key::from_static_str("result");
+
+/// The only User-Interactive Authentication stage we support
+const PASSWORD_STAGE: &str = "m.login.password";
+
+#[derive(Error, Debug)]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error(transparent)]
+    Authentication(#[from] CompatAuthError),
+
+    #[error("Password changes are not allowed")]
+    PasswordChangesDisabled,
+
+    #[error("Missing user-interactive authentication")]
+    MissingAuth,
+
+    #[error("Unsupported user-interactive authentication type")]
+    UnsupportedAuthType,
+
+    #[error("User has no password")]
+    NoPassword,
+
+    #[error("Password verification failed")]
+    PasswordMismatch,
+
+    #[error("Request rate limited")]
+    RateLimited(#[from] PasswordCheckLimitedError),
+
+    #[error("New password is too weak")]
+    WeakPassword,
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+impl_from_error_for_route!(crate::passwords::PasswordManagerDisabledError);
+
+impl From<anyhow::Error> for RouteError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::Internal(err.into())
+    }
+}
+
+/// Response sent when the user-interactive authentication is missing or
+/// failed, advertising the only flow we support
+#[derive(Serialize)]
+struct UserInteractiveAuthResponse {
+    #[serde(flatten)]
+    error: Option<MatrixError>,
+    flows: [UserInteractiveAuthFlow; 1],
+    params: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct UserInteractiveAuthFlow {
+    stages: [&'static str; 1],
+}
+
+impl UserInteractiveAuthResponse {
+    fn new(error: Option<MatrixError>) -> Self {
+        Self {
+            error,
+            flows: [UserInteractiveAuthFlow {
+                stages: [PASSWORD_STAGE],
+            }],
+            params: serde_json::Map::new(),
+        }
+    }
+}
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        PASSWORD_CHANGE_COUNTER.add(1, &[KeyValue::new(RESULT, "error")]);
+
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+
+        let response = match self {
+            Self::Authentication(e) => e.into_response(),
+            Self::Internal(_) => MatrixError {
+                errcode: "M_UNKNOWN",
+                error: "Internal error",
+                key: "error.compat.internal",
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+            }
+            .into_response(),
+            Self::PasswordChangesDisabled => MatrixError {
+                errcode: "M_FORBIDDEN",
+                error: "Password changes are not allowed on this server",
+                key: "error.compat.password_change_not_allowed",
+                status: StatusCode::FORBIDDEN,
+            }
+            .into_response(),
+            Self::UnsupportedAuthType => MatrixError {
+                errcode: "M_UNRECOGNIZED",
+                error: "Unsupported authentication type",
+                key: "error.compat.unsupported_auth_type",
+                status: StatusCode::BAD_REQUEST,
+            }
+            .into_response(),
+            Self::RateLimited(_) => MatrixError {
+                errcode: "M_LIMIT_EXCEEDED",
+                error: "Too many password attempts",
+                key: "error.compat.too_many_password_attempts",
+                status: StatusCode::TOO_MANY_REQUESTS,
+            }
+            .into_response(),
+            Self::WeakPassword => MatrixError {
+                errcode: "M_WEAK_PASSWORD",
+                error: "The new password is too weak",
+                key: "error.compat.password_too_weak",
+                status: StatusCode::BAD_REQUEST,
+            }
+            .into_response(),
+            Self::MissingAuth => (
+                StatusCode::UNAUTHORIZED,
+                Json(UserInteractiveAuthResponse::new(None)),
+            )
+                .into_response(),
+            Self::NoPassword | Self::PasswordMismatch => {
+                let error = MatrixError {
+                    errcode: "M_FORBIDDEN",
+                    error: "Invalid password",
+                    key: "error.compat.invalid_password",
+                    status: StatusCode::UNAUTHORIZED,
+                };
+
+                (
+                    StatusCode::UNAUTHORIZED,
+                    Extension(error),
+                    Json(UserInteractiveAuthResponse::new(Some(error))),
+                )
+                    .into_response()
+            }
+        };
+
+        (sentry_event_id, response).into_response()
+    }
+}
+
+/// The simplified User-Interactive Authentication dict we accept: only the
+/// `m.login.password` stage, in a single step
+#[derive(Deserialize)]
+pub(crate) struct AuthenticationData {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RequestBody {
+    new_password: String,
+
+    #[serde(default = "default_logout_devices")]
+    logout_devices: bool,
+
+    auth: Option<AuthenticationData>,
+}
+
+const fn default_logout_devices() -> bool {
+    true
+}
+
+#[tracing::instrument(name = "handlers.compat.account_password.post", skip_all)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn post(
+    clock: BoxClock,
+    mut rng: BoxRng,
+    mut repo: BoxRepository,
+    State(password_manager): State<PasswordManager>,
+    State(site_config): State<SiteConfig>,
+    (State(limiter), requester): (State<Limiter>, RequesterFingerprint),
+    activity_tracker: BoundActivityTracker,
+    maybe_authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    MatrixJsonBody(input): MatrixJsonBody<RequestBody>,
+) -> Result<impl IntoResponse, RouteError> {
+    let (session, user) =
+        authenticate(&clock, &mut repo, &activity_tracker, maybe_authorization).await?;
+
+    if !password_manager.is_enabled() || !site_config.password_change_allowed {
+        return Err(RouteError::PasswordChangesDisabled);
+    }
+
+    let auth = input.auth.ok_or(RouteError::MissingAuth)?;
+    if auth.kind.as_deref() != Some(PASSWORD_STAGE) {
+        return Err(RouteError::UnsupportedAuthType);
+    }
+    let current_password = auth.password.ok_or(RouteError::MissingAuth)?;
+
+    limiter.check_password(requester, &user)?;
+
+    let active_password = repo
+        .user_password()
+        .active(&user)
+        .await?
+        .ok_or(RouteError::NoPassword)?;
+
+    if !password_manager
+        .verify(
+            active_password.version,
+            Zeroizing::new(current_password),
+            active_password.hashed_password.clone(),
+        )
+        .await?
+        .is_success()
+    {
+        return Err(RouteError::PasswordMismatch);
+    }
+
+    if input.new_password.is_empty()
+        || !password_manager.is_password_complex_enough(&input.new_password)?
+    {
+        return Err(RouteError::WeakPassword);
+    }
+
+    let (version, hashed_password) = password_manager
+        .hash(&mut rng, Zeroizing::new(input.new_password))
+        .await?;
+
+    repo.user_password()
+        .add(
+            &mut rng,
+            &clock,
+            &user,
+            version,
+            hashed_password,
+            Some(&active_password),
+        )
+        .await?;
+
+    schedule_audit_event(
+        &mut repo,
+        &mut rng,
+        &clock,
+        &site_config,
+        requester.ip(),
+        AuditEventPayload::password_change(&user, PasswordChangeInitiator::User),
+    )
+    .await?;
+
+    if input.logout_devices {
+        // End all the other compatibility sessions, keeping the one which made
+        // this request
+        let filter = CompatSessionFilter::new().for_user(&user).active_only();
+        let mut pagination = Pagination::first(100);
+        let mut compat_sessions_affected = 0;
+        loop {
+            let page = repo.compat_session().list(filter, pagination).await?;
+            for edge in page.edges {
+                pagination = pagination.after(edge.cursor);
+                let (other_session, _) = edge.node;
+                if other_session.id != session.id {
+                    repo.compat_session().finish(&clock, other_session).await?;
+                    compat_sessions_affected += 1;
+                }
+            }
+
+            if !page.has_next_page {
+                break;
+            }
+        }
+
+        let filter = OAuth2SessionFilter::new().for_user(&user).active_only();
+        let oauth2_sessions_affected = repo.oauth2_session().finish_bulk(&clock, filter).await?;
+
+        info!(
+            "Ended {compat_sessions_affected} compatibility sessions and {oauth2_sessions_affected} OAuth 2.0 sessions after password change for user {user_id}",
+            user_id = user.id
+        );
+
+        // Schedule a job to sync the devices of the user with the homeserver
+        repo.queue_job()
+            .schedule_job(&mut rng, &clock, SyncDevicesJob::new(&user))
+            .await?;
+    }
+
+    repo.save().await?;
+
+    PASSWORD_CHANGE_COUNTER.add(1, &[KeyValue::new(RESULT, "success")]);
+
+    Ok(Json(serde_json::json!({})))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Request;
+    use mas_data_model::User;
+    use mas_matrix::{HomeserverConnection, ProvisionRequest};
+    use mas_storage::{compat::CompatAccessTokenRepository, user::UserRepository};
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    /// Provision a user with a password
+    async fn user_with_password(state: &TestState, username: &str, password: &str) -> User {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, username.to_owned())
+            .await
+            .unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new(password.to_owned()))
+            .await
+            .unwrap();
+
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        state
+            .homeserver_connection
+            .provision_user(&ProvisionRequest::new(&user.username, &user.sub))
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+        user
+    }
+
+    /// Login with a password, returning the access token
+    async fn login(state: &TestState, username: &str, password: &str) -> String {
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": username,
+            },
+            "password": password,
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        body["access_token"].as_str().unwrap().to_owned()
+    }
+
+    /// Check whether an access token can still be used
+    async fn is_token_valid(state: &TestState, token: &str) -> bool {
+        let mut repo = state.repository().await.unwrap();
+        let token = repo
+            .compat_access_token()
+            .find_by_token(token)
+            .await
+            .unwrap()
+            .unwrap();
+        let session = repo
+            .compat_session()
+            .lookup(token.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        session.is_valid()
+    }
+
+    /// Check whether the given password is the active password of the user
+    async fn has_password(state: &TestState, user: &User, password: &str) -> bool {
+        let mut repo = state.repository().await.unwrap();
+        let active = repo.user_password().active(user).await.unwrap().unwrap();
+        state
+            .password_manager
+            .verify(
+                active.version,
+                Zeroizing::new(password.to_owned()),
+                active.hashed_password,
+            )
+            .await
+            .unwrap()
+            .is_success()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_missing_auth(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        user_with_password(&state, "alice", "password").await;
+        let token = login(&state, "alice", "password").await;
+
+        let request = Request::post("/_matrix/client/v3/account/password")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "new_password": "new password",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body, @r###"
+        {
+          "flows": [
+            {
+              "stages": [
+                "m.login.password"
+              ]
+            }
+          ],
+          "params": {}
+        }
+        "###);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_wrong_current_password(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let user = user_with_password(&state, "alice", "password").await;
+        let token = login(&state, "alice", "password").await;
+
+        let request = Request::post("/_matrix/client/v3/account/password")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "new_password": "new password",
+                "auth": {
+                    "type": "m.login.password",
+                    "identifier": {
+                        "type": "m.id.user",
+                        "user": "alice",
+                    },
+                    "password": "wrong password",
+                },
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body, @r###"
+        {
+          "errcode": "M_FORBIDDEN",
+          "error": "Invalid password",
+          "flows": [
+            {
+              "stages": [
+                "m.login.password"
+              ]
+            }
+          ],
+          "params": {}
+        }
+        "###);
+
+        // The password didn't change
+        assert!(has_password(&state, &user, "password").await);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_change_password_logout_devices(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let user = user_with_password(&state, "alice", "password").await;
+        let token = login(&state, "alice", "password").await;
+        let other_token = login(&state, "alice", "password").await;
+
+        // logout_devices defaults to true
+        let request = Request::post("/_matrix/client/v3/account/password")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "new_password": "new password",
+                "auth": {
+                    "type": "m.login.password",
+                    "password": "password",
+                },
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        assert!(has_password(&state, &user, "new password").await);
+
+        // The session which changed the password is still valid, but the other
+        // one was logged out
+        assert!(is_token_valid(&state, &token).await);
+        assert!(!is_token_valid(&state, &other_token).await);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_change_password_keep_devices(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let user = user_with_password(&state, "alice", "password").await;
+        let token = login(&state, "alice", "password").await;
+        let other_token = login(&state, "alice", "password").await;
+
+        let request = Request::post("/_matrix/client/v3/account/password")
+            .bearer(&token)
+            .json(serde_json::json!({
+                "new_password": "new password",
+                "logout_devices": false,
+                "auth": {
+                    "type": "m.login.password",
+                    "password": "password",
+                },
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        assert!(has_password(&state, &user, "new password").await);
+
+        // Both sessions are still valid
+        assert!(is_token_valid(&state, &token).await);
+        assert!(is_token_valid(&state, &other_token).await);
+    }
+}
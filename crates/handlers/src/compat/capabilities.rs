@@ -0,0 +1,220 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Capabilities advertisement for legacy clients
+//!
+//! Clients use this endpoint to decide which account management features to
+//! show. Since those features are handled by MAS, the answer has to reflect
+//! its configuration rather than the homeserver's.
+
+use axum::{Json, extract::State, response::IntoResponse};
+use axum_extra::typed_header::TypedHeader;
+use headers::{Authorization, authorization::Bearer};
+use hyper::StatusCode;
+use mas_axum_utils::record_error;
+use mas_data_model::{BoxClock, SiteConfig};
+use mas_storage::BoxRepository;
+use serde::Serialize;
+use thiserror::Error;
+
+use super::{CompatAuthError, MatrixError, authenticate};
+use crate::{BoundActivityTracker, impl_from_error_for_route, passwords::PasswordManager};
+
+#[derive(Error, Debug)]
+pub enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error(transparent)]
+    Authentication(#[from] CompatAuthError),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::Authentication(e) => e.into_response(),
+            Self::Internal(_) => {
+                let sentry_event_id = record_error!(self);
+                let response = MatrixError {
+                    errcode: "M_UNKNOWN",
+                    error: "Internal error",
+                    key: "error.compat.internal",
+                    status: StatusCode::INTERNAL_SERVER_ERROR,
+                };
+
+                (sentry_event_id, response).into_response()
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BooleanCapability {
+    enabled: bool,
+}
+
+#[derive(Serialize)]
+struct Capabilities {
+    #[serde(rename = "m.change_password")]
+    change_password: BooleanCapability,
+
+    #[serde(rename = "m.set_displayname")]
+    set_displayname: BooleanCapability,
+
+    #[serde(rename = "m.set_avatar_url")]
+    set_avatar_url: BooleanCapability,
+
+    #[serde(rename = "m.3pid_changes")]
+    threepid_changes: BooleanCapability,
+}
+
+#[derive(Serialize)]
+struct Response {
+    capabilities: Capabilities,
+}
+
+#[tracing::instrument(name = "handlers.compat.capabilities.get", skip_all)]
+pub(crate) async fn get(
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    activity_tracker: BoundActivityTracker,
+    State(password_manager): State<PasswordManager>,
+    State(site_config): State<SiteConfig>,
+    maybe_authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<impl IntoResponse, RouteError> {
+    authenticate(&clock, &mut repo, &activity_tracker, maybe_authorization).await?;
+
+    repo.cancel().await?;
+
+    let capabilities = Capabilities {
+        // This mirrors the check done by the password change endpoint
+        change_password: BooleanCapability {
+            enabled: password_manager.is_enabled() && site_config.password_change_allowed,
+        },
+        set_displayname: BooleanCapability {
+            enabled: site_config.displayname_change_allowed,
+        },
+        // MAS has no say on avatars, those are handled by the homeserver
+        set_avatar_url: BooleanCapability { enabled: true },
+        // Email addresses can't be added through the client-server API, as
+        // they have to go through the account management UI
+        threepid_changes: BooleanCapability { enabled: false },
+    };
+
+    Ok(Json(Response { capabilities }))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Request;
+    use mas_data_model::{Device, TokenType};
+    use mas_storage::{
+        RepositoryAccess,
+        compat::{CompatAccessTokenRepository, CompatSessionRepository},
+        user::UserRepository,
+    };
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup, test_site_config};
+
+    /// Provision a user with a compatibility session, returning its access
+    /// token
+    async fn compat_token(state: &TestState) -> String {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        let device = Device::generate(&mut rng);
+        let session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &user, device, None, false, None)
+            .await
+            .unwrap();
+
+        let token = TokenType::CompatAccessToken.generate(&mut rng);
+        repo.compat_access_token()
+            .add(&mut rng, &state.clock, &session, token.clone(), None)
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        token
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_capabilities(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let token = compat_token(&state).await;
+
+        // The endpoint requires a valid compatibility token
+        let request = Request::get("/_matrix/client/v3/capabilities").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_MISSING_TOKEN");
+
+        let request = Request::get("/_matrix/client/v3/capabilities")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body, @r###"
+        {
+          "capabilities": {
+            "m.change_password": {
+              "enabled": true
+            },
+            "m.set_displayname": {
+              "enabled": true
+            },
+            "m.set_avatar_url": {
+              "enabled": true
+            },
+            "m.3pid_changes": {
+              "enabled": false
+            }
+          }
+        }
+        "###);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_capabilities_restricted(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                password_change_allowed: false,
+                displayname_change_allowed: false,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let token = compat_token(&state).await;
+
+        let request = Request::get("/_matrix/client/v3/capabilities")
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["capabilities"]["m.change_password"]["enabled"], false);
+        assert_eq!(body["capabilities"]["m.set_displayname"]["enabled"], false);
+        assert_eq!(body["capabilities"]["m.set_avatar_url"]["enabled"], true);
+        assert_eq!(body["capabilities"]["m.3pid_changes"]["enabled"], false);
+    }
+}
@@ -9,13 +9,16 @@ use std::sync::{Arc, LazyLock};
 use axum::{Json, extract::State, response::IntoResponse};
 use axum_extra::typed_header::TypedHeader;
 use chrono::Duration;
-use hyper::StatusCode;
+use hyper::{HeaderMap, StatusCode};
 use mas_axum_utils::record_error;
 use mas_data_model::{
-    BoxClock, BoxRng, Clock, CompatSession, CompatSsoLoginState, Device, SiteConfig, TokenType,
-    User,
+    AuditEventPayload, AuditSessionType, AuthenticatedBy, BoxClock, BoxRng, Clock, CompatSession,
+    CompatSsoLoginState, Device, InvalidDeviceIdError, LoginFailureOrigin, LoginFailureReason,
+    SiteConfig, TokenType, User,
 };
+use mas_keystore::Encrypter;
 use mas_matrix::HomeserverConnection;
+use mas_router::UrlBuilder;
 use mas_storage::{
     BoxRepository, BoxRepositoryFactory, RepositoryAccess,
     compat::{
@@ -25,6 +28,7 @@ use mas_storage::{
     queue::{QueueJobRepositoryExt as _, SyncDevicesJob},
     user::{UserPasswordRepository, UserRepository},
 };
+use mas_templates::Templates;
 use opentelemetry::{Key, KeyValue, metrics::Counter};
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
@@ -34,9 +38,14 @@ use zeroize::Zeroizing;
 
 use super::{MatrixError, MatrixJsonBody};
 use crate::{
-    BoundActivityTracker, Limiter, METER, RequesterFingerprint, impl_from_error_for_route,
+    BoundActivityTracker, Limiter, METER, PreferredLanguage, RequesterFingerprint,
+    audit::schedule_audit_event,
+    impl_from_error_for_route,
+    login_failures::record_login_failure,
     passwords::{PasswordManager, PasswordVerificationResult},
     rate_limit::PasswordCheckLimitedError,
+    session_limit::{self, SessionLimit},
+    views::revoke_session::schedule_new_login_email,
 };
 
 static LOGIN_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
@@ -211,8 +220,17 @@ pub enum RouteError {
     #[error("user is locked")]
     UserLocked,
 
+    #[error("user is deactivated")]
+    UserDeactivated,
+
+    #[error("user reached the limit of {limit} active sessions")]
+    TooManySessions { limit: u32 },
+
     #[error("failed to provision device")]
     ProvisionDeviceFailed(#[source] anyhow::Error),
+
+    #[error("invalid device ID")]
+    InvalidDeviceId(#[from] InvalidDeviceIdError),
 }
 
 impl_from_error_for_route!(mas_storage::RepositoryError);
@@ -232,48 +250,75 @@ impl IntoResponse for RouteError {
             Self::Internal(_) | Self::ProvisionDeviceFailed(_) => MatrixError {
                 errcode: "M_UNKNOWN",
                 error: "Internal server error",
+                key: "error.compat.internal_server",
                 status: StatusCode::INTERNAL_SERVER_ERROR,
             },
             Self::RateLimited(_) => MatrixError {
                 errcode: "M_LIMIT_EXCEEDED",
                 error: "Too many login attempts",
+                key: "error.compat.too_many_login_attempts",
                 status: StatusCode::TOO_MANY_REQUESTS,
             },
             Self::Unsupported => MatrixError {
                 errcode: "M_UNKNOWN",
                 error: "Invalid login type",
+                key: "error.compat.invalid_login_type",
                 status: StatusCode::BAD_REQUEST,
             },
             Self::UnsupportedIdentifier => MatrixError {
                 errcode: "M_UNKNOWN",
                 error: "Unsupported login identifier",
+                key: "error.compat.unsupported_login_identifier",
                 status: StatusCode::BAD_REQUEST,
             },
             Self::MissingIdentifier => MatrixError {
                 errcode: "M_BAD_JSON",
-                error: "Missing property 'identifier",
+                error: "Missing property 'identifier'",
+                key: "error.compat.missing_identifier",
                 status: StatusCode::BAD_REQUEST,
             },
             Self::UserNotFound | Self::NoPassword | Self::PasswordMismatch => MatrixError {
                 errcode: "M_FORBIDDEN",
                 error: "Invalid username/password",
+                key: "error.compat.invalid_username_password",
                 status: StatusCode::FORBIDDEN,
             },
             Self::LoginTookTooLong => MatrixError {
                 errcode: "M_FORBIDDEN",
                 error: "Login token expired",
+                key: "error.compat.login_token_expired",
                 status: StatusCode::FORBIDDEN,
             },
             Self::InvalidLoginToken => MatrixError {
                 errcode: "M_FORBIDDEN",
                 error: "Invalid login token",
+                key: "error.compat.invalid_login_token",
                 status: StatusCode::FORBIDDEN,
             },
             Self::UserLocked => MatrixError {
                 errcode: "M_USER_LOCKED",
                 error: "User account has been locked",
+                key: "error.compat.user_locked",
                 status: StatusCode::UNAUTHORIZED,
             },
+            Self::UserDeactivated => MatrixError {
+                errcode: "M_USER_DEACTIVATED",
+                error: "User account has been deactivated",
+                key: "error.compat.user_deactivated",
+                status: StatusCode::FORBIDDEN,
+            },
+            Self::TooManySessions { .. } => MatrixError {
+                errcode: "M_FORBIDDEN",
+                error: "Too many active sessions, sign out of another device first",
+                key: "error.compat.too_many_sessions",
+                status: StatusCode::FORBIDDEN,
+            },
+            Self::InvalidDeviceId(_) => MatrixError {
+                errcode: "M_INVALID_PARAM",
+                error: "Invalid device ID",
+                key: "error.compat.invalid_device_id",
+                status: StatusCode::BAD_REQUEST,
+            },
         };
 
         (sentry_event_id, response).into_response()
@@ -290,12 +335,19 @@ pub(crate) async fn post(
     State(homeserver): State<Arc<dyn HomeserverConnection>>,
     State(site_config): State<SiteConfig>,
     State(limiter): State<Limiter>,
+    (State(encrypter), State(url_builder), State(templates)): (
+        State<Encrypter>,
+        State<UrlBuilder>,
+        State<Templates>,
+    ),
     requester: RequesterFingerprint,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
+    headers: HeaderMap,
     MatrixJsonBody(input): MatrixJsonBody<RequestBody>,
 ) -> Result<impl IntoResponse, RouteError> {
     let user_agent = user_agent.map(|ua| ua.as_str().to_owned());
     let login_type = input.credentials.login_type();
+    let requested_device = input.device_id.map(Device::try_from).transpose()?;
     let mut repo = repository_factory.create().await?;
     let (mut session, user) = match (password_manager.is_enabled(), input.credentials) {
         (
@@ -328,10 +380,12 @@ pub(crate) async fn post(
                 &password_manager,
                 &limiter,
                 requester,
+                &repository_factory,
                 &mut repo,
+                &site_config,
                 username,
                 password,
-                input.device_id, // TODO check for validity
+                requested_device,
                 input.initial_device_display_name,
             )
             .await?
@@ -341,9 +395,12 @@ pub(crate) async fn post(
             token_login(
                 &mut rng,
                 &clock,
+                requester,
+                &repository_factory,
                 &mut repo,
+                &site_config,
                 &token,
-                input.device_id,
+                requested_device,
                 input.initial_device_display_name,
             )
             .await?
@@ -387,6 +444,16 @@ pub(crate) async fn post(
         None
     };
 
+    schedule_audit_event(
+        &mut repo,
+        &mut rng,
+        &clock,
+        &site_config,
+        requester.ip(),
+        AuditEventPayload::login_success(&user, LoginFailureOrigin::Compat, session.id),
+    )
+    .await?;
+
     // Ideally, we'd keep the lock whilst we actually create the device, but we
     // really want to stop holding the transaction while we talk to the
     // homeserver.
@@ -438,6 +505,25 @@ pub(crate) async fn post(
         return Err(RouteError::ProvisionDeviceFailed(err));
     }
 
+    if site_config.new_login_notification {
+        let PreferredLanguage(locale) =
+            PreferredLanguage::from_headers(&templates.translator(), &headers);
+
+        let mut repo = repository_factory.create().await?;
+        schedule_new_login_email(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &encrypter,
+            &url_builder,
+            &user,
+            &session,
+            locale.to_string(),
+        )
+        .await?;
+        repo.save().await?;
+    }
+
     LOGIN_COUNTER.add(
         1,
         &[
@@ -455,12 +541,46 @@ pub(crate) async fn post(
     }))
 }
 
+/// Make sure the user can start a new session with the given device, ending
+/// their least recently active sessions if the site is configured to do so
+async fn enforce_session_limit(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    site_config: &SiteConfig,
+    requester: RequesterFingerprint,
+    user: &User,
+    device: &Device,
+) -> Result<(), RouteError> {
+    match session_limit::check(repo, site_config, user, std::slice::from_ref(device)).await? {
+        SessionLimit::Allowed => {}
+        SessionLimit::Reached { limit } => return Err(RouteError::TooManySessions { limit }),
+        SessionLimit::Evict(sessions) => {
+            session_limit::evict(
+                repo,
+                rng,
+                clock,
+                site_config,
+                requester.ip(),
+                user,
+                sessions,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
 async fn token_login(
     rng: &mut (dyn RngCore + Send),
     clock: &dyn Clock,
+    requester: RequesterFingerprint,
+    repository_factory: &BoxRepositoryFactory,
     repo: &mut BoxRepository,
+    site_config: &SiteConfig,
     token: &str,
-    requested_device_id: Option<String>,
+    requested_device: Option<Device>,
     initial_device_display_name: Option<String>,
 ) -> Result<(CompatSession, User), RouteError> {
     let login = repo
@@ -495,12 +615,41 @@ async fn token_login(
             ..
         } => {
             if now > exchanged_at + Duration::microseconds(30 * 1000 * 1000) {
-                // TODO: log that session out
                 tracing::error!(
                     compat_sso_login.id = %login.id,
                     compat_session.id = %compat_session_id,
-                    "Login token exchanged a second time more than 30s after"
+                    "Login token exchanged a second time more than 30s after, ending the session"
                 );
+
+                // The token was most likely leaked, so we end the session it created.
+                // This is done in a separate transaction, as the one of the request is
+                // rolled back when returning an error
+                let mut repo = repository_factory.create().await?;
+                let session = repo.compat_session().lookup(compat_session_id).await?;
+                if let Some(session) = session.filter(|s| s.is_valid()) {
+                    // Finishing the session also invalidates its access and refresh tokens
+                    let session = repo.compat_session().finish(clock, session).await?;
+
+                    repo.queue_job()
+                        .schedule_job(rng, clock, SyncDevicesJob::new_for_id(session.user_id))
+                        .await?;
+
+                    schedule_audit_event(
+                        &mut repo,
+                        rng,
+                        clock,
+                        site_config,
+                        requester.ip(),
+                        AuditEventPayload::session_end(
+                            session.user_id,
+                            session.id,
+                            AuditSessionType::Compat,
+                        ),
+                    )
+                    .await?;
+                }
+
+                repo.save().await?;
             }
 
             return Err(RouteError::InvalidLoginToken);
@@ -538,16 +687,23 @@ async fn token_login(
         .acquire_lock_for_sync(&browser_session.user)
         .await?;
 
-    let device = if let Some(requested_device_id) = requested_device_id {
-        Device::from(requested_device_id)
-    } else {
-        Device::generate(rng)
-    };
+    let device = requested_device.unwrap_or_else(|| Device::generate(rng));
 
     repo.app_session()
         .finish_sessions_to_replace_device(clock, &browser_session.user, &device)
         .await?;
 
+    enforce_session_limit(
+        repo,
+        rng,
+        clock,
+        site_config,
+        requester,
+        &browser_session.user,
+        &device,
+    )
+    .await?;
+
     // We first create the session in the database, commit the transaction, then
     // create it on the homeserver, scheduling a device sync job afterwards to
     // make sure we don't end up in an inconsistent state.
@@ -564,6 +720,11 @@ async fn token_login(
         )
         .await?;
 
+    let compat_session = repo
+        .compat_session()
+        .set_authenticated_by(compat_session, AuthenticatedBy::Token)
+        .await?;
+
     repo.compat_sso_login()
         .exchange(clock, login, &compat_session)
         .await?;
@@ -577,33 +738,53 @@ async fn user_password_login(
     password_manager: &PasswordManager,
     limiter: &Limiter,
     requester: RequesterFingerprint,
+    repository_factory: &BoxRepositoryFactory,
     repo: &mut BoxRepository,
+    site_config: &SiteConfig,
     username: &str,
     password: String,
-    requested_device_id: Option<String>,
+    requested_device: Option<Device>,
     initial_device_display_name: Option<String>,
 ) -> Result<(CompatSession, User), RouteError> {
-    // Find the user
+    // Find the user. We don't record failures for unknown users, to avoid
+    // storing arbitrary usernames
     let user = repo
         .user()
         .find_by_username(username)
         .await?
-        .filter(|user| user.deactivated_at.is_none())
         .ok_or(RouteError::UserNotFound)?;
 
-    if user.locked_at.is_some() {
-        return Err(RouteError::UserLocked);
-    }
-
     // Check the rate limit
-    limiter.check_password(requester, &user)?;
+    if let Err(e) = limiter.check_password(requester, &user) {
+        record_login_failure(
+            repository_factory,
+            &mut rng,
+            clock,
+            site_config,
+            &user,
+            LoginFailureOrigin::Compat,
+            LoginFailureReason::RateLimited,
+            requester,
+        )
+        .await;
+        return Err(e.into());
+    }
 
     // Lookup its password
-    let user_password = repo
-        .user_password()
-        .active(&user)
-        .await?
-        .ok_or(RouteError::NoPassword)?;
+    let Some(user_password) = repo.user_password().active(&user).await? else {
+        record_login_failure(
+            repository_factory,
+            &mut rng,
+            clock,
+            site_config,
+            &user,
+            LoginFailureOrigin::Compat,
+            LoginFailureReason::NoPassword,
+            requester,
+        )
+        .await;
+        return Err(RouteError::NoPassword);
+    };
 
     // Verify the password
     let password = Zeroizing::new(password);
@@ -632,25 +813,75 @@ async fn user_password_login(
         }
         PasswordVerificationResult::Success(None) => {}
         PasswordVerificationResult::Failure => {
+            record_login_failure(
+                repository_factory,
+                &mut rng,
+                clock,
+                site_config,
+                &user,
+                LoginFailureOrigin::Compat,
+                LoginFailureReason::PasswordMismatch,
+                requester,
+            )
+            .await;
             return Err(RouteError::PasswordMismatch);
         }
     }
 
+    // Only tell whether the account is deactivated or locked once the password
+    // was verified, so that this doesn't leak to someone guessing passwords
+    if user.deactivated_at.is_some() {
+        record_login_failure(
+            repository_factory,
+            &mut rng,
+            clock,
+            site_config,
+            &user,
+            LoginFailureOrigin::Compat,
+            LoginFailureReason::UserDeactivated,
+            requester,
+        )
+        .await;
+        return Err(RouteError::UserDeactivated);
+    }
+
+    if user.locked_at.is_some() {
+        record_login_failure(
+            repository_factory,
+            &mut rng,
+            clock,
+            site_config,
+            &user,
+            LoginFailureOrigin::Compat,
+            LoginFailureReason::UserLocked,
+            requester,
+        )
+        .await;
+        return Err(RouteError::UserLocked);
+    }
+
     // We're about to create a device, let's explicitly acquire a lock, so that
     // any concurrent sync will read after we've committed
     repo.user().acquire_lock_for_sync(&user).await?;
 
     // Now that the user credentials have been verified, start a new compat session
-    let device = if let Some(requested_device_id) = requested_device_id {
-        Device::from(requested_device_id)
-    } else {
-        Device::generate(&mut rng)
-    };
+    let device = requested_device.unwrap_or_else(|| Device::generate(&mut rng));
 
     repo.app_session()
         .finish_sessions_to_replace_device(clock, &user, &device)
         .await?;
 
+    enforce_session_limit(
+        repo,
+        &mut rng,
+        clock,
+        site_config,
+        requester,
+        &user,
+        &device,
+    )
+    .await?;
+
     let session = repo
         .compat_session()
         .add(
@@ -664,13 +895,22 @@ async fn user_password_login(
         )
         .await?;
 
+    let session = repo
+        .compat_session()
+        .set_authenticated_by(session, AuthenticatedBy::Password)
+        .await?;
+
     Ok((session, user))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroU32;
+
     use hyper::Request;
+    use mas_data_model::{SessionLimitConfig, SessionLimitStrategy};
     use mas_matrix::{HomeserverConnection, ProvisionRequest};
+    use mas_storage::{Pagination, compat::CompatSessionFilter};
     use rand::distributions::{Alphanumeric, DistString};
     use sqlx::PgPool;
 
@@ -899,6 +1139,20 @@ mod tests {
         }
         "###);
 
+        // The session records how the user authenticated
+        let mut repo = state.repository().await.unwrap();
+        let sessions = repo
+            .compat_session()
+            .list(
+                CompatSessionFilter::new().for_user(&user),
+                Pagination::first(1),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        let (session, _) = &sessions.edges[0].node;
+        assert_eq!(session.authenticated_by, AuthenticatedBy::Password);
+
         // Do the same, but this time ask for a refresh token.
         let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
             "type": "m.login.password",
@@ -1002,12 +1256,12 @@ mod tests {
         let response = state.request(request.clone()).await;
         response.assert_status(StatusCode::FORBIDDEN);
         let body: serde_json::Value = response.json();
-        insta::assert_json_snapshot!(body, @r###"
+        insta::assert_json_snapshot!(body, @r#"
         {
-          "errcode": "M_FORBIDDEN",
-          "error": "Invalid username/password"
+          "errcode": "M_USER_DEACTIVATED",
+          "error": "User account has been deactivated"
         }
-        "###);
+        "#);
 
         // Should get the same error if the deactivated user is also locked
         let mut repo = state.repository().await.unwrap();
@@ -1017,12 +1271,144 @@ mod tests {
         let response = state.request(request).await;
         response.assert_status(StatusCode::FORBIDDEN);
         let body: serde_json::Value = response.json();
-        insta::assert_json_snapshot!(body, @r###"
+        insta::assert_json_snapshot!(body, @r#"
         {
-          "errcode": "M_FORBIDDEN",
-          "error": "Invalid username/password"
+          "errcode": "M_USER_DEACTIVATED",
+          "error": "User account has been deactivated"
         }
-        "###);
+        "#);
+    }
+
+    /// Test that the status of locked and deactivated accounts is only
+    /// disclosed once the password was verified
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_user_password_login_account_status(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let alice = user_with_password(&state, "alice", "password", true).await;
+        let bob = user_with_password(&state, "bob", "password", false).await;
+        let mut repo = state.repository().await.unwrap();
+        let bob = repo.user().deactivate(&state.clock, bob).await.unwrap();
+        repo.save().await.unwrap();
+
+        let login = |user: &str, password: &str| {
+            Request::post("/_matrix/client/v3/login")
+                .header("Accept-Language", "fr")
+                .json(serde_json::json!({
+                    "type": "m.login.password",
+                    "identifier": {
+                        "type": "m.id.user",
+                        "user": user,
+                    },
+                    "password": password,
+                }))
+        };
+
+        // A wrong password on a locked account gets the generic error
+        let response = state.request(login("alice", "wrongpassword")).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_FORBIDDEN");
+
+        // The right password tells that the account is locked
+        let response = state.request(login("alice", "password")).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body, @r#"
+        {
+          "errcode": "M_USER_LOCKED",
+          "error": "Le compte utilisateur a été verrouillé"
+        }
+        "#);
+
+        // Reset the state, to reset rate limits
+        let state = state.reset().await;
+
+        // Same for deactivated accounts
+        let response = state.request(login("bob", "wrongpassword")).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_FORBIDDEN");
+
+        let response = state.request(login("bob", "password")).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body, @r#"
+        {
+          "errcode": "M_USER_DEACTIVATED",
+          "error": "Le compte utilisateur a été désactivé"
+        }
+        "#);
+
+        // The failures were recorded with the right reason
+        let mut repo = state.repository().await.unwrap();
+        for (user, reason) in [
+            (&alice, LoginFailureReason::UserLocked),
+            (&bob, LoginFailureReason::UserDeactivated),
+        ] {
+            let failures = repo
+                .user_login_failure()
+                .list_recent(user, 10)
+                .await
+                .unwrap();
+            let reasons: Vec<_> = failures.iter().map(|failure| failure.reason).collect();
+            assert_eq!(reasons.len(), 2);
+            assert!(reasons.contains(&reason));
+            assert!(reasons.contains(&LoginFailureReason::PasswordMismatch));
+        }
+        repo.save().await.unwrap();
+    }
+
+    /// Test that failed login attempts are recorded for known users only
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_password_login_failures_recorded(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+
+        let user = user_with_password(&state, "alice", "password", false).await;
+
+        // Try to login with a wrong password
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": "alice",
+            },
+            "password": "wrongpassword",
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        let mut repo = state.repository().await.unwrap();
+        let failures = repo
+            .user_login_failure()
+            .list_recent(&user, 10)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].origin, LoginFailureOrigin::Compat);
+        assert_eq!(failures[0].reason, LoginFailureReason::PasswordMismatch);
+
+        // Try to login with an unknown user
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": "bob",
+            },
+            "password": "wrongpassword",
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        // Nothing should have been recorded for the unknown user
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_login_failures")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
     }
 
     /// Test that we can send a login request without a Content-Type header
@@ -1123,6 +1509,51 @@ mod tests {
         "###);
     }
 
+    /// Test that a login with a device ID which isn't valid is rejected
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_password_login_invalid_device_id(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        user_with_password(&state, "alice", "password", false).await;
+
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": "alice",
+            },
+            "password": "password",
+            "device_id": "my device",
+        }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body, @r###"
+        {
+          "errcode": "M_INVALID_PARAM",
+          "error": "Invalid device ID"
+        }
+        "###);
+
+        // A valid device ID is used as-is
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": "alice",
+            },
+            "password": "password",
+            "device_id": "MYDEVICE",
+        }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["device_id"], "MYDEVICE");
+    }
+
     /// Test that password logins are rate limited.
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_password_login_rate_limit(pool: PgPool) {
@@ -1177,6 +1608,179 @@ mod tests {
         "###);
     }
 
+    /// Log in as alice with a password and return the ID of the new device
+    async fn password_login_device(state: &TestState) -> String {
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": "alice",
+            },
+            "password": "password",
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        body["device_id"].as_str().unwrap().to_owned()
+    }
+
+    /// Test that a new login is refused once the user reached the session
+    /// limit, with the default strategy.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_session_limit_reject(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                session_limit: SessionLimitConfig {
+                    max_per_user: NonZeroU32::new(2),
+                    strategy: SessionLimitStrategy::Reject,
+                },
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+
+        let user = user_with_password(&state, "alice", "password", false).await;
+
+        password_login_device(&state).await;
+        password_login_device(&state).await;
+
+        // The third login should be refused
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": "alice",
+            },
+            "password": "password",
+        }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(body, @r###"
+        {
+          "errcode": "M_FORBIDDEN",
+          "error": "Too many active sessions, sign out of another device first"
+        }
+        "###);
+
+        // Only the first two sessions should exist
+        let mut repo = state.repository().await.unwrap();
+        let count = repo
+            .compat_session()
+            .count(CompatSessionFilter::new().for_user(&user).active_only())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        assert_eq!(count, 2);
+
+        // Raising the limit for this user lets them log in again
+        let mut repo = state.repository().await.unwrap();
+        repo.user().set_max_sessions(user, Some(3)).await.unwrap();
+        repo.save().await.unwrap();
+
+        let state = state.reset().await;
+        password_login_device(&state).await;
+    }
+
+    /// Test that the least recently active session is ended once the user
+    /// reached the session limit, with the `evict_oldest` strategy.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_session_limit_evict_oldest(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool.clone(),
+            SiteConfig {
+                session_limit: SessionLimitConfig {
+                    max_per_user: NonZeroU32::new(2),
+                    strategy: SessionLimitStrategy::EvictOldest,
+                },
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+
+        let user = user_with_password(&state, "alice", "password", false).await;
+
+        let first = password_login_device(&state).await;
+        state.clock.advance(Duration::try_minutes(1).unwrap());
+        let second = password_login_device(&state).await;
+        state.clock.advance(Duration::try_minutes(1).unwrap());
+        let third = password_login_device(&state).await;
+
+        // The first session should have been ended to make room for the third
+        let mut repo = state.repository().await.unwrap();
+        let page = repo
+            .compat_session()
+            .list(
+                CompatSessionFilter::new().for_user(&user).active_only(),
+                Pagination::first(10),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let mut devices: Vec<String> = page
+            .edges
+            .into_iter()
+            .filter_map(|edge| edge.node.0.device.map(String::from))
+            .collect();
+        devices.sort();
+        let mut expected = vec![second, third];
+        expected.sort();
+        assert_eq!(devices, expected);
+        assert!(!devices.contains(&first));
+
+        // The devices should be synced with the homeserver
+        let count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM queue_jobs WHERE queue_name = 'sync-devices'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    /// Test that users get a notification with a link to sign out the new
+    /// session when they log in, if enabled.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_new_login_notification(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool.clone(),
+            SiteConfig {
+                new_login_notification: true,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+
+        user_with_password(&state, "alice", "password", false).await;
+        password_login_device(&state).await;
+
+        let payloads: Vec<serde_json::Value> = sqlx::query_scalar(
+            "SELECT payload FROM queue_jobs WHERE queue_name = 'send-new-login-email'",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(payloads.len(), 1);
+
+        // The link leads to the page to sign the new session out
+        let link: url::Url = payloads[0]["revocation_link"]
+            .as_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let path = format!("{}?{}", link.path(), link.query().unwrap());
+        let response = state.request(Request::get(&*path).empty()).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("name=\"csrf\""));
+    }
+
     /// Test the response of an unsupported password identifier.
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_unsupported_login_identifier(pool: PgPool) {
@@ -1226,6 +1830,72 @@ mod tests {
         "###);
     }
 
+    /// Test that error messages are translated according to the
+    /// `Accept-Language` header.
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_translated_errors(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        user_with_password(&state, "alice", "password", false).await;
+
+        let body = serde_json::json!({
+            "type": "m.login.password",
+            "identifier": {
+                "type": "m.id.user",
+                "user": "alice",
+            },
+            "password": "wrongpassword",
+        });
+
+        // Try to login with a wrong password, asking for French
+        let request = Request::post("/_matrix/client/v3/login")
+            .header("Accept-Language", "fr-FR, fr;q=0.9, en;q=0.8")
+            .json(&body);
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let response: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(response, @r###"
+        {
+          "errcode": "M_FORBIDDEN",
+          "error": "Nom d'utilisateur ou mot de passe invalide"
+        }
+        "###);
+
+        // Errors raised while parsing the request body are also translated
+        let request = Request::post("/_matrix/client/v3/login")
+            .header("Accept-Language", "fr")
+            .header("Content-Type", "application/json")
+            .body("{".to_owned())
+            .unwrap();
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let response: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(response, @r###"
+        {
+          "errcode": "M_NOT_JSON",
+          "error": "Le corps de la requête n'est pas un document JSON valide"
+        }
+        "###);
+
+        // Languages without a translation for the message fall back to English
+        let request = Request::post("/_matrix/client/v3/login")
+            .header("Accept-Language", "de")
+            .json(&body);
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let response: serde_json::Value = response.json();
+        insta::assert_json_snapshot!(response, @r###"
+        {
+          "errcode": "M_FORBIDDEN",
+          "error": "Invalid username/password"
+        }
+        "###);
+    }
+
     /// Test `m.login.token` login flow.
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_login_token_login(pool: PgPool) {
@@ -1300,6 +1970,20 @@ mod tests {
         }
         "#);
 
+        // The session records how the user authenticated
+        let mut repo = state.repository().await.unwrap();
+        let sessions = repo
+            .compat_session()
+            .list(
+                CompatSessionFilter::new().for_user(&user),
+                Pagination::first(1),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        let (session, _) = &sessions.edges[0].node;
+        assert_eq!(session.authenticated_by, AuthenticatedBy::Token);
+
         // Try again with the same token, it should fail.
         let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
             "type": "m.login.token",
@@ -1373,6 +2057,81 @@ mod tests {
         "###);
     }
 
+    /// Test that exchanging a login token a second time, long after the first
+    /// exchange, ends the session it created
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_login_token_replay(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Provision a user
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        state
+            .homeserver_connection
+            .provision_user(&ProvisionRequest::new(&user.username, &user.sub))
+            .await
+            .unwrap();
+
+        let token = get_login_token(&state, &user).await;
+        let request = Request::post("/_matrix/client/v3/login").json(serde_json::json!({
+            "type": "m.login.token",
+            "token": token,
+        }));
+        let response = state.request(request.clone()).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        let access_token = body["access_token"].as_str().unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        let access_token = repo
+            .compat_access_token()
+            .find_by_token(access_token)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.cancel().await.unwrap();
+
+        // Exchanging it again right away fails, but keeps the session alive
+        let response = state.request(request.clone()).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+
+        let mut repo = state.repository().await.unwrap();
+        let session = repo
+            .compat_session()
+            .lookup(access_token.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(session.is_valid());
+        repo.cancel().await.unwrap();
+
+        // Exchanging it again later fails, and ends the session
+        state
+            .clock
+            .advance(Duration::microseconds(60 * 1000 * 1000));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["errcode"], "M_FORBIDDEN");
+        assert_eq!(body["error"], "Invalid login token");
+
+        let mut repo = state.repository().await.unwrap();
+        let session = repo
+            .compat_session()
+            .lookup(access_token.session_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(session.is_finished());
+    }
+
     /// Get a login token for a user.
     /// Returns the device and the token.
     ///
@@ -21,12 +21,12 @@ use mas_axum_utils::{
 use mas_data_model::{BoxClock, BoxRng, Clock};
 use mas_router::{CompatLoginSsoAction, UrlBuilder};
 use mas_storage::{BoxRepository, RepositoryAccess, compat::CompatSsoLoginRepository};
-use mas_templates::{CompatSsoContext, ErrorContext, TemplateContext, Templates};
+use mas_templates::{CompatSsoContext, ErrorCode, ErrorContext, TemplateContext, Templates};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
 use crate::{
-    PreferredLanguage,
+    PreferredLanguage, SiteConfig,
     session::{SessionOrFallback, load_session_or_fallback},
 };
 
@@ -56,12 +56,19 @@ pub async fn get(
     mut repo: BoxRepository,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
     cookie_jar: CookieJar,
     Path(id): Path<Ulid>,
     Query(params): Query<Params>,
 ) -> Result<Response, InternalError> {
     let (cookie_jar, maybe_session) = match load_session_or_fallback(
-        cookie_jar, &clock, &mut rng, &templates, &locale, &mut repo,
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
     )
     .await?
     {
@@ -99,7 +106,7 @@ pub async fn get(
     // Bail out if that login session is more than 30min old
     if clock.now() > login.created_at + Duration::microseconds(30 * 60 * 1000 * 1000) {
         let ctx = ErrorContext::new()
-            .with_code("compat_sso_login_expired")
+            .with_code(ErrorCode::CompatSsoLoginExpired)
             .with_description("This login session expired.".to_owned())
             .with_language(&locale);
 
@@ -129,13 +136,20 @@ pub async fn post(
     PreferredLanguage(locale): PreferredLanguage,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
     cookie_jar: CookieJar,
     Path(id): Path<Ulid>,
     Query(params): Query<Params>,
     Form(form): Form<ProtectedForm<()>>,
 ) -> Result<Response, InternalError> {
     let (cookie_jar, maybe_session) = match load_session_or_fallback(
-        cookie_jar, &clock, &mut rng, &templates, &locale, &mut repo,
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
     )
     .await?
     {
@@ -175,7 +189,7 @@ pub async fn post(
         || clock.now() > login.created_at + Duration::microseconds(30 * 60 * 1000 * 1000)
     {
         let ctx = ErrorContext::new()
-            .with_code("compat_sso_login_expired")
+            .with_code(ErrorCode::CompatSsoLoginExpired)
             .with_description("This login session expired.".to_owned())
             .with_language(&locale);
 
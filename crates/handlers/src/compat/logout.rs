@@ -6,12 +6,14 @@
 
 use std::sync::LazyLock;
 
-use axum::{Json, response::IntoResponse};
+use axum::{Json, extract::State, response::IntoResponse};
 use axum_extra::typed_header::TypedHeader;
 use headers::{Authorization, authorization::Bearer};
 use hyper::StatusCode;
 use mas_axum_utils::record_error;
-use mas_data_model::{BoxClock, BoxRng, Clock, TokenType};
+use mas_data_model::{
+    AuditEventPayload, AuditSessionType, BoxClock, BoxRng, Clock, SiteConfig, TokenType,
+};
 use mas_storage::{
     BoxRepository, RepositoryAccess,
     compat::{CompatAccessTokenRepository, CompatSessionRepository},
@@ -21,7 +23,7 @@ use opentelemetry::{Key, KeyValue, metrics::Counter};
 use thiserror::Error;
 
 use super::MatrixError;
-use crate::{BoundActivityTracker, METER, impl_from_error_for_route};
+use crate::{BoundActivityTracker, METER, audit::schedule_audit_event, impl_from_error_for_route};
 
 static LOGOUT_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
     METER
@@ -57,16 +59,19 @@ impl IntoResponse for RouteError {
             Self::Internal(_) => MatrixError {
                 errcode: "M_UNKNOWN",
                 error: "Internal error",
+                key: "error.compat.internal",
                 status: StatusCode::INTERNAL_SERVER_ERROR,
             },
             Self::MissingAuthorization => MatrixError {
                 errcode: "M_MISSING_TOKEN",
                 error: "Missing access token",
+                key: "error.compat.missing_access_token",
                 status: StatusCode::UNAUTHORIZED,
             },
             Self::InvalidAuthorization | Self::TokenFormat(_) => MatrixError {
                 errcode: "M_UNKNOWN_TOKEN",
                 error: "Invalid access token",
+                key: "error.compat.invalid_access_token",
                 status: StatusCode::UNAUTHORIZED,
             },
         };
@@ -80,6 +85,7 @@ pub(crate) async fn post(
     clock: BoxClock,
     mut rng: BoxRng,
     mut repo: BoxRepository,
+    State(site_config): State<SiteConfig>,
     activity_tracker: BoundActivityTracker,
     maybe_authorization: Option<TypedHeader<Authorization<Bearer>>>,
 ) -> Result<impl IntoResponse, RouteError> {
@@ -122,7 +128,17 @@ pub(crate) async fn post(
         .schedule_job(&mut rng, &clock, SyncDevicesJob::new(&user))
         .await?;
 
-    repo.compat_session().finish(&clock, session).await?;
+    let session = repo.compat_session().finish(&clock, session).await?;
+
+    schedule_audit_event(
+        &mut repo,
+        &mut rng,
+        &clock,
+        &site_config,
+        activity_tracker.ip(),
+        AuditEventPayload::session_end(user.id, session.id, AuditSessionType::Compat),
+    )
+    .await?;
 
     repo.save().await?;
 
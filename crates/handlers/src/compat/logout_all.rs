@@ -91,11 +91,13 @@ impl IntoResponse for RouteError {
             Self::Internal(_) | Self::CantLoadSession(_) | Self::CantLoadUser(_) => MatrixError {
                 errcode: "M_UNKNOWN",
                 error: "Internal error",
+                key: "error.compat.internal",
                 status: StatusCode::INTERNAL_SERVER_ERROR,
             },
             Self::MissingAuthorization => MatrixError {
                 errcode: "M_MISSING_TOKEN",
                 error: "Missing access token",
+                key: "error.compat.missing_access_token",
                 status: StatusCode::UNAUTHORIZED,
             },
             Self::InvalidUser(_)
@@ -105,11 +107,13 @@ impl IntoResponse for RouteError {
             | Self::TokenFormat(_) => MatrixError {
                 errcode: "M_UNKNOWN_TOKEN",
                 error: "Invalid access token",
+                key: "error.compat.invalid_access_token",
                 status: StatusCode::UNAUTHORIZED,
             },
             Self::NotSupported => MatrixError {
                 errcode: "M_UNRECOGNIZED",
                 error: "The /logout/all endpoint is not supported by this deployment",
+                key: "error.compat.logout_all_unsupported",
                 status: StatusCode::NOT_FOUND,
             },
         };
@@ -5,19 +5,36 @@
 // Please see LICENSE files in the repository root for full details.
 
 use axum::{
-    Json,
+    Extension, Json,
     body::Bytes,
     extract::{
         Request,
         rejection::{BytesRejection, FailedToBufferBody},
     },
-    response::IntoResponse,
+    middleware::Next,
+    response::{IntoResponse, Response},
 };
+use axum_extra::typed_header::TypedHeader;
+use headers::{Authorization, authorization::Bearer};
 use hyper::{StatusCode, header};
 use mas_axum_utils::record_error;
+use mas_data_model::{Clock, CompatSession, TokenType, User};
+use mas_i18n::{ArgumentList, DataLocale, Translator};
+use mas_storage::{
+    BoxRepository, RepositoryAccess,
+    compat::{CompatAccessTokenRepository, CompatSessionRepository},
+    user::UserRepository,
+};
+use mas_templates::Templates;
 use serde::{Serialize, de::DeserializeOwned};
 use thiserror::Error;
+use ulid::Ulid;
+
+use crate::{BoundActivityTracker, PreferredLanguage, impl_from_error_for_route};
 
+pub(crate) mod account_3pid;
+pub(crate) mod account_password;
+pub(crate) mod capabilities;
 pub(crate) mod login;
 pub(crate) mod login_sso_complete;
 pub(crate) mod login_sso_redirect;
@@ -25,20 +42,76 @@ pub(crate) mod logout;
 pub(crate) mod logout_all;
 pub(crate) mod refresh;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize)]
 struct MatrixError {
     errcode: &'static str,
     error: &'static str,
+    /// Key of the translated version of the `error` message
+    #[serde(skip)]
+    key: &'static str,
     #[serde(skip)]
     status: StatusCode,
 }
 
+impl MatrixError {
+    /// Get the error message in the given locale, falling back to English if
+    /// it is not translated
+    fn translated_message(&self, translator: &Translator, locale: DataLocale) -> String {
+        translator
+            .message_with_fallback(locale, self.key)
+            .and_then(|(message, _locale)| message.format(&ArgumentList::default()).ok())
+            .unwrap_or_else(|| self.error.to_owned())
+    }
+}
+
 impl IntoResponse for MatrixError {
     fn into_response(self) -> axum::response::Response {
-        (self.status, Json(self)).into_response()
+        // The error is also attached as an extension, so that the
+        // [`translate_errors`] middleware can translate the message
+        (self.status, Extension(self), Json(self)).into_response()
     }
 }
 
+/// A middleware which translates the `error` message of Matrix error
+/// responses in the language negotiated from the request `Accept-Language`
+/// header. The `errcode` is left untouched.
+pub(crate) async fn translate_errors(
+    templates: Templates,
+    request: Request,
+    next: Next,
+) -> Response {
+    let translator = templates.translator();
+    let PreferredLanguage(locale) = PreferredLanguage::from_headers(&translator, request.headers());
+
+    let response = next.run(request).await;
+    let Some(error) = response.extensions().get::<MatrixError>().copied() else {
+        return response;
+    };
+
+    let message = error.translated_message(&translator, locale);
+    if message == error.error {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        // This should not happen, as error bodies are in-memory JSON documents
+        return (parts, Json(error)).into_response();
+    };
+
+    // Replace the message in place, as some responses have extra fields
+    let Ok(mut value) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        return (parts, bytes).into_response();
+    };
+
+    if let Some(object) = value.as_object_mut() {
+        object.insert("error".to_owned(), message.into());
+    }
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    (parts, Json(value)).into_response()
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 #[must_use]
 pub struct MatrixJsonBody<T>(pub T);
@@ -65,6 +138,7 @@ impl IntoResponse for MatrixJsonBodyRejection {
             Self::InvalidContentType | Self::ContentTypeNotJson(_) => MatrixError {
                 errcode: "M_NOT_JSON",
                 error: "Invalid Content-Type header: expected application/json",
+                key: "error.compat.invalid_content_type",
                 status: StatusCode::BAD_REQUEST,
             },
 
@@ -73,6 +147,7 @@ impl IntoResponse for MatrixJsonBodyRejection {
             )) => MatrixError {
                 errcode: "M_TOO_LARGE",
                 error: "Request body too large",
+                key: "error.compat.body_too_large",
                 status: StatusCode::PAYLOAD_TOO_LARGE,
             },
 
@@ -81,24 +156,28 @@ impl IntoResponse for MatrixJsonBodyRejection {
             )) => MatrixError {
                 errcode: "M_UNKNOWN",
                 error: "Failed to read request body",
+                key: "error.compat.body_read_failed",
                 status: StatusCode::BAD_REQUEST,
             },
 
             Self::BytesRejection(_) => MatrixError {
                 errcode: "M_UNKNOWN",
                 error: "Unknown error while reading request body",
+                key: "error.compat.body_read_unknown",
                 status: StatusCode::BAD_REQUEST,
             },
 
             Self::Json(err) if err.is_data() => MatrixError {
                 errcode: "M_BAD_JSON",
                 error: "JSON fields are not valid",
+                key: "error.compat.bad_json",
                 status: StatusCode::BAD_REQUEST,
             },
 
             Self::Json(_) => MatrixError {
                 errcode: "M_NOT_JSON",
                 error: "Body is not a valid JSON document",
+                key: "error.compat.not_json",
                 status: StatusCode::BAD_REQUEST,
             },
         };
@@ -167,3 +246,128 @@ where
         Ok(Some(Self(value)))
     }
 }
+
+/// An error which happened while authenticating a request with a compatibility
+/// access token
+#[derive(Debug, Error)]
+pub enum CompatAuthError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("Can't load session {0}")]
+    CantLoadSession(Ulid),
+
+    #[error("Can't load user {0}")]
+    CantLoadUser(Ulid),
+
+    #[error("Token {0} has expired")]
+    InvalidToken(Ulid),
+
+    #[error("Session {0} has been revoked")]
+    InvalidSession(Ulid),
+
+    #[error("User {0} is locked or deactivated")]
+    InvalidUser(Ulid),
+
+    #[error("Missing access token")]
+    MissingAuthorization,
+
+    #[error("Invalid token format")]
+    TokenFormat(#[from] mas_data_model::TokenFormatError),
+
+    #[error("Access token is not a compatibility access token")]
+    NotACompatToken,
+}
+
+impl_from_error_for_route!(CompatAuthError: mas_storage::RepositoryError);
+
+impl IntoResponse for CompatAuthError {
+    fn into_response(self) -> axum::response::Response {
+        let sentry_event_id = record_error!(
+            self,
+            Self::Internal(_) | Self::CantLoadSession(_) | Self::CantLoadUser(_)
+        );
+
+        let response = match self {
+            Self::Internal(_) | Self::CantLoadSession(_) | Self::CantLoadUser(_) => MatrixError {
+                errcode: "M_UNKNOWN",
+                error: "Internal error",
+                key: "error.compat.internal",
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Self::MissingAuthorization => MatrixError {
+                errcode: "M_MISSING_TOKEN",
+                error: "Missing access token",
+                key: "error.compat.missing_access_token",
+                status: StatusCode::UNAUTHORIZED,
+            },
+            Self::InvalidUser(_)
+            | Self::InvalidSession(_)
+            | Self::InvalidToken(_)
+            | Self::NotACompatToken
+            | Self::TokenFormat(_) => MatrixError {
+                errcode: "M_UNKNOWN_TOKEN",
+                error: "Invalid access token",
+                key: "error.compat.invalid_access_token",
+                status: StatusCode::UNAUTHORIZED,
+            },
+        };
+
+        (sentry_event_id, response).into_response()
+    }
+}
+
+/// Load the session and the user behind the compatibility access token used
+/// for the request, and record the activity on the session
+async fn authenticate(
+    clock: &dyn Clock,
+    repo: &mut BoxRepository,
+    activity_tracker: &BoundActivityTracker,
+    maybe_authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<(CompatSession, User), CompatAuthError> {
+    let TypedHeader(authorization) =
+        maybe_authorization.ok_or(CompatAuthError::MissingAuthorization)?;
+
+    let token = authorization.token();
+    let token_type = TokenType::check(token)?;
+
+    if token_type != TokenType::CompatAccessToken {
+        return Err(CompatAuthError::NotACompatToken);
+    }
+
+    let token = repo
+        .compat_access_token()
+        .find_by_token(token)
+        .await?
+        .ok_or(CompatAuthError::NotACompatToken)?;
+
+    if !token.is_valid(clock.now()) {
+        return Err(CompatAuthError::InvalidToken(token.id));
+    }
+
+    let session = repo
+        .compat_session()
+        .lookup(token.session_id)
+        .await?
+        .ok_or(CompatAuthError::CantLoadSession(token.session_id))?;
+
+    if !session.is_valid() {
+        return Err(CompatAuthError::InvalidSession(session.id));
+    }
+
+    activity_tracker
+        .record_compat_session(clock, &session)
+        .await;
+
+    let user = repo
+        .user()
+        .lookup(session.user_id)
+        .await?
+        .ok_or(CompatAuthError::CantLoadUser(session.user_id))?;
+
+    if !user.is_valid() {
+        return Err(CompatAuthError::InvalidUser(session.user_id));
+    }
+
+    Ok((session, user))
+}
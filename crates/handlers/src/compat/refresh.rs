@@ -57,6 +57,7 @@ impl IntoResponse for RouteError {
             Self::Internal(_) | Self::UnknownSession(_) => MatrixError {
                 errcode: "M_UNKNOWN",
                 error: "Internal error",
+                key: "error.compat.internal",
                 status: StatusCode::INTERNAL_SERVER_ERROR,
             },
             Self::InvalidToken(_)
@@ -66,6 +67,7 @@ impl IntoResponse for RouteError {
             | Self::RefreshTokenConsumed(_) => MatrixError {
                 errcode: "M_UNKNOWN_TOKEN",
                 error: "Invalid refresh token",
+                key: "error.compat.invalid_refresh_token",
                 status: StatusCode::UNAUTHORIZED,
             },
         };
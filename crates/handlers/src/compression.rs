@@ -0,0 +1,157 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Compression of the HTTP responses
+
+use axum::body::HttpBody;
+use hyper::{Response, header::CONTENT_TYPE};
+use tower_http::compression::{
+    CompressionLayer, Predicate,
+    predicate::{And, SizeAbove},
+};
+
+/// The content types which are worth compressing.
+///
+/// Anything else, like images which are already compressed or server-sent
+/// events which must be flushed as soon as they are written, is sent as-is.
+const COMPRESSIBLE_CONTENT_TYPES: &[&str] = &[
+    "application/graphql-response+json",
+    "application/javascript",
+    "application/json",
+    "application/problem+json",
+    "image/svg+xml",
+    "text/css",
+    "text/html",
+    "text/javascript",
+    "text/plain",
+];
+
+/// A [`Predicate`] which only allows compressing responses with one of the
+/// [`COMPRESSIBLE_CONTENT_TYPES`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompressibleContentType;
+
+impl Predicate for CompressibleContentType {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        let Some(content_type) = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return false;
+        };
+
+        // Ignore the parameters, like the charset
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+
+        COMPRESSIBLE_CONTENT_TYPES
+            .iter()
+            .any(|compressible| essence.eq_ignore_ascii_case(compressible))
+    }
+}
+
+/// Build a layer which compresses the responses with brotli or gzip, depending
+/// on what the client accepts.
+///
+/// Only responses with a compressible content type and of at least `min_size`
+/// bytes are compressed. Responses which already have a `Content-Encoding`,
+/// like the precompressed static assets, are left untouched.
+#[must_use]
+pub fn compression_layer(
+    min_size: u16,
+) -> CompressionLayer<And<SizeAbove, CompressibleContentType>> {
+    CompressionLayer::new()
+        .br(true)
+        .gzip(true)
+        .no_deflate()
+        .no_zstd()
+        .compress_when(SizeAbove::new(min_size).and(CompressibleContentType))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, body::Body, routing::get};
+    use hyper::{
+        Request, StatusCode,
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE},
+    };
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    use super::compression_layer;
+    use crate::test_utils::{TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_compress_spec(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let app = crate::admin_api_router()
+            .1
+            .layer(compression_layer(1024))
+            .with_state(state);
+
+        let request = Request::get("/api/spec.json")
+            .header(ACCEPT_ENCODING, "gzip, deflate, br")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_ENCODING], "br");
+
+        let request = Request::get("/api/spec.json")
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+
+        // Clients which don't ask for compression get the plain document
+        let request = Request::get("/api/spec.json").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    }
+
+    #[tokio::test]
+    async fn test_skip_small_or_incompressible() {
+        let app = Router::new()
+            .route(
+                "/small",
+                get(async || Json(serde_json::json!({ "hello": "world" }))),
+            )
+            .route(
+                "/stream",
+                get(async || {
+                    (
+                        [(CONTENT_TYPE, "text/event-stream")],
+                        "data: x\n\n".repeat(1024),
+                    )
+                }),
+            )
+            .layer(compression_layer(1024));
+
+        // Small bodies are not worth compressing
+        let request = Request::get("/small")
+            .header(ACCEPT_ENCODING, "gzip, br")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+
+        // Content types outside of the allowlist are never compressed
+        let request = Request::get("/stream")
+            .header(ACCEPT_ENCODING, "gzip, br")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    }
+}
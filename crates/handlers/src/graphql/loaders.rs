@@ -0,0 +1,208 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Per-request loaders, used to avoid querying the database once per item when
+//! resolving a list of objects
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use async_graphql::Context;
+use async_trait::async_trait;
+use mas_data_model::{Client, UpstreamOAuthProvider, User};
+use mas_storage::{
+    BoxRepository, RepositoryAccess, RepositoryError, oauth2::OAuth2ClientRepository,
+    upstream_oauth2::UpstreamOAuthProviderRepository, user::UserRepository,
+};
+use ulid::Ulid;
+
+use super::state::BoxState;
+
+/// Fields of an OAuth 2.0 session which need its client to be loaded
+const CLIENT_FIELDS: &[&str] = &["client", "clientName", "clientLogoUri", "clientTosUri"];
+
+/// Fields of a session which need its user to be loaded
+const USER_FIELDS: &[&str] = &["user"];
+
+/// Fields of an upstream OAuth 2.0 link which need its provider to be loaded
+const PROVIDER_FIELDS: &[&str] = &["provider"];
+
+/// An object which can be loaded by a [`Loader`]
+#[async_trait]
+pub trait Loadable: Clone + Send + Sync + 'static {
+    /// Load a single object by its ID
+    async fn lookup(repo: &mut BoxRepository, id: Ulid) -> Result<Option<Self>, RepositoryError>;
+
+    /// Load a batch of objects by their IDs, in a single query
+    async fn load_batch(
+        repo: &mut BoxRepository,
+        ids: BTreeSet<Ulid>,
+    ) -> Result<BTreeMap<Ulid, Self>, RepositoryError>;
+}
+
+#[async_trait]
+impl Loadable for Client {
+    async fn lookup(repo: &mut BoxRepository, id: Ulid) -> Result<Option<Self>, RepositoryError> {
+        repo.oauth2_client().lookup(id).await
+    }
+
+    async fn load_batch(
+        repo: &mut BoxRepository,
+        ids: BTreeSet<Ulid>,
+    ) -> Result<BTreeMap<Ulid, Self>, RepositoryError> {
+        repo.oauth2_client().load_batch(ids).await
+    }
+}
+
+#[async_trait]
+impl Loadable for User {
+    async fn lookup(repo: &mut BoxRepository, id: Ulid) -> Result<Option<Self>, RepositoryError> {
+        repo.user().lookup(id).await
+    }
+
+    async fn load_batch(
+        repo: &mut BoxRepository,
+        ids: BTreeSet<Ulid>,
+    ) -> Result<BTreeMap<Ulid, Self>, RepositoryError> {
+        repo.user().load_batch(ids).await
+    }
+}
+
+#[async_trait]
+impl Loadable for UpstreamOAuthProvider {
+    async fn lookup(repo: &mut BoxRepository, id: Ulid) -> Result<Option<Self>, RepositoryError> {
+        repo.upstream_oauth_provider().lookup(id).await
+    }
+
+    async fn load_batch(
+        repo: &mut BoxRepository,
+        ids: BTreeSet<Ulid>,
+    ) -> Result<BTreeMap<Ulid, Self>, RepositoryError> {
+        repo.upstream_oauth_provider().load_batch(ids).await
+    }
+}
+
+/// Loads objects for the duration of a GraphQL request
+///
+/// Connections listing objects prime the loader with the related objects of
+/// the whole page in one query, so that resolving the related object of each
+/// item doesn't need its own query.
+pub struct Loader<T> {
+    objects: Arc<Mutex<HashMap<Ulid, T>>>,
+    queries: Arc<AtomicUsize>,
+}
+
+/// Loads OAuth 2.0 clients for the duration of a GraphQL request
+pub type OAuth2ClientLoader = Loader<Client>;
+
+/// Loads users for the duration of a GraphQL request
+pub type UserLoader = Loader<User>;
+
+/// Loads upstream OAuth 2.0 providers for the duration of a GraphQL request
+pub type UpstreamOAuthProviderLoader = Loader<UpstreamOAuthProvider>;
+
+impl<T> Default for Loader<T> {
+    fn default() -> Self {
+        Self {
+            objects: Arc::default(),
+            queries: Arc::default(),
+        }
+    }
+}
+
+impl<T> Clone for Loader<T> {
+    fn clone(&self) -> Self {
+        Self {
+            objects: self.objects.clone(),
+            queries: self.queries.clone(),
+        }
+    }
+}
+
+impl<T: Loadable> Loader<T> {
+    /// Load the objects with the given IDs which are not cached yet, in a
+    /// single query
+    pub async fn prime(
+        &self,
+        repo: &mut BoxRepository,
+        ids: impl IntoIterator<Item = Ulid>,
+    ) -> Result<(), RepositoryError> {
+        let missing: BTreeSet<Ulid> = {
+            let objects = self.objects.lock().unwrap();
+            ids.into_iter()
+                .filter(|id| !objects.contains_key(id))
+                .collect()
+        };
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        let loaded = T::load_batch(repo, missing).await?;
+        self.objects.lock().unwrap().extend(loaded);
+
+        Ok(())
+    }
+
+    /// Get an object, looking it up if it wasn't loaded before
+    pub async fn load(&self, state: &BoxState, id: Ulid) -> Result<Option<T>, RepositoryError> {
+        if let Some(object) = self.objects.lock().unwrap().get(&id) {
+            return Ok(Some(object.clone()));
+        }
+
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        let mut repo = state.repository().await?;
+        let object = T::lookup(&mut repo, id).await?;
+        repo.cancel().await?;
+
+        if let Some(object) = &object {
+            self.objects.lock().unwrap().insert(id, object.clone());
+        }
+
+        Ok(object)
+    }
+
+    /// How many queries the loader made so far
+    #[cfg(test)]
+    pub fn queries(&self) -> usize {
+        self.queries.load(Ordering::Relaxed)
+    }
+}
+
+/// Returns true if the selection of a connection asks for any of the given
+/// fields on its nodes
+fn wants_fields(ctx: &Context<'_>, fields: &[&str]) -> bool {
+    let look_ahead = ctx.look_ahead();
+    let edge_node = look_ahead.field("edges").field("node");
+    let node = look_ahead.field("nodes");
+
+    fields
+        .iter()
+        .any(|field| edge_node.field(field).exists() || node.field(field).exists())
+}
+
+/// Returns true if the selection of a connection of OAuth 2.0 sessions (or of
+/// application sessions) asks for anything about the clients of the sessions
+pub fn wants_oauth2_clients(ctx: &Context<'_>) -> bool {
+    wants_fields(ctx, CLIENT_FIELDS)
+}
+
+/// Returns true if the selection of a connection of sessions asks for the
+/// users of the sessions
+pub fn wants_users(ctx: &Context<'_>) -> bool {
+    wants_fields(ctx, USER_FIELDS)
+}
+
+/// Returns true if the selection of a connection of upstream OAuth 2.0 links
+/// asks for the providers of the links
+pub fn wants_upstream_oauth_providers(ctx: &Context<'_>) -> bool {
+    wants_fields(ctx, PROVIDER_FIELDS)
+}
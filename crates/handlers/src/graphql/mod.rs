@@ -29,12 +29,14 @@ use mas_axum_utils::{
     InternalError, SessionInfo, SessionInfoExt, cookies::CookieJar, sentry::SentryEventID,
 };
 use mas_data_model::{
-    BoxClock, BoxRng, BrowserSession, Clock, Session, SiteConfig, SystemClock, User,
+    BoxClock, BoxRng, BrowserSession, Clock, Session, SiteConfig, SystemClock, TchapConfig, User,
 };
+use mas_i18n::Translator;
 use mas_matrix::HomeserverConnection;
 use mas_policy::{InstantiateError, Policy, PolicyFactory};
 use mas_router::UrlBuilder;
 use mas_storage::{BoxRepository, BoxRepositoryFactory, RepositoryError};
+use mas_templates::Templates;
 use opentelemetry_semantic_conventions::trace::{GRAPHQL_DOCUMENT, GRAPHQL_OPERATION_NAME};
 use rand::{SeedableRng, thread_rng};
 use rand_chacha::ChaChaRng;
@@ -42,6 +44,7 @@ use state::has_session_ended;
 use tracing::{Instrument, info_span};
 use ulid::Ulid;
 
+mod loaders;
 mod model;
 mod mutations;
 mod query;
@@ -49,6 +52,7 @@ mod state;
 
 pub use self::state::{BoxState, State};
 use self::{
+    loaders::{OAuth2ClientLoader, UpstreamOAuthProviderLoader, UserLoader},
     model::{CreationEvent, Node},
     mutations::Mutation,
     query::Query,
@@ -76,6 +80,10 @@ struct GraphQLState {
     password_manager: PasswordManager,
     url_builder: UrlBuilder,
     limiter: Limiter,
+    templates: Templates,
+    //:tchap:
+    tchap_config: TchapConfig,
+    //:tchap:end
 }
 
 #[async_trait::async_trait]
@@ -108,6 +116,16 @@ impl state::State for GraphQLState {
         &self.limiter
     }
 
+    fn translator(&self) -> Arc<Translator> {
+        self.templates.translator()
+    }
+
+    //:tchap:
+    fn tchap_config(&self) -> &TchapConfig {
+        &self.tchap_config
+    }
+    //:tchap:end
+
     fn clock(&self) -> BoxClock {
         let clock = SystemClock::default();
         Box::new(clock)
@@ -131,6 +149,10 @@ pub fn schema(
     password_manager: PasswordManager,
     url_builder: UrlBuilder,
     limiter: Limiter,
+    templates: Templates,
+    //:tchap:
+    tchap_config: TchapConfig,
+    //:tchap:end
 ) -> Schema {
     let state = GraphQLState {
         repository_factory,
@@ -140,6 +162,10 @@ pub fn schema(
         password_manager,
         url_builder,
         limiter,
+        templates,
+        //:tchap:
+        tchap_config,
+        //:tchap:end
     };
     let state: BoxState = Box::new(state);
 
@@ -229,9 +255,11 @@ impl IntoResponse for RouteError {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn get_requester(
     undocumented_oauth2_access: bool,
     clock: &impl Clock,
+    site_config: &SiteConfig,
     activity_tracker: &BoundActivityTracker,
     mut repo: BoxRepository,
     session_info: &SessionInfo,
@@ -285,7 +313,16 @@ async fn get_requester(
 
         RequestingEntity::OAuth2Session(Box::new((session, user)))
     } else {
-        let maybe_session = session_info.load_active_session(&mut repo).await?;
+        let mut maybe_session = session_info.load_active_session(&mut repo).await?;
+
+        // Sessions which have to be re-authenticated can't be used until the
+        // user went through the re-authentication prompt
+        if let Some(session) = maybe_session.as_ref()
+            && crate::session::needs_reauthentication(&mut repo, clock, site_config, session)
+                .await?
+        {
+            maybe_session = None;
+        }
 
         if let Some(session) = maybe_session.as_ref() {
             activity_tracker
@@ -308,6 +345,7 @@ async fn get_requester(
 
 pub async fn post(
     AxumState(schema): AxumState<Schema>,
+    AxumState(site_config): AxumState<SiteConfig>,
     Extension(ExtraRouterParameters {
         undocumented_oauth2_access,
     }): Extension<ExtraRouterParameters>,
@@ -329,6 +367,7 @@ pub async fn post(
     let requester = get_requester(
         undocumented_oauth2_access,
         &clock,
+        &site_config,
         &activity_tracker,
         repo,
         &session_info,
@@ -345,7 +384,10 @@ pub async fn post(
         MultipartOptions::default(),
     )
     .await?
-    .data(requester); // XXX: this should probably return another error response?
+    .data(requester) // XXX: this should probably return another error response?
+    .data(OAuth2ClientLoader::default())
+    .data(UserLoader::default())
+    .data(UpstreamOAuthProviderLoader::default());
 
     let span = span_for_graphql_request(&request);
     let mut response = schema.execute(request).instrument(span).await;
@@ -368,6 +410,7 @@ pub async fn post(
 
 pub async fn get(
     AxumState(schema): AxumState<Schema>,
+    AxumState(site_config): AxumState<SiteConfig>,
     Extension(ExtraRouterParameters {
         undocumented_oauth2_access,
     }): Extension<ExtraRouterParameters>,
@@ -387,6 +430,7 @@ pub async fn get(
     let requester = get_requester(
         undocumented_oauth2_access,
         &clock,
+        &site_config,
         &activity_tracker,
         repo,
         &session_info,
@@ -395,8 +439,11 @@ pub async fn get(
     )
     .await?;
 
-    let request =
-        async_graphql::http::parse_query_string(&query.unwrap_or_default())?.data(requester);
+    let request = async_graphql::http::parse_query_string(&query.unwrap_or_default())?
+        .data(requester)
+        .data(OAuth2ClientLoader::default())
+        .data(UserLoader::default())
+        .data(UpstreamOAuthProviderLoader::default());
 
     let span = span_for_graphql_request(&request);
     let mut response = schema.execute(request).instrument(span).await;
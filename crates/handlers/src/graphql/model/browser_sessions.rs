@@ -18,7 +18,7 @@ use super::{
     AppSession, CompatSession, Cursor, NodeCursor, NodeType, OAuth2Session, PreloadedTotalCount,
     SessionState, User, UserAgent,
 };
-use crate::graphql::state::ContextExt;
+use crate::graphql::{loaders::wants_users, state::ContextExt};
 
 /// A browser session represents a logged in user in a browser.
 #[derive(Description)]
@@ -162,6 +162,16 @@ impl BrowserSession {
                     None
                 };
 
+                if wants_users(ctx) {
+                    let user_ids = page.edges.iter().filter_map(|edge| match &edge.node {
+                        mas_storage::app_session::AppSession::OAuth2(session) => session.user_id,
+                        mas_storage::app_session::AppSession::Compat(session) => {
+                            Some(session.user_id)
+                        }
+                    });
+                    ctx.user_loader().prime(&mut repo, user_ids).await?;
+                }
+
                 repo.cancel().await?;
 
                 let mut connection = Connection::with_additional_fields(
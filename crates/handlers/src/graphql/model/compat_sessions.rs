@@ -8,10 +8,10 @@ use anyhow::Context as _;
 use async_graphql::{Context, Description, Enum, ID, Object};
 use chrono::{DateTime, Utc};
 use mas_data_model::Device;
-use mas_storage::{compat::CompatSessionRepository, user::UserRepository};
+use mas_storage::compat::CompatSessionRepository;
 use url::Url;
 
-use super::{BrowserSession, NodeType, SessionState, User, UserAgent};
+use super::{BrowserSession, NodeType, SessionAuthenticationMethod, SessionState, User, UserAgent};
 use crate::graphql::state::ContextExt;
 
 /// Lazy-loaded reverse reference.
@@ -69,14 +69,11 @@ impl CompatSession {
 
     /// The user authorized for this session.
     async fn user(&self, ctx: &Context<'_>) -> Result<User, async_graphql::Error> {
-        let state = ctx.state();
-        let mut repo = state.repository().await?;
-        let user = repo
-            .user()
-            .lookup(self.session.user_id)
+        let user = ctx
+            .user_loader()
+            .load(ctx.state(), self.session.user_id)
             .await?
             .context("Could not load user")?;
-        repo.cancel().await?;
 
         Ok(User(user))
     }
@@ -170,6 +167,20 @@ impl CompatSession {
     pub async fn human_name(&self) -> Option<&str> {
         self.session.human_name.as_deref()
     }
+
+    /// How the user authenticated when the session was established.
+    pub async fn authenticated_by(&self) -> SessionAuthenticationMethod {
+        self.session.authenticated_by.into()
+    }
+
+    /// The ID of the upstream OAuth 2.0 provider the user authenticated with,
+    /// if they authenticated through one.
+    pub async fn authenticated_by_upstream_provider_id(&self) -> Option<ID> {
+        self.session
+            .authenticated_by
+            .upstream_provider_id()
+            .map(|id| NodeType::UpstreamOAuth2Provider.id(id))
+    }
 }
 
 /// A compat SSO login represents a login done through the legacy Matrix login
@@ -5,6 +5,7 @@
 // Please see LICENSE files in the repository root for full details.
 
 use async_graphql::connection::OpaqueCursor;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
@@ -32,3 +33,9 @@ impl NodeCursor {
 }
 
 pub type Cursor = OpaqueCursor<NodeCursor>;
+
+/// Marks the point in time up to which session events were already returned
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionEventsCursor(pub DateTime<Utc>);
+
+pub type EventsCursor = OpaqueCursor<SessionEventsCursor>;
@@ -21,9 +21,9 @@ mod viewer;
 pub use self::{
     browser_sessions::{Authentication, BrowserSession},
     compat_sessions::{CompatSession, CompatSsoLogin},
-    cursor::{Cursor, NodeCursor},
+    cursor::{Cursor, EventsCursor, NodeCursor, SessionEventsCursor},
     node::{Node, NodeType},
-    oauth::{OAuth2Client, OAuth2Session},
+    oauth::{DeviceCodeGrant, DeviceCodeGrantState, OAuth2Client, OAuth2Consent, OAuth2Session},
     site_config::{SITE_CONFIG_ID, SiteConfig},
     upstream_oauth::{UpstreamOAuth2Link, UpstreamOAuth2Provider},
     users::{AppSession, User, UserEmail, UserEmailAuthentication, UserRecoveryTicket},
@@ -70,6 +70,38 @@ pub enum SessionState {
     Finished,
 }
 
+/// How the user authenticated when a session was established
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum SessionAuthenticationMethod {
+    /// The session was created before this was recorded, or through a path
+    /// which doesn't record it.
+    Unknown,
+
+    /// The user entered their password.
+    Password,
+
+    /// The user authenticated through an upstream OAuth 2.0 provider.
+    Upstream,
+
+    /// The session was obtained by exchanging a login token.
+    Token,
+
+    /// The session was issued by an administrator.
+    AdminIssued,
+}
+
+impl From<mas_data_model::AuthenticatedBy> for SessionAuthenticationMethod {
+    fn from(authenticated_by: mas_data_model::AuthenticatedBy) -> Self {
+        match authenticated_by {
+            mas_data_model::AuthenticatedBy::Unknown => Self::Unknown,
+            mas_data_model::AuthenticatedBy::Password => Self::Password,
+            mas_data_model::AuthenticatedBy::Upstream { .. } => Self::Upstream,
+            mas_data_model::AuthenticatedBy::Token => Self::Token,
+            mas_data_model::AuthenticatedBy::AdminIssued => Self::AdminIssued,
+        }
+    }
+}
+
 /// The type of a user agent
 #[derive(Enum, Copy, Clone, Eq, PartialEq)]
 pub enum DeviceType {
@@ -10,9 +10,9 @@ use thiserror::Error;
 use ulid::Ulid;
 
 use super::{
-    Anonymous, Authentication, BrowserSession, CompatSession, CompatSsoLogin, OAuth2Client,
-    OAuth2Session, SiteConfig, UpstreamOAuth2Link, UpstreamOAuth2Provider, User, UserEmail,
-    UserEmailAuthentication, UserRecoveryTicket,
+    Anonymous, Authentication, BrowserSession, CompatSession, CompatSsoLogin, DeviceCodeGrant,
+    OAuth2Client, OAuth2Session, SiteConfig, UpstreamOAuth2Link, UpstreamOAuth2Provider, User,
+    UserEmail, UserEmailAuthentication, UserRecoveryTicket,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -21,6 +21,7 @@ pub enum NodeType {
     BrowserSession,
     CompatSession,
     CompatSsoLogin,
+    DeviceCodeGrant,
     OAuth2Client,
     OAuth2Session,
     UpstreamOAuth2Provider,
@@ -47,6 +48,7 @@ impl NodeType {
             NodeType::BrowserSession => "browser_session",
             NodeType::CompatSession => "compat_session",
             NodeType::CompatSsoLogin => "compat_sso_login",
+            NodeType::DeviceCodeGrant => "device_code_grant",
             NodeType::OAuth2Client => "oauth2_client",
             NodeType::OAuth2Session => "oauth2_session",
             NodeType::UpstreamOAuth2Provider => "upstream_oauth2_provider",
@@ -64,6 +66,7 @@ impl NodeType {
             "browser_session" => Some(NodeType::BrowserSession),
             "compat_session" => Some(NodeType::CompatSession),
             "compat_sso_login" => Some(NodeType::CompatSsoLogin),
+            "device_code_grant" => Some(NodeType::DeviceCodeGrant),
             "oauth2_client" => Some(NodeType::OAuth2Client),
             "oauth2_session" => Some(NodeType::OAuth2Session),
             "upstream_oauth2_provider" => Some(NodeType::UpstreamOAuth2Provider),
@@ -120,6 +123,7 @@ pub enum Node {
     BrowserSession(Box<BrowserSession>),
     CompatSession(Box<CompatSession>),
     CompatSsoLogin(Box<CompatSsoLogin>),
+    DeviceCodeGrant(Box<DeviceCodeGrant>),
     OAuth2Client(Box<OAuth2Client>),
     OAuth2Session(Box<OAuth2Session>),
     SiteConfig(Box<SiteConfig>),
@@ -11,7 +11,7 @@ use mas_storage::{oauth2::OAuth2ClientRepository, user::BrowserSessionRepository
 use oauth2_types::oidc::ApplicationType;
 use url::Url;
 
-use super::{BrowserSession, NodeType, SessionState, User, UserAgent};
+use super::{BrowserSession, NodeType, SessionAuthenticationMethod, SessionState, User, UserAgent};
 use crate::graphql::{UserId, state::ContextExt};
 
 /// An OAuth 2.0 session represents a client session which used the OAuth APIs
@@ -28,23 +28,68 @@ impl OAuth2Session {
 
     /// OAuth 2.0 client used by this session.
     pub async fn client(&self, ctx: &Context<'_>) -> Result<OAuth2Client, async_graphql::Error> {
-        let state = ctx.state();
-        let mut repo = state.repository().await?;
-        let client = repo
-            .oauth2_client()
-            .lookup(self.0.client_id)
+        let client = ctx
+            .oauth2_client_loader()
+            .load(ctx.state(), self.0.client_id)
             .await?
             .context("Could not load client")?;
-        repo.cancel().await?;
 
         Ok(OAuth2Client(client))
     }
 
+    /// Name of the OAuth 2.0 client used by this session, as advertised by the
+    /// client.
+    pub async fn client_name(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<String>, async_graphql::Error> {
+        let client = self.load_client_for_owner(ctx).await?;
+        Ok(client.client_name)
+    }
+
+    /// Logo URI of the OAuth 2.0 client used by this session, as advertised by
+    /// the client.
+    pub async fn client_logo_uri(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<Url>, async_graphql::Error> {
+        let client = self.load_client_for_owner(ctx).await?;
+        Ok(client.logo_uri)
+    }
+
+    /// Terms of services URI of the OAuth 2.0 client used by this session, as
+    /// advertised by the client.
+    pub async fn client_tos_uri(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<Url>, async_graphql::Error> {
+        let client = self.load_client_for_owner(ctx).await?;
+        Ok(client.tos_uri)
+    }
+
     /// Scope granted for this session.
     pub async fn scope(&self) -> String {
         self.0.scope.to_string()
     }
 
+    /// Scope granted for this session, split into individual scope tokens
+    /// along with what they give access to.
+    pub async fn scopes(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<OAuth2SessionScope>, async_graphql::Error> {
+        if !ctx.requester().is_owner_or_admin(&self.0) {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        Ok(self
+            .0
+            .scope
+            .iter()
+            .map(|token| OAuth2SessionScope::new(token.as_str()))
+            .collect())
+    }
+
     /// When the object was created.
     pub async fn created_at(&self) -> DateTime<Utc> {
         self.0.created_at
@@ -107,13 +152,11 @@ impl OAuth2Session {
             return Err(async_graphql::Error::new("Unauthorized"));
         }
 
-        let mut repo = state.repository().await?;
-        let user = repo
-            .user()
-            .lookup(user_id)
+        let user = ctx
+            .user_loader()
+            .load(state, user_id)
             .await?
             .context("Could not load user")?;
-        repo.cancel().await?;
 
         Ok(Some(User(user)))
     }
@@ -132,6 +175,125 @@ impl OAuth2Session {
     pub async fn human_name(&self) -> Option<&str> {
         self.0.human_name.as_deref()
     }
+
+    /// How the user authenticated when the session was established.
+    pub async fn authenticated_by(&self) -> SessionAuthenticationMethod {
+        self.0.authenticated_by.into()
+    }
+
+    /// The ID of the upstream OAuth 2.0 provider the user authenticated with,
+    /// if they authenticated through one.
+    pub async fn authenticated_by_upstream_provider_id(&self) -> Option<ID> {
+        self.0
+            .authenticated_by
+            .upstream_provider_id()
+            .map(|id| NodeType::UpstreamOAuth2Provider.id(id))
+    }
+}
+
+impl OAuth2Session {
+    /// Load the client of this session, making sure the requester is allowed
+    /// to see the details of the session
+    async fn load_client_for_owner(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<mas_data_model::Client, async_graphql::Error> {
+        if !ctx.requester().is_owner_or_admin(&self.0) {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let client = ctx
+            .oauth2_client_loader()
+            .load(ctx.state(), self.0.client_id)
+            .await?
+            .context("Could not load client")?;
+
+        Ok(client)
+    }
+}
+
+/// What a scope token granted to an OAuth 2.0 session gives access to.
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum OAuth2ScopeKind {
+    /// The `openid` scope: the client can see the profile of the user.
+    Openid,
+
+    /// The `email` scope: the client can see the email address of the user.
+    Email,
+
+    /// The client can edit the profile of the user and manage their
+    /// sessions through the GraphQL API.
+    MasGraphql,
+
+    /// The client can see and send messages on behalf of the user through the
+    /// Matrix Client-Server API.
+    MatrixClientApi,
+
+    /// The Matrix device bound to the session.
+    MatrixDevice,
+
+    /// The client can administer the homeserver.
+    SynapseAdmin,
+
+    /// The client can administer the authentication service.
+    MasAdmin,
+
+    /// Any other scope token.
+    Other,
+}
+
+/// A single scope token granted to an OAuth 2.0 session.
+#[derive(Description)]
+pub struct OAuth2SessionScope {
+    scope: String,
+    kind: OAuth2ScopeKind,
+}
+
+impl OAuth2SessionScope {
+    fn new(scope: &str) -> Self {
+        let kind = match scope {
+            "openid" => OAuth2ScopeKind::Openid,
+            "email" => OAuth2ScopeKind::Email,
+            "urn:mas:graphql:*" => OAuth2ScopeKind::MasGraphql,
+            "urn:matrix:client:api:*" | "urn:matrix:org.matrix.msc2967.client:api:*" => {
+                OAuth2ScopeKind::MatrixClientApi
+            }
+            "urn:synapse:admin:*" => OAuth2ScopeKind::SynapseAdmin,
+            "urn:mas:admin" => OAuth2ScopeKind::MasAdmin,
+            _ if scope.starts_with("urn:matrix:client:device:")
+                || scope.starts_with("urn:matrix:org.matrix.msc2967.client:device:") =>
+            {
+                OAuth2ScopeKind::MatrixDevice
+            }
+            _ => OAuth2ScopeKind::Other,
+        };
+
+        Self {
+            scope: scope.to_owned(),
+            kind,
+        }
+    }
+}
+
+#[Object(use_type_description)]
+impl OAuth2SessionScope {
+    /// The scope token, as granted to the session.
+    pub async fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    /// What the scope token gives access to.
+    pub async fn kind(&self) -> OAuth2ScopeKind {
+        self.kind
+    }
+
+    /// Whether the scope token gives administrative access.
+    pub async fn dangerous(&self) -> bool {
+        matches!(
+            self.kind,
+            OAuth2ScopeKind::SynapseAdmin | OAuth2ScopeKind::MasAdmin
+        )
+    }
 }
 
 /// The application type advertised by the client.
@@ -199,3 +361,138 @@ impl OAuth2Client {
         }
     }
 }
+
+/// A remembered consent given by a user to an OAuth 2.0 client. While it
+/// exists, the user won't be asked for consent again for scopes it covers.
+#[derive(Description)]
+pub struct OAuth2Consent(pub mas_data_model::Consent);
+
+#[Object(use_type_description)]
+impl OAuth2Consent {
+    /// OAuth 2.0 client the consent was given to.
+    pub async fn client(&self, ctx: &Context<'_>) -> Result<OAuth2Client, async_graphql::Error> {
+        let state = ctx.state();
+        let mut repo = state.repository().await?;
+        let client = repo
+            .oauth2_client()
+            .lookup(self.0.client_id)
+            .await?
+            .context("Could not load client")?;
+        repo.cancel().await?;
+
+        Ok(OAuth2Client(client))
+    }
+
+    /// Scope the user consented to.
+    pub async fn scope(&self) -> String {
+        self.0.scope.to_string()
+    }
+
+    /// When the user first consented to this client.
+    pub async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    /// When the user last consented to this client.
+    pub async fn updated_at(&self) -> DateTime<Utc> {
+        self.0.updated_at
+    }
+}
+
+/// The state of an OAuth 2.0 device code grant.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum DeviceCodeGrantState {
+    /// The grant is waiting for a user to approve or reject it.
+    Pending,
+
+    /// The grant was approved by a user, and is waiting for the device to
+    /// exchange it.
+    Fulfilled,
+
+    /// The grant was rejected by a user.
+    Rejected,
+
+    /// The grant was exchanged by the device for a session.
+    Exchanged,
+}
+
+impl From<DeviceCodeGrantState> for mas_storage::oauth2::OAuth2DeviceCodeGrantState {
+    fn from(state: DeviceCodeGrantState) -> Self {
+        match state {
+            DeviceCodeGrantState::Pending => Self::Pending,
+            DeviceCodeGrantState::Fulfilled => Self::Fulfilled,
+            DeviceCodeGrantState::Rejected => Self::Rejected,
+            DeviceCodeGrantState::Exchanged => Self::Exchanged,
+        }
+    }
+}
+
+/// An OAuth 2.0 device code grant, started by a device and approved or
+/// rejected by a user from their browser.
+#[derive(Description)]
+pub struct DeviceCodeGrant(pub mas_data_model::DeviceCodeGrant);
+
+#[Object(use_type_description)]
+impl DeviceCodeGrant {
+    /// ID of the object.
+    pub async fn id(&self) -> ID {
+        NodeType::DeviceCodeGrant.id(self.0.id)
+    }
+
+    /// OAuth 2.0 client which started this grant.
+    pub async fn client(&self, ctx: &Context<'_>) -> Result<OAuth2Client, async_graphql::Error> {
+        let state = ctx.state();
+        let mut repo = state.repository().await?;
+        let client = repo
+            .oauth2_client()
+            .lookup(self.0.client_id)
+            .await?
+            .context("Could not load client")?;
+        repo.cancel().await?;
+
+        Ok(OAuth2Client(client))
+    }
+
+    /// Scope requested by the client.
+    pub async fn scope(&self) -> String {
+        self.0.scope.to_string()
+    }
+
+    /// The state of the grant.
+    pub async fn state(&self) -> DeviceCodeGrantState {
+        match &self.0.state {
+            mas_data_model::DeviceCodeGrantState::Pending => DeviceCodeGrantState::Pending,
+            mas_data_model::DeviceCodeGrantState::Fulfilled { .. } => {
+                DeviceCodeGrantState::Fulfilled
+            }
+            mas_data_model::DeviceCodeGrantState::Rejected { .. } => DeviceCodeGrantState::Rejected,
+            mas_data_model::DeviceCodeGrantState::Exchanged { .. } => {
+                DeviceCodeGrantState::Exchanged
+            }
+        }
+    }
+
+    /// When the object was created.
+    pub async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    /// When the grant expires.
+    pub async fn expires_at(&self) -> DateTime<Utc> {
+        self.0.expires_at
+    }
+
+    /// Whether the grant has expired.
+    pub async fn expired(&self, ctx: &Context<'_>) -> bool {
+        self.0.expires_at < ctx.state().clock().now()
+    }
+
+    /// The user-agent of the device which started the grant.
+    pub async fn user_agent(&self) -> Option<UserAgent> {
+        self.0
+            .user_agent
+            .clone()
+            .map(mas_data_model::UserAgent::parse)
+            .map(UserAgent::from)
+    }
+}
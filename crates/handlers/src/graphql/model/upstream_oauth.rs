@@ -7,7 +7,6 @@
 use anyhow::Context as _;
 use async_graphql::{Context, ID, Object};
 use chrono::{DateTime, Utc};
-use mas_storage::{upstream_oauth2::UpstreamOAuthProviderRepository, user::UserRepository};
 use url::Url;
 
 use super::{NodeType, User};
@@ -119,16 +118,10 @@ impl UpstreamOAuth2Link {
             provider.clone()
         } else {
             // Fetch on-the-fly
-            let mut repo = state.repository().await?;
-
-            let provider = repo
-                .upstream_oauth_provider()
-                .lookup(self.link.provider_id)
+            ctx.upstream_oauth_provider_loader()
+                .load(state, self.link.provider_id)
                 .await?
-                .context("Upstream OAuth 2.0 provider not found")?;
-            repo.cancel().await?;
-
-            provider
+                .context("Upstream OAuth 2.0 provider not found")?
         };
 
         Ok(UpstreamOAuth2Provider::new(provider))
@@ -142,16 +135,10 @@ impl UpstreamOAuth2Link {
             user.clone()
         } else if let Some(user_id) = &self.link.user_id {
             // Fetch on-the-fly
-            let mut repo = state.repository().await?;
-
-            let user = repo
-                .user()
-                .lookup(*user_id)
+            ctx.user_loader()
+                .load(state, *user_id)
                 .await?
-                .context("User not found")?;
-            repo.cancel().await?;
-
-            user
+                .context("User not found")?
         } else {
             return Ok(None);
         };
@@ -6,8 +6,8 @@
 
 use anyhow::Context as _;
 use async_graphql::{
-    Context, Description, Enum, ID, Object, Union,
-    connection::{Connection, Edge, OpaqueCursor, query},
+    Context, Description, Enum, ID, Object, SimpleObject, Union,
+    connection::{Connection, CursorType, Edge, OpaqueCursor, query},
 };
 use chrono::{DateTime, Utc};
 use mas_data_model::Device;
@@ -15,18 +15,35 @@ use mas_storage::{
     Pagination, RepositoryAccess,
     app_session::AppSessionFilter,
     compat::{CompatSessionFilter, CompatSsoLoginFilter, CompatSsoLoginRepository},
-    oauth2::{OAuth2SessionFilter, OAuth2SessionRepository},
+    oauth2::{
+        OAuth2ClientRepository, OAuth2DeviceCodeGrantFilter, OAuth2SessionFilter,
+        OAuth2SessionRepository,
+    },
     upstream_oauth2::{UpstreamOAuthLinkFilter, UpstreamOAuthLinkRepository},
     user::{BrowserSessionFilter, BrowserSessionRepository, UserEmailFilter, UserEmailRepository},
 };
 
+use ulid::Ulid;
+
 use super::{
-    BrowserSession, CompatSession, Cursor, NodeCursor, NodeType, OAuth2Session,
-    PreloadedTotalCount, SessionState, UpstreamOAuth2Link,
+    BrowserSession, CompatSession, Cursor, DeviceCodeGrant, DeviceCodeGrantState, EventsCursor,
+    NodeCursor, NodeType, OAuth2Consent, OAuth2Session, PreloadedTotalCount, SessionEventsCursor,
+    SessionState, UpstreamOAuth2Link,
     compat_sessions::{CompatSessionType, CompatSsoLogin},
     matrix::MatrixUser,
 };
-use crate::graphql::{DateFilter, state::ContextExt};
+use crate::graphql::{
+    DateFilter,
+    loaders::{wants_oauth2_clients, wants_upstream_oauth_providers, wants_users},
+    state::ContextExt,
+};
+
+/// How many failed login attempts to expose on a [`User`]
+const RECENT_LOGIN_FAILURES: usize = 10;
+
+/// How many session creations and endings to return at most in one batch of
+/// [`AppSessionEvents`]
+const SESSION_EVENTS_LIMIT: usize = 100;
 
 #[derive(Description)]
 /// A user is an individual's account.
@@ -71,6 +88,54 @@ impl User {
         self.0.can_request_admin
     }
 
+    /// The language explicitly chosen by the user for the pages and emails
+    /// sent to them, if any.
+    async fn preferred_language(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<&str>, async_graphql::Error> {
+        if !ctx.requester().is_owner_or_admin(&self.0) {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        Ok(self.0.locale.as_deref())
+    }
+
+    //:tchap:
+    /// The organization the user belongs to, as imported from the upstream
+    /// provider. Only visible to the user themselves and to admins.
+    async fn organization(&self, ctx: &Context<'_>) -> Result<Option<&str>, async_graphql::Error> {
+        if !ctx.requester().is_owner_or_admin(&self.0) {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        Ok(self.0.organization.as_deref())
+    }
+
+    /// The label derived from the domain of the user's email addresses, as
+    /// configured by the server. Only visible to the user themselves and to
+    /// admins.
+    async fn email_domain_label(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Option<String>, async_graphql::Error> {
+        if !ctx.requester().is_owner_or_admin(&self.0) {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let state = ctx.state();
+        let mut repo = state.repository().await?;
+        let emails = repo.user_email().all(&self.0).await?;
+        repo.cancel().await?;
+
+        let tchap_config = state.tchap_config();
+        Ok(emails
+            .iter()
+            .find_map(|email| tchap_config.email_domain_label(&email.email))
+            .map(ToOwned::to_owned))
+    }
+    //:tchap:end
+
     /// Access to the user's Matrix account information.
     async fn matrix(&self, ctx: &Context<'_>) -> Result<MatrixUser, async_graphql::Error> {
         let state = ctx.state();
@@ -212,6 +277,12 @@ impl User {
                     None
                 };
 
+                if wants_users(ctx) {
+                    ctx.user_loader()
+                        .prime(&mut repo, page.edges.iter().map(|edge| edge.node.0.user_id))
+                        .await?;
+                }
+
                 repo.cancel().await?;
 
                 let mut connection = Connection::with_additional_fields(
@@ -471,6 +542,21 @@ impl User {
                     None
                 };
 
+                if wants_oauth2_clients(ctx) {
+                    ctx.oauth2_client_loader()
+                        .prime(&mut repo, page.edges.iter().map(|edge| edge.node.client_id))
+                        .await?;
+                }
+
+                if wants_users(ctx) {
+                    ctx.user_loader()
+                        .prime(
+                            &mut repo,
+                            page.edges.iter().filter_map(|edge| edge.node.user_id),
+                        )
+                        .await?;
+                }
+
                 repo.cancel().await?;
 
                 let mut connection = Connection::with_additional_fields(
@@ -539,6 +625,15 @@ impl User {
                     None
                 };
 
+                if wants_upstream_oauth_providers(ctx) {
+                    ctx.upstream_oauth_provider_loader()
+                        .prime(
+                            &mut repo,
+                            page.edges.iter().map(|edge| edge.node.provider_id),
+                        )
+                        .await?;
+                }
+
                 repo.cancel().await?;
 
                 let mut connection = Connection::with_additional_fields(
@@ -559,6 +654,97 @@ impl User {
         .await
     }
 
+    /// Get the list of OAuth 2.0 device code grants approved or rejected by
+    /// this user.
+    ///
+    /// Grants still waiting for a user to enter their code are not associated
+    /// with any user, so they never show up here.
+    #[allow(clippy::too_many_arguments)]
+    async fn device_code_grants(
+        &self,
+        ctx: &Context<'_>,
+
+        #[graphql(name = "state", desc = "List only grants in the given state.")]
+        state_param: Option<DeviceCodeGrantState>,
+
+        #[graphql(
+            name = "expired",
+            desc = "List only grants which are (or are not) expired."
+        )]
+        expired_param: Option<bool>,
+
+        #[graphql(desc = "Returns the elements in the list that come after the cursor.")]
+        after: Option<String>,
+        #[graphql(desc = "Returns the elements in the list that come before the cursor.")]
+        before: Option<String>,
+        #[graphql(desc = "Returns the first *n* elements from the list.")] first: Option<i32>,
+        #[graphql(desc = "Returns the last *n* elements from the list.")] last: Option<i32>,
+    ) -> Result<Connection<Cursor, DeviceCodeGrant, PreloadedTotalCount>, async_graphql::Error>
+    {
+        let state = ctx.state();
+        let mut repo = state.repository().await?;
+        let now = state.clock().now();
+
+        query(
+            after,
+            before,
+            first,
+            last,
+            async |after, before, first, last| {
+                let after_id = after
+                    .map(|x: OpaqueCursor<NodeCursor>| {
+                        x.extract_for_type(NodeType::DeviceCodeGrant)
+                    })
+                    .transpose()?;
+                let before_id = before
+                    .map(|x: OpaqueCursor<NodeCursor>| {
+                        x.extract_for_type(NodeType::DeviceCodeGrant)
+                    })
+                    .transpose()?;
+                let pagination = Pagination::try_new(before_id, after_id, first, last)?;
+
+                let mut filter = OAuth2DeviceCodeGrantFilter::new(now).for_user(&self.0);
+
+                if let Some(state) = state_param {
+                    filter = filter.with_state(state.into());
+                }
+
+                if let Some(expired) = expired_param {
+                    filter = filter.with_expired(expired);
+                }
+
+                let page = repo
+                    .oauth2_device_code_grant()
+                    .list(filter, pagination)
+                    .await?;
+
+                // Preload the total count if requested
+                let count = if ctx.look_ahead().field("totalCount").exists() {
+                    Some(repo.oauth2_device_code_grant().count(filter).await?)
+                } else {
+                    None
+                };
+
+                repo.cancel().await?;
+
+                let mut connection = Connection::with_additional_fields(
+                    page.has_previous_page,
+                    page.has_next_page,
+                    PreloadedTotalCount(count),
+                );
+                connection.edges.extend(page.edges.into_iter().map(|edge| {
+                    Edge::new(
+                        OpaqueCursor(NodeCursor(NodeType::DeviceCodeGrant, edge.cursor)),
+                        DeviceCodeGrant(edge.node),
+                    )
+                }));
+
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+
     /// Get the list of both compat and OAuth 2.0 sessions, chronologically
     /// sorted
     #[allow(clippy::too_many_arguments)]
@@ -678,6 +864,28 @@ impl User {
                     None
                 };
 
+                if wants_oauth2_clients(ctx) {
+                    let client_ids = page.edges.iter().filter_map(|edge| match &edge.node {
+                        mas_storage::app_session::AppSession::OAuth2(session) => {
+                            Some(session.client_id)
+                        }
+                        mas_storage::app_session::AppSession::Compat(_) => None,
+                    });
+                    ctx.oauth2_client_loader()
+                        .prime(&mut repo, client_ids)
+                        .await?;
+                }
+
+                if wants_users(ctx) {
+                    let user_ids = page.edges.iter().filter_map(|edge| match &edge.node {
+                        mas_storage::app_session::AppSession::OAuth2(session) => session.user_id,
+                        mas_storage::app_session::AppSession::Compat(session) => {
+                            Some(session.user_id)
+                        }
+                    });
+                    ctx.user_loader().prime(&mut repo, user_ids).await?;
+                }
+
                 repo.cancel().await?;
 
                 let mut connection = Connection::with_additional_fields(
@@ -705,6 +913,82 @@ impl User {
         .await
     }
 
+    /// Get the application sessions which were created or ended since the
+    /// given cursor, so that a list of sessions can be kept up to date without
+    /// fetching it again.
+    ///
+    /// Calling this without a cursor returns no events, only the cursor to use
+    /// in the next call.
+    async fn app_session_events(
+        &self,
+        ctx: &Context<'_>,
+
+        #[graphql(desc = "Returns the events which happened after this cursor.")] since: Option<
+            String,
+        >,
+    ) -> Result<AppSessionEvents, async_graphql::Error> {
+        let state = ctx.state();
+        let now = state.clock().now();
+
+        let Some(since) = since else {
+            return Ok(AppSessionEvents::new(Vec::new(), now, false));
+        };
+
+        let OpaqueCursor(SessionEventsCursor(since)) = EventsCursor::decode_cursor(&since)
+            .map_err(|_| async_graphql::Error::new("invalid cursor"))?;
+
+        let mut repo = state.repository().await?;
+
+        // Session IDs are ULIDs, so the sessions created since the cursor are the
+        // ones with an ID after the first possible ULID of that millisecond. This
+        // may return again sessions created during that same millisecond, which
+        // is better than missing some.
+        let since_ms = u64::try_from(since.timestamp_millis()).unwrap_or_default();
+        let pagination =
+            Pagination::first(SESSION_EVENTS_LIMIT).after(Ulid::from_parts(since_ms, 0));
+        let filter = AppSessionFilter::new().for_user(&self.0);
+        let created = repo.app_session().list(filter, pagination).await?;
+
+        let pagination = Pagination::first(SESSION_EVENTS_LIMIT);
+        let filter = AppSessionFilter::new()
+            .for_user(&self.0)
+            .with_finished_after(since);
+        let ended = repo.app_session().list(filter, pagination).await?;
+
+        repo.cancel().await?;
+
+        let truncated = created.has_next_page || ended.has_next_page;
+
+        let created = created.edges.into_iter().map(|edge| {
+            let created_at = match &edge.node {
+                mas_storage::app_session::AppSession::Compat(session) => session.created_at,
+                mas_storage::app_session::AppSession::OAuth2(session) => session.created_at,
+            };
+            AppSessionEvent::new(AppSessionEventKind::Created, created_at, edge.node)
+        });
+
+        let ended = ended.edges.into_iter().filter_map(|edge| {
+            let finished_at = match &edge.node {
+                mas_storage::app_session::AppSession::Compat(session) => {
+                    session.state.finished_at()
+                }
+                mas_storage::app_session::AppSession::OAuth2(session) => {
+                    session.state.finished_at()
+                }
+            }?;
+            Some(AppSessionEvent::new(
+                AppSessionEventKind::Ended,
+                finished_at,
+                edge.node,
+            ))
+        });
+
+        let mut events: Vec<_> = created.chain(ended).collect();
+        events.sort_by_key(|event| event.at);
+
+        Ok(AppSessionEvents::new(events, now, truncated))
+    }
+
     /// Check if the user has a password set.
     async fn has_password(&self, ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
         let state = ctx.state();
@@ -714,6 +998,40 @@ impl User {
 
         Ok(password.is_some())
     }
+
+    /// Get the most recent failed login attempts of the user, most recent
+    /// first.
+    async fn recent_login_failures(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<UserLoginFailure>, async_graphql::Error> {
+        let state = ctx.state();
+        let mut repo = state.repository().await?;
+
+        let failures = repo
+            .user_login_failure()
+            .list_recent(&self.0, RECENT_LOGIN_FAILURES)
+            .await?;
+
+        repo.cancel().await?;
+
+        Ok(failures.into_iter().map(UserLoginFailure).collect())
+    }
+
+    /// Get the consents the user chose to remember for OAuth 2.0 clients.
+    async fn oauth2_consents(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<OAuth2Consent>, async_graphql::Error> {
+        let state = ctx.state();
+        let mut repo = state.repository().await?;
+
+        let consents = repo.oauth2_client().list_consents_for_user(&self.0).await?;
+
+        repo.cancel().await?;
+
+        Ok(consents.into_iter().map(OAuth2Consent).collect())
+    }
 }
 
 /// A session in an application, either a compatibility or an OAuth 2.0 one
@@ -723,6 +1041,157 @@ pub enum AppSession {
     OAuth2Session(Box<OAuth2Session>),
 }
 
+impl From<mas_storage::app_session::AppSession> for AppSession {
+    fn from(value: mas_storage::app_session::AppSession) -> Self {
+        match value {
+            mas_storage::app_session::AppSession::Compat(session) => {
+                Self::CompatSession(Box::new(CompatSession::new(*session)))
+            }
+            mas_storage::app_session::AppSession::OAuth2(session) => {
+                Self::OAuth2Session(Box::new(OAuth2Session(*session)))
+            }
+        }
+    }
+}
+
+/// What happened to an application session
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum AppSessionEventKind {
+    /// The session was created
+    Created,
+
+    /// The session was ended
+    Ended,
+}
+
+/// Something which happened to an application session of a user
+#[derive(SimpleObject)]
+pub struct AppSessionEvent {
+    /// What happened to the session.
+    kind: AppSessionEventKind,
+
+    /// When it happened.
+    at: DateTime<Utc>,
+
+    /// The session, in its current state.
+    session: AppSession,
+}
+
+impl AppSessionEvent {
+    fn new(
+        kind: AppSessionEventKind,
+        at: DateTime<Utc>,
+        session: mas_storage::app_session::AppSession,
+    ) -> Self {
+        Self {
+            kind,
+            at,
+            session: session.into(),
+        }
+    }
+}
+
+/// The application sessions of a user which changed since a cursor
+#[derive(SimpleObject)]
+pub struct AppSessionEvents {
+    /// The events, in chronological order.
+    events: Vec<AppSessionEvent>,
+
+    /// The cursor to pass in the next call to only get newer events.
+    cursor: String,
+
+    /// Whether there were too many changes to return all of them. In that
+    /// case, the full list of sessions should be fetched again.
+    truncated: bool,
+}
+
+impl AppSessionEvents {
+    fn new(events: Vec<AppSessionEvent>, now: DateTime<Utc>, truncated: bool) -> Self {
+        Self {
+            events,
+            cursor: OpaqueCursor(SessionEventsCursor(now)).encode_cursor(),
+            truncated,
+        }
+    }
+}
+
+/// Where a failed login attempt came from
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum LoginFailureOrigin {
+    /// The attempt was made through the legacy Matrix login API
+    Compat,
+
+    /// The attempt was made through the interactive login form
+    Interactive,
+}
+
+impl From<mas_data_model::LoginFailureOrigin> for LoginFailureOrigin {
+    fn from(value: mas_data_model::LoginFailureOrigin) -> Self {
+        match value {
+            mas_data_model::LoginFailureOrigin::Compat => Self::Compat,
+            mas_data_model::LoginFailureOrigin::Interactive => Self::Interactive,
+        }
+    }
+}
+
+/// Why a login attempt failed
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum LoginFailureReason {
+    /// The password did not match
+    PasswordMismatch,
+
+    /// The user has no password set
+    NoPassword,
+
+    /// The attempt was rejected by the rate limiter
+    RateLimited,
+
+    /// The user is locked
+    UserLocked,
+
+    /// The user is deactivated
+    UserDeactivated,
+}
+
+impl From<mas_data_model::LoginFailureReason> for LoginFailureReason {
+    fn from(value: mas_data_model::LoginFailureReason) -> Self {
+        match value {
+            mas_data_model::LoginFailureReason::PasswordMismatch => Self::PasswordMismatch,
+            mas_data_model::LoginFailureReason::NoPassword => Self::NoPassword,
+            mas_data_model::LoginFailureReason::RateLimited => Self::RateLimited,
+            mas_data_model::LoginFailureReason::UserLocked => Self::UserLocked,
+            mas_data_model::LoginFailureReason::UserDeactivated => Self::UserDeactivated,
+        }
+    }
+}
+
+/// A failed login attempt of a user
+#[derive(Description)]
+pub struct UserLoginFailure(pub mas_data_model::UserLoginFailure);
+
+#[Object(use_type_description)]
+impl UserLoginFailure {
+    /// When the login attempt was made.
+    pub async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    /// Where the login attempt came from.
+    async fn origin(&self) -> LoginFailureOrigin {
+        self.0.origin.into()
+    }
+
+    /// Why the login attempt failed.
+    async fn reason(&self) -> LoginFailureReason {
+        self.0.reason.into()
+    }
+
+    /// The IP address the login attempt was made from, if known.
+    async fn ip_address(&self) -> Option<String> {
+        self.0.ip_address.map(|ip| ip.to_string())
+    }
+}
+
 /// A user email address
 #[derive(Description)]
 pub struct UserEmail(pub mas_data_model::UserEmail);
@@ -14,13 +14,13 @@ pub use self::anonymous::Anonymous;
 /// Represents the current viewer
 #[derive(Union)]
 pub enum Viewer {
-    User(User),
+    User(Box<User>),
     Anonymous(Anonymous),
 }
 
 impl Viewer {
     pub fn user(user: mas_data_model::User) -> Self {
-        Self::User(User(user))
+        Self::User(Box::new(User(user)))
     }
 
     pub fn anonymous() -> Self {
@@ -80,7 +80,7 @@ pub enum SetCompatSessionNamePayload {
     NotFound,
 
     /// The session was updated.
-    Updated(mas_data_model::CompatSession),
+    Updated(Box<mas_data_model::CompatSession>),
 }
 
 /// The status of the `setCompatSessionName` mutation.
@@ -106,7 +106,7 @@ impl SetCompatSessionNamePayload {
     /// The session that was updated.
     async fn oauth2_session(&self) -> Option<CompatSession> {
         match self {
-            Self::Updated(session) => Some(CompatSession::new(session.clone())),
+            Self::Updated(session) => Some(CompatSession::new(*session.clone())),
             Self::NotFound => None,
         }
     }
@@ -196,6 +196,6 @@ impl CompatSessionMutations {
 
         repo.save().await?;
 
-        Ok(SetCompatSessionNamePayload::Updated(session))
+        Ok(SetCompatSessionNamePayload::Updated(Box::new(session)))
     }
 }
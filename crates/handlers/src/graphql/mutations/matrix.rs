@@ -40,7 +40,7 @@ pub enum SetDisplayNameStatus {
 /// The payload of the `setDisplayName` mutation
 #[derive(Description)]
 enum SetDisplayNamePayload {
-    Set(User),
+    Set(Box<User>),
     Invalid,
 }
 
@@ -113,6 +113,6 @@ impl MatrixMutations {
                 .context("Failed to unset display name")?;
         }
 
-        Ok(SetDisplayNamePayload::Set(User(user.clone())))
+        Ok(SetDisplayNamePayload::Set(Box::new(User(user.clone()))))
     }
 }
@@ -20,6 +20,7 @@ use mas_storage::{
 use oauth2_types::scope::Scope;
 
 use crate::graphql::{
+    UserId,
     model::{NodeType, OAuth2Session},
     state::ContextExt,
 };
@@ -158,6 +159,46 @@ impl SetOAuth2SessionNamePayload {
     }
 }
 
+/// The input of the `revokeOauth2Consent` mutation.
+#[derive(InputObject)]
+pub struct RevokeOAuth2ConsentInput {
+    /// The ID of the user who gave the consent.
+    user_id: ID,
+
+    /// The ID of the client the consent was given to.
+    oauth2_client_id: ID,
+}
+
+/// The payload of the `revokeOauth2Consent` mutation.
+pub enum RevokeOAuth2ConsentPayload {
+    /// The consent was not found.
+    NotFound,
+
+    /// The consent was revoked.
+    Revoked,
+}
+
+/// The status of the `revokeOauth2Consent` mutation.
+#[derive(Enum, Copy, Clone, PartialEq, Eq, Debug)]
+enum RevokeOAuth2ConsentStatus {
+    /// The consent was revoked.
+    Revoked,
+
+    /// The consent was not found.
+    NotFound,
+}
+
+#[Object]
+impl RevokeOAuth2ConsentPayload {
+    /// The status of the mutation.
+    async fn status(&self) -> RevokeOAuth2ConsentStatus {
+        match self {
+            Self::Revoked => RevokeOAuth2ConsentStatus::Revoked,
+            Self::NotFound => RevokeOAuth2ConsentStatus::NotFound,
+        }
+    }
+}
+
 #[Object]
 impl OAuth2SessionMutations {
     /// Create a new arbitrary OAuth 2.0 Session.
@@ -343,4 +384,44 @@ impl OAuth2SessionMutations {
 
         Ok(SetOAuth2SessionNamePayload::Updated(Box::new(session)))
     }
+
+    /// Forget the consent a user gave to an OAuth 2.0 client, so that they
+    /// are asked for consent again on the next authorization.
+    async fn revoke_oauth2_consent(
+        &self,
+        ctx: &Context<'_>,
+        input: RevokeOAuth2ConsentInput,
+    ) -> Result<RevokeOAuth2ConsentPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let user_id = NodeType::User.extract_ulid(&input.user_id)?;
+        let client_id = NodeType::OAuth2Client.extract_ulid(&input.oauth2_client_id)?;
+        let requester = ctx.requester();
+
+        if !requester.is_owner_or_admin(&UserId(user_id)) {
+            return Ok(RevokeOAuth2ConsentPayload::NotFound);
+        }
+
+        let mut repo = state.repository().await?;
+
+        let Some(user) = repo.user().lookup(user_id).await? else {
+            return Ok(RevokeOAuth2ConsentPayload::NotFound);
+        };
+
+        let Some(client) = repo.oauth2_client().lookup(client_id).await? else {
+            return Ok(RevokeOAuth2ConsentPayload::NotFound);
+        };
+
+        let revoked = repo
+            .oauth2_client()
+            .revoke_consent_for_user(&client, &user)
+            .await?;
+
+        repo.save().await?;
+
+        if revoked == 0 {
+            return Ok(RevokeOAuth2ConsentPayload::NotFound);
+        }
+
+        Ok(RevokeOAuth2ConsentPayload::Revoked)
+    }
 }
@@ -0,0 +1,214 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use anyhow::Context as _;
+use async_graphql::{Context, Description, Enum, ID, InputObject, Object};
+use mas_data_model::AuditEventPayload;
+use mas_storage::{
+    compat::CompatSessionFilter,
+    oauth2::OAuth2SessionFilter,
+    queue::{QueueJobRepositoryExt as _, SyncDevicesJob},
+    upstream_oauth2::{UpstreamOAuthLinkFilter, UpstreamOAuthSessionFilter},
+    user::BrowserSessionFilter,
+};
+use tracing::info;
+
+use crate::{
+    audit::schedule_audit_event,
+    graphql::{
+        model::{NodeType, UpstreamOAuth2Link},
+        state::ContextExt,
+    },
+};
+
+#[derive(Default)]
+pub struct UpstreamOAuthMutations {
+    _private: (),
+}
+
+/// The input for the `transferUpstreamOauthLink` mutation.
+#[derive(InputObject)]
+struct TransferUpstreamOAuthLinkInput {
+    /// The ID of the upstream link to transfer.
+    upstream_oauth2_link_id: ID,
+
+    /// The ID of the user which should own the upstream link.
+    user_id: ID,
+}
+
+/// The status of the `transferUpstreamOauthLink` mutation.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+enum TransferUpstreamOAuthLinkStatus {
+    /// The upstream link was transferred.
+    Transferred,
+
+    /// The upstream link was not found.
+    LinkNotFound,
+
+    /// The user was not found.
+    UserNotFound,
+
+    /// The user already has an upstream link for the same provider.
+    AlreadyLinked,
+}
+
+/// The payload for the `transferUpstreamOauthLink` mutation.
+#[derive(Description)]
+enum TransferUpstreamOAuthLinkPayload {
+    /// The upstream link was transferred.
+    Transferred(Box<mas_data_model::UpstreamOAuthLink>),
+
+    /// The upstream link was not found.
+    LinkNotFound,
+
+    /// The user was not found.
+    UserNotFound,
+
+    /// The user already has an upstream link for the same provider.
+    AlreadyLinked,
+}
+
+#[Object(use_type_description)]
+impl TransferUpstreamOAuthLinkPayload {
+    /// Status of the operation
+    async fn status(&self) -> TransferUpstreamOAuthLinkStatus {
+        match self {
+            Self::Transferred(_) => TransferUpstreamOAuthLinkStatus::Transferred,
+            Self::LinkNotFound => TransferUpstreamOAuthLinkStatus::LinkNotFound,
+            Self::UserNotFound => TransferUpstreamOAuthLinkStatus::UserNotFound,
+            Self::AlreadyLinked => TransferUpstreamOAuthLinkStatus::AlreadyLinked,
+        }
+    }
+
+    /// The upstream link that was transferred.
+    async fn upstream_oauth2_link(&self) -> Option<UpstreamOAuth2Link> {
+        match self {
+            Self::Transferred(link) => Some(UpstreamOAuth2Link::new(*link.clone())),
+            Self::LinkNotFound | Self::UserNotFound | Self::AlreadyLinked => None,
+        }
+    }
+}
+
+#[Object]
+impl UpstreamOAuthMutations {
+    /// Move an upstream link from the user it belongs to onto another user.
+    ///
+    /// The sessions which were started through this upstream link are ended.
+    /// This is only available to administrators.
+    async fn transfer_upstream_oauth_link(
+        &self,
+        ctx: &Context<'_>,
+        input: TransferUpstreamOAuthLinkInput,
+    ) -> Result<TransferUpstreamOAuthLinkPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let clock = state.clock();
+        let mut rng = state.rng();
+        let requester = ctx.requester();
+
+        if !requester.is_admin() {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let link_id = NodeType::UpstreamOAuth2Link.extract_ulid(&input.upstream_oauth2_link_id)?;
+        let user_id = NodeType::User.extract_ulid(&input.user_id)?;
+
+        let mut repo = state.repository().await?;
+
+        let Some(link) = repo.upstream_oauth_link().lookup(link_id).await? else {
+            return Ok(TransferUpstreamOAuthLinkPayload::LinkNotFound);
+        };
+
+        let Some(user) = repo.user().lookup(user_id).await? else {
+            return Ok(TransferUpstreamOAuthLinkPayload::UserNotFound);
+        };
+
+        let provider = repo
+            .upstream_oauth_provider()
+            .lookup(link.provider_id)
+            .await?
+            .context("Failed to load upstream OAuth 2.0 provider")?;
+
+        // A user can only have one link per provider
+        let filter = UpstreamOAuthLinkFilter::new()
+            .for_user(&user)
+            .for_provider(&provider);
+        if repo.upstream_oauth_link().count(filter).await? > 0 {
+            return Ok(TransferUpstreamOAuthLinkPayload::AlreadyLinked);
+        }
+
+        // The sessions started through this link belong to the previous user, so
+        // we end them before handing the link over
+        let browser_session_filter = BrowserSessionFilter::new()
+            .authenticated_by_upstream_sessions_only(
+                UpstreamOAuthSessionFilter::new().for_link(&link),
+            );
+
+        let browser_sessions_affected = repo
+            .browser_session()
+            .finish_bulk(&clock, browser_session_filter.active_only())
+            .await?;
+
+        let oauth2_sessions_affected = repo
+            .oauth2_session()
+            .finish_bulk(
+                &clock,
+                OAuth2SessionFilter::new()
+                    .active_only()
+                    .for_browser_sessions(browser_session_filter),
+            )
+            .await?;
+
+        let compat_sessions_affected = repo
+            .compat_session()
+            .finish_bulk(
+                &clock,
+                CompatSessionFilter::new()
+                    .active_only()
+                    .for_browser_sessions(browser_session_filter),
+            )
+            .await?;
+
+        let previous_user_id = link.user_id;
+        if let Some(previous_user_id) = previous_user_id {
+            // Schedule a job to sync the devices of the previous user with the
+            // homeserver
+            repo.queue_job()
+                .schedule_job(
+                    &mut rng,
+                    &clock,
+                    SyncDevicesJob::new_for_id(previous_user_id),
+                )
+                .await?;
+        }
+
+        let link = repo
+            .upstream_oauth_link()
+            .transfer_to_user(link, &user)
+            .await?;
+
+        info!(
+            upstream_oauth_link.id = %link.id,
+            previous_user.id = previous_user_id.map(tracing::field::display),
+            %user.id,
+            "Transferred upstream link, ended {browser_sessions_affected} browser sessions, {oauth2_sessions_affected} OAuth 2.0 sessions and {compat_sessions_affected} compatibility sessions"
+        );
+
+        schedule_audit_event(
+            &mut repo,
+            &mut rng,
+            &clock,
+            state.site_config(),
+            None,
+            AuditEventPayload::upstream_link_transfer(&link, previous_user_id, &user),
+        )
+        .await?;
+
+        repo.save().await?;
+
+        Ok(TransferUpstreamOAuthLinkPayload::Transferred(Box::new(
+            link,
+        )))
+    }
+}
@@ -6,10 +6,14 @@
 
 use anyhow::Context as _;
 use async_graphql::{Context, Description, Enum, ID, InputObject, Object};
+use chrono::{DateTime, Utc};
+use mas_data_model::{AuditEventPayload, PasswordChangeInitiator};
+use mas_i18n::DataLocale;
 use mas_storage::{
     queue::{
-        DeactivateUserJob, ProvisionUserJob, QueueJobRepositoryExt as _,
-        SendAccountRecoveryEmailsJob,
+        DeactivateUserJob, FinishScheduledDeactivationJob, ProvisionUserJob,
+        QueueJobRepositoryExt as _, SendAccountRecoveryEmailsJob,
+        SendDeactivationScheduledEmailJob,
     },
     user::UserRepository,
 };
@@ -19,10 +23,15 @@ use url::Url;
 use zeroize::Zeroizing;
 
 use super::verify_password_if_needed;
-use crate::graphql::{
-    UserId,
-    model::{NodeType, User},
-    state::ContextExt,
+use crate::{
+    audit::schedule_audit_event,
+    graphql::{
+        UserId,
+        model::{NodeType, User},
+        state::ContextExt,
+    },
+    session::recently_authenticated,
+    user_actions::end_sessions_after_recovery,
 };
 
 #[derive(Default)]
@@ -114,7 +123,7 @@ enum LockUserStatus {
 #[derive(Description)]
 enum LockUserPayload {
     /// The user was locked.
-    Locked(mas_data_model::User),
+    Locked(Box<mas_data_model::User>),
 
     /// The user was not found.
     NotFound,
@@ -133,7 +142,7 @@ impl LockUserPayload {
     /// The user that was locked.
     async fn user(&self) -> Option<User> {
         match self {
-            Self::Locked(user) => Some(User(user.clone())),
+            Self::Locked(user) => Some(User(*user.clone())),
             Self::NotFound => None,
         }
     }
@@ -160,7 +169,7 @@ enum UnlockUserStatus {
 #[derive(Description)]
 enum UnlockUserPayload {
     /// The user was unlocked.
-    Unlocked(mas_data_model::User),
+    Unlocked(Box<mas_data_model::User>),
 
     /// The user was not found.
     NotFound,
@@ -179,7 +188,7 @@ impl UnlockUserPayload {
     /// The user that was unlocked.
     async fn user(&self) -> Option<User> {
         match self {
-            Self::Unlocked(user) => Some(User(user.clone())),
+            Self::Unlocked(user) => Some(User(*user.clone())),
             Self::NotFound => None,
         }
     }
@@ -199,7 +208,7 @@ struct SetCanRequestAdminInput {
 #[derive(Description)]
 enum SetCanRequestAdminPayload {
     /// The user was updated.
-    Updated(mas_data_model::User),
+    Updated(Box<mas_data_model::User>),
 
     /// The user was not found.
     NotFound,
@@ -210,7 +219,7 @@ impl SetCanRequestAdminPayload {
     /// The user that was updated.
     async fn user(&self) -> Option<User> {
         match self {
-            Self::Updated(user) => Some(User(user.clone())),
+            Self::Updated(user) => Some(User(*user.clone())),
             Self::NotFound => None,
         }
     }
@@ -227,7 +236,7 @@ struct AllowUserCrossSigningResetInput {
 #[derive(Description)]
 enum AllowUserCrossSigningResetPayload {
     /// The user was updated.
-    Allowed(mas_data_model::User),
+    Allowed(Box<mas_data_model::User>),
 
     /// The user was not found.
     NotFound,
@@ -238,7 +247,7 @@ impl AllowUserCrossSigningResetPayload {
     /// The user that was updated.
     async fn user(&self) -> Option<User> {
         match self {
-            Self::Allowed(user) => Some(User(user.clone())),
+            Self::Allowed(user) => Some(User(*user.clone())),
             Self::NotFound => None,
         }
     }
@@ -270,6 +279,11 @@ struct SetPasswordByRecoveryInput {
 
     /// The new password for the user.
     new_password: String,
+
+    /// Whether to keep the other sessions of the user signed in. By default,
+    /// they are all ended, in case someone else had access to the account.
+    #[graphql(default)]
+    keep_sessions: bool,
 }
 
 /// The return type for the `setPassword` mutation.
@@ -319,6 +333,9 @@ enum SetPasswordStatus {
 
     /// Your account is locked and you can't change its password.
     AccountLocked,
+
+    /// You need to sign in again before changing your password.
+    ReauthenticationRequired,
 }
 
 #[Object(use_type_description)]
@@ -405,7 +422,7 @@ pub struct DeactivateUserInput {
 #[derive(Description)]
 pub enum DeactivateUserPayload {
     /// The user was deactivated.
-    Deactivated(mas_data_model::User),
+    Deactivated(Box<mas_data_model::User>),
 
     /// The password was wrong or missing.
     IncorrectPassword,
@@ -433,12 +450,133 @@ impl DeactivateUserPayload {
 
     async fn user(&self) -> Option<User> {
         match self {
-            Self::Deactivated(user) => Some(User(user.clone())),
+            Self::Deactivated(user) => Some(User(*user.clone())),
             Self::IncorrectPassword => None,
         }
     }
 }
 
+/// The input for the `scheduleDeactivation` mutation.
+#[derive(InputObject)]
+pub struct ScheduleDeactivationInput {
+    /// The username of the current user, to confirm the deactivation. It must
+    /// match exactly.
+    username: String,
+
+    /// Whether to ask the homeserver to GDPR-erase the user once the
+    /// deactivation is carried out
+    ///
+    /// This has the same meaning as in the `deactivateUser` mutation.
+    hs_erase: bool,
+
+    /// The language to use for the confirmation email, if the user has no
+    /// preferred language
+    #[graphql(default = "en")]
+    language: String,
+}
+
+/// The payload for the `scheduleDeactivation` mutation.
+#[derive(Description)]
+pub enum ScheduleDeactivationPayload {
+    /// The user was locked, and will be deactivated after the grace period.
+    Scheduled(Box<mas_data_model::User>),
+
+    /// The username doesn't match the one of the current user.
+    UsernameMismatch,
+
+    /// The user needs to sign in again before deactivating their account.
+    ReauthenticationRequired,
+}
+
+/// The status of the `scheduleDeactivation` mutation.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ScheduleDeactivationStatus {
+    /// The user was locked, and will be deactivated after the grace period.
+    Scheduled,
+
+    /// The username doesn't match the one of the current user.
+    UsernameMismatch,
+
+    /// The user needs to sign in again before deactivating their account.
+    ReauthenticationRequired,
+}
+
+#[Object(use_type_description)]
+impl ScheduleDeactivationPayload {
+    /// Status of the operation
+    async fn status(&self) -> ScheduleDeactivationStatus {
+        match self {
+            Self::Scheduled(_) => ScheduleDeactivationStatus::Scheduled,
+            Self::UsernameMismatch => ScheduleDeactivationStatus::UsernameMismatch,
+            Self::ReauthenticationRequired => ScheduleDeactivationStatus::ReauthenticationRequired,
+        }
+    }
+
+    /// The user that was locked.
+    async fn user(&self) -> Option<User> {
+        match self {
+            Self::Scheduled(user) => Some(User(*user.clone())),
+            Self::UsernameMismatch | Self::ReauthenticationRequired => None,
+        }
+    }
+
+    /// When the deactivation will be carried out, unless the user signs in
+    /// again to cancel it.
+    async fn scheduled_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Scheduled(user) => user.deactivation_scheduled_at,
+            Self::UsernameMismatch | Self::ReauthenticationRequired => None,
+        }
+    }
+}
+
+/// The input for the `setPreferredLanguage` mutation.
+#[derive(InputObject)]
+pub struct SetPreferredLanguageInput {
+    /// The language tag to use for the pages and emails sent to the user, or
+    /// `null` to use the language negotiated with the browser.
+    language: Option<String>,
+}
+
+/// The payload for the `setPreferredLanguage` mutation.
+#[derive(Description)]
+pub enum SetPreferredLanguagePayload {
+    /// The preferred language was updated.
+    Set(Box<mas_data_model::User>),
+
+    /// The language is not supported by this server.
+    InvalidLanguage,
+}
+
+/// The status of the `setPreferredLanguage` mutation.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum SetPreferredLanguageStatus {
+    /// The preferred language was updated.
+    Set,
+
+    /// The language is not supported by this server.
+    InvalidLanguage,
+}
+
+#[Object(use_type_description)]
+impl SetPreferredLanguagePayload {
+    /// Status of the operation
+    async fn status(&self) -> SetPreferredLanguageStatus {
+        match self {
+            Self::Set(_) => SetPreferredLanguageStatus::Set,
+            Self::InvalidLanguage => SetPreferredLanguageStatus::InvalidLanguage,
+        }
+    }
+
+    /// The user that was updated.
+    async fn user(&self) -> Option<User> {
+        match self {
+            Self::Set(user) => Some(User(*user.clone())),
+            Self::InvalidLanguage => None,
+        }
+    }
+}
+
 fn valid_username_character(c: char) -> bool {
     c.is_ascii_lowercase()
         || c.is_ascii_digit()
@@ -551,6 +689,16 @@ impl UserMutations {
 
         let user = repo.user().lock(&state.clock(), user).await?;
 
+        schedule_audit_event(
+            &mut repo,
+            &mut rng,
+            &clock,
+            state.site_config(),
+            None,
+            AuditEventPayload::account_lock(&user),
+        )
+        .await?;
+
         if deactivate {
             info!(%user.id, "Scheduling deactivation of user");
             repo.queue_job()
@@ -560,7 +708,7 @@ impl UserMutations {
 
         repo.save().await?;
 
-        Ok(LockUserPayload::Locked(user))
+        Ok(LockUserPayload::Locked(Box::new(user)))
     }
 
     /// Unlock and reactivate a user. This is only available to administrators.
@@ -594,7 +742,7 @@ impl UserMutations {
 
         repo.save().await?;
 
-        Ok(UnlockUserPayload::Unlocked(user))
+        Ok(UnlockUserPayload::Unlocked(Box::new(user)))
     }
 
     /// Set whether a user can request admin. This is only available to
@@ -627,7 +775,7 @@ impl UserMutations {
 
         repo.save().await?;
 
-        Ok(SetCanRequestAdminPayload::Updated(user))
+        Ok(SetCanRequestAdminPayload::Updated(Box::new(user)))
     }
 
     /// Temporarily allow user to reset their cross-signing keys.
@@ -657,7 +805,7 @@ impl UserMutations {
             .await
             .context("Failed to allow cross-signing reset")?;
 
-        Ok(AllowUserCrossSigningResetPayload::Allowed(user))
+        Ok(AllowUserCrossSigningResetPayload::Allowed(Box::new(user)))
     }
 
     /// Set the password for a user.
@@ -721,6 +869,22 @@ impl UserMutations {
                 });
             }
 
+            // Changing the password is a sensitive action, which requires a
+            // recent authentication of the browser session
+            if let Some(browser_session) = requester.browser_session()
+                && !recently_authenticated(
+                    &mut repo,
+                    &state.clock(),
+                    state.site_config(),
+                    browser_session,
+                )
+                .await?
+            {
+                return Ok(SetPasswordPayload {
+                    status: SetPasswordStatus::ReauthenticationRequired,
+                });
+            }
+
             let Some(active_password) = repo.user_password().active(&user).await? else {
                 // The user has no current password, so can't verify against one.
                 // In the future, it may be desirable to let the user set a password without any
@@ -767,6 +931,21 @@ impl UserMutations {
             )
             .await?;
 
+        let initiator = if requester.is_admin() {
+            PasswordChangeInitiator::Admin
+        } else {
+            PasswordChangeInitiator::User
+        };
+        schedule_audit_event(
+            &mut repo,
+            &mut state.rng(),
+            &state.clock(),
+            state.site_config(),
+            None,
+            AuditEventPayload::password_change(&user, initiator),
+        )
+        .await?;
+
         repo.save().await?;
 
         Ok(SetPasswordPayload {
@@ -862,11 +1041,37 @@ impl UserMutations {
             )
             .await?;
 
+        // Notify the user in the language they used to start the recovery
+        let language = session.locale.clone();
+
         // Mark the session as consumed
         repo.user_recovery()
             .consume_ticket(&clock, ticket, session)
             .await?;
 
+        // Someone else may have had access to the account, so end all of its
+        // sessions
+        end_sessions_after_recovery(
+            &mut repo,
+            &mut state.rng(),
+            &clock,
+            &user,
+            None,
+            input.keep_sessions,
+            language,
+        )
+        .await?;
+
+        schedule_audit_event(
+            &mut repo,
+            &mut state.rng(),
+            &clock,
+            state.site_config(),
+            None,
+            AuditEventPayload::password_change(&user, PasswordChangeInitiator::Recovery),
+        )
+        .await?;
+
         repo.save().await?;
 
         Ok(SetPasswordPayload {
@@ -981,6 +1186,125 @@ impl UserMutations {
 
         repo.save().await?;
 
-        Ok(DeactivateUserPayload::Deactivated(user))
+        Ok(DeactivateUserPayload::Deactivated(Box::new(user)))
+    }
+
+    /// Schedule the deactivation of the current user account
+    ///
+    /// The account is locked right away, and deactivated once the grace
+    /// period configured on the server is over. The user can cancel the
+    /// deactivation by signing in again during this period.
+    async fn schedule_deactivation(
+        &self,
+        ctx: &Context<'_>,
+        input: ScheduleDeactivationInput,
+    ) -> Result<ScheduleDeactivationPayload, async_graphql::Error> {
+        let state = ctx.state();
+        let mut rng = state.rng();
+        let clock = state.clock();
+        let requester = ctx.requester();
+        let site_config = state.site_config();
+
+        // Only allow calling this if the requester is a browser session
+        let Some(browser_session) = requester.browser_session() else {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        };
+
+        if !site_config.account_deactivation_allowed {
+            return Err(async_graphql::Error::new(
+                "Account deactivation is not allowed on this server",
+            ));
+        }
+
+        // Check if the locale is valid
+        let _: DataLocale = input.language.parse()?;
+
+        let mut repo = state.repository().await?;
+
+        // Deactivating the account is a sensitive action, which requires a
+        // recent authentication of the browser session
+        if !recently_authenticated(&mut repo, &clock, site_config, browser_session).await? {
+            return Ok(ScheduleDeactivationPayload::ReauthenticationRequired);
+        }
+
+        if input.username != browser_session.user.username {
+            return Ok(ScheduleDeactivationPayload::UsernameMismatch);
+        }
+
+        // Lock the user right away, so that they are logged out everywhere
+        let user = repo
+            .user()
+            .lock(&clock, browser_session.user.clone())
+            .await?;
+
+        let scheduled_at = clock.now() + site_config.deactivation_grace_period;
+        let user = repo
+            .user()
+            .schedule_deactivation(user, scheduled_at)
+            .await?;
+
+        // Deactivate the user fully once the grace period is over
+        repo.queue_job()
+            .schedule_job_later(
+                &mut rng,
+                &clock,
+                FinishScheduledDeactivationJob::new(&user, input.hs_erase),
+                scheduled_at,
+            )
+            .await?;
+
+        repo.queue_job()
+            .schedule_job(
+                &mut rng,
+                &clock,
+                SendDeactivationScheduledEmailJob::new(&user, input.language),
+            )
+            .await?;
+
+        repo.save().await?;
+
+        info!(%user.id, %scheduled_at, "User scheduled the deactivation of their account");
+
+        Ok(ScheduleDeactivationPayload::Scheduled(Box::new(user)))
+    }
+
+    /// Set the language used for the pages and emails sent to the current
+    /// user, overriding the one negotiated with the browser.
+    async fn set_preferred_language(
+        &self,
+        ctx: &Context<'_>,
+        input: SetPreferredLanguageInput,
+    ) -> Result<SetPreferredLanguagePayload, async_graphql::Error> {
+        let state = ctx.state();
+        let requester = ctx.requester();
+
+        // Only allow calling this if the requester is a browser session
+        let Some(browser_session) = requester.browser_session() else {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        };
+
+        let locale = match input.language {
+            Some(language) => {
+                let Ok(locale) = language.parse::<DataLocale>() else {
+                    return Ok(SetPreferredLanguagePayload::InvalidLanguage);
+                };
+
+                if !state.translator().has_locale(&locale) {
+                    return Ok(SetPreferredLanguagePayload::InvalidLanguage);
+                }
+
+                Some(locale.to_string())
+            }
+            None => None,
+        };
+
+        let mut repo = state.repository().await?;
+        let user = repo
+            .user()
+            .set_locale(browser_session.user.clone(), locale)
+            .await?;
+        repo.save().await?;
+
+        Ok(SetPreferredLanguagePayload::Set(Box::new(user)))
     }
 }
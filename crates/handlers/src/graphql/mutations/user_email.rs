@@ -6,19 +6,31 @@
 
 use anyhow::Context as _;
 use async_graphql::{Context, Description, Enum, ID, InputObject, Object};
+use chrono::Duration;
 use mas_i18n::DataLocale;
 use mas_storage::{
     RepositoryAccess,
-    queue::{ProvisionUserJob, QueueJobRepositoryExt as _, SendEmailAuthenticationCodeJob},
+    queue::{
+        ProvisionUserJob, QueueJobRepositoryExt as _, SendEmailAuthenticationCodeJob,
+        SendEmailChangeConfirmationJob,
+    },
     user::{UserEmailFilter, UserEmailRepository, UserRepository},
 };
+use rand::distributions::{Alphanumeric, DistString};
 
 use super::verify_password_if_needed;
-use crate::graphql::{
-    model::{NodeType, User, UserEmail, UserEmailAuthentication},
-    state::ContextExt,
+use crate::{
+    graphql::{
+        model::{NodeType, User, UserEmail, UserEmailAuthentication},
+        state::ContextExt,
+    },
+    session::recently_authenticated,
 };
 
+/// How long the previous email address of a user has to confirm a change of
+/// email address
+const EMAIL_CHANGE_CONFIRMATION_TTL: Duration = Duration::hours(24);
+
 #[derive(Default)]
 pub struct UserEmailMutations {
     _private: (),
@@ -138,6 +150,10 @@ enum RemoveEmailStatus {
 
     /// The password provided is incorrect
     IncorrectPassword,
+
+    /// The email address is the last one of the user, and can't be removed
+    /// because email changes have to be confirmed from a previous address
+    LastEmail,
 }
 
 /// The payload of the `removeEmail` mutation
@@ -146,6 +162,7 @@ enum RemoveEmailPayload {
     Removed(mas_data_model::UserEmail),
     NotFound,
     IncorrectPassword,
+    LastEmail,
 }
 
 #[Object(use_type_description)]
@@ -156,6 +173,7 @@ impl RemoveEmailPayload {
             RemoveEmailPayload::Removed(_) => RemoveEmailStatus::Removed,
             RemoveEmailPayload::NotFound => RemoveEmailStatus::NotFound,
             RemoveEmailPayload::IncorrectPassword => RemoveEmailStatus::IncorrectPassword,
+            RemoveEmailPayload::LastEmail => RemoveEmailStatus::LastEmail,
         }
     }
 
@@ -163,7 +181,9 @@ impl RemoveEmailPayload {
     async fn email(&self) -> Option<UserEmail> {
         match self {
             RemoveEmailPayload::Removed(email) => Some(UserEmail(email.clone())),
-            RemoveEmailPayload::NotFound | RemoveEmailPayload::IncorrectPassword => None,
+            RemoveEmailPayload::NotFound
+            | RemoveEmailPayload::IncorrectPassword
+            | RemoveEmailPayload::LastEmail => None,
         }
     }
 
@@ -173,7 +193,9 @@ impl RemoveEmailPayload {
 
         let user_id = match self {
             RemoveEmailPayload::Removed(email) => email.user_id,
-            RemoveEmailPayload::NotFound | RemoveEmailPayload::IncorrectPassword => {
+            RemoveEmailPayload::NotFound
+            | RemoveEmailPayload::IncorrectPassword
+            | RemoveEmailPayload::LastEmail => {
                 return Ok(None);
             }
         };
@@ -211,7 +233,7 @@ enum SetPrimaryEmailStatus {
 /// The payload of the `setPrimaryEmail` mutation
 #[derive(Description)]
 enum SetPrimaryEmailPayload {
-    Set(mas_data_model::User),
+    Set(Box<mas_data_model::User>),
     NotFound,
 }
 
@@ -227,7 +249,7 @@ impl SetPrimaryEmailPayload {
     /// The user to whom the email address belongs
     async fn user(&self) -> Option<User> {
         match self {
-            SetPrimaryEmailPayload::Set(user) => Some(User(user.clone())),
+            SetPrimaryEmailPayload::Set(user) => Some(User(*user.clone())),
             SetPrimaryEmailPayload::NotFound => None,
         }
     }
@@ -263,6 +285,8 @@ enum StartEmailAuthenticationStatus {
     InUse,
     /// The password provided is incorrect
     IncorrectPassword,
+    /// The user needs to sign in again before adding an email address
+    ReauthenticationRequired,
 }
 
 /// The payload of the `startEmailAuthentication` mutation
@@ -276,6 +300,7 @@ enum StartEmailAuthenticationPayload {
     },
     InUse,
     IncorrectPassword,
+    ReauthenticationRequired,
 }
 
 #[Object(use_type_description)]
@@ -289,6 +314,9 @@ impl StartEmailAuthenticationPayload {
             Self::Denied { .. } => StartEmailAuthenticationStatus::Denied,
             Self::InUse => StartEmailAuthenticationStatus::InUse,
             Self::IncorrectPassword => StartEmailAuthenticationStatus::IncorrectPassword,
+            Self::ReauthenticationRequired => {
+                StartEmailAuthenticationStatus::ReauthenticationRequired
+            }
         }
     }
 
@@ -300,7 +328,8 @@ impl StartEmailAuthenticationPayload {
             | Self::RateLimited
             | Self::Denied { .. }
             | Self::InUse
-            | Self::IncorrectPassword => None,
+            | Self::IncorrectPassword
+            | Self::ReauthenticationRequired => None,
         }
     }
 
@@ -323,12 +352,18 @@ struct CompleteEmailAuthenticationInput {
 
     /// The ID of the authentication session to complete
     id: ID,
+
+    /// The language to use for the email sent to the previous address, if the
+    /// change has to be confirmed from there
+    #[graphql(default = "en")]
+    language: String,
 }
 
 /// The payload of the `completeEmailAuthentication` mutation
 #[derive(Description)]
 enum CompleteEmailAuthenticationPayload {
     Completed,
+    PendingConfirmation,
     InvalidCode,
     CodeExpired,
     InUse,
@@ -340,6 +375,9 @@ enum CompleteEmailAuthenticationPayload {
 enum CompleteEmailAuthenticationStatus {
     /// The authentication was completed
     Completed,
+    /// The authentication was completed, but the change has to be confirmed
+    /// from the previous email address of the user before the new one is added
+    PendingConfirmation,
     /// The authentication code is invalid
     InvalidCode,
     /// The authentication code has expired
@@ -356,6 +394,7 @@ impl CompleteEmailAuthenticationPayload {
     async fn status(&self) -> CompleteEmailAuthenticationStatus {
         match self {
             Self::Completed => CompleteEmailAuthenticationStatus::Completed,
+            Self::PendingConfirmation => CompleteEmailAuthenticationStatus::PendingConfirmation,
             Self::InvalidCode => CompleteEmailAuthenticationStatus::InvalidCode,
             Self::CodeExpired => CompleteEmailAuthenticationStatus::CodeExpired,
             Self::InUse => CompleteEmailAuthenticationStatus::InUse,
@@ -531,7 +570,18 @@ impl UserEmailMutations {
             return Ok(RemoveEmailPayload::IncorrectPassword);
         }
 
-        // TODO: don't allow removing the last email address
+        // When email changes have to be confirmed from a previous address, users
+        // could get around it by removing their only address before adding a
+        // new one, so they can't remove their last address
+        if !requester.is_admin() && state.site_config().email_change_double_confirmation {
+            let count = repo
+                .user_email()
+                .count(UserEmailFilter::new().for_user(&user))
+                .await?;
+            if count <= 1 {
+                return Ok(RemoveEmailPayload::LastEmail);
+            }
+        }
 
         repo.user_email().remove(user_email.clone()).await?;
 
@@ -584,7 +634,7 @@ impl UserEmailMutations {
 
         repo.save().await?;
 
-        Ok(SetPrimaryEmailPayload::Set(user))
+        Ok(SetPrimaryEmailPayload::Set(Box::new(user)))
     }
 
     /// Start a new email authentication flow
@@ -634,6 +684,12 @@ impl UserEmailMutations {
 
         let mut repo = state.repository().await?;
 
+        // Adding an email address is a sensitive action, which requires a recent
+        // authentication of the browser session
+        if !recently_authenticated(&mut repo, &clock, state.site_config(), browser_session).await? {
+            return Ok(StartEmailAuthenticationPayload::ReauthenticationRequired);
+        }
+
         // Check if the email address is already in use by the same user
         // We don't report here if the email address is already in use by another user,
         // because we don't want to leak information about other users. We will do that
@@ -688,7 +744,8 @@ impl UserEmailMutations {
             .schedule_job(
                 &mut rng,
                 &clock,
-                SendEmailAuthenticationCodeJob::new(&authentication, input.language),
+                SendEmailAuthenticationCodeJob::new(&authentication, input.language)
+                    .for_user(&browser_session.user),
             )
             .await?;
 
@@ -753,7 +810,8 @@ impl UserEmailMutations {
             .schedule_job(
                 &mut rng,
                 &clock,
-                SendEmailAuthenticationCodeJob::new(&authentication, input.language),
+                SendEmailAuthenticationCodeJob::new(&authentication, input.language)
+                    .for_user(&browser_session.user),
             )
             .await?;
 
@@ -775,6 +833,9 @@ impl UserEmailMutations {
 
         let id = NodeType::UserEmailAuthentication.extract_ulid(&input.id)?;
 
+        // Validate the language
+        let _: DataLocale = input.language.parse()?;
+
         let Some(browser_session) = ctx.requester().browser_session() else {
             return Err(async_graphql::Error::new("Unauthorized"));
         };
@@ -833,6 +894,44 @@ impl UserEmailMutations {
             return Ok(CompleteEmailAuthenticationPayload::InUse);
         }
 
+        if state.site_config().email_change_double_confirmation {
+            // The oldest address of the user is the one they had first, and the
+            // one which has to confirm the change
+            let previous_email = repo
+                .user_email()
+                .all(&browser_session.user)
+                .await?
+                .into_iter()
+                .min_by_key(|email| email.created_at);
+
+            if let Some(previous_email) = previous_email {
+                let ticket = Alphanumeric.sample_string(&mut rng, 32);
+                let change = repo
+                    .user_email()
+                    .add_change(
+                        &mut rng,
+                        &clock,
+                        EMAIL_CHANGE_CONFIRMATION_TTL,
+                        &authentication,
+                        &previous_email,
+                        ticket,
+                    )
+                    .await?;
+
+                repo.queue_job()
+                    .schedule_job(
+                        &mut rng,
+                        &clock,
+                        SendEmailChangeConfirmationJob::new(&change, input.language),
+                    )
+                    .await?;
+
+                repo.save().await?;
+
+                return Ok(CompleteEmailAuthenticationPayload::PendingConfirmation);
+            }
+        }
+
         repo.user_email()
             .add(
                 &mut rng,
@@ -4,17 +4,28 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use async_graphql::{Context, ID, Object, Union};
+use anyhow::Context as _;
+use async_graphql::{
+    Context, ID, Object, Union,
+    connection::{Connection, Edge, OpaqueCursor, query},
+};
+use chrono::{DateTime, Utc};
+use ipnetwork::IpNetwork;
 use mas_data_model::Device;
 use mas_storage::{
     Pagination, RepositoryAccess,
+    app_session::AppSessionFilter,
     compat::{CompatSessionFilter, CompatSessionRepository},
     oauth2::OAuth2SessionFilter,
 };
 
 use crate::graphql::{
     UserId,
-    model::{CompatSession, NodeType, OAuth2Session},
+    loaders::{wants_oauth2_clients, wants_users},
+    model::{
+        AppSession, CompatSession, Cursor, NodeCursor, NodeType, OAuth2Session,
+        PreloadedTotalCount, SessionState,
+    },
     state::ContextExt,
 };
 
@@ -43,7 +54,10 @@ impl SessionQuery {
             return Ok(None);
         }
 
-        let device = Device::from(device_id);
+        // No session can exist with an invalid device ID
+        let Ok(device) = Device::try_from(device_id) else {
+            return Ok(None);
+        };
         let state = ctx.state();
         let mut repo = state.repository().await?;
 
@@ -103,4 +117,192 @@ impl SessionQuery {
 
         Ok(None)
     }
+
+    /// Search both compat and OAuth 2.0 sessions across all users,
+    /// chronologically sorted.
+    ///
+    /// This is only available to administrators.
+    #[allow(clippy::too_many_arguments)]
+    async fn sessions(
+        &self,
+        ctx: &Context<'_>,
+
+        #[graphql(name = "user", desc = "List only sessions of the given user.")]
+        user_param: Option<ID>,
+
+        #[graphql(
+            name = "client",
+            desc = "List only OAuth 2.0 sessions of the given client. Compat sessions are never returned with this filter."
+        )]
+        client_param: Option<ID>,
+
+        #[graphql(
+            name = "ip",
+            desc = "List only sessions last active from an IP address in the given network, either a single address or a CIDR range like `192.0.2.0/24`."
+        )]
+        ip_param: Option<String>,
+
+        #[graphql(name = "state", desc = "List only sessions in the given state.")]
+        state_param: Option<SessionState>,
+
+        #[graphql(
+            name = "createdAfter",
+            desc = "List only sessions created after the given time."
+        )]
+        created_after_param: Option<DateTime<Utc>>,
+
+        #[graphql(desc = "Returns the elements in the list that come after the cursor.")]
+        after: Option<String>,
+        #[graphql(desc = "Returns the elements in the list that come before the cursor.")]
+        before: Option<String>,
+        #[graphql(desc = "Returns the first *n* elements from the list.")] first: Option<i32>,
+        #[graphql(desc = "Returns the last *n* elements from the list.")] last: Option<i32>,
+    ) -> Result<Connection<Cursor, AppSession, PreloadedTotalCount>, async_graphql::Error> {
+        let requester = ctx.requester();
+        if !requester.is_admin() {
+            return Err(async_graphql::Error::new("Unauthorized"));
+        }
+
+        let network = ip_param
+            .map(|ip| ip.parse::<IpNetwork>())
+            .transpose()
+            .context("Invalid ip parameter")?;
+
+        let state = ctx.state();
+        let mut repo = state.repository().await?;
+
+        query(
+            after,
+            before,
+            first,
+            last,
+            async |after, before, first, last| {
+                let after_id = after
+                    .map(|x: OpaqueCursor<NodeCursor>| {
+                        x.extract_for_types(&[NodeType::OAuth2Session, NodeType::CompatSession])
+                    })
+                    .transpose()?;
+                let before_id = before
+                    .map(|x: OpaqueCursor<NodeCursor>| {
+                        x.extract_for_types(&[NodeType::OAuth2Session, NodeType::CompatSession])
+                    })
+                    .transpose()?;
+                let pagination = Pagination::try_new(before_id, after_id, first, last)?;
+
+                let user = match user_param {
+                    Some(id) => {
+                        let id = NodeType::User.extract_ulid(&id)?;
+                        let Some(user) = repo.user().lookup(id).await? else {
+                            // If we couldn't find the user, return an empty list
+                            return Ok(Connection::with_additional_fields(
+                                false,
+                                false,
+                                PreloadedTotalCount(Some(0)),
+                            ));
+                        };
+                        Some(user)
+                    }
+                    None => None,
+                };
+
+                let client = match client_param {
+                    Some(id) => {
+                        let id = NodeType::OAuth2Client.extract_ulid(&id)?;
+                        let Some(client) = repo.oauth2_client().lookup(id).await? else {
+                            // If we couldn't find the client, return an empty list
+                            return Ok(Connection::with_additional_fields(
+                                false,
+                                false,
+                                PreloadedTotalCount(Some(0)),
+                            ));
+                        };
+                        Some(client)
+                    }
+                    None => None,
+                };
+
+                let filter = AppSessionFilter::new();
+
+                let filter = match user.as_ref() {
+                    Some(user) => filter.for_user(user),
+                    None => filter,
+                };
+
+                let filter = match client.as_ref() {
+                    Some(client) => filter.for_client(client),
+                    None => filter,
+                };
+
+                let filter = match network {
+                    Some(network) => filter.with_last_active_ip_in(network),
+                    None => filter,
+                };
+
+                let filter = match state_param {
+                    Some(SessionState::Active) => filter.active_only(),
+                    Some(SessionState::Finished) => filter.finished_only(),
+                    None => filter,
+                };
+
+                let filter = match created_after_param {
+                    Some(created_after) => filter.with_created_after(created_after),
+                    None => filter,
+                };
+
+                let page = repo.app_session().list(filter, pagination).await?;
+
+                let count = if ctx.look_ahead().field("totalCount").exists() {
+                    Some(repo.app_session().count(filter).await?)
+                } else {
+                    None
+                };
+
+                if wants_oauth2_clients(ctx) {
+                    let client_ids = page.edges.iter().filter_map(|edge| match &edge.node {
+                        mas_storage::app_session::AppSession::OAuth2(session) => {
+                            Some(session.client_id)
+                        }
+                        mas_storage::app_session::AppSession::Compat(_) => None,
+                    });
+                    ctx.oauth2_client_loader()
+                        .prime(&mut repo, client_ids)
+                        .await?;
+                }
+
+                if wants_users(ctx) {
+                    let user_ids = page.edges.iter().filter_map(|edge| match &edge.node {
+                        mas_storage::app_session::AppSession::OAuth2(session) => session.user_id,
+                        mas_storage::app_session::AppSession::Compat(session) => {
+                            Some(session.user_id)
+                        }
+                    });
+                    ctx.user_loader().prime(&mut repo, user_ids).await?;
+                }
+
+                repo.cancel().await?;
+
+                let mut connection = Connection::with_additional_fields(
+                    page.has_previous_page,
+                    page.has_next_page,
+                    PreloadedTotalCount(count),
+                );
+
+                connection
+                    .edges
+                    .extend(page.edges.into_iter().map(|edge| match edge.node {
+                        mas_storage::app_session::AppSession::Compat(session) => Edge::new(
+                            OpaqueCursor(NodeCursor(NodeType::CompatSession, edge.cursor)),
+                            AppSession::CompatSession(Box::new(CompatSession::new(*session))),
+                        ),
+                        mas_storage::app_session::AppSession::OAuth2(session) => Edge::new(
+                            OpaqueCursor(NodeCursor(NodeType::OAuth2Session, edge.cursor)),
+                            AppSession::OAuth2Session(Box::new(OAuth2Session(*session))),
+                        ),
+                    }));
+
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
 }
@@ -4,14 +4,24 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::sync::Arc;
+
 use async_graphql::{Response, ServerError};
-use mas_data_model::{BoxClock, BoxRng, SiteConfig};
+use mas_data_model::{BoxClock, BoxRng, SiteConfig, TchapConfig};
+use mas_i18n::Translator;
 use mas_matrix::HomeserverConnection;
 use mas_policy::Policy;
 use mas_router::UrlBuilder;
 use mas_storage::{BoxRepository, RepositoryError};
 
-use crate::{Limiter, graphql::Requester, passwords::PasswordManager};
+use crate::{
+    Limiter,
+    graphql::{
+        Requester,
+        loaders::{OAuth2ClientLoader, UpstreamOAuthProviderLoader, UserLoader},
+    },
+    passwords::PasswordManager,
+};
 
 const CLEAR_SESSION_SENTINEL: &str = "__CLEAR_SESSION__";
 
@@ -26,6 +36,10 @@ pub trait State {
     fn site_config(&self) -> &SiteConfig;
     fn url_builder(&self) -> &UrlBuilder;
     fn limiter(&self) -> &Limiter;
+    fn translator(&self) -> Arc<Translator>;
+    //:tchap:
+    fn tchap_config(&self) -> &TchapConfig;
+    //:tchap:end
 }
 
 pub type BoxState = Box<dyn State + Send + Sync + 'static>;
@@ -36,6 +50,12 @@ pub trait ContextExt {
     fn mark_session_ended(&self);
 
     fn requester(&self) -> &Requester;
+
+    fn oauth2_client_loader(&self) -> &OAuth2ClientLoader;
+
+    fn user_loader(&self) -> &UserLoader;
+
+    fn upstream_oauth_provider_loader(&self) -> &UpstreamOAuthProviderLoader;
 }
 
 impl ContextExt for async_graphql::Context<'_> {
@@ -54,6 +74,18 @@ impl ContextExt for async_graphql::Context<'_> {
     fn requester(&self) -> &Requester {
         self.data_unchecked()
     }
+
+    fn oauth2_client_loader(&self) -> &OAuth2ClientLoader {
+        self.data_unchecked()
+    }
+
+    fn user_loader(&self) -> &UserLoader {
+        self.data_unchecked()
+    }
+
+    fn upstream_oauth_provider_loader(&self) -> &UpstreamOAuthProviderLoader {
+        self.data_unchecked()
+    }
 }
 
 /// Returns true if the response contains a sentinel error indicating that the
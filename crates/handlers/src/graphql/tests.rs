@@ -4,15 +4,29 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
 use axum::http::Request;
+use chrono::Duration;
 use hyper::StatusCode;
 use mas_axum_utils::SessionInfoExt;
-use mas_data_model::{AccessToken, Client, TokenType, User};
+use mas_data_model::{
+    AccessToken, BrowserSessionLifetimeConfig, Client, Clock, Device, SiteConfig, TokenType,
+    UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderDiscoveryMode,
+    UpstreamOAuthProviderOnBackchannelLogout, UpstreamOAuthProviderPkceMode,
+    UpstreamOAuthProviderTokenAuthMethod, User,
+};
+use mas_iana::jose::JsonWebSignatureAlg;
 use mas_matrix::{HomeserverConnection, ProvisionRequest};
 use mas_router::SimpleRoute;
 use mas_storage::{
     RepositoryAccess,
     oauth2::{OAuth2AccessTokenRepository, OAuth2ClientRepository},
+    upstream_oauth2::UpstreamOAuthProviderParams,
+    user::UserNoteRepository,
 };
 use oauth2_types::{
     registration::ClientRegistrationResponse,
@@ -20,44 +34,16 @@ use oauth2_types::{
     scope::{OPENID, Scope, ScopeToken},
 };
 use sqlx::PgPool;
+use tracing_subscriber::layer::SubscriberExt;
 use zeroize::Zeroizing;
 
-use crate::test_utils::{self, CookieHelper, RequestBuilderExt, ResponseExt, TestState, setup};
-
-async fn create_test_client(state: &TestState) -> Client {
-    let mut repo = state.repository().await.unwrap();
-    let mut rng = state.rng();
-
-    let client = repo
-        .oauth2_client()
-        .add(
-            &mut rng,
-            &state.clock,
-            vec![],
-            None,
-            None,
-            None,
-            vec![],
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-            None,
-        )
-        .await
-        .unwrap();
-
-    repo.save().await.unwrap();
-
-    client
-}
+use super::{
+    Requester, RequestingEntity,
+    loaders::{OAuth2ClientLoader, UpstreamOAuthProviderLoader, UserLoader},
+};
+use crate::test_utils::{
+    self, CookieHelper, RequestBuilderExt, ResponseExt, TestState, create_test_client, setup,
+};
 
 async fn create_test_user<U: Into<String> + Send>(state: &TestState, username: U) -> User {
     let username = username.into();
@@ -222,6 +208,76 @@ async fn test_oauth2_viewer(pool: PgPool) {
     );
 }
 
+/// Test that the notes left by administrators on a user are not exposed
+/// through the GraphQL API.
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_user_notes_not_exposed(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let client = create_test_client(&state).await;
+    let user = create_test_user(&state, "alice").await;
+
+    let mut repo = state.repository().await.unwrap();
+    repo.user_note()
+        .add(
+            &mut state.rng(),
+            &state.clock,
+            &user,
+            None,
+            "Some private note".to_owned(),
+        )
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let access_token =
+        start_oauth_session(&state, &client, &user, Scope::from_iter([GRAPHQL])).await;
+    let access_token = access_token.access_token;
+
+    for field in ["notes", "latestNote"] {
+        let req = Request::post("/graphql")
+            .bearer(&access_token)
+            .json(serde_json::json!({
+                "query": format!("query {{ viewer {{ ... on User {{ id {field} }} }} }}"),
+            }));
+
+        let response = state.request(req).await;
+        response.assert_status(StatusCode::OK);
+        let response: GraphQLResponse = response.json();
+
+        assert_eq!(response.data, serde_json::json!(null));
+        assert_eq!(response.errors.len(), 1);
+        let message = response.errors[0]["message"].as_str().unwrap();
+        assert!(
+            message.contains(&format!("Unknown field \"{field}\"")),
+            "{message}"
+        );
+    }
+
+    // The note does not leak through any other field of the user either
+    let req = Request::post("/graphql")
+        .bearer(&access_token)
+        .json(serde_json::json!({
+            "query": r"
+                query {
+                    viewer {
+                        ... on User {
+                            id
+                            username
+                            createdAt
+                        }
+                    }
+                }
+            ",
+        }));
+    let response = state.request(req).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert!(!response.data.to_string().contains("Some private note"));
+}
+
 /// Test that the GraphQL endpoint requires the GraphQL scope.
 #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
 async fn test_oauth2_no_scope(pool: PgPool) {
@@ -926,6 +982,114 @@ async fn test_start_email_authentication_rejected_wrong_password(pool: PgPool) {
     );
 }
 
+/// Test the completeEmailAuthentication mutation when email changes have to
+/// be confirmed from the previous address
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_complete_email_authentication_double_confirmation(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool_with_site_config(
+        pool,
+        SiteConfig {
+            email_change_double_confirmation: true,
+            ..test_utils::test_site_config()
+        },
+    )
+    .await
+    .unwrap();
+
+    let mut rng = state.rng();
+    let mut repo = state.repository().await.unwrap();
+    let user = repo
+        .user()
+        .add(&mut rng, &state.clock, "alice".to_owned())
+        .await
+        .unwrap();
+    repo.user_email()
+        .add(
+            &mut rng,
+            &state.clock,
+            &user,
+            "alice@example.com".to_owned(),
+        )
+        .await
+        .unwrap();
+    let browser_session = repo
+        .browser_session()
+        .add(&mut rng, &state.clock, &user, None)
+        .await
+        .unwrap();
+    let authentication = repo
+        .user_email()
+        .add_authentication_for_session(
+            &mut rng,
+            &state.clock,
+            "alice@example.org".to_owned(),
+            &browser_session,
+        )
+        .await
+        .unwrap();
+    repo.user_email()
+        .add_authentication_code(
+            &mut rng,
+            &state.clock,
+            Duration::minutes(5),
+            &authentication,
+            "123456".to_owned(),
+        )
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let cookie_jar = state.cookie_jar();
+    let cookie_jar = cookie_jar.set_session(&browser_session);
+
+    let request = Request::post("/graphql").json(serde_json::json!({
+        "query": r#"
+            mutation CompleteEmailAuthentication($id: ID!) {
+                completeEmailAuthentication(input: {
+                    id: $id,
+                    code: "123456"
+                }) {
+                    status
+                }
+            }
+        "#,
+        "variables": {
+            "id": format!("user_email_authentication:{}", authentication.id),
+        },
+    }));
+
+    let cookies = CookieHelper::new();
+    cookies.import(cookie_jar);
+    let request = cookies.with_cookies(request);
+
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data["completeEmailAuthentication"]["status"].as_str(),
+        Some("PENDING_CONFIRMATION"),
+        "{:?}",
+        response.data
+    );
+
+    // The new address isn't added yet, but a change waits to be confirmed from
+    // the previous one
+    let mut repo = state.repository().await.unwrap();
+    let emails = repo.user_email().all(&user).await.unwrap();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].email, "alice@example.com");
+
+    let authentication = repo
+        .user_email()
+        .lookup_authentication(authentication.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(authentication.completed_at.is_some());
+}
+
 /// Test the removeEmail mutation where the current password
 /// provided is invalid.
 #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
@@ -1078,3 +1242,1293 @@ async fn test_deactivate_user_rejected_wrong_password(pool: PgPool) {
         response.data
     );
 }
+
+/// Test the scheduleDeactivation mutation: the user is locked right away, and
+/// deactivated once the grace period is over.
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_schedule_deactivation(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool_with_site_config(
+        pool.clone(),
+        SiteConfig {
+            browser_session_lifetime: BrowserSessionLifetimeConfig {
+                reauthentication_max_age: Some(Duration::minutes(5)),
+                ..BrowserSessionLifetimeConfig::default()
+            },
+            ..test_utils::test_site_config()
+        },
+    )
+    .await
+    .unwrap();
+
+    let mut rng = state.rng();
+    let user = create_test_user(&state, "alice").await;
+    state
+        .homeserver_connection
+        .provision_user(&ProvisionRequest::new(&user.username, &user.sub))
+        .await
+        .unwrap();
+    let mut repo = state.repository().await.unwrap();
+    let stale_session = repo
+        .browser_session()
+        .add(&mut rng, &state.clock, &user, None)
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let schedule = async |browser_session: &mas_data_model::BrowserSession, username: &str| {
+        let cookie_jar = state.cookie_jar();
+        let cookie_jar = cookie_jar.set_session(browser_session);
+        let cookies = CookieHelper::new();
+        cookies.import(cookie_jar);
+
+        let request = Request::post("/graphql").json(serde_json::json!({
+            "query": r"
+                mutation ScheduleDeactivation($username: String!) {
+                    scheduleDeactivation(input: { username: $username, hsErase: false }) {
+                        status
+                        scheduledAt
+                    }
+                }
+            ",
+            "variables": {
+                "username": username,
+            },
+        }));
+        let request = cookies.with_cookies(request);
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: GraphQLResponse = response.json();
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data["scheduleDeactivation"].clone()
+    };
+
+    // The session wasn't authenticated recently enough
+    state.clock.advance(Duration::minutes(10));
+    let payload = schedule(&stale_session, "alice").await;
+    assert_eq!(payload["status"], "REAUTHENTICATION_REQUIRED");
+
+    let mut repo = state.repository().await.unwrap();
+    let browser_session = repo
+        .browser_session()
+        .add(&mut rng, &state.clock, &user, None)
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    // The username must match exactly
+    let payload = schedule(&browser_session, "Alice").await;
+    assert_eq!(payload["status"], "USERNAME_MISMATCH");
+
+    let mut repo = state.repository().await.unwrap();
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    repo.save().await.unwrap();
+    assert!(user.is_valid());
+
+    let payload = schedule(&browser_session, "alice").await;
+    assert_eq!(payload["status"], "SCHEDULED");
+    let scheduled_at = state.clock.now() + Duration::days(7);
+    assert_eq!(
+        payload["scheduledAt"]
+            .as_str()
+            .unwrap()
+            .parse::<chrono::DateTime<chrono::Utc>>()
+            .unwrap(),
+        scheduled_at
+    );
+
+    // The user is locked right away, but not deactivated yet
+    let mut repo = state.repository().await.unwrap();
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    repo.save().await.unwrap();
+    assert!(user.locked_at.is_some());
+    assert!(user.deactivated_at.is_none());
+    assert_eq!(user.deactivation_scheduled_at, Some(scheduled_at));
+
+    // A confirmation email was queued
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM queue_jobs WHERE queue_name = 'send-deactivation-scheduled-email'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(count, 1);
+
+    // Nothing happens during the grace period
+    state.clock.advance(Duration::days(6));
+    state.run_jobs_in_queue().await;
+    state.run_jobs_in_queue().await;
+
+    let mut repo = state.repository().await.unwrap();
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    repo.save().await.unwrap();
+    assert!(user.deactivated_at.is_none());
+
+    // Once it is over, the user is deactivated
+    state.clock.advance(Duration::days(1));
+    state.run_jobs_in_queue().await;
+    state.run_jobs_in_queue().await;
+
+    let mut repo = state.repository().await.unwrap();
+    let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+    repo.save().await.unwrap();
+    assert!(user.deactivated_at.is_some());
+    assert!(user.deactivation_scheduled_at.is_none());
+}
+
+/// Test the setPreferredLanguage mutation, and that the stored language takes
+/// precedence over the one negotiated with the browser.
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_set_preferred_language(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let mut rng = state.rng();
+    let mut repo = state.repository().await.unwrap();
+    let user = repo
+        .user()
+        .add(&mut rng, &state.clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let browser_session = repo
+        .browser_session()
+        .add(&mut rng, &state.clock, &user, None)
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let cookie_jar = state.cookie_jar();
+    let cookie_jar = cookie_jar.set_session(&browser_session);
+    let cookies = CookieHelper::new();
+    cookies.import(cookie_jar);
+
+    let set_language = async |language: &str| {
+        let request = Request::post("/graphql").json(serde_json::json!({
+            "query": r"
+                mutation SetPreferredLanguage($language: String) {
+                    setPreferredLanguage(input: { language: $language }) {
+                        status
+                        user {
+                            preferredLanguage
+                        }
+                    }
+                }
+            ",
+            "variables": {
+                "language": language,
+            },
+        }));
+        let request = cookies.with_cookies(request);
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: GraphQLResponse = response.json();
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data["setPreferredLanguage"].clone()
+    };
+
+    // Languages which are not valid or not available are rejected
+    let payload = set_language("not a language").await;
+    assert_eq!(payload["status"], "INVALID_LANGUAGE");
+    let payload = set_language("tlh").await;
+    assert_eq!(payload["status"], "INVALID_LANGUAGE");
+
+    let payload = set_language("fr").await;
+    assert_eq!(payload["status"], "SET");
+    assert_eq!(payload["user"]["preferredLanguage"], "fr");
+
+    // Pages are now rendered in French, even if the browser asks for English
+    let request = Request::get(mas_router::Index::PATH)
+        .header("Accept-Language", "en")
+        .empty();
+    let request = cookies.with_cookies(request);
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    assert!(response.body().contains(r#"<html lang="fr">"#));
+
+    // Without a session, the language is still negotiated with the browser
+    let request = Request::get(mas_router::Index::PATH)
+        .header("Accept-Language", "en")
+        .empty();
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    assert!(response.body().contains(r#"<html lang="en">"#));
+}
+
+/// Test that the session events only contain what happened since the cursor.
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_app_session_events(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let client = create_test_client(&state).await;
+    let user = create_test_user(&state, "alice").await;
+    let access_token =
+        start_oauth_session(&state, &client, &user, Scope::from_iter([GRAPHQL])).await;
+    let access_token = access_token.access_token;
+
+    let query = r"
+        query ($since: String) {
+            viewer {
+                ... on User {
+                    appSessionEvents(since: $since) {
+                        cursor
+                        truncated
+                        events {
+                            kind
+                            session {
+                                __typename
+                                ... on CompatSession {
+                                    id
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    ";
+
+    // Without a cursor, we only get a cursor back
+    let req = Request::post("/graphql")
+        .bearer(&access_token)
+        .json(serde_json::json!({ "query": query }));
+    let response = state.request(req).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let events = &response.data["viewer"]["appSessionEvents"];
+    assert_eq!(events["events"], serde_json::json!([]));
+    let cursor = events["cursor"].as_str().unwrap().to_owned();
+
+    // Create a compat session, then end it
+    state.clock.advance(Duration::try_minutes(1).unwrap());
+    let mut rng = state.rng();
+    let device = Device::generate(&mut rng);
+    let mut repo = state.repository().await.unwrap();
+    let session = repo
+        .compat_session()
+        .add(&mut rng, &state.clock, &user, device, None, false, None)
+        .await
+        .unwrap();
+    state.clock.advance(Duration::try_minutes(1).unwrap());
+    let session = repo
+        .compat_session()
+        .finish(&state.clock, session)
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let req = Request::post("/graphql")
+        .bearer(&access_token)
+        .json(serde_json::json!({
+            "query": query,
+            "variables": { "since": cursor },
+        }));
+    let response = state.request(req).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let events = &response.data["viewer"]["appSessionEvents"];
+    assert_eq!(events["truncated"], false);
+    let session = serde_json::json!({
+        "__typename": "CompatSession",
+        "id": format!("compat_session:{}", session.id),
+    });
+    assert_eq!(
+        events["events"],
+        serde_json::json!([
+            { "kind": "CREATED", "session": session },
+            { "kind": "ENDED", "session": session },
+        ])
+    );
+
+    // Nothing happened since the last call
+    let cursor = events["cursor"].as_str().unwrap().to_owned();
+    state.clock.advance(Duration::try_minutes(1).unwrap());
+    let req = Request::post("/graphql")
+        .bearer(&access_token)
+        .json(serde_json::json!({
+            "query": query,
+            "variables": { "since": cursor },
+        }));
+    let response = state.request(req).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data["viewer"]["appSessionEvents"]["events"],
+        serde_json::json!([])
+    );
+}
+
+/// Test that the clients of a page of OAuth 2.0 sessions are loaded in a single
+/// query, and that their details are only visible to the owner of the sessions
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_oauth2_session_client_details(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let alice = create_test_user(&state, "alice").await;
+    let bob = create_test_user(&state, "bob").await;
+
+    // Create three clients, and four sessions for Alice across them
+    let mut repo = state.repository().await.unwrap();
+    let mut rng = state.rng();
+    let mut clients = Vec::new();
+    for name in ["Element", "FluffyChat", "Nheko"] {
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &state.clock,
+                vec![],
+                None,
+                None,
+                None,
+                vec![],
+                Some(name.to_owned()),
+                Some(
+                    format!("https://{name}.example.com/logo.png")
+                        .parse()
+                        .unwrap(),
+                ),
+                None,
+                None,
+                Some(format!("https://{name}.example.com/tos").parse().unwrap()),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        clients.push(client);
+    }
+
+    let alice_browser_session = repo
+        .browser_session()
+        .add(&mut rng, &state.clock, &alice, None)
+        .await
+        .unwrap();
+    let bob_browser_session = repo
+        .browser_session()
+        .add(&mut rng, &state.clock, &bob, None)
+        .await
+        .unwrap();
+
+    let sessions = [
+        (&clients[0], "openid urn:mas:graphql:*"),
+        (
+            &clients[1],
+            "openid urn:matrix:client:api:* urn:matrix:client:device:ABCDEF",
+        ),
+        (&clients[2], "urn:synapse:admin:* custom"),
+        (&clients[0], "openid"),
+    ];
+    for (client, scope) in sessions {
+        state.clock.advance(Duration::try_minutes(1).unwrap());
+        repo.oauth2_session()
+            .add_from_browser_session(
+                &mut rng,
+                &state.clock,
+                client,
+                &alice_browser_session,
+                scope.parse().unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+    let admin_session = repo
+        .oauth2_session()
+        .add_from_browser_session(
+            &mut rng,
+            &state.clock,
+            &clients[0],
+            &bob_browser_session,
+            Scope::from_iter([GRAPHQL, ADMIN]),
+        )
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let query = r"
+        query ($id: ID!) {
+            user(id: $id) {
+                oauth2Sessions(first: 10) {
+                    edges {
+                        node {
+                            clientName
+                            clientLogoUri
+                            clientTosUri
+                            client { clientId }
+                            scopes { scope kind dangerous }
+                        }
+                    }
+                }
+            }
+        }
+    ";
+    let variables = async_graphql::Variables::from_json(serde_json::json!({
+        "id": format!("user:{}", alice.id),
+    }));
+
+    let run = |entity: RequestingEntity| {
+        let loader = OAuth2ClientLoader::default();
+        let request = async_graphql::Request::new(query)
+            .variables(variables.clone())
+            .data(Requester {
+                entity,
+                ip_address: None,
+                user_agent: None,
+            })
+            .data(loader.clone())
+            .data(UserLoader::default())
+            .data(UpstreamOAuthProviderLoader::default());
+        let schema = state.graphql_schema.clone();
+        async move { (schema.execute(request).await, loader) }
+    };
+
+    // Alice sees the details of her sessions, with a single query for the
+    // clients of the whole page
+    let (response, loader) = run(alice_browser_session.into()).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(loader.queries(), 1);
+
+    let data = response.data.into_json().unwrap();
+    let edges = data["user"]["oauth2Sessions"]["edges"].as_array().unwrap();
+    assert_eq!(edges.len(), 4);
+    let names: Vec<_> = edges
+        .iter()
+        .map(|edge| edge["node"]["clientName"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["Element", "FluffyChat", "Nheko", "Element"]);
+    assert_eq!(
+        edges[1]["node"]["clientLogoUri"],
+        "https://fluffychat.example.com/logo.png"
+    );
+    assert_eq!(
+        edges[1]["node"]["clientTosUri"],
+        "https://fluffychat.example.com/tos"
+    );
+    assert_eq!(
+        edges[1]["node"]["client"]["clientId"],
+        clients[1].client_id.as_str()
+    );
+    assert_eq!(
+        edges[1]["node"]["scopes"],
+        serde_json::json!([
+            { "scope": "openid", "kind": "OPENID", "dangerous": false },
+            { "scope": "urn:matrix:client:api:*", "kind": "MATRIX_CLIENT_API", "dangerous": false },
+            { "scope": "urn:matrix:client:device:ABCDEF", "kind": "MATRIX_DEVICE", "dangerous": false },
+        ])
+    );
+    assert_eq!(
+        edges[2]["node"]["scopes"],
+        serde_json::json!([
+            { "scope": "custom", "kind": "OTHER", "dangerous": false },
+            { "scope": "urn:synapse:admin:*", "kind": "SYNAPSE_ADMIN", "dangerous": true },
+        ])
+    );
+
+    // Bob can't see Alice at all
+    let (response, _loader) = run(bob_browser_session.into()).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap(),
+        serde_json::json!({ "user": null })
+    );
+
+    // Unless they use an admin session
+    let (response, loader) = run(RequestingEntity::OAuth2Session(Box::new((
+        admin_session,
+        Some(bob),
+    ))))
+    .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(loader.queries(), 1);
+    let data = response.data.into_json().unwrap();
+    let edges = data["user"]["oauth2Sessions"]["edges"].as_array().unwrap();
+    assert_eq!(edges.len(), 4);
+    assert_eq!(edges[2]["node"]["clientName"], "Nheko");
+}
+
+/// Counts the spans created on the current thread by name, to see which
+/// repository methods were called
+#[derive(Clone, Default)]
+struct SpanCounter(Arc<Mutex<HashMap<&'static str, usize>>>);
+
+impl SpanCounter {
+    fn count(&self, name: &str) -> usize {
+        self.0
+            .lock()
+            .unwrap()
+            .get(name)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanCounter {
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        _id: &tracing::span::Id,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        *self
+            .0
+            .lock()
+            .unwrap()
+            .entry(attrs.metadata().name())
+            .or_default() += 1;
+    }
+}
+
+/// Test that the users of a page of application sessions are loaded in a
+/// single query, instead of one query per session
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_app_sessions_batch_user_lookups(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let alice = create_test_user(&state, "alice").await;
+    let bob = create_test_user(&state, "bob").await;
+    let carol = create_test_user(&state, "carol").await;
+    let users = [&alice, &bob, &carol];
+    let client = create_test_client(&state).await;
+
+    // Create 50 sessions spread over the three users, half of them compat
+    // sessions and half of them OAuth 2.0 sessions
+    let mut repo = state.repository().await.unwrap();
+    let mut rng = state.rng();
+    for i in 0..50 {
+        let user = users[i % users.len()];
+        state.clock.advance(Duration::try_minutes(1).unwrap());
+        if i % 2 == 0 {
+            let device = Device::generate(&mut rng);
+            repo.compat_session()
+                .add(&mut rng, &state.clock, user, device, None, false, None)
+                .await
+                .unwrap();
+        } else {
+            repo.oauth2_session()
+                .add(
+                    &mut rng,
+                    &state.clock,
+                    &client,
+                    Some(user),
+                    None,
+                    Scope::from_iter([OPENID]),
+                )
+                .await
+                .unwrap();
+        }
+    }
+    let admin_session = repo
+        .oauth2_session()
+        .add(
+            &mut rng,
+            &state.clock,
+            &client,
+            Some(&alice),
+            None,
+            Scope::from_iter([GRAPHQL, ADMIN]),
+        )
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let query = r"
+        query {
+            sessions(first: 50) {
+                edges {
+                    node {
+                        ... on CompatSession { user { id } }
+                        ... on Oauth2Session { user { id } }
+                    }
+                }
+            }
+        }
+    ";
+    let request = async_graphql::Request::new(query)
+        .data(Requester {
+            entity: RequestingEntity::OAuth2Session(Box::new((admin_session, Some(alice.clone())))),
+            ip_address: None,
+            user_agent: None,
+        })
+        .data(OAuth2ClientLoader::default())
+        .data(UserLoader::default())
+        .data(UpstreamOAuthProviderLoader::default());
+
+    // Record which repository methods get called while resolving the query
+    let counter = SpanCounter::default();
+    let subscriber = tracing_subscriber::registry().with(counter.clone());
+    let response = {
+        let _guard = tracing::subscriber::set_default(subscriber);
+        state.graphql_schema.execute(request).await
+    };
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let data = response.data.into_json().unwrap();
+    let edges = data["sessions"]["edges"].as_array().unwrap();
+    assert_eq!(edges.len(), 50);
+    for user in users {
+        let id = format!("user:{}", user.id);
+        assert!(edges.iter().any(|edge| edge["node"]["user"]["id"] == id));
+    }
+
+    // All the user lookups were collapsed into a single batched query
+    assert_eq!(counter.count("db.user.load_batch"), 1);
+    assert_eq!(counter.count("db.user.lookup"), 0);
+}
+
+/// Test the admin-only `sessions` query, which searches sessions across users
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_admin_sessions(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let client = create_test_client(&state).await;
+    let alice = create_test_user(&state, "alice").await;
+    let bob = create_test_user(&state, "bob").await;
+
+    let access_token =
+        start_oauth_session(&state, &client, &alice, Scope::from_iter([GRAPHQL])).await;
+    let access_token = access_token.access_token;
+    let access_token_admin =
+        start_oauth_session(&state, &client, &alice, Scope::from_iter([GRAPHQL, ADMIN])).await;
+    let access_token_admin = access_token_admin.access_token;
+
+    // A compat session for Alice and an OAuth 2.0 session for Bob, both seen
+    // from the same network
+    let mut repo = state.repository().await.unwrap();
+    let mut rng = state.rng();
+    state.clock.advance(Duration::try_minutes(1).unwrap());
+    let device = Device::generate(&mut rng);
+    let compat_session = repo
+        .compat_session()
+        .add(&mut rng, &state.clock, &alice, device, None, false, None)
+        .await
+        .unwrap();
+    state.clock.advance(Duration::try_minutes(1).unwrap());
+    let oauth2_session = repo
+        .oauth2_session()
+        .add(
+            &mut rng,
+            &state.clock,
+            &client,
+            Some(&bob),
+            None,
+            Scope::from_iter([OPENID]),
+        )
+        .await
+        .unwrap();
+    repo.compat_session()
+        .record_batch_activity(vec![(
+            compat_session.id,
+            state.clock.now(),
+            Some([192, 0, 2, 10].into()),
+        )])
+        .await
+        .unwrap();
+    repo.oauth2_session()
+        .record_batch_activity(vec![(
+            oauth2_session.id,
+            state.clock.now(),
+            Some([192, 0, 2, 20].into()),
+        )])
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let query = r"
+        query ($ip: String, $client: ID) {
+            sessions(ip: $ip, client: $client, first: 10) {
+                totalCount
+                edges {
+                    node {
+                        __typename
+                        ... on CompatSession { id user { username } }
+                        ... on Oauth2Session { id user { username } }
+                    }
+                }
+            }
+        }
+    ";
+
+    let request = |token: &str, variables: serde_json::Value| {
+        Request::post("/graphql")
+            .bearer(token)
+            .json(serde_json::json!({
+                "query": query,
+                "variables": variables,
+            }))
+    };
+
+    // Regular users can't search sessions
+    let response = state
+        .request(request(
+            &access_token,
+            serde_json::json!({ "ip": "192.0.2.0/24" }),
+        ))
+        .await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(response.errors[0]["message"], "Unauthorized");
+
+    // Both kinds of sessions are returned, each with their own type
+    let response = state
+        .request(request(
+            &access_token_admin,
+            serde_json::json!({ "ip": "192.0.2.0/24" }),
+        ))
+        .await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data,
+        serde_json::json!({
+            "sessions": {
+                "totalCount": 2,
+                "edges": [
+                    {
+                        "node": {
+                            "__typename": "CompatSession",
+                            "id": format!("compat_session:{}", compat_session.id),
+                            "user": { "username": "alice" },
+                        },
+                    },
+                    {
+                        "node": {
+                            "__typename": "Oauth2Session",
+                            "id": format!("oauth2_session:{}", oauth2_session.id),
+                            "user": { "username": "bob" },
+                        },
+                    },
+                ],
+            },
+        })
+    );
+
+    // A single address only matches that address
+    let response = state
+        .request(request(
+            &access_token_admin,
+            serde_json::json!({ "ip": "192.0.2.20" }),
+        ))
+        .await;
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data["sessions"]["totalCount"], 1);
+    assert_eq!(
+        response.data["sessions"]["edges"][0]["node"]["__typename"],
+        "Oauth2Session"
+    );
+
+    // Filtering on a client leaves compat sessions out
+    let response = state
+        .request(request(
+            &access_token_admin,
+            serde_json::json!({
+                "ip": "192.0.2.0/24",
+                "client": format!("oauth2_client:{}", client.id),
+            }),
+        ))
+        .await;
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data["sessions"]["totalCount"], 1);
+    assert_eq!(
+        response.data["sessions"]["edges"][0]["node"]["id"],
+        format!("oauth2_session:{}", oauth2_session.id)
+    );
+
+    // Other networks don't match
+    let response = state
+        .request(request(
+            &access_token_admin,
+            serde_json::json!({ "ip": "198.51.100.0/24" }),
+        ))
+        .await;
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data["sessions"]["totalCount"], 0);
+
+    // Invalid networks are rejected
+    let response = state
+        .request(request(
+            &access_token_admin,
+            serde_json::json!({ "ip": "192.0.2.0/33" }),
+        ))
+        .await;
+    let response: GraphQLResponse = response.json();
+    assert_eq!(response.errors.len(), 1);
+}
+
+/// Start an account recovery for the given email address, returning the
+/// recovery ticket
+async fn start_recovery(state: &TestState, user_email: &mas_data_model::UserEmail) -> String {
+    let mut repo = state.repository().await.unwrap();
+    let mut rng = state.rng();
+
+    let session = repo
+        .user_recovery()
+        .add_session(
+            &mut rng,
+            &state.clock,
+            user_email.email.clone(),
+            "Mozilla/5.0".to_owned(),
+            None,
+            "en".to_owned(),
+        )
+        .await
+        .unwrap();
+
+    let token = TokenType::AccessToken.generate(&mut rng);
+    let ticket = repo
+        .user_recovery()
+        .add_ticket(&mut rng, &state.clock, &session, user_email, token)
+        .await
+        .unwrap();
+
+    repo.save().await.unwrap();
+
+    ticket.ticket
+}
+
+/// Test that recovering an account ends the other sessions of the user,
+/// unless they asked to keep them
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_set_password_by_recovery_ends_sessions(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+    let mut rng = state.rng();
+
+    let user = create_test_user(&state, "alice").await;
+    let mut repo = state.repository().await.unwrap();
+    let user_email = repo
+        .user_email()
+        .add(
+            &mut rng,
+            &state.clock,
+            &user,
+            "alice@example.com".to_owned(),
+        )
+        .await
+        .unwrap();
+
+    // Sessions opened by someone who took over the account
+    let device = Device::generate(&mut rng);
+    let attacker_compat_session = repo
+        .compat_session()
+        .add(&mut rng, &state.clock, &user, device, None, false, None)
+        .await
+        .unwrap();
+    let attacker_browser_session = repo
+        .browser_session()
+        .add(&mut rng, &state.clock, &user, None)
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let recover = async |ticket: String, keep_sessions: bool| {
+        let request = Request::post("/graphql").json(serde_json::json!({
+            "query": r#"
+                mutation RecoverPassword($ticket: String!, $keepSessions: Boolean!) {
+                    setPasswordByRecovery(input: {
+                        ticket: $ticket,
+                        newPassword: "new.password.123",
+                        keepSessions: $keepSessions
+                    }) {
+                        status
+                    }
+                }
+            "#,
+            "variables": {
+                "ticket": ticket,
+                "keepSessions": keep_sessions,
+            },
+        }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: GraphQLResponse = response.json();
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data["setPasswordByRecovery"]["status"].as_str(),
+            Some("ALLOWED"),
+            "{:?}",
+            response.data
+        );
+    };
+
+    // By default, all the sessions are ended
+    let ticket = start_recovery(&state, &user_email).await;
+    recover(ticket, false).await;
+
+    let mut repo = state.repository().await.unwrap();
+    let compat_session = repo
+        .compat_session()
+        .lookup(attacker_compat_session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(compat_session.is_finished());
+    let browser_session = repo
+        .browser_session()
+        .lookup(attacker_browser_session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(browser_session.finished_at.is_some());
+
+    // Open a new session, and recover again while keeping the sessions
+    let attacker_browser_session = repo
+        .browser_session()
+        .add(&mut rng, &state.clock, &user, None)
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let ticket = start_recovery(&state, &user_email).await;
+    recover(ticket, true).await;
+
+    let mut repo = state.repository().await.unwrap();
+    let browser_session = repo
+        .browser_session()
+        .lookup(attacker_browser_session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(browser_session.finished_at.is_none());
+}
+
+//:tchap:
+/// Test that the organization and the email domain label are exposed to the
+/// owner and to admins.
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_user_organization_and_email_domain_label(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+
+    let client = create_test_client(&state).await;
+    let alice = create_test_user(&state, "alice").await;
+    let bob = create_test_user(&state, "bob").await;
+
+    let mut repo = state.repository().await.unwrap();
+    let mut rng = state.rng();
+    let alice = repo
+        .user()
+        .set_organization(alice, Some("Ministère de l'Intérieur".to_owned()))
+        .await
+        .unwrap();
+    repo.user_email()
+        .add(
+            &mut rng,
+            &state.clock,
+            &alice,
+            "alice@numerique.gouv.fr".to_owned(),
+        )
+        .await
+        .unwrap();
+    repo.user_email()
+        .add(&mut rng, &state.clock, &bob, "bob@example.com".to_owned())
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let access_token =
+        start_oauth_session(&state, &client, &alice, Scope::from_iter([GRAPHQL])).await;
+    let access_token = access_token.access_token;
+
+    let request = Request::post("/graphql")
+        .bearer(&access_token)
+        .json(serde_json::json!({
+            "query": r"
+                query {
+                    viewer {
+                        ... on User {
+                            organization
+                            emailDomainLabel
+                        }
+                    }
+                }
+            ",
+        }));
+
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data,
+        serde_json::json!({
+            "viewer": {
+                "organization": "Ministère de l'Intérieur",
+                "emailDomainLabel": "DINUM",
+            },
+        })
+    );
+
+    // An admin can see them on other users
+    let access_token_admin =
+        start_oauth_session(&state, &client, &alice, Scope::from_iter([GRAPHQL, ADMIN])).await;
+    let access_token_admin = access_token_admin.access_token;
+
+    let request = Request::post("/graphql")
+        .bearer(&access_token_admin)
+        .json(serde_json::json!({
+            "query": r"
+                query UserQuery($id: ID) {
+                    user(id: $id) {
+                        organization
+                        emailDomainLabel
+                    }
+                }
+            ",
+            "variables": {
+                "id": format!("user:{id}", id = bob.id),
+            },
+        }));
+
+    let response = state.request(request).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data,
+        serde_json::json!({
+            "user": {
+                "organization": null,
+                "emailDomainLabel": null,
+            },
+        })
+    );
+}
+//:tchap:end
+
+/// Test the transferUpstreamOauthLink mutation
+#[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+async fn test_transfer_upstream_oauth_link(pool: PgPool) {
+    setup();
+    let state = TestState::from_pool(pool).await.unwrap();
+    let mut rng = state.rng();
+
+    let client = create_test_client(&state).await;
+    let admin = create_test_user(&state, "admin").await;
+    let access_token =
+        start_oauth_session(&state, &client, &admin, Scope::from_iter([GRAPHQL, ADMIN])).await;
+    let access_token = access_token.access_token;
+
+    let alice = create_test_user(&state, "alice").await;
+    let bob = create_test_user(&state, "bob").await;
+    let carol = create_test_user(&state, "carol").await;
+
+    let mut repo = state.repository().await.unwrap();
+    let provider = repo
+        .upstream_oauth_provider()
+        .add(
+            &mut rng,
+            &state.clock,
+            UpstreamOAuthProviderParams {
+                issuer: Some("https://example.com/".to_owned()),
+                human_name: Some("Example Ltd.".to_owned()),
+                brand_name: None,
+                scope: Scope::from_iter([OPENID]),
+                token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                token_endpoint_signing_alg: None,
+                id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                fetch_userinfo: false,
+                userinfo_signed_response_alg: None,
+                client_id: "client".to_owned(),
+                encrypted_client_secret: None,
+                claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+                authorization_endpoint_override: None,
+                token_endpoint_override: None,
+                userinfo_endpoint_override: None,
+                jwks_uri_override: None,
+                discovery_mode: UpstreamOAuthProviderDiscoveryMode::Oidc,
+                pkce_mode: UpstreamOAuthProviderPkceMode::Auto,
+                response_mode: None,
+                additional_authorization_parameters: Vec::new(),
+                forward_login_hint: false,
+                ui_order: 0,
+                ui_hidden: false,
+                on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+            },
+        )
+        .await
+        .unwrap();
+
+    // Alice and Carol both have a link with the provider
+    let alice_link = repo
+        .upstream_oauth_link()
+        .add(&mut rng, &state.clock, &provider, "alice".to_owned(), None)
+        .await
+        .unwrap();
+    repo.upstream_oauth_link()
+        .associate_to_user(&alice_link, &alice)
+        .await
+        .unwrap();
+    let carol_link = repo
+        .upstream_oauth_link()
+        .add(&mut rng, &state.clock, &provider, "carol".to_owned(), None)
+        .await
+        .unwrap();
+    repo.upstream_oauth_link()
+        .associate_to_user(&carol_link, &carol)
+        .await
+        .unwrap();
+
+    // Alice logged in through her link, and used that to log into a client
+    let upstream_session = repo
+        .upstream_oauth_session()
+        .add(
+            &mut rng,
+            &state.clock,
+            &provider,
+            "state".to_owned(),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let upstream_session = repo
+        .upstream_oauth_session()
+        .complete_with_link(
+            &state.clock,
+            upstream_session,
+            &alice_link,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let browser_session = repo
+        .browser_session()
+        .add(&mut rng, &state.clock, &alice, None)
+        .await
+        .unwrap();
+    repo.browser_session()
+        .authenticate_with_upstream(&mut rng, &state.clock, &browser_session, &upstream_session)
+        .await
+        .unwrap();
+    let oauth2_session = repo
+        .oauth2_session()
+        .add_from_browser_session(
+            &mut rng,
+            &state.clock,
+            &client,
+            &browser_session,
+            Scope::from_iter([OPENID]),
+        )
+        .await
+        .unwrap();
+
+    // Another session of Alice, which wasn't started through the link
+    let other_browser_session = repo
+        .browser_session()
+        .add(&mut rng, &state.clock, &alice, None)
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let transfer = |user: &User| {
+        Request::post("/graphql")
+            .bearer(&access_token)
+            .json(serde_json::json!({
+                "query": r"
+                    mutation TransferUpstreamOauthLink($linkId: ID!, $userId: ID!) {
+                        transferUpstreamOauthLink(input: {
+                            upstreamOauth2LinkId: $linkId,
+                            userId: $userId,
+                        }) {
+                            status
+                            upstreamOauth2Link {
+                                id
+                            }
+                        }
+                    }
+                ",
+                "variables": {
+                    "linkId": format!("upstream_oauth2_link:{}", alice_link.id),
+                    "userId": format!("user:{}", user.id),
+                },
+            }))
+    };
+
+    // Carol already has a link with this provider, so this should be rejected
+    let response = state.request(transfer(&carol)).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data,
+        serde_json::json!({
+            "transferUpstreamOauthLink": {
+                "status": "ALREADY_LINKED",
+                "upstreamOauth2Link": null,
+            },
+        })
+    );
+
+    // Bob doesn't, so the link can be moved to him
+    let response = state.request(transfer(&bob)).await;
+    response.assert_status(StatusCode::OK);
+    let response: GraphQLResponse = response.json();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data,
+        serde_json::json!({
+            "transferUpstreamOauthLink": {
+                "status": "TRANSFERRED",
+                "upstreamOauth2Link": {
+                    "id": format!("upstream_oauth2_link:{}", alice_link.id),
+                },
+            },
+        })
+    );
+
+    let mut repo = state.repository().await.unwrap();
+    let link = repo
+        .upstream_oauth_link()
+        .lookup(alice_link.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(link.user_id, Some(bob.id));
+
+    // The sessions started through the link were ended, but not the others
+    let browser_session = repo
+        .browser_session()
+        .lookup(browser_session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(browser_session.finished_at.is_some());
+    let oauth2_session = repo
+        .oauth2_session()
+        .lookup(oauth2_session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(oauth2_session.is_finished());
+    let other_browser_session = repo
+        .browser_session()
+        .lookup(other_browser_session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(other_browser_session.finished_at.is_none());
+}
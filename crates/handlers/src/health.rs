@@ -4,10 +4,13 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use axum::{extract::State, response::IntoResponse};
-use mas_axum_utils::InternalError;
+use axum::{Json, extract::State, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use mas_axum_utils::{InternalError, RepositoryRejection};
+use mas_storage::BoxRepositoryFactory;
+use serde::Serialize;
 use sqlx::PgPool;
-use tracing::{Instrument, info_span};
+use tracing::{Instrument, info_span, warn};
 
 pub async fn get(State(pool): State<PgPool>) -> Result<impl IntoResponse, InternalError> {
     let mut conn = pool.acquire().await?;
@@ -21,9 +24,118 @@ pub async fn get(State(pool): State<PgPool>) -> Result<impl IntoResponse, Intern
     Ok("ok")
 }
 
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ComponentStatus {
+    /// The component works
+    Ok,
+
+    /// The last check of the component failed
+    Failing,
+
+    /// The component was never checked
+    Unknown,
+}
+
+#[derive(Serialize)]
+struct Component {
+    status: ComponentStatus,
+
+    /// Whether the service can't serve requests when this component fails
+    critical: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checked_at: Option<DateTime<Utc>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Components {
+    database: Component,
+    email: Component,
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    status: ComponentStatus,
+    components: Components,
+}
+
+/// Checks whether we can serve requests, going through the repository factory
+/// so that this reflects the state of its circuit breaker: while the database
+/// is unreachable, this responds with a `503 Service Unavailable`.
+///
+/// It also reports the outcome of the last email delivery check, which doesn't
+/// affect the response status.
+pub async fn ready(
+    State(repository_factory): State<BoxRepositoryFactory>,
+) -> Result<impl IntoResponse, RepositoryRejection> {
+    let mut repo = repository_factory
+        .create()
+        .instrument(info_span!("DB readiness"))
+        .await?;
+
+    let email_probe = repo.email_probe().latest().await;
+
+    repo.cancel().await?;
+
+    let email = match email_probe {
+        Ok(Some(result)) => Component {
+            status: if result.is_success() {
+                ComponentStatus::Ok
+            } else {
+                ComponentStatus::Failing
+            },
+            critical: false,
+            checked_at: Some(result.created_at),
+            latency_ms: Some(result.latency_ms),
+        },
+
+        Ok(None) => Component {
+            status: ComponentStatus::Unknown,
+            critical: false,
+            checked_at: None,
+            latency_ms: None,
+        },
+
+        Err(e) => {
+            warn!(
+                error = &e as &dyn std::error::Error,
+                "Failed to load the status of the email delivery checks"
+            );
+            Component {
+                status: ComponentStatus::Unknown,
+                critical: false,
+                checked_at: None,
+                latency_ms: None,
+            }
+        }
+    };
+
+    Ok(Json(Readiness {
+        status: ComponentStatus::Ok,
+        components: Components {
+            database: Component {
+                status: ComponentStatus::Ok,
+                critical: true,
+                checked_at: None,
+                latency_ms: None,
+            },
+            email,
+        },
+    }))
+}
+
 #[cfg(test)]
 mod tests {
-    use hyper::{Request, StatusCode};
+    use std::time::Duration;
+
+    use hyper::{Request, StatusCode, header::RETRY_AFTER};
+    use mas_data_model::EmailProbeKind;
+    use mas_storage_pg::PgRepositoryFactory;
+    use sqlx::postgres::PgPoolOptions;
 
     use super::*;
     use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
@@ -37,5 +149,94 @@ mod tests {
         let response = state.request(request).await;
         response.assert_status(StatusCode::OK);
         assert_eq!(response.body(), "ok");
+
+        let request = Request::get("/health/ready").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["components"]["database"]["status"], "ok");
+        assert_eq!(body["components"]["email"]["status"], "unknown");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_ready_email_probe(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        // A failed email delivery check shows up, but doesn't make the service
+        // unavailable
+        let mut repo = state.repository().await.unwrap();
+        repo.email_probe()
+            .add(
+                &mut rng,
+                &state.clock,
+                EmailProbeKind::Connection,
+                30_000,
+                Some("Timed out".to_owned()),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get("/health/ready").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["components"]["email"]["status"], "failing");
+        assert_eq!(body["components"]["email"]["critical"], false);
+        assert_eq!(body["components"]["email"]["latency_ms"], 30_000);
+
+        // Once a check succeeds, it is reported as working again
+        state.clock.advance(chrono::Duration::hours(1));
+        let mut repo = state.repository().await.unwrap();
+        repo.email_probe()
+            .add(&mut rng, &state.clock, EmailProbeKind::Connection, 50, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get("/health/ready").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["components"]["email"]["status"], "ok");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_database_unavailable(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool.clone()).await.unwrap();
+
+        // Swap the repository factory for one backed by a pool which can never
+        // acquire a connection, as it points to a port nothing listens on
+        let options = (*pool.connect_options()).clone().port(1);
+        let poisoned_pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy_with(options);
+        state.repository_factory = PgRepositoryFactory::new(poisoned_pool);
+
+        // Requests which need a repository get a 503 instead of a 500
+        let request = Request::get("/login").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        let retry_after: u64 = response
+            .headers()
+            .get(RETRY_AFTER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=5).contains(&retry_after));
+
+        // The circuit breaker is now open, which is visible on the readiness
+        // check
+        let request = Request::get("/health/ready").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.headers().contains_key(RETRY_AFTER));
     }
 }
@@ -16,15 +16,14 @@
 )]
 
 use std::{
-    convert::Infallible,
     sync::{Arc, LazyLock},
     time::Duration,
 };
 
 use axum::{
-    Extension, Router,
+    Extension, Json, Router,
     extract::{FromRef, FromRequestParts, OriginalUri, RawQuery, State},
-    http::Method,
+    http::{HeaderMap, Method},
     response::{Html, IntoResponse},
     routing::{get, post},
 };
@@ -35,11 +34,16 @@ use hyper::{
         ACCEPT, ACCEPT_LANGUAGE, AUTHORIZATION, CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_TYPE,
     },
 };
-use mas_axum_utils::{InternalError, cookies::CookieJar};
+use mas_axum_utils::{
+    InternalError,
+    cookies::CookieJar,
+    error_format::{ErrorDocument, ErrorFormat, RouteClass},
+};
 use mas_data_model::{
     SiteConfig,
     //:tchap:
-    TchapConfig, //:tchap:end
+    TchapConfig,
+    TchapFeatures, //:tchap:end
 };
 use mas_http::CorsLayerExt;
 use mas_keystore::{Encrypter, Keystore};
@@ -47,10 +51,11 @@ use mas_matrix::HomeserverConnection;
 use mas_policy::Policy;
 use mas_router::{Route, UrlBuilder};
 use mas_storage::{BoxRepository, BoxRepositoryFactory};
-use mas_templates::{ErrorContext, NotFoundContext, TemplateContext, Templates};
+use mas_templates::{
+    ErrorCode, ErrorContext, MethodNotAllowedContext, NotFoundContext, TemplateContext, Templates,
+};
 use opentelemetry::metrics::Meter;
 use sqlx::PgPool;
-use tower::util::AndThenLayer;
 use tower_http::cors::{Any, CorsLayer};
 
 use self::{graphql::ExtraRouterParameters, passwords::PasswordManager};
@@ -62,17 +67,26 @@ mod health;
 mod oauth2;
 pub mod passwords;
 pub mod upstream_oauth2;
+pub mod user_actions;
 mod views;
 
 mod activity_tracker;
+mod audit;
 mod captcha;
+mod compression;
+mod login_failures;
 mod preferred_language;
 mod rate_limit;
 mod session;
+mod session_limit;
 #[cfg(test)]
 mod test_utils;
 
 static METER: LazyLock<Meter> = LazyLock::new(|| {
+    // Make sure the instruments report to the in-memory exporter in tests
+    #[cfg(test)]
+    LazyLock::force(&test_utils::METRICS);
+
     let scope = opentelemetry::InstrumentationScope::builder(env!("CARGO_PKG_NAME"))
         .with_version(env!("CARGO_PKG_VERSION"))
         .with_schema_url(opentelemetry_semantic_conventions::SCHEMA_URL)
@@ -97,15 +111,23 @@ macro_rules! impl_from_error_for_route {
     };
 }
 
-pub use mas_axum_utils::{ErrorWrapper, cookies::CookieManager};
+pub use mas_axum_utils::{
+    ErrorWrapper, RepositoryRejection,
+    cookies::{CookieManager, SameSite as CookieSameSite},
+};
 use mas_data_model::{BoxClock, BoxRng};
 
 pub use self::{
     activity_tracker::{ActivityTracker, Bound as BoundActivityTracker},
-    admin::router as admin_api_router,
+    admin::{
+        EffectiveSiteConfig, EffectiveTemplatesPaths, EffectiveUpstreamProvider,
+        router as admin_api_router,
+    },
+    compression::{CompressibleContentType, compression_layer},
     graphql::{
         Schema as GraphQLSchema, schema as graphql_schema, schema_builder as graphql_schema_builder,
     },
+    oauth2::claims_augmentor::{ClaimsAugmentor, ClaimsAugmentors, OrganizationClaimsAugmentor},
     preferred_language::PreferredLanguage,
     rate_limit::{Limiter, RequesterFingerprint},
     upstream_oauth2::cache::MetadataCache,
@@ -115,14 +137,21 @@ pub fn healthcheck_router<S>() -> Router<S>
 where
     S: Clone + Send + Sync + 'static,
     PgPool: FromRef<S>,
+    BoxRepositoryFactory: FromRef<S>,
 {
-    Router::new().route(mas_router::Healthcheck::route(), get(self::health::get))
+    Router::new()
+        .route(mas_router::Healthcheck::route(), get(self::health::get))
+        .route(
+            mas_router::ReadinessCheck::route(),
+            get(self::health::ready),
+        )
 }
 
 pub fn graphql_router<S>(playground: bool, undocumented_oauth2_access: bool) -> Router<S>
 where
     S: Clone + Send + Sync + 'static,
     graphql::Schema: FromRef<S>,
+    SiteConfig: FromRef<S>,
     BoundActivityTracker: FromRequestParts<S>,
     BoxRepository: FromRequestParts<S>,
     BoxClock: FromRequestParts<S>,
@@ -141,6 +170,9 @@ where
         .layer(Extension(ExtraRouterParameters {
             undocumented_oauth2_access,
         }))
+        .layer(axum::middleware::map_response(
+            async |response: axum::response::Response| (Extension(RouteClass::Api), response),
+        ))
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
@@ -197,6 +229,26 @@ where
         )
 }
 
+/// A router only serving the JWKS, used to serve it under the base path of the
+/// issuer aliases
+pub fn jwks_router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Keystore: FromRef<S>,
+{
+    Router::new()
+        .route(
+            mas_router::OAuth2Keys::route(),
+            get(self::oauth2::keys::get),
+        )
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods(Any)
+                .max_age(Duration::from_secs(60 * 60)),
+        )
+}
+
 pub fn api_router<S>() -> Router<S>
 where
     S: Clone + Send + Sync + 'static,
@@ -210,6 +262,7 @@ where
     SiteConfig: FromRef<S>,
     Templates: FromRef<S>,
     Arc<dyn HomeserverConnection>: FromRef<S>,
+    ClaimsAugmentors: FromRef<S>,
     BoxClock: FromRequestParts<S>,
     BoxRng: FromRequestParts<S>,
     Policy: FromRequestParts<S>,
@@ -240,10 +293,23 @@ where
             mas_router::OAuth2RegistrationEndpoint::route(),
             post(self::oauth2::registration::post),
         )
+        .route(
+            mas_router::OAuth2ClientConfiguration::route(),
+            get(self::oauth2::client_configuration::get)
+                .put(self::oauth2::client_configuration::put)
+                .delete(self::oauth2::client_configuration::delete),
+        )
         .route(
             mas_router::OAuth2DeviceAuthorizationEndpoint::route(),
             post(self::oauth2::device::authorize::post),
         )
+        .route(
+            mas_router::OAuth2PushedAuthorizationRequestEndpoint::route(),
+            post(self::oauth2::pushed_authorization_request::post),
+        )
+        .layer(axum::middleware::map_response(
+            async |response: axum::response::Response| (Extension(RouteClass::Api), response),
+        ))
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
@@ -266,6 +332,8 @@ pub fn compat_router<S>(templates: Templates) -> Router<S>
 where
     S: Clone + Send + Sync + 'static,
     UrlBuilder: FromRef<S>,
+    Encrypter: FromRef<S>,
+    Templates: FromRef<S>,
     SiteConfig: FromRef<S>,
     Arc<dyn HomeserverConnection>: FromRef<S>,
     PasswordManager: FromRef<S>,
@@ -291,10 +359,8 @@ where
             mas_router::CompatLoginSsoRedirectSlash::route(),
             get(self::compat::login_sso_redirect::get),
         )
-        .layer(AndThenLayer::new(
-            async move |response: axum::response::Response| {
-                Ok::<_, Infallible>(recover_error(&templates, response))
-            },
+        .layer(axum::middleware::map_response(
+            async |response: axum::response::Response| (Extension(RouteClass::Browser), response),
         ));
 
     // A sub-router for API-facing routes with CORS
@@ -315,6 +381,35 @@ where
             mas_router::CompatRefresh::route(),
             post(self::compat::refresh::post),
         )
+        .route(
+            mas_router::CompatAccountPassword::route(),
+            post(self::compat::account_password::post),
+        )
+        .route(
+            mas_router::CompatAccount3pid::route(),
+            // The legacy `POST /account/3pid` is equivalent to `/account/3pid/add`
+            get(self::compat::account_3pid::get).post(self::compat::account_3pid::add),
+        )
+        .route(
+            mas_router::CompatAccount3pidAdd::route(),
+            post(self::compat::account_3pid::add),
+        )
+        .route(
+            mas_router::CompatAccount3pidDelete::route(),
+            post(self::compat::account_3pid::delete),
+        )
+        .route(
+            mas_router::CompatCapabilities::route(),
+            get(self::compat::capabilities::get),
+        )
+        .layer(axum::middleware::from_fn(
+            move |request: axum::extract::Request, next: axum::middleware::Next| {
+                self::compat::translate_errors(templates.clone(), request, next)
+            },
+        ))
+        .layer(axum::middleware::map_response(
+            async |response: axum::response::Response| (Extension(RouteClass::Api), response),
+        ))
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
@@ -333,7 +428,7 @@ where
     Router::new().merge(human_router).merge(api_router)
 }
 
-pub fn human_router<S>(templates: Templates) -> Router<S>
+pub fn human_router<S>() -> Router<S>
 where
     S: Clone + Send + Sync + 'static,
     UrlBuilder: FromRef<S>,
@@ -351,11 +446,14 @@ where
     Limiter: FromRef<S>,
     reqwest::Client: FromRef<S>,
     Arc<dyn HomeserverConnection>: FromRef<S>,
+    BoxRepositoryFactory: FromRef<S>,
+    ClaimsAugmentors: FromRef<S>,
     BoxClock: FromRequestParts<S>,
     BoxRng: FromRequestParts<S>,
     Policy: FromRequestParts<S>,
     //:tchap:
     TchapConfig: FromRef<S>,
+    TchapFeatures: FromRef<S>,
     //:tchap:end
 {
     Router::new()
@@ -385,6 +483,10 @@ where
             mas_router::AccountRecoveryFinish::route(),
             get(self::views::app::get_anonymous),
         )
+        .route(
+            mas_router::FrontendConfigEndpoint::route(),
+            get(self::views::app::frontend_config),
+        )
         .route(
             mas_router::ChangePasswordDiscovery::route(),
             get(async |State(url_builder): State<UrlBuilder>| {
@@ -396,6 +498,10 @@ where
             mas_router::Login::route(),
             get(self::views::login::get).post(self::views::login::post),
         )
+        .route(
+            mas_router::Reauth::route(),
+            get(self::views::reauth::get).post(self::views::reauth::post),
+        )
         .route(mas_router::Logout::route(), post(self::views::logout::post))
         .route(
             mas_router::Register::route(),
@@ -432,6 +538,18 @@ where
             mas_router::AccountRecoveryProgress::route(),
             get(self::views::recovery::progress::get).post(self::views::recovery::progress::post),
         )
+        .route(
+            mas_router::AccountRecoveryUpstream::route(),
+            get(self::views::recovery::upstream::get).post(self::views::recovery::upstream::post),
+        )
+        .route(
+            mas_router::EmailChange::route(),
+            get(self::views::email_change::get).post(self::views::email_change::post),
+        )
+        .route(
+            mas_router::RevokeSession::route(),
+            get(self::views::revoke_session::get).post(self::views::revoke_session::post),
+        )
         .route(
             mas_router::OAuth2AuthorizationEndpoint::route(),
             get(self::oauth2::authorization::get),
@@ -462,6 +580,10 @@ where
             mas_router::UpstreamOAuth2Link::route(),
             get(self::upstream_oauth2::link::get).post(self::upstream_oauth2::link::post),
         )
+        .route(
+            mas_router::UpstreamOAuth2LinkUsername::route(),
+            get(self::upstream_oauth2::link::check_username),
+        )
         .route(
             mas_router::UpstreamOAuth2BackchannelLogout::route(),
             post(self::upstream_oauth2::backchannel_logout::post),
@@ -474,29 +596,56 @@ where
             mas_router::DeviceCodeConsent::route(),
             get(self::oauth2::device::consent::get).post(self::oauth2::device::consent::post),
         )
-        .layer(AndThenLayer::new(
-            async move |response: axum::response::Response| {
-                Ok::<_, Infallible>(recover_error(&templates, response))
-            },
+        .method_not_allowed_fallback(self::method_not_allowed_fallback)
+        .layer(axum::middleware::map_response(
+            async |response: axum::response::Response| (Extension(RouteClass::Browser), response),
         ))
 }
 
-fn recover_error(
-    templates: &Templates,
-    response: axum::response::Response,
+/// A middleware which renders the error responses carrying an
+/// [`ErrorContext`] in the format negotiated with the client.
+///
+/// The routers attach their [`RouteClass`] to their responses, which decides
+/// the format when the `Accept` header of the request has no preference
+/// between HTML and JSON. Responses without a [`RouteClass`] are left
+/// untouched.
+pub async fn negotiate_error_format(
+    templates: Templates,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
 ) -> axum::response::Response {
-    // Error responses should have an ErrorContext attached to them
-    let ext = response.extensions().get::<ErrorContext>();
-    if let Some(ctx) = ext
-        && let Ok(res) = templates.render_error(ctx)
-    {
-        let (mut parts, _original_body) = response.into_parts();
-        parts.headers.remove(CONTENT_TYPE);
-        parts.headers.remove(CONTENT_LENGTH);
-        return (parts, Html(res)).into_response();
-    }
+    let preferred = ErrorFormat::preferred(request.headers());
+    let response = next.run(request).await;
+
+    let Some(class) = response.extensions().get::<RouteClass>().copied() else {
+        return response;
+    };
+
+    let Some(ctx) = response.extensions().get::<ErrorContext>() else {
+        return response;
+    };
+
+    match preferred.unwrap_or(class.default_error_format()) {
+        ErrorFormat::Html => {
+            let Ok(res) = templates.render_error(ctx) else {
+                return response;
+            };
+
+            let (mut parts, _original_body) = response.into_parts();
+            parts.headers.remove(CONTENT_TYPE);
+            parts.headers.remove(CONTENT_LENGTH);
+            (parts, Html(res)).into_response()
+        }
 
-    response
+        ErrorFormat::Json => {
+            let ctx = ctx.clone();
+            let (mut parts, _original_body) = response.into_parts();
+            parts.headers.remove(CONTENT_TYPE);
+            parts.headers.remove(CONTENT_LENGTH);
+            let document = ErrorDocument::new(parts.status, &ctx);
+            (parts, Json(document)).into_response()
+        }
+    }
 }
 
 /// The fallback handler for all routes that don't match anything else.
@@ -509,12 +658,236 @@ pub async fn fallback(
     OriginalUri(uri): OriginalUri,
     method: Method,
     version: Version,
+    headers: HeaderMap,
     PreferredLanguage(locale): PreferredLanguage,
-) -> Result<impl IntoResponse, InternalError> {
-    let ctx = NotFoundContext::new(&method, version, &uri).with_language(locale);
-    // XXX: this should look at the Accept header and return JSON if requested
+) -> Result<axum::response::Response, InternalError> {
+    if ErrorFormat::negotiate(&headers, RouteClass::Browser) == ErrorFormat::Json {
+        let ctx = ErrorContext::new()
+            .with_code(ErrorCode::NotFound)
+            .with_description(format!("No route found for {method} {uri}"));
+        let document = ErrorDocument::new(StatusCode::NOT_FOUND, &ctx);
+        return Ok((StatusCode::NOT_FOUND, Json(document)).into_response());
+    }
 
+    let ctx = NotFoundContext::new(&method, version, &uri).with_language(locale);
     let res = templates.render_not_found(&ctx)?;
 
-    Ok((StatusCode::NOT_FOUND, Html(res)))
+    Ok((StatusCode::NOT_FOUND, Html(res)).into_response())
+}
+
+/// The fallback handler for browser routes which exist, but don't accept the
+/// request method.
+///
+/// # Errors
+///
+/// Returns an error if the template rendering fails.
+pub async fn method_not_allowed_fallback(
+    State(templates): State<Templates>,
+    OriginalUri(uri): OriginalUri,
+    method: Method,
+    version: Version,
+    headers: HeaderMap,
+    PreferredLanguage(locale): PreferredLanguage,
+) -> Result<axum::response::Response, InternalError> {
+    if ErrorFormat::negotiate(&headers, RouteClass::Browser) == ErrorFormat::Json {
+        let ctx = ErrorContext::new()
+            .with_code(ErrorCode::MethodNotAllowed)
+            .with_description(format!("Method {method} is not allowed on {uri}"));
+        let document = ErrorDocument::new(StatusCode::METHOD_NOT_ALLOWED, &ctx);
+        return Ok((StatusCode::METHOD_NOT_ALLOWED, Json(document)).into_response());
+    }
+
+    let ctx = MethodNotAllowedContext::new(&method, version, &uri).with_language(locale);
+    let res = templates.render_method_not_allowed(&ctx)?;
+
+    Ok((StatusCode::METHOD_NOT_ALLOWED, Html(res)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{
+        Request, StatusCode,
+        header::{ACCEPT, ACCEPT_LANGUAGE, CONTENT_TYPE},
+    };
+    use mas_router::{Route, SimpleRoute};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    async fn count_scheduled_jobs(pool: &PgPool, schedule_name: &str) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM queue_jobs WHERE schedule_name = $1")
+            .bind(schedule_name)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_queue_leader_election(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+
+        // Two workers running against the same database
+        let mut first = state.new_queue_worker().await;
+        let mut second = state.new_queue_worker().await;
+
+        // The first one to tick becomes the leader and schedules the recurring jobs
+        assert!(first.tick_in_tests().await.unwrap());
+        assert!(!second.tick_in_tests().await.unwrap());
+        assert_eq!(
+            count_scheduled_jobs(&pool, "cleanup-expired-tokens").await,
+            1
+        );
+
+        // Further ticks don't schedule anything else
+        for _ in 0..3 {
+            assert!(first.tick_in_tests().await.unwrap());
+            assert!(!second.tick_in_tests().await.unwrap());
+        }
+        assert_eq!(
+            count_scheduled_jobs(&pool, "cleanup-expired-tokens").await,
+            1
+        );
+
+        // Once the job ran, the next one is scheduled exactly once
+        state.clock.advance(Duration::try_hours(1).unwrap());
+        assert!(first.tick_in_tests().await.unwrap());
+        first.process_all_jobs_in_tests().await.unwrap();
+        for _ in 0..3 {
+            assert!(first.tick_in_tests().await.unwrap());
+            assert!(!second.tick_in_tests().await.unwrap());
+        }
+        assert_eq!(
+            count_scheduled_jobs(&pool, "cleanup-expired-tokens").await,
+            2
+        );
+
+        // The leader dies without stepping down: once its lease expires, the
+        // other worker takes over
+        sqlx::query("UPDATE queue_leader SET expires_at = NOW() - INTERVAL '1 second'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(second.tick_in_tests().await.unwrap());
+        assert!(!first.tick_in_tests().await.unwrap());
+        assert_eq!(
+            count_scheduled_jobs(&pool, "cleanup-expired-tokens").await,
+            2
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_error_format_browser_route(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let path = mas_router::UpstreamOAuth2Authorize::new(Ulid::nil()).path_and_query();
+        let path = &*path;
+
+        // Browsers get the HTML error page
+        let request = Request::get(path)
+            .header(
+                ACCEPT,
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+            )
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
+        assert!(response.body().contains("upstream-provider-not-found"));
+
+        // API clients get a JSON document with the same error code
+        let request = Request::get(path)
+            .header(ACCEPT, "application/json")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["title"], "Not Found");
+        assert_eq!(body["code"], "upstream-provider-not-found");
+        assert!(body["detail"].is_string());
+
+        // Same for routes which don't exist
+        let request = Request::get("/this-route-does-not-exist")
+            .header(ACCEPT, "application/json")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["code"], "not-found");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_error_format_api_route(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        let token = state.token_with_scope("urn:mas:admin").await;
+        let path = format!("/api/admin/v1/users/{}", Ulid::nil());
+
+        // API errors are always JSON, whatever the client asks for
+        for accept in ["application/json", "text/html"] {
+            let request = Request::get(&path)
+                .bearer(&token)
+                .header(ACCEPT, accept)
+                .empty();
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::NOT_FOUND);
+            let body: serde_json::Value = response.json();
+            assert!(body["errors"].is_array(), "{body}");
+        }
+
+        // Discovery documents are left untouched
+        let request = Request::get(mas_router::OidcConfiguration::PATH)
+            .header(ACCEPT, "text/html")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let _: serde_json::Value = response.json();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_fallback_pages(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        // Unknown routes render the localized not found page
+        let request = Request::get("/this-route-does-not-exist")
+            .header(ACCEPT, "text/html")
+            .header(ACCEPT_LANGUAGE, "fr")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+        response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
+        assert!(response.body().contains("Page introuvable"));
+
+        // Browser routes called with the wrong method render the localized
+        // method not allowed page
+        let request = Request::delete(mas_router::Login::route())
+            .header(ACCEPT, "text/html")
+            .header(ACCEPT_LANGUAGE, "fr")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::METHOD_NOT_ALLOWED);
+        response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
+        assert!(response.body().contains("Méthode non autorisée"));
+
+        // Unless the client asks for JSON
+        let request = Request::delete(mas_router::Login::route())
+            .header(ACCEPT, "application/json")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::METHOD_NOT_ALLOWED);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["code"], "method-not-allowed");
+
+        // API routes don't render the HTML page
+        let request = Request::get(mas_router::OAuth2TokenEndpoint::PATH)
+            .header(ACCEPT, "text/html")
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::METHOD_NOT_ALLOWED);
+        assert!(!response.body().contains("<html"));
+    }
 }
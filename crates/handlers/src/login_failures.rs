@@ -0,0 +1,86 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Recording of failed login attempts, to help support figure out why a user
+//! can't log in
+
+use mas_data_model::{
+    AuditEventPayload, Clock, LoginFailureOrigin, LoginFailureReason, SiteConfig, User,
+};
+use mas_storage::{BoxRepository, BoxRepositoryFactory, RepositoryError};
+use rand::RngCore;
+
+use crate::{RequesterFingerprint, audit::schedule_audit_event};
+
+/// Record a failed login attempt for a user in the given repository
+///
+/// The failure is also reported to the audit webhook, if one is configured.
+/// The caller is responsible for saving the repository.
+pub(crate) async fn add_login_failure(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    site_config: &SiteConfig,
+    user: &User,
+    origin: LoginFailureOrigin,
+    reason: LoginFailureReason,
+    requester: RequesterFingerprint,
+) -> Result<(), RepositoryError> {
+    repo.user_login_failure()
+        .add(rng, clock, user, origin, reason, requester.ip())
+        .await?;
+    schedule_audit_event(
+        repo,
+        rng,
+        clock,
+        site_config,
+        requester.ip(),
+        AuditEventPayload::login_failure(user, origin, reason),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Record a failed login attempt for a user in its own transaction
+///
+/// This is for login flows which roll back their transaction on failure.
+/// Recording is best-effort: errors are logged and never propagated, so that
+/// it can't fail the login flow itself.
+pub(crate) async fn record_login_failure(
+    repository_factory: &BoxRepositoryFactory,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    site_config: &SiteConfig,
+    user: &User,
+    origin: LoginFailureOrigin,
+    reason: LoginFailureReason,
+    requester: RequesterFingerprint,
+) {
+    let res: Result<(), RepositoryError> = async {
+        let mut repo = repository_factory.create().await?;
+        add_login_failure(
+            &mut repo,
+            rng,
+            clock,
+            site_config,
+            user,
+            origin,
+            reason,
+            requester,
+        )
+        .await?;
+        repo.save().await?;
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = res {
+        tracing::warn!(
+            error = &e as &dyn std::error::Error,
+            user.id = %user.id,
+            "Failed to record login failure"
+        );
+    }
+}
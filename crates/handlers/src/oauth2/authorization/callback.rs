@@ -9,10 +9,21 @@
 use std::collections::HashMap;
 
 use axum::response::{Html, IntoResponse, Redirect, Response};
-use mas_data_model::AuthorizationGrant;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{AuthorizationGrant, Client, Clock};
 use mas_i18n::DataLocale;
+use mas_iana::jose::JsonWebSignatureAlg;
+use mas_jose::{
+    claims,
+    constraints::Constrainable,
+    jwt::{JsonWebSignatureHeader, Jwt},
+};
+use mas_keystore::Keystore;
+use mas_router::UrlBuilder;
 use mas_templates::{FormPostContext, Templates};
 use oauth2_types::requests::ResponseMode;
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
 use serde::Serialize;
 use thiserror::Error;
 use url::Url;
@@ -26,11 +37,57 @@ enum CallbackDestinationMode {
     FormPost,
 }
 
+/// Signs the authorization response parameters into a JWT, as described by
+/// [JARM](https://openid.net/specs/oauth-v2-jarm.html)
+#[derive(Clone)]
+struct ResponseSigner {
+    key_store: Keystore,
+    alg: JsonWebSignatureAlg,
+    issuer: String,
+    client_id: String,
+    now: DateTime<Utc>,
+    rng: ChaChaRng,
+}
+
+impl std::fmt::Debug for ResponseSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseSigner")
+            .field("alg", &self.alg)
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResponseSigner {
+    fn sign<T: Serialize>(mut self, params: T) -> Result<String, ResponseSignatureError> {
+        let mut claims: HashMap<String, serde_json::Value> =
+            serde_json::from_value(serde_json::to_value(params)?)?;
+        claims::ISS.insert(&mut claims, self.issuer)?;
+        claims::AUD.insert(&mut claims, self.client_id)?;
+        // The response is meant to be consumed right away by the client
+        claims::EXP.insert(&mut claims, self.now + Duration::try_minutes(10).unwrap())?;
+
+        let key = self
+            .key_store
+            .signing_key_for_algorithm(&self.alg)
+            .ok_or(ResponseSignatureError::InvalidSigningKey)?;
+        let signer = key.params().signing_key_for_alg(&self.alg)?;
+        let header = JsonWebSignatureHeader::new(self.alg)
+            .with_kid(key.kid().ok_or(ResponseSignatureError::InvalidSigningKey)?);
+        let jwt = Jwt::sign_with_rng(&mut self.rng, header, claims, &signer)?;
+
+        Ok(jwt.into_string())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CallbackDestination {
     mode: CallbackDestinationMode,
     safe_redirect_uri: Url,
     state: Option<String>,
+    jwt: bool,
+    signer: Option<ResponseSigner>,
 }
 
 #[derive(Debug, Error)]
@@ -43,6 +100,15 @@ pub enum IntoCallbackDestinationError {
 
     #[error("Requested response_mode is not supported")]
     UnsupportedResponseMode,
+
+    #[error("Client did not register an algorithm to sign authorization responses")]
+    MissingResponseSigningAlg,
+
+    #[error("No key available to sign authorization responses with {0}")]
+    NoResponseSigningKey(JsonWebSignatureAlg),
+
+    #[error("Failed to seed the response signer")]
+    SignerSeed(#[from] rand::Error),
 }
 
 #[derive(Debug, Error)]
@@ -52,6 +118,23 @@ pub enum CallbackDestinationError {
 
     #[error("Failed to serialize parameters query string")]
     ParamsSerialization(#[from] serde_urlencoded::ser::Error),
+
+    #[error("The response mode requires a signed response, but no signer was set up")]
+    MissingResponseSigner,
+
+    #[error("Failed to sign the authorization response")]
+    ResponseSignature(#[from] ResponseSignatureError),
+}
+
+#[derive(Debug, Error)]
+#[error(transparent)]
+pub enum ResponseSignatureError {
+    #[error("The signing key is invalid")]
+    InvalidSigningKey,
+    Serialization(#[from] serde_json::Error),
+    Claim(#[from] mas_jose::claims::ClaimError),
+    JwtSignature(#[from] mas_jose::jwt::JwtSignatureError),
+    WrongAlgorithm(#[from] mas_keystore::WrongAlgorithmError),
 }
 
 impl TryFrom<&AuthorizationGrant> for CallbackDestination {
@@ -76,8 +159,13 @@ impl CallbackDestination {
             return Err(IntoCallbackDestinationError::RedirectUriFragmentNotAllowed);
         }
 
+        let jwt = matches!(
+            mode,
+            ResponseMode::QueryJwt | ResponseMode::FragmentJwt | ResponseMode::FormPostJwt
+        );
+
         let mode = match mode {
-            ResponseMode::Query => {
+            ResponseMode::Query | ResponseMode::QueryJwt => {
                 let existing_params = redirect_uri
                     .query()
                     .map(serde_urlencoded::from_str)
@@ -89,8 +177,8 @@ impl CallbackDestination {
 
                 CallbackDestinationMode::Query { existing_params }
             }
-            ResponseMode::Fragment => CallbackDestinationMode::Fragment,
-            ResponseMode::FormPost => CallbackDestinationMode::FormPost,
+            ResponseMode::Fragment | ResponseMode::FragmentJwt => CallbackDestinationMode::Fragment,
+            ResponseMode::FormPost | ResponseMode::FormPostJwt => CallbackDestinationMode::FormPost,
             _ => return Err(IntoCallbackDestinationError::UnsupportedResponseMode),
         };
 
@@ -98,14 +186,109 @@ impl CallbackDestination {
             mode,
             safe_redirect_uri: redirect_uri,
             state,
+            jwt,
+            signer: None,
         })
     }
 
+    /// Set up the signing of the response parameters, if the response mode
+    /// requires them to be sent as a JWT. This is a no-op for other response
+    /// modes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client did not register an
+    /// `authorization_signed_response_alg`, or if there is no key to sign
+    /// responses with that algorithm.
+    pub fn with_response_signing(
+        mut self,
+        rng: impl RngCore + CryptoRng,
+        clock: &impl Clock,
+        key_store: &Keystore,
+        url_builder: &UrlBuilder,
+        client: &Client,
+    ) -> Result<Self, IntoCallbackDestinationError> {
+        if !self.jwt {
+            return Ok(self);
+        }
+
+        let alg = client
+            .authorization_signed_response_alg
+            .clone()
+            .ok_or(IntoCallbackDestinationError::MissingResponseSigningAlg)?;
+
+        if key_store.signing_key_for_algorithm(&alg).is_none() {
+            return Err(IntoCallbackDestinationError::NoResponseSigningKey(alg));
+        }
+
+        self.signer = Some(ResponseSigner {
+            key_store: key_store.clone(),
+            alg,
+            issuer: url_builder.oidc_issuer().to_string(),
+            client_id: client.client_id.clone(),
+            now: clock.now(),
+            rng: ChaChaRng::from_rng(rng)?,
+        });
+
+        Ok(self)
+    }
+
     pub fn go<T: Serialize + Send + Sync>(
         self,
         templates: &Templates,
         locale: &DataLocale,
         params: T,
+    ) -> Result<Response, CallbackDestinationError> {
+        #[derive(Serialize)]
+        struct SignedParams<T> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            state: Option<String>,
+
+            #[serde(flatten)]
+            params: T,
+        }
+
+        #[derive(Serialize)]
+        struct JwtResponse {
+            response: String,
+        }
+
+        if !self.jwt {
+            return Self::deliver(
+                self.mode,
+                self.safe_redirect_uri,
+                self.state,
+                params,
+                templates,
+                locale,
+            );
+        }
+
+        let signer = self
+            .signer
+            .ok_or(CallbackDestinationError::MissingResponseSigner)?;
+        let response = signer.sign(SignedParams {
+            state: self.state,
+            params,
+        })?;
+
+        Self::deliver(
+            self.mode,
+            self.safe_redirect_uri,
+            None,
+            JwtResponse { response },
+            templates,
+            locale,
+        )
+    }
+
+    fn deliver<T: Serialize + Send + Sync>(
+        mode: CallbackDestinationMode,
+        mut redirect_uri: Url,
+        state: Option<String>,
+        params: T,
+        templates: &Templates,
+        locale: &DataLocale,
     ) -> Result<Response, CallbackDestinationError> {
         #[derive(Serialize)]
         struct AllParams<'s, T> {
@@ -119,10 +302,7 @@ impl CallbackDestination {
             params: T,
         }
 
-        let mut redirect_uri = self.safe_redirect_uri;
-        let state = self.state;
-
-        match self.mode {
+        match mode {
             CallbackDestinationMode::Query { existing_params } => {
                 let merged = AllParams {
                     existing: Some(&existing_params),
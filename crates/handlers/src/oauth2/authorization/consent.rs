@@ -4,6 +4,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::sync::Arc;
+
 use axum::{
     extract::{Form, Path, State},
     response::{Html, IntoResponse, Response},
@@ -15,24 +17,34 @@ use mas_axum_utils::{
     cookies::CookieJar,
     csrf::{CsrfExt, ProtectedForm},
 };
-use mas_data_model::{AuthorizationGrantStage, BoxClock, BoxRng};
+use mas_data_model::{
+    AuthenticatedBy, Authentication, AuthenticationMethod, AuthorizationGrant,
+    AuthorizationGrantStage, BoxClock, BoxRng, BrowserSession, Client, Clock, Device, SiteConfig,
+    without_device_scopes,
+};
+use mas_i18n::DataLocale;
 use mas_keystore::Keystore;
+use mas_matrix::HomeserverConnection;
 use mas_policy::Policy;
 use mas_router::{PostAuthAction, UrlBuilder};
 use mas_storage::{
-    BoxRepository,
+    BoxRepository, RepositoryError,
     oauth2::{OAuth2AuthorizationGrantRepository, OAuth2ClientRepository},
 };
-use mas_templates::{ConsentContext, PolicyViolationContext, TemplateContext, Templates};
+use mas_templates::{
+    ConsentContext, ErrorCode, PolicyViolationContext, TemplateContext, Templates,
+};
 use oauth2_types::requests::AuthorizationResponse;
+use serde::Deserialize;
 use thiserror::Error;
 use ulid::Ulid;
 
-use super::callback::CallbackDestination;
+use super::{AuthenticationCheck, callback::CallbackDestination, check_authentication};
 use crate::{
     BoundActivityTracker, PreferredLanguage, impl_from_error_for_route,
-    oauth2::generate_id_token,
+    oauth2::{claims_augmentor::ClaimsAugmentors, generate_id_token, user_claims},
     session::{SessionOrFallback, load_session_or_fallback},
+    session_limit::{self, SessionLimit},
 };
 
 #[derive(Debug, Error)]
@@ -51,6 +63,11 @@ pub enum RouteError {
 
     #[error("Failed to load client {0}")]
     NoSuchClient(Ulid),
+
+    #[error(
+        "You reached the limit of {limit} active sessions, sign out of another device to continue"
+    )]
+    TooManySessions { limit: u32 },
 }
 
 impl_from_error_for_route!(mas_templates::TemplateError);
@@ -62,16 +79,29 @@ impl_from_error_for_route!(crate::oauth2::IdTokenSignatureError);
 impl_from_error_for_route!(super::callback::IntoCallbackDestinationError);
 impl_from_error_for_route!(super::callback::CallbackDestinationError);
 
+#[derive(Deserialize, Debug)]
+pub(crate) struct ConsentForm {
+    #[serde(default)]
+    remember: String,
+}
+
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
         match self {
             Self::Internal(e) => InternalError::new(e).into_response(),
             e @ Self::NoSuchClient(_) => InternalError::new(Box::new(e)).into_response(),
-            e @ Self::GrantNotFound => GenericError::new(StatusCode::NOT_FOUND, e).into_response(),
-            e @ Self::GrantNotPending(_) => {
-                GenericError::new(StatusCode::CONFLICT, e).into_response()
-            }
-            e @ Self::Csrf(_) => GenericError::new(StatusCode::BAD_REQUEST, e).into_response(),
+            e @ Self::GrantNotFound => GenericError::new(StatusCode::NOT_FOUND, e)
+                .with_error_code(ErrorCode::AuthorizationGrantNotFound)
+                .into_response(),
+            e @ Self::GrantNotPending(_) => GenericError::new(StatusCode::CONFLICT, e)
+                .with_error_code(ErrorCode::AuthorizationGrantNotPending)
+                .into_response(),
+            e @ Self::Csrf(_) => GenericError::new(StatusCode::BAD_REQUEST, e)
+                .with_error_code(ErrorCode::CsrfMismatch)
+                .into_response(),
+            e @ Self::TooManySessions { .. } => GenericError::new(StatusCode::FORBIDDEN, e)
+                .with_error_code(ErrorCode::SessionLimitReached)
+                .into_response(),
         }
     }
 }
@@ -86,7 +116,11 @@ pub(crate) async fn get(
     clock: BoxClock,
     PreferredLanguage(locale): PreferredLanguage,
     State(templates): State<Templates>,
+    State(key_store): State<Keystore>,
     State(url_builder): State<UrlBuilder>,
+    State(claims_augmentors): State<ClaimsAugmentors>,
+    State(homeserver): State<Arc<dyn HomeserverConnection>>,
+    State(site_config): State<SiteConfig>,
     mut policy: Policy,
     mut repo: BoxRepository,
     activity_tracker: BoundActivityTracker,
@@ -95,7 +129,13 @@ pub(crate) async fn get(
     Path(grant_id): Path<Ulid>,
 ) -> Result<Response, RouteError> {
     let (cookie_jar, maybe_session) = match load_session_or_fallback(
-        cookie_jar, &clock, &mut rng, &templates, &locale, &mut repo,
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
     )
     .await?
     {
@@ -130,6 +170,15 @@ pub(crate) async fn get(
         return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
     };
 
+    // Make sure the session satisfies the max_age and acr_values asked by the
+    // client
+    let AuthenticationCheck::Satisfied { acr } =
+        check_authentication(&mut repo, &grant, &session).await?
+    else {
+        let reauth = mas_router::Reauth::and_then(PostAuthAction::continue_grant(grant_id));
+        return Ok((cookie_jar, url_builder.redirect(&reauth)).into_response());
+    };
+
     activity_tracker
         .record_browser_session(&clock, &session)
         .await;
@@ -150,6 +199,7 @@ pub(crate) async fn get(
         .await?;
     if !res.valid() {
         let ctx = PolicyViolationContext::for_authorization_grant(grant, client)
+            .with_violation_code(res.code().map(|code| code.as_str()))
             .with_session(session)
             .with_csrf(csrf_token.form_value())
             .with_language(locale);
@@ -159,6 +209,57 @@ pub(crate) async fn get(
         return Ok((cookie_jar, Html(content)).into_response());
     }
 
+    // Check whether the user can start one more session. If it means ending
+    // other sessions, the user has to be told which ones on the consent screen
+    let devices: Vec<Device> = grant
+        .scope
+        .iter()
+        .filter_map(Device::from_scope_token)
+        .collect();
+    let evicted_sessions =
+        match session_limit::check(&mut repo, &site_config, &session.user, &devices).await? {
+            SessionLimit::Allowed => Vec::new(),
+            SessionLimit::Reached { limit } => return Err(RouteError::TooManySessions { limit }),
+            SessionLimit::Evict(sessions) => {
+                sessions.iter().map(session_limit::display_name).collect()
+            }
+        };
+
+    // If the user previously chose to remember their decision for this client,
+    // and it covers all the requested scopes, skip the consent screen
+    if !client.always_prompt_consent && evicted_sessions.is_empty() {
+        let consent = repo
+            .oauth2_client()
+            .get_consent_for_user(&client, &session.user)
+            .await?;
+
+        if without_device_scopes(&grant.scope).is_subset(&consent) {
+            let callback_destination = CallbackDestination::try_from(&grant)?
+                .with_response_signing(&mut rng, &clock, &key_store, &url_builder, &client)?;
+            let response = complete_grant(
+                &mut rng,
+                &clock,
+                &templates,
+                &key_store,
+                &url_builder,
+                &claims_augmentors,
+                &*homeserver,
+                &site_config,
+                repo,
+                &activity_tracker,
+                &locale,
+                &session,
+                &client,
+                grant,
+                acr,
+                callback_destination,
+            )
+            .await?;
+
+            return Ok((cookie_jar, response).into_response());
+        }
+    }
+
     // :tchap:
     let email = repo
         .user_email()
@@ -168,7 +269,10 @@ pub(crate) async fn get(
         .map(|user_email| user_email.email.clone());
     // :tchap: end
 
+    let can_remember = !client.always_prompt_consent;
     let ctx = ConsentContext::new(grant, client)
+        .with_can_remember(can_remember)
+        .with_evicted_sessions(evicted_sessions)
         // :tchap:
         .with_email(email)
         // :tchap: end
@@ -198,13 +302,22 @@ pub(crate) async fn post(
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     cookie_jar: CookieJar,
     State(url_builder): State<UrlBuilder>,
+    State(claims_augmentors): State<ClaimsAugmentors>,
+    State(homeserver): State<Arc<dyn HomeserverConnection>>,
+    State(site_config): State<SiteConfig>,
     Path(grant_id): Path<Ulid>,
-    Form(form): Form<ProtectedForm<()>>,
+    Form(form): Form<ProtectedForm<ConsentForm>>,
 ) -> Result<Response, RouteError> {
-    cookie_jar.verify_form(&clock, form)?;
+    let form = cookie_jar.verify_form(&clock, form)?;
 
     let (cookie_jar, maybe_session) = match load_session_or_fallback(
-        cookie_jar, &clock, &mut rng, &templates, &locale, &mut repo,
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
     )
     .await?
     {
@@ -225,7 +338,6 @@ pub(crate) async fn post(
         .lookup(grant_id)
         .await?
         .ok_or(RouteError::GrantNotFound)?;
-    let callback_destination = CallbackDestination::try_from(&grant)?;
 
     let Some(browser_session) = maybe_session else {
         let next = PostAuthAction::continue_grant(grant_id);
@@ -233,6 +345,13 @@ pub(crate) async fn post(
         return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
     };
 
+    let AuthenticationCheck::Satisfied { acr } =
+        check_authentication(&mut repo, &grant, &browser_session).await?
+    else {
+        let reauth = mas_router::Reauth::and_then(PostAuthAction::continue_grant(grant_id));
+        return Ok((cookie_jar, url_builder.redirect(&reauth)).into_response());
+    };
+
     activity_tracker
         .record_browser_session(&clock, &browser_session)
         .await;
@@ -247,6 +366,14 @@ pub(crate) async fn post(
         return Err(RouteError::GrantNotPending(grant.id));
     }
 
+    let callback_destination = CallbackDestination::try_from(&grant)?.with_response_signing(
+        &mut rng,
+        &clock,
+        &key_store,
+        &url_builder,
+        &client,
+    )?;
+
     let res = policy
         .evaluate_authorization_grant(mas_policy::AuthorizationGrantInput {
             user: Some(&browser_session.user),
@@ -262,6 +389,7 @@ pub(crate) async fn post(
 
     if !res.valid() {
         let ctx = PolicyViolationContext::for_authorization_grant(grant, client)
+            .with_violation_code(res.code().map(|code| code.as_str()))
             .with_session(browser_session)
             .with_csrf(csrf_token.form_value())
             .with_language(locale);
@@ -271,41 +399,165 @@ pub(crate) async fn post(
         return Ok((cookie_jar, Html(content)).into_response());
     }
 
+    // Remember the decision if the user asked for it
+    if !form.remember.is_empty() && !client.always_prompt_consent {
+        repo.oauth2_client()
+            .give_consent_for_user(
+                &mut rng,
+                &clock,
+                &client,
+                &browser_session.user,
+                &grant.scope,
+            )
+            .await?;
+    }
+
+    let response = complete_grant(
+        &mut rng,
+        &clock,
+        &templates,
+        &key_store,
+        &url_builder,
+        &claims_augmentors,
+        &*homeserver,
+        &site_config,
+        repo,
+        &activity_tracker,
+        &locale,
+        &browser_session,
+        &client,
+        grant,
+        acr,
+        callback_destination,
+    )
+    .await?;
+
+    Ok((cookie_jar, response).into_response())
+}
+
+/// Start the session for a consented authorization grant, and redirect back to
+/// the client
+#[allow(clippy::too_many_arguments)]
+/// Figure out how the user authenticated from the last authentication of their
+/// browser session
+async fn authenticated_by(
+    repo: &mut BoxRepository,
+    last_authentication: Option<&Authentication>,
+) -> Result<AuthenticatedBy, RepositoryError> {
+    let Some(last_authentication) = last_authentication else {
+        return Ok(AuthenticatedBy::Unknown);
+    };
+
+    match last_authentication.authentication_method {
+        AuthenticationMethod::Password { .. } => Ok(AuthenticatedBy::Password),
+        AuthenticationMethod::UpstreamOAuth2 {
+            upstream_oauth2_session_id,
+        } => {
+            let upstream_session = repo
+                .upstream_oauth_session()
+                .lookup(upstream_oauth2_session_id)
+                .await?;
+            Ok(
+                upstream_session.map_or(AuthenticatedBy::Unknown, |session| {
+                    AuthenticatedBy::Upstream {
+                        provider_id: session.provider_id,
+                    }
+                }),
+            )
+        }
+        AuthenticationMethod::Unknown => Ok(AuthenticatedBy::Unknown),
+    }
+}
+
+pub(super) async fn complete_grant(
+    rng: &mut BoxRng,
+    clock: &impl Clock,
+    templates: &Templates,
+    key_store: &Keystore,
+    url_builder: &UrlBuilder,
+    claims_augmentors: &ClaimsAugmentors,
+    homeserver: &dyn HomeserverConnection,
+    site_config: &SiteConfig,
+    mut repo: BoxRepository,
+    activity_tracker: &BoundActivityTracker,
+    locale: &DataLocale,
+    browser_session: &BrowserSession,
+    client: &Client,
+    grant: AuthorizationGrant,
+    acr: Option<String>,
+    callback_destination: CallbackDestination,
+) -> Result<Response, RouteError> {
+    // Check the session limit of the user again, as other sessions may have
+    // started since the consent screen was displayed
+    let devices: Vec<Device> = grant
+        .scope
+        .iter()
+        .filter_map(Device::from_scope_token)
+        .collect();
+    match session_limit::check(&mut repo, site_config, &browser_session.user, &devices).await? {
+        SessionLimit::Allowed => {}
+        SessionLimit::Reached { limit } => return Err(RouteError::TooManySessions { limit }),
+        SessionLimit::Evict(sessions) => {
+            session_limit::evict(
+                &mut repo,
+                rng,
+                clock,
+                site_config,
+                activity_tracker.ip(),
+                &browser_session.user,
+                sessions,
+            )
+            .await?;
+        }
+    }
+
+    // Fetch the last authentication, to record how the user authenticated on the
+    // session and to put it in the ID token
+    let last_authentication = repo
+        .browser_session()
+        .get_last_authentication(browser_session)
+        .await?;
+    let authenticated_by = authenticated_by(&mut repo, last_authentication.as_ref()).await?;
+
     // All good, let's start the session
     let session = repo
         .oauth2_session()
-        .add_from_browser_session(
-            &mut rng,
-            &clock,
-            &client,
-            &browser_session,
-            grant.scope.clone(),
-        )
+        .add_from_browser_session(rng, clock, client, browser_session, grant.scope.clone())
+        .await?;
+    let session = repo
+        .oauth2_session()
+        .set_authenticated_by(session, authenticated_by)
         .await?;
 
     let grant = repo
         .oauth2_authorization_grant()
-        .fulfill(&clock, &session, grant)
+        .fulfill(clock, &session, acr, grant)
         .await?;
 
     let mut params = AuthorizationResponse::default();
 
     // Did they request an ID token?
     if grant.response_type_id_token {
-        // Fetch the last authentication
-        let last_authentication = repo
-            .browser_session()
-            .get_last_authentication(&browser_session)
-            .await?;
+        let user_claims = user_claims::load_for_id_token(
+            &mut repo,
+            homeserver,
+            site_config,
+            &browser_session.user,
+            &grant,
+        )
+        .await?;
 
         params.id_token = Some(generate_id_token(
-            &mut rng,
-            &clock,
-            &url_builder,
-            &key_store,
-            &client,
+            rng,
+            clock,
+            url_builder,
+            key_store,
+            claims_augmentors,
+            client,
             Some(&grant),
-            &browser_session,
+            &session,
+            browser_session,
+            user_claims,
             None,
             last_authentication.as_ref(),
         )?);
@@ -319,12 +571,363 @@ pub(crate) async fn post(
     repo.save().await?;
 
     activity_tracker
-        .record_oauth2_session(&clock, &session)
+        .record_oauth2_session(clock, &session)
         .await;
 
-    Ok((
-        cookie_jar,
-        callback_destination.go(&templates, &locale, params)?,
-    )
-        .into_response())
+    Ok(callback_destination.go(templates, locale, params)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_axum_utils::SessionInfoExt;
+    use mas_data_model::{
+        AuthorizationCode, Client, CompatSession, Device, SessionLimitConfig, SessionLimitStrategy,
+        SiteConfig,
+    };
+    use mas_router::{Route, SimpleRoute};
+    use mas_storage::{
+        RepositoryAccess,
+        compat::CompatSessionRepository,
+        oauth2::{OAuth2AuthorizationGrantRepository, OAuth2ClientRepository},
+        user::{BrowserSessionRepository, UserRepository},
+    };
+    use oauth2_types::{
+        registration::ClientRegistrationResponse,
+        requests::ResponseMode,
+        scope::{EMAIL, OPENID, Scope},
+    };
+    use rand::distributions::{Alphanumeric, DistString};
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{
+        CookieHelper, RequestBuilderExt, ResponseExt, TestState, setup, test_site_config,
+    };
+
+    /// Start a new authorization grant for the given client and scope
+    async fn start_grant(state: &TestState, client: &Client, scope: Scope) -> Ulid {
+        let mut rng = state.rng();
+        let code = Alphanumeric.sample_string(&mut rng, 32);
+        let mut repo = state.repository().await.unwrap();
+        let grant = repo
+            .oauth2_authorization_grant()
+            .add(
+                &mut rng,
+                &state.clock,
+                client,
+                "https://example.com/callback".parse().unwrap(),
+                scope,
+                Some(AuthorizationCode { code, pkce: None }),
+                Some("state".to_owned()),
+                None,
+                ResponseMode::Query,
+                false,
+                None,
+                None,
+                Vec::new(),
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        grant.id
+    }
+
+    /// The `openid` scope, along with the scope of a new device
+    fn scope_with_device(state: &TestState) -> Scope {
+        let device = Device::generate(&mut state.rng());
+        let mut scope = Scope::from_iter([OPENID]);
+        for token in device.to_scope_token().unwrap() {
+            scope.insert(token);
+        }
+        scope
+    }
+
+    /// Load the consent page for a grant, and return the response status and
+    /// the CSRF token if the consent screen was displayed
+    async fn load_consent(
+        state: &TestState,
+        cookies: &CookieHelper,
+        grant_id: Ulid,
+    ) -> (StatusCode, Option<String>) {
+        let request = Request::get(&*mas_router::Consent(grant_id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('\"').next())
+            .map(ToOwned::to_owned);
+
+        (response.status(), csrf_token)
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_remembered_consent(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+
+        // Provision a client
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "token_endpoint_auth_method": "none",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let ClientRegistrationResponse { client_id, .. } = response.json();
+
+        // Provision a user with a browser session
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.save().await.unwrap();
+
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+
+        // The first time, the consent screen is displayed
+        let grant_id = start_grant(&state, &client, scope_with_device(&state)).await;
+        let (status, csrf_token) = load_consent(&state, &cookies, grant_id).await;
+        assert_eq!(status, StatusCode::OK);
+        let csrf_token = csrf_token.unwrap();
+
+        // Accept and ask to remember the decision
+        let request =
+            Request::post(&*mas_router::Consent(grant_id).path()).form(serde_json::json!({
+                "csrf": csrf_token,
+                "remember": "on",
+            }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // Asking for the same scope again skips the consent screen, even with
+        // another device
+        let grant_id = start_grant(&state, &client, scope_with_device(&state)).await;
+        let (status, _) = load_consent(&state, &cookies, grant_id).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+
+        let mut repo = state.repository().await.unwrap();
+        let grant = repo
+            .oauth2_authorization_grant()
+            .lookup(grant_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(grant.stage.is_fulfilled());
+        repo.cancel().await.unwrap();
+
+        // Asking for a broader scope prompts again
+        let grant_id = start_grant(&state, &client, Scope::from_iter([OPENID, EMAIL])).await;
+        let (status, _) = load_consent(&state, &cookies, grant_id).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Revoking the consent prompts again, even for the original scope
+        let mut repo = state.repository().await.unwrap();
+        let revoked = repo
+            .oauth2_client()
+            .revoke_consent_for_user(&client, &user)
+            .await
+            .unwrap();
+        assert_eq!(revoked, 1);
+        repo.save().await.unwrap();
+
+        let grant_id = start_grant(&state, &client, Scope::from_iter([OPENID])).await;
+        let (status, _) = load_consent(&state, &cookies, grant_id).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    /// Provision a client, and a user with a browser session and two sessions
+    /// with a device, the first one being the least recently active
+    async fn setup_session_limit(
+        state: &TestState,
+        cookies: &CookieHelper,
+    ) -> (Client, CompatSession, CompatSession) {
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "token_endpoint_auth_method": "none",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let ClientRegistrationResponse { client_id, .. } = response.json();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let first = repo
+            .compat_session()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &user,
+                Device::try_from("DEVICE1".to_owned()).unwrap(),
+                None,
+                false,
+                Some("Element X on iPhone".to_owned()),
+            )
+            .await
+            .unwrap();
+        state.clock.advance(Duration::try_minutes(1).unwrap());
+        let second = repo
+            .compat_session()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &user,
+                Device::try_from("DEVICE2".to_owned()).unwrap(),
+                None,
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+
+        (client, first, second)
+    }
+
+    /// The scope of a grant for a new session with a device
+    fn device_scope() -> Scope {
+        let device = Device::try_from("DEVICE3".to_owned()).unwrap();
+        let [stable, unstable] = device.to_scope_token().unwrap();
+        Scope::from_iter([OPENID, stable, unstable])
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_session_limit_reject(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                session_limit: SessionLimitConfig {
+                    max_per_user: NonZeroU32::new(2),
+                    strategy: SessionLimitStrategy::Reject,
+                },
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let cookies = CookieHelper::new();
+
+        let (client, _, _) = setup_session_limit(&state, &cookies).await;
+
+        // Asking for a session with a device is refused
+        let grant_id = start_grant(&state, &client, device_scope()).await;
+        let (status, _) = load_consent(&state, &cookies, grant_id).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Sessions without a device are not limited
+        let grant_id = start_grant(&state, &client, Scope::from_iter([OPENID])).await;
+        let (status, _) = load_consent(&state, &cookies, grant_id).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_session_limit_evict_oldest(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                session_limit: SessionLimitConfig {
+                    max_per_user: NonZeroU32::new(2),
+                    strategy: SessionLimitStrategy::EvictOldest,
+                },
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let cookies = CookieHelper::new();
+
+        let (client, first, second) = setup_session_limit(&state, &cookies).await;
+
+        // The consent screen warns about the session which will be ended
+        let grant_id = start_grant(&state, &client, device_scope()).await;
+        let request = Request::get(&*mas_router::Consent(grant_id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("Element X on iPhone"));
+        assert!(!response.body().contains("DEVICE2"));
+
+        let (_, csrf_token) = load_consent(&state, &cookies, grant_id).await;
+        let csrf_token = csrf_token.unwrap();
+
+        // Accepting ends the least recently active session
+        let request =
+            Request::post(&*mas_router::Consent(grant_id).path()).form(serde_json::json!({
+                "csrf": csrf_token,
+            }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let mut repo = state.repository().await.unwrap();
+        let first = repo
+            .compat_session()
+            .lookup(first.id)
+            .await
+            .unwrap()
+            .unwrap();
+        let second = repo
+            .compat_session()
+            .lookup(second.id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.cancel().await.unwrap();
+        assert!(first.is_finished());
+        assert!(second.is_valid());
+    }
 }
@@ -4,16 +4,25 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::sync::{Arc, LazyLock};
+
 use axum::{
-    extract::{Form, State},
+    extract::{RawQuery, State},
     response::{IntoResponse, Response},
 };
+use axum_extra::TypedHeader;
 use hyper::StatusCode;
 use mas_axum_utils::{GenericError, InternalError, SessionInfoExt, cookies::CookieJar};
-use mas_data_model::{AuthorizationCode, BoxClock, BoxRng, Pkce};
+use mas_data_model::{
+    AuthenticationMethod, AuthorizationCode, AuthorizationGrant, BoxClock, BoxRng, BrowserSession,
+    Client, Clock, Device, Pkce, PushedAuthorizationRequest, SiteConfig, without_device_scopes,
+};
+use mas_keystore::Keystore;
+use mas_matrix::HomeserverConnection;
+use mas_policy::Policy;
 use mas_router::{PostAuthAction, UrlBuilder};
 use mas_storage::{
-    BoxRepository,
+    BoxRepository, RepositoryError,
     oauth2::{OAuth2AuthorizationGrantRepository, OAuth2ClientRepository},
 };
 use mas_templates::Templates;
@@ -23,16 +32,34 @@ use oauth2_types::{
     requests::{AuthorizationRequest, GrantType, Prompt, ResponseMode},
     response_type::ResponseType,
 };
+use opentelemetry::{Key, KeyValue, metrics::Counter};
 use rand::{Rng, distributions::Alphanumeric};
 use serde::Deserialize;
 use thiserror::Error;
 
 use self::callback::CallbackDestination;
-use crate::{BoundActivityTracker, PreferredLanguage, impl_from_error_for_route};
+use crate::{
+    BoundActivityTracker, METER, PreferredLanguage, impl_from_error_for_route,
+    oauth2::{claims_augmentor::ClaimsAugmentors, restrict_client_scope},
+    session::needs_reauthentication,
+    session_limit::{self, SessionLimit},
+};
 
 mod callback;
 pub(crate) mod consent;
 
+static PUSHED_AUTHORIZATION_REQUEST_REJECTED_COUNTER: LazyLock<Counter<u64>> =
+    LazyLock::new(|| {
+        METER
+            .u64_counter("mas.oauth2.pushed_authorization_request.rejected")
+            .with_description(
+                "Number of authorization requests rejected because of their request_uri",
+            )
+            .with_unit("{request}")
+            .build()
+    });
+const CAUSE: Key = Key::from_static_str("cause");
+
 #[derive(Debug, Error)]
 pub enum RouteError {
     #[error(transparent)]
@@ -49,6 +76,12 @@ pub enum RouteError {
 
     #[error("invalid redirect uri")]
     UnknownRedirectUri(#[from] mas_data_model::InvalidRedirectUriError),
+
+    #[error("invalid authorization request")]
+    InvalidParameters(#[source] serde_urlencoded::de::Error),
+
+    #[error("unknown request_uri")]
+    UnknownRequestUri,
 }
 
 impl IntoResponse for RouteError {
@@ -58,7 +91,9 @@ impl IntoResponse for RouteError {
             e @ (Self::ClientNotFound
             | Self::InvalidResponseMode
             | Self::IntoCallbackDestination(_)
-            | Self::UnknownRedirectUri(_)) => {
+            | Self::UnknownRedirectUri(_)
+            | Self::InvalidParameters(_)
+            | Self::UnknownRequestUri) => {
                 GenericError::new(StatusCode::BAD_REQUEST, e).into_response()
             }
         }
@@ -70,14 +105,97 @@ impl_from_error_for_route!(mas_templates::TemplateError);
 impl_from_error_for_route!(self::callback::CallbackDestinationError);
 impl_from_error_for_route!(mas_policy::LoadError);
 impl_from_error_for_route!(mas_policy::EvaluationError);
+impl_from_error_for_route!(self::consent::RouteError);
 
 #[derive(Deserialize)]
 pub(crate) struct Params {
     #[serde(flatten)]
-    auth: AuthorizationRequest,
+    pub(crate) auth: AuthorizationRequest,
 
     #[serde(flatten)]
-    pkce: Option<pkce::AuthorizationRequest>,
+    pub(crate) pkce: Option<pkce::AuthorizationRequest>,
+}
+
+/// The parameters of an authorization request referencing a pushed
+/// authorization request
+#[derive(Deserialize)]
+struct RequestUriParams {
+    client_id: String,
+    request_uri: String,
+}
+
+/// Parse the authorization request parameters, loading them from the pushed
+/// authorization request if the request references one
+async fn load_params(
+    repo: &mut BoxRepository,
+    query: &str,
+) -> Result<(Params, Option<PushedAuthorizationRequest>), RouteError> {
+    // Requests which reference a `request_uri` we didn't issue go through the
+    // regular path, which rejects them as unsupported
+    let pushed = serde_urlencoded::from_str::<RequestUriParams>(query)
+        .ok()
+        .and_then(|p| {
+            PushedAuthorizationRequest::id_from_request_uri(&p.request_uri)
+                .map(|id| (p.client_id, id))
+        });
+
+    let Some((client_id, id)) = pushed else {
+        let params = serde_urlencoded::from_str(query).map_err(RouteError::InvalidParameters)?;
+        return Ok((params, None));
+    };
+
+    let Some(pushed_authorization_request) = repo
+        .oauth2_pushed_authorization_request()
+        .lookup(id)
+        .await?
+    else {
+        PUSHED_AUTHORIZATION_REQUEST_REJECTED_COUNTER.add(1, &[KeyValue::new(CAUSE, "not_found")]);
+        return Err(RouteError::UnknownRequestUri);
+    };
+
+    let params: Params = serde_urlencoded::from_str(&pushed_authorization_request.parameters)
+        .map_err(RouteError::InvalidParameters)?;
+
+    // The request_uri is bound to the client which pushed it
+    if params.auth.client_id != client_id {
+        PUSHED_AUTHORIZATION_REQUEST_REJECTED_COUNTER
+            .add(1, &[KeyValue::new(CAUSE, "client_mismatch")]);
+        return Err(RouteError::UnknownRequestUri);
+    }
+
+    Ok((params, Some(pushed_authorization_request)))
+}
+
+/// Mark the pushed authorization request as used, returning the error to send
+/// back to the client if it can't be used
+async fn consume_pushed_authorization_request(
+    repo: &mut BoxRepository,
+    clock: &dyn Clock,
+    pushed_authorization_request: PushedAuthorizationRequest,
+) -> Result<Option<ClientError>, RouteError> {
+    if pushed_authorization_request.is_expired(clock.now()) {
+        PUSHED_AUTHORIZATION_REQUEST_REJECTED_COUNTER.add(1, &[KeyValue::new(CAUSE, "expired")]);
+        return Ok(Some(
+            ClientError::from(ClientErrorCode::InvalidRequestUri)
+                .with_description("The request_uri has expired".to_owned()),
+        ));
+    }
+
+    // This fails if another request consumed it first, even concurrently
+    let consumed = repo
+        .oauth2_pushed_authorization_request()
+        .consume(clock, pushed_authorization_request)
+        .await?;
+
+    if consumed.is_none() {
+        PUSHED_AUTHORIZATION_REQUEST_REJECTED_COUNTER.add(1, &[KeyValue::new(CAUSE, "consumed")]);
+        return Ok(Some(
+            ClientError::from(ClientErrorCode::InvalidRequestUri)
+                .with_description("The request_uri has already been used".to_owned()),
+        ));
+    }
+
+    Ok(None)
 }
 
 /// Given a list of response types and an optional user-defined response mode,
@@ -90,23 +208,162 @@ fn resolve_response_mode(
     use ResponseMode as M;
 
     // If the response type includes either "token" or "id_token", the default
-    // response mode is "fragment" and the response mode "query" must not be
-    // used
+    // response mode is "fragment" and the response modes "query" and
+    // "query.jwt" must not be used
     if response_type.has_token() || response_type.has_id_token() {
         match suggested_response_mode {
             None => Ok(M::Fragment),
-            Some(M::Query) => Err(RouteError::InvalidResponseMode),
+            Some(M::Jwt) => Ok(M::FragmentJwt),
+            Some(M::Query | M::QueryJwt) => Err(RouteError::InvalidResponseMode),
             Some(mode) => Ok(mode),
         }
     } else {
         // In other cases, all response modes are allowed, defaulting to "query"
-        Ok(suggested_response_mode.unwrap_or(M::Query))
+        match suggested_response_mode {
+            None => Ok(M::Query),
+            Some(M::Jwt) => Ok(M::QueryJwt),
+            Some(mode) => Ok(mode),
+        }
+    }
+}
+
+/// Whether the Authentication Context Class References requested by a client
+/// include at least one we know how to satisfy
+fn acr_values_supported(acr_values: &[String]) -> bool {
+    acr_values
+        .iter()
+        .any(|acr| AuthenticationMethod::SUPPORTED_ACR_VALUES.contains(&acr.as_str()))
+}
+
+/// Outcome of checking a browser session against the authentication
+/// requirements of an authorization grant
+pub(crate) enum AuthenticationCheck {
+    /// The user has to authenticate again before continuing
+    Reauthenticate,
+
+    /// The session can be used, and satisfied the given Authentication Context
+    /// Class Reference, if any
+    Satisfied { acr: Option<String> },
+}
+
+/// Check whether a browser session satisfies the `max_age` and `acr_values`
+/// requested by the client for this authorization grant
+pub(crate) async fn check_authentication(
+    repo: &mut BoxRepository,
+    grant: &AuthorizationGrant,
+    browser_session: &BrowserSession,
+) -> Result<AuthenticationCheck, RepositoryError> {
+    let last_authentication = repo
+        .browser_session()
+        .get_last_authentication(browser_session)
+        .await?;
+
+    let authenticated_at = last_authentication
+        .as_ref()
+        .map_or(browser_session.created_at, |a| a.created_at);
+
+    if !grant.is_authentication_fresh(authenticated_at) {
+        return Ok(AuthenticationCheck::Reauthenticate);
+    }
+
+    let acr = last_authentication
+        .as_ref()
+        .and_then(|a| a.authentication_method.acr());
+
+    // If the client asked for an ACR we know how to satisfy, but the last
+    // authentication didn't, ask for a new authentication. This is only done
+    // once per grant: the `acr_values` are voluntary, so if the user still
+    // can't satisfy them, we continue with whatever they did satisfy.
+    let acr_satisfied = acr.is_some_and(|acr| grant.acr_values.iter().any(|v| v == acr));
+    if !grant.acr_values.is_empty()
+        && !acr_satisfied
+        && acr_values_supported(&grant.acr_values)
+        && authenticated_at < grant.created_at
+    {
+        return Ok(AuthenticationCheck::Reauthenticate);
+    }
+
+    Ok(AuthenticationCheck::Satisfied {
+        acr: acr.map(ToOwned::to_owned),
+    })
+}
+
+/// Check whether an authorization grant can be completed without any
+/// interaction with the user, as requested by the client with `prompt=none`.
+///
+/// Returns the ACR satisfied by the session if so, or the error to send back to
+/// the client otherwise.
+#[allow(clippy::too_many_arguments)]
+async fn check_silent_authorization(
+    repo: &mut BoxRepository,
+    clock: &impl Clock,
+    site_config: &SiteConfig,
+    policy: &mut Policy,
+    activity_tracker: &BoundActivityTracker,
+    user_agent: Option<String>,
+    client: &Client,
+    grant: &AuthorizationGrant,
+    browser_session: &BrowserSession,
+) -> Result<Result<Option<String>, ClientErrorCode>, RouteError> {
+    if needs_reauthentication(repo, clock, site_config, browser_session).await? {
+        return Ok(Err(ClientErrorCode::LoginRequired));
+    }
+
+    let AuthenticationCheck::Satisfied { acr } =
+        check_authentication(repo, grant, browser_session).await?
+    else {
+        return Ok(Err(ClientErrorCode::LoginRequired));
+    };
+
+    // A policy violation would be shown to the user
+    let res = policy
+        .evaluate_authorization_grant(mas_policy::AuthorizationGrantInput {
+            user: Some(&browser_session.user),
+            client,
+            scope: &grant.scope,
+            grant_type: mas_policy::GrantType::AuthorizationCode,
+            requester: mas_policy::Requester {
+                ip_address: activity_tracker.ip(),
+                user_agent,
+            },
+        })
+        .await?;
+    if !res.valid() {
+        return Ok(Err(ClientErrorCode::InteractionRequired));
+    }
+
+    // So would the sessions ended to make room for this one
+    let devices: Vec<Device> = grant
+        .scope
+        .iter()
+        .filter_map(Device::from_scope_token)
+        .collect();
+    match session_limit::check(repo, site_config, &browser_session.user, &devices).await? {
+        SessionLimit::Allowed => {}
+        SessionLimit::Reached { .. } | SessionLimit::Evict(_) => {
+            return Ok(Err(ClientErrorCode::InteractionRequired));
+        }
+    }
+
+    // The user must have already consented to all the requested scopes
+    if client.always_prompt_consent {
+        return Ok(Err(ClientErrorCode::ConsentRequired));
     }
+
+    let consent = repo
+        .oauth2_client()
+        .get_consent_for_user(client, &browser_session.user)
+        .await?;
+    if !without_device_scopes(&grant.scope).is_subset(&consent) {
+        return Ok(Err(ClientErrorCode::ConsentRequired));
+    }
+
+    Ok(Ok(acr))
 }
 
 #[tracing::instrument(
     name = "handlers.oauth2.authorization.get",
-    fields(client.id = %params.auth.client_id),
+    fields(client.id),
     skip_all,
 )]
 pub(crate) async fn get(
@@ -115,11 +372,22 @@ pub(crate) async fn get(
     PreferredLanguage(locale): PreferredLanguage,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    State(key_store): State<Keystore>,
+    State(claims_augmentors): State<ClaimsAugmentors>,
+    State(homeserver): State<Arc<dyn HomeserverConnection>>,
+    mut policy: Policy,
     activity_tracker: BoundActivityTracker,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
     mut repo: BoxRepository,
     cookie_jar: CookieJar,
-    Form(params): Form<Params>,
+    RawQuery(query): RawQuery,
 ) -> Result<Response, RouteError> {
+    let (params, pushed_authorization_request) =
+        load_params(&mut repo, query.as_deref().unwrap_or_default()).await?;
+    tracing::Span::current().record("client.id", &params.auth.client_id);
+    let user_agent = user_agent.map(|ua| ua.to_string());
+
     // First, figure out what client it is
     let client = repo
         .oauth2_client()
@@ -139,7 +407,8 @@ pub(crate) async fn get(
         &response_mode,
         redirect_uri.clone(),
         params.auth.state.clone(),
-    )?;
+    )?
+    .with_response_signing(&mut rng, &clock, &key_store, &url_builder, &client)?;
 
     // Get the session info from the cookie
     let (session_info, cookie_jar) = cookie_jar.session_info();
@@ -150,6 +419,17 @@ pub(crate) async fn get(
         let callback_destination = callback_destination.clone();
         let locale = locale.clone();
         async move {
+            if let Some(pushed_authorization_request) = pushed_authorization_request
+                && let Some(error) = consume_pushed_authorization_request(
+                    &mut repo,
+                    &clock,
+                    pushed_authorization_request,
+                )
+                .await?
+            {
+                return Ok(callback_destination.go(&templates, &locale, error)?);
+            }
+
             let maybe_session = session_info.load_active_session(&mut repo).await?;
             let prompt = params.auth.prompt.as_deref().unwrap_or_default();
 
@@ -199,13 +479,41 @@ pub(crate) async fn get(
                 )?);
             }
 
-            // Fail early if prompt=none; we never let it go through
-            if prompt.contains(&Prompt::None) {
+            // Don't even create the grant if the client asked for scopes it is not
+            // allowed to request
+            let Ok(scope) = restrict_client_scope(&site_config, &client, params.auth.scope) else {
                 return Ok(callback_destination.go(
                     &templates,
                     &locale,
-                    ClientError::from(ClientErrorCode::LoginRequired),
+                    ClientError::from(ClientErrorCode::InvalidScope),
                 )?);
+            };
+
+            let mut acr_values: Vec<String> = params
+                .auth
+                .acr_values
+                .map(|acr_values| acr_values.into_iter().collect())
+                .unwrap_or_default();
+            acr_values.sort();
+
+            // Fail early if prompt=none and the client asked for ACRs we can't
+            // satisfy at all, or if there is no session to use
+            if prompt.contains(&Prompt::None) {
+                let code = if !acr_values.is_empty() && !acr_values_supported(&acr_values) {
+                    Some(ClientErrorCode::UnmetAuthenticationRequirements)
+                } else if maybe_session.is_none() {
+                    Some(ClientErrorCode::LoginRequired)
+                } else {
+                    None
+                };
+
+                if let Some(code) = code {
+                    return Ok(callback_destination.go(
+                        &templates,
+                        &locale,
+                        ClientError::from(code),
+                    )?);
+                }
             }
 
             let code: Option<AuthorizationCode> = if response_type.has_code() {
@@ -245,6 +553,14 @@ pub(crate) async fn get(
                 None
             };
 
+            // Only the claims requested in the ID token need to be remembered, as the
+            // userinfo endpoint always returns all the claims allowed by the scope
+            let id_token_claims = params
+                .auth
+                .claims
+                .map(|claims| claims.id_token.into_keys().collect())
+                .unwrap_or_default();
+
             let grant = repo
                 .oauth2_authorization_grant()
                 .add(
@@ -252,7 +568,7 @@ pub(crate) async fn get(
                     &clock,
                     &client,
                     redirect_uri.clone(),
-                    params.auth.scope,
+                    scope,
                     code,
                     params.auth.state.clone(),
                     params.auth.nonce,
@@ -260,12 +576,76 @@ pub(crate) async fn get(
                     response_type.has_id_token(),
                     params.auth.login_hint,
                     Some(locale.to_string()),
+                    id_token_claims,
+                    params.auth.max_age,
+                    acr_values,
                 )
                 .await?;
             let continue_grant = PostAuthAction::continue_grant(grant.id);
 
+            // With prompt=none, the grant is either completed right away, or we go
+            // back to the client with an error, never showing anything to the user.
+            // In the latter case, the repository isn't saved, so the grant is
+            // discarded.
+            if prompt.contains(&Prompt::None)
+                && let Some(user_session) = &maybe_session
+            {
+                let acr = match check_silent_authorization(
+                    &mut repo,
+                    &clock,
+                    &site_config,
+                    &mut policy,
+                    &activity_tracker,
+                    user_agent,
+                    &client,
+                    &grant,
+                    user_session,
+                )
+                .await?
+                {
+                    Ok(acr) => acr,
+                    Err(code) => {
+                        return Ok(callback_destination.go(
+                            &templates,
+                            &locale,
+                            ClientError::from(code),
+                        )?);
+                    }
+                };
+
+                activity_tracker
+                    .record_browser_session(&clock, user_session)
+                    .await;
+
+                let response = consent::complete_grant(
+                    &mut rng,
+                    &clock,
+                    &templates,
+                    &key_store,
+                    &url_builder,
+                    &claims_augmentors,
+                    &*homeserver,
+                    &site_config,
+                    repo,
+                    &activity_tracker,
+                    &locale,
+                    user_session,
+                    &client,
+                    grant,
+                    acr,
+                    callback_destination,
+                )
+                .await?;
+
+                return Ok(response);
+            }
+
             let res = match maybe_session {
-                None if prompt.contains(&Prompt::Create) => {
+                // Only honour prompt=create if registration is available, which is also
+                // when we advertise it in the discovery document
+                None if prompt.contains(&Prompt::Create)
+                    && site_config.password_registration_enabled =>
+                {
                     // Client asked for a registration, show the registration prompt
                     repo.save().await?;
 
@@ -275,7 +655,8 @@ pub(crate) async fn get(
                 }
 
                 None => {
-                    // Other cases where we don't have a session, ask for a login
+                    // Other cases where we don't have a session, including prompt=create
+                    // when registration is disabled, ask for a login
                     repo.save().await?;
 
                     url_builder
@@ -284,15 +665,25 @@ pub(crate) async fn get(
                 }
 
                 Some(user_session) => {
-                    // TODO: better support for prompt=create when we have a session
+                    // We already have a session, so there is nothing to register: prompt=create
+                    // is ignored. If the session doesn't satisfy the max_age or acr_values
+                    // asked by the client, the user has to authenticate again, else we go
+                    // straight to the consent screen
+                    let check = check_authentication(&mut repo, &grant, &user_session).await?;
                     repo.save().await?;
 
                     activity_tracker
                         .record_browser_session(&clock, &user_session)
                         .await;
-                    url_builder
-                        .redirect(&mas_router::Consent(grant.id))
-                        .into_response()
+
+                    match check {
+                        AuthenticationCheck::Reauthenticate => url_builder
+                            .redirect(&mas_router::Reauth::and_then(continue_grant))
+                            .into_response(),
+                        AuthenticationCheck::Satisfied { .. } => url_builder
+                            .redirect(&mas_router::Consent(grant.id))
+                            .into_response(),
+                    }
                 }
             };
 
@@ -315,3 +706,647 @@ pub(crate) async fn get(
 
     Ok((cookie_jar, response).into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::Duration;
+    use hyper::{Request, StatusCode, header::LOCATION};
+    use mas_axum_utils::SessionInfoExt;
+    use std::num::NonZeroU32;
+
+    use mas_data_model::{
+        AuthenticatedBy, AuthenticationMethod, BrowserSession, Clock, Device, SessionLimitConfig,
+        SessionLimitStrategy, SiteConfig,
+    };
+    use mas_iana::jose::JsonWebSignatureAlg;
+    use mas_jose::jwt::Jwt;
+    use mas_router::{Route, SimpleRoute};
+    use mas_storage::{Pagination, oauth2::OAuth2SessionFilter};
+    use oauth2_types::{
+        registration::ClientRegistrationResponse,
+        requests::AccessTokenResponse,
+        scope::{OPENID, Scope},
+    };
+    use sqlx::PgPool;
+    use url::Url;
+
+    use crate::test_utils::{
+        CookieHelper, RequestBuilderExt, ResponseExt, TestState, setup, test_site_config,
+    };
+
+    /// Provision a client which can use the authorization code grant
+    async fn create_client(state: &TestState) -> String {
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "token_endpoint_auth_method": "none",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let ClientRegistrationResponse { client_id, .. } = response.json();
+        client_id
+    }
+
+    /// Start an authorization request with `prompt=create`, and return where
+    /// the user was redirected to
+    async fn authorize_with_prompt_create(
+        state: &TestState,
+        cookies: &CookieHelper,
+        client_id: &str,
+    ) -> String {
+        let request = Request::get(format!(
+            "{}?client_id={client_id}&redirect_uri=https://example.com/callback\
+&response_type=code&scope=openid&state=abc&prompt=create",
+            mas_router::OAuth2AuthorizationEndpoint::PATH,
+        ))
+        .empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        response
+            .headers()
+            .get(LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_prompt_create_redirects_to_registration(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+        let client_id = create_client(&state).await;
+
+        let location = authorize_with_prompt_create(&state, &cookies, &client_id).await;
+        assert!(
+            location.starts_with("/register?kind=continue_authorization_grant&id="),
+            "{location}"
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_prompt_create_registration_disabled(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                password_registration_enabled: false,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let cookies = CookieHelper::new();
+        let client_id = create_client(&state).await;
+
+        let location = authorize_with_prompt_create(&state, &cookies, &client_id).await;
+        assert!(
+            location.starts_with("/login?kind=continue_authorization_grant&id="),
+            "{location}"
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_prompt_create_with_session(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+        let client_id = create_client(&state).await;
+
+        // Provision a user with a browser session
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+
+        let location = authorize_with_prompt_create(&state, &cookies, &client_id).await;
+        assert!(location.starts_with("/consent/"), "{location}");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_claims_parameter(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+        let client_id = create_client(&state).await;
+
+        // Provision a user with a browser session
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+
+        let query = serde_urlencoded::to_string([
+            ("client_id", client_id.as_str()),
+            ("redirect_uri", "https://example.com/callback"),
+            ("response_type", "code"),
+            ("scope", "openid email"),
+            ("state", "abc"),
+            (
+                "claims",
+                r#"{"id_token":{"email":null,"email_verified":{"essential":true}}}"#,
+            ),
+        ])
+        .unwrap();
+        let request = Request::get(format!(
+            "{}?{query}",
+            mas_router::OAuth2AuthorizationEndpoint::PATH
+        ))
+        .empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // The claims asked in the ID token are saved on the grant
+        let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+        let grant_id = location.strip_prefix("/consent/").unwrap().parse().unwrap();
+        let mut repo = state.repository().await.unwrap();
+        let grant = repo
+            .oauth2_authorization_grant()
+            .lookup(grant_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(grant.id_token_claims, ["email", "email_verified"]);
+    }
+
+    /// Provision a user with a browser session. If `with_password` is set, the
+    /// session is authenticated with a password
+    async fn create_session(state: &TestState, with_password: bool) -> BrowserSession {
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+
+        if with_password {
+            let password = repo
+                .user_password()
+                .add(
+                    &mut state.rng(),
+                    &state.clock,
+                    &user,
+                    1,
+                    "hashed".to_owned(),
+                    None,
+                )
+                .await
+                .unwrap();
+            repo.browser_session()
+                .authenticate_with_password(
+                    &mut state.rng(),
+                    &state.clock,
+                    &browser_session,
+                    &password,
+                )
+                .await
+                .unwrap();
+        }
+
+        repo.save().await.unwrap();
+        browser_session
+    }
+
+    /// Start an authorization request with the given additional parameters,
+    /// which override the default ones, and return where the user was
+    /// redirected to
+    async fn authorize(
+        state: &TestState,
+        cookies: &CookieHelper,
+        client_id: &str,
+        params: &[(&str, &str)],
+    ) -> String {
+        let mut query = vec![
+            ("client_id", client_id),
+            ("redirect_uri", "https://example.com/callback"),
+            ("response_type", "code"),
+            ("scope", "openid"),
+            ("state", "abc"),
+        ];
+        query.retain(|(key, _)| params.iter().all(|(k, _)| k != key));
+        query.extend_from_slice(params);
+        let query = serde_urlencoded::to_string(query).unwrap();
+
+        let request = Request::get(format!(
+            "{}?{query}",
+            mas_router::OAuth2AuthorizationEndpoint::PATH
+        ))
+        .empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        response
+            .headers()
+            .get(LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_max_age(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+        let client_id = create_client(&state).await;
+
+        let browser_session = create_session(&state, true).await;
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+
+        // The session was authenticated recently enough
+        let location = authorize(&state, &cookies, &client_id, &[("max_age", "3600")]).await;
+        assert!(location.starts_with("/consent/"), "{location}");
+
+        // But not anymore after two hours
+        state.clock.advance(Duration::try_hours(2).unwrap());
+        let location = authorize(&state, &cookies, &client_id, &[("max_age", "3600")]).await;
+        assert!(
+            location.starts_with("/reauth?kind=continue_authorization_grant&id="),
+            "{location}"
+        );
+
+        // max_age=0 always forces the user to authenticate again, and the consent
+        // screen can't be used to skip it
+        let location = authorize(&state, &cookies, &client_id, &[("max_age", "0")]).await;
+        let grant_id = location
+            .strip_prefix("/reauth?kind=continue_authorization_grant&id=")
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let request = Request::get(&*mas_router::Consent(grant_id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        assert_eq!(response.headers().get(LOCATION).unwrap(), &*location);
+
+        // Once the user authenticated again, the consent screen is shown
+        state.clock.advance(Duration::try_seconds(1).unwrap());
+        let mut repo = state.repository().await.unwrap();
+        let password = repo
+            .user_password()
+            .active(&browser_session.user)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.browser_session()
+            .authenticate_with_password(&mut state.rng(), &state.clock, &browser_session, &password)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get(&*mas_router::Consent(grant_id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_acr_values(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+        let client_id = create_client(&state).await;
+
+        // With prompt=none, ACRs we don't know about can't be satisfied
+        let location = authorize(
+            &state,
+            &cookies,
+            &client_id,
+            &[("prompt", "none"), ("acr_values", "urn:example:mfa")],
+        )
+        .await;
+        let url = Url::parse(&location).unwrap();
+        let query: HashMap<_, _> = url.query_pairs().collect();
+        assert_eq!(query["error"], "unmet_authentication_requirements");
+
+        // The session wasn't authenticated with a password, so the user has to
+        // authenticate again to satisfy the ACR
+        let browser_session = create_session(&state, false).await;
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+        state.clock.advance(Duration::try_seconds(1).unwrap());
+
+        let location = authorize(
+            &state,
+            &cookies,
+            &client_id,
+            &[("acr_values", AuthenticationMethod::PASSWORD_ACR)],
+        )
+        .await;
+        let grant_id = location
+            .strip_prefix("/reauth?kind=continue_authorization_grant&id=")
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        state.clock.advance(Duration::try_seconds(1).unwrap());
+        let mut repo = state.repository().await.unwrap();
+        let password = repo
+            .user_password()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &browser_session.user,
+                1,
+                "hashed".to_owned(),
+                None,
+            )
+            .await
+            .unwrap();
+        repo.browser_session()
+            .authenticate_with_password(&mut state.rng(), &state.clock, &browser_session, &password)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Accept on the consent screen
+        let request = Request::get(&*mas_router::Consent(grant_id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let body = response.body();
+        let csrf_token = body
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('\"').next())
+            .unwrap();
+
+        let request = Request::post(&*mas_router::Consent(grant_id).path())
+            .form(serde_json::json!({ "csrf": csrf_token }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+        let url = Url::parse(location).unwrap();
+        let query: HashMap<_, _> = url.query_pairs().collect();
+
+        // Exchange the code, the satisfied ACR is in the ID token
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": query["code"],
+                "redirect_uri": "https://example.com/callback",
+                "client_id": client_id,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let AccessTokenResponse { id_token, .. } = response.json();
+        let id_token: Jwt<'_, HashMap<String, serde_json::Value>> =
+            Jwt::try_from(id_token.as_deref().unwrap()).unwrap();
+        assert_eq!(
+            id_token.payload()["acr"],
+            AuthenticationMethod::PASSWORD_ACR
+        );
+
+        // The session records that the user authenticated with a password
+        let mut repo = state.repository().await.unwrap();
+        let sessions = repo
+            .oauth2_session()
+            .list(
+                OAuth2SessionFilter::new().for_user(&browser_session.user),
+                Pagination::first(1),
+            )
+            .await
+            .unwrap();
+        repo.cancel().await.unwrap();
+        assert_eq!(
+            sessions.edges[0].node.authenticated_by,
+            AuthenticatedBy::Password
+        );
+    }
+
+    /// Get the query parameters of the redirect back to the client
+    fn callback_params(location: &str) -> HashMap<String, String> {
+        let url = Url::parse(location).unwrap();
+        assert!(location.starts_with("https://example.com/callback?"));
+        url.query_pairs().into_owned().collect()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_prompt_none(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                session_limit: SessionLimitConfig {
+                    max_per_user: NonZeroU32::new(1),
+                    strategy: SessionLimitStrategy::Reject,
+                },
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let cookies = CookieHelper::new();
+        let client_id = create_client(&state).await;
+
+        let device = Device::try_from("DEVICE1".to_owned()).unwrap();
+        let [stable, unstable] = device.to_scope_token().unwrap();
+        let device_scope = Scope::from_iter([OPENID, stable, unstable]);
+        let device_scope_str = device_scope.to_string();
+
+        // Without a session, the user would have to log in
+        let location = authorize(&state, &cookies, &client_id, &[("prompt", "none")]).await;
+        let params = callback_params(&location);
+        assert_eq!(params["error"], "login_required");
+        assert_eq!(params["state"], "abc");
+
+        // With a session, the user would have to consent
+        let browser_session = create_session(&state, true).await;
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+
+        let location = authorize(&state, &cookies, &client_id, &[("prompt", "none")]).await;
+        let params = callback_params(&location);
+        assert_eq!(params["error"], "consent_required");
+
+        // Once the user consented, the grant is completed silently
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.oauth2_client()
+            .give_consent_for_user(
+                &mut state.rng(),
+                &state.clock,
+                &client,
+                &browser_session.user,
+                &device_scope,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let location = authorize(&state, &cookies, &client_id, &[("prompt", "none")]).await;
+        let params = callback_params(&location);
+        assert!(!params.contains_key("error"), "{location}");
+        assert_eq!(params["state"], "abc");
+
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": params["code"],
+                "redirect_uri": "https://example.com/callback",
+                "client_id": client_id,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        // Once the user reached the session limit, a session with a device would
+        // have to be refused on the consent screen
+        let mut repo = state.repository().await.unwrap();
+        repo.compat_session()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &browser_session.user,
+                Device::try_from("DEVICE2".to_owned()).unwrap(),
+                None,
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let location = authorize(
+            &state,
+            &cookies,
+            &client_id,
+            &[("prompt", "none"), ("scope", device_scope_str.as_str())],
+        )
+        .await;
+        let params = callback_params(&location);
+        assert_eq!(params["error"], "interaction_required");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_jwt_response_mode(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+
+        // A client which didn't register a signing algorithm can't use the mode
+        let client_id = create_client(&state).await;
+        let request = Request::get(format!(
+            "{}?client_id={client_id}&redirect_uri=https://example.com/callback\
+&response_type=code&scope=openid&state=abc&response_mode=query.jwt",
+            mas_router::OAuth2AuthorizationEndpoint::PATH,
+        ))
+        .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "token_endpoint_auth_method": "none",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+                "authorization_signed_response_alg": "RS256",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let ClientRegistrationResponse { client_id, .. } = response.json();
+
+        let browser_session = create_session(&state, true).await;
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.oauth2_client()
+            .give_consent_for_user(
+                &mut state.rng(),
+                &state.clock,
+                &client,
+                &browser_session.user,
+                &Scope::from_iter([OPENID]),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // The response parameters are only sent in a signed JWT
+        let location = authorize(
+            &state,
+            &cookies,
+            &client_id,
+            &[("prompt", "none"), ("response_mode", "query.jwt")],
+        )
+        .await;
+        let params = callback_params(&location);
+        assert!(!params.contains_key("code"));
+        assert!(!params.contains_key("state"));
+
+        let response: Jwt<'_, HashMap<String, serde_json::Value>> =
+            Jwt::try_from(params["response"].as_str()).unwrap();
+        response
+            .verify_with_jwks(&state.key_store.public_jwks())
+            .unwrap();
+        assert_eq!(response.header().alg(), &JsonWebSignatureAlg::Rs256);
+
+        let claims = response.payload();
+        assert_eq!(claims["iss"], state.url_builder.oidc_issuer().as_str());
+        assert_eq!(claims["aud"], client_id);
+        assert_eq!(claims["state"], "abc");
+        assert!(claims["exp"].as_i64().unwrap() > state.clock.now().timestamp());
+
+        // The code in the response can be exchanged as usual
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": claims["code"],
+                "redirect_uri": "https://example.com/callback",
+                "client_id": client_id,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Hooks to add custom claims to the ID tokens and userinfo responses
+
+use std::{collections::HashMap, sync::Arc};
+
+use mas_data_model::{Session, TchapFeatures, User};
+use oauth2_types::scope::Scope;
+
+/// Claims which can't be set by a [`ClaimsAugmentor`], because they are
+/// registered claims set by MAS itself
+const RESERVED_CLAIMS: &[&str] = &[
+    "iss",
+    "sub",
+    "aud",
+    "exp",
+    "iat",
+    "nbf",
+    "jti",
+    "auth_time",
+    "nonce",
+    "acr",
+    "amr",
+    "azp",
+    "at_hash",
+    "c_hash",
+    "sid",
+    "username",
+];
+
+/// A hook which adds extra claims to the ID tokens and userinfo responses
+/// issued for a session
+pub trait ClaimsAugmentor: Send + Sync {
+    /// Compute the extra claims to add for the given user and session
+    ///
+    /// Claims which are registered claims (like `sub` or `exp`), or which were
+    /// already set by another augmentor, are ignored.
+    fn claims(
+        &self,
+        user: &User,
+        session: &Session,
+        scope: &Scope,
+    ) -> HashMap<String, serde_json::Value>;
+}
+
+/// The list of [`ClaimsAugmentor`] registered on the server
+#[derive(Clone, Default)]
+pub struct ClaimsAugmentors {
+    augmentors: Vec<Arc<dyn ClaimsAugmentor>>,
+}
+
+impl std::fmt::Debug for ClaimsAugmentors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClaimsAugmentors")
+            .field("augmentors", &self.augmentors.len())
+            .finish()
+    }
+}
+
+impl ClaimsAugmentors {
+    /// The augmentors to register for the enabled Tchap features
+    #[must_use]
+    pub fn from_tchap_features(tchap_features: &TchapFeatures) -> Self {
+        let mut augmentors = Self::default();
+        if tchap_features.organization_claim {
+            augmentors = augmentors.with(OrganizationClaimsAugmentor);
+        }
+        augmentors
+    }
+
+    /// Register a new [`ClaimsAugmentor`]
+    ///
+    /// Augmentors are called in the order they were registered in.
+    #[must_use]
+    pub fn with(mut self, augmentor: impl ClaimsAugmentor + 'static) -> Self {
+        self.augmentors.push(Arc::new(augmentor));
+        self
+    }
+
+    /// Merge the claims of all the registered augmentors into the given claims
+    ///
+    /// Reserved claims and claims already present are never overridden.
+    pub(crate) fn augment(
+        &self,
+        claims: &mut HashMap<String, serde_json::Value>,
+        user: &User,
+        session: &Session,
+    ) {
+        for augmentor in &self.augmentors {
+            for (claim, value) in augmentor.claims(user, session, &session.scope) {
+                if RESERVED_CLAIMS.contains(&claim.as_str()) || claims.contains_key(&claim) {
+                    tracing::warn!(
+                        %claim,
+                        "Ignoring a claim returned by a claims augmentor, as it would override an existing claim"
+                    );
+                    continue;
+                }
+
+                claims.insert(claim, value);
+            }
+        }
+    }
+}
+
+/// Adds the organization of the user, if known, as an `organization` claim
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OrganizationClaimsAugmentor;
+
+impl ClaimsAugmentor for OrganizationClaimsAugmentor {
+    fn claims(
+        &self,
+        user: &User,
+        _session: &Session,
+        _scope: &Scope,
+    ) -> HashMap<String, serde_json::Value> {
+        user.organization
+            .iter()
+            .map(|organization| ("organization".to_owned(), serde_json::json!(organization)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{AuthorizationCode, Session, TchapFeatures, User};
+    use mas_jose::jwt::Jwt;
+    use mas_router::SimpleRoute;
+    use oauth2_types::{
+        registration::ClientRegistrationResponse,
+        requests::{AccessTokenResponse, ResponseMode},
+        scope::{OPENID, Scope},
+    };
+    use sqlx::PgPool;
+
+    use super::{ClaimsAugmentor, ClaimsAugmentors};
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    /// An augmentor which adds the size of the scope, and tries to override
+    /// the subject and the organization
+    struct ScopeSizeAugmentor;
+
+    impl ClaimsAugmentor for ScopeSizeAugmentor {
+        fn claims(
+            &self,
+            _user: &User,
+            _session: &Session,
+            scope: &Scope,
+        ) -> HashMap<String, serde_json::Value> {
+            let mut claims = HashMap::new();
+            claims.insert(
+                "organization".to_owned(),
+                serde_json::json!("not-the-real-organization"),
+            );
+            claims.insert(
+                "scope_size".to_owned(),
+                serde_json::json!(scope.iter().count()),
+            );
+            claims.insert("sub".to_owned(), serde_json::json!("not-the-real-sub"));
+            claims
+        }
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_claims_augmentor(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        state.claims_augmentors = ClaimsAugmentors::from_tchap_features(&TchapFeatures::default())
+            .with(ScopeSizeAugmentor);
+
+        // Provision a client
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "token_endpoint_auth_method": "none",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let ClientRegistrationResponse { client_id, .. } = response.json();
+
+        // Provision a user with an organization, a browser session and a
+        // fulfilled authorization grant
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let user = repo
+            .user()
+            .set_organization(user, Some("Ministère de l'Intérieur".to_owned()))
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let code = "thisisaverysecurecode";
+        let grant = repo
+            .oauth2_authorization_grant()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &client,
+                "https://example.com/callback".parse().unwrap(),
+                Scope::from_iter([OPENID]),
+                Some(AuthorizationCode {
+                    code: code.to_owned(),
+                    pkce: None,
+                }),
+                Some("state".to_owned()),
+                Some("nonce".to_owned()),
+                ResponseMode::Query,
+                false,
+                None,
+                None,
+                Vec::new(),
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        let session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut state.rng(),
+                &state.clock,
+                &client,
+                &browser_session,
+                grant.scope.clone(),
+            )
+            .await
+            .unwrap();
+        let grant = repo
+            .oauth2_authorization_grant()
+            .fulfill(&state.clock, &session, None, grant)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Exchange the code
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": code,
+                "redirect_uri": grant.redirect_uri,
+                "client_id": client.client_id,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let AccessTokenResponse {
+            access_token,
+            id_token,
+            ..
+        } = response.json();
+
+        // The extra claims are in the ID token, but the subject is untouched
+        let id_token: Jwt<'_, HashMap<String, serde_json::Value>> =
+            Jwt::try_from(id_token.as_deref().unwrap()).unwrap();
+        let claims = id_token.payload();
+        assert_eq!(claims["organization"], "Ministère de l'Intérieur");
+        assert_eq!(claims["scope_size"], 1);
+        assert_eq!(claims["sub"], user.sub);
+        assert_eq!(claims["nonce"], "nonce");
+
+        // Same thing in the userinfo response
+        let request = Request::get(mas_router::OidcUserinfo::PATH)
+            .bearer(&access_token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let userinfo: serde_json::Value = response.json();
+        assert_eq!(userinfo["organization"], "Ministère de l'Intérieur");
+        assert_eq!(userinfo["scope_size"], 1);
+        assert_eq!(userinfo["sub"], user.sub);
+        assert_eq!(userinfo["username"], "alice");
+    }
+}
@@ -0,0 +1,630 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Client configuration endpoint, as defined in [RFC7592].
+//!
+//! Dynamically registered clients get a registration access token when they
+//! register, which they can use on this endpoint to read, update or delete
+//! their registration.
+//!
+//! [RFC7592]: https://www.rfc-editor.org/rfc/rfc7592.html
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use axum_extra::TypedHeader;
+use headers::{Authorization, authorization::Bearer};
+use hyper::{HeaderMap, StatusCode, header::WWW_AUTHENTICATE};
+use mas_axum_utils::record_error;
+use mas_data_model::{Client, JwksOrJwksUri};
+use mas_keystore::Encrypter;
+use mas_policy::Policy;
+use mas_router::{OAuth2ClientConfiguration, UrlBuilder};
+use mas_storage::{BoxRepository, oauth2::OAuth2ClientRepository};
+use oauth2_types::{
+    errors::{ClientError, ClientErrorCode},
+    registration::{ClientMetadata, ClientRegistrationResponse, Localized},
+};
+use thiserror::Error;
+use tracing::info;
+
+use super::registration::{self, RouteResponse, validate_client_metadata};
+use crate::{BoundActivityTracker, impl_from_error_for_route};
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("missing or invalid registration access token")]
+    InvalidToken,
+
+    #[error("could not parse the client metadata")]
+    InvalidMetadata(#[source] serde_json::Error),
+
+    #[error("client_id does not match the client being updated")]
+    ClientIdMismatch,
+
+    #[error("token_endpoint_auth_method can't be changed")]
+    AuthMethodChanged,
+
+    #[error("scope can only be narrowed down")]
+    ScopeWidened,
+
+    #[error(transparent)]
+    Registration(#[from] registration::RouteError),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+impl_from_error_for_route!(mas_keystore::DecryptError);
+impl_from_error_for_route!(oauth2_types::registration::ClientMetadataVerificationError);
+impl_from_error_for_route!(std::string::FromUtf8Error);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+
+        let response = match self {
+            Self::Internal(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ClientError::from(ClientErrorCode::ServerError)),
+            )
+                .into_response(),
+
+            // As per RFC7592, an invalid token or an unknown client both result in a 401
+            // response, in the format of RFC6750
+            Self::InvalidToken => {
+                let mut headers = HeaderMap::new();
+                headers.insert(
+                    WWW_AUTHENTICATE,
+                    hyper::header::HeaderValue::from_static(r#"Bearer error="invalid_token""#),
+                );
+                (StatusCode::UNAUTHORIZED, headers).into_response()
+            }
+
+            Self::InvalidMetadata(e) => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidClientMetadata)
+                        .with_description(e.to_string()),
+                ),
+            )
+                .into_response(),
+
+            Self::ClientIdMismatch => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidRequest)
+                        .with_description("client_id does not match".to_owned()),
+                ),
+            )
+                .into_response(),
+
+            Self::AuthMethodChanged => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidClientMetadata)
+                        .with_description("token_endpoint_auth_method can't be changed".to_owned()),
+                ),
+            )
+                .into_response(),
+
+            Self::ScopeWidened => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidClientMetadata)
+                        .with_description("scope can only be narrowed down".to_owned()),
+                ),
+            )
+                .into_response(),
+
+            Self::Registration(e) => e.into_response(),
+        };
+
+        (sentry_event_id, response).into_response()
+    }
+}
+
+/// Find the client matching the registration access token, making sure it is
+/// the one referenced in the path
+async fn authenticate(
+    repo: &mut BoxRepository,
+    client_id: &str,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<(Client, String), RouteError> {
+    let Some(TypedHeader(Authorization(bearer))) = authorization else {
+        return Err(RouteError::InvalidToken);
+    };
+
+    let client = repo
+        .oauth2_client()
+        .find_by_registration_access_token(bearer.token())
+        .await?
+        .filter(|client| client.client_id == client_id)
+        .ok_or(RouteError::InvalidToken)?;
+
+    Ok((client, bearer.token().to_owned()))
+}
+
+/// Build the response for the given client, as described in [RFC7592]
+/// section 3
+///
+/// [RFC7592]: https://www.rfc-editor.org/rfc/rfc7592.html#section-3
+fn client_information_response(
+    client: Client,
+    registration_access_token: String,
+    encrypter: &Encrypter,
+    url_builder: &UrlBuilder,
+) -> Result<RouteResponse, RouteError> {
+    let client_secret = client
+        .encrypted_client_secret
+        .as_deref()
+        .map(|encrypted| encrypter.decrypt_string(encrypted))
+        .transpose()?
+        .map(String::from_utf8)
+        .transpose()?;
+
+    let response = ClientRegistrationResponse {
+        client_id: client.client_id.clone(),
+        client_secret,
+        client_id_issued_at: Some(client.id.datetime().into()),
+        client_secret_expires_at: None,
+        registration_access_token: Some(registration_access_token),
+        registration_client_uri: Some(
+            url_builder.absolute_url_for(&OAuth2ClientConfiguration::new(client.id)),
+        ),
+    };
+
+    // This should never fail, as the client is valid
+    let metadata = client.into_metadata().validate()?;
+
+    Ok(RouteResponse { response, metadata })
+}
+
+#[tracing::instrument(
+    name = "handlers.oauth2.client_configuration.get",
+    fields(client.id = client_id),
+    skip_all,
+)]
+pub(crate) async fn get(
+    mut repo: BoxRepository,
+    State(encrypter): State<Encrypter>,
+    State(url_builder): State<UrlBuilder>,
+    Path(client_id): Path<String>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<impl IntoResponse, RouteError> {
+    let (client, token) = authenticate(&mut repo, &client_id, authorization).await?;
+
+    repo.cancel().await?;
+
+    let response = client_information_response(client, token, &encrypter, &url_builder)?;
+
+    Ok(Json(response))
+}
+
+#[tracing::instrument(
+    name = "handlers.oauth2.client_configuration.put",
+    fields(client.id = client_id),
+    skip_all,
+)]
+pub(crate) async fn put(
+    mut repo: BoxRepository,
+    mut policy: Policy,
+    activity_tracker: BoundActivityTracker,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    State(encrypter): State<Encrypter>,
+    State(url_builder): State<UrlBuilder>,
+    Path(client_id): Path<String>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    body: Result<Json<serde_json::Value>, axum::extract::rejection::JsonRejection>,
+) -> Result<impl IntoResponse, RouteError> {
+    let (client, token) = authenticate(&mut repo, &client_id, authorization).await?;
+
+    let Json(mut body) = body.map_err(registration::RouteError::from)?;
+
+    // The request body is the full client metadata, along with the client_id. The
+    // fields generated by the server are ignored.
+    if let Some(body) = body.as_object_mut() {
+        if body
+            .remove("client_id")
+            .is_some_and(|value| value.as_str() != Some(client.client_id.as_str()))
+        {
+            return Err(RouteError::ClientIdMismatch);
+        }
+
+        for field in [
+            "client_secret",
+            "client_id_issued_at",
+            "client_secret_expires_at",
+            "registration_access_token",
+            "registration_client_uri",
+        ] {
+            body.remove(field);
+        }
+    }
+
+    let body: ClientMetadata = serde_json::from_value(body).map_err(RouteError::InvalidMetadata)?;
+    let body = body.sorted();
+
+    info!(?body, "Client registration update");
+
+    let user_agent = user_agent.map(|ua| ua.to_string());
+    let metadata =
+        validate_client_metadata(body, &mut policy, &activity_tracker, user_agent).await?;
+
+    // Changing the authentication method would mean issuing or revoking a client
+    // secret, which we don't support
+    if metadata.token_endpoint_auth_method()
+        != client.clone().into_metadata().token_endpoint_auth_method()
+    {
+        return Err(RouteError::AuthMethodChanged);
+    }
+
+    // The scopes the client is allowed to request can only be narrowed down, as
+    // the client could otherwise get scopes it wasn't allowed at registration.
+    // Omitting the scope keeps the current ones.
+    let allowed_scopes = match (&client.allowed_scopes, metadata.scope.clone()) {
+        (Some(current), Some(scope)) if !scope.is_subset(current) => {
+            return Err(RouteError::ScopeWidened);
+        }
+        (Some(current), None) => Some(current.clone()),
+        (_, scope) => scope,
+    };
+
+    let jwks = match (metadata.jwks.clone(), metadata.jwks_uri.clone()) {
+        (Some(jwks), _) => Some(JwksOrJwksUri::Jwks(jwks)),
+        (None, Some(jwks_uri)) => Some(JwksOrJwksUri::JwksUri(jwks_uri)),
+        (None, None) => None,
+    };
+
+    let client = Client {
+        // Only clients with a secret have a registration access token, and
+        // those are never deduplicated
+        metadata_digest: None,
+        application_type: metadata.application_type.clone(),
+        redirect_uris: metadata.redirect_uris().to_vec(),
+        grant_types: metadata.grant_types().to_vec(),
+        client_name: metadata
+            .client_name
+            .clone()
+            .map(Localized::to_non_localized),
+        logo_uri: metadata.logo_uri.clone().map(Localized::to_non_localized),
+        client_uri: metadata.client_uri.clone().map(Localized::to_non_localized),
+        policy_uri: metadata.policy_uri.clone().map(Localized::to_non_localized),
+        tos_uri: metadata.tos_uri.clone().map(Localized::to_non_localized),
+        jwks,
+        id_token_signed_response_alg: metadata.id_token_signed_response_alg.clone(),
+        userinfo_signed_response_alg: metadata.userinfo_signed_response_alg.clone(),
+        authorization_signed_response_alg: metadata.authorization_signed_response_alg.clone(),
+        token_endpoint_auth_signing_alg: metadata.token_endpoint_auth_signing_alg.clone(),
+        initiate_login_uri: metadata.initiate_login_uri.clone(),
+        allowed_scopes,
+        ..client
+    };
+
+    let client = repo.oauth2_client().update_metadata(client).await?;
+
+    repo.save().await?;
+
+    tracing::info!(%client.id, "Updated client registration");
+
+    let response = client_information_response(client, token, &encrypter, &url_builder)?;
+
+    Ok(Json(response))
+}
+
+#[tracing::instrument(
+    name = "handlers.oauth2.client_configuration.delete",
+    fields(client.id = client_id),
+    skip_all,
+)]
+pub(crate) async fn delete(
+    mut repo: BoxRepository,
+    Path(client_id): Path<String>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<impl IntoResponse, RouteError> {
+    let (client, _token) = authenticate(&mut repo, &client_id, authorization).await?;
+
+    // This also removes all the sessions and tokens of the client
+    let id = client.id;
+    repo.oauth2_client().delete(client).await?;
+
+    repo.save().await?;
+
+    tracing::info!(client.id = %id, "Deleted client registration");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_router::SimpleRoute;
+    use mas_storage::RepositoryAccess;
+    use oauth2_types::{
+        errors::{ClientError, ClientErrorCode},
+        registration::ClientRegistrationResponse,
+        scope::{OPENID, Scope},
+    };
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    /// Register a client, and return the registration response
+    async fn register(state: &TestState) -> ClientRegistrationResponse {
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "client_name": "Example",
+                "redirect_uris": ["https://example.com/callback"],
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "client_secret_basic",
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        response.json()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_read_back(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let registration = register(&state).await;
+        let token = registration.registration_access_token.unwrap();
+        let uri = registration.registration_client_uri.unwrap();
+        assert_eq!(
+            uri.path(),
+            format!("/oauth2/registration/{}", registration.client_id)
+        );
+
+        let request = Request::get(uri.path()).bearer(&token).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["client_id"], registration.client_id);
+        assert_eq!(
+            body["client_secret"].as_str(),
+            registration.client_secret.as_deref()
+        );
+        assert_eq!(body["client_name"], "Example");
+        assert_eq!(body["registration_access_token"], token);
+
+        // Using a wrong token should fail
+        let request = Request::get(uri.path()).bearer("wrong-token").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+
+        // Missing token should fail
+        let request = Request::get(uri.path()).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+
+        // Using the token on another client should fail
+        let other = register(&state).await;
+        let request = Request::get(format!("/oauth2/registration/{}", other.client_id))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_update(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let registration = register(&state).await;
+        let token = registration.registration_access_token.unwrap();
+        let uri = registration.registration_client_uri.unwrap();
+
+        // An update which is denied by the policy
+        let request = Request::put(uri.path())
+            .bearer(&token)
+            .json(serde_json::json!({
+                "client_id": registration.client_id,
+                "client_uri": "https://example.com/",
+                "tos_uri": "https://example.org/tos",
+                "redirect_uris": ["https://example.com/callback"],
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "client_secret_basic",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let error: ClientError = response.json();
+        assert_eq!(error.error, ClientErrorCode::InvalidClientMetadata);
+
+        // The client should not have changed
+        let request = Request::get(uri.path()).bearer(&token).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["client_name"], "Example");
+        assert!(body.get("tos_uri").is_none());
+
+        // Changing the authentication method is not allowed
+        let request = Request::put(uri.path())
+            .bearer(&token)
+            .json(serde_json::json!({
+                "client_id": registration.client_id,
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "none",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        // A valid update
+        let request = Request::put(uri.path())
+            .bearer(&token)
+            .json(serde_json::json!({
+                "client_id": registration.client_id,
+                "client_uri": "https://example.com/",
+                "client_name": "Renamed",
+                "tos_uri": "https://example.com/tos",
+                "redirect_uris": ["https://example.com/other-callback"],
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "client_secret_basic",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["client_name"], "Renamed");
+        assert_eq!(body["tos_uri"], "https://example.com/tos");
+        assert_eq!(
+            body["redirect_uris"],
+            serde_json::json!(["https://example.com/other-callback"])
+        );
+        assert_eq!(
+            body["client_secret"].as_str(),
+            registration.client_secret.as_deref()
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_update_scope(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "client_secret_basic",
+                "scope": "openid email",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let registration: ClientRegistrationResponse = response.json();
+        let token = registration.registration_access_token.unwrap();
+        let uri = registration.registration_client_uri.unwrap();
+
+        let update = |scope: Option<&str>| {
+            let mut body = serde_json::json!({
+                "client_id": registration.client_id,
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+                "token_endpoint_auth_method": "client_secret_basic",
+            });
+            if let Some(scope) = scope {
+                body["scope"] = scope.into();
+            }
+            Request::put(uri.path()).bearer(&token).json(body)
+        };
+
+        // Widening the scope is not allowed
+        let response = state.request(update(Some("openid email profile"))).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let error: ClientError = response.json();
+        assert_eq!(error.error, ClientErrorCode::InvalidClientMetadata);
+
+        // Omitting the scope keeps the current one
+        let response = state.request(update(None)).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["scope"], "email openid");
+
+        // Narrowing the scope is allowed
+        let response = state.request(update(Some("openid"))).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["scope"], "openid");
+
+        // And it can't be widened back
+        let response = state.request(update(Some("openid email"))).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_delete(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        let registration = register(&state).await;
+        let token = registration.registration_access_token.unwrap();
+        let uri = registration.registration_client_uri.unwrap();
+
+        // Start a session for this client
+        let mut repo = state.repository().await.unwrap();
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&registration.client_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let scope = Scope::from_iter([OPENID]);
+        let session = repo
+            .oauth2_session()
+            .add_from_client_credentials(&mut rng, &state.clock, &client, scope.clone())
+            .await
+            .unwrap();
+        let user_session = repo
+            .oauth2_session()
+            .add(&mut rng, &state.clock, &client, Some(&user), None, scope)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::delete(uri.path()).bearer(&token).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::NO_CONTENT);
+
+        // The client and its sessions are gone
+        let mut repo = state.repository().await.unwrap();
+        assert!(
+            repo.oauth2_client()
+                .lookup(client.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.oauth2_session()
+                .lookup(session.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            repo.oauth2_session()
+                .lookup(user_session.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        repo.save().await.unwrap();
+
+        // The token can't be used anymore
+        let request = Request::get(uri.path()).bearer(&token).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+
+        // Unknown clients also result in a 401
+        let request = Request::delete(format!("/oauth2/registration/{}", Ulid::nil()))
+            .bearer(&token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+    }
+}
@@ -13,7 +13,7 @@ use mas_axum_utils::{
     client_authorization::{ClientAuthorization, CredentialsVerificationError},
     record_error,
 };
-use mas_data_model::{BoxClock, BoxRng};
+use mas_data_model::{BoxClock, BoxRng, SiteConfig};
 use mas_keystore::Encrypter;
 use mas_router::UrlBuilder;
 use mas_storage::{BoxRepository, oauth2::OAuth2DeviceCodeGrantParams};
@@ -26,7 +26,10 @@ use rand::distributions::{Alphanumeric, DistString};
 use thiserror::Error;
 use ulid::Ulid;
 
-use crate::{BoundActivityTracker, impl_from_error_for_route};
+use crate::{
+    BoundActivityTracker, impl_from_error_for_route,
+    oauth2::{DisallowedScopeError, restrict_client_scope},
+};
 
 #[derive(Debug, Error)]
 pub(crate) enum RouteError {
@@ -52,6 +55,9 @@ pub(crate) enum RouteError {
         #[source]
         source: CredentialsVerificationError,
     },
+
+    #[error(transparent)]
+    DisallowedScope(#[from] DisallowedScopeError),
 }
 
 impl_from_error_for_route!(mas_storage::RepositoryError);
@@ -73,6 +79,10 @@ impl IntoResponse for RouteError {
                 StatusCode::UNAUTHORIZED,
                 Json(ClientError::from(ClientErrorCode::UnauthorizedClient)),
             ),
+            Self::DisallowedScope(_) => (
+                StatusCode::BAD_REQUEST,
+                Json(ClientError::from(ClientErrorCode::InvalidScope)),
+            ),
         };
 
         (sentry_event_id, response).into_response()
@@ -93,6 +103,7 @@ pub(crate) async fn post(
     State(url_builder): State<UrlBuilder>,
     State(http_client): State<reqwest::Client>,
     State(encrypter): State<Encrypter>,
+    State(site_config): State<SiteConfig>,
     client_authorization: ClientAuthorization<DeviceAuthorizationRequest>,
 ) -> Result<impl IntoResponse, RouteError> {
     let client = client_authorization
@@ -134,6 +145,7 @@ pub(crate) async fn post(
         .and_then(|f| f.scope)
         // XXX: Is this really how we do empty scopes?
         .unwrap_or(std::iter::empty::<ScopeToken>().collect());
+    let scope = restrict_client_scope(&site_config, &client, scope)?;
 
     let expires_in = Duration::microseconds(20 * 60 * 1000 * 1000);
 
@@ -182,13 +194,36 @@ pub(crate) async fn post(
 #[cfg(test)]
 mod tests {
     use hyper::{Request, StatusCode};
+    use mas_data_model::{DisallowedScopeHandling, SiteConfig};
     use mas_router::SimpleRoute;
+    use mas_storage::RepositoryAccess;
     use oauth2_types::{
-        registration::ClientRegistrationResponse, requests::DeviceAuthorizationResponse,
+        errors::{ClientError, ClientErrorCode},
+        registration::ClientRegistrationResponse,
+        requests::DeviceAuthorizationResponse,
     };
     use sqlx::PgPool;
 
-    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup, test_site_config};
+
+    /// Provision a client which can use the device code grant, and is only
+    /// allowed to request the `openid` scope and device scopes
+    async fn create_restricted_client(state: &TestState) -> String {
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "token_endpoint_auth_method": "none",
+                "grant_types": ["urn:ietf:params:oauth:grant-type:device_code"],
+                "response_types": [],
+                "scope": "openid urn:matrix:org.matrix.msc2967.client:device:*",
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+
+        let response: ClientRegistrationResponse = response.json();
+        response.client_id
+    }
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_device_code_request(pool: PgPool) {
@@ -224,4 +259,74 @@ mod tests {
         assert_eq!(response.device_code.len(), 32);
         assert_eq!(response.user_code.len(), 6);
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_device_code_request_disallowed_scope(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let client_id = create_restricted_client(&state).await;
+
+        // Asking for the admin scope is rejected
+        let request = Request::post(mas_router::OAuth2DeviceAuthorizationEndpoint::PATH).form(
+            serde_json::json!({
+                "client_id": client_id,
+                "scope": "openid urn:mas:admin",
+            }),
+        );
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let error: ClientError = response.json();
+        assert_eq!(error.error, ClientErrorCode::InvalidScope);
+
+        // Allowed scopes still go through
+        let request = Request::post(mas_router::OAuth2DeviceAuthorizationEndpoint::PATH).form(
+            serde_json::json!({
+                "client_id": client_id,
+                "scope": "openid urn:matrix:org.matrix.msc2967.client:device:ABCDEF",
+            }),
+        );
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_device_code_request_trim_disallowed_scope(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                disallowed_scope_handling: DisallowedScopeHandling::Trim,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let client_id = create_restricted_client(&state).await;
+
+        let request = Request::post(mas_router::OAuth2DeviceAuthorizationEndpoint::PATH).form(
+            serde_json::json!({
+                "client_id": client_id,
+                "scope": "openid urn:mas:admin urn:matrix:org.matrix.msc2967.client:device:ABCDEF",
+            }),
+        );
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: DeviceAuthorizationResponse = response.json();
+
+        // The grant was created without the admin scope
+        let mut repo = state.repository().await.unwrap();
+        let grant = repo
+            .oauth2_device_code_grant()
+            .find_by_device_code(&response.device_code)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(grant.scope.contains("openid"));
+        assert!(
+            grant
+                .scope
+                .contains("urn:matrix:org.matrix.msc2967.client:device:ABCDEF")
+        );
+        assert!(!grant.scope.contains("urn:mas:admin"));
+    }
 }
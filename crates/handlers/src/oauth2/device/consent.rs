@@ -11,35 +11,59 @@ use axum::{
     response::{Html, IntoResponse, Response},
 };
 use axum_extra::TypedHeader;
+use hyper::StatusCode;
 use mas_axum_utils::{
-    InternalError,
+    GenericError, InternalError,
     cookies::CookieJar,
     csrf::{CsrfExt, ProtectedForm},
 };
-use mas_data_model::{BoxClock, BoxRng};
+use mas_data_model::{BoxClock, BoxRng, DeviceCodeGrant, SiteConfig};
 use mas_policy::Policy;
 use mas_router::UrlBuilder;
 use mas_storage::BoxRepository;
-use mas_templates::{DeviceConsentContext, PolicyViolationContext, TemplateContext, Templates};
-use serde::Deserialize;
+use mas_templates::{
+    DeviceConsentContext, DeviceConsentFormField, ErrorCode, FieldError, FormError,
+    PolicyViolationContext, TemplateContext, Templates, ToFormState,
+};
+use serde::{Deserialize, Serialize};
 use tracing::warn;
 use ulid::Ulid;
 
 use crate::{
-    BoundActivityTracker, PreferredLanguage,
+    BoundActivityTracker, Limiter, PreferredLanguage,
     session::{SessionOrFallback, load_session_or_fallback},
 };
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "lowercase")]
 enum Action {
     Consent,
     Reject,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub(crate) struct ConsentForm {
     action: Action,
+
+    /// The user code, re-entered by the user when the grant requests sensitive
+    /// scopes
+    #[serde(default)]
+    user_code: Option<String>,
+}
+
+impl ToFormState for ConsentForm {
+    type Field = DeviceConsentFormField;
+}
+
+/// Whether the user has to re-enter the user code to approve the grant,
+/// because it requests one of the sensitive scopes
+fn requires_user_code_confirmation(site_config: &SiteConfig, grant: &DeviceCodeGrant) -> bool {
+    grant.scope.iter().any(|token| {
+        site_config
+            .device_code_sensitive_scopes
+            .iter()
+            .any(|scope| scope == token.as_str())
+    })
 }
 
 #[tracing::instrument(name = "handlers.oauth2.device.consent.get", skip_all)]
@@ -49,6 +73,7 @@ pub(crate) async fn get(
     PreferredLanguage(locale): PreferredLanguage,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
     mut repo: BoxRepository,
     mut policy: Policy,
     activity_tracker: BoundActivityTracker,
@@ -57,7 +82,13 @@ pub(crate) async fn get(
     Path(grant_id): Path<Ulid>,
 ) -> Result<Response, InternalError> {
     let (cookie_jar, maybe_session) = match load_session_or_fallback(
-        cookie_jar, &clock, &mut rng, &templates, &locale, &mut repo,
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
     )
     .await?
     {
@@ -82,18 +113,22 @@ pub(crate) async fn get(
         .record_browser_session(&clock, &session)
         .await;
 
-    // TODO: better error handling
-    let grant = repo
-        .oauth2_device_code_grant()
-        .lookup(grant_id)
-        .await?
-        .context("Device grant not found")
-        .map_err(InternalError::from_anyhow)?;
+    let Some(grant) = repo.oauth2_device_code_grant().lookup(grant_id).await? else {
+        return Ok(GenericError::from_anyhow(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Device grant not found"),
+        )
+        .with_error_code(ErrorCode::DeviceGrantNotFound)
+        .into_response());
+    };
 
     if grant.expires_at < clock.now() {
-        return Err(InternalError::from_anyhow(anyhow::anyhow!(
-            "Grant is expired"
-        )));
+        return Ok(GenericError::from_anyhow(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Grant is expired"),
+        )
+        .with_error_code(ErrorCode::DeviceGrantExpired)
+        .into_response());
     }
 
     let client = repo
@@ -121,6 +156,7 @@ pub(crate) async fn get(
 
         let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
         let ctx = PolicyViolationContext::for_device_code_grant(grant, client)
+            .with_violation_code(res.code().map(|code| code.as_str()))
             .with_session(session)
             .with_csrf(csrf_token.form_value())
             .with_language(locale);
@@ -130,7 +166,9 @@ pub(crate) async fn get(
         return Ok((cookie_jar, Html(content)).into_response());
     }
 
+    let user_code_confirmation = requires_user_code_confirmation(&site_config, &grant);
     let ctx = DeviceConsentContext::new(grant, client)
+        .with_user_code_confirmation(user_code_confirmation)
         .with_session(session)
         .with_csrf(csrf_token.form_value())
         .with_language(locale);
@@ -150,6 +188,8 @@ pub(crate) async fn post(
     PreferredLanguage(locale): PreferredLanguage,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    State(limiter): State<Limiter>,
     mut repo: BoxRepository,
     mut policy: Policy,
     activity_tracker: BoundActivityTracker,
@@ -160,7 +200,13 @@ pub(crate) async fn post(
 ) -> Result<Response, InternalError> {
     let form = cookie_jar.verify_form(&clock, form)?;
     let (cookie_jar, maybe_session) = match load_session_or_fallback(
-        cookie_jar, &clock, &mut rng, &templates, &locale, &mut repo,
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
     )
     .await?
     {
@@ -184,18 +230,22 @@ pub(crate) async fn post(
         .record_browser_session(&clock, &session)
         .await;
 
-    // TODO: better error handling
-    let grant = repo
-        .oauth2_device_code_grant()
-        .lookup(grant_id)
-        .await?
-        .context("Device grant not found")
-        .map_err(InternalError::from_anyhow)?;
+    let Some(grant) = repo.oauth2_device_code_grant().lookup(grant_id).await? else {
+        return Ok(GenericError::from_anyhow(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Device grant not found"),
+        )
+        .with_error_code(ErrorCode::DeviceGrantNotFound)
+        .into_response());
+    };
 
     if grant.expires_at < clock.now() {
-        return Err(InternalError::from_anyhow(anyhow::anyhow!(
-            "Grant is expired"
-        )));
+        return Ok(GenericError::from_anyhow(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Grant is expired"),
+        )
+        .with_error_code(ErrorCode::DeviceGrantExpired)
+        .into_response());
     }
 
     let client = repo
@@ -223,6 +273,7 @@ pub(crate) async fn post(
 
         let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
         let ctx = PolicyViolationContext::for_device_code_grant(grant, client)
+            .with_violation_code(res.code().map(|code| code.as_str()))
             .with_session(session)
             .with_csrf(csrf_token.form_value())
             .with_language(locale);
@@ -232,6 +283,43 @@ pub(crate) async fn post(
         return Ok((cookie_jar, Html(content)).into_response());
     }
 
+    let user_code_confirmation = requires_user_code_confirmation(&site_config, &grant);
+
+    // Approving a grant for sensitive scopes needs the user to re-enter the
+    // code displayed on the device
+    if grant.is_pending() && matches!(form.action, Action::Consent) && user_code_confirmation {
+        let mut form_state = form.to_form_state();
+
+        if let Err(e) = limiter.check_device_code_confirmation(&grant) {
+            warn!(error = &e as &dyn std::error::Error);
+            form_state.add_error_on_form(FormError::RateLimitExceeded);
+        } else {
+            match form.user_code.as_deref().map(str::trim) {
+                None | Some("") => form_state
+                    .add_error_on_field(DeviceConsentFormField::UserCode, FieldError::Required),
+                Some(user_code) if !user_code.eq_ignore_ascii_case(&grant.user_code) => form_state
+                    .add_error_on_field(DeviceConsentFormField::UserCode, FieldError::Invalid),
+                Some(_) => {}
+            }
+        }
+
+        if !form_state.is_valid() {
+            let ctx = DeviceConsentContext::new(grant, client)
+                .with_user_code_confirmation(true)
+                .with_form_state(form_state)
+                .with_session(session)
+                .with_csrf(csrf_token.form_value())
+                .with_language(locale);
+
+            let rendered = templates
+                .render_device_consent(&ctx)
+                .context("Failed to render template")
+                .map_err(InternalError::from_anyhow)?;
+
+            return Ok((cookie_jar, Html(rendered)).into_response());
+        }
+    }
+
     let grant = if grant.is_pending() {
         match form.action {
             Action::Consent => {
@@ -260,6 +348,7 @@ pub(crate) async fn post(
     repo.save().await?;
 
     let ctx = DeviceConsentContext::new(grant, client)
+        .with_user_code_confirmation(user_code_confirmation)
         .with_session(session)
         .with_csrf(csrf_token.form_value())
         .with_language(locale);
@@ -271,3 +360,255 @@ pub(crate) async fn post(
 
     Ok((cookie_jar, Html(rendered)).into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_axum_utils::SessionInfoExt;
+    use mas_data_model::{Client, DeviceCodeGrant};
+    use mas_router::{Route, SimpleRoute};
+    use mas_storage::{RepositoryAccess, oauth2::OAuth2DeviceCodeGrantParams};
+    use oauth2_types::{
+        registration::ClientRegistrationResponse,
+        scope::{OPENID, Scope},
+    };
+    use sqlx::PgPool;
+
+    use crate::test_utils::{CookieHelper, RequestBuilderExt, ResponseExt, TestState, setup};
+
+    /// Start a new device code grant for the given client, scope and user code
+    async fn start_grant(
+        state: &TestState,
+        client: &Client,
+        scope: Scope,
+        user_code: &str,
+    ) -> DeviceCodeGrant {
+        let mut repo = state.repository().await.unwrap();
+        let grant = repo
+            .oauth2_device_code_grant()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                OAuth2DeviceCodeGrantParams {
+                    client,
+                    scope,
+                    device_code: format!("device-code-{user_code}"),
+                    user_code: user_code.to_owned(),
+                    expires_in: Duration::minutes(20),
+                    ip_address: None,
+                    user_agent: None,
+                },
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        grant
+    }
+
+    /// Load the consent page of a grant, and return its body and CSRF token
+    async fn load_consent(
+        state: &TestState,
+        cookies: &CookieHelper,
+        grant: &DeviceCodeGrant,
+    ) -> (String, String) {
+        let request = Request::get(&*mas_router::DeviceCodeConsent::new(grant.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+
+        let body = response.body().to_owned();
+        let csrf_token = body
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('\"').next())
+            .unwrap()
+            .to_owned();
+
+        (body, csrf_token)
+    }
+
+    /// Submit the consent form of a grant, and return the grant afterwards
+    async fn submit_consent(
+        state: &TestState,
+        cookies: &CookieHelper,
+        grant: &DeviceCodeGrant,
+        form: serde_json::Value,
+    ) -> (String, DeviceCodeGrant) {
+        let request =
+            Request::post(&*mas_router::DeviceCodeConsent::new(grant.id).path()).form(form);
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+
+        let mut repo = state.repository().await.unwrap();
+        let grant = repo
+            .oauth2_device_code_grant()
+            .lookup(grant.id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.cancel().await.unwrap();
+
+        (response.body().to_owned(), grant)
+    }
+
+    /// Provision a device code client, and an admin user with a browser
+    /// session
+    async fn provision(state: &TestState, cookies: &CookieHelper) -> Client {
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "token_endpoint_auth_method": "none",
+                "grant_types": ["urn:ietf:params:oauth:grant-type:device_code"],
+                "response_types": [],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let ClientRegistrationResponse { client_id, .. } = response.json();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let user = repo.user().set_can_request_admin(user, true).await.unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+        repo.save().await.unwrap();
+
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+
+        client
+    }
+
+    fn admin_scope() -> Scope {
+        Scope::from_iter([OPENID, "urn:mas:admin".parse().unwrap()])
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_user_code_confirmation(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+        let client = provision(&state, &cookies).await;
+
+        // Grants without sensitive scopes don't need the user code
+        let grant = start_grant(&state, &client, Scope::from_iter([OPENID]), "AAAAAA").await;
+        let (body, csrf_token) = load_consent(&state, &cookies, &grant).await;
+        assert!(!body.contains("name=\"user_code\""));
+        let (_, grant) = submit_consent(
+            &state,
+            &cookies,
+            &grant,
+            serde_json::json!({ "csrf": csrf_token, "action": "consent" }),
+        )
+        .await;
+        assert!(grant.is_fulfilled());
+
+        // Grants for admin scopes ask for the user code
+        let grant = start_grant(&state, &client, admin_scope(), "ABCDEF").await;
+        let (body, csrf_token) = load_consent(&state, &cookies, &grant).await;
+        assert!(body.contains("name=\"user_code\""));
+
+        // Without the code, or with the wrong one, the grant is left pending
+        let (_, grant) = submit_consent(
+            &state,
+            &cookies,
+            &grant,
+            serde_json::json!({ "csrf": csrf_token, "action": "consent" }),
+        )
+        .await;
+        assert!(grant.is_pending());
+
+        let (body, grant) = submit_consent(
+            &state,
+            &cookies,
+            &grant,
+            serde_json::json!({ "csrf": csrf_token, "action": "consent", "user_code": "ZZZZZZ" }),
+        )
+        .await;
+        assert!(grant.is_pending());
+        assert!(body.contains("name=\"user_code\""));
+
+        // The code is not case-sensitive
+        let (_, grant) = submit_consent(
+            &state,
+            &cookies,
+            &grant,
+            serde_json::json!({ "csrf": csrf_token, "action": "consent", "user_code": "abcdef" }),
+        )
+        .await;
+        assert!(grant.is_fulfilled());
+
+        // Rejecting the grant doesn't need the code
+        let grant = start_grant(&state, &client, admin_scope(), "BBBBBB").await;
+        let (_, csrf_token) = load_consent(&state, &cookies, &grant).await;
+        let (_, grant) = submit_consent(
+            &state,
+            &cookies,
+            &grant,
+            serde_json::json!({ "csrf": csrf_token, "action": "reject" }),
+        )
+        .await;
+        assert!(grant.is_rejected());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_user_code_confirmation_attempts(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+        let client = provision(&state, &cookies).await;
+
+        let grant = start_grant(&state, &client, admin_scope(), "ABCDEF").await;
+        let (_, csrf_token) = load_consent(&state, &cookies, &grant).await;
+
+        // The default limit is five attempts per grant
+        for _ in 0..5 {
+            let (_, grant) = submit_consent(
+                &state,
+                &cookies,
+                &grant,
+                serde_json::json!({ "csrf": csrf_token, "action": "consent", "user_code": "ZZZZZZ" }),
+            )
+            .await;
+            assert!(grant.is_pending());
+        }
+
+        // Once the attempts are exhausted, even the right code is refused
+        let (_, grant) = submit_consent(
+            &state,
+            &cookies,
+            &grant,
+            serde_json::json!({ "csrf": csrf_token, "action": "consent", "user_code": "ABCDEF" }),
+        )
+        .await;
+        assert!(grant.is_pending());
+
+        // Other grants are not affected
+        let grant = start_grant(&state, &client, admin_scope(), "BBBBBB").await;
+        let (_, csrf_token) = load_consent(&state, &cookies, &grant).await;
+        let (_, grant) = submit_consent(
+            &state,
+            &cookies,
+            &grant,
+            serde_json::json!({ "csrf": csrf_token, "action": "consent", "user_code": "BBBBBB" }),
+        )
+        .await;
+        assert!(grant.is_fulfilled());
+    }
+}
@@ -5,6 +5,7 @@
 // Please see LICENSE files in the repository root for full details.
 
 use axum::{Json, extract::State, response::IntoResponse};
+use mas_data_model::AuthenticationMethod;
 use mas_iana::oauth::{
     OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod,
     PkceCodeChallengeMethod,
@@ -58,6 +59,8 @@ pub(crate) async fn get(
     // Prepare all the endpoints
     let issuer = Some(url_builder.oidc_issuer().into());
     let authorization_endpoint = Some(url_builder.oauth_authorization_endpoint());
+    let pushed_authorization_request_endpoint =
+        Some(url_builder.oauth_pushed_authorization_request_endpoint());
     let token_endpoint = Some(url_builder.oauth_token_endpoint());
     let device_authorization_endpoint = Some(url_builder.oauth_device_authorization_endpoint());
     let jwks_uri = Some(url_builder.jwks_uri());
@@ -79,6 +82,10 @@ pub(crate) async fn get(
         ResponseMode::FormPost,
         ResponseMode::Query,
         ResponseMode::Fragment,
+        ResponseMode::FormPostJwt,
+        ResponseMode::QueryJwt,
+        ResponseMode::FragmentJwt,
+        ResponseMode::Jwt,
     ]);
 
     let grant_types_supported = Some(vec![
@@ -106,33 +113,56 @@ pub(crate) async fn get(
         PkceCodeChallengeMethod::S256,
     ]);
 
+    let acr_values_supported = Some(
+        AuthenticationMethod::SUPPORTED_ACR_VALUES
+            .iter()
+            .map(|acr| (*acr).to_owned())
+            .collect(),
+    );
+
     let subject_types_supported = Some(vec![SubjectType::Public]);
 
     let id_token_signing_alg_values_supported = jwt_signing_alg_values_supported.clone();
-    let userinfo_signing_alg_values_supported = jwt_signing_alg_values_supported;
+    let userinfo_signing_alg_values_supported = jwt_signing_alg_values_supported.clone();
+    let authorization_signing_alg_values_supported = jwt_signing_alg_values_supported;
 
     let display_values_supported = Some(vec![Display::Page]);
 
     let claim_types_supported = Some(vec![ClaimType::Normal]);
 
-    let claims_supported = Some(vec![
-        "iss".to_owned(),
-        "sub".to_owned(),
-        "aud".to_owned(),
-        "iat".to_owned(),
-        "exp".to_owned(),
-        "nonce".to_owned(),
-        "auth_time".to_owned(),
-        "at_hash".to_owned(),
-        "c_hash".to_owned(),
-    ]);
+    let claims_supported = Some({
+        let mut v = vec![
+            "iss".to_owned(),
+            "sub".to_owned(),
+            "aud".to_owned(),
+            "iat".to_owned(),
+            "exp".to_owned(),
+            "nonce".to_owned(),
+            "auth_time".to_owned(),
+            "acr".to_owned(),
+            "at_hash".to_owned(),
+            "c_hash".to_owned(),
+            "email".to_owned(),
+            "email_verified".to_owned(),
+        ];
+        if site_config.userinfo_claims.name {
+            v.push("name".to_owned());
+        }
+        if site_config.userinfo_claims.locale {
+            v.push("locale".to_owned());
+        }
+        if site_config.userinfo_claims.updated_at {
+            v.push("updated_at".to_owned());
+        }
+        v
+    });
 
-    let claims_parameter_supported = Some(false);
+    let claims_parameter_supported = Some(true);
     let request_parameter_supported = Some(false);
     let request_uri_parameter_supported = Some(false);
 
     let prompt_values_supported = Some({
-        let mut v = vec![Prompt::Login];
+        let mut v = vec![Prompt::None, Prompt::Login];
         // Advertise for prompt=create if password registration is enabled
         // TODO: we may want to be able to forward that to upstream providers if they
         // support it
@@ -162,6 +192,7 @@ pub(crate) async fn get(
         introspection_endpoint_auth_signing_alg_values_supported,
         code_challenge_methods_supported,
         userinfo_endpoint,
+        acr_values_supported,
         subject_types_supported,
         id_token_signing_alg_values_supported,
         userinfo_signing_alg_values_supported,
@@ -174,6 +205,8 @@ pub(crate) async fn get(
         prompt_values_supported,
         device_authorization_endpoint,
         end_session_endpoint,
+        pushed_authorization_request_endpoint,
+        authorization_signing_alg_values_supported,
         ..ProviderMetadata::default()
     };
 
@@ -181,22 +214,18 @@ pub(crate) async fn get(
         standard,
         graphql_endpoint: url_builder.graphql_endpoint(),
         account_management_uri: url_builder.account_management_uri(),
-        // This needs to be kept in sync with what is supported in the frontend,
-        // see frontend/src/routes/__root.tsx
-        account_management_actions_supported: vec![
-            "org.matrix.profile".to_owned(),
-            "org.matrix.sessions_list".to_owned(),
-            "org.matrix.session_view".to_owned(),
-            "org.matrix.session_end".to_owned(),
-            "org.matrix.cross_signing_reset".to_owned(),
-        ],
+        account_management_actions_supported: mas_router::AccountAction::SUPPORTED
+            .iter()
+            .map(|action| (*action).to_owned())
+            .collect(),
     })
 }
 
 #[cfg(test)]
 mod tests {
     use hyper::{Request, StatusCode};
-    use oauth2_types::oidc::ProviderMetadata;
+    use mas_iana::jose::JsonWebSignatureAlg;
+    use oauth2_types::{oidc::ProviderMetadata, requests::ResponseMode};
     use sqlx::PgPool;
 
     use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
@@ -215,4 +244,28 @@ mod tests {
             .validate(state.url_builder.oidc_issuer().as_str())
             .expect("Invalid metadata");
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_discovery_advertises_jarm(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let request = Request::get("/.well-known/openid-configuration").empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let metadata: ProviderMetadata = response.json();
+        let response_modes = metadata.response_modes_supported.unwrap();
+        for mode in [
+            ResponseMode::QueryJwt,
+            ResponseMode::FragmentJwt,
+            ResponseMode::FormPostJwt,
+            ResponseMode::Jwt,
+        ] {
+            assert!(response_modes.contains(&mode), "{mode} is not advertised");
+        }
+
+        let algs = metadata.authorization_signing_alg_values_supported.unwrap();
+        assert!(algs.contains(&JsonWebSignatureAlg::Rs256));
+    }
 }
@@ -9,9 +9,11 @@ use axum::{
     response::{IntoResponse, Redirect, Response},
 };
 use axum_extra::extract::Query;
+use chrono::{DateTime, Utc};
 use hyper::StatusCode;
 use mas_axum_utils::{SessionInfoExt, cookies::CookieJar, record_error};
 use mas_data_model::{BoxClock, BoxRng, Clock};
+use mas_iana::jose::JsonWebSignatureAlg;
 use mas_keystore::Keystore;
 use mas_oidc_client::{
     error::IdTokenError,
@@ -106,6 +108,44 @@ impl From<IdTokenError> for RouteError {
     }
 }
 
+/// Verify an ID token previously issued to the client
+///
+/// The issuer of the token can either be the current issuer or one of its
+/// aliases, so that tokens issued before a change of issuer are still accepted.
+fn verify_id_token_hint(
+    id_token_hint: &str,
+    url_builder: &UrlBuilder,
+    key_store: &Keystore,
+    client_id: &String,
+    signing_algorithm: &JsonWebSignatureAlg,
+    now: DateTime<Utc>,
+) -> Result<(), RouteError> {
+    let jwks = key_store.public_jwks();
+
+    // The issuer is checked below, as the verification only supports a single
+    // issuer
+    let id_token_verification_data = JwtVerificationData {
+        issuer: None,
+        jwks: &jwks,
+        signing_algorithm,
+        client_id,
+    };
+
+    let id_token = verify_id_token(id_token_hint, id_token_verification_data, None, now)?;
+
+    let issuer = id_token
+        .payload()
+        .get("iss")
+        .and_then(serde_json::Value::as_str)
+        .ok_or(RouteError::UnknownToken)?;
+
+    if !url_builder.is_oidc_issuer(issuer) {
+        return Err(RouteError::UnknownToken);
+    }
+
+    Ok(())
+}
+
 #[tracing::instrument(name = "handlers.oauth2.end_session.get", skip_all)]
 pub(crate) async fn get(
     mut rng: BoxRng,
@@ -142,20 +182,12 @@ pub(crate) async fn get(
         .filter(|client| client.id_token_signed_response_alg.is_some())
         .ok_or(RouteError::ClientNotFound)?;
 
-    let jwks = key_store.public_jwks();
-    let issuer: String = url_builder.oidc_issuer().into();
-
-    let id_token_verification_data = JwtVerificationData {
-        issuer: Some(&issuer),
-        jwks: &jwks,
-        signing_algorithm: &client.id_token_signed_response_alg.unwrap(),
-        client_id: &client.client_id,
-    };
-
-    verify_id_token(
+    verify_id_token_hint(
         &params.id_token_hint,
-        id_token_verification_data,
-        None,
+        &url_builder,
+        &key_store,
+        &client.client_id,
+        &client.id_token_signed_response_alg.unwrap(),
         clock.now(),
     )?;
 
@@ -209,3 +241,89 @@ pub(crate) async fn get(
 
     Ok((cookie_jar, Redirect::to(&params.post_logout_redirect_uri)).into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::Duration;
+    use mas_jose::{
+        claims,
+        constraints::Constrainable,
+        jwt::{JsonWebSignatureHeader, Jwt},
+    };
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{TestState, setup};
+
+    const CLIENT_ID: &str = "client";
+
+    /// Mint an ID token for the test client with the given issuer
+    fn id_token(state: &TestState, issuer: &str) -> String {
+        let now = state.clock.now();
+        let mut claims = HashMap::new();
+        claims::ISS.insert(&mut claims, issuer.to_owned()).unwrap();
+        claims::SUB.insert(&mut claims, "subject").unwrap();
+        claims::AUD
+            .insert(&mut claims, CLIENT_ID.to_owned())
+            .unwrap();
+        claims::IAT.insert(&mut claims, now).unwrap();
+        claims::EXP
+            .insert(&mut claims, now + Duration::try_hours(1).unwrap())
+            .unwrap();
+
+        let alg = JsonWebSignatureAlg::Rs256;
+        let key = state.key_store.signing_key_for_algorithm(&alg).unwrap();
+        let signer = key.params().signing_key_for_alg(&alg).unwrap();
+        let header = JsonWebSignatureHeader::new(alg).with_kid(key.kid().unwrap());
+        Jwt::sign_with_rng(&mut state.rng(), header, claims, &signer)
+            .unwrap()
+            .into_string()
+    }
+
+    fn verify(state: &TestState, url_builder: &UrlBuilder, id_token: &str) -> bool {
+        verify_id_token_hint(
+            id_token,
+            url_builder,
+            &state.key_store,
+            &CLIENT_ID.to_owned(),
+            &JsonWebSignatureAlg::Rs256,
+            state.clock.now(),
+        )
+        .is_ok()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_id_token_hint_issuer_aliases(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let issuer = state.url_builder.oidc_issuer();
+        let canonical = id_token(&state, issuer.as_str());
+        let legacy = id_token(&state, "https://legacy.example.com/");
+        let unknown = id_token(&state, "https://unknown.example.com/");
+
+        // Without aliases, only the canonical issuer is accepted
+        assert!(verify(&state, &state.url_builder, &canonical));
+        assert!(!verify(&state, &state.url_builder, &legacy));
+        assert!(!verify(&state, &state.url_builder, &unknown));
+
+        // With an alias, tokens minted against the alias are accepted as well
+        let url_builder = state
+            .url_builder
+            .clone()
+            .with_issuer_aliases(vec!["https://legacy.example.com/".parse().unwrap()]);
+        assert!(verify(&state, &url_builder, &canonical));
+        assert!(verify(&state, &url_builder, &legacy));
+        assert!(!verify(&state, &url_builder, &unknown));
+
+        // The signature is still checked
+        let (payload, _signature) = legacy.rsplit_once('.').unwrap();
+        let tampered = format!("{payload}.AAAA");
+        assert!(!verify(&state, &url_builder, &tampered));
+
+        // The discovery document still advertises the canonical issuer
+        assert_eq!(url_builder.oidc_issuer(), issuer);
+    }
+}
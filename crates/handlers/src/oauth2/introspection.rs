@@ -16,7 +16,8 @@ use mas_axum_utils::{
     record_error,
 };
 use mas_data_model::{
-    BoxClock, Clock, Device, TokenFormatError, TokenType, personal::session::PersonalSessionOwner,
+    BoxClock, Clock, Device, SiteConfig, TokenFormatError, TokenType,
+    personal::session::PersonalSessionOwner,
 };
 use mas_iana::oauth::{OAuthClientAuthenticationMethod, OAuthTokenTypeHint};
 use mas_keystore::Encrypter;
@@ -36,6 +37,7 @@ use opentelemetry::{Key, KeyValue, metrics::Counter};
 use thiserror::Error;
 use ulid::Ulid;
 
+use super::matrix_session_extension;
 use crate::{ActivityTracker, METER, impl_from_error_for_route};
 
 static INTROSPECTION_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
@@ -224,6 +226,7 @@ const INACTIVE: IntrospectionResponse = IntrospectionResponse {
     iss: None,
     jti: None,
     device_id: None,
+    matrix_session: None,
 };
 
 const UNSTABLE_API_SCOPE: ScopeToken =
@@ -265,6 +268,7 @@ pub(crate) async fn post(
     activity_tracker: ActivityTracker,
     State(encrypter): State<Encrypter>,
     State(homeserver): State<Arc<dyn HomeserverConnection>>,
+    State(site_config): State<SiteConfig>,
     headers: HeaderMap,
     ClientAuthorization { credentials, form }: ClientAuthorization<IntrospectionRequest>,
 ) -> Result<impl IntoResponse, RouteError> {
@@ -355,7 +359,7 @@ pub(crate) async fn post(
 
             // The session might not have a user on it (for Client Credentials grants for
             // example), so we're optionally fetching the user
-            let (sub, username) = if let Some(user_id) = session.user_id {
+            let (sub, username, matrix_session) = if let Some(user_id) = session.user_id {
                 let user = repo
                     .user()
                     .lookup(user_id)
@@ -366,9 +370,12 @@ pub(crate) async fn post(
                     return Err(RouteError::InvalidUser(user.id));
                 }
 
-                (Some(user.sub), Some(user.username))
+                let matrix_session =
+                    matrix_session_extension(&site_config, &*homeserver, &session, &user);
+
+                (Some(user.sub), Some(user.username), matrix_session)
             } else {
-                (None, None)
+                (None, None, None)
             };
 
             activity_tracker
@@ -402,6 +409,7 @@ pub(crate) async fn post(
                 iss: None,
                 jti: Some(access_token.jti()),
                 device_id: None,
+                matrix_session,
             }
         }
 
@@ -428,7 +436,7 @@ pub(crate) async fn post(
 
             // The session might not have a user on it (for Client Credentials grants for
             // example), so we're optionally fetching the user
-            let (sub, username) = if let Some(user_id) = session.user_id {
+            let (sub, username, matrix_session) = if let Some(user_id) = session.user_id {
                 let user = repo
                     .user()
                     .lookup(user_id)
@@ -439,9 +447,12 @@ pub(crate) async fn post(
                     return Err(RouteError::InvalidUser(user.id));
                 }
 
-                (Some(user.sub), Some(user.username))
+                let matrix_session =
+                    matrix_session_extension(&site_config, &*homeserver, &session, &user);
+
+                (Some(user.sub), Some(user.username), matrix_session)
             } else {
-                (None, None)
+                (None, None, None)
             };
 
             activity_tracker
@@ -473,6 +484,7 @@ pub(crate) async fn post(
                 iss: None,
                 jti: Some(refresh_token.jti()),
                 device_id: None,
+                matrix_session,
             }
         }
 
@@ -557,6 +569,7 @@ pub(crate) async fn post(
                 iss: None,
                 jti: None,
                 device_id: session.device.map(Device::into),
+                matrix_session: None,
             }
         }
 
@@ -639,6 +652,7 @@ pub(crate) async fn post(
                 iss: None,
                 jti: None,
                 device_id: session.device.map(Device::into),
+                matrix_session: None,
             }
         }
 
@@ -730,6 +744,7 @@ pub(crate) async fn post(
                 iss: None,
                 jti: None,
                 device_id: None,
+                matrix_session: None,
             }
         }
     };
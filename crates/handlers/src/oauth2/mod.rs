@@ -8,8 +8,8 @@ use std::collections::HashMap;
 
 use chrono::Duration;
 use mas_data_model::{
-    AccessToken, Authentication, AuthorizationGrant, BrowserSession, Client, Clock, RefreshToken,
-    Session, TokenType,
+    AccessToken, Authentication, AuthorizationGrant, BrowserSession, Client, Clock, Device,
+    DisallowedScopeHandling, RefreshToken, Session, SiteConfig, TokenType, User,
 };
 use mas_iana::jose::JsonWebSignatureAlg;
 use mas_jose::{
@@ -18,19 +18,27 @@ use mas_jose::{
     jwt::{JsonWebSignatureHeader, Jwt},
 };
 use mas_keystore::Keystore;
+use mas_matrix::HomeserverConnection;
 use mas_router::UrlBuilder;
 use mas_storage::RepositoryAccess;
+use oauth2_types::{requests::MatrixSessionExtension, scope::Scope};
 use thiserror::Error;
 
+use self::claims_augmentor::ClaimsAugmentors;
+
 pub mod authorization;
+pub mod claims_augmentor;
+pub mod client_configuration;
 pub mod device;
 pub mod discovery;
 pub mod end_session;
 pub mod introspection;
 pub mod keys;
+pub mod pushed_authorization_request;
 pub mod registration;
 pub mod revoke;
 pub mod token;
+pub(crate) mod user_claims;
 pub mod userinfo;
 pub mod webfinger;
 
@@ -45,14 +53,76 @@ pub(crate) enum IdTokenSignatureError {
     TokenHash(#[from] mas_jose::claims::TokenHashError),
 }
 
+#[derive(Debug, Error)]
+#[error("client is not allowed to request the scope {0}")]
+pub(crate) struct DisallowedScopeError(Scope);
+
+/// Restrict the requested scope to the scopes the client is allowed to
+/// request.
+///
+/// Depending on the site configuration, disallowed scope tokens are either
+/// removed from the scope, or make the whole request fail.
+pub(crate) fn restrict_client_scope(
+    site_config: &SiteConfig,
+    client: &Client,
+    mut scope: Scope,
+) -> Result<Scope, DisallowedScopeError> {
+    let disallowed: Scope = scope
+        .iter()
+        .filter(|token| !client.is_scope_allowed(token))
+        .cloned()
+        .collect();
+
+    if disallowed.is_empty() {
+        return Ok(scope);
+    }
+
+    match site_config.disallowed_scope_handling {
+        DisallowedScopeHandling::Reject => Err(DisallowedScopeError(disallowed)),
+        DisallowedScopeHandling::Trim => {
+            tracing::info!(
+                %client.id,
+                scope = %disallowed,
+                "Removing scopes the client is not allowed to request",
+            );
+            scope.retain(|token| client.is_scope_allowed(token));
+            Ok(scope)
+        }
+    }
+}
+
+/// Build the Matrix session extension for a session, if enabled in the site
+/// configuration and the session carries a device scope.
+pub(crate) fn matrix_session_extension(
+    site_config: &SiteConfig,
+    homeserver: &dyn HomeserverConnection,
+    session: &Session,
+    user: &User,
+) -> Option<MatrixSessionExtension> {
+    if !site_config.matrix_session_in_token_response {
+        return None;
+    }
+
+    let device = session.scope.iter().find_map(Device::from_scope_token)?;
+
+    Some(MatrixSessionExtension {
+        device_id: device.into(),
+        mxid: homeserver.mxid(&user.username),
+        session_id: session.id.to_string(),
+    })
+}
+
 pub(crate) fn generate_id_token(
     rng: &mut (impl rand::RngCore + rand::CryptoRng),
     clock: &impl Clock,
     url_builder: &UrlBuilder,
     key_store: &Keystore,
+    claims_augmentors: &ClaimsAugmentors,
     client: &Client,
     grant: Option<&AuthorizationGrant>,
+    session: &Session,
     browser_session: &BrowserSession,
+    user_claims: HashMap<String, serde_json::Value>,
     access_token: Option<&AccessToken>,
     last_authentication: Option<&Authentication>,
 ) -> Result<String, IdTokenSignatureError> {
@@ -72,6 +142,10 @@ pub(crate) fn generate_id_token(
         claims::AUTH_TIME.insert(&mut claims, last_authentication.created_at)?;
     }
 
+    if let Some(acr) = grant.and_then(|grant| grant.acr.as_ref()) {
+        claims::ACR.insert(&mut claims, acr)?;
+    }
+
     let alg = client
         .id_token_signed_response_alg
         .clone()
@@ -88,6 +162,10 @@ pub(crate) fn generate_id_token(
         claims::C_HASH.insert(&mut claims, hash_token(&alg, &code.code)?)?;
     }
 
+    claims.extend(user_claims);
+
+    claims_augmentors.augment(&mut claims, &browser_session.user, session);
+
     let signer = key.params().signing_key_for_alg(&alg)?;
     let header = JsonWebSignatureHeader::new(alg)
         .with_kid(key.kid().ok_or(IdTokenSignatureError::InvalidSigningKey)?);
@@ -0,0 +1,364 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Pushed authorization request endpoint, as per [RFC 9126]
+//!
+//! [RFC 9126]: https://www.rfc-editor.org/rfc/rfc9126.html
+
+use std::collections::BTreeMap;
+
+use axum::{Json, extract::State, response::IntoResponse};
+use axum_extra::typed_header::TypedHeader;
+use headers::{CacheControl, Pragma};
+use hyper::StatusCode;
+use mas_axum_utils::{
+    client_authorization::{ClientAuthorization, CredentialsVerificationError},
+    record_error,
+};
+use mas_data_model::{BoxClock, BoxRng, SiteConfig};
+use mas_keystore::Encrypter;
+use mas_storage::BoxRepository;
+use oauth2_types::{
+    errors::{ClientError, ClientErrorCode},
+    requests::PushedAuthorizationResponse,
+};
+use thiserror::Error;
+use ulid::Ulid;
+
+use super::authorization::Params;
+use crate::impl_from_error_for_route;
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("client not found")]
+    ClientNotFound,
+
+    #[error("client {0} is not allowed to push authorization requests")]
+    ClientNotAllowed(Ulid),
+
+    #[error("invalid client credentials for client {client_id}")]
+    InvalidClientCredentials {
+        client_id: Ulid,
+        #[source]
+        source: CredentialsVerificationError,
+    },
+
+    #[error("could not verify client credentials for client {client_id}")]
+    ClientCredentialsVerification {
+        client_id: Ulid,
+        #[source]
+        source: CredentialsVerificationError,
+    },
+
+    #[error("the request_uri parameter can't be pushed")]
+    RequestUriPushed,
+
+    #[error("invalid authorization request")]
+    InvalidParameters(#[source] serde_urlencoded::de::Error),
+
+    #[error("invalid redirect uri")]
+    UnknownRedirectUri(#[from] mas_data_model::InvalidRedirectUriError),
+}
+
+impl_from_error_for_route!(mas_storage::RepositoryError);
+impl_from_error_for_route!(serde_urlencoded::ser::Error);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> axum::response::Response {
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+
+        let response = match self {
+            Self::Internal(_) | Self::ClientCredentialsVerification { .. } => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ClientError::from(ClientErrorCode::ServerError)),
+            ),
+            Self::ClientNotFound | Self::InvalidClientCredentials { .. } => (
+                StatusCode::UNAUTHORIZED,
+                Json(ClientError::from(ClientErrorCode::InvalidClient)),
+            ),
+            Self::ClientNotAllowed(_) => (
+                StatusCode::UNAUTHORIZED,
+                Json(ClientError::from(ClientErrorCode::UnauthorizedClient)),
+            ),
+            Self::RequestUriPushed | Self::UnknownRedirectUri(_) => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidRequest)
+                        .with_description(self.to_string()),
+                ),
+            ),
+            Self::InvalidParameters(ref e) => (
+                StatusCode::BAD_REQUEST,
+                Json(
+                    ClientError::from(ClientErrorCode::InvalidRequest)
+                        .with_description(format!("{self}: {e}")),
+                ),
+            ),
+        };
+
+        (sentry_event_id, response).into_response()
+    }
+}
+
+#[tracing::instrument(
+    name = "handlers.oauth2.pushed_authorization_request.post",
+    fields(client.id = client_authorization.client_id()),
+    skip_all,
+)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(http_client): State<reqwest::Client>,
+    State(encrypter): State<Encrypter>,
+    State(site_config): State<SiteConfig>,
+    client_authorization: ClientAuthorization<BTreeMap<String, String>>,
+) -> Result<impl IntoResponse, RouteError> {
+    let client = client_authorization
+        .credentials
+        .fetch(&mut repo)
+        .await?
+        .ok_or(RouteError::ClientNotFound)?;
+
+    // Reuse the token endpoint auth method to verify the client
+    let method = client
+        .token_endpoint_auth_method
+        .as_ref()
+        .ok_or(RouteError::ClientNotAllowed(client.id))?;
+
+    client_authorization
+        .credentials
+        .verify(&http_client, &encrypter, method, &client)
+        .await
+        .map_err(|err| {
+            if err.is_internal() {
+                RouteError::ClientCredentialsVerification {
+                    client_id: client.id,
+                    source: err,
+                }
+            } else {
+                RouteError::InvalidClientCredentials {
+                    client_id: client.id,
+                    source: err,
+                }
+            }
+        })?;
+
+    // The client authentication parameters are consumed by the extractor, so
+    // what's left is the authorization request itself
+    let mut parameters = client_authorization.form.unwrap_or_default();
+
+    // A pushed request can't itself reference another request
+    if parameters.contains_key("request_uri") {
+        return Err(RouteError::RequestUriPushed);
+    }
+
+    parameters.insert("client_id".to_owned(), client.client_id.clone());
+    let parameters = serde_urlencoded::to_string(&parameters)?;
+
+    // Validate the request now, so that the client gets the error right away
+    // instead of on the authorization endpoint
+    let params: Params =
+        serde_urlencoded::from_str(&parameters).map_err(RouteError::InvalidParameters)?;
+    client.resolve_redirect_uri(&params.auth.redirect_uri)?;
+
+    let expires_in = site_config.pushed_authorization_request_ttl;
+    let pushed_authorization_request = repo
+        .oauth2_pushed_authorization_request()
+        .add(&mut rng, &clock, &client, parameters, expires_in)
+        .await?;
+
+    repo.save().await?;
+
+    let response = PushedAuthorizationResponse {
+        request_uri: pushed_authorization_request.request_uri(),
+        expires_in,
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        TypedHeader(CacheControl::new().with_no_store()),
+        TypedHeader(Pragma::no_cache()),
+        Json(response),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode, header::LOCATION};
+    use mas_router::{Route, SimpleRoute};
+    use oauth2_types::{
+        registration::ClientRegistrationResponse, requests::PushedAuthorizationResponse,
+    };
+    use sqlx::PgPool;
+    use url::Url;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    /// Register a client, returning its `client_id`
+    async fn register_client(state: &TestState) -> String {
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "token_endpoint_auth_method": "none",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let response: ClientRegistrationResponse = response.json();
+        response.client_id
+    }
+
+    /// Push an authorization request for the client, returning the
+    /// `request_uri`
+    async fn push(state: &TestState, client_id: &str) -> String {
+        let request = Request::post(mas_router::OAuth2PushedAuthorizationRequestEndpoint::PATH)
+            .form(serde_json::json!({
+                "client_id": client_id,
+                "response_type": "code",
+                "redirect_uri": "https://example.com/callback",
+                "scope": "openid",
+                "state": "abc",
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let response: PushedAuthorizationResponse = response.json();
+        assert_eq!(response.expires_in, Duration::minutes(1));
+        response.request_uri
+    }
+
+    fn authorize(client_id: &str, request_uri: &str) -> Request<String> {
+        let query =
+            serde_urlencoded::to_string([("client_id", client_id), ("request_uri", request_uri)])
+                .unwrap();
+        Request::get(format!(
+            "{}?{query}",
+            mas_router::OAuth2AuthorizationEndpoint::PATH
+        ))
+        .empty()
+    }
+
+    /// Get the `error` and `error_description` from a redirect back to the
+    /// client
+    fn callback_error(location: &str) -> (String, String) {
+        let url = Url::parse(location).unwrap();
+        assert_eq!(url.path(), "/callback");
+        let mut error = None;
+        let mut description = None;
+        for (key, value) in url.query_pairs() {
+            match &*key {
+                "error" => error = Some(value.into_owned()),
+                "error_description" => description = Some(value.into_owned()),
+                "state" => assert_eq!(value, "abc"),
+                _ => {}
+            }
+        }
+        (error.unwrap(), description.unwrap())
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_pushed_authorization_request(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let client_id = register_client(&state).await;
+        let request_uri = push(&state, &client_id).await;
+
+        // Using the request_uri starts the authorization flow
+        let response = state.request(authorize(&client_id, &request_uri)).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+        assert!(location.starts_with(mas_router::Login::route()));
+
+        // It can't be used twice
+        let response = state.request(authorize(&client_id, &request_uri)).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+        let (error, description) = callback_error(location);
+        assert_eq!(error, "invalid_request_uri");
+        assert_eq!(description, "The request_uri has already been used");
+
+        // It can't be used by another client
+        let other_client_id = register_client(&state).await;
+        let request_uri = push(&state, &client_id).await;
+        let response = state
+            .request(authorize(&other_client_id, &request_uri))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_pushed_authorization_request_expired(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let client_id = register_client(&state).await;
+        let request_uri = push(&state, &client_id).await;
+
+        state.clock.advance(Duration::minutes(2));
+
+        let response = state.request(authorize(&client_id, &request_uri)).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+        let (error, description) = callback_error(location);
+        assert_eq!(error, "invalid_request_uri");
+        assert_eq!(description, "The request_uri has expired");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_pushed_authorization_request_concurrent_use(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let client_id = register_client(&state).await;
+        let request_uri = push(&state, &client_id).await;
+
+        let (first, second) = tokio::join!(
+            state.request(authorize(&client_id, &request_uri)),
+            state.request(authorize(&client_id, &request_uri)),
+        );
+
+        let succeeded = [first, second]
+            .iter()
+            .filter(|response| {
+                response.assert_status(StatusCode::SEE_OTHER);
+                let location = response.headers().get(LOCATION).unwrap().to_str().unwrap();
+                location.starts_with(mas_router::Login::route())
+            })
+            .count();
+        assert_eq!(succeeded, 1);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_pushed_authorization_request_rejects_request_uri(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let client_id = register_client(&state).await;
+
+        let request = Request::post(mas_router::OAuth2PushedAuthorizationRequestEndpoint::PATH)
+            .form(serde_json::json!({
+                "client_id": client_id,
+                "request_uri": "urn:ietf:params:oauth:request_uri:whatever",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+
+        // The redirect_uri must be registered
+        let request = Request::post(mas_router::OAuth2PushedAuthorizationRequestEndpoint::PATH)
+            .form(serde_json::json!({
+                "client_id": client_id,
+                "response_type": "code",
+                "redirect_uri": "https://evil.example.org/callback",
+                "scope": "openid",
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+}
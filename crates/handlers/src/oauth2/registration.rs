@@ -14,6 +14,7 @@ use mas_data_model::{BoxClock, BoxRng};
 use mas_iana::oauth::OAuthClientAuthenticationMethod;
 use mas_keystore::Encrypter;
 use mas_policy::{EvaluationResult, Policy};
+use mas_router::{OAuth2ClientConfiguration, UrlBuilder};
 use mas_storage::{BoxRepository, oauth2::OAuth2ClientRepository};
 use oauth2_types::{
     errors::{ClientError, ClientErrorCode},
@@ -176,11 +177,11 @@ impl IntoResponse for RouteError {
 }
 
 #[derive(Serialize)]
-struct RouteResponse {
+pub(crate) struct RouteResponse {
     #[serde(flatten)]
-    response: ClientRegistrationResponse,
+    pub response: ClientRegistrationResponse,
     #[serde(flatten)]
-    metadata: VerifiedClientMetadata,
+    pub metadata: VerifiedClientMetadata,
 }
 
 /// Check if the host of the given URL is a public suffix
@@ -213,30 +214,17 @@ fn localised_url_has_public_suffix(url: &Localized<Url>) -> bool {
     url.iter().any(|(_lang, url)| host_is_public_suffix(url))
 }
 
-#[tracing::instrument(name = "handlers.oauth2.registration.post", skip_all)]
-pub(crate) async fn post(
-    mut rng: BoxRng,
-    clock: BoxClock,
-    mut repo: BoxRepository,
-    mut policy: Policy,
-    activity_tracker: BoundActivityTracker,
-    user_agent: Option<TypedHeader<headers::UserAgent>>,
-    State(encrypter): State<Encrypter>,
-    body: Result<Json<ClientMetadata>, axum::extract::rejection::JsonRejection>,
-) -> Result<impl IntoResponse, RouteError> {
-    // Propagate any JSON extraction error
-    let Json(body) = body?;
-
-    // Sort the properties to ensure a stable serialisation order for hashing
-    let body = body.sorted();
-
-    // We need to serialize the body to compute the hash, and to log it
-    let body_json = serde_json::to_string(&body)?;
-
-    info!(body = body_json, "Client registration");
-
-    let user_agent = user_agent.map(|ua| ua.to_string());
-
+/// Validate the given client metadata, and check it against the client
+/// registration policy
+///
+/// This is used both when registering a new client and when updating an
+/// existing client registration.
+pub(crate) async fn validate_client_metadata(
+    body: ClientMetadata,
+    policy: &mut Policy,
+    activity_tracker: &BoundActivityTracker,
+    user_agent: Option<String>,
+) -> Result<VerifiedClientMetadata, RouteError> {
     // Validate the body
     let metadata = body.validate()?;
 
@@ -291,6 +279,37 @@ pub(crate) async fn post(
         return Err(RouteError::PolicyDenied(res));
     }
 
+    Ok(metadata)
+}
+
+#[tracing::instrument(name = "handlers.oauth2.registration.post", skip_all)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    mut policy: Policy,
+    activity_tracker: BoundActivityTracker,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    State(encrypter): State<Encrypter>,
+    State(url_builder): State<UrlBuilder>,
+    body: Result<Json<ClientMetadata>, axum::extract::rejection::JsonRejection>,
+) -> Result<impl IntoResponse, RouteError> {
+    // Propagate any JSON extraction error
+    let Json(body) = body?;
+
+    // Sort the properties to ensure a stable serialisation order for hashing
+    let body = body.sorted();
+
+    // We need to serialize the body to compute the hash, and to log it
+    let body_json = serde_json::to_string(&body)?;
+
+    info!(body = body_json, "Client registration");
+
+    let user_agent = user_agent.map(|ua| ua.to_string());
+
+    let metadata =
+        validate_client_metadata(body, &mut policy, &activity_tracker, user_agent).await?;
+
     let (client_secret, encrypted_client_secret) = match metadata.token_endpoint_auth_method {
         Some(
             OAuthClientAuthenticationMethod::ClientSecretJwt
@@ -351,22 +370,44 @@ pub(crate) async fn post(
                 // XXX: those might not be right, should be function calls
                 metadata.id_token_signed_response_alg.clone(),
                 metadata.userinfo_signed_response_alg.clone(),
+                metadata.authorization_signed_response_alg.clone(),
                 metadata.token_endpoint_auth_method.clone(),
                 metadata.token_endpoint_auth_signing_alg.clone(),
                 metadata.initiate_login_uri.clone(),
+                metadata.scope.clone(),
             )
             .await?;
+
         tracing::info!(%client.id, "Registered new client");
         REGISTRATION_COUNTER.add(1, &[KeyValue::new(RESULT, "created")]);
         client
     };
 
+    // Clients without a secret are deduplicated on their metadata, so they may be
+    // shared between multiple parties. Only clients with a secret get a
+    // registration access token, as it lets its holder update or delete the client
+    let registration_access_token = if client_secret.is_some() {
+        let registration_access_token = Alphanumeric.sample_string(&mut rng, 32);
+        repo.oauth2_client()
+            .set_registration_access_token(&client, &registration_access_token)
+            .await?;
+        Some(registration_access_token)
+    } else {
+        None
+    };
+
+    let registration_client_uri = registration_access_token
+        .is_some()
+        .then(|| url_builder.absolute_url_for(&OAuth2ClientConfiguration::new(client.id)));
+
     let response = ClientRegistrationResponse {
         client_id: client.client_id.clone(),
         client_secret,
         // XXX: we should have a `created_at` field on the clients
         client_id_issued_at: Some(client.id.datetime().into()),
         client_secret_expires_at: None,
+        registration_access_token,
+        registration_client_uri,
     };
 
     // We round-trip back to the metadata to output it in the response
@@ -569,11 +610,15 @@ mod tests {
         response.assert_status(StatusCode::CREATED);
         let response: ClientRegistrationResponse = response.json();
         let client_id = response.client_id;
+        assert!(response.registration_access_token.is_none());
 
         let response = state.request(request).await;
         response.assert_status(StatusCode::CREATED);
         let response: ClientRegistrationResponse = response.json();
         assert_eq!(response.client_id, client_id);
+        // The client may be shared, so nobody gets to manage it
+        assert!(response.registration_access_token.is_none());
+        assert!(response.registration_client_uri.is_none());
 
         // Check that the order of some properties doesn't matter
         let request =
@@ -609,6 +654,7 @@ mod tests {
         let response: ClientRegistrationResponse = response.json();
         // Sanity check that the client_id is different
         assert_ne!(response.client_id, client_id);
+        assert!(response.registration_access_token.is_some());
         let client_id = response.client_id;
 
         let response = state.request(request).await;
@@ -4,7 +4,10 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::sync::{Arc, LazyLock};
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+};
 
 use axum::{Json, extract::State, response::IntoResponse};
 use axum_extra::typed_header::TypedHeader;
@@ -31,7 +34,7 @@ use mas_storage::{
         OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository,
         OAuth2RefreshTokenRepository, OAuth2SessionRepository,
     },
-    user::BrowserSessionRepository,
+    user::{BrowserSessionRepository, UserRepository},
 };
 use mas_templates::{DeviceNameContext, TemplateContext, Templates};
 use oauth2_types::{
@@ -48,7 +51,10 @@ use thiserror::Error;
 use tracing::{debug, info, warn};
 use ulid::Ulid;
 
-use super::{generate_id_token, generate_token_pair};
+use super::{
+    claims_augmentor::ClaimsAugmentors, generate_id_token, generate_token_pair,
+    matrix_session_extension, user_claims,
+};
 use crate::{BoundActivityTracker, METER, impl_from_error_for_route};
 
 static TOKEN_REQUEST_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
@@ -128,6 +134,9 @@ pub(crate) enum RouteError {
     #[error("failed to load oauth session {0}")]
     NoSuchOAuthSession(Ulid),
 
+    #[error("failed to load user {0}")]
+    NoSuchUser(Ulid),
+
     #[error(
         "failed to load the next refresh token ({next:?}) from the previous one ({previous:?})"
     )]
@@ -168,6 +177,7 @@ impl IntoResponse for RouteError {
                 | Self::ClientCredentialsVerification { .. }
                 | Self::NoSuchBrowserSession(_)
                 | Self::NoSuchOAuthSession(_)
+                | Self::NoSuchUser(_)
                 | Self::ProvisionDeviceFailed(_)
                 | Self::NoSuchNextRefreshToken { .. }
                 | Self::NoSuchNextAccessToken { .. }
@@ -181,6 +191,7 @@ impl IntoResponse for RouteError {
             | Self::ClientCredentialsVerification { .. }
             | Self::NoSuchBrowserSession(_)
             | Self::NoSuchOAuthSession(_)
+            | Self::NoSuchUser(_)
             | Self::ProvisionDeviceFailed(_)
             | Self::NoSuchNextRefreshToken { .. }
             | Self::NoSuchNextAccessToken { .. }
@@ -287,6 +298,7 @@ pub(crate) async fn post(
     State(site_config): State<SiteConfig>,
     State(encrypter): State<Encrypter>,
     State(templates): State<Templates>,
+    State(claims_augmentors): State<ClaimsAugmentors>,
     policy: Policy,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     client_authorization: ClientAuthorization<AccessTokenRequest>,
@@ -337,6 +349,7 @@ pub(crate) async fn post(
                 &client,
                 &key_store,
                 &url_builder,
+                &claims_augmentors,
                 &site_config,
                 repo,
                 &homeserver,
@@ -354,6 +367,7 @@ pub(crate) async fn post(
                 &client,
                 &site_config,
                 repo,
+                &homeserver,
                 user_agent,
             )
             .await?
@@ -381,6 +395,7 @@ pub(crate) async fn post(
                 &client,
                 &key_store,
                 &url_builder,
+                &claims_augmentors,
                 &site_config,
                 repo,
                 &homeserver,
@@ -418,6 +433,7 @@ async fn authorization_code_grant(
     client: &Client,
     key_store: &Keystore,
     url_builder: &UrlBuilder,
+    claims_augmentors: &ClaimsAugmentors,
     site_config: &SiteConfig,
     mut repo: BoxRepository,
     homeserver: &Arc<dyn HomeserverConnection>,
@@ -545,14 +561,26 @@ async fn authorization_code_grant(
         generate_token_pair(&mut rng, clock, &mut repo, &session, ttl).await?;
 
     let id_token = if session.scope.contains(&scope::OPENID) {
+        let user_claims = user_claims::load_for_id_token(
+            &mut repo,
+            &**homeserver,
+            site_config,
+            &browser_session.user,
+            &authz_grant,
+        )
+        .await?;
+
         Some(generate_id_token(
             &mut rng,
             clock,
             url_builder,
             key_store,
+            claims_augmentors,
             client,
             Some(&authz_grant),
+            &session,
             &browser_session,
+            user_claims,
             Some(&access_token),
             last_authentication.as_ref(),
         )?)
@@ -569,6 +597,12 @@ async fn authorization_code_grant(
         params = params.with_id_token(id_token);
     }
 
+    if let Some(matrix_session) =
+        matrix_session_extension(site_config, &**homeserver, &session, &browser_session.user)
+    {
+        params = params.with_matrix_session(matrix_session);
+    }
+
     // Lock the user sync to make sure we don't get into a race condition
     repo.user()
         .acquire_lock_for_sync(&browser_session.user)
@@ -610,6 +644,7 @@ async fn refresh_token_grant(
     client: &Client,
     site_config: &SiteConfig,
     mut repo: BoxRepository,
+    homeserver: &Arc<dyn HomeserverConnection>,
     user_agent: Option<String>,
 ) -> Result<(AccessTokenResponse, BoxRepository), RouteError> {
     // Check that the client is allowed to use this grant type
@@ -746,10 +781,27 @@ async fn refresh_token_grant(
         }
     }
 
-    let params = AccessTokenResponse::new(new_access_token.access_token)
+    let mut params = AccessTokenResponse::new(new_access_token.access_token)
         .with_expires_in(ttl)
         .with_refresh_token(new_refresh_token.refresh_token)
-        .with_scope(session.scope);
+        .with_scope(session.scope.clone());
+
+    // Only load the user if we're going to include the Matrix session details
+    if site_config.matrix_session_in_token_response
+        && let Some(user_id) = session.user_id
+    {
+        let user = repo
+            .user()
+            .lookup(user_id)
+            .await?
+            .ok_or(RouteError::NoSuchUser(user_id))?;
+
+        if let Some(matrix_session) =
+            matrix_session_extension(site_config, &**homeserver, &session, &user)
+        {
+            params = params.with_matrix_session(matrix_session);
+        }
+    }
 
     Ok((params, repo))
 }
@@ -839,6 +891,7 @@ async fn device_code_grant(
     client: &Client,
     key_store: &Keystore,
     url_builder: &UrlBuilder,
+    claims_augmentors: &ClaimsAugmentors,
     site_config: &SiteConfig,
     mut repo: BoxRepository,
     homeserver: &Arc<dyn HomeserverConnection>,
@@ -930,16 +983,21 @@ async fn device_code_grant(
         params = params.with_refresh_token(refresh_token.refresh_token);
     }
 
-    // If the client asked for an ID token, we generate one
+    // If the client asked for an ID token, we generate one. The device
+    // authorization request has no `claims` parameter, so no standard claims are
+    // added to it.
     if session.scope.contains(&scope::OPENID) {
         let id_token = generate_id_token(
             rng,
             clock,
             url_builder,
             key_store,
+            claims_augmentors,
             client,
             None,
+            &session,
             &browser_session,
+            HashMap::new(),
             Some(&access_token),
             None,
         )?;
@@ -947,6 +1005,12 @@ async fn device_code_grant(
         params = params.with_id_token(id_token);
     }
 
+    if let Some(matrix_session) =
+        matrix_session_extension(site_config, &**homeserver, &session, &browser_session.user)
+    {
+        params = params.with_matrix_session(matrix_session);
+    }
+
     // Lock the user sync to make sure we don't get into a race condition
     repo.user()
         .acquire_lock_for_sync(&browser_session.user)
@@ -1056,6 +1120,9 @@ mod tests {
                 false,
                 None,
                 None,
+                Vec::new(),
+                None,
+                Vec::new(),
             )
             .await
             .unwrap();
@@ -1075,7 +1142,7 @@ mod tests {
         // And fulfill it
         let grant = repo
             .oauth2_authorization_grant()
-            .fulfill(&state.clock, &session, grant)
+            .fulfill(&state.clock, &session, None, grant)
             .await
             .unwrap();
 
@@ -1156,6 +1223,9 @@ mod tests {
                 false,
                 None,
                 None,
+                Vec::new(),
+                None,
+                Vec::new(),
             )
             .await
             .unwrap();
@@ -1175,7 +1245,7 @@ mod tests {
         // And fulfill it
         let grant = repo
             .oauth2_authorization_grant()
-            .fulfill(&state.clock, &session, grant)
+            .fulfill(&state.clock, &session, None, grant)
             .await
             .unwrap();
 
@@ -1819,6 +1889,142 @@ mod tests {
         assert_eq!(error, ClientErrorCode::AccessDenied);
     }
 
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_matrix_session_extension(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                matrix_session_in_token_response: true,
+                ..crate::test_utils::test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+
+        // Provision a client
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "token_endpoint_auth_method": "client_secret_post",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code", "client_credentials"],
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+
+        let response: ClientRegistrationResponse = response.json();
+        let client_id = response.client_id;
+        let client_secret = response.client_secret.expect("to have a client secret");
+
+        let mut repo = state.repository().await.unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Start a grant with a device scope
+        let device = Device::try_from("ABCDEF".to_owned()).unwrap();
+        let [stable, unstable] = device.to_scope_token().unwrap();
+        let code = "thisisaverysecurecode";
+        let grant = repo
+            .oauth2_authorization_grant()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &client,
+                "https://example.com/redirect".parse().unwrap(),
+                Scope::from_iter([OPENID, stable, unstable]),
+                Some(AuthorizationCode {
+                    code: code.to_owned(),
+                    pkce: None,
+                }),
+                Some("state".to_owned()),
+                Some("nonce".to_owned()),
+                ResponseMode::Query,
+                false,
+                None,
+                None,
+                Vec::new(),
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+
+        let session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut state.rng(),
+                &state.clock,
+                &client,
+                &browser_session,
+                grant.scope.clone(),
+            )
+            .await
+            .unwrap();
+
+        let grant = repo
+            .oauth2_authorization_grant()
+            .fulfill(&state.clock, &session, None, grant)
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": code,
+                "redirect_uri": grant.redirect_uri,
+                "client_id": client_id,
+                "client_secret": client_secret,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let response: AccessTokenResponse = response.json();
+        let matrix_session = response
+            .matrix_session
+            .expect("to have the Matrix session details");
+        assert_eq!(matrix_session.device_id, "ABCDEF");
+        assert_eq!(matrix_session.mxid, "@alice:example.com");
+        assert_eq!(matrix_session.session_id, session.id.to_string());
+
+        // Client credentials grants don't have a user nor a device, so the
+        // extension should not be there
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "client_credentials",
+                "client_id": client_id,
+                "client_secret": client_secret,
+            }));
+
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        let response: AccessTokenResponse = response.json();
+        assert!(response.matrix_session.is_none());
+    }
+
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_unsupported_grant(pool: PgPool) {
         setup();
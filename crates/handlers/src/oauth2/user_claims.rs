@@ -0,0 +1,343 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Standard claims released about users on the userinfo endpoint and in ID
+//! tokens
+
+use std::collections::HashMap;
+
+use mas_data_model::{AuthorizationGrant, SiteConfig, User};
+use mas_matrix::HomeserverConnection;
+use mas_storage::RepositoryAccess;
+use oauth2_types::scope::{self, Scope};
+
+/// Load the standard claims about a user which can be released with the given
+/// scope
+///
+/// The `email` and `email_verified` claims are released if the `email` scope
+/// was granted. The `name`, `locale` and `updated_at` claims are released if
+/// they are enabled in the site configuration. The `locale` claim is only
+/// released if it is known, which is when the claims are loaded for an
+/// authorization grant.
+pub(crate) async fn load<R: RepositoryAccess>(
+    repo: &mut R,
+    homeserver: &dyn HomeserverConnection,
+    site_config: &SiteConfig,
+    user: &User,
+    scope: &Scope,
+    locale: Option<&str>,
+) -> Result<HashMap<String, serde_json::Value>, R::Error> {
+    let config = site_config.userinfo_claims;
+    let mut claims = HashMap::new();
+
+    // All the email addresses are verified, so we pick the oldest one as the
+    // primary one
+    let email = if scope.contains(&scope::EMAIL) || config.updated_at {
+        repo.user_email()
+            .all(user)
+            .await?
+            .into_iter()
+            .min_by_key(|email| email.created_at)
+    } else {
+        None
+    };
+
+    if scope.contains(&scope::EMAIL)
+        && let Some(email) = &email
+    {
+        claims.insert("email".to_owned(), email.email.clone().into());
+        claims.insert("email_verified".to_owned(), true.into());
+    }
+
+    if config.name {
+        match homeserver.query_user(&user.username).await {
+            Ok(matrix_user) => {
+                if let Some(displayname) = matrix_user.displayname {
+                    claims.insert("name".to_owned(), displayname.into());
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error = &*e as &dyn std::error::Error,
+                    "Failed to query the user on the homeserver, not releasing the name claim"
+                );
+            }
+        }
+    }
+
+    if config.locale
+        && let Some(locale) = locale
+    {
+        claims.insert("locale".to_owned(), locale.into());
+    }
+
+    if config.updated_at {
+        let updated_at = email.map_or(user.created_at, |email| {
+            email.created_at.max(user.created_at)
+        });
+        claims.insert("updated_at".to_owned(), updated_at.timestamp().into());
+    }
+
+    Ok(claims)
+}
+
+/// Load the standard claims which the client asked to be included in the ID
+/// token of the given authorization grant
+pub(crate) async fn load_for_id_token<R: RepositoryAccess>(
+    repo: &mut R,
+    homeserver: &dyn HomeserverConnection,
+    site_config: &SiteConfig,
+    user: &User,
+    grant: &AuthorizationGrant,
+) -> Result<HashMap<String, serde_json::Value>, R::Error> {
+    if grant.id_token_claims.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut claims = load(
+        repo,
+        homeserver,
+        site_config,
+        user,
+        &grant.scope,
+        grant.locale.as_deref(),
+    )
+    .await?;
+    claims.retain(|claim, _| grant.id_token_claims.contains(claim));
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{AuthorizationCode, SiteConfig, UserinfoClaimsConfig};
+    use mas_jose::jwt::Jwt;
+    use mas_matrix::{HomeserverConnection, ProvisionRequest};
+    use mas_router::SimpleRoute;
+    use oauth2_types::{
+        registration::ClientRegistrationResponse,
+        requests::{AccessTokenResponse, ResponseMode},
+        scope::{EMAIL, OPENID, Scope},
+    };
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup, test_site_config};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_email_and_profile_claims(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                userinfo_claims: UserinfoClaimsConfig {
+                    name: true,
+                    locale: true,
+                    updated_at: true,
+                },
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+
+        // Provision a client
+        let request =
+            Request::post(mas_router::OAuth2RegistrationEndpoint::PATH).json(serde_json::json!({
+                "client_uri": "https://example.com/",
+                "redirect_uris": ["https://example.com/callback"],
+                "token_endpoint_auth_method": "none",
+                "response_types": ["code"],
+                "grant_types": ["authorization_code"],
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::CREATED);
+        let ClientRegistrationResponse { client_id, .. } = response.json();
+
+        // Provision a user with an email address and a display name
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let user_email = repo
+            .user_email()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &user,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        state
+            .homeserver_connection
+            .provision_user(&ProvisionRequest::new(&user.username, &user.sub))
+            .await
+            .unwrap();
+        state
+            .homeserver_connection
+            .set_displayname(&user.username, "Alice")
+            .await
+            .unwrap();
+
+        // Fulfill an authorization grant which asks for some claims in the ID
+        // token
+        let browser_session = repo
+            .browser_session()
+            .add(&mut state.rng(), &state.clock, &user, None)
+            .await
+            .unwrap();
+        let client = repo
+            .oauth2_client()
+            .find_by_client_id(&client_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let code = "thisisaverysecurecode";
+        let grant = repo
+            .oauth2_authorization_grant()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &client,
+                "https://example.com/callback".parse().unwrap(),
+                Scope::from_iter([OPENID, EMAIL]),
+                Some(AuthorizationCode {
+                    code: code.to_owned(),
+                    pkce: None,
+                }),
+                Some("state".to_owned()),
+                None,
+                ResponseMode::Query,
+                false,
+                None,
+                Some("fr".to_owned()),
+                vec![
+                    "email".to_owned(),
+                    "email_verified".to_owned(),
+                    "name".to_owned(),
+                    "locale".to_owned(),
+                ],
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        let session = repo
+            .oauth2_session()
+            .add_from_browser_session(
+                &mut state.rng(),
+                &state.clock,
+                &client,
+                &browser_session,
+                grant.scope.clone(),
+            )
+            .await
+            .unwrap();
+        let grant = repo
+            .oauth2_authorization_grant()
+            .fulfill(&state.clock, &session, None, grant)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Exchange the code
+        let request =
+            Request::post(mas_router::OAuth2TokenEndpoint::PATH).form(serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": code,
+                "redirect_uri": grant.redirect_uri,
+                "client_id": client.client_id,
+            }));
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let AccessTokenResponse {
+            access_token,
+            id_token,
+            ..
+        } = response.json();
+
+        // The ID token only has the claims which were asked for
+        let id_token: Jwt<'_, HashMap<String, serde_json::Value>> =
+            Jwt::try_from(id_token.as_deref().unwrap()).unwrap();
+        let claims = id_token.payload();
+        assert_eq!(claims["email"], "alice@example.com");
+        assert_eq!(claims["email_verified"], true);
+        assert_eq!(claims["name"], "Alice");
+        assert_eq!(claims["locale"], "fr");
+        assert!(!claims.contains_key("updated_at"));
+
+        // The userinfo endpoint has all the claims allowed by the scope, except
+        // the locale which isn't known there
+        let request = Request::get(mas_router::OidcUserinfo::PATH)
+            .bearer(&access_token)
+            .empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let userinfo: serde_json::Value = response.json();
+        assert_eq!(userinfo["email"], "alice@example.com");
+        assert_eq!(userinfo["email_verified"], true);
+        assert_eq!(userinfo["name"], "Alice");
+        assert_eq!(userinfo["updated_at"], user_email.created_at.timestamp());
+        assert!(userinfo.get("locale").is_none());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_email_claims_need_email_scope(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user_email()
+            .add(
+                &mut state.rng(),
+                &state.clock,
+                &user,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+
+        let site_config = test_site_config();
+
+        let claims = super::load(
+            &mut repo,
+            &*state.homeserver_connection,
+            &site_config,
+            &user,
+            &Scope::from_iter([OPENID]),
+            Some("fr"),
+        )
+        .await
+        .unwrap();
+        // Nothing is released: no email scope, and the optional claims are
+        // disabled by default
+        assert!(claims.is_empty());
+
+        let claims = super::load(
+            &mut repo,
+            &*state.homeserver_connection,
+            &site_config,
+            &user,
+            &Scope::from_iter([OPENID, EMAIL]),
+            Some("fr"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(claims.len(), 2);
+        assert_eq!(claims["email"], "alice@example.com");
+        assert_eq!(claims["email_verified"], true);
+    }
+}
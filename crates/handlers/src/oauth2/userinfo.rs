@@ -4,6 +4,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::{collections::HashMap, sync::Arc};
+
 use axum::{
     Json,
     extract::State,
@@ -15,12 +17,13 @@ use mas_axum_utils::{
     record_error,
     user_authorization::{AuthorizationVerificationError, UserAuthorization},
 };
-use mas_data_model::{BoxClock, BoxRng};
+use mas_data_model::{BoxClock, BoxRng, SiteConfig};
 use mas_jose::{
     constraints::Constrainable,
     jwt::{JsonWebSignatureHeader, Jwt},
 };
 use mas_keystore::Keystore;
+use mas_matrix::HomeserverConnection;
 use mas_router::UrlBuilder;
 use mas_storage::{BoxRepository, oauth2::OAuth2ClientRepository};
 use serde::Serialize;
@@ -28,6 +31,7 @@ use serde_with::skip_serializing_none;
 use thiserror::Error;
 use ulid::Ulid;
 
+use super::{claims_augmentor::ClaimsAugmentors, user_claims};
 use crate::{BoundActivityTracker, impl_from_error_for_route};
 
 #[skip_serializing_none]
@@ -35,6 +39,9 @@ use crate::{BoundActivityTracker, impl_from_error_for_route};
 struct UserInfo {
     sub: String,
     username: String,
+
+    #[serde(flatten)]
+    extra_claims: HashMap<String, serde_json::Value>,
 }
 
 #[derive(Serialize)]
@@ -105,6 +112,9 @@ pub async fn get(
     activity_tracker: BoundActivityTracker,
     mut repo: BoxRepository,
     State(key_store): State<Keystore>,
+    State(claims_augmentors): State<ClaimsAugmentors>,
+    State(homeserver): State<Arc<dyn HomeserverConnection>>,
+    State(site_config): State<SiteConfig>,
     user_authorization: UserAuthorization,
 ) -> Result<Response, RouteError> {
     let session = user_authorization.protected(&mut repo, &clock).await?;
@@ -129,9 +139,21 @@ pub async fn get(
         .await?
         .ok_or(RouteError::NoSuchUser(user_id))?;
 
+    let mut extra_claims = user_claims::load(
+        &mut repo,
+        &*homeserver,
+        &site_config,
+        &user,
+        &session.scope,
+        None,
+    )
+    .await?;
+    claims_augmentors.augment(&mut extra_claims, &user, &session);
+
     let user_info = UserInfo {
         sub: user.sub.clone(),
         username: user.username.clone(),
+        extra_claims,
     };
 
     let client = repo
@@ -238,9 +238,21 @@ impl PasswordManager {
     ) -> Result<PasswordVerificationResult<Option<(SchemeVersion, String)>>, anyhow::Error> {
         let inner = self.get_inner()?;
 
+        // Hashes made with the current scheme but with different parameters mean the
+        // parameters were changed without bumping the version. Rehash those too, so
+        // that they don't stay with the old parameters forever
+        let stale_parameters = scheme == inner.current_version
+            && inner.current_hasher.has_stale_parameters(&hashed_password);
+        if stale_parameters {
+            tracing::warn!(
+                %scheme,
+                "Password hash parameters don't match the current scheme, the hashing scheme parameters were probably changed without bumping its version"
+            );
+        }
+
         // If the current scheme isn't the default one, we also hash with the default
         // one so that
-        let new_hash_fut: OptionFuture<_> = (scheme != inner.current_version)
+        let new_hash_fut: OptionFuture<_> = (scheme != inner.current_version || stale_parameters)
             .then(|| self.hash(rng, password.clone()))
             .into();
 
@@ -278,10 +290,15 @@ impl Hasher {
         }
     }
 
-    /// Creates a new hashing scheme based on the argon2id algorithm
+    /// Creates a new hashing scheme based on the argon2id algorithm, with the
+    /// default parameters
     #[must_use]
     pub const fn argon2id(pepper: Option<Vec<u8>>, unicode_normalization: bool) -> Self {
-        let algorithm = Algorithm::Argon2id;
+        let algorithm = Algorithm::Argon2id {
+            memory: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+        };
         Self {
             algorithm,
             unicode_normalization,
@@ -289,6 +306,57 @@ impl Hasher {
         }
     }
 
+    /// Override the parameters of an argon2id hashing scheme. Parameters left
+    /// to `None` keep their current value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the scheme isn't based on the argon2id algorithm, or
+    /// if the parameters are out of the range accepted by the algorithm
+    pub fn with_argon2id_params(
+        mut self,
+        memory: Option<u32>,
+        iterations: Option<u32>,
+        parallelism: Option<u32>,
+    ) -> Result<Self, anyhow::Error> {
+        let Algorithm::Argon2id {
+            memory: current_memory,
+            iterations: current_iterations,
+            parallelism: current_parallelism,
+        } = self.algorithm
+        else {
+            anyhow::bail!("Only argon2id hashing schemes have tunable parameters");
+        };
+
+        let memory = memory.unwrap_or(current_memory);
+        let iterations = iterations.unwrap_or(current_iterations);
+        let parallelism = parallelism.unwrap_or(current_parallelism);
+
+        // Check that the parameters are valid early
+        argon2::Params::new(memory, iterations, parallelism, None)?;
+
+        self.algorithm = Algorithm::Argon2id {
+            memory,
+            iterations,
+            parallelism,
+        };
+        Ok(self)
+    }
+
+    /// The memory, iterations and parallelism parameters of an argon2id
+    /// hashing scheme, `None` for other algorithms
+    #[must_use]
+    pub const fn argon2id_params(&self) -> Option<(u32, u32, u32)> {
+        match self.algorithm {
+            Algorithm::Argon2id {
+                memory,
+                iterations,
+                parallelism,
+            } => Some((memory, iterations, parallelism)),
+            Algorithm::Bcrypt { .. } | Algorithm::Pbkdf2 => None,
+        }
+    }
+
     /// Creates a new hashing scheme based on the pbkdf2 algorithm
     #[must_use]
     pub const fn pbkdf2(pepper: Option<Vec<u8>>, unicode_normalization: bool) -> Self {
@@ -331,16 +399,51 @@ impl Hasher {
         self.algorithm
             .verify_blocking(hashed_password, password.as_bytes(), self.pepper.as_deref())
     }
+
+    /// Whether the given hash was made with parameters different from the ones
+    /// of this scheme
+    fn has_stale_parameters(&self, hashed_password: &str) -> bool {
+        self.algorithm.has_stale_parameters(hashed_password)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 enum Algorithm {
-    Bcrypt { cost: Option<u32> },
-    Argon2id,
+    Bcrypt {
+        cost: Option<u32>,
+    },
+    Argon2id {
+        memory: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
     Pbkdf2,
 }
 
 impl Algorithm {
+    fn has_stale_parameters(self, hashed_password: &str) -> bool {
+        match self {
+            Self::Argon2id {
+                memory,
+                iterations,
+                parallelism,
+            } => {
+                let Ok(hashed_password) = PasswordHash::new(hashed_password) else {
+                    return false;
+                };
+                let Ok(params) = argon2::Params::try_from(&hashed_password) else {
+                    return false;
+                };
+
+                params.m_cost() != memory
+                    || params.t_cost() != iterations
+                    || params.p_cost() != parallelism
+            }
+
+            Self::Bcrypt { .. } | Self::Pbkdf2 => false,
+        }
+    }
+
     fn hash_blocking<R: CryptoRng + RngCore>(
         self,
         mut rng: R,
@@ -360,10 +463,14 @@ impl Algorithm {
                 Ok(hashed.format_for_version(bcrypt::Version::TwoB))
             }
 
-            Self::Argon2id => {
+            Self::Argon2id {
+                memory,
+                iterations,
+                parallelism,
+            } => {
                 let algorithm = argon2::Algorithm::default();
                 let version = argon2::Version::default();
-                let params = argon2::Params::default();
+                let params = argon2::Params::new(memory, iterations, parallelism, None)?;
 
                 let phf = if let Some(secret) = pepper {
                     Argon2::new_with_secret(secret, algorithm, version, params)?
@@ -406,9 +513,10 @@ impl Algorithm {
                 PasswordVerificationResult::from(result)
             }
 
-            Algorithm::Argon2id => {
+            Algorithm::Argon2id { .. } => {
                 let algorithm = argon2::Algorithm::default();
                 let version = argon2::Version::default();
+                // The parameters are read from the hash itself
                 let params = argon2::Params::default();
 
                 let phf = if let Some(secret) = pepper {
@@ -519,7 +627,7 @@ mod tests {
         let pepper = b"a-secret-pepper";
         let pepper2 = b"the-wrong-pepper";
 
-        let alg = Algorithm::Argon2id;
+        let alg = Hasher::argon2id(None, false).algorithm;
         // Hash with a pepper
         let hash = alg
             .hash_blocking(&mut rng, password, Some(pepper))
@@ -812,4 +920,112 @@ mod tests {
             .expect("Failed to verify");
         assert_eq!(res, PasswordVerificationResult::Failure);
     }
+
+    #[test]
+    fn argon2id_params() {
+        let hasher = Hasher::argon2id(None, false);
+        assert_eq!(
+            hasher.argon2id_params(),
+            Some((
+                argon2::Params::DEFAULT_M_COST,
+                argon2::Params::DEFAULT_T_COST,
+                argon2::Params::DEFAULT_P_COST
+            ))
+        );
+
+        // Unset parameters keep their value
+        let hasher = hasher
+            .with_argon2id_params(Some(65536), None, Some(4))
+            .unwrap();
+        assert_eq!(
+            hasher.argon2id_params(),
+            Some((65536, argon2::Params::DEFAULT_T_COST, 4))
+        );
+
+        // Invalid parameters are rejected
+        assert!(
+            Hasher::argon2id(None, false)
+                .with_argon2id_params(None, None, Some(0))
+                .is_err(),
+            "A parallelism of 0 should be rejected"
+        );
+
+        // Other algorithms don't have those parameters
+        assert_eq!(Hasher::bcrypt(None, None, false).argon2id_params(), None);
+        assert!(
+            Hasher::bcrypt(None, None, false)
+                .with_argon2id_params(Some(65536), None, None)
+                .is_err(),
+            "bcrypt doesn't have argon2id parameters"
+        );
+    }
+
+    #[tokio::test]
+    async fn upgrade_argon2id_params() {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let password = Zeroizing::new("hunter2".to_owned());
+
+        // Keep the parameters low so that the test runs quickly
+        let weak = || {
+            Hasher::argon2id(None, false)
+                .with_argon2id_params(Some(1024), Some(1), Some(1))
+                .unwrap()
+        };
+        let strong = || {
+            Hasher::argon2id(None, false)
+                .with_argon2id_params(Some(2048), Some(2), Some(1))
+                .unwrap()
+        };
+
+        let manager = PasswordManager::new(0, [(1, weak())]).unwrap();
+        let (version, hash) = manager.hash(&mut rng, password.clone()).await.unwrap();
+        assert_eq!(version, 1);
+        assert!(hash.contains("m=1024,t=1,p=1"));
+        assert!(!weak().has_stale_parameters(&hash));
+        assert!(strong().has_stale_parameters(&hash));
+
+        // Bumping the version with the new parameters rehashes on login
+        let manager = PasswordManager::new(0, [(2, strong()), (1, weak())]).unwrap();
+        let res = manager
+            .verify_and_upgrade(&mut rng, version, password.clone(), hash.clone())
+            .await
+            .expect("Failed to verify");
+        let PasswordVerificationResult::Success(Some((new_version, new_hash))) = res else {
+            panic!("Expected a successful upgrade");
+        };
+        assert_eq!(new_version, 2);
+        assert!(new_hash.contains("m=2048,t=2,p=1"));
+
+        // The new hash is then left as-is
+        let res = manager
+            .verify_and_upgrade(&mut rng, new_version, password.clone(), new_hash)
+            .await
+            .expect("Failed to verify");
+        assert_eq!(res, PasswordVerificationResult::Success(None));
+
+        // Changing the parameters without bumping the version is detected, and
+        // still rehashes with the new parameters
+        let manager = PasswordManager::new(0, [(1, strong())]).unwrap();
+        let res = manager
+            .verify_and_upgrade(&mut rng, version, password.clone(), hash.clone())
+            .await
+            .expect("Failed to verify");
+        let PasswordVerificationResult::Success(Some((new_version, new_hash))) = res else {
+            panic!("Expected a successful upgrade");
+        };
+        assert_eq!(new_version, 1);
+        assert!(new_hash.contains("m=2048,t=2,p=1"));
+
+        // A wrong password is still rejected
+        let res = manager
+            .verify_and_upgrade(
+                &mut rng,
+                version,
+                Zeroizing::new("wrong-password".to_owned()),
+                hash,
+            )
+            .await
+            .expect("Failed to verify");
+        assert_eq!(res, PasswordVerificationResult::Failure);
+    }
 }
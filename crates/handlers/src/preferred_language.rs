@@ -8,24 +8,24 @@ use std::{convert::Infallible, sync::Arc};
 
 use axum::{
     extract::{FromRef, FromRequestParts},
-    http::request::Parts,
+    http::{HeaderMap, request::Parts},
 };
 use headers::HeaderMapExt as _;
-use mas_axum_utils::language_detection::AcceptLanguage;
+use mas_axum_utils::{
+    SessionInfoExt as _,
+    cookies::{CookieJar, CookieManager},
+    language_detection::AcceptLanguage,
+};
 use mas_i18n::{DataLocale, Translator, locale};
+use mas_storage::BoxRepositoryFactory;
 
 pub struct PreferredLanguage(pub DataLocale);
 
-impl<S> FromRequestParts<S> for PreferredLanguage
-where
-    S: Send + Sync,
-    Arc<Translator>: FromRef<S>,
-{
-    type Rejection = Infallible;
-
-    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let translator: Arc<Translator> = FromRef::from_ref(state);
-        let accept_language = parts.headers.typed_get::<AcceptLanguage>();
+impl PreferredLanguage {
+    /// Negotiate the best available locale from the `Accept-Language` header
+    #[must_use]
+    pub fn from_headers(translator: &Translator, headers: &HeaderMap) -> Self {
+        let accept_language = headers.typed_get::<AcceptLanguage>();
 
         let iter = accept_language
             .iter()
@@ -45,6 +45,61 @@ where
 
         let locale = translator.choose_locale(iter);
 
-        Ok(PreferredLanguage(locale))
+        PreferredLanguage(locale)
+    }
+
+    /// Get the language explicitly chosen by the user of the current browser
+    /// session, if any and if it is still available
+    async fn from_session(
+        translator: &Translator,
+        repository_factory: &BoxRepositoryFactory,
+        cookie_jar: CookieJar,
+    ) -> Result<Option<Self>, mas_storage::RepositoryError> {
+        let (session_info, _cookie_jar) = cookie_jar.session_info();
+        if session_info.current_session_id().is_none() {
+            return Ok(None);
+        }
+
+        let mut repo = repository_factory.create().await?;
+        let maybe_session = session_info.load_active_session(&mut repo).await?;
+        repo.cancel().await?;
+
+        let locale = maybe_session
+            .and_then(|session| session.user.locale)
+            .and_then(|locale| locale.parse::<DataLocale>().ok())
+            .filter(|locale| translator.has_locale(locale));
+
+        Ok(locale.map(PreferredLanguage))
+    }
+}
+
+impl<S> FromRequestParts<S> for PreferredLanguage
+where
+    S: Send + Sync,
+    Arc<Translator>: FromRef<S>,
+    BoxRepositoryFactory: FromRef<S>,
+    CookieManager: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let translator: Arc<Translator> = FromRef::from_ref(state);
+
+        // If the user chose a language, it takes precedence over the one
+        // negotiated with the browser
+        let repository_factory = BoxRepositoryFactory::from_ref(state);
+        let cookie_jar = CookieJar::from_request_parts(parts, state).await?;
+        match Self::from_session(&translator, &repository_factory, cookie_jar).await {
+            Ok(Some(preferred_language)) => return Ok(preferred_language),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(
+                    error = &e as &dyn std::error::Error,
+                    "Failed to load the preferred language of the current user"
+                );
+            }
+        }
+
+        Ok(Self::from_headers(&translator, &parts.headers))
     }
 }
@@ -4,11 +4,16 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::{net::IpAddr, sync::Arc, time::Duration};
-
-use governor::{RateLimiter, clock::QuantaClock, state::keyed::DashMapStateStore};
+use std::{collections::HashMap, hash::Hash, net::IpAddr, sync::Arc, time::Duration};
+
+use governor::{
+    Quota, RateLimiter,
+    clock::{Clock, QuantaClock},
+    middleware::NoOpMiddleware,
+    state::keyed::DashMapStateStore,
+};
 use mas_config::RateLimitingConfig;
-use mas_data_model::{User, UserEmailAuthentication};
+use mas_data_model::{DeviceCodeGrant, User, UserEmailAuthentication};
 use ulid::Ulid;
 
 #[derive(Debug, Clone, thiserror::Error)]
@@ -47,6 +52,80 @@ pub enum EmailAuthenticationLimitedError {
     Email(String),
 }
 
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+pub enum DeviceCodeConfirmationLimitedError {
+    #[error("Too many confirmation attempts for device code grant {0}")]
+    Grant(Ulid),
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+pub enum AdminApiLimitedError {
+    #[error("Too many {operation} requests to the admin API for client {client_id}")]
+    Client {
+        client_id: Ulid,
+        operation: AdminApiOperation,
+        retry_after: Duration,
+    },
+
+    #[error("Too many {operation} requests to the admin API for personal session {session_id}")]
+    PersonalSession {
+        session_id: Ulid,
+        operation: AdminApiOperation,
+        retry_after: Duration,
+    },
+}
+
+impl AdminApiLimitedError {
+    /// How long the client should wait before retrying
+    #[must_use]
+    pub const fn retry_after(&self) -> Duration {
+        match self {
+            Self::Client { retry_after, .. } | Self::PersonalSession { retry_after, .. } => {
+                *retry_after
+            }
+        }
+    }
+}
+
+/// The kind of operation done on the admin API, which have separate budgets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminApiOperation {
+    Read,
+    Write,
+}
+
+impl AdminApiOperation {
+    /// Classify a request by its HTTP method
+    #[must_use]
+    pub fn from_method(method: &hyper::Method) -> Self {
+        if method.is_safe() {
+            Self::Read
+        } else {
+            Self::Write
+        }
+    }
+}
+
+impl std::fmt::Display for AdminApiOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read => write!(f, "read"),
+            Self::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// Key used to rate limit requests to the admin API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AdminApiCaller {
+    /// An OAuth 2.0 client, either through one of its sessions or through a
+    /// personal session it owns
+    Client(Ulid),
+
+    /// A personal session owned by a user
+    PersonalSession(Ulid),
+}
+
 /// Key used to rate limit requests per requester
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct RequesterFingerprint {
@@ -73,53 +152,115 @@ impl RequesterFingerprint {
     pub const fn new(ip: IpAddr) -> Self {
         Self { ip: Some(ip) }
     }
+
+    /// Get the IP address of the requester, if known
+    #[must_use]
+    pub const fn ip(&self) -> Option<IpAddr> {
+        self.ip
+    }
 }
 
 /// Rate limiters for the different operations
+///
+/// The clock is only customisable so that tests can control the passing of
+/// time.
 #[derive(Debug, Clone)]
-pub struct Limiter {
-    inner: Arc<LimiterInner>,
+pub struct Limiter<C: Clock = QuantaClock> {
+    inner: Arc<LimiterInner<C>>,
 }
 
-type KeyedRateLimiter<K> = RateLimiter<K, DashMapStateStore<K>, QuantaClock>;
+type KeyedRateLimiter<K, C> =
+    RateLimiter<K, DashMapStateStore<K>, C, NoOpMiddleware<<C as Clock>::Instant>>;
+
+#[derive(Debug)]
+struct LimiterInner<C: Clock> {
+    account_recovery_per_requester: KeyedRateLimiter<RequesterFingerprint, C>,
+    account_recovery_per_email: KeyedRateLimiter<String, C>,
+    password_check_for_requester: KeyedRateLimiter<RequesterFingerprint, C>,
+    password_check_for_user: KeyedRateLimiter<Ulid, C>,
+    registration_per_requester: KeyedRateLimiter<RequesterFingerprint, C>,
+    email_authentication_per_requester: KeyedRateLimiter<RequesterFingerprint, C>,
+    email_authentication_per_email: KeyedRateLimiter<String, C>,
+    email_authentication_emails_per_session: KeyedRateLimiter<Ulid, C>,
+    email_authentication_attempt_per_session: KeyedRateLimiter<Ulid, C>,
+    device_code_confirmation_per_grant: KeyedRateLimiter<Ulid, C>,
+    admin_api_read_per_caller: KeyedRateLimiter<AdminApiCaller, C>,
+    admin_api_write_per_caller: KeyedRateLimiter<AdminApiCaller, C>,
+    admin_api_client_overrides: HashMap<Ulid, AdminApiClientLimiters<C>>,
+    config: RateLimitingConfig,
+    clock: C,
+}
 
+/// Rate limiters for an admin API client which has its own limits configured
 #[derive(Debug)]
-struct LimiterInner {
-    account_recovery_per_requester: KeyedRateLimiter<RequesterFingerprint>,
-    account_recovery_per_email: KeyedRateLimiter<String>,
-    password_check_for_requester: KeyedRateLimiter<RequesterFingerprint>,
-    password_check_for_user: KeyedRateLimiter<Ulid>,
-    registration_per_requester: KeyedRateLimiter<RequesterFingerprint>,
-    email_authentication_per_requester: KeyedRateLimiter<RequesterFingerprint>,
-    email_authentication_per_email: KeyedRateLimiter<String>,
-    email_authentication_emails_per_session: KeyedRateLimiter<Ulid>,
-    email_authentication_attempt_per_session: KeyedRateLimiter<Ulid>,
+struct AdminApiClientLimiters<C: Clock> {
+    read: Option<KeyedRateLimiter<AdminApiCaller, C>>,
+    write: Option<KeyedRateLimiter<AdminApiCaller, C>>,
 }
 
-impl LimiterInner {
-    fn new(config: &RateLimitingConfig) -> Option<Self> {
+/// Create a keyed rate limiter using the given clock
+fn keyed<K, C>(quota: Quota, clock: &C) -> KeyedRateLimiter<K, C>
+where
+    K: Hash + Eq + Clone,
+    C: Clock + Clone,
+{
+    RateLimiter::new(quota, DashMapStateStore::default(), clock.clone())
+}
+
+impl<C: Clock + Clone> LimiterInner<C> {
+    fn new(config: &RateLimitingConfig, clock: &C) -> Option<Self> {
         Some(Self {
-            account_recovery_per_requester: RateLimiter::keyed(
+            account_recovery_per_requester: keyed(
                 config.account_recovery.per_ip.to_quota()?,
+                clock,
             ),
-            account_recovery_per_email: RateLimiter::keyed(
+            account_recovery_per_email: keyed(
                 config.account_recovery.per_address.to_quota()?,
+                clock,
             ),
-            password_check_for_requester: RateLimiter::keyed(config.login.per_ip.to_quota()?),
-            password_check_for_user: RateLimiter::keyed(config.login.per_account.to_quota()?),
-            registration_per_requester: RateLimiter::keyed(config.registration.to_quota()?),
-            email_authentication_per_email: RateLimiter::keyed(
+            password_check_for_requester: keyed(config.login.per_ip.to_quota()?, clock),
+            password_check_for_user: keyed(config.login.per_account.to_quota()?, clock),
+            registration_per_requester: keyed(config.registration.to_quota()?, clock),
+            email_authentication_per_email: keyed(
                 config.email_authentication.per_address.to_quota()?,
+                clock,
             ),
-            email_authentication_per_requester: RateLimiter::keyed(
+            email_authentication_per_requester: keyed(
                 config.email_authentication.per_ip.to_quota()?,
+                clock,
             ),
-            email_authentication_emails_per_session: RateLimiter::keyed(
+            email_authentication_emails_per_session: keyed(
                 config.email_authentication.emails_per_session.to_quota()?,
+                clock,
             ),
-            email_authentication_attempt_per_session: RateLimiter::keyed(
+            email_authentication_attempt_per_session: keyed(
                 config.email_authentication.attempt_per_session.to_quota()?,
+                clock,
+            ),
+            device_code_confirmation_per_grant: keyed(
+                config.device_code_confirmation.to_quota()?,
+                clock,
             ),
+            admin_api_read_per_caller: keyed(config.admin_api.read.to_quota()?, clock),
+            admin_api_write_per_caller: keyed(config.admin_api.write.to_quota()?, clock),
+            admin_api_client_overrides: config
+                .admin_api
+                .clients
+                .iter()
+                .map(|client| {
+                    let read = match client.read {
+                        Some(read) => Some(keyed(read.to_quota()?, clock)),
+                        None => None,
+                    };
+                    let write = match client.write {
+                        Some(write) => Some(keyed(write.to_quota()?, clock)),
+                        None => None,
+                    };
+                    Some((client.client_id, AdminApiClientLimiters { read, write }))
+                })
+                .collect::<Option<_>>()?,
+            config: config.clone(),
+            clock: clock.clone(),
         })
     }
 }
@@ -131,11 +272,28 @@ impl Limiter {
     /// (This should not happen if the config was validated, though.)
     #[must_use]
     pub fn new(config: &RateLimitingConfig) -> Option<Self> {
+        Self::with_clock(config, &QuantaClock::default())
+    }
+}
+
+impl<C: Clock + Clone + Send + Sync + 'static> Limiter<C> {
+    /// Creates a new `Limiter` based on a `RateLimitingConfig`, using the
+    /// given clock.
+    ///
+    /// If the config is not valid, returns `None`.
+    #[must_use]
+    pub fn with_clock(config: &RateLimitingConfig, clock: &C) -> Option<Self> {
         Some(Self {
-            inner: Arc::new(LimiterInner::new(config)?),
+            inner: Arc::new(LimiterInner::new(config, clock)?),
         })
     }
 
+    /// Get the configuration this `Limiter` was created from
+    #[must_use]
+    pub fn config(&self) -> &RateLimitingConfig {
+        &self.inner.config
+    }
+
     /// Start the rate limiter housekeeping task
     ///
     /// This task will periodically remove old entries from the rate limiters,
@@ -165,6 +323,19 @@ impl Limiter {
                 this.inner
                     .email_authentication_attempt_per_session
                     .retain_recent();
+                this.inner
+                    .device_code_confirmation_per_grant
+                    .retain_recent();
+                this.inner.admin_api_read_per_caller.retain_recent();
+                this.inner.admin_api_write_per_caller.retain_recent();
+                for limiters in this.inner.admin_api_client_overrides.values() {
+                    if let Some(read) = &limiters.read {
+                        read.retain_recent();
+                    }
+                    if let Some(write) = &limiters.write {
+                        write.retain_recent();
+                    }
+                }
 
                 interval.tick().await;
             }
@@ -297,6 +468,69 @@ impl Limiter {
             .check_key(&authentication.id)
             .map_err(|_| EmailAuthenticationLimitedError::Authentication(authentication.id))
     }
+
+    /// Check if an attempt at confirming a device code grant with its user code
+    /// can be done
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation is rate limited.
+    pub fn check_device_code_confirmation(
+        &self,
+        grant: &DeviceCodeGrant,
+    ) -> Result<(), DeviceCodeConfirmationLimitedError> {
+        self.inner
+            .device_code_confirmation_per_grant
+            .check_key(&grant.id)
+            .map_err(|_| DeviceCodeConfirmationLimitedError::Grant(grant.id))
+    }
+
+    /// Check if a request can be made to the admin API by the given caller
+    ///
+    /// Clients which have their own limits configured use those, others share
+    /// the global limits, each with their own budget.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation is rate limited.
+    pub fn check_admin_api(
+        &self,
+        caller: AdminApiCaller,
+        operation: AdminApiOperation,
+    ) -> Result<(), AdminApiLimitedError> {
+        let overrides = match caller {
+            AdminApiCaller::Client(client_id) => {
+                self.inner.admin_api_client_overrides.get(&client_id)
+            }
+            AdminApiCaller::PersonalSession(_) => None,
+        };
+        let limiter = match operation {
+            AdminApiOperation::Read => overrides
+                .and_then(|o| o.read.as_ref())
+                .unwrap_or(&self.inner.admin_api_read_per_caller),
+            AdminApiOperation::Write => overrides
+                .and_then(|o| o.write.as_ref())
+                .unwrap_or(&self.inner.admin_api_write_per_caller),
+        };
+
+        limiter.check_key(&caller).map_err(|not_until| {
+            let retry_after = not_until.wait_time_from(self.inner.clock.now());
+            match caller {
+                AdminApiCaller::Client(client_id) => AdminApiLimitedError::Client {
+                    client_id,
+                    operation,
+                    retry_after,
+                },
+                AdminApiCaller::PersonalSession(session_id) => {
+                    AdminApiLimitedError::PersonalSession {
+                        session_id,
+                        operation,
+                        retry_after,
+                    }
+                }
+            }
+        })
+    }
 }
 
 #[cfg(test)]
@@ -327,8 +561,14 @@ mod tests {
             created_at: now,
             locked_at: None,
             deactivated_at: None,
+            deleted_at: None,
             can_request_admin: false,
             is_guest: true,
+            organization: None,
+            max_sessions: None,
+            locale: None,
+            deactivation_scheduled_at: None,
+            external_id: None,
         };
 
         let bob = User {
@@ -338,8 +578,14 @@ mod tests {
             created_at: now,
             locked_at: None,
             deactivated_at: None,
+            deleted_at: None,
             can_request_admin: false,
             is_guest: true,
+            organization: None,
+            max_sessions: None,
+            locale: None,
+            deactivation_scheduled_at: None,
+            external_id: None,
         };
 
         // Three times the same IP address should be allowed
@@ -373,4 +619,153 @@ mod tests {
         // The other account isn't rate-limited
         assert!(limiter.check_password(requesters[603], &bob).is_ok());
     }
+
+    #[test]
+    fn test_account_recovery_limiter() {
+        let clock = governor::clock::FakeRelativeClock::default();
+        let limiter = Limiter::with_clock(&RateLimitingConfig::default(), &clock).unwrap();
+
+        let requester = RequesterFingerprint::new([192, 0, 2, 1].into());
+        let other_requester = RequesterFingerprint::new([192, 0, 2, 2].into());
+
+        // The default configuration allows a burst of 3 requests per address
+        assert!(
+            limiter
+                .check_account_recovery(requester, "alice@example.com")
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .check_account_recovery(requester, "bob@example.com")
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .check_account_recovery(requester, "carol@example.com")
+                .is_ok()
+        );
+
+        // The fourth one from the same requester is rejected, whatever the address
+        assert!(matches!(
+            limiter.check_account_recovery(requester, "dave@example.com"),
+            Err(AccountRecoveryLimitedError::Requester(_))
+        ));
+
+        // Changing the case of the address doesn't bypass the per-address limit
+        assert!(
+            limiter
+                .check_account_recovery(other_requester, "alice@example.com")
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .check_account_recovery(other_requester, "ALICE@example.com")
+                .is_ok()
+        );
+        assert!(matches!(
+            limiter.check_account_recovery(other_requester, "Alice@Example.com"),
+            Err(AccountRecoveryLimitedError::Email(_))
+        ));
+
+        // The requester gets one request back every 20 minutes
+        clock.advance(Duration::from_mins(19));
+        assert!(
+            limiter
+                .check_account_recovery(requester, "dave@example.com")
+                .is_err()
+        );
+        clock.advance(Duration::from_mins(1));
+        assert!(
+            limiter
+                .check_account_recovery(requester, "dave@example.com")
+                .is_ok()
+        );
+        assert!(
+            limiter
+                .check_account_recovery(requester, "erin@example.com")
+                .is_err()
+        );
+
+        // The address gets one request back every hour
+        clock.advance(Duration::from_mins(39));
+        assert!(matches!(
+            limiter.check_account_recovery(other_requester, "alice@example.com"),
+            Err(AccountRecoveryLimitedError::Email(_))
+        ));
+        clock.advance(Duration::from_mins(1));
+        assert!(
+            limiter
+                .check_account_recovery(other_requester, "alice@example.com")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_admin_api_limiter() {
+        let clock = governor::clock::FakeRelativeClock::default();
+        let mut config = RateLimitingConfig::default();
+        config.admin_api.write = mas_config::RateLimiterConfiguration {
+            burst: std::num::NonZeroU32::new(2).unwrap(),
+            per_second: 1.0 / 60.0,
+        };
+
+        let client = AdminApiCaller::Client(Ulid::from_parts(0, 1));
+        let other_client = AdminApiCaller::Client(Ulid::from_parts(0, 2));
+        let special_client_id = Ulid::from_parts(0, 3);
+        let special_client = AdminApiCaller::Client(special_client_id);
+        let personal_session = AdminApiCaller::PersonalSession(Ulid::from_parts(0, 3));
+        config
+            .admin_api
+            .clients
+            .push(mas_config::AdminApiClientRateLimitingConfig {
+                client_id: special_client_id,
+                read: None,
+                write: Some(mas_config::RateLimiterConfiguration {
+                    burst: std::num::NonZeroU32::new(5).unwrap(),
+                    per_second: 1.0 / 60.0,
+                }),
+            });
+        let limiter = Limiter::with_clock(&config, &clock).unwrap();
+
+        let write = AdminApiOperation::Write;
+        let read = AdminApiOperation::Read;
+
+        assert!(limiter.check_admin_api(client, write).is_ok());
+        assert!(limiter.check_admin_api(client, write).is_ok());
+        let error = limiter.check_admin_api(client, write).unwrap_err();
+        assert_eq!(error.retry_after(), Duration::from_mins(1));
+        assert_eq!(
+            error.to_string(),
+            "Too many write requests to the admin API for client 00000000000000000000000001"
+        );
+
+        // Reads and other clients have their own budget
+        assert!(limiter.check_admin_api(client, read).is_ok());
+        assert!(limiter.check_admin_api(other_client, write).is_ok());
+
+        // Clients with their own limits get a bigger budget
+        for _ in 0..5 {
+            assert!(limiter.check_admin_api(special_client, write).is_ok());
+        }
+        assert!(limiter.check_admin_api(special_client, write).is_err());
+
+        // Personal sessions don't share the budget of a client with the same ID
+        assert!(limiter.check_admin_api(personal_session, write).is_ok());
+        assert!(limiter.check_admin_api(personal_session, write).is_ok());
+        let error = limiter
+            .check_admin_api(personal_session, write)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Too many write requests to the admin API for personal session \
+             00000000000000000000000003"
+        );
+
+        // The budget replenishes over time
+        clock.advance(Duration::from_secs(59));
+        assert!(limiter.check_admin_api(client, write).is_err());
+        clock.advance(Duration::from_secs(1));
+        assert!(limiter.check_admin_api(client, write).is_ok());
+        assert!(limiter.check_admin_api(client, write).is_err());
+    }
 }
@@ -4,11 +4,13 @@
 // Please see LICENSE files in the repository root for full details.
 
 //! Utilities for showing proposer HTML fallbacks when the user is logged out,
-//! locked or deactivated
+//! locked or deactivated, and for checking whether a browser session has to be
+//! re-authenticated
 
 use axum::response::{Html, IntoResponse as _, Response};
+use chrono::{DateTime, Utc};
 use mas_axum_utils::{SessionInfoExt, cookies::CookieJar, csrf::CsrfExt};
-use mas_data_model::{BrowserSession, Clock};
+use mas_data_model::{BrowserSession, Clock, SiteConfig};
 use mas_i18n::DataLocale;
 use mas_storage::{BoxRepository, RepositoryError};
 use mas_templates::{AccountInactiveContext, TemplateContext, Templates};
@@ -27,20 +29,101 @@ pub enum SessionOrFallback {
     MaybeSession {
         cookie_jar: CookieJar,
         maybe_session: Option<BrowserSession>,
+
+        /// A session which is still active, but which reached its idle timeout
+        /// or its maximum lifetime, and needs to be re-authenticated before
+        /// being used again
+        stale_session: Option<BrowserSession>,
     },
     Fallback {
         response: Response,
     },
 }
 
+/// Get the time at which a browser session was last authenticated
+async fn last_authenticated_at(
+    repo: &mut BoxRepository,
+    session: &BrowserSession,
+) -> Result<DateTime<Utc>, RepositoryError> {
+    let authentication = repo
+        .browser_session()
+        .get_last_authentication(session)
+        .await?;
+
+    Ok(authentication.map_or(session.created_at, |a| a.created_at))
+}
+
+/// Check whether a browser session has to be re-authenticated before being
+/// used, because it was idle for too long or because its last authentication
+/// is too old
+pub async fn needs_reauthentication(
+    repo: &mut BoxRepository,
+    clock: &impl Clock,
+    site_config: &SiteConfig,
+    session: &BrowserSession,
+) -> Result<bool, RepositoryError> {
+    let config = site_config.browser_session_lifetime;
+    if config.idle_timeout.is_none() && config.max_lifetime.is_none() {
+        return Ok(false);
+    }
+
+    let now = clock.now();
+    let authenticated_at = last_authenticated_at(repo, session).await?;
+
+    if let Some(max_lifetime) = config.max_lifetime
+        && now - authenticated_at > max_lifetime
+    {
+        return Ok(true);
+    }
+
+    // Authenticating again counts as activity, even if the activity tracker
+    // didn't record it yet
+    let last_active_at = session
+        .last_active_at
+        .map_or(authenticated_at, |t| t.max(authenticated_at));
+
+    if let Some(idle_timeout) = config.idle_timeout
+        && now - last_active_at > idle_timeout
+    {
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Check whether a browser session was authenticated recently enough to
+/// perform sensitive actions, like changing the password or adding an email
+/// address
+pub async fn recently_authenticated(
+    repo: &mut BoxRepository,
+    clock: &impl Clock,
+    site_config: &SiteConfig,
+    session: &BrowserSession,
+) -> Result<bool, RepositoryError> {
+    let Some(max_age) = site_config
+        .browser_session_lifetime
+        .reauthentication_max_age
+    else {
+        return Ok(true);
+    };
+
+    let authenticated_at = last_authenticated_at(repo, session).await?;
+    Ok(clock.now() - authenticated_at <= max_age)
+}
+
 /// Load a session from the cookie jar, or fall back to an HTML error page if
 /// the account is locked, deactivated or logged out
+///
+/// Sessions which have to be re-authenticated are returned as
+/// `stale_session`, so that routes requiring authentication treat them as
+/// absent and send the user to the login page.
 pub async fn load_session_or_fallback(
     cookie_jar: CookieJar,
     clock: &impl Clock,
     rng: impl RngCore,
     templates: &Templates,
     locale: &DataLocale,
+    site_config: &SiteConfig,
     repo: &mut BoxRepository,
 ) -> Result<SessionOrFallback, SessionLoadError> {
     let (session_info, cookie_jar) = cookie_jar.session_info();
@@ -48,6 +131,7 @@ pub async fn load_session_or_fallback(
         return Ok(SessionOrFallback::MaybeSession {
             cookie_jar,
             maybe_session: None,
+            stale_session: None,
         });
     };
 
@@ -58,6 +142,7 @@ pub async fn load_session_or_fallback(
         return Ok(SessionOrFallback::MaybeSession {
             cookie_jar,
             maybe_session: None,
+            stale_session: None,
         });
     };
 
@@ -97,8 +182,17 @@ pub async fn load_session_or_fallback(
         return Ok(SessionOrFallback::Fallback { response });
     }
 
+    if needs_reauthentication(repo, clock, site_config, &session).await? {
+        return Ok(SessionOrFallback::MaybeSession {
+            cookie_jar,
+            maybe_session: None,
+            stale_session: Some(session),
+        });
+    }
+
     Ok(SessionOrFallback::MaybeSession {
         cookie_jar,
         maybe_session: Some(session),
+        stale_session: None,
     })
 }
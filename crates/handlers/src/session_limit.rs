@@ -0,0 +1,186 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Enforcement of the maximum number of active sessions with a device a user
+//! can have at once
+
+use std::{net::IpAddr, num::NonZeroU32};
+
+use chrono::{DateTime, Utc};
+use mas_data_model::{
+    AuditEventPayload, AuditSessionType, Clock, Device, SessionLimitStrategy, SiteConfig, User,
+};
+use mas_storage::{
+    BoxRepository, Pagination, RepositoryAccess, RepositoryError,
+    app_session::{AppSession, AppSessionFilter},
+    queue::{QueueJobRepositoryExt as _, SyncDevicesJob},
+};
+use rand::RngCore;
+
+use crate::audit::schedule_audit_event;
+
+/// The outcome of checking the session limit of a user before starting a new
+/// session
+#[derive(Debug)]
+pub(crate) enum SessionLimit {
+    /// The user can start a new session
+    Allowed,
+
+    /// The user reached the limit, and the new session must be refused
+    Reached { limit: u32 },
+
+    /// The user reached the limit, and those sessions must be ended to make
+    /// room for the new one, least recently active first
+    Evict(Vec<AppSession>),
+}
+
+/// Get the devices of an application session
+fn devices(session: &AppSession) -> Vec<Device> {
+    match session {
+        AppSession::Compat(session) => session.device.iter().cloned().collect(),
+        AppSession::OAuth2(session) => session
+            .scope
+            .iter()
+            .filter_map(Device::from_scope_token)
+            .collect(),
+    }
+}
+
+/// Get the last time an application session was active
+fn last_active_at(session: &AppSession) -> DateTime<Utc> {
+    match session {
+        AppSession::Compat(session) => session.last_active_at.unwrap_or(session.created_at),
+        AppSession::OAuth2(session) => session.last_active_at.unwrap_or(session.created_at),
+    }
+}
+
+/// Get a name for an application session which the user can recognise: the
+/// name of the session if it has one, else its device ID
+pub(crate) fn display_name(session: &AppSession) -> String {
+    let human_name = match session {
+        AppSession::Compat(session) => session.human_name.as_deref(),
+        AppSession::OAuth2(session) => session.human_name.as_deref(),
+    };
+
+    if let Some(human_name) = human_name {
+        return human_name.to_owned();
+    }
+
+    devices(session)
+        .into_iter()
+        .next()
+        .map(String::from)
+        .unwrap_or_default()
+}
+
+/// Check whether a user can start a new session with the given devices
+///
+/// The limit set on the user overrides the one from the configuration. Only
+/// sessions with a device are limited. Active sessions which already use one
+/// of the devices are not counted, as the new session replaces them.
+pub(crate) async fn check(
+    repo: &mut BoxRepository,
+    site_config: &SiteConfig,
+    user: &User,
+    new_devices: &[Device],
+) -> Result<SessionLimit, RepositoryError> {
+    let config = site_config.session_limit;
+    let Some(limit) = user
+        .max_sessions
+        .or(config.max_per_user.map(NonZeroU32::get))
+    else {
+        return Ok(SessionLimit::Allowed);
+    };
+
+    if new_devices.is_empty() {
+        return Ok(SessionLimit::Allowed);
+    }
+
+    let mut sessions = Vec::new();
+    let filter = AppSessionFilter::new().for_user(user).active_only();
+    let mut cursor = Pagination::first(1000);
+    loop {
+        let page = repo.app_session().list(filter, cursor).await?;
+
+        for edge in page.edges {
+            let session_devices = devices(&edge.node);
+            if !session_devices.is_empty()
+                && !session_devices
+                    .iter()
+                    .any(|device| new_devices.contains(device))
+            {
+                sessions.push(edge.node);
+            }
+
+            cursor = cursor.after(edge.cursor);
+        }
+
+        if !page.has_next_page {
+            break;
+        }
+    }
+
+    let limit_usize = usize::try_from(limit).unwrap_or(usize::MAX);
+    if sessions.len() < limit_usize {
+        return Ok(SessionLimit::Allowed);
+    }
+
+    match config.strategy {
+        SessionLimitStrategy::Reject => Ok(SessionLimit::Reached { limit }),
+        SessionLimitStrategy::EvictOldest => {
+            sessions.sort_by_key(last_active_at);
+            // Make room for the new session
+            sessions.truncate(sessions.len() + 1 - limit_usize);
+            Ok(SessionLimit::Evict(sessions))
+        }
+    }
+}
+
+/// End the sessions evicted to make room for a new one, and schedule a sync
+/// of the devices of the user with the homeserver
+pub(crate) async fn evict(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    site_config: &SiteConfig,
+    ip_address: Option<IpAddr>,
+    user: &User,
+    sessions: Vec<AppSession>,
+) -> Result<(), RepositoryError> {
+    for session in sessions {
+        let (session_id, session_type) = match session {
+            AppSession::Compat(session) => {
+                let session = repo.compat_session().finish(clock, *session).await?;
+                (session.id, AuditSessionType::Compat)
+            }
+            AppSession::OAuth2(session) => {
+                let session = repo.oauth2_session().finish(clock, *session).await?;
+                (session.id, AuditSessionType::OAuth2)
+            }
+        };
+
+        tracing::info!(
+            user.id = %user.id,
+            session.id = %session_id,
+            "Ended a session to stay within the session limit of the user"
+        );
+
+        schedule_audit_event(
+            repo,
+            rng,
+            clock,
+            site_config,
+            ip_address,
+            AuditEventPayload::session_end(user.id, session_id, session_type),
+        )
+        .await?;
+    }
+
+    repo.queue_job()
+        .schedule_job(rng, clock, SyncDevicesJob::new(user))
+        .await?;
+
+    Ok(())
+}
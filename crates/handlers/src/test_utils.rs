@@ -5,8 +5,9 @@
 // Please see LICENSE files in the repository root for full details.
 
 use std::{
+    collections::BTreeMap,
     convert::Infallible,
-    sync::{Arc, Mutex, RwLock},
+    sync::{Arc, LazyLock, Mutex, RwLock},
     task::{Context, Poll},
 };
 
@@ -15,7 +16,7 @@ use axum::{
     extract::{FromRef, FromRequestParts},
     response::{IntoResponse, IntoResponseParts},
 };
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 use cookie_store::{CookieStore, RawCookie};
 use futures_util::future::BoxFuture;
 use headers::{Authorization, ContentType, HeaderMapExt, HeaderName, HeaderValue};
@@ -24,22 +25,34 @@ use hyper::{
     header::{CONTENT_TYPE, COOKIE, SET_COOKIE},
 };
 use mas_axum_utils::{
-    ErrorWrapper,
+    ErrorWrapper, RepositoryRejection,
     cookies::{CookieJar, CookieManager},
 };
 use mas_config::RateLimitingConfig;
-use mas_data_model::{AppVersion, BoxClock, BoxRng, SiteConfig, TchapConfig, clock::MockClock}; /*  */
+use mas_data_model::{
+    AppBuildInfo, AppVersion, BoxClock, BoxRng, BrowserSessionLifetimeConfig, Client, Clock,
+    DisallowedScopeHandling, SessionLimitConfig, SiteConfig, TchapConfig, TchapFeatures,
+    UserinfoClaimsConfig, clock::MockClock,
+}; /*  */
 use mas_email::{MailTransport, Mailer};
 use mas_i18n::Translator;
 use mas_keystore::{Encrypter, JsonWebKey, JsonWebKeySet, Keystore, PrivateKey};
 use mas_matrix::{HomeserverConnection, MockHomeserverConnection};
 use mas_policy::{InstantiateError, Policy, PolicyFactory};
 use mas_router::{SimpleRoute, UrlBuilder};
-use mas_storage::{BoxRepository, BoxRepositoryFactory, RepositoryError, RepositoryFactory};
+use mas_storage::{
+    BoxRepository, BoxRepositoryFactory, RepositoryAccess, RepositoryError, RepositoryFactory,
+    oauth2::OAuth2ClientRepository,
+};
 use mas_storage_pg::PgRepositoryFactory;
 use mas_tasks::QueueWorker;
 use mas_templates::{SiteConfigExt, Templates};
 use oauth2_types::{registration::ClientRegistrationResponse, requests::AccessTokenResponse};
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::metrics::{
+    InMemoryMetricExporter, PeriodicReader, SdkMeterProvider,
+    data::{AggregatedMetrics, HistogramDataPoint, Metric, MetricData, ScopeMetrics, SumDataPoint},
+};
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
 use serde::{Serialize, de::DeserializeOwned};
@@ -53,10 +66,49 @@ use url::Url;
 
 use crate::{
     ActivityTracker, BoundActivityTracker, Limiter, RequesterFingerprint, graphql,
+    oauth2::claims_augmentor::ClaimsAugmentors,
     passwords::{Hasher, PasswordManager},
     upstream_oauth2::cache::MetadataCache,
 };
 
+/// Provision an OAuth 2.0 client with no metadata
+pub(crate) async fn create_test_client(state: &TestState) -> Client {
+    let mut repo = state.repository().await.unwrap();
+    let mut rng = state.rng();
+
+    let client = repo
+        .oauth2_client()
+        .add(
+            &mut rng,
+            &state.clock,
+            vec![],
+            None,
+            None,
+            None,
+            vec![],
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    repo.save().await.unwrap();
+
+    client
+}
+
 /// Setup rustcrypto and tracing for tests.
 #[allow(unused_must_use)]
 pub(crate) fn setup() {
@@ -68,6 +120,76 @@ pub(crate) fn setup() {
         .try_init();
 }
 
+/// The metrics recorded during the tests, through an in-memory exporter
+/// installed as the global meter provider.
+///
+/// This gets installed before the crate-wide [`crate::METER`] is created, so
+/// that all the instruments report to it.
+pub(crate) static METRICS: LazyLock<TestMetrics> = LazyLock::new(|| {
+    let exporter = InMemoryMetricExporter::default();
+    let provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(exporter.clone()).build())
+        .build();
+    opentelemetry::global::set_meter_provider(provider.clone());
+    TestMetrics { provider, exporter }
+});
+
+pub(crate) struct TestMetrics {
+    provider: SdkMeterProvider,
+    exporter: InMemoryMetricExporter,
+}
+
+impl TestMetrics {
+    /// Collect the current value of the metric with the given name, and call
+    /// the callback on it
+    fn with_metric<T>(&self, name: &str, f: impl FnOnce(&Metric) -> T) -> Option<T> {
+        self.provider.force_flush().unwrap();
+        let metrics = self.exporter.get_finished_metrics().unwrap();
+
+        // Metrics are cumulative, so the last export has the latest values
+        let resource_metrics = metrics.last()?;
+        let metric = resource_metrics
+            .scope_metrics()
+            .flat_map(ScopeMetrics::metrics)
+            .find(|metric| metric.name() == name)?;
+        Some(f(metric))
+    }
+
+    /// Get the value of a counter, summed over the data points which have at
+    /// least the given attributes
+    pub fn counter(&self, name: &str, attributes: &[KeyValue]) -> u64 {
+        self.with_metric(name, |metric| match metric.data() {
+            AggregatedMetrics::U64(MetricData::Sum(sum)) => sum
+                .data_points()
+                .filter(|point| has_attributes(&point.attributes().collect::<Vec<_>>(), attributes))
+                .map(SumDataPoint::value)
+                .sum(),
+            _ => panic!("Metric {name} is not a u64 counter"),
+        })
+        .unwrap_or(0)
+    }
+
+    /// Get the number of values recorded in a histogram, over the data points
+    /// which have at least the given attributes
+    pub fn histogram_count(&self, name: &str, attributes: &[KeyValue]) -> u64 {
+        self.with_metric(name, |metric| match metric.data() {
+            AggregatedMetrics::F64(MetricData::Histogram(histogram)) => histogram
+                .data_points()
+                .filter(|point| has_attributes(&point.attributes().collect::<Vec<_>>(), attributes))
+                .map(HistogramDataPoint::count)
+                .sum(),
+            _ => panic!("Metric {name} is not a f64 histogram"),
+        })
+        .unwrap_or(0)
+    }
+}
+
+fn has_attributes(point_attributes: &[&KeyValue], attributes: &[KeyValue]) -> bool {
+    attributes
+        .iter()
+        .all(|attribute| point_attributes.contains(&attribute))
+}
+
 pub(crate) async fn policy_factory(
     server_name: &str,
     data: serde_json::Value,
@@ -83,6 +205,7 @@ pub(crate) async fn policy_factory(
         client_registration: "client_registration/violation".to_owned(),
         authorization_grant: "authorization_grant/violation".to_owned(),
         email: "email/violation".to_owned(),
+        account_recovery: "account_recovery/violation".to_owned(),
     };
 
     let data = mas_policy::Data::new(server_name.to_owned()).with_rest(data);
@@ -111,9 +234,12 @@ pub(crate) struct TestState {
     pub clock: Arc<MockClock>,
     pub rng: Arc<Mutex<ChaChaRng>>,
     pub http_client: reqwest::Client,
+    pub claims_augmentors: ClaimsAugmentors,
     pub task_tracker: TaskTracker,
+    pub started_at: DateTime<Utc>,
     //:tchap:
     pub tchap_config: TchapConfig,
+    pub tchap_features: TchapFeatures,
     //:tchap:end
     queue_worker: Arc<tokio::sync::Mutex<QueueWorker>>,
 
@@ -133,24 +259,49 @@ pub fn test_site_config() -> SiteConfig {
     SiteConfig {
         access_token_ttl: Duration::try_minutes(5).unwrap(),
         compat_token_ttl: Duration::try_minutes(5).unwrap(),
+        pushed_authorization_request_ttl: Duration::try_minutes(1).unwrap(),
         server_name: "example.com".to_owned(),
         policy_uri: Some("https://example.com/policy".parse().unwrap()),
         tos_uri: Some("https://example.com/tos".parse().unwrap()),
         imprint: None,
+        template_globals: BTreeMap::new(),
         password_login_enabled: true,
         password_registration_enabled: true,
         registration_token_required: false,
         email_change_allowed: true,
+        email_change_double_confirmation: false,
+        new_login_notification: false,
         displayname_change_allowed: true,
         password_change_allowed: true,
         password_registration_email_required: true,
         account_recovery_allowed: true,
+        account_recovery_email_interval: Duration::try_minutes(5).unwrap(),
         account_deactivation_allowed: true,
+        deactivation_grace_period: Duration::try_days(7).unwrap(),
+        deleted_user_retention: Duration::try_days(30).unwrap(),
+        protected_usernames: Vec::new(),
+        upstream_authorization_retention: Duration::try_days(7).unwrap(),
         captcha: None,
         minimum_password_complexity: 1,
         session_expiration: None,
         login_with_email_allowed: true,
+        auto_redirect_single_provider: true,
+        prefer_password_login: true,
         plan_management_iframe_uri: None,
+        admin_api_csv_export_limit: 100_000,
+        audit_webhook: None,
+        email_probe: None,
+        sync_emails_to_homeserver: true,
+        userinfo_claims: UserinfoClaimsConfig::default(),
+        device_code_sensitive_scopes: vec![
+            "urn:mas:admin".to_owned(),
+            "urn:synapse:admin:*".to_owned(),
+        ],
+        session_limit: SessionLimitConfig::default(),
+        browser_session_lifetime: BrowserSessionLifetimeConfig::default(),
+        disallowed_scope_handling: DisallowedScopeHandling::default(),
+        matrix_session_in_token_response: false,
+        admin_api_examples: false,
     }
 }
 
@@ -215,10 +366,15 @@ impl TestState {
             Arc::new(MockHomeserverConnection::new(&site_config.server_name));
 
         let clock = Arc::new(MockClock::default());
+        let started_at = clock.now();
         let rng = Arc::new(Mutex::new(ChaChaRng::seed_from_u64(42)));
 
         let limiter = Limiter::new(&RateLimitingConfig::default()).unwrap();
 
+        //:tchap:
+        let tchap_config = tchap::test_tchap_config();
+        //:tchap:end
+
         let graphql_state = TestGraphQLState {
             repository_factory: PgRepositoryFactory::new(pool.clone()).boxed(),
             policy_factory: Arc::clone(&policy_factory),
@@ -229,6 +385,10 @@ impl TestState {
             password_manager: password_manager.clone(),
             url_builder: url_builder.clone(),
             limiter: limiter.clone(),
+            templates: templates.clone(),
+            //:tchap:
+            tchap_config: tchap_config.clone(),
+            //:tchap:end
         };
         let state: crate::graphql::BoxState = Box::new(graphql_state);
 
@@ -255,6 +415,7 @@ impl TestState {
             homeserver_connection.clone(),
             url_builder.clone(),
             &site_config,
+            &TchapFeatures::default(),
             shutdown_token.child_token(),
         )
         .await
@@ -262,10 +423,6 @@ impl TestState {
 
         let queue_worker = Arc::new(tokio::sync::Mutex::new(queue_worker));
 
-        //:tchap:
-        let tchap_config = tchap::test_tchap_config();
-        //:tchap:end
-
         Ok(Self {
             repository_factory: PgRepositoryFactory::new(pool),
             templates,
@@ -284,11 +441,14 @@ impl TestState {
             clock,
             rng,
             http_client,
+            claims_augmentors: ClaimsAugmentors::default(),
             task_tracker,
+            started_at,
             queue_worker,
             cancellation_drop_guard: Arc::new(shutdown_token.drop_guard()),
             //:tchap:
             tchap_config,
+            tchap_features: TchapFeatures::default(),
             //:tchap:end
         })
     }
@@ -301,6 +461,32 @@ impl TestState {
         queue.process_all_jobs_in_tests().await.unwrap();
     }
 
+    /// Start another queue worker on the same database, like a separate
+    /// worker process would.
+    ///
+    /// Panics if it fails to start the worker
+    pub async fn new_queue_worker(&self) -> QueueWorker {
+        let mailer = Mailer::new(
+            self.templates.clone(),
+            MailTransport::blackhole(),
+            "hello@example.com".parse().unwrap(),
+            "hello@example.com".parse().unwrap(),
+        );
+
+        mas_tasks::init(
+            PgRepositoryFactory::new(self.repository_factory.pool()),
+            Arc::clone(&self.clock),
+            &mailer,
+            Arc::clone(&self.homeserver_connection),
+            self.url_builder.clone(),
+            &self.site_config,
+            &self.tchap_features,
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap()
+    }
+
     /// Reset the test utils to a fresh state, with the same configuration.
     pub async fn reset(self) -> Self {
         let site_config = self.site_config.clone();
@@ -330,11 +516,18 @@ impl TestState {
             .merge(crate::discovery_router())
             .merge(crate::api_router())
             .merge(crate::compat_router(self.templates.clone()))
-            .merge(crate::human_router(self.templates.clone()))
+            .merge(crate::human_router())
             // We enable undocumented_oauth2_access for the tests, as it is easier to query the API
             // with it
             .merge(crate::graphql_router(false, true))
             .merge(crate::admin_api_router().1)
+            .fallback(crate::fallback)
+            .layer(axum::middleware::from_fn({
+                let templates = self.templates.clone();
+                move |request: axum::extract::Request, next: axum::middleware::Next| {
+                    crate::negotiate_error_format(templates.clone(), request, next)
+                }
+            }))
             .with_state(self.clone())
             .into_service();
 
@@ -449,6 +642,10 @@ struct TestGraphQLState {
     password_manager: PasswordManager,
     url_builder: UrlBuilder,
     limiter: Limiter,
+    templates: Templates,
+    //:tchap:
+    tchap_config: TchapConfig,
+    //:tchap:end
 }
 
 #[async_trait::async_trait]
@@ -485,6 +682,16 @@ impl graphql::State for TestGraphQLState {
         &self.limiter
     }
 
+    fn translator(&self) -> Arc<Translator> {
+        self.templates.translator()
+    }
+
+    //:tchap:
+    fn tchap_config(&self) -> &TchapConfig {
+        &self.tchap_config
+    }
+    //:tchap:end
+
     fn rng(&self) -> BoxRng {
         let mut parent_rng = self.rng.lock().expect("Failed to lock RNG");
         let rng = ChaChaRng::from_rng(&mut *parent_rng).expect("Failed to seed RNG");
@@ -588,12 +795,24 @@ impl FromRef<TestState> for reqwest::Client {
     }
 }
 
+impl FromRef<TestState> for ClaimsAugmentors {
+    fn from_ref(input: &TestState) -> Self {
+        input.claims_augmentors.clone()
+    }
+}
+
 //:tchap:
 impl FromRef<TestState> for TchapConfig {
     fn from_ref(input: &TestState) -> Self {
         input.tchap_config.clone()
     }
 }
+
+impl FromRef<TestState> for TchapFeatures {
+    fn from_ref(input: &TestState) -> Self {
+        input.tchap_features
+    }
+}
 //:tchap:end
 
 impl FromRef<TestState> for AppVersion {
@@ -602,6 +821,16 @@ impl FromRef<TestState> for AppVersion {
     }
 }
 
+impl FromRef<TestState> for AppBuildInfo {
+    fn from_ref(input: &TestState) -> Self {
+        AppBuildInfo {
+            git_sha: Some("0000000000000000000000000000000000000000"),
+            rustc_version: Some("1.0.0"),
+            started_at: input.started_at,
+        }
+    }
+}
+
 impl FromRequestParts<TestState> for ActivityTracker {
     type Rejection = Infallible;
 
@@ -661,7 +890,7 @@ impl FromRequestParts<TestState> for BoxRng {
 }
 
 impl FromRequestParts<TestState> for BoxRepository {
-    type Rejection = ErrorWrapper<RepositoryError>;
+    type Rejection = RepositoryRejection;
 
     async fn from_request_parts(
         _parts: &mut axum::http::request::Parts,
@@ -4,6 +4,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::sync::LazyLock;
+
 use axum::{
     extract::{Path, State},
     response::{IntoResponse, Redirect},
@@ -18,15 +20,25 @@ use mas_storage::{
     BoxRepository,
     upstream_oauth2::{UpstreamOAuthProviderRepository, UpstreamOAuthSessionRepository},
 };
+use mas_templates::ErrorCode;
+use opentelemetry::metrics::Counter;
 use thiserror::Error;
 use ulid::Ulid;
 
-use super::{UpstreamSessionsCookie, cache::LazyProviderInfos};
+use super::{UpstreamSessionsCookie, cache::LazyProviderInfos, provider_attributes};
 use crate::{
-    impl_from_error_for_route, upstream_oauth2::cache::MetadataCache,
+    METER, impl_from_error_for_route, upstream_oauth2::cache::MetadataCache,
     views::shared::OptionalPostAuthAction,
 };
 
+static AUTHORIZE_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    METER
+        .u64_counter("mas.upstream_oauth2.authorize")
+        .with_description("Number of redirects to the authorization endpoint of upstream providers")
+        .with_unit("{redirect}")
+        .build()
+});
+
 #[derive(Debug, Error)]
 pub(crate) enum RouteError {
     #[error("Provider not found")]
@@ -43,9 +55,9 @@ impl_from_error_for_route!(mas_storage::RepositoryError);
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
         match self {
-            e @ Self::ProviderNotFound => {
-                GenericError::new(StatusCode::NOT_FOUND, e).into_response()
-            }
+            e @ Self::ProviderNotFound => GenericError::new(StatusCode::NOT_FOUND, e)
+                .with_error_code(ErrorCode::UpstreamProviderNotFound)
+                .into_response(),
             Self::Internal(e) => InternalError::new(e).into_response(),
         }
     }
@@ -145,5 +157,7 @@ pub(crate) async fn get(
 
     repo.save().await?;
 
+    AUTHORIZE_COUNTER.add(1, &provider_attributes(&provider));
+
     Ok((cookie_jar, Redirect::temporary(url.as_str())))
 }
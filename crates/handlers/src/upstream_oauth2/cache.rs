@@ -6,9 +6,11 @@
 
 use std::{collections::HashMap, sync::Arc};
 
+use chrono::{DateTime, Utc};
 use mas_context::LogContext;
 use mas_data_model::{
-    UpstreamOAuthProvider, UpstreamOAuthProviderDiscoveryMode, UpstreamOAuthProviderPkceMode,
+    Clock, SystemClock, UpstreamOAuthProvider, UpstreamOAuthProviderDiscoveryMode,
+    UpstreamOAuthProviderPkceMode,
 };
 use mas_iana::oauth::PkceCodeChallengeMethod;
 use mas_oidc_client::error::DiscoveryError;
@@ -54,17 +56,7 @@ impl<'a> LazyProviderInfos<'a> {
 
     async fn load(&mut self) -> Result<&VerifiedProviderMetadata, DiscoveryError> {
         if self.loaded_metadata.is_none() {
-            let verify = match self.provider.discovery_mode {
-                UpstreamOAuthProviderDiscoveryMode::Oidc => true,
-                UpstreamOAuthProviderDiscoveryMode::Insecure => false,
-                UpstreamOAuthProviderDiscoveryMode::Disabled => {
-                    return Err(DiscoveryError::Disabled);
-                }
-            };
-
-            let Some(issuer) = &self.provider.issuer else {
-                return Err(DiscoveryError::MissingIssuer);
-            };
+            let (issuer, verify) = discovery_params(self.provider)?;
 
             let metadata = self.cache.get(self.client, issuer, verify).await?;
 
@@ -142,16 +134,65 @@ impl<'a> LazyProviderInfos<'a> {
     }
 }
 
+/// Get the issuer and whether the metadata should be verified for a provider
+fn discovery_params(provider: &UpstreamOAuthProvider) -> Result<(&str, bool), DiscoveryError> {
+    let verify = match provider.discovery_mode {
+        UpstreamOAuthProviderDiscoveryMode::Oidc => true,
+        UpstreamOAuthProviderDiscoveryMode::Insecure => false,
+        UpstreamOAuthProviderDiscoveryMode::Disabled => {
+            return Err(DiscoveryError::Disabled);
+        }
+    };
+
+    let Some(issuer) = &provider.issuer else {
+        return Err(DiscoveryError::MissingIssuer);
+    };
+
+    Ok((issuer, verify))
+}
+
+/// The outcome of the last metadata discovery for an issuer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveryStatus {
+    /// When the metadata was last fetched
+    pub fetched_at: DateTime<Utc>,
+
+    /// The error which happened during the last fetch, if it failed
+    pub error: Option<String>,
+}
+
+/// A snapshot of the discovery statuses known by the [`MetadataCache`]
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveryStatuses {
+    inner: HashMap<(String, bool), DiscoveryStatus>,
+}
+
+impl DiscoveryStatuses {
+    /// Get the status of the last discovery for the given provider.
+    ///
+    /// Returns `None` if discovery is disabled for this provider, or if its
+    /// metadata was never fetched yet.
+    #[must_use]
+    pub fn for_provider(&self, provider: &UpstreamOAuthProvider) -> Option<&DiscoveryStatus> {
+        let (issuer, verify) = discovery_params(provider).ok()?;
+        self.inner.get(&(issuer.to_owned(), verify))
+    }
+}
+
 /// A simple OIDC metadata cache
 ///
 /// It never evicts entries, does not cache failures and has no locking.
 /// It can also be refreshed in the background, and warmed up on startup.
 /// It is good enough for our use case.
+///
+/// It also keeps track of the outcome of the last fetch of each issuer, so
+/// that failing issuers are retried on the next background refresh.
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Clone, Default)]
 pub struct MetadataCache {
     cache: Arc<RwLock<HashMap<String, Arc<VerifiedProviderMetadata>>>>,
     insecure_cache: Arc<RwLock<HashMap<String, Arc<VerifiedProviderMetadata>>>>,
+    statuses: Arc<RwLock<HashMap<(String, bool), DiscoveryStatus>>>,
 }
 
 impl MetadataCache {
@@ -179,15 +220,13 @@ impl MetadataCache {
         let providers = repository.upstream_oauth_provider().all_enabled().await?;
 
         for provider in providers {
-            let verify = match provider.discovery_mode {
-                UpstreamOAuthProviderDiscoveryMode::Oidc => true,
-                UpstreamOAuthProviderDiscoveryMode::Insecure => false,
-                UpstreamOAuthProviderDiscoveryMode::Disabled => continue,
-            };
-
-            let Some(issuer) = &provider.issuer else {
-                tracing::error!(%provider.id, "Provider doesn't have an issuer set, but discovery is enabled!");
-                continue;
+            let (issuer, verify) = match discovery_params(&provider) {
+                Ok(params) => params,
+                Err(DiscoveryError::MissingIssuer) => {
+                    tracing::error!(%provider.id, "Provider doesn't have an issuer set, but discovery is enabled!");
+                    continue;
+                }
+                Err(_) => continue,
             };
 
             if let Err(e) = self.fetch(client, issuer, verify).await {
@@ -216,27 +255,61 @@ impl MetadataCache {
         issuer: &str,
         verify: bool,
     ) -> Result<Arc<VerifiedProviderMetadata>, DiscoveryError> {
-        if verify {
-            let metadata = mas_oidc_client::requests::discovery::discover(client, issuer).await?;
-            let metadata = Arc::new(metadata);
+        let result = if verify {
+            mas_oidc_client::requests::discovery::discover(client, issuer).await
+        } else {
+            mas_oidc_client::requests::discovery::insecure_discover(client, issuer).await
+        };
 
-            self.cache
-                .write()
-                .await
-                .insert(issuer.to_owned(), metadata.clone());
+        let status = DiscoveryStatus {
+            fetched_at: SystemClock::default().now(),
+            error: result.as_ref().err().map(ToString::to_string),
+        };
+        self.statuses
+            .write()
+            .await
+            .insert((issuer.to_owned(), verify), status);
 
-            Ok(metadata)
+        let metadata = Arc::new(result?);
+
+        let cache = if verify {
+            &self.cache
         } else {
-            let metadata =
-                mas_oidc_client::requests::discovery::insecure_discover(client, issuer).await?;
-            let metadata = Arc::new(metadata);
+            &self.insecure_cache
+        };
+
+        cache
+            .write()
+            .await
+            .insert(issuer.to_owned(), metadata.clone());
 
-            self.insecure_cache
-                .write()
-                .await
-                .insert(issuer.to_owned(), metadata.clone());
+        Ok(metadata)
+    }
 
-            Ok(metadata)
+    /// Force a re-fetch of the metadata of the given provider, bypassing the
+    /// cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if discovery is disabled for this provider, or if the
+    /// metadata could not be retrieved.
+    #[tracing::instrument(name = "metadata_cache.refresh", fields(upstream_oauth_provider.id = %provider.id), skip_all)]
+    pub async fn refresh(
+        &self,
+        client: &reqwest::Client,
+        provider: &UpstreamOAuthProvider,
+    ) -> Result<Arc<VerifiedProviderMetadata>, DiscoveryError> {
+        let (issuer, verify) = discovery_params(provider)?;
+        self.fetch(client, issuer, verify).await
+    }
+
+    /// Get a snapshot of the outcome of the last discovery of each known
+    /// issuer.
+    ///
+    /// This never triggers a fetch.
+    pub async fn discovery_statuses(&self) -> DiscoveryStatuses {
+        DiscoveryStatuses {
+            inner: self.statuses.read().await.clone(),
         }
     }
 
@@ -270,26 +343,16 @@ impl MetadataCache {
 
     #[tracing::instrument(name = "metadata_cache.refresh_all", skip_all)]
     async fn refresh_all(&self, client: &reqwest::Client) {
-        // Grab all the keys first to avoid locking the cache for too long
-        let keys: Vec<String> = {
-            let cache = self.cache.read().await;
-            cache.keys().cloned().collect()
+        // Grab all the keys first to avoid locking the cache for too long. This
+        // includes the issuers for which the last fetch failed, so that they get
+        // retried.
+        let keys: Vec<(String, bool)> = {
+            let statuses = self.statuses.read().await;
+            statuses.keys().cloned().collect()
         };
 
-        for issuer in keys {
-            if let Err(e) = self.fetch(client, &issuer, true).await {
-                tracing::error!(issuer = %issuer, error = &e as &dyn std::error::Error, "Failed to refresh provider metadata");
-            }
-        }
-
-        // Do the same for the insecure cache
-        let keys: Vec<String> = {
-            let cache = self.insecure_cache.read().await;
-            cache.keys().cloned().collect()
-        };
-
-        for issuer in keys {
-            if let Err(e) = self.fetch(client, &issuer, false).await {
+        for (issuer, verify) in keys {
+            if let Err(e) = self.fetch(client, &issuer, verify).await {
                 tracing::error!(issuer = %issuer, error = &e as &dyn std::error::Error, "Failed to refresh provider metadata");
             }
         }
@@ -330,7 +393,7 @@ mod tests {
             .await
             .unwrap_err();
 
-        let expected_calls = 3;
+        let expected_calls = 4;
         let mut calls = 0;
         let _mock_guard = Mock::given(method("GET"))
             .and(path("/.well-known/openid-configuration"))
@@ -372,11 +435,19 @@ mod tests {
             .unwrap_err();
         calls += 1;
 
-        // Calling refresh should refresh all the known issuers
+        // Calling refresh should refresh all the known issuers, including the one
+        // which failed
         cache.refresh_all(&http_client).await;
-        calls += 1;
+        calls += 2;
 
         assert_eq!(calls, expected_calls);
+
+        // The outcome of the last fetches should be recorded
+        let statuses = cache.statuses.read().await;
+        let insecure = &statuses[&(mock_server.uri(), false)];
+        assert_eq!(insecure.error, None);
+        let secure = &statuses[&(mock_server.uri(), true)];
+        assert!(secure.error.is_some());
     }
 
     #[tokio::test]
@@ -433,6 +504,7 @@ mod tests {
             claims_imports: UpstreamOAuthProviderClaimsImports::default(),
             additional_authorization_parameters: Vec::new(),
             forward_login_hint: false,
+            ui_hidden: false,
             on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
         };
 
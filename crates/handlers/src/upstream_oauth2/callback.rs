@@ -13,7 +13,7 @@ use axum::{
     response::{Html, IntoResponse, Response},
 };
 use hyper::StatusCode;
-use mas_axum_utils::{GenericError, InternalError, cookies::CookieJar};
+use mas_axum_utils::{GenericError, InternalError, cookies::CookieJar, record_error};
 use mas_data_model::{
     BoxClock, BoxRng, Clock, UpstreamOAuthProvider, UpstreamOAuthProviderResponseMode,
 };
@@ -28,7 +28,7 @@ use mas_storage::{
         UpstreamOAuthSessionRepository,
     },
 };
-use mas_templates::{FormPostContext, Templates};
+use mas_templates::{ErrorCode, FormPostContext, TemplateContext, Templates, UpstreamErrorContext};
 use oauth2_types::{errors::ClientErrorCode, requests::AccessTokenRequest};
 use opentelemetry::{Key, KeyValue, metrics::Counter};
 use serde::{Deserialize, Serialize};
@@ -39,7 +39,7 @@ use ulid::Ulid;
 use super::{
     UpstreamSessionsCookie,
     cache::LazyProviderInfos,
-    client_credentials_for_provider,
+    check_token_endpoint_auth, client_credentials_for_provider, provider_attributes,
     template::{AttributeMappingContext, environment},
 };
 use crate::{
@@ -52,8 +52,44 @@ static CALLBACK_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
         .with_description("Number of requests to the upstream OAuth2 callback endpoint")
         .build()
 });
-const PROVIDER: Key = Key::from_static_str("provider");
+static TOKEN_EXCHANGE_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    METER
+        .u64_counter("mas.upstream_oauth2.token_exchange")
+        .with_description("Number of authorization codes exchanged with upstream providers")
+        .with_unit("{exchange}")
+        .build()
+});
 const RESULT: Key = Key::from_static_str("result");
+const ERROR_TYPE: Key = Key::from_static_str("error.type");
+const ERROR_CODE: Key = Key::from_static_str("error.code");
+
+/// Record a failed token exchange with an upstream provider, both on the
+/// current span and in the metrics
+fn record_token_exchange_failure(
+    provider: &UpstreamOAuthProvider,
+    error_type: &'static str,
+    error: &(dyn std::error::Error + 'static),
+) {
+    tracing::error!(
+        error,
+        "Failed to exchange the authorization code with the upstream provider"
+    );
+
+    let mut attributes = provider_attributes(provider);
+    attributes.push(KeyValue::new(RESULT, "failure"));
+    attributes.push(KeyValue::new(ERROR_TYPE, error_type));
+    TOKEN_EXCHANGE_COUNTER.add(1, &attributes);
+}
+
+/// The class of error of a failed token request, used as an attribute on the
+/// metrics
+fn token_request_error_type(error: &mas_oidc_client::error::TokenRequestError) -> &'static str {
+    match error {
+        mas_oidc_client::error::TokenRequestError::Http(_) => "http",
+        mas_oidc_client::error::TokenRequestError::OAuth2(_) => "oauth2",
+        mas_oidc_client::error::TokenRequestError::Credentials(_) => "credentials",
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct Params {
@@ -119,12 +155,6 @@ pub(crate) enum RouteError {
     #[error("Subject is empty")]
     EmptySubject,
 
-    #[error("Error from the provider: {error}")]
-    ClientError {
-        error: ClientErrorCode,
-        error_description: Option<String>,
-    },
-
     #[error("Missing session cookie")]
     MissingCookie,
 
@@ -155,13 +185,19 @@ impl_from_error_for_route!(super::cookie::UpstreamSessionNotFound);
 
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
-        match self {
+        let sentry_event_id = record_error!(self, Self::Internal(_));
+        let response = match self {
             Self::Internal(e) => InternalError::new(e).into_response(),
-            e @ (Self::ProviderNotFound | Self::SessionNotFound) => {
-                GenericError::new(StatusCode::NOT_FOUND, e).into_response()
-            }
+            e @ Self::ProviderNotFound => GenericError::new(StatusCode::NOT_FOUND, e)
+                .with_error_code(ErrorCode::UpstreamProviderNotFound)
+                .into_response(),
+            e @ Self::SessionNotFound => GenericError::new(StatusCode::NOT_FOUND, e)
+                .with_error_code(ErrorCode::SessionRequired)
+                .into_response(),
             e => GenericError::new(StatusCode::BAD_REQUEST, e).into_response(),
-        }
+        };
+
+        (sentry_event_id, response).into_response()
     }
 }
 
@@ -229,18 +265,64 @@ pub(crate) async fn handler(
     }
 
     if let Some(error) = params.error {
-        CALLBACK_COUNTER.add(
-            1,
-            &[
-                KeyValue::new(PROVIDER, provider_id.to_string()),
-                KeyValue::new(RESULT, "error"),
-            ],
+        // Don't let arbitrary error codes from the provider blow up the
+        // cardinality of the metric
+        let error_code_attribute = match &error {
+            ClientErrorCode::Unknown(_) => "unknown".to_owned(),
+            error => error.to_string(),
+        };
+        let mut attributes = provider_attributes(&provider);
+        attributes.push(KeyValue::new(RESULT, "error"));
+        attributes.push(KeyValue::new(ERROR_CODE, error_code_attribute));
+        CALLBACK_COUNTER.add(1, &attributes);
+
+        tracing::info!(
+            %error,
+            error_description = params.error_description.as_deref(),
+            "The upstream provider answered the authorization request with an error"
         );
 
-        return Err(RouteError::ClientError {
-            error,
-            error_description: params.error_description.clone(),
-        });
+        let mut retry = mas_router::UpstreamOAuth2Authorize::new(provider.id);
+
+        // If we can find the authorization session this callback is for, mark it as
+        // failed, and make sure retrying keeps what the user was doing
+        if let Some(state) = params.state.as_deref()
+            && let Ok((session_id, post_auth_action)) =
+                sessions_cookie.find_session(provider_id, state)
+        {
+            if let Some(post_auth_action) = post_auth_action {
+                retry = retry.and_then(post_auth_action.clone());
+            }
+
+            let session = repo
+                .upstream_oauth_session()
+                .lookup(session_id)
+                .await?
+                .filter(|session| {
+                    session.provider_id == provider.id
+                        && session.state_str == state
+                        && session.is_pending()
+                });
+
+            if let Some(session) = session {
+                repo.upstream_oauth_session()
+                    .fail(&clock, session, error.to_string())
+                    .await?;
+            }
+        }
+
+        repo.save().await?;
+
+        let context = UpstreamErrorContext::new(
+            provider,
+            error.to_string(),
+            url_builder.relative_url_for(&retry),
+        )
+        .with_error_description(params.error_description)
+        .with_language(locale);
+        let html = templates.render_upstream_oauth2_error(&context)?;
+
+        return Ok(Html(html).into_response());
     }
 
     let Some(state) = params.state else {
@@ -277,16 +359,17 @@ pub(crate) async fn handler(
         return Err(RouteError::MissingCode);
     };
 
-    CALLBACK_COUNTER.add(
-        1,
-        &[
-            KeyValue::new(PROVIDER, provider_id.to_string()),
-            KeyValue::new(RESULT, "success"),
-        ],
-    );
+    let mut attributes = provider_attributes(&provider);
+    attributes.push(KeyValue::new(RESULT, "success"));
+    CALLBACK_COUNTER.add(1, &attributes);
 
     let mut lazy_metadata = LazyProviderInfos::new(&metadata_cache, &provider, &client);
 
+    // Make sure the provider accepts the way we authenticate to it
+    if let Some(metadata) = lazy_metadata.maybe_discover().await? {
+        check_token_endpoint_auth(&provider, metadata)?;
+    }
+
     // Figure out the client credentials
     let client_credentials = client_credentials_for_provider(
         &provider,
@@ -309,7 +392,8 @@ pub(crate) async fn handler(
         clock.now(),
         &mut rng,
     )
-    .await?;
+    .await
+    .inspect_err(|e| record_token_exchange_failure(&provider, token_request_error_type(e), e))?;
 
     let mut jwks = None;
     let mut id_token_claims = None;
@@ -318,7 +402,8 @@ pub(crate) async fn handler(
     if let Some(id_token) = token_response.id_token.as_ref() {
         jwks = Some(
             mas_oidc_client::requests::jose::fetch_jwks(&client, lazy_metadata.jwks_uri().await?)
-                .await?,
+                .await
+                .inspect_err(|e| record_token_exchange_failure(&provider, "jwks", e))?,
         );
 
         let id_token_verification_data = JwtVerificationData {
@@ -334,7 +419,8 @@ pub(crate) async fn handler(
             id_token_verification_data,
             None,
             clock.now(),
-        )?;
+        )
+        .inspect_err(|e| record_token_exchange_failure(&provider, "id_token", e))?;
 
         let (_headers, mut claims) = id_token.into_parts();
 
@@ -355,7 +441,8 @@ pub(crate) async fn handler(
                     &token_response.access_token,
                 ),
             )
-            .map_err(mas_oidc_client::error::IdTokenError::from)?;
+            .map_err(mas_oidc_client::error::IdTokenError::from)
+            .inspect_err(|e| record_token_exchange_failure(&provider, "id_token", e))?;
 
         // Code hash must match.
         mas_jose::claims::C_HASH
@@ -363,18 +450,24 @@ pub(crate) async fn handler(
                 &mut claims,
                 TokenHash::new(id_token_verification_data.signing_algorithm, &code),
             )
-            .map_err(mas_oidc_client::error::IdTokenError::from)?;
+            .map_err(mas_oidc_client::error::IdTokenError::from)
+            .inspect_err(|e| record_token_exchange_failure(&provider, "id_token", e))?;
 
         // Nonce must match if present.
         if let Some(nonce) = session.nonce.as_deref() {
             mas_jose::claims::NONCE
                 .extract_required_with_options(&mut claims, nonce)
-                .map_err(mas_oidc_client::error::IdTokenError::from)?;
+                .map_err(mas_oidc_client::error::IdTokenError::from)
+                .inspect_err(|e| record_token_exchange_failure(&provider, "id_token", e))?;
         }
 
         context = context.with_id_token_claims(claims);
     }
 
+    let mut attributes = provider_attributes(&provider);
+    attributes.push(KeyValue::new(RESULT, "success"));
+    TOKEN_EXCHANGE_COUNTER.add(1, &attributes);
+
     if let Some(extra_callback_parameters) = params.extra_callback_parameters.clone() {
         context = context.with_extra_callback_parameters(extra_callback_parameters);
     }
@@ -499,3 +592,417 @@ pub(crate) async fn handler(
     )
         .into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode, header::LOCATION};
+    use mas_data_model::{
+        UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderDiscoveryMode,
+        UpstreamOAuthProviderOnBackchannelLogout, UpstreamOAuthProviderPkceMode,
+        UpstreamOAuthProviderTokenAuthMethod,
+    };
+    use mas_iana::jose::JsonWebSignatureAlg;
+    use mas_router::Route;
+    use mas_storage::{upstream_oauth2::UpstreamOAuthProviderParams, user::UserRepository};
+    use oauth2_types::scope::{OPENID, Scope};
+    use opentelemetry::KeyValue;
+    use sqlx::PgPool;
+    use url::Url;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, method, path},
+    };
+
+    use super::*;
+    use crate::test_utils::{
+        CookieHelper, METRICS, RequestBuilderExt, ResponseExt, TestState, setup,
+    };
+
+    /// Start a login with the provider, and return the state parameter sent to
+    /// it
+    async fn authorize(state: &TestState, cookies: &CookieHelper, provider_id: Ulid) -> String {
+        authorize_with(
+            state,
+            cookies,
+            mas_router::UpstreamOAuth2Authorize::new(provider_id),
+        )
+        .await
+    }
+
+    /// Same as [`authorize`], but with an arbitrary authorize route, e.g. with
+    /// a post-auth action
+    async fn authorize_with(
+        state: &TestState,
+        cookies: &CookieHelper,
+        route: mas_router::UpstreamOAuth2Authorize,
+    ) -> String {
+        let request = Request::get(&*route.path_and_query()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::TEMPORARY_REDIRECT);
+
+        let location = response.headers()[LOCATION].to_str().unwrap();
+        let url = Url::parse(location).unwrap();
+        url.query_pairs()
+            .find(|(key, _)| key == "state")
+            .map(|(_, value)| value.into_owned())
+            .unwrap()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_login_metrics(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let mock_server = MockServer::start().await;
+        let base = Url::parse(&mock_server.uri()).unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("code=good"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "access-token",
+                "token_type": "Bearer",
+            })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("code=bad"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": "invalid_grant",
+            })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/userinfo"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "sub": "alice",
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    issuer: None,
+                    human_name: Some("Metrics Ltd.".to_owned()),
+                    brand_name: None,
+                    scope: Scope::from_iter([OPENID]),
+                    token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                    token_endpoint_signing_alg: None,
+                    id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                    client_id: "client".to_owned(),
+                    encrypted_client_secret: None,
+                    claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+                    authorization_endpoint_override: Some(base.join("/authorize").unwrap()),
+                    token_endpoint_override: Some(base.join("/token").unwrap()),
+                    userinfo_endpoint_override: Some(base.join("/userinfo").unwrap()),
+                    fetch_userinfo: true,
+                    userinfo_signed_response_alg: None,
+                    jwks_uri_override: None,
+                    discovery_mode: UpstreamOAuthProviderDiscoveryMode::Disabled,
+                    pkce_mode: UpstreamOAuthProviderPkceMode::Disabled,
+                    response_mode: None,
+                    additional_authorization_parameters: Vec::new(),
+                    forward_login_hint: false,
+                    on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                    ui_order: 0,
+                    ui_hidden: false,
+                },
+            )
+            .await
+            .unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let link = repo
+            .upstream_oauth_link()
+            .add(&mut rng, &state.clock, &provider, "alice".to_owned(), None)
+            .await
+            .unwrap();
+        repo.upstream_oauth_link()
+            .associate_to_user(&link, &user)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let attributes = [
+            KeyValue::new("provider", provider.id.to_string()),
+            KeyValue::new("provider.name", "Metrics Ltd."),
+        ];
+        let with = |extra: &[KeyValue]| [attributes.as_slice(), extra].concat();
+        let callback_path = mas_router::UpstreamOAuth2Callback::new(provider.id).path();
+
+        // A successful login
+        let upstream_state = authorize(&state, &cookies, provider.id).await;
+        state.clock.advance(Duration::try_seconds(30).unwrap());
+
+        let query =
+            serde_urlencoded::to_string([("state", upstream_state.as_str()), ("code", "good")])
+                .unwrap();
+        let request = Request::get(format!("{callback_path}?{query}")).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.headers()[LOCATION].to_str().unwrap().to_owned();
+
+        let request = Request::get(location).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        assert_eq!(
+            METRICS.counter("mas.upstream_oauth2.authorize", &attributes),
+            1
+        );
+        assert_eq!(
+            METRICS.counter(
+                "mas.upstream_oauth2.callback",
+                &with(&[KeyValue::new("result", "success")])
+            ),
+            1
+        );
+        assert_eq!(
+            METRICS.counter(
+                "mas.upstream_oauth2.token_exchange",
+                &with(&[KeyValue::new("result", "success")])
+            ),
+            1
+        );
+        assert_eq!(
+            METRICS.histogram_count("mas.upstream_oauth2.login.duration", &attributes),
+            1
+        );
+
+        // A login where the provider refuses the authorization code
+        let upstream_state = authorize(&state, &cookies, provider.id).await;
+
+        let query =
+            serde_urlencoded::to_string([("state", upstream_state.as_str()), ("code", "bad")])
+                .unwrap();
+        let request = Request::get(format!("{callback_path}?{query}")).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+
+        assert_eq!(
+            METRICS.counter("mas.upstream_oauth2.authorize", &attributes),
+            2
+        );
+        assert_eq!(
+            METRICS.counter(
+                "mas.upstream_oauth2.callback",
+                &with(&[KeyValue::new("result", "success")])
+            ),
+            2
+        );
+        assert_eq!(
+            METRICS.counter(
+                "mas.upstream_oauth2.token_exchange",
+                &with(&[
+                    KeyValue::new("result", "failure"),
+                    KeyValue::new("error.type", "oauth2"),
+                ])
+            ),
+            1
+        );
+        assert_eq!(
+            METRICS.counter(
+                "mas.upstream_oauth2.token_exchange",
+                &with(&[KeyValue::new("result", "success")])
+            ),
+            1
+        );
+        assert_eq!(
+            METRICS.histogram_count("mas.upstream_oauth2.login.duration", &attributes),
+            1
+        );
+    }
+
+    /// Add a provider which doesn't need to be reachable, as the tests using
+    /// it never get to the token exchange
+    async fn add_unreachable_provider(state: &TestState, name: &str) -> UpstreamOAuthProvider {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    issuer: None,
+                    human_name: Some(name.to_owned()),
+                    brand_name: None,
+                    scope: Scope::from_iter([OPENID]),
+                    token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                    token_endpoint_signing_alg: None,
+                    id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                    client_id: "client".to_owned(),
+                    encrypted_client_secret: None,
+                    claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+                    authorization_endpoint_override: Some(
+                        "https://upstream.example.com/authorize".parse().unwrap(),
+                    ),
+                    token_endpoint_override: Some(
+                        "https://upstream.example.com/token".parse().unwrap(),
+                    ),
+                    userinfo_endpoint_override: None,
+                    fetch_userinfo: false,
+                    userinfo_signed_response_alg: None,
+                    jwks_uri_override: None,
+                    discovery_mode: UpstreamOAuthProviderDiscoveryMode::Disabled,
+                    pkce_mode: UpstreamOAuthProviderPkceMode::Disabled,
+                    response_mode: None,
+                    additional_authorization_parameters: Vec::new(),
+                    forward_login_hint: false,
+                    on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                    ui_order: 0,
+                    ui_hidden: false,
+                },
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        provider
+    }
+
+    /// Find the only authorization session started with the given provider
+    async fn single_session(
+        state: &TestState,
+        provider: &UpstreamOAuthProvider,
+    ) -> mas_data_model::UpstreamOAuthAuthorizationSession {
+        let mut repo = state.repository().await.unwrap();
+        let page = repo
+            .upstream_oauth_session()
+            .list(
+                mas_storage::upstream_oauth2::UpstreamOAuthSessionFilter::new()
+                    .for_provider(provider),
+                mas_storage::Pagination::first(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.edges.len(), 1);
+        page.edges.into_iter().next().unwrap().node
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_access_denied(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+        let provider = add_unreachable_provider(&state, "Cancel Ltd.").await;
+
+        // Start a login which is meant to continue an authorization grant
+        let grant_id = Ulid::from_datetime_with_source(state.clock.now().into(), &mut state.rng());
+        let upstream_state = authorize_with(
+            &state,
+            &cookies,
+            mas_router::UpstreamOAuth2Authorize::new(provider.id)
+                .and_then(mas_router::PostAuthAction::continue_grant(grant_id)),
+        )
+        .await;
+
+        // The user cancels the login on the provider
+        let callback_path = mas_router::UpstreamOAuth2Callback::new(provider.id).path();
+        let query = serde_urlencoded::to_string([
+            ("state", upstream_state.as_str()),
+            ("error", "access_denied"),
+            ("error_description", "The user cancelled the login"),
+        ])
+        .unwrap();
+        let request = Request::get(format!("{callback_path}?{query}")).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        // The page offers to retry, keeping what the user was doing
+        let body = response.body();
+        assert!(body.contains("data-error-code=\"access_denied\""));
+        assert!(body.contains("continue_authorization_grant"));
+        assert!(body.contains(&grant_id.to_string()));
+
+        // The session is marked as failed rather than left pending
+        let session = single_session(&state, &provider).await;
+        assert!(session.is_failed());
+        assert_eq!(session.error_code(), Some("access_denied"));
+
+        assert_eq!(
+            METRICS.counter(
+                "mas.upstream_oauth2.callback",
+                &[
+                    KeyValue::new("provider", provider.id.to_string()),
+                    KeyValue::new("provider.name", "Cancel Ltd."),
+                    KeyValue::new("result", "error"),
+                    KeyValue::new("error.code", "access_denied"),
+                ]
+            ),
+            1
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_server_error(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+        let provider = add_unreachable_provider(&state, "Broken Ltd.").await;
+
+        let upstream_state = authorize(&state, &cookies, provider.id).await;
+
+        let callback_path = mas_router::UpstreamOAuth2Callback::new(provider.id).path();
+        let query = serde_urlencoded::to_string([
+            ("state", upstream_state.as_str()),
+            ("error", "server_error"),
+            ("error_description", "The database is down"),
+        ])
+        .unwrap();
+        let request = Request::get(format!("{callback_path}?{query}")).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        // The generic failure page shows what the provider told us
+        let body = response.body();
+        assert!(body.contains("data-error-code=\"server_error\""));
+        assert!(body.contains("The database is down"));
+
+        let session = single_session(&state, &provider).await;
+        assert!(session.is_failed());
+        assert_eq!(session.error_code(), Some("server_error"));
+
+        // Replaying the callback doesn't fail the session a second time
+        let request = Request::get(format!("{callback_path}?{query}")).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+
+        assert_eq!(
+            METRICS.counter(
+                "mas.upstream_oauth2.callback",
+                &[
+                    KeyValue::new("provider", provider.id.to_string()),
+                    KeyValue::new("provider.name", "Broken Ltd."),
+                    KeyValue::new("result", "error"),
+                    KeyValue::new("error.code", "server_error"),
+                ]
+            ),
+            2
+        );
+    }
+}
@@ -4,14 +4,17 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::sync::{Arc, LazyLock};
+use std::{
+    net::IpAddr,
+    sync::{Arc, LazyLock},
+};
 
 use axum::{
-    Form,
+    Form, Json,
     extract::{Path, State},
     response::{Html, IntoResponse, Response},
 };
-use axum_extra::typed_header::TypedHeader;
+use axum_extra::{extract::Query, typed_header::TypedHeader};
 use hyper::StatusCode;
 use mas_axum_utils::{
     GenericError, SessionInfoExt,
@@ -20,17 +23,24 @@ use mas_axum_utils::{
     record_error,
 };
 use mas_data_model::{
+    AuditEventPayload,
     BoxClock,
     BoxRng,
+    Clock,
     //:tchap:
     TchapConfig,
+    TchapFeatures,
     //:tchap:end
+    UpstreamOAuthAuthorizationSession,
+    UpstreamOAuthProvider,
     UpstreamOAuthProviderOnConflict,
+    UpstreamOAuthProviderOnUnavailable,
+    User,
 };
 use mas_jose::jwt::Jwt;
 use mas_matrix::HomeserverConnection;
 use mas_policy::Policy;
-use mas_router::UrlBuilder;
+use mas_router::{PostAuthAction, UrlBuilder};
 use mas_storage::{
     BoxRepository, RepositoryAccess,
     queue::{ProvisionUserJob, QueueJobRepositoryExt as _},
@@ -38,11 +48,13 @@ use mas_storage::{
     user::{BrowserSessionRepository, UserEmailRepository, UserRepository},
 };
 use mas_templates::{
-    AccountInactiveContext, ErrorContext, FieldError, FormError, TemplateContext, Templates,
-    ToFormState, UpstreamExistingLinkContext, UpstreamRegister, UpstreamSuggestLink,
+    AccountInactiveContext, ErrorCode, ErrorContext, FieldError, FormError, FormState,
+    RecoveryUpstreamUnlinkedContext, TemplateContext, Templates, ToFormState,
+    UpstreamExistingLinkContext, UpstreamRegister, UpstreamSuggestLink,
 };
 use minijinja::Environment;
-use opentelemetry::{Key, KeyValue, metrics::Counter};
+use opentelemetry::metrics::Counter;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
 //:tchap:
 use tchap::{self, EmailAllowedResult};
@@ -51,12 +63,12 @@ use thiserror::Error;
 use ulid::Ulid;
 
 use super::{
-    UpstreamSessionsCookie,
-    template::{AttributeMappingContext, environment},
+    UpstreamSessionsCookie, provider_attributes, record_login_duration,
+    template::{AttributeMappingContext, add_tchap_filters, environment},
 };
 use crate::{
-    BoundActivityTracker, METER, PreferredLanguage, SiteConfig, impl_from_error_for_route,
-    views::shared::OptionalPostAuthAction,
+    BoundActivityTracker, METER, PreferredLanguage, SiteConfig, audit::schedule_audit_event,
+    impl_from_error_for_route, views::shared::OptionalPostAuthAction,
 };
 
 static LOGIN_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
@@ -73,12 +85,19 @@ static REGISTRATION_COUNTER: LazyLock<Counter<u64>> = LazyLock::new(|| {
         .with_unit("{registration}")
         .build()
 });
-const PROVIDER: Key = Key::from_static_str("provider");
 
 const DEFAULT_LOCALPART_TEMPLATE: &str = "{{ user.preferred_username }}";
 const DEFAULT_DISPLAYNAME_TEMPLATE: &str = "{{ user.name }}";
+const DEFAULT_ORGANIZATION_TEMPLATE: &str = "{{ user.organization }}";
+const DEFAULT_ADMIN_TEMPLATE: &str = "{{ user.can_request_admin }}";
 const DEFAULT_EMAIL_TEMPLATE: &str = "{{ user.email }}";
 
+/// How many usernames to suggest when the one picked isn't available
+const USERNAME_SUGGESTIONS: usize = 3;
+
+/// How many candidates to try when looking for available usernames
+const USERNAME_SUGGESTION_ATTEMPTS: usize = 20;
+
 #[derive(Debug, Error)]
 pub(crate) enum RouteError {
     /// Couldn't find the link specified in the URL
@@ -149,12 +168,20 @@ impl IntoResponse for RouteError {
                 | Self::HomeserverConnection(_)
         );
 
-        let status_code = match self {
-            Self::LinkNotFound => StatusCode::NOT_FOUND,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        let (status_code, error_code) = match self {
+            Self::LinkNotFound => (StatusCode::NOT_FOUND, ErrorCode::UpstreamLinkNotFound),
+            Self::SessionConsumed(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::UpstreamSessionConsumed,
+            ),
+            Self::MissingCookie => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::SessionRequired,
+            ),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError),
         };
 
-        let response = GenericError::new(status_code, self);
+        let response = GenericError::new(status_code, self).with_error_code(error_code);
         (sentry_event_id, response).into_response()
     }
 }
@@ -204,6 +231,299 @@ fn render_attribute_template(
     }
 }
 
+/// Render the organization of the user, if the provider is configured to
+/// import it.
+///
+/// The organization is not shown to the user, so unlike other attributes, the
+/// `suggest` action imports it on registration.
+fn render_organization(
+    environment: &Environment,
+    provider: &UpstreamOAuthProvider,
+    context: &minijinja::Value,
+) -> Result<Option<String>, RouteError> {
+    if provider.claims_imports.organization.ignore() {
+        return Ok(None);
+    }
+
+    let template = provider
+        .claims_imports
+        .organization
+        .template
+        .as_deref()
+        .unwrap_or(DEFAULT_ORGANIZATION_TEMPLATE);
+
+    render_attribute_template(
+        environment,
+        template,
+        context,
+        provider.claims_imports.organization.is_required(),
+    )
+}
+
+/// Build the context used to render the attribute templates from the claims
+/// of an upstream session
+fn mapping_context(
+    upstream_session: &UpstreamOAuthAuthorizationSession,
+) -> Result<minijinja::Value, RouteError> {
+    let id_token = upstream_session.id_token().map(Jwt::try_from).transpose()?;
+
+    let mut context = AttributeMappingContext::new();
+    if let Some(id_token) = id_token {
+        let (_, payload) = id_token.into_parts();
+        context = context.with_id_token_claims(payload);
+    }
+    if let Some(extra_callback_parameters) = upstream_session.extra_callback_parameters() {
+        context = context.with_extra_callback_parameters(extra_callback_parameters.clone());
+    }
+    if let Some(userinfo) = upstream_session.userinfo() {
+        context = context.with_userinfo_claims(userinfo.clone());
+    }
+
+    Ok(context.build())
+}
+
+/// Update the organization of an existing user when they log in again.
+///
+/// With the `suggest` action, the organization is only set if the user doesn't
+/// have one yet. With `force` and `require`, it is replaced by the one from the
+/// claims.
+async fn update_organization(
+    repo: &mut BoxRepository,
+    provider: &UpstreamOAuthProvider,
+    upstream_session: &UpstreamOAuthAuthorizationSession,
+    user: &User,
+) -> Result<(), RouteError> {
+    let preference = &provider.claims_imports.organization;
+    if preference.ignore() || (!preference.is_forced_or_required() && user.organization.is_some()) {
+        return Ok(());
+    }
+
+    let env = environment();
+    let context = mapping_context(upstream_session)?;
+
+    let Some(organization) = render_organization(&env, provider, &context)? else {
+        // Nothing in the claims, keep the organization the user already has
+        return Ok(());
+    };
+
+    if user.organization.as_deref() != Some(organization.as_str()) {
+        repo.user()
+            .set_organization(user.clone(), Some(organization))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Render whether the user can request admin access, if the provider is
+/// configured to import it.
+///
+/// Returns `None` if the claim is missing or doesn't render to a boolean, in
+/// which case the current value should be kept.
+fn render_can_request_admin(
+    environment: &Environment,
+    provider: &UpstreamOAuthProvider,
+    context: &minijinja::Value,
+) -> Result<Option<bool>, RouteError> {
+    if provider.claims_imports.admin.ignore() {
+        return Ok(None);
+    }
+
+    let template = provider
+        .claims_imports
+        .admin
+        .template
+        .as_deref()
+        .unwrap_or(DEFAULT_ADMIN_TEMPLATE);
+
+    let Some(value) = render_attribute_template(environment, template, context, false)? else {
+        return Ok(None);
+    };
+
+    match value.trim() {
+        "true" => Ok(Some(true)),
+        "false" => Ok(Some(false)),
+        value => {
+            tracing::warn!(%template, %value, "Admin claim template didn't render to a boolean");
+            Ok(None)
+        }
+    }
+}
+
+/// Update the right of an existing user to request admin access when they log
+/// in again, if the provider is configured to keep it in sync with the claims.
+///
+/// An audit event is scheduled when the right changes.
+#[expect(clippy::too_many_arguments, reason = "this is fine")]
+async fn update_can_request_admin(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    site_config: &SiteConfig,
+    ip_address: Option<IpAddr>,
+    provider: &UpstreamOAuthProvider,
+    upstream_session: &UpstreamOAuthAuthorizationSession,
+    user: &User,
+) -> Result<(), RouteError> {
+    if !provider.claims_imports.admin.update_on_login() {
+        return Ok(());
+    }
+
+    let env = environment();
+    let context = mapping_context(upstream_session)?;
+
+    let Some(can_request_admin) = render_can_request_admin(&env, provider, &context)? else {
+        // Nothing usable in the claims, keep the current value
+        return Ok(());
+    };
+
+    if user.can_request_admin == can_request_admin {
+        return Ok(());
+    }
+
+    let user = repo
+        .user()
+        .set_can_request_admin(user.clone(), can_request_admin)
+        .await?;
+
+    schedule_audit_event(
+        repo,
+        rng,
+        clock,
+        site_config,
+        ip_address,
+        AuditEventPayload::admin_permission_change(&user, provider.id),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// What the registration policy is told about the user, besides the username
+/// they register with
+struct RegistrationCheck<'a> {
+    email: Option<&'a str>,
+    organization: Option<&'a str>,
+    can_request_admin: Option<bool>,
+    ip_address: Option<IpAddr>,
+    user_agent: Option<String>,
+}
+
+impl RegistrationCheck<'_> {
+    /// Check whether a new account can be registered with this username,
+    /// returning the error to show on the username field if it can't.
+    ///
+    /// Policy violations take precedence over the homeserver denying the
+    /// username, as they are more helpful to the user.
+    async fn username_error(
+        &self,
+        repo: &mut BoxRepository,
+        homeserver: &dyn HomeserverConnection,
+        policy: &mut Policy,
+        username: &str,
+    ) -> Result<Option<FieldError>, RouteError> {
+        if username.is_empty() {
+            return Ok(Some(FieldError::Required));
+        }
+
+        if repo.user().exists(username).await? {
+            return Ok(Some(FieldError::Exists));
+        }
+
+        let res = policy
+            .evaluate_register(mas_policy::RegisterInput {
+                registration_method: mas_policy::RegistrationMethod::UpstreamOAuth2,
+                username,
+                email: self.email,
+                organization: self.organization,
+                can_request_admin: self.can_request_admin,
+                requester: mas_policy::Requester {
+                    ip_address: self.ip_address,
+                    user_agent: self.user_agent.clone(),
+                },
+            })
+            .await?;
+
+        if let Some(violation) = res
+            .violations
+            .into_iter()
+            .find(|violation| violation.field.as_deref() == Some("username"))
+        {
+            return Ok(Some(FieldError::Policy {
+                code: violation.code.map(|c| c.as_str()),
+                message: violation.msg,
+            }));
+        }
+
+        if !homeserver
+            .is_localpart_available(username)
+            .await
+            .map_err(RouteError::HomeserverConnection)?
+        {
+            return Ok(Some(FieldError::Exists));
+        }
+
+        Ok(None)
+    }
+
+    /// Find up to `limit` available usernames derived from `base`, by
+    /// appending a number to it.
+    ///
+    /// The suggestions are deterministic, so that they can be computed again
+    /// when the form is submitted instead of trusting the user input.
+    async fn suggest_usernames(
+        &self,
+        repo: &mut BoxRepository,
+        homeserver: &dyn HomeserverConnection,
+        policy: &mut Policy,
+        base: &str,
+        limit: usize,
+    ) -> Result<Vec<String>, RouteError> {
+        let base = sanitize_localpart(base);
+        let mut suggestions = Vec::with_capacity(limit);
+        if base.is_empty() {
+            return Ok(suggestions);
+        }
+
+        for n in 1..=USERNAME_SUGGESTION_ATTEMPTS {
+            if suggestions.len() >= limit {
+                break;
+            }
+
+            let candidate = format!("{base}{n}");
+            if self
+                .username_error(repo, homeserver, policy, &candidate)
+                .await?
+                .is_none()
+            {
+                suggestions.push(candidate);
+            }
+        }
+
+        Ok(suggestions)
+    }
+}
+
+/// Turn a value into something usable as a localpart, by lowercasing it and
+/// dropping the characters which aren't allowed in Matrix user IDs
+fn sanitize_localpart(value: &str) -> String {
+    value
+        .to_lowercase()
+        .chars()
+        .filter(|c| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '=' | '-' | '/')
+        })
+        .collect()
+}
+
+/// The value suggestions are derived from when no localpart was imported: the
+/// local part of the email address
+fn email_suggestion_base(email: Option<&str>) -> Option<&str> {
+    email
+        .and_then(|email| email.split_once('@'))
+        .map(|(local, _)| local)
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "lowercase", tag = "action")]
 pub(crate) enum FormData {
@@ -238,8 +558,10 @@ pub(crate) async fn get(
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
     State(homeserver): State<Arc<dyn HomeserverConnection>>,
+    State(site_config): State<SiteConfig>,
     //:tchap:
     State(tchap_config): State<TchapConfig>,
+    State(tchap_features): State<TchapFeatures>,
     //:tchap:end
     cookie_jar: CookieJar,
     activity_tracker: BoundActivityTracker,
@@ -286,6 +608,25 @@ pub(crate) async fn get(
         (Some(session), Some(user_id)) if session.user.id == user_id => {
             // Session already linked, and link matches the currently logged
             // user. Mark the session as consumed and renew the authentication.
+            let provider = repo
+                .upstream_oauth_provider()
+                .lookup(link.provider_id)
+                .await?
+                .ok_or(RouteError::ProviderNotFound(link.provider_id))?;
+
+            update_organization(&mut repo, &provider, &upstream_session, &session.user).await?;
+            update_can_request_admin(
+                &mut repo,
+                &mut rng,
+                &clock,
+                &site_config,
+                activity_tracker.ip(),
+                &provider,
+                &upstream_session,
+                &session.user,
+            )
+            .await?;
+
             let upstream_session = repo
                 .upstream_oauth_session()
                 .consume(&clock, upstream_session)
@@ -295,10 +636,12 @@ pub(crate) async fn get(
                 .authenticate_with_upstream(&mut rng, &clock, &session, &upstream_session)
                 .await?;
 
-            cookie_jar = cookie_jar.set_session(&session);
+            cookie_jar = cookie_jar.set_authenticated_session(&session, &clock, &mut rng);
 
             repo.save().await?;
 
+            record_login_duration(&clock, &provider, &upstream_session);
+
             post_auth_action.go_next(&url_builder).into_response()
         }
 
@@ -357,6 +700,25 @@ pub(crate) async fn get(
                 return Ok((cookie_jar, Html(fallback).into_response()));
             }
 
+            let provider = repo
+                .upstream_oauth_provider()
+                .lookup(link.provider_id)
+                .await?
+                .ok_or(RouteError::ProviderNotFound(link.provider_id))?;
+
+            update_organization(&mut repo, &provider, &upstream_session, &user).await?;
+            update_can_request_admin(
+                &mut repo,
+                &mut rng,
+                &clock,
+                &site_config,
+                activity_tracker.ip(),
+                &provider,
+                &upstream_session,
+                &user,
+            )
+            .await?;
+
             let session = repo
                 .browser_session()
                 .add(&mut rng, &clock, &user, user_agent)
@@ -374,21 +736,35 @@ pub(crate) async fn get(
             cookie_jar = sessions_cookie
                 .consume_link(link_id)?
                 .save(cookie_jar, &clock);
-            cookie_jar = cookie_jar.set_session(&session);
+            cookie_jar = cookie_jar.set_authenticated_session(&session, &clock, &mut rng);
 
             repo.save().await?;
 
-            LOGIN_COUNTER.add(
-                1,
-                &[KeyValue::new(
-                    PROVIDER,
-                    upstream_session.provider_id.to_string(),
-                )],
-            );
+            LOGIN_COUNTER.add(1, &provider_attributes(&provider));
+            record_login_duration(&clock, &provider, &upstream_session);
 
             post_auth_action.go_next(&url_builder).into_response()
         }
 
+        (None, None)
+            if matches!(
+                post_auth_action.post_auth_action,
+                Some(PostAuthAction::RecoverAccount)
+            ) =>
+        {
+            // Session not linked, but the user was trying to recover their
+            // account: there is no account to recover with this identity
+            let provider = repo
+                .upstream_oauth_provider()
+                .lookup(link.provider_id)
+                .await?
+                .ok_or(RouteError::ProviderNotFound(link.provider_id))?;
+
+            let ctx = RecoveryUpstreamUnlinkedContext::new(provider).with_language(locale);
+
+            Html(templates.render_recovery_upstream_unlinked(&ctx)?).into_response()
+        }
+
         (None, None) => {
             // Session not linked and used not logged in: suggest creating an
             // account or logging in an existing user
@@ -402,7 +778,10 @@ pub(crate) async fn get(
 
             let ctx = UpstreamRegister::new(link.clone(), provider.clone());
 
-            let env = environment();
+            let mut env = environment();
+            //:tchap:
+            add_tchap_filters(&mut env, tchap_features);
+            //:tchap:end
 
             let mut context = AttributeMappingContext::new();
             if let Some(id_token) = id_token {
@@ -417,6 +796,11 @@ pub(crate) async fn get(
             }
             let context = context.build();
 
+            // The organization and the admin right aren't shown in the form, but we need
+            // them to check the policy
+            let organization = render_organization(&env, &provider, &context)?;
+            let can_request_admin = render_can_request_admin(&env, &provider, &context)?;
+
             let ctx = if provider.claims_imports.displayname.ignore() {
                 ctx
             } else {
@@ -503,16 +887,22 @@ pub(crate) async fn get(
                         let mut maybe_existing_user = None;
 
                         if let Ok(Some(email)) = maybe_email {
-                            let maybe_user_tchap =
+                            let maybe_user_tchap = if tchap_features.upstream_email_matching {
                                 tchap::search_user_by_email(&mut repo, &email, &tchap_config)
-                                    .await?;
+                                    .await?
+                            } else {
+                                None
+                            };
                             if maybe_user_tchap.is_none() {
                                 //:tchap:
                                 //when user is not found, check if acccount creation is allowed for
                                 // this user on this server
                                 let server_name = homeserver.homeserver();
-                                let email_result =
-                                    check_email_allowed(&email, server_name, &tchap_config).await;
+                                let email_result = if tchap_features.checks_upstream_email() {
+                                    check_email_allowed(&email, server_name, &tchap_config).await
+                                } else {
+                                    EmailAllowedResult::Allowed
+                                };
 
                                 match email_result {
                                     EmailAllowedResult::Allowed => {
@@ -521,7 +911,7 @@ pub(crate) async fn get(
                                     EmailAllowedResult::WrongServer => {
                                         // Email is mapped to a different server
                                         let ctx = ErrorContext::new()
-                                            .with_code("wrong_server")
+                                            .with_code(ErrorCode::WrongServer)
                                             .with_description(format!("Votre adresse mail {email} est associée à un autre serveur."))
                                             .with_details("Veuillez-vous contacter le support de Tchap support@tchap.beta.gouv.fr".to_owned())
                                             .with_language(&locale);
@@ -535,7 +925,7 @@ pub(crate) async fn get(
                                     EmailAllowedResult::InvitationMissing => {
                                         // Server requires an invitation that is not present
                                         let ctx = ErrorContext::new()
-                                            .with_code("invitation_missing")
+                                            .with_code(ErrorCode::InvitationMissing)
                                             .with_description("Vous avez besoin d'une invitation pour accéder à Tchap.".to_owned())
                                             .with_details("Les partenaires externes peuvent accéder à Tchap uniquement avec une invitation d'un agent public.".to_owned())
                                             .with_language(&locale);
@@ -571,10 +961,14 @@ pub(crate) async fn get(
                             //:tchap:
                             //if username matches whereas email has not
                             //throw a invalid data error to solve the situtation manually
-                            let maybe_existing_user =
+                            let maybe_user_by_localpart =
                                 repo.user().find_by_username(&localpart).await?;
 
-                            if let Some(existing_user) = maybe_existing_user {
+                            if !tchap_features.upstream_email_matching {
+                                // Without email matching, the upstream account
+                                // matches the user with the same username
+                                maybe_existing_user = maybe_user_by_localpart;
+                            } else if let Some(existing_user) = maybe_user_by_localpart {
                                 let email = &repo
                                     .user_email()
                                     .all(&existing_user)
@@ -583,7 +977,7 @@ pub(crate) async fn get(
                                     .map(|user_email| user_email.email.clone());
 
                                 let ctx = ErrorContext::new()
-                                    .with_code("Invalid Data")
+                                    .with_code(ErrorCode::InvalidUpstreamData)
                                     .with_description(format!(
                                         r"Un compte Tchap existe mais l'email associé diffère de votre email Proconnect. 
                                         Veuillez contacter le support Tchap: support@tchap.beta.gouv.fr. 
@@ -612,7 +1006,7 @@ pub(crate) async fn get(
                                 UpstreamOAuthProviderOnConflict::Fail => {
                                     // TODO: translate
                                     let ctx = ErrorContext::new()
-                                        .with_code("User exists")
+                                        .with_code(ErrorCode::UpstreamUserExists)
                                         .with_description(format!(
                                             r"Upstream account provider returned {localpart:?} as username,
                                             which is not linked to that upstream account. Your homeserver does not allow
@@ -641,63 +1035,166 @@ pub(crate) async fn get(
                         }
 
                         if !is_available {
-                            // TODO: translate
-                            let ctx = ErrorContext::new()
-                                .with_code("Localpart not available")
-                                .with_description(format!(
-                                    r"Localpart {localpart:?} is not available on this homeserver"
-                                ))
-                                .with_language(&locale);
-
-                            return Ok((
-                                cookie_jar,
-                                Html(templates.render_error(&ctx)?).into_response(),
-                            ));
-                        }
+                            let on_unavailable = provider.claims_imports.localpart.on_unavailable;
+                            let limit = match on_unavailable {
+                                UpstreamOAuthProviderOnUnavailable::Fail => 0,
+                                UpstreamOAuthProviderOnUnavailable::Suggest => USERNAME_SUGGESTIONS,
+                                UpstreamOAuthProviderOnUnavailable::AutoAccept => 1,
+                            };
 
-                        let res = policy
-                            .evaluate_register(mas_policy::RegisterInput {
-                                registration_method: mas_policy::RegistrationMethod::UpstreamOAuth2,
-                                username: &localpart,
+                            let check = RegistrationCheck {
                                 email: None,
-                                requester: mas_policy::Requester {
-                                    ip_address: activity_tracker.ip(),
-                                    user_agent: user_agent.clone(),
-                                },
-                            })
-                            .await?;
-
-                        if res.valid() {
-                            // The username passes the policy check, add it to the context
-                            ctx.with_localpart(
-                                localpart,
-                                provider.claims_imports.localpart.is_forced_or_required(),
-                            )
-                        } else if provider.claims_imports.localpart.is_forced_or_required() {
-                            // If the username claim is 'forced' but doesn't pass the policy check,
-                            // we display an error message.
-                            // TODO: translate
-                            let ctx = ErrorContext::new()
-                                .with_code("Policy error")
-                                .with_description(format!(
-                                    r"Upstream account provider returned {localpart:?} as username,
-                                    which does not pass the policy check: {res}"
-                                ))
-                                .with_language(&locale);
+                                organization: organization.as_deref(),
+                                can_request_admin,
+                                ip_address: activity_tracker.ip(),
+                                user_agent: user_agent.clone(),
+                            };
+                            let suggestions = check
+                                .suggest_usernames(
+                                    &mut repo,
+                                    homeserver.as_ref(),
+                                    &mut policy,
+                                    &localpart,
+                                    limit,
+                                )
+                                .await?;
+
+                            match (on_unavailable, suggestions.first().cloned()) {
+                                (UpstreamOAuthProviderOnUnavailable::Suggest, _) => {
+                                    // Let the user pick another username, even if the claim is
+                                    // forced, starting from the one we mapped
+                                    ctx.with_localpart(localpart, false)
+                                        .with_username_suggestions(suggestions)
+                                        .with_form_state(FormState::default().with_error_on_field(
+                                            mas_templates::UpstreamRegisterFormField::Username,
+                                            FieldError::Exists,
+                                        ))
+                                }
+
+                                (
+                                    UpstreamOAuthProviderOnUnavailable::AutoAccept,
+                                    Some(username),
+                                ) => {
+                                    // The suggestion already passed the policy check
+                                    ctx.with_localpart(
+                                        username,
+                                        provider.claims_imports.localpart.is_forced_or_required(),
+                                    )
+                                }
+
+                                _ => {
+                                    // TODO: translate
+                                    let ctx = ErrorContext::new()
+                                        .with_code(ErrorCode::LocalpartNotAvailable)
+                                        .with_description(format!(
+                                            r"Localpart {localpart:?} is not available on this homeserver"
+                                        ))
+                                        .with_language(&locale);
 
-                            return Ok((
-                                cookie_jar,
-                                Html(templates.render_error(&ctx)?).into_response(),
-                            ));
+                                    return Ok((
+                                        cookie_jar,
+                                        Html(templates.render_error(&ctx)?).into_response(),
+                                    ));
+                                }
+                            }
                         } else {
-                            // Else, we just ignore it when it doesn't pass the policy check.
-                            ctx
+                            let res = policy
+                                .evaluate_register(mas_policy::RegisterInput {
+                                    registration_method:
+                                        mas_policy::RegistrationMethod::UpstreamOAuth2,
+                                    username: &localpart,
+                                    email: None,
+                                    organization: organization.as_deref(),
+                                    can_request_admin,
+                                    requester: mas_policy::Requester {
+                                        ip_address: activity_tracker.ip(),
+                                        user_agent: user_agent.clone(),
+                                    },
+                                })
+                                .await?;
+
+                            if res.valid() {
+                                // The username passes the policy check, add it to the context
+                                ctx.with_localpart(
+                                    localpart,
+                                    provider.claims_imports.localpart.is_forced_or_required(),
+                                )
+                            } else if provider.claims_imports.localpart.is_forced_or_required() {
+                                // If the username claim is 'forced' but doesn't pass the policy check,
+                                // we display an error message.
+                                // TODO: translate
+                                let ctx = ErrorContext::new()
+                                    .with_code(ErrorCode::PolicyViolation(
+                                        res.code().map(|code| code.as_str()),
+                                    ))
+                                    .with_description(format!(
+                                        r"Upstream account provider returned {localpart:?} as username,
+                                    which does not pass the policy check: {res}"
+                                    ))
+                                    .with_language(&locale);
+
+                                return Ok((
+                                    cookie_jar,
+                                    Html(templates.render_error(&ctx)?).into_response(),
+                                ));
+                            } else {
+                                // Else, we just ignore it when it doesn't pass the policy check.
+                                ctx
+                            }
                         }
                     }
                     None => ctx,
                 }
             };
 
+            // Without a localpart to import, users would have to invent a username:
+            // suggest some derived from their email address instead
+            let on_unavailable = provider.claims_imports.localpart.on_unavailable;
+            let ctx = if provider.claims_imports.localpart.ignore()
+                && !provider.claims_imports.email.ignore()
+                && on_unavailable != UpstreamOAuthProviderOnUnavailable::Fail
+            {
+                let template = provider
+                    .claims_imports
+                    .email
+                    .template
+                    .as_deref()
+                    .unwrap_or(DEFAULT_EMAIL_TEMPLATE);
+                let email = render_attribute_template(&env, template, &context, false)?;
+
+                let check = RegistrationCheck {
+                    email: email.as_deref(),
+                    organization: organization.as_deref(),
+                    can_request_admin,
+                    ip_address: activity_tracker.ip(),
+                    user_agent: user_agent.clone(),
+                };
+                let mut suggestions = if let Some(base) = email_suggestion_base(email.as_deref()) {
+                    check
+                        .suggest_usernames(
+                            &mut repo,
+                            homeserver.as_ref(),
+                            &mut policy,
+                            base,
+                            USERNAME_SUGGESTIONS,
+                        )
+                        .await?
+                } else {
+                    Vec::new()
+                };
+
+                if on_unavailable == UpstreamOAuthProviderOnUnavailable::AutoAccept
+                    && !suggestions.is_empty()
+                {
+                    // Prefill the form with the first suggestion, the user can still change it
+                    ctx.with_localpart(suggestions.remove(0), false)
+                } else {
+                    ctx.with_username_suggestions(suggestions)
+                }
+            } else {
+                ctx
+            };
+
             let ctx = ctx.with_csrf(csrf_token.form_value()).with_language(locale);
 
             Html(templates.render_upstream_oauth2_do_register(&ctx)?).into_response()
@@ -727,6 +1224,7 @@ pub(crate) async fn post(
     State(site_config): State<SiteConfig>,
     //:tchap:
     State(tchap_config): State<TchapConfig>,
+    State(tchap_features): State<TchapFeatures>,
     //:tchap:end
     Path(link_id): Path<Ulid>,
     Form(form): Form<ProtectedForm<FormData>>,
@@ -816,55 +1314,63 @@ pub(crate) async fn post(
                 return Err(RouteError::InvalidFormAction);
             }
 
-            //:tchap:
             let template = provider
                 .claims_imports
-                .email
+                .localpart
                 .template
                 .as_deref()
-                .unwrap_or(DEFAULT_EMAIL_TEMPLATE);
+                .unwrap_or(DEFAULT_LOCALPART_TEMPLATE);
 
-            let maybe_email = render_attribute_template(
-                &env,
-                template,
-                &context,
-                provider.claims_imports.email.is_required(),
-            );
-
-            let maybe_user = if let Ok(Some(email)) = maybe_email {
-                tchap::search_user_by_email(&mut repo, &email, &tchap_config).await?
-            } else {
-                None
+            let Some(localpart) = render_attribute_template(&env, template, &context, true)? else {
+                // This should never be the case at this point
+                return Err(RouteError::InvalidFormAction);
             };
-            if maybe_user.is_none() {
+
+            //:tchap:
+            let maybe_user = if tchap_features.upstream_email_matching {
                 let template = provider
                     .claims_imports
-                    .localpart
+                    .email
                     .template
                     .as_deref()
-                    .unwrap_or(DEFAULT_LOCALPART_TEMPLATE);
+                    .unwrap_or(DEFAULT_EMAIL_TEMPLATE);
 
-                let Some(localpart) = render_attribute_template(&env, template, &context, true)?
-                else {
-                    // This should never be the case at this point
-                    return Err(RouteError::InvalidFormAction);
+                let maybe_email = render_attribute_template(
+                    &env,
+                    template,
+                    &context,
+                    provider.claims_imports.email.is_required(),
+                );
+
+                let maybe_user = if let Ok(Some(email)) = maybe_email {
+                    tchap::search_user_by_email(&mut repo, &email, &tchap_config).await?
+                } else {
+                    None
                 };
-                //:tchap:
-                if let Some(_existing_user) = repo.user().find_by_username(&localpart).await? {
+
+                if maybe_user.is_none() && repo.user().find_by_username(&localpart).await?.is_some()
+                {
                     //this should never be the case at this point
                     //if we didnt find user by email we should not find it by localpart (derived
                     // from email) if we do, there is a problem of email
                     // binding, raise an error
                     return Err(RouteError::InvalidFormAction);
                 }
-            }
+
+                maybe_user
+            } else {
+                // Without email matching, the upstream account is linked to
+                // the user with the same username
+                repo.user().find_by_username(&localpart).await?
+            };
+            //:tchap:end
 
             let Some(user) = maybe_user else {
                 // user cannot be None at this stage
                 return Err(RouteError::InvalidFormAction);
             };
 
-            let on_conflict = provider.claims_imports.localpart.on_conflict;
+            let on_conflict = &provider.claims_imports.localpart.on_conflict;
 
             match on_conflict {
                 UpstreamOAuthProviderOnConflict::Fail => {
@@ -877,6 +1383,19 @@ pub(crate) async fn post(
                         .associate_to_user(&link, &user)
                         .await?;
 
+                    update_organization(&mut repo, &provider, &upstream_session, &user).await?;
+                    update_can_request_admin(
+                        &mut repo,
+                        &mut rng,
+                        &clock,
+                        &site_config,
+                        activity_tracker.ip(),
+                        &provider,
+                        &upstream_session,
+                        &user,
+                    )
+                    .await?;
+
                     repo.browser_session()
                         .add(&mut rng, &clock, &user, user_agent)
                         .await?
@@ -913,7 +1432,10 @@ pub(crate) async fn post(
                 .ok_or(RouteError::ProviderNotFound(link.provider_id))?;
 
             // Let's try to import the claims from the ID token
-            let env = environment();
+            let mut env = environment();
+            //:tchap:
+            add_tchap_filters(&mut env, tchap_features);
+            //:tchap:end
 
             let mut context = AttributeMappingContext::new();
             if let Some(id_token) = id_token {
@@ -989,7 +1511,23 @@ pub(crate) async fn post(
                 ctx
             };
 
-            let username = if provider.claims_imports.localpart.is_forced_or_required() {
+            let organization = render_organization(&env, &provider, &context)?;
+            let can_request_admin = render_can_request_admin(&env, &provider, &context)?;
+
+            let on_unavailable = provider.claims_imports.localpart.on_unavailable;
+            let check = RegistrationCheck {
+                email: email.as_deref(),
+                organization: organization.as_deref(),
+                can_request_admin,
+                ip_address: activity_tracker.ip(),
+                user_agent: user_agent.clone(),
+            };
+
+            let (username, force_localpart) = if provider
+                .claims_imports
+                .localpart
+                .is_forced_or_required()
+            {
                 let template = provider
                     .claims_imports
                     .localpart
@@ -997,19 +1535,42 @@ pub(crate) async fn post(
                     .as_deref()
                     .unwrap_or(DEFAULT_LOCALPART_TEMPLATE);
 
-                render_attribute_template(&env, template, &context, true)?
+                let mapped =
+                    render_attribute_template(&env, template, &context, true)?.unwrap_or_default();
+
+                // If the forced username is taken, it may have been replaced, which we
+                // have to figure out again instead of trusting the form data
+                let mapped_unavailable = on_unavailable != UpstreamOAuthProviderOnUnavailable::Fail
+                    && matches!(
+                        check
+                            .username_error(&mut repo, homeserver.as_ref(), &mut policy, &mapped)
+                            .await?,
+                        Some(FieldError::Exists)
+                    );
+
+                if !mapped_unavailable {
+                    (mapped, true)
+                } else if on_unavailable == UpstreamOAuthProviderOnUnavailable::AutoAccept {
+                    let suggestion = check
+                        .suggest_usernames(&mut repo, homeserver.as_ref(), &mut policy, &mapped, 1)
+                        .await?
+                        .into_iter()
+                        .next();
+
+                    (suggestion.unwrap_or(mapped), true)
+                } else {
+                    // The user was allowed to pick another username
+                    (username.unwrap_or_default(), false)
+                }
             } else {
                 // If there is no forced username, we can use the one the user entered
-                username
-            }
-            .unwrap_or_default();
+                (username.unwrap_or_default(), false)
+            };
 
-            let ctx = ctx.with_localpart(
-                username.clone(),
-                provider.claims_imports.localpart.is_forced_or_required(),
-            );
+            let ctx = ctx.with_localpart(username.clone(), force_localpart);
 
             // Validate the form
+            let mut username_unavailable = false;
             let form_state = {
                 let mut form_state = form_state;
                 let mut homeserver_denied_username = false;
@@ -1019,6 +1580,7 @@ pub(crate) async fn post(
                         FieldError::Required,
                     );
                 } else if repo.user().exists(&username).await? {
+                    username_unavailable = true;
                     form_state.add_error_on_field(
                         mas_templates::UpstreamRegisterFormField::Username,
                         FieldError::Exists,
@@ -1053,6 +1615,8 @@ pub(crate) async fn post(
                         registration_method: mas_policy::RegistrationMethod::UpstreamOAuth2,
                         username: &username,
                         email: email.as_deref(),
+                        organization: organization.as_deref(),
+                        can_request_admin,
                         requester: mas_policy::Requester {
                             ip_address: activity_tracker.ip(),
                             user_agent: user_agent.clone(),
@@ -1083,6 +1647,7 @@ pub(crate) async fn post(
                 }
 
                 if homeserver_denied_username {
+                    username_unavailable = true;
                     // XXX: we may want to return different errors like "this username is reserved"
                     form_state.add_error_on_field(
                         mas_templates::UpstreamRegisterFormField::Username,
@@ -1094,7 +1659,25 @@ pub(crate) async fn post(
             };
 
             if !form_state.is_valid() {
+                let suggestions = if username_unavailable
+                    && !force_localpart
+                    && on_unavailable != UpstreamOAuthProviderOnUnavailable::Fail
+                {
+                    check
+                        .suggest_usernames(
+                            &mut repo,
+                            homeserver.as_ref(),
+                            &mut policy,
+                            &username,
+                            USERNAME_SUGGESTIONS,
+                        )
+                        .await?
+                } else {
+                    Vec::new()
+                };
+
                 let ctx = ctx
+                    .with_username_suggestions(suggestions)
                     .with_form_state(form_state)
                     .with_csrf(csrf_token.form_value())
                     .with_language(locale);
@@ -1106,11 +1689,37 @@ pub(crate) async fn post(
                     .into_response());
             }
 
-            REGISTRATION_COUNTER.add(1, &[KeyValue::new(PROVIDER, provider.id.to_string())]);
+            REGISTRATION_COUNTER.add(1, &provider_attributes(&provider));
 
             // Now we can create the user
             let user = repo.user().add(&mut rng, &clock, username).await?;
 
+            let user = if let Some(organization) = organization {
+                repo.user()
+                    .set_organization(user, Some(organization))
+                    .await?
+            } else {
+                user
+            };
+
+            let user = if can_request_admin == Some(true) {
+                let user = repo.user().set_can_request_admin(user, true).await?;
+
+                schedule_audit_event(
+                    &mut repo,
+                    &mut rng,
+                    &clock,
+                    &site_config,
+                    activity_tracker.ip(),
+                    AuditEventPayload::admin_permission_change(&user, provider.id),
+                )
+                .await?;
+
+                user
+            } else {
+                user
+            };
+
             if let Some(terms_url) = &site_config.tos_uri {
                 repo.user_terms()
                     .accept_terms(&mut rng, &clock, &user, terms_url.clone())
@@ -1146,6 +1755,12 @@ pub(crate) async fn post(
         _ => return Err(RouteError::InvalidFormAction),
     };
 
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(link.provider_id)
+        .await?
+        .ok_or(RouteError::ProviderNotFound(link.provider_id))?;
+
     let upstream_session = repo
         .upstream_oauth_session()
         .consume(&clock, upstream_session)
@@ -1158,50 +1773,169 @@ pub(crate) async fn post(
     let cookie_jar = sessions_cookie
         .consume_link(link_id)?
         .save(cookie_jar, &clock);
-    let cookie_jar = cookie_jar.set_session(&session);
+    let cookie_jar = cookie_jar.set_authenticated_session(&session, &clock, &mut rng);
 
     repo.save().await?;
 
+    record_login_duration(&clock, &provider, &upstream_session);
+
     Ok((cookie_jar, post_auth_action.go_next(&url_builder)).into_response())
 }
 
-//:tchap:
-///real function used when not testing
-#[cfg(not(test))]
-async fn check_email_allowed(
-    email: &str,
-    server_name: &str,
-    tchap_config: &TchapConfig,
-) -> EmailAllowedResult {
-    tchap::is_email_allowed(email, server_name, tchap_config).await
+#[derive(Deserialize)]
+pub(crate) struct UsernameQuery {
+    username: String,
 }
-///mock function used when testing
-#[cfg(test)]
-async fn check_email_allowed(
-    _email: &str,
-    _server_name: &str,
-    _tchap_config: &TchapConfig,
-) -> EmailAllowedResult {
-    EmailAllowedResult::Allowed
+
+/// Whether a username can be used to register through an upstream link
+#[derive(Serialize)]
+pub(crate) struct UsernameAvailability {
+    username: String,
+    available: bool,
+    error: Option<FieldError>,
+    suggestions: Vec<String>,
 }
-//:tchap:end
 
-#[cfg(test)]
-mod tests {
-    use hyper::{Request, StatusCode, header::CONTENT_TYPE};
-    use mas_data_model::{
-        UpstreamOAuthAuthorizationSession, UpstreamOAuthLink, UpstreamOAuthProviderClaimsImports,
-        UpstreamOAuthProviderImportPreference, UpstreamOAuthProviderLocalpartPreference,
-        UpstreamOAuthProviderTokenAuthMethod,
+/// Check whether a username is available while the user fills the
+/// registration form, and suggest other ones if it isn't
+#[tracing::instrument(
+    name = "handlers.upstream_oauth2.link.check_username",
+    fields(upstream_oauth_link.id = %link_id),
+    skip_all,
+)]
+pub(crate) async fn check_username(
+    mut repo: BoxRepository,
+    mut policy: Policy,
+    cookie_jar: CookieJar,
+    activity_tracker: BoundActivityTracker,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    State(homeserver): State<Arc<dyn HomeserverConnection>>,
+    Path(link_id): Path<Ulid>,
+    Query(query): Query<UsernameQuery>,
+) -> Result<Json<UsernameAvailability>, RouteError> {
+    let user_agent = user_agent.map(|ua| ua.as_str().to_owned());
+    let sessions_cookie = UpstreamSessionsCookie::load(&cookie_jar);
+    let (session_id, _post_auth_action) = sessions_cookie
+        .lookup_link(link_id)
+        .map_err(|_| RouteError::MissingCookie)?;
+
+    let link = repo
+        .upstream_oauth_link()
+        .lookup(link_id)
+        .await?
+        .ok_or(RouteError::LinkNotFound)?;
+
+    let upstream_session = repo
+        .upstream_oauth_session()
+        .lookup(session_id)
+        .await?
+        .ok_or(RouteError::SessionNotFound(session_id))?;
+
+    // Same as when registering, this must be the browser which started the
+    // upstream authorization
+    if upstream_session.link_id() != Some(link.id) {
+        return Err(RouteError::SessionNotFound(session_id));
+    }
+
+    if upstream_session.is_consumed() {
+        return Err(RouteError::SessionConsumed(session_id));
+    }
+
+    // There is nothing to register if the link already has a user
+    if link.user_id.is_some() {
+        return Err(RouteError::InvalidFormAction);
+    }
+
+    let provider = repo
+        .upstream_oauth_provider()
+        .lookup(link.provider_id)
+        .await?
+        .ok_or(RouteError::ProviderNotFound(link.provider_id))?;
+
+    let env = environment();
+    let context = mapping_context(&upstream_session)?;
+    let organization = render_organization(&env, &provider, &context)?;
+    let can_request_admin = render_can_request_admin(&env, &provider, &context)?;
+
+    let check = RegistrationCheck {
+        email: None,
+        organization: organization.as_deref(),
+        can_request_admin,
+        ip_address: activity_tracker.ip(),
+        user_agent,
+    };
+
+    let error = check
+        .username_error(&mut repo, homeserver.as_ref(), &mut policy, &query.username)
+        .await?;
+
+    let suggestions = if matches!(error, Some(FieldError::Exists))
+        && provider.claims_imports.localpart.on_unavailable
+            != UpstreamOAuthProviderOnUnavailable::Fail
+    {
+        check
+            .suggest_usernames(
+                &mut repo,
+                homeserver.as_ref(),
+                &mut policy,
+                &query.username,
+                USERNAME_SUGGESTIONS,
+            )
+            .await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(Json(UsernameAvailability {
+        username: query.username,
+        available: error.is_none(),
+        error,
+        suggestions,
+    }))
+}
+
+//:tchap:
+///real function used when not testing
+#[cfg(not(test))]
+async fn check_email_allowed(
+    email: &str,
+    server_name: &str,
+    tchap_config: &TchapConfig,
+) -> EmailAllowedResult {
+    tchap::is_email_allowed(email, server_name, tchap_config).await
+}
+///mock function used when testing
+#[cfg(test)]
+async fn check_email_allowed(
+    _email: &str,
+    _server_name: &str,
+    _tchap_config: &TchapConfig,
+) -> EmailAllowedResult {
+    EmailAllowedResult::Allowed
+}
+//:tchap:end
+
+#[cfg(test)]
+mod tests {
+    use hyper::{
+        Request, StatusCode,
+        header::{CONTENT_TYPE, LOCATION},
+    };
+    use mas_data_model::{
+        AuditEventKind, AuditWebhookConfig, SiteConfig, UpstreamOAuthAuthorizationSession,
+        UpstreamOAuthLink, UpstreamOAuthProviderAdminImportAction,
+        UpstreamOAuthProviderAdminPreference, UpstreamOAuthProviderClaimsImports,
+        UpstreamOAuthProviderImportPreference, UpstreamOAuthProviderLocalpartPreference,
+        UpstreamOAuthProviderOnUnavailable, UpstreamOAuthProviderTokenAuthMethod,
     };
     use mas_iana::jose::JsonWebSignatureAlg;
     use mas_jose::jwt::{JsonWebSignatureHeader, Jwt};
     use mas_keystore::Keystore;
-    use mas_router::Route;
+    use mas_router::{PostAuthAction, Route, SimpleRoute};
     use mas_storage::{
         Pagination, Repository, RepositoryError,
         upstream_oauth2::{UpstreamOAuthLinkFilter, UpstreamOAuthProviderParams},
-        user::UserEmailFilter,
+        user::{BrowserSessionFilter, UserEmailFilter},
     };
     use oauth2_types::scope::{OPENID, Scope};
     use rand_chacha::ChaChaRng;
@@ -1209,7 +1943,9 @@ mod tests {
     use sqlx::PgPool;
 
     use super::UpstreamSessionsCookie;
-    use crate::test_utils::{CookieHelper, RequestBuilderExt, ResponseExt, TestState, setup};
+    use crate::test_utils::{
+        CookieHelper, RequestBuilderExt, ResponseExt, TestState, setup, test_site_config,
+    };
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_register(pool: PgPool) {
@@ -1223,6 +1959,7 @@ mod tests {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
                 template: None,
                 on_conflict: mas_data_model::UpstreamOAuthProviderOnConflict::default(),
+                on_unavailable: mas_data_model::UpstreamOAuthProviderOnUnavailable::default(),
             },
             email: UpstreamOAuthProviderImportPreference {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
@@ -1283,6 +2020,183 @@ mod tests {
                     additional_authorization_parameters: Vec::new(),
                     forward_login_hint: false,
                     ui_order: 0,
+                    ui_hidden: false,
+                    on_backchannel_logout:
+                        mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                },
+            )
+            .await
+            .unwrap();
+
+        let session = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &provider,
+                "state".to_owned(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let link = repo
+            .upstream_oauth_link()
+            .add(
+                &mut rng,
+                &state.clock,
+                &provider,
+                "subject".to_owned(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let session = repo
+            .upstream_oauth_session()
+            .complete_with_link(
+                &state.clock,
+                session,
+                &link,
+                Some(id_token.into_string()),
+                Some(id_token_claims),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        let cookie_jar = state.cookie_jar();
+        let upstream_sessions = UpstreamSessionsCookie::default()
+            .add(session.id, provider.id, "state".to_owned(), None)
+            .add_link_to_session(session.id, link.id)
+            .unwrap();
+        let cookie_jar = upstream_sessions.save(cookie_jar, &state.clock);
+        cookies.import(cookie_jar);
+
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
+
+        // Extract the CSRF token from the response body
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap();
+
+        let request = Request::post(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).form(
+            serde_json::json!({
+                "csrf": csrf_token,
+                "action": "register",
+                "import_email": "on",
+                "accept_terms": "on",
+            }),
+        );
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // Check that we have a registered user, with the email imported
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .find_by_username("john")
+            .await
+            .unwrap()
+            .expect("user exists");
+
+        let link = repo
+            .upstream_oauth_link()
+            .find_by_subject(&provider, "subject")
+            .await
+            .unwrap()
+            .expect("link exists");
+
+        assert_eq!(link.user_id, Some(user.id));
+
+        let page = repo
+            .user_email()
+            .list(UserEmailFilter::new().for_user(&user), Pagination::first(1))
+            .await
+            .unwrap();
+        let edge = page.edges.first().expect("email exists");
+
+        assert_eq!(edge.node.email, "john@example.com");
+    }
+
+    /// Start registering `john` through an upstream provider, while the
+    /// `john` username is reserved on the homeserver.
+    ///
+    /// Returns the link, the cookies of the browser and the registration page
+    async fn start_registration_with_unavailable_username(
+        state: &TestState,
+        on_unavailable: UpstreamOAuthProviderOnUnavailable,
+    ) -> (UpstreamOAuthLink, CookieHelper, String) {
+        let mut rng = state.rng();
+        state.homeserver_connection.reserve_localpart("john").await;
+
+        let claims_imports = UpstreamOAuthProviderClaimsImports {
+            localpart: UpstreamOAuthProviderLocalpartPreference {
+                action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
+                template: None,
+                on_conflict: mas_data_model::UpstreamOAuthProviderOnConflict::default(),
+                on_unavailable,
+            },
+            email: UpstreamOAuthProviderImportPreference {
+                action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
+                template: None,
+            },
+            ..UpstreamOAuthProviderClaimsImports::default()
+        };
+
+        let id_token_claims = serde_json::json!({
+            "preferred_username": "john",
+            "email": "john@example.com",
+            "email_verified": true,
+        });
+        let id_token = sign_token(&mut rng, &state.key_store, id_token_claims.clone()).unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    issuer: Some("https://example.com/".to_owned()),
+                    human_name: Some("Example Ltd.".to_owned()),
+                    brand_name: None,
+                    scope: Scope::from_iter([OPENID]),
+                    token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                    token_endpoint_signing_alg: None,
+                    id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                    client_id: "client".to_owned(),
+                    encrypted_client_secret: None,
+                    claims_imports,
+                    authorization_endpoint_override: None,
+                    token_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    fetch_userinfo: false,
+                    userinfo_signed_response_alg: None,
+                    jwks_uri_override: None,
+                    discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
+                    pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
+                    response_mode: None,
+                    additional_authorization_parameters: Vec::new(),
+                    forward_login_hint: false,
+                    ui_order: 0,
+                    ui_hidden: false,
                     on_backchannel_logout:
                         mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                 },
@@ -1331,6 +2245,593 @@ mod tests {
 
         repo.save().await.unwrap();
 
+        let cookies = CookieHelper::new();
+        let cookie_jar = state.cookie_jar();
+        let upstream_sessions = UpstreamSessionsCookie::default()
+            .add(session.id, provider.id, "state".to_owned(), None)
+            .add_link_to_session(session.id, link.id)
+            .unwrap();
+        let cookie_jar = upstream_sessions.save(cookie_jar, &state.clock);
+        cookies.import(cookie_jar);
+
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+
+        let body = response.body().to_owned();
+        (link, cookies, body)
+    }
+
+    /// Extract the CSRF token from a form
+    fn csrf_token(body: &str) -> &str {
+        body.split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap()
+    }
+
+    /// Ask the live username check endpoint about a username
+    async fn check_username(
+        state: &TestState,
+        cookies: &CookieHelper,
+        link: &UpstreamOAuthLink,
+        username: &str,
+    ) -> Value {
+        let path = mas_router::UpstreamOAuth2LinkUsername::new(link.id).path();
+        let request = Request::get(format!("{path}?username={username}")).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        response.json()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_register_unavailable_username_suggestions(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let (link, cookies, body) = start_registration_with_unavailable_username(
+            &state,
+            UpstreamOAuthProviderOnUnavailable::Suggest,
+        )
+        .await;
+
+        // Instead of failing, the form is shown with the mapped username, which can
+        // be edited, and some suggestions
+        assert!(body.contains(r#"name="username""#));
+        assert!(body.contains(r#"value="john""#));
+        assert!(body.contains(r#"data-username-suggestion="john1""#));
+        assert!(body.contains(r#"data-username-suggestion="john2""#));
+        assert!(body.contains(r#"data-username-suggestion="john3""#));
+
+        // The live check agrees
+        let result = check_username(&state, &cookies, &link, "john").await;
+        assert_eq!(result["available"], false);
+        assert_eq!(result["error"]["kind"], "exists");
+        assert_eq!(
+            result["suggestions"],
+            serde_json::json!(["john1", "john2", "john3"])
+        );
+
+        let result = check_username(&state, &cookies, &link, "john1").await;
+        assert_eq!(result["available"], true);
+        assert_eq!(result["error"], Value::Null);
+        assert_eq!(result["suggestions"], serde_json::json!([]));
+
+        // Pick one of the suggestions
+        let request = Request::post(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).form(
+            serde_json::json!({
+                "csrf": csrf_token(&body),
+                "action": "register",
+                "username": "john1",
+                "import_email": "on",
+                "accept_terms": "on",
+            }),
+        );
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .find_by_username("john1")
+            .await
+            .unwrap()
+            .expect("user exists");
+        let link = repo
+            .upstream_oauth_link()
+            .lookup(link.id)
+            .await
+            .unwrap()
+            .expect("link exists");
+        assert_eq!(link.user_id, Some(user.id));
+        assert!(
+            repo.user()
+                .find_by_username("john")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_register_unavailable_username_policy(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let (link, cookies, body) = start_registration_with_unavailable_username(
+            &state,
+            UpstreamOAuthProviderOnUnavailable::Suggest,
+        )
+        .await;
+
+        // The edited username is checked against the policy
+        let result = check_username(&state, &cookies, &link, "John").await;
+        assert_eq!(result["available"], false);
+        assert_eq!(result["error"]["kind"], "policy");
+        assert_eq!(result["error"]["code"], "username-invalid-chars");
+        assert_eq!(result["suggestions"], serde_json::json!([]));
+
+        // And so is the submitted one
+        let request = Request::post(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).form(
+            serde_json::json!({
+                "csrf": csrf_token(&body),
+                "action": "register",
+                "username": "John",
+                "import_email": "on",
+                "accept_terms": "on",
+            }),
+        );
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(
+            response
+                .body()
+                .contains("Username contains invalid characters")
+        );
+
+        let mut repo = state.repository().await.unwrap();
+        assert!(
+            repo.user()
+                .find_by_username("John")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let link = repo
+            .upstream_oauth_link()
+            .lookup(link.id)
+            .await
+            .unwrap()
+            .expect("link exists");
+        assert_eq!(link.user_id, None);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_register_unavailable_username_auto_accept(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let (link, cookies, body) = start_registration_with_unavailable_username(
+            &state,
+            UpstreamOAuthProviderOnUnavailable::AutoAccept,
+        )
+        .await;
+
+        // The first suggestion is used, and can't be changed
+        assert!(!body.contains(r#"name="username""#));
+        assert!(body.contains("@john1:"));
+
+        // Whatever username is submitted is ignored
+        let request = Request::post(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).form(
+            serde_json::json!({
+                "csrf": csrf_token(&body),
+                "action": "register",
+                "username": "someone-else",
+                "import_email": "on",
+                "accept_terms": "on",
+            }),
+        );
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .find_by_username("john1")
+            .await
+            .unwrap()
+            .expect("user exists");
+        let link = repo
+            .upstream_oauth_link()
+            .lookup(link.id)
+            .await
+            .unwrap()
+            .expect("link exists");
+        assert_eq!(link.user_id, Some(user.id));
+        assert!(
+            repo.user()
+                .find_by_username("someone-else")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_register_with_organization(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let claims_imports = UpstreamOAuthProviderClaimsImports {
+            localpart: UpstreamOAuthProviderLocalpartPreference {
+                action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
+                template: None,
+                on_conflict: mas_data_model::UpstreamOAuthProviderOnConflict::default(),
+                on_unavailable: mas_data_model::UpstreamOAuthProviderOnUnavailable::default(),
+            },
+            organization: UpstreamOAuthProviderImportPreference {
+                action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
+                template: Some("{{ user.ministry }}".to_owned()),
+            },
+            ..UpstreamOAuthProviderClaimsImports::default()
+        };
+
+        let id_token_claims = serde_json::json!({
+            "preferred_username": "john",
+            "ministry": "Ministère de l'Intérieur",
+        });
+        let id_token = sign_token(&mut rng, &state.key_store, id_token_claims.clone()).unwrap();
+
+        // Provision a provider and a link
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    issuer: Some("https://example.com/".to_owned()),
+                    human_name: Some("Example Ltd.".to_owned()),
+                    brand_name: None,
+                    scope: Scope::from_iter([OPENID]),
+                    token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                    token_endpoint_signing_alg: None,
+                    id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                    client_id: "client".to_owned(),
+                    encrypted_client_secret: None,
+                    claims_imports,
+                    authorization_endpoint_override: None,
+                    token_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    fetch_userinfo: false,
+                    userinfo_signed_response_alg: None,
+                    jwks_uri_override: None,
+                    discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
+                    pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
+                    response_mode: None,
+                    additional_authorization_parameters: Vec::new(),
+                    forward_login_hint: false,
+                    ui_order: 0,
+                    ui_hidden: false,
+                    on_backchannel_logout:
+                        mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                },
+            )
+            .await
+            .unwrap();
+
+        let (link, session) = add_linked_upstream_session(
+            &mut rng,
+            &state.clock,
+            &mut repo,
+            &provider,
+            "subject",
+            &id_token.into_string(),
+            id_token_claims,
+        )
+        .await
+        .unwrap();
+
+        repo.save().await.unwrap();
+
+        let cookie_jar = state.cookie_jar();
+        let upstream_sessions = UpstreamSessionsCookie::default()
+            .add(session.id, provider.id, "state".to_owned(), None)
+            .add_link_to_session(session.id, link.id)
+            .unwrap();
+        let cookie_jar = upstream_sessions.save(cookie_jar, &state.clock);
+        cookies.import(cookie_jar);
+
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+
+        // Extract the CSRF token from the response body
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap();
+
+        let request = Request::post(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).form(
+            serde_json::json!({
+                "csrf": csrf_token,
+                "action": "register",
+                "accept_terms": "on",
+            }),
+        );
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // Check that the organization was imported on registration
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .find_by_username("john")
+            .await
+            .unwrap()
+            .expect("user exists");
+        assert_eq!(
+            user.organization.as_deref(),
+            Some("Ministère de l'Intérieur")
+        );
+
+        // Log in again through the same link, with a different organization in the
+        // claims
+        let id_token_claims = serde_json::json!({
+            "preferred_username": "john",
+            "ministry": "Ministère de la Culture",
+        });
+        let id_token = sign_token(&mut rng, &state.key_store, id_token_claims.clone()).unwrap();
+        let session = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                &provider,
+                "state2".to_owned(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let session = repo
+            .upstream_oauth_session()
+            .complete_with_link(
+                &state.clock,
+                session,
+                &link,
+                Some(id_token.into_string()),
+                Some(id_token_claims),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        // Start from a fresh browser, so that this is a login
+        let cookies = CookieHelper::new();
+        let cookie_jar = state.cookie_jar();
+        let upstream_sessions = UpstreamSessionsCookie::default()
+            .add(session.id, provider.id, "state2".to_owned(), None)
+            .add_link_to_session(session.id, link.id)
+            .unwrap();
+        let cookie_jar = upstream_sessions.save(cookie_jar, &state.clock);
+        cookies.import(cookie_jar);
+
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // The organization is forced, so it was updated
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert_eq!(
+            user.organization.as_deref(),
+            Some("Ministère de la Culture")
+        );
+    }
+    async fn add_admin_provider(
+        rng: &mut ChaChaRng,
+        clock: &impl mas_data_model::Clock,
+        repo: &mut Box<dyn Repository<RepositoryError> + Send + Sync + 'static>,
+        action: UpstreamOAuthProviderAdminImportAction,
+    ) -> Result<mas_data_model::UpstreamOAuthProvider, RepositoryError> {
+        let claims_imports = UpstreamOAuthProviderClaimsImports {
+            localpart: UpstreamOAuthProviderLocalpartPreference {
+                action: mas_data_model::UpstreamOAuthProviderImportAction::Force,
+                template: None,
+                on_conflict: mas_data_model::UpstreamOAuthProviderOnConflict::default(),
+                on_unavailable: mas_data_model::UpstreamOAuthProviderOnUnavailable::default(),
+            },
+            admin: UpstreamOAuthProviderAdminPreference {
+                action,
+                template: Some("{{ 'mas-admin' in (user.roles or []) }}".to_owned()),
+            },
+            ..UpstreamOAuthProviderClaimsImports::default()
+        };
+
+        repo.upstream_oauth_provider()
+            .add(
+                rng,
+                clock,
+                UpstreamOAuthProviderParams {
+                    issuer: Some("https://example.com/".to_owned()),
+                    human_name: Some("Example Ltd.".to_owned()),
+                    brand_name: None,
+                    scope: Scope::from_iter([OPENID]),
+                    token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                    token_endpoint_signing_alg: None,
+                    id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                    client_id: "client".to_owned(),
+                    encrypted_client_secret: None,
+                    claims_imports,
+                    authorization_endpoint_override: None,
+                    token_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    fetch_userinfo: false,
+                    userinfo_signed_response_alg: None,
+                    jwks_uri_override: None,
+                    discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
+                    pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
+                    response_mode: None,
+                    additional_authorization_parameters: Vec::new(),
+                    forward_login_hint: false,
+                    on_backchannel_logout:
+                        mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                    ui_order: 0,
+                    ui_hidden: false,
+                },
+            )
+            .await
+    }
+
+    /// Log in through an existing link from a fresh browser, with the given
+    /// claims in the ID token
+    async fn login_with_claims(
+        state: &TestState,
+        provider: &mas_data_model::UpstreamOAuthProvider,
+        link: &UpstreamOAuthLink,
+        id_token_claims: Value,
+    ) {
+        let mut rng = state.rng();
+        let id_token = sign_token(&mut rng, &state.key_store, id_token_claims.clone()).unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        let session = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &state.clock,
+                provider,
+                "login".to_owned(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let session = repo
+            .upstream_oauth_session()
+            .complete_with_link(
+                &state.clock,
+                session,
+                link,
+                Some(id_token.into_string()),
+                Some(id_token_claims),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let cookies = CookieHelper::new();
+        let cookie_jar = state.cookie_jar();
+        let upstream_sessions = UpstreamSessionsCookie::default()
+            .add(session.id, provider.id, "login".to_owned(), None)
+            .add_link_to_session(session.id, link.id)
+            .unwrap();
+        let cookie_jar = upstream_sessions.save(cookie_jar, &state.clock);
+        cookies.import(cookie_jar);
+
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+    }
+
+    /// Get the `can_request_admin` value of the admin permission changes
+    /// queued for the audit webhook
+    async fn admin_permission_changes(pool: &PgPool) -> Vec<bool> {
+        let mut changes: Vec<bool> = sqlx::query_scalar(
+            r"
+                SELECT (payload->'event'->>'can_request_admin')::BOOLEAN
+                FROM queue_jobs
+                WHERE queue_name = 'send-audit-event'
+                  AND payload->'event'->>'type' = 'admin_permission_change'
+            ",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        changes.sort_unstable();
+        changes
+    }
+
+    async fn state_with_audit_webhook(pool: PgPool) -> TestState {
+        let site_config = SiteConfig {
+            audit_webhook: Some(AuditWebhookConfig {
+                url: "https://audit.example.com/events".parse().unwrap(),
+                secret: None,
+                events: vec![AuditEventKind::AdminPermissionChange],
+            }),
+            ..test_site_config()
+        };
+
+        TestState::from_pool_with_site_config(pool, site_config)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_register_with_admin_claim(pool: PgPool) {
+        setup();
+        let state = state_with_audit_webhook(pool.clone()).await;
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let id_token_claims = serde_json::json!({
+            "preferred_username": "john",
+            "roles": ["agent", "mas-admin"],
+        });
+        let id_token = sign_token(&mut rng, &state.key_store, id_token_claims.clone()).unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        let provider = add_admin_provider(
+            &mut rng,
+            &state.clock,
+            &mut repo,
+            UpstreamOAuthProviderAdminImportAction::Always,
+        )
+        .await
+        .unwrap();
+
+        let (link, session) = add_linked_upstream_session(
+            &mut rng,
+            &state.clock,
+            &mut repo,
+            &provider,
+            "subject",
+            &id_token.into_string(),
+            id_token_claims,
+        )
+        .await
+        .unwrap();
+
+        repo.save().await.unwrap();
+
         let cookie_jar = state.cookie_jar();
         let upstream_sessions = UpstreamSessionsCookie::default()
             .add(session.id, provider.id, "state".to_owned(), None)
@@ -1344,9 +2845,7 @@ mod tests {
         let response = state.request(request).await;
         cookies.save_cookies(&response);
         response.assert_status(StatusCode::OK);
-        response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
 
-        // Extract the CSRF token from the response body
         let csrf_token = response
             .body()
             .split("name=\"csrf\" value=\"")
@@ -1360,7 +2859,6 @@ mod tests {
             serde_json::json!({
                 "csrf": csrf_token,
                 "action": "register",
-                "import_email": "on",
                 "accept_terms": "on",
             }),
         );
@@ -1369,7 +2867,7 @@ mod tests {
         cookies.save_cookies(&response);
         response.assert_status(StatusCode::SEE_OTHER);
 
-        // Check that we have a registered user, with the email imported
+        // The right to request admin access was granted on registration
         let mut repo = state.repository().await.unwrap();
         let user = repo
             .user()
@@ -1377,25 +2875,110 @@ mod tests {
             .await
             .unwrap()
             .expect("user exists");
+        assert!(user.can_request_admin);
+        assert_eq!(admin_permission_changes(&pool).await, vec![true]);
 
-        let link = repo
-            .upstream_oauth_link()
-            .find_by_subject(&provider, "subject")
-            .await
-            .unwrap()
-            .expect("link exists");
+        repo.cancel().await.unwrap();
 
-        assert_eq!(link.user_id, Some(user.id));
+        // Logging in again with the same claims doesn't change anything
+        login_with_claims(
+            &state,
+            &provider,
+            &link,
+            serde_json::json!({
+                "preferred_username": "john",
+                "roles": ["mas-admin"],
+            }),
+        )
+        .await;
+        assert_eq!(admin_permission_changes(&pool).await, vec![true]);
 
-        let page = repo
-            .user_email()
-            .list(UserEmailFilter::new().for_user(&user), Pagination::first(1))
+        // The role was removed upstream, so the right is revoked on the next login
+        login_with_claims(
+            &state,
+            &provider,
+            &link,
+            serde_json::json!({
+                "preferred_username": "john",
+                "roles": ["agent"],
+            }),
+        )
+        .await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert!(!user.can_request_admin);
+        assert_eq!(admin_permission_changes(&pool).await, vec![false, true]);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_admin_claim_not_updated(pool: PgPool) {
+        setup();
+        let state = state_with_audit_webhook(pool.clone()).await;
+        let mut rng = state.rng();
+
+        let mut repo = state.repository().await.unwrap();
+        let ignoring_provider = add_admin_provider(
+            &mut rng,
+            &state.clock,
+            &mut repo,
+            UpstreamOAuthProviderAdminImportAction::Ignore,
+        )
+        .await
+        .unwrap();
+        let registration_only_provider = add_admin_provider(
+            &mut rng,
+            &state.clock,
+            &mut repo,
+            UpstreamOAuthProviderAdminImportAction::Never,
+        )
+        .await
+        .unwrap();
+
+        // An existing user, linked to both providers
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
             .await
             .unwrap();
-        let edge = page.edges.first().expect("email exists");
+        let mut links = Vec::new();
+        for provider in [&ignoring_provider, &registration_only_provider] {
+            let link = repo
+                .upstream_oauth_link()
+                .add(&mut rng, &state.clock, provider, "subject".to_owned(), None)
+                .await
+                .unwrap();
+            repo.upstream_oauth_link()
+                .associate_to_user(&link, &user)
+                .await
+                .unwrap();
+            links.push(link);
+        }
+        repo.save().await.unwrap();
 
-        assert_eq!(edge.node.email, "john@example.com");
+        // Neither provider updates the right on login
+        for (provider, link) in [&ignoring_provider, &registration_only_provider]
+            .into_iter()
+            .zip(&links)
+        {
+            login_with_claims(
+                &state,
+                provider,
+                link,
+                serde_json::json!({
+                    "preferred_username": "john",
+                    "roles": ["mas-admin"],
+                }),
+            )
+            .await;
+        }
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        assert!(!user.can_request_admin);
+        assert!(admin_permission_changes(&pool).await.is_empty());
     }
+
     #[ignore = "Tchap links existing account by email"]
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_link_existing_account(pool: PgPool) {
@@ -1412,6 +2995,7 @@ mod tests {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Require,
                 template: None,
                 on_conflict: mas_data_model::UpstreamOAuthProviderOnConflict::Add,
+                on_unavailable: mas_data_model::UpstreamOAuthProviderOnUnavailable::default(),
             },
             email: UpstreamOAuthProviderImportPreference {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Require,
@@ -1461,6 +3045,7 @@ mod tests {
                     on_backchannel_logout:
                         mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                     ui_order: 0,
+                    ui_hidden: false,
                 },
             )
             .await
@@ -1551,6 +3136,7 @@ mod tests {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Require,
                 template: None,
                 on_conflict: mas_data_model::UpstreamOAuthProviderOnConflict::default(),
+                on_unavailable: mas_data_model::UpstreamOAuthProviderOnUnavailable::default(),
             },
             email: UpstreamOAuthProviderImportPreference {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Require,
@@ -1600,6 +3186,7 @@ mod tests {
                     on_backchannel_logout:
                         mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                     ui_order: 0,
+                    ui_hidden: false,
                 },
             )
             .await
@@ -1663,6 +3250,7 @@ mod tests {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Require,
                 template: None,
                 on_conflict: mas_data_model::UpstreamOAuthProviderOnConflict::Add,
+                on_unavailable: mas_data_model::UpstreamOAuthProviderOnUnavailable::default(),
             },
             email: UpstreamOAuthProviderImportPreference {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Require,
@@ -1711,6 +3299,7 @@ mod tests {
                     on_backchannel_logout:
                         mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                     ui_order: 0,
+                    ui_hidden: false,
                 },
             )
             .await
@@ -1834,6 +3423,7 @@ mod tests {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Require,
                 template: None,
                 on_conflict: mas_data_model::UpstreamOAuthProviderOnConflict::Add,
+                on_unavailable: mas_data_model::UpstreamOAuthProviderOnUnavailable::default(),
             },
             email: UpstreamOAuthProviderImportPreference {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Require,
@@ -1882,6 +3472,7 @@ mod tests {
                     on_backchannel_logout:
                         mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                     ui_order: 0,
+                    ui_hidden: false,
                 },
             )
             .await
@@ -2070,6 +3661,7 @@ mod tests {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Require,
                 template: None,
                 on_conflict: mas_data_model::UpstreamOAuthProviderOnConflict::Add,
+                on_unavailable: mas_data_model::UpstreamOAuthProviderOnUnavailable::default(),
             },
             email: UpstreamOAuthProviderImportPreference {
                 action: mas_data_model::UpstreamOAuthProviderImportAction::Require,
@@ -2119,6 +3711,7 @@ mod tests {
                     on_backchannel_logout:
                         mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                     ui_order: 0,
+                    ui_hidden: false,
                 },
             )
             .await
@@ -2163,4 +3756,277 @@ mod tests {
 
         assert!(response.body().contains("Unexpected error"));
     }
+
+    /// Add a provider which can be used to recover accounts in the tests
+    async fn add_recovery_provider(
+        rng: &mut ChaChaRng,
+        clock: &impl mas_data_model::Clock,
+        repo: &mut Box<dyn Repository<RepositoryError> + Send + Sync + 'static>,
+    ) -> Result<mas_data_model::UpstreamOAuthProvider, RepositoryError> {
+        repo.upstream_oauth_provider()
+            .add(
+                rng,
+                clock,
+                UpstreamOAuthProviderParams {
+                    issuer: Some("https://example.com/".to_owned()),
+                    human_name: Some("Example Ltd.".to_owned()),
+                    brand_name: None,
+                    scope: Scope::from_iter([OPENID]),
+                    token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                    token_endpoint_signing_alg: None,
+                    id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                    client_id: "client".to_owned(),
+                    encrypted_client_secret: None,
+                    claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+                    authorization_endpoint_override: None,
+                    token_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    fetch_userinfo: false,
+                    userinfo_signed_response_alg: None,
+                    jwks_uri_override: None,
+                    discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
+                    pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
+                    response_mode: None,
+                    additional_authorization_parameters: Vec::new(),
+                    forward_login_hint: false,
+                    on_backchannel_logout:
+                        mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                    ui_order: 0,
+                    ui_hidden: false,
+                },
+            )
+            .await
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_recover_account(pool: PgPool) {
+        setup();
+        let site_config = SiteConfig {
+            audit_webhook: Some(AuditWebhookConfig {
+                url: "https://audit.example.com/".parse().unwrap(),
+                secret: None,
+                events: vec![AuditEventKind::PasswordChange],
+            }),
+            ..test_site_config()
+        };
+        let state = TestState::from_pool_with_site_config(pool.clone(), site_config)
+            .await
+            .unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let id_token_claims = serde_json::json!({
+            "email": "john@example.com",
+            "email_verified": true,
+        });
+        let id_token = sign_token(&mut rng, &state.key_store, id_token_claims.clone()).unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        let provider = add_recovery_provider(&mut rng, &state.clock, &mut repo)
+            .await
+            .unwrap();
+
+        let (link, session) = add_linked_upstream_session(
+            &mut rng,
+            &state.clock,
+            &mut repo,
+            &provider,
+            "subject",
+            &id_token.into_string(),
+            id_token_claims,
+        )
+        .await
+        .unwrap();
+
+        // The upstream identity is linked to an existing user
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        repo.upstream_oauth_link()
+            .associate_to_user(&link, &user)
+            .await
+            .unwrap();
+
+        repo.save().await.unwrap();
+
+        let cookie_jar = state.cookie_jar();
+        let upstream_sessions = UpstreamSessionsCookie::default()
+            .add(
+                session.id,
+                provider.id,
+                "state".to_owned(),
+                Some(PostAuthAction::RecoverAccount),
+            )
+            .add_link_to_session(session.id, link.id)
+            .unwrap();
+        let cookie_jar = upstream_sessions.save(cookie_jar, &state.clock);
+        cookies.import(cookie_jar);
+
+        // Consuming the link logs the user in and sends them to the recovery page
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "/recover/upstream");
+
+        let request = Request::get(mas_router::AccountRecoveryUpstream::PATH).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap();
+
+        // Mismatching passwords are rejected
+        let request =
+            Request::post(mas_router::AccountRecoveryUpstream::PATH).form(serde_json::json!({
+                "csrf": csrf_token,
+                "new_password": "a very complex password, 1234",
+                "new_password_confirm": "another password",
+            }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap();
+
+        let request =
+            Request::post(mas_router::AccountRecoveryUpstream::PATH).form(serde_json::json!({
+                "csrf": csrf_token,
+                "new_password": "a very complex password, 1234",
+                "new_password_confirm": "a very complex password, 1234",
+            }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "/account/?action=sessions_list");
+
+        // The user now has a password
+        let mut repo = state.repository().await.unwrap();
+        let password = repo.user_password().active(&user).await.unwrap();
+        assert!(password.is_some());
+
+        // The browser session is authenticated by the upstream login which
+        // allowed the recovery
+        let browser_session = repo
+            .browser_session()
+            .list(
+                BrowserSessionFilter::new().for_user(&user).active_only(),
+                Pagination::first(1),
+            )
+            .await
+            .unwrap()
+            .edges
+            .remove(0)
+            .node;
+        let authentication = repo
+            .browser_session()
+            .get_last_authentication(&browser_session)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            authentication.authentication_method,
+            mas_data_model::AuthenticationMethod::UpstreamOAuth2 {
+                upstream_oauth2_session_id: session.id,
+            }
+        );
+        repo.save().await.unwrap();
+
+        // The password change was recorded as a recovery
+        let job: sqlx::types::Json<Value> = sqlx::query_scalar(
+            "SELECT payload FROM queue_jobs WHERE queue_name = 'send-audit-event'",
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Audit event to be scheduled");
+        let event = &job["event"];
+        assert_eq!(event["type"], "password_change");
+        assert_eq!(event["user_id"], serde_json::json!(user.id));
+        assert_eq!(event["initiator"], "recovery");
+
+        // The upstream authentication can't be used to set another password
+        let request = Request::get(mas_router::AccountRecoveryUpstream::PATH).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "/");
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_recover_account_unlinked(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let cookies = CookieHelper::new();
+
+        let id_token_claims = serde_json::json!({
+            "email": "john@example.com",
+            "email_verified": true,
+        });
+        let id_token = sign_token(&mut rng, &state.key_store, id_token_claims.clone()).unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        let provider = add_recovery_provider(&mut rng, &state.clock, &mut repo)
+            .await
+            .unwrap();
+
+        let (link, session) = add_linked_upstream_session(
+            &mut rng,
+            &state.clock,
+            &mut repo,
+            &provider,
+            "subject",
+            &id_token.into_string(),
+            id_token_claims,
+        )
+        .await
+        .unwrap();
+
+        repo.save().await.unwrap();
+
+        let cookie_jar = state.cookie_jar();
+        let upstream_sessions = UpstreamSessionsCookie::default()
+            .add(
+                session.id,
+                provider.id,
+                "state".to_owned(),
+                Some(PostAuthAction::RecoverAccount),
+            )
+            .add_link_to_session(session.id, link.id)
+            .unwrap();
+        let cookie_jar = upstream_sessions.save(cookie_jar, &state.clock);
+        cookies.import(cookie_jar);
+
+        // There is no account to recover, so no registration form is offered
+        let request = Request::get(&*mas_router::UpstreamOAuth2Link::new(link.id).path()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
+        assert!(
+            !response
+                .body()
+                .contains("name=\"action\" value=\"register\"")
+        );
+    }
 }
@@ -4,17 +4,27 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::string::FromUtf8Error;
+use std::{string::FromUtf8Error, sync::LazyLock};
 
-use mas_data_model::{UpstreamOAuthProvider, UpstreamOAuthProviderTokenAuthMethod};
-use mas_iana::jose::JsonWebSignatureAlg;
-use mas_keystore::{DecryptError, Encrypter, Keystore};
+use mas_data_model::{
+    Clock, UpstreamOAuthAuthorizationSession, UpstreamOAuthProvider,
+    UpstreamOAuthProviderTokenAuthMethod,
+};
+use mas_iana::{
+    jose::{JsonWebKeyUse, JsonWebSignatureAlg},
+    oauth::OAuthClientAuthenticationMethod,
+};
+use mas_keystore::{DecryptError, Encrypter, JsonWebKey, JsonWebKeySet, Keystore, PrivateKey};
 use mas_oidc_client::types::client_credentials::ClientCredentials;
+use oauth2_types::oidc::ProviderMetadata;
+use opentelemetry::{Key, KeyValue, metrics::Histogram};
 use pkcs8::DecodePrivateKey;
 use serde::Deserialize;
 use thiserror::Error;
 use url::Url;
 
+use crate::METER;
+
 pub(crate) mod authorize;
 pub(crate) mod backchannel_logout;
 pub(crate) mod cache;
@@ -25,6 +35,40 @@ mod template;
 
 use self::cookie::UpstreamSessions as UpstreamSessionsCookie;
 
+static LOGIN_DURATION_HISTOGRAM: LazyLock<Histogram<f64>> = LazyLock::new(|| {
+    METER
+        .f64_histogram("mas.upstream_oauth2.login.duration")
+        .with_description(
+            "Time between the redirect to the upstream provider and the completion of the login",
+        )
+        .with_unit("s")
+        .build()
+});
+const PROVIDER: Key = Key::from_static_str("provider");
+const PROVIDER_NAME: Key = Key::from_static_str("provider.name");
+
+/// The attributes identifying a provider on the upstream OAuth 2.0 metrics
+fn provider_attributes(provider: &UpstreamOAuthProvider) -> Vec<KeyValue> {
+    vec![
+        KeyValue::new(PROVIDER, provider.id.to_string()),
+        KeyValue::new(
+            PROVIDER_NAME,
+            provider.human_name.clone().unwrap_or_default(),
+        ),
+    ]
+}
+
+/// Record how long it took to go through the upstream provider, from the
+/// creation of the authorization session until now
+fn record_login_duration(
+    clock: &dyn Clock,
+    provider: &UpstreamOAuthProvider,
+    session: &UpstreamOAuthAuthorizationSession,
+) {
+    let duration = clock.now() - session.created_at;
+    LOGIN_DURATION_HISTOGRAM.record(duration.as_seconds_f64(), &provider_attributes(provider));
+}
+
 #[derive(Debug, Error)]
 #[allow(clippy::enum_variant_names)]
 enum ProviderCredentialsError {
@@ -54,6 +98,20 @@ enum ProviderCredentialsError {
         #[from]
         inner: pkcs8::Error,
     },
+
+    #[error("Could not load the private key used to sign the client assertion")]
+    InvalidSigningKey {
+        #[from]
+        inner: mas_keystore::LoadError,
+    },
+
+    #[error("Provider does not support the {method} token endpoint authentication method")]
+    UnsupportedAuthMethod {
+        method: UpstreamOAuthProviderTokenAuthMethod,
+    },
+
+    #[error("Provider does not support signing client assertions with {alg}")]
+    UnsupportedSigningAlgorithm { alg: JsonWebSignatureAlg },
 }
 
 #[derive(Debug, Deserialize)]
@@ -63,6 +121,93 @@ pub struct SignInWithApple {
     pub key_id: String,
 }
 
+/// The dedicated keys of a provider using the `private_key_jwt` method, as
+/// stored in its encrypted client secret
+#[derive(Debug, Deserialize)]
+pub struct PrivateKeyJwt {
+    pub keys: Vec<PrivateKeyJwtKey>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrivateKeyJwtKey {
+    pub kid: String,
+    pub private_key: String,
+}
+
+impl PrivateKeyJwt {
+    /// Build a keystore out of the dedicated keys, keeping them in the order
+    /// they were configured so that the newest one is picked when signing
+    fn keystore(&self) -> Result<Keystore, mas_keystore::LoadError> {
+        let keys = self
+            .keys
+            .iter()
+            .map(|key| {
+                let private_key = PrivateKey::load_pem(&key.private_key)?;
+                Ok(JsonWebKey::new(private_key)
+                    .with_kid(key.kid.clone())
+                    .with_use(JsonWebKeyUse::Sig))
+            })
+            .collect::<Result<Vec<_>, mas_keystore::LoadError>>()?;
+
+        Ok(Keystore::new(JsonWebKeySet::new(keys)))
+    }
+}
+
+/// Check that the provider advertises support for the token endpoint
+/// authentication method and signing algorithm configured for it.
+///
+/// Providers which don't advertise what they support in their metadata are
+/// assumed to support the configured method.
+fn check_token_endpoint_auth(
+    provider: &UpstreamOAuthProvider,
+    metadata: &ProviderMetadata,
+) -> Result<(), ProviderCredentialsError> {
+    let method = match provider.token_endpoint_auth_method {
+        UpstreamOAuthProviderTokenAuthMethod::None => OAuthClientAuthenticationMethod::None,
+        UpstreamOAuthProviderTokenAuthMethod::ClientSecretBasic => {
+            OAuthClientAuthenticationMethod::ClientSecretBasic
+        }
+        UpstreamOAuthProviderTokenAuthMethod::ClientSecretPost => {
+            OAuthClientAuthenticationMethod::ClientSecretPost
+        }
+        UpstreamOAuthProviderTokenAuthMethod::ClientSecretJwt => {
+            OAuthClientAuthenticationMethod::ClientSecretJwt
+        }
+        UpstreamOAuthProviderTokenAuthMethod::PrivateKeyJwt => {
+            OAuthClientAuthenticationMethod::PrivateKeyJwt
+        }
+        // Apple doesn't advertise this non-standard method
+        UpstreamOAuthProviderTokenAuthMethod::SignInWithApple => return Ok(()),
+    };
+
+    if let Some(supported) = &metadata.token_endpoint_auth_methods_supported
+        && !supported.contains(&method)
+    {
+        return Err(ProviderCredentialsError::UnsupportedAuthMethod {
+            method: provider.token_endpoint_auth_method,
+        });
+    }
+
+    if matches!(
+        method,
+        OAuthClientAuthenticationMethod::ClientSecretJwt
+            | OAuthClientAuthenticationMethod::PrivateKeyJwt
+    ) {
+        let alg = provider
+            .token_endpoint_signing_alg
+            .clone()
+            .unwrap_or(JsonWebSignatureAlg::Rs256);
+
+        if let Some(supported) = &metadata.token_endpoint_auth_signing_alg_values_supported
+            && !supported.contains(&alg)
+        {
+            return Err(ProviderCredentialsError::UnsupportedSigningAlgorithm { alg });
+        }
+    }
+
+    Ok(())
+}
+
 fn client_credentials_for_provider(
     provider: &UpstreamOAuthProvider,
     token_endpoint: &Url,
@@ -114,15 +259,26 @@ fn client_credentials_for_provider(
             }
         }
 
-        UpstreamOAuthProviderTokenAuthMethod::PrivateKeyJwt => ClientCredentials::PrivateKeyJwt {
-            client_id,
-            keystore: keystore.clone(),
-            signing_algorithm: provider
-                .token_endpoint_signing_alg
-                .clone()
-                .unwrap_or(JsonWebSignatureAlg::Rs256),
-            token_endpoint: token_endpoint.clone(),
-        },
+        UpstreamOAuthProviderTokenAuthMethod::PrivateKeyJwt => {
+            // Use the dedicated keys of the provider if it has some, else fall back to the
+            // keys from the `secrets` section
+            let keystore = if let Some(params) = client_secret {
+                let params: PrivateKeyJwt = serde_json::from_str(&params)?;
+                params.keystore()?
+            } else {
+                keystore.clone()
+            };
+
+            ClientCredentials::PrivateKeyJwt {
+                client_id,
+                keystore,
+                signing_algorithm: provider
+                    .token_endpoint_signing_alg
+                    .clone()
+                    .unwrap_or(JsonWebSignatureAlg::Rs256),
+                token_endpoint: token_endpoint.clone(),
+            }
+        }
 
         UpstreamOAuthProviderTokenAuthMethod::SignInWithApple => {
             let params = client_secret.ok_or(ProviderCredentialsError::MissingClientSecret)?;
@@ -141,3 +297,210 @@ fn client_credentials_for_provider(
 
     Ok(client_credentials)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mas_data_model::{
+        Clock, UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderDiscoveryMode,
+        UpstreamOAuthProviderOnBackchannelLogout, UpstreamOAuthProviderPkceMode, clock::MockClock,
+    };
+    use mas_iana::oauth::OAuthAccessTokenType;
+    use mas_jose::jwt::Jwt;
+    use oauth2_types::{
+        requests::AccessTokenResponse,
+        scope::{OPENID, Scope},
+    };
+    use rand::SeedableRng;
+    use serde_json::Value;
+    use ulid::Ulid;
+    use url::form_urlencoded;
+    use wiremock::{
+        Mock, MockServer, Request, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    use super::*;
+    use crate::test_utils::setup;
+
+    fn provider(
+        token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod,
+    ) -> UpstreamOAuthProvider {
+        UpstreamOAuthProvider {
+            id: Ulid::nil(),
+            issuer: Some("https://example.com/".to_owned()),
+            human_name: None,
+            brand_name: None,
+            discovery_mode: UpstreamOAuthProviderDiscoveryMode::Disabled,
+            pkce_mode: UpstreamOAuthProviderPkceMode::Auto,
+            fetch_userinfo: false,
+            userinfo_signed_response_alg: None,
+            jwks_uri_override: None,
+            authorization_endpoint_override: None,
+            scope: Scope::from_iter([OPENID]),
+            userinfo_endpoint_override: None,
+            token_endpoint_override: None,
+            client_id: "client".to_owned(),
+            encrypted_client_secret: None,
+            token_endpoint_signing_alg: Some(JsonWebSignatureAlg::Es256),
+            token_endpoint_auth_method,
+            id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+            response_mode: None,
+            created_at: MockClock::default().now(),
+            disabled_at: None,
+            claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+            additional_authorization_parameters: Vec::new(),
+            forward_login_hint: false,
+            on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+            ui_hidden: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_private_key_jwt_dedicated_keys() {
+        setup();
+        let mock_server = MockServer::start().await;
+        let http_client = mas_http::reqwest_client();
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let encrypter = Encrypter::new(&[0x42; 32]);
+
+        // The provider has two dedicated keys: the second one was added when
+        // rotating, and should be the one used
+        let old_key = PrivateKey::generate_ec_p256(&mut rng)
+            .to_pem(pkcs8::LineEnding::LF)
+            .unwrap()
+            .to_string();
+        let new_key = PrivateKey::generate_ec_p256(&mut rng)
+            .to_pem(pkcs8::LineEnding::LF)
+            .unwrap()
+            .to_string();
+
+        let new_public_jwks = PrivateKeyJwt {
+            keys: vec![PrivateKeyJwtKey {
+                kid: "new".to_owned(),
+                private_key: new_key.clone(),
+            }],
+        }
+        .keystore()
+        .unwrap()
+        .public_jwks();
+
+        let params = serde_json::json!({
+            "keys": [
+                { "kid": "old", "private_key": old_key },
+                { "kid": "new", "private_key": new_key },
+            ],
+        });
+        let mut provider = provider(UpstreamOAuthProviderTokenAuthMethod::PrivateKeyJwt);
+        provider.encrypted_client_secret = Some(
+            encrypter
+                .encrypt_to_string(&serde_json::to_vec(&params).unwrap())
+                .unwrap(),
+        );
+
+        let token_endpoint = Url::parse(&mock_server.uri())
+            .unwrap()
+            .join("token")
+            .unwrap();
+        let audience = token_endpoint.to_string();
+
+        // The keys from the `secrets` section must not be used
+        let client_credentials = client_credentials_for_provider(
+            &provider,
+            &token_endpoint,
+            &Keystore::default(),
+            &encrypter,
+        )
+        .unwrap();
+
+        let _mock_guard = Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(move |req: &Request| {
+                let form = form_urlencoded::parse(&req.body).collect::<HashMap<_, _>>();
+                if form
+                    .get("client_assertion_type")
+                    .is_none_or(|t| t != "urn:ietf:params:oauth:client-assertion-type:jwt-bearer")
+                {
+                    return false;
+                }
+
+                let Some(assertion) = form.get("client_assertion") else {
+                    return false;
+                };
+                let jwt = Jwt::<HashMap<String, Value>>::try_from(assertion.as_ref()).unwrap();
+                if jwt.header().kid() != Some("new") {
+                    return false;
+                }
+
+                if jwt.verify_with_jwks(&new_public_jwks).is_err() {
+                    return false;
+                }
+
+                let claims = jwt.payload();
+                claims["iss"] == "client"
+                    && claims["sub"] == "client"
+                    && claims["aud"] == audience.as_str()
+                    && claims.get("jti").is_some_and(Value::is_string)
+                    && claims["exp"].as_i64() > claims["iat"].as_i64()
+            })
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(AccessTokenResponse {
+                    access_token: "access_token".to_owned(),
+                    refresh_token: None,
+                    id_token: None,
+                    token_type: OAuthAccessTokenType::Bearer,
+                    expires_in: None,
+                    scope: None,
+                    matrix_session: None,
+                }),
+            )
+            .expect(1)
+            .mount_as_scoped(&mock_server)
+            .await;
+
+        mas_oidc_client::requests::client_credentials::access_token_with_client_credentials(
+            &http_client,
+            client_credentials,
+            &token_endpoint,
+            None,
+            MockClock::default().now(),
+            &mut rng,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_check_token_endpoint_auth() {
+        let provider = provider(UpstreamOAuthProviderTokenAuthMethod::PrivateKeyJwt);
+
+        // Providers which don't advertise anything are trusted
+        let mut metadata = ProviderMetadata::default();
+        check_token_endpoint_auth(&provider, &metadata).unwrap();
+
+        metadata.token_endpoint_auth_methods_supported = Some(vec![
+            OAuthClientAuthenticationMethod::ClientSecretBasic,
+            OAuthClientAuthenticationMethod::PrivateKeyJwt,
+        ]);
+        metadata.token_endpoint_auth_signing_alg_values_supported =
+            Some(vec![JsonWebSignatureAlg::Rs256, JsonWebSignatureAlg::Es256]);
+        check_token_endpoint_auth(&provider, &metadata).unwrap();
+
+        // The signing algorithm is not supported
+        metadata.token_endpoint_auth_signing_alg_values_supported =
+            Some(vec![JsonWebSignatureAlg::Rs256]);
+        assert!(matches!(
+            check_token_endpoint_auth(&provider, &metadata),
+            Err(ProviderCredentialsError::UnsupportedSigningAlgorithm { .. })
+        ));
+
+        // The method itself is not supported
+        metadata.token_endpoint_auth_methods_supported =
+            Some(vec![OAuthClientAuthenticationMethod::ClientSecretBasic]);
+        assert!(matches!(
+            check_token_endpoint_auth(&provider, &metadata),
+            Err(ProviderCredentialsError::UnsupportedAuthMethod { .. })
+        ));
+    }
+}
@@ -7,6 +7,7 @@
 use std::{collections::HashMap, sync::Arc};
 
 use base64ct::{Base64, Base64Unpadded, Base64Url, Base64UrlUnpadded, Encoding};
+use mas_data_model::TchapFeatures;
 use minijinja::{
     Environment, Error, ErrorKind, Value,
     value::{Enumerator, Object},
@@ -192,9 +193,6 @@ pub fn environment() -> Environment<'static> {
     // Add Tchap-specific filters, this could be a generic config submitted
     // to upstream allowing all users to add their own filters without upstream code
     // modifications tester les fonctions async pour le reseau
-    env.add_filter("email_to_display_name", |s: &str| {
-        tchap::email_to_display_name(s)
-    });
     env.add_filter("email_to_mxid_localpart", |s: &str| {
         tchap::email_to_mxid_localpart(s)
     });
@@ -204,9 +202,23 @@ pub fn environment() -> Environment<'static> {
     env
 }
 
+//:tchap:
+/// Add the filters of the Tchap behaviours enabled on this deployment to the
+/// environment
+pub fn add_tchap_filters(env: &mut Environment<'static>, tchap_features: TchapFeatures) {
+    if tchap_features.displayname_suffixing {
+        env.add_filter("email_to_display_name", |s: &str| {
+            tchap::email_to_display_name(s)
+        });
+    }
+}
+//:tchap:end
+
 #[cfg(test)]
 mod tests {
-    use super::environment;
+    use mas_data_model::TchapFeatures;
+
+    use super::{add_tchap_filters, environment};
 
     #[test]
     fn test_split() {
@@ -249,4 +261,26 @@ mod tests {
             .unwrap();
         assert_eq!(res, "unpadded");
     }
+
+    #[test]
+    fn test_email_to_display_name() {
+        let template = "{{ 'john.doe@example.com' | email_to_display_name }}";
+
+        let mut env = environment();
+        add_tchap_filters(&mut env, TchapFeatures::default());
+        let res = env.render_str(template, ()).unwrap();
+        assert_eq!(res, "John Doe [Example]");
+
+        // The filter is not available when the display names are not derived
+        // from the email
+        let mut env = environment();
+        add_tchap_filters(
+            &mut env,
+            TchapFeatures {
+                displayname_suffixing: false,
+                ..TchapFeatures::default()
+            },
+        );
+        assert!(env.render_str(template, ()).is_err());
+    }
 }
@@ -0,0 +1,444 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Operations on users shared between the admin API, the `manage` CLI
+//! commands and the user-facing handlers, so that they all behave the same
+//! way.
+
+use std::str::FromStr;
+
+use mas_data_model::{AuditEventPayload, Clock, SiteConfig, User, UserEmail};
+use mas_storage::{
+    BoxRepository, RepositoryError,
+    compat::CompatSessionFilter,
+    oauth2::OAuth2SessionFilter,
+    personal::PersonalSessionFilter,
+    queue::{
+        EndedSessions, ProvisionUserJob, QueueJobRepositoryExt as _, SendAccountRecoveredEmailJob,
+        SyncDevicesJob,
+    },
+    user::{BrowserSessionFilter, UserEmailFilter},
+};
+use rand::RngCore;
+use tracing::info;
+use ulid::Ulid;
+
+use crate::audit::schedule_audit_event;
+
+/// Lock a user, and report it to the audit webhook
+///
+/// # Errors
+///
+/// Returns an error if the underlying repository fails
+pub async fn lock_user(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    site_config: &SiteConfig,
+    user: User,
+) -> Result<User, RepositoryError> {
+    let user = repo.user().lock(clock, user).await?;
+
+    schedule_audit_event(
+        repo,
+        rng,
+        clock,
+        site_config,
+        None,
+        AuditEventPayload::account_lock(&user),
+    )
+    .await?;
+
+    Ok(user)
+}
+
+/// Unlock a user
+///
+/// # Errors
+///
+/// Returns an error if the underlying repository fails
+pub async fn unlock_user(repo: &mut BoxRepository, user: User) -> Result<User, RepositoryError> {
+    repo.user().unlock(user).await
+}
+
+/// The number of sessions and tokens which were ended by
+/// [`revoke_all_sessions`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RevokedSessions {
+    /// The number of compatibility sessions which were finished
+    pub compat_sessions: usize,
+
+    /// The number of OAuth 2.0 sessions which were finished
+    pub oauth2_sessions: usize,
+
+    /// The number of browser sessions which were finished
+    pub browser_sessions: usize,
+
+    /// The number of personal sessions acting as the user which were revoked
+    pub personal_sessions: usize,
+
+    /// The number of OAuth 2.0 access tokens which were revoked
+    pub oauth2_access_tokens: usize,
+
+    /// The number of OAuth 2.0 refresh tokens which were revoked
+    pub oauth2_refresh_tokens: usize,
+}
+
+/// Finish all the active sessions of a user, revoke the tokens of its OAuth
+/// 2.0 sessions and revoke the personal sessions acting as the user
+///
+/// The compatibility, OAuth 2.0, browser or personal session with the ID
+/// `except_session_id`, if any, is kept active. A job is scheduled to sync the
+/// user's devices with the homeserver.
+///
+/// # Errors
+///
+/// Returns an error if the underlying repository fails
+pub async fn revoke_all_sessions(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    user: &User,
+    except_session_id: Option<Ulid>,
+) -> Result<RevokedSessions, RepositoryError> {
+    let compat_filter = CompatSessionFilter::new().for_user(user).active_only();
+    let oauth2_filter = OAuth2SessionFilter::new().for_user(user).active_only();
+    let browser_filter = BrowserSessionFilter::new().for_user(user).active_only();
+    let personal_filter = PersonalSessionFilter::new()
+        .for_actor_user(user)
+        .active_only();
+
+    let (compat_filter, oauth2_filter, browser_filter, personal_filter) = match except_session_id {
+        Some(session_id) => (
+            compat_filter.excluding(session_id),
+            oauth2_filter.excluding(session_id),
+            browser_filter.excluding(session_id),
+            personal_filter.excluding(session_id),
+        ),
+        None => (
+            compat_filter,
+            oauth2_filter,
+            browser_filter,
+            personal_filter,
+        ),
+    };
+
+    // Revoke the tokens first, as they are matched on the sessions being active
+    let oauth2_access_tokens = repo
+        .oauth2_access_token()
+        .revoke_bulk(clock, oauth2_filter)
+        .await?;
+    let oauth2_refresh_tokens = repo
+        .oauth2_refresh_token()
+        .revoke_bulk(clock, oauth2_filter)
+        .await?;
+
+    let compat_sessions = repo
+        .compat_session()
+        .finish_bulk(clock, compat_filter)
+        .await?;
+    let oauth2_sessions = repo
+        .oauth2_session()
+        .finish_bulk(clock, oauth2_filter)
+        .await?;
+    let browser_sessions = repo
+        .browser_session()
+        .finish_bulk(clock, browser_filter)
+        .await?;
+    let personal_sessions = repo
+        .personal_session()
+        .revoke_bulk(clock, personal_filter)
+        .await?;
+
+    // Schedule a job to sync the devices of the user with the homeserver
+    repo.queue_job()
+        .schedule_job(rng, clock, SyncDevicesJob::new(user))
+        .await?;
+
+    info!(
+        %user.id,
+        compat_sessions,
+        oauth2_sessions,
+        browser_sessions,
+        personal_sessions,
+        oauth2_access_tokens,
+        oauth2_refresh_tokens,
+        "Revoked all sessions of user"
+    );
+
+    Ok(RevokedSessions {
+        compat_sessions,
+        oauth2_sessions,
+        browser_sessions,
+        personal_sessions,
+        oauth2_access_tokens,
+        oauth2_refresh_tokens,
+    })
+}
+
+/// Mark a user as deleted, finishing all its sessions and revoking the
+/// personal sessions it owns
+///
+/// The user and its data are permanently removed once the retention period is
+/// over.
+///
+/// # Errors
+///
+/// Returns an error if the underlying repository fails
+pub async fn delete_user(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    user: User,
+) -> Result<User, RepositoryError> {
+    let user = repo.user().delete(clock, user).await?;
+
+    // This also schedules a job to remove the devices of the user from the
+    // homeserver
+    revoke_all_sessions(repo, rng, clock, &user, None).await?;
+    repo.personal_session()
+        .revoke_bulk(
+            clock,
+            PersonalSessionFilter::new()
+                .for_owner_user(&user)
+                .active_only(),
+        )
+        .await?;
+
+    Ok(user)
+}
+
+/// Finish the other sessions of a user who just recovered their account,
+/// unless they asked to keep them, and notify them by email
+///
+/// Someone who had access to the account before its password was reset may
+/// still have sessions open, which is why they are ended by default. The
+/// session with the ID `except_session_id`, if any, is the one used for the
+/// recovery and is kept active.
+///
+/// # Errors
+///
+/// Returns an error if the underlying repository fails
+pub async fn end_sessions_after_recovery(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    user: &User,
+    except_session_id: Option<Ulid>,
+    keep_sessions: bool,
+    language: String,
+) -> Result<Option<RevokedSessions>, RepositoryError> {
+    let job = SendAccountRecoveredEmailJob::new(user, language);
+
+    let (job, revoked) = if keep_sessions {
+        info!(%user.id, "Keeping the other sessions of the user after account recovery");
+        (job, None)
+    } else {
+        let revoked = revoke_all_sessions(repo, rng, clock, user, except_session_id).await?;
+        let job = job.with_ended_sessions(EndedSessions {
+            compat_sessions: revoked.compat_sessions,
+            oauth2_sessions: revoked.oauth2_sessions,
+            browser_sessions: revoked.browser_sessions,
+        });
+        (job, Some(revoked))
+    };
+
+    repo.queue_job().schedule_job(rng, clock, job).await?;
+
+    Ok(revoked)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AddEmailError {
+    #[error("Email {email:?} is not valid")]
+    EmailNotValid {
+        email: String,
+
+        #[source]
+        source: lettre::address::AddressError,
+    },
+
+    #[error("User email {0:?} already in use")]
+    EmailAlreadyInUse(String),
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+/// Add an already verified email address to a user, and schedule a job to
+/// provision the user on the homeserver
+///
+/// # Errors
+///
+/// Returns an error if the email address is invalid, if it is already in use,
+/// or if the underlying repository fails
+pub async fn add_email(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    user: &User,
+    email: String,
+) -> Result<UserEmail, AddEmailError> {
+    if let Err(source) = lettre::Address::from_str(&email) {
+        return Err(AddEmailError::EmailNotValid { email, source });
+    }
+
+    let count = repo
+        .user_email()
+        .count(UserEmailFilter::new().for_email(&email))
+        .await?;
+
+    if count > 0 {
+        return Err(AddEmailError::EmailAlreadyInUse(email));
+    }
+
+    let user_email = repo.user_email().add(rng, clock, user, email).await?;
+
+    // Schedule a job to update the user
+    repo.queue_job()
+        .schedule_job(rng, clock, ProvisionUserJob::new_for_id(user.id))
+        .await?;
+
+    Ok(user_email)
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_storage::{
+        RepositoryAccess,
+        user::{BrowserSessionRepository, UserEmailRepository, UserRepository},
+    };
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{TestState, setup};
+
+    async fn add_user(state: &TestState, username: &str) -> User {
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut state.rng(), &state.clock, username.to_owned())
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        user
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_lock_unlock_user(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let user = add_user(&state, "alice").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = lock_user(&mut repo, &mut rng, &state.clock, &state.site_config, user)
+            .await
+            .unwrap();
+        assert!(user.locked_at.is_some());
+        let user = unlock_user(&mut repo, user).await.unwrap();
+        assert!(user.locked_at.is_none());
+        repo.save().await.unwrap();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_revoke_all_sessions(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let user = add_user(&state, "alice").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let kept = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        let ended = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+
+        let revoked = revoke_all_sessions(&mut repo, &mut rng, &state.clock, &user, Some(kept.id))
+            .await
+            .unwrap();
+        assert_eq!(
+            revoked,
+            RevokedSessions {
+                browser_sessions: 1,
+                ..RevokedSessions::default()
+            }
+        );
+
+        let kept = repo
+            .browser_session()
+            .lookup(kept.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(kept.finished_at.is_none());
+        let ended = repo
+            .browser_session()
+            .lookup(ended.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(ended.finished_at.is_some());
+
+        // Without an exception, the remaining session is finished too
+        let revoked = revoke_all_sessions(&mut repo, &mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        assert_eq!(revoked.browser_sessions, 1);
+        repo.save().await.unwrap();
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_add_email(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+        let alice = add_user(&state, "alice").await;
+        let bob = add_user(&state, "bob").await;
+
+        let mut repo = state.repository().await.unwrap();
+        let email = add_email(
+            &mut repo,
+            &mut rng,
+            &state.clock,
+            &alice,
+            "alice@example.com".to_owned(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(email.user_id, alice.id);
+
+        let error = add_email(
+            &mut repo,
+            &mut rng,
+            &state.clock,
+            &bob,
+            "alice@example.com".to_owned(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, AddEmailError::EmailAlreadyInUse(_)));
+
+        let error = add_email(
+            &mut repo,
+            &mut rng,
+            &state.clock,
+            &bob,
+            "not an email".to_owned(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, AddEmailError::EmailNotValid { .. }));
+
+        assert!(repo.user_email().all(&bob).await.unwrap().is_empty());
+        repo.save().await.unwrap();
+    }
+}
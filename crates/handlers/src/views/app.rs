@@ -5,15 +5,18 @@
 // Please see LICENSE files in the repository root for full details.
 
 use axum::{
+    Json,
     extract::State,
     response::{Html, IntoResponse},
 };
 use axum_extra::extract::Query;
 use mas_axum_utils::{InternalError, cookies::CookieJar};
-use mas_data_model::{BoxClock, BoxRng};
+use mas_data_model::{BoxClock, BoxRng, SiteConfig, TchapConfig, TchapFeatures, User};
 use mas_router::{PostAuthAction, UrlBuilder};
-use mas_storage::BoxRepository;
-use mas_templates::{AppContext, TemplateContext, Templates};
+use mas_storage::{BoxRepository, RepositoryAccess, RepositoryError};
+use mas_templates::{
+    AppContext, FrontendConfig, ProfileBadge, SiteConfigExt, TemplateContext, Templates,
+};
 use serde::Deserialize;
 
 use crate::{
@@ -27,12 +30,45 @@ pub struct Params {
     action: Option<mas_router::AccountAction>,
 }
 
+/// Load the configuration exposed to the frontend
+async fn load_frontend_config(
+    repo: &mut BoxRepository,
+    site_config: &SiteConfig,
+    tchap_features: &TchapFeatures,
+) -> Result<FrontendConfig, RepositoryError> {
+    let providers = repo.upstream_oauth_provider().all_enabled().await?;
+    Ok(site_config.frontend_config(&providers, tchap_features))
+}
+
+//:tchap:
+/// Load the badge shown in the profile area of the given user
+async fn load_profile_badge(
+    repo: &mut BoxRepository,
+    tchap_config: &TchapConfig,
+    user: &User,
+) -> Result<Option<ProfileBadge>, RepositoryError> {
+    let emails = repo.user_email().all(user).await?;
+    let email_domain_label = emails
+        .iter()
+        .find_map(|email| tchap_config.email_domain_label(&email.email))
+        .map(ToOwned::to_owned);
+
+    Ok(ProfileBadge::new(
+        user.organization.clone(),
+        email_domain_label,
+    ))
+}
+//:tchap:end
+
 #[tracing::instrument(name = "handlers.views.app.get", skip_all)]
 pub async fn get(
     PreferredLanguage(locale): PreferredLanguage,
     State(templates): State<Templates>,
     activity_tracker: BoundActivityTracker,
     State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    State(tchap_config): State<TchapConfig>,
+    State(tchap_features): State<TchapFeatures>,
     Query(Params { action }): Query<Params>,
     mut repo: BoxRepository,
     clock: BoxClock,
@@ -40,7 +76,13 @@ pub async fn get(
     cookie_jar: CookieJar,
 ) -> Result<impl IntoResponse, InternalError> {
     let (cookie_jar, maybe_session) = match load_session_or_fallback(
-        cookie_jar, &clock, &mut rng, &templates, &locale, &mut repo,
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
     )
     .await?
     {
@@ -67,7 +109,14 @@ pub async fn get(
         .record_browser_session(&clock, &session)
         .await;
 
-    let ctx = AppContext::from_url_builder(&url_builder).with_language(locale);
+    let frontend_config = load_frontend_config(&mut repo, &site_config, &tchap_features).await?;
+    //:tchap:
+    let profile_badge = load_profile_badge(&mut repo, &tchap_config, &session.user).await?;
+    //:tchap:end
+    let ctx = AppContext::from_url_builder(&url_builder, frontend_config)
+        .with_action(action)
+        .with_profile_badge(profile_badge)
+        .with_language(locale);
     let content = templates.render_app(&ctx)?;
 
     Ok((cookie_jar, Html(content)).into_response())
@@ -81,9 +130,140 @@ pub async fn get_anonymous(
     PreferredLanguage(locale): PreferredLanguage,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    State(tchap_features): State<TchapFeatures>,
+    mut repo: BoxRepository,
 ) -> Result<impl IntoResponse, InternalError> {
-    let ctx = AppContext::from_url_builder(&url_builder).with_language(locale);
+    let frontend_config = load_frontend_config(&mut repo, &site_config, &tchap_features).await?;
+    repo.cancel().await?;
+
+    let ctx = AppContext::from_url_builder(&url_builder, frontend_config).with_language(locale);
     let content = templates.render_app(&ctx)?;
 
     Ok(Html(content).into_response())
 }
+
+/// Expose the same configuration as the one embedded in the `app.html`
+/// template, for clients which can't rely on it
+#[tracing::instrument(name = "handlers.views.app.frontend_config", skip_all)]
+pub async fn frontend_config(
+    State(site_config): State<SiteConfig>,
+    State(tchap_features): State<TchapFeatures>,
+    mut repo: BoxRepository,
+) -> Result<impl IntoResponse, InternalError> {
+    let frontend_config = load_frontend_config(&mut repo, &site_config, &tchap_features).await?;
+    repo.cancel().await?;
+
+    Ok(Json(frontend_config))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Request, StatusCode};
+    use mas_data_model::{
+        TchapFeatures, UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderDiscoveryMode,
+        UpstreamOAuthProviderOnBackchannelLogout, UpstreamOAuthProviderPkceMode,
+        UpstreamOAuthProviderTokenAuthMethod,
+    };
+    use mas_iana::jose::JsonWebSignatureAlg;
+    use mas_router::SimpleRoute;
+    use mas_storage::{RepositoryAccess, upstream_oauth2::UpstreamOAuthProviderParams};
+    use oauth2_types::scope::OPENID;
+    use sqlx::PgPool;
+
+    use crate::test_utils::{RequestBuilderExt, ResponseExt, TestState, setup};
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_frontend_config(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let mut rng = state.rng();
+
+        // Add an upstream provider, with a client secret which must not leak
+        let mut repo = state.repository().await.unwrap();
+        let encrypted_client_secret = state.encrypter.encrypt_to_string(b"secret").unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    issuer: Some("https://example.com/".to_owned()),
+                    human_name: Some("Example Ltd.".to_owned()),
+                    brand_name: Some("example".to_owned()),
+                    scope: [OPENID].into_iter().collect(),
+                    token_endpoint_auth_method:
+                        UpstreamOAuthProviderTokenAuthMethod::ClientSecretPost,
+                    token_endpoint_signing_alg: None,
+                    id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                    fetch_userinfo: false,
+                    userinfo_signed_response_alg: None,
+                    client_id: "client".to_owned(),
+                    encrypted_client_secret: Some(encrypted_client_secret),
+                    claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+                    authorization_endpoint_override: None,
+                    token_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    jwks_uri_override: None,
+                    discovery_mode: UpstreamOAuthProviderDiscoveryMode::Oidc,
+                    pkce_mode: UpstreamOAuthProviderPkceMode::Auto,
+                    response_mode: None,
+                    additional_authorization_parameters: Vec::new(),
+                    forward_login_hint: false,
+                    ui_order: 0,
+                    ui_hidden: false,
+                    on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                },
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = Request::get(mas_router::FrontendConfigEndpoint::PATH).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let mut body: serde_json::Value = response.json();
+
+        // The provider ID is random, replace it to get a stable snapshot
+        assert_eq!(body["upstreamProviders"][0]["id"], provider.id.to_string());
+        body["upstreamProviders"][0]["id"] = "[id]".into();
+
+        insta::assert_json_snapshot!(body, @r#"
+        {
+          "passwordLoginEnabled": true,
+          "passwordRegistrationEnabled": true,
+          "passwordChangeAllowed": true,
+          "emailChangeAllowed": true,
+          "accountRecoveryAllowed": true,
+          "upstreamProviders": [
+            {
+              "id": "[id]",
+              "humanName": "Example Ltd.",
+              "brandName": "example"
+            }
+          ],
+          "tchap": true
+        }
+        "#);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_frontend_config_without_tchap(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        state.tchap_features = TchapFeatures {
+            registration_email_gatekeeping: false,
+            upstream_email_gatekeeping: false,
+            upstream_email_matching: false,
+            displayname_suffixing: false,
+            identity_server_lookups: false,
+            organization_claim: false,
+        };
+
+        let request = Request::get(mas_router::FrontendConfigEndpoint::PATH).empty();
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["tchap"], false);
+    }
+}
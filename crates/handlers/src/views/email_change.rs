@@ -0,0 +1,544 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Pages where users confirm or cancel a change of their email address, from
+//! the link sent to their previous address
+
+use axum::{
+    Form,
+    extract::{Path, State},
+    response::{Html, IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use mas_axum_utils::{
+    GenericError, InternalError,
+    cookies::CookieJar,
+    csrf::{CsrfExt as _, ProtectedForm},
+};
+use mas_data_model::{BoxClock, BoxRng, UserEmailChange};
+use mas_storage::{
+    BoxRepository,
+    queue::{ProvisionUserJob, QueueJobRepositoryExt as _},
+    user::UserEmailFilter,
+};
+use mas_templates::{
+    EmailChangeContext, EmailChangeState, ErrorCode, TemplateContext as _, Templates,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{PreferredLanguage, impl_from_error_for_route};
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync>),
+
+    #[error(transparent)]
+    Csrf(#[from] mas_axum_utils::csrf::CsrfError),
+
+    #[error("Email change not found")]
+    ChangeNotFound,
+
+    #[error("User is locked or deactivated")]
+    UserNotAllowed,
+}
+
+impl_from_error_for_route!(mas_templates::TemplateError);
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Internal(e) => InternalError::new(e).into_response(),
+            e @ Self::ChangeNotFound => GenericError::new(StatusCode::NOT_FOUND, e)
+                .with_error_code(ErrorCode::EmailChangeNotFound)
+                .into_response(),
+            e @ Self::UserNotAllowed => GenericError::new(StatusCode::FORBIDDEN, e)
+                .with_error_code(ErrorCode::Forbidden)
+                .into_response(),
+            e @ Self::Csrf(_) => GenericError::new(StatusCode::BAD_REQUEST, e)
+                .with_error_code(ErrorCode::CsrfMismatch)
+                .into_response(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Confirm,
+    Cancel,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct EmailChangeForm {
+    action: Action,
+}
+
+/// The state of the change, as shown to the user
+fn change_state(change: &UserEmailChange, now: DateTime<Utc>) -> EmailChangeState {
+    if change.confirmed_at.is_some() {
+        EmailChangeState::Confirmed
+    } else if change.cancelled_at.is_some() {
+        EmailChangeState::Cancelled
+    } else if change.is_pending(now) {
+        EmailChangeState::Pending
+    } else {
+        EmailChangeState::Expired
+    }
+}
+
+#[tracing::instrument(name = "handlers.views.email_change.get", skip_all)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    Path(ticket): Path<String>,
+) -> Result<Response, RouteError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let change = repo
+        .user_email()
+        .find_change_by_ticket(&ticket)
+        .await?
+        .ok_or(RouteError::ChangeNotFound)?;
+
+    let state = change_state(&change, clock.now());
+    let context = EmailChangeContext::new(change, state)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let rendered = templates.render_email_change(&context)?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.email_change.post", skip_all)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    Path(ticket): Path<String>,
+    Form(form): Form<ProtectedForm<EmailChangeForm>>,
+) -> Result<Response, RouteError> {
+    let form = cookie_jar.verify_form(&clock, form)?;
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let change = repo
+        .user_email()
+        .find_change_by_ticket(&ticket)
+        .await?
+        .ok_or(RouteError::ChangeNotFound)?;
+
+    // Holding the ticket is what proves that the request comes from the
+    // previous address, so there is no need for the user to be logged in
+    let (change, state) = if change.is_pending(clock.now()) {
+        let user = repo
+            .user()
+            .lookup(change.user_id)
+            .await?
+            .ok_or_else(|| RouteError::Internal("User not found".into()))?;
+
+        // The account may have been locked or deactivated since the change was
+        // started
+        if !user.is_valid() {
+            return Err(RouteError::UserNotAllowed);
+        }
+
+        match form.action {
+            Action::Confirm => {
+                // The address may have been claimed by another account since the
+                // change was started
+                let count = repo
+                    .user_email()
+                    .count(UserEmailFilter::new().for_email(&change.email))
+                    .await?;
+
+                if count > 0 {
+                    (change, EmailChangeState::EmailInUse)
+                } else {
+                    repo.user_email()
+                        .add(&mut rng, &clock, &user, change.email.clone())
+                        .await?;
+
+                    let change = repo.user_email().confirm_change(&clock, change).await?;
+
+                    // Schedule a job to update the user
+                    repo.queue_job()
+                        .schedule_job(&mut rng, &clock, ProvisionUserJob::new(&user))
+                        .await?;
+
+                    (change, EmailChangeState::Confirmed)
+                }
+            }
+
+            Action::Cancel => {
+                let change = repo.user_email().cancel_change(&clock, change).await?;
+                (change, EmailChangeState::Cancelled)
+            }
+        }
+    } else {
+        let state = change_state(&change, clock.now());
+        (change, state)
+    };
+
+    repo.save().await?;
+
+    let context = EmailChangeContext::new(change, state)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    let rendered = templates.render_email_change(&context)?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode};
+    use mas_axum_utils::SessionInfoExt;
+    use mas_data_model::{Clock, SiteConfig, User, UserEmailChange};
+    use mas_router::Route as _;
+    use mas_storage::{RepositoryAccess, user::UserEmailFilter};
+    use sqlx::PgPool;
+
+    use crate::test_utils::{
+        CookieHelper, RequestBuilderExt, ResponseExt, TestState, setup, test_site_config,
+    };
+
+    /// Start a change of email address for a new user, from
+    /// `alice@example.com` to `alice@example.org`
+    async fn start_change(state: &TestState) -> (User, UserEmailChange) {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let previous_email = repo
+            .user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        let authentication = repo
+            .user_email()
+            .add_authentication_for_session(
+                &mut rng,
+                &state.clock,
+                "alice@example.org".to_owned(),
+                &browser_session,
+            )
+            .await
+            .unwrap();
+        let change = repo
+            .user_email()
+            .add_change(
+                &mut rng,
+                &state.clock,
+                Duration::hours(24),
+                &authentication,
+                &previous_email,
+                "ticket".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        (user, change)
+    }
+
+    /// Open the link from the email, returning the CSRF token of the form if
+    /// the change can still be confirmed or cancelled
+    async fn open(state: &TestState, cookies: &CookieHelper) -> Option<String> {
+        let path = mas_router::EmailChange::new("ticket".to_owned()).path_and_query();
+        let request = cookies.with_cookies(Request::get(&*path).empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+
+        // Extract the CSRF token from the response body
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)?
+            .split('\"')
+            .next()?;
+        Some(csrf_token.to_owned())
+    }
+
+    /// Submit the form with the given action
+    async fn submit(state: &TestState, cookies: &CookieHelper, csrf_token: &str, action: &str) {
+        let path = mas_router::EmailChange::new("ticket".to_owned()).path_and_query();
+        let request = Request::post(&*path).form(serde_json::json!({
+            "csrf": csrf_token,
+            "action": action,
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+    }
+
+    async fn emails(state: &TestState, user: &User) -> Vec<String> {
+        let mut repo = state.repository().await.unwrap();
+        let mut emails: Vec<String> = repo
+            .user_email()
+            .all(user)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|email| email.email)
+            .collect();
+        emails.sort();
+        emails
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_confirm(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (user, change) = start_change(&state).await;
+
+        // The new address isn't added until the change is confirmed
+        assert_eq!(emails(&state, &user).await, vec!["alice@example.com"]);
+
+        let cookies = CookieHelper::new();
+        let csrf_token = open(&state, &cookies).await.unwrap();
+        submit(&state, &cookies, &csrf_token, "confirm").await;
+
+        assert_eq!(
+            emails(&state, &user).await,
+            vec!["alice@example.com", "alice@example.org"]
+        );
+
+        let mut repo = state.repository().await.unwrap();
+        let change = repo
+            .user_email()
+            .lookup_change(change.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(change.confirmed_at.is_some());
+        assert!(change.cancelled_at.is_none());
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_cancel(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (user, change) = start_change(&state).await;
+
+        let cookies = CookieHelper::new();
+        let csrf_token = open(&state, &cookies).await.unwrap();
+        submit(&state, &cookies, &csrf_token, "cancel").await;
+
+        assert_eq!(emails(&state, &user).await, vec!["alice@example.com"]);
+
+        let mut repo = state.repository().await.unwrap();
+        let change = repo
+            .user_email()
+            .lookup_change(change.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(change.cancelled_at.is_some());
+        assert!(change.confirmed_at.is_none());
+        repo.cancel().await.unwrap();
+
+        // The change can't be confirmed anymore
+        assert!(open(&state, &cookies).await.is_none());
+        submit(&state, &cookies, &csrf_token, "confirm").await;
+        assert_eq!(emails(&state, &user).await, vec!["alice@example.com"]);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_expired(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (user, change) = start_change(&state).await;
+
+        // The link is opened just before it expires, but the form is only
+        // submitted after
+        state.clock.advance(Duration::minutes(23 * 60 + 30));
+        let cookies = CookieHelper::new();
+        let csrf_token = open(&state, &cookies).await.unwrap();
+
+        state.clock.advance(Duration::minutes(30));
+        assert!(open(&state, &cookies).await.is_none());
+        submit(&state, &cookies, &csrf_token, "confirm").await;
+
+        assert_eq!(emails(&state, &user).await, vec!["alice@example.com"]);
+
+        let mut repo = state.repository().await.unwrap();
+        let change = repo
+            .user_email()
+            .lookup_change(change.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(change.confirmed_at.is_none());
+        assert!(!change.is_pending(state.clock.now()));
+
+        let count = repo
+            .user_email()
+            .count(UserEmailFilter::new().for_email("alice@example.org"))
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_locked_user(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (user, change) = start_change(&state).await;
+
+        let cookies = CookieHelper::new();
+        let csrf_token = open(&state, &cookies).await.unwrap();
+
+        // The account gets locked before the change is confirmed
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lock(&state.clock, user).await.unwrap();
+        repo.save().await.unwrap();
+
+        for action in ["confirm", "cancel"] {
+            let path = mas_router::EmailChange::new("ticket".to_owned()).path_and_query();
+            let request = Request::post(&*path).form(serde_json::json!({
+                "csrf": csrf_token,
+                "action": action,
+            }));
+            let request = cookies.with_cookies(request);
+            let response = state.request(request).await;
+            response.assert_status(StatusCode::FORBIDDEN);
+        }
+
+        assert_eq!(emails(&state, &user).await, vec!["alice@example.com"]);
+
+        let mut repo = state.repository().await.unwrap();
+        let change = repo
+            .user_email()
+            .lookup_change(change.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(change.confirmed_at.is_none());
+        assert!(change.cancelled_at.is_none());
+    }
+
+    /// Remove an email address of the user through the GraphQL API, returning
+    /// the status of the mutation
+    async fn remove_email(
+        state: &TestState,
+        cookies: &CookieHelper,
+        user: &User,
+        email: &str,
+    ) -> String {
+        let mut repo = state.repository().await.unwrap();
+        let user_email = repo
+            .user_email()
+            .all(user)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|user_email| user_email.email == email)
+            .unwrap();
+        repo.cancel().await.unwrap();
+
+        let request = Request::post("/graphql").json(serde_json::json!({
+            "query": r"
+                mutation RemoveEmail($id: ID!) {
+                    removeEmail(input: { userEmailId: $id }) {
+                        status
+                    }
+                }
+            ",
+            "variables": {
+                "id": format!("user_email:{}", user_email.id),
+            },
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::OK);
+        let response: serde_json::Value = response.json();
+        response["data"]["removeEmail"]["status"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_remove_last_email(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                email_change_double_confirmation: true,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let (user, _change) = start_change(&state).await;
+
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        let browser_session = repo
+            .browser_session()
+            .add(&mut rng, &state.clock, &user, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let cookies = CookieHelper::new();
+        cookies.import(state.cookie_jar().set_session(&browser_session));
+
+        // Removing the only address would let the user add a new one without
+        // confirming it from the previous address
+        assert_eq!(
+            remove_email(&state, &cookies, &user, "alice@example.com").await,
+            "LAST_EMAIL"
+        );
+        assert_eq!(emails(&state, &user).await, vec!["alice@example.com"]);
+
+        // Once the change is confirmed, the previous address can be removed
+        let csrf_token = open(&state, &cookies).await.unwrap();
+        submit(&state, &cookies, &csrf_token, "confirm").await;
+        assert_eq!(
+            remove_email(&state, &cookies, &user, "alice@example.com").await,
+            "REMOVED"
+        );
+        assert_eq!(emails(&state, &user).await, vec!["alice@example.org"]);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_unknown_ticket(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let path = mas_router::EmailChange::new("unknown".to_owned()).path_and_query();
+        let response = state.request(Request::get(&*path).empty()).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
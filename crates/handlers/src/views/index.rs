@@ -15,7 +15,7 @@ use mas_storage::BoxRepository;
 use mas_templates::{IndexContext, TemplateContext, Templates};
 
 use crate::{
-    BoundActivityTracker,
+    BoundActivityTracker, SiteConfig,
     preferred_language::PreferredLanguage,
     session::{SessionOrFallback, load_session_or_fallback},
 };
@@ -27,12 +27,19 @@ pub async fn get(
     activity_tracker: BoundActivityTracker,
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
     mut repo: BoxRepository,
     cookie_jar: CookieJar,
     PreferredLanguage(locale): PreferredLanguage,
 ) -> Result<Response, InternalError> {
     let (cookie_jar, maybe_session) = match load_session_or_fallback(
-        cookie_jar, &clock, &mut rng, &templates, &locale, &mut repo,
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
     )
     .await?
     {
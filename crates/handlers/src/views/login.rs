@@ -17,10 +17,13 @@ use mas_axum_utils::{
     cookies::CookieJar,
     csrf::{CsrfExt, ProtectedForm},
 };
-use mas_data_model::{BoxClock, BoxRng, Clock, oauth2::LoginHint};
+use mas_data_model::{
+    AuditEventPayload, BoxClock, BoxRng, Clock, LoginFailureOrigin, LoginFailureReason,
+    oauth2::LoginHint,
+};
 use mas_i18n::DataLocale;
 use mas_matrix::HomeserverConnection;
-use mas_router::{UpstreamOAuth2Authorize, UrlBuilder};
+use mas_router::{Reauth, UpstreamOAuth2Authorize, UrlBuilder};
 use mas_storage::{
     BoxRepository, RepositoryAccess,
     upstream_oauth2::UpstreamOAuthProviderRepository,
@@ -38,6 +41,8 @@ use zeroize::Zeroizing;
 use super::shared::OptionalPostAuthAction;
 use crate::{
     BoundActivityTracker, Limiter, METER, PreferredLanguage, RequesterFingerprint, SiteConfig,
+    audit::schedule_audit_event,
+    login_failures::add_login_failure,
     passwords::{PasswordManager, PasswordVerificationResult},
     session::{SessionOrFallback, load_session_or_fallback},
 };
@@ -55,12 +60,34 @@ const RESULT: Key = Key::from_static_str("result");
 pub(crate) struct LoginForm {
     username: String,
     password: String,
+
+    /// Set if the user confirmed they want to cancel the scheduled
+    /// deactivation of their account
+    #[serde(default)]
+    reactivate: Option<String>,
 }
 
 impl ToFormState for LoginForm {
     type Field = LoginFormField;
 }
 
+#[derive(Debug, Deserialize)]
+pub(crate) struct LoginQuery {
+    /// Set to `1` to show the login page even if the user would otherwise be
+    /// sent straight to the only upstream provider
+    #[serde(default)]
+    no_redirect: Option<String>,
+
+    #[serde(flatten)]
+    action: OptionalPostAuthAction,
+}
+
+impl LoginQuery {
+    fn no_redirect(&self) -> bool {
+        matches!(self.no_redirect.as_deref(), Some("1" | "true"))
+    }
+}
+
 #[tracing::instrument(name = "handlers.views.login.get", skip_all)]
 pub(crate) async fn get(
     mut rng: BoxRng,
@@ -72,22 +99,38 @@ pub(crate) async fn get(
     State(homeserver): State<Arc<dyn HomeserverConnection>>,
     mut repo: BoxRepository,
     activity_tracker: BoundActivityTracker,
-    Query(query): Query<OptionalPostAuthAction>,
+    Query(query): Query<LoginQuery>,
     cookie_jar: CookieJar,
 ) -> Result<Response, InternalError> {
-    let (cookie_jar, maybe_session) = match load_session_or_fallback(
-        cookie_jar, &clock, &mut rng, &templates, &locale, &mut repo,
+    let no_redirect = query.no_redirect();
+    let query = query.action;
+    let (cookie_jar, maybe_session, stale_session) = match load_session_or_fallback(
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
     )
     .await?
     {
         SessionOrFallback::MaybeSession {
             cookie_jar,
             maybe_session,
-            ..
-        } => (cookie_jar, maybe_session),
+            stale_session,
+        } => (cookie_jar, maybe_session, stale_session),
         SessionOrFallback::Fallback { response } => return Ok(response),
     };
 
+    if stale_session.is_some() {
+        // The session has to be re-authenticated: send the user to the
+        // re-authentication prompt, which upgrades the existing session instead
+        // of starting a new one
+        let destination = Reauth::from(query.post_auth_action);
+        return Ok((cookie_jar, url_builder.redirect(&destination)).into_response());
+    }
+
     if let Some(session) = maybe_session {
         activity_tracker
             .record_browser_session(&clock, &session)
@@ -97,11 +140,19 @@ pub(crate) async fn get(
         return Ok((cookie_jar, reply).into_response());
     }
 
-    let providers = repo.upstream_oauth_provider().all_enabled().await?;
-
-    // If password-based login is disabled, and there is only one upstream provider,
-    // we can directly start an authorization flow
-    if !site_config.password_login_enabled && providers.len() == 1 {
+    let mut providers = repo.upstream_oauth_provider().all_enabled().await?;
+    providers.retain(|provider| !provider.ui_hidden);
+
+    // If there is only one upstream provider shown, and password-based login is
+    // either disabled or not preferred, we can directly start an authorization
+    // flow
+    let password_login_preferred =
+        site_config.password_login_enabled && site_config.prefer_password_login;
+    if site_config.auto_redirect_single_provider
+        && !no_redirect
+        && !password_login_preferred
+        && providers.len() == 1
+    {
         let provider = providers.into_iter().next().unwrap();
 
         let mut destination = UpstreamOAuth2Authorize::new(provider.id);
@@ -213,9 +264,20 @@ pub(crate) async fn post(
     // Check the rate limit
     if let Err(e) = limiter.check_password(requester, &user) {
         tracing::warn!(error = &e as &dyn std::error::Error, "ratelimit exceeded");
+        add_login_failure(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &site_config,
+            &user,
+            LoginFailureOrigin::Interactive,
+            LoginFailureReason::RateLimited,
+            requester,
+        )
+        .await?;
         let form_state = form_state.with_error_on_form(FormError::RateLimitExceeded);
         PASSWORD_LOGIN_COUNTER.add(1, &[KeyValue::new(RESULT, "error")]);
-        return render(
+        let response = render(
             locale,
             cookie_jar,
             form_state,
@@ -227,7 +289,9 @@ pub(crate) async fn post(
             &homeserver,
             &site_config,
         )
-        .await;
+        .await?;
+        repo.save().await?;
+        return Ok(response);
     }
 
     // And its password
@@ -235,9 +299,20 @@ pub(crate) async fn post(
         // There is no password for this user, but we don't want to disclose that. Show
         // a generic 'invalid credentials' error instead
         tracing::warn!(username, "No password for user");
+        add_login_failure(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &site_config,
+            &user,
+            LoginFailureOrigin::Interactive,
+            LoginFailureReason::NoPassword,
+            requester,
+        )
+        .await?;
         let form_state = form_state.with_error_on_form(FormError::InvalidCredentials);
         PASSWORD_LOGIN_COUNTER.add(1, &[KeyValue::new(RESULT, "error")]);
-        return render(
+        let response = render(
             locale,
             cookie_jar,
             form_state,
@@ -249,7 +324,9 @@ pub(crate) async fn post(
             &homeserver,
             &site_config,
         )
-        .await;
+        .await?;
+        repo.save().await?;
+        return Ok(response);
     };
 
     let password = Zeroizing::new(form.password);
@@ -280,9 +357,20 @@ pub(crate) async fn post(
         Ok(PasswordVerificationResult::Success(None)) => user_password,
         Ok(PasswordVerificationResult::Failure) => {
             tracing::warn!(username, "Failed to verify/upgrade password for user");
+            add_login_failure(
+                &mut repo,
+                &mut rng,
+                &clock,
+                &site_config,
+                &user,
+                LoginFailureOrigin::Interactive,
+                LoginFailureReason::PasswordMismatch,
+                requester,
+            )
+            .await?;
             let form_state = form_state.with_error_on_form(FormError::InvalidCredentials);
             PASSWORD_LOGIN_COUNTER.add(1, &[KeyValue::new(RESULT, "mismatch")]);
-            return render(
+            let response = render(
                 locale,
                 cookie_jar,
                 form_state,
@@ -294,7 +382,9 @@ pub(crate) async fn post(
                 &homeserver,
                 &site_config,
             )
-            .await;
+            .await?;
+            repo.save().await?;
+            return Ok(response);
         }
         Err(err) => return Err(InternalError::from_anyhow(err)),
     };
@@ -303,23 +393,76 @@ pub(crate) async fn post(
     // the user is locked or deactivated
     if user.deactivated_at.is_some() {
         tracing::warn!(username, "User is deactivated");
+        add_login_failure(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &site_config,
+            &user,
+            LoginFailureOrigin::Interactive,
+            LoginFailureReason::UserDeactivated,
+            requester,
+        )
+        .await?;
         PASSWORD_LOGIN_COUNTER.add(1, &[KeyValue::new(RESULT, "error")]);
         let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
         let ctx = AccountInactiveContext::new(user)
             .with_csrf(csrf_token.form_value())
             .with_language(locale);
         let content = templates.render_account_deactivated(&ctx)?;
+        repo.save().await?;
         return Ok((cookie_jar, Html(content)).into_response());
     }
 
+    // Users who asked for their account to be deactivated are locked until the
+    // grace period is over, but they can still cancel it by signing in again
+    let user = if user.locked_at.is_some() && user.deactivation_scheduled_at.is_some() {
+        if form.reactivate.is_none() {
+            tracing::info!(username, "User has a scheduled deactivation");
+            PASSWORD_LOGIN_COUNTER.add(1, &[KeyValue::new(RESULT, "error")]);
+            let form_state = form_state.with_error_on_form(FormError::DeactivationScheduled);
+            return render(
+                locale,
+                cookie_jar,
+                form_state,
+                query,
+                &mut repo,
+                &clock,
+                &mut rng,
+                &templates,
+                &homeserver,
+                &site_config,
+            )
+            .await;
+        }
+
+        tracing::info!(username, "User cancelled their scheduled deactivation");
+        let user = repo.user().unschedule_deactivation(user).await?;
+        repo.user().unlock(user).await?
+    } else {
+        user
+    };
+
     if user.locked_at.is_some() {
         tracing::warn!(username, "User is locked");
+        add_login_failure(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &site_config,
+            &user,
+            LoginFailureOrigin::Interactive,
+            LoginFailureReason::UserLocked,
+            requester,
+        )
+        .await?;
         PASSWORD_LOGIN_COUNTER.add(1, &[KeyValue::new(RESULT, "error")]);
         let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
         let ctx = AccountInactiveContext::new(user)
             .with_csrf(csrf_token.form_value())
             .with_language(locale);
         let content = templates.render_account_locked(&ctx)?;
+        repo.save().await?;
         return Ok((cookie_jar, Html(content)).into_response());
     }
 
@@ -338,6 +481,16 @@ pub(crate) async fn post(
         .authenticate_with_password(&mut rng, &clock, &user_session, &user_password)
         .await?;
 
+    schedule_audit_event(
+        &mut repo,
+        &mut rng,
+        &clock,
+        &site_config,
+        requester.ip(),
+        AuditEventPayload::login_success(&user, LoginFailureOrigin::Interactive, user_session.id),
+    )
+    .await?;
+
     repo.save().await?;
 
     PASSWORD_LOGIN_COUNTER.add(1, &[KeyValue::new(RESULT, "success")]);
@@ -346,7 +499,7 @@ pub(crate) async fn post(
         .record_browser_session(&clock, &user_session)
         .await;
 
-    let cookie_jar = cookie_jar.set_session(&user_session);
+    let cookie_jar = cookie_jar.set_authenticated_session(&user_session, &clock, &mut rng);
     let reply = query.go_next(&url_builder);
     Ok((cookie_jar, reply).into_response())
 }
@@ -413,7 +566,8 @@ async fn render(
     site_config: &SiteConfig,
 ) -> Result<Response, InternalError> {
     let (csrf_token, cookie_jar) = cookie_jar.csrf_token(clock, rng);
-    let providers = repo.upstream_oauth_provider().all_enabled().await?;
+    let mut providers = repo.upstream_oauth_provider().all_enabled().await?;
+    providers.retain(|provider| !provider.ui_hidden);
 
     let ctx = LoginContext::default()
         .with_form_state(form_state)
@@ -437,18 +591,20 @@ async fn render(
 
 #[cfg(test)]
 mod test {
+    use chrono::Duration;
     use hyper::{
         Request, StatusCode,
         header::{CONTENT_TYPE, LOCATION},
     };
     use mas_data_model::{
-        UpstreamOAuthProviderClaimsImports, UpstreamOAuthProviderOnBackchannelLogout,
-        UpstreamOAuthProviderTokenAuthMethod,
+        Clock, LoginFailureReason, UpstreamOAuthProviderClaimsImports,
+        UpstreamOAuthProviderOnBackchannelLogout, UpstreamOAuthProviderTokenAuthMethod,
     };
     use mas_iana::jose::JsonWebSignatureAlg;
     use mas_router::Route;
     use mas_storage::{
         RepositoryAccess,
+        queue::{FinishScheduledDeactivationJob, QueueJobRepositoryExt as _},
         upstream_oauth2::{UpstreamOAuthProviderParams, UpstreamOAuthProviderRepository},
     };
     use mas_templates::escape_html;
@@ -519,6 +675,7 @@ mod test {
                     additional_authorization_parameters: Vec::new(),
                     forward_login_hint: false,
                     ui_order: 0,
+                    ui_hidden: false,
                     on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                 },
             )
@@ -562,6 +719,7 @@ mod test {
                     additional_authorization_parameters: Vec::new(),
                     forward_login_hint: false,
                     ui_order: 1,
+                    ui_hidden: false,
                     on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                 },
             )
@@ -588,6 +746,137 @@ mod test {
         );
     }
 
+    async fn add_provider(
+        state: &TestState,
+        human_name: &str,
+        ui_order: i32,
+        ui_hidden: bool,
+    ) -> mas_data_model::UpstreamOAuthProvider {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &state.clock,
+                UpstreamOAuthProviderParams {
+                    issuer: Some(format!("https://{}.com/", human_name.to_lowercase())),
+                    human_name: Some(human_name.to_owned()),
+                    brand_name: None,
+                    scope: [OPENID].into_iter().collect(),
+                    token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                    token_endpoint_signing_alg: None,
+                    id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                    fetch_userinfo: false,
+                    userinfo_signed_response_alg: None,
+                    client_id: "client".to_owned(),
+                    encrypted_client_secret: None,
+                    claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+                    authorization_endpoint_override: None,
+                    token_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    jwks_uri_override: None,
+                    discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
+                    pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
+                    response_mode: None,
+                    additional_authorization_parameters: Vec::new(),
+                    forward_login_hint: false,
+                    ui_order,
+                    ui_hidden,
+                    on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                },
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        provider
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_provider_order_and_visibility(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+
+        let charlie = add_provider(&state, "Charlie", 2, false).await;
+        let alpha = add_provider(&state, "Alpha", 0, false).await;
+        let bravo = add_provider(&state, "Bravo", 1, true).await;
+
+        let response = state.request(Request::get("/login").empty()).await;
+        response.assert_status(StatusCode::OK);
+        let body = response.body();
+
+        // Providers are listed by their UI order, regardless of when they were added
+        let alpha_login = mas_router::UpstreamOAuth2Authorize::new(alpha.id);
+        let charlie_login = mas_router::UpstreamOAuth2Authorize::new(charlie.id);
+        let alpha_position = body
+            .find(&escape_html(&alpha_login.path_and_query()))
+            .unwrap();
+        let charlie_position = body
+            .find(&escape_html(&charlie_login.path_and_query()))
+            .unwrap();
+        assert!(alpha_position < charlie_position);
+
+        // Hidden providers are not listed at all
+        let bravo_login = mas_router::UpstreamOAuth2Authorize::new(bravo.id);
+        assert!(!body.contains("Bravo"));
+        assert!(!body.contains(&escape_html(&bravo_login.path_and_query())));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_auto_redirect_single_provider(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool.clone()).await.unwrap();
+
+        // A hidden provider doesn't count towards the number of providers shown
+        let provider = add_provider(&state, "First", 0, false).await;
+        add_provider(&state, "Hidden", 1, true).await;
+        let provider_login = mas_router::UpstreamOAuth2Authorize::new(provider.id);
+
+        // Password login is enabled and preferred, so we don't redirect
+        let response = state.request(Request::get("/login").empty()).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("First"));
+        drop(state);
+
+        // Once password login isn't preferred anymore, we redirect to the provider
+        let state = TestState::from_pool_with_site_config(
+            pool.clone(),
+            SiteConfig {
+                prefer_password_login: false,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let response = state.request(Request::get("/login").empty()).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, &provider_login.path_and_query());
+
+        // ...unless explicitly asked not to
+        let response = state
+            .request(Request::get("/login?no_redirect=1").empty())
+            .await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("First"));
+        drop(state);
+
+        // Disabling the auto-redirect shows the login page even without password
+        // login
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                password_login_enabled: false,
+                auto_redirect_single_provider: false,
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let response = state.request(Request::get("/login").empty()).await;
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("First"));
+    }
+
     async fn user_with_password(
         state: &TestState,
         username: &str,
@@ -658,6 +947,33 @@ mod test {
         response.assert_status(StatusCode::OK);
         response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
         assert!(response.body().contains("john"));
+
+        // The CSRF token was rotated on login
+        let new_csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap();
+        assert_ne!(new_csrf_token, csrf_token);
+
+        // So the token from before the login can't be used anymore
+        let request = Request::post("/logout").form(serde_json::json!({
+            "csrf": csrf_token,
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        assert!(!response.status().is_redirection());
+
+        // While the new one can
+        let request = Request::post("/logout").form(serde_json::json!({
+            "csrf": new_csrf_token,
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        response.assert_status(StatusCode::SEE_OTHER);
     }
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
@@ -873,6 +1189,20 @@ mod test {
         response.assert_header_value(CONTENT_TYPE, "text/html; charset=utf-8");
         assert!(!response.body().contains("Account locked"));
         assert!(response.body().contains("Invalid credentials"));
+
+        // Both failures should have been recorded
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().find_by_username("john").await.unwrap().unwrap();
+        let failures = repo
+            .user_login_failure()
+            .list_recent(&user, 10)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+        let reasons: Vec<_> = failures.iter().map(|f| f.reason).collect();
+        assert_eq!(reasons.len(), 2);
+        assert!(reasons.contains(&LoginFailureReason::PasswordMismatch));
+        assert!(reasons.contains(&LoginFailureReason::UserLocked));
     }
 
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
@@ -933,4 +1263,98 @@ mod test {
         assert!(!response.body().contains("Account deleted"));
         assert!(response.body().contains("Invalid credentials"));
     }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_password_login_cancel_scheduled_deactivation(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let cookies = CookieHelper::new();
+        let mut rng = state.rng();
+
+        // Provision a user with a password, who asked for their account to be
+        // deactivated
+        let user = user_with_password(&state, "john", "hunter2").await;
+        let scheduled_at = state.clock.now() + Duration::try_days(7).unwrap();
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lock(&state.clock, user).await.unwrap();
+        let user = repo
+            .user()
+            .schedule_deactivation(user, scheduled_at)
+            .await
+            .unwrap();
+        repo.queue_job()
+            .schedule_job_later(
+                &mut rng,
+                &state.clock,
+                FinishScheduledDeactivationJob::new(&user, false),
+                scheduled_at,
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        state.clock.advance(Duration::try_days(1).unwrap());
+
+        // Render the login page to get a CSRF token
+        let request = Request::get("/login").empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap();
+
+        // Without confirming the reactivation, the user is asked to do so
+        let request = Request::post("/login").form(serde_json::json!({
+            "csrf": csrf_token,
+            "username": "john",
+            "password": "hunter2",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains(r#"name="reactivate""#));
+        assert!(!response.body().contains("Account locked"));
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        repo.save().await.unwrap();
+        assert!(user.locked_at.is_some());
+        assert_eq!(user.deactivation_scheduled_at, Some(scheduled_at));
+
+        // Confirming it cancels the deactivation and logs the user in
+        let request = Request::post("/login").form(serde_json::json!({
+            "csrf": csrf_token,
+            "username": "john",
+            "password": "hunter2",
+            "reactivate": "on",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        repo.save().await.unwrap();
+        assert!(user.locked_at.is_none());
+        assert!(user.deactivation_scheduled_at.is_none());
+
+        // Once the grace period is over, the user is left alone
+        state.clock.advance(Duration::try_days(7).unwrap());
+        state.run_jobs_in_queue().await;
+        state.run_jobs_in_queue().await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo.user().lookup(user.id).await.unwrap().unwrap();
+        repo.save().await.unwrap();
+        assert!(user.is_valid());
+    }
 }
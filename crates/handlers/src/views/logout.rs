@@ -13,18 +13,20 @@ use mas_axum_utils::{
     cookies::CookieJar,
     csrf::{CsrfExt, ProtectedForm},
 };
-use mas_data_model::BoxClock;
+use mas_data_model::{AuditEventPayload, AuditSessionType, BoxClock, BoxRng, SiteConfig};
 use mas_router::{PostAuthAction, UrlBuilder};
 use mas_storage::{BoxRepository, user::BrowserSessionRepository};
 
-use crate::BoundActivityTracker;
+use crate::{BoundActivityTracker, audit::schedule_audit_event};
 
 #[tracing::instrument(name = "handlers.views.logout.post", skip_all)]
 pub(crate) async fn post(
     clock: BoxClock,
+    mut rng: BoxRng,
     mut repo: BoxRepository,
     cookie_jar: CookieJar,
     State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
     activity_tracker: BoundActivityTracker,
     Form(form): Form<ProtectedForm<Option<PostAuthAction>>>,
 ) -> Result<impl IntoResponse, InternalError> {
@@ -41,7 +43,21 @@ pub(crate) async fn post(
                 .record_browser_session(&clock, &session)
                 .await;
 
-            repo.browser_session().finish(&clock, session).await?;
+            let session = repo.browser_session().finish(&clock, session).await?;
+
+            schedule_audit_event(
+                &mut repo,
+                &mut rng,
+                &clock,
+                &site_config,
+                activity_tracker.ip(),
+                AuditEventPayload::session_end(
+                    session.user.id,
+                    session.id,
+                    AuditSessionType::Browser,
+                ),
+            )
+            .await?;
         }
     }
 
@@ -5,9 +5,12 @@
 // Please see LICENSE files in the repository root for full details.
 
 pub mod app;
+pub mod email_change;
 pub mod index;
 pub mod login;
 pub mod logout;
+pub mod reauth;
 pub mod recovery;
 pub mod register;
+pub mod revoke_session;
 pub mod shared;
@@ -0,0 +1,588 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Re-authentication prompt, shown when a browser session reached its idle
+//! timeout or its maximum lifetime, or before performing a sensitive action.
+//!
+//! Unlike the login page, this never starts a new browser session: it adds a
+//! new authentication to the existing one.
+
+use axum::{
+    extract::{Form, State},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::Query;
+use hyper::StatusCode;
+use mas_axum_utils::{
+    InternalError, SessionInfoExt,
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+};
+use mas_data_model::{
+    BoxClock, BoxRng, Clock, LoginFailureOrigin, LoginFailureReason, UpstreamOAuthProvider, User,
+};
+use mas_i18n::DataLocale;
+use mas_router::{Login, UpstreamOAuth2Authorize, UrlBuilder};
+use mas_storage::{
+    BoxRepository, BoxRepositoryFactory, RepositoryAccess,
+    upstream_oauth2::{
+        UpstreamOAuthLinkFilter, UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
+    },
+    user::{BrowserSessionRepository, UserPasswordRepository},
+};
+use mas_templates::{
+    FieldError, FormError, FormState, ReauthContext, ReauthFormField, TemplateContext, Templates,
+    ToFormState,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use super::shared::OptionalPostAuthAction;
+use crate::{
+    BoundActivityTracker, Limiter, PreferredLanguage, RequesterFingerprint, SiteConfig,
+    login_failures::record_login_failure,
+    passwords::{PasswordManager, PasswordVerificationResult},
+    session::{SessionOrFallback, load_session_or_fallback},
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct ReauthForm {
+    password: String,
+}
+
+impl ToFormState for ReauthForm {
+    type Field = ReauthFormField;
+}
+
+/// Get the enabled upstream providers the user has a link with, with which
+/// they can re-authenticate
+async fn linked_providers(
+    repo: &mut impl RepositoryAccess,
+    user: &User,
+) -> Result<Vec<UpstreamOAuthProvider>, InternalError> {
+    let providers = repo.upstream_oauth_provider().all_enabled().await?;
+
+    let mut linked = Vec::with_capacity(providers.len());
+    for provider in providers {
+        let filter = UpstreamOAuthLinkFilter::new()
+            .for_user(user)
+            .for_provider(&provider);
+        if repo.upstream_oauth_link().count(filter).await? > 0 {
+            linked.push(provider);
+        }
+    }
+
+    Ok(linked)
+}
+
+#[tracing::instrument(name = "handlers.views.reauth.get", skip_all)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    State(site_config): State<SiteConfig>,
+    mut repo: BoxRepository,
+    Query(query): Query<OptionalPostAuthAction>,
+    cookie_jar: CookieJar,
+) -> Result<Response, InternalError> {
+    let (cookie_jar, maybe_session, stale_session) = match load_session_or_fallback(
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
+    )
+    .await?
+    {
+        SessionOrFallback::MaybeSession {
+            cookie_jar,
+            maybe_session,
+            stale_session,
+        } => (cookie_jar, maybe_session, stale_session),
+        SessionOrFallback::Fallback { response } => return Ok(response),
+    };
+
+    // Sessions which are still fresh can also be re-authenticated, before a
+    // sensitive action
+    let Some(session) = stale_session.or(maybe_session) else {
+        // If there is no session at all, this is a regular login
+        let login = Login::from(query.post_auth_action);
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    let providers = linked_providers(&mut repo, &session.user).await?;
+
+    // If password-based login is disabled, and the user is linked to only one
+    // upstream provider, we can directly start an authorization flow. The
+    // callback of the upstream provider then re-authenticates the current session
+    if !site_config.password_login_enabled && providers.len() == 1 {
+        let provider = providers.into_iter().next().unwrap();
+
+        let mut destination = UpstreamOAuth2Authorize::new(provider.id);
+
+        if let Some(action) = query.post_auth_action {
+            destination = destination.and_then(action);
+        }
+
+        return Ok((cookie_jar, url_builder.redirect(&destination)).into_response());
+    }
+
+    render(
+        locale,
+        cookie_jar,
+        FormState::default(),
+        query,
+        session.user,
+        &mut repo,
+        &clock,
+        &mut rng,
+        &templates,
+    )
+    .await
+}
+
+#[tracing::instrument(name = "handlers.views.reauth.post", skip_all)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    PreferredLanguage(locale): PreferredLanguage,
+    State(password_manager): State<PasswordManager>,
+    State(site_config): State<SiteConfig>,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    State(limiter): State<Limiter>,
+    State(repository_factory): State<BoxRepositoryFactory>,
+    activity_tracker: BoundActivityTracker,
+    requester: RequesterFingerprint,
+    Query(query): Query<OptionalPostAuthAction>,
+    cookie_jar: CookieJar,
+    Form(form): Form<ProtectedForm<ReauthForm>>,
+) -> Result<Response, InternalError> {
+    if !site_config.password_login_enabled {
+        return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
+    }
+
+    // We need the factory to record login failures outside of the request
+    // transaction, so we create the repository ourselves
+    let mut repo = repository_factory.create().await?;
+
+    let form = cookie_jar.verify_form(&clock, form)?;
+
+    let (cookie_jar, maybe_session, stale_session) = match load_session_or_fallback(
+        cookie_jar,
+        &clock,
+        &mut rng,
+        &templates,
+        &locale,
+        &site_config,
+        &mut repo,
+    )
+    .await?
+    {
+        SessionOrFallback::MaybeSession {
+            cookie_jar,
+            maybe_session,
+            stale_session,
+        } => (cookie_jar, maybe_session, stale_session),
+        SessionOrFallback::Fallback { response } => return Ok(response),
+    };
+
+    let Some(session) = stale_session.or(maybe_session) else {
+        let login = Login::from(query.post_auth_action);
+        return Ok((cookie_jar, url_builder.redirect(&login)).into_response());
+    };
+
+    let mut form_state = form.to_form_state();
+
+    if form.password.is_empty() {
+        form_state.add_error_on_field(ReauthFormField::Password, FieldError::Required);
+        return render(
+            locale,
+            cookie_jar,
+            form_state,
+            query,
+            session.user,
+            &mut repo,
+            &clock,
+            &mut rng,
+            &templates,
+        )
+        .await;
+    }
+
+    // Check the rate limit
+    if let Err(e) = limiter.check_password(requester, &session.user) {
+        tracing::warn!(error = &e as &dyn std::error::Error, "ratelimit exceeded");
+        record_login_failure(
+            &repository_factory,
+            &mut rng,
+            &clock,
+            &site_config,
+            &session.user,
+            LoginFailureOrigin::Interactive,
+            LoginFailureReason::RateLimited,
+            requester,
+        )
+        .await;
+        let form_state = form_state.with_error_on_form(FormError::RateLimitExceeded);
+        return render(
+            locale,
+            cookie_jar,
+            form_state,
+            query,
+            session.user,
+            &mut repo,
+            &clock,
+            &mut rng,
+            &templates,
+        )
+        .await;
+    }
+
+    let Some(user_password) = repo.user_password().active(&session.user).await? else {
+        tracing::warn!(user.id = %session.user.id, "No password for user");
+        record_login_failure(
+            &repository_factory,
+            &mut rng,
+            &clock,
+            &site_config,
+            &session.user,
+            LoginFailureOrigin::Interactive,
+            LoginFailureReason::NoPassword,
+            requester,
+        )
+        .await;
+        let form_state = form_state.with_error_on_form(FormError::InvalidCredentials);
+        return render(
+            locale,
+            cookie_jar,
+            form_state,
+            query,
+            session.user,
+            &mut repo,
+            &clock,
+            &mut rng,
+            &templates,
+        )
+        .await;
+    };
+
+    let password = Zeroizing::new(form.password);
+
+    // Verify the password, and upgrade it on-the-fly if needed
+    let user_password = match password_manager
+        .verify_and_upgrade(
+            &mut rng,
+            user_password.version,
+            password,
+            user_password.hashed_password.clone(),
+        )
+        .await
+    {
+        Ok(PasswordVerificationResult::Success(Some((version, new_password_hash)))) => {
+            repo.user_password()
+                .add(
+                    &mut rng,
+                    &clock,
+                    &session.user,
+                    version,
+                    new_password_hash,
+                    Some(&user_password),
+                )
+                .await?
+        }
+        Ok(PasswordVerificationResult::Success(None)) => user_password,
+        Ok(PasswordVerificationResult::Failure) => {
+            tracing::warn!(user.id = %session.user.id, "Failed to verify password for user");
+            record_login_failure(
+                &repository_factory,
+                &mut rng,
+                &clock,
+                &site_config,
+                &session.user,
+                LoginFailureOrigin::Interactive,
+                LoginFailureReason::PasswordMismatch,
+                requester,
+            )
+            .await;
+            let form_state = form_state.with_error_on_form(FormError::InvalidCredentials);
+            return render(
+                locale,
+                cookie_jar,
+                form_state,
+                query,
+                session.user,
+                &mut repo,
+                &clock,
+                &mut rng,
+                &templates,
+            )
+            .await;
+        }
+        Err(err) => return Err(InternalError::from_anyhow(err)),
+    };
+
+    // Mark the existing session as authenticated again
+    repo.browser_session()
+        .authenticate_with_password(&mut rng, &clock, &session, &user_password)
+        .await?;
+
+    repo.save().await?;
+
+    activity_tracker
+        .record_browser_session(&clock, &session)
+        .await;
+
+    // The session was upgraded, so rotate the CSRF token
+    let cookie_jar = cookie_jar.set_authenticated_session(&session, &clock, &mut rng);
+
+    let reply = query.go_next(&url_builder);
+    Ok((cookie_jar, reply).into_response())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn render(
+    locale: DataLocale,
+    cookie_jar: CookieJar,
+    form_state: FormState<ReauthFormField>,
+    action: OptionalPostAuthAction,
+    user: User,
+    repo: &mut impl RepositoryAccess,
+    clock: &impl Clock,
+    rng: impl Rng,
+    templates: &Templates,
+) -> Result<Response, InternalError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(clock, rng);
+    let providers = linked_providers(repo, &user).await?;
+
+    let ctx = ReauthContext::new(user)
+        .with_form_state(form_state)
+        .with_upstream_providers(providers);
+
+    let next = action
+        .load_context(repo)
+        .await
+        .map_err(InternalError::from_anyhow)?;
+    let ctx = if let Some(next) = next {
+        ctx.with_post_action(next)
+    } else {
+        ctx
+    };
+    let ctx = ctx.with_csrf(csrf_token.form_value()).with_language(locale);
+
+    let content = templates.render_reauth(&ctx)?;
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Duration;
+    use hyper::{Request, StatusCode, header::LOCATION};
+    use mas_data_model::BrowserSessionLifetimeConfig;
+    use mas_storage::{RepositoryAccess, user::BrowserSessionFilter};
+    use sqlx::PgPool;
+    use zeroize::Zeroizing;
+
+    use crate::{
+        SiteConfig,
+        test_utils::{
+            CookieHelper, RequestBuilderExt, ResponseExt, TestState, setup, test_site_config,
+        },
+    };
+
+    fn extract_csrf_token(body: &str) -> String {
+        body.split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap()
+            .to_owned()
+    }
+
+    /// Provision a user with a password and log them in
+    async fn log_in(state: &TestState, cookies: &CookieHelper) {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "john".to_owned())
+            .await
+            .unwrap();
+        let (version, hash) = state
+            .password_manager
+            .hash(&mut rng, Zeroizing::new("hunter2".to_owned()))
+            .await
+            .unwrap();
+        repo.user_password()
+            .add(&mut rng, &state.clock, &user, version, hash, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let request = cookies.with_cookies(Request::get("/login").empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = extract_csrf_token(response.body());
+
+        let request = Request::post("/login").form(serde_json::json!({
+            "csrf": csrf_token,
+            "username": "john",
+            "password": "hunter2",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+    }
+
+    /// Check where the login page sends a user wanting to change their
+    /// password: straight to the password change page if the session can be
+    /// used, to the re-authentication prompt otherwise
+    async fn login_destination(state: &TestState, cookies: &CookieHelper) -> String {
+        let request = Request::get("/login?kind=change_password").empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.headers()[LOCATION].to_str().unwrap().to_owned()
+    }
+
+    /// Go through the re-authentication prompt, and check that it upgraded
+    /// the existing session
+    async fn reauthenticate(state: &TestState, cookies: &CookieHelper) {
+        let request = Request::get("/reauth?kind=change_password").empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        assert!(response.body().contains("john"));
+        let csrf_token = extract_csrf_token(response.body());
+
+        // A wrong password shows the prompt again
+        let request = Request::post("/reauth?kind=change_password").form(serde_json::json!({
+            "csrf": csrf_token,
+            "password": "wrong",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = extract_csrf_token(response.body());
+
+        let request = Request::post("/reauth?kind=change_password").form(serde_json::json!({
+            "csrf": csrf_token,
+            "password": "hunter2",
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header_value(LOCATION, "/account/password/change");
+
+        // No new session was started
+        let mut repo = state.repository().await.unwrap();
+        let count = repo
+            .browser_session()
+            .count(BrowserSessionFilter::new().active_only())
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_idle_timeout(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                browser_session_lifetime: BrowserSessionLifetimeConfig {
+                    idle_timeout: Some(Duration::hours(1)),
+                    ..BrowserSessionLifetimeConfig::default()
+                },
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let cookies = CookieHelper::new();
+
+        log_in(&state, &cookies).await;
+        assert_eq!(
+            login_destination(&state, &cookies).await,
+            "/account/password/change"
+        );
+
+        // Still within the idle timeout
+        state.clock.advance(Duration::minutes(50));
+        assert_eq!(
+            login_destination(&state, &cookies).await,
+            "/account/password/change"
+        );
+
+        // Past the idle timeout, the user is asked to re-authenticate
+        state.clock.advance(Duration::hours(2));
+        assert_eq!(
+            login_destination(&state, &cookies).await,
+            "/reauth?kind=change_password"
+        );
+
+        reauthenticate(&state, &cookies).await;
+
+        // The session can be used again
+        assert_eq!(
+            login_destination(&state, &cookies).await,
+            "/account/password/change"
+        );
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_max_lifetime(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool_with_site_config(
+            pool,
+            SiteConfig {
+                browser_session_lifetime: BrowserSessionLifetimeConfig {
+                    max_lifetime: Some(Duration::days(1)),
+                    ..BrowserSessionLifetimeConfig::default()
+                },
+                ..test_site_config()
+            },
+        )
+        .await
+        .unwrap();
+        let cookies = CookieHelper::new();
+
+        log_in(&state, &cookies).await;
+
+        state.clock.advance(Duration::hours(23));
+        assert_eq!(
+            login_destination(&state, &cookies).await,
+            "/account/password/change"
+        );
+
+        // Past the maximum lifetime, the user is asked to re-authenticate
+        state.clock.advance(Duration::hours(2));
+        assert_eq!(
+            login_destination(&state, &cookies).await,
+            "/reauth?kind=change_password"
+        );
+
+        reauthenticate(&state, &cookies).await;
+
+        // The lifetime is counted from the new authentication
+        state.clock.advance(Duration::hours(23));
+        assert_eq!(
+            login_destination(&state, &cookies).await,
+            "/account/password/change"
+        );
+    }
+}
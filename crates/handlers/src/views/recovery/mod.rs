@@ -6,3 +6,4 @@
 
 pub mod progress;
 pub mod start;
+pub mod upstream;
@@ -56,7 +56,7 @@ pub(crate) async fn get(
         // XXX: is that the right thing to do?
         return Ok((
             cookie_jar,
-            url_builder.redirect(&mas_router::AccountRecoveryStart),
+            url_builder.redirect(&mas_router::AccountRecoveryStart::default()),
         )
             .into_response());
     };
@@ -110,7 +110,7 @@ pub(crate) async fn post(
         // XXX: is that the right thing to do?
         return Ok((
             cookie_jar,
-            url_builder.redirect(&mas_router::AccountRecoveryStart),
+            url_builder.redirect(&mas_router::AccountRecoveryStart::default()),
         )
             .into_response());
     };
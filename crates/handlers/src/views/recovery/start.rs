@@ -11,7 +11,7 @@ use axum::{
     extract::State,
     response::{Html, IntoResponse, Response},
 };
-use axum_extra::typed_header::TypedHeader;
+use axum_extra::{extract::Query, typed_header::TypedHeader};
 use lettre::Address;
 use mas_axum_utils::{
     InternalError, SessionInfoExt,
@@ -37,6 +37,11 @@ pub(crate) struct StartRecoveryForm {
     email: String,
 }
 
+#[derive(Deserialize)]
+pub(crate) struct QueryParams {
+    email: Option<String>,
+}
+
 pub(crate) async fn get(
     mut rng: BoxRng,
     clock: BoxClock,
@@ -45,6 +50,7 @@ pub(crate) async fn get(
     State(templates): State<Templates>,
     State(url_builder): State<UrlBuilder>,
     PreferredLanguage(locale): PreferredLanguage,
+    Query(query): Query<QueryParams>,
     cookie_jar: CookieJar,
 ) -> Result<Response, InternalError> {
     if !site_config.account_recovery_allowed {
@@ -62,7 +68,21 @@ pub(crate) async fn get(
         return Ok((cookie_jar, url_builder.redirect(&mas_router::Index)).into_response());
     }
 
-    let context = RecoveryStartContext::new()
+    let mut providers = repo.upstream_oauth_provider().all_enabled().await?;
+    providers.retain(|provider| !provider.ui_hidden);
+
+    let mut context = RecoveryStartContext::new();
+
+    // If we got an email address from the query string, use it to prefill the
+    // form
+    if let Some(email) = query.email {
+        let mut form_state = FormState::default();
+        form_state.set_value(RecoveryStartFormField::Email, Some(email));
+        context = context.with_form_state(form_state);
+    }
+
+    let context = context
+        .with_upstream_providers(providers)
         .with_csrf(csrf_token.form_value())
         .with_language(locale);
 
@@ -122,9 +142,12 @@ pub(crate) async fn post(
     }
 
     if !form_state.is_valid() {
+        let mut providers = repo.upstream_oauth_provider().all_enabled().await?;
+        providers.retain(|provider| !provider.ui_hidden);
         repo.save().await?;
         let context = RecoveryStartContext::new()
             .with_form_state(form_state)
+            .with_upstream_providers(providers)
             .with_csrf(csrf_token.form_value())
             .with_language(locale);
 
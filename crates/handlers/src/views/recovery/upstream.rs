@@ -0,0 +1,392 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Account recovery through an upstream provider
+//!
+//! A fresh login through an upstream provider, with an identity linked to an
+//! existing account, proves that the user owns that account. This lets them
+//! set a new password without knowing the current one.
+
+use axum::{
+    extract::{Form, State},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::typed_header::TypedHeader;
+use chrono::Duration;
+use mas_axum_utils::{
+    InternalError, SessionInfoExt,
+    cookies::CookieJar,
+    csrf::{CsrfExt, ProtectedForm},
+};
+use mas_data_model::{
+    AuditEventPayload, AuthenticationMethod, BoxClock, BoxRng, BrowserSession, Clock,
+    PasswordChangeInitiator, SiteConfig, UpstreamOAuthAuthorizationSession, UpstreamOAuthProvider,
+};
+use mas_i18n::DataLocale;
+use mas_policy::{AccountRecoveryInput, AccountRecoveryMethod, Policy, Requester};
+use mas_router::{AccountAction, PostAuthAction, UrlBuilder};
+use mas_storage::BoxRepository;
+use mas_templates::{
+    EmptyContext, ErrorCode, ErrorContext, FieldError, RecoveryFinishFormField,
+    RecoveryUpstreamContext, TemplateContext, Templates, ToFormState,
+};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    BoundActivityTracker, PreferredLanguage, audit::schedule_audit_event,
+    passwords::PasswordManager, user_actions::end_sessions_after_recovery,
+};
+
+/// How long after authenticating with the upstream provider the user can set
+/// a new password
+const MAX_AUTHENTICATION_AGE: Duration = Duration::minutes(10);
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct RecoveryUpstreamForm {
+    new_password: String,
+    new_password_confirm: String,
+
+    /// Set if the user asked to keep their other sessions signed in
+    #[serde(default)]
+    keep_sessions: Option<String>,
+}
+
+impl ToFormState for RecoveryUpstreamForm {
+    type Field = RecoveryFinishFormField;
+}
+
+/// Find the upstream provider with which the browser session was last
+/// authenticated, along with the upstream session of that authentication, if
+/// it is recent enough to allow recovering the account
+///
+/// The age is measured from the upstream login itself, and an upstream login
+/// stops being usable once a password was set after it, so that it can only be
+/// used for one recovery.
+async fn recent_upstream_authentication(
+    clock: &dyn Clock,
+    repo: &mut BoxRepository,
+    session: &BrowserSession,
+) -> Result<Option<(UpstreamOAuthProvider, UpstreamOAuthAuthorizationSession)>, InternalError> {
+    let Some(authentication) = repo
+        .browser_session()
+        .get_last_authentication(session)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let AuthenticationMethod::UpstreamOAuth2 {
+        upstream_oauth2_session_id,
+    } = authentication.authentication_method
+    else {
+        return Ok(None);
+    };
+
+    let Some(upstream_session) = repo
+        .upstream_oauth_session()
+        .lookup(upstream_oauth2_session_id)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let Some(completed_at) = upstream_session.completed_at() else {
+        return Ok(None);
+    };
+
+    if clock.now() - completed_at > MAX_AUTHENTICATION_AGE {
+        return Ok(None);
+    }
+
+    if let Some(password) = repo.user_password().active(&session.user).await?
+        && password.created_at >= completed_at
+    {
+        return Ok(None);
+    }
+
+    let Some(provider) = repo
+        .upstream_oauth_provider()
+        .lookup(upstream_session.provider_id)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some((provider, upstream_session)))
+}
+
+/// Evaluate the account recovery policy, returning the error page to render if
+/// the recovery is denied
+async fn check_policy(
+    policy: &mut Policy,
+    templates: &Templates,
+    locale: &DataLocale,
+    session: &BrowserSession,
+    provider: &UpstreamOAuthProvider,
+    requester: Requester,
+) -> Result<Option<String>, InternalError> {
+    let res = policy
+        .evaluate_account_recovery(AccountRecoveryInput {
+            recovery_method: AccountRecoveryMethod::UpstreamOAuth2,
+            user: &session.user,
+            upstream_provider_id: Some(provider.id),
+            requester,
+        })
+        .await?;
+
+    if res.valid() {
+        return Ok(None);
+    }
+
+    tracing::warn!(
+        user.id = %session.user.id,
+        upstream_oauth_provider.id = %provider.id,
+        "Account recovery denied by policy: {res}"
+    );
+
+    let ctx = ErrorContext::new()
+        .with_code(ErrorCode::AccountRecoveryDenied)
+        .with_description("Account recovery through this provider is not allowed.".to_owned())
+        .with_language(locale);
+
+    Ok(Some(templates.render_error(&ctx)?))
+}
+
+#[tracing::instrument(name = "handlers.views.recovery.upstream.get", skip_all)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    mut policy: Policy,
+    State(site_config): State<SiteConfig>,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    PreferredLanguage(locale): PreferredLanguage,
+    (user_agent, activity_tracker): (
+        Option<TypedHeader<headers::UserAgent>>,
+        BoundActivityTracker,
+    ),
+    cookie_jar: CookieJar,
+) -> Result<Response, InternalError> {
+    if !site_config.account_recovery_allowed {
+        let context = EmptyContext.with_language(locale);
+        let rendered = templates.render_recovery_disabled(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let Some(session) = session_info.load_active_session(&mut repo).await? else {
+        return Ok((
+            cookie_jar,
+            url_builder.redirect(&mas_router::AccountRecoveryStart::default()),
+        )
+            .into_response());
+    };
+
+    let Some((provider, _)) = recent_upstream_authentication(&clock, &mut repo, &session).await?
+    else {
+        return Ok((cookie_jar, url_builder.redirect(&mas_router::Index)).into_response());
+    };
+
+    let requester = Requester {
+        ip_address: activity_tracker.ip(),
+        user_agent: user_agent.map(|ua| ua.as_str().to_owned()),
+    };
+    if let Some(rendered) = check_policy(
+        &mut policy,
+        &templates,
+        &locale,
+        &session,
+        &provider,
+        requester,
+    )
+    .await?
+    {
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    let context = RecoveryUpstreamContext::new(session.user, provider)
+        .with_csrf(csrf_token.form_value())
+        .with_language(locale);
+
+    repo.save().await?;
+
+    let rendered = templates.render_recovery_upstream(&context)?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.recovery.upstream.post", skip_all)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    mut policy: Policy,
+    State(password_manager): State<PasswordManager>,
+    State(site_config): State<SiteConfig>,
+    State(templates): State<Templates>,
+    State(url_builder): State<UrlBuilder>,
+    PreferredLanguage(locale): PreferredLanguage,
+    (user_agent, activity_tracker): (
+        Option<TypedHeader<headers::UserAgent>>,
+        BoundActivityTracker,
+    ),
+    cookie_jar: CookieJar,
+    Form(form): Form<ProtectedForm<RecoveryUpstreamForm>>,
+) -> Result<Response, InternalError> {
+    if !site_config.account_recovery_allowed {
+        let context = EmptyContext.with_language(locale);
+        let rendered = templates.render_recovery_disabled(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    let form = cookie_jar.verify_form(&clock, form)?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    let Some(session) = session_info.load_active_session(&mut repo).await? else {
+        return Ok((
+            cookie_jar,
+            url_builder.redirect(&mas_router::AccountRecoveryStart::default()),
+        )
+            .into_response());
+    };
+
+    let Some((provider, upstream_session)) =
+        recent_upstream_authentication(&clock, &mut repo, &session).await?
+    else {
+        return Ok((cookie_jar, url_builder.redirect(&mas_router::Index)).into_response());
+    };
+
+    let requester = Requester {
+        ip_address: activity_tracker.ip(),
+        user_agent: user_agent.map(|ua| ua.as_str().to_owned()),
+    };
+    if let Some(rendered) = check_policy(
+        &mut policy,
+        &templates,
+        &locale,
+        &session,
+        &provider,
+        requester,
+    )
+    .await?
+    {
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    // Validate the form
+    let mut state = form.to_form_state();
+
+    if form.new_password.is_empty() {
+        state.add_error_on_field(RecoveryFinishFormField::NewPassword, FieldError::Required);
+    }
+
+    if form.new_password_confirm.is_empty() {
+        state.add_error_on_field(
+            RecoveryFinishFormField::NewPasswordConfirm,
+            FieldError::Required,
+        );
+    }
+
+    if form.new_password != form.new_password_confirm {
+        state.add_error_on_field(
+            RecoveryFinishFormField::NewPasswordConfirm,
+            FieldError::PasswordMismatch,
+        );
+    }
+
+    if !password_manager.is_password_complex_enough(&form.new_password)? {
+        // TODO localise this error
+        state.add_error_on_field(
+            RecoveryFinishFormField::NewPassword,
+            FieldError::Policy {
+                code: None,
+                message: "Password is too weak".to_owned(),
+            },
+        );
+    }
+
+    if !state.is_valid() {
+        let context = RecoveryUpstreamContext::new(session.user, provider)
+            .with_form_state(state)
+            .with_csrf(csrf_token.form_value())
+            .with_language(locale);
+
+        repo.save().await?;
+
+        let rendered = templates.render_recovery_upstream(&context)?;
+        return Ok((cookie_jar, Html(rendered)).into_response());
+    }
+
+    let password = Zeroizing::new(form.new_password);
+    let (version, hashed_password) = password_manager
+        .hash(&mut rng, password)
+        .await
+        .map_err(InternalError::from_anyhow)?;
+    repo.user_password()
+        .add(
+            &mut rng,
+            &clock,
+            &session.user,
+            version,
+            hashed_password,
+            None,
+        )
+        .await?;
+
+    // The recovery was allowed by the upstream login, so record it as the
+    // authentication of the browser session which is kept signed in
+    repo.browser_session()
+        .authenticate_with_upstream(&mut rng, &clock, &session, &upstream_session)
+        .await?;
+
+    // Someone else may have had access to the account, so end all the other
+    // sessions, keeping the one which was used for the recovery
+    end_sessions_after_recovery(
+        &mut repo,
+        &mut rng,
+        &clock,
+        &session.user,
+        Some(session.id),
+        form.keep_sessions.is_some(),
+        locale.to_string(),
+    )
+    .await?;
+
+    schedule_audit_event(
+        &mut repo,
+        &mut rng,
+        &clock,
+        &site_config,
+        activity_tracker.ip(),
+        AuditEventPayload::password_change(&session.user, PasswordChangeInitiator::Recovery),
+    )
+    .await?;
+
+    repo.save().await?;
+
+    tracing::info!(
+        user.id = %session.user.id,
+        upstream_oauth_provider.id = %provider.id,
+        upstream_oauth_authorization_session.id = %upstream_session.id,
+        "User recovered their account through an upstream provider"
+    );
+
+    let cookie_jar = cookie_jar.set_authenticated_session(&session, &clock, &mut rng);
+
+    // Send the user to their sessions list, so that they can review what is
+    // still signed in to their account
+    let reply =
+        PostAuthAction::manage_account(Some(AccountAction::SessionsList)).go_next(&url_builder);
+
+    Ok((cookie_jar, reply).into_response())
+}
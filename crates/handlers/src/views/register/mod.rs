@@ -48,7 +48,8 @@ pub(crate) async fn get(
         return Ok((cookie_jar, reply).into_response());
     }
 
-    let providers = repo.upstream_oauth_provider().all_enabled().await?;
+    let mut providers = repo.upstream_oauth_provider().all_enabled().await?;
+    providers.retain(|provider| !provider.ui_hidden);
 
     // If password-based login is disabled, and there is only one upstream provider,
     // we can directly start an authorization flow
@@ -18,7 +18,7 @@ use mas_axum_utils::{
     cookies::CookieJar,
     csrf::{CsrfExt, CsrfToken, ProtectedForm},
 };
-use mas_data_model::{BoxClock, BoxRng, CaptchaConfig, TchapConfig};
+use mas_data_model::{BoxClock, BoxRng, CaptchaConfig, TchapConfig, TchapFeatures};
 use mas_i18n::DataLocale;
 use mas_matrix::HomeserverConnection;
 use mas_policy::Policy;
@@ -170,7 +170,11 @@ pub(crate) async fn post(
     State(url_builder): State<UrlBuilder>,
     //:tchap: add tchap to the state with site_config as a tuple to stay under the limit of 16
     //:tchap: arguments
-    (State(site_config), State(tchap_config)): (State<SiteConfig>, State<TchapConfig>),
+    (State(site_config), State(tchap_config), State(tchap_features)): (
+        State<SiteConfig>,
+        State<TchapConfig>,
+        State<TchapFeatures>,
+    ),
     //:tchap:end
     State(homeserver): State<Arc<dyn HomeserverConnection>>,
     State(http_client): State<reqwest::Client>,
@@ -238,32 +242,34 @@ pub(crate) async fn post(
             }
 
             //verify that email address is allowed in this homeserver
-            let server_name = homeserver.homeserver();
-            let email_result = check_email_allowed(email, server_name, &tchap_config).await;
-
-            match email_result {
-                EmailAllowedResult::Allowed => {
-                    // Email is allowed, continue
-                }
-                EmailAllowedResult::WrongServer => {
-                    state.add_error_on_field(
-                        RegisterFormField::Email,
-                        FieldError::Policy {
-                            code: None,
-                            message: "Votre adresse mail est associée à un autre serveur."
-                                .to_owned(),
-                        },
-                    );
-                }
-                EmailAllowedResult::InvitationMissing => {
-                    state.add_error_on_field(
-                        RegisterFormField::Email,
-                        FieldError::Policy {
-                            code: None,
-                            message: "Vous avez besoin d'une invitation pour accéder à Tchap"
-                                .to_owned(),
-                        },
-                    );
+            if tchap_features.checks_registration_email() {
+                let server_name = homeserver.homeserver();
+                let email_result = check_email_allowed(email, server_name, &tchap_config).await;
+
+                match email_result {
+                    EmailAllowedResult::Allowed => {
+                        // Email is allowed, continue
+                    }
+                    EmailAllowedResult::WrongServer => {
+                        state.add_error_on_field(
+                            RegisterFormField::Email,
+                            FieldError::Policy {
+                                code: None,
+                                message: "Votre adresse mail est associée à un autre serveur."
+                                    .to_owned(),
+                            },
+                        );
+                    }
+                    EmailAllowedResult::InvitationMissing => {
+                        state.add_error_on_field(
+                            RegisterFormField::Email,
+                            FieldError::Policy {
+                                code: None,
+                                message: "Vous avez besoin d'une invitation pour accéder à Tchap"
+                                    .to_owned(),
+                            },
+                        );
+                    }
                 }
             }
 
@@ -335,6 +341,8 @@ pub(crate) async fn post(
                 registration_method: mas_policy::RegistrationMethod::Password,
                 username: &form.username,
                 email: email.as_deref(),
+                organization: None,
+                can_request_admin: None,
                 requester: mas_policy::Requester {
                     ip_address: activity_tracker.ip(),
                     user_agent: user_agent.clone(),
@@ -441,7 +449,9 @@ pub(crate) async fn post(
 
     let registration = if let Some(email) = email {
         //:tchap: set display name automatically - skip display name page
-        let maybe_display_name = Some(email_to_display_name(&email));
+        let maybe_display_name = tchap_features
+            .displayname_suffixing
+            .then(|| email_to_display_name(&email));
 
         let registration = if let Some(display_name) = maybe_display_name {
             repo.user_registration()
@@ -662,6 +672,9 @@ mod tests {
                 false,
                 Some(login_hint.to_owned()),
                 None,
+                Vec::new(),
+                None,
+                Vec::new(),
             )
             .await
             .unwrap();
@@ -797,6 +810,7 @@ mod tests {
         //assert_eq!(registration.username, "john".to_owned());
         let expected_username = "john-example.com";
         assert_eq!(registration.username, expected_username.to_owned());
+        assert_eq!(registration.display_name.as_deref(), Some("John [Example]"));
         //:tchap:end
         assert!(registration.password.is_some());
 
@@ -809,6 +823,64 @@ mod tests {
         assert_eq!(email_authentication.email, "john@example.com");
     }
 
+    /// :tchap:
+    /// When display name suffixing is disabled, the display name is not
+    /// derived from the email
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_register_without_displayname_suffixing(pool: PgPool) {
+        setup();
+        let mut state = TestState::from_pool(pool).await.unwrap();
+        state.tchap_features.displayname_suffixing = false;
+        let cookies = CookieHelper::new();
+
+        // Render the registration page and get the CSRF token
+        let request =
+            Request::get(&*mas_router::PasswordRegister::default().path_and_query()).empty();
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+        let csrf_token = response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap();
+
+        // Submit the registration form
+        let request = Request::post(&*mas_router::PasswordRegister::default().path_and_query())
+            .form(serde_json::json!({
+                "csrf": csrf_token,
+                "username": "john",
+                "email": "john@example.com",
+                "password": "correcthorsebatterystaple",
+                "password_confirm": "correcthorsebatterystaple",
+                "accept_terms": "on",
+            }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::SEE_OTHER);
+        let location = response.headers().get(LOCATION).unwrap();
+
+        let id = location
+            .to_str()
+            .unwrap()
+            .rsplit('/')
+            .nth(1)
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        // The registration should not have a display name set
+        let mut repo = state.repository().await.unwrap();
+        let registration = repo.user_registration().lookup(id).await.unwrap().unwrap();
+        assert_eq!(registration.username, "john-example.com");
+        assert_eq!(registration.display_name, None);
+    }
+
     /// When the two password fields mismatch, it should give an error
     #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
     async fn test_register_password_mismatch(pool: PgPool) {
@@ -296,7 +296,7 @@ pub(crate) async fn get(
         .transpose()?;
 
     // Login the user with the session we just created
-    let cookie_jar = cookie_jar.set_session(&user_session);
+    let cookie_jar = cookie_jar.set_authenticated_session(&user_session, &clock, &mut rng);
 
     return Ok((
         cookie_jar,
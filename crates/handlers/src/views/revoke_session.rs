@@ -0,0 +1,519 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Links sent in the new login notification, letting users sign out a session
+//! they don't recognize and start recovering their account
+
+use axum::{
+    Form,
+    extract::State,
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::Query;
+use chrono::{DateTime, Duration, Utc};
+use hyper::StatusCode;
+use mas_axum_utils::{
+    GenericError, InternalError,
+    cookies::CookieJar,
+    csrf::{CsrfExt as _, ProtectedForm},
+};
+use mas_data_model::{BoxClock, BoxRng, Clock, CompatSession, Session, User};
+use mas_keystore::Encrypter;
+use mas_router::UrlBuilder;
+use mas_storage::{
+    BoxRepository,
+    queue::{QueueJobRepositoryExt as _, SendNewLoginEmailJob, SyncDevicesJob},
+};
+use mas_templates::{ErrorCode, RevokeSessionContext, TemplateContext as _, Templates};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use ulid::Ulid;
+
+use crate::{PreferredLanguage, impl_from_error_for_route};
+
+/// How long a revocation link stays valid after being issued
+pub const SESSION_REVOCATION_LINK_VALIDITY: Duration = Duration::days(7);
+
+#[derive(Debug, Error)]
+pub(crate) enum RouteError {
+    #[error(transparent)]
+    Internal(Box<dyn std::error::Error + Send + Sync>),
+
+    #[error(transparent)]
+    Csrf(#[from] mas_axum_utils::csrf::CsrfError),
+
+    #[error("This link is invalid")]
+    InvalidToken,
+
+    #[error("This link expired, sign in to review your sessions instead")]
+    Expired,
+
+    #[error("This session was not found")]
+    SessionNotFound,
+
+    #[error("This link was already used, or the session already ended")]
+    AlreadyUsed,
+}
+
+impl_from_error_for_route!(mas_templates::TemplateError);
+impl_from_error_for_route!(mas_storage::RepositoryError);
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Internal(e) => InternalError::new(e).into_response(),
+            e @ Self::Csrf(_) => GenericError::new(StatusCode::BAD_REQUEST, e)
+                .with_error_code(ErrorCode::CsrfMismatch)
+                .into_response(),
+            e @ Self::InvalidToken => GenericError::new(StatusCode::BAD_REQUEST, e)
+                .with_error_code(ErrorCode::SessionRevocationLinkInvalid)
+                .into_response(),
+            e @ Self::Expired => GenericError::new(StatusCode::BAD_REQUEST, e)
+                .with_error_code(ErrorCode::SessionRevocationLinkExpired)
+                .into_response(),
+            e @ Self::SessionNotFound => GenericError::new(StatusCode::NOT_FOUND, e)
+                .with_error_code(ErrorCode::NotFound)
+                .into_response(),
+            e @ Self::AlreadyUsed => GenericError::new(StatusCode::CONFLICT, e)
+                .with_error_code(ErrorCode::SessionRevocationLinkUsed)
+                .into_response(),
+        }
+    }
+}
+
+/// The kind of session a revocation link targets
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionKind {
+    #[serde(rename = "compat")]
+    Compat,
+
+    #[serde(rename = "oauth2")]
+    OAuth2,
+}
+
+/// The payload of a session revocation link.
+///
+/// It is sealed with the encryption secret, which authenticates it: a link
+/// can't be forged or altered without knowing the secret. Links are
+/// single-use, as the session they point to is ended once the user confirms
+/// they want to sign it out.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionRevocationToken {
+    kind: SessionKind,
+    session_id: Ulid,
+    expires_at: DateTime<Utc>,
+}
+
+impl SessionRevocationToken {
+    /// Create a token for the given session, valid for
+    /// [`SESSION_REVOCATION_LINK_VALIDITY`]
+    #[must_use]
+    pub fn new(clock: &dyn Clock, kind: SessionKind, session_id: Ulid) -> Self {
+        Self {
+            kind,
+            session_id,
+            expires_at: clock.now() + SESSION_REVOCATION_LINK_VALIDITY,
+        }
+    }
+
+    /// Seal the token, returning the route to put in the link
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the token could not be encrypted
+    pub fn seal(&self, encrypter: &Encrypter) -> anyhow::Result<mas_router::RevokeSession> {
+        let payload = serde_json::to_vec(self)?;
+        let token = encrypter
+            .encrypt_to_string(&payload)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt the session revocation token"))?;
+        Ok(mas_router::RevokeSession::new(token))
+    }
+
+    /// Open a sealed token, checking that it was issued by us
+    fn unseal(encrypter: &Encrypter, token: &str) -> Option<Self> {
+        let payload = encrypter.decrypt_string(token).ok()?;
+        serde_json::from_slice(&payload).ok()
+    }
+}
+
+/// A session targeted by a revocation link, which is still active
+enum RevocableSession {
+    Compat(CompatSession),
+    OAuth2(Session),
+}
+
+impl RevocableSession {
+    fn human_name(&self) -> Option<&str> {
+        match self {
+            Self::Compat(session) => session.human_name.as_deref(),
+            Self::OAuth2(session) => session.human_name.as_deref(),
+        }
+    }
+
+    fn created_at(&self) -> DateTime<Utc> {
+        match self {
+            Self::Compat(session) => session.created_at,
+            Self::OAuth2(session) => session.created_at,
+        }
+    }
+}
+
+/// Check the token from the link, and load the session it points to
+async fn load_session(
+    repo: &mut BoxRepository,
+    clock: &dyn Clock,
+    encrypter: &Encrypter,
+    token: &str,
+) -> Result<RevocableSession, RouteError> {
+    let token = SessionRevocationToken::unseal(encrypter, token).ok_or(RouteError::InvalidToken)?;
+
+    if token.expires_at < clock.now() {
+        return Err(RouteError::Expired);
+    }
+
+    let session = match token.kind {
+        SessionKind::Compat => {
+            let session = repo
+                .compat_session()
+                .lookup(token.session_id)
+                .await?
+                .ok_or(RouteError::SessionNotFound)?;
+
+            if session.is_finished() {
+                return Err(RouteError::AlreadyUsed);
+            }
+
+            RevocableSession::Compat(session)
+        }
+
+        SessionKind::OAuth2 => {
+            let session = repo
+                .oauth2_session()
+                .lookup(token.session_id)
+                .await?
+                .ok_or(RouteError::SessionNotFound)?;
+
+            if session.is_finished() {
+                return Err(RouteError::AlreadyUsed);
+            }
+
+            // Links are only issued for sessions belonging to a user
+            if session.user_id.is_none() {
+                return Err(RouteError::SessionNotFound);
+            }
+
+            RevocableSession::OAuth2(session)
+        }
+    };
+
+    Ok(session)
+}
+
+/// Schedule the email notifying a user that a new session was started on their
+/// account, with a link to sign that session out
+///
+/// # Errors
+///
+/// Returns an error if the link could not be sealed, or if the job could not
+/// be scheduled
+pub(crate) async fn schedule_new_login_email(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    encrypter: &Encrypter,
+    url_builder: &UrlBuilder,
+    user: &User,
+    session: &CompatSession,
+    language: String,
+) -> anyhow::Result<()> {
+    let route =
+        SessionRevocationToken::new(clock, SessionKind::Compat, session.id).seal(encrypter)?;
+    let revocation_link = url_builder.absolute_url_for(&route);
+
+    repo.queue_job()
+        .schedule_job(
+            rng,
+            clock,
+            SendNewLoginEmailJob::new(user, session.human_name.clone(), revocation_link, language),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "handlers.views.revoke_session.get", skip_all)]
+pub(crate) async fn get(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(encrypter): State<Encrypter>,
+    State(templates): State<Templates>,
+    PreferredLanguage(locale): PreferredLanguage,
+    cookie_jar: CookieJar,
+    Query(query): Query<mas_router::RevokeSession>,
+) -> Result<Response, RouteError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token(&clock, &mut rng);
+
+    // Following the link only shows what is about to happen: mail scanners
+    // open links on their own, so the session is only ended once the user
+    // submits the form
+    let session = load_session(&mut repo, &clock, &encrypter, query.token()).await?;
+
+    let context = RevokeSessionContext::new(
+        session.human_name().map(ToOwned::to_owned),
+        session.created_at(),
+    )
+    .with_csrf(csrf_token.form_value())
+    .with_language(locale);
+
+    let rendered = templates.render_revoke_session(&context)?;
+
+    Ok((cookie_jar, Html(rendered)).into_response())
+}
+
+#[tracing::instrument(name = "handlers.views.revoke_session.post", skip_all)]
+pub(crate) async fn post(
+    mut rng: BoxRng,
+    clock: BoxClock,
+    mut repo: BoxRepository,
+    State(encrypter): State<Encrypter>,
+    State(url_builder): State<UrlBuilder>,
+    cookie_jar: CookieJar,
+    Query(query): Query<mas_router::RevokeSession>,
+    Form(form): Form<ProtectedForm<()>>,
+) -> Result<Response, RouteError> {
+    let () = cookie_jar.verify_form(&clock, form)?;
+
+    // Holding the link is what proves that the request comes from the owner of
+    // the account, so there is no need for the user to be logged in
+    let user_id = match load_session(&mut repo, &clock, &encrypter, query.token()).await? {
+        RevocableSession::Compat(session) => {
+            let user_id = session.user_id;
+            repo.compat_session().finish(&clock, session).await?;
+            user_id
+        }
+
+        RevocableSession::OAuth2(session) => {
+            let user_id = session.user_id.ok_or(RouteError::SessionNotFound)?;
+            repo.oauth2_session().finish(&clock, session).await?;
+            user_id
+        }
+    };
+
+    let user = repo
+        .user()
+        .lookup(user_id)
+        .await?
+        .ok_or_else(|| RouteError::Internal("User not found".into()))?;
+
+    // Schedule a job to sync the devices of the user with the homeserver
+    repo.queue_job()
+        .schedule_job(&mut rng, &clock, SyncDevicesJob::new(&user))
+        .await?;
+
+    let email = repo.user_email().all(&user).await?.into_iter().next();
+
+    repo.save().await?;
+
+    let destination = match email {
+        Some(email) => mas_router::AccountRecoveryStart::with_email(email.email),
+        None => mas_router::AccountRecoveryStart::default(),
+    };
+
+    Ok(url_builder.redirect(&destination).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use hyper::{Request, Response, StatusCode, header::LOCATION};
+    use mas_data_model::{CompatSession, Device};
+    use mas_router::Route as _;
+    use mas_storage::RepositoryAccess;
+    use sqlx::PgPool;
+
+    use super::{SessionKind, SessionRevocationToken};
+    use crate::test_utils::{CookieHelper, RequestBuilderExt, ResponseExt, TestState, setup};
+
+    /// Create a user with an email address and a compat session, returning
+    /// the session and the path of a revocation link for it
+    async fn start_session(state: &TestState) -> (CompatSession, String) {
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &state.clock, "alice".to_owned())
+            .await
+            .unwrap();
+        repo.user_email()
+            .add(
+                &mut rng,
+                &state.clock,
+                &user,
+                "alice@example.com".to_owned(),
+            )
+            .await
+            .unwrap();
+        let device = Device::generate(&mut rng);
+        let session = repo
+            .compat_session()
+            .add(&mut rng, &state.clock, &user, device, None, false, None)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        let path = SessionRevocationToken::new(&state.clock, SessionKind::Compat, session.id)
+            .seal(&state.encrypter)
+            .unwrap()
+            .path_and_query();
+
+        (session, path.into_owned())
+    }
+
+    async fn is_finished(state: &TestState, session: &CompatSession) -> bool {
+        let mut repo = state.repository().await.unwrap();
+        let session = repo
+            .compat_session()
+            .lookup(session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        session.is_finished()
+    }
+
+    /// Open the link from the email, returning the CSRF token of the
+    /// confirmation form
+    async fn open(state: &TestState, cookies: &CookieHelper, path: &str) -> String {
+        let request = cookies.with_cookies(Request::get(path).empty());
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response.assert_status(StatusCode::OK);
+
+        // Extract the CSRF token from the response body
+        response
+            .body()
+            .split("name=\"csrf\" value=\"")
+            .nth(1)
+            .unwrap()
+            .split('\"')
+            .next()
+            .unwrap()
+            .to_owned()
+    }
+
+    /// Submit the confirmation form
+    async fn submit(
+        state: &TestState,
+        cookies: &CookieHelper,
+        path: &str,
+        csrf_token: &str,
+    ) -> Response<String> {
+        let request = Request::post(path).form(serde_json::json!({
+            "csrf": csrf_token,
+        }));
+        let request = cookies.with_cookies(request);
+        let response = state.request(request).await;
+        cookies.save_cookies(&response);
+        response
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_revoke(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (session, path) = start_session(&state).await;
+
+        // Opening the link alone doesn't end the session
+        let cookies = CookieHelper::new();
+        let csrf_token = open(&state, &cookies, &path).await;
+        assert!(!is_finished(&state, &session).await);
+
+        let response = submit(&state, &cookies, &path, &csrf_token).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        let destination =
+            mas_router::AccountRecoveryStart::with_email("alice@example.com".to_owned())
+                .path_and_query();
+        response.assert_header_value(LOCATION, &destination);
+
+        assert!(is_finished(&state, &session).await);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_missing_csrf(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (session, path) = start_session(&state).await;
+
+        let cookies = CookieHelper::new();
+        open(&state, &cookies, &path).await;
+
+        let response = submit(&state, &cookies, &path, "not-the-csrf-token").await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        assert!(!is_finished(&state, &session).await);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_replayed(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (_session, path) = start_session(&state).await;
+
+        let cookies = CookieHelper::new();
+        let csrf_token = open(&state, &cookies, &path).await;
+        let response = submit(&state, &cookies, &path, &csrf_token).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+
+        // Following the link a second time fails
+        let response = state.request(Request::get(&*path).empty()).await;
+        response.assert_status(StatusCode::CONFLICT);
+        assert!(
+            response
+                .body()
+                .contains("data-error-code=\"session-revocation-link-used\"")
+        );
+
+        // And so does submitting the form again
+        let response = submit(&state, &cookies, &path, &csrf_token).await;
+        response.assert_status(StatusCode::CONFLICT);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_expired(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (session, path) = start_session(&state).await;
+
+        state.clock.advance(Duration::days(8));
+
+        let response = state.request(Request::get(&*path).empty()).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        assert!(
+            response
+                .body()
+                .contains("data-error-code=\"session-revocation-link-expired\"")
+        );
+
+        assert!(!is_finished(&state, &session).await);
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_tampered(pool: PgPool) {
+        setup();
+        let state = TestState::from_pool(pool).await.unwrap();
+        let (session, _path) = start_session(&state).await;
+
+        let path = mas_router::RevokeSession::new("not-a-valid-token".to_owned()).path_and_query();
+        let response = state.request(Request::get(&*path).empty()).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        assert!(
+            response
+                .body()
+                .contains("data-error-code=\"session-revocation-link-invalid\"")
+        );
+
+        assert!(!is_finished(&state, &session).await);
+    }
+}
@@ -102,6 +102,8 @@ impl OptionalPostAuthAction {
             }
 
             PostAuthAction::ManageAccount { .. } => PostAuthContextInner::ManageAccount,
+
+            PostAuthAction::RecoverAccount => PostAuthContextInner::RecoverAccount,
         };
 
         Ok(Some(PostAuthContext {
@@ -180,8 +180,16 @@ async fn send_traced(
         let duration = start.elapsed().as_millis().try_into().unwrap_or(u64::MAX);
         let result = match result {
             Ok(response) => {
-                span.record("otel.status_code", "OK");
-                span.record(HTTP_RESPONSE_STATUS_CODE, response.status().as_u16());
+                let status = response.status();
+                // Client spans are errored on both 4xx and 5xx responses, as per the
+                // OpenTelemetry semantic conventions
+                if status.is_client_error() || status.is_server_error() {
+                    span.record("otel.status_code", "ERROR");
+                    metrics_labels.push(KeyValue::new(ERROR_TYPE, status.as_str().to_owned()));
+                } else {
+                    span.record("otel.status_code", "OK");
+                }
+                span.record(HTTP_RESPONSE_STATUS_CODE, status.as_u16());
 
                 if let Some(ContentLength(content_length)) = response.headers().typed_get() {
                     span.record(HTTP_RESPONSE_BODY_SIZE, content_length);
@@ -202,7 +210,7 @@ async fn send_traced(
 
                 metrics_labels.push(KeyValue::new(
                     HTTP_RESPONSE_STATUS_CODE,
-                    i64::from(response.status().as_u16()),
+                    i64::from(status.as_u16()),
                 ));
 
                 Ok(response)
@@ -237,3 +245,73 @@ impl RequestBuilderExt for reqwest::RequestBuilder {
         send_traced(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{
+        TraceContextExt as _, TracerProvider as _, noop::NoopTextMapPropagator,
+    };
+    use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider};
+    use tracing_subscriber::layer::SubscriberExt as _;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    use super::*;
+
+    /// Puts back the default no-op global propagator when dropped, so that
+    /// the propagator set by a test doesn't leak into the others
+    struct ResetPropagatorGuard;
+
+    impl Drop for ResetPropagatorGuard {
+        fn drop(&mut self) {
+            opentelemetry::global::set_text_map_propagator(NoopTextMapPropagator::new());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_traceparent_propagation() {
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let _reset_propagator = ResetPropagatorGuard;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let span = tracing::info_span!("test");
+        let parent = span.context().span().span_context().clone();
+        assert!(parent.is_valid());
+
+        let response = client()
+            .get(server.uri())
+            .send_traced()
+            .instrument(span)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        let requests = server.received_requests().await.unwrap();
+        let traceparent = requests[0]
+            .headers
+            .get("traceparent")
+            .expect("traceparent header should be set")
+            .to_str()
+            .unwrap();
+
+        // The header is `{version}-{trace_id}-{parent_id}-{flags}`, and should
+        // point to the client span, which is a child of the current span
+        let parts: Vec<&str> = traceparent.split('-').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[1], parent.trace_id().to_string());
+        assert_ne!(parts[2], parent.span_id().to_string());
+    }
+}
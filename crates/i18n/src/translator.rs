@@ -247,9 +247,10 @@ impl Translator {
                 return Some((message, iter.take()));
             }
 
-            // Stop if we hit the `und` locale
+            // Try the defaut locale if we hit the `und` locale
             if locale.is_und() {
-                return None;
+                let message = self.plural(&self.default_locale, key, count).ok()?;
+                return Some((message, self.default_locale.clone()));
             }
 
             iter.step();
@@ -476,5 +477,13 @@ mod tests {
         let formatted = message.format(&arg_list!(count = 1)).unwrap();
         assert_eq!(formatted, "1 active session.");
         assert_eq!(locale, locale!("en").into());
+
+        // Locales without translations use the default locale and its rules
+        let (message, locale) = translator
+            .plural_with_fallback(locale!("cs").into(), "active_sessions", 3)
+            .unwrap();
+        let formatted = message.format(&arg_list!(count = 3)).unwrap();
+        assert_eq!(formatted, "3 active sessions.");
+        assert_eq!(locale, locale!("en").into());
     }
 }
@@ -512,6 +512,7 @@ mod oidc_core {
 
     pub const AUTH_TIME: Claim<Timestamp> = Claim::new("auth_time");
     pub const NONCE: Claim<String, Equality<str>> = Claim::new("nonce");
+    pub const ACR: Claim<String> = Claim::new("acr");
     pub const AT_HASH: Claim<String, TokenHash> = Claim::new("at_hash");
     pub const C_HASH: Claim<String, TokenHash> = Claim::new("c_hash");
 
@@ -68,6 +68,32 @@ impl SynapseConnection {
     fn delete(&self, url: &str) -> reqwest::RequestBuilder {
         self.builder(Method::DELETE, url)
     }
+
+    /// Get the current threepids of a user, or an empty list if the user
+    /// doesn't exist yet
+    async fn query_three_pids(&self, encoded_mxid: &str) -> Result<Vec<ThreePID>, anyhow::Error> {
+        let response = self
+            .get(&format!("_synapse/admin/v2/users/{encoded_mxid}"))
+            .send_traced()
+            .await
+            .context("Failed to query user threepids from Synapse")?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+
+        let response = response
+            .error_for_synapse_error()
+            .await
+            .context("Unexpected HTTP response while querying user threepids from Synapse")?;
+
+        let body: SynapseUser = response
+            .json()
+            .await
+            .context("Failed to deserialize response while querying user threepids from Synapse")?;
+
+        Ok(body.three_pids.unwrap_or_default())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -76,19 +102,48 @@ struct ExternalID {
     external_id: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 enum ThreePIDMedium {
     Email,
     Msisdn,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 struct ThreePID {
     medium: ThreePIDMedium,
     address: String,
 }
 
+/// Compute the threepids to set on the homeserver so that its email threepids
+/// match the wanted emails.
+///
+/// Synapse replaces all the threepids of the user, so the threepids which are
+/// not emails are kept, as well as the emails which are still wanted, and the
+/// new emails are appended.
+fn sync_three_pids(current: Vec<ThreePID>, wanted_emails: &[String]) -> Vec<ThreePID> {
+    let mut three_pids: Vec<ThreePID> = current
+        .into_iter()
+        .filter(|three_pid| {
+            three_pid.medium != ThreePIDMedium::Email || wanted_emails.contains(&three_pid.address)
+        })
+        .collect();
+
+    for email in wanted_emails {
+        let known = three_pids.iter().any(|three_pid| {
+            three_pid.medium == ThreePIDMedium::Email && &three_pid.address == email
+        });
+        if !known {
+            three_pids.push(ThreePID {
+                medium: ThreePIDMedium::Email,
+                address: email.clone(),
+            });
+        }
+    }
+
+    three_pids
+}
+
 #[derive(Default, Serialize, Deserialize)]
 struct SynapseUser {
     #[serde(
@@ -277,22 +332,20 @@ impl HomeserverConnection for SynapseConnection {
             })
             .on_avatar_url(|avatar_url| {
                 body.avatar_url = Some(avatar_url.unwrap_or_default().to_owned());
-            })
-            .on_emails(|emails| {
-                body.three_pids = Some(
-                    emails
-                        .unwrap_or_default()
-                        .iter()
-                        .map(|email| ThreePID {
-                            medium: ThreePIDMedium::Email,
-                            address: email.clone(),
-                        })
-                        .collect(),
-                );
             });
 
         let mxid = self.mxid(request.localpart());
         let encoded_mxid = urlencoding::encode(&mxid);
+
+        let mut wanted_emails = None;
+        request.on_emails(|emails| {
+            wanted_emails = Some(emails.unwrap_or_default().to_owned());
+        });
+        if let Some(wanted_emails) = wanted_emails {
+            let current = self.query_three_pids(&encoded_mxid).await?;
+            body.three_pids = Some(sync_three_pids(current, &wanted_emails));
+        }
+
         let response = self
             .put(&format!("_synapse/admin/v2/users/{encoded_mxid}"))
             .json(&body)
@@ -686,3 +739,60 @@ impl HomeserverConnection for SynapseConnection {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(address: &str) -> ThreePID {
+        ThreePID {
+            medium: ThreePIDMedium::Email,
+            address: address.to_owned(),
+        }
+    }
+
+    fn msisdn(address: &str) -> ThreePID {
+        ThreePID {
+            medium: ThreePIDMedium::Msisdn,
+            address: address.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_sync_three_pids() {
+        // New emails are appended to the existing threepids
+        let three_pids = sync_three_pids(
+            vec![msisdn("33612345678")],
+            &["alice@example.org".to_owned(), "bob@example.org".to_owned()],
+        );
+        assert_eq!(
+            three_pids,
+            vec![
+                msisdn("33612345678"),
+                email("alice@example.org"),
+                email("bob@example.org"),
+            ]
+        );
+
+        // Stale emails are removed, the others are kept in place
+        let three_pids = sync_three_pids(
+            three_pids,
+            &[
+                "charlie@example.org".to_owned(),
+                "bob@example.org".to_owned(),
+            ],
+        );
+        assert_eq!(
+            three_pids,
+            vec![
+                msisdn("33612345678"),
+                email("bob@example.org"),
+                email("charlie@example.org"),
+            ]
+        );
+
+        // Removing all the emails keeps the other threepids
+        let three_pids = sync_three_pids(three_pids, &[]);
+        assert_eq!(three_pids, vec![msisdn("33612345678")]);
+    }
+}
@@ -50,6 +50,36 @@ impl HomeserverConnection {
     pub async fn reserve_localpart(&self, localpart: &'static str) {
         self.reserved_localparts.write().await.insert(localpart);
     }
+
+    /// Get the emails currently known for the given user, if any.
+    ///
+    /// Returns `None` if the user doesn't exist or no emails were ever set.
+    pub async fn emails(&self, localpart: &str) -> Option<Vec<String>> {
+        let mxid = crate::HomeserverConnection::mxid(self, localpart);
+        let users = self.users.read().await;
+        users.get(&mxid)?.emails.clone()
+    }
+}
+
+/// Apply the wanted set of emails on top of the current ones, removing the
+/// stale ones and appending the new ones, while keeping the order of the emails
+/// which are kept.
+fn sync_emails(current: Option<&[String]>, wanted: &[String]) -> Vec<String> {
+    let wanted_set: HashSet<&str> = wanted.iter().map(String::as_str).collect();
+    let mut emails: Vec<String> = current
+        .unwrap_or_default()
+        .iter()
+        .filter(|email| wanted_set.contains(email.as_str()))
+        .cloned()
+        .collect();
+
+    for email in wanted {
+        if !emails.contains(email) {
+            emails.push(email.clone());
+        }
+    }
+
+    emails
 }
 
 #[async_trait]
@@ -93,7 +123,7 @@ impl crate::HomeserverConnection for HomeserverConnection {
         );
 
         request.on_emails(|emails| {
-            user.emails = emails.map(ToOwned::to_owned);
+            user.emails = emails.map(|emails| sync_emails(user.emails.as_deref(), emails));
         });
 
         request.on_displayname(|displayname| {
@@ -279,4 +309,62 @@ mod tests {
         conn.reserve_localpart("alice").await;
         assert!(!conn.is_localpart_available("alice").await.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_mock_connection_emails() {
+        let conn = HomeserverConnection::new("example.org");
+
+        // Provisioning without emails doesn't set any
+        let request = ProvisionRequest::new("test", "test");
+        conn.provision_user(&request).await.unwrap();
+        assert_eq!(conn.emails("test").await, None);
+
+        let request = ProvisionRequest::new("test", "test").set_emails(vec![
+            "alice@example.org".to_owned(),
+            "bob@example.org".to_owned(),
+        ]);
+        conn.provision_user(&request).await.unwrap();
+        assert_eq!(
+            conn.emails("test").await,
+            Some(vec![
+                "alice@example.org".to_owned(),
+                "bob@example.org".to_owned()
+            ])
+        );
+
+        // Adding one and removing another keeps the existing ones in place
+        let request = ProvisionRequest::new("test", "test").set_emails(vec![
+            "charlie@example.org".to_owned(),
+            "bob@example.org".to_owned(),
+        ]);
+        conn.provision_user(&request).await.unwrap();
+        assert_eq!(
+            conn.emails("test").await,
+            Some(vec![
+                "bob@example.org".to_owned(),
+                "charlie@example.org".to_owned()
+            ])
+        );
+
+        // Not touching the emails leaves them as they are
+        let request = ProvisionRequest::new("test", "test").set_displayname("Test".to_owned());
+        conn.provision_user(&request).await.unwrap();
+        assert_eq!(
+            conn.emails("test").await,
+            Some(vec![
+                "bob@example.org".to_owned(),
+                "charlie@example.org".to_owned()
+            ])
+        );
+
+        // Removing all of them
+        let request = ProvisionRequest::new("test", "test").set_emails(Vec::new());
+        conn.provision_user(&request).await.unwrap();
+        assert_eq!(conn.emails("test").await, Some(Vec::new()));
+
+        // Unsetting them
+        let request = ProvisionRequest::new("test", "test").unset_emails();
+        conn.provision_user(&request).await.unwrap();
+        assert_eq!(conn.emails("test").await, None);
+    }
 }
@@ -204,6 +204,14 @@ pub enum ClientErrorCode {
     /// From [OpenID Connect Core 1.0](https://openid.net/specs/openid-connect-core-1_0.html#AuthError).
     RegistrationNotSupported,
 
+    /// `unmet_authentication_requirements`
+    ///
+    /// The authorization server is unable to meet the requirements of the
+    /// client for the authentication of the end-user.
+    ///
+    /// From [OpenID Connect Unmet Authentication Requirements 1.0](https://openid.net/specs/openid-connect-unmet-authentication-requirements-1_0.html).
+    UnmetAuthenticationRequirements,
+
     /// `invalid_redirect_uri`
     ///
     /// The value of one or more redirection URIs is invalid.
@@ -291,6 +299,9 @@ impl core::fmt::Display for ClientErrorCode {
             ClientErrorCode::RequestNotSupported => f.write_str("request_not_supported"),
             ClientErrorCode::RequestUriNotSupported => f.write_str("request_uri_not_supported"),
             ClientErrorCode::RegistrationNotSupported => f.write_str("registration_not_supported"),
+            ClientErrorCode::UnmetAuthenticationRequirements => {
+                f.write_str("unmet_authentication_requirements")
+            }
             ClientErrorCode::InvalidRedirectUri => f.write_str("invalid_redirect_uri"),
             ClientErrorCode::InvalidClientMetadata => f.write_str("invalid_client_metadata"),
             ClientErrorCode::AuthorizationPending => f.write_str("authorization_pending"),
@@ -326,6 +337,9 @@ impl core::str::FromStr for ClientErrorCode {
             "request_not_supported" => Ok(ClientErrorCode::RequestNotSupported),
             "request_uri_not_supported" => Ok(ClientErrorCode::RequestUriNotSupported),
             "registration_not_supported" => Ok(ClientErrorCode::RegistrationNotSupported),
+            "unmet_authentication_requirements" => {
+                Ok(ClientErrorCode::UnmetAuthenticationRequirements)
+            }
             "invalid_redirect_uri" => Ok(ClientErrorCode::InvalidRedirectUri),
             "invalid_client_metadata" => Ok(ClientErrorCode::InvalidClientMetadata),
             "authorization_pending" => Ok(ClientErrorCode::AuthorizationPending),
@@ -405,6 +419,10 @@ impl ClientErrorCode {
             ClientErrorCode::RegistrationNotSupported => {
                 "The provider does not support use of the registration parameter."
             }
+            ClientErrorCode::UnmetAuthenticationRequirements => {
+                "The provider is unable to meet the requirements for the authentication \
+                of the End-User."
+            }
             ClientErrorCode::InvalidRedirectUri => {
                 "The value of one or more redirection URIs is invalid."
             }
@@ -508,6 +526,10 @@ mod tests {
             serde_json::to_string(&ClientErrorCode::RegistrationNotSupported).unwrap(),
             "\"registration_not_supported\""
         );
+        assert_eq!(
+            serde_json::to_string(&ClientErrorCode::UnmetAuthenticationRequirements).unwrap(),
+            "\"unmet_authentication_requirements\""
+        );
         assert_eq!(
             serde_json::to_string(&ClientErrorCode::InvalidRedirectUri).unwrap(),
             "\"invalid_redirect_uri\""
@@ -602,6 +624,11 @@ mod tests {
             serde_json::from_str::<ClientErrorCode>("\"registration_not_supported\"").unwrap(),
             ClientErrorCode::RegistrationNotSupported
         );
+        assert_eq!(
+            serde_json::from_str::<ClientErrorCode>("\"unmet_authentication_requirements\"")
+                .unwrap(),
+            ClientErrorCode::UnmetAuthenticationRequirements
+        );
         assert_eq!(
             serde_json::from_str::<ClientErrorCode>("\"invalid_redirect_uri\"").unwrap(),
             ClientErrorCode::InvalidRedirectUri
@@ -596,6 +596,13 @@ pub struct ProviderMetadata {
     /// Defaults to `false`.
     pub require_pushed_authorization_requests: Option<bool>,
 
+    /// JSON array containing a list of the JWS signing algorithms (`alg`
+    /// values) supported by the authorization endpoint to sign the response,
+    /// when using one of the [JARM] response modes.
+    ///
+    /// [JARM]: https://openid.net/specs/oauth-v2-jarm.html
+    pub authorization_signing_alg_values_supported: Option<Vec<JsonWebSignatureAlg>>,
+
     /// Array containing the list of prompt values that this OP supports.
     ///
     /// This field can be used to detect if the OP supports the [prompt
@@ -28,6 +28,7 @@ use crate::{
     oidc::{ApplicationType, SubjectType},
     requests::GrantType,
     response_type::ResponseType,
+    scope::Scope,
 };
 
 impl<T> Localized<T> {
@@ -96,6 +97,7 @@ pub struct ClientMetadataSerdeHelper {
     redirect_uris: Option<Vec<Url>>,
     response_types: Option<Vec<ResponseType>>,
     grant_types: Option<Vec<GrantType>>,
+    scope: Option<Scope>,
     application_type: Option<ApplicationType>,
     contacts: Option<Vec<String>>,
     jwks_uri: Option<Url>,
@@ -112,6 +114,7 @@ pub struct ClientMetadataSerdeHelper {
     userinfo_signed_response_alg: Option<JsonWebSignatureAlg>,
     userinfo_encrypted_response_alg: Option<JsonWebEncryptionAlg>,
     userinfo_encrypted_response_enc: Option<JsonWebEncryptionEnc>,
+    authorization_signed_response_alg: Option<JsonWebSignatureAlg>,
     request_object_signing_alg: Option<JsonWebSignatureAlg>,
     request_object_encryption_alg: Option<JsonWebEncryptionAlg>,
     request_object_encryption_enc: Option<JsonWebEncryptionEnc>,
@@ -143,6 +146,7 @@ impl From<ClientMetadata> for ClientMetadataSerdeHelper {
             redirect_uris,
             response_types,
             grant_types,
+            scope,
             application_type,
             contacts,
             client_name,
@@ -164,6 +168,7 @@ impl From<ClientMetadata> for ClientMetadataSerdeHelper {
             userinfo_signed_response_alg,
             userinfo_encrypted_response_alg,
             userinfo_encrypted_response_enc,
+            authorization_signed_response_alg,
             request_object_signing_alg,
             request_object_encryption_alg,
             request_object_encryption_enc,
@@ -184,6 +189,7 @@ impl From<ClientMetadata> for ClientMetadataSerdeHelper {
             redirect_uris,
             response_types,
             grant_types,
+            scope,
             application_type,
             contacts,
             jwks_uri,
@@ -200,6 +206,7 @@ impl From<ClientMetadata> for ClientMetadataSerdeHelper {
             userinfo_signed_response_alg,
             userinfo_encrypted_response_alg,
             userinfo_encrypted_response_enc,
+            authorization_signed_response_alg,
             request_object_signing_alg,
             request_object_encryption_alg,
             request_object_encryption_enc,
@@ -231,6 +238,7 @@ impl From<ClientMetadataSerdeHelper> for ClientMetadata {
             redirect_uris,
             response_types,
             grant_types,
+            scope,
             application_type,
             contacts,
             jwks_uri,
@@ -247,6 +255,7 @@ impl From<ClientMetadataSerdeHelper> for ClientMetadata {
             userinfo_signed_response_alg,
             userinfo_encrypted_response_alg,
             userinfo_encrypted_response_enc,
+            authorization_signed_response_alg,
             request_object_signing_alg,
             request_object_encryption_alg,
             request_object_encryption_enc,
@@ -275,6 +284,7 @@ impl From<ClientMetadataSerdeHelper> for ClientMetadata {
             redirect_uris,
             response_types,
             grant_types,
+            scope,
             application_type,
             contacts,
             client_name,
@@ -296,6 +306,7 @@ impl From<ClientMetadataSerdeHelper> for ClientMetadata {
             userinfo_signed_response_alg,
             userinfo_encrypted_response_alg,
             userinfo_encrypted_response_enc,
+            authorization_signed_response_alg,
             request_object_signing_alg,
             request_object_encryption_alg,
             request_object_encryption_enc,
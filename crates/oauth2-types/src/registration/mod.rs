@@ -27,6 +27,7 @@ use crate::{
     oidc::{ApplicationType, SubjectType},
     requests::GrantType,
     response_type::ResponseType,
+    scope::Scope,
 };
 
 mod client_metadata_serde;
@@ -161,6 +162,15 @@ pub struct ClientMetadata {
     /// [token endpoint]: https://www.rfc-editor.org/rfc/rfc6749.html#section-3.2
     pub grant_types: Option<Vec<GrantType>>,
 
+    /// The [scope values] that the client can use when requesting access
+    /// tokens.
+    ///
+    /// A value ending with `*` allows all the scope tokens starting with the
+    /// same prefix. If this is not set, the client is not restricted.
+    ///
+    /// [scope values]: https://www.rfc-editor.org/rfc/rfc7591#section-2
+    pub scope: Option<Scope>,
+
     /// The kind of the application.
     ///
     /// Defaults to [`DEFAULT_APPLICATION_TYPE`].
@@ -311,6 +321,15 @@ pub struct ClientMetadata {
     /// [JWE]: http://tools.ietf.org/html/draft-ietf-jose-json-web-encryption
     pub userinfo_encrypted_response_enc: Option<JsonWebEncryptionEnc>,
 
+    /// [JWS] `alg` algorithm required for signing authorization responses, as
+    /// defined by [JARM].
+    ///
+    /// Clients must register this to use one of the `jwt` response modes.
+    ///
+    /// [JWS]: http://tools.ietf.org/html/draft-ietf-jose-json-web-signature
+    /// [JARM]: https://openid.net/specs/oauth-v2-jarm.html
+    pub authorization_signed_response_alg: Option<JsonWebSignatureAlg>,
+
     /// [JWS] `alg` algorithm that must be used for signing Request Objects sent
     /// to the provider.
     ///
@@ -537,6 +556,12 @@ impl ClientMetadata {
             )?;
         }
 
+        if self.authorization_signed_response_alg == Some(JsonWebSignatureAlg::None) {
+            return Err(ClientMetadataVerificationError::UnauthorizedSigningAlgNone(
+                "authorization",
+            ));
+        }
+
         if self.request_object_encryption_enc.is_some() {
             self.request_object_encryption_alg.as_ref().ok_or(
                 ClientMetadataVerificationError::MissingEncryptionAlg("request_object"),
@@ -918,6 +943,21 @@ pub struct ClientRegistrationResponse {
     #[serde(default)]
     #[serde_as(as = "Option<TimestampSeconds<i64>>")]
     pub client_secret_expires_at: Option<DateTime<Utc>>,
+
+    /// A token that can be used at the client configuration endpoint to
+    /// perform subsequent operations upon the client registration, as defined
+    /// in [RFC7592].
+    ///
+    /// [RFC7592]: https://www.rfc-editor.org/rfc/rfc7592.html
+    #[serde(default)]
+    pub registration_access_token: Option<String>,
+
+    /// The fully qualified URL of the client configuration endpoint for this
+    /// client, as defined in [RFC7592].
+    ///
+    /// [RFC7592]: https://www.rfc-editor.org/rfc/rfc7592.html
+    #[serde(default)]
+    pub registration_client_uri: Option<Url>,
 }
 
 #[cfg(test)]
@@ -1368,6 +1408,23 @@ mod tests {
         metadata.validate().unwrap();
     }
 
+    #[test]
+    fn validate_authorization_signed_response() {
+        let mut metadata = valid_client_metadata();
+        metadata.authorization_signed_response_alg = Some(JsonWebSignatureAlg::None);
+
+        // Err - Authorization responses can't be unsigned
+        let endpoint = assert_matches!(
+            metadata.clone().validate(),
+            Err(ClientMetadataVerificationError::UnauthorizedSigningAlgNone(endpoint)) => endpoint
+        );
+        assert_eq!(endpoint, "authorization");
+
+        // Ok - Signed with a real algorithm
+        metadata.authorization_signed_response_alg = Some(JsonWebSignatureAlg::Rs256);
+        metadata.validate().unwrap();
+    }
+
     #[test]
     fn validate_request_object_encryption() {
         let mut metadata = valid_client_metadata();
@@ -8,7 +8,12 @@
 //!
 //! [OAuth 2.0]: https://oauth.net/2/
 
-use std::{collections::HashSet, fmt, hash::Hash, num::NonZeroU32};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    hash::Hash,
+    str::FromStr,
+};
 
 use chrono::{DateTime, Duration, Utc};
 use language_tags::LanguageTag;
@@ -50,6 +55,31 @@ pub enum ResponseMode {
     /// Defined in [OAuth 2.0 Form Post Response Mode](https://openid.net/specs/oauth-v2-form-post-response-mode-1_0.html).
     FormPost,
 
+    /// Authorization Response parameters are encoded in a JWT, added to the
+    /// query string of the `redirect_uri` as the `response` parameter.
+    ///
+    /// Defined in [JWT Secured Authorization Response Mode for OAuth 2.0 (JARM)](https://openid.net/specs/oauth-v2-jarm.html).
+    QueryJwt,
+
+    /// Authorization Response parameters are encoded in a JWT, added to the
+    /// fragment of the `redirect_uri` as the `response` parameter.
+    ///
+    /// Defined in [JWT Secured Authorization Response Mode for OAuth 2.0 (JARM)](https://openid.net/specs/oauth-v2-jarm.html).
+    FragmentJwt,
+
+    /// Authorization Response parameters are encoded in a JWT, transmitted
+    /// as the `response` form value via the HTTP `POST` method.
+    ///
+    /// Defined in [JWT Secured Authorization Response Mode for OAuth 2.0 (JARM)](https://openid.net/specs/oauth-v2-jarm.html).
+    FormPostJwt,
+
+    /// Authorization Response parameters are encoded in a JWT, using the
+    /// default encoding of the response type: [`ResponseMode::QueryJwt`] for
+    /// the `code` response type, [`ResponseMode::FragmentJwt`] otherwise.
+    ///
+    /// Defined in [JWT Secured Authorization Response Mode for OAuth 2.0 (JARM)](https://openid.net/specs/oauth-v2-jarm.html).
+    Jwt,
+
     /// An unknown value.
     Unknown(String),
 }
@@ -60,6 +90,10 @@ impl core::fmt::Display for ResponseMode {
             ResponseMode::Query => f.write_str("query"),
             ResponseMode::Fragment => f.write_str("fragment"),
             ResponseMode::FormPost => f.write_str("form_post"),
+            ResponseMode::QueryJwt => f.write_str("query.jwt"),
+            ResponseMode::FragmentJwt => f.write_str("fragment.jwt"),
+            ResponseMode::FormPostJwt => f.write_str("form_post.jwt"),
+            ResponseMode::Jwt => f.write_str("jwt"),
             ResponseMode::Unknown(s) => f.write_str(s),
         }
     }
@@ -73,6 +107,10 @@ impl core::str::FromStr for ResponseMode {
             "query" => Ok(ResponseMode::Query),
             "fragment" => Ok(ResponseMode::Fragment),
             "form_post" => Ok(ResponseMode::FormPost),
+            "query.jwt" => Ok(ResponseMode::QueryJwt),
+            "fragment.jwt" => Ok(ResponseMode::FragmentJwt),
+            "form_post.jwt" => Ok(ResponseMode::FormPostJwt),
+            "jwt" => Ok(ResponseMode::Jwt),
             s => Ok(ResponseMode::Unknown(s.to_owned())),
         }
     }
@@ -204,6 +242,38 @@ impl core::str::FromStr for Prompt {
     }
 }
 
+/// Individual claims requested with the [`claims` parameter].
+///
+/// The value of each claim is either `null` or an object further describing
+/// the request, for example with an `essential` flag.
+///
+/// [`claims` parameter]: https://openid.net/specs/openid-connect-core-1_0.html#ClaimsParameter
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClaimsRequest {
+    /// Claims requested to be returned from the userinfo endpoint.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub userinfo: BTreeMap<String, serde_json::Value>,
+
+    /// Claims requested to be returned in the ID token.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub id_token: BTreeMap<String, serde_json::Value>,
+}
+
+impl FromStr for ClaimsRequest {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl fmt::Display for ClaimsRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
 /// The body of a request to the [Authorization Endpoint].
 ///
 /// [Authorization Endpoint]: https://www.rfc-editor.org/rfc/rfc6749.html#section-3.1
@@ -261,9 +331,11 @@ pub struct AuthorizationRequest {
 
     /// The allowable elapsed time in seconds since the last time the End-User
     /// was actively authenticated by the OpenID Provider.
+    ///
+    /// A value of `0` forces the End-User to authenticate again.
     #[serde(default)]
     #[serde_as(as = "Option<DisplayFromStr>")]
-    pub max_age: Option<NonZeroU32>,
+    pub max_age: Option<u32>,
 
     /// End-User's preferred languages and scripts for the user interface.
     #[serde_as(as = "Option<StringWithSeparator::<SpaceSeparator, LanguageTag>>")]
@@ -284,6 +356,11 @@ pub struct AuthorizationRequest {
     #[serde(default)]
     pub acr_values: Option<HashSet<String>>,
 
+    /// Individual claims requested to be returned, encoded as JSON.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub claims: Option<ClaimsRequest>,
+
     /// A JWT that contains the request's parameter values, called a [Request
     /// Object].
     ///
@@ -323,6 +400,7 @@ impl AuthorizationRequest {
             id_token_hint: None,
             login_hint: None,
             acr_values: None,
+            claims: None,
             request: None,
             request_uri: None,
             registration: None,
@@ -343,6 +421,7 @@ impl fmt::Debug for AuthorizationRequest {
             .field("ui_locales", &self.ui_locales)
             .field("login_hint", &self.login_hint)
             .field("acr_values", &self.acr_values)
+            .field("claims", &self.claims)
             .field("request", &self.request)
             .field("request_uri", &self.request_uri)
             .field("registration", &self.registration)
@@ -674,6 +753,12 @@ pub struct AccessTokenResponse {
 
     /// The scope of the access token.
     pub scope: Option<Scope>,
+
+    /// MAS extension: the Matrix session this token belongs to.
+    ///
+    /// Only present for sessions with a device scope, when enabled on the
+    /// server.
+    pub matrix_session: Option<MatrixSessionExtension>,
 }
 
 impl AccessTokenResponse {
@@ -687,6 +772,7 @@ impl AccessTokenResponse {
             token_type: OAuthAccessTokenType::Bearer,
             expires_in: None,
             scope: None,
+            matrix_session: None,
         }
     }
 
@@ -717,6 +803,13 @@ impl AccessTokenResponse {
         self.expires_in = Some(expires_in);
         self
     }
+
+    /// Adds the Matrix session details to an `AccessTokenResponse`.
+    #[must_use]
+    pub fn with_matrix_session(mut self, matrix_session: MatrixSessionExtension) -> Self {
+        self.matrix_session = Some(matrix_session);
+        self
+    }
 }
 
 impl fmt::Debug for AccessTokenResponse {
@@ -725,10 +818,27 @@ impl fmt::Debug for AccessTokenResponse {
             .field("token_type", &self.token_type)
             .field("expires_in", &self.expires_in)
             .field("scope", &self.scope)
+            .field("matrix_session", &self.matrix_session)
             .finish_non_exhaustive()
     }
 }
 
+/// MAS extension describing the Matrix session associated with a token.
+///
+/// It is included in the token and introspection responses for sessions
+/// carrying a Matrix device scope.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MatrixSessionExtension {
+    /// The Matrix device ID of the session.
+    pub device_id: String,
+
+    /// The fully-qualified Matrix ID of the user owning the session.
+    pub mxid: String,
+
+    /// The ID of the session in MAS.
+    pub session_id: String,
+}
+
 /// A request to the [Introspection Endpoint].
 ///
 /// [Introspection Endpoint]: https://www.rfc-editor.org/rfc/rfc7662#section-2
@@ -805,6 +915,12 @@ pub struct IntrospectionResponse {
     /// MAS extension: explicit device ID
     /// Only used for compatibility access and refresh tokens.
     pub device_id: Option<String>,
+
+    /// MAS extension: the Matrix session this token belongs to.
+    ///
+    /// Only present for OAuth 2.0 sessions with a device scope, when enabled
+    /// on the server.
+    pub matrix_session: Option<MatrixSessionExtension>,
 }
 
 /// A request to the [Revocation Endpoint].
@@ -1057,4 +1173,29 @@ mod tests {
             Prompt::Create
         );
     }
+
+    #[test]
+    fn parse_claims_request() {
+        let request: ClaimsRequest =
+            r#"{"id_token":{"email":null,"email_verified":{"essential":true}}}"#
+                .parse()
+                .unwrap();
+        assert!(request.userinfo.is_empty());
+        assert_eq!(
+            request.id_token.keys().collect::<Vec<_>>(),
+            ["email", "email_verified"]
+        );
+        assert_eq!(
+            request.id_token["email_verified"],
+            json!({"essential": true})
+        );
+
+        // It round-trips through its string representation
+        assert_eq!(
+            request.to_string().parse::<ClaimsRequest>().unwrap(),
+            request
+        );
+
+        assert!("not json".parse::<ClaimsRequest>().is_err());
+    }
 }
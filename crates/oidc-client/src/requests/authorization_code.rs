@@ -272,11 +272,12 @@ fn build_authorization_request(
             nonce: nonce.clone(),
             display,
             prompt,
-            max_age,
+            max_age: max_age.map(NonZeroU32::get),
             ui_locales,
             id_token_hint,
             login_hint,
             acr_values,
+            claims: None,
             request: None,
             request_uri: None,
             registration: None,
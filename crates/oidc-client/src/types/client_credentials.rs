@@ -88,7 +88,11 @@ pub enum ClientCredentials {
         /// The unique ID for the client.
         client_id: String,
 
-        /// The keystore used to sign the JWT
+        /// The keystore used to sign the JWT.
+        ///
+        /// If multiple keys are compatible with the signing algorithm, the last
+        /// one is used, so that keys can be rotated by adding a new one at the
+        /// end of the keystore.
         keystore: Keystore,
 
         /// The algorithm used to sign the JWT.
@@ -210,6 +214,7 @@ impl ClientCredentials {
                 let claims =
                     prepare_claims(client_id.clone(), token_endpoint.to_string(), now, rng)?;
 
+                // This picks the last compatible key, which is the newest one
                 let key = keystore
                     .signing_key_for_algorithm(signing_algorithm)
                     .ok_or(CredentialsError::NoPrivateKeyFound)?;
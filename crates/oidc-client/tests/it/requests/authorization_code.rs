@@ -210,6 +210,7 @@ async fn pass_access_token_with_authorization_code() {
                 token_type: OAuthAccessTokenType::Bearer,
                 expires_in: None,
                 scope: Some([OPENID].into_iter().collect()),
+                matrix_session: None,
             }),
         )
         .mount(&mock_server)
@@ -268,6 +269,7 @@ async fn fail_access_token_with_authorization_code_wrong_nonce() {
                 token_type: OAuthAccessTokenType::Bearer,
                 expires_in: None,
                 scope: Some([OPENID].into_iter().collect()),
+                matrix_session: None,
             }),
         )
         .mount(&mock_server)
@@ -329,6 +331,7 @@ async fn fail_access_token_with_authorization_code_no_id_token() {
                 token_type: OAuthAccessTokenType::Bearer,
                 expires_in: None,
                 scope: Some([OPENID].into_iter().collect()),
+                matrix_session: None,
             }),
         )
         .mount(&mock_server)
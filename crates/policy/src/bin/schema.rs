@@ -12,7 +12,8 @@
 use std::path::{Path, PathBuf};
 
 use mas_policy::model::{
-    AuthorizationGrantInput, ClientRegistrationInput, EmailInput, RegisterInput,
+    AccountRecoveryInput, AuthorizationGrantInput, ClientRegistrationInput, EmailInput,
+    RegisterInput,
 };
 use schemars::{JsonSchema, r#gen::SchemaSettings};
 
@@ -47,4 +48,5 @@ fn main() {
     write_schema::<ClientRegistrationInput>(output_root, "client_registration_input.json");
     write_schema::<AuthorizationGrantInput>(output_root, "authorization_grant_input.json");
     write_schema::<EmailInput>(output_root, "email_input.json");
+    write_schema::<AccountRecoveryInput>(output_root, "account_recovery_input.json");
 }
@@ -18,8 +18,9 @@ use thiserror::Error;
 use tokio::io::{AsyncRead, AsyncReadExt};
 
 pub use self::model::{
-    AuthorizationGrantInput, ClientRegistrationInput, Code as ViolationCode, EmailInput,
-    EvaluationResult, GrantType, RegisterInput, RegistrationMethod, Requester, Violation,
+    AccountRecoveryInput, AccountRecoveryMethod, AuthorizationGrantInput, ClientRegistrationInput,
+    Code as ViolationCode, EmailInput, EvaluationResult, GrantType, RegisterInput,
+    RegistrationMethod, Requester, Violation,
 };
 
 #[derive(Debug, Error)]
@@ -72,15 +73,17 @@ pub struct Entrypoints {
     pub client_registration: String,
     pub authorization_grant: String,
     pub email: String,
+    pub account_recovery: String,
 }
 
 impl Entrypoints {
-    fn all(&self) -> [&str; 4] {
+    fn all(&self) -> [&str; 5] {
         [
             self.register.as_str(),
             self.client_registration.as_str(),
             self.authorization_grant.as_str(),
             self.email.as_str(),
+            self.account_recovery.as_str(),
         ]
     }
 }
@@ -288,6 +291,19 @@ impl PolicyFactory {
         Ok(true)
     }
 
+    /// Check whether the given username is listed in the `admin_users` policy
+    /// data, which lets the user request admin scopes regardless of their
+    /// `can_request_admin` flag.
+    #[must_use]
+    pub fn is_admin_user(&self, username: &str) -> bool {
+        self.dynamic_data
+            .load()
+            .merged
+            .get("admin_users")
+            .and_then(serde_json::Value::as_array)
+            .is_some_and(|users| users.iter().any(|user| user.as_str() == Some(username)))
+    }
+
     /// Create a new policy instance.
     ///
     /// # Errors
@@ -447,6 +463,31 @@ impl Policy {
 
         Ok(res)
     }
+
+    /// Evaluate the `account_recovery` entrypoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the policy engine fails to evaluate the entrypoint.
+    #[tracing::instrument(
+        name = "policy.evaluate.account_recovery",
+        skip_all,
+        fields(
+            ?input.recovery_method,
+            %input.user.id,
+        ),
+    )]
+    pub async fn evaluate_account_recovery(
+        &mut self,
+        input: AccountRecoveryInput<'_>,
+    ) -> Result<EvaluationResult, EvaluationError> {
+        let [res]: [EvaluationResult; 1] = self
+            .instance
+            .evaluate(&mut self.store, &self.entrypoints.account_recovery, &input)
+            .await?;
+
+        Ok(res)
+    }
 }
 
 #[cfg(test)]
@@ -477,6 +518,7 @@ mod tests {
             client_registration: "client_registration/violation".to_owned(),
             authorization_grant: "authorization_grant/violation".to_owned(),
             email: "email/violation".to_owned(),
+            account_recovery: "account_recovery/violation".to_owned(),
         };
 
         let factory = PolicyFactory::load(file, data, entrypoints).await.unwrap();
@@ -488,6 +530,8 @@ mod tests {
                 registration_method: RegistrationMethod::Password,
                 username: "hello",
                 email: Some("hello@example.com"),
+                organization: None,
+                can_request_admin: None,
                 requester: Requester {
                     ip_address: None,
                     user_agent: None,
@@ -502,6 +546,8 @@ mod tests {
                 registration_method: RegistrationMethod::Password,
                 username: "hello",
                 email: Some("hello@foo.element.io"),
+                organization: None,
+                can_request_admin: None,
                 requester: Requester {
                     ip_address: None,
                     user_agent: None,
@@ -516,6 +562,8 @@ mod tests {
                 registration_method: RegistrationMethod::Password,
                 username: "hello",
                 email: Some("hello@staging.element.io"),
+                organization: None,
+                can_request_admin: None,
                 requester: Requester {
                     ip_address: None,
                     user_agent: None,
@@ -544,6 +592,7 @@ mod tests {
             client_registration: "client_registration/violation".to_owned(),
             authorization_grant: "authorization_grant/violation".to_owned(),
             email: "email/violation".to_owned(),
+            account_recovery: "account_recovery/violation".to_owned(),
         };
 
         let factory = PolicyFactory::load(file, data, entrypoints).await.unwrap();
@@ -555,6 +604,8 @@ mod tests {
                 registration_method: RegistrationMethod::Password,
                 username: "hello",
                 email: Some("hello@example.com"),
+                organization: None,
+                can_request_admin: None,
                 requester: Requester {
                     ip_address: None,
                     user_agent: None,
@@ -585,6 +636,8 @@ mod tests {
                 registration_method: RegistrationMethod::Password,
                 username: "hello",
                 email: Some("hello@example.com"),
+                organization: None,
+                can_request_admin: None,
                 requester: Requester {
                     ip_address: None,
                     user_agent: None,
@@ -595,6 +648,49 @@ mod tests {
         assert!(!res.valid());
     }
 
+    #[tokio::test]
+    async fn test_is_admin_user() {
+        let data = Data::new("example.com".to_owned()).with_rest(serde_json::json!({
+            "admin_users": ["alice"],
+        }));
+
+        #[allow(clippy::disallowed_types)]
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("..")
+            .join("policies")
+            .join("policy.wasm");
+
+        let file = tokio::fs::File::open(path).await.unwrap();
+
+        let entrypoints = Entrypoints {
+            register: "register/violation".to_owned(),
+            client_registration: "client_registration/violation".to_owned(),
+            authorization_grant: "authorization_grant/violation".to_owned(),
+            email: "email/violation".to_owned(),
+            account_recovery: "account_recovery/violation".to_owned(),
+        };
+
+        let factory = PolicyFactory::load(file, data, entrypoints).await.unwrap();
+        assert!(factory.is_admin_user("alice"));
+        assert!(!factory.is_admin_user("bob"));
+
+        // The dynamic data is merged with the list from the static data
+        factory
+            .set_dynamic_data(mas_data_model::PolicyData {
+                id: Ulid::nil(),
+                created_at: SystemTime::now().into(),
+                data: serde_json::json!({
+                    "admin_users": ["bob"],
+                }),
+            })
+            .await
+            .unwrap();
+        assert!(factory.is_admin_user("alice"));
+        assert!(factory.is_admin_user("bob"));
+        assert!(!factory.is_admin_user("charlie"));
+    }
+
     #[tokio::test]
     async fn test_big_dynamic_data() {
         let data = Data::new("example.com".to_owned());
@@ -613,6 +709,7 @@ mod tests {
             client_registration: "client_registration/violation".to_owned(),
             authorization_grant: "authorization_grant/violation".to_owned(),
             email: "email/violation".to_owned(),
+            account_recovery: "account_recovery/violation".to_owned(),
         };
 
         let factory = PolicyFactory::load(file, data, entrypoints).await.unwrap();
@@ -640,6 +737,8 @@ mod tests {
                 registration_method: RegistrationMethod::Password,
                 username: "hello",
                 email: Some("12345@example.com"),
+                organization: None,
+                can_request_admin: None,
                 requester: Requester {
                     ip_address: None,
                     user_agent: None,
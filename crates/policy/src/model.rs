@@ -11,7 +11,7 @@
 
 use std::net::IpAddr;
 
-use mas_data_model::{Client, User};
+use mas_data_model::{Client, Ulid, User};
 use oauth2_types::{registration::VerifiedClientMetadata, scope::Scope};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -107,6 +107,12 @@ impl EvaluationResult {
     pub fn valid(&self) -> bool {
         self.violations.is_empty()
     }
+
+    /// Returns the code of the first violation which has one, if any.
+    #[must_use]
+    pub fn code(&self) -> Option<Code> {
+        self.violations.iter().find_map(|violation| violation.code)
+    }
 }
 
 /// Identity of the requester
@@ -140,6 +146,16 @@ pub struct RegisterInput<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub email: Option<&'a str>,
 
+    /// The organization the user belongs to, when imported from an upstream
+    /// provider
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization: Option<&'a str>,
+
+    /// Whether the claims of the upstream provider grant the user the right to
+    /// request admin access, when the provider is configured to import it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub can_request_admin: Option<bool>,
+
     pub requester: Requester,
 }
 
@@ -187,3 +203,27 @@ pub struct EmailInput<'a> {
 
     pub requester: Requester,
 }
+
+#[derive(Serialize, Debug, JsonSchema)]
+pub enum AccountRecoveryMethod {
+    #[serde(rename = "upstream-oauth2")]
+    UpstreamOAuth2,
+}
+
+/// Input for the account recovery policy.
+#[derive(Serialize, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct AccountRecoveryInput<'a> {
+    pub recovery_method: AccountRecoveryMethod,
+
+    #[schemars(with = "std::collections::HashMap<String, serde_json::Value>")]
+    pub user: &'a User,
+
+    /// The upstream provider the user authenticated with, when recovering
+    /// through an upstream provider
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub upstream_provider_id: Option<Ulid>,
+
+    pub requester: Requester,
+}
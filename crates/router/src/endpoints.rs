@@ -30,6 +30,7 @@ pub enum PostAuthAction {
         #[serde(flatten)]
         action: Option<AccountAction>,
     },
+    RecoverAccount,
 }
 
 impl PostAuthAction {
@@ -72,6 +73,7 @@ impl PostAuthAction {
             Self::ManageAccount { action } => url_builder.redirect(&Account {
                 action: action.clone(),
             }),
+            Self::RecoverAccount => url_builder.redirect(&AccountRecoveryUpstream),
         }
     }
 }
@@ -147,6 +149,30 @@ impl SimpleRoute for OAuth2RegistrationEndpoint {
     const PATH: &'static str = "/oauth2/registration";
 }
 
+/// `GET|PUT|DELETE /oauth2/registration/{client_id}`
+#[derive(Debug, Clone)]
+pub struct OAuth2ClientConfiguration {
+    id: Ulid,
+}
+
+impl OAuth2ClientConfiguration {
+    #[must_use]
+    pub const fn new(id: Ulid) -> Self {
+        Self { id }
+    }
+}
+
+impl Route for OAuth2ClientConfiguration {
+    type Query = ();
+    fn route() -> &'static str {
+        "/oauth2/registration/{client_id}"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/oauth2/registration/{}", self.id).into()
+    }
+}
+
 /// `GET /authorize`
 #[derive(Default, Debug, Clone)]
 pub struct OAuth2AuthorizationEndpoint;
@@ -155,6 +181,14 @@ impl SimpleRoute for OAuth2AuthorizationEndpoint {
     const PATH: &'static str = "/authorize";
 }
 
+/// `POST /oauth2/par`
+#[derive(Default, Debug, Clone)]
+pub struct OAuth2PushedAuthorizationRequestEndpoint;
+
+impl SimpleRoute for OAuth2PushedAuthorizationRequestEndpoint {
+    const PATH: &'static str = "/oauth2/par";
+}
+
 /// `POST /oauth2/end_session`
 #[derive(Default, Debug, Clone)]
 pub struct OAuth2EndSession;
@@ -179,6 +213,22 @@ impl SimpleRoute for Healthcheck {
     const PATH: &'static str = "/health";
 }
 
+/// `GET /health/ready`
+#[derive(Default, Debug, Clone)]
+pub struct ReadinessCheck;
+
+impl SimpleRoute for ReadinessCheck {
+    const PATH: &'static str = "/health/ready";
+}
+
+/// `GET /frontend-config.json`
+#[derive(Default, Debug, Clone)]
+pub struct FrontendConfigEndpoint;
+
+impl SimpleRoute for FrontendConfigEndpoint {
+    const PATH: &'static str = "/frontend-config.json";
+}
+
 /// `GET|POST /login`
 #[derive(Default, Debug, Clone)]
 pub struct Login {
@@ -253,6 +303,45 @@ impl From<Option<PostAuthAction>> for Login {
     }
 }
 
+/// `GET|POST /reauth`
+#[derive(Default, Debug, Clone)]
+pub struct Reauth {
+    post_auth_action: Option<PostAuthAction>,
+}
+
+impl Reauth {
+    #[must_use]
+    pub fn and_then(action: PostAuthAction) -> Self {
+        Self {
+            post_auth_action: Some(action),
+        }
+    }
+
+    /// Get a reference to the reauth's post auth action.
+    #[must_use]
+    pub fn post_auth_action(&self) -> Option<&PostAuthAction> {
+        self.post_auth_action.as_ref()
+    }
+}
+
+impl Route for Reauth {
+    type Query = PostAuthAction;
+
+    fn route() -> &'static str {
+        "/reauth"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        self.post_auth_action.as_ref()
+    }
+}
+
+impl From<Option<PostAuthAction>> for Reauth {
+    fn from(post_auth_action: Option<PostAuthAction>) -> Self {
+        Self { post_auth_action }
+    }
+}
+
 /// `POST /logout`
 #[derive(Default, Debug, Clone)]
 pub struct Logout;
@@ -487,7 +576,7 @@ impl Route for RegisterFinish {
 }
 
 /// Actions parameters as defined by MSC2965
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "action")]
 pub enum AccountAction {
     #[serde(rename = "org.matrix.profile")]
@@ -512,6 +601,30 @@ pub enum AccountAction {
 
     #[serde(rename = "org.matrix.cross_signing_reset")]
     OrgMatrixCrossSigningReset,
+    #[serde(rename = "cross_signing_reset")]
+    CrossSigningReset,
+
+    #[serde(rename = "org.matrix.account_deactivate")]
+    OrgMatrixAccountDeactivate,
+    #[serde(rename = "account_deactivate")]
+    AccountDeactivate,
+}
+
+impl AccountAction {
+    /// The actions advertised in the `account_management_actions_supported`
+    /// server metadata. The unprefixed aliases are accepted, but not
+    /// advertised.
+    ///
+    /// This needs to be kept in sync with what is supported in the frontend,
+    /// see `frontend/src/routes/_account.index.tsx`
+    pub const SUPPORTED: &'static [&'static str] = &[
+        "org.matrix.profile",
+        "org.matrix.sessions_list",
+        "org.matrix.session_view",
+        "org.matrix.session_end",
+        "org.matrix.cross_signing_reset",
+        "org.matrix.account_deactivate",
+    ];
 }
 
 /// `GET /account/`
@@ -587,6 +700,41 @@ impl SimpleRoute for CompatLogoutAll {
     const PATH: &'static str = "/_matrix/client/{version}/logout/all";
 }
 
+/// `POST /_matrix/client/v3/account/password`
+pub struct CompatAccountPassword;
+
+impl SimpleRoute for CompatAccountPassword {
+    const PATH: &'static str = "/_matrix/client/{version}/account/password";
+}
+
+/// `GET /_matrix/client/v3/account/3pid`
+pub struct CompatAccount3pid;
+
+impl SimpleRoute for CompatAccount3pid {
+    const PATH: &'static str = "/_matrix/client/{version}/account/3pid";
+}
+
+/// `POST /_matrix/client/v3/account/3pid/add`
+pub struct CompatAccount3pidAdd;
+
+impl SimpleRoute for CompatAccount3pidAdd {
+    const PATH: &'static str = "/_matrix/client/{version}/account/3pid/add";
+}
+
+/// `POST /_matrix/client/v3/account/3pid/delete`
+pub struct CompatAccount3pidDelete;
+
+impl SimpleRoute for CompatAccount3pidDelete {
+    const PATH: &'static str = "/_matrix/client/{version}/account/3pid/delete";
+}
+
+/// `GET /_matrix/client/v3/capabilities`
+pub struct CompatCapabilities;
+
+impl SimpleRoute for CompatCapabilities {
+    const PATH: &'static str = "/_matrix/client/{version}/capabilities";
+}
+
 /// `POST /_matrix/client/v3/refresh`
 pub struct CompatRefresh;
 
@@ -746,6 +894,29 @@ impl Route for UpstreamOAuth2Link {
     }
 }
 
+/// `GET /upstream/link/{id}/username`
+pub struct UpstreamOAuth2LinkUsername {
+    id: Ulid,
+}
+
+impl UpstreamOAuth2LinkUsername {
+    #[must_use]
+    pub const fn new(id: Ulid) -> Self {
+        Self { id }
+    }
+}
+
+impl Route for UpstreamOAuth2LinkUsername {
+    type Query = ();
+    fn route() -> &'static str {
+        "/upstream/link/{link_id}/username"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/upstream/link/{}/username", self.id).into()
+    }
+}
+
 /// `POST /upstream/backchannel-logout/{id}`
 pub struct UpstreamOAuth2BackchannelLogout {
     id: Ulid,
@@ -827,10 +998,45 @@ impl SimpleRoute for OAuth2DeviceAuthorizationEndpoint {
 
 /// `GET|POST /recover`
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
-pub struct AccountRecoveryStart;
+pub struct AccountRecoveryStart {
+    email: Option<String>,
+}
 
-impl SimpleRoute for AccountRecoveryStart {
-    const PATH: &'static str = "/recover";
+impl AccountRecoveryStart {
+    /// Pre-fill the recovery form with the given email address
+    #[must_use]
+    pub fn with_email(email: String) -> Self {
+        Self { email: Some(email) }
+    }
+
+    /// Get a reference to the email address to pre-fill the form with
+    #[must_use]
+    pub fn email(&self) -> Option<&str> {
+        self.email.as_deref()
+    }
+}
+
+impl Route for AccountRecoveryStart {
+    type Query = Self;
+
+    fn route() -> &'static str {
+        "/recover"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        Some(self)
+    }
+}
+
+/// `GET|POST /recover/upstream`
+///
+/// Where users land after proving ownership of their account by
+/// authenticating with an upstream provider
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct AccountRecoveryUpstream;
+
+impl SimpleRoute for AccountRecoveryUpstream {
+    const PATH: &'static str = "/recover/upstream";
 }
 
 /// `GET|POST /recover/progress/{session_id}`
@@ -857,6 +1063,67 @@ impl Route for AccountRecoveryProgress {
     }
 }
 
+/// `GET|POST /email-change/{ticket}`
+///
+/// Where users land from the link sent to their previous email address, to
+/// confirm or cancel a change of their email address
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct EmailChange {
+    ticket: String,
+}
+
+impl EmailChange {
+    #[must_use]
+    pub fn new(ticket: String) -> Self {
+        Self { ticket }
+    }
+}
+
+impl Route for EmailChange {
+    type Query = ();
+    fn route() -> &'static str {
+        "/email-change/{ticket}"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/email-change/{}", self.ticket).into()
+    }
+}
+
+/// `GET|POST /revoke-session?token=:token`
+///
+/// Where users land from the link in the notification sent when a new session
+/// is created on their account, to sign that session out
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct RevokeSession {
+    token: String,
+}
+
+impl RevokeSession {
+    #[must_use]
+    pub fn new(token: String) -> Self {
+        Self { token }
+    }
+
+    /// Get a reference to the signed revocation token
+    #[must_use]
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+impl Route for RevokeSession {
+    type Query = Self;
+
+    fn route() -> &'static str {
+        "/revoke-session"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        Some(self)
+    }
+}
+
 /// `GET /account/password/recovery?ticket=:ticket`
 /// Rendered by the React frontend
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
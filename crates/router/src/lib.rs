@@ -32,6 +32,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_account_actions() {
+        let parse = |query: &str| serde_urlencoded::from_str::<AccountAction>(query).unwrap();
+
+        assert_eq!(
+            parse("action=org.matrix.profile"),
+            AccountAction::OrgMatrixProfile
+        );
+        assert_eq!(
+            parse("action=session_end&device_id=ABCDEF"),
+            AccountAction::SessionEnd {
+                device_id: "ABCDEF".to_owned()
+            }
+        );
+        assert_eq!(
+            parse("action=org.matrix.session_end&device_id=ABCDEF"),
+            AccountAction::OrgMatrixSessionEnd {
+                device_id: "ABCDEF".to_owned()
+            }
+        );
+        assert_eq!(
+            parse("action=cross_signing_reset"),
+            AccountAction::CrossSigningReset
+        );
+        assert_eq!(
+            parse("action=org.matrix.cross_signing_reset"),
+            AccountAction::OrgMatrixCrossSigningReset
+        );
+        assert_eq!(
+            parse("action=org.matrix.account_deactivate"),
+            AccountAction::OrgMatrixAccountDeactivate
+        );
+
+        // Every advertised action can be parsed
+        for action in AccountAction::SUPPORTED {
+            let query = format!("action={action}&device_id=ABCDEF");
+            serde_urlencoded::from_str::<AccountAction>(&query).unwrap();
+        }
+    }
+
     #[test]
     fn test_absolute_urls() {
         let base = Url::try_from("https://example.com/").unwrap();
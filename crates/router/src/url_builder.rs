@@ -17,6 +17,7 @@ pub struct UrlBuilder {
     prefix: String,
     assets_base: String,
     issuer: Url,
+    issuer_aliases: Vec<Url>,
 }
 
 impl UrlBuilder {
@@ -103,9 +104,18 @@ impl UrlBuilder {
             prefix,
             assets_base,
             issuer,
+            issuer_aliases: Vec::new(),
         }
     }
 
+    /// Set the legacy issuers which are still accepted in tokens, on top of
+    /// the canonical issuer
+    #[must_use]
+    pub fn with_issuer_aliases(mut self, issuer_aliases: Vec<Url>) -> Self {
+        self.issuer_aliases = issuer_aliases;
+        self
+    }
+
     /// Site public hostname
     ///
     /// # Panics
@@ -130,6 +140,20 @@ impl UrlBuilder {
         self.issuer.clone()
     }
 
+    /// Legacy OIDC issuers, which are accepted in tokens but not advertised
+    #[must_use]
+    pub fn oidc_issuer_aliases(&self) -> &[Url] {
+        &self.issuer_aliases
+    }
+
+    /// Check whether the given issuer is the OIDC issuer or one of its aliases
+    #[must_use]
+    pub fn is_oidc_issuer(&self, issuer: &str) -> bool {
+        std::iter::once(&self.issuer)
+            .chain(&self.issuer_aliases)
+            .any(|candidate| candidate.as_str() == issuer)
+    }
+
     /// OIDC discovery document URL
     #[must_use]
     pub fn oidc_discovery(&self) -> Url {
@@ -142,6 +166,12 @@ impl UrlBuilder {
         self.absolute_url_for(&crate::endpoints::OAuth2AuthorizationEndpoint)
     }
 
+    /// OAuth 2.0 pushed authorization request endpoint
+    #[must_use]
+    pub fn oauth_pushed_authorization_request_endpoint(&self) -> Url {
+        self.absolute_url_for(&crate::endpoints::OAuth2PushedAuthorizationRequestEndpoint)
+    }
+
     /// OAuth 2.0 token endpoint
     #[must_use]
     pub fn oauth_token_endpoint(&self) -> Url {
@@ -243,6 +273,12 @@ impl UrlBuilder {
     pub fn account_recovery_link(&self, ticket: String) -> Url {
         self.absolute_url_for(&crate::endpoints::AccountRecoveryFinish::new(ticket))
     }
+
+    /// Link to confirm or cancel a change of email address
+    #[must_use]
+    pub fn email_change_link(&self, ticket: String) -> Url {
+        self.absolute_url_for(&crate::endpoints::EmailChange::new(ticket))
+    }
 }
 
 #[cfg(test)]
@@ -308,4 +344,27 @@ mod tests {
         let uri = builder.absolute_url_for(&crate::endpoints::OAuth2AuthorizationEndpoint);
         assert_eq!(uri.as_str(), "https://example.com/foo/authorize");
     }
+
+    #[test]
+    fn test_issuer_aliases() {
+        let builder =
+            super::UrlBuilder::new(url::Url::parse("https://example.com/").unwrap(), None, None);
+        assert!(builder.is_oidc_issuer("https://example.com/"));
+        assert!(!builder.is_oidc_issuer("https://legacy.example.com/"));
+
+        let builder = builder.with_issuer_aliases(vec![
+            url::Url::parse("https://legacy.example.com/").unwrap(),
+        ]);
+        assert!(builder.is_oidc_issuer("https://example.com/"));
+        assert!(builder.is_oidc_issuer("https://legacy.example.com/"));
+        assert!(!builder.is_oidc_issuer("https://legacy.example.com"));
+        assert!(!builder.is_oidc_issuer("https://other.example.com/"));
+
+        // The canonical issuer is still the one advertised
+        assert_eq!(builder.oidc_issuer().as_str(), "https://example.com/");
+        assert_eq!(
+            builder.oidc_discovery().as_str(),
+            "https://example.com/.well-known/openid-configuration"
+        );
+    }
 }
@@ -0,0 +1,26 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- A capped history of failed login attempts, for support purposes.
+-- Attempts are only recorded when the user could be resolved, so that we never
+-- store arbitrary usernames typed by unknown requesters.
+CREATE TABLE user_login_failures (
+    user_login_failure_id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL
+        REFERENCES users(user_id) ON DELETE CASCADE,
+
+    -- Where the attempt came from, either 'compat' or 'interactive'
+    origin TEXT NOT NULL,
+
+    -- The category of reason why the attempt failed
+    reason TEXT NOT NULL,
+
+    ip_address INET,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- Used to list the most recent failures of a user, and to prune old entries
+CREATE INDEX user_login_failures_user_id_idx
+    ON user_login_failures (user_id, user_login_failure_id DESC);
@@ -0,0 +1,12 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- The registration access token, used by dynamically registered clients to
+-- manage their registration, as per RFC 7592.
+-- Like personal access tokens, we only store the SHA256 of the token.
+ALTER TABLE oauth2_clients
+  ADD COLUMN registration_access_token_sha256 BYTEA UNIQUE
+    -- A SHA256 hash is 32 bytes long
+    CHECK (octet_length(registration_access_token_sha256) = 32);
@@ -0,0 +1,25 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Authorization requests pushed by clients, as per RFC 9126
+CREATE TABLE "oauth2_pushed_authorization_requests" (
+    "oauth2_pushed_authorization_request_id" UUID NOT NULL
+        PRIMARY KEY,
+
+    -- The client which pushed the request
+    "oauth2_client_id" UUID NOT NULL
+        REFERENCES "oauth2_clients" ("oauth2_client_id")
+        ON DELETE CASCADE,
+
+    -- The authorization request parameters, form-encoded
+    "parameters" TEXT NOT NULL,
+
+    "created_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+    "expires_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    -- When the request was used on the authorization endpoint. It can only be
+    -- used once.
+    "consumed_at" TIMESTAMP WITH TIME ZONE
+);
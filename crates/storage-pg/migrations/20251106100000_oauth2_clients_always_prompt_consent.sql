@@ -0,0 +1,9 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Whether users should always be asked for consent for this client, even if
+-- they previously chose to remember their decision.
+ALTER TABLE oauth2_clients
+  ADD COLUMN always_prompt_consent BOOLEAN NOT NULL DEFAULT FALSE;
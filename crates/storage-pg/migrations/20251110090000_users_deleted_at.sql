@@ -0,0 +1,9 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- When the user was deleted. Deleted users are kept around for a retention
+-- period, during which they can be restored, before being purged.
+ALTER TABLE users
+  ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
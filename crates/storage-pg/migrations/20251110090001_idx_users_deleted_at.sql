@@ -0,0 +1,10 @@
+-- no-transaction
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Used by the purge job to find users whose retention period is over
+CREATE INDEX CONCURRENTLY IF NOT EXISTS users_deleted_at_idx
+  ON users (deleted_at)
+  WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,9 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- The organization the user belongs to, usually imported from the upstream
+-- provider claims. This is a free-form value.
+ALTER TABLE users
+  ADD COLUMN organization TEXT;
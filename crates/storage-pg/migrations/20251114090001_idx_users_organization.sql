@@ -0,0 +1,10 @@
+-- no-transaction
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Used to filter users by organization in the admin API
+CREATE INDEX CONCURRENTLY IF NOT EXISTS users_organization_idx
+  ON users (organization)
+  WHERE organization IS NOT NULL;
@@ -0,0 +1,30 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Free-text notes left on user accounts by administrators, for support
+-- purposes. They are never exposed to the user themselves.
+CREATE TABLE user_notes (
+    user_note_id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL
+        REFERENCES users(user_id) ON DELETE CASCADE,
+
+    -- The administrator who wrote the note. This is NULL if the note was
+    -- added by a client acting on its own behalf, or if the author was
+    -- removed since.
+    author_user_id UUID
+        REFERENCES users(user_id) ON DELETE SET NULL,
+
+    content TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- Used to list the notes of a user, and to get the most recent one
+CREATE INDEX user_notes_user_id_idx
+    ON user_notes (user_id, user_note_id);
+
+-- Used to clear the author on the notes when a user is removed
+CREATE INDEX user_notes_author_user_id_idx
+    ON user_notes (author_user_id)
+    WHERE author_user_id IS NOT NULL;
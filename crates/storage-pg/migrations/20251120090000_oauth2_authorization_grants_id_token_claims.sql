@@ -0,0 +1,9 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- The claims the client asked to be included in the ID token, through the
+-- `claims` parameter of the authorization request
+ALTER TABLE oauth2_authorization_grants
+  ADD COLUMN id_token_claims TEXT[] NOT NULL DEFAULT '{}';
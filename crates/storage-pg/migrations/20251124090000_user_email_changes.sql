@@ -0,0 +1,37 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Email addresses verified by a user which are waiting to be confirmed from
+-- the address the user already had, before they are added to the account
+CREATE TABLE user_email_changes (
+    user_email_change_id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL
+        REFERENCES users(user_id) ON DELETE CASCADE,
+    user_email_authentication_id UUID NOT NULL
+        REFERENCES user_email_authentications(user_email_authentication_id)
+        ON DELETE CASCADE,
+
+    -- The new email address
+    email TEXT NOT NULL,
+
+    -- The address which was asked to confirm the change
+    previous_email TEXT NOT NULL,
+
+    -- The secret sent in the links of the confirmation email
+    ticket TEXT NOT NULL UNIQUE,
+
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    confirmed_at TIMESTAMP WITH TIME ZONE,
+    cancelled_at TIMESTAMP WITH TIME ZONE
+);
+
+-- Used to remove the changes when a user is removed
+CREATE INDEX user_email_changes_user_id_idx
+    ON user_email_changes (user_id);
+
+-- Used to remove the changes when an email authentication is removed
+CREATE INDEX user_email_changes_user_email_authentication_id_idx
+    ON user_email_changes (user_email_authentication_id);
@@ -0,0 +1,21 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Reports of the reconciliations comparing the users in MAS with their state
+-- on the homeserver
+CREATE TABLE provisioning_reports (
+    provisioning_report_id UUID NOT NULL PRIMARY KEY,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    -- Whether the reconciliation fixes the discrepancies it can fix
+    fix BOOLEAN NOT NULL,
+
+    -- Set once the reconciliation finished
+    completed_at TIMESTAMP WITH TIME ZONE,
+    users_checked BIGINT NOT NULL DEFAULT 0,
+
+    -- The list of discrepancies found
+    discrepancies JSONB NOT NULL DEFAULT '[]'
+);
@@ -0,0 +1,10 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Per-user override of the maximum number of active sessions with a device.
+-- If null, the limit from the configuration applies.
+ALTER TABLE users
+  ADD COLUMN max_sessions INTEGER
+  CHECK (max_sessions > 0);
@@ -0,0 +1,10 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- The user a job is about, so that the jobs of a user can be listed. Jobs
+-- scheduled before this column was added don't reference their user.
+ALTER TABLE queue_jobs
+  ADD COLUMN user_id UUID
+  REFERENCES users (user_id) ON DELETE SET NULL;
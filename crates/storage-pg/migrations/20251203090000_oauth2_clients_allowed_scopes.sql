@@ -0,0 +1,9 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- The scopes a client is allowed to request. NULL means the client is not
+-- restricted.
+ALTER TABLE oauth2_clients
+  ADD COLUMN allowed_scopes TEXT[];
@@ -0,0 +1,11 @@
+-- no-transaction
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Used to report on the registrations completed in a time window in the admin
+-- API
+CREATE INDEX CONCURRENTLY IF NOT EXISTS user_registrations_completed_at_idx
+  ON user_registrations (completed_at)
+  WHERE completed_at IS NOT NULL;
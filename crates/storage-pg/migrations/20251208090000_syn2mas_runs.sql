@@ -0,0 +1,25 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Records of the syn2mas migrations which ran against this database
+CREATE TABLE syn2mas_runs (
+    syn2mas_run_id UUID NOT NULL PRIMARY KEY,
+    started_at TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    -- Whether the migration ran in dry-run mode, in which case the migrated
+    -- data was discarded
+    dry_run BOOLEAN NOT NULL,
+
+    -- Set once the migration finished. A run which never finished was
+    -- interrupted, and was superseded by the next one.
+    finished_at TIMESTAMP WITH TIME ZONE,
+    user_count BIGINT,
+    compat_session_count BIGINT
+);
+
+-- The syn2mas run which migrated the user from Synapse, if any
+ALTER TABLE users
+  ADD COLUMN syn2mas_run_id UUID
+  REFERENCES syn2mas_runs (syn2mas_run_id);
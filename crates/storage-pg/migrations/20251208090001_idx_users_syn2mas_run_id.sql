@@ -0,0 +1,10 @@
+-- no-transaction
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Used to filter migrated users in the admin API
+CREATE INDEX CONCURRENTLY IF NOT EXISTS users_syn2mas_run_id_idx
+  ON users (syn2mas_run_id)
+  WHERE syn2mas_run_id IS NOT NULL;
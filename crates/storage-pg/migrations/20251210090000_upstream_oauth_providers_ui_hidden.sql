@@ -0,0 +1,9 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Whether the provider is hidden from the login and registration pages. Hidden
+-- providers can still be used through a direct link.
+ALTER TABLE upstream_oauth_providers
+  ADD COLUMN ui_hidden BOOLEAN NOT NULL DEFAULT FALSE;
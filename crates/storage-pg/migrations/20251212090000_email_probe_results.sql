@@ -0,0 +1,20 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Results of the periodic checks of the email delivery
+CREATE TABLE email_probe_results (
+    email_probe_result_id UUID NOT NULL PRIMARY KEY,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    -- Either 'message' if a probe email was sent, or 'connection' if only the
+    -- connection to the mail server was checked
+    kind TEXT NOT NULL,
+
+    -- How long the check took, in milliseconds
+    latency_ms BIGINT NOT NULL,
+
+    -- Why the check failed, NULL if it succeeded
+    error TEXT
+);
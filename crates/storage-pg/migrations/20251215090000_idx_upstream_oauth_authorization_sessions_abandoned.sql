@@ -0,0 +1,11 @@
+-- no-transaction
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Used to find the unfinished upstream authorization sessions to clean up
+CREATE INDEX CONCURRENTLY IF NOT EXISTS
+  upstream_oauth_authorization_sessions_abandoned_idx
+  ON upstream_oauth_authorization_sessions (created_at)
+  WHERE completed_at IS NULL;
@@ -0,0 +1,9 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- The language the user chose for the pages and emails sent to them.
+-- If null, the language negotiated with the browser is used.
+ALTER TABLE users
+  ADD COLUMN locale TEXT;
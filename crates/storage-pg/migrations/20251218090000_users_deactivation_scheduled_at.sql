@@ -0,0 +1,10 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- When the user asked for their own account to be deactivated, the time at
+-- which the grace period ends and the deactivation is carried out.
+-- If null, no deactivation is pending.
+ALTER TABLE users
+  ADD COLUMN deactivation_scheduled_at TIMESTAMP WITH TIME ZONE;
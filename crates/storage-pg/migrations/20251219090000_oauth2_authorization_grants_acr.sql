@@ -0,0 +1,13 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- The Authentication Context Class References requested by the client through
+-- the `acr_values` parameter, and the one satisfied by the user when the grant
+-- was fulfilled.
+-- The `max_age` column already exists since the initial schema, but was never
+-- populated until now.
+ALTER TABLE oauth2_authorization_grants
+  ADD COLUMN acr_values TEXT[] NOT NULL DEFAULT '{}',
+  ADD COLUMN acr TEXT;
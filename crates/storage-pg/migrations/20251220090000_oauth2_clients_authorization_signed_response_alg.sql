@@ -0,0 +1,9 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- The algorithm used to sign authorization responses for the JWT response
+-- modes. NULL means the client can't use those response modes.
+ALTER TABLE oauth2_clients
+  ADD COLUMN authorization_signed_response_alg TEXT;
@@ -0,0 +1,11 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- When the upstream provider answered the authorization request with an
+-- error, like the user cancelling the sign-in, the time at which the callback
+-- was received and the error code the provider sent.
+ALTER TABLE upstream_oauth_authorization_sessions
+  ADD COLUMN failed_at TIMESTAMP WITH TIME ZONE,
+  ADD COLUMN error_code TEXT;
@@ -0,0 +1,17 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Record how the user authenticated when OAuth 2.0 and compatibility sessions
+-- were established. Existing sessions are marked as 'unknown'.
+--
+-- The upstream provider ID is deliberately not a foreign key, so that the
+-- information is kept for audits even if the provider is removed.
+ALTER TABLE oauth2_sessions
+  ADD COLUMN authenticated_by TEXT NOT NULL DEFAULT 'unknown',
+  ADD COLUMN authenticated_by_upstream_oauth_provider_id UUID;
+
+ALTER TABLE compat_sessions
+  ADD COLUMN authenticated_by TEXT NOT NULL DEFAULT 'unknown',
+  ADD COLUMN authenticated_by_upstream_oauth_provider_id UUID;
@@ -0,0 +1,9 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- An identifier set by an external provisioning system to correlate its
+-- records with MAS users. It is opaque to MAS.
+ALTER TABLE users
+  ADD COLUMN external_id TEXT;
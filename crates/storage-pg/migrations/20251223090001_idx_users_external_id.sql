@@ -0,0 +1,11 @@
+-- no-transaction
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Makes sure external IDs are unique, and used to look up users by their
+-- external ID in the admin API
+CREATE UNIQUE INDEX CONCURRENTLY IF NOT EXISTS users_external_id_idx
+  ON users (external_id)
+  WHERE external_id IS NOT NULL;
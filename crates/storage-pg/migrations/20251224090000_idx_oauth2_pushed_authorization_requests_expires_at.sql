@@ -0,0 +1,10 @@
+-- no-transaction
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+-- Please see LICENSE in the repository root for full details.
+
+-- Used to find the expired pushed authorization requests to clean up
+CREATE INDEX CONCURRENTLY IF NOT EXISTS
+  oauth2_pushed_authorization_requests_expires_at_idx
+  ON oauth2_pushed_authorization_requests (expires_at);
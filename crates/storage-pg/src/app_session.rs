@@ -8,7 +8,7 @@
 
 use async_trait::async_trait;
 use mas_data_model::{
-    Clock, CompatSession, CompatSessionState, Device, Session, SessionState, User,
+    AuthenticatedBy, Clock, CompatSession, CompatSessionState, Device, Session, SessionState, User,
 };
 use mas_storage::{
     Page, Pagination,
@@ -78,6 +78,8 @@ mod priv_ {
         pub(super) user_agent: Option<String>,
         pub(super) last_active_at: Option<DateTime<Utc>>,
         pub(super) last_active_ip: Option<IpAddr>,
+        pub(super) authenticated_by: String,
+        pub(super) authenticated_by_upstream_oauth_provider_id: Option<Uuid>,
     }
 
     impl Node<Ulid> for AppSessionLookup {
@@ -111,9 +113,21 @@ impl TryFrom<AppSessionLookup> for AppSession {
             user_agent,
             last_active_at,
             last_active_ip,
+            authenticated_by,
+            authenticated_by_upstream_oauth_provider_id,
         } = value;
 
         let user_session_id = user_session_id.map(Ulid::from);
+        let authenticated_by = AuthenticatedBy::from_parts(
+            &authenticated_by,
+            authenticated_by_upstream_oauth_provider_id.map(Ulid::from),
+        )
+        .map_err(|e| {
+            DatabaseInconsistencyError::on("sessions")
+                .column("authenticated_by")
+                .row(cursor.into())
+                .source(e)
+        })?;
 
         match (
             compat_session_id,
@@ -134,15 +148,7 @@ impl TryFrom<AppSessionLookup> for AppSession {
                 Some(is_synapse_admin),
             ) => {
                 let id = compat_session_id.into();
-                let device = device_id_opt
-                    .map(Device::try_from)
-                    .transpose()
-                    .map_err(|e| {
-                        DatabaseInconsistencyError::on("compat_sessions")
-                            .column("device_id")
-                            .row(id)
-                            .source(e)
-                    })?;
+                let device = device_id_opt.map(Device::from_trusted);
 
                 let state = match finished_at {
                     None => CompatSessionState::Valid,
@@ -161,6 +167,7 @@ impl TryFrom<AppSessionLookup> for AppSession {
                     user_agent,
                     last_active_at,
                     last_active_ip,
+                    authenticated_by,
                 };
 
                 Ok(AppSession::Compat(Box::new(session)))
@@ -202,6 +209,7 @@ impl TryFrom<AppSessionLookup> for AppSession {
                     last_active_at,
                     last_active_ip,
                     human_name,
+                    authenticated_by,
                 };
 
                 Ok(AppSession::OAuth2(Box::new(session)))
@@ -259,6 +267,27 @@ fn split_filter(
         oauth2_filter = oauth2_filter.with_last_active_after(last_active_after);
     }
 
+    if let Some(finished_after) = filter.finished_after() {
+        compat_filter = compat_filter.with_finished_after(finished_after);
+        oauth2_filter = oauth2_filter.with_finished_after(finished_after);
+    }
+
+    if let Some(created_after) = filter.created_after() {
+        compat_filter = compat_filter.with_created_after(created_after);
+        oauth2_filter = oauth2_filter.with_created_after(created_after);
+    }
+
+    if let Some(network) = filter.last_active_ip_in() {
+        compat_filter = compat_filter.with_last_active_ip_in(network);
+        oauth2_filter = oauth2_filter.with_last_active_ip_in(network);
+    }
+
+    // Compatibility sessions don't have a client, so they are excluded by the
+    // callers when filtering on one
+    if let Some(client) = filter.client() {
+        oauth2_filter = oauth2_filter.for_client(client);
+    }
+
     (compat_filter, oauth2_filter)
 }
 
@@ -333,6 +362,17 @@ impl AppSessionRepository for PgAppSessionRepository<'_> {
                 Expr::col((OAuth2Sessions::Table, OAuth2Sessions::LastActiveIp)),
                 AppSessionLookupIden::LastActiveIp,
             )
+            .expr_as(
+                Expr::col((OAuth2Sessions::Table, OAuth2Sessions::AuthenticatedBy)),
+                AppSessionLookupIden::AuthenticatedBy,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2Sessions::Table,
+                    OAuth2Sessions::AuthenticatedByUpstreamOAuthProviderId,
+                )),
+                AppSessionLookupIden::AuthenticatedByUpstreamOauthProviderId,
+            )
             .from(OAuth2Sessions::Table)
             .apply_filter(oauth2_filter)
             .clone();
@@ -389,8 +429,20 @@ impl AppSessionRepository for PgAppSessionRepository<'_> {
                 Expr::col((CompatSessions::Table, CompatSessions::LastActiveIp)),
                 AppSessionLookupIden::LastActiveIp,
             )
+            .expr_as(
+                Expr::col((CompatSessions::Table, CompatSessions::AuthenticatedBy)),
+                AppSessionLookupIden::AuthenticatedBy,
+            )
+            .expr_as(
+                Expr::col((
+                    CompatSessions::Table,
+                    CompatSessions::AuthenticatedByUpstreamOAuthProviderId,
+                )),
+                AppSessionLookupIden::AuthenticatedByUpstreamOauthProviderId,
+            )
             .from(CompatSessions::Table)
             .apply_filter(compat_filter)
+            .and_where_option(filter.client().map(|_| Expr::val(false).into()))
             .clone();
 
         let common_table_expression = CommonTableExpression::new()
@@ -442,6 +494,7 @@ impl AppSessionRepository for PgAppSessionRepository<'_> {
             .expr(Expr::cust("1"))
             .from(CompatSessions::Table)
             .apply_filter(compat_filter)
+            .and_where_option(filter.client().map(|_| Expr::val(false).into()))
             .clone();
 
         let common_table_expression = CommonTableExpression::new()
@@ -660,7 +713,9 @@ mod tests {
                 None,
                 None,
                 None,
+                None,
                 Some("https://example.com/login".parse().unwrap()),
+                None,
             )
             .await
             .unwrap();
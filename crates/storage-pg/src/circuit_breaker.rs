@@ -0,0 +1,128 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! A circuit breaker around the acquisition of database connections, so that
+//! requests fail fast while the database is unreachable instead of all
+//! waiting for the pool to time out.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How many times we try to start a transaction before giving up
+pub(crate) const MAX_ATTEMPTS: u32 = 3;
+
+/// The delay before the first retry, doubled on each subsequent attempt
+const BASE_BACKOFF: Duration = Duration::from_millis(50);
+
+/// The maximum delay between two attempts
+const MAX_BACKOFF: Duration = Duration::from_millis(500);
+
+/// How long the breaker stays open once tripped, during which we don't try to
+/// acquire connections at all
+const OPEN_DURATION: Duration = Duration::from_secs(5);
+
+/// Returns the delay to wait before the given retry attempt
+///
+/// Uses a capped exponential backoff: 50ms, 100ms, 200ms, 400ms, 500ms…
+pub(crate) fn backoff(attempt: u32) -> Duration {
+    BASE_BACKOFF
+        .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// Whether the error means we lost (or could not get) a connection to the
+/// database, in which case retrying later might help
+#[must_use]
+pub fn is_connection_error(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::PoolTimedOut | sqlx::Error::Io(_) | sqlx::Error::Tls(_) => true,
+        // Class 08 is for connection exceptions, and class 57P for the server
+        // shutting down or starting up
+        sqlx::Error::Database(e) => e
+            .code()
+            .is_some_and(|code| code.starts_with("08") || code.starts_with("57P")),
+        _ => false,
+    }
+}
+
+/// A circuit breaker, shared between all the clones of a
+/// [`crate::PgRepositoryFactory`]
+#[derive(Clone, Default)]
+pub(crate) struct CircuitBreaker {
+    tripped_at: Arc<Mutex<Option<Instant>>>,
+}
+
+impl CircuitBreaker {
+    /// If the breaker is open, how long until we try to reach the database
+    /// again
+    pub fn retry_after(&self) -> Option<Duration> {
+        let tripped_at = (*self
+            .tripped_at
+            .lock()
+            .expect("circuit breaker lock poisoned"))?;
+        OPEN_DURATION
+            .checked_sub(tripped_at.elapsed())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Open the breaker, returning how long it will stay open
+    pub fn trip(&self) -> Duration {
+        let mut tripped_at = self
+            .tripped_at
+            .lock()
+            .expect("circuit breaker lock poisoned");
+        if tripped_at.is_none() {
+            tracing::warn!(
+                "Lost the connection to the database, failing requests for {OPEN_DURATION:?}"
+            );
+        }
+        *tripped_at = Some(Instant::now());
+        OPEN_DURATION
+    }
+
+    /// Close the breaker after successfully reaching the database
+    pub fn reset(&self) {
+        let mut tripped_at = self
+            .tripped_at
+            .lock()
+            .expect("circuit breaker lock poisoned");
+        if tripped_at.take().is_some() {
+            tracing::info!("Connection to the database recovered");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_millis(50));
+        assert_eq!(backoff(2), Duration::from_millis(100));
+        assert_eq!(backoff(3), Duration::from_millis(200));
+        assert_eq!(backoff(4), Duration::from_millis(400));
+        assert_eq!(backoff(5), Duration::from_millis(500));
+        assert_eq!(backoff(u32::MAX), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::default();
+        assert_eq!(breaker.retry_after(), None);
+
+        assert_eq!(breaker.trip(), OPEN_DURATION);
+        let retry_after = breaker.retry_after().unwrap();
+        assert!(retry_after <= OPEN_DURATION);
+
+        // The state is shared between clones
+        assert!(breaker.clone().retry_after().is_some());
+
+        breaker.reset();
+        assert_eq!(breaker.retry_after(), None);
+    }
+}
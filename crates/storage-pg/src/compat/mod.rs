@@ -20,7 +20,7 @@ pub use self::{
 #[cfg(test)]
 mod tests {
     use chrono::Duration;
-    use mas_data_model::{Clock, Device, clock::MockClock};
+    use mas_data_model::{AuthenticatedBy, Clock, Device, clock::MockClock};
     use mas_storage::{
         Pagination, RepositoryAccess,
         compat::{
@@ -138,6 +138,24 @@ mod tests {
             .expect("compat session not found");
         assert_eq!(session_lookup.user_agent.as_deref(), Some("Mozilla/5.0"));
 
+        // Record how the user authenticated
+        assert_eq!(session_lookup.authenticated_by, AuthenticatedBy::Unknown);
+        let session = repo
+            .compat_session()
+            .set_authenticated_by(session_lookup, AuthenticatedBy::Password)
+            .await
+            .unwrap();
+        assert_eq!(session.authenticated_by, AuthenticatedBy::Password);
+
+        // Reload the session and check again
+        let session_lookup = repo
+            .compat_session()
+            .lookup(session.id)
+            .await
+            .unwrap()
+            .expect("compat session not found");
+        assert_eq!(session_lookup.authenticated_by, AuthenticatedBy::Password);
+
         // Look up the session by device
         let list = repo
             .compat_session()
@@ -9,8 +9,8 @@ use std::net::IpAddr;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use mas_data_model::{
-    BrowserSession, Clock, CompatSession, CompatSessionState, CompatSsoLogin, CompatSsoLoginState,
-    Device, User,
+    AuthenticatedBy, BrowserSession, Clock, CompatSession, CompatSessionState, CompatSsoLogin,
+    CompatSsoLoginState, Device, User,
 };
 use mas_storage::{
     Page, Pagination,
@@ -18,7 +18,7 @@ use mas_storage::{
     pagination::Node,
 };
 use rand::RngCore;
-use sea_query::{Expr, PostgresQueryBuilder, Query, enum_def};
+use sea_query::{Alias, BinOper, Expr, PostgresQueryBuilder, Query, enum_def};
 use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
@@ -46,6 +46,19 @@ impl<'c> PgCompatSessionRepository<'c> {
     }
 }
 
+fn authenticated_by_from_parts(
+    id: Ulid,
+    kind: &str,
+    upstream_provider_id: Option<Uuid>,
+) -> Result<AuthenticatedBy, DatabaseInconsistencyError> {
+    AuthenticatedBy::from_parts(kind, upstream_provider_id.map(Ulid::from)).map_err(|e| {
+        DatabaseInconsistencyError::on("compat_sessions")
+            .column("authenticated_by")
+            .row(id)
+            .source(e)
+    })
+}
+
 struct CompatSessionLookup {
     compat_session_id: Uuid,
     device_id: Option<String>,
@@ -58,6 +71,8 @@ struct CompatSessionLookup {
     user_agent: Option<String>,
     last_active_at: Option<DateTime<Utc>>,
     last_active_ip: Option<IpAddr>,
+    authenticated_by: String,
+    authenticated_by_upstream_oauth_provider_id: Option<Uuid>,
 }
 
 impl Node<Ulid> for CompatSessionLookup {
@@ -66,8 +81,10 @@ impl Node<Ulid> for CompatSessionLookup {
     }
 }
 
-impl From<CompatSessionLookup> for CompatSession {
-    fn from(value: CompatSessionLookup) -> Self {
+impl TryFrom<CompatSessionLookup> for CompatSession {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: CompatSessionLookup) -> Result<Self, Self::Error> {
         let id = value.compat_session_id.into();
 
         let state = match value.finished_at {
@@ -75,19 +92,26 @@ impl From<CompatSessionLookup> for CompatSession {
             Some(finished_at) => CompatSessionState::Finished { finished_at },
         };
 
-        CompatSession {
+        let authenticated_by = authenticated_by_from_parts(
+            id,
+            &value.authenticated_by,
+            value.authenticated_by_upstream_oauth_provider_id,
+        )?;
+
+        Ok(CompatSession {
             id,
             state,
             user_id: value.user_id.into(),
             user_session_id: value.user_session_id.map(Ulid::from),
-            device: value.device_id.map(Device::from),
+            device: value.device_id.map(Device::from_trusted),
             human_name: value.human_name,
             created_at: value.created_at,
             is_synapse_admin: value.is_synapse_admin,
             user_agent: value.user_agent,
             last_active_at: value.last_active_at,
             last_active_ip: value.last_active_ip,
-        }
+            authenticated_by,
+        })
     }
 }
 
@@ -105,6 +129,8 @@ struct CompatSessionAndSsoLoginLookup {
     user_agent: Option<String>,
     last_active_at: Option<DateTime<Utc>>,
     last_active_ip: Option<IpAddr>,
+    authenticated_by: String,
+    authenticated_by_upstream_oauth_provider_id: Option<Uuid>,
     compat_sso_login_id: Option<Uuid>,
     compat_sso_login_token: Option<String>,
     compat_sso_login_redirect_uri: Option<String>,
@@ -130,11 +156,17 @@ impl TryFrom<CompatSessionAndSsoLoginLookup> for (CompatSession, Option<CompatSs
             Some(finished_at) => CompatSessionState::Finished { finished_at },
         };
 
+        let authenticated_by = authenticated_by_from_parts(
+            id,
+            &value.authenticated_by,
+            value.authenticated_by_upstream_oauth_provider_id,
+        )?;
+
         let session = CompatSession {
             id,
             state,
             user_id: value.user_id.into(),
-            device: value.device_id.map(Device::from),
+            device: value.device_id.map(Device::from_trusted),
             human_name: value.human_name,
             user_session_id: value.user_session_id.map(Ulid::from),
             created_at: value.created_at,
@@ -142,6 +174,7 @@ impl TryFrom<CompatSessionAndSsoLoginLookup> for (CompatSession, Option<CompatSs
             user_agent: value.user_agent,
             last_active_at: value.last_active_at,
             last_active_ip: value.last_active_ip,
+            authenticated_by,
         };
 
         match (
@@ -262,9 +295,27 @@ impl Filter for CompatSessionFilter<'_> {
                 Expr::col((CompatSessions::Table, CompatSessions::LastActiveAt))
                     .lt(last_active_before)
             }))
+            .add_option(self.finished_after().map(|finished_after| {
+                Expr::col((CompatSessions::Table, CompatSessions::FinishedAt)).gt(finished_after)
+            }))
+            .add_option(self.created_after().map(|created_after| {
+                Expr::col((CompatSessions::Table, CompatSessions::CreatedAt)).gt(created_after)
+            }))
+            .add_option(self.last_active_ip_in().map(|network| {
+                // `<<=` is the PostgreSQL "is contained within or equals" operator for
+                // network types
+                Expr::col((CompatSessions::Table, CompatSessions::LastActiveIp)).binary(
+                    BinOper::Custom("<<="),
+                    Expr::val(network.to_string()).cast_as(Alias::new("inet")),
+                )
+            }))
             .add_option(self.device().map(|device| {
                 Expr::col((CompatSessions::Table, CompatSessions::DeviceId)).eq(device.as_str())
             }))
+            .add_option(self.excluded_id().map(|excluded_id| {
+                Expr::col((CompatSessions::Table, CompatSessions::CompatSessionId))
+                    .ne(Uuid::from(excluded_id))
+            }))
     }
 }
 
@@ -296,6 +347,8 @@ impl CompatSessionRepository for PgCompatSessionRepository<'_> {
                      , user_agent
                      , last_active_at
                      , last_active_ip as "last_active_ip: IpAddr"
+                     , authenticated_by
+                     , authenticated_by_upstream_oauth_provider_id
                 FROM compat_sessions
                 WHERE compat_session_id = $1
             "#,
@@ -307,7 +360,7 @@ impl CompatSessionRepository for PgCompatSessionRepository<'_> {
 
         let Some(res) = res else { return Ok(None) };
 
-        Ok(Some(res.into()))
+        Ok(Some(res.try_into()?))
     }
 
     #[tracing::instrument(
@@ -368,6 +421,7 @@ impl CompatSessionRepository for PgCompatSessionRepository<'_> {
             user_agent: None,
             last_active_at: None,
             last_active_ip: None,
+            authenticated_by: AuthenticatedBy::Unknown,
         })
     }
 
@@ -437,6 +491,106 @@ impl CompatSessionRepository for PgCompatSessionRepository<'_> {
         Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
     }
 
+    #[tracing::instrument(
+        name = "db.compat_session.transfer_bulk",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn transfer_bulk(
+        &mut self,
+        filter: CompatSessionFilter<'_>,
+        user: &User,
+    ) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::update()
+            .table(CompatSessions::Table)
+            .value(CompatSessions::UserId, Uuid::from(user.id))
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let res = sqlx::query_with(&sql, arguments)
+            .traced()
+            .execute(&mut *self.conn)
+            .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
+    #[tracing::instrument(
+        name = "db.compat_session.delete_finished_before",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn delete_finished_before(
+        &mut self,
+        user: &User,
+        finished_before: DateTime<Utc>,
+    ) -> Result<usize, Self::Error> {
+        // Lock the sessions first, so that the set of sessions we delete the
+        // tokens of is the same as the set of sessions we delete
+        let session_ids: Vec<Uuid> = sqlx::query_scalar!(
+            r#"
+                SELECT compat_session_id
+                FROM compat_sessions
+                WHERE user_id = $1
+                  AND finished_at IS NOT NULL
+                  AND finished_at < $2
+                FOR UPDATE
+            "#,
+            Uuid::from(user.id),
+            finished_before,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        if session_ids.is_empty() {
+            return Ok(0);
+        }
+
+        sqlx::query!(
+            r#"
+                DELETE FROM compat_refresh_tokens
+                WHERE compat_session_id = ANY($1::uuid[])
+            "#,
+            &session_ids,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM compat_access_tokens
+                WHERE compat_session_id = ANY($1::uuid[])
+            "#,
+            &session_ids,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM compat_sessions
+                WHERE compat_session_id = ANY($1::uuid[])
+            "#,
+            &session_ids,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.compat_session.list",
         skip_all,
@@ -495,6 +649,17 @@ impl CompatSessionRepository for PgCompatSessionRepository<'_> {
                 Expr::col((CompatSessions::Table, CompatSessions::LastActiveIp)),
                 CompatSessionAndSsoLoginLookupIden::LastActiveIp,
             )
+            .expr_as(
+                Expr::col((CompatSessions::Table, CompatSessions::AuthenticatedBy)),
+                CompatSessionAndSsoLoginLookupIden::AuthenticatedBy,
+            )
+            .expr_as(
+                Expr::col((
+                    CompatSessions::Table,
+                    CompatSessions::AuthenticatedByUpstreamOAuthProviderId,
+                )),
+                CompatSessionAndSsoLoginLookupIden::AuthenticatedByUpstreamOauthProviderId,
+            )
             .expr_as(
                 Expr::col((CompatSsoLogins::Table, CompatSsoLogins::CompatSsoLoginId)),
                 CompatSessionAndSsoLoginLookupIden::CompatSsoLoginId,
@@ -684,4 +849,74 @@ impl CompatSessionRepository for PgCompatSessionRepository<'_> {
 
         Ok(compat_session)
     }
+
+    #[tracing::instrument(
+        name = "repository.compat_session.set_authenticated_by",
+        skip(self),
+        fields(
+            compat_session.id = %compat_session.id,
+            compat_session.authenticated_by = %authenticated_by,
+        ),
+        err,
+    )]
+    async fn set_authenticated_by(
+        &mut self,
+        mut compat_session: CompatSession,
+        authenticated_by: AuthenticatedBy,
+    ) -> Result<CompatSession, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+            UPDATE compat_sessions
+            SET authenticated_by = $2
+              , authenticated_by_upstream_oauth_provider_id = $3
+            WHERE compat_session_id = $1
+        "#,
+            Uuid::from(compat_session.id),
+            authenticated_by.as_str(),
+            authenticated_by.upstream_provider_id().map(Uuid::from),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        compat_session.authenticated_by = authenticated_by;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(compat_session)
+    }
+
+    #[tracing::instrument(
+        name = "repository.compat_session.set_device",
+        skip(self),
+        fields(
+            compat_session.id = %compat_session.id,
+            compat_session.device.id = device.as_str(),
+        ),
+        err,
+    )]
+    async fn set_device(
+        &mut self,
+        mut compat_session: CompatSession,
+        device: Device,
+    ) -> Result<CompatSession, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+            UPDATE compat_sessions
+            SET device_id = $2
+            WHERE compat_session_id = $1
+        "#,
+            Uuid::from(compat_session.id),
+            device.as_str(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        compat_session.device = Some(device);
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(compat_session)
+    }
 }
@@ -0,0 +1,234 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! A module containing the PostgreSQL implementation of the email delivery
+//! checks storage.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{Clock, EmailProbeKind, EmailProbeResult};
+use mas_storage::email_probe::EmailProbeRepository;
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, DatabaseInconsistencyError, ExecuteExt};
+
+/// An implementation of [`EmailProbeRepository`] for a PostgreSQL connection.
+pub struct PgEmailProbeRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgEmailProbeRepository<'c> {
+    /// Create a new [`PgEmailProbeRepository`] from an active PostgreSQL
+    /// connection.
+    #[must_use]
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct EmailProbeResultLookup {
+    email_probe_result_id: Uuid,
+    created_at: DateTime<Utc>,
+    kind: String,
+    latency_ms: i64,
+    error: Option<String>,
+}
+
+impl TryFrom<EmailProbeResultLookup> for EmailProbeResult {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: EmailProbeResultLookup) -> Result<Self, Self::Error> {
+        let id = Ulid::from(value.email_probe_result_id);
+
+        let kind = value.kind.parse().map_err(|e| {
+            DatabaseInconsistencyError::on("email_probe_results")
+                .column("kind")
+                .row(id)
+                .source(e)
+        })?;
+
+        let latency_ms = u64::try_from(value.latency_ms).map_err(|e| {
+            DatabaseInconsistencyError::on("email_probe_results")
+                .column("latency_ms")
+                .row(id)
+                .source(e)
+        })?;
+
+        Ok(EmailProbeResult {
+            id,
+            created_at: value.created_at,
+            kind,
+            latency_ms,
+            error: value.error,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailProbeRepository for PgEmailProbeRepository<'_> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.email_probe.latest",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn latest(&mut self) -> Result<Option<EmailProbeResult>, Self::Error> {
+        let row = sqlx::query_as!(
+            EmailProbeResultLookup,
+            r#"
+                SELECT email_probe_result_id
+                     , created_at
+                     , kind
+                     , latency_ms
+                     , error
+                FROM email_probe_results
+                ORDER BY email_probe_result_id DESC
+                LIMIT 1
+            "#
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.email_probe.add",
+        skip_all,
+        fields(
+            db.query.text,
+            email_probe_result.id,
+            email_probe_result.kind = %kind,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        kind: EmailProbeKind,
+        latency_ms: u64,
+        error: Option<String>,
+    ) -> Result<EmailProbeResult, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("email_probe_result.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+                INSERT INTO email_probe_results
+                    (email_probe_result_id, created_at, kind, latency_ms, error)
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            Uuid::from(id),
+            created_at,
+            kind.as_str(),
+            i64::try_from(latency_ms).unwrap_or(i64::MAX),
+            error.as_deref(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(EmailProbeResult {
+            id,
+            created_at,
+            kind,
+            latency_ms,
+            error,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.email_probe.prune",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn prune(&mut self, keep: usize) -> Result<usize, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM email_probe_results
+                WHERE email_probe_result_id IN (
+                    SELECT email_probe_result_id
+                    FROM email_probe_results
+                    ORDER BY email_probe_result_id DESC
+                    OFFSET $1
+                )
+            "#,
+            i64::try_from(keep).map_err(DatabaseError::to_invalid_operation)?
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res
+            .rows_affected()
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_data_model::{EmailProbeKind, clock::MockClock};
+    use mas_storage::email_probe::EmailProbeRepository;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+
+    use crate::email_probe::PgEmailProbeRepository;
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_email_probe(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut conn = pool.acquire().await.unwrap();
+        let mut repo = PgEmailProbeRepository::new(&mut conn);
+
+        // Nothing was recorded yet
+        assert_eq!(repo.latest().await.unwrap(), None);
+
+        let failure = repo
+            .add(
+                &mut rng,
+                &clock,
+                EmailProbeKind::Connection,
+                120,
+                Some("Connection refused".to_owned()),
+            )
+            .await
+            .unwrap();
+        assert!(!failure.is_success());
+        assert_eq!(repo.latest().await.unwrap().as_ref(), Some(&failure));
+
+        clock.advance(chrono::Duration::hours(1));
+        let success = repo
+            .add(&mut rng, &clock, EmailProbeKind::Message, 42, None)
+            .await
+            .unwrap();
+        assert!(success.is_success());
+        assert_eq!(repo.latest().await.unwrap().as_ref(), Some(&success));
+
+        // Only keep the latest result
+        assert_eq!(repo.prune(1).await.unwrap(), 1);
+        assert_eq!(repo.prune(1).await.unwrap(), 0);
+        assert_eq!(repo.latest().await.unwrap(), Some(success));
+    }
+}
@@ -41,6 +41,19 @@ pub enum DatabaseError {
         /// How many rows were actually affected
         actual: u64,
     },
+
+    /// An error which happened because one of the items of a batch operation
+    /// was rejected by the database, for example because it violates a
+    /// constraint
+    #[error("Item {index} of the batch was rejected by the database")]
+    BatchItem {
+        /// The index of the rejected item in the input of the batch operation
+        index: usize,
+
+        /// The underlying error from the database driver
+        #[source]
+        source: sqlx::Error,
+    },
 }
 
 impl DatabaseError {
@@ -56,6 +69,16 @@ impl DatabaseError {
         }
     }
 
+    /// The index of the item of a batch operation which was rejected by the
+    /// database, if this error is about a batch item
+    #[must_use]
+    pub const fn batch_index(&self) -> Option<usize> {
+        match self {
+            Self::BatchItem { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+
     pub(crate) fn to_invalid_operation<E: std::error::Error + Send + Sync + 'static>(e: E) -> Self {
         Self::InvalidOperation {
             source: Some(Box::new(e)),
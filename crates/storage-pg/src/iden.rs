@@ -38,8 +38,26 @@ pub enum Users {
     CreatedAt,
     LockedAt,
     DeactivatedAt,
+    DeletedAt,
     CanRequestAdmin,
     IsGuest,
+    Organization,
+    MaxSessions,
+    Locale,
+    DeactivationScheduledAt,
+    ExternalId,
+    Syn2masRunId,
+}
+
+#[derive(sea_query::Iden)]
+pub enum Syn2masRuns {
+    Table,
+    Syn2masRunId,
+    StartedAt,
+    DryRun,
+    FinishedAt,
+    UserCount,
+    CompatSessionCount,
 }
 
 #[derive(sea_query::Iden)]
@@ -51,6 +69,16 @@ pub enum UserEmails {
     CreatedAt,
 }
 
+#[derive(sea_query::Iden)]
+pub enum UserNotes {
+    Table,
+    UserNoteId,
+    UserId,
+    AuthorUserId,
+    Content,
+    CreatedAt,
+}
+
 #[derive(sea_query::Iden)]
 pub enum CompatSessions {
     Table,
@@ -65,6 +93,9 @@ pub enum CompatSessions {
     UserAgent,
     LastActiveAt,
     LastActiveIp,
+    AuthenticatedBy,
+    #[iden = "authenticated_by_upstream_oauth_provider_id"]
+    AuthenticatedByUpstreamOAuthProviderId,
 }
 
 #[derive(sea_query::Iden)]
@@ -97,6 +128,52 @@ pub enum OAuth2Sessions {
     LastActiveAt,
     LastActiveIp,
     HumanName,
+    AuthenticatedBy,
+    #[iden = "authenticated_by_upstream_oauth_provider_id"]
+    AuthenticatedByUpstreamOAuthProviderId,
+}
+
+#[derive(sea_query::Iden)]
+#[iden = "oauth2_device_code_grant"]
+pub enum OAuth2DeviceCodeGrants {
+    Table,
+    #[iden = "oauth2_device_code_grant_id"]
+    OAuth2DeviceCodeGrantId,
+    #[iden = "oauth2_client_id"]
+    OAuth2ClientId,
+    Scope,
+    DeviceCode,
+    UserCode,
+    CreatedAt,
+    ExpiresAt,
+    FulfilledAt,
+    RejectedAt,
+    ExchangedAt,
+    UserSessionId,
+    #[iden = "oauth2_session_id"]
+    OAuth2SessionId,
+    IpAddress,
+    UserAgent,
+}
+
+#[derive(sea_query::Iden)]
+#[iden = "oauth2_access_tokens"]
+pub enum OAuth2AccessTokens {
+    Table,
+    #[iden = "oauth2_session_id"]
+    OAuth2SessionId,
+    ExpiresAt,
+    RevokedAt,
+}
+
+#[derive(sea_query::Iden)]
+#[iden = "oauth2_refresh_tokens"]
+pub enum OAuth2RefreshTokens {
+    Table,
+    #[iden = "oauth2_session_id"]
+    OAuth2SessionId,
+    ConsumedAt,
+    RevokedAt,
 }
 
 #[derive(sea_query::Iden)]
@@ -167,6 +244,7 @@ pub enum UpstreamOAuthProviders {
     AuthorizationEndpointOverride,
     UserinfoEndpointOverride,
     OnBackchannelLogout,
+    UiHidden,
 }
 
 #[derive(sea_query::Iden)]
@@ -204,6 +282,8 @@ pub enum UpstreamOAuthAuthorizationSessions {
     CompletedAt,
     ConsumedAt,
     UnlinkedAt,
+    FailedAt,
+    ErrorCode,
 }
 
 #[derive(sea_query::Iden)]
@@ -218,3 +298,21 @@ pub enum UserRegistrationTokens {
     ExpiresAt,
     RevokedAt,
 }
+
+#[derive(sea_query::Iden)]
+pub enum QueueJobs {
+    Table,
+    QueueJobId,
+    QueueName,
+    Payload,
+    UserId,
+    Status,
+    Attempt,
+    CreatedAt,
+    ScheduledAt,
+    StartedAt,
+    CompletedAt,
+    FailedAt,
+    FailedReason,
+    NextAttemptId,
+}
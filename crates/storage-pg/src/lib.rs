@@ -170,17 +170,22 @@ pub mod queue;
 pub mod upstream_oauth2;
 pub mod user;
 
+pub(crate) mod circuit_breaker;
+pub(crate) mod email_probe;
 mod errors;
 pub(crate) mod filter;
 pub(crate) mod iden;
 pub(crate) mod pagination;
 pub(crate) mod policy_data;
+pub(crate) mod provisioning_report;
 pub(crate) mod repository;
+pub(crate) mod syn2mas_run;
 pub(crate) mod telemetry;
 pub(crate) mod tracing;
 
 pub(crate) use self::errors::DatabaseInconsistencyError;
 pub use self::{
+    circuit_breaker::is_connection_error,
     errors::DatabaseError,
     repository::{PgRepository, PgRepositoryFactory},
     tracing::ExecuteExt,
@@ -7,13 +7,20 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
 use mas_data_model::{AccessToken, AccessTokenState, Clock, Session};
-use mas_storage::oauth2::OAuth2AccessTokenRepository;
+use mas_storage::oauth2::{OAuth2AccessTokenRepository, OAuth2SessionFilter};
 use rand::RngCore;
+use sea_query::{Condition, Expr, PostgresQueryBuilder, Query};
+use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
 use uuid::Uuid;
 
-use crate::{DatabaseError, tracing::ExecuteExt};
+use crate::{
+    DatabaseError,
+    filter::StatementExt,
+    iden::{OAuth2AccessTokens, OAuth2Sessions},
+    tracing::ExecuteExt,
+};
 
 /// An implementation of [`OAuth2AccessTokenRepository`] for a PostgreSQL
 /// connection
@@ -214,6 +221,71 @@ impl OAuth2AccessTokenRepository for PgOAuth2AccessTokenRepository<'_> {
             .map_err(DatabaseError::to_invalid_operation)
     }
 
+    #[tracing::instrument(
+        name = "db.oauth2_access_token.revoke_bulk",
+        skip_all,
+        fields(db.query.text),
+        err,
+    )]
+    async fn revoke_bulk(
+        &mut self,
+        clock: &dyn Clock,
+        filter: OAuth2SessionFilter<'_>,
+    ) -> Result<usize, Self::Error> {
+        let revoked_at = clock.now();
+        let (sql, arguments) = Query::update()
+            .table(OAuth2AccessTokens::Table)
+            .value(OAuth2AccessTokens::RevokedAt, revoked_at)
+            .cond_where(
+                Condition::all()
+                    .add(
+                        Expr::col((OAuth2AccessTokens::Table, OAuth2AccessTokens::RevokedAt))
+                            .is_null(),
+                    )
+                    .add(
+                        Condition::any()
+                            .add(
+                                Expr::col((
+                                    OAuth2AccessTokens::Table,
+                                    OAuth2AccessTokens::ExpiresAt,
+                                ))
+                                .is_null(),
+                            )
+                            .add(
+                                Expr::col((
+                                    OAuth2AccessTokens::Table,
+                                    OAuth2AccessTokens::ExpiresAt,
+                                ))
+                                .gt(revoked_at),
+                            ),
+                    )
+                    .add(
+                        Expr::col((
+                            OAuth2AccessTokens::Table,
+                            OAuth2AccessTokens::OAuth2SessionId,
+                        ))
+                        .in_subquery(
+                            Query::select()
+                                .expr(Expr::col((
+                                    OAuth2Sessions::Table,
+                                    OAuth2Sessions::OAuth2SessionId,
+                                )))
+                                .apply_filter(filter)
+                                .from(OAuth2Sessions::Table)
+                                .take(),
+                        ),
+                    ),
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        let res = sqlx::query_with(&sql, arguments)
+            .traced()
+            .execute(&mut *self.conn)
+            .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.oauth2_access_token.mark_used",
         skip_all,
@@ -53,6 +53,10 @@ struct GrantLookup {
     code_challenge_method: Option<String>,
     login_hint: Option<String>,
     locale: Option<String>,
+    id_token_claims: Vec<String>,
+    max_age: Option<i32>,
+    acr_values: Vec<String>,
+    acr: Option<String>,
     oauth2_client_id: Uuid,
     oauth2_session_id: Option<Uuid>,
 }
@@ -149,6 +153,13 @@ impl TryFrom<GrantLookup> for AuthorizationGrant {
                 .source(e)
         })?;
 
+        let max_age = value.max_age.map(u32::try_from).transpose().map_err(|e| {
+            DatabaseInconsistencyError::on("oauth2_authorization_grants")
+                .column("max_age")
+                .row(id)
+                .source(e)
+        })?;
+
         Ok(AuthorizationGrant {
             id,
             stage,
@@ -163,6 +174,10 @@ impl TryFrom<GrantLookup> for AuthorizationGrant {
             response_type_id_token: value.response_type_id_token,
             login_hint: value.login_hint,
             locale: value.locale,
+            id_token_claims: value.id_token_claims,
+            max_age,
+            acr_values: value.acr_values,
+            acr: value.acr,
         })
     }
 }
@@ -196,6 +211,9 @@ impl OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantRepository
         response_type_id_token: bool,
         login_hint: Option<String>,
         locale: Option<String>,
+        id_token_claims: Vec<String>,
+        max_age: Option<u32>,
+        acr_values: Vec<String>,
     ) -> Result<AuthorizationGrant, Self::Error> {
         let code_challenge = code
             .as_ref()
@@ -207,6 +225,10 @@ impl OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantRepository
             .map(|p| p.challenge_method.to_string());
         let code_str = code.as_ref().map(|c| &c.code);
 
+        // The column is a 32-bit signed integer; anything above that is over 68
+        // years anyway
+        let max_age_db = max_age.map(|max_age| i32::try_from(max_age).unwrap_or(i32::MAX));
+
         let created_at = clock.now();
         let id = Ulid::from_datetime_with_source(created_at.into(), rng);
         tracing::Span::current().record("grant.id", tracing::field::display(id));
@@ -228,10 +250,14 @@ impl OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantRepository
                      authorization_code,
                      login_hint,
                      locale,
+                     id_token_claims,
+                     max_age,
+                     acr_values,
                      created_at
                 )
                 VALUES
-                    ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                    ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16,
+                     $17, $18)
             "#,
             Uuid::from(id),
             Uuid::from(client.id),
@@ -247,6 +273,9 @@ impl OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantRepository
             code_str,
             login_hint,
             locale,
+            &id_token_claims,
+            max_age_db,
+            &acr_values,
             created_at,
         )
         .traced()
@@ -267,6 +296,10 @@ impl OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantRepository
             response_type_id_token,
             login_hint,
             locale,
+            id_token_claims,
+            max_age,
+            acr_values,
+            acr: None,
         })
     }
 
@@ -301,6 +334,10 @@ impl OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantRepository
                      , code_challenge_method
                      , login_hint
                      , locale
+                     , id_token_claims
+                     , max_age
+                     , acr_values
+                     , acr
                      , oauth2_session_id
                 FROM
                     oauth2_authorization_grants
@@ -351,6 +388,10 @@ impl OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantRepository
                      , code_challenge_method
                      , login_hint
                      , locale
+                     , id_token_claims
+                     , max_age
+                     , acr_values
+                     , acr
                      , oauth2_session_id
                 FROM
                     oauth2_authorization_grants
@@ -383,6 +424,7 @@ impl OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantRepository
         &mut self,
         clock: &dyn Clock,
         session: &Session,
+        acr: Option<String>,
         grant: AuthorizationGrant,
     ) -> Result<AuthorizationGrant, Self::Error> {
         let fulfilled_at = clock.now();
@@ -391,11 +433,13 @@ impl OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantRepository
                 UPDATE oauth2_authorization_grants
                 SET fulfilled_at = $2
                   , oauth2_session_id = $3
+                  , acr = $4
                 WHERE oauth2_authorization_grant_id = $1
             "#,
             Uuid::from(grant.id),
             fulfilled_at,
             Uuid::from(session.id),
+            acr.as_deref(),
         )
         .traced()
         .execute(&mut *self.conn)
@@ -405,7 +449,7 @@ impl OAuth2AuthorizationGrantRepository for PgOAuth2AuthorizationGrantRepository
 
         // XXX: check affected rows & new methods
         let grant = grant
-            .fulfill(fulfilled_at, session)
+            .fulfill(fulfilled_at, session, acr)
             .map_err(DatabaseError::to_invalid_operation)?;
 
         Ok(grant)
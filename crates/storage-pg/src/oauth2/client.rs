@@ -10,13 +10,19 @@ use std::{
 };
 
 use async_trait::async_trait;
-use mas_data_model::{Client, Clock, JwksOrJwksUri};
+use chrono::{DateTime, Utc};
+use mas_data_model::{Client, Clock, Consent, JwksOrJwksUri, User, without_device_scopes};
 use mas_iana::{jose::JsonWebSignatureAlg, oauth::OAuthClientAuthenticationMethod};
 use mas_jose::jwk::PublicJsonWebKeySet;
 use mas_storage::oauth2::OAuth2ClientRepository;
-use oauth2_types::{oidc::ApplicationType, requests::GrantType};
+use oauth2_types::{
+    oidc::ApplicationType,
+    requests::GrantType,
+    scope::{Scope, ScopeToken},
+};
 use opentelemetry_semantic_conventions::attribute::DB_QUERY_TEXT;
 use rand::RngCore;
+use sha2::{Digest, Sha256};
 use sqlx::PgConnection;
 use tracing::{Instrument, info_span};
 use ulid::Ulid;
@@ -59,9 +65,12 @@ struct OAuth2ClientLookup {
     jwks: Option<serde_json::Value>,
     id_token_signed_response_alg: Option<String>,
     userinfo_signed_response_alg: Option<String>,
+    authorization_signed_response_alg: Option<String>,
     token_endpoint_auth_method: Option<String>,
     token_endpoint_auth_signing_alg: Option<String>,
     initiate_login_uri: Option<String>,
+    always_prompt_consent: bool,
+    allowed_scopes: Option<Vec<String>>,
 }
 
 impl TryInto<Client> for OAuth2ClientLookup {
@@ -162,6 +171,17 @@ impl TryInto<Client> for OAuth2ClientLookup {
                     .source(e)
             })?;
 
+        let authorization_signed_response_alg = self
+            .authorization_signed_response_alg
+            .map(|s| s.parse())
+            .transpose()
+            .map_err(|e| {
+                DatabaseInconsistencyError::on("oauth2_clients")
+                    .column("authorization_signed_response_alg")
+                    .row(id)
+                    .source(e)
+            })?;
+
         let token_endpoint_auth_method = self
             .token_endpoint_auth_method
             .map(|s| s.parse())
@@ -223,6 +243,22 @@ impl TryInto<Client> for OAuth2ClientLookup {
             }
         };
 
+        let allowed_scopes = self
+            .allowed_scopes
+            .map(|scopes| {
+                scopes
+                    .iter()
+                    .map(|token| token.parse::<ScopeToken>())
+                    .collect::<Result<Scope, _>>()
+            })
+            .transpose()
+            .map_err(|e| {
+                DatabaseInconsistencyError::on("oauth2_clients")
+                    .column("allowed_scopes")
+                    .row(id)
+                    .source(e)
+            })?;
+
         Ok(Client {
             id,
             client_id: id.to_string(),
@@ -239,9 +275,47 @@ impl TryInto<Client> for OAuth2ClientLookup {
             jwks,
             id_token_signed_response_alg,
             userinfo_signed_response_alg,
+            authorization_signed_response_alg,
             token_endpoint_auth_method,
             token_endpoint_auth_signing_alg,
             initiate_login_uri,
+            always_prompt_consent: self.always_prompt_consent,
+            allowed_scopes,
+        })
+    }
+}
+
+struct ConsentLookup {
+    oauth2_client_id: Uuid,
+    user_id: Uuid,
+    scope_tokens: Vec<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<ConsentLookup> for Consent {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: ConsentLookup) -> Result<Self, Self::Error> {
+        let client_id = Ulid::from(value.oauth2_client_id);
+        let scope = value
+            .scope_tokens
+            .iter()
+            .map(|token| token.parse::<ScopeToken>())
+            .collect::<Result<Scope, _>>()
+            .map_err(|e| {
+                DatabaseInconsistencyError::on("oauth2_consents")
+                    .column("scope_token")
+                    .row(client_id)
+                    .source(e)
+            })?;
+
+        Ok(Consent {
+            client_id,
+            user_id: value.user_id.into(),
+            scope,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
         })
     }
 }
@@ -281,9 +355,12 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
                      , jwks
                      , id_token_signed_response_alg
                      , userinfo_signed_response_alg
+                     , authorization_signed_response_alg
                      , token_endpoint_auth_method
                      , token_endpoint_auth_signing_alg
                      , initiate_login_uri
+                     , always_prompt_consent
+                     , allowed_scopes
                 FROM oauth2_clients c
 
                 WHERE oauth2_client_id = $1
@@ -332,9 +409,12 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
                     , jwks
                     , id_token_signed_response_alg
                     , userinfo_signed_response_alg
+                    , authorization_signed_response_alg
                     , token_endpoint_auth_method
                     , token_endpoint_auth_signing_alg
                     , initiate_login_uri
+                    , always_prompt_consent
+                    , allowed_scopes
                 FROM oauth2_clients
                 WHERE metadata_digest = $1
             "#,
@@ -349,6 +429,62 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
         Ok(Some(res.try_into()?))
     }
 
+    #[tracing::instrument(
+        name = "db.oauth2_client.find_by_registration_access_token",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn find_by_registration_access_token(
+        &mut self,
+        registration_access_token: &str,
+    ) -> Result<Option<Client>, Self::Error> {
+        let token_sha256 = Sha256::digest(registration_access_token.as_bytes()).to_vec();
+
+        let res = sqlx::query_as!(
+            OAuth2ClientLookup,
+            r#"
+                SELECT oauth2_client_id
+                     , metadata_digest
+                     , encrypted_client_secret
+                     , application_type
+                     , redirect_uris
+                     , grant_type_authorization_code
+                     , grant_type_refresh_token
+                     , grant_type_client_credentials
+                     , grant_type_device_code
+                     , client_name
+                     , logo_uri
+                     , client_uri
+                     , policy_uri
+                     , tos_uri
+                     , jwks_uri
+                     , jwks
+                     , id_token_signed_response_alg
+                     , userinfo_signed_response_alg
+                     , authorization_signed_response_alg
+                     , token_endpoint_auth_method
+                     , token_endpoint_auth_signing_alg
+                     , initiate_login_uri
+                     , always_prompt_consent
+                     , allowed_scopes
+                FROM oauth2_clients
+                WHERE registration_access_token_sha256 = $1
+                  AND NOT is_static
+            "#,
+            &token_sha256,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
     #[tracing::instrument(
         name = "db.oauth2_client.load_batch",
         skip_all,
@@ -383,9 +519,12 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
                      , jwks
                      , id_token_signed_response_alg
                      , userinfo_signed_response_alg
+                     , authorization_signed_response_alg
                      , token_endpoint_auth_method
                      , token_endpoint_auth_signing_alg
                      , initiate_login_uri
+                     , always_prompt_consent
+                     , allowed_scopes
                 FROM oauth2_clients c
 
                 WHERE oauth2_client_id = ANY($1::uuid[])
@@ -433,9 +572,11 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
         jwks: Option<PublicJsonWebKeySet>,
         id_token_signed_response_alg: Option<JsonWebSignatureAlg>,
         userinfo_signed_response_alg: Option<JsonWebSignatureAlg>,
+        authorization_signed_response_alg: Option<JsonWebSignatureAlg>,
         token_endpoint_auth_method: Option<OAuthClientAuthenticationMethod>,
         token_endpoint_auth_signing_alg: Option<JsonWebSignatureAlg>,
         initiate_login_uri: Option<Url>,
+        allowed_scopes: Option<Scope>,
     ) -> Result<Client, Self::Error> {
         let now = clock.now();
         let id = Ulid::from_datetime_with_source(now.into(), rng);
@@ -448,6 +589,9 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
             .map_err(DatabaseError::to_invalid_operation)?;
 
         let redirect_uris_array = redirect_uris.iter().map(Url::to_string).collect::<Vec<_>>();
+        let allowed_scopes_array = allowed_scopes
+            .as_ref()
+            .map(|scope| scope.iter().map(ToString::to_string).collect::<Vec<_>>());
 
         sqlx::query!(
             r#"
@@ -473,11 +617,13 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
                     , token_endpoint_auth_method
                     , token_endpoint_auth_signing_alg
                     , initiate_login_uri
+                    , allowed_scopes
+                    , authorization_signed_response_alg
                     , is_static
                     )
                 VALUES
                     ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13,
-                    $14, $15, $16, $17, $18, $19, $20, $21, FALSE)
+                    $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, FALSE)
             "#,
             Uuid::from(id),
             metadata_digest,
@@ -506,6 +652,10 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
                 .as_ref()
                 .map(ToString::to_string),
             initiate_login_uri.as_ref().map(Url::as_str),
+            allowed_scopes_array.as_deref(),
+            authorization_signed_response_alg
+                .as_ref()
+                .map(ToString::to_string),
         )
         .traced()
         .execute(&mut *self.conn)
@@ -534,12 +684,169 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
             jwks,
             id_token_signed_response_alg,
             userinfo_signed_response_alg,
+            authorization_signed_response_alg,
             token_endpoint_auth_method,
             token_endpoint_auth_signing_alg,
             initiate_login_uri,
+            always_prompt_consent: false,
+            allowed_scopes,
         })
     }
 
+    #[tracing::instrument(
+        name = "db.oauth2_client.set_registration_access_token",
+        skip_all,
+        fields(
+            db.query.text,
+            %client.id,
+        ),
+        err,
+    )]
+    async fn set_registration_access_token(
+        &mut self,
+        client: &Client,
+        registration_access_token: &str,
+    ) -> Result<(), Self::Error> {
+        let token_sha256 = Sha256::digest(registration_access_token.as_bytes()).to_vec();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_clients
+                SET registration_access_token_sha256 = $2
+                WHERE oauth2_client_id = $1
+                  AND NOT is_static
+            "#,
+            Uuid::from(client.id),
+            &token_sha256,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_client.update_metadata",
+        skip_all,
+        fields(
+            db.query.text,
+            %client.id,
+        ),
+        err,
+    )]
+    async fn update_metadata(&mut self, client: Client) -> Result<Client, Self::Error> {
+        let (jwks, jwks_uri) = match &client.jwks {
+            None => (None, None),
+            Some(JwksOrJwksUri::Jwks(jwks)) => (Some(jwks), None),
+            Some(JwksOrJwksUri::JwksUri(jwks_uri)) => (None, Some(jwks_uri)),
+        };
+
+        let jwks_json = jwks
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(DatabaseError::to_invalid_operation)?;
+
+        let redirect_uris_array = client
+            .redirect_uris
+            .iter()
+            .map(Url::to_string)
+            .collect::<Vec<_>>();
+
+        let allowed_scopes_array = client
+            .allowed_scopes
+            .as_ref()
+            .map(|scope| scope.iter().map(ToString::to_string).collect::<Vec<_>>());
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_clients
+                SET metadata_digest = $2
+                  , application_type = $3
+                  , redirect_uris = $4
+                  , grant_type_authorization_code = $5
+                  , grant_type_refresh_token = $6
+                  , grant_type_client_credentials = $7
+                  , grant_type_device_code = $8
+                  , client_name = $9
+                  , logo_uri = $10
+                  , client_uri = $11
+                  , policy_uri = $12
+                  , tos_uri = $13
+                  , jwks_uri = $14
+                  , jwks = $15
+                  , id_token_signed_response_alg = $16
+                  , userinfo_signed_response_alg = $17
+                  , token_endpoint_auth_signing_alg = $18
+                  , initiate_login_uri = $19
+                  , allowed_scopes = $20
+                  , authorization_signed_response_alg = $21
+                WHERE oauth2_client_id = $1
+                  AND NOT is_static
+            "#,
+            Uuid::from(client.id),
+            client.metadata_digest,
+            client.application_type.as_ref().map(ToString::to_string),
+            &redirect_uris_array,
+            client.grant_types.contains(&GrantType::AuthorizationCode),
+            client.grant_types.contains(&GrantType::RefreshToken),
+            client.grant_types.contains(&GrantType::ClientCredentials),
+            client.grant_types.contains(&GrantType::DeviceCode),
+            client.client_name,
+            client.logo_uri.as_ref().map(Url::as_str),
+            client.client_uri.as_ref().map(Url::as_str),
+            client.policy_uri.as_ref().map(Url::as_str),
+            client.tos_uri.as_ref().map(Url::as_str),
+            jwks_uri.map(Url::as_str),
+            jwks_json,
+            client
+                .id_token_signed_response_alg
+                .as_ref()
+                .map(ToString::to_string),
+            client
+                .userinfo_signed_response_alg
+                .as_ref()
+                .map(ToString::to_string),
+            client
+                .token_endpoint_auth_signing_alg
+                .as_ref()
+                .map(ToString::to_string),
+            client.initiate_login_uri.as_ref().map(Url::as_str),
+            allowed_scopes_array.as_deref(),
+            client
+                .authorization_signed_response_alg
+                .as_ref()
+                .map(ToString::to_string),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        // Users consented to the previous metadata, forget their decisions
+        {
+            let span = info_span!(
+                "db.oauth2_client.update_metadata.consents",
+                { DB_QUERY_TEXT } = tracing::field::Empty,
+            );
+
+            sqlx::query!(
+                r#"
+                    DELETE FROM oauth2_consents
+                    WHERE oauth2_client_id = $1
+                "#,
+                Uuid::from(client.id),
+            )
+            .record(&span)
+            .execute(&mut *self.conn)
+            .instrument(span)
+            .await?;
+        }
+
+        Ok(client)
+    }
+
     #[tracing::instrument(
         name = "db.oauth2_client.upsert_static",
         skip_all,
@@ -558,6 +865,8 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
         jwks: Option<PublicJsonWebKeySet>,
         jwks_uri: Option<Url>,
         redirect_uris: Vec<Url>,
+        always_prompt_consent: bool,
+        allowed_scopes: Option<Scope>,
     ) -> Result<Client, Self::Error> {
         let jwks_json = jwks
             .as_ref()
@@ -567,6 +876,41 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
 
         let client_auth_method = client_auth_method.to_string();
         let redirect_uris_array = redirect_uris.iter().map(Url::to_string).collect::<Vec<_>>();
+        let allowed_scopes_array = allowed_scopes
+            .as_ref()
+            .map(|scope| scope.iter().map(ToString::to_string).collect::<Vec<_>>());
+
+        // If the metadata users consented to changed, forget their decisions
+        {
+            let span = info_span!(
+                "db.oauth2_client.upsert_static.consents",
+                { DB_QUERY_TEXT } = tracing::field::Empty,
+            );
+
+            sqlx::query!(
+                r#"
+                    DELETE FROM oauth2_consents
+                    WHERE oauth2_client_id = $1
+                      AND EXISTS (
+                          SELECT 1
+                          FROM oauth2_clients
+                          WHERE oauth2_client_id = $1
+                            AND ( client_name IS DISTINCT FROM $2
+                               OR redirect_uris IS DISTINCT FROM $3
+                               OR allowed_scopes IS DISTINCT FROM $4
+                                )
+                      )
+                "#,
+                Uuid::from(client_id),
+                client_name,
+                &redirect_uris_array,
+                allowed_scopes_array.as_deref(),
+            )
+            .record(&span)
+            .execute(&mut *self.conn)
+            .instrument(span)
+            .await?;
+        }
 
         sqlx::query!(
             r#"
@@ -582,10 +926,12 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
                     , jwks
                     , client_name
                     , jwks_uri
+                    , always_prompt_consent
+                    , allowed_scopes
                     , is_static
                     )
                 VALUES
-                    ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, TRUE)
+                    ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, TRUE)
                 ON CONFLICT (oauth2_client_id)
                 DO
                     UPDATE SET encrypted_client_secret = EXCLUDED.encrypted_client_secret
@@ -598,6 +944,8 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
                              , jwks = EXCLUDED.jwks
                              , client_name = EXCLUDED.client_name
                              , jwks_uri = EXCLUDED.jwks_uri
+                             , always_prompt_consent = EXCLUDED.always_prompt_consent
+                             , allowed_scopes = EXCLUDED.allowed_scopes
                              , is_static = TRUE
             "#,
             Uuid::from(client_id),
@@ -611,6 +959,8 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
             jwks_json,
             client_name,
             jwks_uri.as_ref().map(Url::as_str),
+            always_prompt_consent,
+            allowed_scopes_array.as_deref(),
         )
         .traced()
         .execute(&mut *self.conn)
@@ -643,9 +993,12 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
             jwks,
             id_token_signed_response_alg: None,
             userinfo_signed_response_alg: None,
+            authorization_signed_response_alg: None,
             token_endpoint_auth_method: None,
             token_endpoint_auth_signing_alg: None,
             initiate_login_uri: None,
+            always_prompt_consent,
+            allowed_scopes,
         })
     }
 
@@ -679,9 +1032,12 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
                      , jwks
                      , id_token_signed_response_alg
                      , userinfo_signed_response_alg
+                     , authorization_signed_response_alg
                      , token_endpoint_auth_method
                      , token_endpoint_auth_signing_alg
                      , initiate_login_uri
+                     , always_prompt_consent
+                     , allowed_scopes
                 FROM oauth2_clients c
                 WHERE is_static = TRUE
             "#,
@@ -695,6 +1051,163 @@ impl OAuth2ClientRepository for PgOAuth2ClientRepository<'_> {
             .collect()
     }
 
+    #[tracing::instrument(
+        name = "db.oauth2_client.get_consent_for_user",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+            %client.id,
+        ),
+        err,
+    )]
+    async fn get_consent_for_user(
+        &mut self,
+        client: &Client,
+        user: &User,
+    ) -> Result<Scope, Self::Error> {
+        let scope_tokens: Vec<String> = sqlx::query_scalar!(
+            r#"
+                SELECT scope_token
+                FROM oauth2_consents
+                WHERE user_id = $1 AND oauth2_client_id = $2
+            "#,
+            Uuid::from(user.id),
+            Uuid::from(client.id),
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        let scope = scope_tokens
+            .iter()
+            .map(|token| token.parse::<ScopeToken>())
+            .collect::<Result<Scope, _>>()
+            .map_err(|e| {
+                DatabaseInconsistencyError::on("oauth2_consents")
+                    .column("scope_token")
+                    .source(e)
+            })?;
+
+        Ok(without_device_scopes(&scope))
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_client.give_consent_for_user",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+            %client.id,
+            %scope,
+        ),
+        err,
+    )]
+    async fn give_consent_for_user(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        client: &Client,
+        user: &User,
+        scope: &Scope,
+    ) -> Result<(), Self::Error> {
+        let now = clock.now();
+        let (tokens, ids): (Vec<String>, Vec<Uuid>) = without_device_scopes(scope)
+            .iter()
+            .map(|token| {
+                (
+                    token.to_string(),
+                    Uuid::from(Ulid::from_datetime_with_source(now.into(), rng)),
+                )
+            })
+            .unzip();
+
+        sqlx::query!(
+            r#"
+                INSERT INTO oauth2_consents
+                    (oauth2_consent_id, user_id, oauth2_client_id, scope_token, created_at)
+                SELECT id, $2, $3, scope_token, $5
+                FROM UNNEST($1::uuid[], $4::text[]) u(id, scope_token)
+                ON CONFLICT (user_id, oauth2_client_id, scope_token)
+                DO UPDATE SET refreshed_at = $5
+            "#,
+            &ids,
+            Uuid::from(user.id),
+            Uuid::from(client.id),
+            &tokens,
+            now,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_client.list_consents_for_user",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn list_consents_for_user(&mut self, user: &User) -> Result<Vec<Consent>, Self::Error> {
+        let res = sqlx::query_as!(
+            ConsentLookup,
+            r#"
+                SELECT oauth2_client_id
+                     , user_id
+                     , ARRAY_AGG(scope_token) AS "scope_tokens!"
+                     , MIN(created_at) AS "created_at!"
+                     , MAX(COALESCE(refreshed_at, created_at)) AS "updated_at!"
+                FROM oauth2_consents
+                WHERE user_id = $1
+                GROUP BY oauth2_client_id, user_id
+                ORDER BY MAX(COALESCE(refreshed_at, created_at)) DESC, oauth2_client_id
+            "#,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        res.into_iter()
+            .map(|r| r.try_into().map_err(DatabaseError::from))
+            .collect()
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_client.revoke_consent_for_user",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+            %client.id,
+        ),
+        err,
+    )]
+    async fn revoke_consent_for_user(
+        &mut self,
+        client: &Client,
+        user: &User,
+    ) -> Result<usize, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM oauth2_consents
+                WHERE user_id = $1 AND oauth2_client_id = $2
+            "#,
+            Uuid::from(user.id),
+            Uuid::from(client.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.oauth2_client.delete_by_id",
         skip_all,
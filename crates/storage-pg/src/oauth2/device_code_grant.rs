@@ -9,14 +9,29 @@ use std::net::IpAddr;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use mas_data_model::{BrowserSession, Clock, DeviceCodeGrant, DeviceCodeGrantState, Session};
-use mas_storage::oauth2::{OAuth2DeviceCodeGrantParams, OAuth2DeviceCodeGrantRepository};
+use mas_storage::{
+    Page, Pagination,
+    oauth2::{
+        OAuth2DeviceCodeGrantFilter, OAuth2DeviceCodeGrantParams, OAuth2DeviceCodeGrantRepository,
+        OAuth2DeviceCodeGrantState,
+    },
+    pagination::Node,
+};
 use oauth2_types::scope::Scope;
 use rand::RngCore;
+use sea_query::{Condition, Expr, PostgresQueryBuilder, Query, enum_def};
+use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
 use uuid::Uuid;
 
-use crate::{DatabaseError, ExecuteExt, errors::DatabaseInconsistencyError};
+use crate::{
+    DatabaseError, ExecuteExt,
+    errors::DatabaseInconsistencyError,
+    filter::{Filter, StatementExt},
+    iden::{OAuth2DeviceCodeGrants, UserSessions},
+    pagination::QueryBuilderExt,
+};
 
 /// An implementation of [`OAuth2DeviceCodeGrantRepository`] for a PostgreSQL
 /// connection
@@ -32,6 +47,8 @@ impl<'c> PgOAuth2DeviceCodeGrantRepository<'c> {
     }
 }
 
+#[derive(sqlx::FromRow)]
+#[enum_def]
 struct OAuth2DeviceGrantLookup {
     oauth2_device_code_grant_id: Uuid,
     oauth2_client_id: Uuid,
@@ -49,6 +66,12 @@ struct OAuth2DeviceGrantLookup {
     user_agent: Option<String>,
 }
 
+impl Node<Ulid> for OAuth2DeviceGrantLookup {
+    fn cursor(&self) -> Ulid {
+        self.oauth2_device_code_grant_id.into()
+    }
+}
+
 impl TryFrom<OAuth2DeviceGrantLookup> for DeviceCodeGrant {
     type Error = DatabaseInconsistencyError;
 
@@ -134,6 +157,90 @@ impl TryFrom<OAuth2DeviceGrantLookup> for DeviceCodeGrant {
     }
 }
 
+impl Filter for OAuth2DeviceCodeGrantFilter<'_> {
+    fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
+        sea_query::Condition::all()
+            .add_option(self.user().map(|user| {
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::UserSessionId,
+                ))
+                .in_subquery(
+                    Query::select()
+                        .expr(Expr::col((
+                            UserSessions::Table,
+                            UserSessions::UserSessionId,
+                        )))
+                        .from(UserSessions::Table)
+                        .and_where(
+                            Expr::col((UserSessions::Table, UserSessions::UserId))
+                                .eq(Uuid::from(user.id)),
+                        )
+                        .take(),
+                )
+            }))
+            .add_option(self.state().map(|state| {
+                match state {
+                    OAuth2DeviceCodeGrantState::Pending => Condition::all()
+                        .add(
+                            Expr::col((
+                                OAuth2DeviceCodeGrants::Table,
+                                OAuth2DeviceCodeGrants::FulfilledAt,
+                            ))
+                            .is_null(),
+                        )
+                        .add(
+                            Expr::col((
+                                OAuth2DeviceCodeGrants::Table,
+                                OAuth2DeviceCodeGrants::RejectedAt,
+                            ))
+                            .is_null(),
+                        ),
+                    OAuth2DeviceCodeGrantState::Fulfilled => Condition::all()
+                        .add(
+                            Expr::col((
+                                OAuth2DeviceCodeGrants::Table,
+                                OAuth2DeviceCodeGrants::FulfilledAt,
+                            ))
+                            .is_not_null(),
+                        )
+                        .add(
+                            Expr::col((
+                                OAuth2DeviceCodeGrants::Table,
+                                OAuth2DeviceCodeGrants::ExchangedAt,
+                            ))
+                            .is_null(),
+                        ),
+                    OAuth2DeviceCodeGrantState::Rejected => Condition::all().add(
+                        Expr::col((
+                            OAuth2DeviceCodeGrants::Table,
+                            OAuth2DeviceCodeGrants::RejectedAt,
+                        ))
+                        .is_not_null(),
+                    ),
+                    OAuth2DeviceCodeGrantState::Exchanged => Condition::all().add(
+                        Expr::col((
+                            OAuth2DeviceCodeGrants::Table,
+                            OAuth2DeviceCodeGrants::ExchangedAt,
+                        ))
+                        .is_not_null(),
+                    ),
+                }
+            }))
+            .add_option(self.is_expired().map(|is_expired| {
+                let expires_at = Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::ExpiresAt,
+                ));
+                if is_expired {
+                    expires_at.lt(Expr::val(self.now()))
+                } else {
+                    expires_at.gte(Expr::val(self.now()))
+                }
+            }))
+    }
+}
+
 #[async_trait]
 impl OAuth2DeviceCodeGrantRepository for PgOAuth2DeviceCodeGrantRepository<'_> {
     type Error = DatabaseError;
@@ -469,4 +576,170 @@ impl OAuth2DeviceCodeGrantRepository for PgOAuth2DeviceCodeGrantRepository<'_> {
 
         Ok(device_code_grant)
     }
+
+    #[tracing::instrument(
+        name = "db.oauth2_device_code_grant.list",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn list(
+        &mut self,
+        filter: OAuth2DeviceCodeGrantFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<DeviceCodeGrant>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::OAuth2DeviceCodeGrantId,
+                )),
+                OAuth2DeviceGrantLookupIden::Oauth2DeviceCodeGrantId,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::OAuth2ClientId,
+                )),
+                OAuth2DeviceGrantLookupIden::Oauth2ClientId,
+            )
+            .expr_as(
+                Expr::col((OAuth2DeviceCodeGrants::Table, OAuth2DeviceCodeGrants::Scope)),
+                OAuth2DeviceGrantLookupIden::Scope,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::DeviceCode,
+                )),
+                OAuth2DeviceGrantLookupIden::DeviceCode,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::UserCode,
+                )),
+                OAuth2DeviceGrantLookupIden::UserCode,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::CreatedAt,
+                )),
+                OAuth2DeviceGrantLookupIden::CreatedAt,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::ExpiresAt,
+                )),
+                OAuth2DeviceGrantLookupIden::ExpiresAt,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::FulfilledAt,
+                )),
+                OAuth2DeviceGrantLookupIden::FulfilledAt,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::RejectedAt,
+                )),
+                OAuth2DeviceGrantLookupIden::RejectedAt,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::ExchangedAt,
+                )),
+                OAuth2DeviceGrantLookupIden::ExchangedAt,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::UserSessionId,
+                )),
+                OAuth2DeviceGrantLookupIden::UserSessionId,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::OAuth2SessionId,
+                )),
+                OAuth2DeviceGrantLookupIden::Oauth2SessionId,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::IpAddress,
+                )),
+                OAuth2DeviceGrantLookupIden::IpAddress,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::UserAgent,
+                )),
+                OAuth2DeviceGrantLookupIden::UserAgent,
+            )
+            .from(OAuth2DeviceCodeGrants::Table)
+            .apply_filter(filter)
+            .generate_pagination(
+                (
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::OAuth2DeviceCodeGrantId,
+                ),
+                pagination,
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<OAuth2DeviceGrantLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination
+            .process(edges)
+            .try_map(DeviceCodeGrant::try_from)?;
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_device_code_grant.count",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count(
+        &mut self,
+        filter: OAuth2DeviceCodeGrantFilter<'_>,
+    ) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(
+                Expr::col((
+                    OAuth2DeviceCodeGrants::Table,
+                    OAuth2DeviceCodeGrants::OAuth2DeviceCodeGrantId,
+                ))
+                .count(),
+            )
+            .from(OAuth2DeviceCodeGrants::Table)
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
 }
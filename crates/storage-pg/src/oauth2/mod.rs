@@ -11,6 +11,7 @@ mod access_token;
 mod authorization_grant;
 mod client;
 mod device_code_grant;
+mod pushed_authorization_request;
 mod refresh_token;
 mod session;
 
@@ -18,16 +19,23 @@ pub use self::{
     access_token::PgOAuth2AccessTokenRepository,
     authorization_grant::PgOAuth2AuthorizationGrantRepository, client::PgOAuth2ClientRepository,
     device_code_grant::PgOAuth2DeviceCodeGrantRepository,
+    pushed_authorization_request::PgOAuth2PushedAuthorizationRequestRepository,
     refresh_token::PgOAuth2RefreshTokenRepository, session::PgOAuth2SessionRepository,
 };
 
 #[cfg(test)]
 mod tests {
     use chrono::Duration;
-    use mas_data_model::{AuthorizationCode, Clock, clock::MockClock};
+    use mas_data_model::{
+        AuthenticatedBy, AuthorizationCode, Client, Clock, Device, clock::MockClock,
+    };
+    use mas_iana::oauth::OAuthClientAuthenticationMethod;
     use mas_storage::{
         Pagination,
-        oauth2::{OAuth2DeviceCodeGrantParams, OAuth2SessionFilter, OAuth2SessionRepository},
+        oauth2::{
+            OAuth2DeviceCodeGrantFilter, OAuth2DeviceCodeGrantParams, OAuth2DeviceCodeGrantState,
+            OAuth2SessionFilter, OAuth2SessionRepository,
+        },
     };
     use oauth2_types::{
         requests::{GrantType, ResponseMode},
@@ -80,7 +88,9 @@ mod tests {
                 None,
                 None,
                 None,
+                None,
                 Some("https://example.com/login".parse().unwrap()),
+                None,
             )
             .await
             .unwrap();
@@ -138,6 +148,9 @@ mod tests {
                 true,
                 None,
                 None,
+                vec!["email".to_owned()],
+                None,
+                Vec::new(),
             )
             .await
             .unwrap();
@@ -193,7 +206,7 @@ mod tests {
         // Mark the grant as fulfilled
         let grant = repo
             .oauth2_authorization_grant()
-            .fulfill(&clock, &session, grant)
+            .fulfill(&clock, &session, None, grant)
             .await
             .unwrap();
         assert!(grant.is_fulfilled());
@@ -365,6 +378,31 @@ mod tests {
             .expect("session not found");
         assert_eq!(session.user_agent.as_deref(), Some("Mozilla/5.0"));
 
+        // Record how the user authenticated
+        assert_eq!(session.authenticated_by, AuthenticatedBy::Unknown);
+        let provider_id = Ulid::from_datetime_with_source(clock.now().into(), &mut rng);
+        let session = repo
+            .oauth2_session()
+            .set_authenticated_by(session, AuthenticatedBy::Upstream { provider_id })
+            .await
+            .unwrap();
+        assert_eq!(
+            session.authenticated_by,
+            AuthenticatedBy::Upstream { provider_id }
+        );
+
+        // Reload the session and check it was persisted
+        let session = repo
+            .oauth2_session()
+            .lookup(session.id)
+            .await
+            .unwrap()
+            .expect("session not found");
+        assert_eq!(
+            session.authenticated_by,
+            AuthenticatedBy::Upstream { provider_id }
+        );
+
         // Mark the session as finished
         assert!(session.is_valid());
         let session = repo.oauth2_session().finish(&clock, session).await.unwrap();
@@ -424,7 +462,9 @@ mod tests {
                 None,
                 None,
                 None,
+                None,
                 Some("https://first.example.com/login".parse().unwrap()),
+                None,
             )
             .await
             .unwrap();
@@ -449,7 +489,9 @@ mod tests {
                 None,
                 None,
                 None,
+                None,
                 Some("https://second.example.com/login".parse().unwrap()),
+                None,
             )
             .await
             .unwrap();
@@ -749,7 +791,9 @@ mod tests {
                 None,
                 None,
                 None,
+                None,
                 Some("https://example.com/login".parse().unwrap()),
+                None,
             )
             .await
             .unwrap();
@@ -943,5 +987,531 @@ mod tests {
             .exchange(&clock, grant, &session)
             .await;
         assert!(res.is_err());
+
+        // Do a third grant, left pending
+        let pending = repo
+            .oauth2_device_code_grant()
+            .add(
+                &mut rng,
+                &clock,
+                OAuth2DeviceCodeGrantParams {
+                    client: &client,
+                    scope: scope.clone(),
+                    device_code: "third_devicecode".to_owned(),
+                    user_code: "third_usercode".to_owned(),
+                    expires_in: Duration::try_minutes(5).unwrap(),
+                    ip_address: None,
+                    user_agent: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let pagination = Pagination::first(10);
+
+        // The pending grant isn't associated with any user yet
+        let filter = OAuth2DeviceCodeGrantFilter::new(clock.now()).for_user(&user);
+        let list = repo
+            .oauth2_device_code_grant()
+            .list(filter, pagination)
+            .await
+            .unwrap();
+        assert_eq!(list.edges.len(), 2);
+        assert!(list.edges.iter().any(|edge| edge.node.is_exchanged()));
+        assert!(
+            list.edges
+                .iter()
+                .any(|edge| edge.node.id == id && edge.node.is_rejected())
+        );
+        assert_eq!(
+            repo.oauth2_device_code_grant().count(filter).await.unwrap(),
+            2
+        );
+
+        // Filter by state
+        let filter = OAuth2DeviceCodeGrantFilter::new(clock.now())
+            .with_state(OAuth2DeviceCodeGrantState::Pending);
+        let list = repo
+            .oauth2_device_code_grant()
+            .list(filter, pagination)
+            .await
+            .unwrap();
+        assert_eq!(list.edges.len(), 1);
+        assert_eq!(list.edges[0].node, pending);
+
+        let filter = OAuth2DeviceCodeGrantFilter::new(clock.now())
+            .for_user(&user)
+            .with_state(OAuth2DeviceCodeGrantState::Exchanged);
+        assert_eq!(
+            repo.oauth2_device_code_grant().count(filter).await.unwrap(),
+            1
+        );
+
+        let filter = OAuth2DeviceCodeGrantFilter::new(clock.now())
+            .for_user(&user)
+            .with_state(OAuth2DeviceCodeGrantState::Fulfilled);
+        assert_eq!(
+            repo.oauth2_device_code_grant().count(filter).await.unwrap(),
+            0
+        );
+
+        // Filter by expiration
+        let filter = OAuth2DeviceCodeGrantFilter::new(clock.now()).with_expired(true);
+        assert_eq!(
+            repo.oauth2_device_code_grant().count(filter).await.unwrap(),
+            0
+        );
+
+        clock.advance(Duration::try_minutes(10).unwrap());
+
+        let filter = OAuth2DeviceCodeGrantFilter::new(clock.now()).with_expired(true);
+        assert_eq!(
+            repo.oauth2_device_code_grant().count(filter).await.unwrap(),
+            3
+        );
+        let filter = OAuth2DeviceCodeGrantFilter::new(clock.now()).with_expired(false);
+        assert_eq!(
+            repo.oauth2_device_code_grant().count(filter).await.unwrap(),
+            0
+        );
+    }
+
+    /// Test the registration access token and metadata update methods of the
+    /// [`OAuth2ClientRepository`] implementation
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_client_registration_access_token(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &clock,
+                vec!["https://example.com/redirect".parse().unwrap()],
+                None,
+                None,
+                None,
+                vec![GrantType::AuthorizationCode],
+                Some("Example".to_owned()),
+                None,
+                Some("https://example.com/".parse().unwrap()),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        // No token set yet
+        let found = repo
+            .oauth2_client()
+            .find_by_registration_access_token("some-token")
+            .await
+            .unwrap();
+        assert_eq!(found, None);
+
+        repo.oauth2_client()
+            .set_registration_access_token(&client, "some-token")
+            .await
+            .unwrap();
+
+        let found = repo
+            .oauth2_client()
+            .find_by_registration_access_token("some-token")
+            .await
+            .unwrap()
+            .expect("client not found");
+        assert_eq!(found, client);
+
+        let found = repo
+            .oauth2_client()
+            .find_by_registration_access_token("other-token")
+            .await
+            .unwrap();
+        assert_eq!(found, None);
+
+        // Update the client metadata
+        let updated = mas_data_model::Client {
+            client_name: Some("Renamed".to_owned()),
+            redirect_uris: vec!["https://example.com/other".parse().unwrap()],
+            tos_uri: Some("https://example.com/tos".parse().unwrap()),
+            ..client.clone()
+        };
+        let updated = repo.oauth2_client().update_metadata(updated).await.unwrap();
+
+        let lookup = repo
+            .oauth2_client()
+            .lookup(client.id)
+            .await
+            .unwrap()
+            .expect("client not found");
+        assert_eq!(lookup, updated);
+        assert_eq!(lookup.client_name.as_deref(), Some("Renamed"));
+        assert_eq!(
+            lookup.redirect_uris,
+            vec!["https://example.com/other".parse().unwrap()]
+        );
+
+        // The token still works after the update
+        let found = repo
+            .oauth2_client()
+            .find_by_registration_access_token("some-token")
+            .await
+            .unwrap()
+            .expect("client not found");
+        assert_eq!(found.id, client.id);
+    }
+
+    /// Test the consent methods of the [`OAuth2ClientRepository`]
+    /// implementation
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_client_consents(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &clock, "john".to_owned())
+            .await
+            .unwrap();
+
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &clock,
+                vec!["https://example.com/redirect".parse().unwrap()],
+                None,
+                None,
+                None,
+                vec![GrantType::AuthorizationCode],
+                Some("Example".to_owned()),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        // No consent given yet
+        let consent = repo
+            .oauth2_client()
+            .get_consent_for_user(&client, &user)
+            .await
+            .unwrap();
+        assert!(consent.is_empty());
+        let consents = repo
+            .oauth2_client()
+            .list_consents_for_user(&user)
+            .await
+            .unwrap();
+        assert!(consents.is_empty());
+
+        // Device scopes are not remembered
+        let scope = Scope::from_iter([OPENID, EMAIL]);
+        let device = Device::generate(&mut rng);
+        let mut scope_with_device = scope.clone();
+        for token in device.to_scope_token().unwrap() {
+            scope_with_device.insert(token);
+        }
+        repo.oauth2_client()
+            .give_consent_for_user(&mut rng, &clock, &client, &user, &scope_with_device)
+            .await
+            .unwrap();
+
+        let consent = repo
+            .oauth2_client()
+            .get_consent_for_user(&client, &user)
+            .await
+            .unwrap();
+        assert_eq!(consent, scope);
+
+        // Consenting again to a broader scope extends the consent
+        clock.advance(Duration::try_minutes(1).unwrap());
+        let broader_scope = Scope::from_iter([OPENID, PROFILE]);
+        repo.oauth2_client()
+            .give_consent_for_user(&mut rng, &clock, &client, &user, &broader_scope)
+            .await
+            .unwrap();
+
+        let consent = repo
+            .oauth2_client()
+            .get_consent_for_user(&client, &user)
+            .await
+            .unwrap();
+        assert_eq!(consent, Scope::from_iter([OPENID, EMAIL, PROFILE]));
+
+        let consents = repo
+            .oauth2_client()
+            .list_consents_for_user(&user)
+            .await
+            .unwrap();
+        assert_eq!(consents.len(), 1);
+        assert_eq!(consents[0].client_id, client.id);
+        assert_eq!(consents[0].user_id, user.id);
+        assert_eq!(
+            consents[0].scope,
+            Scope::from_iter([OPENID, EMAIL, PROFILE])
+        );
+        assert_eq!(
+            consents[0].created_at,
+            clock.now() - Duration::try_minutes(1).unwrap()
+        );
+        assert_eq!(consents[0].updated_at, clock.now());
+
+        // Revoking the consent forgets all the scopes
+        let revoked = repo
+            .oauth2_client()
+            .revoke_consent_for_user(&client, &user)
+            .await
+            .unwrap();
+        assert_eq!(revoked, 3);
+
+        let consent = repo
+            .oauth2_client()
+            .get_consent_for_user(&client, &user)
+            .await
+            .unwrap();
+        assert!(consent.is_empty());
+        let consents = repo
+            .oauth2_client()
+            .list_consents_for_user(&user)
+            .await
+            .unwrap();
+        assert!(consents.is_empty());
+
+        // Updating the client metadata forgets the consents
+        repo.oauth2_client()
+            .give_consent_for_user(&mut rng, &clock, &client, &user, &scope)
+            .await
+            .unwrap();
+        let client = repo
+            .oauth2_client()
+            .update_metadata(Client {
+                client_name: Some("Renamed".to_owned()),
+                ..client
+            })
+            .await
+            .unwrap();
+        let consent = repo
+            .oauth2_client()
+            .get_consent_for_user(&client, &user)
+            .await
+            .unwrap();
+        assert!(consent.is_empty());
+    }
+
+    /// Test that changing the metadata of a static client forgets the consents
+    /// given to it
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_static_client_consents(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &clock, "john".to_owned())
+            .await
+            .unwrap();
+
+        let client_id = Ulid::from_datetime_with_source(clock.now().into(), &mut rng);
+        let redirect_uris = vec!["https://example.com/redirect".parse().unwrap()];
+        let client = repo
+            .oauth2_client()
+            .upsert_static(
+                client_id,
+                Some("Example".to_owned()),
+                OAuthClientAuthenticationMethod::None,
+                None,
+                None,
+                None,
+                redirect_uris.clone(),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let scope = Scope::from_iter([OPENID, EMAIL]);
+        repo.oauth2_client()
+            .give_consent_for_user(&mut rng, &clock, &client, &user, &scope)
+            .await
+            .unwrap();
+
+        // Syncing the same metadata keeps the consent
+        let client = repo
+            .oauth2_client()
+            .upsert_static(
+                client_id,
+                Some("Example".to_owned()),
+                OAuthClientAuthenticationMethod::None,
+                None,
+                None,
+                None,
+                redirect_uris.clone(),
+                true,
+                None,
+            )
+            .await
+            .unwrap();
+        let consent = repo
+            .oauth2_client()
+            .get_consent_for_user(&client, &user)
+            .await
+            .unwrap();
+        assert_eq!(consent, scope);
+
+        // Changing the redirect URIs forgets it
+        let client = repo
+            .oauth2_client()
+            .upsert_static(
+                client_id,
+                Some("Example".to_owned()),
+                OAuthClientAuthenticationMethod::None,
+                None,
+                None,
+                None,
+                vec!["https://example.org/redirect".parse().unwrap()],
+                true,
+                None,
+            )
+            .await
+            .unwrap();
+        let consent = repo
+            .oauth2_client()
+            .get_consent_for_user(&client, &user)
+            .await
+            .unwrap();
+        assert!(consent.is_empty());
+    }
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_pushed_authorization_request_repository(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        let client = repo
+            .oauth2_client()
+            .add(
+                &mut rng,
+                &clock,
+                vec!["https://example.com/redirect".parse().unwrap()],
+                None,
+                None,
+                None,
+                vec![GrantType::AuthorizationCode],
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let request = repo
+            .oauth2_pushed_authorization_request()
+            .add(
+                &mut rng,
+                &clock,
+                &client,
+                "response_type=code".to_owned(),
+                Duration::try_minutes(1).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(!request.is_consumed());
+        assert!(!request.is_expired(clock.now()));
+        repo.save().await.unwrap();
+
+        // Consume the request from two transactions at the same time. The
+        // first one holds the row until it commits, after which the second one
+        // doesn't match it anymore
+        let mut first = PgRepository::from_pool(&pool).await.unwrap().boxed();
+        let mut second = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        let consumed = first
+            .oauth2_pushed_authorization_request()
+            .consume(&clock, request.clone())
+            .await
+            .unwrap()
+            .expect("request should be consumed");
+        assert!(consumed.is_consumed());
+
+        let mut second_repo = second.oauth2_pushed_authorization_request();
+        let (second_result, ()) =
+            futures_util::join!(second_repo.consume(&clock, request.clone()), async {
+                first.save().await.unwrap();
+            },);
+        drop(second_repo);
+        assert_eq!(second_result.unwrap(), None);
+        second.save().await.unwrap();
+
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+        let request = repo
+            .oauth2_pushed_authorization_request()
+            .lookup(request.id)
+            .await
+            .unwrap()
+            .expect("request not found");
+        assert!(request.is_consumed());
+
+        clock.advance(Duration::try_minutes(2).unwrap());
+        assert!(request.is_expired(clock.now()));
+
+        // The request is kept until the retention period is over
+        let count = repo
+            .oauth2_pushed_authorization_request()
+            .cleanup_expired(&clock, Duration::try_hours(1).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+
+        clock.advance(Duration::try_hours(1).unwrap());
+        let count = repo
+            .oauth2_pushed_authorization_request()
+            .cleanup_expired(&clock, Duration::try_hours(1).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let request = repo
+            .oauth2_pushed_authorization_request()
+            .lookup(request.id)
+            .await
+            .unwrap();
+        assert!(request.is_none());
     }
 }
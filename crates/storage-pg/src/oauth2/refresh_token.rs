@@ -7,13 +7,20 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use mas_data_model::{AccessToken, Clock, RefreshToken, RefreshTokenState, Session};
-use mas_storage::oauth2::OAuth2RefreshTokenRepository;
+use mas_storage::oauth2::{OAuth2RefreshTokenRepository, OAuth2SessionFilter};
 use rand::RngCore;
+use sea_query::{Condition, Expr, PostgresQueryBuilder, Query};
+use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use ulid::Ulid;
 use uuid::Uuid;
 
-use crate::{DatabaseError, DatabaseInconsistencyError, tracing::ExecuteExt};
+use crate::{
+    DatabaseError, DatabaseInconsistencyError,
+    filter::StatementExt,
+    iden::{OAuth2RefreshTokens, OAuth2Sessions},
+    tracing::ExecuteExt,
+};
 
 /// An implementation of [`OAuth2RefreshTokenRepository`] for a PostgreSQL
 /// connection
@@ -281,4 +288,56 @@ impl OAuth2RefreshTokenRepository for PgOAuth2RefreshTokenRepository<'_> {
             .revoke(revoked_at)
             .map_err(DatabaseError::to_invalid_operation)
     }
+
+    #[tracing::instrument(
+        name = "db.oauth2_refresh_token.revoke_bulk",
+        skip_all,
+        fields(db.query.text),
+        err,
+    )]
+    async fn revoke_bulk(
+        &mut self,
+        clock: &dyn Clock,
+        filter: OAuth2SessionFilter<'_>,
+    ) -> Result<usize, Self::Error> {
+        let revoked_at = clock.now();
+        let (sql, arguments) = Query::update()
+            .table(OAuth2RefreshTokens::Table)
+            .value(OAuth2RefreshTokens::RevokedAt, revoked_at)
+            .cond_where(
+                Condition::all()
+                    .add(
+                        Expr::col((OAuth2RefreshTokens::Table, OAuth2RefreshTokens::RevokedAt))
+                            .is_null(),
+                    )
+                    .add(
+                        Expr::col((OAuth2RefreshTokens::Table, OAuth2RefreshTokens::ConsumedAt))
+                            .is_null(),
+                    )
+                    .add(
+                        Expr::col((
+                            OAuth2RefreshTokens::Table,
+                            OAuth2RefreshTokens::OAuth2SessionId,
+                        ))
+                        .in_subquery(
+                            Query::select()
+                                .expr(Expr::col((
+                                    OAuth2Sessions::Table,
+                                    OAuth2Sessions::OAuth2SessionId,
+                                )))
+                                .apply_filter(filter)
+                                .from(OAuth2Sessions::Table)
+                                .take(),
+                        ),
+                    ),
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        let res = sqlx::query_with(&sql, arguments)
+            .traced()
+            .execute(&mut *self.conn)
+            .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
 }
@@ -8,7 +8,7 @@ use std::net::IpAddr;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use mas_data_model::{BrowserSession, Client, Clock, Session, SessionState, User};
+use mas_data_model::{AuthenticatedBy, BrowserSession, Client, Clock, Session, SessionState, User};
 use mas_storage::{
     Page, Pagination,
     oauth2::{OAuth2SessionFilter, OAuth2SessionRepository},
@@ -17,7 +17,7 @@ use mas_storage::{
 use oauth2_types::scope::{Scope, ScopeToken};
 use rand::RngCore;
 use sea_query::{
-    Condition, Expr, PgFunc, PostgresQueryBuilder, Query, SimpleExpr, enum_def,
+    Alias, BinOper, Condition, Expr, PgFunc, PostgresQueryBuilder, Query, SimpleExpr, enum_def,
     extension::postgres::PgExpr,
 };
 use sea_query_binder::SqlxBinder;
@@ -60,6 +60,8 @@ struct OAuthSessionLookup {
     last_active_at: Option<DateTime<Utc>>,
     last_active_ip: Option<IpAddr>,
     human_name: Option<String>,
+    authenticated_by: String,
+    authenticated_by_upstream_oauth_provider_id: Option<Uuid>,
 }
 
 impl Node<Ulid> for OAuthSessionLookup {
@@ -90,6 +92,19 @@ impl TryFrom<OAuthSessionLookup> for Session {
             Some(finished_at) => SessionState::Finished { finished_at },
         };
 
+        let authenticated_by = AuthenticatedBy::from_parts(
+            &value.authenticated_by,
+            value
+                .authenticated_by_upstream_oauth_provider_id
+                .map(Ulid::from),
+        )
+        .map_err(|e| {
+            DatabaseInconsistencyError::on("oauth2_sessions")
+                .column("authenticated_by")
+                .row(id)
+                .source(e)
+        })?;
+
         Ok(Session {
             id,
             state,
@@ -102,6 +117,7 @@ impl TryFrom<OAuthSessionLookup> for Session {
             last_active_at: value.last_active_at,
             last_active_ip: value.last_active_ip,
             human_name: value.human_name,
+            authenticated_by,
         })
     }
 }
@@ -196,6 +212,24 @@ impl Filter for OAuth2SessionFilter<'_> {
                 Expr::col((OAuth2Sessions::Table, OAuth2Sessions::LastActiveAt))
                     .lt(last_active_before)
             }))
+            .add_option(self.finished_after().map(|finished_after| {
+                Expr::col((OAuth2Sessions::Table, OAuth2Sessions::FinishedAt)).gt(finished_after)
+            }))
+            .add_option(self.created_after().map(|created_after| {
+                Expr::col((OAuth2Sessions::Table, OAuth2Sessions::CreatedAt)).gt(created_after)
+            }))
+            .add_option(self.last_active_ip_in().map(|network| {
+                // `<<=` is the PostgreSQL "is contained within or equals" operator for
+                // network types
+                Expr::col((OAuth2Sessions::Table, OAuth2Sessions::LastActiveIp)).binary(
+                    BinOper::Custom("<<="),
+                    Expr::val(network.to_string()).cast_as(Alias::new("inet")),
+                )
+            }))
+            .add_option(self.excluded_id().map(|excluded_id| {
+                Expr::col((OAuth2Sessions::Table, OAuth2Sessions::OAuth2SessionId))
+                    .ne(Uuid::from(excluded_id))
+            }))
     }
 }
 
@@ -227,6 +261,8 @@ impl OAuth2SessionRepository for PgOAuth2SessionRepository<'_> {
                      , last_active_at
                      , last_active_ip as "last_active_ip: IpAddr"
                      , human_name
+                     , authenticated_by
+                     , authenticated_by_upstream_oauth_provider_id
                 FROM oauth2_sessions
 
                 WHERE oauth2_session_id = $1
@@ -303,6 +339,7 @@ impl OAuth2SessionRepository for PgOAuth2SessionRepository<'_> {
             last_active_at: None,
             last_active_ip: None,
             human_name: None,
+            authenticated_by: AuthenticatedBy::Unknown,
         })
     }
 
@@ -334,6 +371,117 @@ impl OAuth2SessionRepository for PgOAuth2SessionRepository<'_> {
         Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
     }
 
+    #[tracing::instrument(
+        name = "db.oauth2_session.transfer_bulk",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn transfer_bulk(
+        &mut self,
+        filter: OAuth2SessionFilter<'_>,
+        user: &User,
+    ) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::update()
+            .table(OAuth2Sessions::Table)
+            .value(OAuth2Sessions::UserId, Uuid::from(user.id))
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let res = sqlx::query_with(&sql, arguments)
+            .traced()
+            .execute(&mut *self.conn)
+            .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
+    #[tracing::instrument(
+        name = "db.oauth2_session.delete_finished_before",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn delete_finished_before(
+        &mut self,
+        user: &User,
+        finished_before: DateTime<Utc>,
+    ) -> Result<usize, Self::Error> {
+        // Lock the sessions first, so that the set of sessions we delete the
+        // dependent rows of is the same as the set of sessions we delete
+        let session_ids: Vec<Uuid> = sqlx::query_scalar!(
+            r#"
+                SELECT oauth2_session_id
+                FROM oauth2_sessions
+                WHERE user_id = $1
+                  AND finished_at IS NOT NULL
+                  AND finished_at < $2
+                FOR UPDATE
+            "#,
+            Uuid::from(user.id),
+            finished_before,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        if session_ids.is_empty() {
+            return Ok(0);
+        }
+
+        sqlx::query!(
+            r#"
+                DELETE FROM oauth2_refresh_tokens
+                WHERE oauth2_session_id = ANY($1::uuid[])
+            "#,
+            &session_ids,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM oauth2_access_tokens
+                WHERE oauth2_session_id = ANY($1::uuid[])
+            "#,
+            &session_ids,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM oauth2_authorization_grants
+                WHERE oauth2_session_id = ANY($1::uuid[])
+            "#,
+            &session_ids,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM oauth2_sessions
+                WHERE oauth2_session_id = ANY($1::uuid[])
+            "#,
+            &session_ids,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.oauth2_session.finish",
         skip_all,
@@ -429,6 +577,17 @@ impl OAuth2SessionRepository for PgOAuth2SessionRepository<'_> {
                 Expr::col((OAuth2Sessions::Table, OAuth2Sessions::HumanName)),
                 OAuthSessionLookupIden::HumanName,
             )
+            .expr_as(
+                Expr::col((OAuth2Sessions::Table, OAuth2Sessions::AuthenticatedBy)),
+                OAuthSessionLookupIden::AuthenticatedBy,
+            )
+            .expr_as(
+                Expr::col((
+                    OAuth2Sessions::Table,
+                    OAuth2Sessions::AuthenticatedByUpstreamOAuthProviderId,
+                )),
+                OAuthSessionLookupIden::AuthenticatedByUpstreamOauthProviderId,
+            )
             .from(OAuth2Sessions::Table)
             .apply_filter(filter)
             .generate_pagination(
@@ -593,6 +752,42 @@ impl OAuth2SessionRepository for PgOAuth2SessionRepository<'_> {
         Ok(session)
     }
 
+    #[tracing::instrument(
+        name = "repository.oauth2_session.set_authenticated_by",
+        skip(self),
+        fields(
+            %session.id,
+            session.authenticated_by = %authenticated_by,
+        ),
+        err,
+    )]
+    async fn set_authenticated_by(
+        &mut self,
+        mut session: Session,
+        authenticated_by: AuthenticatedBy,
+    ) -> Result<Session, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_sessions
+                SET authenticated_by = $2
+                  , authenticated_by_upstream_oauth_provider_id = $3
+                WHERE oauth2_session_id = $1
+            "#,
+            Uuid::from(session.id),
+            authenticated_by.as_str(),
+            authenticated_by.upstream_provider_id().map(Uuid::from),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        session.authenticated_by = authenticated_by;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(session)
+    }
+
     #[tracing::instrument(
         name = "db.oauth2_session.find_by_browser_session",
         skip_all,
@@ -617,6 +812,8 @@ impl OAuth2SessionRepository for PgOAuth2SessionRepository<'_> {
                      , last_active_at
                      , last_active_ip as "last_active_ip: IpAddr"
                      , human_name
+                     , authenticated_by
+                     , authenticated_by_upstream_oauth_provider_id
                 FROM oauth2_sessions
 
                 WHERE user_session_id = $1
@@ -631,4 +828,39 @@ impl OAuth2SessionRepository for PgOAuth2SessionRepository<'_> {
 
         Ok(Some(session.try_into()?))
     }
+
+    #[tracing::instrument(
+        name = "repository.oauth2_session.set_scope",
+        skip(self),
+        fields(
+            %session.id,
+            %scope,
+        ),
+        err,
+    )]
+    async fn set_scope(
+        &mut self,
+        mut session: Session,
+        scope: Scope,
+    ) -> Result<Session, Self::Error> {
+        let scope_list: Vec<String> = scope.iter().map(|s| s.as_str().to_owned()).collect();
+        let res = sqlx::query!(
+            r#"
+                UPDATE oauth2_sessions
+                SET scope_list = $2
+                WHERE oauth2_session_id = $1
+            "#,
+            Uuid::from(session.id),
+            &scope_list,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        session.scope = scope;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(session)
+    }
 }
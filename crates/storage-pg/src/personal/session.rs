@@ -698,5 +698,9 @@ impl Filter for PersonalSessionFilter<'_> {
                     column.is_null()
                 }
             }))
+            .add_option(self.excluded_id().map(|excluded_id| {
+                Expr::col((PersonalSessions::Table, PersonalSessions::PersonalSessionId))
+                    .ne(Uuid::from(excluded_id))
+            }))
     }
 }
@@ -0,0 +1,307 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! A module containing the PostgreSQL implementation of the provisioning
+//! reports storage.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{Clock, ProvisioningDiscrepancy, ProvisioningReport};
+use mas_storage::provisioning_report::ProvisioningReportRepository;
+use rand::RngCore;
+use sqlx::{PgConnection, types::Json};
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, DatabaseInconsistencyError, ExecuteExt};
+
+/// An implementation of [`ProvisioningReportRepository`] for a PostgreSQL
+/// connection.
+pub struct PgProvisioningReportRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgProvisioningReportRepository<'c> {
+    /// Create a new [`PgProvisioningReportRepository`] from an active
+    /// PostgreSQL connection.
+    #[must_use]
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct ProvisioningReportLookup {
+    provisioning_report_id: Uuid,
+    created_at: DateTime<Utc>,
+    fix: bool,
+    completed_at: Option<DateTime<Utc>>,
+    users_checked: i64,
+    discrepancies: Json<Vec<ProvisioningDiscrepancy>>,
+}
+
+impl TryFrom<ProvisioningReportLookup> for ProvisioningReport {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: ProvisioningReportLookup) -> Result<Self, Self::Error> {
+        let id = Ulid::from(value.provisioning_report_id);
+        let users_checked = value.users_checked.try_into().map_err(|e| {
+            DatabaseInconsistencyError::on("provisioning_reports")
+                .column("users_checked")
+                .row(id)
+                .source(e)
+        })?;
+
+        Ok(ProvisioningReport {
+            id,
+            created_at: value.created_at,
+            fix: value.fix,
+            completed_at: value.completed_at,
+            users_checked,
+            discrepancies: value.discrepancies.0,
+        })
+    }
+}
+
+#[async_trait]
+impl ProvisioningReportRepository for PgProvisioningReportRepository<'_> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.provisioning_report.lookup",
+        skip_all,
+        fields(
+            db.query.text,
+            provisioning_report.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<ProvisioningReport>, Self::Error> {
+        let row = sqlx::query_as!(
+            ProvisioningReportLookup,
+            r#"
+            SELECT provisioning_report_id
+                 , created_at
+                 , fix
+                 , completed_at
+                 , users_checked
+                 , discrepancies as "discrepancies: Json<Vec<ProvisioningDiscrepancy>>"
+            FROM provisioning_reports
+            WHERE provisioning_report_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.provisioning_report.add",
+        skip_all,
+        fields(
+            db.query.text,
+            provisioning_report.id,
+            provisioning_report.fix = fix,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        fix: bool,
+    ) -> Result<ProvisioningReport, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("provisioning_report.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+            INSERT INTO provisioning_reports
+                (provisioning_report_id, created_at, fix)
+            VALUES ($1, $2, $3)
+            "#,
+            Uuid::from(id),
+            created_at,
+            fix,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(ProvisioningReport {
+            id,
+            created_at,
+            fix,
+            completed_at: None,
+            users_checked: 0,
+            discrepancies: Vec::new(),
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.provisioning_report.add_progress",
+        skip_all,
+        fields(
+            db.query.text,
+            %report.id,
+            provisioning_report.users_checked = users_checked,
+            provisioning_report.discrepancies = discrepancies.len(),
+        ),
+        err,
+    )]
+    async fn add_progress(
+        &mut self,
+        mut report: ProvisioningReport,
+        users_checked: u64,
+        discrepancies: Vec<ProvisioningDiscrepancy>,
+    ) -> Result<ProvisioningReport, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+            UPDATE provisioning_reports
+            SET users_checked = users_checked + $2
+              , discrepancies = discrepancies || $3
+            WHERE provisioning_report_id = $1
+              AND completed_at IS NULL
+            "#,
+            Uuid::from(report.id),
+            i64::try_from(users_checked).map_err(DatabaseError::to_invalid_operation)?,
+            Json(&discrepancies) as _,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        report.users_checked += users_checked;
+        report.discrepancies.extend(discrepancies);
+        Ok(report)
+    }
+
+    #[tracing::instrument(
+        name = "db.provisioning_report.complete",
+        skip_all,
+        fields(
+            db.query.text,
+            %report.id,
+        ),
+        err,
+    )]
+    async fn complete(
+        &mut self,
+        clock: &dyn Clock,
+        mut report: ProvisioningReport,
+    ) -> Result<ProvisioningReport, Self::Error> {
+        let completed_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+            UPDATE provisioning_reports
+            SET completed_at = $2
+            WHERE provisioning_report_id = $1
+              AND completed_at IS NULL
+            "#,
+            Uuid::from(report.id),
+            completed_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        report.completed_at = Some(completed_at);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_data_model::{
+        Clock, ProvisioningDiscrepancy, ProvisioningDiscrepancyKind, clock::MockClock,
+    };
+    use mas_storage::provisioning_report::ProvisioningReportRepository;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use crate::provisioning_report::PgProvisioningReportRepository;
+
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_provisioning_report(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut conn = pool.acquire().await.unwrap();
+        let mut repo = PgProvisioningReportRepository::new(&mut conn);
+
+        // Unknown reports are not found
+        assert!(repo.lookup(Ulid::nil()).await.unwrap().is_none());
+
+        // Create a report
+        let report = repo.add(&mut rng, &clock, true).await.unwrap();
+        assert!(report.fix);
+        assert!(!report.is_completed());
+        assert!(report.discrepancies.is_empty());
+
+        let report_fetched = repo.lookup(report.id).await.unwrap().unwrap();
+        assert_eq!(report_fetched, report);
+
+        // Add the results of two batches
+        clock.advance(chrono::Duration::seconds(10));
+        let discrepancies = vec![
+            ProvisioningDiscrepancy {
+                user_id: Ulid::from_datetime_with_source(clock.now().into(), &mut rng),
+                username: "alice".to_owned(),
+                kind: ProvisioningDiscrepancyKind::MissingOnHomeserver,
+                fixed: true,
+            },
+            ProvisioningDiscrepancy {
+                user_id: Ulid::from_datetime_with_source(clock.now().into(), &mut rng),
+                username: "bob".to_owned(),
+                kind: ProvisioningDiscrepancyKind::DisplaynameDrift,
+                fixed: false,
+            },
+        ];
+        let report = repo
+            .add_progress(report, 2, vec![discrepancies[0].clone()])
+            .await
+            .unwrap();
+        let report = repo
+            .add_progress(report, 3, vec![discrepancies[1].clone()])
+            .await
+            .unwrap();
+        assert!(!report.is_completed());
+        assert_eq!(report.users_checked, 5);
+        assert_eq!(report.discrepancies, discrepancies);
+
+        let report_fetched = repo.lookup(report.id).await.unwrap().unwrap();
+        assert_eq!(report_fetched, report);
+
+        // Complete it
+        let report = repo.complete(&clock, report).await.unwrap();
+        assert_eq!(report.completed_at, Some(clock.now()));
+        assert_eq!(report.users_checked, 5);
+        assert_eq!(report.discrepancies, discrepancies);
+
+        let report_fetched = repo.lookup(report.id).await.unwrap().unwrap();
+        assert_eq!(report_fetched, report);
+
+        // A completed report can't be updated anymore
+        assert!(
+            repo.add_progress(report.clone(), 1, Vec::new())
+                .await
+                .is_err()
+        );
+        assert!(repo.complete(&clock, report).await.is_err());
+    }
+}
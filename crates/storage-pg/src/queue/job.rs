@@ -9,15 +9,25 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
 use mas_data_model::Clock;
-use mas_storage::queue::{Job, QueueJobRepository, Worker};
+use mas_storage::{
+    Page, Pagination,
+    queue::{Job, QueueJob, QueueJobFilter, QueueJobRepository, Worker},
+};
 use opentelemetry_semantic_conventions::trace::DB_QUERY_TEXT;
 use rand::RngCore;
+use sea_query::{Alias, Expr, PostgresQueryBuilder, Query};
+use sea_query_binder::SqlxBinder;
 use sqlx::PgConnection;
 use tracing::Instrument;
 use ulid::Ulid;
 use uuid::Uuid;
 
-use crate::{DatabaseError, DatabaseInconsistencyError, ExecuteExt};
+use crate::{
+    DatabaseError, DatabaseInconsistencyError, ExecuteExt,
+    filter::{Filter, StatementExt},
+    iden::QueueJobs,
+    pagination::QueryBuilderExt,
+};
 
 /// An implementation of [`QueueJobRepository`] for a PostgreSQL connection.
 pub struct PgQueueJobRepository<'c> {
@@ -73,6 +83,91 @@ impl TryFrom<JobReservationResult> for Job {
     }
 }
 
+mod priv_ {
+    // The enum_def macro generates a public enum, which we don't want, because it
+    // triggers the missing docs warning
+    #![allow(missing_docs)]
+
+    use chrono::{DateTime, Utc};
+    use mas_storage::pagination::Node;
+    use sea_query::enum_def;
+    use ulid::Ulid;
+    use uuid::Uuid;
+
+    #[derive(sqlx::FromRow)]
+    #[enum_def]
+    pub(super) struct QueueJobLookup {
+        pub(super) queue_job_id: Uuid,
+        pub(super) queue_name: String,
+        pub(super) payload: serde_json::Value,
+        pub(super) user_id: Option<Uuid>,
+        pub(super) status: String,
+        pub(super) attempt: i32,
+        pub(super) created_at: DateTime<Utc>,
+        pub(super) scheduled_at: Option<DateTime<Utc>>,
+        pub(super) started_at: Option<DateTime<Utc>>,
+        pub(super) completed_at: Option<DateTime<Utc>>,
+        pub(super) failed_at: Option<DateTime<Utc>>,
+        pub(super) failed_reason: Option<String>,
+        pub(super) next_attempt_id: Option<Uuid>,
+    }
+
+    impl Node<Ulid> for QueueJobLookup {
+        fn cursor(&self) -> Ulid {
+            self.queue_job_id.into()
+        }
+    }
+}
+
+use priv_::{QueueJobLookup, QueueJobLookupIden};
+
+impl TryFrom<QueueJobLookup> for QueueJob {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: QueueJobLookup) -> Result<Self, Self::Error> {
+        let id = value.queue_job_id.into();
+
+        let status = value.status.parse().map_err(|()| {
+            DatabaseInconsistencyError::on("queue_jobs")
+                .column("status")
+                .row(id)
+        })?;
+
+        let attempt = value.attempt.try_into().map_err(|e| {
+            DatabaseInconsistencyError::on("queue_jobs")
+                .column("attempt")
+                .row(id)
+                .source(e)
+        })?;
+
+        Ok(Self {
+            id,
+            queue_name: value.queue_name,
+            payload: value.payload,
+            user_id: value.user_id.map(Ulid::from),
+            status,
+            attempt,
+            created_at: value.created_at,
+            scheduled_at: value.scheduled_at,
+            started_at: value.started_at,
+            completed_at: value.completed_at,
+            failed_at: value.failed_at,
+            failed_reason: value.failed_reason,
+            next_attempt_id: value.next_attempt_id.map(Ulid::from),
+        })
+    }
+}
+
+impl Filter for QueueJobFilter<'_> {
+    fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
+        sea_query::Condition::all().add_option(
+            self.user().map(|user| {
+                Expr::col((QueueJobs::Table, QueueJobs::UserId)).eq(Uuid::from(user.id))
+            }),
+        )
+    }
+}
+
 #[async_trait]
 impl QueueJobRepository for PgQueueJobRepository<'_> {
     type Error = DatabaseError;
@@ -94,6 +189,7 @@ impl QueueJobRepository for PgQueueJobRepository<'_> {
         queue_name: &str,
         payload: serde_json::Value,
         metadata: serde_json::Value,
+        user_id: Option<Ulid>,
     ) -> Result<(), Self::Error> {
         let created_at = clock.now();
         let id = Ulid::from_datetime_with_source(created_at.into(), rng);
@@ -102,13 +198,14 @@ impl QueueJobRepository for PgQueueJobRepository<'_> {
         sqlx::query!(
             r#"
                 INSERT INTO queue_jobs
-                    (queue_job_id, queue_name, payload, metadata, created_at)
-                VALUES ($1, $2, $3, $4, $5)
+                    (queue_job_id, queue_name, payload, metadata, user_id, created_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
             "#,
             Uuid::from(id),
             queue_name,
             payload,
             metadata,
+            user_id.map(Uuid::from),
             created_at,
         )
         .traced()
@@ -136,6 +233,7 @@ impl QueueJobRepository for PgQueueJobRepository<'_> {
         queue_name: &str,
         payload: serde_json::Value,
         metadata: serde_json::Value,
+        user_id: Option<Ulid>,
         scheduled_at: DateTime<Utc>,
         schedule_name: Option<&str>,
     ) -> Result<(), Self::Error> {
@@ -146,13 +244,14 @@ impl QueueJobRepository for PgQueueJobRepository<'_> {
         sqlx::query!(
             r#"
                 INSERT INTO queue_jobs
-                    (queue_job_id, queue_name, payload, metadata, created_at, scheduled_at, schedule_name, status)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, 'scheduled')
+                    (queue_job_id, queue_name, payload, metadata, user_id, created_at, scheduled_at, schedule_name, status)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'scheduled')
             "#,
             Uuid::from(id),
             queue_name,
             payload,
             metadata,
+            user_id.map(Uuid::from),
             created_at,
             scheduled_at,
             schedule_name,
@@ -353,9 +452,9 @@ impl QueueJobRepository for PgQueueJobRepository<'_> {
         let res = sqlx::query!(
             r#"
                 INSERT INTO queue_jobs
-                    (queue_job_id, queue_name, payload, metadata, created_at,
+                    (queue_job_id, queue_name, payload, metadata, user_id, created_at,
                      attempt, scheduled_at, schedule_name, status)
-                SELECT $1, queue_name, payload, metadata, $2, attempt + 1, $3, schedule_name, 'scheduled'
+                SELECT $1, queue_name, payload, metadata, user_id, $2, attempt + 1, $3, schedule_name, 'scheduled'
                 FROM queue_jobs
                 WHERE queue_job_id = $4
                   AND status = 'failed'
@@ -444,4 +543,151 @@ impl QueueJobRepository for PgQueueJobRepository<'_> {
         let count = res.rows_affected();
         Ok(usize::try_from(count).unwrap_or(usize::MAX))
     }
+
+    #[tracing::instrument(
+        name = "db.queue_job.lookup",
+        skip_all,
+        fields(
+            db.query.text,
+            queue_job.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<QueueJob>, Self::Error> {
+        let res = sqlx::query_as!(
+            QueueJobLookup,
+            r#"
+                SELECT queue_job_id
+                     , queue_name
+                     , payload
+                     , user_id
+                     , status::text AS "status!"
+                     , attempt
+                     , created_at
+                     , scheduled_at
+                     , started_at
+                     , completed_at
+                     , failed_at
+                     , failed_reason
+                     , next_attempt_id
+                FROM queue_jobs
+                WHERE queue_job_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.try_into()?))
+    }
+
+    #[tracing::instrument(
+        name = "db.queue_job.list",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn list(
+        &mut self,
+        filter: QueueJobFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<QueueJob>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::QueueJobId)),
+                QueueJobLookupIden::QueueJobId,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::QueueName)),
+                QueueJobLookupIden::QueueName,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::Payload)),
+                QueueJobLookupIden::Payload,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::UserId)),
+                QueueJobLookupIden::UserId,
+            )
+            .expr_as(
+                // The status is an enum, which sqlx can't decode as a string
+                Expr::col((QueueJobs::Table, QueueJobs::Status)).cast_as(Alias::new("text")),
+                QueueJobLookupIden::Status,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::Attempt)),
+                QueueJobLookupIden::Attempt,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::CreatedAt)),
+                QueueJobLookupIden::CreatedAt,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::ScheduledAt)),
+                QueueJobLookupIden::ScheduledAt,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::StartedAt)),
+                QueueJobLookupIden::StartedAt,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::CompletedAt)),
+                QueueJobLookupIden::CompletedAt,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::FailedAt)),
+                QueueJobLookupIden::FailedAt,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::FailedReason)),
+                QueueJobLookupIden::FailedReason,
+            )
+            .expr_as(
+                Expr::col((QueueJobs::Table, QueueJobs::NextAttemptId)),
+                QueueJobLookupIden::NextAttemptId,
+            )
+            .from(QueueJobs::Table)
+            .apply_filter(filter)
+            .generate_pagination((QueueJobs::Table, QueueJobs::QueueJobId), pagination)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<QueueJobLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination.process(edges).try_map(QueueJob::try_from)?;
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.queue_job.count",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count(&mut self, filter: QueueJobFilter<'_>) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(Expr::col((QueueJobs::Table, QueueJobs::QueueJobId)).count())
+            .from(QueueJobs::Table)
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
 }
@@ -16,21 +16,26 @@ use mas_storage::{
         CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository,
         CompatSsoLoginRepository,
     },
+    email_probe::EmailProbeRepository,
     oauth2::{
         OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository, OAuth2ClientRepository,
-        OAuth2DeviceCodeGrantRepository, OAuth2RefreshTokenRepository, OAuth2SessionRepository,
+        OAuth2DeviceCodeGrantRepository, OAuth2PushedAuthorizationRequestRepository,
+        OAuth2RefreshTokenRepository, OAuth2SessionRepository,
     },
     personal::PersonalSessionRepository,
     policy_data::PolicyDataRepository,
+    provisioning_report::ProvisioningReportRepository,
     queue::{QueueJobRepository, QueueScheduleRepository, QueueWorkerRepository},
+    syn2mas_run::Syn2masRunRepository,
     upstream_oauth2::{
         UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
         UpstreamOAuthSessionRepository,
     },
     user::{
-        BrowserSessionRepository, UserEmailRepository, UserPasswordRepository,
-        UserRecoveryRepository, UserRegistrationRepository, UserRegistrationTokenRepository,
-        UserRepository, UserTermsRepository,
+        BrowserSessionRepository, UserEmailRepository, UserLoginFailureRepository,
+        UserNoteRepository, UserPasswordRepository, UserRecoveryRepository,
+        UserRegistrationRepository, UserRegistrationTokenRepository, UserRepository,
+        UserTermsRepository,
     },
 };
 use sqlx::{PgConnection, PgPool, Postgres, Transaction};
@@ -39,45 +44,60 @@ use tracing::Instrument;
 use crate::{
     DatabaseError,
     app_session::PgAppSessionRepository,
+    circuit_breaker::{CircuitBreaker, MAX_ATTEMPTS, backoff, is_connection_error},
     compat::{
         PgCompatAccessTokenRepository, PgCompatRefreshTokenRepository, PgCompatSessionRepository,
         PgCompatSsoLoginRepository,
     },
+    email_probe::PgEmailProbeRepository,
     oauth2::{
         PgOAuth2AccessTokenRepository, PgOAuth2AuthorizationGrantRepository,
         PgOAuth2ClientRepository, PgOAuth2DeviceCodeGrantRepository,
-        PgOAuth2RefreshTokenRepository, PgOAuth2SessionRepository,
+        PgOAuth2PushedAuthorizationRequestRepository, PgOAuth2RefreshTokenRepository,
+        PgOAuth2SessionRepository,
     },
     personal::{PgPersonalAccessTokenRepository, PgPersonalSessionRepository},
     policy_data::PgPolicyDataRepository,
+    provisioning_report::PgProvisioningReportRepository,
     queue::{
         job::PgQueueJobRepository, schedule::PgQueueScheduleRepository,
         worker::PgQueueWorkerRepository,
     },
+    syn2mas_run::PgSyn2masRunRepository,
     telemetry::DB_CLIENT_CONNECTIONS_CREATE_TIME_HISTOGRAM,
     upstream_oauth2::{
         PgUpstreamOAuthLinkRepository, PgUpstreamOAuthProviderRepository,
         PgUpstreamOAuthSessionRepository,
     },
     user::{
-        PgBrowserSessionRepository, PgUserEmailRepository, PgUserPasswordRepository,
-        PgUserRecoveryRepository, PgUserRegistrationRepository, PgUserRegistrationTokenRepository,
-        PgUserRepository, PgUserTermsRepository,
+        PgBrowserSessionRepository, PgUserEmailRepository, PgUserLoginFailureRepository,
+        PgUserNoteRepository, PgUserPasswordRepository, PgUserRecoveryRepository,
+        PgUserRegistrationRepository, PgUserRegistrationTokenRepository, PgUserRepository,
+        PgUserTermsRepository,
     },
 };
 
 /// An implementation of the [`RepositoryFactory`] trait backed by a PostgreSQL
 /// connection pool.
+///
+/// Starting a transaction is retried a few times with a capped backoff if the
+/// connection to the database is lost. If that still fails, a circuit breaker
+/// opens and the factory fails fast with an 'unavailable' [`RepositoryError`]
+/// for a few seconds, instead of having every caller wait on the pool.
 #[derive(Clone)]
 pub struct PgRepositoryFactory {
     pool: PgPool,
+    circuit_breaker: CircuitBreaker,
 }
 
 impl PgRepositoryFactory {
     /// Create a new [`PgRepositoryFactory`] from a PostgreSQL connection pool.
     #[must_use]
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            circuit_breaker: CircuitBreaker::default(),
+        }
     }
 
     /// Box the factory
@@ -96,11 +116,33 @@ impl PgRepositoryFactory {
 #[async_trait]
 impl RepositoryFactory for PgRepositoryFactory {
     async fn create(&self) -> Result<BoxRepository, RepositoryError> {
+        if let Some(retry_after) = self.circuit_breaker.retry_after() {
+            return Err(RepositoryError::unavailable(retry_after));
+        }
+
         let start = std::time::Instant::now();
-        let repo = PgRepository::from_pool(&self.pool)
-            .await
-            .map_err(RepositoryError::from_error)?
-            .boxed();
+        let mut attempt = 0;
+        let repo = loop {
+            match PgRepository::from_pool(&self.pool).await {
+                Ok(repo) => break repo.boxed(),
+                Err(DatabaseError::Driver { source }) if is_connection_error(&source) => {
+                    attempt += 1;
+                    if attempt >= MAX_ATTEMPTS {
+                        tracing::warn!(
+                            error = &source as &dyn std::error::Error,
+                            "Failed to acquire a database connection after {attempt} attempts"
+                        );
+                        let retry_after = self.circuit_breaker.trip();
+                        return Err(RepositoryError::unavailable(retry_after));
+                    }
+
+                    tokio::time::sleep(backoff(attempt)).await;
+                }
+                Err(e) => return Err(RepositoryError::from_error(e)),
+            }
+        };
+
+        self.circuit_breaker.reset();
 
         // Measure the time it took to create the connection
         let duration = start.elapsed();
@@ -236,6 +278,16 @@ where
         Box::new(PgUserPasswordRepository::new(self.conn.as_mut()))
     }
 
+    fn user_login_failure<'c>(
+        &'c mut self,
+    ) -> Box<dyn UserLoginFailureRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserLoginFailureRepository::new(self.conn.as_mut()))
+    }
+
+    fn user_note<'c>(&'c mut self) -> Box<dyn UserNoteRepository<Error = Self::Error> + 'c> {
+        Box::new(PgUserNoteRepository::new(self.conn.as_mut()))
+    }
+
     fn user_recovery<'c>(
         &'c mut self,
     ) -> Box<dyn UserRecoveryRepository<Error = Self::Error> + 'c> {
@@ -306,6 +358,14 @@ where
         Box::new(PgOAuth2DeviceCodeGrantRepository::new(self.conn.as_mut()))
     }
 
+    fn oauth2_pushed_authorization_request<'c>(
+        &'c mut self,
+    ) -> Box<dyn OAuth2PushedAuthorizationRequestRepository<Error = Self::Error> + 'c> {
+        Box::new(PgOAuth2PushedAuthorizationRequestRepository::new(
+            self.conn.as_mut(),
+        ))
+    }
+
     fn compat_session<'c>(
         &'c mut self,
     ) -> Box<dyn CompatSessionRepository<Error = Self::Error> + 'c> {
@@ -360,4 +420,18 @@ where
     fn policy_data<'c>(&'c mut self) -> Box<dyn PolicyDataRepository<Error = Self::Error> + 'c> {
         Box::new(PgPolicyDataRepository::new(self.conn.as_mut()))
     }
+
+    fn provisioning_report<'c>(
+        &'c mut self,
+    ) -> Box<dyn ProvisioningReportRepository<Error = Self::Error> + 'c> {
+        Box::new(PgProvisioningReportRepository::new(self.conn.as_mut()))
+    }
+
+    fn syn2mas_run<'c>(&'c mut self) -> Box<dyn Syn2masRunRepository<Error = Self::Error> + 'c> {
+        Box::new(PgSyn2masRunRepository::new(self.conn.as_mut()))
+    }
+
+    fn email_probe<'c>(&'c mut self) -> Box<dyn EmailProbeRepository<Error = Self::Error> + 'c> {
+        Box::new(PgEmailProbeRepository::new(self.conn.as_mut()))
+    }
 }
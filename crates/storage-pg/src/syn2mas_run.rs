@@ -0,0 +1,167 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! A module containing the PostgreSQL implementation of the syn2mas runs
+//! storage.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::Syn2masRun;
+use mas_storage::{Page, Pagination, pagination::Node, syn2mas_run::Syn2masRunRepository};
+use sea_query::{Expr, PostgresQueryBuilder, Query, enum_def};
+use sea_query_binder::SqlxBinder;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    DatabaseError, DatabaseInconsistencyError, iden::Syn2masRuns, pagination::QueryBuilderExt,
+    tracing::ExecuteExt,
+};
+
+/// An implementation of [`Syn2masRunRepository`] for a PostgreSQL connection.
+pub struct PgSyn2masRunRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgSyn2masRunRepository<'c> {
+    /// Create a new [`PgSyn2masRunRepository`] from an active PostgreSQL
+    /// connection.
+    #[must_use]
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[enum_def]
+struct Syn2masRunLookup {
+    syn2mas_run_id: Uuid,
+    started_at: DateTime<Utc>,
+    dry_run: bool,
+    finished_at: Option<DateTime<Utc>>,
+    user_count: Option<i64>,
+    compat_session_count: Option<i64>,
+}
+
+impl Node<Ulid> for Syn2masRunLookup {
+    fn cursor(&self) -> Ulid {
+        self.syn2mas_run_id.into()
+    }
+}
+
+impl TryFrom<Syn2masRunLookup> for Syn2masRun {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: Syn2masRunLookup) -> Result<Self, Self::Error> {
+        let id = Ulid::from(value.syn2mas_run_id);
+        let user_count = value
+            .user_count
+            .map(u64::try_from)
+            .transpose()
+            .map_err(|e| {
+                DatabaseInconsistencyError::on("syn2mas_runs")
+                    .column("user_count")
+                    .row(id)
+                    .source(e)
+            })?;
+        let compat_session_count = value
+            .compat_session_count
+            .map(u64::try_from)
+            .transpose()
+            .map_err(|e| {
+                DatabaseInconsistencyError::on("syn2mas_runs")
+                    .column("compat_session_count")
+                    .row(id)
+                    .source(e)
+            })?;
+
+        Ok(Syn2masRun {
+            id,
+            started_at: value.started_at,
+            dry_run: value.dry_run,
+            finished_at: value.finished_at,
+            user_count,
+            compat_session_count,
+        })
+    }
+}
+
+#[async_trait]
+impl Syn2masRunRepository for PgSyn2masRunRepository<'_> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.syn2mas_run.list",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn list(&mut self, pagination: Pagination) -> Result<Page<Syn2masRun>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((Syn2masRuns::Table, Syn2masRuns::Syn2masRunId)),
+                Syn2masRunLookupIden::Syn2masRunId,
+            )
+            .expr_as(
+                Expr::col((Syn2masRuns::Table, Syn2masRuns::StartedAt)),
+                Syn2masRunLookupIden::StartedAt,
+            )
+            .expr_as(
+                Expr::col((Syn2masRuns::Table, Syn2masRuns::DryRun)),
+                Syn2masRunLookupIden::DryRun,
+            )
+            .expr_as(
+                Expr::col((Syn2masRuns::Table, Syn2masRuns::FinishedAt)),
+                Syn2masRunLookupIden::FinishedAt,
+            )
+            .expr_as(
+                Expr::col((Syn2masRuns::Table, Syn2masRuns::UserCount)),
+                Syn2masRunLookupIden::UserCount,
+            )
+            .expr_as(
+                Expr::col((Syn2masRuns::Table, Syn2masRuns::CompatSessionCount)),
+                Syn2masRunLookupIden::CompatSessionCount,
+            )
+            .from(Syn2masRuns::Table)
+            .generate_pagination((Syn2masRuns::Table, Syn2masRuns::Syn2masRunId), pagination)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<Syn2masRunLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination.process(edges).try_map(Syn2masRun::try_from)?;
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.syn2mas_run.count",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count(&mut self) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(Expr::col((Syn2masRuns::Table, Syn2masRuns::Syn2masRunId)).count())
+            .from(Syn2masRuns::Table)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
+}
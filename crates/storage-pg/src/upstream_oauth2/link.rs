@@ -16,7 +16,7 @@ use opentelemetry_semantic_conventions::trace::DB_QUERY_TEXT;
 use rand::RngCore;
 use sea_query::{Expr, PostgresQueryBuilder, Query, enum_def};
 use sea_query_binder::SqlxBinder;
-use sqlx::PgConnection;
+use sqlx::{Connection, PgConnection};
 use tracing::Instrument;
 use ulid::Ulid;
 use uuid::Uuid;
@@ -116,6 +116,42 @@ impl Filter for UpstreamOAuthLinkFilter<'_> {
     }
 }
 
+/// Insert a batch of upstream OAuth links with a single statement
+async fn insert_upstream_oauth_links(
+    conn: &mut PgConnection,
+    ids: &[Uuid],
+    provider_ids: &[Uuid],
+    user_ids: &[Uuid],
+    subjects: &[String],
+    created_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO upstream_oauth_links (
+                upstream_oauth_link_id,
+                upstream_oauth_provider_id,
+                user_id,
+                subject,
+                human_account_name,
+                created_at
+            )
+            SELECT upstream_oauth_link_id, upstream_oauth_provider_id, user_id, subject, NULL, $5
+            FROM UNNEST($1::uuid[], $2::uuid[], $3::uuid[], $4::text[])
+                AS t(upstream_oauth_link_id, upstream_oauth_provider_id, user_id, subject)
+        "#,
+        ids,
+        provider_ids,
+        user_ids,
+        subjects,
+        created_at,
+    )
+    .traced()
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
 #[async_trait]
 impl UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'_> {
     type Error = DatabaseError;
@@ -252,6 +288,99 @@ impl UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'_> {
         })
     }
 
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.add_many",
+        skip_all,
+        fields(
+            db.query.text,
+            upstream_oauth_link.count = links.len(),
+        ),
+        err,
+    )]
+    async fn add_many(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        links: &[(&UpstreamOAuthProvider, &User, String)],
+    ) -> Result<Vec<UpstreamOAuthLink>, Self::Error> {
+        if links.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let created_at = clock.now();
+        let mut ids = Vec::with_capacity(links.len());
+        let mut provider_ids = Vec::with_capacity(links.len());
+        let mut user_ids = Vec::with_capacity(links.len());
+        let mut subjects = Vec::with_capacity(links.len());
+        let mut upstream_oauth_links = Vec::with_capacity(links.len());
+
+        for (provider, user, subject) in links {
+            let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+            ids.push(Uuid::from(id));
+            provider_ids.push(Uuid::from(provider.id));
+            user_ids.push(Uuid::from(user.id));
+            subjects.push(subject.clone());
+            upstream_oauth_links.push(UpstreamOAuthLink {
+                id,
+                provider_id: provider.id,
+                user_id: Some(user.id),
+                subject: subject.clone(),
+                human_account_name: None,
+                created_at,
+            });
+        }
+
+        // Insert everything in a savepoint, so that we can find out which item
+        // was rejected if the insert fails
+        let mut savepoint = self.conn.begin().await?;
+        let Err(source) = insert_upstream_oauth_links(
+            &mut savepoint,
+            &ids,
+            &provider_ids,
+            &user_ids,
+            &subjects,
+            created_at,
+        )
+        .await
+        else {
+            savepoint.commit().await?;
+            return Ok(upstream_oauth_links);
+        };
+        savepoint.rollback().await?;
+
+        if source.as_database_error().is_none() {
+            return Err(source.into());
+        }
+
+        // Replay the insert one item at a time to find the one which was
+        // rejected, which also catches duplicates within the batch
+        let mut replay = self.conn.begin().await?;
+        let mut index = None;
+        for i in 0..ids.len() {
+            let range = i..=i;
+            let res = insert_upstream_oauth_links(
+                &mut replay,
+                &ids[range.clone()],
+                &provider_ids[range.clone()],
+                &user_ids[range.clone()],
+                &subjects[range],
+                created_at,
+            )
+            .await;
+
+            if res.is_err() {
+                index = Some(i);
+                break;
+            }
+        }
+        replay.rollback().await?;
+
+        Err(match index {
+            Some(index) => DatabaseError::BatchItem { index, source },
+            None => source.into(),
+        })
+    }
+
     #[tracing::instrument(
         name = "db.upstream_oauth_link.associate_to_user",
         skip_all,
@@ -285,6 +414,46 @@ impl UpstreamOAuthLinkRepository for PgUpstreamOAuthLinkRepository<'_> {
         Ok(())
     }
 
+    #[tracing::instrument(
+        name = "db.upstream_oauth_link.transfer_to_user",
+        skip_all,
+        fields(
+            db.query.text,
+            %upstream_oauth_link.id,
+            %upstream_oauth_link.subject,
+            %user.id,
+            %user.username,
+        ),
+        err,
+    )]
+    async fn transfer_to_user(
+        &mut self,
+        upstream_oauth_link: UpstreamOAuthLink,
+        user: &User,
+    ) -> Result<UpstreamOAuthLink, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE upstream_oauth_links
+                SET user_id = $1
+                WHERE upstream_oauth_link_id = $2
+                  AND user_id IS NOT DISTINCT FROM $3
+            "#,
+            Uuid::from(user.id),
+            Uuid::from(upstream_oauth_link.id),
+            upstream_oauth_link.user_id.map(Uuid::from),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(UpstreamOAuthLink {
+            user_id: Some(user.id),
+            ..upstream_oauth_link
+        })
+    }
+
     #[tracing::instrument(
         name = "db.upstream_oauth_link.list",
         skip_all,
@@ -78,6 +78,7 @@ mod tests {
                     additional_authorization_parameters: Vec::new(),
                     forward_login_hint: false,
                     ui_order: 0,
+                    ui_hidden: false,
                     on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                 },
             )
@@ -181,6 +182,36 @@ mod tests {
             .expect("session to be found in the database");
         assert!(session.is_consumed());
 
+        // A session for which the provider returned an error is marked as failed
+        let failed_session = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &clock,
+                &provider,
+                "another-state".to_owned(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let failed_session = repo
+            .upstream_oauth_session()
+            .fail(&clock, failed_session, "access_denied".to_owned())
+            .await
+            .unwrap();
+        // Reload the session
+        let failed_session = repo
+            .upstream_oauth_session()
+            .lookup(failed_session.id)
+            .await
+            .unwrap()
+            .expect("session to be found in the database");
+        assert!(failed_session.is_failed());
+        assert!(!failed_session.is_pending());
+        assert!(failed_session.failed_at().is_some());
+        assert_eq!(failed_session.error_code(), Some("access_denied"));
+
         let user = repo
             .user()
             .add(&mut rng, &clock, "john".to_owned())
@@ -211,6 +242,30 @@ mod tests {
 
         assert_eq!(repo.upstream_oauth_link().count(filter).await.unwrap(), 1);
 
+        // Transfer the link to another user
+        let link = links.edges[0].node.clone();
+        let other_user = repo
+            .user()
+            .add(&mut rng, &clock, "jane".to_owned())
+            .await
+            .unwrap();
+        let transferred = repo
+            .upstream_oauth_link()
+            .transfer_to_user(link.clone(), &other_user)
+            .await
+            .unwrap();
+        assert_eq!(transferred.id, link.id);
+        assert_eq!(transferred.user_id, Some(other_user.id));
+        assert_eq!(repo.upstream_oauth_link().count(filter).await.unwrap(), 0);
+
+        // Transferring a stale version of the link should fail
+        assert!(
+            repo.upstream_oauth_link()
+                .transfer_to_user(link, &user)
+                .await
+                .is_err()
+        );
+
         // There should be exactly one enabled provider
         assert_eq!(
             repo.upstream_oauth_provider()
@@ -272,7 +327,7 @@ mod tests {
             .count(session_filter)
             .await
             .unwrap();
-        assert_eq!(session_count, 1);
+        assert_eq!(session_count, 2);
 
         // List the sessions for the provider
         let session_page = repo
@@ -281,11 +336,30 @@ mod tests {
             .await
             .unwrap();
 
-        assert_eq!(session_page.edges.len(), 1);
-        assert_eq!(session_page.edges[0].node.id, session.id);
+        assert_eq!(session_page.edges.len(), 2);
+        assert!(
+            session_page
+                .edges
+                .iter()
+                .any(|edge| edge.node.id == session.id)
+        );
+        assert!(
+            session_page
+                .edges
+                .iter()
+                .any(|edge| edge.node.id == failed_session.id)
+        );
         assert!(!session_page.has_next_page);
         assert!(!session_page.has_previous_page);
 
+        // Count the sessions for the link
+        let session_count = repo
+            .upstream_oauth_session()
+            .count(UpstreamOAuthSessionFilter::new().for_link(&transferred))
+            .await
+            .unwrap();
+        assert_eq!(session_count, 1);
+
         // Try deleting the provider
         repo.upstream_oauth_provider()
             .delete(provider)
@@ -350,6 +424,7 @@ mod tests {
                         additional_authorization_parameters: Vec::new(),
                         forward_login_hint: false,
                         ui_order: 0,
+                        ui_hidden: false,
                         on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                     },
                 )
@@ -488,6 +563,7 @@ mod tests {
                     additional_authorization_parameters: Vec::new(),
                     forward_login_hint: false,
                     ui_order: 0,
+                    ui_hidden: false,
                     on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
                 },
             )
@@ -657,4 +733,284 @@ mod tests {
             );
         }
     }
+
+    /// Test adding many upstream OAuth links at once
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_link_repository_add_many(pool: PgPool) {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &clock,
+                UpstreamOAuthProviderParams {
+                    issuer: Some("https://example.com/".to_owned()),
+                    human_name: None,
+                    brand_name: None,
+                    scope: Scope::from_iter([OPENID]),
+                    token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                    id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                    fetch_userinfo: false,
+                    userinfo_signed_response_alg: None,
+                    token_endpoint_signing_alg: None,
+                    client_id: "client-id".to_owned(),
+                    encrypted_client_secret: None,
+                    claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+                    token_endpoint_override: None,
+                    authorization_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    jwks_uri_override: None,
+                    discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
+                    pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
+                    response_mode: None,
+                    additional_authorization_parameters: Vec::new(),
+                    forward_login_hint: false,
+                    ui_order: 0,
+                    ui_hidden: false,
+                    on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                },
+            )
+            .await
+            .unwrap();
+
+        let user = repo
+            .user()
+            .add(&mut rng, &clock, "john".to_owned())
+            .await
+            .unwrap();
+
+        // Add a thousand links at once
+        let batch: Vec<_> = (0..1000)
+            .map(|i| (&provider, &user, format!("subject-{i}")))
+            .collect();
+        let links = repo
+            .upstream_oauth_link()
+            .add_many(&mut rng, &clock, &batch)
+            .await
+            .unwrap();
+        assert_eq!(links.len(), 1000);
+        assert_eq!(links[42].subject, "subject-42");
+        assert_eq!(links[42].user_id, Some(user.id));
+        assert_eq!(links[42].provider_id, provider.id);
+
+        let link = repo
+            .upstream_oauth_link()
+            .find_by_subject(&provider, "subject-999")
+            .await
+            .unwrap()
+            .expect("link to be found in the database");
+        assert_eq!(link, links[999]);
+
+        let filter = UpstreamOAuthLinkFilter::new().for_user(&user);
+        assert_eq!(
+            repo.upstream_oauth_link().count(filter).await.unwrap(),
+            1000
+        );
+
+        // A subject which is already linked is rejected, and the error reports
+        // its index in the batch
+        let batch = [
+            (&provider, &user, "new-subject".to_owned()),
+            (&provider, &user, "subject-500".to_owned()),
+        ];
+        let error = repo
+            .upstream_oauth_link()
+            .add_many(&mut rng, &clock, &batch)
+            .await
+            .unwrap_err();
+        assert_eq!(error.batch_index(), Some(1));
+
+        // Same for duplicates within the batch
+        let batch = [
+            (&provider, &user, "a".to_owned()),
+            (&provider, &user, "b".to_owned()),
+            (&provider, &user, "c".to_owned()),
+            (&provider, &user, "b".to_owned()),
+        ];
+        let error = repo
+            .upstream_oauth_link()
+            .add_many(&mut rng, &clock, &batch)
+            .await
+            .unwrap_err();
+        assert_eq!(error.batch_index(), Some(3));
+
+        // Nothing from the failed batches was written, and the transaction can
+        // still be used
+        assert_eq!(
+            repo.upstream_oauth_link().count(filter).await.unwrap(),
+            1000
+        );
+        assert!(
+            repo.upstream_oauth_link()
+                .find_by_subject(&provider, "new-subject")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    /// Test that only the unfinished sessions are cleaned up
+    #[sqlx::test(migrator = "crate::MIGRATOR")]
+    async fn test_session_repository_cleanup_abandoned(pool: PgPool) {
+        let mut rng = rand_chacha::ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+
+        let provider = repo
+            .upstream_oauth_provider()
+            .add(
+                &mut rng,
+                &clock,
+                UpstreamOAuthProviderParams {
+                    issuer: Some("https://example.com/".to_owned()),
+                    human_name: None,
+                    brand_name: None,
+                    scope: Scope::from_iter([OPENID]),
+                    token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::None,
+                    id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+                    fetch_userinfo: false,
+                    userinfo_signed_response_alg: None,
+                    token_endpoint_signing_alg: None,
+                    client_id: "client-id".to_owned(),
+                    encrypted_client_secret: None,
+                    claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+                    token_endpoint_override: None,
+                    authorization_endpoint_override: None,
+                    userinfo_endpoint_override: None,
+                    jwks_uri_override: None,
+                    discovery_mode: mas_data_model::UpstreamOAuthProviderDiscoveryMode::Oidc,
+                    pkce_mode: mas_data_model::UpstreamOAuthProviderPkceMode::Auto,
+                    response_mode: None,
+                    additional_authorization_parameters: Vec::new(),
+                    forward_login_hint: false,
+                    ui_order: 0,
+                    ui_hidden: false,
+                    on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
+                },
+            )
+            .await
+            .unwrap();
+
+        let link = repo
+            .upstream_oauth_link()
+            .add(&mut rng, &clock, &provider, "a-subject".to_owned(), None)
+            .await
+            .unwrap();
+
+        // An old session the user never came back from
+        let abandoned = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &clock,
+                &provider,
+                "abandoned".to_owned(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        // An old session which was completed
+        let completed = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &clock,
+                &provider,
+                "completed".to_owned(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let completed = repo
+            .upstream_oauth_session()
+            .complete_with_link(&clock, completed, &link, None, None, None, None)
+            .await
+            .unwrap();
+
+        // An old session which was completed and consumed
+        let consumed = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &clock,
+                &provider,
+                "consumed".to_owned(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let consumed = repo
+            .upstream_oauth_session()
+            .complete_with_link(&clock, consumed, &link, None, None, None, None)
+            .await
+            .unwrap();
+        let consumed = repo
+            .upstream_oauth_session()
+            .consume(&clock, consumed)
+            .await
+            .unwrap();
+
+        clock.advance(Duration::days(8));
+
+        // A recent session which is still in progress
+        let pending = repo
+            .upstream_oauth_session()
+            .add(
+                &mut rng,
+                &clock,
+                &provider,
+                "pending".to_owned(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let count = repo
+            .upstream_oauth_session()
+            .cleanup_abandoned(&clock, Duration::days(7))
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let session = repo
+            .upstream_oauth_session()
+            .lookup(abandoned.id)
+            .await
+            .unwrap();
+        assert!(session.is_none());
+        let session = repo
+            .upstream_oauth_session()
+            .lookup(completed.id)
+            .await
+            .unwrap();
+        assert!(session.is_some());
+        let session = repo
+            .upstream_oauth_session()
+            .lookup(consumed.id)
+            .await
+            .unwrap();
+        assert!(session.is_some());
+        let session = repo
+            .upstream_oauth_session()
+            .lookup(pending.id)
+            .await
+            .unwrap();
+        assert!(session.is_some());
+
+        // Running it again doesn't delete anything else
+        let count = repo
+            .upstream_oauth_session()
+            .cleanup_abandoned(&clock, Duration::days(7))
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
 }
@@ -4,6 +4,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::collections::{BTreeMap, BTreeSet};
+
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use mas_data_model::{Clock, UpstreamOAuthProvider, UpstreamOAuthProviderClaimsImports};
@@ -73,6 +75,7 @@ struct ProviderLookup {
     additional_parameters: Option<Json<Vec<(String, String)>>>,
     forward_login_hint: bool,
     on_backchannel_logout: String,
+    ui_hidden: bool,
 }
 
 impl Node<Ulid> for ProviderLookup {
@@ -234,6 +237,7 @@ impl TryFrom<ProviderLookup> for UpstreamOAuthProvider {
             additional_authorization_parameters,
             forward_login_hint: value.forward_login_hint,
             on_backchannel_logout,
+            ui_hidden: value.ui_hidden,
         })
     }
 }
@@ -293,7 +297,8 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
                     response_mode,
                     additional_parameters as "additional_parameters: Json<Vec<(String, String)>>",
                     forward_login_hint,
-                    on_backchannel_logout
+                    on_backchannel_logout,
+                    ui_hidden
                 FROM upstream_oauth_providers
                 WHERE upstream_oauth_provider_id = $1
             "#,
@@ -311,6 +316,67 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
         Ok(res)
     }
 
+    #[tracing::instrument(
+        name = "db.upstream_oauth_provider.load_batch",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn load_batch(
+        &mut self,
+        ids: BTreeSet<Ulid>,
+    ) -> Result<BTreeMap<Ulid, UpstreamOAuthProvider>, Self::Error> {
+        let ids: Vec<Uuid> = ids.into_iter().map(Uuid::from).collect();
+        let res = sqlx::query_as!(
+            ProviderLookup,
+            r#"
+                SELECT
+                    upstream_oauth_provider_id,
+                    issuer,
+                    human_name,
+                    brand_name,
+                    scope,
+                    client_id,
+                    encrypted_client_secret,
+                    token_endpoint_signing_alg,
+                    token_endpoint_auth_method,
+                    id_token_signed_response_alg,
+                    fetch_userinfo,
+                    userinfo_signed_response_alg,
+                    created_at,
+                    disabled_at,
+                    claims_imports as "claims_imports: Json<UpstreamOAuthProviderClaimsImports>",
+                    jwks_uri_override,
+                    authorization_endpoint_override,
+                    token_endpoint_override,
+                    userinfo_endpoint_override,
+                    discovery_mode,
+                    pkce_mode,
+                    response_mode,
+                    additional_parameters as "additional_parameters: Json<Vec<(String, String)>>",
+                    forward_login_hint,
+                    on_backchannel_logout,
+                    ui_hidden
+                FROM upstream_oauth_providers
+                WHERE upstream_oauth_provider_id = ANY($1::uuid[])
+            "#,
+            &ids,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        res.into_iter()
+            .map(|r| {
+                UpstreamOAuthProvider::try_from(r)
+                    .map(|provider| (provider.id, provider))
+                    .map_err(DatabaseError::from)
+            })
+            .collect()
+    }
+
     #[tracing::instrument(
         name = "db.upstream_oauth_provider.add",
         skip_all,
@@ -357,10 +423,11 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
                 response_mode,
                 forward_login_hint,
                 on_backchannel_logout,
+                ui_hidden,
                 created_at
             ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11,
                       $12, $13, $14, $15, $16, $17, $18, $19, $20,
-                      $21, $22, $23)
+                      $21, $22, $23, $24)
         "#,
             Uuid::from(id),
             params.issuer.as_deref(),
@@ -399,6 +466,7 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
             params.response_mode.as_ref().map(ToString::to_string),
             params.forward_login_hint,
             params.on_backchannel_logout.as_str(),
+            params.ui_hidden,
             created_at,
         )
         .traced()
@@ -431,6 +499,7 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
             additional_authorization_parameters: params.additional_authorization_parameters,
             on_backchannel_logout: params.on_backchannel_logout,
             forward_login_hint: params.forward_login_hint,
+            ui_hidden: params.ui_hidden,
         })
     }
 
@@ -545,11 +614,12 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
                     additional_parameters,
                     forward_login_hint,
                     ui_order,
+                    ui_hidden,
                     on_backchannel_logout,
                     created_at
                 ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
                           $11, $12, $13, $14, $15, $16, $17, $18, $19, $20,
-                          $21, $22, $23, $24, $25)
+                          $21, $22, $23, $24, $25, $26)
                 ON CONFLICT (upstream_oauth_provider_id)
                     DO UPDATE
                     SET
@@ -576,6 +646,7 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
                         additional_parameters = EXCLUDED.additional_parameters,
                         forward_login_hint = EXCLUDED.forward_login_hint,
                         ui_order = EXCLUDED.ui_order,
+                        ui_hidden = EXCLUDED.ui_hidden,
                         on_backchannel_logout = EXCLUDED.on_backchannel_logout
                 RETURNING created_at
             "#,
@@ -617,6 +688,7 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
             Json(&params.additional_authorization_parameters) as _,
             params.forward_login_hint,
             params.ui_order,
+            params.ui_hidden,
             params.on_backchannel_logout.as_str(),
             created_at,
         )
@@ -650,6 +722,7 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
             additional_authorization_parameters: params.additional_authorization_parameters,
             forward_login_hint: params.forward_login_hint,
             on_backchannel_logout: params.on_backchannel_logout,
+            ui_hidden: params.ui_hidden,
         })
     }
 
@@ -874,6 +947,13 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
                 )),
                 ProviderLookupIden::OnBackchannelLogout,
             )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthProviders::Table,
+                    UpstreamOAuthProviders::UiHidden,
+                )),
+                ProviderLookupIden::UiHidden,
+            )
             .from(UpstreamOAuthProviders::Table)
             .apply_filter(filter)
             .generate_pagination(
@@ -968,7 +1048,8 @@ impl UpstreamOAuthProviderRepository for PgUpstreamOAuthProviderRepository<'_> {
                     response_mode,
                     additional_parameters as "additional_parameters: Json<Vec<(String, String)>>",
                     forward_login_hint,
-                    on_backchannel_logout
+                    on_backchannel_logout,
+                    ui_hidden
                 FROM upstream_oauth_providers
                 WHERE disabled_at IS NULL
                 ORDER BY ui_order ASC, upstream_oauth_provider_id ASC
@@ -5,7 +5,7 @@
 // Please see LICENSE files in the repository root for full details.
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use mas_data_model::{
     Clock, UpstreamOAuthAuthorizationSession, UpstreamOAuthAuthorizationSessionState,
     UpstreamOAuthLink, UpstreamOAuthProvider,
@@ -40,6 +40,13 @@ impl Filter for UpstreamOAuthSessionFilter<'_> {
                 ))
                 .eq(Uuid::from(provider.id))
             }))
+            .add_option(self.link().map(|link| {
+                Expr::col((
+                    UpstreamOAuthAuthorizationSessions::Table,
+                    UpstreamOAuthAuthorizationSessions::UpstreamOAuthLinkId,
+                ))
+                .eq(Uuid::from(link.id))
+            }))
             .add_option(self.sub_claim().map(|sub| {
                 Expr::col((
                     UpstreamOAuthAuthorizationSessions::Table,
@@ -90,6 +97,8 @@ struct SessionLookup {
     consumed_at: Option<DateTime<Utc>>,
     extra_callback_parameters: Option<serde_json::Value>,
     unlinked_at: Option<DateTime<Utc>>,
+    failed_at: Option<DateTime<Utc>>,
+    error_code: Option<String>,
 }
 
 impl Node<Ulid> for SessionLookup {
@@ -112,8 +121,10 @@ impl TryFrom<SessionLookup> for UpstreamOAuthAuthorizationSession {
             value.completed_at,
             value.consumed_at,
             value.unlinked_at,
+            value.failed_at,
+            value.error_code,
         ) {
-            (None, None, None, None, None, None, None, None) => {
+            (None, None, None, None, None, None, None, None, None, None) => {
                 UpstreamOAuthAuthorizationSessionState::Pending
             }
             (
@@ -125,6 +136,8 @@ impl TryFrom<SessionLookup> for UpstreamOAuthAuthorizationSession {
                 Some(completed_at),
                 None,
                 None,
+                None,
+                None,
             ) => UpstreamOAuthAuthorizationSessionState::Completed {
                 completed_at,
                 link_id: link_id.into(),
@@ -142,6 +155,8 @@ impl TryFrom<SessionLookup> for UpstreamOAuthAuthorizationSession {
                 Some(completed_at),
                 Some(consumed_at),
                 None,
+                None,
+                None,
             ) => UpstreamOAuthAuthorizationSessionState::Consumed {
                 completed_at,
                 link_id: link_id.into(),
@@ -160,6 +175,8 @@ impl TryFrom<SessionLookup> for UpstreamOAuthAuthorizationSession {
                 Some(completed_at),
                 consumed_at,
                 Some(unlinked_at),
+                None,
+                None,
             ) => UpstreamOAuthAuthorizationSessionState::Unlinked {
                 completed_at,
                 id_token,
@@ -167,6 +184,12 @@ impl TryFrom<SessionLookup> for UpstreamOAuthAuthorizationSession {
                 consumed_at,
                 unlinked_at,
             },
+            (None, None, None, None, None, None, None, None, Some(failed_at), Some(error_code)) => {
+                UpstreamOAuthAuthorizationSessionState::Failed {
+                    failed_at,
+                    error_code,
+                }
+            }
             _ => {
                 return Err(DatabaseInconsistencyError::on(
                     "upstream_oauth_authorization_sessions",
@@ -221,7 +244,9 @@ impl UpstreamOAuthSessionRepository for PgUpstreamOAuthSessionRepository<'_> {
                     created_at,
                     completed_at,
                     consumed_at,
-                    unlinked_at
+                    unlinked_at,
+                    failed_at,
+                    error_code
                 FROM upstream_oauth_authorization_sessions
                 WHERE upstream_oauth_authorization_session_id = $1
             "#,
@@ -396,6 +421,45 @@ impl UpstreamOAuthSessionRepository for PgUpstreamOAuthSessionRepository<'_> {
         Ok(upstream_oauth_authorization_session)
     }
 
+    #[tracing::instrument(
+        name = "db.upstream_oauth_authorization_session.fail",
+        skip_all,
+        fields(
+            db.query.text,
+            %upstream_oauth_authorization_session.id,
+            upstream_oauth_authorization_session.error_code = error_code,
+        ),
+        err,
+    )]
+    async fn fail(
+        &mut self,
+        clock: &dyn Clock,
+        upstream_oauth_authorization_session: UpstreamOAuthAuthorizationSession,
+        error_code: String,
+    ) -> Result<UpstreamOAuthAuthorizationSession, Self::Error> {
+        let failed_at = clock.now();
+        sqlx::query!(
+            r#"
+                UPDATE upstream_oauth_authorization_sessions
+                SET failed_at = $1
+                  , error_code = $2
+                WHERE upstream_oauth_authorization_session_id = $3
+            "#,
+            failed_at,
+            &error_code,
+            Uuid::from(upstream_oauth_authorization_session.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        let upstream_oauth_authorization_session = upstream_oauth_authorization_session
+            .fail(failed_at, error_code)
+            .map_err(DatabaseError::to_invalid_operation)?;
+
+        Ok(upstream_oauth_authorization_session)
+    }
+
     #[tracing::instrument(
         name = "db.upstream_oauth_authorization_session.list",
         skip_all,
@@ -508,6 +572,20 @@ impl UpstreamOAuthSessionRepository for PgUpstreamOAuthSessionRepository<'_> {
                 )),
                 SessionLookupIden::UnlinkedAt,
             )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthAuthorizationSessions::Table,
+                    UpstreamOAuthAuthorizationSessions::FailedAt,
+                )),
+                SessionLookupIden::FailedAt,
+            )
+            .expr_as(
+                Expr::col((
+                    UpstreamOAuthAuthorizationSessions::Table,
+                    UpstreamOAuthAuthorizationSessions::ErrorCode,
+                )),
+                SessionLookupIden::ErrorCode,
+            )
             .from(UpstreamOAuthAuthorizationSessions::Table)
             .apply_filter(filter)
             .generate_pagination(
@@ -564,4 +642,36 @@ impl UpstreamOAuthSessionRepository for PgUpstreamOAuthSessionRepository<'_> {
             .try_into()
             .map_err(DatabaseError::to_invalid_operation)
     }
+
+    #[tracing::instrument(
+        name = "db.upstream_oauth_authorization_session.cleanup_abandoned",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn cleanup_abandoned(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error> {
+        let threshold = clock.now() - retention;
+        // Sessions are linked and completed at the same time, but we check both
+        // to make sure we never delete a session referenced by a link
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM upstream_oauth_authorization_sessions
+                WHERE completed_at IS NULL
+                  AND upstream_oauth_link_id IS NULL
+                  AND created_at < $1
+            "#,
+            threshold,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
 }
@@ -8,7 +8,7 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use mas_data_model::{
     BrowserSession, Clock, User, UserEmail, UserEmailAuthentication, UserEmailAuthenticationCode,
-    UserRegistration,
+    UserEmailChange, UserRegistration,
 };
 use mas_storage::{
     Page, Pagination,
@@ -18,7 +18,7 @@ use mas_storage::{
 use rand::RngCore;
 use sea_query::{Expr, Func, PostgresQueryBuilder, Query, SimpleExpr, enum_def};
 use sea_query_binder::SqlxBinder;
-use sqlx::PgConnection;
+use sqlx::{Connection, PgConnection};
 use ulid::Ulid;
 use uuid::Uuid;
 
@@ -111,6 +111,36 @@ impl From<UserEmailAuthenticationCodeLookup> for UserEmailAuthenticationCode {
     }
 }
 
+struct UserEmailChangeLookup {
+    user_email_change_id: Uuid,
+    user_id: Uuid,
+    user_email_authentication_id: Uuid,
+    email: String,
+    previous_email: String,
+    ticket: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+    cancelled_at: Option<DateTime<Utc>>,
+}
+
+impl From<UserEmailChangeLookup> for UserEmailChange {
+    fn from(value: UserEmailChangeLookup) -> Self {
+        UserEmailChange {
+            id: value.user_email_change_id.into(),
+            user_id: value.user_id.into(),
+            user_email_authentication_id: value.user_email_authentication_id.into(),
+            email: value.email,
+            previous_email: value.previous_email,
+            ticket: value.ticket,
+            created_at: value.created_at,
+            expires_at: value.expires_at,
+            confirmed_at: value.confirmed_at,
+            cancelled_at: value.cancelled_at,
+        }
+    }
+}
+
 impl Filter for UserEmailFilter<'_> {
     fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
         sea_query::Condition::all()
@@ -127,6 +157,33 @@ impl Filter for UserEmailFilter<'_> {
     }
 }
 
+/// Insert a batch of user emails with a single statement
+async fn insert_user_emails(
+    conn: &mut PgConnection,
+    ids: &[Uuid],
+    user_ids: &[Uuid],
+    emails: &[String],
+    created_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO user_emails (user_email_id, user_id, email, created_at)
+            SELECT user_email_id, user_id, email, $4
+            FROM UNNEST($1::uuid[], $2::uuid[], $3::text[])
+                AS t(user_email_id, user_id, email)
+        "#,
+        ids,
+        user_ids,
+        emails,
+        created_at,
+    )
+    .traced()
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
 #[async_trait]
 impl UserEmailRepository for PgUserEmailRepository<'_> {
     type Error = DatabaseError;
@@ -340,6 +397,48 @@ impl UserEmailRepository for PgUserEmailRepository<'_> {
             .map_err(DatabaseError::to_invalid_operation)
     }
 
+    #[tracing::instrument(
+        name = "db.user_email.count_users_by_domain",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count_users_by_domain(
+        &mut self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<(String, usize)>, Self::Error> {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let res = sqlx::query!(
+            r#"
+                SELECT LOWER(SPLIT_PART(ue.email, '@', 2)) AS "domain!"
+                     , COUNT(DISTINCT u.user_id) AS "count!"
+                FROM users u
+                INNER JOIN user_emails ue
+                  ON ue.user_id = u.user_id
+                WHERE u.created_at >= $1
+                  AND u.created_at < $2
+                GROUP BY 1
+                ORDER BY 2 DESC, 1
+                LIMIT $3
+            "#,
+            since,
+            until,
+            limit,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        Ok(res
+            .into_iter()
+            .map(|row| (row.domain, row.count.try_into().unwrap_or(usize::MAX)))
+            .collect())
+    }
+
     #[tracing::instrument(
         name = "db.user_email.add",
         skip_all,
@@ -384,6 +483,88 @@ impl UserEmailRepository for PgUserEmailRepository<'_> {
         })
     }
 
+    #[tracing::instrument(
+        name = "db.user_email.add_many",
+        skip_all,
+        fields(
+            db.query.text,
+            user_email.count = emails.len(),
+        ),
+        err,
+    )]
+    async fn add_many(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        emails: &[(&User, String)],
+    ) -> Result<Vec<UserEmail>, Self::Error> {
+        if emails.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let created_at = clock.now();
+        let mut ids = Vec::with_capacity(emails.len());
+        let mut user_ids = Vec::with_capacity(emails.len());
+        let mut addresses = Vec::with_capacity(emails.len());
+        let mut user_emails = Vec::with_capacity(emails.len());
+
+        for (user, email) in emails {
+            let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+            ids.push(Uuid::from(id));
+            user_ids.push(Uuid::from(user.id));
+            addresses.push(email.clone());
+            user_emails.push(UserEmail {
+                id,
+                user_id: user.id,
+                email: email.clone(),
+                created_at,
+            });
+        }
+
+        // Insert everything in a savepoint, so that we can find out which item
+        // was rejected if the insert fails
+        let mut savepoint = self.conn.begin().await?;
+        let Err(source) =
+            insert_user_emails(&mut savepoint, &ids, &user_ids, &addresses, created_at).await
+        else {
+            savepoint.commit().await?;
+            return Ok(user_emails);
+        };
+        savepoint.rollback().await?;
+
+        if source.as_database_error().is_none() {
+            return Err(source.into());
+        }
+
+        // Replay the insert one item at a time to find the one which was
+        // rejected. This only happens on the error path, so the extra
+        // round-trips are fine.
+        let mut replay = self.conn.begin().await?;
+        let mut index = None;
+        for i in 0..ids.len() {
+            let range = i..=i;
+            let res = insert_user_emails(
+                &mut replay,
+                &ids[range.clone()],
+                &user_ids[range.clone()],
+                &addresses[range],
+                created_at,
+            )
+            .await;
+
+            if res.is_err() {
+                index = Some(i);
+                break;
+            }
+        }
+        replay.rollback().await?;
+
+        Err(match index {
+            Some(index) => DatabaseError::BatchItem { index, source },
+            None => source.into(),
+        })
+    }
+
     #[tracing::instrument(
         name = "db.user_email.remove",
         skip_all,
@@ -434,6 +615,44 @@ impl UserEmailRepository for PgUserEmailRepository<'_> {
         Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
     }
 
+    #[tracing::instrument(
+        name = "db.user_email.transfer_to_user",
+        skip_all,
+        fields(
+            db.query.text,
+            previous_user.id = %user_email.user_id,
+            %user_email.id,
+            %user_email.email,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn transfer_to_user(
+        &mut self,
+        user_email: UserEmail,
+        user: &User,
+    ) -> Result<UserEmail, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_emails
+                SET user_id = $1
+                WHERE user_email_id = $2
+            "#,
+            Uuid::from(user.id),
+            Uuid::from(user_email.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(UserEmail {
+            user_id: user.id,
+            ..user_email
+        })
+    }
+
     #[tracing::instrument(
         name = "db.user_email.add_authentication_for_session",
         skip_all,
@@ -712,4 +931,220 @@ impl UserEmailRepository for PgUserEmailRepository<'_> {
         user_email_authentication.completed_at = Some(completed_at);
         Ok(user_email_authentication)
     }
+
+    #[tracing::instrument(
+        name = "db.user_email.add_change",
+        skip_all,
+        fields(
+            db.query.text,
+            %user_email_authentication.id,
+            %user_email_authentication.email,
+            %previous_email.id,
+            %previous_email.user_id,
+            user_email_change.id,
+        ),
+        err,
+    )]
+    async fn add_change(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        duration: chrono::Duration,
+        user_email_authentication: &UserEmailAuthentication,
+        previous_email: &UserEmail,
+        ticket: String,
+    ) -> Result<UserEmailChange, Self::Error> {
+        let created_at = clock.now();
+        let expires_at = created_at + duration;
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_email_change.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_email_changes
+                  ( user_email_change_id
+                  , user_id
+                  , user_email_authentication_id
+                  , email
+                  , previous_email
+                  , ticket
+                  , created_at
+                  , expires_at
+                  )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            Uuid::from(id),
+            Uuid::from(previous_email.user_id),
+            Uuid::from(user_email_authentication.id),
+            &user_email_authentication.email,
+            &previous_email.email,
+            &ticket,
+            created_at,
+            expires_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UserEmailChange {
+            id,
+            user_id: previous_email.user_id,
+            user_email_authentication_id: user_email_authentication.id,
+            email: user_email_authentication.email.clone(),
+            previous_email: previous_email.email.clone(),
+            ticket,
+            created_at,
+            expires_at,
+            confirmed_at: None,
+            cancelled_at: None,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_email.lookup_change",
+        skip_all,
+        fields(
+            db.query.text,
+            user_email_change.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup_change(&mut self, id: Ulid) -> Result<Option<UserEmailChange>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserEmailChangeLookup,
+            r#"
+                SELECT user_email_change_id
+                     , user_id
+                     , user_email_authentication_id
+                     , email
+                     , previous_email
+                     , ticket
+                     , created_at
+                     , expires_at
+                     , confirmed_at
+                     , cancelled_at
+                FROM user_email_changes
+                WHERE user_email_change_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(res.map(UserEmailChange::from))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_email.find_change_by_ticket",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn find_change_by_ticket(
+        &mut self,
+        ticket: &str,
+    ) -> Result<Option<UserEmailChange>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserEmailChangeLookup,
+            r#"
+                SELECT user_email_change_id
+                     , user_id
+                     , user_email_authentication_id
+                     , email
+                     , previous_email
+                     , ticket
+                     , created_at
+                     , expires_at
+                     , confirmed_at
+                     , cancelled_at
+                FROM user_email_changes
+                WHERE ticket = $1
+            "#,
+            ticket,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(res.map(UserEmailChange::from))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_email.confirm_change",
+        skip_all,
+        fields(
+            db.query.text,
+            %user_email_change.id,
+            %user_email_change.email,
+        ),
+        err,
+    )]
+    async fn confirm_change(
+        &mut self,
+        clock: &dyn Clock,
+        mut user_email_change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error> {
+        let confirmed_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_email_changes
+                SET confirmed_at = $2
+                WHERE user_email_change_id = $1
+                  AND confirmed_at IS NULL
+                  AND cancelled_at IS NULL
+            "#,
+            Uuid::from(user_email_change.id),
+            confirmed_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user_email_change.confirmed_at = Some(confirmed_at);
+        Ok(user_email_change)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_email.cancel_change",
+        skip_all,
+        fields(
+            db.query.text,
+            %user_email_change.id,
+            %user_email_change.email,
+        ),
+        err,
+    )]
+    async fn cancel_change(
+        &mut self,
+        clock: &dyn Clock,
+        mut user_email_change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error> {
+        let cancelled_at = clock.now();
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE user_email_changes
+                SET cancelled_at = $2
+                WHERE user_email_change_id = $1
+                  AND confirmed_at IS NULL
+                  AND cancelled_at IS NULL
+            "#,
+            Uuid::from(user_email_change.id),
+            cancelled_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user_email_change.cancelled_at = Some(cancelled_at);
+        Ok(user_email_change)
+    }
 }
@@ -0,0 +1,214 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{Clock, LoginFailureOrigin, LoginFailureReason, User, UserLoginFailure};
+use mas_storage::user::UserLoginFailureRepository;
+use rand::RngCore;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{DatabaseError, DatabaseInconsistencyError, tracing::ExecuteExt};
+
+/// An implementation of [`UserLoginFailureRepository`] for a PostgreSQL
+/// connection
+pub struct PgUserLoginFailureRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserLoginFailureRepository<'c> {
+    /// Create a new [`PgUserLoginFailureRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+struct UserLoginFailureLookup {
+    user_login_failure_id: Uuid,
+    user_id: Uuid,
+    origin: String,
+    reason: String,
+    ip_address: Option<IpAddr>,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<UserLoginFailureLookup> for UserLoginFailure {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(value: UserLoginFailureLookup) -> Result<Self, Self::Error> {
+        let id = Ulid::from(value.user_login_failure_id);
+
+        let origin = value.origin.parse().map_err(|e| {
+            DatabaseInconsistencyError::on("user_login_failures")
+                .column("origin")
+                .row(id)
+                .source(e)
+        })?;
+
+        let reason = value.reason.parse().map_err(|e| {
+            DatabaseInconsistencyError::on("user_login_failures")
+                .column("reason")
+                .row(id)
+                .source(e)
+        })?;
+
+        Ok(UserLoginFailure {
+            id,
+            user_id: value.user_id.into(),
+            origin,
+            reason,
+            ip_address: value.ip_address,
+            created_at: value.created_at,
+        })
+    }
+}
+
+#[async_trait]
+impl UserLoginFailureRepository for PgUserLoginFailureRepository<'_> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_login_failure.add",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+            user_login_failure.id,
+            user_login_failure.origin = %origin,
+            user_login_failure.reason = %reason,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        origin: LoginFailureOrigin,
+        reason: LoginFailureReason,
+        ip_address: Option<IpAddr>,
+    ) -> Result<UserLoginFailure, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_login_failure.id", tracing::field::display(id));
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_login_failures
+                    ( user_login_failure_id
+                    , user_id
+                    , origin
+                    , reason
+                    , ip_address
+                    , created_at
+                    )
+                VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user.id),
+            origin.as_str(),
+            reason.as_str(),
+            ip_address as Option<IpAddr>,
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UserLoginFailure {
+            id,
+            user_id: user.id,
+            origin,
+            reason,
+            ip_address,
+            created_at,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_failure.list_recent",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn list_recent(
+        &mut self,
+        user: &User,
+        limit: usize,
+    ) -> Result<Vec<UserLoginFailure>, Self::Error> {
+        let rows = sqlx::query_as!(
+            UserLoginFailureLookup,
+            r#"
+                SELECT user_login_failure_id
+                     , user_id
+                     , origin
+                     , reason
+                     , ip_address as "ip_address: IpAddr"
+                     , created_at
+                FROM user_login_failures
+                WHERE user_id = $1
+                ORDER BY user_login_failure_id DESC
+                LIMIT $2
+            "#,
+            Uuid::from(user.id),
+            i64::try_from(limit).map_err(DatabaseError::to_invalid_operation)?,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        let failures = rows
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()?;
+
+        Ok(failures)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_login_failure.prune",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn prune(&mut self, keep: usize) -> Result<usize, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM user_login_failures
+                WHERE user_login_failure_id IN (
+                    SELECT user_login_failure_id
+                    FROM (
+                        SELECT user_login_failure_id
+                             , ROW_NUMBER() OVER (
+                                   PARTITION BY user_id
+                                   ORDER BY user_login_failure_id DESC
+                               ) AS rn
+                        FROM user_login_failures
+                    ) ranked
+                    WHERE ranked.rn > $1
+                )
+            "#,
+            i64::try_from(keep).map_err(DatabaseError::to_invalid_operation)?,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(res
+            .rows_affected()
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)?)
+    }
+}
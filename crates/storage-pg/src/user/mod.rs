@@ -7,7 +7,10 @@
 //! A module containing the PostgreSQL implementation of the user-related
 //! repositories
 
+use std::collections::{BTreeMap, BTreeSet};
+
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use mas_data_model::{Clock, User};
 use mas_storage::user::{UserFilter, UserRepository};
 use rand::RngCore;
@@ -20,12 +23,14 @@ use uuid::Uuid;
 use crate::{
     DatabaseError,
     filter::{Filter, StatementExt},
-    iden::Users,
+    iden::{UpstreamOAuthLinks, Users},
     pagination::QueryBuilderExt,
     tracing::ExecuteExt,
 };
 
 mod email;
+mod login_failure;
+mod note;
 mod password;
 mod recovery;
 mod registration;
@@ -37,7 +42,8 @@ mod terms;
 mod tests;
 
 pub use self::{
-    email::PgUserEmailRepository, password::PgUserPasswordRepository,
+    email::PgUserEmailRepository, login_failure::PgUserLoginFailureRepository,
+    note::PgUserNoteRepository, password::PgUserPasswordRepository,
     recovery::PgUserRecoveryRepository, registration::PgUserRegistrationRepository,
     registration_token::PgUserRegistrationTokenRepository, session::PgBrowserSessionRepository,
     terms::PgUserTermsRepository,
@@ -74,8 +80,14 @@ mod priv_ {
         pub(super) created_at: DateTime<Utc>,
         pub(super) locked_at: Option<DateTime<Utc>>,
         pub(super) deactivated_at: Option<DateTime<Utc>>,
+        pub(super) deleted_at: Option<DateTime<Utc>>,
         pub(super) can_request_admin: bool,
         pub(super) is_guest: bool,
+        pub(super) organization: Option<String>,
+        pub(super) max_sessions: Option<i32>,
+        pub(super) locale: Option<String>,
+        pub(super) deactivation_scheduled_at: Option<DateTime<Utc>>,
+        pub(super) external_id: Option<String>,
     }
 
     impl Node<Ulid> for UserLookup {
@@ -97,8 +109,17 @@ impl From<UserLookup> for User {
             created_at: value.created_at,
             locked_at: value.locked_at,
             deactivated_at: value.deactivated_at,
+            deleted_at: value.deleted_at,
             can_request_admin: value.can_request_admin,
             is_guest: value.is_guest,
+            organization: value.organization,
+            // The database makes sure the value is positive
+            max_sessions: value
+                .max_sessions
+                .and_then(|max_sessions| u32::try_from(max_sessions).ok()),
+            locale: value.locale,
+            deactivation_scheduled_at: value.deactivation_scheduled_at,
+            external_id: value.external_id,
         }
     }
 }
@@ -131,6 +152,63 @@ impl Filter for UserFilter<'_> {
             .add_option(self.search().map(|search| {
                 Expr::col((Users::Table, Users::Username)).ilike(format!("%{search}%"))
             }))
+            .add_option(self.deleted_before().map(|deleted_before| {
+                Expr::col((Users::Table, Users::DeletedAt)).lt(deleted_before)
+            }))
+            .add_option(self.organization().map(|organization| {
+                Expr::col((Users::Table, Users::Organization)).eq(organization)
+            }))
+            .add_option(self.upstream_provider_id().map(|upstream_provider_id| {
+                Expr::exists(
+                    Query::select()
+                        .expr(Expr::cust("1"))
+                        .from(UpstreamOAuthLinks::Table)
+                        .and_where(
+                            Expr::col((UpstreamOAuthLinks::Table, UpstreamOAuthLinks::UserId))
+                                .equals((Users::Table, Users::UserId)),
+                        )
+                        .and_where(
+                            Expr::col((
+                                UpstreamOAuthLinks::Table,
+                                UpstreamOAuthLinks::UpstreamOAuthProviderId,
+                            ))
+                            .eq(Uuid::from(upstream_provider_id)),
+                        )
+                        .take(),
+                )
+            }))
+            .add_option(self.has_upstream_link().map(|has_upstream_link| {
+                let exists = Expr::exists(
+                    Query::select()
+                        .expr(Expr::cust("1"))
+                        .from(UpstreamOAuthLinks::Table)
+                        .and_where(
+                            Expr::col((UpstreamOAuthLinks::Table, UpstreamOAuthLinks::UserId))
+                                .equals((Users::Table, Users::UserId)),
+                        )
+                        .take(),
+                );
+
+                if has_upstream_link {
+                    exists
+                } else {
+                    exists.not()
+                }
+            }))
+            .add_option(self.has_external_id().map(|has_external_id| {
+                if has_external_id {
+                    Expr::col((Users::Table, Users::ExternalId)).is_not_null()
+                } else {
+                    Expr::col((Users::Table, Users::ExternalId)).is_null()
+                }
+            }))
+            .add_option(self.migrated().map(|migrated| {
+                if migrated {
+                    Expr::col((Users::Table, Users::Syn2masRunId)).is_not_null()
+                } else {
+                    Expr::col((Users::Table, Users::Syn2masRunId)).is_null()
+                }
+            }))
     }
 }
 
@@ -156,8 +234,14 @@ impl UserRepository for PgUserRepository<'_> {
                      , created_at
                      , locked_at
                      , deactivated_at
+                     , deleted_at
                      , can_request_admin
                      , is_guest
+                     , organization
+                     , max_sessions
+                     , locale
+                     , deactivation_scheduled_at
+                     , external_id
                 FROM users
                 WHERE user_id = $1
             "#,
@@ -193,8 +277,14 @@ impl UserRepository for PgUserRepository<'_> {
                      , created_at
                      , locked_at
                      , deactivated_at
+                     , deleted_at
                      , can_request_admin
                      , is_guest
+                     , organization
+                     , max_sessions
+                     , locale
+                     , deactivation_scheduled_at
+                     , external_id
                 FROM users
                 WHERE LOWER(username) = LOWER($1)
             "#,
@@ -222,6 +312,96 @@ impl UserRepository for PgUserRepository<'_> {
         }
     }
 
+    #[tracing::instrument(
+        name = "db.user.find_by_external_id",
+        skip_all,
+        fields(
+            db.query.text,
+            user.external_id = external_id,
+        ),
+        err,
+    )]
+    async fn find_by_external_id(
+        &mut self,
+        external_id: &str,
+    ) -> Result<Option<User>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserLookup,
+            r#"
+                SELECT user_id
+                     , username
+                     , created_at
+                     , locked_at
+                     , deactivated_at
+                     , deleted_at
+                     , can_request_admin
+                     , is_guest
+                     , organization
+                     , max_sessions
+                     , locale
+                     , deactivation_scheduled_at
+                     , external_id
+                FROM users
+                WHERE external_id = $1
+            "#,
+            external_id,
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(res) = res else { return Ok(None) };
+
+        Ok(Some(res.into()))
+    }
+
+    #[tracing::instrument(
+        name = "db.user.load_batch",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn load_batch(
+        &mut self,
+        ids: BTreeSet<Ulid>,
+    ) -> Result<BTreeMap<Ulid, User>, Self::Error> {
+        let ids: Vec<Uuid> = ids.into_iter().map(Uuid::from).collect();
+        let res = sqlx::query_as!(
+            UserLookup,
+            r#"
+                SELECT user_id
+                     , username
+                     , created_at
+                     , locked_at
+                     , deactivated_at
+                     , deleted_at
+                     , can_request_admin
+                     , is_guest
+                     , organization
+                     , max_sessions
+                     , locale
+                     , deactivation_scheduled_at
+                     , external_id
+                FROM users
+                WHERE user_id = ANY($1::uuid[])
+            "#,
+            &ids,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        Ok(res
+            .into_iter()
+            .map(|r| {
+                let user = User::from(r);
+                (user.id, user)
+            })
+            .collect())
+    }
+
     #[tracing::instrument(
         name = "db.user.add",
         skip_all,
@@ -267,8 +447,14 @@ impl UserRepository for PgUserRepository<'_> {
             created_at,
             locked_at: None,
             deactivated_at: None,
+            deleted_at: None,
             can_request_admin: false,
             is_guest: false,
+            organization: None,
+            max_sessions: None,
+            locale: None,
+            deactivation_scheduled_at: None,
+            external_id: None,
         })
     }
 
@@ -434,6 +620,363 @@ impl UserRepository for PgUserRepository<'_> {
         Ok(user)
     }
 
+    #[tracing::instrument(
+        name = "db.user.delete",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn delete(&mut self, clock: &dyn Clock, mut user: User) -> Result<User, Self::Error> {
+        if user.deleted_at.is_some() {
+            return Ok(user);
+        }
+
+        let deleted_at = clock.now();
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET deleted_at = $2
+                  , deactivated_at = COALESCE(deactivated_at, $2)
+                WHERE user_id = $1
+                  AND deleted_at IS NULL
+            "#,
+            Uuid::from(user.id),
+            deleted_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.deleted_at = Some(deleted_at);
+        user.deactivated_at.get_or_insert(deleted_at);
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.undelete",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn undelete(&mut self, mut user: User) -> Result<User, Self::Error> {
+        if user.deleted_at.is_none() {
+            return Ok(user);
+        }
+
+        // If the user was deactivated as part of the deletion, both timestamps
+        // are the same, and we reactivate it as well
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET deleted_at = NULL
+                  , deactivated_at = CASE
+                        WHEN deactivated_at = deleted_at THEN NULL
+                        ELSE deactivated_at
+                    END
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        if user.deactivated_at == user.deleted_at {
+            user.deactivated_at = None;
+        }
+        user.deleted_at = None;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.purge",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn purge(&mut self, user: User) -> Result<(), Self::Error> {
+        let user_id = Uuid::from(user.id);
+
+        // Most of the foreign keys pointing to the user don't cascade, so we need
+        // to remove the dependent rows first, from the leaves to the user row.
+
+        // Personal sessions owned by the user, or acting as the user
+        sqlx::query!(
+            r#"
+                DELETE FROM personal_access_tokens
+                WHERE personal_session_id IN (
+                    SELECT personal_session_id
+                    FROM personal_sessions
+                    WHERE owner_user_id = $1 OR actor_user_id = $1
+                )
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM personal_sessions
+                WHERE owner_user_id = $1 OR actor_user_id = $1
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        // Compatibility sessions and their tokens
+        sqlx::query!(
+            r#"
+                DELETE FROM compat_refresh_tokens
+                WHERE compat_session_id IN (
+                    SELECT compat_session_id
+                    FROM compat_sessions
+                    WHERE user_id = $1
+                )
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM compat_access_tokens
+                WHERE compat_session_id IN (
+                    SELECT compat_session_id
+                    FROM compat_sessions
+                    WHERE user_id = $1
+                )
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM compat_sessions
+                WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        // OAuth 2.0 sessions, their tokens and the grants which started them
+        sqlx::query!(
+            r#"
+                DELETE FROM oauth2_refresh_tokens
+                WHERE oauth2_session_id IN (
+                    SELECT oauth2_session_id
+                    FROM oauth2_sessions
+                    WHERE user_id = $1
+                )
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM oauth2_access_tokens
+                WHERE oauth2_session_id IN (
+                    SELECT oauth2_session_id
+                    FROM oauth2_sessions
+                    WHERE user_id = $1
+                )
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM oauth2_authorization_grants
+                WHERE oauth2_session_id IN (
+                    SELECT oauth2_session_id
+                    FROM oauth2_sessions
+                    WHERE user_id = $1
+                )
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM oauth2_sessions
+                WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM oauth2_consents
+                WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        // Browser sessions, and what references them
+        sqlx::query!(
+            r#"
+                DELETE FROM oauth2_device_code_grant
+                WHERE user_session_id IN (
+                    SELECT user_session_id
+                    FROM user_sessions
+                    WHERE user_id = $1
+                )
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM user_session_authentications
+                WHERE user_session_id IN (
+                    SELECT user_session_id
+                    FROM user_sessions
+                    WHERE user_id = $1
+                )
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM user_sessions
+                WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        // Upstream OAuth 2.0 links, and the authorization sessions which used them
+        sqlx::query!(
+            r#"
+                DELETE FROM upstream_oauth_authorization_sessions
+                WHERE upstream_oauth_link_id IN (
+                    SELECT upstream_oauth_link_id
+                    FROM upstream_oauth_links
+                    WHERE user_id = $1
+                )
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM upstream_oauth_links
+                WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        // Passwords and email addresses
+        sqlx::query!(
+            r#"
+                DELETE FROM user_passwords
+                WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM user_email_confirmation_codes
+                WHERE user_email_id IN (
+                    SELECT user_email_id
+                    FROM user_emails
+                    WHERE user_id = $1
+                )
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+                DELETE FROM user_emails
+                WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        // Finally, the user itself. The remaining tables (terms, unsupported
+        // third-party IDs, login failures, email changes) cascade on deletion.
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM users
+                WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(())
+    }
+
     #[tracing::instrument(
         name = "db.user.set_can_request_admin",
         skip_all,
@@ -469,6 +1012,212 @@ impl UserRepository for PgUserRepository<'_> {
         Ok(user)
     }
 
+    #[tracing::instrument(
+        name = "db.user.set_organization",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn set_organization(
+        &mut self,
+        mut user: User,
+        organization: Option<String>,
+    ) -> Result<User, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET organization = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+            organization.as_deref(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.organization = organization;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.set_max_sessions",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn set_max_sessions(
+        &mut self,
+        mut user: User,
+        max_sessions: Option<u32>,
+    ) -> Result<User, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET max_sessions = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+            max_sessions
+                .map(i32::try_from)
+                .transpose()
+                .map_err(DatabaseError::to_invalid_operation)?,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.max_sessions = max_sessions;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.set_locale",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn set_locale(
+        &mut self,
+        mut user: User,
+        locale: Option<String>,
+    ) -> Result<User, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET locale = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+            locale.as_deref(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.locale = locale;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.set_external_id",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn set_external_id(
+        &mut self,
+        mut user: User,
+        external_id: Option<String>,
+    ) -> Result<User, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET external_id = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+            external_id.as_deref(),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.external_id = external_id;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.schedule_deactivation",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn schedule_deactivation(
+        &mut self,
+        mut user: User,
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<User, Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET deactivation_scheduled_at = $2
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+            scheduled_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.deactivation_scheduled_at = Some(scheduled_at);
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(
+        name = "db.user.unschedule_deactivation",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn unschedule_deactivation(&mut self, mut user: User) -> Result<User, Self::Error> {
+        if user.deactivation_scheduled_at.is_none() {
+            return Ok(user);
+        }
+
+        let res = sqlx::query!(
+            r#"
+                UPDATE users
+                SET deactivation_scheduled_at = NULL
+                WHERE user_id = $1
+            "#,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        user.deactivation_scheduled_at = None;
+
+        Ok(user)
+    }
+
     #[tracing::instrument(
         name = "db.user.list",
         skip_all,
@@ -503,6 +1252,10 @@ impl UserRepository for PgUserRepository<'_> {
                 Expr::col((Users::Table, Users::DeactivatedAt)),
                 UserLookupIden::DeactivatedAt,
             )
+            .expr_as(
+                Expr::col((Users::Table, Users::DeletedAt)),
+                UserLookupIden::DeletedAt,
+            )
             .expr_as(
                 Expr::col((Users::Table, Users::CanRequestAdmin)),
                 UserLookupIden::CanRequestAdmin,
@@ -511,6 +1264,26 @@ impl UserRepository for PgUserRepository<'_> {
                 Expr::col((Users::Table, Users::IsGuest)),
                 UserLookupIden::IsGuest,
             )
+            .expr_as(
+                Expr::col((Users::Table, Users::Organization)),
+                UserLookupIden::Organization,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::MaxSessions)),
+                UserLookupIden::MaxSessions,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::Locale)),
+                UserLookupIden::Locale,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::DeactivationScheduledAt)),
+                UserLookupIden::DeactivationScheduledAt,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::ExternalId)),
+                UserLookupIden::ExternalId,
+            )
             .from(Users::Table)
             .apply_filter(filter)
             .generate_pagination((Users::Table, Users::UserId), pagination)
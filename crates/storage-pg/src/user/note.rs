@@ -0,0 +1,303 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mas_data_model::{Clock, User, UserNote};
+use mas_storage::{
+    Page, Pagination,
+    pagination::Node,
+    user::{UserNoteFilter, UserNoteRepository},
+};
+use rand::RngCore;
+use sea_query::{Expr, PostgresQueryBuilder, Query, enum_def};
+use sea_query_binder::SqlxBinder;
+use sqlx::PgConnection;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    DatabaseError,
+    filter::{Filter, StatementExt},
+    iden::UserNotes,
+    pagination::QueryBuilderExt,
+    tracing::ExecuteExt,
+};
+
+/// An implementation of [`UserNoteRepository`] for a PostgreSQL connection
+pub struct PgUserNoteRepository<'c> {
+    conn: &'c mut PgConnection,
+}
+
+impl<'c> PgUserNoteRepository<'c> {
+    /// Create a new [`PgUserNoteRepository`] from an active PostgreSQL
+    /// connection
+    pub fn new(conn: &'c mut PgConnection) -> Self {
+        Self { conn }
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+#[enum_def]
+struct UserNoteLookup {
+    user_note_id: Uuid,
+    user_id: Uuid,
+    author_user_id: Option<Uuid>,
+    content: String,
+    created_at: DateTime<Utc>,
+}
+
+impl Node<Ulid> for UserNoteLookup {
+    fn cursor(&self) -> Ulid {
+        self.user_note_id.into()
+    }
+}
+
+impl From<UserNoteLookup> for UserNote {
+    fn from(value: UserNoteLookup) -> Self {
+        UserNote {
+            id: value.user_note_id.into(),
+            user_id: value.user_id.into(),
+            author_id: value.author_user_id.map(Ulid::from),
+            content: value.content,
+            created_at: value.created_at,
+        }
+    }
+}
+
+impl Filter for UserNoteFilter<'_> {
+    fn generate_condition(&self, _has_joins: bool) -> impl sea_query::IntoCondition {
+        sea_query::Condition::all().add_option(
+            self.user().map(|user| {
+                Expr::col((UserNotes::Table, UserNotes::UserId)).eq(Uuid::from(user.id))
+            }),
+        )
+    }
+}
+
+#[async_trait]
+impl UserNoteRepository for PgUserNoteRepository<'_> {
+    type Error = DatabaseError;
+
+    #[tracing::instrument(
+        name = "db.user_note.lookup",
+        skip_all,
+        fields(
+            db.query.text,
+            user_note.id = %id,
+        ),
+        err,
+    )]
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserNote>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserNoteLookup,
+            r#"
+                SELECT user_note_id
+                     , user_id
+                     , author_user_id
+                     , content
+                     , created_at
+                FROM user_notes
+
+                WHERE user_note_id = $1
+            "#,
+            Uuid::from(id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(res.map(Into::into))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_note.latest",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn latest(&mut self, user: &User) -> Result<Option<UserNote>, Self::Error> {
+        let res = sqlx::query_as!(
+            UserNoteLookup,
+            r#"
+                SELECT user_note_id
+                     , user_id
+                     , author_user_id
+                     , content
+                     , created_at
+                FROM user_notes
+
+                WHERE user_id = $1
+
+                ORDER BY user_note_id DESC
+                LIMIT 1
+            "#,
+            Uuid::from(user.id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        Ok(res.map(Into::into))
+    }
+
+    #[tracing::instrument(
+        name = "db.user_note.list",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn list(
+        &mut self,
+        filter: UserNoteFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<UserNote>, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr_as(
+                Expr::col((UserNotes::Table, UserNotes::UserNoteId)),
+                UserNoteLookupIden::UserNoteId,
+            )
+            .expr_as(
+                Expr::col((UserNotes::Table, UserNotes::UserId)),
+                UserNoteLookupIden::UserId,
+            )
+            .expr_as(
+                Expr::col((UserNotes::Table, UserNotes::AuthorUserId)),
+                UserNoteLookupIden::AuthorUserId,
+            )
+            .expr_as(
+                Expr::col((UserNotes::Table, UserNotes::Content)),
+                UserNoteLookupIden::Content,
+            )
+            .expr_as(
+                Expr::col((UserNotes::Table, UserNotes::CreatedAt)),
+                UserNoteLookupIden::CreatedAt,
+            )
+            .from(UserNotes::Table)
+            .apply_filter(filter)
+            .generate_pagination((UserNotes::Table, UserNotes::UserNoteId), pagination)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let edges: Vec<UserNoteLookup> = sqlx::query_as_with(&sql, arguments)
+            .traced()
+            .fetch_all(&mut *self.conn)
+            .await?;
+
+        let page = pagination.process(edges).map(UserNote::from);
+
+        Ok(page)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_note.count",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count(&mut self, filter: UserNoteFilter<'_>) -> Result<usize, Self::Error> {
+        let (sql, arguments) = Query::select()
+            .expr(Expr::col((UserNotes::Table, UserNotes::UserNoteId)).count())
+            .from(UserNotes::Table)
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let count: i64 = sqlx::query_scalar_with(&sql, arguments)
+            .traced()
+            .fetch_one(&mut *self.conn)
+            .await?;
+
+        count
+            .try_into()
+            .map_err(DatabaseError::to_invalid_operation)
+    }
+
+    #[tracing::instrument(
+        name = "db.user_note.add",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+            user_note.id,
+        ),
+        err,
+    )]
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        author: Option<&User>,
+        content: String,
+    ) -> Result<UserNote, Self::Error> {
+        let created_at = clock.now();
+        let id = Ulid::from_datetime_with_source(created_at.into(), rng);
+        tracing::Span::current().record("user_note.id", tracing::field::display(id));
+
+        let author_id = author.map(|author| author.id);
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_notes
+                    ( user_note_id
+                    , user_id
+                    , author_user_id
+                    , content
+                    , created_at
+                    )
+                VALUES ($1, $2, $3, $4, $5)
+            "#,
+            Uuid::from(id),
+            Uuid::from(user.id),
+            author_id.map(Uuid::from),
+            &content,
+            created_at,
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        Ok(UserNote {
+            id,
+            user_id: user.id,
+            author_id,
+            content,
+            created_at,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "db.user_note.remove",
+        skip_all,
+        fields(
+            db.query.text,
+            user.id = %note.user_id,
+            %note.id,
+        ),
+        err,
+    )]
+    async fn remove(&mut self, note: UserNote) -> Result<(), Self::Error> {
+        let res = sqlx::query!(
+            r#"
+                DELETE FROM user_notes
+                WHERE user_note_id = $1
+            "#,
+            Uuid::from(note.id),
+        )
+        .traced()
+        .execute(&mut *self.conn)
+        .await?;
+
+        DatabaseError::ensure_affected_rows(&res, 1)?;
+
+        Ok(())
+    }
+}
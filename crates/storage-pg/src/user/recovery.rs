@@ -218,6 +218,47 @@ impl UserRecoveryRepository for PgUserRecoveryRepository<'_> {
         Ok(Some(row.into()))
     }
 
+    #[tracing::instrument(
+        name = "db.user_recovery.find_latest_ticket_for_email",
+        skip_all,
+        fields(
+            db.query.text,
+            %user_email.id,
+        ),
+        err,
+    )]
+    async fn find_latest_ticket_for_email(
+        &mut self,
+        user_email: &UserEmail,
+    ) -> Result<Option<UserRecoveryTicket>, Self::Error> {
+        let row = sqlx::query_as!(
+            UserRecoveryTicketRow,
+            r#"
+                SELECT
+                      user_recovery_ticket_id
+                    , user_recovery_session_id
+                    , user_email_id
+                    , ticket
+                    , created_at
+                    , expires_at
+                FROM user_recovery_tickets
+                WHERE user_email_id = $1
+                ORDER BY created_at DESC
+                LIMIT 1
+            "#,
+            Uuid::from(user_email.id),
+        )
+        .traced()
+        .fetch_optional(&mut *self.conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.into()))
+    }
+
     #[tracing::instrument(
         name = "db.user_recovery.add_ticket",
         skip_all,
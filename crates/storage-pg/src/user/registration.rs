@@ -11,7 +11,7 @@ use mas_data_model::{
     Clock, UserEmailAuthentication, UserRegistration, UserRegistrationPassword,
     UserRegistrationToken,
 };
-use mas_storage::user::UserRegistrationRepository;
+use mas_storage::user::{RegistrationBurst, UserRegistrationRepository};
 use rand::RngCore;
 use sqlx::PgConnection;
 use ulid::Ulid;
@@ -427,6 +427,128 @@ impl UserRegistrationRepository for PgUserRegistrationRepository<'_> {
 
         Ok(user_registration)
     }
+
+    #[tracing::instrument(
+        name = "db.user_registration.count_by_ip_address",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn count_by_ip_address(
+        &mut self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<(IpAddr, usize)>, Self::Error> {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let res = sqlx::query!(
+            r#"
+                SELECT ip_address AS "ip_address!: IpAddr"
+                     , COUNT(*) AS "count!"
+                FROM user_registrations
+                WHERE completed_at >= $1
+                  AND completed_at < $2
+                  AND ip_address IS NOT NULL
+                GROUP BY ip_address
+                ORDER BY COUNT(*) DESC, ip_address
+                LIMIT $3
+            "#,
+            since,
+            until,
+            limit,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        Ok(res
+            .into_iter()
+            .map(|row| (row.ip_address, row.count.try_into().unwrap_or(usize::MAX)))
+            .collect())
+    }
+
+    #[tracing::instrument(
+        name = "db.user_registration.list_bursts",
+        skip_all,
+        fields(
+            db.query.text,
+        ),
+        err,
+    )]
+    async fn list_bursts(
+        &mut self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        interval: chrono::Duration,
+        limit: usize,
+    ) -> Result<Vec<RegistrationBurst>, Self::Error> {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        // A new burst starts on every registration which comes more than
+        // `interval` after the previous one from the same IP address
+        let res = sqlx::query!(
+            r#"
+                WITH registrations AS (
+                    SELECT r.ip_address
+                         , r.completed_at
+                         , u.user_id
+                         , u.username
+                         , CASE
+                             WHEN r.completed_at - LAG(r.completed_at) OVER (
+                                 PARTITION BY r.ip_address ORDER BY r.completed_at, u.user_id
+                             ) <= $3::BIGINT * INTERVAL '1 second'
+                             THEN 0
+                             ELSE 1
+                           END AS starts_burst
+                    FROM user_registrations r
+                    INNER JOIN users u
+                      ON u.username = r.username
+                    WHERE r.completed_at >= $1
+                      AND r.completed_at < $2
+                      AND r.ip_address IS NOT NULL
+                ), bursts AS (
+                    SELECT *
+                         , SUM(starts_burst) OVER (
+                             PARTITION BY ip_address ORDER BY completed_at, user_id
+                           ) AS burst
+                    FROM registrations
+                )
+                SELECT ip_address AS "ip_address!: IpAddr"
+                     , MIN(completed_at) AS "first_registered_at!"
+                     , MAX(completed_at) AS "last_registered_at!"
+                     , ARRAY_AGG(user_id ORDER BY completed_at, user_id) AS "user_ids!"
+                     , ARRAY_AGG(username ORDER BY completed_at, user_id) AS "usernames!"
+                FROM bursts
+                GROUP BY ip_address, burst
+                HAVING COUNT(*) > 1
+                ORDER BY COUNT(*) DESC, MIN(completed_at), ip_address
+                LIMIT $4
+            "#,
+            since,
+            until,
+            interval.num_seconds(),
+            limit,
+        )
+        .traced()
+        .fetch_all(&mut *self.conn)
+        .await?;
+
+        Ok(res
+            .into_iter()
+            .map(|row| RegistrationBurst {
+                ip_address: row.ip_address,
+                first_registered_at: row.first_registered_at,
+                last_registered_at: row.last_registered_at,
+                users: row
+                    .user_ids
+                    .into_iter()
+                    .map(Ulid::from)
+                    .zip(row.usernames)
+                    .collect(),
+            })
+            .collect())
+    }
 }
 
 #[cfg(test)]
@@ -61,8 +61,14 @@ struct SessionLookup {
     user_created_at: DateTime<Utc>,
     user_locked_at: Option<DateTime<Utc>>,
     user_deactivated_at: Option<DateTime<Utc>>,
+    user_deleted_at: Option<DateTime<Utc>>,
     user_can_request_admin: bool,
     user_is_guest: bool,
+    user_organization: Option<String>,
+    user_max_sessions: Option<i32>,
+    user_locale: Option<String>,
+    user_deactivation_scheduled_at: Option<DateTime<Utc>>,
+    user_external_id: Option<String>,
 }
 
 impl Node<Ulid> for SessionLookup {
@@ -83,8 +89,16 @@ impl TryFrom<SessionLookup> for BrowserSession {
             created_at: value.user_created_at,
             locked_at: value.user_locked_at,
             deactivated_at: value.user_deactivated_at,
+            deleted_at: value.user_deleted_at,
             can_request_admin: value.user_can_request_admin,
             is_guest: value.user_is_guest,
+            organization: value.user_organization,
+            max_sessions: value
+                .user_max_sessions
+                .and_then(|max_sessions| u32::try_from(max_sessions).ok()),
+            locale: value.user_locale,
+            deactivation_scheduled_at: value.user_deactivation_scheduled_at,
+            external_id: value.user_external_id,
         };
 
         Ok(BrowserSession {
@@ -154,6 +168,10 @@ impl crate::filter::Filter for BrowserSessionFilter<'_> {
             .add_option(self.last_active_before().map(|last_active_before| {
                 Expr::col((UserSessions::Table, UserSessions::LastActiveAt)).lt(last_active_before)
             }))
+            .add_option(self.excluded_id().map(|excluded_id| {
+                Expr::col((UserSessions::Table, UserSessions::UserSessionId))
+                    .ne(Uuid::from(excluded_id))
+            }))
             .add_option(self.authenticated_by_upstream_sessions().map(|filter| {
                 // For filtering by upstream sessions, we need to hop over the
                 // `user_session_authentications` table
@@ -209,8 +227,14 @@ impl BrowserSessionRepository for PgBrowserSessionRepository<'_> {
                      , u.created_at            AS "user_created_at"
                      , u.locked_at             AS "user_locked_at"
                      , u.deactivated_at        AS "user_deactivated_at"
+                     , u.deleted_at            AS "user_deleted_at"
                      , u.can_request_admin     AS "user_can_request_admin"
                      , u.is_guest              AS "user_is_guest"
+                     , u.organization          AS "user_organization"
+                     , u.max_sessions          AS "user_max_sessions"
+                     , u.locale                AS "user_locale"
+                     , u.deactivation_scheduled_at AS "user_deactivation_scheduled_at"
+                     , u.external_id           AS "user_external_id"
                 FROM user_sessions s
                 INNER JOIN users u
                     USING (user_id)
@@ -339,6 +363,34 @@ impl BrowserSessionRepository for PgBrowserSessionRepository<'_> {
         Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
     }
 
+    #[tracing::instrument(
+        name = "db.browser_session.transfer_bulk",
+        skip_all,
+        fields(
+            db.query.text,
+            %user.id,
+        ),
+        err,
+    )]
+    async fn transfer_bulk(
+        &mut self,
+        filter: BrowserSessionFilter<'_>,
+        user: &User,
+    ) -> Result<usize, Self::Error> {
+        let (sql, arguments) = sea_query::Query::update()
+            .table(UserSessions::Table)
+            .value(UserSessions::UserId, Uuid::from(user.id))
+            .apply_filter(filter)
+            .build_sqlx(PostgresQueryBuilder);
+
+        let res = sqlx::query_with(&sql, arguments)
+            .traced()
+            .execute(&mut *self.conn)
+            .await?;
+
+        Ok(res.rows_affected().try_into().unwrap_or(usize::MAX))
+    }
+
     #[tracing::instrument(
         name = "db.browser_session.list",
         skip_all,
@@ -397,6 +449,10 @@ impl BrowserSessionRepository for PgBrowserSessionRepository<'_> {
                 Expr::col((Users::Table, Users::DeactivatedAt)),
                 SessionLookupIden::UserDeactivatedAt,
             )
+            .expr_as(
+                Expr::col((Users::Table, Users::DeletedAt)),
+                SessionLookupIden::UserDeletedAt,
+            )
             .expr_as(
                 Expr::col((Users::Table, Users::CanRequestAdmin)),
                 SessionLookupIden::UserCanRequestAdmin,
@@ -405,6 +461,26 @@ impl BrowserSessionRepository for PgBrowserSessionRepository<'_> {
                 Expr::col((Users::Table, Users::IsGuest)),
                 SessionLookupIden::UserIsGuest,
             )
+            .expr_as(
+                Expr::col((Users::Table, Users::Organization)),
+                SessionLookupIden::UserOrganization,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::MaxSessions)),
+                SessionLookupIden::UserMaxSessions,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::Locale)),
+                SessionLookupIden::UserLocale,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::DeactivationScheduledAt)),
+                SessionLookupIden::UserDeactivationScheduledAt,
+            )
+            .expr_as(
+                Expr::col((Users::Table, Users::ExternalId)),
+                SessionLookupIden::UserExternalId,
+            )
             .from(UserSessions::Table)
             .inner_join(
                 Users::Table,
@@ -5,20 +5,23 @@
 // Please see LICENSE files in the repository root for full details.
 
 use chrono::Duration;
-use mas_data_model::{Clock, clock::MockClock};
+use mas_data_model::{Clock, LoginFailureOrigin, LoginFailureReason, User, clock::MockClock};
 use mas_iana::jose::JsonWebSignatureAlg;
 use mas_storage::{
     Pagination, RepositoryAccess,
+    compat::{CompatAccessTokenRepository, CompatSessionRepository},
     upstream_oauth2::{UpstreamOAuthProviderParams, UpstreamOAuthSessionFilter},
     user::{
         BrowserSessionFilter, BrowserSessionRepository, UserEmailFilter, UserEmailRepository,
-        UserFilter, UserPasswordRepository, UserRepository,
+        UserFilter, UserNoteFilter, UserNoteRepository, UserPasswordRepository,
+        UserRecoveryRepository, UserRepository,
     },
 };
 use oauth2_types::scope::{OPENID, Scope};
 use rand::SeedableRng;
 use rand_chacha::ChaChaRng;
 use sqlx::PgPool;
+use ulid::Ulid;
 
 use crate::PgRepository;
 
@@ -404,6 +407,102 @@ async fn test_user_email_repo(pool: PgPool) {
     repo.save().await.unwrap();
 }
 
+/// Test adding many user emails at once
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_email_repo_add_many(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let bob = repo
+        .user()
+        .add(&mut rng, &clock, "bob".to_owned())
+        .await
+        .unwrap();
+
+    // Adding nothing is a no-op
+    let emails = repo
+        .user_email()
+        .add_many(&mut rng, &clock, &[])
+        .await
+        .unwrap();
+    assert!(emails.is_empty());
+
+    // Add a thousand emails, alternating between the two users
+    let batch: Vec<_> = (0..1000)
+        .map(|i| {
+            let user = if i % 2 == 0 { &alice } else { &bob };
+            (user, format!("user{i}@example.com"))
+        })
+        .collect();
+    let emails = repo
+        .user_email()
+        .add_many(&mut rng, &clock, &batch)
+        .await
+        .unwrap();
+    assert_eq!(emails.len(), 1000);
+
+    // They are returned in the same order as the input
+    assert_eq!(emails[0].user_id, alice.id);
+    assert_eq!(emails[0].email, "user0@example.com");
+    assert_eq!(emails[999].user_id, bob.id);
+    assert_eq!(emails[999].email, "user999@example.com");
+
+    let lookup = repo
+        .user_email()
+        .lookup(emails[999].id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lookup, emails[999]);
+
+    let filter = UserEmailFilter::new().for_user(&alice);
+    assert_eq!(repo.user_email().count(filter).await.unwrap(), 500);
+    let filter = UserEmailFilter::new().for_user(&bob);
+    assert_eq!(repo.user_email().count(filter).await.unwrap(), 500);
+
+    // A batch with an email for a user which doesn't exist is rejected, and
+    // the error reports which one failed
+    let ghost = User {
+        id: Ulid::nil(),
+        ..alice.clone()
+    };
+    let batch = [
+        (&alice, "first@example.com".to_owned()),
+        (&bob, "second@example.com".to_owned()),
+        (&ghost, "ghost@example.com".to_owned()),
+        (&bob, "fourth@example.com".to_owned()),
+    ];
+    let error = repo
+        .user_email()
+        .add_many(&mut rng, &clock, &batch)
+        .await
+        .unwrap_err();
+    assert_eq!(error.batch_index(), Some(2));
+
+    // Nothing from the failed batch was written, and the transaction can still
+    // be used
+    assert_eq!(
+        repo.user_email()
+            .count(UserEmailFilter::new())
+            .await
+            .unwrap(),
+        1000
+    );
+    assert!(
+        repo.user_email()
+            .find_by_email("first@example.com")
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
 /// Test the authentication codes methods in the user email repository
 #[sqlx::test(migrator = "crate::MIGRATOR")]
 async fn test_user_email_repo_authentications(pool: PgPool) {
@@ -519,6 +618,133 @@ async fn test_user_email_repo_authentications(pool: PgPool) {
     assert!(res.is_err());
 }
 
+/// Test the email changes methods in the user email repository
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_email_repo_changes(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    // Create a user with an email address, and a completed authentication for
+    // another address
+    let user = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let user_email = repo
+        .user_email()
+        .add(&mut rng, &clock, &user, "alice@example.com".to_owned())
+        .await
+        .unwrap();
+    let browser_session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &user, None)
+        .await
+        .unwrap();
+    let authentication = repo
+        .user_email()
+        .add_authentication_for_session(
+            &mut rng,
+            &clock,
+            "alice@example.org".to_owned(),
+            &browser_session,
+        )
+        .await
+        .unwrap();
+
+    // Start two changes
+    let change = repo
+        .user_email()
+        .add_change(
+            &mut rng,
+            &clock,
+            Duration::hours(24),
+            &authentication,
+            &user_email,
+            "first-ticket".to_owned(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(change.user_id, user.id);
+    assert_eq!(change.user_email_authentication_id, authentication.id);
+    assert_eq!(change.email, "alice@example.org");
+    assert_eq!(change.previous_email, "alice@example.com");
+    assert_eq!(change.expires_at, clock.now() + Duration::hours(24));
+    assert!(change.is_pending(clock.now()));
+
+    let other_change = repo
+        .user_email()
+        .add_change(
+            &mut rng,
+            &clock,
+            Duration::hours(24),
+            &authentication,
+            &user_email,
+            "second-ticket".to_owned(),
+        )
+        .await
+        .unwrap();
+
+    // They can be found by ID and by ticket
+    let lookup = repo
+        .user_email()
+        .lookup_change(change.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lookup, change);
+
+    let lookup = repo
+        .user_email()
+        .find_change_by_ticket("second-ticket")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lookup, other_change);
+
+    assert!(
+        repo.user_email()
+            .find_change_by_ticket("unknown-ticket")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // The change expires after the given duration
+    assert!(!change.is_pending(clock.now() + Duration::hours(24)));
+
+    // Confirm the first one, and cancel the second one
+    let change = repo
+        .user_email()
+        .confirm_change(&clock, change)
+        .await
+        .unwrap();
+    assert_eq!(change.confirmed_at, Some(clock.now()));
+    assert!(!change.is_pending(clock.now()));
+
+    let other_change = repo
+        .user_email()
+        .cancel_change(&clock, other_change)
+        .await
+        .unwrap();
+    assert_eq!(other_change.cancelled_at, Some(clock.now()));
+    assert!(!other_change.is_pending(clock.now()));
+
+    let lookup = repo
+        .user_email()
+        .lookup_change(change.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(lookup.confirmed_at, Some(clock.now()));
+    assert_eq!(lookup.cancelled_at, None);
+
+    // A change can't be confirmed or cancelled twice
+    let res = repo.user_email().cancel_change(&clock, change).await;
+    assert!(res.is_err());
+}
+
 /// Test the user password repository implementation.
 #[sqlx::test(migrator = "crate::MIGRATOR")]
 async fn test_user_password_repo(pool: PgPool) {
@@ -769,6 +995,7 @@ async fn test_user_session(pool: PgPool) {
                 additional_authorization_parameters: Vec::new(),
                 forward_login_hint: false,
                 ui_order: 0,
+                ui_hidden: false,
                 on_backchannel_logout:
                     mas_data_model::UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
             },
@@ -887,3 +1114,725 @@ async fn test_user_terms(pool: PgPool) {
         .unwrap();
     assert_eq!(res, 2);
 }
+
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_login_failures(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let bob = repo
+        .user()
+        .add(&mut rng, &clock, "bob".to_owned())
+        .await
+        .unwrap();
+
+    // Nothing recorded at first
+    let failures = repo
+        .user_login_failure()
+        .list_recent(&alice, 10)
+        .await
+        .unwrap();
+    assert!(failures.is_empty());
+
+    let ip = "192.0.2.1".parse().unwrap();
+    for _ in 0..3 {
+        repo.user_login_failure()
+            .add(
+                &mut rng,
+                &clock,
+                &alice,
+                LoginFailureOrigin::Compat,
+                LoginFailureReason::PasswordMismatch,
+                Some(ip),
+            )
+            .await
+            .unwrap();
+        clock.advance(Duration::microseconds(10 * 1000 * 1000));
+    }
+
+    let last = repo
+        .user_login_failure()
+        .add(
+            &mut rng,
+            &clock,
+            &alice,
+            LoginFailureOrigin::Interactive,
+            LoginFailureReason::RateLimited,
+            None,
+        )
+        .await
+        .unwrap();
+
+    repo.user_login_failure()
+        .add(
+            &mut rng,
+            &clock,
+            &bob,
+            LoginFailureOrigin::Interactive,
+            LoginFailureReason::UserLocked,
+            None,
+        )
+        .await
+        .unwrap();
+
+    // The most recent entry comes first, and the limit is respected
+    let failures = repo
+        .user_login_failure()
+        .list_recent(&alice, 2)
+        .await
+        .unwrap();
+    assert_eq!(failures.len(), 2);
+    assert_eq!(failures[0], last);
+    assert_eq!(failures[1].origin, LoginFailureOrigin::Compat);
+    assert_eq!(failures[1].reason, LoginFailureReason::PasswordMismatch);
+    assert_eq!(failures[1].ip_address, Some(ip));
+
+    // Pruning keeps the most recent entries for each user
+    let pruned = repo.user_login_failure().prune(2).await.unwrap();
+    assert_eq!(pruned, 2);
+
+    let failures = repo
+        .user_login_failure()
+        .list_recent(&alice, 10)
+        .await
+        .unwrap();
+    assert_eq!(failures.len(), 2);
+    assert_eq!(failures[0], last);
+
+    let failures = repo
+        .user_login_failure()
+        .list_recent(&bob, 10)
+        .await
+        .unwrap();
+    assert_eq!(failures.len(), 1);
+}
+
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_recovery_latest_ticket(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let email = repo
+        .user_email()
+        .add(&mut rng, &clock, &alice, "alice@example.com".to_owned())
+        .await
+        .unwrap();
+    let other_email = repo
+        .user_email()
+        .add(&mut rng, &clock, &alice, "alice@example.org".to_owned())
+        .await
+        .unwrap();
+
+    // No ticket yet
+    assert!(
+        repo.user_recovery()
+            .find_latest_ticket_for_email(&email)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // Tickets from different recovery sessions for the same email
+    let mut latest = None;
+    for ticket in ["first", "second"] {
+        let session = repo
+            .user_recovery()
+            .add_session(
+                &mut rng,
+                &clock,
+                "alice@example.com".to_owned(),
+                "Mozilla/5.0".to_owned(),
+                None,
+                "en".to_owned(),
+            )
+            .await
+            .unwrap();
+
+        latest = Some(
+            repo.user_recovery()
+                .add_ticket(&mut rng, &clock, &session, &email, ticket.to_owned())
+                .await
+                .unwrap(),
+        );
+
+        clock.advance(Duration::minutes(1));
+    }
+
+    let ticket = repo
+        .user_recovery()
+        .find_latest_ticket_for_email(&email)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(ticket), latest);
+
+    // Tickets are looked up per email
+    assert!(
+        repo.user_recovery()
+            .find_latest_ticket_for_email(&other_email)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+/// Test soft-deleting, restoring and purging users
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_soft_delete(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let deleted = UserFilter::new().deactivated_only();
+
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let bob = repo
+        .user()
+        .add(&mut rng, &clock, "bob".to_owned())
+        .await
+        .unwrap();
+
+    // Attach some data to alice
+    repo.user_email()
+        .add(&mut rng, &clock, &alice, "alice@example.com".to_owned())
+        .await
+        .unwrap();
+    let password = repo
+        .user_password()
+        .add(&mut rng, &clock, &alice, 1, "hashed".to_owned(), None)
+        .await
+        .unwrap();
+    let browser_session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &alice, None)
+        .await
+        .unwrap();
+    repo.browser_session()
+        .authenticate_with_password(&mut rng, &clock, &browser_session, &password)
+        .await
+        .unwrap();
+    let device = mas_data_model::Device::generate(&mut rng);
+    let compat_session = repo
+        .compat_session()
+        .add(
+            &mut rng,
+            &clock,
+            &alice,
+            device,
+            Some(&browser_session),
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+    repo.compat_access_token()
+        .add(&mut rng, &clock, &compat_session, "token".to_owned(), None)
+        .await
+        .unwrap();
+
+    // Soft-delete alice
+    clock.advance(Duration::minutes(1));
+    let alice = repo.user().delete(&clock, alice).await.unwrap();
+    assert!(alice.is_deleted());
+    assert_eq!(alice.deleted_at, Some(clock.now()));
+    assert_eq!(alice.deactivated_at, alice.deleted_at);
+
+    // The username is still reserved
+    assert!(repo.user().exists("alice").await.unwrap());
+    let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert!(alice.is_deleted());
+
+    // Deleting twice keeps the original deletion timestamp
+    let deleted_at = alice.deleted_at;
+    clock.advance(Duration::minutes(1));
+    let alice = repo.user().delete(&clock, alice).await.unwrap();
+    assert_eq!(alice.deleted_at, deleted_at);
+
+    // Filter on the deletion date
+    let filter = UserFilter::new().with_deleted_before(clock.now());
+    assert_eq!(repo.user().count(filter).await.unwrap(), 1);
+    let filter = UserFilter::new().with_deleted_before(deleted_at.unwrap());
+    assert_eq!(repo.user().count(filter).await.unwrap(), 0);
+    assert_eq!(repo.user().count(deleted).await.unwrap(), 1);
+
+    // Restoring the user also reactivates it
+    let alice = repo.user().undelete(alice).await.unwrap();
+    assert!(!alice.is_deleted());
+    assert!(alice.deactivated_at.is_none());
+    assert_eq!(repo.user().count(deleted).await.unwrap(), 0);
+
+    // A user deactivated before being deleted stays deactivated when restored
+    let alice = repo.user().deactivate(&clock, alice).await.unwrap();
+    let deactivated_at = alice.deactivated_at;
+    clock.advance(Duration::minutes(1));
+    let alice = repo.user().delete(&clock, alice).await.unwrap();
+    assert_eq!(alice.deactivated_at, deactivated_at);
+    let alice = repo.user().undelete(alice).await.unwrap();
+    assert!(!alice.is_deleted());
+    assert_eq!(alice.deactivated_at, deactivated_at);
+
+    // Purge alice
+    let alice = repo.user().delete(&clock, alice).await.unwrap();
+    repo.user().purge(alice.clone()).await.unwrap();
+
+    assert!(!repo.user().exists("alice").await.unwrap());
+    assert!(repo.user().lookup(alice.id).await.unwrap().is_none());
+    assert!(
+        repo.user_email()
+            .find(&alice, "alice@example.com")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        repo.browser_session()
+            .lookup(browser_session.id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        repo.compat_session()
+            .lookup(compat_session.id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // Bob is left untouched
+    assert!(repo.user().exists("bob").await.unwrap());
+    let bob = repo.user().lookup(bob.id).await.unwrap().unwrap();
+    assert!(!bob.is_deleted());
+    assert_eq!(repo.user().count(UserFilter::new()).await.unwrap(), 1);
+}
+
+/// Test setting the organization of a user and filtering on it
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_organization(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let bob = repo
+        .user()
+        .add(&mut rng, &clock, "bob".to_owned())
+        .await
+        .unwrap();
+    assert!(alice.organization.is_none());
+
+    let alice = repo
+        .user()
+        .set_organization(alice, Some("Ministère de l'Intérieur".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(
+        alice.organization.as_deref(),
+        Some("Ministère de l'Intérieur")
+    );
+
+    // The organization is loaded back from the database
+    let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert_eq!(
+        alice.organization.as_deref(),
+        Some("Ministère de l'Intérieur")
+    );
+
+    // It is also loaded with browser sessions
+    let session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &alice, None)
+        .await
+        .unwrap();
+    let session = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session.user, alice);
+
+    // Filter on the organization
+    let interior = UserFilter::new().with_organization("Ministère de l'Intérieur");
+    let culture = UserFilter::new().with_organization("Ministère de la Culture");
+    assert_eq!(repo.user().count(interior).await.unwrap(), 1);
+    assert_eq!(repo.user().count(culture).await.unwrap(), 0);
+
+    let page = repo
+        .user()
+        .list(interior, Pagination::first(10))
+        .await
+        .unwrap();
+    assert_eq!(page.edges.len(), 1);
+    assert_eq!(page.edges[0].node.id, alice.id);
+
+    let bob = repo
+        .user()
+        .set_organization(bob, Some("Ministère de la Culture".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(repo.user().count(culture).await.unwrap(), 1);
+
+    // Unset the organization
+    let bob = repo.user().set_organization(bob, None).await.unwrap();
+    assert!(bob.organization.is_none());
+    let bob = repo.user().lookup(bob.id).await.unwrap().unwrap();
+    assert!(bob.organization.is_none());
+    assert_eq!(repo.user().count(culture).await.unwrap(), 0);
+}
+
+/// Test overriding the maximum number of sessions of a user
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_max_sessions(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    assert!(alice.max_sessions.is_none());
+
+    let alice = repo.user().set_max_sessions(alice, Some(2)).await.unwrap();
+    assert_eq!(alice.max_sessions, Some(2));
+
+    // The override is loaded back from the database, also with browser sessions
+    let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert_eq!(alice.max_sessions, Some(2));
+
+    let session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &alice, None)
+        .await
+        .unwrap();
+    let session = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session.user, alice);
+
+    // Remove the override
+    let alice = repo.user().set_max_sessions(alice, None).await.unwrap();
+    assert!(alice.max_sessions.is_none());
+    let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert!(alice.max_sessions.is_none());
+}
+
+/// Test setting the preferred language of a user
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_locale(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    assert!(alice.locale.is_none());
+
+    let alice = repo
+        .user()
+        .set_locale(alice, Some("fr".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(alice.locale.as_deref(), Some("fr"));
+
+    // It is loaded back from the database, also with browser sessions
+    let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert_eq!(alice.locale.as_deref(), Some("fr"));
+    let found = repo
+        .user()
+        .find_by_username("ALICE")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, alice);
+
+    let session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &alice, None)
+        .await
+        .unwrap();
+    let session = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session.user, alice);
+
+    // Go back to the negotiated language
+    let alice = repo.user().set_locale(alice, None).await.unwrap();
+    assert!(alice.locale.is_none());
+    let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert!(alice.locale.is_none());
+}
+
+/// Test setting the external ID of a user, looking it up and filtering on it
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_external_id(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let bob = repo
+        .user()
+        .add(&mut rng, &clock, "bob".to_owned())
+        .await
+        .unwrap();
+    assert!(alice.external_id.is_none());
+    assert!(
+        repo.user()
+            .find_by_external_id("hr-1234")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    let alice = repo
+        .user()
+        .set_external_id(alice, Some("hr-1234".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(alice.external_id.as_deref(), Some("hr-1234"));
+
+    // It is loaded back from the database, also with browser sessions
+    let found = repo
+        .user()
+        .find_by_external_id("hr-1234")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, alice);
+    let found = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert_eq!(found, alice);
+
+    let session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &alice, None)
+        .await
+        .unwrap();
+    let session = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session.user, alice);
+
+    // Filter on whether users have an external ID
+    let page = repo
+        .user()
+        .list(
+            UserFilter::new().with_external_id_only(),
+            Pagination::first(10),
+        )
+        .await
+        .unwrap();
+    assert_eq!(page.edges.len(), 1);
+    assert_eq!(page.edges[0].node, alice);
+    assert_eq!(
+        repo.user()
+            .count(UserFilter::new().without_external_id_only())
+            .await
+            .unwrap(),
+        1
+    );
+
+    // Removing it makes it available again
+    let alice = repo.user().set_external_id(alice, None).await.unwrap();
+    assert!(alice.external_id.is_none());
+    assert!(
+        repo.user()
+            .find_by_external_id("hr-1234")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    repo.user()
+        .set_external_id(bob, Some("hr-1234".to_owned()))
+        .await
+        .unwrap();
+
+    // Two users can't have the same external ID
+    let res = repo
+        .user()
+        .set_external_id(alice, Some("hr-1234".to_owned()))
+        .await;
+    assert!(res.is_err());
+}
+
+/// Test scheduling and cancelling the deactivation of a user
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_scheduled_deactivation(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    assert!(alice.deactivation_scheduled_at.is_none());
+
+    let scheduled_at = clock.now() + Duration::days(7);
+    let alice = repo
+        .user()
+        .schedule_deactivation(alice, scheduled_at)
+        .await
+        .unwrap();
+    assert_eq!(alice.deactivation_scheduled_at, Some(scheduled_at));
+
+    // It is loaded back from the database, also with browser sessions
+    let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert_eq!(alice.deactivation_scheduled_at, Some(scheduled_at));
+    let session = repo
+        .browser_session()
+        .add(&mut rng, &clock, &alice, None)
+        .await
+        .unwrap();
+    let session = repo
+        .browser_session()
+        .lookup(session.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session.user, alice);
+
+    let alice = repo.user().unschedule_deactivation(alice).await.unwrap();
+    assert!(alice.deactivation_scheduled_at.is_none());
+    let alice = repo.user().lookup(alice.id).await.unwrap().unwrap();
+    assert!(alice.deactivation_scheduled_at.is_none());
+
+    // Unscheduling again is a no-op
+    let alice = repo.user().unschedule_deactivation(alice).await.unwrap();
+    assert!(alice.deactivation_scheduled_at.is_none());
+}
+
+/// Test adding, listing and removing notes on a user
+#[sqlx::test(migrator = "crate::MIGRATOR")]
+async fn test_user_notes(pool: PgPool) {
+    let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let clock = MockClock::default();
+
+    let admin = repo
+        .user()
+        .add(&mut rng, &clock, "admin".to_owned())
+        .await
+        .unwrap();
+    let alice = repo
+        .user()
+        .add(&mut rng, &clock, "alice".to_owned())
+        .await
+        .unwrap();
+    let bob = repo
+        .user()
+        .add(&mut rng, &clock, "bob".to_owned())
+        .await
+        .unwrap();
+
+    let alice_notes = UserNoteFilter::new().for_user(&alice);
+    let bob_notes = UserNoteFilter::new().for_user(&bob);
+
+    // No notes at first
+    assert_eq!(repo.user_note().count(alice_notes).await.unwrap(), 0);
+    assert!(repo.user_note().latest(&alice).await.unwrap().is_none());
+
+    let first = repo
+        .user_note()
+        .add(
+            &mut rng,
+            &clock,
+            &alice,
+            Some(&admin),
+            "Called support about her password".to_owned(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(first.user_id, alice.id);
+    assert_eq!(first.author_id, Some(admin.id));
+
+    clock.advance(Duration::minutes(1));
+
+    // Notes can also be added without an author
+    let second = repo
+        .user_note()
+        .add(
+            &mut rng,
+            &clock,
+            &alice,
+            None,
+            "Account reviewed".to_owned(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(second.author_id, None);
+
+    // Lookup the notes
+    let note = repo.user_note().lookup(first.id).await.unwrap().unwrap();
+    assert_eq!(note, first);
+
+    // The latest note is the most recent one
+    let latest = repo.user_note().latest(&alice).await.unwrap().unwrap();
+    assert_eq!(latest, second);
+    assert!(repo.user_note().latest(&bob).await.unwrap().is_none());
+
+    // Count and list the notes
+    assert_eq!(repo.user_note().count(alice_notes).await.unwrap(), 2);
+    assert_eq!(repo.user_note().count(bob_notes).await.unwrap(), 0);
+    assert_eq!(
+        repo.user_note().count(UserNoteFilter::new()).await.unwrap(),
+        2
+    );
+
+    let page = repo
+        .user_note()
+        .list(alice_notes, Pagination::first(10))
+        .await
+        .unwrap();
+    assert!(!page.has_next_page);
+    assert_eq!(page.edges.len(), 2);
+    assert_eq!(page.edges[0].node, first);
+    assert_eq!(page.edges[1].node, second);
+
+    // Remove the latest note
+    repo.user_note().remove(second).await.unwrap();
+    assert_eq!(repo.user_note().count(alice_notes).await.unwrap(), 1);
+    let latest = repo.user_note().latest(&alice).await.unwrap().unwrap();
+    assert_eq!(latest, first);
+
+    repo.save().await.unwrap();
+}
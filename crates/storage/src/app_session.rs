@@ -8,7 +8,8 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use mas_data_model::{BrowserSession, Clock, CompatSession, Device, Session, User};
+use ipnetwork::IpNetwork;
+use mas_data_model::{BrowserSession, Client, Clock, CompatSession, Device, Session, User};
 
 use crate::{Page, Pagination, repository_impl};
 
@@ -54,6 +55,10 @@ pub struct AppSessionFilter<'a> {
     device_id: Option<&'a Device>,
     last_active_before: Option<DateTime<Utc>>,
     last_active_after: Option<DateTime<Utc>>,
+    finished_after: Option<DateTime<Utc>>,
+    created_after: Option<DateTime<Utc>>,
+    last_active_ip_in: Option<IpNetwork>,
+    client: Option<&'a Client>,
 }
 
 impl<'a> AppSessionFilter<'a> {
@@ -132,6 +137,67 @@ impl<'a> AppSessionFilter<'a> {
         self.last_active_after
     }
 
+    /// Only return sessions which finished after the given time
+    #[must_use]
+    pub fn with_finished_after(mut self, finished_after: DateTime<Utc>) -> Self {
+        self.finished_after = Some(finished_after);
+        self
+    }
+
+    /// Get the finished after filter
+    ///
+    /// Returns [`None`] if no finished after filter was set
+    #[must_use]
+    pub fn finished_after(&self) -> Option<DateTime<Utc>> {
+        self.finished_after
+    }
+
+    /// Only return sessions created after the given time
+    #[must_use]
+    pub fn with_created_after(mut self, created_after: DateTime<Utc>) -> Self {
+        self.created_after = Some(created_after);
+        self
+    }
+
+    /// Get the created after filter
+    ///
+    /// Returns [`None`] if no created after filter was set
+    #[must_use]
+    pub fn created_after(&self) -> Option<DateTime<Utc>> {
+        self.created_after
+    }
+
+    /// Only return sessions last seen from an IP address in the given network
+    #[must_use]
+    pub fn with_last_active_ip_in(mut self, network: IpNetwork) -> Self {
+        self.last_active_ip_in = Some(network);
+        self
+    }
+
+    /// Get the network the last active IP address must be in
+    ///
+    /// Returns [`None`] if no IP filter was set
+    #[must_use]
+    pub fn last_active_ip_in(&self) -> Option<IpNetwork> {
+        self.last_active_ip_in
+    }
+
+    /// Only return OAuth 2.0 sessions of the given client. Compatibility
+    /// sessions never match this filter.
+    #[must_use]
+    pub fn for_client(mut self, client: &'a Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Get the client filter
+    ///
+    /// Returns [`None`] if no client filter was set
+    #[must_use]
+    pub fn client(&self) -> Option<&'a Client> {
+        self.client
+    }
+
     /// Only return active compatibility sessions
     #[must_use]
     pub fn active_only(mut self) -> Self {
@@ -8,7 +8,10 @@ use std::net::IpAddr;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use mas_data_model::{BrowserSession, Clock, CompatSession, CompatSsoLogin, Device, User};
+use ipnetwork::IpNetwork;
+use mas_data_model::{
+    AuthenticatedBy, BrowserSession, Clock, CompatSession, CompatSsoLogin, Device, User,
+};
 use rand_core::RngCore;
 use ulid::Ulid;
 
@@ -65,6 +68,10 @@ pub struct CompatSessionFilter<'a> {
     device: Option<&'a Device>,
     last_active_before: Option<DateTime<Utc>>,
     last_active_after: Option<DateTime<Utc>>,
+    finished_after: Option<DateTime<Utc>>,
+    created_after: Option<DateTime<Utc>>,
+    last_active_ip_in: Option<IpNetwork>,
+    excluded_id: Option<Ulid>,
 }
 
 impl<'a> CompatSessionFilter<'a> {
@@ -159,6 +166,66 @@ impl<'a> CompatSessionFilter<'a> {
         self.last_active_after
     }
 
+    /// Only return sessions which finished after the given time
+    #[must_use]
+    pub fn with_finished_after(mut self, finished_after: DateTime<Utc>) -> Self {
+        self.finished_after = Some(finished_after);
+        self
+    }
+
+    /// Get the finished after filter
+    ///
+    /// Returns [`None`] if no finished after filter was set
+    #[must_use]
+    pub fn finished_after(&self) -> Option<DateTime<Utc>> {
+        self.finished_after
+    }
+
+    /// Only return sessions created after the given time
+    #[must_use]
+    pub fn with_created_after(mut self, created_after: DateTime<Utc>) -> Self {
+        self.created_after = Some(created_after);
+        self
+    }
+
+    /// Get the created after filter
+    ///
+    /// Returns [`None`] if no created after filter was set
+    #[must_use]
+    pub fn created_after(&self) -> Option<DateTime<Utc>> {
+        self.created_after
+    }
+
+    /// Only return sessions last seen from an IP address in the given network
+    #[must_use]
+    pub fn with_last_active_ip_in(mut self, network: IpNetwork) -> Self {
+        self.last_active_ip_in = Some(network);
+        self
+    }
+
+    /// Get the network the last active IP address must be in
+    ///
+    /// Returns [`None`] if no IP filter was set
+    #[must_use]
+    pub fn last_active_ip_in(&self) -> Option<IpNetwork> {
+        self.last_active_ip_in
+    }
+
+    /// Exclude the session with the given ID
+    #[must_use]
+    pub fn excluding(mut self, id: Ulid) -> Self {
+        self.excluded_id = Some(id);
+        self
+    }
+
+    /// Get the ID of the session excluded by this filter
+    ///
+    /// Returns [`None`] if no session is excluded
+    #[must_use]
+    pub fn excluded_id(&self) -> Option<Ulid> {
+        self.excluded_id
+    }
+
     /// Only return active compatibility sessions
     #[must_use]
     pub fn active_only(mut self) -> Self {
@@ -286,6 +353,46 @@ pub trait CompatSessionRepository: Send + Sync {
         filter: CompatSessionFilter<'_>,
     ) -> Result<usize, Self::Error>;
 
+    /// Move all the [`CompatSession`] matching the given filter to another
+    /// user
+    ///
+    /// Returns the number of sessions affected
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter to apply
+    /// * `user`: The user which should now own the sessions
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn transfer_bulk(
+        &mut self,
+        filter: CompatSessionFilter<'_>,
+        user: &User,
+    ) -> Result<usize, Self::Error>;
+
+    /// Permanently delete the finished [`CompatSession`]s of a user which
+    /// finished before the given date, along with their access and refresh
+    /// tokens
+    ///
+    /// Returns the number of sessions deleted
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The user whose sessions should be deleted
+    /// * `finished_before`: Only sessions finished strictly before this date
+    ///   are deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn delete_finished_before(
+        &mut self,
+        user: &User,
+        finished_before: DateTime<Utc>,
+    ) -> Result<usize, Self::Error>;
+
     /// List [`CompatSession`] with the given filter and pagination
     ///
     /// Returns a page of compat sessions, with the associated SSO logins if any
@@ -361,6 +468,43 @@ pub trait CompatSessionRepository: Send + Sync {
         compat_session: CompatSession,
         human_name: Option<String>,
     ) -> Result<CompatSession, Self::Error>;
+
+    /// Record how the user authenticated when a [`CompatSession`] was
+    /// established
+    ///
+    /// Returns the updated session
+    ///
+    /// # Parameters
+    ///
+    /// * `compat_session`: The [`CompatSession`] to update
+    /// * `authenticated_by`: How the user authenticated
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_authenticated_by(
+        &mut self,
+        compat_session: CompatSession,
+        authenticated_by: AuthenticatedBy,
+    ) -> Result<CompatSession, Self::Error>;
+
+    /// Give a new device ID to a [`CompatSession`]
+    ///
+    /// Returns the updated session
+    ///
+    /// # Parameters
+    ///
+    /// * `compat_session`: The [`CompatSession`] to update
+    /// * `device`: The new device of the session
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_device(
+        &mut self,
+        compat_session: CompatSession,
+        device: Device,
+    ) -> Result<CompatSession, Self::Error>;
 }
 
 repository_impl!(CompatSessionRepository:
@@ -389,6 +533,18 @@ repository_impl!(CompatSessionRepository:
         filter: CompatSessionFilter<'_>,
     ) -> Result<usize, Self::Error>;
 
+    async fn transfer_bulk(
+        &mut self,
+        filter: CompatSessionFilter<'_>,
+        user: &User,
+    ) -> Result<usize, Self::Error>;
+
+    async fn delete_finished_before(
+        &mut self,
+        user: &User,
+        finished_before: DateTime<Utc>,
+    ) -> Result<usize, Self::Error>;
+
     async fn list(
         &mut self,
         filter: CompatSessionFilter<'_>,
@@ -413,4 +569,16 @@ repository_impl!(CompatSessionRepository:
         compat_session: CompatSession,
         human_name: Option<String>,
     ) -> Result<CompatSession, Self::Error>;
+
+    async fn set_authenticated_by(
+        &mut self,
+        compat_session: CompatSession,
+        authenticated_by: AuthenticatedBy,
+    ) -> Result<CompatSession, Self::Error>;
+
+    async fn set_device(
+        &mut self,
+        compat_session: CompatSession,
+        device: Device,
+    ) -> Result<CompatSession, Self::Error>;
 );
@@ -0,0 +1,82 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Repositories to interact with the results of the email delivery checks
+//! saved in the storage backend.
+
+use async_trait::async_trait;
+use mas_data_model::{Clock, EmailProbeKind, EmailProbeResult};
+use rand_core::RngCore;
+
+use crate::repository_impl;
+
+/// An [`EmailProbeRepository`] helps interacting with the results of the
+/// email delivery checks saved in the storage backend.
+#[async_trait]
+pub trait EmailProbeRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Get the result of the latest check
+    ///
+    /// Returns `None` if no check ran yet
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn latest(&mut self) -> Result<Option<EmailProbeResult>, Self::Error>;
+
+    /// Record the result of a check
+    ///
+    /// Returns the newly recorded result
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate the timestamps
+    /// * `kind`: How the email delivery was checked
+    /// * `latency_ms`: How long the check took, in milliseconds
+    /// * `error`: Why the check failed, if it did
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        kind: EmailProbeKind,
+        latency_ms: u64,
+        error: Option<String>,
+    ) -> Result<EmailProbeResult, Self::Error>;
+
+    /// Prune old results
+    ///
+    /// Returns the number of results pruned
+    ///
+    /// # Parameters
+    ///
+    /// * `keep`: the number of most recent results to keep
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn prune(&mut self, keep: usize) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(EmailProbeRepository:
+    async fn latest(&mut self) -> Result<Option<EmailProbeResult>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        kind: EmailProbeKind,
+        latency_ms: u64,
+        error: Option<String>,
+    ) -> Result<EmailProbeResult, Self::Error>;
+
+    async fn prune(&mut self, keep: usize) -> Result<usize, Self::Error>;
+);
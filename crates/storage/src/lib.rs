@@ -110,10 +110,13 @@ mod utils;
 
 pub mod app_session;
 pub mod compat;
+pub mod email_probe;
 pub mod oauth2;
 pub mod personal;
 pub mod policy_data;
+pub mod provisioning_report;
 pub mod queue;
+pub mod syn2mas_run;
 pub mod upstream_oauth2;
 pub mod user;
 
@@ -121,7 +124,7 @@ pub use self::{
     pagination::{Page, Pagination},
     repository::{
         BoxRepository, BoxRepositoryFactory, Repository, RepositoryAccess, RepositoryError,
-        RepositoryFactory, RepositoryTransaction,
+        RepositoryFactory, RepositoryTransaction, RepositoryUnavailableError,
     },
     utils::MapErr,
 };
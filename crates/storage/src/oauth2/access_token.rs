@@ -10,7 +10,7 @@ use mas_data_model::{AccessToken, Clock, Session};
 use rand_core::RngCore;
 use ulid::Ulid;
 
-use crate::repository_impl;
+use crate::{oauth2::OAuth2SessionFilter, repository_impl};
 
 /// An [`OAuth2AccessTokenRepository`] helps interacting with [`AccessToken`]
 /// saved in the storage backend
@@ -91,6 +91,26 @@ pub trait OAuth2AccessTokenRepository: Send + Sync {
         access_token: AccessToken,
     ) -> Result<AccessToken, Self::Error>;
 
+    /// Revoke all the active access tokens of the sessions matching the
+    /// given filter
+    ///
+    /// Returns the number of access tokens that were revoked
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `filter`: The filter matching the sessions whose access tokens should
+    ///   be revoked
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn revoke_bulk(
+        &mut self,
+        clock: &dyn Clock,
+        filter: OAuth2SessionFilter<'_>,
+    ) -> Result<usize, Self::Error>;
+
     /// Mark the access token as used, to track when it was first used
     ///
     /// # Parameters
@@ -144,6 +164,12 @@ repository_impl!(OAuth2AccessTokenRepository:
         access_token: AccessToken,
     ) -> Result<AccessToken, Self::Error>;
 
+    async fn revoke_bulk(
+        &mut self,
+        clock: &dyn Clock,
+        filter: OAuth2SessionFilter<'_>,
+    ) -> Result<usize, Self::Error>;
+
     async fn mark_used(
         &mut self,
         clock: &dyn Clock,
@@ -41,6 +41,11 @@ pub trait OAuth2AuthorizationGrantRepository: Send + Sync {
     /// * `login_hint`: The `login_hint` the client sent, if set
     /// * `locale`: The locale the detected when the user asked for the
     ///   authorization grant
+    /// * `id_token_claims`: The claims the client asked to be included in the
+    ///   ID token, through the `claims` parameter
+    /// * `max_age`: The `max_age` the client sent, if set
+    /// * `acr_values`: The Authentication Context Class References the client
+    ///   asked for, through the `acr_values` parameter
     ///
     /// # Errors
     ///
@@ -60,6 +65,9 @@ pub trait OAuth2AuthorizationGrantRepository: Send + Sync {
         response_type_id_token: bool,
         login_hint: Option<String>,
         locale: Option<String>,
+        id_token_claims: Vec<String>,
+        max_age: Option<u32>,
+        acr_values: Vec<String>,
     ) -> Result<AuthorizationGrant, Self::Error>;
 
     /// Lookup an authorization grant by its ID
@@ -98,6 +106,8 @@ pub trait OAuth2AuthorizationGrantRepository: Send + Sync {
     ///
     /// * `clock`: The clock used to generate timestamps
     /// * `session`: The session that was created using this authorization grant
+    /// * `acr`: The Authentication Context Class Reference satisfied by the
+    ///   user when completing this authorization grant, if any
     /// * `authorization_grant`: The authorization grant to fulfill
     ///
     /// # Errors
@@ -107,6 +117,7 @@ pub trait OAuth2AuthorizationGrantRepository: Send + Sync {
         &mut self,
         clock: &dyn Clock,
         session: &Session,
+        acr: Option<String>,
         authorization_grant: AuthorizationGrant,
     ) -> Result<AuthorizationGrant, Self::Error>;
 
@@ -144,6 +155,9 @@ repository_impl!(OAuth2AuthorizationGrantRepository:
         response_type_id_token: bool,
         login_hint: Option<String>,
         locale: Option<String>,
+        id_token_claims: Vec<String>,
+        max_age: Option<u32>,
+        acr_values: Vec<String>,
     ) -> Result<AuthorizationGrant, Self::Error>;
 
     async fn lookup(&mut self, id: Ulid) -> Result<Option<AuthorizationGrant>, Self::Error>;
@@ -155,6 +169,7 @@ repository_impl!(OAuth2AuthorizationGrantRepository:
         &mut self,
         clock: &dyn Clock,
         session: &Session,
+        acr: Option<String>,
         authorization_grant: AuthorizationGrant,
     ) -> Result<AuthorizationGrant, Self::Error>;
 
@@ -7,10 +7,10 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use async_trait::async_trait;
-use mas_data_model::{Client, Clock};
+use mas_data_model::{Client, Clock, Consent, User};
 use mas_iana::{jose::JsonWebSignatureAlg, oauth::OAuthClientAuthenticationMethod};
 use mas_jose::jwk::PublicJsonWebKeySet;
-use oauth2_types::{oidc::ApplicationType, requests::GrantType};
+use oauth2_types::{oidc::ApplicationType, requests::GrantType, scope::Scope};
 use rand_core::RngCore;
 use ulid::Ulid;
 use url::Url;
@@ -62,6 +62,23 @@ pub trait OAuth2ClientRepository: Send + Sync {
         digest: &str,
     ) -> Result<Option<Client>, Self::Error>;
 
+    /// Find a dynamically registered OAuth client by its registration access
+    /// token
+    ///
+    /// Returns `None` if no client has this registration access token
+    ///
+    /// # Parameters
+    ///
+    /// * `registration_access_token`: The registration access token to look for
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_by_registration_access_token(
+        &mut self,
+        registration_access_token: &str,
+    ) -> Result<Option<Client>, Self::Error>;
+
     /// Load a batch of OAuth clients by their IDs
     ///
     /// Returns a map of client IDs to clients. If a client does not exist, it
@@ -103,12 +120,16 @@ pub trait OAuth2ClientRepository: Send + Sync {
     ///   token
     /// * `userinfo_signed_response_alg`: The algorithm used to sign the user
     ///   info. If none, the user info endpoint will not sign the response
+    /// * `authorization_signed_response_alg`: The algorithm used to sign the
+    ///   authorization responses, when using a JWT response mode
     /// * `token_endpoint_auth_method`: The authentication method used by this
     ///   client when calling the token endpoint
     /// * `token_endpoint_auth_signing_alg`: The algorithm used to sign the JWT
     ///   when using the `client_secret_jwt` or `private_key_jwt` authentication
     ///   methods
     /// * `initiate_login_uri`: The URI used to initiate a login, if given
+    /// * `allowed_scopes`: The scopes this client is allowed to request, if
+    ///   restricted
     ///
     /// # Errors
     ///
@@ -132,14 +153,55 @@ pub trait OAuth2ClientRepository: Send + Sync {
         jwks: Option<PublicJsonWebKeySet>,
         id_token_signed_response_alg: Option<JsonWebSignatureAlg>,
         userinfo_signed_response_alg: Option<JsonWebSignatureAlg>,
+        authorization_signed_response_alg: Option<JsonWebSignatureAlg>,
         token_endpoint_auth_method: Option<OAuthClientAuthenticationMethod>,
         token_endpoint_auth_signing_alg: Option<JsonWebSignatureAlg>,
         initiate_login_uri: Option<Url>,
+        allowed_scopes: Option<Scope>,
     ) -> Result<Client, Self::Error>;
 
+    /// Set the registration access token of a dynamically registered client,
+    /// replacing any previous one
+    ///
+    /// # Parameters
+    ///
+    /// * `client`: The client to set the registration access token on
+    /// * `registration_access_token`: The new registration access token
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails, or if the
+    /// client is not a dynamically registered client
+    async fn set_registration_access_token(
+        &mut self,
+        client: &Client,
+        registration_access_token: &str,
+    ) -> Result<(), Self::Error>;
+
+    /// Update the metadata of a dynamically registered client
+    ///
+    /// All the metadata fields of the given [`Client`] are saved, except for
+    /// the client secret and the token endpoint authentication method, which
+    /// can't be changed.
+    ///
+    /// Returns the updated client. The consents users remembered for this
+    /// client are forgotten.
+    ///
+    /// # Parameters
+    ///
+    /// * `client`: The client with its updated metadata
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails, or if the
+    /// client is not a dynamically registered client
+    async fn update_metadata(&mut self, client: Client) -> Result<Client, Self::Error>;
+
     /// Add or replace a static client
     ///
-    /// Returns the client that was added or replaced
+    /// Returns the client that was added or replaced. If the name, redirect URIs
+    /// or allowed scopes of an existing client changed, the consents users
+    /// remembered for it are forgotten.
     ///
     /// # Parameters
     ///
@@ -149,6 +211,10 @@ pub trait OAuth2ClientRepository: Send + Sync {
     /// * `jwks`: The client JWKS, if any
     /// * `jwks_uri`: The client JWKS URI, if any
     /// * `redirect_uris`: The list of redirect URIs used by this client
+    /// * `always_prompt_consent`: Whether users should always be asked for
+    ///   consent, even if they previously chose to remember their decision
+    /// * `allowed_scopes`: The scopes this client is allowed to request, if
+    ///   restricted
     ///
     /// # Errors
     ///
@@ -163,6 +229,8 @@ pub trait OAuth2ClientRepository: Send + Sync {
         jwks: Option<PublicJsonWebKeySet>,
         jwks_uri: Option<Url>,
         redirect_uris: Vec<Url>,
+        always_prompt_consent: bool,
+        allowed_scopes: Option<Scope>,
     ) -> Result<Client, Self::Error>;
 
     /// List all static clients
@@ -172,6 +240,79 @@ pub trait OAuth2ClientRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn all_static(&mut self) -> Result<Vec<Client>, Self::Error>;
 
+    /// Get the scopes a user remembered consenting to for a client
+    ///
+    /// Returns an empty scope if the user never consented to this client
+    ///
+    /// # Parameters
+    ///
+    /// * `client`: The client to get the consent for
+    /// * `user`: The user who gave the consent
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn get_consent_for_user(
+        &mut self,
+        client: &Client,
+        user: &User,
+    ) -> Result<Scope, Self::Error>;
+
+    /// Remember that a user consented to a set of scopes for a client
+    ///
+    /// Scopes the user already consented to are refreshed. Device scopes are
+    /// not remembered.
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `client`: The client the consent is given to
+    /// * `user`: The user giving the consent
+    /// * `scope`: The scopes the user consented to
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn give_consent_for_user(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        client: &Client,
+        user: &User,
+        scope: &Scope,
+    ) -> Result<(), Self::Error>;
+
+    /// List the consents a user remembered, one per client, most recently
+    /// updated first
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The user who gave the consents
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list_consents_for_user(&mut self, user: &User) -> Result<Vec<Consent>, Self::Error>;
+
+    /// Forget all the scopes a user consented to for a client
+    ///
+    /// Returns the number of scopes which were forgotten
+    ///
+    /// # Parameters
+    ///
+    /// * `client`: The client to revoke the consent for
+    /// * `user`: The user who gave the consent
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn revoke_consent_for_user(
+        &mut self,
+        client: &Client,
+        user: &User,
+    ) -> Result<usize, Self::Error>;
+
     /// Delete a client
     ///
     /// # Parameters
@@ -207,6 +348,11 @@ repository_impl!(OAuth2ClientRepository:
         digest: &str,
     ) -> Result<Option<Client>, Self::Error>;
 
+    async fn find_by_registration_access_token(
+        &mut self,
+        registration_access_token: &str,
+    ) -> Result<Option<Client>, Self::Error>;
+
     async fn load_batch(
         &mut self,
         ids: BTreeSet<Ulid>,
@@ -230,11 +376,21 @@ repository_impl!(OAuth2ClientRepository:
         jwks: Option<PublicJsonWebKeySet>,
         id_token_signed_response_alg: Option<JsonWebSignatureAlg>,
         userinfo_signed_response_alg: Option<JsonWebSignatureAlg>,
+        authorization_signed_response_alg: Option<JsonWebSignatureAlg>,
         token_endpoint_auth_method: Option<OAuthClientAuthenticationMethod>,
         token_endpoint_auth_signing_alg: Option<JsonWebSignatureAlg>,
         initiate_login_uri: Option<Url>,
+        allowed_scopes: Option<Scope>,
     ) -> Result<Client, Self::Error>;
 
+    async fn set_registration_access_token(
+        &mut self,
+        client: &Client,
+        registration_access_token: &str,
+    ) -> Result<(), Self::Error>;
+
+    async fn update_metadata(&mut self, client: Client) -> Result<Client, Self::Error>;
+
     async fn upsert_static(
         &mut self,
         client_id: Ulid,
@@ -244,10 +400,35 @@ repository_impl!(OAuth2ClientRepository:
         jwks: Option<PublicJsonWebKeySet>,
         jwks_uri: Option<Url>,
         redirect_uris: Vec<Url>,
+        always_prompt_consent: bool,
+        allowed_scopes: Option<Scope>,
     ) -> Result<Client, Self::Error>;
 
     async fn all_static(&mut self) -> Result<Vec<Client>, Self::Error>;
 
+    async fn get_consent_for_user(
+        &mut self,
+        client: &Client,
+        user: &User,
+    ) -> Result<Scope, Self::Error>;
+
+    async fn give_consent_for_user(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        client: &Client,
+        user: &User,
+        scope: &Scope,
+    ) -> Result<(), Self::Error>;
+
+    async fn list_consents_for_user(&mut self, user: &User) -> Result<Vec<Consent>, Self::Error>;
+
+    async fn revoke_consent_for_user(
+        &mut self,
+        client: &Client,
+        user: &User,
+    ) -> Result<usize, Self::Error>;
+
     async fn delete(&mut self, client: Client) -> Result<(), Self::Error>;
 
     async fn delete_by_id(&mut self, id: Ulid) -> Result<(), Self::Error>;
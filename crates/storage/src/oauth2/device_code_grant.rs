@@ -7,13 +7,105 @@
 use std::net::IpAddr;
 
 use async_trait::async_trait;
-use chrono::Duration;
-use mas_data_model::{BrowserSession, Client, Clock, DeviceCodeGrant, Session};
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{BrowserSession, Client, Clock, DeviceCodeGrant, Session, User};
 use oauth2_types::scope::Scope;
 use rand_core::RngCore;
 use ulid::Ulid;
 
-use crate::repository_impl;
+use crate::{Pagination, pagination::Page, repository_impl};
+
+/// The state of a [`DeviceCodeGrant`] to filter on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OAuth2DeviceCodeGrantState {
+    /// Neither fulfilled nor rejected yet
+    Pending,
+
+    /// Fulfilled by a user, but not yet exchanged by the device
+    Fulfilled,
+
+    /// Rejected by a user
+    Rejected,
+
+    /// Exchanged for an OAuth 2.0 session by the device
+    Exchanged,
+}
+
+/// A filter to apply when listing [`DeviceCodeGrant`]s
+#[derive(Debug, Clone, Copy)]
+pub struct OAuth2DeviceCodeGrantFilter<'a> {
+    now: DateTime<Utc>,
+    user: Option<&'a User>,
+    state: Option<OAuth2DeviceCodeGrantState>,
+    is_expired: Option<bool>,
+}
+
+impl<'a> OAuth2DeviceCodeGrantFilter<'a> {
+    /// Create a new empty filter
+    #[must_use]
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now,
+            user: None,
+            state: None,
+            is_expired: None,
+        }
+    }
+
+    /// List device code grants fulfilled or rejected by a specific user
+    ///
+    /// Pending grants are not yet associated with a user, so they never match
+    /// this filter.
+    #[must_use]
+    pub fn for_user(mut self, user: &'a User) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    /// Get the user filter
+    ///
+    /// Returns [`None`] if no user filter was set
+    #[must_use]
+    pub fn user(&self) -> Option<&'a User> {
+        self.user
+    }
+
+    /// Filter by state
+    #[must_use]
+    pub fn with_state(mut self, state: OAuth2DeviceCodeGrantState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Get the state filter
+    ///
+    /// Returns [`None`] if no state filter was set
+    #[must_use]
+    pub fn state(&self) -> Option<OAuth2DeviceCodeGrantState> {
+        self.state
+    }
+
+    /// Filter by expired status
+    #[must_use]
+    pub fn with_expired(mut self, is_expired: bool) -> Self {
+        self.is_expired = Some(is_expired);
+        self
+    }
+
+    /// Get the expired status filter
+    ///
+    /// Returns [`None`] if no expired status filter was set
+    #[must_use]
+    pub fn is_expired(&self) -> Option<bool> {
+        self.is_expired
+    }
+
+    /// Get the current time for this filter evaluation
+    #[must_use]
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+}
 
 /// Parameters used to create a new [`DeviceCodeGrant`]
 pub struct OAuth2DeviceCodeGrantParams<'a> {
@@ -183,6 +275,36 @@ pub trait OAuth2DeviceCodeGrantRepository: Send + Sync {
         device_code_grant: DeviceCodeGrant,
         session: &Session,
     ) -> Result<DeviceCodeGrant, Self::Error>;
+
+    /// List [`DeviceCodeGrant`]s matching the given filter, with pagination
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter to apply
+    /// * `pagination`: The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list(
+        &mut self,
+        filter: OAuth2DeviceCodeGrantFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<DeviceCodeGrant>, Self::Error>;
+
+    /// Count the [`DeviceCodeGrant`]s matching the given filter
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter to apply
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count(
+        &mut self,
+        filter: OAuth2DeviceCodeGrantFilter<'_>,
+    ) -> Result<usize, Self::Error>;
 }
 
 repository_impl!(OAuth2DeviceCodeGrantRepository:
@@ -225,4 +347,13 @@ repository_impl!(OAuth2DeviceCodeGrantRepository:
         device_code_grant: DeviceCodeGrant,
         session: &Session,
     ) -> Result<DeviceCodeGrant, Self::Error>;
+
+    async fn list(
+        &mut self,
+        filter: OAuth2DeviceCodeGrantFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<DeviceCodeGrant>, Self::Error>;
+
+    async fn count(&mut self, filter: OAuth2DeviceCodeGrantFilter<'_>)
+    -> Result<usize, Self::Error>;
 );
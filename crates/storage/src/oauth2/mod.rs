@@ -10,6 +10,7 @@ mod access_token;
 mod authorization_grant;
 mod client;
 mod device_code_grant;
+mod pushed_authorization_request;
 mod refresh_token;
 mod session;
 
@@ -17,7 +18,11 @@ pub use self::{
     access_token::OAuth2AccessTokenRepository,
     authorization_grant::OAuth2AuthorizationGrantRepository,
     client::OAuth2ClientRepository,
-    device_code_grant::{OAuth2DeviceCodeGrantParams, OAuth2DeviceCodeGrantRepository},
+    device_code_grant::{
+        OAuth2DeviceCodeGrantFilter, OAuth2DeviceCodeGrantParams, OAuth2DeviceCodeGrantRepository,
+        OAuth2DeviceCodeGrantState,
+    },
+    pushed_authorization_request::OAuth2PushedAuthorizationRequestRepository,
     refresh_token::OAuth2RefreshTokenRepository,
     session::{OAuth2SessionFilter, OAuth2SessionRepository},
 };
@@ -0,0 +1,126 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use async_trait::async_trait;
+use chrono::Duration;
+use mas_data_model::{Client, Clock, PushedAuthorizationRequest};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::repository_impl;
+
+/// An [`OAuth2PushedAuthorizationRequestRepository`] helps interacting with
+/// [`PushedAuthorizationRequest`] saved in the storage backend
+#[async_trait]
+pub trait OAuth2PushedAuthorizationRequestRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Save a new pushed authorization request
+    ///
+    /// Returns the newly created [`PushedAuthorizationRequest`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `client`: The client which pushed the request
+    /// * `parameters`: The form-encoded authorization request parameters
+    /// * `expires_in`: How long the request can be used for
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        client: &Client,
+        parameters: String,
+        expires_in: Duration,
+    ) -> Result<PushedAuthorizationRequest, Self::Error>;
+
+    /// Lookup a pushed authorization request by its ID
+    ///
+    /// Returns `None` if no [`PushedAuthorizationRequest`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the pushed authorization request to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid)
+    -> Result<Option<PushedAuthorizationRequest>, Self::Error>;
+
+    /// Mark a pushed authorization request as consumed.
+    ///
+    /// This is done atomically: if two transactions try to consume the same
+    /// request concurrently, only one of them gets it back, the other one
+    /// gets `None`.
+    ///
+    /// Returns the consumed [`PushedAuthorizationRequest`], or `None` if it
+    /// was already consumed
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `pushed_authorization_request`: The request to consume
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn consume(
+        &mut self,
+        clock: &dyn Clock,
+        pushed_authorization_request: PushedAuthorizationRequest,
+    ) -> Result<Option<PushedAuthorizationRequest>, Self::Error>;
+
+    /// Delete the [`PushedAuthorizationRequest`]s which expired more than
+    /// `retention` ago, whether they were consumed or not
+    ///
+    /// Returns the number of requests that were deleted
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to get the current time
+    /// * `retention`: How long expired requests are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn cleanup_expired(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(OAuth2PushedAuthorizationRequestRepository:
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        client: &Client,
+        parameters: String,
+        expires_in: Duration,
+    ) -> Result<PushedAuthorizationRequest, Self::Error>;
+
+    async fn lookup(&mut self, id: Ulid)
+    -> Result<Option<PushedAuthorizationRequest>, Self::Error>;
+
+    async fn consume(
+        &mut self,
+        clock: &dyn Clock,
+        pushed_authorization_request: PushedAuthorizationRequest,
+    ) -> Result<Option<PushedAuthorizationRequest>, Self::Error>;
+
+    async fn cleanup_expired(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
+);
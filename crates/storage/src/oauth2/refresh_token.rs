@@ -9,7 +9,7 @@ use mas_data_model::{AccessToken, Clock, RefreshToken, Session};
 use rand_core::RngCore;
 use ulid::Ulid;
 
-use crate::repository_impl;
+use crate::{oauth2::OAuth2SessionFilter, repository_impl};
 
 /// An [`OAuth2RefreshTokenRepository`] helps interacting with [`RefreshToken`]
 /// saved in the storage backend
@@ -111,6 +111,26 @@ pub trait OAuth2RefreshTokenRepository: Send + Sync {
         clock: &dyn Clock,
         refresh_token: RefreshToken,
     ) -> Result<RefreshToken, Self::Error>;
+
+    /// Revoke all the active refresh tokens of the sessions matching the
+    /// given filter
+    ///
+    /// Returns the number of refresh tokens that were revoked
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `filter`: The filter matching the sessions whose refresh tokens should
+    ///   be revoked
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn revoke_bulk(
+        &mut self,
+        clock: &dyn Clock,
+        filter: OAuth2SessionFilter<'_>,
+    ) -> Result<usize, Self::Error>;
 }
 
 repository_impl!(OAuth2RefreshTokenRepository:
@@ -142,4 +162,10 @@ repository_impl!(OAuth2RefreshTokenRepository:
         clock: &dyn Clock,
         refresh_token: RefreshToken,
     ) -> Result<RefreshToken, Self::Error>;
+
+    async fn revoke_bulk(
+        &mut self,
+        clock: &dyn Clock,
+        filter: OAuth2SessionFilter<'_>,
+    ) -> Result<usize, Self::Error>;
 );
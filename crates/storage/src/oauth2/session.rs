@@ -8,7 +8,8 @@ use std::net::IpAddr;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use mas_data_model::{BrowserSession, Client, Clock, Device, Session, User};
+use ipnetwork::IpNetwork;
+use mas_data_model::{AuthenticatedBy, BrowserSession, Client, Clock, Device, Session, User};
 use oauth2_types::scope::Scope;
 use rand_core::RngCore;
 use ulid::Ulid;
@@ -57,6 +58,10 @@ pub struct OAuth2SessionFilter<'a> {
     scope: Option<&'a Scope>,
     last_active_before: Option<DateTime<Utc>>,
     last_active_after: Option<DateTime<Utc>>,
+    finished_after: Option<DateTime<Utc>>,
+    created_after: Option<DateTime<Utc>>,
+    last_active_ip_in: Option<IpNetwork>,
+    excluded_id: Option<Ulid>,
 }
 
 impl<'a> OAuth2SessionFilter<'a> {
@@ -203,6 +208,66 @@ impl<'a> OAuth2SessionFilter<'a> {
         self.last_active_after
     }
 
+    /// Only return sessions which finished after the given time
+    #[must_use]
+    pub fn with_finished_after(mut self, finished_after: DateTime<Utc>) -> Self {
+        self.finished_after = Some(finished_after);
+        self
+    }
+
+    /// Get the finished after filter
+    ///
+    /// Returns [`None`] if no finished after filter was set
+    #[must_use]
+    pub fn finished_after(&self) -> Option<DateTime<Utc>> {
+        self.finished_after
+    }
+
+    /// Only return sessions created after the given time
+    #[must_use]
+    pub fn with_created_after(mut self, created_after: DateTime<Utc>) -> Self {
+        self.created_after = Some(created_after);
+        self
+    }
+
+    /// Get the created after filter
+    ///
+    /// Returns [`None`] if no created after filter was set
+    #[must_use]
+    pub fn created_after(&self) -> Option<DateTime<Utc>> {
+        self.created_after
+    }
+
+    /// Only return sessions last seen from an IP address in the given network
+    #[must_use]
+    pub fn with_last_active_ip_in(mut self, network: IpNetwork) -> Self {
+        self.last_active_ip_in = Some(network);
+        self
+    }
+
+    /// Get the network the last active IP address must be in
+    ///
+    /// Returns [`None`] if no IP filter was set
+    #[must_use]
+    pub fn last_active_ip_in(&self) -> Option<IpNetwork> {
+        self.last_active_ip_in
+    }
+
+    /// Exclude the session with the given ID
+    #[must_use]
+    pub fn excluding(mut self, id: Ulid) -> Self {
+        self.excluded_id = Some(id);
+        self
+    }
+
+    /// Get the ID of the session excluded by this filter
+    ///
+    /// Returns [`None`] if no session is excluded
+    #[must_use]
+    pub fn excluded_id(&self) -> Option<Ulid> {
+        self.excluded_id
+    }
+
     /// Only return active sessions
     #[must_use]
     pub fn active_only(mut self) -> Self {
@@ -396,6 +461,45 @@ pub trait OAuth2SessionRepository: Send + Sync {
         filter: OAuth2SessionFilter<'_>,
     ) -> Result<usize, Self::Error>;
 
+    /// Move all the [`Session`] matching the given filter to another user
+    ///
+    /// Returns the number of sessions affected
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    /// * `user`: The user which should now own the sessions
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn transfer_bulk(
+        &mut self,
+        filter: OAuth2SessionFilter<'_>,
+        user: &User,
+    ) -> Result<usize, Self::Error>;
+
+    /// Permanently delete the finished [`Session`]s of a user which finished
+    /// before the given date, along with their access and refresh tokens and
+    /// the authorization grants which created them
+    ///
+    /// Returns the number of sessions deleted
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The user whose sessions should be deleted
+    /// * `finished_before`: Only sessions finished strictly before this date
+    ///   are deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn delete_finished_before(
+        &mut self,
+        user: &User,
+        finished_before: DateTime<Utc>,
+    ) -> Result<usize, Self::Error>;
+
     /// List [`Session`]s matching the given filter and pagination parameters
     ///
     /// # Parameters
@@ -462,6 +566,24 @@ pub trait OAuth2SessionRepository: Send + Sync {
         human_name: Option<String>,
     ) -> Result<Session, Self::Error>;
 
+    /// Record how the user authenticated when a [`Session`] was established
+    ///
+    /// Returns the updated session
+    ///
+    /// # Parameters
+    ///
+    /// * `session`: The [`Session`] to update
+    /// * `authenticated_by`: How the user authenticated
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_authenticated_by(
+        &mut self,
+        session: Session,
+        authenticated_by: AuthenticatedBy,
+    ) -> Result<Session, Self::Error>;
+
     /// Lookup an [`Session`] by its browser session id
     ///
     /// Returns `None` if no [`Session`] was found
@@ -474,6 +596,23 @@ pub trait OAuth2SessionRepository: Send + Sync {
     ///
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn find_by_browser_session(&mut self, id: Ulid) -> Result<Option<Session>, Self::Error>;
+
+    /// Replace the scope of a [`Session`]
+    ///
+    /// This is used to give a new device ID to a session, by swapping the
+    /// device scope tokens.
+    ///
+    /// Returns the updated session
+    ///
+    /// # Parameters
+    ///
+    /// * `session`: The [`Session`] to update
+    /// * `scope`: The new scope of the session
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_scope(&mut self, session: Session, scope: Scope) -> Result<Session, Self::Error>;
 }
 
 repository_impl!(OAuth2SessionRepository:
@@ -515,6 +654,18 @@ repository_impl!(OAuth2SessionRepository:
         filter: OAuth2SessionFilter<'_>,
     ) -> Result<usize, Self::Error>;
 
+    async fn transfer_bulk(
+        &mut self,
+        filter: OAuth2SessionFilter<'_>,
+        user: &User,
+    ) -> Result<usize, Self::Error>;
+
+    async fn delete_finished_before(
+        &mut self,
+        user: &User,
+        finished_before: DateTime<Utc>,
+    ) -> Result<usize, Self::Error>;
+
     async fn list(
         &mut self,
         filter: OAuth2SessionFilter<'_>,
@@ -540,5 +691,13 @@ repository_impl!(OAuth2SessionRepository:
         human_name: Option<String>,
     ) -> Result<Session, Self::Error>;
 
+    async fn set_authenticated_by(
+        &mut self,
+        session: Session,
+        authenticated_by: AuthenticatedBy,
+    ) -> Result<Session, Self::Error>;
+
     async fn find_by_browser_session(&mut self, id: Ulid) -> Result<Option<Session>, Self::Error>;
+
+    async fn set_scope(&mut self, session: Session, scope: Scope) -> Result<Session, Self::Error>;
 );
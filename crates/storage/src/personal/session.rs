@@ -203,6 +203,7 @@ pub struct PersonalSessionFilter<'a> {
     expires_before: Option<DateTime<Utc>>,
     expires_after: Option<DateTime<Utc>>,
     expires: Option<bool>,
+    excluded_id: Option<Ulid>,
 }
 
 /// Filter for what state a personal session is in.
@@ -298,6 +299,21 @@ impl<'a> PersonalSessionFilter<'a> {
         self.last_active_after
     }
 
+    /// Exclude the session with the given ID
+    #[must_use]
+    pub fn excluding(mut self, id: Ulid) -> Self {
+        self.excluded_id = Some(id);
+        self
+    }
+
+    /// Get the ID of the session excluded by this filter
+    ///
+    /// Returns [`None`] if no session is excluded
+    #[must_use]
+    pub fn excluded_id(&self) -> Option<Ulid> {
+        self.excluded_id
+    }
+
     /// Only return active sessions
     #[must_use]
     pub fn active_only(mut self) -> Self {
@@ -0,0 +1,118 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Repositories to interact with the reports of the provisioning
+//! reconciliations saved in the storage backend.
+
+use async_trait::async_trait;
+use mas_data_model::{Clock, ProvisioningDiscrepancy, ProvisioningReport};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::repository_impl;
+
+/// A [`ProvisioningReportRepository`] helps interacting with the reports of
+/// the provisioning reconciliations saved in the storage backend.
+#[async_trait]
+pub trait ProvisioningReportRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup a report by its ID
+    ///
+    /// Returns `None` if no report was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the report to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<ProvisioningReport>, Self::Error>;
+
+    /// Create a new, empty report
+    ///
+    /// Returns the newly created report
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate the timestamps
+    /// * `fix`: Whether the reconciliation should fix the discrepancies it
+    ///   finds
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        fix: bool,
+    ) -> Result<ProvisioningReport, Self::Error>;
+
+    /// Add the results of a batch of the reconciliation to a report
+    ///
+    /// Returns the updated report
+    ///
+    /// # Parameters
+    ///
+    /// * `report`: The report to update
+    /// * `users_checked`: How many users were checked in this batch
+    /// * `discrepancies`: The discrepancies which were found in this batch
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add_progress(
+        &mut self,
+        report: ProvisioningReport,
+        users_checked: u64,
+        discrepancies: Vec<ProvisioningDiscrepancy>,
+    ) -> Result<ProvisioningReport, Self::Error>;
+
+    /// Mark a report as completed
+    ///
+    /// Returns the updated report
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate the timestamps
+    /// * `report`: The report to complete
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn complete(
+        &mut self,
+        clock: &dyn Clock,
+        report: ProvisioningReport,
+    ) -> Result<ProvisioningReport, Self::Error>;
+}
+
+repository_impl!(ProvisioningReportRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<ProvisioningReport>, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        fix: bool,
+    ) -> Result<ProvisioningReport, Self::Error>;
+
+    async fn add_progress(
+        &mut self,
+        report: ProvisioningReport,
+        users_checked: u64,
+        discrepancies: Vec<ProvisioningDiscrepancy>,
+    ) -> Result<ProvisioningReport, Self::Error>;
+
+    async fn complete(
+        &mut self,
+        clock: &dyn Clock,
+        report: ProvisioningReport,
+    ) -> Result<ProvisioningReport, Self::Error>;
+);
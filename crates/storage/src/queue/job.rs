@@ -7,7 +7,7 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
-use mas_data_model::Clock;
+use mas_data_model::{Clock, User};
 use opentelemetry::trace::TraceContextExt;
 use rand_core::RngCore;
 use serde::{Deserialize, Serialize};
@@ -15,7 +15,7 @@ use tracing_opentelemetry::OpenTelemetrySpanExt;
 use ulid::Ulid;
 
 use super::Worker;
-use crate::repository_impl;
+use crate::{Page, Pagination, repository_impl};
 
 /// Represents a job in the job queue
 pub struct Job {
@@ -35,6 +35,139 @@ pub struct Job {
     pub attempt: usize,
 }
 
+/// The status of a job in the job queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    /// The job is waiting to be picked up by a worker
+    Available,
+
+    /// The job is being processed by a worker
+    Running,
+
+    /// The job completed successfully
+    Completed,
+
+    /// The worker processing the job was lost
+    Lost,
+
+    /// The job failed
+    Failed,
+
+    /// The job is scheduled to run at a later date
+    Scheduled,
+}
+
+impl JobStatus {
+    /// Get the status as a string, as stored in the database
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Available => "available",
+            Self::Running => "running",
+            Self::Completed => "completed",
+            Self::Lost => "lost",
+            Self::Failed => "failed",
+            Self::Scheduled => "scheduled",
+        }
+    }
+}
+
+impl std::str::FromStr for JobStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "available" => Ok(Self::Available),
+            "running" => Ok(Self::Running),
+            "completed" => Ok(Self::Completed),
+            "lost" => Ok(Self::Lost),
+            "failed" => Ok(Self::Failed),
+            "scheduled" => Ok(Self::Scheduled),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A job in the job queue, with the state of its processing
+#[derive(Debug, Clone)]
+pub struct QueueJob {
+    /// The ID of the job
+    pub id: Ulid,
+
+    /// The queue on which the job was placed
+    pub queue_name: String,
+
+    /// The payload of the job
+    pub payload: serde_json::Value,
+
+    /// The ID of the user this job is about, if any
+    pub user_id: Option<Ulid>,
+
+    /// The status of the job
+    pub status: JobStatus,
+
+    /// Which attempt it is, starting at 0
+    pub attempt: usize,
+
+    /// When the job was created
+    pub created_at: DateTime<Utc>,
+
+    /// When the job is scheduled to run, if it was scheduled for later
+    pub scheduled_at: Option<DateTime<Utc>>,
+
+    /// When a worker started processing the job
+    pub started_at: Option<DateTime<Utc>>,
+
+    /// When the job completed
+    pub completed_at: Option<DateTime<Utc>>,
+
+    /// When the job failed
+    pub failed_at: Option<DateTime<Utc>>,
+
+    /// Why the job failed
+    pub failed_reason: Option<String>,
+
+    /// The ID of the job which retries this one, if it was retried
+    pub next_attempt_id: Option<Ulid>,
+}
+
+impl QueueJob {
+    /// Whether the job failed and was not retried yet
+    #[must_use]
+    pub fn can_be_retried(&self) -> bool {
+        self.status == JobStatus::Failed && self.next_attempt_id.is_none()
+    }
+}
+
+/// Filter parameters for listing jobs in the job queue
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QueueJobFilter<'a> {
+    user: Option<&'a User>,
+}
+
+impl<'a> QueueJobFilter<'a> {
+    /// Create a new empty filter
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List jobs about a specific user
+    #[must_use]
+    pub fn for_user(mut self, user: &'a User) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    /// Get the user filter
+    ///
+    /// Returns [`None`] if no user filter was set
+    #[must_use]
+    pub fn user(&self) -> Option<&'a User> {
+        self.user
+    }
+}
+
 /// Metadata stored alongside the job
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct JobMetadata {
@@ -76,6 +209,12 @@ impl JobMetadata {
 pub trait InsertableJob: Serialize + Send {
     /// The name of the queue this job belongs to
     const QUEUE_NAME: &'static str;
+
+    /// The ID of the user this job is about, if any. This is saved alongside
+    /// the job, so that the jobs of a user can be listed.
+    fn user_id(&self) -> Option<Ulid> {
+        None
+    }
 }
 
 /// A [`QueueJobRepository`] is used to schedule jobs to be executed by a
@@ -94,6 +233,7 @@ pub trait QueueJobRepository: Send + Sync {
     /// * `queue_name` - The name of the queue to schedule the job on
     /// * `payload` - The payload of the job
     /// * `metadata` - Arbitrary metadata about the job scheduled immediately.
+    /// * `user_id` - The ID of the user this job is about, if any
     ///
     /// # Errors
     ///
@@ -105,6 +245,7 @@ pub trait QueueJobRepository: Send + Sync {
         queue_name: &str,
         payload: serde_json::Value,
         metadata: serde_json::Value,
+        user_id: Option<Ulid>,
     ) -> Result<(), Self::Error>;
 
     /// Schedule a job to be executed at a later date by a worker.
@@ -116,6 +257,7 @@ pub trait QueueJobRepository: Send + Sync {
     /// * `queue_name` - The name of the queue to schedule the job on
     /// * `payload` - The payload of the job
     /// * `metadata` - Arbitrary metadata about the job scheduled immediately.
+    /// * `user_id` - The ID of the user this job is about, if any
     /// * `scheduled_at` - The date and time to schedule the job for
     /// * `schedule_name` - The name of the recurring schedule which scheduled
     ///   this job
@@ -131,6 +273,7 @@ pub trait QueueJobRepository: Send + Sync {
         queue_name: &str,
         payload: serde_json::Value,
         metadata: serde_json::Value,
+        user_id: Option<Ulid>,
         scheduled_at: DateTime<Utc>,
         schedule_name: Option<&str>,
     ) -> Result<(), Self::Error>;
@@ -213,6 +356,46 @@ pub trait QueueJobRepository: Send + Sync {
     ///
     /// Returns an error if the underlying repository fails.
     async fn schedule_available_jobs(&mut self, clock: &dyn Clock) -> Result<usize, Self::Error>;
+
+    /// Lookup a job by its ID
+    ///
+    /// Returns `None` if no job was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id` - The ID of the job to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying repository fails.
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<QueueJob>, Self::Error>;
+
+    /// List jobs matching the given filter, most recent last
+    ///
+    /// # Parameters
+    ///
+    /// * `filter` - The filter to apply
+    /// * `pagination` - The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying repository fails.
+    async fn list(
+        &mut self,
+        filter: QueueJobFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<QueueJob>, Self::Error>;
+
+    /// Count the jobs matching the given filter
+    ///
+    /// # Parameters
+    ///
+    /// * `filter` - The filter to apply
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying repository fails.
+    async fn count(&mut self, filter: QueueJobFilter<'_>) -> Result<usize, Self::Error>;
 }
 
 repository_impl!(QueueJobRepository:
@@ -223,6 +406,7 @@ repository_impl!(QueueJobRepository:
         queue_name: &str,
         payload: serde_json::Value,
         metadata: serde_json::Value,
+        user_id: Option<Ulid>,
     ) -> Result<(), Self::Error>;
 
     async fn schedule_later(
@@ -232,6 +416,7 @@ repository_impl!(QueueJobRepository:
         queue_name: &str,
         payload: serde_json::Value,
         metadata: serde_json::Value,
+        user_id: Option<Ulid>,
         scheduled_at: DateTime<Utc>,
         schedule_name: Option<&str>,
     ) -> Result<(), Self::Error>;
@@ -261,6 +446,16 @@ repository_impl!(QueueJobRepository:
     ) -> Result<(), Self::Error>;
 
     async fn schedule_available_jobs(&mut self, clock: &dyn Clock) -> Result<usize, Self::Error>;
+
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<QueueJob>, Self::Error>;
+
+    async fn list(
+        &mut self,
+        filter: QueueJobFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<QueueJob>, Self::Error>;
+
+    async fn count(&mut self, filter: QueueJobFilter<'_>) -> Result<usize, Self::Error>;
 );
 
 /// Extension trait for [`QueueJobRepository`] to help adding a job to the queue
@@ -334,8 +529,9 @@ where
         let metadata = JobMetadata::new(span_context);
         let metadata = serde_json::to_value(metadata).expect("Could not serialize metadata");
 
+        let user_id = job.user_id();
         let payload = serde_json::to_value(job).expect("Could not serialize job");
-        self.schedule(rng, clock, J::QUEUE_NAME, payload, metadata)
+        self.schedule(rng, clock, J::QUEUE_NAME, payload, metadata, user_id)
             .await
     }
 
@@ -362,6 +558,7 @@ where
         let metadata = JobMetadata::new(span_context);
         let metadata = serde_json::to_value(metadata).expect("Could not serialize metadata");
 
+        let user_id = job.user_id();
         let payload = serde_json::to_value(job).expect("Could not serialize job");
         self.schedule_later(
             rng,
@@ -369,6 +566,7 @@ where
             J::QUEUE_NAME,
             payload,
             metadata,
+            user_id,
             scheduled_at,
             None,
         )
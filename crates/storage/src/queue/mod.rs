@@ -11,7 +11,10 @@ mod tasks;
 mod worker;
 
 pub use self::{
-    job::{InsertableJob, Job, JobMetadata, QueueJobRepository, QueueJobRepositoryExt},
+    job::{
+        InsertableJob, Job, JobMetadata, JobStatus, QueueJob, QueueJobFilter, QueueJobRepository,
+        QueueJobRepositoryExt,
+    },
     schedule::{QueueScheduleRepository, ScheduleStatus},
     tasks::*,
     worker::{QueueWorkerRepository, Worker},
@@ -5,11 +5,12 @@
 
 use chrono::{DateTime, Utc};
 use mas_data_model::{
-    BrowserSession, CompatSession, Device, Session, User, UserEmailAuthentication,
-    UserRecoverySession,
+    AuditEvent, BrowserSession, CompatSession, Device, ProvisioningReport, Session, User,
+    UserEmail, UserEmailAuthentication, UserEmailChange, UserRecoverySession,
 };
 use serde::{Deserialize, Serialize};
 use ulid::Ulid;
+use url::Url;
 
 use super::InsertableJob;
 use crate::{Page, Pagination};
@@ -40,6 +41,8 @@ impl InsertableJob for VerifyEmailJob {
 pub struct SendEmailAuthenticationCodeJob {
     user_email_authentication_id: Ulid,
     language: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<Ulid>,
 }
 
 impl SendEmailAuthenticationCodeJob {
@@ -49,9 +52,18 @@ impl SendEmailAuthenticationCodeJob {
         Self {
             user_email_authentication_id: user_email_authentication.id,
             language,
+            user_id: None,
         }
     }
 
+    /// Set the user the code is sent to, if the email is added to an existing
+    /// account
+    #[must_use]
+    pub fn for_user(mut self, user: &User) -> Self {
+        self.user_id = Some(user.id);
+        self
+    }
+
     /// The language to use for the email.
     #[must_use]
     pub fn language(&self) -> &str {
@@ -67,6 +79,52 @@ impl SendEmailAuthenticationCodeJob {
 
 impl InsertableJob for SendEmailAuthenticationCodeJob {
     const QUEUE_NAME: &'static str = "send-email-authentication-code";
+
+    fn user_id(&self) -> Option<Ulid> {
+        self.user_id
+    }
+}
+
+/// A job to ask a user to confirm a change of their email address, from the
+/// address they already had.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SendEmailChangeConfirmationJob {
+    user_email_change_id: Ulid,
+    language: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<Ulid>,
+}
+
+impl SendEmailChangeConfirmationJob {
+    /// Create a new job to send the confirmation email of an email change.
+    #[must_use]
+    pub fn new(user_email_change: &UserEmailChange, language: String) -> Self {
+        Self {
+            user_email_change_id: user_email_change.id,
+            language,
+            user_id: Some(user_email_change.user_id),
+        }
+    }
+
+    /// The language to use for the email.
+    #[must_use]
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The ID of the email change to send the confirmation email for.
+    #[must_use]
+    pub fn user_email_change_id(&self) -> Ulid {
+        self.user_email_change_id
+    }
+}
+
+impl InsertableJob for SendEmailChangeConfirmationJob {
+    const QUEUE_NAME: &'static str = "send-email-change-confirmation";
+
+    fn user_id(&self) -> Option<Ulid> {
+        self.user_id
+    }
 }
 
 /// A job to provision the user on the homeserver.
@@ -117,6 +175,10 @@ impl ProvisionUserJob {
 
 impl InsertableJob for ProvisionUserJob {
     const QUEUE_NAME: &'static str = "provision-user";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
 }
 
 /// A job to provision a device for a user on the homeserver.
@@ -145,6 +207,10 @@ impl ProvisionDeviceJob {
 
 impl InsertableJob for ProvisionDeviceJob {
     const QUEUE_NAME: &'static str = "provision-device";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
 }
 
 /// A job to delete a device for a user on the homeserver.
@@ -182,6 +248,10 @@ impl DeleteDeviceJob {
 
 impl InsertableJob for DeleteDeviceJob {
     const QUEUE_NAME: &'static str = "delete-device";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
 }
 
 /// A job which syncs the list of devices of a user with the homeserver
@@ -216,6 +286,65 @@ impl SyncDevicesJob {
 
 impl InsertableJob for SyncDevicesJob {
     const QUEUE_NAME: &'static str = "sync-devices";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
+}
+
+/// A job to compare the users in MAS with their state on the homeserver, and
+/// save the discrepancies in a provisioning report
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReconcileProvisioningJob {
+    provisioning_report_id: Ulid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    after: Option<Ulid>,
+}
+
+impl ReconcileProvisioningJob {
+    /// Create a new job to fill the given provisioning report
+    #[must_use]
+    pub fn new(report: &ProvisioningReport) -> Self {
+        Self {
+            provisioning_report_id: report.id,
+            after: None,
+        }
+    }
+
+    /// The ID of the provisioning report to fill
+    #[must_use]
+    pub fn provisioning_report_id(&self) -> Ulid {
+        self.provisioning_report_id
+    }
+
+    /// Get the pagination cursor
+    #[must_use]
+    pub fn pagination(&self, batch_size: usize) -> Pagination {
+        let pagination = Pagination::first(batch_size);
+        if let Some(after) = self.after {
+            pagination.after(after)
+        } else {
+            pagination
+        }
+    }
+
+    /// Get the next job given the page returned by the database
+    #[must_use]
+    pub fn next(&self, page: &Page<User>) -> Option<Self> {
+        if !page.has_next_page {
+            return None;
+        }
+
+        let last_edge = page.edges.last()?;
+        Some(Self {
+            provisioning_report_id: self.provisioning_report_id,
+            after: Some(last_edge.cursor),
+        })
+    }
+}
+
+impl InsertableJob for ReconcileProvisioningJob {
+    const QUEUE_NAME: &'static str = "reconcile-provisioning";
 }
 
 /// A job to deactivate and lock a user
@@ -255,6 +384,10 @@ impl DeactivateUserJob {
 
 impl InsertableJob for DeactivateUserJob {
     const QUEUE_NAME: &'static str = "deactivate-user";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
 }
 
 /// A job to reactivate a user
@@ -283,6 +416,92 @@ impl ReactivateUserJob {
 
 impl InsertableJob for ReactivateUserJob {
     const QUEUE_NAME: &'static str = "reactivate-user";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
+}
+
+/// A job to deactivate a user who asked for it, once the grace period is over
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FinishScheduledDeactivationJob {
+    user_id: Ulid,
+    hs_erase: bool,
+}
+
+impl FinishScheduledDeactivationJob {
+    /// Create a new job to deactivate a user once their grace period is over
+    ///
+    /// # Parameters
+    ///
+    /// * `user` - The user to deactivate
+    /// * `hs_erase` - Whether to erase the user from the homeserver
+    #[must_use]
+    pub fn new(user: &User, hs_erase: bool) -> Self {
+        Self {
+            user_id: user.id,
+            hs_erase,
+        }
+    }
+
+    /// The ID of the user to deactivate
+    #[must_use]
+    pub fn user_id(&self) -> Ulid {
+        self.user_id
+    }
+
+    /// Whether to erase the user from the homeserver
+    #[must_use]
+    pub fn hs_erase(&self) -> bool {
+        self.hs_erase
+    }
+}
+
+impl InsertableJob for FinishScheduledDeactivationJob {
+    const QUEUE_NAME: &'static str = "finish-scheduled-deactivation";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
+}
+
+/// Notify a user that the deactivation of their account was scheduled
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SendDeactivationScheduledEmailJob {
+    user_id: Ulid,
+    language: String,
+}
+
+impl SendDeactivationScheduledEmailJob {
+    /// Create a new job to notify a user that the deactivation of their
+    /// account was scheduled
+    #[must_use]
+    pub fn new(user: &User, language: String) -> Self {
+        Self {
+            user_id: user.id,
+            language,
+        }
+    }
+
+    /// The ID of the user to notify
+    #[must_use]
+    pub fn user_id(&self) -> Ulid {
+        self.user_id
+    }
+
+    /// The language to use for the email, if the user has no preferred one
+    #[must_use]
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+}
+
+impl InsertableJob for SendDeactivationScheduledEmailJob {
+    const QUEUE_NAME: &'static str = "send-deactivation-scheduled-email";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
 }
 
 /// Send account recovery emails
@@ -317,6 +536,192 @@ impl InsertableJob for SendAccountRecoveryEmailsJob {
     const QUEUE_NAME: &'static str = "send-account-recovery-email";
 }
 
+/// The number of sessions which were ended when an account was recovered
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EndedSessions {
+    /// The number of compatibility sessions which were finished
+    pub compat_sessions: usize,
+
+    /// The number of OAuth 2.0 sessions which were finished
+    pub oauth2_sessions: usize,
+
+    /// The number of browser sessions which were finished
+    pub browser_sessions: usize,
+}
+
+/// Notify a user that their account was recovered, and which of their
+/// sessions were ended as a result
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SendAccountRecoveredEmailJob {
+    user_id: Ulid,
+    language: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ended_sessions: Option<EndedSessions>,
+}
+
+impl SendAccountRecoveredEmailJob {
+    /// Create a new job to notify a user that their account was recovered,
+    /// while keeping their other sessions active
+    #[must_use]
+    pub fn new(user: &User, language: String) -> Self {
+        Self {
+            user_id: user.id,
+            language,
+            ended_sessions: None,
+        }
+    }
+
+    /// Record the sessions which were ended by the recovery
+    #[must_use]
+    pub fn with_ended_sessions(mut self, ended_sessions: EndedSessions) -> Self {
+        self.ended_sessions = Some(ended_sessions);
+        self
+    }
+
+    /// The ID of the user to notify
+    #[must_use]
+    pub fn user_id(&self) -> Ulid {
+        self.user_id
+    }
+
+    /// The language to use for the email
+    #[must_use]
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The sessions which were ended by the recovery, if the other sessions
+    /// weren't kept
+    #[must_use]
+    pub fn ended_sessions(&self) -> Option<EndedSessions> {
+        self.ended_sessions
+    }
+}
+
+impl InsertableJob for SendAccountRecoveredEmailJob {
+    const QUEUE_NAME: &'static str = "send-account-recovered-email";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
+}
+
+/// Notify a user that an email address was added to their account
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SendEmailAddedEmailJob {
+    user_id: Ulid,
+    user_email_id: Ulid,
+    language: String,
+}
+
+impl SendEmailAddedEmailJob {
+    /// Create a new job to notify a user that the given email address was
+    /// added to their account
+    #[must_use]
+    pub fn new(user_email: &UserEmail, language: String) -> Self {
+        Self {
+            user_id: user_email.user_id,
+            user_email_id: user_email.id,
+            language,
+        }
+    }
+
+    /// The ID of the user to notify
+    #[must_use]
+    pub fn user_id(&self) -> Ulid {
+        self.user_id
+    }
+
+    /// The ID of the email address which was added
+    #[must_use]
+    pub fn user_email_id(&self) -> Ulid {
+        self.user_email_id
+    }
+
+    /// The language to use for the email, if the user has no preferred one
+    #[must_use]
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+}
+
+impl InsertableJob for SendEmailAddedEmailJob {
+    const QUEUE_NAME: &'static str = "send-email-added-email";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
+}
+
+/// Notify a user that a new session was started on their account, with a link
+/// to sign that session out
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SendNewLoginEmailJob {
+    user_id: Ulid,
+    session_name: Option<String>,
+    revocation_link: Url,
+    language: String,
+}
+
+impl SendNewLoginEmailJob {
+    /// Create a new job to notify a user that a session was started on their
+    /// account
+    ///
+    /// # Parameters
+    ///
+    /// * `user` - The user who owns the new session
+    /// * `session_name` - The human-readable name of the session, if any
+    /// * `revocation_link` - The link to sign the new session out
+    /// * `language` - The language to use for the email, if the user has no
+    ///   preferred one
+    #[must_use]
+    pub fn new(
+        user: &User,
+        session_name: Option<String>,
+        revocation_link: Url,
+        language: String,
+    ) -> Self {
+        Self {
+            user_id: user.id,
+            session_name,
+            revocation_link,
+            language,
+        }
+    }
+
+    /// The ID of the user to notify
+    #[must_use]
+    pub fn user_id(&self) -> Ulid {
+        self.user_id
+    }
+
+    /// The human-readable name of the new session, if any
+    #[must_use]
+    pub fn session_name(&self) -> Option<&str> {
+        self.session_name.as_deref()
+    }
+
+    /// The link to sign the new session out
+    #[must_use]
+    pub fn revocation_link(&self) -> &Url {
+        &self.revocation_link
+    }
+
+    /// The language to use for the email, if the user has no preferred one
+    #[must_use]
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+}
+
+impl InsertableJob for SendNewLoginEmailJob {
+    const QUEUE_NAME: &'static str = "send-new-login-email";
+
+    fn user_id(&self) -> Option<Ulid> {
+        Some(self.user_id)
+    }
+}
+
 /// Cleanup expired tokens
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct CleanupExpiredTokensJob;
@@ -514,3 +919,44 @@ pub struct PruneStalePolicyDataJob;
 impl InsertableJob for PruneStalePolicyDataJob {
     const QUEUE_NAME: &'static str = "prune-stale-policy-data";
 }
+
+/// Permanently remove users which were deleted longer than the retention
+/// period ago
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeDeletedUsersJob;
+
+impl InsertableJob for PurgeDeletedUsersJob {
+    const QUEUE_NAME: &'static str = "purge-deleted-users";
+}
+
+/// A job to send an event to the audit webhook
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SendAuditEventJob {
+    event: AuditEvent,
+}
+
+impl SendAuditEventJob {
+    /// Create a new job to send the given event to the audit webhook
+    #[must_use]
+    pub fn new(event: AuditEvent) -> Self {
+        Self { event }
+    }
+
+    /// The event to send
+    #[must_use]
+    pub fn event(&self) -> &AuditEvent {
+        &self.event
+    }
+}
+
+impl InsertableJob for SendAuditEventJob {
+    const QUEUE_NAME: &'static str = "send-audit-event";
+}
+
+/// Check that emails can be delivered, and record the outcome
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProbeEmailDeliveryJob;
+
+impl InsertableJob for ProbeEmailDeliveryJob {
+    const QUEUE_NAME: &'static str = "probe-email-delivery";
+}
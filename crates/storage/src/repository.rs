@@ -4,6 +4,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::time::Duration;
+
 use async_trait::async_trait;
 use futures_util::future::BoxFuture;
 use thiserror::Error;
@@ -14,21 +16,26 @@ use crate::{
         CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository,
         CompatSsoLoginRepository,
     },
+    email_probe::EmailProbeRepository,
     oauth2::{
         OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository, OAuth2ClientRepository,
-        OAuth2DeviceCodeGrantRepository, OAuth2RefreshTokenRepository, OAuth2SessionRepository,
+        OAuth2DeviceCodeGrantRepository, OAuth2PushedAuthorizationRequestRepository,
+        OAuth2RefreshTokenRepository, OAuth2SessionRepository,
     },
     personal::{PersonalAccessTokenRepository, PersonalSessionRepository},
     policy_data::PolicyDataRepository,
+    provisioning_report::ProvisioningReportRepository,
     queue::{QueueJobRepository, QueueScheduleRepository, QueueWorkerRepository},
+    syn2mas_run::Syn2masRunRepository,
     upstream_oauth2::{
         UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
         UpstreamOAuthSessionRepository,
     },
     user::{
-        BrowserSessionRepository, UserEmailRepository, UserPasswordRepository,
-        UserRecoveryRepository, UserRegistrationRepository, UserRegistrationTokenRepository,
-        UserRepository, UserTermsRepository,
+        BrowserSessionRepository, UserEmailRepository, UserLoginFailureRepository,
+        UserNoteRepository, UserPasswordRepository, UserRecoveryRepository,
+        UserRegistrationRepository, UserRegistrationTokenRepository, UserRepository,
+        UserTermsRepository,
     },
 };
 
@@ -69,6 +76,39 @@ impl RepositoryError {
             source: Box::new(value),
         }
     }
+
+    /// Construct a [`RepositoryError`] signaling that the storage backend is
+    /// temporarily unavailable, and that the operation can be retried after
+    /// the given delay
+    #[must_use]
+    pub fn unavailable(retry_after: Duration) -> Self {
+        Self::from_error(RepositoryUnavailableError { retry_after })
+    }
+
+    /// If the storage backend is temporarily unavailable, how long to wait
+    /// before retrying
+    #[must_use]
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.source
+            .downcast_ref::<RepositoryUnavailableError>()
+            .map(|e| e.retry_after)
+    }
+}
+
+/// An error returned when the storage backend is temporarily unavailable,
+/// for example because the connection to the database was lost
+#[derive(Debug, Error)]
+#[error("The storage backend is temporarily unavailable, retry after {retry_after:?}")]
+pub struct RepositoryUnavailableError {
+    retry_after: Duration,
+}
+
+impl RepositoryUnavailableError {
+    /// How long to wait before retrying
+    #[must_use]
+    pub const fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
 }
 
 /// A type-erased [`Repository`]
@@ -141,6 +181,14 @@ pub trait RepositoryAccess: Send {
     fn user_password<'c>(&'c mut self)
     -> Box<dyn UserPasswordRepository<Error = Self::Error> + 'c>;
 
+    /// Get an [`UserLoginFailureRepository`]
+    fn user_login_failure<'c>(
+        &'c mut self,
+    ) -> Box<dyn UserLoginFailureRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`UserNoteRepository`]
+    fn user_note<'c>(&'c mut self) -> Box<dyn UserNoteRepository<Error = Self::Error> + 'c>;
+
     /// Get an [`UserRecoveryRepository`]
     fn user_recovery<'c>(&'c mut self)
     -> Box<dyn UserRecoveryRepository<Error = Self::Error> + 'c>;
@@ -195,6 +243,11 @@ pub trait RepositoryAccess: Send {
         &'c mut self,
     ) -> Box<dyn OAuth2DeviceCodeGrantRepository<Error = Self::Error> + 'c>;
 
+    /// Get an [`OAuth2PushedAuthorizationRequestRepository`]
+    fn oauth2_pushed_authorization_request<'c>(
+        &'c mut self,
+    ) -> Box<dyn OAuth2PushedAuthorizationRequestRepository<Error = Self::Error> + 'c>;
+
     /// Get a [`CompatSessionRepository`]
     fn compat_session<'c>(
         &'c mut self,
@@ -238,6 +291,17 @@ pub trait RepositoryAccess: Send {
 
     /// Get a [`PolicyDataRepository`]
     fn policy_data<'c>(&'c mut self) -> Box<dyn PolicyDataRepository<Error = Self::Error> + 'c>;
+
+    /// Get a [`ProvisioningReportRepository`]
+    fn provisioning_report<'c>(
+        &'c mut self,
+    ) -> Box<dyn ProvisioningReportRepository<Error = Self::Error> + 'c>;
+
+    /// Get a [`Syn2masRunRepository`]
+    fn syn2mas_run<'c>(&'c mut self) -> Box<dyn Syn2masRunRepository<Error = Self::Error> + 'c>;
+
+    /// Get an [`EmailProbeRepository`]
+    fn email_probe<'c>(&'c mut self) -> Box<dyn EmailProbeRepository<Error = Self::Error> + 'c>;
 }
 
 /// Implementations of the [`RepositoryAccess`], [`RepositoryTransaction`] and
@@ -253,22 +317,26 @@ mod impls {
             CompatAccessTokenRepository, CompatRefreshTokenRepository, CompatSessionRepository,
             CompatSsoLoginRepository,
         },
+        email_probe::EmailProbeRepository,
         oauth2::{
             OAuth2AccessTokenRepository, OAuth2AuthorizationGrantRepository,
-            OAuth2ClientRepository, OAuth2DeviceCodeGrantRepository, OAuth2RefreshTokenRepository,
+            OAuth2ClientRepository, OAuth2DeviceCodeGrantRepository,
+            OAuth2PushedAuthorizationRequestRepository, OAuth2RefreshTokenRepository,
             OAuth2SessionRepository,
         },
         personal::{PersonalAccessTokenRepository, PersonalSessionRepository},
         policy_data::PolicyDataRepository,
+        provisioning_report::ProvisioningReportRepository,
         queue::{QueueJobRepository, QueueScheduleRepository, QueueWorkerRepository},
+        syn2mas_run::Syn2masRunRepository,
         upstream_oauth2::{
             UpstreamOAuthLinkRepository, UpstreamOAuthProviderRepository,
             UpstreamOAuthSessionRepository,
         },
         user::{
-            BrowserSessionRepository, UserEmailRepository, UserPasswordRepository,
-            UserRegistrationRepository, UserRegistrationTokenRepository, UserRepository,
-            UserTermsRepository,
+            BrowserSessionRepository, UserEmailRepository, UserLoginFailureRepository,
+            UserNoteRepository, UserPasswordRepository, UserRegistrationRepository,
+            UserRegistrationTokenRepository, UserRepository, UserTermsRepository,
         },
     };
 
@@ -352,6 +420,19 @@ mod impls {
             Box::new(MapErr::new(self.inner.user_password(), &mut self.mapper))
         }
 
+        fn user_login_failure<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserLoginFailureRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(
+                self.inner.user_login_failure(),
+                &mut self.mapper,
+            ))
+        }
+
+        fn user_note<'c>(&'c mut self) -> Box<dyn UserNoteRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.user_note(), &mut self.mapper))
+        }
+
         fn user_recovery<'c>(
             &'c mut self,
         ) -> Box<dyn crate::user::UserRecoveryRepository<Error = Self::Error> + 'c> {
@@ -440,6 +521,15 @@ mod impls {
             ))
         }
 
+        fn oauth2_pushed_authorization_request<'c>(
+            &'c mut self,
+        ) -> Box<dyn OAuth2PushedAuthorizationRequestRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(
+                self.inner.oauth2_pushed_authorization_request(),
+                &mut self.mapper,
+            ))
+        }
+
         fn compat_session<'c>(
             &'c mut self,
         ) -> Box<dyn CompatSessionRepository<Error = Self::Error> + 'c> {
@@ -506,6 +596,27 @@ mod impls {
         ) -> Box<dyn PolicyDataRepository<Error = Self::Error> + 'c> {
             Box::new(MapErr::new(self.inner.policy_data(), &mut self.mapper))
         }
+
+        fn provisioning_report<'c>(
+            &'c mut self,
+        ) -> Box<dyn ProvisioningReportRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(
+                self.inner.provisioning_report(),
+                &mut self.mapper,
+            ))
+        }
+
+        fn syn2mas_run<'c>(
+            &'c mut self,
+        ) -> Box<dyn Syn2masRunRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.syn2mas_run(), &mut self.mapper))
+        }
+
+        fn email_probe<'c>(
+            &'c mut self,
+        ) -> Box<dyn EmailProbeRepository<Error = Self::Error> + 'c> {
+            Box::new(MapErr::new(self.inner.email_probe(), &mut self.mapper))
+        }
     }
 
     impl<R: RepositoryAccess + ?Sized> RepositoryAccess for Box<R> {
@@ -543,6 +654,16 @@ mod impls {
             (**self).user_password()
         }
 
+        fn user_login_failure<'c>(
+            &'c mut self,
+        ) -> Box<dyn UserLoginFailureRepository<Error = Self::Error> + 'c> {
+            (**self).user_login_failure()
+        }
+
+        fn user_note<'c>(&'c mut self) -> Box<dyn UserNoteRepository<Error = Self::Error> + 'c> {
+            (**self).user_note()
+        }
+
         fn user_recovery<'c>(
             &'c mut self,
         ) -> Box<dyn crate::user::UserRecoveryRepository<Error = Self::Error> + 'c> {
@@ -613,6 +734,12 @@ mod impls {
             (**self).oauth2_device_code_grant()
         }
 
+        fn oauth2_pushed_authorization_request<'c>(
+            &'c mut self,
+        ) -> Box<dyn OAuth2PushedAuthorizationRequestRepository<Error = Self::Error> + 'c> {
+            (**self).oauth2_pushed_authorization_request()
+        }
+
         fn compat_session<'c>(
             &'c mut self,
         ) -> Box<dyn CompatSessionRepository<Error = Self::Error> + 'c> {
@@ -670,5 +797,23 @@ mod impls {
         ) -> Box<dyn PolicyDataRepository<Error = Self::Error> + 'c> {
             (**self).policy_data()
         }
+
+        fn provisioning_report<'c>(
+            &'c mut self,
+        ) -> Box<dyn ProvisioningReportRepository<Error = Self::Error> + 'c> {
+            (**self).provisioning_report()
+        }
+
+        fn syn2mas_run<'c>(
+            &'c mut self,
+        ) -> Box<dyn Syn2masRunRepository<Error = Self::Error> + 'c> {
+            (**self).syn2mas_run()
+        }
+
+        fn email_probe<'c>(
+            &'c mut self,
+        ) -> Box<dyn EmailProbeRepository<Error = Self::Error> + 'c> {
+            (**self).email_probe()
+        }
     }
 }
@@ -0,0 +1,47 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Repositories to interact with the records of the syn2mas migrations saved
+//! in the storage backend.
+//!
+//! Those records are written by syn2mas itself, so this only allows reading
+//! them.
+
+use async_trait::async_trait;
+use mas_data_model::Syn2masRun;
+
+use crate::{Page, Pagination, repository_impl};
+
+/// A [`Syn2masRunRepository`] helps interacting with the records of the
+/// syn2mas migrations saved in the storage backend.
+#[async_trait]
+pub trait Syn2masRunRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// List the syn2mas runs, with the given pagination
+    ///
+    /// # Parameters
+    ///
+    /// * `pagination`: The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list(&mut self, pagination: Pagination) -> Result<Page<Syn2masRun>, Self::Error>;
+
+    /// Count the syn2mas runs
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count(&mut self) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(Syn2masRunRepository:
+    async fn list(&mut self, pagination: Pagination) -> Result<Page<Syn2masRun>, Self::Error>;
+
+    async fn count(&mut self) -> Result<usize, Self::Error>;
+);
@@ -156,6 +156,31 @@ pub trait UpstreamOAuthLinkRepository: Send + Sync {
         human_account_name: Option<String>,
     ) -> Result<UpstreamOAuthLink, Self::Error>;
 
+    /// Add many upstream OAuth links at once, already associated to users
+    ///
+    /// Returns the newly created upstream OAuth links, in the same order as
+    /// the input
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `links`: The upstream OAuth provider, the user to associate and the
+    ///   subject of each link to create
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails. If one of
+    /// the links is rejected, for example because the subject is already
+    /// linked, the error reports its index in `links`, and none of them are
+    /// created.
+    async fn add_many(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        links: &[(&UpstreamOAuthProvider, &User, String)],
+    ) -> Result<Vec<UpstreamOAuthLink>, Self::Error>;
+
     /// Associate an upstream OAuth link to a user
     ///
     /// Returns the updated upstream OAuth link
@@ -174,6 +199,26 @@ pub trait UpstreamOAuthLinkRepository: Send + Sync {
         user: &User,
     ) -> Result<(), Self::Error>;
 
+    /// Move an [`UpstreamOAuthLink`] from the user it is currently associated
+    /// with to another user
+    ///
+    /// Returns the updated upstream OAuth link
+    ///
+    /// # Parameters
+    ///
+    /// * `upstream_oauth_link`: The upstream OAuth link to transfer
+    /// * `user`: The user which should now own the upstream OAuth link
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails, or if the
+    /// link was associated with another user in the meantime
+    async fn transfer_to_user(
+        &mut self,
+        upstream_oauth_link: UpstreamOAuthLink,
+        user: &User,
+    ) -> Result<UpstreamOAuthLink, Self::Error>;
+
     /// List [`UpstreamOAuthLink`] with the given filter and pagination
     ///
     /// # Parameters
@@ -236,12 +281,25 @@ repository_impl!(UpstreamOAuthLinkRepository:
         human_account_name: Option<String>,
     ) -> Result<UpstreamOAuthLink, Self::Error>;
 
+    async fn add_many(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        links: &[(&UpstreamOAuthProvider, &User, String)],
+    ) -> Result<Vec<UpstreamOAuthLink>, Self::Error>;
+
     async fn associate_to_user(
         &mut self,
         upstream_oauth_link: &UpstreamOAuthLink,
         user: &User,
     ) -> Result<(), Self::Error>;
 
+    async fn transfer_to_user(
+        &mut self,
+        upstream_oauth_link: UpstreamOAuthLink,
+        user: &User,
+    ) -> Result<UpstreamOAuthLink, Self::Error>;
+
     async fn list(
         &mut self,
         filter: UpstreamOAuthLinkFilter<'_>,
@@ -4,7 +4,10 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::marker::PhantomData;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    marker::PhantomData,
+};
 
 use async_trait::async_trait;
 use mas_data_model::{
@@ -103,6 +106,9 @@ pub struct UpstreamOAuthProviderParams {
     /// The position of the provider in the UI
     pub ui_order: i32,
 
+    /// Whether the provider is hidden from the login and registration pages
+    pub ui_hidden: bool,
+
     /// The behavior when receiving a backchannel logout notification
     pub on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout,
 }
@@ -168,6 +174,23 @@ pub trait UpstreamOAuthProviderRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn lookup(&mut self, id: Ulid) -> Result<Option<UpstreamOAuthProvider>, Self::Error>;
 
+    /// Load a batch of upstream OAuth providers by their IDs
+    ///
+    /// Returns a map of provider IDs to providers. If a provider does not
+    /// exist, it is not present in the map.
+    ///
+    /// # Parameters
+    ///
+    /// * `ids`: The IDs of the providers to load
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn load_batch(
+        &mut self,
+        ids: BTreeSet<Ulid>,
+    ) -> Result<BTreeMap<Ulid, UpstreamOAuthProvider>, Self::Error>;
+
     /// Add a new upstream OAuth provider
     ///
     /// Returns the newly created provider
@@ -289,6 +312,11 @@ pub trait UpstreamOAuthProviderRepository: Send + Sync {
 repository_impl!(UpstreamOAuthProviderRepository:
     async fn lookup(&mut self, id: Ulid) -> Result<Option<UpstreamOAuthProvider>, Self::Error>;
 
+    async fn load_batch(
+        &mut self,
+        ids: BTreeSet<Ulid>,
+    ) -> Result<BTreeMap<Ulid, UpstreamOAuthProvider>, Self::Error>;
+
     async fn add(
         &mut self,
         rng: &mut (dyn RngCore + Send),
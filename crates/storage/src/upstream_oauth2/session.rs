@@ -5,6 +5,7 @@
 // Please see LICENSE files in the repository root for full details.
 
 use async_trait::async_trait;
+use chrono::Duration;
 use mas_data_model::{
     Clock, UpstreamOAuthAuthorizationSession, UpstreamOAuthLink, UpstreamOAuthProvider,
 };
@@ -17,6 +18,7 @@ use crate::{Pagination, pagination::Page, repository_impl};
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 pub struct UpstreamOAuthSessionFilter<'a> {
     provider: Option<&'a UpstreamOAuthProvider>,
+    link: Option<&'a UpstreamOAuthLink>,
     sub_claim: Option<&'a str>,
     sid_claim: Option<&'a str>,
 }
@@ -43,6 +45,21 @@ impl<'a> UpstreamOAuthSessionFilter<'a> {
         self.provider
     }
 
+    /// Set the upstream OAuth link for which to list sessions
+    #[must_use]
+    pub fn for_link(mut self, link: &'a UpstreamOAuthLink) -> Self {
+        self.link = Some(link);
+        self
+    }
+
+    /// Get the upstream OAuth link filter
+    ///
+    /// Returns [`None`] if no filter was set
+    #[must_use]
+    pub fn link(&self) -> Option<&UpstreamOAuthLink> {
+        self.link
+    }
+
     /// Set the `sub` claim to filter by
     #[must_use]
     pub fn with_sub_claim(mut self, sub_claim: &'a str) -> Self {
@@ -176,6 +193,27 @@ pub trait UpstreamOAuthSessionRepository: Send + Sync {
         upstream_oauth_authorization_session: UpstreamOAuthAuthorizationSession,
     ) -> Result<UpstreamOAuthAuthorizationSession, Self::Error>;
 
+    /// Mark a session as failed, because the upstream provider answered the
+    /// authorization request with an error
+    ///
+    /// Returns the updated session
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: the clock source
+    /// * `upstream_oauth_authorization_session`: the session to mark as failed
+    /// * `error_code`: the error code returned by the upstream provider
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn fail(
+        &mut self,
+        clock: &dyn Clock,
+        upstream_oauth_authorization_session: UpstreamOAuthAuthorizationSession,
+        error_code: String,
+    ) -> Result<UpstreamOAuthAuthorizationSession, Self::Error>;
+
     /// List [`UpstreamOAuthAuthorizationSession`] with the given filter and
     /// pagination
     ///
@@ -205,6 +243,27 @@ pub trait UpstreamOAuthSessionRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn count(&mut self, filter: UpstreamOAuthSessionFilter<'_>)
     -> Result<usize, Self::Error>;
+
+    /// Delete the [`UpstreamOAuthAuthorizationSession`] which were never
+    /// completed, and which were created longer than `retention` ago
+    ///
+    /// Sessions completed with a link are never deleted.
+    ///
+    /// Returns the number of sessions that were deleted
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to get the current time
+    /// * `retention`: How long unfinished sessions are kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn cleanup_abandoned(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
 }
 
 repository_impl!(UpstreamOAuthSessionRepository:
@@ -240,6 +299,13 @@ repository_impl!(UpstreamOAuthSessionRepository:
         upstream_oauth_authorization_session: UpstreamOAuthAuthorizationSession,
     ) -> Result<UpstreamOAuthAuthorizationSession, Self::Error>;
 
+    async fn fail(
+        &mut self,
+        clock: &dyn Clock,
+        upstream_oauth_authorization_session: UpstreamOAuthAuthorizationSession,
+        error_code: String,
+    ) -> Result<UpstreamOAuthAuthorizationSession, Self::Error>;
+
     async fn list(
         &mut self,
         filter: UpstreamOAuthSessionFilter<'_>,
@@ -247,4 +313,10 @@ repository_impl!(UpstreamOAuthSessionRepository:
     ) -> Result<Page<UpstreamOAuthAuthorizationSession>, Self::Error>;
 
     async fn count(&mut self, filter: UpstreamOAuthSessionFilter<'_>) -> Result<usize, Self::Error>;
+
+    async fn cleanup_abandoned(
+        &mut self,
+        clock: &dyn Clock,
+        retention: Duration,
+    ) -> Result<usize, Self::Error>;
 );
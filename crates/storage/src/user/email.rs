@@ -5,9 +5,10 @@
 // Please see LICENSE files in the repository root for full details.
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use mas_data_model::{
     BrowserSession, Clock, User, UserEmail, UserEmailAuthentication, UserEmailAuthenticationCode,
-    UserRegistration,
+    UserEmailChange, UserRegistration,
 };
 use rand_core::RngCore;
 use ulid::Ulid;
@@ -150,6 +151,28 @@ pub trait UserEmailRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn count(&mut self, filter: UserEmailFilter<'_>) -> Result<usize, Self::Error>;
 
+    /// Count the users created in the given time window by the domain of
+    /// their email addresses
+    ///
+    /// Returns the domains with the most users first, along with their number
+    /// of users
+    ///
+    /// # Parameters
+    ///
+    /// * `since`: Only count users created at or after this time
+    /// * `until`: Only count users created before this time
+    /// * `limit`: The maximum number of domains to return
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count_users_by_domain(
+        &mut self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<(String, usize)>, Self::Error>;
+
     /// Create a new [`UserEmail`] for a [`User`]
     ///
     /// Returns the newly created [`UserEmail`]
@@ -172,6 +195,30 @@ pub trait UserEmailRepository: Send + Sync {
         email: String,
     ) -> Result<UserEmail, Self::Error>;
 
+    /// Create many [`UserEmail`]s at once
+    ///
+    /// Returns the newly created [`UserEmail`]s, in the same order as the
+    /// input
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `emails`: The [`User`]s for whom to create the [`UserEmail`]s, with
+    ///   their email address
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails. If one of
+    /// the emails is rejected, the error reports its index in `emails`, and
+    /// none of them are created.
+    async fn add_many(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        emails: &[(&User, String)],
+    ) -> Result<Vec<UserEmail>, Self::Error>;
+
     /// Delete a [`UserEmail`]
     ///
     /// # Parameters
@@ -196,6 +243,24 @@ pub trait UserEmailRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn remove_bulk(&mut self, filter: UserEmailFilter<'_>) -> Result<usize, Self::Error>;
 
+    /// Move a [`UserEmail`] to another user
+    ///
+    /// Returns the updated [`UserEmail`]
+    ///
+    /// # Parameters
+    ///
+    /// * `user_email`: The [`UserEmail`] to move
+    /// * `user`: The user which should now own the email
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn transfer_to_user(
+        &mut self,
+        user_email: UserEmail,
+        user: &User,
+    ) -> Result<UserEmail, Self::Error>;
+
     /// Add a new [`UserEmailAuthentication`] for a [`BrowserSession`]
     ///
     /// # Parameters
@@ -312,6 +377,98 @@ pub trait UserEmailRepository: Send + Sync {
         authentication: UserEmailAuthentication,
         code: &UserEmailAuthenticationCode,
     ) -> Result<UserEmailAuthentication, Self::Error>;
+
+    /// Add a new [`UserEmailChange`], to add the email address of a completed
+    /// [`UserEmailAuthentication`] once confirmed from a previous address
+    ///
+    /// Returns the newly created [`UserEmailChange`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock to use
+    /// * `duration`: The duration for which the change can be confirmed
+    /// * `authentication`: The [`UserEmailAuthentication`] which verified the
+    ///   new address
+    /// * `previous_email`: The [`UserEmail`] of the user asked to confirm the
+    ///   change
+    /// * `ticket`: The secret sent to the previous address
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying repository fails
+    async fn add_change(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        duration: chrono::Duration,
+        authentication: &UserEmailAuthentication,
+        previous_email: &UserEmail,
+        ticket: String,
+    ) -> Result<UserEmailChange, Self::Error>;
+
+    /// Lookup a [`UserEmailChange`]
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`UserEmailChange`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying repository fails
+    async fn lookup_change(&mut self, id: Ulid) -> Result<Option<UserEmailChange>, Self::Error>;
+
+    /// Find a [`UserEmailChange`] by its ticket
+    ///
+    /// # Parameters
+    ///
+    /// * `ticket`: The ticket of the [`UserEmailChange`] to find
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying repository fails
+    async fn find_change_by_ticket(
+        &mut self,
+        ticket: &str,
+    ) -> Result<Option<UserEmailChange>, Self::Error>;
+
+    /// Mark a [`UserEmailChange`] as confirmed
+    ///
+    /// Returns the confirmed [`UserEmailChange`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use to generate timestamps
+    /// * `change`: The [`UserEmailChange`] to confirm
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying repository fails, or if the change
+    /// was already confirmed or cancelled
+    async fn confirm_change(
+        &mut self,
+        clock: &dyn Clock,
+        change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error>;
+
+    /// Mark a [`UserEmailChange`] as cancelled
+    ///
+    /// Returns the cancelled [`UserEmailChange`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock to use to generate timestamps
+    /// * `change`: The [`UserEmailChange`] to cancel
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying repository fails, or if the change
+    /// was already confirmed or cancelled
+    async fn cancel_change(
+        &mut self,
+        clock: &dyn Clock,
+        change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error>;
 }
 
 repository_impl!(UserEmailRepository:
@@ -326,6 +483,12 @@ repository_impl!(UserEmailRepository:
         pagination: Pagination,
     ) -> Result<Page<UserEmail>, Self::Error>;
     async fn count(&mut self, filter: UserEmailFilter<'_>) -> Result<usize, Self::Error>;
+    async fn count_users_by_domain(
+        &mut self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<(String, usize)>, Self::Error>;
 
     async fn add(
         &mut self,
@@ -334,10 +497,22 @@ repository_impl!(UserEmailRepository:
         user: &User,
         email: String,
     ) -> Result<UserEmail, Self::Error>;
+    async fn add_many(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        emails: &[(&User, String)],
+    ) -> Result<Vec<UserEmail>, Self::Error>;
     async fn remove(&mut self, user_email: UserEmail) -> Result<(), Self::Error>;
 
     async fn remove_bulk(&mut self, filter: UserEmailFilter<'_>) -> Result<usize, Self::Error>;
 
+    async fn transfer_to_user(
+        &mut self,
+        user_email: UserEmail,
+        user: &User,
+    ) -> Result<UserEmail, Self::Error>;
+
     async fn add_authentication_for_session(
         &mut self,
         rng: &mut (dyn RngCore + Send),
@@ -380,4 +555,33 @@ repository_impl!(UserEmailRepository:
         authentication: UserEmailAuthentication,
         code: &UserEmailAuthenticationCode,
     ) -> Result<UserEmailAuthentication, Self::Error>;
+
+    async fn add_change(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        duration: chrono::Duration,
+        authentication: &UserEmailAuthentication,
+        previous_email: &UserEmail,
+        ticket: String,
+    ) -> Result<UserEmailChange, Self::Error>;
+
+    async fn lookup_change(&mut self, id: Ulid) -> Result<Option<UserEmailChange>, Self::Error>;
+
+    async fn find_change_by_ticket(
+        &mut self,
+        ticket: &str,
+    ) -> Result<Option<UserEmailChange>, Self::Error>;
+
+    async fn confirm_change(
+        &mut self,
+        clock: &dyn Clock,
+        change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error>;
+
+    async fn cancel_change(
+        &mut self,
+        clock: &dyn Clock,
+        change: UserEmailChange,
+    ) -> Result<UserEmailChange, Self::Error>;
 );
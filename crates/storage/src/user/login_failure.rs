@@ -0,0 +1,97 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use mas_data_model::{Clock, LoginFailureOrigin, LoginFailureReason, User, UserLoginFailure};
+use rand_core::RngCore;
+
+use crate::repository_impl;
+
+/// A [`UserLoginFailureRepository`] helps interacting with the history of
+/// failed login attempts of [`User`]s saved in the storage backend
+#[async_trait]
+pub trait UserLoginFailureRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Record a failed login attempt for a [`User`]
+    ///
+    /// Returns the newly recorded [`UserLoginFailure`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user`: The [`User`] for which the login attempt failed
+    /// * `origin`: Where the login attempt came from
+    /// * `reason`: Why the login attempt failed
+    /// * `ip_address`: The IP address of the requester, if known
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        origin: LoginFailureOrigin,
+        reason: LoginFailureReason,
+        ip_address: Option<IpAddr>,
+    ) -> Result<UserLoginFailure, Self::Error>;
+
+    /// List the most recent failed login attempts of a [`User`], most recent
+    /// first
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] for which to list the failed login attempts
+    /// * `limit`: The maximum number of entries to return
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list_recent(
+        &mut self,
+        user: &User,
+        limit: usize,
+    ) -> Result<Vec<UserLoginFailure>, Self::Error>;
+
+    /// Prune the history of failed login attempts, keeping only the most recent
+    /// entries for each user
+    ///
+    /// Returns the number of entries pruned
+    ///
+    /// # Parameters
+    ///
+    /// * `keep`: The number of entries to keep for each user
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn prune(&mut self, keep: usize) -> Result<usize, Self::Error>;
+}
+
+repository_impl!(UserLoginFailureRepository:
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        origin: LoginFailureOrigin,
+        reason: LoginFailureReason,
+        ip_address: Option<IpAddr>,
+    ) -> Result<UserLoginFailure, Self::Error>;
+
+    async fn list_recent(
+        &mut self,
+        user: &User,
+        limit: usize,
+    ) -> Result<Vec<UserLoginFailure>, Self::Error>;
+
+    async fn prune(&mut self, keep: usize) -> Result<usize, Self::Error>;
+);
@@ -6,7 +6,10 @@
 
 //! Repositories to interact with entities related to user accounts
 
+use std::collections::{BTreeMap, BTreeSet};
+
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use mas_data_model::{Clock, User};
 use rand_core::RngCore;
 use ulid::Ulid;
@@ -14,6 +17,8 @@ use ulid::Ulid;
 use crate::{Page, Pagination, repository_impl};
 
 mod email;
+mod login_failure;
+mod note;
 mod password;
 mod recovery;
 mod registration;
@@ -23,9 +28,11 @@ mod terms;
 
 pub use self::{
     email::{UserEmailFilter, UserEmailRepository},
+    login_failure::UserLoginFailureRepository,
+    note::{UserNoteFilter, UserNoteRepository},
     password::UserPasswordRepository,
     recovery::UserRecoveryRepository,
-    registration::UserRegistrationRepository,
+    registration::{RegistrationBurst, UserRegistrationRepository},
     registration_token::{UserRegistrationTokenFilter, UserRegistrationTokenRepository},
     session::{BrowserSessionFilter, BrowserSessionRepository},
     terms::UserTermsRepository,
@@ -77,6 +84,12 @@ pub struct UserFilter<'a> {
     can_request_admin: Option<bool>,
     is_guest: Option<bool>,
     search: Option<&'a str>,
+    deleted_before: Option<DateTime<Utc>>,
+    organization: Option<&'a str>,
+    upstream_provider_id: Option<Ulid>,
+    has_upstream_link: Option<bool>,
+    has_external_id: Option<bool>,
+    migrated: Option<bool>,
 }
 
 impl<'a> UserFilter<'a> {
@@ -142,6 +155,70 @@ impl<'a> UserFilter<'a> {
         self
     }
 
+    /// Filter for users which were deleted before the given time
+    #[must_use]
+    pub fn with_deleted_before(mut self, deleted_before: DateTime<Utc>) -> Self {
+        self.deleted_before = Some(deleted_before);
+        self
+    }
+
+    /// Filter for users which belong to the given organization
+    #[must_use]
+    pub fn with_organization(mut self, organization: &'a str) -> Self {
+        self.organization = Some(organization);
+        self
+    }
+
+    /// Filter for users which have a link to the upstream provider with the
+    /// given ID
+    #[must_use]
+    pub fn linked_to_upstream_provider(mut self, upstream_provider_id: Ulid) -> Self {
+        self.upstream_provider_id = Some(upstream_provider_id);
+        self
+    }
+
+    /// Filter for users which have at least one upstream link
+    #[must_use]
+    pub fn with_upstream_link_only(mut self) -> Self {
+        self.has_upstream_link = Some(true);
+        self
+    }
+
+    /// Filter for users which don't have any upstream link
+    #[must_use]
+    pub fn without_upstream_link_only(mut self) -> Self {
+        self.has_upstream_link = Some(false);
+        self
+    }
+
+    /// Filter for users which have an external ID set
+    #[must_use]
+    pub fn with_external_id_only(mut self) -> Self {
+        self.has_external_id = Some(true);
+        self
+    }
+
+    /// Filter for users which don't have an external ID set
+    #[must_use]
+    pub fn without_external_id_only(mut self) -> Self {
+        self.has_external_id = Some(false);
+        self
+    }
+
+    /// Filter for users which were migrated from Synapse by syn2mas
+    #[must_use]
+    pub fn migrated_only(mut self) -> Self {
+        self.migrated = Some(true);
+        self
+    }
+
+    /// Filter for users which were not migrated from Synapse by syn2mas
+    #[must_use]
+    pub fn not_migrated_only(mut self) -> Self {
+        self.migrated = Some(false);
+        self
+    }
+
     /// Get the state filter
     ///
     /// Returns [`None`] if no state filter was set
@@ -173,6 +250,54 @@ impl<'a> UserFilter<'a> {
     pub fn search(&self) -> Option<&'a str> {
         self.search
     }
+
+    /// Get the deleted before filter
+    ///
+    /// Returns [`None`] if no deleted before filter was set
+    #[must_use]
+    pub fn deleted_before(&self) -> Option<DateTime<Utc>> {
+        self.deleted_before
+    }
+
+    /// Get the organization filter
+    ///
+    /// Returns [`None`] if no organization filter was set
+    #[must_use]
+    pub fn organization(&self) -> Option<&'a str> {
+        self.organization
+    }
+
+    /// Get the upstream provider filter
+    ///
+    /// Returns [`None`] if no upstream provider filter was set
+    #[must_use]
+    pub fn upstream_provider_id(&self) -> Option<Ulid> {
+        self.upstream_provider_id
+    }
+
+    /// Get the upstream link filter
+    ///
+    /// Returns [`None`] if no upstream link filter was set
+    #[must_use]
+    pub fn has_upstream_link(&self) -> Option<bool> {
+        self.has_upstream_link
+    }
+
+    /// Get the external ID filter
+    ///
+    /// Returns [`None`] if no external ID filter was set
+    #[must_use]
+    pub fn has_external_id(&self) -> Option<bool> {
+        self.has_external_id
+    }
+
+    /// Get the migrated filter
+    ///
+    /// Returns [`None`] if no migrated filter was set
+    #[must_use]
+    pub fn migrated(&self) -> Option<bool> {
+        self.migrated
+    }
 }
 
 /// A [`UserRepository`] helps interacting with [`User`] saved in the storage
@@ -208,6 +333,37 @@ pub trait UserRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn find_by_username(&mut self, username: &str) -> Result<Option<User>, Self::Error>;
 
+    /// Find a [`User`] by the ID set by an external provisioning system
+    ///
+    /// Returns `None` if no [`User`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `external_id`: The external ID of the [`User`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_by_external_id(&mut self, external_id: &str)
+    -> Result<Option<User>, Self::Error>;
+
+    /// Load a batch of [`User`]s by their IDs
+    ///
+    /// Returns a map of user IDs to users. If a user does not exist, it is not
+    /// present in the map.
+    ///
+    /// # Parameters
+    ///
+    /// * `ids`: The IDs of the users to load
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn load_batch(
+        &mut self,
+        ids: BTreeSet<Ulid>,
+    ) -> Result<BTreeMap<Ulid, User>, Self::Error>;
+
     /// Create a new [`User`]
     ///
     /// Returns the newly created [`User`]
@@ -295,6 +451,52 @@ pub trait UserRepository: Send + Sync {
     /// Returns [`Self::Error`] if the underlying repository fails
     async fn reactivate(&mut self, user: User) -> Result<User, Self::Error>;
 
+    /// Soft-delete a [`User`]
+    ///
+    /// The user is marked as deleted, and deactivated if it wasn't already.
+    /// Its data is kept until it gets purged with [`Self::purge`], which
+    /// means its username can't be reused in the meantime.
+    ///
+    /// Returns the deleted [`User`]
+    ///
+    /// # Parameters
+    ///
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user`: The [`User`] to delete
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn delete(&mut self, clock: &dyn Clock, user: User) -> Result<User, Self::Error>;
+
+    /// Restore a soft-deleted [`User`]
+    ///
+    /// If the user was deactivated as part of its deletion, it is reactivated
+    /// as well.
+    ///
+    /// Returns the restored [`User`]
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to restore
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn undelete(&mut self, user: User) -> Result<User, Self::Error>;
+
+    /// Permanently remove a [`User`] and all the data attached to it: email
+    /// addresses, upstream links, passwords, sessions and tokens.
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to purge
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn purge(&mut self, user: User) -> Result<(), Self::Error>;
+
     /// Set whether a [`User`] can request admin
     ///
     /// Returns the [`User`] with the new `can_request_admin` value
@@ -312,6 +514,114 @@ pub trait UserRepository: Send + Sync {
         can_request_admin: bool,
     ) -> Result<User, Self::Error>;
 
+    /// Set the organization a [`User`] belongs to
+    ///
+    /// Returns the [`User`] with the new `organization` value
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    /// * `organization`: The organization to set, or [`None`] to unset it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_organization(
+        &mut self,
+        user: User,
+        organization: Option<String>,
+    ) -> Result<User, Self::Error>;
+
+    /// Set the maximum number of active sessions a [`User`] can have,
+    /// overriding the limit from the configuration
+    ///
+    /// Returns the [`User`] with the new `max_sessions` value
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    /// * `max_sessions`: The maximum number of sessions, or [`None`] to use
+    ///   the limit from the configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_max_sessions(
+        &mut self,
+        user: User,
+        max_sessions: Option<u32>,
+    ) -> Result<User, Self::Error>;
+
+    /// Set the preferred language of a [`User`], used for the pages and
+    /// emails sent to them
+    ///
+    /// Returns the [`User`] with the new `locale` value
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    /// * `locale`: The language tag, or [`None`] to use the language
+    ///   negotiated with the browser
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn set_locale(&mut self, user: User, locale: Option<String>)
+    -> Result<User, Self::Error>;
+
+    /// Set the ID used by an external provisioning system to identify a
+    /// [`User`]
+    ///
+    /// Returns the [`User`] with the new `external_id` value
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    /// * `external_id`: The external ID, or [`None`] to remove it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails, including
+    /// if another [`User`] already has the same external ID
+    async fn set_external_id(
+        &mut self,
+        user: User,
+        external_id: Option<String>,
+    ) -> Result<User, Self::Error>;
+
+    /// Schedule the deactivation of a [`User`] who asked for their own
+    /// account to be deactivated, once the grace period is over
+    ///
+    /// Returns the [`User`] with the new `deactivation_scheduled_at` value
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    /// * `scheduled_at`: When the grace period ends
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn schedule_deactivation(
+        &mut self,
+        user: User,
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<User, Self::Error>;
+
+    /// Clear the scheduled deactivation of a [`User`], either because it was
+    /// cancelled or because it was carried out
+    ///
+    /// Returns the [`User`] without a `deactivation_scheduled_at` value
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] to update
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn unschedule_deactivation(&mut self, user: User) -> Result<User, Self::Error>;
+
     /// List [`User`] with the given filter and pagination
     ///
     /// # Parameters
@@ -356,6 +666,12 @@ pub trait UserRepository: Send + Sync {
 repository_impl!(UserRepository:
     async fn lookup(&mut self, id: Ulid) -> Result<Option<User>, Self::Error>;
     async fn find_by_username(&mut self, username: &str) -> Result<Option<User>, Self::Error>;
+    async fn find_by_external_id(
+        &mut self,
+        external_id: &str,
+    ) -> Result<Option<User>, Self::Error>;
+    async fn load_batch(&mut self, ids: BTreeSet<Ulid>)
+    -> Result<BTreeMap<Ulid, User>, Self::Error>;
     async fn add(
         &mut self,
         rng: &mut (dyn RngCore + Send),
@@ -367,11 +683,40 @@ repository_impl!(UserRepository:
     async fn unlock(&mut self, user: User) -> Result<User, Self::Error>;
     async fn deactivate(&mut self, clock: &dyn Clock, user: User) -> Result<User, Self::Error>;
     async fn reactivate(&mut self, user: User) -> Result<User, Self::Error>;
+    async fn delete(&mut self, clock: &dyn Clock, user: User) -> Result<User, Self::Error>;
+    async fn undelete(&mut self, user: User) -> Result<User, Self::Error>;
+    async fn purge(&mut self, user: User) -> Result<(), Self::Error>;
     async fn set_can_request_admin(
         &mut self,
         user: User,
         can_request_admin: bool,
     ) -> Result<User, Self::Error>;
+    async fn set_organization(
+        &mut self,
+        user: User,
+        organization: Option<String>,
+    ) -> Result<User, Self::Error>;
+    async fn set_max_sessions(
+        &mut self,
+        user: User,
+        max_sessions: Option<u32>,
+    ) -> Result<User, Self::Error>;
+    async fn set_locale(
+        &mut self,
+        user: User,
+        locale: Option<String>,
+    ) -> Result<User, Self::Error>;
+    async fn set_external_id(
+        &mut self,
+        user: User,
+        external_id: Option<String>,
+    ) -> Result<User, Self::Error>;
+    async fn schedule_deactivation(
+        &mut self,
+        user: User,
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<User, Self::Error>;
+    async fn unschedule_deactivation(&mut self, user: User) -> Result<User, Self::Error>;
     async fn list(
         &mut self,
         filter: UserFilter<'_>,
@@ -0,0 +1,161 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use async_trait::async_trait;
+use mas_data_model::{Clock, User, UserNote};
+use rand_core::RngCore;
+use ulid::Ulid;
+
+use crate::{Pagination, pagination::Page, repository_impl};
+
+/// Filter parameters for listing user notes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct UserNoteFilter<'a> {
+    user: Option<&'a User>,
+}
+
+impl<'a> UserNoteFilter<'a> {
+    /// Create a new [`UserNoteFilter`] with default values
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Filter for notes left on a specific user
+    #[must_use]
+    pub fn for_user(mut self, user: &'a User) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    /// Get the user filter
+    ///
+    /// Returns [`None`] if no user filter is set
+    #[must_use]
+    pub fn user(&self) -> Option<&User> {
+        self.user
+    }
+}
+
+/// A [`UserNoteRepository`] helps interacting with [`UserNote`] saved in the
+/// storage backend
+#[async_trait]
+pub trait UserNoteRepository: Send + Sync {
+    /// The error type returned by the repository
+    type Error;
+
+    /// Lookup an [`UserNote`] by its ID
+    ///
+    /// Returns `None` if no [`UserNote`] was found
+    ///
+    /// # Parameters
+    ///
+    /// * `id`: The ID of the [`UserNote`] to lookup
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserNote>, Self::Error>;
+
+    /// Get the most recent [`UserNote`] left on a [`User`]
+    ///
+    /// Returns `None` if the [`User`] has no notes
+    ///
+    /// # Parameters
+    ///
+    /// * `user`: The [`User`] for whom to lookup the [`UserNote`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn latest(&mut self, user: &User) -> Result<Option<UserNote>, Self::Error>;
+
+    /// List [`UserNote`] with the given filter and pagination
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    /// * `pagination`: The pagination parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list(
+        &mut self,
+        filter: UserNoteFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<UserNote>, Self::Error>;
+
+    /// Count the [`UserNote`] with the given filter
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count(&mut self, filter: UserNoteFilter<'_>) -> Result<usize, Self::Error>;
+
+    /// Leave a note on a [`User`]
+    ///
+    /// Returns the newly created [`UserNote`]
+    ///
+    /// # Parameters
+    ///
+    /// * `rng`: The random number generator to use
+    /// * `clock`: The clock used to generate timestamps
+    /// * `user`: The [`User`] on whom to leave the note
+    /// * `author`: The administrator writing the note, if any
+    /// * `content`: The content of the note
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        author: Option<&User>,
+        content: String,
+    ) -> Result<UserNote, Self::Error>;
+
+    /// Delete a [`UserNote`]
+    ///
+    /// # Parameters
+    ///
+    /// * `note`: The [`UserNote`] to delete
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn remove(&mut self, note: UserNote) -> Result<(), Self::Error>;
+}
+
+repository_impl!(UserNoteRepository:
+    async fn lookup(&mut self, id: Ulid) -> Result<Option<UserNote>, Self::Error>;
+
+    async fn latest(&mut self, user: &User) -> Result<Option<UserNote>, Self::Error>;
+
+    async fn list(
+        &mut self,
+        filter: UserNoteFilter<'_>,
+        pagination: Pagination,
+    ) -> Result<Page<UserNote>, Self::Error>;
+
+    async fn count(&mut self, filter: UserNoteFilter<'_>) -> Result<usize, Self::Error>;
+
+    async fn add(
+        &mut self,
+        rng: &mut (dyn RngCore + Send),
+        clock: &dyn Clock,
+        user: &User,
+        author: Option<&User>,
+        content: String,
+    ) -> Result<UserNote, Self::Error>;
+
+    async fn remove(&mut self, note: UserNote) -> Result<(), Self::Error>;
+);
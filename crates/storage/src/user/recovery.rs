@@ -80,6 +80,23 @@ pub trait UserRecoveryRepository: Send + Sync {
         ticket: &str,
     ) -> Result<Option<UserRecoveryTicket>, Self::Error>;
 
+    /// Find the most recent [`UserRecoveryTicket`] created for the given
+    /// [`UserEmail`], across all recovery sessions
+    ///
+    /// Returns `None` if no ticket was ever created for this email
+    ///
+    /// # Parameters
+    ///
+    /// * `user_email`: The [`UserEmail`] to lookup the latest ticket for
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn find_latest_ticket_for_email(
+        &mut self,
+        user_email: &UserEmail,
+    ) -> Result<Option<UserRecoveryTicket>, Self::Error>;
+
     /// Add a [`UserRecoveryTicket`] to the given [`UserRecoverySession`] for
     /// the given [`UserEmail`]
     ///
@@ -141,6 +158,11 @@ repository_impl!(UserRecoveryRepository:
         ticket: &str,
     ) -> Result<Option<UserRecoveryTicket>, Self::Error>;
 
+    async fn find_latest_ticket_for_email(
+        &mut self,
+        user_email: &UserEmail,
+    ) -> Result<Option<UserRecoveryTicket>, Self::Error>;
+
     async fn add_ticket(
         &mut self,
         rng: &mut (dyn RngCore + Send),
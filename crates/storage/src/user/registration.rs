@@ -6,6 +6,7 @@
 use std::net::IpAddr;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use mas_data_model::{Clock, UserEmailAuthentication, UserRegistration, UserRegistrationToken};
 use rand_core::RngCore;
 use ulid::Ulid;
@@ -13,6 +14,22 @@ use url::Url;
 
 use crate::repository_impl;
 
+/// Accounts registered from the same IP address in quick succession
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistrationBurst {
+    /// The IP address the accounts were registered from
+    pub ip_address: IpAddr,
+
+    /// When the first account of the burst was registered
+    pub first_registered_at: DateTime<Utc>,
+
+    /// When the last account of the burst was registered
+    pub last_registered_at: DateTime<Utc>,
+
+    /// The IDs and usernames of the accounts, in registration order
+    pub users: Vec<(Ulid, String)>,
+}
+
 /// A [`UserRegistrationRepository`] helps interacting with [`UserRegistration`]
 /// saved in the storage backend
 #[async_trait]
@@ -175,6 +192,53 @@ pub trait UserRegistrationRepository: Send + Sync {
         clock: &dyn Clock,
         user_registration: UserRegistration,
     ) -> Result<UserRegistration, Self::Error>;
+
+    /// Count the registrations completed in the given time window by the IP
+    /// address they were made from
+    ///
+    /// Returns the IP addresses with the most registrations first, along with
+    /// their number of registrations
+    ///
+    /// # Parameters
+    ///
+    /// * `since`: Only count registrations completed at or after this time
+    /// * `until`: Only count registrations completed before this time
+    /// * `limit`: The maximum number of IP addresses to return
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn count_by_ip_address(
+        &mut self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<(IpAddr, usize)>, Self::Error>;
+
+    /// List the bursts of registrations completed from the same IP address in
+    /// the given time window
+    ///
+    /// Two registrations from the same IP address are part of the same burst
+    /// if they were completed less than `interval` apart. Only bursts of at
+    /// least two registrations are returned, the biggest ones first.
+    ///
+    /// # Parameters
+    ///
+    /// * `since`: Only consider registrations completed at or after this time
+    /// * `until`: Only consider registrations completed before this time
+    /// * `interval`: The maximum time between two registrations of a burst
+    /// * `limit`: The maximum number of bursts to return
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn list_bursts(
+        &mut self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        interval: chrono::Duration,
+        limit: usize,
+    ) -> Result<Vec<RegistrationBurst>, Self::Error>;
 }
 
 repository_impl!(UserRegistrationRepository:
@@ -219,4 +283,17 @@ repository_impl!(UserRegistrationRepository:
         clock: &dyn Clock,
         user_registration: UserRegistration,
     ) -> Result<UserRegistration, Self::Error>;
+    async fn count_by_ip_address(
+        &mut self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<(IpAddr, usize)>, Self::Error>;
+    async fn list_bursts(
+        &mut self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        interval: chrono::Duration,
+        limit: usize,
+    ) -> Result<Vec<RegistrationBurst>, Self::Error>;
 );
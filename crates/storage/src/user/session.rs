@@ -42,6 +42,7 @@ pub struct BrowserSessionFilter<'a> {
     last_active_before: Option<DateTime<Utc>>,
     last_active_after: Option<DateTime<Utc>>,
     authenticated_by_upstream_sessions: Option<UpstreamOAuthSessionFilter<'a>>,
+    excluded_id: Option<Ulid>,
 }
 
 impl<'a> BrowserSessionFilter<'a> {
@@ -94,6 +95,21 @@ impl<'a> BrowserSessionFilter<'a> {
         self.last_active_after
     }
 
+    /// Exclude the session with the given ID
+    #[must_use]
+    pub fn excluding(mut self, id: Ulid) -> Self {
+        self.excluded_id = Some(id);
+        self
+    }
+
+    /// Get the ID of the session excluded by this filter
+    ///
+    /// Returns [`None`] if no session is excluded
+    #[must_use]
+    pub fn excluded_id(&self) -> Option<Ulid> {
+        self.excluded_id
+    }
+
     /// Only return active browser sessions
     #[must_use]
     pub fn active_only(mut self) -> Self {
@@ -210,6 +226,25 @@ pub trait BrowserSessionRepository: Send + Sync {
         filter: BrowserSessionFilter<'_>,
     ) -> Result<usize, Self::Error>;
 
+    /// Move all the [`BrowserSession`] matching the given filter to another
+    /// user
+    ///
+    /// Returns the number of sessions affected
+    ///
+    /// # Parameters
+    ///
+    /// * `filter`: The filter parameters
+    /// * `user`: The user which should now own the sessions
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Self::Error`] if the underlying repository fails
+    async fn transfer_bulk(
+        &mut self,
+        filter: BrowserSessionFilter<'_>,
+        user: &User,
+    ) -> Result<usize, Self::Error>;
+
     /// List [`BrowserSession`] with the given filter and pagination
     ///
     /// # Parameters
@@ -330,6 +365,12 @@ repository_impl!(BrowserSessionRepository:
         filter: BrowserSessionFilter<'_>,
     ) -> Result<usize, Self::Error>;
 
+    async fn transfer_bulk(
+        &mut self,
+        filter: BrowserSessionFilter<'_>,
+        user: &User,
+    ) -> Result<usize, Self::Error>;
+
     async fn list(
         &mut self,
         filter: BrowserSessionFilter<'_>,
@@ -18,6 +18,7 @@ use std::{
 
 use chrono::{DateTime, Utc};
 use futures_util::{FutureExt, TryStreamExt, future::BoxFuture};
+use mas_data_model::Clock;
 use sqlx::{Executor, PgConnection, query, query_as};
 use thiserror::Error;
 use thiserror_ext::{Construct, ContextInto};
@@ -243,6 +244,7 @@ pub struct MasWriter {
     conn: LockedMasDatabase,
     writer_pool: WriterConnectionPool,
     dry_run: bool,
+    run_id: Uuid,
 
     indices_to_restore: Vec<IndexDescription>,
     constraints_to_restore: Vec<ConstraintDescription>,
@@ -268,6 +270,9 @@ pub struct MasNewUser {
     /// Although MAS doesn't support guest access, it's still useful to track
     /// for the future.
     pub is_guest: bool,
+    /// The ID of the syn2mas run migrating this user, see
+    /// [`MasWriter::run_id`]
+    pub syn2mas_run_id: Uuid,
 }
 
 impl WriteBatch for MasNewUser {
@@ -285,6 +290,7 @@ impl WriteBatch for MasNewUser {
         let mut deactivated_ats: Vec<Option<DateTime<Utc>>> = Vec::with_capacity(batch.len());
         let mut can_request_admins: Vec<bool> = Vec::with_capacity(batch.len());
         let mut is_guests: Vec<bool> = Vec::with_capacity(batch.len());
+        let mut syn2mas_run_ids: Vec<Uuid> = Vec::with_capacity(batch.len());
         for MasNewUser {
             user_id,
             username,
@@ -293,6 +299,7 @@ impl WriteBatch for MasNewUser {
             deactivated_at,
             can_request_admin,
             is_guest,
+            syn2mas_run_id,
         } in batch
         {
             user_ids.push(user_id.get());
@@ -302,6 +309,7 @@ impl WriteBatch for MasNewUser {
             deactivated_ats.push(deactivated_at);
             can_request_admins.push(can_request_admin);
             is_guests.push(is_guest);
+            syn2mas_run_ids.push(syn2mas_run_id);
         }
 
         sqlx::query!(
@@ -310,12 +318,14 @@ impl WriteBatch for MasNewUser {
               user_id, username,
               created_at, locked_at,
               deactivated_at,
-              can_request_admin, is_guest)
+              can_request_admin, is_guest,
+              syn2mas_run_id)
             SELECT * FROM UNNEST(
               $1::UUID[], $2::TEXT[],
               $3::TIMESTAMP WITH TIME ZONE[], $4::TIMESTAMP WITH TIME ZONE[],
               $5::TIMESTAMP WITH TIME ZONE[],
-              $6::BOOL[], $7::BOOL[])
+              $6::BOOL[], $7::BOOL[],
+              $8::UUID[])
             "#,
             &user_ids[..],
             &usernames[..],
@@ -325,6 +335,7 @@ impl WriteBatch for MasNewUser {
             &deactivated_ats[..] as &[Option<DateTime<Utc>>],
             &can_request_admins[..],
             &is_guests[..],
+            &syn2mas_run_ids[..],
         )
         .execute(&mut *conn)
         .await
@@ -785,6 +796,9 @@ pub async fn is_syn2mas_in_progress(conn: &mut PgConnection) -> Result<bool, Err
 impl MasWriter {
     /// Creates a new MAS writer.
     ///
+    /// This records a new syn2mas run with the given ID in the database. If a
+    /// previous run was interrupted, its record is kept as unfinished.
+    ///
     /// # Errors
     ///
     /// Errors are returned in the following conditions:
@@ -795,6 +809,8 @@ impl MasWriter {
         mut conn: LockedMasDatabase,
         mut writer_connections: Vec<PgConnection>,
         dry_run: bool,
+        run_id: Uuid,
+        clock: &dyn Clock,
     ) -> Result<Self, Error> {
         // Given that we don't have any concurrent transactions here,
         // the READ COMMITTED isolation level is sufficient.
@@ -889,6 +905,19 @@ impl MasWriter {
             }
         }
 
+        query!(
+            r#"
+            INSERT INTO syn2mas_runs (syn2mas_run_id, started_at, dry_run)
+            VALUES ($1, $2, $3)
+            "#,
+            run_id,
+            clock.now(),
+            dry_run,
+        )
+        .execute(conn.as_mut())
+        .await
+        .into_database("failed to record syn2mas run")?;
+
         query("COMMIT;")
             .execute(conn.as_mut())
             .await
@@ -905,6 +934,7 @@ impl MasWriter {
         Ok(Self {
             conn,
             dry_run,
+            run_id,
             writer_pool: WriterConnectionPool::new(writer_connections),
             indices_to_restore,
             constraints_to_restore,
@@ -912,6 +942,13 @@ impl MasWriter {
         })
     }
 
+    /// The ID of the syn2mas run this writer records, which has to be set on
+    /// the migrated users
+    #[must_use]
+    pub fn run_id(&self) -> Uuid {
+        self.run_id
+    }
+
     #[tracing::instrument(skip_all)]
     async fn pause_indices(
         conn: &mut PgConnection,
@@ -972,13 +1009,20 @@ impl MasWriter {
     /// Finish writing to the MAS database, flushing and committing all changes.
     /// It returns the unlocked underlying connection.
     ///
+    /// This also marks the syn2mas run as finished, recording how many users
+    /// and sessions were migrated.
+    ///
     /// # Errors
     ///
     /// Errors are returned in the following conditions:
     ///
     /// - If the database connection experiences an error.
     #[tracing::instrument(skip_all)]
-    pub async fn finish(mut self, progress: &Progress) -> Result<PgConnection, Error> {
+    pub async fn finish(
+        mut self,
+        progress: &Progress,
+        clock: &dyn Clock,
+    ) -> Result<PgConnection, Error> {
         self.write_buffer_finish_checker.check_all_finished()?;
 
         // Commit all writer transactions to the database.
@@ -1010,6 +1054,23 @@ impl MasWriter {
             .await
             .into_database("could not revert temporary tables")?;
 
+        // The MAS database was empty before the migration, so all the compat
+        // sessions come from this run
+        query!(
+            r#"
+            UPDATE syn2mas_runs
+            SET finished_at = $2
+              , user_count = (SELECT COUNT(*) FROM users WHERE syn2mas_run_id = $1)
+              , compat_session_count = (SELECT COUNT(*) FROM compat_sessions)
+            WHERE syn2mas_run_id = $1
+            "#,
+            self.run_id,
+            clock.now(),
+        )
+        .execute(self.conn.as_mut())
+        .await
+        .into_database("failed to record the end of the syn2mas run")?;
+
         // If we're in dry-run mode, truncate all the tables we've written to
         if self.dry_run {
             warn!("Migration ran in dry-run mode, deleting all imported data");
@@ -1104,6 +1165,7 @@ mod test {
 
     use chrono::DateTime;
     use futures_util::TryStreamExt;
+    use mas_data_model::clock::MockClock;
     use serde::Serialize;
     use sqlx::{Column, PgConnection, PgPool, Row};
     use uuid::{NonNilUuid, Uuid};
@@ -1138,6 +1200,9 @@ mod test {
 
     const SKIPPED_TABLES: &[&str] = &["_sqlx_migrations"];
 
+    /// The ID of the syn2mas run recorded by the [`MasWriter`] in tests
+    const RUN_ID: Uuid = Uuid::from_u128(0x5EED);
+
     /// Produces a serialisable snapshot of a database, usable for snapshot
     /// testing
     ///
@@ -1216,9 +1281,15 @@ mod test {
             .await
             .expect("failed to lock MAS database")
             .expect_left("MAS database is already locked");
-        MasWriter::new(locked_main_conn, writer_conns, false)
-            .await
-            .expect("failed to construct MasWriter")
+        MasWriter::new(
+            locked_main_conn,
+            writer_conns,
+            false,
+            RUN_ID,
+            &MockClock::default(),
+        )
+        .await
+        .expect("failed to construct MasWriter")
     }
 
     /// Tests writing a single user, without a password.
@@ -1238,6 +1309,7 @@ mod test {
                     deactivated_at: None,
                     can_request_admin: false,
                     is_guest: false,
+                    syn2mas_run_id: RUN_ID,
                 },
             )
             .await
@@ -1249,7 +1321,7 @@ mod test {
             .expect("failed to finish MasWriter");
 
         let mut conn = writer
-            .finish(&Progress::default())
+            .finish(&Progress::default(), &MockClock::default())
             .await
             .expect("failed to finish MasWriter");
 
@@ -1277,6 +1349,7 @@ mod test {
                     deactivated_at: None,
                     can_request_admin: false,
                     is_guest: false,
+                    syn2mas_run_id: RUN_ID,
                 },
             )
             .await
@@ -1305,7 +1378,7 @@ mod test {
             .expect("failed to finish MasWriteBuffer");
 
         let mut conn = writer
-            .finish(&Progress::default())
+            .finish(&Progress::default(), &MockClock::default())
             .await
             .expect("failed to finish MasWriter");
 
@@ -1331,6 +1404,7 @@ mod test {
                     deactivated_at: None,
                     can_request_admin: false,
                     is_guest: false,
+                    syn2mas_run_id: RUN_ID,
                 },
             )
             .await
@@ -1359,7 +1433,7 @@ mod test {
             .expect("failed to finish email buffer");
 
         let mut conn = writer
-            .finish(&Progress::default())
+            .finish(&Progress::default(), &MockClock::default())
             .await
             .expect("failed to finish MasWriter");
 
@@ -1386,6 +1460,7 @@ mod test {
                     deactivated_at: None,
                     can_request_admin: false,
                     is_guest: false,
+                    syn2mas_run_id: RUN_ID,
                 },
             )
             .await
@@ -1414,7 +1489,7 @@ mod test {
             .expect("failed to finish threepid buffer");
 
         let mut conn = writer
-            .finish(&Progress::default())
+            .finish(&Progress::default(), &MockClock::default())
             .await
             .expect("failed to finish MasWriter");
 
@@ -1442,6 +1517,7 @@ mod test {
                     deactivated_at: None,
                     can_request_admin: false,
                     is_guest: false,
+                    syn2mas_run_id: RUN_ID,
                 },
             )
             .await
@@ -1471,7 +1547,7 @@ mod test {
             .expect("failed to finish link buffer");
 
         let mut conn = writer
-            .finish(&Progress::default())
+            .finish(&Progress::default(), &MockClock::default())
             .await
             .expect("failed to finish MasWriter");
 
@@ -1497,6 +1573,7 @@ mod test {
                     deactivated_at: None,
                     can_request_admin: false,
                     is_guest: false,
+                    syn2mas_run_id: RUN_ID,
                 },
             )
             .await
@@ -1530,7 +1607,7 @@ mod test {
             .expect("failed to finish session buffer");
 
         let mut conn = writer
-            .finish(&Progress::default())
+            .finish(&Progress::default(), &MockClock::default())
             .await
             .expect("failed to finish MasWriter");
 
@@ -1557,6 +1634,7 @@ mod test {
                     deactivated_at: None,
                     can_request_admin: false,
                     is_guest: false,
+                    syn2mas_run_id: RUN_ID,
                 },
             )
             .await
@@ -1608,7 +1686,7 @@ mod test {
             .expect("failed to finish token buffer");
 
         let mut conn = writer
-            .finish(&Progress::default())
+            .finish(&Progress::default(), &MockClock::default())
             .await
             .expect("failed to finish MasWriter");
 
@@ -1637,6 +1715,7 @@ mod test {
                     deactivated_at: None,
                     can_request_admin: false,
                     is_guest: false,
+                    syn2mas_run_id: RUN_ID,
                 },
             )
             .await
@@ -1706,7 +1785,7 @@ mod test {
             .expect("failed to finish refresh token buffer");
 
         let mut conn = writer
-            .finish(&Progress::default())
+            .finish(&Progress::default(), &MockClock::default())
             .await
             .expect("failed to finish MasWriter");
 
@@ -16,7 +16,7 @@ use std::time::Instant;
 use chrono::{DateTime, Utc};
 use compact_str::CompactString;
 use futures_util::{SinkExt, StreamExt as _, TryFutureExt, TryStreamExt as _};
-use mas_data_model::Clock;
+use mas_data_model::{Clock, Device};
 use rand::{RngCore, SeedableRng};
 use thiserror::Error;
 use thiserror_ext::ContextInto;
@@ -36,6 +36,7 @@ use crate::{
     synapse_reader::{
         self, ExtractLocalpartError, FullUserId, SynapseAccessToken, SynapseDevice,
         SynapseExternalId, SynapseRefreshableTokenPair, SynapseThreepid, SynapseUser,
+        SynapseUserIp,
     },
 };
 
@@ -196,7 +197,7 @@ pub async fn migrate(
         .await
         .into_synapse("failed to close Synapse reader")?;
 
-    mas.finish(progress)
+    mas.finish(progress, clock)
         .await
         .into_mas("failed to finalise MAS database")?;
 
@@ -219,6 +220,7 @@ async fn migrate_users(
     // create a new RNG seeded from the passed RNG so that we can move it into the
     // spawned task
     let mut rng = rand_chacha::ChaChaRng::from_rng(rng).expect("failed to seed rng");
+    let run_id = mas.run_id();
     let task = tokio::spawn(
         async move {
             let mut user_buffer = MasWriteBuffer::new(&mas);
@@ -239,7 +241,7 @@ async fn migrate_users(
                 }
 
                 let (mas_user, mas_password_opt) =
-                    transform_user(&user, &state.server_name, &mut rng)?;
+                    transform_user(&user, &state.server_name, run_id, &mut rng)?;
 
                 let mut flags = UserFlags::empty();
                 if bool::from(user.admin) {
@@ -571,6 +573,23 @@ async fn migrate_devices(
     let start = Instant::now();
     let progress_counter_ = progress_counter.clone();
 
+    // Synapse doesn't always copy the last activity of a device from
+    // `user_ips` to `devices`, so fill the gaps from there. This only holds
+    // the latest row of the devices lacking it, so it stays small compared to
+    // the other migration state.
+    let mut user_ips: HashMap<(CompactString, CompactString), SynapseUserIp> = synapse
+        .read_user_ips()
+        .map_ok(|user_ip| {
+            let key = (
+                CompactString::new(&user_ip.user_id.0),
+                CompactString::new(&user_ip.device_id),
+            );
+            (key, user_ip)
+        })
+        .try_collect()
+        .await
+        .into_synapse("reading user IPs")?;
+
     let (tx, mut rx) = tokio::sync::mpsc::channel(100 * 1024);
 
     // create a new RNG seeded from the passed RNG so that we can move it into the
@@ -589,6 +608,20 @@ async fn migrate_devices(
                     ip,
                     user_agent,
                 } = device;
+
+                let user_ip = user_ips.remove(&(
+                    CompactString::new(&synapse_user_id.0),
+                    CompactString::new(&device_id),
+                ));
+                let (last_seen, ip, user_agent) = match user_ip {
+                    Some(user_ip) => (
+                        last_seen.or(Some(user_ip.last_seen)),
+                        ip.or(Some(user_ip.ip)),
+                        user_agent.or(Some(user_ip.user_agent)),
+                    ),
+                    None => (last_seen, ip, user_agent),
+                };
+
                 let username = synapse_user_id
                     .extract_localpart(&state.server_name)
                     .into_extract_localpart(synapse_user_id.clone())?
@@ -612,6 +645,17 @@ async fn migrate_devices(
                     continue;
                 }
 
+                if let Err(e) = Device::try_from(device_id.clone()) {
+                    tracing::warn!(
+                        error = &e as &dyn std::error::Error,
+                        mxid = %synapse_user_id,
+                        %device_id,
+                        "Device has an invalid device ID, skipping"
+                    );
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
                 let session_id = *state
                     .devices_to_compat_sessions
                     .entry((mas_user_id, CompactString::new(&device_id)))
@@ -756,6 +800,14 @@ async fn migrate_unrefreshable_access_tokens(
                 // fallback.
                 let created_at = last_validated.map_or_else(|| now, DateTime::from);
 
+                if let Some(device_id) = &device_id
+                    && Device::try_from(device_id.clone()).is_err()
+                {
+                    // The device will be skipped when migrating devices, so skip its tokens too
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
                 let session_id = if let Some(device_id) = device_id {
                     // Use the existing device_id if this is the second token for a device
                     *state
@@ -909,6 +961,12 @@ async fn migrate_refreshable_token_pairs(
                 // It's not always accurate, but last_validated is *often* the creation time of
                 // the device If we don't have one, then use the current time as a
                 // fallback.
+                if Device::try_from(device_id.clone()).is_err() {
+                    // The device will be skipped when migrating devices, so skip its tokens too
+                    progress_counter.increment_skipped();
+                    continue;
+                }
+
                 let created_at = last_validated.map_or_else(|| now, DateTime::from);
 
                 // Use the existing device_id if this is the second token for a device
@@ -994,6 +1052,7 @@ async fn migrate_refreshable_token_pairs(
 fn transform_user(
     user: &SynapseUser,
     server_name: &str,
+    syn2mas_run_id: Uuid,
     rng: &mut impl RngCore,
 ) -> Result<(MasNewUser, Option<MasNewUserPassword>), Error> {
     let username = user
@@ -1017,6 +1076,7 @@ fn transform_user(
         deactivated_at: bool::from(user.deactivated).then_some(user.creation_ts.into()),
         can_request_admin: bool::from(user.admin),
         is_guest: bool::from(user.is_guest),
+        syn2mas_run_id,
     };
 
     let mas_password = user
@@ -9,10 +9,12 @@
 //! running the Synapse-to-MAS migration.
 
 use figment::Figment;
+use futures_util::TryStreamExt as _;
 use mas_config::{
     BrandingConfig, CaptchaConfig, ConfigurationSection, ConfigurationSectionExt, MatrixConfig,
     PasswordAlgorithm, PasswordsConfig, UpstreamOAuth2Config,
 };
+use mas_data_model::Device;
 use sqlx::{PgConnection, prelude::FromRow, query_as, query_scalar};
 use thiserror::Error;
 
@@ -130,6 +132,11 @@ pub enum CheckWarning {
         "Synapse database contains {num_non_email_3pids} non-email 3PIDs (probably phone numbers), which will be migrated but are not supported by MAS."
     )]
     NonEmailThreepidsInDatabase { num_non_email_3pids: i64 },
+
+    #[error(
+        "Synapse database contains {num_invalid_device_ids} devices with an ID which is not valid in MAS. These devices and their access tokens will not be migrated."
+    )]
+    InvalidDeviceIdsInDatabase { num_invalid_device_ids: usize },
 }
 
 /// Check that the Synapse configuration is sane for migration.
@@ -199,9 +206,9 @@ pub async fn synapse_config_check_against_mas_config(
     // Look for the MAS password hashing scheme that will be used for imported
     // Synapse passwords, then check the configuration matches so that Synapse
     // passwords will be compatible with MAS.
-    if let Some((_, algorithm, _, secret, _)) = mas_password_schemes
+    if let Some((_, algorithm, _, _, secret, _)) = mas_password_schemes
         .iter()
-        .find(|(version, _, _, _, _)| *version == MIGRATED_PASSWORD_VERSION)
+        .find(|(version, _, _, _, _, _)| *version == MIGRATED_PASSWORD_VERSION)
     {
         if algorithm != &PasswordAlgorithm::Bcrypt {
             errors.push(CheckError::PasswordSchemeNotBcrypt);
@@ -284,6 +291,13 @@ pub async fn synapse_database_check(
         });
     }
 
+    let num_invalid_device_ids = count_invalid_device_ids(&mut *synapse_connection).await?;
+    if num_invalid_device_ids > 0 {
+        warnings.push(CheckWarning::InvalidDeviceIdsInDatabase {
+            num_invalid_device_ids,
+        });
+    }
+
     let oauth_provider_user_counts = query_as::<_, UpstreamOAuthProvider>(
         "
         SELECT auth_provider, COUNT(*) AS num_users
@@ -336,3 +350,26 @@ pub async fn synapse_database_check(
 
     Ok((warnings, errors))
 }
+
+/// Count the devices in the Synapse database which have a device ID that MAS
+/// would reject. Hidden devices are skipped, as they are not migrated anyway.
+async fn count_invalid_device_ids(synapse_connection: &mut PgConnection) -> Result<usize, Error> {
+    let num_invalid_device_ids = query_scalar::<_, String>(
+        "
+        SELECT device_id
+        FROM devices
+        WHERE NOT hidden AND device_id != 'guest_device'
+        ",
+    )
+    .fetch(synapse_connection)
+    .try_fold(0, |count, device_id| async move {
+        Ok(if Device::try_from(device_id).is_err() {
+            count + 1
+        } else {
+            count
+        })
+    })
+    .await?;
+
+    Ok(num_invalid_device_ids)
+}
@@ -180,6 +180,9 @@ impl Config {
                     version: 1,
                     algorithm: PasswordAlgorithm::Bcrypt,
                     cost: self.bcrypt_rounds,
+                    memory: None,
+                    iterations: None,
+                    parallelism: None,
                     secret: self.password_config.pepper,
                     secret_file: None,
                     unicode_normalization: true,
@@ -190,6 +193,9 @@ impl Config {
                     version: 2,
                     algorithm: PasswordAlgorithm::default(),
                     cost: None,
+                    memory: None,
+                    iterations: None,
+                    parallelism: None,
                     secret: None,
                     secret_file: None,
                     unicode_normalization: false,
@@ -8,8 +8,8 @@ use std::{collections::BTreeMap, str::FromStr as _};
 use chrono::{DateTime, Utc};
 use mas_config::{
     UpstreamOAuth2ClaimsImports, UpstreamOAuth2DiscoveryMode, UpstreamOAuth2ImportAction,
-    UpstreamOAuth2OnBackchannelLogout, UpstreamOAuth2PkceMethod, UpstreamOAuth2ResponseMode,
-    UpstreamOAuth2TokenAuthMethod,
+    UpstreamOAuth2OnBackchannelLogout, UpstreamOAuth2PkceMethod, UpstreamOAuth2ProviderVisibility,
+    UpstreamOAuth2ResponseMode, UpstreamOAuth2TokenAuthMethod,
 };
 use mas_iana::jose::JsonWebSignatureAlg;
 use oauth2_types::scope::{OPENID, Scope, ScopeToken};
@@ -331,6 +331,7 @@ impl OidcProvider {
             client_secret: self.client_secret,
             token_endpoint_auth_method,
             sign_in_with_apple: None,
+            private_key_jwt: None,
             token_endpoint_auth_signing_alg: None,
             id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
             scope: scope.to_string(),
@@ -347,6 +348,8 @@ impl OidcProvider {
             additional_authorization_parameters,
             forward_login_hint: self.forward_login_hint,
             on_backchannel_logout,
+            ui_order: None,
+            visibility: UpstreamOAuth2ProviderVisibility::Visible,
         })
     }
 }
@@ -0,0 +1,62 @@
+-- Copyright 2025 New Vector Ltd.
+--
+-- SPDX-License-Identifier: AGPL-3.0-only
+-- Please see LICENSE files in the repository root for full details.
+
+-- A device which was never updated with its last activity
+INSERT INTO devices
+  (
+    user_id,
+    device_id,
+    display_name,
+    last_seen,
+    ip,
+    user_agent,
+    hidden
+  )
+  VALUES
+  (
+    '@alice:example.com',
+    'BDEVICE',
+    'Element Web',
+    NULL,
+    NULL,
+    NULL,
+    FALSE
+  );
+
+INSERT INTO user_ips
+  (
+    user_id,
+    access_token,
+    device_id,
+    ip,
+    user_agent,
+    last_seen
+  )
+  VALUES
+  (
+    '@alice:example.com',
+    'syt_AmReGyAtxlWwzAoJxZlYIXcUhfKnbk_cQJCvmpbdNYXsvHWrdNbP_2zCNvE',
+    'BDEVICE',
+    '203.0.113.2',
+    'Browser/4.0 (X11; ComputerOS 64; rv:512.0)',
+    1623366000000
+  ),
+  (
+    '@alice:example.com',
+    'syt_AmReGyAtxlWwzAoJxZlYIXcUhfKnbk_cQJCvmpbdNYXsvHWrdNbP_2zCNvE',
+    'BDEVICE',
+    '203.0.113.3',
+    'Browser/5.0 (X12; ComputerOS 64; rv:1024.0)',
+    1623452400000
+  ),
+  -- This device already has its last activity in the `devices` table
+  (
+    '@alice:example.com',
+    'syt_AmReGyAtxlWwzAoJxZlYIXcUhfKnbk_cQJCvmpbdNYXsvHWrdNbP_2zCNvE',
+    'ADEVICE',
+    '203.0.113.1',
+    'Browser/5.0 (X12; ComputerOS 64; rv:1024.0)',
+    1623366000000
+  );
@@ -225,6 +225,16 @@ pub struct SynapseDevice {
     pub user_agent: Option<String>,
 }
 
+/// Most recent row of the `user_ips` table in Synapse for a device.
+#[derive(Clone, Debug, FromRow, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SynapseUserIp {
+    pub user_id: FullUserId,
+    pub device_id: String,
+    pub ip: String,
+    pub user_agent: String,
+    pub last_seen: MillisecondsTimestamp,
+}
+
 /// Row of the `access_tokens` table in Synapse.
 #[derive(Clone, Debug, FromRow, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SynapseAccessToken {
@@ -256,6 +266,7 @@ const TABLES_TO_LOCK: &[&str] = &[
     "user_threepids",
     "user_external_ids",
     "devices",
+    "user_ips",
     "access_tokens",
     "refresh_tokens",
 ];
@@ -485,6 +496,32 @@ impl<'conn> SynapseReader<'conn> {
         .map_err(|err| err.into_database("reading Synapse devices"))
     }
 
+    /// Reads the most recent `user_ips` row of each device which lacks its last
+    /// activity in the `devices` table.
+    ///
+    /// Rows are deduplicated by Postgres, so the stream holds at most one row
+    /// per device, sorted by user and device ID.
+    pub fn read_user_ips(&mut self) -> impl Stream<Item = Result<SynapseUserIp, Error>> + '_ {
+        sqlx::query_as(
+            "
+            SELECT DISTINCT ON (user_ips.user_id, user_ips.device_id)
+              user_ips.user_id, user_ips.device_id, user_ips.ip, user_ips.user_agent,
+              user_ips.last_seen
+            FROM user_ips
+            INNER JOIN devices USING (user_id, device_id)
+            WHERE NOT devices.hidden
+              AND (
+                devices.last_seen IS NULL
+                OR devices.ip IS NULL
+                OR devices.user_agent IS NULL
+              )
+            ORDER BY user_ips.user_id, user_ips.device_id, user_ips.last_seen DESC
+            ",
+        )
+        .fetch(&mut *self.txn)
+        .map_err(|err| err.into_database("reading Synapse user IPs"))
+    }
+
     /// Reads unrefreshable access tokens from the Synapse database.
     /// This does not include access tokens used for puppetting users, as those
     /// are not supported by MAS.
@@ -557,7 +594,7 @@ mod test {
         SynapseReader,
         synapse_reader::{
             SynapseAccessToken, SynapseDevice, SynapseExternalId, SynapseRefreshableTokenPair,
-            SynapseThreepid, SynapseUser,
+            SynapseThreepid, SynapseUser, SynapseUserIp,
         },
     };
 
@@ -627,6 +664,25 @@ mod test {
         assert_debug_snapshot!(devices);
     }
 
+    #[sqlx::test(
+        migrator = "MIGRATOR",
+        fixtures("user_alice", "devices_alice", "user_ips_alice")
+    )]
+    async fn test_read_user_ips(pool: PgPool) {
+        let mut conn = pool.acquire().await.expect("failed to get connection");
+        let mut reader = SynapseReader::new(&mut conn, false)
+            .await
+            .expect("failed to make SynapseReader");
+
+        let user_ips: BTreeSet<SynapseUserIp> = reader
+            .read_user_ips()
+            .try_collect()
+            .await
+            .expect("failed to read Synapse user IPs");
+
+        assert_debug_snapshot!(user_ips);
+    }
+
     #[sqlx::test(
         migrator = "MIGRATOR",
         fixtures("user_alice", "devices_alice", "access_token_alice")
@@ -0,0 +1,138 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use std::sync::Mutex;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mas_http::RequestBuilderExt as _;
+use mas_storage::queue::{QueueJobRepositoryExt as _, SendAuditEventJob};
+use tracing::{info, warn};
+
+use crate::{
+    State,
+    new_queue::{JobContext, JobError, RunnableJob},
+};
+
+/// How many times we try to deliver an event before giving up on it
+const MAX_DELIVERY_ATTEMPTS: usize = 5;
+
+/// How many consecutive delivery failures open the circuit breaker
+const CIRCUIT_BREAKER_THRESHOLD: usize = 5;
+
+/// How long the circuit breaker stays open once tripped
+const CIRCUIT_BREAKER_COOLDOWN: Duration = Duration::minutes(5);
+
+#[derive(Debug, Default)]
+struct CircuitBreakerState {
+    consecutive_failures: usize,
+    open_until: Option<DateTime<Utc>>,
+}
+
+/// Stops trying to deliver audit events for a while after too many
+/// consecutive failures, so that an unavailable webhook doesn't keep the queue
+/// busy with retries.
+///
+/// Once the cooldown is over, the next delivery goes through: if it fails
+/// again, the breaker opens right away, and if it succeeds, it closes.
+///
+/// The state of the breaker is kept in memory, so each worker process has its
+/// own breaker: with several workers, each of them has to see the webhook
+/// failing before it stops delivering events, and restarting a worker closes
+/// its breaker.
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    state: Mutex<CircuitBreakerState>,
+}
+
+impl CircuitBreaker {
+    /// Returns when the breaker closes, if it is currently open
+    fn open_until(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let state = self.state.lock().expect("lock poisoned");
+        state.open_until.filter(|open_until| now < *open_until)
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    fn record_failure(&self, now: DateTime<Utc>) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= CIRCUIT_BREAKER_THRESHOLD {
+            state.open_until = Some(now + CIRCUIT_BREAKER_COOLDOWN);
+        }
+    }
+}
+
+/// Job to send an event to the audit webhook
+#[async_trait]
+impl RunnableJob for SendAuditEventJob {
+    #[tracing::instrument(
+        name = "job.send_audit_event",
+        fields(audit_event.id = %self.event().id),
+        skip_all,
+    )]
+    async fn run(&self, state: &State, context: JobContext) -> Result<(), JobError> {
+        let Some(config) = &state.site_config().audit_webhook else {
+            info!("The audit webhook is not configured anymore, dropping event");
+            return Ok(());
+        };
+
+        let clock = state.clock();
+        let breaker = state.audit_circuit_breaker();
+
+        // Don't try to deliver the event while the breaker is open, as the
+        // webhook is most likely still down: postpone it until the breaker
+        // closes instead of piling up retries
+        if let Some(open_until) = breaker.open_until(clock.now()) {
+            info!(%open_until, "The audit webhook circuit breaker is open, postponing the event");
+            let mut rng = state.rng();
+            let mut repo = state.repository().await.map_err(JobError::retry)?;
+            repo.queue_job()
+                .schedule_job_later(&mut rng, clock, self.clone(), open_until)
+                .await
+                .map_err(JobError::retry)?;
+            repo.save().await.map_err(JobError::retry)?;
+            return Ok(());
+        }
+
+        let mut request = state
+            .http_client()
+            .post(config.url.clone())
+            .json(self.event());
+        if let Some(secret) = &config.secret {
+            request = request.bearer_auth(secret);
+        }
+
+        let result = async {
+            request.send_traced().await?.error_for_status()?;
+            Ok::<_, reqwest::Error>(())
+        }
+        .await
+        .context("Failed to deliver the event to the audit webhook");
+
+        if let Err(e) = result {
+            breaker.record_failure(clock.now());
+
+            if context.attempt + 1 >= MAX_DELIVERY_ATTEMPTS {
+                warn!(
+                    attempts = context.attempt + 1,
+                    "Giving up on delivering the audit event"
+                );
+                return Err(JobError::fail(e));
+            }
+
+            return Err(JobError::retry(e));
+        }
+
+        breaker.record_success();
+
+        Ok(())
+    }
+}
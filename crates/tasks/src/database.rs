@@ -6,15 +6,33 @@
 
 //! Database-related tasks
 
+use std::sync::LazyLock;
+
 use async_trait::async_trait;
+use chrono::Duration;
 use mas_storage::queue::{CleanupExpiredTokensJob, PruneStalePolicyDataJob};
+use opentelemetry::metrics::Counter;
 use tracing::{debug, info};
 
 use crate::{
-    State,
+    METER, State,
     new_queue::{JobContext, JobError, RunnableJob},
 };
 
+/// How many failed login attempts to keep for each user
+const LOGIN_FAILURES_TO_KEEP: usize = 50;
+
+/// How long to keep pushed authorization requests after they expired
+const PUSHED_AUTHORIZATION_REQUESTS_RETENTION: Duration = Duration::hours(1);
+
+static UPSTREAM_SESSIONS_PURGED: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    METER
+        .u64_counter("mas.upstream_oauth2.abandoned_sessions_purged")
+        .with_description("Number of unfinished upstream authorization sessions deleted")
+        .with_unit("{session}")
+        .build()
+});
+
 #[async_trait]
 impl RunnableJob for CleanupExpiredTokensJob {
     #[tracing::instrument(name = "job.cleanup_expired_tokens", skip_all)]
@@ -27,6 +45,28 @@ impl RunnableJob for CleanupExpiredTokensJob {
             .cleanup_revoked(clock)
             .await
             .map_err(JobError::retry)?;
+
+        // Keep a capped history of failed login attempts for each user
+        let failures_count = repo
+            .user_login_failure()
+            .prune(LOGIN_FAILURES_TO_KEEP)
+            .await
+            .map_err(JobError::retry)?;
+
+        // Delete the upstream authorization sessions users never came back from
+        let upstream_sessions_count = repo
+            .upstream_oauth_session()
+            .cleanup_abandoned(clock, state.site_config().upstream_authorization_retention)
+            .await
+            .map_err(JobError::retry)?;
+
+        // Delete the pushed authorization requests which can't be used anymore
+        let pushed_requests_count = repo
+            .oauth2_pushed_authorization_request()
+            .cleanup_expired(clock, PUSHED_AUTHORIZATION_REQUESTS_RETENTION)
+            .await
+            .map_err(JobError::retry)?;
+
         repo.save().await.map_err(JobError::retry)?;
 
         if count == 0 {
@@ -35,6 +75,31 @@ impl RunnableJob for CleanupExpiredTokensJob {
             info!(count, "cleaned up revoked tokens");
         }
 
+        if failures_count == 0 {
+            debug!("no login failure to prune");
+        } else {
+            info!(count = failures_count, "pruned old login failures");
+        }
+
+        UPSTREAM_SESSIONS_PURGED.add(upstream_sessions_count.try_into().unwrap_or(u64::MAX), &[]);
+        if upstream_sessions_count == 0 {
+            debug!("no abandoned upstream authorization session to clean up");
+        } else {
+            info!(
+                count = upstream_sessions_count,
+                "cleaned up abandoned upstream authorization sessions"
+            );
+        }
+
+        if pushed_requests_count == 0 {
+            debug!("no expired pushed authorization request to clean up");
+        } else {
+            info!(
+                count = pushed_requests_count,
+                "cleaned up expired pushed authorization requests"
+            );
+        }
+
         Ok(())
     }
 }
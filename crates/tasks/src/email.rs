@@ -4,19 +4,77 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+use std::{
+    sync::{LazyLock, Mutex},
+    time::Instant,
+};
+
+use anyhow::Context as _;
 use async_trait::async_trait;
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::{Clock, EmailProbeKind, EmailProbeResult, User};
 use mas_email::{Address, EmailVerificationContext, Mailbox};
-use mas_storage::queue::{SendEmailAuthenticationCodeJob, VerifyEmailJob};
-use mas_templates::TemplateContext as _;
-use rand::{Rng, distributions::Uniform};
-use tracing::info;
+use mas_i18n::DataLocale;
+use mas_storage::{
+    BoxRepository, RepositoryError,
+    queue::{
+        ProbeEmailDeliveryJob, SendDeactivationScheduledEmailJob, SendEmailAddedEmailJob,
+        SendEmailAuthenticationCodeJob, SendEmailChangeConfirmationJob, SendNewLoginEmailJob,
+        VerifyEmailJob,
+    },
+};
+use mas_templates::{
+    DeactivationScheduledContext, EmailAddedContext, EmailChangeConfirmationContext,
+    NewLoginContext, TemplateContext as _,
+};
+use opentelemetry::{KeyValue, metrics::Counter};
+use rand::{Rng, RngCore, distributions::Uniform};
+use tracing::{debug, error, info};
 
 use crate::{
-    State,
+    METER, State,
     new_queue::{JobContext, JobError, RunnableJob},
 };
 
+/// How long a single email delivery check can take before it is considered
+/// failed
+const EMAIL_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How many results of the email delivery checks are kept, a week worth of
+/// hourly checks
+const EMAIL_PROBE_RESULTS_TO_KEEP: usize = 7 * 24;
+
+/// Minimum interval between two errors logged about failed email delivery
+/// checks, as long as the checks keep failing
+const EMAIL_PROBE_ERROR_LOG_INTERVAL: Duration = Duration::hours(6);
+
+/// The language to use for an email sent to a user: the one they explicitly
+/// chose if any, else the one negotiated when the email was requested
+pub(crate) fn email_language(
+    user: Option<&User>,
+    negotiated: &str,
+) -> Result<DataLocale, JobError> {
+    let preferred = user
+        .and_then(|user| user.locale.as_deref())
+        .and_then(|locale| locale.parse().ok());
+
+    match preferred {
+        Some(locale) => Ok(locale),
+        None => negotiated
+            .parse()
+            .context("Invalid locale")
+            .map_err(JobError::fail),
+    }
+}
+
+static EMAIL_PROBE_FAILURES: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    METER
+        .u64_counter("mas.email.probe_failure")
+        .with_description("Number of failed email delivery checks")
+        .with_unit("{failure}")
+        .build()
+});
+
 #[async_trait]
 impl RunnableJob for VerifyEmailJob {
     #[tracing::instrument(
@@ -119,7 +177,7 @@ impl RunnableJob for SendEmailAuthenticationCodeJob {
 
         info!("Sending email verification code to {}", mailbox);
 
-        let language = self.language().parse().map_err(JobError::fail)?;
+        let language = email_language(browser_session.as_ref().map(|s| &s.user), self.language())?;
 
         let context = EmailVerificationContext::new(code, browser_session, registration)
             .with_language(language);
@@ -133,3 +191,488 @@ impl RunnableJob for SendEmailAuthenticationCodeJob {
         Ok(())
     }
 }
+
+#[async_trait]
+impl RunnableJob for SendEmailChangeConfirmationJob {
+    #[tracing::instrument(
+        name = "job.send_email_change_confirmation",
+        fields(user_email_change.id = %self.user_email_change_id()),
+        skip_all,
+    )]
+    async fn run(&self, state: &State, _context: JobContext) -> Result<(), JobError> {
+        let clock = state.clock();
+        let mailer = state.mailer();
+        let url_builder = state.url_builder();
+        let mut repo = state.repository().await.map_err(JobError::retry)?;
+
+        let change = repo
+            .user_email()
+            .lookup_change(self.user_email_change_id())
+            .await
+            .map_err(JobError::retry)?
+            .ok_or(JobError::fail(anyhow::anyhow!(
+                "User email change not found"
+            )))?;
+
+        if !change.is_pending(clock.now()) {
+            info!("Email change is not pending anymore, not sending the confirmation email");
+            return Ok(());
+        }
+
+        let user = repo
+            .user()
+            .lookup(change.user_id)
+            .await
+            .map_err(JobError::retry)?
+            .ok_or(JobError::fail(anyhow::anyhow!("User not found")))?;
+
+        // The confirmation is sent to the address the user already had, so
+        // that someone who took over a session can't swap the address silently
+        let address: Address = change.previous_email.parse().map_err(JobError::fail)?;
+        let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+        info!("Sending email change confirmation to {}", mailbox);
+
+        let language = self.language().parse().map_err(JobError::fail)?;
+
+        let url = url_builder.email_change_link(change.ticket.clone());
+        let context =
+            EmailChangeConfirmationContext::new(user, change, url).with_language(language);
+        mailer
+            .send_email_change_email(mailbox, &context)
+            .await
+            .map_err(JobError::fail)?;
+
+        repo.save().await.map_err(JobError::fail)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RunnableJob for SendDeactivationScheduledEmailJob {
+    #[tracing::instrument(
+        name = "job.send_deactivation_scheduled_email",
+        fields(user.id = %self.user_id()),
+        skip_all,
+    )]
+    async fn run(&self, state: &State, _context: JobContext) -> Result<(), JobError> {
+        let mailer = state.mailer();
+        let mut repo = state.repository().await.map_err(JobError::retry)?;
+
+        let user = repo
+            .user()
+            .lookup(self.user_id())
+            .await
+            .map_err(JobError::retry)?
+            .context("User not found")
+            .map_err(JobError::fail)?;
+
+        let Some(scheduled_at) = user.deactivation_scheduled_at else {
+            info!("The deactivation was cancelled, not sending the confirmation email");
+            return Ok(());
+        };
+
+        let language = email_language(Some(&user), self.language())?;
+
+        let emails = repo
+            .user_email()
+            .all(&user)
+            .await
+            .map_err(JobError::retry)?;
+
+        let context =
+            DeactivationScheduledContext::new(user.clone(), scheduled_at).with_language(language);
+
+        for email in emails {
+            let address: Address = email.email.parse().map_err(JobError::fail)?;
+            let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+            info!("Sending scheduled deactivation notification to {}", mailbox);
+
+            // XXX: we only log if the email fails to send, to avoid stopping the loop
+            if let Err(e) = mailer
+                .send_deactivation_scheduled_email(mailbox, &context)
+                .await
+            {
+                error!(
+                    error = &e as &dyn std::error::Error,
+                    "Failed to send scheduled deactivation notification"
+                );
+            }
+        }
+
+        repo.save().await.map_err(JobError::fail)?;
+
+        Ok(())
+    }
+}
+
+/// Job to notify a user that an email address was added to their account, on
+/// all their email addresses
+#[async_trait]
+impl RunnableJob for SendEmailAddedEmailJob {
+    #[tracing::instrument(
+        name = "job.send_email_added_email",
+        fields(user.id = %self.user_id(), user_email.id = %self.user_email_id()),
+        skip_all,
+    )]
+    async fn run(&self, state: &State, _context: JobContext) -> Result<(), JobError> {
+        let mailer = state.mailer();
+        let mut repo = state.repository().await.map_err(JobError::retry)?;
+
+        let user = repo
+            .user()
+            .lookup(self.user_id())
+            .await
+            .map_err(JobError::retry)?
+            .context("User not found")
+            .map_err(JobError::fail)?;
+
+        let Some(added_email) = repo
+            .user_email()
+            .lookup(self.user_email_id())
+            .await
+            .map_err(JobError::retry)?
+        else {
+            info!("The email address was removed in the meantime, not sending the notification");
+            return Ok(());
+        };
+
+        let language = email_language(Some(&user), self.language())?;
+
+        let emails = repo
+            .user_email()
+            .all(&user)
+            .await
+            .map_err(JobError::retry)?;
+
+        let context = EmailAddedContext::new(user.clone(), added_email).with_language(language);
+
+        for email in emails {
+            let address: Address = email.email.parse().map_err(JobError::fail)?;
+            let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+            info!("Sending email added notification to {}", mailbox);
+
+            // XXX: we only log if the email fails to send, to avoid stopping the loop
+            if let Err(e) = mailer.send_email_added_email(mailbox, &context).await {
+                error!(
+                    error = &e as &dyn std::error::Error,
+                    "Failed to send email added notification"
+                );
+            }
+        }
+
+        repo.save().await.map_err(JobError::fail)?;
+
+        Ok(())
+    }
+}
+
+/// Job to notify a user that a new session was started on their account, on
+/// all their email addresses
+#[async_trait]
+impl RunnableJob for SendNewLoginEmailJob {
+    #[tracing::instrument(
+        name = "job.send_new_login_email",
+        fields(user.id = %self.user_id()),
+        skip_all,
+    )]
+    async fn run(&self, state: &State, _context: JobContext) -> Result<(), JobError> {
+        let mailer = state.mailer();
+        let mut repo = state.repository().await.map_err(JobError::retry)?;
+
+        let user = repo
+            .user()
+            .lookup(self.user_id())
+            .await
+            .map_err(JobError::retry)?
+            .context("User not found")
+            .map_err(JobError::fail)?;
+
+        let language = email_language(Some(&user), self.language())?;
+
+        let emails = repo
+            .user_email()
+            .all(&user)
+            .await
+            .map_err(JobError::retry)?;
+
+        let context = NewLoginContext::new(
+            user.clone(),
+            self.session_name().map(ToOwned::to_owned),
+            self.revocation_link().clone(),
+        )
+        .with_language(language);
+
+        for email in emails {
+            let address: Address = email.email.parse().map_err(JobError::fail)?;
+            let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+            info!("Sending new login notification to {}", mailbox);
+
+            // XXX: we only log if the email fails to send, to avoid stopping the loop
+            if let Err(e) = mailer.send_new_login_email(mailbox, &context).await {
+                error!(
+                    error = &e as &dyn std::error::Error,
+                    "Failed to send new login notification"
+                );
+            }
+        }
+
+        repo.save().await.map_err(JobError::fail)?;
+
+        Ok(())
+    }
+}
+
+/// Limits how often failed email delivery checks are logged as errors, so that
+/// a mail server which stays down doesn't flood the logs
+#[derive(Debug, Default)]
+pub struct EmailProbeErrorLog {
+    last_logged_at: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl EmailProbeErrorLog {
+    /// Returns `true` if a failure happening at `now` should be logged as an
+    /// error
+    fn should_log(&self, now: DateTime<Utc>) -> bool {
+        let mut last_logged_at = self.last_logged_at.lock().expect("lock poisoned");
+        if last_logged_at.is_some_and(|logged_at| now < logged_at + EMAIL_PROBE_ERROR_LOG_INTERVAL)
+        {
+            return false;
+        }
+
+        *last_logged_at = Some(now);
+        true
+    }
+
+    /// Forget about the previous failures, so that the next one is logged
+    /// right away
+    fn reset(&self) {
+        *self.last_logged_at.lock().expect("lock poisoned") = None;
+    }
+}
+
+/// Run an email delivery check and record its outcome
+async fn probe_email_delivery(
+    repo: &mut BoxRepository,
+    rng: &mut (dyn RngCore + Send),
+    clock: &dyn Clock,
+    error_log: &EmailProbeErrorLog,
+    kind: EmailProbeKind,
+    probe: impl Future<Output = Result<(), anyhow::Error>> + Send,
+) -> Result<EmailProbeResult, RepositoryError> {
+    let start = Instant::now();
+    let outcome = tokio::time::timeout(EMAIL_PROBE_TIMEOUT, probe)
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out")));
+    let latency_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+    let error = outcome.err().map(|e| format!("{e:#}"));
+    let result = repo
+        .email_probe()
+        .add(rng, clock, kind, latency_ms, error)
+        .await?;
+    repo.email_probe()
+        .prune(EMAIL_PROBE_RESULTS_TO_KEEP)
+        .await?;
+
+    if let Some(error) = &result.error {
+        EMAIL_PROBE_FAILURES.add(1, &[KeyValue::new("kind", kind.as_str())]);
+
+        if error_log.should_log(clock.now()) {
+            error!(
+                email_probe_result.id = %result.id,
+                %kind,
+                latency_ms,
+                "Email delivery check failed, emails may not be delivered: {error}"
+            );
+        } else {
+            debug!(
+                email_probe_result.id = %result.id,
+                %kind,
+                latency_ms,
+                "Email delivery check failed again: {error}"
+            );
+        }
+    } else {
+        error_log.reset();
+        info!(
+            email_probe_result.id = %result.id,
+            %kind,
+            latency_ms,
+            "Email delivery check succeeded"
+        );
+    }
+
+    Ok(result)
+}
+
+#[async_trait]
+impl RunnableJob for ProbeEmailDeliveryJob {
+    #[tracing::instrument(name = "job.probe_email_delivery", skip_all)]
+    async fn run(&self, state: &State, _context: JobContext) -> Result<(), JobError> {
+        let Some(config) = &state.site_config().email_probe else {
+            debug!("Email delivery checks are disabled, skipping");
+            return Ok(());
+        };
+
+        let clock = state.clock();
+        let mailer = state.mailer();
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.map_err(JobError::retry)?;
+
+        // A failed check is recorded, not retried: the next scheduled check
+        // tells whether emails can be delivered again
+        if let Some(recipient) = &config.recipient {
+            let mailbox: Mailbox = recipient.parse().map_err(JobError::fail)?;
+            let probe = async {
+                mailer
+                    .send_probe_email(mailbox)
+                    .await
+                    .context("Failed to send the probe email")
+            };
+            probe_email_delivery(
+                &mut repo,
+                &mut rng,
+                clock,
+                state.email_probe_error_log(),
+                EmailProbeKind::Message,
+                probe,
+            )
+            .await
+            .map_err(JobError::retry)?;
+        } else {
+            let probe = async {
+                mailer
+                    .test_connection()
+                    .await
+                    .context("Failed to connect to the mail server")
+            };
+            probe_email_delivery(
+                &mut repo,
+                &mut rng,
+                clock,
+                state.email_probe_error_log(),
+                EmailProbeKind::Connection,
+                probe,
+            )
+            .await
+            .map_err(JobError::retry)?;
+        }
+
+        repo.save().await.map_err(JobError::retry)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use mas_data_model::clock::MockClock;
+    use mas_storage_pg::PgRepository;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+
+    use super::*;
+
+    /// A mailer which fails a given number of times before succeeding
+    struct StubMailer {
+        failures_left: AtomicUsize,
+    }
+
+    impl StubMailer {
+        fn failing(times: usize) -> Self {
+            Self {
+                failures_left: AtomicUsize::new(times),
+            }
+        }
+
+        fn send(&self) -> std::future::Ready<Result<(), anyhow::Error>> {
+            let failed = self
+                .failures_left
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                    left.checked_sub(1)
+                })
+                .is_ok();
+
+            if failed {
+                return std::future::ready(Err(anyhow::anyhow!("Connection refused")));
+            }
+
+            std::future::ready(Ok(()))
+        }
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_probe_status_transitions(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = MockClock::default();
+        let error_log = EmailProbeErrorLog::default();
+        let mailer = StubMailer::failing(1);
+        let mut repo = PgRepository::from_pool(&pool).await.unwrap().boxed();
+
+        // No check ran yet
+        assert_eq!(repo.email_probe().latest().await.unwrap(), None);
+
+        // The first check fails
+        let result = probe_email_delivery(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &error_log,
+            EmailProbeKind::Message,
+            mailer.send(),
+        )
+        .await
+        .unwrap();
+        assert!(!result.is_success());
+        assert_eq!(result.kind, EmailProbeKind::Message);
+        assert_eq!(result.error.as_deref(), Some("Connection refused"));
+        assert_eq!(repo.email_probe().latest().await.unwrap(), Some(result));
+
+        // The failure was logged as an error, and the next one within the
+        // interval won't be
+        assert!(!error_log.should_log(clock.now()));
+
+        // The next check succeeds
+        clock.advance(Duration::hours(1));
+        let result = probe_email_delivery(
+            &mut repo,
+            &mut rng,
+            &clock,
+            &error_log,
+            EmailProbeKind::Message,
+            mailer.send(),
+        )
+        .await
+        .unwrap();
+        assert!(result.is_success());
+        assert_eq!(repo.email_probe().latest().await.unwrap(), Some(result));
+
+        // Recovering means the next failure is logged right away
+        assert!(error_log.should_log(clock.now()));
+    }
+
+    #[test]
+    fn test_error_log_interval() {
+        let clock = MockClock::default();
+        let error_log = EmailProbeErrorLog::default();
+
+        assert!(error_log.should_log(clock.now()));
+        assert!(!error_log.should_log(clock.now()));
+
+        clock.advance(Duration::hours(5));
+        assert!(!error_log.should_log(clock.now()));
+
+        // Once the interval is over, failures are logged again
+        clock.advance(Duration::hours(1));
+        assert!(error_log.should_log(clock.now()));
+        assert!(!error_log.should_log(clock.now()));
+    }
+}
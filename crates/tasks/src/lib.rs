@@ -6,7 +6,7 @@
 
 use std::sync::{Arc, LazyLock};
 
-use mas_data_model::{Clock, SiteConfig};
+use mas_data_model::{Clock, SiteConfig, TchapFeatures};
 use mas_email::Mailer;
 use mas_matrix::HomeserverConnection;
 use mas_router::UrlBuilder;
@@ -20,12 +20,15 @@ use tokio_util::{sync::CancellationToken, task::TaskTracker};
 
 pub use crate::new_queue::QueueWorker;
 
+mod audit;
 mod database;
 mod email;
 mod matrix;
 mod new_queue;
 mod recovery;
 mod sessions;
+#[cfg(test)]
+mod test_utils;
 mod user;
 
 static METER: LazyLock<Meter> = LazyLock::new(|| {
@@ -45,6 +48,12 @@ struct State {
     homeserver: Arc<dyn HomeserverConnection>,
     url_builder: UrlBuilder,
     site_config: SiteConfig,
+    //:tchap:
+    tchap_features: TchapFeatures,
+    //:tchap:end
+    http_client: reqwest::Client,
+    audit_circuit_breaker: Arc<audit::CircuitBreaker>,
+    email_probe_error_log: Arc<email::EmailProbeErrorLog>,
 }
 
 impl State {
@@ -55,6 +64,7 @@ impl State {
         homeserver: impl HomeserverConnection + 'static,
         url_builder: UrlBuilder,
         site_config: SiteConfig,
+        tchap_features: TchapFeatures,
     ) -> Self {
         Self {
             repository_factory,
@@ -63,6 +73,10 @@ impl State {
             homeserver: Arc::new(homeserver),
             url_builder,
             site_config,
+            tchap_features,
+            http_client: mas_http::reqwest_client(),
+            audit_circuit_breaker: Arc::default(),
+            email_probe_error_log: Arc::default(),
         }
     }
 
@@ -99,6 +113,24 @@ impl State {
     pub fn site_config(&self) -> &SiteConfig {
         &self.site_config
     }
+
+    //:tchap:
+    pub fn tchap_features(&self) -> &TchapFeatures {
+        &self.tchap_features
+    }
+    //:tchap:end
+
+    pub fn http_client(&self) -> &reqwest::Client {
+        &self.http_client
+    }
+
+    pub fn audit_circuit_breaker(&self) -> &audit::CircuitBreaker {
+        &self.audit_circuit_breaker
+    }
+
+    pub fn email_probe_error_log(&self) -> &email::EmailProbeErrorLog {
+        &self.email_probe_error_log
+    }
 }
 
 /// Initialise the worker, without running it.
@@ -108,6 +140,7 @@ impl State {
 /// # Errors
 ///
 /// This function can fail if the database connection fails.
+#[expect(clippy::too_many_arguments, reason = "this is fine")]
 pub async fn init(
     repository_factory: PgRepositoryFactory,
     clock: impl Clock + 'static,
@@ -115,6 +148,7 @@ pub async fn init(
     homeserver: impl HomeserverConnection + 'static,
     url_builder: UrlBuilder,
     site_config: &SiteConfig,
+    tchap_features: &TchapFeatures,
     cancellation_token: CancellationToken,
 ) -> Result<QueueWorker, QueueRunnerError> {
     let state = State::new(
@@ -124,6 +158,7 @@ pub async fn init(
         homeserver,
         url_builder,
         site_config.clone(),
+        *tchap_features,
     );
     let mut worker = QueueWorker::new(state, cancellation_token).await?;
 
@@ -131,11 +166,18 @@ pub async fn init(
         .register_handler::<mas_storage::queue::CleanupExpiredTokensJob>()
         .register_handler::<mas_storage::queue::DeactivateUserJob>()
         .register_handler::<mas_storage::queue::DeleteDeviceJob>()
+        .register_handler::<mas_storage::queue::FinishScheduledDeactivationJob>()
         .register_handler::<mas_storage::queue::ProvisionDeviceJob>()
         .register_handler::<mas_storage::queue::ProvisionUserJob>()
         .register_handler::<mas_storage::queue::ReactivateUserJob>()
+        .register_handler::<mas_storage::queue::ReconcileProvisioningJob>()
+        .register_handler::<mas_storage::queue::SendAccountRecoveredEmailJob>()
         .register_handler::<mas_storage::queue::SendAccountRecoveryEmailsJob>()
+        .register_handler::<mas_storage::queue::SendDeactivationScheduledEmailJob>()
+        .register_handler::<mas_storage::queue::SendEmailAddedEmailJob>()
         .register_handler::<mas_storage::queue::SendEmailAuthenticationCodeJob>()
+        .register_handler::<mas_storage::queue::SendEmailChangeConfirmationJob>()
+        .register_handler::<mas_storage::queue::SendNewLoginEmailJob>()
         .register_handler::<mas_storage::queue::SyncDevicesJob>()
         .register_handler::<mas_storage::queue::VerifyEmailJob>()
         .register_handler::<mas_storage::queue::ExpireInactiveSessionsJob>()
@@ -143,6 +185,9 @@ pub async fn init(
         .register_handler::<mas_storage::queue::ExpireInactiveOAuthSessionsJob>()
         .register_handler::<mas_storage::queue::ExpireInactiveUserSessionsJob>()
         .register_handler::<mas_storage::queue::PruneStalePolicyDataJob>()
+        .register_handler::<mas_storage::queue::PurgeDeletedUsersJob>()
+        .register_handler::<mas_storage::queue::SendAuditEventJob>()
+        .register_handler::<mas_storage::queue::ProbeEmailDeliveryJob>()
         .add_schedule(
             "cleanup-expired-tokens",
             "0 0 * * * *".parse()?,
@@ -159,6 +204,18 @@ pub async fn init(
             // Run once a day
             "0 0 2 * * *".parse()?,
             mas_storage::queue::PruneStalePolicyDataJob,
+        )
+        .add_schedule(
+            "purge-deleted-users",
+            // Run once an hour
+            "0 30 * * * *".parse()?,
+            mas_storage::queue::PurgeDeletedUsersJob,
+        )
+        .add_schedule(
+            "probe-email-delivery",
+            // Run once an hour
+            "0 45 * * * *".parse()?,
+            mas_storage::queue::ProbeEmailDeliveryJob,
         );
 
     Ok(worker)
@@ -177,6 +234,7 @@ pub async fn init_and_run(
     homeserver: impl HomeserverConnection + 'static,
     url_builder: UrlBuilder,
     site_config: &SiteConfig,
+    tchap_features: &TchapFeatures,
     cancellation_token: CancellationToken,
     task_tracker: &TaskTracker,
 ) -> Result<(), QueueRunnerError> {
@@ -187,6 +245,7 @@ pub async fn init_and_run(
         homeserver,
         url_builder,
         site_config,
+        tchap_features,
         cancellation_token,
     )
     .await?;
@@ -8,7 +8,7 @@ use std::collections::HashSet;
 
 use anyhow::Context;
 use async_trait::async_trait;
-use mas_data_model::Device;
+use mas_data_model::{Device, ProvisioningDiscrepancy, ProvisioningDiscrepancyKind};
 use mas_matrix::ProvisionRequest;
 use mas_storage::{
     Pagination, RepositoryAccess,
@@ -17,11 +17,11 @@ use mas_storage::{
     personal::PersonalSessionFilter,
     queue::{
         DeleteDeviceJob, ProvisionDeviceJob, ProvisionUserJob, QueueJobRepositoryExt as _,
-        SyncDevicesJob,
+        ReconcileProvisioningJob, SyncDevicesJob,
     },
-    user::{UserEmailRepository, UserRepository},
+    user::{UserEmailRepository, UserFilter, UserRepository},
 };
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::{
     State,
@@ -52,16 +52,19 @@ impl RunnableJob for ProvisionUserJob {
             .context("User not found")
             .map_err(JobError::fail)?;
 
-        let emails = repo
-            .user_email()
-            .all(&user)
-            .await
-            .map_err(JobError::retry)?
-            .into_iter()
-            .map(|email| email.email)
-            .collect();
-        let mut request =
-            ProvisionRequest::new(user.username.clone(), user.sub.clone()).set_emails(emails);
+        let mut request = ProvisionRequest::new(user.username.clone(), user.sub.clone());
+
+        if state.site_config().sync_emails_to_homeserver {
+            let emails = repo
+                .user_email()
+                .all(&user)
+                .await
+                .map_err(JobError::retry)?
+                .into_iter()
+                .map(|email| email.email)
+                .collect();
+            request = request.set_emails(emails);
+        }
 
         if let Some(display_name) = self.display_name_to_set() {
             request = request.set_displayname(display_name.to_owned());
@@ -286,3 +289,176 @@ impl RunnableJob for SyncDevicesJob {
         Ok(())
     }
 }
+
+/// Job to compare the users in MAS with their state on the homeserver.
+///
+/// It reports the users missing on the homeserver, the users which are
+/// deactivated on one side but not the other, and the users whose display
+/// name on the homeserver is missing or, when display names are derived from
+/// the email address, differs from the derived one. If the report asks for
+/// it, a provisioning job is scheduled for the active users missing on the
+/// homeserver.
+///
+/// Each job checks one batch of users and saves its results in the report,
+/// before scheduling a job for the next batch.
+#[async_trait]
+impl RunnableJob for ReconcileProvisioningJob {
+    #[tracing::instrument(
+        name = "job.reconcile_provisioning",
+        fields(provisioning_report.id = %self.provisioning_report_id()),
+        skip_all,
+    )]
+    async fn run(&self, state: &State, _context: JobContext) -> Result<(), JobError> {
+        let matrix = state.matrix_connection();
+        let mut repo = state.repository().await.map_err(JobError::retry)?;
+        let mut rng = state.rng();
+        let clock = state.clock();
+
+        let report = repo
+            .provisioning_report()
+            .lookup(self.provisioning_report_id())
+            .await
+            .map_err(JobError::retry)?
+            .context("Provisioning report not found")
+            .map_err(JobError::fail)?;
+
+        if report.is_completed() {
+            info!("Provisioning report already completed, skipping");
+            return Ok(());
+        }
+
+        let mut users_checked = 0;
+        let mut discrepancies = Vec::new();
+
+        let page = repo
+            .user()
+            .list(UserFilter::new(), self.pagination(1000))
+            .await
+            .map_err(JobError::retry)?;
+
+        for edge in &page.edges {
+            let user = &edge.node;
+
+            // Deleted users are about to be purged, there is no point in
+            // checking them
+            if user.deleted_at.is_some() {
+                continue;
+            }
+
+            let discrepancy = |kind, fixed| ProvisioningDiscrepancy {
+                user_id: user.id,
+                username: user.username.clone(),
+                kind,
+                fixed,
+            };
+
+            let matrix_user = match matrix.query_user(&user.username).await {
+                Ok(matrix_user) => matrix_user,
+                Err(err) => {
+                    // Querying the user failed, which is either because it
+                    // doesn't exist, or because of another error. We
+                    // check if the localpart is available to tell apart the
+                    // two cases
+                    let available = matrix
+                        .is_localpart_available(&user.username)
+                        .await
+                        .map_err(JobError::retry)?;
+
+                    if !available {
+                        warn!(
+                            user.id = %user.id,
+                            error = &*err as &dyn std::error::Error,
+                            "Failed to query user on the homeserver, skipping"
+                        );
+                        continue;
+                    }
+
+                    users_checked += 1;
+
+                    // Only provision active users, so that we don't bring
+                    // back deactivated users on the homeserver
+                    let fix = report.fix && user.deactivated_at.is_none();
+                    if fix {
+                        repo.queue_job()
+                            .schedule_job(&mut rng, clock, ProvisionUserJob::new(user))
+                            .await
+                            .map_err(JobError::retry)?;
+                    }
+
+                    discrepancies.push(discrepancy(
+                        ProvisioningDiscrepancyKind::MissingOnHomeserver,
+                        fix,
+                    ));
+                    continue;
+                }
+            };
+
+            users_checked += 1;
+
+            if user.deactivated_at.is_some() != matrix_user.deactivated {
+                discrepancies.push(discrepancy(
+                    ProvisioningDiscrepancyKind::DeactivatedMismatch,
+                    false,
+                ));
+            }
+
+            //:tchap:
+            // When display names are derived from the email address, the
+            // display name is expected to match the one derived from one of
+            // the email addresses of the user
+            let mut expected_displaynames = Vec::new();
+            if state.tchap_features().displayname_suffixing {
+                let emails = repo.user_email().all(user).await.map_err(JobError::retry)?;
+                expected_displaynames.extend(
+                    emails
+                        .iter()
+                        .map(|email| tchap::email_to_display_name(&email.email)),
+                );
+            }
+            //:tchap:end
+
+            let displayname_drifted = match matrix_user.displayname {
+                None => true,
+                Some(displayname) => {
+                    displayname.is_empty()
+                        || (!expected_displaynames.is_empty()
+                            && !expected_displaynames.contains(&displayname))
+                }
+            };
+            if displayname_drifted {
+                discrepancies.push(discrepancy(
+                    ProvisioningDiscrepancyKind::DisplaynameDrift,
+                    false,
+                ));
+            }
+        }
+
+        let report = repo
+            .provisioning_report()
+            .add_progress(report, users_checked, discrepancies)
+            .await
+            .map_err(JobError::retry)?;
+
+        if let Some(next) = self.next(&page) {
+            repo.queue_job()
+                .schedule_job(&mut rng, clock, next)
+                .await
+                .map_err(JobError::retry)?;
+        } else {
+            info!(
+                users_checked = report.users_checked,
+                discrepancies = report.discrepancies.len(),
+                "Provisioning reconciliation completed"
+            );
+
+            repo.provisioning_report()
+                .complete(clock, report)
+                .await
+                .map_err(JobError::retry)?;
+        }
+
+        repo.save().await.map_err(JobError::retry)?;
+
+        Ok(())
+    }
+}
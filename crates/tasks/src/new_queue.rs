@@ -14,7 +14,7 @@ use mas_storage::{
     RepositoryAccess, RepositoryError,
     queue::{InsertableJob, Job, JobMetadata, Worker},
 };
-use mas_storage_pg::{DatabaseError, PgRepository};
+use mas_storage_pg::{DatabaseError, PgRepository, is_connection_error};
 use opentelemetry::{
     KeyValue,
     metrics::{Counter, Histogram, UpDownCounter},
@@ -159,6 +159,21 @@ pub enum QueueRunnerError {
     NotLeader,
 }
 
+impl QueueRunnerError {
+    /// Whether this error is caused by the connection to the database being
+    /// lost, in which case the worker should back off and try again later
+    /// instead of stopping
+    fn is_connection_lost(&self) -> bool {
+        match self {
+            Self::StartTransaction(e) | Self::CommitTransaction(e) | Self::LeaderLock(e) => {
+                is_connection_error(e)
+            }
+            Self::Database(DatabaseError::Driver { source }) => is_connection_error(source),
+            _ => false,
+        }
+    }
+}
+
 // When the worker waits for a notification, we still want to wake it up every
 // second. Because we don't want all the workers to wake up at the same time, we
 // add a random jitter to the sleep duration, so they effectively sleep between
@@ -175,6 +190,19 @@ const MAX_JOBS_TO_FETCH: usize = 5;
 // How many attempts a job should be retried
 const MAX_ATTEMPTS: usize = 10;
 
+// When the connection to the database is lost, the worker backs off
+// exponentially between attempts, from 100ms up to 30 seconds
+const MIN_CONNECTION_BACKOFF: std::time::Duration = std::time::Duration::from_millis(100);
+const MAX_CONNECTION_BACKOFF: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Returns the delay to wait before trying again after the given number of
+/// consecutive failures to reach the database
+fn connection_backoff(failures: u32) -> std::time::Duration {
+    MIN_CONNECTION_BACKOFF
+        .saturating_mul(2_u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_CONNECTION_BACKOFF)
+}
+
 /// Returns the delay to wait before retrying a job
 ///
 /// Uses an exponential backoff: 5s, 10s, 20s, 40s, 1m20s, 2m40s, 5m20s, 10m50s,
@@ -207,6 +235,7 @@ pub struct QueueWorker {
     tracker: JobTracker,
     wakeup_reason: Counter<u64>,
     tick_time: Histogram<u64>,
+    leader: UpDownCounter<i64>,
 }
 
 impl QueueWorker {
@@ -271,6 +300,14 @@ impl QueueWorker {
             )
             .build();
 
+        let leader = METER
+            .i64_up_down_counter("job.worker.leader")
+            .with_description(
+                "Whether this worker is the leader, in charge of scheduling the recurring jobs",
+            )
+            .build();
+        leader.add(0, &[]);
+
         // We put a cancellation drop guard in the structure, so that when it gets
         // dropped, we're sure to cancel the token
         let cancellation_guard = cancellation_token.clone().drop_guard();
@@ -287,6 +324,7 @@ impl QueueWorker {
             tracker: JobTracker::new(),
             wakeup_reason,
             tick_time,
+            leader,
         })
     }
 
@@ -333,10 +371,36 @@ impl QueueWorker {
     async fn run_inner(&mut self) -> Result<(), QueueRunnerError> {
         self.setup_schedules().await?;
 
+        let mut failures = 0;
         while !self.cancellation_token.is_cancelled() {
-            LogContext::new("worker-run-loop")
+            match LogContext::new("worker-run-loop")
                 .run(|| self.run_loop())
-                .await?;
+                .await
+            {
+                Ok(()) => {
+                    if failures > 0 {
+                        tracing::info!("Connection to the database recovered");
+                    }
+                    failures = 0;
+                }
+
+                // Back off instead of spinning while the database is unreachable
+                Err(e) if e.is_connection_lost() => {
+                    failures += 1;
+                    let delay = connection_backoff(failures);
+                    tracing::warn!(
+                        error = &e as &dyn std::error::Error,
+                        "Lost the connection to the database, retrying in {delay:?}"
+                    );
+
+                    tokio::select! {
+                        () = self.cancellation_token.cancelled() => {},
+                        () = tokio::time::sleep(delay) => {},
+                    }
+                }
+
+                Err(e) => return Err(e),
+            }
         }
 
         self.shutdown().await?;
@@ -434,6 +498,12 @@ impl QueueWorker {
             .await
             .map_err(QueueRunnerError::CommitTransaction)?;
 
+        if self.am_i_leader {
+            self.am_i_leader = false;
+            self.leader.add(-1, &[]);
+            tracing::info!("Stepped down as the leader");
+        }
+
         Ok(())
     }
 
@@ -577,8 +647,10 @@ impl QueueWorker {
             // If we flipped state, log it
             self.am_i_leader = leader;
             if self.am_i_leader {
+                self.leader.add(1, &[]);
                 tracing::info!("I'm the leader now");
             } else {
+                self.leader.add(-1, &[]);
                 tracing::warn!("I am no longer the leader");
             }
         }
@@ -679,6 +751,7 @@ impl QueueWorker {
                     schedule.queue_name,
                     schedule.payload.clone(),
                     serde_json::json!({}),
+                    None,
                     next_tick,
                     Some(schedule.schedule_name),
                 )
@@ -715,6 +788,25 @@ impl QueueWorker {
         Ok(())
     }
 
+    /// Go through one iteration of the worker loop without waiting to be woken
+    /// up, and return whether this worker is the leader.
+    /// This should only be called in tests!
+    ///
+    /// # Errors
+    ///
+    /// This function can fail if the database connection fails.
+    pub async fn tick_in_tests(&mut self) -> Result<bool, QueueRunnerError> {
+        self.setup_schedules().await?;
+
+        self.tick().await?;
+
+        if self.am_i_leader {
+            self.perform_leader_duties().await?;
+        }
+
+        Ok(self.am_i_leader)
+    }
+
     /// Process all the pending jobs in the queue.
     /// This should only be called in tests!
     ///
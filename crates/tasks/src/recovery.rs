@@ -6,22 +6,37 @@
 
 use anyhow::Context;
 use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::UserRecoveryTicket;
 use mas_email::{Address, Mailbox};
 use mas_i18n::DataLocale;
 use mas_storage::{
     Pagination, RepositoryAccess,
-    queue::SendAccountRecoveryEmailsJob,
+    queue::{SendAccountRecoveredEmailJob, SendAccountRecoveryEmailsJob},
     user::{UserEmailFilter, UserRecoveryRepository},
 };
-use mas_templates::{EmailRecoveryContext, TemplateContext};
+use mas_templates::{
+    AccountRecoveredContext, EmailRecoveryContext, EndedSessionsSummary, TemplateContext,
+};
 use rand::distributions::{Alphanumeric, DistString};
 use tracing::{error, info};
 
 use crate::{
     State,
+    email::email_language,
     new_queue::{JobContext, JobError, RunnableJob},
 };
 
+/// Whether a new recovery email can be sent to an address, given the latest
+/// ticket which was sent to it, if any
+fn can_send_email(
+    latest_ticket: Option<&UserRecoveryTicket>,
+    now: DateTime<Utc>,
+    interval: Duration,
+) -> bool {
+    latest_ticket.is_none_or(|ticket| now - ticket.created_at >= interval)
+}
+
 /// Job to send account recovery emails for a given recovery session.
 #[async_trait]
 impl RunnableJob for SendAccountRecoveryEmailsJob {
@@ -37,6 +52,7 @@ impl RunnableJob for SendAccountRecoveryEmailsJob {
         let clock = state.clock();
         let mailer = state.mailer();
         let url_builder = state.url_builder();
+        let interval = state.site_config().account_recovery_email_interval;
         let mut rng = state.rng();
         let mut repo = state.repository().await.map_err(JobError::retry)?;
 
@@ -57,12 +73,6 @@ impl RunnableJob for SendAccountRecoveryEmailsJob {
 
         let mut cursor = Pagination::first(50);
 
-        let lang: DataLocale = session
-            .locale
-            .parse()
-            .context("Invalid locale in database on recovery session")
-            .map_err(JobError::fail)?;
-
         loop {
             let page = repo
                 .user_email()
@@ -71,6 +81,24 @@ impl RunnableJob for SendAccountRecoveryEmailsJob {
                 .map_err(JobError::retry)?;
 
             for edge in page.edges {
+                cursor = cursor.after(edge.cursor);
+
+                // Multiple recovery sessions can be started for the same address,
+                // make sure we don't flood it with emails
+                let latest_ticket = repo
+                    .user_recovery()
+                    .find_latest_ticket_for_email(&edge.node)
+                    .await
+                    .map_err(JobError::retry)?;
+
+                if !can_send_email(latest_ticket.as_ref(), clock.now(), interval) {
+                    info!(
+                        user_email.id = %edge.node.id,
+                        "A recovery email was recently sent to this address, not sending another one"
+                    );
+                    continue;
+                }
+
                 let ticket = Alphanumeric.sample_string(&mut rng, 32);
 
                 let ticket = repo
@@ -93,8 +121,9 @@ impl RunnableJob for SendAccountRecoveryEmailsJob {
                 let mailbox = Mailbox::new(Some(user.username.clone()), address);
 
                 info!("Sending recovery email to {}", mailbox);
-                let context = EmailRecoveryContext::new(user, session.clone(), url)
-                    .with_language(lang.clone());
+                let lang = email_language(Some(&user), &session.locale)?;
+                let context =
+                    EmailRecoveryContext::new(user, session.clone(), url).with_language(lang);
 
                 // XXX: we only log if the email fails to send, to avoid stopping the loop
                 if let Err(e) = mailer.send_recovery_email(mailbox, &context).await {
@@ -103,8 +132,6 @@ impl RunnableJob for SendAccountRecoveryEmailsJob {
                         "Failed to send recovery email"
                     );
                 }
-
-                cursor = cursor.after(edge.cursor);
             }
 
             if !page.has_next_page {
@@ -117,3 +144,252 @@ impl RunnableJob for SendAccountRecoveryEmailsJob {
         Ok(())
     }
 }
+
+/// Job to notify a user that their account was recovered, on all their email
+/// addresses
+#[async_trait]
+impl RunnableJob for SendAccountRecoveredEmailJob {
+    #[tracing::instrument(
+        name = "job.send_account_recovered_email",
+        fields(user.id = %self.user_id()),
+        skip_all,
+    )]
+    async fn run(&self, state: &State, _context: JobContext) -> Result<(), JobError> {
+        let mailer = state.mailer();
+        let mut repo = state.repository().await.map_err(JobError::retry)?;
+
+        let user = repo
+            .user()
+            .lookup(self.user_id())
+            .await
+            .map_err(JobError::retry)?
+            .context("User not found")
+            .map_err(JobError::fail)?;
+
+        let lang: DataLocale = self
+            .language()
+            .parse()
+            .context("Invalid locale in job")
+            .map_err(JobError::fail)?;
+
+        let mut context = AccountRecoveredContext::new(user.clone());
+        if let Some(ended_sessions) = self.ended_sessions() {
+            context = context.with_ended_sessions(EndedSessionsSummary {
+                compat_sessions: ended_sessions.compat_sessions,
+                oauth2_sessions: ended_sessions.oauth2_sessions,
+                browser_sessions: ended_sessions.browser_sessions,
+            });
+        }
+        let context = context.with_language(lang);
+
+        let emails = repo
+            .user_email()
+            .all(&user)
+            .await
+            .map_err(JobError::retry)?;
+
+        for email in emails {
+            let address: Address = email.email.parse().map_err(JobError::fail)?;
+            let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+            info!("Sending account recovered notification to {}", mailbox);
+
+            // XXX: we only log if the email fails to send, to avoid stopping the loop
+            if let Err(e) = mailer.send_account_recovered_email(mailbox, &context).await {
+                error!(
+                    error = &e as &dyn std::error::Error,
+                    "Failed to send account recovered notification"
+                );
+            }
+        }
+
+        repo.save().await.map_err(JobError::fail)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use mas_data_model::{Clock, User, UserRecoverySession, clock::MockClock};
+    use mas_router::UrlBuilder;
+    use mas_storage::queue::QueueJobRepositoryExt as _;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sqlx::PgPool;
+    use ulid::Ulid;
+
+    use super::*;
+    use crate::test_utils::{run_queued_jobs, test_state};
+
+    async fn count_tickets(pool: &PgPool) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM user_recovery_tickets")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_one_email_per_interval() {
+        let clock = MockClock::default();
+        let interval = Duration::minutes(5);
+
+        // Nothing was sent yet
+        assert!(can_send_email(None, clock.now(), interval));
+
+        let ticket = UserRecoveryTicket {
+            id: Ulid::nil(),
+            user_recovery_session_id: Ulid::nil(),
+            user_email_id: Ulid::nil(),
+            ticket: "ticket".to_owned(),
+            created_at: clock.now(),
+            expires_at: clock.now() + Duration::minutes(10),
+        };
+
+        // An email was just sent, whatever the session it was for
+        assert!(!can_send_email(Some(&ticket), clock.now(), interval));
+
+        clock.advance(Duration::minutes(4));
+        assert!(!can_send_email(Some(&ticket), clock.now(), interval));
+
+        // Once the interval is over, another email can be sent
+        clock.advance(Duration::minutes(1));
+        assert!(can_send_email(Some(&ticket), clock.now(), interval));
+    }
+
+    #[sqlx::test(migrator = "mas_storage_pg::MIGRATOR")]
+    async fn test_recovery_job_one_email_per_interval(pool: PgPool) {
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let clock = Arc::new(MockClock::default());
+        let state = test_state(pool.clone(), Arc::clone(&clock)).await;
+
+        let mut repo = state.repository().await.unwrap();
+        let user = repo
+            .user()
+            .add(&mut rng, &*clock, "alice".to_owned())
+            .await
+            .unwrap();
+        let email = repo
+            .user_email()
+            .add(&mut rng, &*clock, &user, "alice@example.com".to_owned())
+            .await
+            .unwrap();
+
+        // Start two recovery sessions for the same address in a row
+        for _ in 0..2 {
+            let session = repo
+                .user_recovery()
+                .add_session(
+                    &mut rng,
+                    &*clock,
+                    "alice@example.com".to_owned(),
+                    "Mozilla/5.0".to_owned(),
+                    None,
+                    "en".to_owned(),
+                )
+                .await
+                .unwrap();
+            repo.queue_job()
+                .schedule_job(
+                    &mut rng,
+                    &*clock,
+                    SendAccountRecoveryEmailsJob::new(&session),
+                )
+                .await
+                .unwrap();
+        }
+        repo.save().await.unwrap();
+
+        // Both jobs ran, but only the first one sent a ticket
+        assert_eq!(
+            run_queued_jobs::<SendAccountRecoveryEmailsJob>(&state).await,
+            2
+        );
+        assert_eq!(count_tickets(&pool).await, 1);
+
+        let mut repo = state.repository().await.unwrap();
+        let first_ticket = repo
+            .user_recovery()
+            .find_latest_ticket_for_email(&email)
+            .await
+            .unwrap()
+            .expect("a ticket should have been sent");
+        repo.save().await.unwrap();
+
+        // Once the interval is over, a new session gets its own email
+        clock.advance(Duration::minutes(5));
+        let mut repo = state.repository().await.unwrap();
+        let session = repo
+            .user_recovery()
+            .add_session(
+                &mut rng,
+                &*clock,
+                "alice@example.com".to_owned(),
+                "Mozilla/5.0".to_owned(),
+                None,
+                "en".to_owned(),
+            )
+            .await
+            .unwrap();
+        repo.queue_job()
+            .schedule_job(
+                &mut rng,
+                &*clock,
+                SendAccountRecoveryEmailsJob::new(&session),
+            )
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+
+        assert_eq!(
+            run_queued_jobs::<SendAccountRecoveryEmailsJob>(&state).await,
+            1
+        );
+        assert_eq!(count_tickets(&pool).await, 2);
+
+        let mut repo = state.repository().await.unwrap();
+        let latest_ticket = repo
+            .user_recovery()
+            .find_latest_ticket_for_email(&email)
+            .await
+            .unwrap()
+            .expect("a ticket should have been sent");
+        repo.save().await.unwrap();
+        assert_ne!(latest_ticket.id, first_ticket.id);
+        assert_eq!(latest_ticket.user_recovery_session_id, session.id);
+    }
+
+    #[test]
+    fn test_recovery_email_language() {
+        let clock = MockClock::default();
+        let mut rng = ChaChaRng::seed_from_u64(42);
+        let mut user = User::samples(clock.now(), &mut rng).remove(0);
+
+        // The recovery was started from a browser asking for English
+        let session = UserRecoverySession {
+            id: Ulid::nil(),
+            email: "alice@example.com".to_owned(),
+            user_agent: "Mozilla/5.0".to_owned(),
+            ip_address: None,
+            locale: "en".to_owned(),
+            created_at: clock.now(),
+            consumed_at: None,
+        };
+        let url_builder = UrlBuilder::new("https://example.com/".parse().unwrap(), None, None);
+        let url = url_builder.account_recovery_link("ticket".to_owned());
+
+        // Without a preferred language, the negotiated one is used
+        let lang = email_language(Some(&user), &session.locale).unwrap();
+        let context = EmailRecoveryContext::new(user.clone(), session.clone(), url.clone())
+            .with_language(lang);
+        assert_eq!(context.language(), "en");
+
+        // Once the user chose French, it takes precedence
+        user.locale = Some("fr".to_owned());
+        let lang = email_language(Some(&user), &session.locale).unwrap();
+        let context = EmailRecoveryContext::new(user, session, url).with_language(lang);
+        assert_eq!(context.language(), "fr");
+    }
+}
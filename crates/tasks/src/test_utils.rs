@@ -0,0 +1,166 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+//! Helpers to run jobs against a database in tests
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use chrono::Duration;
+use mas_data_model::{
+    BrowserSessionLifetimeConfig, DisallowedScopeHandling, SessionLimitConfig, SiteConfig,
+    TchapFeatures, UserinfoClaimsConfig, clock::MockClock,
+};
+use mas_email::{MailTransport, Mailer};
+use mas_matrix::MockHomeserverConnection;
+use mas_router::UrlBuilder;
+use mas_storage::queue::InsertableJob;
+use mas_storage_pg::PgRepositoryFactory;
+use mas_templates::{SiteConfigExt as _, Templates};
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use serde::de::DeserializeOwned;
+use sqlx::PgPool;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    State,
+    new_queue::{JobContext, RunnableJob},
+};
+
+pub(crate) fn test_site_config() -> SiteConfig {
+    SiteConfig {
+        access_token_ttl: Duration::try_minutes(5).unwrap(),
+        compat_token_ttl: Duration::try_minutes(5).unwrap(),
+        pushed_authorization_request_ttl: Duration::try_minutes(1).unwrap(),
+        server_name: "example.com".to_owned(),
+        policy_uri: Some("https://example.com/policy".parse().unwrap()),
+        tos_uri: Some("https://example.com/tos".parse().unwrap()),
+        imprint: None,
+        template_globals: BTreeMap::new(),
+        password_login_enabled: true,
+        password_registration_enabled: true,
+        registration_token_required: false,
+        email_change_allowed: true,
+        email_change_double_confirmation: false,
+        new_login_notification: false,
+        displayname_change_allowed: true,
+        password_change_allowed: true,
+        password_registration_email_required: true,
+        account_recovery_allowed: true,
+        account_recovery_email_interval: Duration::try_minutes(5).unwrap(),
+        account_deactivation_allowed: true,
+        deactivation_grace_period: Duration::try_days(7).unwrap(),
+        deleted_user_retention: Duration::try_days(30).unwrap(),
+        protected_usernames: Vec::new(),
+        upstream_authorization_retention: Duration::try_days(7).unwrap(),
+        captcha: None,
+        minimum_password_complexity: 1,
+        session_expiration: None,
+        login_with_email_allowed: true,
+        auto_redirect_single_provider: true,
+        prefer_password_login: true,
+        plan_management_iframe_uri: None,
+        admin_api_csv_export_limit: 100_000,
+        audit_webhook: None,
+        email_probe: None,
+        sync_emails_to_homeserver: true,
+        userinfo_claims: UserinfoClaimsConfig::default(),
+        device_code_sensitive_scopes: vec![
+            "urn:mas:admin".to_owned(),
+            "urn:synapse:admin:*".to_owned(),
+        ],
+        session_limit: SessionLimitConfig::default(),
+        browser_session_lifetime: BrowserSessionLifetimeConfig::default(),
+        disallowed_scope_handling: DisallowedScopeHandling::default(),
+        matrix_session_in_token_response: false,
+        admin_api_examples: false,
+    }
+}
+
+/// Create a [`State`] running against the given database, with emails going
+/// nowhere and the given clock
+pub(crate) async fn test_state(pool: PgPool, clock: Arc<MockClock>) -> State {
+    let workspace_root = camino::Utf8Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("..");
+
+    let site_config = test_site_config();
+    let url_builder = UrlBuilder::new("https://example.com/".parse().unwrap(), None, None);
+
+    let templates = Templates::load(
+        workspace_root.join("templates"),
+        url_builder.clone(),
+        workspace_root.join("frontend/dist/manifest.json"),
+        workspace_root.join("translations"),
+        site_config.templates_branding(),
+        site_config.templates_features(),
+        // Strict mode in testing
+        true,
+    )
+    .await
+    .unwrap();
+
+    let mailer = Mailer::new(
+        templates,
+        MailTransport::blackhole(),
+        "hello@example.com".parse().unwrap(),
+        "hello@example.com".parse().unwrap(),
+    );
+
+    let homeserver = MockHomeserverConnection::new(&site_config.server_name);
+
+    State::new(
+        PgRepositoryFactory::new(pool),
+        clock,
+        mailer,
+        homeserver,
+        url_builder,
+        site_config,
+        TchapFeatures::default(),
+    )
+}
+
+/// Reserve all the jobs queued for `J` and run them, the same way the queue
+/// worker does. Returns how many jobs were run.
+pub(crate) async fn run_queued_jobs<J>(state: &State) -> usize
+where
+    J: InsertableJob + RunnableJob + DeserializeOwned,
+{
+    let clock = state.clock();
+    let mut rng = ChaChaRng::seed_from_u64(42);
+    let mut repo = state.repository().await.unwrap();
+    let worker = repo.queue_worker().register(&mut rng, clock).await.unwrap();
+    let jobs = repo
+        .queue_job()
+        .reserve(clock, &worker, &[J::QUEUE_NAME], 100)
+        .await
+        .unwrap();
+    repo.save().await.unwrap();
+
+    let count = jobs.len();
+    for job in jobs {
+        let context = JobContext {
+            id: job.id,
+            metadata: job.metadata,
+            queue_name: job.queue_name,
+            attempt: job.attempt,
+            start: Instant::now(),
+            cancellation_token: CancellationToken::new(),
+        };
+
+        let payload: J = serde_json::from_value(job.payload).unwrap();
+        payload.run(state, context).await.unwrap();
+
+        let mut repo = state.repository().await.unwrap();
+        repo.queue_job()
+            .mark_as_completed(clock, job.id)
+            .await
+            .unwrap();
+        repo.save().await.unwrap();
+    }
+
+    count
+}
@@ -7,12 +7,15 @@
 use anyhow::Context;
 use async_trait::async_trait;
 use mas_storage::{
-    RepositoryAccess,
+    Pagination, RepositoryAccess,
     compat::CompatSessionFilter,
     oauth2::OAuth2SessionFilter,
     personal::PersonalSessionFilter,
-    queue::{DeactivateUserJob, ReactivateUserJob},
-    user::{BrowserSessionFilter, UserEmailFilter, UserRepository},
+    queue::{
+        DeactivateUserJob, FinishScheduledDeactivationJob, PurgeDeletedUsersJob,
+        QueueJobRepositoryExt, ReactivateUserJob,
+    },
+    user::{BrowserSessionFilter, UserEmailFilter, UserFilter, UserRepository},
 };
 use tracing::info;
 
@@ -171,3 +174,114 @@ impl RunnableJob for ReactivateUserJob {
         Ok(())
     }
 }
+
+/// Job to deactivate a user who asked for it, once the grace period is over,
+/// unless they cancelled it in the meantime.
+#[async_trait]
+impl RunnableJob for FinishScheduledDeactivationJob {
+    #[tracing::instrument(
+        name = "job.finish_scheduled_deactivation",
+        fields(user.id = %self.user_id(), erase = %self.hs_erase()),
+        skip_all,
+    )]
+    async fn run(&self, state: &State, _context: JobContext) -> Result<(), JobError> {
+        let clock = state.clock();
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.map_err(JobError::retry)?;
+
+        let user = repo
+            .user()
+            .lookup(self.user_id())
+            .await
+            .map_err(JobError::retry)?
+            .context("User not found")
+            .map_err(JobError::fail)?;
+
+        let Some(scheduled_at) = user.deactivation_scheduled_at else {
+            info!("The deactivation was cancelled, not deactivating the user");
+            return Ok(());
+        };
+
+        // The deactivation may have been cancelled, then scheduled again later
+        // on, in which case another job will take care of it
+        if scheduled_at > clock.now() {
+            info!(
+                %scheduled_at,
+                "The deactivation is scheduled later, not deactivating the user yet"
+            );
+            return Ok(());
+        }
+
+        let user = repo
+            .user()
+            .unschedule_deactivation(user)
+            .await
+            .map_err(JobError::retry)?;
+
+        info!("Grace period is over, deactivating user {}", user.username);
+        repo.queue_job()
+            .schedule_job(
+                &mut rng,
+                clock,
+                DeactivateUserJob::new(&user, self.hs_erase()),
+            )
+            .await
+            .map_err(JobError::retry)?;
+
+        repo.save().await.map_err(JobError::retry)?;
+
+        Ok(())
+    }
+}
+
+/// Job to permanently remove users deleted longer than the retention period
+/// ago, both locally and on the Matrix homeserver.
+#[async_trait]
+impl RunnableJob for PurgeDeletedUsersJob {
+    #[tracing::instrument(name = "job.purge_deleted_users", skip_all)]
+    async fn run(&self, state: &State, _context: JobContext) -> Result<(), JobError> {
+        let clock = state.clock();
+        let matrix = state.matrix_connection();
+        let mut rng = state.rng();
+        let mut repo = state.repository().await.map_err(JobError::retry)?;
+
+        let threshold = clock.now() - state.site_config().deleted_user_retention;
+        let filter = UserFilter::new().with_deleted_before(threshold);
+        let page = repo
+            .user()
+            .list(filter, Pagination::first(100))
+            .await
+            .map_err(JobError::retry)?;
+        repo.cancel().await.map_err(JobError::retry)?;
+
+        for edge in page.edges {
+            let user = edge.node;
+
+            // The user was only deactivated on our side until now, erase it on the
+            // homeserver before removing the local data
+            info!(user.id = %user.id, "Erasing user {} on homeserver", user.username);
+            matrix
+                .delete_user(&user.username, true)
+                .await
+                .map_err(JobError::retry)?;
+
+            // Commit each user separately, so that the users already erased on the
+            // homeserver are purged even if a later one fails
+            let mut repo = state.repository().await.map_err(JobError::retry)?;
+            repo.user().purge(user).await.map_err(JobError::retry)?;
+            repo.save().await.map_err(JobError::retry)?;
+        }
+
+        if page.has_next_page {
+            info!("Scheduling job to purge the next batch of deleted users");
+            let mut repo = state.repository().await.map_err(JobError::retry)?;
+            repo.queue_job()
+                .schedule_job(&mut rng, clock, PurgeDeletedUsersJob)
+                .await
+                .map_err(JobError::retry)?;
+            repo.save().await.map_err(JobError::retry)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//
+// MIT License
+//
+// Copyright (c) 2025, Direction interministérielle du numérique - Gouvernement
+// Français
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to
+// deal in the Software without restriction, including without limitation the
+// rights to use, copy, modify, merge, publish, distribute, sublicense, and/or
+// sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.
+// IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR
+// OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE
+// USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+//! A cache of the answers of the identity server to email checks, so that we
+//! don't query it on every registration attempt.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use mas_data_model::EmailCheckCacheTtls;
+
+use crate::EmailAllowedResult;
+
+/// Past this number of entries, expired ones are pruned on insertion
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// The cache used by [`crate::is_email_allowed`]
+pub(crate) static EMAIL_CHECK_CACHE: LazyLock<EmailCheckCache> =
+    LazyLock::new(EmailCheckCache::default);
+
+/// The outcome of an email check, which decides how long it is cached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum EmailCheckOutcome {
+    /// The identity server answered
+    Answered(EmailAllowedResult),
+    /// The identity server could not be queried, so we fell back to the given
+    /// result
+    Failed(EmailAllowedResult),
+}
+
+impl EmailCheckOutcome {
+    pub(crate) fn result(&self) -> &EmailAllowedResult {
+        match self {
+            Self::Answered(result) | Self::Failed(result) => result,
+        }
+    }
+
+    fn ttl(&self, config: &EmailCheckCacheTtls) -> Duration {
+        match self {
+            Self::Answered(EmailAllowedResult::Allowed) => config.allowed,
+            Self::Answered(EmailAllowedResult::WrongServer) => config.wrong_server,
+            Self::Answered(EmailAllowedResult::InvitationMissing) => config.invitation_missing,
+            Self::Failed(_) => config.error,
+        }
+    }
+}
+
+/// The key of a cache entry. The answer depends on the identity server and on
+/// the server names we accept, not only on the email
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    email: String,
+    context: String,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct EmailCheckCache {
+    entries: Mutex<HashMap<CacheKey, (EmailAllowedResult, DateTime<Utc>)>>,
+}
+
+impl EmailCheckCache {
+    fn key(email: &str, context: &str) -> CacheKey {
+        CacheKey {
+            email: email.to_lowercase(),
+            context: context.to_owned(),
+        }
+    }
+
+    /// Get the cached result for the email, if it didn't expire yet
+    pub(crate) fn get(
+        &self,
+        email: &str,
+        context: &str,
+        now: DateTime<Utc>,
+    ) -> Option<EmailAllowedResult> {
+        let key = Self::key(email, context);
+        let mut entries = self.entries.lock().expect("email check cache poisoned");
+        match entries.get(&key) {
+            Some((result, expires_at)) if *expires_at > now => Some(result.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Remember the outcome of a check, for as long as configured for this
+    /// kind of outcome
+    pub(crate) fn insert(
+        &self,
+        email: &str,
+        context: &str,
+        outcome: &EmailCheckOutcome,
+        config: &EmailCheckCacheTtls,
+        now: DateTime<Utc>,
+    ) {
+        let ttl = outcome.ttl(config);
+        if ttl <= Duration::zero() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().expect("email check cache poisoned");
+        if entries.len() >= PRUNE_THRESHOLD {
+            entries.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+        entries.insert(
+            Self::key(email, context),
+            (outcome.result().clone(), now + ttl),
+        );
+    }
+
+    /// Forget everything we know about the email
+    pub(crate) fn bust(&self, email: &str) {
+        let email = email.to_lowercase();
+        self.entries
+            .lock()
+            .expect("email check cache poisoned")
+            .retain(|key, _| key.email != email);
+    }
+}
@@ -26,22 +26,138 @@
 //! This module provides utilities for interacting with the Matrix identity
 //! server API.
 
-use mas_data_model::TchapConfig;
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use mas_data_model::{IdentityServerToken, TchapConfig};
 use mas_http::RequestBuilderExt;
-use tracing::info;
+use opentelemetry::{
+    KeyValue,
+    metrics::{Counter, Meter},
+};
+use reqwest::StatusCode;
+use tracing::{error, info, warn};
+
+static METER: LazyLock<Meter> = LazyLock::new(|| {
+    let scope = opentelemetry::InstrumentationScope::builder(env!("CARGO_PKG_NAME"))
+        .with_version(env!("CARGO_PKG_VERSION"))
+        .with_schema_url(opentelemetry_semantic_conventions::SCHEMA_URL)
+        .build();
+
+    opentelemetry::global::meter_with_scope(scope)
+});
+
+static IDENTITY_SERVER_REQUESTS: LazyLock<Counter<u64>> = LazyLock::new(|| {
+    METER
+        .u64_counter("mas.tchap.identity_server.requests")
+        .with_description("Number of requests made to the identity server, by result")
+        .with_unit("{request}")
+        .build()
+});
+
+/// Tokens last read from disk, keyed by the path of the file they come from
+static TOKEN_FILES: LazyLock<Mutex<HashMap<Utf8PathBuf, String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// An error which happened while querying the identity server
+#[derive(Debug, thiserror::Error)]
+pub enum IdentityClientError {
+    /// The identity server rejected the token, or requires one which is not
+    /// configured
+    #[error("The identity server rejected the request with {0}, check the configured token")]
+    Unauthorized(StatusCode),
+
+    /// The token could not be read from its file
+    #[error("Could not read the identity server token from {path}")]
+    Token {
+        path: Utf8PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The request failed, or the response could not be parsed
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+}
+
+impl IdentityClientError {
+    fn as_metric_result(&self) -> &'static str {
+        match self {
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Token { .. } => "token_error",
+            Self::Request(_) => "error",
+        }
+    }
+}
+
+/// Read the token from its file, or from the cache unless `reload` is set
+async fn token_from_file(path: &Utf8Path, reload: bool) -> Result<String, IdentityClientError> {
+    if !reload && let Some(token) = TOKEN_FILES.lock().unwrap().get(path) {
+        return Ok(token.clone());
+    }
+
+    let token = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|source| IdentityClientError::Token {
+            path: path.to_owned(),
+            source,
+        })?
+        .trim()
+        .to_owned();
+
+    TOKEN_FILES
+        .lock()
+        .unwrap()
+        .insert(path.to_owned(), token.clone());
+
+    Ok(token)
+}
+
+async fn resolve_token(
+    token: Option<&IdentityServerToken>,
+) -> Result<Option<String>, IdentityClientError> {
+    match token {
+        Some(IdentityServerToken::Inline(token)) => Ok(Some(token.clone())),
+        Some(IdentityServerToken::File(path)) => Ok(Some(token_from_file(path, false).await?)),
+        None => Ok(None),
+    }
+}
+
+fn is_auth_failure(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
 
 /// Queries the identity server for information about an email address
 ///
 /// # Parameters
 ///
-/// * `email`: The email address to check///
+/// * `email`: The email address to check
+///
 /// # Returns
 ///
 /// A Result containing either the JSON response or an error
 pub async fn query_identity_server(
     email: &str,
     tchap_config: &TchapConfig,
-) -> Result<serde_json::Value, reqwest::Error> {
+) -> Result<serde_json::Value, IdentityClientError> {
+    let result = query_identity_server_inner(email, tchap_config).await;
+
+    let metric_result = match &result {
+        Ok(_) => "success",
+        Err(e) => e.as_metric_result(),
+    };
+    IDENTITY_SERVER_REQUESTS.add(1, &[KeyValue::new("result", metric_result)]);
+
+    result
+}
+
+async fn query_identity_server_inner(
+    email: &str,
+    tchap_config: &TchapConfig,
+) -> Result<serde_json::Value, IdentityClientError> {
     let identity_server_url = &tchap_config.identity_server_url;
 
     // Construct the URL with the email address
@@ -54,16 +170,168 @@ pub async fn query_identity_server(
     info!("Making request to identity server: {}", url);
 
     let http_client = mas_http::reqwest_client();
+    let send = |token: Option<String>| {
+        let mut request = http_client.get(&url).query(&query_params);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send_traced()
+    };
 
     // Make the HTTP request asynchronously
-    let response = http_client
-        .get(url)
-        .query(&query_params)
-        .send_traced()
-        .await?;
+    let token = resolve_token(tchap_config.identity_server_token.as_ref()).await?;
+    let mut response = send(token.clone()).await?;
+
+    // The token file may have been rotated since we last read it: read it again
+    // and retry once if it changed
+    if is_auth_failure(response.status())
+        && let Some(IdentityServerToken::File(path)) = &tchap_config.identity_server_token
+    {
+        let new_token = token_from_file(path, true).await?;
+        if token.as_ref() != Some(&new_token) {
+            info!("The identity server token changed on disk, retrying");
+            response = send(Some(new_token)).await?;
+        }
+    }
+
+    let status = response.status();
+    if is_auth_failure(status) {
+        if tchap_config.identity_server_token.is_some() {
+            error!("The identity server rejected the configured token with {status}");
+        } else {
+            warn!("The identity server requires a token, but none is configured");
+        }
+        return Err(IdentityClientError::Unauthorized(status));
+    }
 
     // Parse the JSON response
     let json = response.json::<serde_json::Value>().await?;
 
     Ok(json)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use mas_data_model::EmailCheckCacheTtls;
+    use serde_json::json;
+    use url::Url;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method, path},
+    };
+
+    use super::*;
+
+    fn config(mock_server: &MockServer, token: Option<IdentityServerToken>) -> TchapConfig {
+        TchapConfig {
+            identity_server_url: Url::parse(&(mock_server.uri() + "/")).unwrap(),
+            identity_server_token: token,
+            email_lookup_fallback_rules: vec![],
+            additional_server_names: vec![],
+            domain_labels: BTreeMap::new(),
+            email_check_cache: EmailCheckCacheTtls::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_sends_token() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/identity/api/v1/internal-info"))
+            .and(header("authorization", "Bearer s3cret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "hs": "hs1" })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = config(
+            &mock_server,
+            Some(IdentityServerToken::Inline("s3cret".to_owned())),
+        );
+        let json = query_identity_server("user@example.org", &config)
+            .await
+            .unwrap();
+        assert_eq!(json, json!({ "hs": "hs1" }));
+    }
+
+    #[tokio::test]
+    async fn test_query_unauthorized() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/identity/api/v1/internal-info"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = config(&mock_server, None);
+        let error = query_identity_server("user@example.org", &config)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                error,
+                IdentityClientError::Unauthorized(StatusCode::UNAUTHORIZED)
+            ),
+            "{error:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_file_rotation() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/identity/api/v1/internal-info"))
+            .and(header("authorization", "Bearer new-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "hs": "hs1" })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/identity/api/v1/internal-info"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let token_file = Utf8PathBuf::from_path_buf(std::env::temp_dir().join(format!(
+            "mas-tchap-identity-server-token-{}",
+            std::process::id()
+        )))
+        .unwrap();
+        let config = config(
+            &mock_server,
+            Some(IdentityServerToken::File(token_file.clone())),
+        );
+
+        // The token is rejected, and reading the file again doesn't help
+        std::fs::write(&token_file, "old-token\n").unwrap();
+        let error = query_identity_server("user@example.org", &config)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, IdentityClientError::Unauthorized(_)),
+            "{error:?}"
+        );
+
+        // The token got rotated: the cached one gets rejected, then the new one
+        // is read from the file and accepted
+        std::fs::write(&token_file, "new-token\n").unwrap();
+        query_identity_server("user@example.org", &config)
+            .await
+            .unwrap();
+
+        // The new token is now used straight away
+        query_identity_server("user@example.org", &config)
+            .await
+            .unwrap();
+
+        std::fs::remove_file(&token_file).unwrap();
+    }
+}
@@ -26,10 +26,13 @@
 //! Tchap-specific functionality for Matrix Authentication Service
 
 extern crate tracing;
-use mas_data_model::TchapConfig;
+use mas_data_model::{Clock, SystemClock, TchapConfig};
 use mas_storage::BoxRepository;
 use tracing::info;
 
+use self::email_check_cache::{EMAIL_CHECK_CACHE, EmailCheckCache, EmailCheckOutcome};
+
+mod email_check_cache;
 mod identity_client;
 mod test_utils;
 
@@ -177,10 +180,15 @@ pub enum EmailAllowedResult {
 /// server API to retrieve information about the home server associated with an
 /// email address, then applies logic to determine if the email is allowed.
 ///
+/// Answers are cached for as long as configured for each outcome in the Tchap
+/// configuration, see [`bust`] to invalidate them.
+///
 /// # Parameters
 ///
 /// * `email`: The email address to check
-/// * `server_name`: The name of the server to check against
+/// * `server_name`: The name of the server to check against. The additional
+///   server names from the Tchap configuration are also accepted, and names are
+///   compared case-insensitively
 ///
 /// # Returns
 ///
@@ -192,18 +200,71 @@ pub async fn is_email_allowed(
     server_name: &str,
     tchap_config: &TchapConfig,
 ) -> EmailAllowedResult {
+    is_email_allowed_with_cache(
+        email,
+        server_name,
+        tchap_config,
+        &EMAIL_CHECK_CACHE,
+        &SystemClock::default(),
+    )
+    .await
+}
+
+/// Forget the cached answers of the identity server for an email address, so
+/// that the next check queries it again.
+///
+/// This must be called when an invitation is sent to this address, as it
+/// changes the answer of the identity server.
+pub fn bust(email: &str) {
+    EMAIL_CHECK_CACHE.bust(email);
+}
+
+async fn is_email_allowed_with_cache(
+    email: &str,
+    server_name: &str,
+    tchap_config: &TchapConfig,
+    cache: &EmailCheckCache,
+    clock: &dyn Clock,
+) -> EmailAllowedResult {
+    let context = format!("{}#{server_name}", tchap_config.identity_server_url);
+    if let Some(result) = cache.get(email, &context, clock.now()) {
+        return result;
+    }
+
+    let outcome = check_email_with_identity_server(email, server_name, tchap_config).await;
+    cache.insert(
+        email,
+        &context,
+        &outcome,
+        &tchap_config.email_check_cache,
+        clock.now(),
+    );
+    outcome.result().clone()
+}
+
+async fn check_email_with_identity_server(
+    email: &str,
+    server_name: &str,
+    tchap_config: &TchapConfig,
+) -> EmailCheckOutcome {
     // Query the identity server
     match identity_client::query_identity_server(email, tchap_config).await {
         Ok(json) => {
-            let hs = json.get("hs");
-
-            // Check if "hs" is in the response or if hs different from server_name
-            if hs.is_none() || hs.unwrap() != server_name {
-                // Email is mapped to a different server or no server at all
-                return EmailAllowedResult::WrongServer;
+            // Check if "hs" is in the response and matches one of our server names
+            let Some(hs) = json.get("hs").and_then(|v| v.as_str()) else {
+                // Email is not mapped to any server, or "hs" is not a string
+                return EmailCheckOutcome::Answered(EmailAllowedResult::WrongServer);
+            };
+
+            if !tchap_config
+                .acceptable_server_names(server_name)
+                .any(|name| name.eq_ignore_ascii_case(hs))
+            {
+                // Email is mapped to a different server
+                return EmailCheckOutcome::Answered(EmailAllowedResult::WrongServer);
             }
 
-            info!("hs: {} ", hs.unwrap());
+            info!("hs: {} ", hs);
 
             // Check if requires_invite is true and invited is false
             let requires_invite = json
@@ -220,16 +281,16 @@ pub async fn is_email_allowed(
 
             if requires_invite && !invited {
                 // Requires an invite but hasn't been invited
-                return EmailAllowedResult::InvitationMissing;
+                return EmailCheckOutcome::Answered(EmailAllowedResult::InvitationMissing);
             }
 
             // All checks passed
-            EmailAllowedResult::Allowed
+            EmailCheckOutcome::Answered(EmailAllowedResult::Allowed)
         }
         Err(err) => {
             // Log the error and return WrongServer as a default error
             eprintln!("HTTP request failed: {}", err);
-            EmailAllowedResult::WrongServer
+            EmailCheckOutcome::Failed(EmailAllowedResult::WrongServer)
         }
     }
 }
@@ -312,6 +373,10 @@ pub use self::test_utils::*;
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::Duration;
+    use mas_data_model::{EmailCheckCacheTtls, clock::MockClock};
     use serde_json::json;
     use url::Url;
     use wiremock::{
@@ -321,6 +386,24 @@ mod tests {
 
     use super::*;
 
+    /// Check an email with a fresh cache, as the mock servers are pooled and
+    /// their URLs reused across tests
+    async fn is_email_allowed(
+        email: &str,
+        server_name: &str,
+        tchap_config: &TchapConfig,
+    ) -> EmailAllowedResult {
+        let cache = EmailCheckCache::default();
+        is_email_allowed_with_cache(
+            email,
+            server_name,
+            tchap_config,
+            &cache,
+            &SystemClock::default(),
+        )
+        .await
+    }
+
     #[test]
     fn test_cap() {
         assert_eq!(cap("john"), "John");
@@ -417,7 +500,11 @@ mod tests {
         let url = mock_server.uri() + "/";
         let config = TchapConfig {
             identity_server_url: Url::parse(url.as_str()).unwrap(),
+            identity_server_token: None,
             email_lookup_fallback_rules: vec![],
+            additional_server_names: vec![],
+            domain_labels: BTreeMap::new(),
+            email_check_cache: EmailCheckCacheTtls::default(),
         };
 
         let result = is_email_allowed(email, server_name, &config).await;
@@ -452,7 +539,11 @@ mod tests {
         let url = mock_server.uri() + "/";
         let config = TchapConfig {
             identity_server_url: Url::parse(url.as_str()).unwrap(),
+            identity_server_token: None,
             email_lookup_fallback_rules: vec![],
+            additional_server_names: vec![],
+            domain_labels: BTreeMap::new(),
+            email_check_cache: EmailCheckCacheTtls::default(),
         };
 
         let result = is_email_allowed(email, server_name, &config).await;
@@ -487,7 +578,11 @@ mod tests {
         let url = mock_server.uri() + "/";
         let config = TchapConfig {
             identity_server_url: Url::parse(url.as_str()).unwrap(),
+            identity_server_token: None,
             email_lookup_fallback_rules: vec![],
+            additional_server_names: vec![],
+            domain_labels: BTreeMap::new(),
+            email_check_cache: EmailCheckCacheTtls::default(),
         };
 
         let result = is_email_allowed(email, server_name, &config).await;
@@ -522,11 +617,240 @@ mod tests {
         let url = mock_server.uri() + "/";
         let config = TchapConfig {
             identity_server_url: Url::parse(url.as_str()).unwrap(),
+            identity_server_token: None,
             email_lookup_fallback_rules: vec![],
+            additional_server_names: vec![],
+            domain_labels: BTreeMap::new(),
+            email_check_cache: EmailCheckCacheTtls::default(),
         };
 
         let result = is_email_allowed(email, server_name, &config).await;
 
         assert_eq!(result, EmailAllowedResult::Allowed);
     }
+
+    /// Mount a mock identity server answering with the given `hs` value, and
+    /// return the config pointing to it
+    async fn mock_identity_server(
+        mock_server: &MockServer,
+        email: &str,
+        hs: serde_json::Value,
+        additional_server_names: Vec<String>,
+    ) -> TchapConfig {
+        Mock::given(method("GET"))
+            .and(path("/_matrix/identity/api/v1/internal-info"))
+            .and(query_param("medium", "email"))
+            .and(query_param("address", email))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "hs": hs,
+                "requires_invite": false,
+            })))
+            .expect(1)
+            .mount(mock_server)
+            .await;
+
+        let url = mock_server.uri() + "/";
+        TchapConfig {
+            identity_server_url: Url::parse(url.as_str()).unwrap(),
+            identity_server_token: None,
+            email_lookup_fallback_rules: vec![],
+            additional_server_names,
+            domain_labels: BTreeMap::new(),
+            email_check_cache: EmailCheckCacheTtls::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_is_email_allowed_case_insensitive() {
+        let email = "user@example.org";
+        let mock_server = MockServer::start().await;
+        let config =
+            mock_identity_server(&mock_server, email, json!("Agent.Tchap.Gouv.FR"), vec![]).await;
+
+        let result = is_email_allowed(email, "agent.tchap.gouv.fr", &config).await;
+
+        assert_eq!(result, EmailAllowedResult::Allowed);
+    }
+
+    #[tokio::test]
+    async fn test_is_email_allowed_additional_server_name() {
+        let email = "user@example.org";
+        let mock_server = MockServer::start().await;
+        let config = mock_identity_server(
+            &mock_server,
+            email,
+            json!("legacy.tchap.gouv.fr"),
+            vec!["Legacy.Tchap.Gouv.Fr".to_owned()],
+        )
+        .await;
+
+        let result = is_email_allowed(email, "agent.tchap.gouv.fr", &config).await;
+
+        assert_eq!(result, EmailAllowedResult::Allowed);
+    }
+
+    #[tokio::test]
+    async fn test_is_email_allowed_non_string_hs() {
+        let email = "user@example.org";
+        let mock_server = MockServer::start().await;
+        let config =
+            mock_identity_server(&mock_server, email, json!(42), vec!["42".to_owned()]).await;
+
+        let result = is_email_allowed(email, "agent.tchap.gouv.fr", &config).await;
+
+        assert_eq!(result, EmailAllowedResult::WrongServer);
+    }
+
+    /// Mount a mock identity server answering `times` times with the given
+    /// response for the email
+    async fn mount_identity_answer(
+        mock_server: &MockServer,
+        email: &str,
+        response: ResponseTemplate,
+        times: u64,
+    ) {
+        Mock::given(method("GET"))
+            .and(path("/_matrix/identity/api/v1/internal-info"))
+            .and(query_param("medium", "email"))
+            .and(query_param("address", email))
+            .respond_with(response)
+            .expect(times)
+            .mount(mock_server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_email_check_cache_ttls() {
+        let server_name = "homeserver1";
+        let mock_server = MockServer::start().await;
+        let cache = EmailCheckCache::default();
+        let clock = MockClock::default();
+
+        let url = mock_server.uri() + "/";
+        let config = TchapConfig {
+            identity_server_url: Url::parse(url.as_str()).unwrap(),
+            identity_server_token: None,
+            email_lookup_fallback_rules: vec![],
+            additional_server_names: vec![],
+            domain_labels: BTreeMap::new(),
+            email_check_cache: EmailCheckCacheTtls {
+                allowed: Duration::hours(1),
+                wrong_server: Duration::hours(6),
+                invitation_missing: Duration::zero(),
+                error: Duration::minutes(1),
+            },
+        };
+
+        let allowed = "allowed@example.org";
+        let wrong_server = "wrong@example.org";
+        let not_invited = "not-invited@example.org";
+        let failing = "failing@example.org";
+
+        mount_identity_answer(
+            &mock_server,
+            allowed,
+            ResponseTemplate::new(200).set_body_json(json!({ "hs": server_name })),
+            2,
+        )
+        .await;
+        mount_identity_answer(
+            &mock_server,
+            wrong_server,
+            ResponseTemplate::new(200).set_body_json(json!({ "hs": "homeserver2" })),
+            2,
+        )
+        .await;
+        mount_identity_answer(
+            &mock_server,
+            not_invited,
+            ResponseTemplate::new(200).set_body_json(json!({
+                "hs": server_name,
+                "requires_invite": true,
+                "invited": false,
+            })),
+            3,
+        )
+        .await;
+        mount_identity_answer(
+            &mock_server,
+            failing,
+            ResponseTemplate::new(500).set_body_string("oops"),
+            2,
+        )
+        .await;
+
+        let check =
+            |email| is_email_allowed_with_cache(email, server_name, &config, &cache, &clock);
+
+        // The first answers are cached, except the missing invitation
+        for _ in 0..3 {
+            assert_eq!(check(allowed).await, EmailAllowedResult::Allowed);
+            assert_eq!(check(wrong_server).await, EmailAllowedResult::WrongServer);
+            assert_eq!(check(failing).await, EmailAllowedResult::WrongServer);
+        }
+        assert_eq!(
+            check(not_invited).await,
+            EmailAllowedResult::InvitationMissing
+        );
+        assert_eq!(
+            check(not_invited).await,
+            EmailAllowedResult::InvitationMissing
+        );
+
+        // Errors are retried after a minute
+        clock.advance(Duration::minutes(1));
+        assert_eq!(check(failing).await, EmailAllowedResult::WrongServer);
+
+        // Positive answers after an hour
+        clock.advance(Duration::hours(1));
+        assert_eq!(check(allowed).await, EmailAllowedResult::Allowed);
+        assert_eq!(check(wrong_server).await, EmailAllowedResult::WrongServer);
+
+        // Answers saying the email is on another server after 6 hours
+        clock.advance(Duration::hours(5));
+        assert_eq!(check(wrong_server).await, EmailAllowedResult::WrongServer);
+        assert_eq!(
+            check(not_invited).await,
+            EmailAllowedResult::InvitationMissing
+        );
+    }
+
+    #[tokio::test]
+    async fn test_email_check_cache_bust() {
+        let email = "user@example.org";
+        let server_name = "homeserver1";
+        let mock_server = MockServer::start().await;
+        let cache = EmailCheckCache::default();
+        let clock = MockClock::default();
+
+        mount_identity_answer(
+            &mock_server,
+            email,
+            ResponseTemplate::new(200).set_body_json(json!({ "hs": server_name })),
+            2,
+        )
+        .await;
+
+        let url = mock_server.uri() + "/";
+        let config = TchapConfig {
+            identity_server_url: Url::parse(url.as_str()).unwrap(),
+            identity_server_token: None,
+            email_lookup_fallback_rules: vec![],
+            additional_server_names: vec![],
+            domain_labels: BTreeMap::new(),
+            email_check_cache: EmailCheckCacheTtls::default(),
+        };
+
+        for _ in 0..2 {
+            let result =
+                is_email_allowed_with_cache(email, server_name, &config, &cache, &clock).await;
+            assert_eq!(result, EmailAllowedResult::Allowed);
+        }
+
+        // Busting is case-insensitive, and makes the next check query the
+        // identity server again
+        cache.bust("User@Example.org");
+        let result = is_email_allowed_with_cache(email, server_name, &config, &cache, &clock).await;
+        assert_eq!(result, EmailAllowedResult::Allowed);
+    }
 }
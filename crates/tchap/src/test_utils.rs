@@ -23,15 +23,21 @@
 // USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 
-use mas_data_model::{EmailLookupFallbackRule, TchapConfig};
+use std::collections::BTreeMap;
+
+use mas_data_model::{EmailCheckCacheTtls, EmailLookupFallbackRule, TchapConfig};
 use url::Url;
 
 pub fn test_tchap_config() -> TchapConfig {
     TchapConfig {
         identity_server_url: Url::parse("http://localhost:8091").unwrap(),
+        identity_server_token: None,
         email_lookup_fallback_rules: vec![EmailLookupFallbackRule {
             match_with: "@numerique.gouv.fr".to_string(),
             search: "@beta.gouv.fr".to_string(),
         }],
+        additional_server_names: vec![],
+        domain_labels: BTreeMap::from([("numerique.gouv.fr".to_owned(), "DINUM".to_owned())]),
+        email_check_cache: EmailCheckCacheTtls::default(),
     }
 }
@@ -8,6 +8,7 @@
 
 mod branding;
 mod captcha;
+mod error_code;
 mod ext;
 mod features;
 
@@ -23,12 +24,13 @@ use mas_data_model::{
     AuthorizationGrant, BrowserSession, Client, CompatSsoLogin, CompatSsoLoginState,
     DeviceCodeGrant, UpstreamOAuthLink, UpstreamOAuthProvider, UpstreamOAuthProviderClaimsImports,
     UpstreamOAuthProviderDiscoveryMode, UpstreamOAuthProviderOnBackchannelLogout,
-    UpstreamOAuthProviderPkceMode, UpstreamOAuthProviderTokenAuthMethod, User,
-    UserEmailAuthentication, UserEmailAuthenticationCode, UserRecoverySession, UserRegistration,
+    UpstreamOAuthProviderPkceMode, UpstreamOAuthProviderTokenAuthMethod, User, UserEmail,
+    UserEmailAuthentication, UserEmailAuthenticationCode, UserEmailChange, UserRecoverySession,
+    UserRegistration,
 };
 use mas_i18n::DataLocale;
 use mas_iana::jose::JsonWebSignatureAlg;
-use mas_router::{Account, GraphQL, PostAuthAction, UrlBuilder};
+use mas_router::{Account, AccountAction, GraphQL, PostAuthAction, Reauth, UrlBuilder};
 use oauth2_types::scope::{OPENID, Scope};
 use rand::{
     Rng,
@@ -39,9 +41,10 @@ use ulid::Ulid;
 use url::Url;
 
 pub use self::{
-    branding::SiteBranding, captcha::WithCaptcha, ext::SiteConfigExt, features::SiteFeatures,
+    branding::SiteBranding, captcha::WithCaptcha, error_code::ErrorCode, ext::SiteConfigExt,
+    features::SiteFeatures,
 };
-use crate::{FieldError, FormField, FormState};
+use crate::{FieldError, FormError, FormField, FormState};
 
 /// Helper trait to construct context wrappers
 pub trait TemplateContext: Serialize {
@@ -384,13 +387,116 @@ impl TemplateContext for IndexContext {
     }
 }
 
+/// An upstream provider, as exposed to the frontend app
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FrontendUpstreamProvider {
+    id: Ulid,
+    human_name: Option<String>,
+    brand_name: Option<String>,
+}
+
+impl From<&UpstreamOAuthProvider> for FrontendUpstreamProvider {
+    fn from(provider: &UpstreamOAuthProvider) -> Self {
+        Self {
+            id: provider.id,
+            human_name: provider.human_name.clone(),
+            brand_name: provider.brand_name.clone(),
+        }
+    }
+}
+
+/// Subset of the site configuration exposed to the frontend app
+///
+/// This is exposed publicly, both in the `app.html` template and through an
+/// unauthenticated endpoint, so only add fields which are safe to share with
+/// anyone.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FrontendConfig {
+    /// Whether local password-based login is enabled
+    pub password_login_enabled: bool,
+
+    /// Whether local password-based registration is enabled
+    pub password_registration_enabled: bool,
+
+    /// Whether users can change their password
+    pub password_change_allowed: bool,
+
+    /// Whether users can change their email addresses
+    pub email_change_allowed: bool,
+
+    /// Whether email-based account recovery is enabled
+    pub account_recovery_allowed: bool,
+
+    /// The enabled upstream providers
+    pub upstream_providers: Vec<FrontendUpstreamProvider>,
+
+    //:tchap:
+    /// Whether the Tchap-specific behaviours are enabled
+    pub tchap: bool,
+    //:tchap:end
+}
+
+impl FrontendConfig {
+    fn sample() -> Self {
+        Self {
+            password_login_enabled: true,
+            password_registration_enabled: true,
+            password_change_allowed: true,
+            email_change_allowed: true,
+            account_recovery_allowed: true,
+            upstream_providers: Vec::new(),
+            //:tchap:
+            tchap: true,
+            //:tchap:end
+        }
+    }
+}
+
 /// Config used by the frontend app
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AppConfig {
     root: String,
     graphql_endpoint: String,
+    reauth_endpoint: String,
+    frontend_config: FrontendConfig,
+    /// The MSC2965 action requested when opening the app, if any
+    action: Option<AccountAction>,
+    //:tchap:
+    profile_badge: Option<ProfileBadge>,
+    //:tchap:end
+}
+
+//:tchap:
+/// Badge shown in the profile area of the app, only ever rendered for the
+/// logged-in user
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileBadge {
+    organization: Option<String>,
+    email_domain_label: Option<String>,
+}
+
+impl ProfileBadge {
+    /// Constructs a badge from the organization of the user and the label
+    /// derived from their email domain. Returns `None` if there is nothing
+    /// to show.
+    #[must_use]
+    pub fn new(organization: Option<String>, email_domain_label: Option<String>) -> Option<Self> {
+        if organization.is_none() && email_domain_label.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            organization,
+            email_domain_label,
+        })
+    }
 }
+//:tchap:end
 
 /// Context used by the `app.html` template
 #[derive(Serialize)]
@@ -399,18 +505,42 @@ pub struct AppContext {
 }
 
 impl AppContext {
-    /// Constructs the context given the [`UrlBuilder`]
+    /// Constructs the context given the [`UrlBuilder`] and the
+    /// [`FrontendConfig`]
     #[must_use]
-    pub fn from_url_builder(url_builder: &UrlBuilder) -> Self {
+    pub fn from_url_builder(url_builder: &UrlBuilder, frontend_config: FrontendConfig) -> Self {
         let root = url_builder.relative_url_for(&Account::default());
         let graphql_endpoint = url_builder.relative_url_for(&GraphQL);
+        let reauth_endpoint = url_builder.relative_url_for(&Reauth::default());
         Self {
             app_config: AppConfig {
                 root,
                 graphql_endpoint,
+                reauth_endpoint,
+                frontend_config,
+                action: None,
+                //:tchap:
+                profile_badge: None,
+                //:tchap:end
             },
         }
     }
+
+    /// Set the account management action requested when opening the app
+    #[must_use]
+    pub fn with_action(mut self, action: Option<AccountAction>) -> Self {
+        self.app_config.action = action;
+        self
+    }
+
+    //:tchap:
+    /// Set the badge shown in the profile area
+    #[must_use]
+    pub fn with_profile_badge(mut self, profile_badge: Option<ProfileBadge>) -> Self {
+        self.app_config.profile_badge = profile_badge;
+        self
+    }
+    //:tchap:end
 }
 
 impl TemplateContext for AppContext {
@@ -423,7 +553,22 @@ impl TemplateContext for AppContext {
         Self: Sized,
     {
         let url_builder = UrlBuilder::new("https://example.com/".parse().unwrap(), None, None);
-        sample_list(vec![Self::from_url_builder(&url_builder)])
+        sample_list(vec![
+            Self::from_url_builder(&url_builder, FrontendConfig::sample()),
+            Self::from_url_builder(&url_builder, FrontendConfig::sample()).with_action(Some(
+                AccountAction::OrgMatrixSessionEnd {
+                    device_id: "ABCDEF".to_owned(),
+                },
+            )),
+            //:tchap:
+            Self::from_url_builder(&url_builder, FrontendConfig::sample()).with_profile_badge(
+                ProfileBadge::new(
+                    Some("Ministère de l'Intérieur".to_owned()),
+                    Some("DINUM".to_owned()),
+                ),
+            ),
+            //:tchap:end
+        ])
     }
 }
 
@@ -517,6 +662,9 @@ pub enum PostAuthContextInner {
 
     /// Go to the account management page
     ManageAccount,
+
+    /// Recover the account after authenticating with an upstream provider
+    RecoverAccount,
 }
 
 /// Context used in login screen, for the post-auth action to do
@@ -578,6 +726,11 @@ impl TemplateContext for LoginContext {
                 next: None,
                 providers: Vec::new(),
             },
+            LoginContext {
+                form: FormState::default().with_error_on_form(FormError::DeactivationScheduled),
+                next: None,
+                providers: Vec::new(),
+            },
         ])
     }
 }
@@ -610,6 +763,90 @@ impl LoginContext {
     }
 }
 
+/// Fields of the re-authentication form
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReauthFormField {
+    /// The password field
+    Password,
+}
+
+impl FormField for ReauthFormField {
+    fn keep(&self) -> bool {
+        match self {
+            Self::Password => false,
+        }
+    }
+}
+
+/// Context used by the `reauth.html` template
+#[derive(Serialize)]
+pub struct ReauthContext {
+    form: FormState<ReauthFormField>,
+    next: Option<PostAuthContext>,
+    providers: Vec<UpstreamOAuthProvider>,
+    user: User,
+}
+
+impl TemplateContext for ReauthContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        sample_list(
+            User::samples(now, rng)
+                .into_iter()
+                .flat_map(|user| {
+                    [
+                        Self::new(user.clone()),
+                        Self::new(user).with_form_state(
+                            FormState::default().with_error_on_form(FormError::InvalidCredentials),
+                        ),
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
+
+impl ReauthContext {
+    /// Constructs a context for the re-authentication page of the given user
+    #[must_use]
+    pub fn new(user: User) -> Self {
+        Self {
+            form: FormState::default(),
+            next: None,
+            providers: Vec::new(),
+            user,
+        }
+    }
+
+    /// Set the form state
+    #[must_use]
+    pub fn with_form_state(self, form: FormState<ReauthFormField>) -> Self {
+        Self { form, ..self }
+    }
+
+    /// Set the upstream OAuth 2.0 providers
+    #[must_use]
+    pub fn with_upstream_providers(self, providers: Vec<UpstreamOAuthProvider>) -> Self {
+        Self { providers, ..self }
+    }
+
+    /// Add a post authentication action to the context
+    #[must_use]
+    pub fn with_post_action(self, context: PostAuthContext) -> Self {
+        Self {
+            next: Some(context),
+            ..self
+        }
+    }
+}
+
 /// Fields of the registration form
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -729,6 +966,8 @@ pub struct ConsentContext {
     grant: AuthorizationGrant,
     client: Client,
     action: PostAuthAction,
+    can_remember: bool,
+    evicted_sessions: Vec<String>,
     // :tchap:
     email: Option<String>, // :tchap:end
 }
@@ -745,15 +984,24 @@ impl TemplateContext for ConsentContext {
         sample_list(
             Client::samples(now, rng)
                 .into_iter()
-                .map(|client| {
+                .enumerate()
+                .map(|(index, client)| {
                     let mut grant = AuthorizationGrant::sample(now, rng);
                     let action = PostAuthAction::continue_grant(grant.id);
                     // XXX
                     grant.client_id = client.id;
+                    // Have one of the samples end a session when consenting
+                    let evicted_sessions = if index == 0 {
+                        vec!["Element X on iPhone".to_owned()]
+                    } else {
+                        Vec::new()
+                    };
                     Self {
                         grant,
                         client,
                         action,
+                        can_remember: true,
+                        evicted_sessions,
                         // :tchap:
                         email: None, // :tchap: end
                     }
@@ -772,12 +1020,34 @@ impl ConsentContext {
             grant,
             client,
             action,
+            can_remember: false,
+            evicted_sessions: Vec::new(),
             // :tchap:
             email: None,
             // :tchap: end
         }
     }
 
+    /// Set whether the user can choose to remember their decision for this
+    /// client
+    #[must_use]
+    pub fn with_can_remember(self, can_remember: bool) -> Self {
+        Self {
+            can_remember,
+            ..self
+        }
+    }
+
+    /// Set the names of the sessions which will be ended to make room for the
+    /// new one, because the user reached their limit of active sessions
+    #[must_use]
+    pub fn with_evicted_sessions(self, evicted_sessions: Vec<String>) -> Self {
+        Self {
+            evicted_sessions,
+            ..self
+        }
+    }
+
     // :tchap:
     /// Add an email to the context
     #[must_use]
@@ -791,9 +1061,9 @@ impl ConsentContext {
 #[serde(tag = "grant_type")]
 enum PolicyViolationGrant {
     #[serde(rename = "authorization_code")]
-    Authorization(AuthorizationGrant),
+    Authorization(Box<AuthorizationGrant>),
     #[serde(rename = "urn:ietf:params:oauth:grant-type:device_code")]
-    DeviceCode(DeviceCodeGrant),
+    DeviceCode(Box<DeviceCodeGrant>),
 }
 
 /// Context used by the `policy_violation.html` template
@@ -802,6 +1072,7 @@ pub struct PolicyViolationContext {
     grant: PolicyViolationGrant,
     client: Client,
     action: PostAuthAction,
+    code: ErrorCode,
 }
 
 impl TemplateContext for PolicyViolationContext {
@@ -850,26 +1121,36 @@ impl PolicyViolationContext {
     /// Constructs a context for the policy violation page for an authorization
     /// grant
     #[must_use]
-    pub const fn for_authorization_grant(grant: AuthorizationGrant, client: Client) -> Self {
+    pub fn for_authorization_grant(grant: AuthorizationGrant, client: Client) -> Self {
         let action = PostAuthAction::continue_grant(grant.id);
         Self {
-            grant: PolicyViolationGrant::Authorization(grant),
+            grant: PolicyViolationGrant::Authorization(Box::new(grant)),
             client,
             action,
+            code: ErrorCode::PolicyViolation(None),
         }
     }
 
     /// Constructs a context for the policy violation page for a device code
     /// grant
     #[must_use]
-    pub const fn for_device_code_grant(grant: DeviceCodeGrant, client: Client) -> Self {
+    pub fn for_device_code_grant(grant: DeviceCodeGrant, client: Client) -> Self {
         let action = PostAuthAction::continue_device_code_grant(grant.id);
         Self {
-            grant: PolicyViolationGrant::DeviceCode(grant),
+            grant: PolicyViolationGrant::DeviceCode(Box::new(grant)),
             client,
             action,
+            code: ErrorCode::PolicyViolation(None),
         }
     }
+
+    /// Set the code of the policy violation which caused the grant to be
+    /// rejected
+    #[must_use]
+    pub const fn with_violation_code(mut self, code: Option<&'static str>) -> Self {
+        self.code = ErrorCode::PolicyViolation(code);
+        self
+    }
 }
 
 /// Context used by the `sso.html` template
@@ -963,8 +1244,278 @@ impl TemplateContext for EmailRecoveryContext {
 
             let link = "https://example.com/recovery/complete?ticket=abcdefghijklmnopqrstuvwxyz0123456789".parse().unwrap();
 
-            Self::new(user, session, link)
-        }).collect())
+            Self::new(user, session, link)
+        }).collect())
+    }
+}
+
+/// Summary of the sessions ended when an account was recovered
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct EndedSessionsSummary {
+    /// The number of compatibility sessions which were finished
+    pub compat_sessions: usize,
+
+    /// The number of OAuth 2.0 sessions which were finished
+    pub oauth2_sessions: usize,
+
+    /// The number of browser sessions which were finished
+    pub browser_sessions: usize,
+}
+
+/// Context used by the `emails/account_recovered.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct AccountRecoveredContext {
+    user: User,
+    ended_sessions: Option<EndedSessionsSummary>,
+}
+
+impl AccountRecoveredContext {
+    /// Constructs a context for the email notifying a user that their account
+    /// was recovered, and that their other sessions were kept
+    #[must_use]
+    pub fn new(user: User) -> Self {
+        Self {
+            user,
+            ended_sessions: None,
+        }
+    }
+
+    /// Set the summary of the sessions which were ended by the recovery
+    #[must_use]
+    pub fn with_ended_sessions(mut self, ended_sessions: EndedSessionsSummary) -> Self {
+        self.ended_sessions = Some(ended_sessions);
+        self
+    }
+
+    /// Returns the user whose account was recovered
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+}
+
+impl TemplateContext for AccountRecoveredContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        sample_list(
+            User::samples(now, rng)
+                .into_iter()
+                .flat_map(|user| {
+                    vec![
+                        Self::new(user.clone()),
+                        Self::new(user).with_ended_sessions(EndedSessionsSummary {
+                            compat_sessions: 2,
+                            oauth2_sessions: 1,
+                            browser_sessions: 3,
+                        }),
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Context used by the `emails/deactivation_scheduled.{txt,html,subject}`
+/// templates
+#[derive(Serialize)]
+pub struct DeactivationScheduledContext {
+    user: User,
+    scheduled_at: DateTime<Utc>,
+}
+
+impl DeactivationScheduledContext {
+    /// Constructs a context for the email confirming to a user that their
+    /// account will be deactivated at the given time
+    #[must_use]
+    pub fn new(user: User, scheduled_at: DateTime<Utc>) -> Self {
+        Self { user, scheduled_at }
+    }
+
+    /// Returns the user whose account will be deactivated
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+}
+
+impl TemplateContext for DeactivationScheduledContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        sample_list(
+            User::samples(now, rng)
+                .into_iter()
+                .map(|user| Self::new(user, now + Duration::days(7)))
+                .collect(),
+        )
+    }
+}
+
+/// Context used by the `emails/email_added.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct EmailAddedContext {
+    user: User,
+    email: UserEmail,
+}
+
+impl EmailAddedContext {
+    /// Constructs a context for the email notifying a user that an email
+    /// address was added to their account
+    #[must_use]
+    pub fn new(user: User, email: UserEmail) -> Self {
+        Self { user, email }
+    }
+
+    /// Returns the user to which the email address was added
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+}
+
+impl TemplateContext for EmailAddedContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        let emails = UserEmail::samples(now, rng);
+        sample_list(
+            User::samples(now, rng)
+                .into_iter()
+                .zip(emails)
+                .map(|(user, email)| Self::new(user, email))
+                .collect(),
+        )
+    }
+}
+
+/// Context used by the `emails/email_change.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct EmailChangeConfirmationContext {
+    user: User,
+    change: UserEmailChange,
+    confirmation_link: Url,
+}
+
+impl EmailChangeConfirmationContext {
+    /// Constructs a context for the email sent to the previous address of a
+    /// user to confirm a change of email address
+    #[must_use]
+    pub fn new(user: User, change: UserEmailChange, confirmation_link: Url) -> Self {
+        Self {
+            user,
+            change,
+            confirmation_link,
+        }
+    }
+
+    /// Returns the user whose email address is being changed
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    /// Returns the email change to confirm
+    #[must_use]
+    pub fn change(&self) -> &UserEmailChange {
+        &self.change
+    }
+}
+
+impl TemplateContext for EmailChangeConfirmationContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        // Only pending changes get a confirmation email
+        let change = UserEmailChange::samples(now, rng).swap_remove(0);
+        sample_list(
+            User::samples(now, rng)
+                .into_iter()
+                .map(|user| {
+                    let link =
+                        "https://example.com/email-change/abcdefghijklmnopqrstuvwxyz0123456789"
+                            .parse()
+                            .unwrap();
+
+                    Self::new(user, change.clone(), link)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Context used by the `emails/new_login.{txt,html,subject}` templates
+#[derive(Serialize)]
+pub struct NewLoginContext {
+    user: User,
+    session_name: Option<String>,
+    revocation_link: Url,
+}
+
+impl NewLoginContext {
+    /// Constructs a context for the email notifying a user that a new session
+    /// was started on their account
+    #[must_use]
+    pub fn new(user: User, session_name: Option<String>, revocation_link: Url) -> Self {
+        Self {
+            user,
+            session_name,
+            revocation_link,
+        }
+    }
+
+    /// Returns the user who owns the new session
+    #[must_use]
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+}
+
+impl TemplateContext for NewLoginContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        let link: Url =
+            "https://example.com/revoke-session?token=abcdefghijklmnopqrstuvwxyz0123456789"
+                .parse()
+                .unwrap();
+
+        sample_list(
+            User::samples(now, rng)
+                .into_iter()
+                .flat_map(|user| {
+                    [
+                        Self::new(user.clone(), Some("Element X".to_owned()), link.clone()),
+                        Self::new(user, None, link.clone()),
+                    ]
+                })
+                .collect(),
+        )
     }
 }
 
@@ -1266,6 +1817,7 @@ impl FormField for RecoveryStartFormField {
 #[derive(Serialize, Default)]
 pub struct RecoveryStartContext {
     form: FormState<RecoveryStartFormField>,
+    providers: Vec<UpstreamOAuthProvider>,
 }
 
 impl RecoveryStartContext {
@@ -1278,13 +1830,19 @@ impl RecoveryStartContext {
     /// Set the form state
     #[must_use]
     pub fn with_form_state(self, form: FormState<RecoveryStartFormField>) -> Self {
-        Self { form }
+        Self { form, ..self }
+    }
+
+    /// Set the upstream providers which can be used to recover the account
+    #[must_use]
+    pub fn with_upstream_providers(self, providers: Vec<UpstreamOAuthProvider>) -> Self {
+        Self { providers, ..self }
     }
 }
 
 impl TemplateContext for RecoveryStartContext {
     fn sample(
-        _now: chrono::DateTime<Utc>,
+        now: chrono::DateTime<Utc>,
         _rng: &mut impl Rng,
         _locales: &[DataLocale],
     ) -> BTreeMap<SampleIdentifier, Self>
@@ -1301,6 +1859,42 @@ impl TemplateContext for RecoveryStartContext {
                 FormState::default()
                     .with_error_on_field(RecoveryStartFormField::Email, FieldError::Invalid),
             ),
+            Self::new().with_upstream_providers(vec![sample_upstream_provider(now)]),
+        ])
+    }
+}
+
+/// Context used by the `pages/revoke_session.html` template
+#[derive(Serialize)]
+pub struct RevokeSessionContext {
+    session_name: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl RevokeSessionContext {
+    /// Constructs a context for the page confirming that a session should be
+    /// signed out
+    #[must_use]
+    pub fn new(session_name: Option<String>, created_at: DateTime<Utc>) -> Self {
+        Self {
+            session_name,
+            created_at,
+        }
+    }
+}
+
+impl TemplateContext for RevokeSessionContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        _rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        sample_list(vec![
+            Self::new(Some("Element X".to_owned()), now),
+            Self::new(None, now),
         ])
     }
 }
@@ -1392,6 +1986,68 @@ impl TemplateContext for RecoveryExpiredContext {
         sample_list(vec![Self { session }])
     }
 }
+/// The state of an email change, as shown on the `pages/email_change.html`
+/// template
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailChangeState {
+    /// The change is waiting to be confirmed or cancelled
+    Pending,
+
+    /// The change was confirmed and the new address added to the account
+    Confirmed,
+
+    /// The change was cancelled
+    Cancelled,
+
+    /// The new address was claimed by another account in the meantime
+    EmailInUse,
+
+    /// The change wasn't confirmed in time
+    Expired,
+}
+
+/// Context used by the `pages/email_change.html` template
+#[derive(Serialize)]
+pub struct EmailChangeContext {
+    change: UserEmailChange,
+    state: EmailChangeState,
+}
+
+impl EmailChangeContext {
+    /// Constructs a context for the email change confirmation page
+    #[must_use]
+    pub fn new(change: UserEmailChange, state: EmailChangeState) -> Self {
+        Self { change, state }
+    }
+}
+
+impl TemplateContext for EmailChangeContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        let change = UserEmailChange::samples(now, rng).swap_remove(0);
+
+        sample_list(
+            [
+                EmailChangeState::Pending,
+                EmailChangeState::Confirmed,
+                EmailChangeState::Cancelled,
+                EmailChangeState::EmailInUse,
+                EmailChangeState::Expired,
+            ]
+            .into_iter()
+            .map(|state| Self::new(change.clone(), state))
+            .collect(),
+        )
+    }
+}
+
 /// Fields of the account recovery finish form
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -1468,6 +2124,150 @@ impl TemplateContext for RecoveryFinishContext {
     }
 }
 
+/// Context used by the `pages/recovery/upstream.html` template
+#[derive(Serialize)]
+pub struct RecoveryUpstreamContext {
+    user: User,
+    provider: UpstreamOAuthProvider,
+    form: FormState<RecoveryFinishFormField>,
+}
+
+impl RecoveryUpstreamContext {
+    /// Constructs a context for the page shown after recovering an account
+    /// through an upstream provider
+    #[must_use]
+    pub fn new(user: User, provider: UpstreamOAuthProvider) -> Self {
+        Self {
+            user,
+            provider,
+            form: FormState::default(),
+        }
+    }
+
+    /// Set the form state
+    #[must_use]
+    pub fn with_form_state(mut self, form: FormState<RecoveryFinishFormField>) -> Self {
+        self.form = form;
+        self
+    }
+}
+
+impl TemplateContext for RecoveryUpstreamContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        sample_list(
+            User::samples(now, rng)
+                .into_iter()
+                .flat_map(|user| {
+                    let provider = sample_upstream_provider(now);
+                    vec![
+                        Self::new(user.clone(), provider.clone()),
+                        Self::new(user, provider).with_form_state(
+                            FormState::default().with_error_on_field(
+                                RecoveryFinishFormField::NewPasswordConfirm,
+                                FieldError::PasswordMismatch,
+                            ),
+                        ),
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Context used by the `pages/recovery/upstream_unlinked.html` template
+#[derive(Serialize)]
+pub struct RecoveryUpstreamUnlinkedContext {
+    provider: UpstreamOAuthProvider,
+}
+
+impl RecoveryUpstreamUnlinkedContext {
+    /// Constructs a context for the page shown when trying to recover an
+    /// account with an upstream identity which isn't linked to any account
+    #[must_use]
+    pub fn new(provider: UpstreamOAuthProvider) -> Self {
+        Self { provider }
+    }
+}
+
+impl TemplateContext for RecoveryUpstreamUnlinkedContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        _rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        sample_list(vec![Self::new(sample_upstream_provider(now))])
+    }
+}
+
+/// Context used by the `pages/upstream_oauth2/error.html` template
+#[derive(Serialize)]
+pub struct UpstreamErrorContext {
+    provider: UpstreamOAuthProvider,
+    error: String,
+    error_description: Option<String>,
+    retry_url: String,
+}
+
+impl UpstreamErrorContext {
+    /// Constructs a context for the page shown when the upstream provider
+    /// answered the authorization request with an error
+    ///
+    /// # Parameters
+    ///
+    /// * `provider`: the upstream provider which returned the error
+    /// * `error`: the error code returned by the provider
+    /// * `retry_url`: the URL to start the authorization again
+    #[must_use]
+    pub fn new(provider: UpstreamOAuthProvider, error: String, retry_url: String) -> Self {
+        Self {
+            provider,
+            error,
+            error_description: None,
+            retry_url,
+        }
+    }
+
+    /// Set the error description returned by the provider
+    #[must_use]
+    pub fn with_error_description(mut self, error_description: Option<String>) -> Self {
+        self.error_description = error_description;
+        self
+    }
+}
+
+impl TemplateContext for UpstreamErrorContext {
+    fn sample(
+        now: chrono::DateTime<Utc>,
+        _rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        let provider = sample_upstream_provider(now);
+        let retry_url = format!("/upstream/authorize/{}", provider.id);
+        sample_list(vec![
+            Self::new(
+                provider.clone(),
+                "access_denied".to_owned(),
+                retry_url.clone(),
+            ),
+            Self::new(provider, "server_error".to_owned(), retry_url)
+                .with_error_description(Some("The server is on fire".to_owned())),
+        ])
+    }
+}
+
 /// Context used by the `pages/upstream_oauth2/{link_mismatch,login_link}.html`
 /// templates
 #[derive(Serialize)]
@@ -1566,6 +2366,7 @@ pub struct UpstreamRegister {
     force_display_name: bool,
     imported_email: Option<String>,
     force_email: bool,
+    username_suggestions: Vec<String>,
     form_state: FormState<UpstreamRegisterFormField>,
 }
 
@@ -1586,6 +2387,7 @@ impl UpstreamRegister {
             force_display_name: false,
             imported_email: None,
             force_email: false,
+            username_suggestions: Vec::new(),
             form_state: FormState::default(),
         }
     }
@@ -1638,6 +2440,15 @@ impl UpstreamRegister {
         }
     }
 
+    /// Set the available usernames suggested to the user
+    #[must_use]
+    pub fn with_username_suggestions(self, username_suggestions: Vec<String>) -> Self {
+        Self {
+            username_suggestions,
+            ..self
+        }
+    }
+
     /// Set the form state
     pub fn set_form_state(&mut self, form_state: FormState<UpstreamRegisterFormField>) {
         self.form_state = form_state;
@@ -1659,43 +2470,61 @@ impl TemplateContext for UpstreamRegister {
     where
         Self: Sized,
     {
-        sample_list(vec![Self::new(
-            UpstreamOAuthLink {
-                id: Ulid::nil(),
-                provider_id: Ulid::nil(),
-                user_id: None,
-                subject: "subject".to_owned(),
-                human_account_name: Some("@john".to_owned()),
-                created_at: now,
-            },
-            UpstreamOAuthProvider {
-                id: Ulid::nil(),
-                issuer: Some("https://example.com/".to_owned()),
-                human_name: Some("Example Ltd.".to_owned()),
-                brand_name: None,
-                scope: Scope::from_iter([OPENID]),
-                token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::ClientSecretBasic,
-                token_endpoint_signing_alg: None,
-                id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
-                client_id: "client-id".to_owned(),
-                encrypted_client_secret: None,
-                claims_imports: UpstreamOAuthProviderClaimsImports::default(),
-                authorization_endpoint_override: None,
-                token_endpoint_override: None,
-                jwks_uri_override: None,
-                userinfo_endpoint_override: None,
-                fetch_userinfo: false,
-                userinfo_signed_response_alg: None,
-                discovery_mode: UpstreamOAuthProviderDiscoveryMode::Oidc,
-                pkce_mode: UpstreamOAuthProviderPkceMode::Auto,
-                response_mode: None,
-                additional_authorization_parameters: Vec::new(),
-                forward_login_hint: false,
-                created_at: now,
-                disabled_at: None,
-                on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
-            },
-        )])
+        let link = UpstreamOAuthLink {
+            id: Ulid::nil(),
+            provider_id: Ulid::nil(),
+            user_id: None,
+            subject: "subject".to_owned(),
+            human_account_name: Some("@john".to_owned()),
+            created_at: now,
+        };
+        let provider = sample_upstream_provider(now);
+
+        sample_list(vec![
+            Self::new(link.clone(), provider.clone()),
+            // The mapped username is taken, and the user is offered alternatives
+            Self::new(link, provider)
+                .with_localpart("john".to_owned(), false)
+                .with_username_suggestions(vec!["john1".to_owned(), "john2".to_owned()])
+                .with_form_state(
+                    FormState::default().with_error_on_field(
+                        UpstreamRegisterFormField::Username,
+                        FieldError::Exists,
+                    ),
+                ),
+        ])
+    }
+}
+
+/// An upstream provider used in template samples
+fn sample_upstream_provider(now: chrono::DateTime<Utc>) -> UpstreamOAuthProvider {
+    UpstreamOAuthProvider {
+        id: Ulid::nil(),
+        issuer: Some("https://example.com/".to_owned()),
+        human_name: Some("Example Ltd.".to_owned()),
+        brand_name: None,
+        scope: Scope::from_iter([OPENID]),
+        token_endpoint_auth_method: UpstreamOAuthProviderTokenAuthMethod::ClientSecretBasic,
+        token_endpoint_signing_alg: None,
+        id_token_signed_response_alg: JsonWebSignatureAlg::Rs256,
+        client_id: "client-id".to_owned(),
+        encrypted_client_secret: None,
+        claims_imports: UpstreamOAuthProviderClaimsImports::default(),
+        authorization_endpoint_override: None,
+        token_endpoint_override: None,
+        jwks_uri_override: None,
+        userinfo_endpoint_override: None,
+        fetch_userinfo: false,
+        userinfo_signed_response_alg: None,
+        discovery_mode: UpstreamOAuthProviderDiscoveryMode::Oidc,
+        pkce_mode: UpstreamOAuthProviderPkceMode::Auto,
+        response_mode: None,
+        additional_authorization_parameters: Vec::new(),
+        forward_login_hint: false,
+        ui_hidden: false,
+        created_at: now,
+        disabled_at: None,
+        on_backchannel_logout: UpstreamOAuthProviderOnBackchannelLogout::DoNothing,
     }
 }
 
@@ -1755,18 +2584,55 @@ impl TemplateContext for DeviceLinkContext {
     }
 }
 
+/// Form fields on the device consent page
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceConsentFormField {
+    /// The user code field, used to confirm grants for sensitive scopes
+    UserCode,
+}
+
+impl FormField for DeviceConsentFormField {
+    fn keep(&self) -> bool {
+        match self {
+            Self::UserCode => false,
+        }
+    }
+}
+
 /// Context used by the `device_consent.html` template
 #[derive(Serialize, Debug)]
 pub struct DeviceConsentContext {
     grant: DeviceCodeGrant,
     client: Client,
+    user_code_confirmation: bool,
+    form_state: FormState<DeviceConsentFormField>,
 }
 
 impl DeviceConsentContext {
     /// Constructs a new context with an existing linked user
     #[must_use]
     pub fn new(grant: DeviceCodeGrant, client: Client) -> Self {
-        Self { grant, client }
+        Self {
+            grant,
+            client,
+            user_code_confirmation: false,
+            form_state: FormState::default(),
+        }
+    }
+
+    /// Ask the user to re-enter the user code before approving the grant
+    #[must_use]
+    pub fn with_user_code_confirmation(mut self, user_code_confirmation: bool) -> Self {
+        self.user_code_confirmation = user_code_confirmation;
+        self
+    }
+
+    /// Set the form state
+    #[must_use]
+    pub fn with_form_state(mut self, form_state: FormState<DeviceConsentFormField>) -> Self {
+        self.form_state = form_state;
+        self
     }
 }
 
@@ -1781,7 +2647,7 @@ impl TemplateContext for DeviceConsentContext {
     {
         sample_list(Client::samples(now, rng)
             .into_iter()
-            .map(|client|  {
+            .flat_map(|client|  {
                 let grant = DeviceCodeGrant {
                     id: Ulid::from_datetime_with_source(now.into(), rng),
                     state: mas_data_model::DeviceCodeGrantState::Pending,
@@ -1794,7 +2660,15 @@ impl TemplateContext for DeviceConsentContext {
                     ip_address: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
                     user_agent: Some("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/93.0.0.0 Safari/537.36".to_owned()),
                 };
-                Self { grant, client }
+                [
+                    Self::new(grant.clone(), client.clone()),
+                    Self::new(grant, client)
+                        .with_user_code_confirmation(true)
+                        .with_form_state(
+                            FormState::default()
+                                .with_error_on_field(DeviceConsentFormField::UserCode, FieldError::Invalid),
+                        ),
+                ]
             })
             .collect())
     }
@@ -1936,9 +2810,10 @@ impl<T> FormPostContext<T> {
 /// Context used by the `error.html` template
 #[derive(Default, Serialize, Debug, Clone)]
 pub struct ErrorContext {
-    code: Option<&'static str>,
+    code: Option<ErrorCode>,
     description: Option<String>,
     details: Option<String>,
+    request_id: Option<String>,
     lang: Option<String>,
 }
 
@@ -1955,6 +2830,10 @@ impl std::fmt::Display for ErrorContext {
             writeln!(f, "details: {details}")?;
         }
 
+        if let Some(request_id) = &self.request_id {
+            writeln!(f, "request id: {request_id}")?;
+        }
+
         Ok(())
     }
 }
@@ -1970,10 +2849,12 @@ impl TemplateContext for ErrorContext {
     {
         sample_list(vec![
             Self::new()
-                .with_code("sample_error")
+                .with_code(ErrorCode::InternalError)
                 .with_description("A fancy description".into())
-                .with_details("Something happened".into()),
-            Self::new().with_code("another_error"),
+                .with_details("Something happened".into())
+                .with_request_id("http-request-42".into()),
+            Self::new().with_code(ErrorCode::DeviceGrantExpired),
+            Self::new().with_code(ErrorCode::PolicyViolation(Some("username-banned"))),
             Self::new(),
         ])
     }
@@ -1988,7 +2869,7 @@ impl ErrorContext {
 
     /// Add the error code to the context
     #[must_use]
-    pub fn with_code(mut self, code: &'static str) -> Self {
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
         self.code = Some(code);
         self
     }
@@ -2007,6 +2888,14 @@ impl ErrorContext {
         self
     }
 
+    /// Add the ID of the request which failed to the context, so that it can
+    /// be correlated with the server logs
+    #[must_use]
+    pub fn with_request_id(mut self, request_id: String) -> Self {
+        self.request_id = Some(request_id);
+        self
+    }
+
     /// Add the language to the context
     #[must_use]
     pub fn with_language(mut self, lang: &DataLocale) -> Self {
@@ -2016,7 +2905,7 @@ impl ErrorContext {
 
     /// Get the error code, if any
     #[must_use]
-    pub fn code(&self) -> Option<&'static str> {
+    pub fn code(&self) -> Option<ErrorCode> {
         self.code
     }
 
@@ -2031,6 +2920,12 @@ impl ErrorContext {
     pub fn details(&self) -> Option<&str> {
         self.details.as_deref()
     }
+
+    /// Get the ID of the request which failed, if any
+    #[must_use]
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
 }
 
 /// Context used by the not found (`404.html`) template
@@ -2073,3 +2968,47 @@ impl TemplateContext for NotFoundContext {
         ])
     }
 }
+
+/// Context used by the method not allowed (`405.html`) template
+#[derive(Serialize)]
+pub struct MethodNotAllowedContext {
+    method: String,
+    version: String,
+    uri: String,
+}
+
+impl MethodNotAllowedContext {
+    /// Constructs a context for the method not allowed page
+    #[must_use]
+    pub fn new(method: &Method, version: Version, uri: &Uri) -> Self {
+        Self {
+            method: method.to_string(),
+            version: format!("{version:?}"),
+            uri: uri.to_string(),
+        }
+    }
+}
+
+impl TemplateContext for MethodNotAllowedContext {
+    fn sample(
+        _now: DateTime<Utc>,
+        _rng: &mut impl Rng,
+        _locales: &[DataLocale],
+    ) -> BTreeMap<SampleIdentifier, Self>
+    where
+        Self: Sized,
+    {
+        sample_list(vec![
+            Self::new(
+                &Method::DELETE,
+                Version::HTTP_11,
+                &"/login".parse().unwrap(),
+            ),
+            Self::new(
+                &Method::PUT,
+                Version::HTTP_2,
+                &"/account/?action=foo".parse().unwrap(),
+            ),
+        ])
+    }
+}
@@ -4,7 +4,7 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use minijinja::{
     Value,
@@ -18,6 +18,7 @@ pub struct SiteBranding {
     policy_uri: Option<Arc<str>>,
     tos_uri: Option<Arc<str>>,
     imprint: Option<Arc<str>>,
+    globals: Arc<BTreeMap<String, serde_json::Value>>,
 }
 
 impl SiteBranding {
@@ -29,6 +30,7 @@ impl SiteBranding {
             policy_uri: None,
             tos_uri: None,
             imprint: None,
+            globals: Arc::default(),
         }
     }
 
@@ -52,6 +54,19 @@ impl SiteBranding {
         self.imprint = Some(imprint.into());
         self
     }
+
+    /// Set the instance-specific values exposed to templates as `globals`.
+    #[must_use]
+    pub fn with_globals(mut self, globals: BTreeMap<String, serde_json::Value>) -> Self {
+        self.globals = Arc::new(globals);
+        self
+    }
+
+    /// Get the instance-specific values exposed to templates as `globals`.
+    #[must_use]
+    pub fn globals(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.globals
+    }
 }
 
 impl Object for SiteBranding {
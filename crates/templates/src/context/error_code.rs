@@ -0,0 +1,169 @@
+// Copyright 2025 New Vector Ltd.
+//
+// SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+// Please see LICENSE files in the repository root for full details.
+
+use http::StatusCode;
+use serde::Serialize;
+
+/// A stable, machine-readable code identifying the error shown on an error
+/// page.
+///
+/// Those codes are rendered in the page so that integration tests and support
+/// tooling can tell error pages apart without relying on the (translated)
+/// error messages. They must not change once they have been released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// An unexpected error happened while processing the request
+    InternalError,
+
+    /// The request was invalid
+    BadRequest,
+
+    /// The requested resource was not found
+    NotFound,
+
+    /// The route exists, but doesn't accept the request method
+    MethodNotAllowed,
+
+    /// The request was not allowed
+    Forbidden,
+
+    /// The request conflicts with the current state of the resource
+    Conflict,
+
+    /// The CSRF token was missing, invalid or expired
+    CsrfMismatch,
+
+    /// The request needs a browser session, but none was found
+    SessionRequired,
+
+    /// The user reached the limit of active sessions
+    SessionLimitReached,
+
+    /// The authorization grant was not found
+    AuthorizationGrantNotFound,
+
+    /// The authorization grant was already used
+    AuthorizationGrantNotPending,
+
+    /// The device code grant was not found
+    DeviceGrantNotFound,
+
+    /// The device code grant expired
+    DeviceGrantExpired,
+
+    /// The compatibility SSO login expired
+    CompatSsoLoginExpired,
+
+    /// The email change was not found
+    EmailChangeNotFound,
+
+    /// The session revocation link is malformed or was not issued by us
+    SessionRevocationLinkInvalid,
+
+    /// The session revocation link expired
+    SessionRevocationLinkExpired,
+
+    /// The session revocation link was already used, or the session already
+    /// ended
+    SessionRevocationLinkUsed,
+
+    /// Account recovery is not allowed for this account
+    AccountRecoveryDenied,
+
+    /// The upstream OAuth 2.0 provider was not found
+    UpstreamProviderNotFound,
+
+    /// The upstream OAuth 2.0 link was not found
+    UpstreamLinkNotFound,
+
+    /// The upstream OAuth 2.0 session was already used
+    UpstreamSessionConsumed,
+
+    /// The upstream account maps to a username which is already taken by
+    /// another account
+    UpstreamUserExists,
+
+    /// The localpart suggested by the upstream provider is not available
+    LocalpartNotAvailable,
+
+    /// The email address is associated with another homeserver
+    WrongServer,
+
+    /// The homeserver requires an invitation which was not found
+    InvitationMissing,
+
+    /// The upstream account data doesn't match the existing local account
+    InvalidUpstreamData,
+
+    /// The policy rejected the request, optionally with the code of the first
+    /// violation
+    PolicyViolation(Option<&'static str>),
+}
+
+impl ErrorCode {
+    /// Get a generic error code for the given HTTP status code
+    #[must_use]
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::NOT_FOUND => Self::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => Self::MethodNotAllowed,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Forbidden,
+            StatusCode::CONFLICT => Self::Conflict,
+            status if status.is_server_error() => Self::InternalError,
+            _ => Self::BadRequest,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::InternalError => "internal-error",
+            Self::BadRequest => "bad-request",
+            Self::NotFound => "not-found",
+            Self::MethodNotAllowed => "method-not-allowed",
+            Self::Forbidden => "forbidden",
+            Self::Conflict => "conflict",
+            Self::CsrfMismatch => "csrf-mismatch",
+            Self::SessionRequired => "session-required",
+            Self::SessionLimitReached => "session-limit-reached",
+            Self::AuthorizationGrantNotFound => "authorization-grant-not-found",
+            Self::AuthorizationGrantNotPending => "authorization-grant-not-pending",
+            Self::DeviceGrantNotFound => "device-grant-not-found",
+            Self::DeviceGrantExpired => "device-grant-expired",
+            Self::CompatSsoLoginExpired => "compat-sso-login-expired",
+            Self::EmailChangeNotFound => "email-change-not-found",
+            Self::SessionRevocationLinkInvalid => "session-revocation-link-invalid",
+            Self::SessionRevocationLinkExpired => "session-revocation-link-expired",
+            Self::SessionRevocationLinkUsed => "session-revocation-link-used",
+            Self::AccountRecoveryDenied => "account-recovery-denied",
+            Self::UpstreamProviderNotFound => "upstream-provider-not-found",
+            Self::UpstreamLinkNotFound => "upstream-link-not-found",
+            Self::UpstreamSessionConsumed => "upstream-session-consumed",
+            Self::UpstreamUserExists => "upstream-user-exists",
+            Self::LocalpartNotAvailable => "localpart-not-available",
+            Self::WrongServer => "wrong-server",
+            Self::InvitationMissing => "invitation-missing",
+            Self::InvalidUpstreamData => "invalid-upstream-data",
+            Self::PolicyViolation(_) => "policy-violation",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PolicyViolation(Some(code)) => write!(f, "policy-violation:{code}"),
+            _ => f.write_str(self.as_str()),
+        }
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
@@ -4,23 +4,31 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
-use mas_data_model::SiteConfig;
+use mas_data_model::{SiteConfig, TchapFeatures, UpstreamOAuthProvider};
 
-use super::{SiteBranding, SiteFeatures};
+use super::{FrontendConfig, FrontendUpstreamProvider, SiteBranding, SiteFeatures};
 
 mod private {
     pub trait Sealed {}
     impl Sealed for mas_data_model::SiteConfig {}
 }
 
-/// Extension trait for [`SiteConfig`] to construct [`SiteBranding`] and
-/// [`SiteFeatures`] from it.
+/// Extension trait for [`SiteConfig`] to construct [`SiteBranding`],
+/// [`SiteFeatures`] and [`FrontendConfig`] from it.
 pub trait SiteConfigExt: private::Sealed {
     /// Construct a [`SiteBranding`] from the [`SiteConfig`].
     fn templates_branding(&self) -> SiteBranding;
 
     /// Construct a [`SiteFeatures`] from the [`SiteConfig`].
     fn templates_features(&self) -> SiteFeatures;
+
+    /// Construct a [`FrontendConfig`] from the [`SiteConfig`], the list of
+    /// enabled upstream providers and the enabled Tchap behaviours.
+    fn frontend_config(
+        &self,
+        upstream_providers: &[UpstreamOAuthProvider],
+        tchap_features: &TchapFeatures,
+    ) -> FrontendConfig;
 }
 
 impl SiteConfigExt for SiteConfig {
@@ -39,6 +47,12 @@ impl SiteConfigExt for SiteConfig {
             branding = branding.with_imprint(imprint.as_str());
         }
 
+        //:tchap:
+        if !self.template_globals.is_empty() {
+            branding = branding.with_globals(self.template_globals.clone());
+        }
+        //:tchap:end
+
         branding
     }
 
@@ -51,4 +65,25 @@ impl SiteConfigExt for SiteConfig {
             login_with_email_allowed: self.login_with_email_allowed,
         }
     }
+
+    fn frontend_config(
+        &self,
+        upstream_providers: &[UpstreamOAuthProvider],
+        tchap_features: &TchapFeatures,
+    ) -> FrontendConfig {
+        FrontendConfig {
+            password_login_enabled: self.password_login_enabled,
+            password_registration_enabled: self.password_registration_enabled,
+            password_change_allowed: self.password_change_allowed,
+            email_change_allowed: self.email_change_allowed,
+            account_recovery_allowed: self.account_recovery_allowed,
+            upstream_providers: upstream_providers
+                .iter()
+                .map(FrontendUpstreamProvider::from)
+                .collect(),
+            //:tchap:
+            tchap: tchap_features.any_enabled(),
+            //:tchap:end
+        }
+    }
 }
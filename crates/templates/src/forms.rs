@@ -71,6 +71,10 @@ pub enum FormError {
 
     /// Failed to validate CAPTCHA
     Captcha,
+
+    /// The account is locked until its scheduled deactivation, which the user
+    /// has to confirm they want to cancel
+    DeactivationScheduled,
 }
 
 #[derive(Debug, Default, Serialize)]
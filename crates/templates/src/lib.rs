@@ -37,19 +37,25 @@ mod macros;
 
 pub use self::{
     context::{
-        AccountInactiveContext, ApiDocContext, AppContext, CompatSsoContext, ConsentContext,
-        DeviceConsentContext, DeviceLinkContext, DeviceLinkFormField, DeviceNameContext,
-        EmailRecoveryContext, EmailVerificationContext, EmptyContext, ErrorContext,
-        FormPostContext, IndexContext, LoginContext, LoginFormField, NotFoundContext,
-        PasswordRegisterContext, PolicyViolationContext, PostAuthContext, PostAuthContextInner,
-        RecoveryExpiredContext, RecoveryFinishContext, RecoveryFinishFormField,
-        RecoveryProgressContext, RecoveryStartContext, RecoveryStartFormField, RegisterContext,
-        RegisterFormField, RegisterStepsDisplayNameContext, RegisterStepsDisplayNameFormField,
+        AccountInactiveContext, AccountRecoveredContext, ApiDocContext, AppContext,
+        CompatSsoContext, ConsentContext, DeactivationScheduledContext, DeviceConsentContext,
+        DeviceConsentFormField, DeviceLinkContext, DeviceLinkFormField, DeviceNameContext,
+        EmailAddedContext, EmailChangeConfirmationContext, EmailChangeContext, EmailChangeState,
+        EmailRecoveryContext, EmailVerificationContext, EmptyContext, EndedSessionsSummary,
+        ErrorCode, ErrorContext, FormPostContext, FrontendConfig, FrontendUpstreamProvider,
+        IndexContext, LoginContext, LoginFormField, MethodNotAllowedContext, NewLoginContext,
+        NotFoundContext, PasswordRegisterContext, PolicyViolationContext, PostAuthContext,
+        PostAuthContextInner, ProfileBadge, ReauthContext, ReauthFormField, RecoveryExpiredContext,
+        RecoveryFinishContext, RecoveryFinishFormField, RecoveryProgressContext,
+        RecoveryStartContext, RecoveryStartFormField, RecoveryUpstreamContext,
+        RecoveryUpstreamUnlinkedContext, RegisterContext, RegisterFormField,
+        RegisterStepsDisplayNameContext, RegisterStepsDisplayNameFormField,
         RegisterStepsEmailInUseContext, RegisterStepsRegistrationTokenContext,
         RegisterStepsRegistrationTokenFormField, RegisterStepsVerifyEmailContext,
-        RegisterStepsVerifyEmailFormField, SiteBranding, SiteConfigExt, SiteFeatures,
-        TemplateContext, UpstreamExistingLinkContext, UpstreamRegister, UpstreamRegisterFormField,
-        UpstreamSuggestLink, WithCaptcha, WithCsrf, WithLanguage, WithOptionalSession, WithSession,
+        RegisterStepsVerifyEmailFormField, RevokeSessionContext, SiteBranding, SiteConfigExt,
+        SiteFeatures, TemplateContext, UpstreamErrorContext, UpstreamExistingLinkContext,
+        UpstreamRegister, UpstreamRegisterFormField, UpstreamSuggestLink, WithCaptcha, WithCsrf,
+        WithLanguage, WithOptionalSession, WithSession,
     },
     forms::{FieldError, FormError, FormField, FormState, ToFormState},
 };
@@ -254,6 +260,10 @@ impl Templates {
         })
         .await??;
 
+        //:tchap:
+        // Missing keys are caught by strict mode, like any other undefined value
+        env.add_global("globals", Value::from_serialize(branding.globals()));
+        //:tchap:end
         env.add_global("branding", Value::from_object(branding));
         env.add_global("features", Value::from_object(features));
 
@@ -311,6 +321,24 @@ impl Templates {
     pub fn translator(&self) -> Arc<Translator> {
         self.translator.load_full()
     }
+
+    /// Get the path to the templates directory
+    #[must_use]
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// Get the path to the translations directory
+    #[must_use]
+    pub fn translations_path(&self) -> &Utf8Path {
+        &self.translations_path
+    }
+
+    /// Get the path to the assets manifest
+    #[must_use]
+    pub fn vite_manifest_path(&self) -> &Utf8Path {
+        &self.vite_manifest_path
+    }
 }
 
 /// Failed to render a template
@@ -337,12 +365,22 @@ pub enum TemplateError {
         #[source]
         source: minijinja::Error,
     },
+
+    /// The template is not one of the registered templates
+    #[error("unknown template {template:?}")]
+    Unknown {
+        /// The name of the template which was requested
+        template: String,
+    },
 }
 
 register_templates! {
     /// Render the not found fallback page
     pub fn render_not_found(WithLanguage<NotFoundContext>) { "pages/404.html" }
 
+    /// Render the method not allowed fallback page
+    pub fn render_method_not_allowed(WithLanguage<MethodNotAllowedContext>) { "pages/405.html" }
+
     /// Render the frontend app
     pub fn render_app(WithLanguage<AppContext>) { "app.html" }
 
@@ -355,6 +393,9 @@ register_templates! {
     /// Render the login page
     pub fn render_login(WithLanguage<WithCsrf<LoginContext>>) { "pages/login.html" }
 
+    /// Render the re-authentication page
+    pub fn render_reauth(WithLanguage<WithCsrf<ReauthContext>>) { "pages/reauth.html" }
+
     /// Render the registration page
     pub fn render_register(WithLanguage<WithCsrf<RegisterContext>>) { "pages/register/index.html" }
 
@@ -394,6 +435,14 @@ register_templates! {
     /// Render the account recovery finish page
     pub fn render_recovery_finish(WithLanguage<WithCsrf<RecoveryFinishContext>>) { "pages/recovery/finish.html" }
 
+    /// Render the page to set a new password after recovering an account
+    /// through an upstream provider
+    pub fn render_recovery_upstream(WithLanguage<WithCsrf<RecoveryUpstreamContext>>) { "pages/recovery/upstream.html" }
+
+    /// Render the page shown when recovering through an upstream provider with
+    /// an identity which isn't linked to any account
+    pub fn render_recovery_upstream_unlinked(WithLanguage<RecoveryUpstreamUnlinkedContext>) { "pages/recovery/upstream_unlinked.html" }
+
     /// Render the account recovery link expired page
     pub fn render_recovery_expired(WithLanguage<WithCsrf<RecoveryExpiredContext>>) { "pages/recovery/expired.html" }
 
@@ -403,6 +452,13 @@ register_templates! {
     /// Render the account recovery disabled page
     pub fn render_recovery_disabled(WithLanguage<EmptyContext>) { "pages/recovery/disabled.html" }
 
+    /// Render the page to confirm or cancel a change of email address
+    pub fn render_email_change(WithLanguage<WithCsrf<EmailChangeContext>>) { "pages/email_change.html" }
+
+    /// Render the page to confirm signing out a session from the link in the
+    /// new login notification
+    pub fn render_revoke_session(WithLanguage<WithCsrf<RevokeSessionContext>>) { "pages/revoke_session.html" }
+
     /// Render the form used by the `form_post` response mode
     pub fn render_form_post<#[sample(EmptyContext)] T: Serialize>(WithLanguage<FormPostContext<T>>) { "form_post.html" }
 
@@ -418,6 +474,51 @@ register_templates! {
     /// Render the email recovery subject
     pub fn render_email_recovery_subject(WithLanguage<EmailRecoveryContext>) { "emails/recovery.subject" }
 
+    /// Render the account recovered notification email (plain text variant)
+    pub fn render_email_account_recovered_txt(WithLanguage<AccountRecoveredContext>) { "emails/account_recovered.txt" }
+
+    /// Render the account recovered notification email (HTML text variant)
+    pub fn render_email_account_recovered_html(WithLanguage<AccountRecoveredContext>) { "emails/account_recovered.html" }
+
+    /// Render the account recovered notification subject
+    pub fn render_email_account_recovered_subject(WithLanguage<AccountRecoveredContext>) { "emails/account_recovered.subject" }
+
+    /// Render the scheduled deactivation notification email (plain text variant)
+    pub fn render_email_deactivation_scheduled_txt(WithLanguage<DeactivationScheduledContext>) { "emails/deactivation_scheduled.txt" }
+
+    /// Render the scheduled deactivation notification email (HTML text variant)
+    pub fn render_email_deactivation_scheduled_html(WithLanguage<DeactivationScheduledContext>) { "emails/deactivation_scheduled.html" }
+
+    /// Render the scheduled deactivation notification subject
+    pub fn render_email_deactivation_scheduled_subject(WithLanguage<DeactivationScheduledContext>) { "emails/deactivation_scheduled.subject" }
+
+    /// Render the email added notification email (plain text variant)
+    pub fn render_email_added_txt(WithLanguage<EmailAddedContext>) { "emails/email_added.txt" }
+
+    /// Render the email added notification email (HTML text variant)
+    pub fn render_email_added_html(WithLanguage<EmailAddedContext>) { "emails/email_added.html" }
+
+    /// Render the email added notification subject
+    pub fn render_email_added_subject(WithLanguage<EmailAddedContext>) { "emails/email_added.subject" }
+
+    /// Render the new login notification email (plain text variant)
+    pub fn render_email_new_login_txt(WithLanguage<NewLoginContext>) { "emails/new_login.txt" }
+
+    /// Render the new login notification email (HTML text variant)
+    pub fn render_email_new_login_html(WithLanguage<NewLoginContext>) { "emails/new_login.html" }
+
+    /// Render the new login notification email subject
+    pub fn render_email_new_login_subject(WithLanguage<NewLoginContext>) { "emails/new_login.subject" }
+
+    /// Render the email change confirmation email (plain text variant)
+    pub fn render_email_change_txt(WithLanguage<EmailChangeConfirmationContext>) { "emails/email_change.txt" }
+
+    /// Render the email change confirmation email (HTML text variant)
+    pub fn render_email_change_html(WithLanguage<EmailChangeConfirmationContext>) { "emails/email_change.html" }
+
+    /// Render the email change confirmation subject
+    pub fn render_email_change_subject(WithLanguage<EmailChangeConfirmationContext>) { "emails/email_change.subject" }
+
     /// Render the email verification email (plain text variant)
     pub fn render_email_verification_txt(WithLanguage<EmailVerificationContext>) { "emails/verification.txt" }
 
@@ -439,6 +540,9 @@ register_templates! {
     /// Render the upstream register screen
     pub fn render_upstream_oauth2_do_register(WithLanguage<WithCsrf<UpstreamRegister>>) { "pages/upstream_oauth2/do_register.html" }
 
+    /// Render the page shown when the upstream provider returned an error
+    pub fn render_upstream_oauth2_error(WithLanguage<UpstreamErrorContext>) { "pages/upstream_oauth2/error.html" }
+
     /// Render the device code link page
     pub fn render_device_link(WithLanguage<DeviceLinkContext>) { "pages/device_link.html" }
 
@@ -478,22 +582,55 @@ impl Templates {
     ) -> anyhow::Result<BTreeMap<(&'static str, SampleIdentifier), String>> {
         check::all(self, now, rng)
     }
+
+    /// Returns the names of all the registered templates
+    #[must_use]
+    pub fn template_names() -> &'static [&'static str] {
+        &TEMPLATES
+    }
+
+    /// Render a registered template by name, with an arbitrary context.
+    ///
+    /// Unlike the `render_*` methods, nothing checks that the context has the
+    /// shape the template expects. This is meant to preview templates with a
+    /// handcrafted context.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the template is not registered, or if it fails to
+    /// render
+    pub fn render_dynamic(
+        &self,
+        template: &str,
+        context: &serde_json::Value,
+    ) -> Result<String, TemplateError> {
+        let Some(template) = TEMPLATES.iter().copied().find(|name| *name == template) else {
+            return Err(TemplateError::Unknown {
+                template: template.to_owned(),
+            });
+        };
+
+        let ctx = Value::from_serialize(context);
+        let env = self.environment.load();
+        let tmpl = env
+            .get_template(template)
+            .map_err(|source| TemplateError::Missing { template, source })?;
+        tmpl.render(ctx)
+            .map_err(|source| TemplateError::Render { template, source })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[tokio::test]
-    async fn check_builtin_templates() {
-        #[allow(clippy::disallowed_methods)]
-        let now = chrono::Utc::now();
-        #[allow(clippy::disallowed_methods)]
-        let mut rng = rand::thread_rng();
+    async fn load_templates() -> Templates {
+        load_templates_with_branding(SiteBranding::new("example.com")).await
+    }
 
+    async fn load_templates_with_branding(branding: SiteBranding) -> Templates {
         let path = Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("../../templates/");
         let url_builder = UrlBuilder::new("https://example.com/".parse().unwrap(), None, None);
-        let branding = SiteBranding::new("example.com");
         let features = SiteFeatures {
             password_login: true,
             password_registration: true,
@@ -505,7 +642,7 @@ mod tests {
             Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("../../frontend/dist/manifest.json");
         let translations_path =
             Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("../../translations");
-        let templates = Templates::load(
+        Templates::load(
             path,
             url_builder,
             vite_manifest_path,
@@ -516,7 +653,195 @@ mod tests {
             true,
         )
         .await
-        .unwrap();
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn check_builtin_templates() {
+        #[allow(clippy::disallowed_methods)]
+        let now = chrono::Utc::now();
+        #[allow(clippy::disallowed_methods)]
+        let mut rng = rand::thread_rng();
+
+        let templates = load_templates().await;
         templates.check_render(now, &mut rng).unwrap();
     }
+
+    #[tokio::test]
+    async fn render_dynamic_login() {
+        #[allow(clippy::disallowed_methods)]
+        let now = chrono::Utc::now();
+        #[allow(clippy::disallowed_methods)]
+        let mut rng = rand::thread_rng();
+
+        let templates = load_templates().await;
+        let locales = templates.translator().available_locales();
+        let samples: BTreeMap<SampleIdentifier, WithLanguage<WithCsrf<LoginContext>>> =
+            TemplateContext::sample(now, &mut rng, &locales);
+        let (_, sample) = samples.into_iter().next().unwrap();
+        let context = serde_json::to_value(&sample).unwrap();
+
+        let html = templates
+            .render_dynamic("pages/login.html", &context)
+            .unwrap();
+        assert!(!html.is_empty());
+        assert!(html.contains("fake_csrf_token"));
+
+        let error = templates
+            .render_dynamic("pages/unknown.html", &context)
+            .unwrap_err();
+        assert!(matches!(error, TemplateError::Unknown { .. }), "{error:?}");
+        assert!(Templates::template_names().contains(&"pages/login.html"));
+    }
+
+    #[tokio::test]
+    async fn render_globals() {
+        let branding = SiteBranding::new("example.com").with_globals(BTreeMap::from([
+            (
+                "support_email".to_owned(),
+                serde_json::json!("support@example.com"),
+            ),
+            (
+                "ministries".to_owned(),
+                serde_json::json!(["DINUM", "MININT"]),
+            ),
+        ]));
+        let templates = load_templates_with_branding(branding).await;
+        let env = templates.environment.load();
+
+        let rendered = env
+            .render_str(
+                "{{ globals.support_email }} / {{ globals.ministries | join(', ') }}",
+                (),
+            )
+            .unwrap();
+        assert_eq!(rendered, "support@example.com / DINUM, MININT");
+
+        // The built-in templates still render with the globals set
+        #[allow(clippy::disallowed_methods)]
+        let now = chrono::Utc::now();
+        #[allow(clippy::disallowed_methods)]
+        let mut rng = rand::thread_rng();
+        templates.check_render(now, &mut rng).unwrap();
+
+        // A key missing from the configuration is an error in strict mode
+        let error = env
+            .render_str("{{ globals.contact_email }}", ())
+            .unwrap_err();
+        assert_eq!(error.kind(), minijinja::ErrorKind::UndefinedError);
+    }
+
+    #[tokio::test]
+    async fn render_error_codes() {
+        let templates = load_templates().await;
+        let locale: mas_i18n::DataLocale = mas_i18n::locale!("en").into();
+
+        // An internal error keeps a generic code, plus the request ID
+        let ctx = ErrorContext::new()
+            .with_code(ErrorCode::InternalError)
+            .with_description("Something went wrong".to_owned())
+            .with_request_id("http-request-42".to_owned())
+            .with_language(&locale);
+        let html = templates.render_error(&ctx).unwrap();
+        assert!(html.contains(r#"data-error-code="internal-error""#));
+        assert!(html.contains("<title>internal-error · "));
+        assert!(html.contains("http-request-42"));
+
+        // A specific error
+        let ctx = ErrorContext::new()
+            .with_code(ErrorCode::DeviceGrantExpired)
+            .with_language(&locale);
+        let html = templates.render_error(&ctx).unwrap();
+        assert!(html.contains(r#"data-error-code="device-grant-expired""#));
+        assert!(html.contains("<title>device-grant-expired · "));
+
+        // A policy violation, with the code of the violation
+        let ctx = ErrorContext::new()
+            .with_code(ErrorCode::PolicyViolation(Some("username-banned")))
+            .with_language(&locale);
+        let html = templates.render_error(&ctx).unwrap();
+        assert!(html.contains(r#"data-error-code="policy-violation:username-banned""#));
+        assert!(html.contains("<title>policy-violation:username-banned · "));
+    }
+
+    #[tokio::test]
+    async fn render_app_action() {
+        let templates = load_templates().await;
+        let locale: mas_i18n::DataLocale = mas_i18n::locale!("en").into();
+        let url_builder = UrlBuilder::new("https://example.com/".parse().unwrap(), None, None);
+        let frontend_config = FrontendConfig {
+            password_login_enabled: true,
+            password_registration_enabled: true,
+            password_change_allowed: true,
+            email_change_allowed: true,
+            account_recovery_allowed: true,
+            upstream_providers: Vec::new(),
+            tchap: true,
+        };
+
+        let ctx = AppContext::from_url_builder(&url_builder, frontend_config.clone())
+            .with_language(locale.clone());
+        let html = templates.render_app(&ctx).unwrap();
+        assert!(html.contains(r#"\"action\":null"#));
+
+        let cases = [
+            (
+                mas_router::AccountAction::SessionEnd {
+                    device_id: "ABCDEF".to_owned(),
+                },
+                r#"{\"action\":\"session_end\",\"device_id\":\"ABCDEF\"}"#,
+            ),
+            (
+                mas_router::AccountAction::CrossSigningReset,
+                r#"{\"action\":\"cross_signing_reset\"}"#,
+            ),
+            (
+                mas_router::AccountAction::OrgMatrixProfile,
+                r#"{\"action\":\"org.matrix.profile\"}"#,
+            ),
+        ];
+
+        for (action, expected) in cases {
+            let ctx = AppContext::from_url_builder(&url_builder, frontend_config.clone())
+                .with_action(Some(action))
+                .with_language(locale.clone());
+            let html = templates.render_app(&ctx).unwrap();
+            assert!(html.contains(expected), "{expected} not found in {html}");
+        }
+    }
+
+    //:tchap:
+    #[tokio::test]
+    async fn render_app_profile_badge() {
+        let templates = load_templates().await;
+        let locale: mas_i18n::DataLocale = mas_i18n::locale!("en").into();
+        let url_builder = UrlBuilder::new("https://example.com/".parse().unwrap(), None, None);
+        let frontend_config = FrontendConfig {
+            password_login_enabled: true,
+            password_registration_enabled: true,
+            password_change_allowed: true,
+            email_change_allowed: true,
+            account_recovery_allowed: true,
+            upstream_providers: Vec::new(),
+            tchap: true,
+        };
+
+        // Without a badge
+        let ctx = AppContext::from_url_builder(&url_builder, frontend_config.clone())
+            .with_language(locale.clone());
+        let html = templates.render_app(&ctx).unwrap();
+        assert!(html.contains(r#"\"profileBadge\":null"#));
+
+        // With a badge
+        let ctx = AppContext::from_url_builder(&url_builder, frontend_config)
+            .with_profile_badge(ProfileBadge::new(
+                Some("DINUM".to_owned()),
+                Some("Administration".to_owned()),
+            ))
+            .with_language(locale);
+        let html = templates.render_app(&ctx).unwrap();
+        assert!(html.contains(r#"\"organization\":\"DINUM\""#));
+        assert!(html.contains(r#"\"emailDomainLabel\":\"Administration\""#));
+    }
+    //:tchap:end
 }
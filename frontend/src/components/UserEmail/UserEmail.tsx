@@ -170,6 +170,14 @@ const UserEmail: React.FC<{
                   </ErrorMessage>
                 )}
 
+                {status === "LAST_EMAIL" && (
+                  <ErrorMessage>
+                    {t(
+                      "frontend.user_email.delete_button_confirmation_modal.last_email",
+                    )}
+                  </ErrorMessage>
+                )}
+
                 <div className="flex flex-col gap-4">
                   <Button
                     kind="primary"
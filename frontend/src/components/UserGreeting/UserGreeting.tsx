@@ -9,6 +9,7 @@ import IconClose from "@vector-im/compound-design-tokens/assets/web/icons/close"
 import IconEdit from "@vector-im/compound-design-tokens/assets/web/icons/edit";
 import {
   Avatar,
+  Badge,
   Button,
   Form,
   IconButton,
@@ -22,6 +23,7 @@ import {
   useState,
 } from "react";
 import { useTranslation } from "react-i18next";
+import config from "../../config";
 import { type FragmentType, graphql, useFragment } from "../../gql";
 import { graphqlRequest } from "../../graphql";
 import * as Dialog from "../Dialog";
@@ -104,6 +106,13 @@ const UserGreeting: React.FC<Props> = ({ user, siteConfig }) => {
   const [open, setOpen] = useState(false);
   const { t } = useTranslation();
 
+  const profileBadge = [
+    config.profileBadge?.organization,
+    config.profileBadge?.emailDomainLabel,
+  ]
+    .filter(Boolean)
+    .join(" · ");
+
   const onSubmit = (event: React.FormEvent<HTMLFormElement>): void => {
     event.preventDefault();
 
@@ -136,6 +145,11 @@ const UserGreeting: React.FC<Props> = ({ user, siteConfig }) => {
             {data.matrix.mxid}
           </Text>
         )}
+        {profileBadge && (
+          <Badge kind="blue" className="self-start">
+            {profileBadge}
+          </Badge>
+        )}
       </div>
 
       {displayNameChangeAllowed && (
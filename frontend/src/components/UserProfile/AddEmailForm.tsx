@@ -12,6 +12,7 @@ import {
 } from "@vector-im/compound-web";
 import { useCallback } from "react";
 import { useTranslation } from "react-i18next";
+import config from "../../config";
 import { type FragmentType, graphql, useFragment } from "../../gql";
 import { graphqlRequest } from "../../graphql";
 import PasswordConfirmationModal, {
@@ -74,6 +75,14 @@ const AddEmailForm: React.FC<{
     onSuccess: async (data) => {
       queryClient.invalidateQueries({ queryKey: ["userEmails"] });
 
+      if (
+        data.startEmailAuthentication.status === "REAUTHENTICATION_REQUIRED"
+      ) {
+        // Ask the user to sign in again, then come back to the account page
+        window.location.assign(`${config.reauthEndpoint}?kind=manage_account`);
+        return;
+      }
+
       // Don't clear the form if the email was invalid or already exists
       if (data.startEmailAuthentication.status !== "STARTED") {
         return;
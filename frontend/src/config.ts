@@ -4,9 +4,42 @@
 // SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
 // Please see LICENSE files in the repository root for full details.
 
+type UpstreamProvider = {
+  id: string;
+  humanName: string | null;
+  brandName: string | null;
+};
+
+// Subset of the site configuration, as exposed by the server
+type FrontendConfig = {
+  passwordLoginEnabled: boolean;
+  passwordRegistrationEnabled: boolean;
+  passwordChangeAllowed: boolean;
+  emailChangeAllowed: boolean;
+  accountRecoveryAllowed: boolean;
+  upstreamProviders: UpstreamProvider[];
+  tchap: boolean;
+};
+
+// Badge shown in the profile area, only set for the logged-in user
+type ProfileBadge = {
+  organization: string | null;
+  emailDomainLabel: string | null;
+};
+
+// Account management action requested through the URL, as parsed by the server
+type AccountAction = {
+  action: string;
+  device_id?: string;
+};
+
 type AppConfig = {
   root: string;
   graphqlEndpoint: string;
+  reauthEndpoint: string;
+  frontendConfig?: FrontendConfig;
+  action?: AccountAction | null;
+  profileBadge?: ProfileBadge | null;
 };
 
 interface IWindow {
@@ -17,6 +50,7 @@ const config: AppConfig = (typeof window !== "undefined" &&
   (window as IWindow).APP_CONFIG) || {
   root: "/",
   graphqlEndpoint: "/graphql",
+  reauthEndpoint: "/reauth",
 };
 
 export default config;
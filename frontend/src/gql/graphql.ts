@@ -137,6 +137,38 @@ export type AppSessionEdge = {
   node: AppSession;
 };
 
+/** Something which happened to an application session of a user */
+export type AppSessionEvent = {
+  __typename?: 'AppSessionEvent';
+  /** When it happened. */
+  at: Scalars['DateTime']['output'];
+  /** What happened to the session. */
+  kind: AppSessionEventKind;
+  /** The session, in its current state. */
+  session: AppSession;
+};
+
+/** What happened to an application session */
+export type AppSessionEventKind =
+  /** The session was created */
+  | 'CREATED'
+  /** The session was ended */
+  | 'ENDED';
+
+/** The application sessions of a user which changed since a cursor */
+export type AppSessionEvents = {
+  __typename?: 'AppSessionEvents';
+  /** The cursor to pass in the next call to only get newer events. */
+  cursor: Scalars['String']['output'];
+  /** The events, in chronological order. */
+  events: Array<AppSessionEvent>;
+  /**
+   * Whether there were too many changes to return all of them. In that
+   * case, the full list of sessions should be fetched again.
+   */
+  truncated: Scalars['Boolean']['output'];
+};
+
 /**
  * An authentication records when a user enter their credential in a browser
  * session.
@@ -334,6 +366,11 @@ export type CompleteEmailAuthenticationInput = {
   code: Scalars['String']['input'];
   /** The ID of the authentication session to complete */
   id: Scalars['ID']['input'];
+  /**
+   * The language to use for the email sent to the previous address, if the
+   * change has to be confirmed from there
+   */
+  language?: Scalars['String']['input'];
 };
 
 /** The payload of the `completeEmailAuthentication` mutation */
@@ -353,6 +390,11 @@ export type CompleteEmailAuthenticationStatus =
   | 'INVALID_CODE'
   /** The email address is already in use */
   | 'IN_USE'
+  /**
+   * The authentication was completed, but the change has to be confirmed
+   * from the previous email address of the user before the new one is added
+   */
+  | 'PENDING_CONFIRMATION'
   /** Too many attempts to complete an email authentication */
   | 'RATE_LIMITED';
 
@@ -423,6 +465,65 @@ export type DeactivateUserStatus =
   /** The password was wrong. */
   | 'INCORRECT_PASSWORD';
 
+/**
+ * An OAuth 2.0 device code grant, started by a device and approved or
+ * rejected by a user from their browser.
+ */
+export type DeviceCodeGrant = Node & {
+  __typename?: 'DeviceCodeGrant';
+  /** OAuth 2.0 client which started this grant. */
+  client: Oauth2Client;
+  /** When the object was created. */
+  createdAt: Scalars['DateTime']['output'];
+  /** Whether the grant has expired. */
+  expired: Scalars['Boolean']['output'];
+  /** When the grant expires. */
+  expiresAt: Scalars['DateTime']['output'];
+  /** ID of the object. */
+  id: Scalars['ID']['output'];
+  /** Scope requested by the client. */
+  scope: Scalars['String']['output'];
+  /** The state of the grant. */
+  state: DeviceCodeGrantState;
+  /** The user-agent of the device which started the grant. */
+  userAgent?: Maybe<UserAgent>;
+};
+
+export type DeviceCodeGrantConnection = {
+  __typename?: 'DeviceCodeGrantConnection';
+  /** A list of edges. */
+  edges: Array<DeviceCodeGrantEdge>;
+  /** A list of nodes. */
+  nodes: Array<DeviceCodeGrant>;
+  /** Information to aid in pagination. */
+  pageInfo: PageInfo;
+  /** Identifies the total count of items in the connection. */
+  totalCount: Scalars['Int']['output'];
+};
+
+/** An edge in a connection. */
+export type DeviceCodeGrantEdge = {
+  __typename?: 'DeviceCodeGrantEdge';
+  /** A cursor for use in pagination */
+  cursor: Scalars['String']['output'];
+  /** The item at the end of the edge */
+  node: DeviceCodeGrant;
+};
+
+/** The state of an OAuth 2.0 device code grant. */
+export type DeviceCodeGrantState =
+  /** The grant was exchanged by the device for a session. */
+  | 'EXCHANGED'
+  /**
+   * The grant was approved by a user, and is waiting for the device to
+   * exchange it.
+   */
+  | 'FULFILLED'
+  /** The grant is waiting for a user to approve or reject it. */
+  | 'PENDING'
+  /** The grant was rejected by a user. */
+  | 'REJECTED';
+
 /** The type of a user agent */
 export type DeviceType =
   /** A mobile phone. Can also sometimes be a tablet. */
@@ -521,6 +622,26 @@ export type LockUserStatus =
   /** The user was not found. */
   | 'NOT_FOUND';
 
+/** Where a failed login attempt came from */
+export type LoginFailureOrigin =
+  /** The attempt was made through the legacy Matrix login API */
+  | 'COMPAT'
+  /** The attempt was made through the interactive login form */
+  | 'INTERACTIVE';
+
+/** Why a login attempt failed */
+export type LoginFailureReason =
+  /** The user has no password set */
+  | 'NO_PASSWORD'
+  /** The password did not match */
+  | 'PASSWORD_MISMATCH'
+  /** The attempt was rejected by the rate limiter */
+  | 'RATE_LIMITED'
+  /** The user is deactivated */
+  | 'USER_DEACTIVATED'
+  /** The user is locked */
+  | 'USER_LOCKED';
+
 export type MatrixUser = {
   __typename?: 'MatrixUser';
   /** The avatar URL of the user, if any. */
@@ -577,6 +698,19 @@ export type Mutation = {
    * calls this mutation.
    */
   resendRecoveryEmail: ResendRecoveryEmailPayload;
+  /**
+   * Forget the consent a user gave to an OAuth 2.0 client, so that they
+   * are asked for consent again on the next authorization.
+   */
+  revokeOauth2Consent: RevokeOAuth2ConsentPayload;
+  /**
+   * Schedule the deactivation of the current user account
+   *
+   * The account is locked right away, and deactivated once the grace
+   * period configured on the server is over. The user can cancel the
+   * deactivation by signing in again during this period.
+   */
+  scheduleDeactivation: ScheduleDeactivationPayload;
   /**
    * Set whether a user can request admin. This is only available to
    * administrators.
@@ -597,6 +731,11 @@ export type Mutation = {
   setPassword: SetPasswordPayload;
   /** Set the password for yourself, using a recovery ticket sent by e-mail. */
   setPasswordByRecovery: SetPasswordPayload;
+  /**
+   * Set the language used for the pages and emails sent to the current
+   * user, overriding the one negotiated with the browser.
+   */
+  setPreferredLanguage: SetPreferredLanguagePayload;
   /**
    * Set an email address as primary
    * @deprecated This doesn't do anything anymore, but is kept to avoid breaking existing queries
@@ -604,6 +743,13 @@ export type Mutation = {
   setPrimaryEmail: SetPrimaryEmailPayload;
   /** Start a new email authentication flow */
   startEmailAuthentication: StartEmailAuthenticationPayload;
+  /**
+   * Move an upstream link from the user it belongs to onto another user.
+   *
+   * The sessions which were started through this upstream link are ended.
+   * This is only available to administrators.
+   */
+  transferUpstreamOauthLink: TransferUpstreamOAuthLinkPayload;
   /** Unlock and reactivate a user. This is only available to administrators. */
   unlockUser: UnlockUserPayload;
 };
@@ -687,6 +833,18 @@ export type MutationResendRecoveryEmailArgs = {
 };
 
 
+/** The mutations root of the GraphQL interface. */
+export type MutationRevokeOauth2ConsentArgs = {
+  input: RevokeOAuth2ConsentInput;
+};
+
+
+/** The mutations root of the GraphQL interface. */
+export type MutationScheduleDeactivationArgs = {
+  input: ScheduleDeactivationInput;
+};
+
+
 /** The mutations root of the GraphQL interface. */
 export type MutationSetCanRequestAdminArgs = {
   input: SetCanRequestAdminInput;
@@ -723,6 +881,12 @@ export type MutationSetPasswordByRecoveryArgs = {
 };
 
 
+/** The mutations root of the GraphQL interface. */
+export type MutationSetPreferredLanguageArgs = {
+  input: SetPreferredLanguageInput;
+};
+
+
 /** The mutations root of the GraphQL interface. */
 export type MutationSetPrimaryEmailArgs = {
   input: SetPrimaryEmailInput;
@@ -735,6 +899,12 @@ export type MutationStartEmailAuthenticationArgs = {
 };
 
 
+/** The mutations root of the GraphQL interface. */
+export type MutationTransferUpstreamOauthLinkArgs = {
+  input: TransferUpstreamOAuthLinkInput;
+};
+
+
 /** The mutations root of the GraphQL interface. */
 export type MutationUnlockUserArgs = {
   input: UnlockUserInput;
@@ -776,6 +946,47 @@ export type Oauth2Client = Node & {
   tosUri?: Maybe<Scalars['Url']['output']>;
 };
 
+/**
+ * A remembered consent given by a user to an OAuth 2.0 client. While it
+ * exists, the user won't be asked for consent again for scopes it covers.
+ */
+export type Oauth2Consent = {
+  __typename?: 'Oauth2Consent';
+  /** OAuth 2.0 client the consent was given to. */
+  client: Oauth2Client;
+  /** When the user first consented to this client. */
+  createdAt: Scalars['DateTime']['output'];
+  /** Scope the user consented to. */
+  scope: Scalars['String']['output'];
+  /** When the user last consented to this client. */
+  updatedAt: Scalars['DateTime']['output'];
+};
+
+/** What a scope token granted to an OAuth 2.0 session gives access to. */
+export type Oauth2ScopeKind =
+  /** The `email` scope: the client can see the email address of the user. */
+  | 'EMAIL'
+  /** The client can administer the authentication service. */
+  | 'MAS_ADMIN'
+  /**
+   * The client can edit the profile of the user and manage their
+   * sessions through the GraphQL API.
+   */
+  | 'MAS_GRAPHQL'
+  /**
+   * The client can see and send messages on behalf of the user through the
+   * Matrix Client-Server API.
+   */
+  | 'MATRIX_CLIENT_API'
+  /** The Matrix device bound to the session. */
+  | 'MATRIX_DEVICE'
+  /** The `openid` scope: the client can see the profile of the user. */
+  | 'OPENID'
+  /** Any other scope token. */
+  | 'OTHER'
+  /** The client can administer the homeserver. */
+  | 'SYNAPSE_ADMIN';
+
 /**
  * An OAuth 2.0 session represents a client session which used the OAuth APIs
  * to login.
@@ -786,6 +997,21 @@ export type Oauth2Session = CreationEvent & Node & {
   browserSession?: Maybe<BrowserSession>;
   /** OAuth 2.0 client used by this session. */
   client: Oauth2Client;
+  /**
+   * Logo URI of the OAuth 2.0 client used by this session, as advertised by
+   * the client.
+   */
+  clientLogoUri?: Maybe<Scalars['Url']['output']>;
+  /**
+   * Name of the OAuth 2.0 client used by this session, as advertised by the
+   * client.
+   */
+  clientName?: Maybe<Scalars['String']['output']>;
+  /**
+   * Terms of services URI of the OAuth 2.0 client used by this session, as
+   * advertised by the client.
+   */
+  clientTosUri?: Maybe<Scalars['Url']['output']>;
   /** When the object was created. */
   createdAt: Scalars['DateTime']['output'];
   /** When the session ended. */
@@ -800,6 +1026,11 @@ export type Oauth2Session = CreationEvent & Node & {
   lastActiveIp?: Maybe<Scalars['String']['output']>;
   /** Scope granted for this session. */
   scope: Scalars['String']['output'];
+  /**
+   * Scope granted for this session, split into individual scope tokens
+   * along with what they give access to.
+   */
+  scopes: Array<Oauth2SessionScope>;
   /** The state of the session. */
   state: SessionState;
   /** User authorized for this session. */
@@ -829,6 +1060,17 @@ export type Oauth2SessionEdge = {
   node: Oauth2Session;
 };
 
+/** A single scope token granted to an OAuth 2.0 session. */
+export type Oauth2SessionScope = {
+  __typename?: 'Oauth2SessionScope';
+  /** Whether the scope token gives administrative access. */
+  dangerous: Scalars['Boolean']['output'];
+  /** What the scope token gives access to. */
+  kind: Oauth2ScopeKind;
+  /** The scope token, as granted to the session. */
+  scope: Scalars['String']['output'];
+};
+
 /** Information about pagination in a connection */
 export type PageInfo = {
   __typename?: 'PageInfo';
@@ -867,6 +1109,13 @@ export type Query = {
   oauth2Session?: Maybe<Oauth2Session>;
   /** Lookup a compat or OAuth 2.0 session */
   session?: Maybe<Session>;
+  /**
+   * Search both compat and OAuth 2.0 sessions across all users,
+   * chronologically sorted.
+   *
+   * This is only available to administrators.
+   */
+  sessions: AppSessionConnection;
   /** Get the current site configuration */
   siteConfig: SiteConfig;
   /** Fetch an upstream OAuth 2.0 link by its ID. */
@@ -935,6 +1184,20 @@ export type QuerySessionArgs = {
 };
 
 
+/** The query root of the GraphQL interface. */
+export type QuerySessionsArgs = {
+  after?: InputMaybe<Scalars['String']['input']>;
+  before?: InputMaybe<Scalars['String']['input']>;
+  client?: InputMaybe<Scalars['ID']['input']>;
+  createdAfter?: InputMaybe<Scalars['DateTime']['input']>;
+  first?: InputMaybe<Scalars['Int']['input']>;
+  ip?: InputMaybe<Scalars['String']['input']>;
+  last?: InputMaybe<Scalars['Int']['input']>;
+  state?: InputMaybe<SessionState>;
+  user?: InputMaybe<Scalars['ID']['input']>;
+};
+
+
 /** The query root of the GraphQL interface. */
 export type QueryUpstreamOauth2LinkArgs = {
   id: Scalars['ID']['input'];
@@ -1022,6 +1285,11 @@ export type RemoveEmailPayload = {
 export type RemoveEmailStatus =
   /** The password provided is incorrect */
   | 'INCORRECT_PASSWORD'
+  /**
+   * The email address is the last one of the user, and can't be removed
+   * because email changes have to be confirmed from a previous address
+   */
+  | 'LAST_EMAIL'
   /** The email address was not found */
   | 'NOT_FOUND'
   /** The email address was removed */
@@ -1075,6 +1343,71 @@ export type ResendRecoveryEmailStatus =
   /** The recovery email was sent. */
   | 'SENT';
 
+/** The input of the `revokeOauth2Consent` mutation. */
+export type RevokeOAuth2ConsentInput = {
+  /** The ID of the client the consent was given to. */
+  oauth2ClientId: Scalars['ID']['input'];
+  /** The ID of the user who gave the consent. */
+  userId: Scalars['ID']['input'];
+};
+
+export type RevokeOAuth2ConsentPayload = {
+  __typename?: 'RevokeOAuth2ConsentPayload';
+  /** The status of the mutation. */
+  status: RevokeOAuth2ConsentStatus;
+};
+
+/** The status of the `revokeOauth2Consent` mutation. */
+export type RevokeOAuth2ConsentStatus =
+  /** The consent was not found. */
+  | 'NOT_FOUND'
+  /** The consent was revoked. */
+  | 'REVOKED';
+
+/** The input for the `scheduleDeactivation` mutation. */
+export type ScheduleDeactivationInput = {
+  /**
+   * Whether to ask the homeserver to GDPR-erase the user once the
+   * deactivation is carried out
+   *
+   * This has the same meaning as in the `deactivateUser` mutation.
+   */
+  hsErase: Scalars['Boolean']['input'];
+  /**
+   * The language to use for the confirmation email, if the user has no
+   * preferred language
+   */
+  language?: Scalars['String']['input'];
+  /**
+   * The username of the current user, to confirm the deactivation. It must
+   * match exactly.
+   */
+  username: Scalars['String']['input'];
+};
+
+/** The payload for the `scheduleDeactivation` mutation. */
+export type ScheduleDeactivationPayload = {
+  __typename?: 'ScheduleDeactivationPayload';
+  /**
+   * When the deactivation will be carried out, unless the user signs in
+   * again to cancel it.
+   */
+  scheduledAt?: Maybe<Scalars['DateTime']['output']>;
+  /** Status of the operation */
+  status: ScheduleDeactivationStatus;
+  /** The user that was locked. */
+  user?: Maybe<User>;
+};
+
+/** The status of the `scheduleDeactivation` mutation. */
+export type ScheduleDeactivationStatus =
+  /** The user needs to sign in again before deactivating their account. */
+  | 'REAUTHENTICATION_REQUIRED'
+  /** The user was locked, and will be deactivated after the grace period. */
+  | 'SCHEDULED'
+  /** The username doesn't match the one of the current user. */
+  | 'USERNAME_MISMATCH';
+
 /** A client session, either compat or OAuth 2.0 */
 export type Session = CompatSession | Oauth2Session;
 
@@ -1172,6 +1505,11 @@ export type SetOAuth2SessionNameStatus =
 
 /** The input for the `setPasswordByRecovery` mutation. */
 export type SetPasswordByRecoveryInput = {
+  /**
+   * Whether to keep the other sessions of the user signed in. By default,
+   * they are all ended, in case someone else had access to the account.
+   */
+  keepSessions?: Scalars['Boolean']['input'];
   /** The new password for the user. */
   newPassword: Scalars['String']['input'];
   /**
@@ -1237,6 +1575,8 @@ export type SetPasswordStatus =
    * provider.
    */
   | 'PASSWORD_CHANGES_DISABLED'
+  /** You need to sign in again before changing your password. */
+  | 'REAUTHENTICATION_REQUIRED'
   /**
    * The specified recovery ticket has already been used and cannot be used
    * again.
@@ -1245,6 +1585,31 @@ export type SetPasswordStatus =
   /** The supplied current password was wrong. */
   | 'WRONG_PASSWORD';
 
+/** The input for the `setPreferredLanguage` mutation. */
+export type SetPreferredLanguageInput = {
+  /**
+   * The language tag to use for the pages and emails sent to the user, or
+   * `null` to use the language negotiated with the browser.
+   */
+  language?: InputMaybe<Scalars['String']['input']>;
+};
+
+/** The payload for the `setPreferredLanguage` mutation. */
+export type SetPreferredLanguagePayload = {
+  __typename?: 'SetPreferredLanguagePayload';
+  /** Status of the operation */
+  status: SetPreferredLanguageStatus;
+  /** The user that was updated. */
+  user?: Maybe<User>;
+};
+
+/** The status of the `setPreferredLanguage` mutation. */
+export type SetPreferredLanguageStatus =
+  /** The language is not supported by this server. */
+  | 'INVALID_LANGUAGE'
+  /** The preferred language was updated. */
+  | 'SET';
+
 /** The input for the `setPrimaryEmail` mutation */
 export type SetPrimaryEmailInput = {
   /** The ID of the email address to set as primary */
@@ -1342,9 +1707,39 @@ export type StartEmailAuthenticationStatus =
   | 'IN_USE'
   /** Too many attempts to start an email authentication */
   | 'RATE_LIMITED'
+  /** The user needs to sign in again before adding an email address */
+  | 'REAUTHENTICATION_REQUIRED'
   /** The email address was started */
   | 'STARTED';
 
+/** The input for the `transferUpstreamOauthLink` mutation. */
+export type TransferUpstreamOAuthLinkInput = {
+  /** The ID of the upstream link to transfer. */
+  upstreamOauth2LinkId: Scalars['ID']['input'];
+  /** The ID of the user which should own the upstream link. */
+  userId: Scalars['ID']['input'];
+};
+
+/** The payload for the `transferUpstreamOauthLink` mutation. */
+export type TransferUpstreamOAuthLinkPayload = {
+  __typename?: 'TransferUpstreamOAuthLinkPayload';
+  /** Status of the operation */
+  status: TransferUpstreamOAuthLinkStatus;
+  /** The upstream link that was transferred. */
+  upstreamOauth2Link?: Maybe<UpstreamOAuth2Link>;
+};
+
+/** The status of the `transferUpstreamOauthLink` mutation. */
+export type TransferUpstreamOAuthLinkStatus =
+  /** The user already has an upstream link for the same provider. */
+  | 'ALREADY_LINKED'
+  /** The upstream link was not found. */
+  | 'LINK_NOT_FOUND'
+  /** The upstream link was transferred. */
+  | 'TRANSFERRED'
+  /** The user was not found. */
+  | 'USER_NOT_FOUND';
+
 /** The input for the `unlockUser` mutation. */
 export type UnlockUserInput = {
   /** The ID of the user to unlock */
@@ -1450,6 +1845,15 @@ export type UpstreamOAuth2ProviderEdge = {
 /** A user is an individual's account. */
 export type User = Node & {
   __typename?: 'User';
+  /**
+   * Get the application sessions which were created or ended since the
+   * given cursor, so that a list of sessions can be kept up to date without
+   * fetching it again.
+   *
+   * Calling this without a cursor returns no events, only the cursor to use
+   * in the next call.
+   */
+  appSessionEvents: AppSessionEvents;
   /**
    * Get the list of both compat and OAuth 2.0 sessions, chronologically
    * sorted
@@ -1465,6 +1869,20 @@ export type User = Node & {
   compatSsoLogins: CompatSsoLoginConnection;
   /** When the object was created. */
   createdAt: Scalars['DateTime']['output'];
+  /**
+   * Get the list of OAuth 2.0 device code grants approved or rejected by
+   * this user.
+   *
+   * Grants still waiting for a user to enter their code are not associated
+   * with any user, so they never show up here.
+   */
+  deviceCodeGrants: DeviceCodeGrantConnection;
+  /**
+   * The label derived from the domain of the user's email addresses, as
+   * configured by the server. Only visible to the user themselves and to
+   * admins.
+   */
+  emailDomainLabel?: Maybe<Scalars['String']['output']>;
   /** Get the list of emails, chronologically sorted */
   emails: UserEmailConnection;
   /** Check if the user has a password set. */
@@ -1475,8 +1893,25 @@ export type User = Node & {
   lockedAt?: Maybe<Scalars['DateTime']['output']>;
   /** Access to the user's Matrix account information. */
   matrix: MatrixUser;
+  /** Get the consents the user chose to remember for OAuth 2.0 clients. */
+  oauth2Consents: Array<Oauth2Consent>;
   /** Get the list of OAuth 2.0 sessions, chronologically sorted */
   oauth2Sessions: Oauth2SessionConnection;
+  /**
+   * The organization the user belongs to, as imported from the upstream
+   * provider. Only visible to the user themselves and to admins.
+   */
+  organization?: Maybe<Scalars['String']['output']>;
+  /**
+   * The language explicitly chosen by the user for the pages and emails
+   * sent to them, if any.
+   */
+  preferredLanguage?: Maybe<Scalars['String']['output']>;
+  /**
+   * Get the most recent failed login attempts of the user, most recent
+   * first.
+   */
+  recentLoginFailures: Array<UserLoginFailure>;
   /** Get the list of upstream OAuth 2.0 links */
   upstreamOauth2Links: UpstreamOAuth2LinkConnection;
   /** Username chosen by the user. */
@@ -1484,6 +1919,12 @@ export type User = Node & {
 };
 
 
+/** A user is an individual's account. */
+export type UserAppSessionEventsArgs = {
+  since?: InputMaybe<Scalars['String']['input']>;
+};
+
+
 /** A user is an individual's account. */
 export type UserAppSessionsArgs = {
   after?: InputMaybe<Scalars['String']['input']>;
@@ -1529,6 +1970,17 @@ export type UserCompatSsoLoginsArgs = {
 };
 
 
+/** A user is an individual's account. */
+export type UserDeviceCodeGrantsArgs = {
+  after?: InputMaybe<Scalars['String']['input']>;
+  before?: InputMaybe<Scalars['String']['input']>;
+  expired?: InputMaybe<Scalars['Boolean']['input']>;
+  first?: InputMaybe<Scalars['Int']['input']>;
+  last?: InputMaybe<Scalars['Int']['input']>;
+  state?: InputMaybe<DeviceCodeGrantState>;
+};
+
+
 /** A user is an individual's account. */
 export type UserEmailsArgs = {
   after?: InputMaybe<Scalars['String']['input']>;
@@ -1657,6 +2109,19 @@ export type UserEmailState =
   /** The email address is pending confirmation. */
   | 'PENDING';
 
+/** A failed login attempt of a user */
+export type UserLoginFailure = {
+  __typename?: 'UserLoginFailure';
+  /** When the login attempt was made. */
+  createdAt: Scalars['DateTime']['output'];
+  /** The IP address the login attempt was made from, if known. */
+  ipAddress?: Maybe<Scalars['String']['output']>;
+  /** Where the login attempt came from. */
+  origin: LoginFailureOrigin;
+  /** Why the login attempt failed. */
+  reason: LoginFailureReason;
+};
+
 /** A recovery ticket */
 export type UserRecoveryTicket = CreationEvent & Node & {
   __typename?: 'UserRecoveryTicket';
@@ -1988,6 +2453,7 @@ export type VerifyEmailQuery = { __typename?: 'Query', userEmailAuthentication?:
 export type DoVerifyEmailMutationVariables = Exact<{
   id: Scalars['ID']['input'];
   code: Scalars['String']['input'];
+  language: Scalars['String']['input'];
 }>;
 
 
@@ -2013,6 +2479,7 @@ export type ChangePasswordMutation = { __typename?: 'Mutation', setPassword: { _
 export type RecoverPasswordMutationVariables = Exact<{
   ticket: Scalars['String']['input'];
   newPassword: Scalars['String']['input'];
+  keepSessions: Scalars['Boolean']['input'];
 }>;
 
 
@@ -2903,8 +3370,10 @@ export const VerifyEmailDocument = new TypedDocumentString(`
 }
     `) as unknown as TypedDocumentString<VerifyEmailQuery, VerifyEmailQueryVariables>;
 export const DoVerifyEmailDocument = new TypedDocumentString(`
-    mutation DoVerifyEmail($id: ID!, $code: String!) {
-  completeEmailAuthentication(input: {id: $id, code: $code}) {
+    mutation DoVerifyEmail($id: ID!, $code: String!, $language: String!) {
+  completeEmailAuthentication(
+    input: {id: $id, code: $code, language: $language}
+  ) {
     status
   }
 }
@@ -2926,8 +3395,10 @@ export const ChangePasswordDocument = new TypedDocumentString(`
 }
     `) as unknown as TypedDocumentString<ChangePasswordMutation, ChangePasswordMutationVariables>;
 export const RecoverPasswordDocument = new TypedDocumentString(`
-    mutation RecoverPassword($ticket: String!, $newPassword: String!) {
-  setPasswordByRecovery(input: {ticket: $ticket, newPassword: $newPassword}) {
+    mutation RecoverPassword($ticket: String!, $newPassword: String!, $keepSessions: Boolean!) {
+  setPasswordByRecovery(
+    input: {ticket: $ticket, newPassword: $newPassword, keepSessions: $keepSessions}
+  ) {
     status
   }
 }
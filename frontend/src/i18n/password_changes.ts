@@ -53,6 +53,10 @@ export function translateSetPasswordError(
       // These cases are shown as inline errors in the form itself.
       return undefined;
 
+    case "REAUTHENTICATION_REQUIRED":
+      // The user is sent to the re-authentication page instead
+      return undefined;
+
     case "ALLOWED":
     case undefined:
       return undefined;
@@ -76,7 +76,13 @@ const actionSchema = v.variant("action", [
     device_id: v.optional(v.string()),
   }),
   v.object({
-    action: v.literal("org.matrix.cross_signing_reset"),
+    action: v.picklist([
+      "cross_signing_reset",
+      "org.matrix.cross_signing_reset",
+    ]),
+  }),
+  v.object({
+    action: v.picklist(["account_deactivate", "org.matrix.account_deactivate"]),
   }),
   v.object({
     action: v.literal("org.matrix.plan_management"),
@@ -119,11 +125,18 @@ export const Route = createFileRoute({
           });
         throw redirect({ to: "/sessions" });
 
+      case "cross_signing_reset": // This is an unspecced alias for org.matrix.cross_signing_reset that can be removed
       case "org.matrix.cross_signing_reset": // This is from unstable MSC4191
         throw redirect({
           to: "/reset-cross-signing",
           search: { deepLink: true },
         });
+
+      case "account_deactivate": // This is an unspecced alias for org.matrix.account_deactivate that can be removed
+      case "org.matrix.account_deactivate": // This is from unstable MSC4191
+        // The account deactivation button lives on the profile page
+        throw redirect({ to: "/", search: {} });
+
       case "org.matrix.plan_management": {
         // This is an unspecced experimental value
         // We don't both checking if the plan management iframe is actually available and
@@ -34,8 +34,10 @@ const QUERY = graphql(/* GraphQL */ `
 `);
 
 const VERIFY_EMAIL_MUTATION = graphql(/* GraphQL */ `
-  mutation DoVerifyEmail($id: ID!, $code: String!) {
-    completeEmailAuthentication(input: { id: $id, code: $code }) {
+  mutation DoVerifyEmail($id: ID!, $code: String!, $language: String!) {
+    completeEmailAuthentication(
+      input: { id: $id, code: $code, language: $language }
+    ) {
       status
     }
   }
@@ -81,8 +83,19 @@ function EmailVerify(): React.ReactElement {
   const queryClient = useQueryClient();
   const navigate = useNavigate();
   const verifyEmail = useMutation({
-    mutationFn: ({ id, code }: { id: string; code: string }) =>
-      graphqlRequest({ query: VERIFY_EMAIL_MUTATION, variables: { id, code } }),
+    mutationFn: ({
+      id,
+      code,
+      language,
+    }: {
+      id: string;
+      code: string;
+      language: string;
+    }) =>
+      graphqlRequest({
+        query: VERIFY_EMAIL_MUTATION,
+        variables: { id, code, language },
+      }),
     async onSuccess(data): Promise<void> {
       await queryClient.invalidateQueries({ queryKey: ["userEmails"] });
       await queryClient.invalidateQueries({ queryKey: ["verifyEmail", id] });
@@ -115,7 +128,11 @@ function EmailVerify(): React.ReactElement {
     const formData = new FormData(form);
     const code = formData.get("code") as string;
     verifyEmail
-      .mutateAsync({ id: userEmailAuthentication.id, code })
+      .mutateAsync({
+        id: userEmailAuthentication.id,
+        code,
+        language: i18n.languages[0],
+      })
       .finally(() => form.reset());
   };
 
@@ -135,6 +152,9 @@ function EmailVerify(): React.ReactElement {
     verifyEmail.data?.completeEmailAuthentication.status === "CODE_EXPIRED";
   const rateLimited =
     verifyEmail.data?.completeEmailAuthentication.status === "RATE_LIMITED";
+  const pendingConfirmation =
+    verifyEmail.data?.completeEmailAuthentication.status ===
+    "PENDING_CONFIRMATION";
 
   return (
     <Layout>
@@ -185,6 +205,15 @@ function EmailVerify(): React.ReactElement {
           />
         )}
 
+        {pendingConfirmation && (
+          <Alert
+            type="info"
+            title={t("frontend.verify_email.pending_confirmation_alert.title")}
+          >
+            {t("frontend.verify_email.pending_confirmation_alert.description")}
+          </Alert>
+        )}
+
         <Form.Field
           name="code"
           serverInvalid={invalidCode || rateLimited}
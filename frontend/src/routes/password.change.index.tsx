@@ -20,6 +20,7 @@ import LoadingSpinner from "../components/LoadingSpinner";
 import PageHeading from "../components/PageHeading";
 import PasswordCreationDoubleInput from "../components/PasswordCreationDoubleInput";
 import Separator from "../components/Separator";
+import config from "../config";
 import { graphql } from "../gql";
 import { graphqlRequest } from "../graphql";
 import { translateSetPasswordError } from "../i18n/password_changes";
@@ -103,6 +104,11 @@ function ChangePassword(): React.ReactNode {
         router.navigate({ to: "/password/change/success" });
       }
 
+      if (response.setPassword.status === "REAUTHENTICATION_REQUIRED") {
+        // Ask the user to sign in again, then come back to this page
+        window.location.assign(`${config.reauthEndpoint}?kind=change_password`);
+      }
+
       return response.setPassword;
     },
   });
@@ -26,9 +26,17 @@ import { graphqlRequest } from "../graphql";
 import { translateSetPasswordError } from "../i18n/password_changes";
 
 const RECOVER_PASSWORD_MUTATION = graphql(/* GraphQL */ `
-  mutation RecoverPassword($ticket: String!, $newPassword: String!) {
+  mutation RecoverPassword(
+    $ticket: String!
+    $newPassword: String!
+    $keepSessions: Boolean!
+  ) {
     setPasswordByRecovery(
-      input: { ticket: $ticket, newPassword: $newPassword }
+      input: {
+        ticket: $ticket
+        newPassword: $newPassword
+        keepSessions: $keepSessions
+      }
     ) {
       status
     }
@@ -205,6 +213,7 @@ const EmailRecovery: React.FC<{
     }) => {
       const newPassword = form.get("new_password") as string;
       const newPasswordAgain = form.get("new_password_again") as string;
+      const keepSessions = form.get("keep_sessions") === "on";
 
       if (newPassword !== newPasswordAgain) {
         throw new Error(
@@ -217,6 +226,7 @@ const EmailRecovery: React.FC<{
         variables: {
           ticket,
           newPassword,
+          keepSessions,
         },
       });
 
@@ -293,6 +303,16 @@ const EmailRecovery: React.FC<{
             }
           />
 
+          <Form.InlineField
+            control={<Form.CheckboxControl />}
+            name="keep_sessions"
+          >
+            <Form.Label>{t("frontend.password_reset.keep_sessions")}</Form.Label>
+            <Form.HelpMessage>
+              {t("frontend.password_reset.keep_sessions_help")}
+            </Form.HelpMessage>
+          </Form.InlineField>
+
           <Form.Submit kind="primary" disabled={mutation.isPending}>
             {!!mutation.isPending && <LoadingSpinner inline />}
             {t("action.save_and_continue")}
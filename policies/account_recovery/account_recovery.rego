@@ -0,0 +1,48 @@
+# Copyright 2025 New Vector Ltd.
+#
+# SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+# Please see LICENSE files in the repository root for full details.
+
+# METADATA
+# schemas:
+#   - input: schema["account_recovery_input"]
+package account_recovery
+
+import rego.v1
+
+import data.common
+
+default allow := false
+
+allow if {
+	count(violation) == 0
+}
+
+# Allow recovery through any upstream provider if the
+# data.account_recovery.upstream_providers array is not set
+upstream_provider_allowed if {
+	not data.account_recovery.upstream_providers
+}
+
+upstream_provider_allowed if {
+	some provider in data.account_recovery.upstream_providers
+	input.upstream_provider_id == provider
+}
+
+# METADATA
+# entrypoint: true
+violation contains {"msg": "unknown recovery method"} if {
+	not input.recovery_method in ["upstream-oauth2"]
+}
+
+violation contains {"msg": "account recovery is not allowed with this upstream provider"} if {
+	input.recovery_method == "upstream-oauth2"
+	not upstream_provider_allowed
+}
+
+violation contains {"msg": sprintf(
+	"Requester [%s] isn't allowed to do this action",
+	[common.format_requester(input.requester)],
+)} if {
+	common.requester_banned(input.requester, data.requester)
+}
@@ -0,0 +1,48 @@
+# Copyright 2025 New Vector Ltd.
+#
+# SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-Element-Commercial
+# Please see LICENSE files in the repository root for full details.
+
+package account_recovery_test
+
+import data.account_recovery
+import rego.v1
+
+user := {"username": "alice", "sub": "01H8PKNWKKRPCBW4YGH1RWV279"}
+
+test_upstream_recovery if {
+	account_recovery.allow with input as {
+		"recovery_method": "upstream-oauth2",
+		"user": user,
+		"upstream_provider_id": "01H8PKNWKKRPCBW4YGH1RWV279",
+		"requester": {},
+	}
+}
+
+test_upstream_provider_allowlist if {
+	account_recovery.allow with input as {
+		"recovery_method": "upstream-oauth2",
+		"user": user,
+		"upstream_provider_id": "01H8PKNWKKRPCBW4YGH1RWV279",
+		"requester": {},
+	}
+		with data.account_recovery.upstream_providers as ["01H8PKNWKKRPCBW4YGH1RWV279"]
+
+	not account_recovery.allow with input as {
+		"recovery_method": "upstream-oauth2",
+		"user": user,
+		"upstream_provider_id": "01JQ2ZJ4Y6GR0S8GMRZ8CJ4AGS",
+		"requester": {},
+	}
+		with data.account_recovery.upstream_providers as ["01H8PKNWKKRPCBW4YGH1RWV279"]
+}
+
+test_banned_requester if {
+	not account_recovery.allow with input as {
+		"recovery_method": "upstream-oauth2",
+		"user": user,
+		"upstream_provider_id": "01H8PKNWKKRPCBW4YGH1RWV279",
+		"requester": {"ip_address": "192.0.2.1"},
+	}
+		with data.requester.banned_ips as ["192.0.2.1"]
+}